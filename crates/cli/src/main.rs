@@ -85,6 +85,7 @@ async fn main() -> std::io::Result<()> {
         Commands::Group(command) => command.exec(client).await,
         Commands::Queue(command) => command.exec(client).await,
         Commands::Report(command) => command.exec(client).await,
+        Commands::Expression(command) => command.exec(client).await,
     }
 
     Ok(())
@@ -189,6 +190,13 @@ pub enum Response<T> {
 }
 
 impl Client {
+    pub fn authorization(&self) -> String {
+        match &self.credentials {
+            Credentials::Basic(s) => format!("Basic {s}"),
+            Credentials::Bearer(s) => format!("Bearer {s}"),
+        }
+    }
+
     pub async fn into_jmap_client(self) -> jmap_client::client::Client {
         jmap_client::client::Client::new()
             .credentials(self.credentials)
@@ -224,13 +232,7 @@ impl Client {
             .build()
             .unwrap_or_default()
             .request(method, url)
-            .header(
-                AUTHORIZATION,
-                match &self.credentials {
-                    Credentials::Basic(s) => format!("Basic {s}"),
-                    Credentials::Bearer(s) => format!("Bearer {s}"),
-                },
-            );
+            .header(AUTHORIZATION, self.authorization());
 
         if let Some(body) = body {
             request = request.body(serde_json::to_string(&body).unwrap_result("serialize body"));
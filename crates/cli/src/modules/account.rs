@@ -74,6 +74,7 @@ impl AccountCommands {
                 is_admin,
                 addresses,
                 member_of,
+                referral,
             } => {
                 let mut changes = Vec::new();
                 if let Some(new_name) = new_name {
@@ -126,6 +127,12 @@ impl AccountCommands {
                         PrincipalValue::StringList(member_of),
                     ));
                 }
+                if let Some(referral) = referral {
+                    changes.push(PrincipalUpdate::set(
+                        PrincipalField::Referral,
+                        PrincipalValue::String(referral),
+                    ));
+                }
 
                 if !changes.is_empty() {
                     client
@@ -270,6 +277,12 @@ impl Client {
                 Cell::new(&description),
             ]));
         }
+        if let Some(referral) = principal.referral {
+            table.add_row(Row::new(vec![
+                Cell::new("Referral").with_style(Attr::Bold),
+                Cell::new(&referral),
+            ]));
+        }
         if matches!(
             principal.typ,
             Some(Type::Individual | Type::Superuser | Type::Group)
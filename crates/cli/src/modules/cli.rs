@@ -80,6 +80,10 @@ pub enum Commands {
     /// Manage SMTP DMARC/TLS report queue
     #[clap(subcommand)]
     Report(ReportCommands),
+
+    /// Evaluate SMTP configuration expressions
+    #[clap(subcommand)]
+    Expression(ExpressionCommands),
 }
 
 pub struct Client {
@@ -138,6 +142,9 @@ pub enum AccountCommands {
         /// Update groups this account is a member of
         #[clap(short, long)]
         member_of: Option<Vec<String>>,
+        /// Update the server the account is referred to (empty to remove)
+        #[clap(short, long)]
+        referral: Option<String>,
     },
 
     /// Add e-mail aliases to a user account
@@ -403,6 +410,12 @@ pub enum ServerCommands {
     /// Perform database maintenance
     DatabaseMaintenance {},
 
+    /// Rebuild the full-text index
+    Reindex {
+        /// Account name to reindex, defaults to all accounts
+        account: Option<String>,
+    },
+
     /// Reload TLS certificates
     ReloadCertificates {},
 
@@ -458,11 +471,21 @@ pub enum QueueCommands {
         #[clap(short, long)]
         #[arg(value_parser = parse_datetime)]
         after: Option<DateTime>,
+        /// Filter deferred messages by reason (dns, connection, tls, remote, quota, throttled or local)
+        #[clap(long)]
+        reason: Option<String>,
         /// Number of items to show per page
         #[clap(short, long)]
         page_size: Option<usize>,
     },
 
+    /// Summarizes deferred messages by reason and domain
+    Deferred {
+        /// Filter by domain
+        #[clap(short, long)]
+        domain: Option<String>,
+    },
+
     /// Displays details about a queued message
     Status {
         #[clap(required = true)]
@@ -485,6 +508,9 @@ pub enum QueueCommands {
         #[clap(short, long)]
         #[arg(value_parser = parse_datetime)]
         after: Option<DateTime>,
+        /// Apply to domains deferred for a specific reason
+        #[clap(long)]
+        reason: Option<String>,
         /// Schedule delivery at a specific time
         #[clap(short, long)]
         #[arg(value_parser = parse_datetime)]
@@ -512,6 +538,63 @@ pub enum QueueCommands {
         // Cancel one or multiple message ids
         ids: Vec<String>,
     },
+
+    /// Exports all queued messages to an archive on the server
+    Export {
+        /// Archive path on the server
+        path: String,
+        /// Store references to the spooled files instead of the message contents
+        #[clap(long)]
+        reference: bool,
+        /// Remove the exported messages from the queue
+        #[clap(long)]
+        remove: bool,
+    },
+
+    /// Imports the messages of an archive located on the server
+    Import {
+        /// Archive path on the server
+        path: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ExpressionCommands {
+    /// Evaluates an expression against a test envelope
+    Eval {
+        /// Expression to evaluate, using the configuration file syntax
+        expr: Option<String>,
+        /// Evaluate the expression of a configuration setting instead
+        #[clap(short, long)]
+        key: Option<String>,
+        /// Envelope sender address
+        #[clap(short, long)]
+        sender: Option<String>,
+        /// Envelope recipient address
+        #[clap(short, long)]
+        rcpt: Option<String>,
+        /// HELO domain
+        #[clap(long)]
+        helo_domain: Option<String>,
+        /// Authenticated account name
+        #[clap(long)]
+        authenticated_as: Option<String>,
+        /// Listener id
+        #[clap(short, long)]
+        listener: Option<String>,
+        /// Remote IP address
+        #[clap(long)]
+        remote_ip: Option<String>,
+        /// Local IP address
+        #[clap(long)]
+        local_ip: Option<String>,
+        /// Message priority
+        #[clap(short, long)]
+        priority: Option<i16>,
+        /// MX host
+        #[clap(short, long)]
+        mx: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
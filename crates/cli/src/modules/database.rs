@@ -21,20 +21,55 @@
  * for more details.
 */
 
+use std::time::Duration;
+
 use prettytable::{Attr, Cell, Row, Table};
 use reqwest::Method;
+use serde::Deserialize;
 use serde_json::Value;
 
 use super::cli::{Client, ServerCommands};
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JobHandle {
+    job_id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JobProgress {
+    status: String,
+    total: usize,
+    processed: usize,
+    failed: usize,
+    summary: Option<String>,
+    error: Option<String>,
+}
+
 impl ServerCommands {
     pub async fn exec(self, client: Client) {
         match self {
             ServerCommands::DatabaseMaintenance {} => {
-                client
-                    .http_request::<Value, String>(Method::GET, "/admin/store/maintenance", None)
+                let job = client
+                    .http_request::<JobHandle, String>(
+                        Method::GET,
+                        "/admin/store/maintenance",
+                        None,
+                    )
                     .await;
-                eprintln!("Success.");
+                client.wait_job(job.job_id).await;
+            }
+            ServerCommands::Reindex { account } => {
+                let mut query =
+                    form_urlencoded::Serializer::new("/admin/store/reindex?".to_string());
+                if let Some(account) = &account {
+                    query.append_pair("account", account);
+                }
+                let job = client
+                    .http_request::<JobHandle, String>(Method::GET, &query.finish(), None)
+                    .await;
+                client.wait_job(job.job_id).await;
             }
             ServerCommands::ReloadCertificates {} => {
                 client
@@ -102,3 +137,40 @@ impl ServerCommands {
         }
     }
 }
+
+impl Client {
+    async fn wait_job(&self, id: u64) {
+        eprintln!("Started job {id}, waiting for it to complete...");
+        loop {
+            let progress = self
+                .http_request::<JobProgress, String>(Method::GET, &format!("/admin/job/{id}"), None)
+                .await;
+            match progress.status.as_str() {
+                "running" => {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                "completed" => {
+                    if progress.failed > 0 {
+                        eprintln!(
+                            "Processed {}/{} item(s), {} failed.",
+                            progress.processed, progress.total, progress.failed
+                        );
+                    }
+                    eprintln!("{}", progress.summary.as_deref().unwrap_or("Success."));
+                    break;
+                }
+                "cancelled" => {
+                    eprintln!("Job {id} was cancelled.");
+                    std::process::exit(1);
+                }
+                _ => {
+                    eprintln!(
+                        "Job {id} failed: {}",
+                        progress.error.as_deref().unwrap_or("Unknown error.")
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
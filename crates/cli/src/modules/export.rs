@@ -34,6 +34,7 @@ use jmap_client::{
     sieve::{self, SieveScript},
     vacation_response::{self, VacationResponse},
 };
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
 use serde::Serialize;
 use tokio::io::AsyncWriteExt;
 
@@ -41,11 +42,12 @@ use crate::modules::RETRY_ATTEMPTS;
 
 use super::{
     cli::{Client, ExportCommands},
-    name_to_id, UnwrapResult,
+    is_localhost, name_to_id, UnwrapResult,
 };
 
 impl ExportCommands {
     pub async fn exec(self, client: Client) {
+        let authorization = client.authorization();
         let mut client = client.into_jmap_client().await;
         match self {
             ExportCommands::Account {
@@ -81,6 +83,7 @@ impl ExportCommands {
                 export_sieve_scripts(&client, max_objects_in_get, &mut blobs, &path).await;
                 export_identities(&client, &path).await;
                 export_vacation_responses(&client, &path).await;
+                export_annotations(&client, &authorization, max_objects_in_get, &path).await;
 
                 // Export blobs
                 path.push("blobs");
@@ -445,6 +448,134 @@ async fn export_vacation_responses(client: &jmap_client::client::Client, path: &
     );
 }
 
+pub async fn fetch_annotations(
+    client: &jmap_client::client::Client,
+    authorization: &str,
+    max_objects_in_get: usize,
+) -> Vec<serde_json::Value> {
+    // Annotations cannot be queried, obtain their ids from the changes log
+    let mut ids = Vec::new();
+    let mut since_state = "n".to_string();
+    loop {
+        let response = jmap_method_call(
+            client,
+            authorization,
+            "Annotation/changes",
+            serde_json::json!({
+                "accountId": client.default_account_id(),
+                "sinceState": since_state,
+            }),
+        )
+        .await;
+        for id in ["created", "updated"]
+            .into_iter()
+            .filter_map(|list| response[list].as_array())
+            .flatten()
+            .filter_map(|id| id.as_str())
+        {
+            if !ids.iter().any(|existing_id| existing_id == id) {
+                ids.push(id.to_string());
+            }
+        }
+        if !response["hasMoreChanges"].as_bool().unwrap_or(false) {
+            break;
+        }
+        since_state = response["newState"]
+            .as_str()
+            .unwrap_result("obtain annotation state")
+            .to_string();
+    }
+
+    let mut results = Vec::with_capacity(ids.len());
+    for ids in ids.chunks(max_objects_in_get) {
+        let mut response = jmap_method_call(
+            client,
+            authorization,
+            "Annotation/get",
+            serde_json::json!({
+                "accountId": client.default_account_id(),
+                "ids": ids,
+            }),
+        )
+        .await;
+        if let serde_json::Value::Array(list) = response["list"].take() {
+            results.extend(list);
+        }
+    }
+
+    results
+}
+
+async fn export_annotations(
+    client: &jmap_client::client::Client,
+    authorization: &str,
+    max_objects_in_get: usize,
+    path: &Path,
+) {
+    eprintln!(
+        "Exported {} annotations.",
+        write_file(
+            path,
+            "annotations.json",
+            fetch_annotations(client, authorization, max_objects_in_get).await
+        )
+        .await
+    );
+}
+
+// Sends a method call for objects not supported by the JMAP client library
+pub async fn jmap_method_call(
+    client: &jmap_client::client::Client,
+    authorization: &str,
+    method: &str,
+    arguments: serde_json::Value,
+) -> serde_json::Value {
+    let url = client.session().api_url().to_string();
+    let response = reqwest::Client::builder()
+        .danger_accept_invalid_certs(is_localhost(&url))
+        .build()
+        .unwrap_or_default()
+        .post(url)
+        .header(AUTHORIZATION, authorization)
+        .header(CONTENT_TYPE, "application/json")
+        .body(
+            serde_json::json!({
+                "using": ["urn:ietf:params:jmap:core", "urn:ietf:params:jmap:annotations"],
+                "methodCalls": [[method, arguments, "0"]],
+            })
+            .to_string(),
+        )
+        .send()
+        .await
+        .unwrap_result("send JMAP request");
+    if !response.status().is_success() {
+        eprintln!(
+            "JMAP request failed: {}",
+            response.text().await.unwrap_result("fetch text")
+        );
+        std::process::exit(1);
+    }
+
+    let mut response = serde_json::from_slice::<serde_json::Value>(
+        &response.bytes().await.unwrap_result("fetch bytes"),
+    )
+    .unwrap_result("deserialize JMAP response");
+    match response["methodResponses"][0].take() {
+        serde_json::Value::Array(mut method_response) if method_response.len() == 3 => {
+            if method_response[0].as_str() == Some(method) {
+                method_response[1].take()
+            } else {
+                eprintln!("{method} failed: {}", method_response[1]);
+                std::process::exit(1);
+            }
+        }
+        _ => {
+            eprintln!("Invalid response for {method}.");
+            std::process::exit(1);
+        }
+    }
+}
+
 async fn write_file<T: Serialize>(path: &Path, name: &str, contents: Vec<T>) -> usize {
     let mut path = PathBuf::from(path);
     path.push(name);
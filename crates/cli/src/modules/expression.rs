@@ -0,0 +1,129 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::cli::{Client, ExpressionCommands};
+use prettytable::{Attr, Cell, Row, Table};
+use reqwest::Method;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct EvalResult {
+    pub result: Vec<String>,
+    #[serde(default)]
+    pub matched: Option<usize>,
+    pub trace: Vec<EvalStep>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EvalStep {
+    pub block: usize,
+    pub key: String,
+    pub value: String,
+    pub op: String,
+    pub expected: String,
+    pub matched: bool,
+}
+
+impl ExpressionCommands {
+    pub async fn exec(self, client: Client) {
+        match self {
+            ExpressionCommands::Eval {
+                expr,
+                key,
+                sender,
+                rcpt,
+                helo_domain,
+                authenticated_as,
+                listener,
+                remote_ip,
+                local_ip,
+                priority,
+                mx,
+            } => {
+                let mut query =
+                    form_urlencoded::Serializer::new("/admin/expression/eval?".to_string());
+
+                match (&expr, &key) {
+                    (Some(expr), None) => {
+                        query.append_pair("expr", expr);
+                    }
+                    (None, Some(key)) => {
+                        query.append_pair("key", key);
+                    }
+                    _ => {
+                        eprintln!("Please specify either an expression or a setting key.");
+                        std::process::exit(1);
+                    }
+                }
+                for (name, value) in [
+                    ("sender", sender),
+                    ("rcpt", rcpt),
+                    ("helo-domain", helo_domain),
+                    ("authenticated-as", authenticated_as),
+                    ("listener", listener),
+                    ("remote-ip", remote_ip),
+                    ("local-ip", local_ip),
+                    ("priority", priority.map(|p| p.to_string())),
+                    ("mx", mx),
+                ] {
+                    if let Some(value) = value {
+                        query.append_pair(name, &value);
+                    }
+                }
+
+                let result = client
+                    .http_request::<EvalResult, String>(Method::GET, &query.finish(), None)
+                    .await;
+
+                if !result.trace.is_empty() {
+                    let mut table = Table::new();
+                    table.add_row(Row::new(
+                        ["Block", "Key", "Value", "Operation", "Expected", "Matched"]
+                            .iter()
+                            .map(|p| Cell::new(p).with_style(Attr::Bold))
+                            .collect(),
+                    ));
+                    for step in &result.trace {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&(step.block + 1).to_string()),
+                            Cell::new(&step.key),
+                            Cell::new(&step.value),
+                            Cell::new(&step.op),
+                            Cell::new(&step.expected),
+                            Cell::new(if step.matched { "Yes" } else { "No" }),
+                        ]));
+                    }
+                    eprintln!();
+                    table.printstd();
+                }
+
+                eprintln!();
+                match result.matched {
+                    Some(block) => eprintln!("Matched condition {}.", block + 1),
+                    None => eprintln!("No conditions matched, using default value."),
+                }
+                eprintln!("Result: {}", result.result.join(", "));
+            }
+        }
+    }
+}
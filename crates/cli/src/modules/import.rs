@@ -53,7 +53,7 @@ use super::{
     cli::{Client, ImportCommands, MailboxFormat},
     export::{
         fetch_emails, fetch_identities, fetch_mailboxes, fetch_sieve_scripts,
-        fetch_vacation_responses,
+        fetch_vacation_responses, jmap_method_call,
     },
     read_file,
 };
@@ -80,6 +80,7 @@ struct Message {
 }
 impl ImportCommands {
     pub async fn exec(self, client: Client) {
+        let authorization = client.authorization();
         let mut client = client.into_jmap_client().await;
 
         match self {
@@ -460,6 +461,7 @@ impl ImportCommands {
                 import_sieve_scripts(&client, &path, num_concurrent).await;
                 import_identities(&client, &path).await;
                 import_vacation_responses(&client, &path).await;
+                import_annotations(&client, &authorization, &path).await;
             }
         }
     }
@@ -966,6 +968,90 @@ async fn import_vacation_responses(client: &jmap_client::client::Client, path: &
     }
 }
 
+async fn import_annotations(
+    client: &jmap_client::client::Client,
+    authorization: &str,
+    path: &Path,
+) {
+    let annotations = read_json::<serde_json::Value>(path, "annotations.json").await;
+    if annotations.is_empty() {
+        return;
+    }
+
+    // Map exported email ids to the ids of the imported messages
+    let exported_emails = read_json::<jmap_client::email::Email>(path, "emails.json").await;
+    let existing_emails = fetch_emails(
+        client,
+        client
+            .session()
+            .core_capabilities()
+            .map(|c| c.max_objects_in_get())
+            .unwrap_or(500),
+    )
+    .await;
+    let existing_ids = existing_emails
+        .iter()
+        .filter_map(|email| Some(((email.message_id(), email.received_at()), email.id()?)))
+        .collect::<HashMap<_, _>>();
+    let email_ids = exported_emails
+        .iter()
+        .filter_map(|email| {
+            Some((
+                email.id()?,
+                *existing_ids.get(&(email.message_id(), email.received_at()))?,
+            ))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut total_imported = 0;
+    let mut total_existing = 0;
+    for mut annotation in annotations {
+        let id = annotation["id"].as_str().unwrap_or_default().to_string();
+        let email_id = if let Some(email_id) = annotation["emailId"]
+            .as_str()
+            .and_then(|email_id| email_ids.get(email_id))
+        {
+            email_id.to_string()
+        } else {
+            eprintln!("Skipping annotation {id} with unknown emailId");
+            continue;
+        };
+        if let Some(annotation) = annotation.as_object_mut() {
+            annotation.remove("id");
+            annotation.insert("emailId".to_string(), email_id.into());
+        }
+
+        let mut response = jmap_method_call(
+            client,
+            authorization,
+            "Annotation/set",
+            serde_json::json!({
+                "accountId": client.default_account_id(),
+                "create": { "a": annotation },
+            }),
+        )
+        .await;
+        let error = response["notCreated"]["a"].take();
+        match error["type"].as_str() {
+            None => total_imported += 1,
+            Some("alreadyExists") => total_existing += 1,
+            Some(_) => {
+                eprintln!(
+                    "Failed to import annotation {id}: {}",
+                    error["description"].as_str().unwrap_or_default()
+                );
+            }
+        }
+    }
+
+    eprintln!(
+        "Successfully processed {} annotations ({} imported, {} already exist).",
+        total_imported + total_existing,
+        total_imported,
+        total_existing
+    );
+}
+
 fn build_mailbox_tree(
     mailboxes: &[jmap_client::mailbox::Mailbox],
 ) -> HashMap<Vec<&str>, &jmap_client::mailbox::Mailbox> {
@@ -34,6 +34,7 @@ pub mod cli;
 pub mod database;
 pub mod domain;
 pub mod export;
+pub mod expression;
 pub mod group;
 pub mod import;
 pub mod list;
@@ -76,6 +77,9 @@ pub struct Principal {
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referral: Option<String>,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -107,6 +111,8 @@ pub enum PrincipalField {
     Quota,
     #[serde(rename = "description")]
     Description,
+    #[serde(rename = "referral")]
+    Referral,
     #[serde(rename = "secrets")]
     Secrets,
     #[serde(rename = "emails")]
@@ -28,6 +28,7 @@ use mail_parser::DateTime;
 use prettytable::{format::Alignment, Attr, Cell, Row, Table};
 use reqwest::Method;
 use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 pub struct Message {
@@ -48,12 +49,42 @@ pub struct Domain {
     pub recipients: Vec<Recipient>,
 
     pub retry_num: u32,
+    #[serde(default)]
+    pub retry_strategy: Option<String>,
     #[serde(deserialize_with = "deserialize_maybe_datetime")]
     pub next_retry: Option<DateTime>,
     #[serde(deserialize_with = "deserialize_maybe_datetime")]
     pub next_notify: Option<DateTime>,
     #[serde(deserialize_with = "deserialize_datetime")]
     pub expires: DateTime,
+    #[serde(default)]
+    pub defer_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct DeferredSummary {
+    pub reason: String,
+    pub messages: usize,
+    pub domains: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct QueueExport {
+    pub path: String,
+    pub messages: usize,
+    pub size: usize,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct QueueImport {
+    pub messages: Vec<ImportedMessage>,
+    pub skipped: Vec<u64>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct ImportedMessage {
+    pub previous_id: u64,
+    pub id: u64,
 }
 
 #[derive(Debug, Deserialize, PartialEq, Eq)]
@@ -83,10 +114,13 @@ impl QueueCommands {
                 rcpt,
                 before,
                 after,
+                reason,
                 page_size,
             } => {
                 let stdout = Term::buffered_stdout();
-                let ids = client.query_messages(&sender, &rcpt, &before, &after).await;
+                let ids = client
+                    .query_messages(&sender, &rcpt, &before, &after, &reason)
+                    .await;
                 let ids_len = ids.len();
                 let page_size = page_size.map(|p| std::cmp::max(p, 1)).unwrap_or(20);
                 let pages_total = (ids_len as f64 / page_size as f64).ceil() as usize;
@@ -172,6 +206,52 @@ impl QueueCommands {
                 }
                 eprintln!("\n{ids_len} queued message(s) found.")
             }
+            QueueCommands::Deferred { domain } => {
+                let mut query =
+                    form_urlencoded::Serializer::new("/admin/queue/deferred?".to_string());
+                if let Some(domain) = &domain {
+                    query.append_pair("domain", domain);
+                }
+                let summary = client
+                    .http_request::<Vec<DeferredSummary>, String>(
+                        Method::GET,
+                        &query.finish(),
+                        None,
+                    )
+                    .await;
+
+                if !summary.is_empty() {
+                    let mut table = Table::new();
+                    table.add_row(Row::new(
+                        ["Reason", "Messages", "Domains"]
+                            .iter()
+                            .map(|p| Cell::new(p).with_style(Attr::Bold))
+                            .collect(),
+                    ));
+                    for item in &summary {
+                        let mut domains = String::new();
+                        for (domain, count) in &item.domains {
+                            if !domains.is_empty() {
+                                domains.push('\n');
+                            }
+                            domains.push_str(&format!("{domain} ({count})"));
+                        }
+                        table.add_row(Row::new(vec![
+                            Cell::new(&item.reason),
+                            Cell::new(&item.messages.to_string()),
+                            Cell::new(&domains),
+                        ]));
+                    }
+
+                    eprintln!();
+                    table.printstd();
+                    eprintln!();
+                }
+                eprintln!(
+                    "\n{} deferred message(s) found.",
+                    summary.iter().map(|item| item.messages).sum::<usize>()
+                );
+            }
             QueueCommands::Status { ids } => {
                 for (message, id) in client
                     .http_request::<Vec<Option<Message>>, String>(
@@ -241,6 +321,18 @@ impl QueueCommands {
                                 Cell::new("Retry #").with_style(Attr::Bold),
                                 Cell::new(&domain.retry_num.to_string()),
                             ]));
+                            if let Some(defer_reason) = &domain.defer_reason {
+                                table.add_row(Row::new(vec![
+                                    Cell::new("Defer Reason").with_style(Attr::Bold),
+                                    Cell::new(defer_reason),
+                                ]));
+                            }
+                            if let Some(retry_strategy) = &domain.retry_strategy {
+                                table.add_row(Row::new(vec![
+                                    Cell::new("Retry Strategy").with_style(Attr::Bold),
+                                    Cell::new(retry_strategy),
+                                ]));
+                            }
                             if let Some(dt) = &domain.next_retry {
                                 table.add_row(Row::new(vec![
                                     Cell::new("Delivery Due").with_style(Attr::Bold),
@@ -294,13 +386,19 @@ impl QueueCommands {
                 domain,
                 before,
                 after,
+                reason,
                 time,
                 ids,
             } => {
                 let (parsed_ids, ids) = if ids.is_empty() {
-                    if sender.is_some() || domain.is_some() || before.is_some() || after.is_some() {
+                    if sender.is_some()
+                        || domain.is_some()
+                        || before.is_some()
+                        || after.is_some()
+                        || reason.is_some()
+                    {
                         let parsed_ids = client
-                            .query_messages(&sender, &domain, &before, &after)
+                            .query_messages(&sender, &domain, &before, &after, &reason)
                             .await;
                         let ids = parsed_ids.iter().map(|id| format!("{id:X}")).collect();
                         (parsed_ids, ids)
@@ -321,6 +419,9 @@ impl QueueCommands {
                 if let Some(filter) = &domain {
                     query.append_pair("filter", filter);
                 }
+                if let Some(reason) = &reason {
+                    query.append_pair("reason", reason);
+                }
                 if let Some(at) = time {
                     query.append_pair("at", &at.to_rfc3339());
                 }
@@ -355,8 +456,9 @@ impl QueueCommands {
             } => {
                 let (parsed_ids, ids) = if ids.is_empty() {
                     if sender.is_some() || rcpt.is_some() || before.is_some() || after.is_some() {
-                        let parsed_ids =
-                            client.query_messages(&sender, &rcpt, &before, &after).await;
+                        let parsed_ids = client
+                            .query_messages(&sender, &rcpt, &before, &after, &None)
+                            .await;
                         let ids = parsed_ids.iter().map(|id| format!("{id:X}")).collect();
                         (parsed_ids, ids)
                     } else {
@@ -402,6 +504,52 @@ impl QueueCommands {
                 }
                 eprintln!();
             }
+            QueueCommands::Export {
+                path,
+                reference,
+                remove,
+            } => {
+                let mut query =
+                    form_urlencoded::Serializer::new("/admin/queue/export?".to_string());
+                query.append_pair("path", &path);
+                if reference {
+                    query.append_pair("content", "reference");
+                }
+                if remove {
+                    query.append_pair("remove", "true");
+                }
+                let result = client
+                    .http_request::<QueueExport, String>(Method::GET, &query.finish(), None)
+                    .await;
+                eprintln!(
+                    "\nExported {} message(s) to {} ({} bytes).",
+                    result.messages, result.path, result.size
+                );
+            }
+            QueueCommands::Import { path } => {
+                let mut query =
+                    form_urlencoded::Serializer::new("/admin/queue/import?".to_string());
+                query.append_pair("path", &path);
+                let result = client
+                    .http_request::<QueueImport, String>(Method::GET, &query.finish(), None)
+                    .await;
+                for message in &result.messages {
+                    println!("{:X} -> {:X}", message.previous_id, message.id);
+                }
+                eprint!("\nImported {} message(s).", result.messages.len());
+                if !result.skipped.is_empty() {
+                    eprint!(
+                        " Skipped id(s): {}.",
+                        result
+                            .skipped
+                            .iter()
+                            .map(|id| format!("{id:X}"))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                }
+                eprintln!();
+            }
         }
     }
 }
@@ -413,6 +561,7 @@ impl Client {
         rcpt: &Option<String>,
         before: &Option<DateTime>,
         after: &Option<DateTime>,
+        reason: &Option<String>,
     ) -> Vec<u64> {
         let mut query = form_urlencoded::Serializer::new("/admin/queue/list?".to_string());
 
@@ -428,6 +577,9 @@ impl Client {
         if let Some(after) = after {
             query.append_pair("after", &after.to_rfc3339());
         }
+        if let Some(reason) = reason {
+            query.append_pair("reason", reason);
+        }
 
         self.http_request::<Vec<u64>, String>(Method::GET, &query.finish(), None)
             .await
@@ -44,6 +44,7 @@ pub trait DirectoryStore: Sync + Send {
     async fn rcpt(&self, address: &str) -> crate::Result<bool>;
     async fn vrfy(&self, address: &str) -> crate::Result<Vec<String>>;
     async fn expn(&self, address: &str) -> crate::Result<Vec<String>>;
+    async fn domain_addresses(&self, domain: &str) -> crate::Result<Vec<String>>;
 }
 
 impl DirectoryStore for Store {
@@ -158,6 +159,32 @@ impl DirectoryStore for Store {
         Ok(results)
     }
 
+    async fn domain_addresses(&self, domain: &str) -> crate::Result<Vec<String>> {
+        let mut results = Vec::new();
+        self.iterate(
+            IterateParams::new(
+                ValueKey::from(ValueClass::Directory(DirectoryClass::EmailToId(vec![0u8]))),
+                ValueKey::from(ValueClass::Directory(DirectoryClass::EmailToId(
+                    vec![u8::MAX; 10],
+                ))),
+            )
+            .no_values(),
+            |key, _| {
+                let key = std::str::from_utf8(key.get(1..).unwrap_or_default()).unwrap_or_default();
+                if key
+                    .rsplit_once('@')
+                    .map_or(false, |(_, domain_part)| domain_part == domain)
+                {
+                    results.push(key.to_string());
+                }
+                Ok(true)
+            },
+        )
+        .await?;
+
+        Ok(results)
+    }
+
     async fn expn(&self, address: &str) -> crate::Result<Vec<String>> {
         let mut results = Vec::new();
         for account_id in self.email_to_ids(address).await? {
@@ -425,6 +425,17 @@ impl ManageDirectory for Store {
                         principal.inner.description = None;
                     }
                 }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::Referral,
+                    PrincipalValue::String(referral),
+                ) => {
+                    if !referral.is_empty() {
+                        principal.inner.referral = Some(referral);
+                    } else {
+                        principal.inner.referral = None;
+                    }
+                }
                 (PrincipalAction::Set, PrincipalField::Quota, PrincipalValue::Integer(quota)) => {
                     principal.inner.quota = quota;
                 }
@@ -759,6 +770,7 @@ impl ManageDirectory for Store {
             emails: principal.emails,
             member_of: Vec::with_capacity(principal.member_of.len()),
             description: principal.description,
+            referral: principal.referral,
         };
 
         for account_id in principal.member_of {
@@ -784,6 +796,7 @@ impl ManageDirectory for Store {
             emails: principal.emails,
             member_of: Vec::with_capacity(principal.member_of.len()),
             description: principal.description,
+            referral: principal.referral,
         };
 
         for member in principal.member_of {
@@ -991,6 +1004,7 @@ impl From<Principal<String>> for Principal<u32> {
             emails: principal.emails,
             member_of: Vec::with_capacity(0),
             description: principal.description,
+            referral: principal.referral,
         }
     }
 }
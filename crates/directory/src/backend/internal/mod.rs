@@ -50,7 +50,8 @@ impl Serialize for &Principal<u32> {
                 + self.name.len()
                 + self.emails.iter().map(|s| s.len()).sum::<usize>()
                 + self.secrets.iter().map(|s| s.len()).sum::<usize>()
-                + self.description.as_ref().map(|s| s.len()).unwrap_or(0),
+                + self.description.as_ref().map(|s| s.len()).unwrap_or(0)
+                + self.referral.as_ref().map(|s| s.len()).unwrap_or(0),
         )
         .write(1u8)
         .write_leb128(self.id)
@@ -68,6 +69,13 @@ impl Serialize for &Principal<u32> {
             }
         }
 
+        // Optional trailing fields, absent in principals written by older versions
+        if let Some(referral) = &self.referral {
+            serializer = serializer
+                .write_leb128(referral.len())
+                .write(referral.as_bytes());
+        }
+
         serializer.finalize()
     }
 }
@@ -129,6 +137,7 @@ fn deserialize(bytes: &[u8]) -> Option<Principal<u32>> {
         secrets: deserialize_string_list(&mut bytes)?,
         emails: deserialize_string_list(&mut bytes)?,
         member_of: Vec::new(),
+        referral: deserialize_string(&mut bytes).filter(|v| !v.is_empty()),
     }
     .into()
 }
@@ -143,6 +152,8 @@ pub enum PrincipalField {
     Quota,
     #[serde(rename = "description")]
     Description,
+    #[serde(rename = "referral")]
+    Referral,
     #[serde(rename = "secrets")]
     Secrets,
     #[serde(rename = "emails")]
@@ -211,6 +222,7 @@ impl Display for PrincipalField {
             PrincipalField::Type => write!(f, "type"),
             PrincipalField::Quota => write!(f, "quota"),
             PrincipalField::Description => write!(f, "description"),
+            PrincipalField::Referral => write!(f, "referral"),
             PrincipalField::Secrets => write!(f, "secrets"),
             PrincipalField::Emails => write!(f, "emails"),
             PrincipalField::MemberOf => write!(f, "memberOf"),
@@ -228,7 +240,7 @@ fn deserialize_string(bytes: &mut Iter<'_, u8>) -> Option<String> {
     String::from_utf8(string).ok()
 }
 
-fn deserialize_string_list(bytes: &mut Iter<'_, u8>) -> Option<Vec<String>> {
+pub(crate) fn deserialize_string_list(bytes: &mut Iter<'_, u8>) -> Option<Vec<String>> {
     let len = bytes.next_leb128()?;
     let mut list = Vec::with_capacity(len);
     for _ in 0..len {
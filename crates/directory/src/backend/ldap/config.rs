@@ -96,6 +96,10 @@ impl LdapDirectory {
                 .values((&prefix, "attributes.email-alias"))
                 .map(|(_, v)| v.to_string())
                 .collect(),
+            attr_referral: config
+                .values((&prefix, "attributes.referral"))
+                .map(|(_, v)| v.to_string())
+                .collect(),
             attrs_principal: vec!["objectClass".to_string()],
         };
 
@@ -108,6 +112,7 @@ impl LdapDirectory {
             &mappings.attr_groups,
             &mappings.attr_email_address,
             &mappings.attr_email_alias,
+            &mappings.attr_referral,
         ] {
             mappings.attrs_principal.extend(attr.iter().cloned());
         }
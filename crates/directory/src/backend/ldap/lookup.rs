@@ -346,6 +346,8 @@ impl LdapMappings {
                 if principal.description.is_none() || idx == 0 {
                     principal.description = value.into_iter().next();
                 }
+            } else if self.attr_referral.contains(&attr) {
+                principal.referral = value.into_iter().find(|v| !v.is_empty());
             } else if self.attr_groups.contains(&attr) {
                 principal.member_of.extend(value);
             } else if self.attr_quota.contains(&attr) {
@@ -136,6 +136,9 @@ impl MemoryDirectory {
                 member_of,
                 id,
                 emails,
+                referral: config
+                    .value((prefix.as_str(), "principals", lookup_id, "referral"))
+                    .map(|v| v.to_string()),
             });
         }
 
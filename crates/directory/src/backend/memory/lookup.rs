@@ -96,6 +96,18 @@ impl MemoryDirectory {
         Ok(result)
     }
 
+    pub async fn domain_addresses(&self, domain: &str) -> crate::Result<Vec<String>> {
+        Ok(self
+            .emails_to_ids
+            .keys()
+            .filter(|key| {
+                key.rsplit_once('@')
+                    .map_or(false, |(_, domain_part)| domain_part == domain)
+            })
+            .cloned()
+            .collect())
+    }
+
     pub async fn expn(&self, address: &str) -> crate::Result<Vec<String>> {
         let mut result = Vec::new();
         for (key, value) in &self.emails_to_ids {
@@ -120,4 +132,8 @@ impl MemoryDirectory {
     pub async fn is_local_domain(&self, domain: &str) -> crate::Result<bool> {
         Ok(self.domains.contains(domain))
     }
+
+    pub fn domains(&self) -> impl Iterator<Item = &String> {
+        self.domains.iter()
+    }
 }
@@ -21,10 +21,12 @@
  * for more details.
 */
 
+use std::time::Duration;
+
 use store::{Store, Stores};
 use utils::config::{utils::AsKey, Config};
 
-use super::{SqlDirectory, SqlMappings};
+use super::{health::SqlHealth, SqlDirectory, SqlMappings};
 
 impl SqlDirectory {
     pub fn from_config(
@@ -60,6 +62,10 @@ impl SqlDirectory {
                 .value((&prefix, "columns.type"))
                 .unwrap_or_default()
                 .to_string(),
+            column_referral: config
+                .value((&prefix, "columns.referral"))
+                .unwrap_or_default()
+                .to_string(),
             ..Default::default()
         };
 
@@ -78,9 +84,28 @@ impl SqlDirectory {
                 .to_string();
         }
 
+        let health = SqlHealth::new(
+            config
+                .value((&prefix, "health.query"))
+                .filter(|query| !query.is_empty())
+                .map(|query| query.to_string()),
+            config
+                .property((&prefix, "health.interval"))?
+                .unwrap_or(Duration::from_secs(30)),
+            config.property((&prefix, "retry.attempts"))?.unwrap_or(2),
+            config
+                .property((&prefix, "retry.backoff"))?
+                .unwrap_or(Duration::from_millis(100)),
+            config
+                .property((&prefix, "retry.max-backoff"))?
+                .unwrap_or(Duration::from_secs(30)),
+        );
+
         Ok(SqlDirectory {
             store,
             mappings,
+            health,
+            metrics: Default::default(),
             data_store,
         })
     }
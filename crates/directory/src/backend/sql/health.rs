@@ -0,0 +1,311 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryKind {
+    Name = 0,
+    Members = 1,
+    Recipients = 2,
+    Emails = 3,
+    Verify = 4,
+    Expand = 5,
+    Domains = 6,
+    Health = 7,
+}
+
+pub(crate) struct SqlHealth {
+    pub query: Option<String>,
+    pub interval: Duration,
+    pub retry_attempts: u32,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    state: Mutex<HealthState>,
+}
+
+#[derive(Default)]
+struct HealthState {
+    last_check: Option<Instant>,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HealthStatus {
+    Healthy,
+    Validate,
+    Unavailable,
+}
+
+#[derive(Default)]
+pub(crate) struct SqlMetrics {
+    queries: [QueryStats; 8],
+}
+
+#[derive(Default)]
+struct QueryStats {
+    count: AtomicU64,
+    errors: AtomicU64,
+    total_us: AtomicU64,
+    max_us: AtomicU64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryReport {
+    pub query: &'static str,
+    pub count: u64,
+    pub errors: u64,
+    pub total_latency_ms: f64,
+    pub max_latency_ms: f64,
+}
+
+impl SqlHealth {
+    pub fn new(
+        query: Option<String>,
+        interval: Duration,
+        retry_attempts: u32,
+        backoff: Duration,
+        max_backoff: Duration,
+    ) -> Self {
+        SqlHealth {
+            query,
+            interval,
+            retry_attempts,
+            backoff,
+            max_backoff,
+            state: Mutex::new(HealthState::default()),
+        }
+    }
+
+    pub fn status(&self) -> HealthStatus {
+        let now = Instant::now();
+        let mut state = self.state.lock();
+
+        if state.retry_at.map_or(false, |retry_at| now < retry_at) {
+            HealthStatus::Unavailable
+        } else if self.query.is_some()
+            && (state.failures > 0
+                || state.last_check.map_or(true, |last_check| {
+                    now.duration_since(last_check) >= self.interval
+                }))
+        {
+            // Concurrent lookups skip validation while this check is in flight
+            state.last_check = Some(now);
+            HealthStatus::Validate
+        } else {
+            HealthStatus::Healthy
+        }
+    }
+
+    pub fn success(&self) {
+        let mut state = self.state.lock();
+        state.failures = 0;
+        state.retry_at = None;
+        state.last_check = Some(Instant::now());
+    }
+
+    pub fn failure(&self) -> Duration {
+        let mut state = self.state.lock();
+        state.failures = state.failures.saturating_add(1);
+        let delay = self.delay(state.failures - 1);
+        state.retry_at = Some(Instant::now() + delay);
+        delay
+    }
+
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.backoff
+            .saturating_mul(1u32 << attempt.min(16))
+            .min(self.max_backoff)
+    }
+}
+
+impl SqlMetrics {
+    pub fn record(&self, kind: QueryKind, elapsed: Duration, success: bool) {
+        let stats = &self.queries[kind as usize];
+        let elapsed = elapsed.as_micros() as u64;
+        stats.count.fetch_add(1, Ordering::Relaxed);
+        if !success {
+            stats.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        stats.total_us.fetch_add(elapsed, Ordering::Relaxed);
+        stats.max_us.fetch_max(elapsed, Ordering::Relaxed);
+    }
+
+    pub fn report(&self) -> Vec<QueryReport> {
+        [
+            QueryKind::Name,
+            QueryKind::Members,
+            QueryKind::Recipients,
+            QueryKind::Emails,
+            QueryKind::Verify,
+            QueryKind::Expand,
+            QueryKind::Domains,
+            QueryKind::Health,
+        ]
+        .into_iter()
+        .map(|kind| {
+            let stats = &self.queries[kind as usize];
+            QueryReport {
+                query: kind.as_str(),
+                count: stats.count.load(Ordering::Relaxed),
+                errors: stats.errors.load(Ordering::Relaxed),
+                total_latency_ms: stats.total_us.load(Ordering::Relaxed) as f64 / 1000.0,
+                max_latency_ms: stats.max_us.load(Ordering::Relaxed) as f64 / 1000.0,
+            }
+        })
+        .collect()
+    }
+}
+
+impl QueryKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueryKind::Name => "name",
+            QueryKind::Members => "members",
+            QueryKind::Recipients => "recipients",
+            QueryKind::Emails => "emails",
+            QueryKind::Verify => "verify",
+            QueryKind::Expand => "expand",
+            QueryKind::Domains => "domains",
+            QueryKind::Health => "health",
+        }
+    }
+}
+
+/// Renders SQL directory query reports using the Prometheus text exposition format.
+pub fn to_prometheus(reports: &[QueryReport]) -> String {
+    let mut out = String::with_capacity(reports.len() * 256);
+
+    for (metric, typ, help, value) in [
+        (
+            "stalwart_directory_queries_total",
+            "counter",
+            "Number of SQL directory queries executed.",
+            (|r: &QueryReport| r.count as f64) as fn(&QueryReport) -> f64,
+        ),
+        (
+            "stalwart_directory_query_errors_total",
+            "counter",
+            "Number of SQL directory queries that failed.",
+            |r| r.errors as f64,
+        ),
+        (
+            "stalwart_directory_query_latency_seconds_sum",
+            "counter",
+            "Total time spent executing SQL directory queries.",
+            |r| r.total_latency_ms / 1000.0,
+        ),
+        (
+            "stalwart_directory_query_latency_seconds_max",
+            "gauge",
+            "Slowest SQL directory query observed.",
+            |r| r.max_latency_ms / 1000.0,
+        ),
+    ] {
+        let _ = writeln!(out, "# HELP {metric} {help}");
+        let _ = writeln!(out, "# TYPE {metric} {typ}");
+        for report in reports {
+            let _ = writeln!(
+                out,
+                "{metric}{{query=\"{}\"}} {}",
+                report.query,
+                value(report)
+            );
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{to_prometheus, HealthStatus, QueryKind, SqlHealth, SqlMetrics};
+
+    #[test]
+    fn health_backoff() {
+        let health = SqlHealth::new(
+            "SELECT 1".to_string().into(),
+            Duration::from_secs(30),
+            2,
+            Duration::from_millis(100),
+            Duration::from_secs(1),
+        );
+
+        // First lookup validates the connection, subsequent ones don't
+        assert_eq!(health.status(), HealthStatus::Validate);
+        health.success();
+        assert_eq!(health.status(), HealthStatus::Healthy);
+
+        // Failures back off exponentially up to the maximum
+        assert_eq!(health.failure(), Duration::from_millis(100));
+        assert_eq!(health.status(), HealthStatus::Unavailable);
+        assert_eq!(health.failure(), Duration::from_millis(200));
+        assert_eq!(health.failure(), Duration::from_millis(400));
+        assert_eq!(health.failure(), Duration::from_millis(800));
+        assert_eq!(health.failure(), Duration::from_secs(1));
+        assert_eq!(health.failure(), Duration::from_secs(1));
+
+        health.success();
+        assert_eq!(health.status(), HealthStatus::Healthy);
+    }
+
+    #[test]
+    fn query_metrics() {
+        let metrics = SqlMetrics::default();
+        metrics.record(QueryKind::Name, Duration::from_millis(4), true);
+        metrics.record(QueryKind::Name, Duration::from_millis(10), false);
+        metrics.record(QueryKind::Domains, Duration::from_millis(1), true);
+
+        let report = metrics.report();
+        assert_eq!(report[0].query, "name");
+        assert_eq!(report[0].count, 2);
+        assert_eq!(report[0].errors, 1);
+        assert_eq!(report[0].total_latency_ms, 14.0);
+        assert_eq!(report[0].max_latency_ms, 10.0);
+        assert_eq!(report[6].query, "domains");
+        assert_eq!(report[6].count, 1);
+
+        let metrics = to_prometheus(&report);
+        for expected in [
+            "stalwart_directory_queries_total{query=\"name\"} 2",
+            "stalwart_directory_query_errors_total{query=\"name\"} 1",
+            "stalwart_directory_query_latency_seconds_max{query=\"name\"} 0.01",
+            "stalwart_directory_queries_total{query=\"domains\"} 1",
+        ] {
+            assert!(
+                metrics.contains(expected),
+                "missing {expected:?} in {metrics}"
+            );
+        }
+    }
+}
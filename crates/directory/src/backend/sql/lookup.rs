@@ -21,12 +21,17 @@
  * for more details.
 */
 
+use std::time::Instant;
+
 use mail_send::Credentials;
-use store::{NamedRows, Rows, Value};
+use store::{NamedRows, QueryResult, Rows, Value};
 
-use crate::{backend::internal::manage::ManageDirectory, Principal, QueryBy, Type};
+use crate::{backend::internal::manage::ManageDirectory, DirectoryError, Principal, QueryBy, Type};
 
-use super::{SqlDirectory, SqlMappings};
+use super::{
+    health::{HealthStatus, QueryKind},
+    SqlDirectory, SqlMappings,
+};
 
 impl SqlDirectory {
     pub async fn query(
@@ -42,9 +47,12 @@ impl SqlDirectory {
             QueryBy::Name(username) => {
                 account_name = username.to_string();
 
-                self.store
-                    .query::<NamedRows>(&self.mappings.query_name, vec![username.into()])
-                    .await?
+                self.run::<NamedRows>(
+                    QueryKind::Name,
+                    &self.mappings.query_name,
+                    vec![username.into()],
+                )
+                .await?
             }
             QueryBy::Id(uid) => {
                 if let Some(username) = self.data_store.get_account_name(uid).await? {
@@ -54,12 +62,12 @@ impl SqlDirectory {
                 }
                 account_id = Some(uid);
 
-                self.store
-                    .query::<NamedRows>(
-                        &self.mappings.query_name,
-                        vec![account_name.clone().into()],
-                    )
-                    .await?
+                self.run::<NamedRows>(
+                    QueryKind::Name,
+                    &self.mappings.query_name,
+                    vec![account_name.clone().into()],
+                )
+                .await?
             }
             QueryBy::Credentials(credentials) => {
                 let (username, secret_) = match credentials {
@@ -70,9 +78,12 @@ impl SqlDirectory {
                 account_name = username.to_string();
                 secret = secret_.into();
 
-                self.store
-                    .query::<NamedRows>(&self.mappings.query_name, vec![username.into()])
-                    .await?
+                self.run::<NamedRows>(
+                    QueryKind::Name,
+                    &self.mappings.query_name,
+                    vec![username.into()],
+                )
+                .await?
             }
         };
 
@@ -111,8 +122,8 @@ impl SqlDirectory {
         // Obtain members
         if return_member_of && !self.mappings.query_members.is_empty() {
             for row in self
-                .store
-                .query::<Rows>(
+                .run::<Rows>(
+                    QueryKind::Members,
                     &self.mappings.query_members,
                     vec![principal.name.clone().into()],
                 )
@@ -130,8 +141,8 @@ impl SqlDirectory {
         // Obtain emails
         if !self.mappings.query_emails.is_empty() {
             principal.emails = self
-                .store
-                .query::<Rows>(
+                .run::<Rows>(
+                    QueryKind::Emails,
                     &self.mappings.query_emails,
                     vec![principal.name.clone().into()],
                 )
@@ -144,8 +155,11 @@ impl SqlDirectory {
 
     pub async fn email_to_ids(&self, address: &str) -> crate::Result<Vec<u32>> {
         let names = self
-            .store
-            .query::<Rows>(&self.mappings.query_recipients, vec![address.into()])
+            .run::<Rows>(
+                QueryKind::Recipients,
+                &self.mappings.query_recipients,
+                vec![address.into()],
+            )
             .await?;
 
         let mut ids = Vec::with_capacity(names.rows.len());
@@ -160,42 +174,128 @@ impl SqlDirectory {
     }
 
     pub async fn rcpt(&self, address: &str) -> crate::Result<bool> {
-        self.store
-            .query::<bool>(
-                &self.mappings.query_recipients,
-                vec![address.to_string().into()],
-            )
-            .await
-            .map_err(Into::into)
+        self.run::<bool>(
+            QueryKind::Recipients,
+            &self.mappings.query_recipients,
+            vec![address.to_string().into()],
+        )
+        .await
     }
 
     pub async fn vrfy(&self, address: &str) -> crate::Result<Vec<String>> {
-        self.store
-            .query::<Rows>(
-                &self.mappings.query_verify,
-                vec![address.to_string().into()],
-            )
-            .await
-            .map(Into::into)
-            .map_err(Into::into)
+        self.run::<Rows>(
+            QueryKind::Verify,
+            &self.mappings.query_verify,
+            vec![address.to_string().into()],
+        )
+        .await
+        .map(Into::into)
     }
 
     pub async fn expn(&self, address: &str) -> crate::Result<Vec<String>> {
-        self.store
-            .query::<Rows>(
-                &self.mappings.query_expand,
-                vec![address.to_string().into()],
-            )
-            .await
-            .map(Into::into)
-            .map_err(Into::into)
+        self.run::<Rows>(
+            QueryKind::Expand,
+            &self.mappings.query_expand,
+            vec![address.to_string().into()],
+        )
+        .await
+        .map(Into::into)
     }
 
     pub async fn is_local_domain(&self, domain: &str) -> crate::Result<bool> {
-        self.store
-            .query::<bool>(&self.mappings.query_domains, vec![domain.into()])
-            .await
-            .map_err(Into::into)
+        self.run::<bool>(
+            QueryKind::Domains,
+            &self.mappings.query_domains,
+            vec![domain.into()],
+        )
+        .await
+    }
+
+    async fn run<T: QueryResult + std::fmt::Debug>(
+        &self,
+        kind: QueryKind,
+        query: &str,
+        params: Vec<Value<'_>>,
+    ) -> crate::Result<T> {
+        // Validate the connection before use, or fail fast while backing off
+        match self.health.status() {
+            HealthStatus::Healthy => (),
+            HealthStatus::Validate => {
+                let health_query = self.health.query.as_deref().unwrap_or_default();
+                let time = Instant::now();
+                let result = self.store.query::<bool>(health_query, vec![]).await;
+                self.metrics
+                    .record(QueryKind::Health, time.elapsed(), result.is_ok());
+                if let Err(err) = result {
+                    let retry_in = self.health.failure();
+                    tracing::warn!(
+                        context = "directory",
+                        event = "unhealthy",
+                        protocol = "sql",
+                        reason = %err,
+                        retry_in = ?retry_in,
+                        "SQL directory health check failed"
+                    );
+                    return Err(err.into());
+                }
+                self.health.success();
+            }
+            HealthStatus::Unavailable => {
+                return Err(DirectoryError::Pool(
+                    "SQL directory is unavailable, backing off".to_string(),
+                ));
+            }
+        }
+
+        let mut attempt = 0;
+        loop {
+            let time = Instant::now();
+            let result = self.store.query::<T>(query, params.clone()).await;
+            let elapsed = time.elapsed();
+            self.metrics.record(kind, elapsed, result.is_ok());
+
+            match result {
+                Ok(result) => {
+                    self.health.success();
+                    tracing::trace!(
+                        context = "directory",
+                        event = "query",
+                        protocol = "sql",
+                        query = kind.as_str(),
+                        elapsed = ?elapsed,
+                        "SQL directory query completed"
+                    );
+                    return Ok(result);
+                }
+                Err(err) if attempt < self.health.retry_attempts => {
+                    let delay = self.health.delay(attempt);
+                    tracing::debug!(
+                        context = "directory",
+                        event = "retry",
+                        protocol = "sql",
+                        query = kind.as_str(),
+                        attempt = attempt + 1,
+                        reason = %err,
+                        "SQL directory query failed, retrying in {delay:?}"
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(err) => {
+                    let retry_in = self.health.failure();
+                    tracing::warn!(
+                        context = "directory",
+                        event = "unavailable",
+                        protocol = "sql",
+                        query = kind.as_str(),
+                        reason = %err,
+                        retry_in = ?retry_in,
+                        "SQL directory query failed"
+                    );
+                    return Err(err.into());
+                }
+            }
+        }
     }
 }
 
@@ -224,6 +324,12 @@ impl SqlMappings {
                     if let Value::Integer(quota) = value {
                         principal.quota = quota as u32;
                     }
+                } else if name.eq_ignore_ascii_case(&self.column_referral) {
+                    if let Value::Text(text) = value {
+                        if !text.is_empty() {
+                            principal.referral = text.into_owned().into();
+                        }
+                    }
                 }
             }
         }
@@ -23,12 +23,17 @@
 
 use store::{LookupStore, Store};
 
+use self::health::{QueryReport, SqlHealth, SqlMetrics};
+
 pub mod config;
+pub mod health;
 pub mod lookup;
 
 pub struct SqlDirectory {
     store: LookupStore,
     mappings: SqlMappings,
+    health: SqlHealth,
+    metrics: SqlMetrics,
     pub(crate) data_store: Store,
 }
 
@@ -45,4 +50,11 @@ pub(crate) struct SqlMappings {
     column_secret: String,
     column_quota: String,
     column_type: String,
+    column_referral: String,
+}
+
+impl SqlDirectory {
+    pub fn query_report(&self) -> Vec<QueryReport> {
+        self.metrics.report()
+    }
 }
@@ -0,0 +1,239 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use store::{
+    write::{key::KeySerializer, now, BatchBuilder, DirectoryClass, ValueClass},
+    Deserialize, Serialize, ValueKey, U32_LEN, U64_LEN,
+};
+use utils::codec::leb128::Leb128Iterator;
+
+use crate::{backend::internal::deserialize_string_list, Directory};
+
+const STAT_ACCEPTED: u8 = 0;
+const STAT_BLOCKED: u8 = 1;
+const STAT_EXPIRED: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisposableAlias {
+    pub account_id: u32,
+    pub document_id: u32,
+    pub expires: Option<u64>,
+    pub blocked_senders: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AliasVerdict {
+    Accept(u32),
+    Expired,
+    Blocked,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct AliasStats {
+    pub accepted: u64,
+    pub blocked: u64,
+    pub expired: u64,
+}
+
+impl DisposableAlias {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires.map_or(false, |expires| expires <= now)
+    }
+
+    // Blocked senders are either full addresses or domain names, in which
+    // case subdomains are blocked as well.
+    pub fn is_blocked(&self, sender: &str) -> bool {
+        let sender = sender.to_lowercase();
+        let domain = sender
+            .rsplit_once('@')
+            .map_or(sender.as_str(), |(_, domain)| domain);
+
+        self.blocked_senders.iter().any(|blocked| {
+            if blocked.contains('@') {
+                *blocked == sender
+            } else {
+                domain == blocked
+                    || domain
+                        .strip_suffix(blocked.as_str())
+                        .map_or(false, |prefix| prefix.ends_with('.'))
+            }
+        })
+    }
+}
+
+impl Serialize for &DisposableAlias {
+    fn serialize(self) -> Vec<u8> {
+        let mut serializer = KeySerializer::new(
+            U32_LEN * 2
+                + U64_LEN
+                + 2
+                + self
+                    .blocked_senders
+                    .iter()
+                    .map(|s| s.len() + 1)
+                    .sum::<usize>(),
+        )
+        .write(1u8)
+        .write_leb128(self.account_id)
+        .write_leb128(self.document_id)
+        .write_leb128(self.expires.unwrap_or_default())
+        .write_leb128(self.blocked_senders.len());
+
+        for sender in &self.blocked_senders {
+            serializer = serializer
+                .write_leb128(sender.len())
+                .write(sender.as_bytes());
+        }
+
+        serializer.finalize()
+    }
+}
+
+impl Deserialize for DisposableAlias {
+    fn deserialize(bytes: &[u8]) -> store::Result<Self> {
+        let mut bytes = bytes.iter();
+        (|| {
+            if bytes.next()? != &1 {
+                return None;
+            }
+            Some(DisposableAlias {
+                account_id: bytes.next_leb128()?,
+                document_id: bytes.next_leb128()?,
+                expires: bytes.next_leb128::<u64>().map(|expires| {
+                    if expires != 0 {
+                        Some(expires)
+                    } else {
+                        None
+                    }
+                })?,
+                blocked_senders: deserialize_string_list(&mut bytes)?,
+            })
+        })()
+        .ok_or_else(|| store::Error::InternalError("Failed to deserialize alias".into()))
+    }
+}
+
+impl Directory {
+    pub async fn alias_get(&self, address: &str) -> crate::Result<Option<DisposableAlias>> {
+        self.store()
+            .get_value::<DisposableAlias>(ValueKey::from(ValueClass::Directory(
+                DirectoryClass::Alias(address.to_lowercase().into_bytes()),
+            )))
+            .await
+            .map_err(Into::into)
+    }
+
+    pub async fn alias_set(
+        &self,
+        address: &str,
+        alias: Option<&DisposableAlias>,
+    ) -> crate::Result<()> {
+        let mut batch = BatchBuilder::new();
+        if let Some(alias) = alias {
+            batch.set(
+                DirectoryClass::Alias(address.to_lowercase().into_bytes()),
+                alias.serialize(),
+            );
+        } else {
+            // Counters cannot be removed, reset them instead
+            let stats = self.alias_stats(address).await?;
+            let address = address.to_lowercase().into_bytes();
+            for (stat, value) in [
+                (STAT_ACCEPTED, stats.accepted),
+                (STAT_BLOCKED, stats.blocked),
+                (STAT_EXPIRED, stats.expired),
+            ] {
+                if value > 0 {
+                    batch.add(
+                        DirectoryClass::AliasStats {
+                            address: address.clone(),
+                            stat,
+                        },
+                        -(value as i64),
+                    );
+                }
+            }
+            batch.clear(DirectoryClass::Alias(address));
+        }
+        self.store().write(batch.build()).await?;
+
+        Ok(())
+    }
+
+    // Decides whether a message from the sender may be delivered to the
+    // alias and updates its statistics. Returns None for non-alias addresses.
+    pub async fn alias_verdict(
+        &self,
+        address: &str,
+        sender: &str,
+    ) -> crate::Result<Option<AliasVerdict>> {
+        let alias = if let Some(alias) = self.alias_get(address).await? {
+            alias
+        } else {
+            return Ok(None);
+        };
+        let (verdict, stat) = if alias.is_expired(now()) {
+            (AliasVerdict::Expired, STAT_EXPIRED)
+        } else if !sender.is_empty() && alias.is_blocked(sender) {
+            (AliasVerdict::Blocked, STAT_BLOCKED)
+        } else {
+            (AliasVerdict::Accept(alias.account_id), STAT_ACCEPTED)
+        };
+
+        let mut batch = BatchBuilder::new();
+        batch.add(
+            DirectoryClass::AliasStats {
+                address: address.to_lowercase().into_bytes(),
+                stat,
+            },
+            1,
+        );
+        self.store().write(batch.build()).await?;
+
+        Ok(Some(verdict))
+    }
+
+    pub async fn alias_stats(&self, address: &str) -> crate::Result<AliasStats> {
+        let address = address.to_lowercase().into_bytes();
+        let mut stats = [0u64; 3];
+        for (stat, value) in [STAT_ACCEPTED, STAT_BLOCKED, STAT_EXPIRED]
+            .into_iter()
+            .zip(stats.iter_mut())
+        {
+            *value = self
+                .store()
+                .get_counter(DirectoryClass::AliasStats {
+                    address: address.clone(),
+                    stat,
+                })
+                .await?
+                .max(0) as u64;
+        }
+
+        Ok(AliasStats {
+            accepted: stats[0],
+            blocked: stats[1],
+            expired: stats[2],
+        })
+    }
+}
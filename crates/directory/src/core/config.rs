@@ -43,7 +43,7 @@ use crate::{
     AddressMapping, Directories, Directory, DirectoryInner, Lookup,
 };
 
-use super::cache::CachedDirectory;
+use super::{cache::CachedDirectory, reserved::ReservedAddresses};
 
 #[allow(async_fn_in_trait)]
 pub trait ConfigDirectory {
@@ -150,6 +150,10 @@ impl ConfigDirectory for Config {
                     self,
                     ("directory", id, "options.subaddressing"),
                 )?,
+                reserved: ReservedAddresses::from_config(
+                    self,
+                    ("directory", id, "options.reserved"),
+                )?,
                 cache: CachedDirectory::try_from_config(self, ("directory", id))?,
                 blocked_ips: servers.blocked_ips.clone(),
             });
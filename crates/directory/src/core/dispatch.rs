@@ -24,11 +24,17 @@
 use std::net::IpAddr;
 
 use mail_send::Credentials;
-use store::Store;
+use store::{write::now, Store};
 
 use crate::{
-    backend::internal::lookup::DirectoryStore, AuthResult, Directory, DirectoryInner, Principal,
-    QueryBy,
+    backend::{
+        internal::{
+            lookup::DirectoryStore, manage::ManageDirectory, PrincipalField, PrincipalUpdate,
+            PrincipalValue,
+        },
+        sql::health::QueryReport,
+    },
+    AuthResult, Directory, DirectoryError, DirectoryInner, Principal, QueryBy,
 };
 
 impl Directory {
@@ -88,17 +94,65 @@ impl Directory {
         }
     }
 
+    /// Looks up the first principal matching one of the identities, either by
+    /// e-mail address or by name. Catch-all addresses are not considered.
+    pub async fn query_identities(
+        &self,
+        identities: &[String],
+        return_member_of: bool,
+    ) -> crate::Result<Option<Principal<u32>>> {
+        for identity in identities {
+            if identity.contains('@') {
+                let ids = match &self.store {
+                    DirectoryInner::Internal(store) => store.email_to_ids(identity).await,
+                    DirectoryInner::Ldap(store) => store.email_to_ids(identity).await,
+                    DirectoryInner::Sql(store) => store.email_to_ids(identity).await,
+                    DirectoryInner::Imap(store) => store.email_to_ids(identity).await,
+                    DirectoryInner::Smtp(store) => store.email_to_ids(identity).await,
+                    DirectoryInner::Memory(store) => store.email_to_ids(identity).await,
+                }?;
+                if ids.len() == 1 {
+                    if let Some(principal) =
+                        self.query(QueryBy::Id(ids[0]), return_member_of).await?
+                    {
+                        return Ok(Some(principal));
+                    }
+                }
+            }
+
+            if let Some(principal) = self
+                .query(QueryBy::Name(identity), return_member_of)
+                .await?
+            {
+                return Ok(Some(principal));
+            }
+        }
+
+        Ok(None)
+    }
+
     pub async fn email_to_ids(&self, email: &str) -> crate::Result<Vec<u32>> {
+        // Reserved addresses are always delivered to their role mailbox
+        if let Some(route) = self.reserved_route(email).await? {
+            return self.email_to_ids_unrouted(&route).await;
+        }
+
+        // Disposable aliases deliver to their owner until they expire
+        if let Some(alias) = self.alias_get(email).await? {
+            return Ok(if !alias.is_expired(now()) {
+                vec![alias.account_id]
+            } else {
+                vec![]
+            });
+        }
+
+        self.email_to_ids_unrouted(email).await
+    }
+
+    pub(crate) async fn email_to_ids_unrouted(&self, email: &str) -> crate::Result<Vec<u32>> {
         let mut address = self.subaddressing.to_subaddress(email);
         for _ in 0..2 {
-            let result = match &self.store {
-                DirectoryInner::Internal(store) => store.email_to_ids(address.as_ref()).await,
-                DirectoryInner::Ldap(store) => store.email_to_ids(address.as_ref()).await,
-                DirectoryInner::Sql(store) => store.email_to_ids(address.as_ref()).await,
-                DirectoryInner::Imap(store) => store.email_to_ids(address.as_ref()).await,
-                DirectoryInner::Smtp(store) => store.email_to_ids(address.as_ref()).await,
-                DirectoryInner::Memory(store) => store.email_to_ids(address.as_ref()).await,
-            }?;
+            let result = self.store_email_to_ids(address.as_ref()).await?;
 
             if !result.is_empty() {
                 return Ok(result);
@@ -112,6 +166,17 @@ impl Directory {
         Ok(vec![])
     }
 
+    pub(crate) async fn store_email_to_ids(&self, address: &str) -> crate::Result<Vec<u32>> {
+        match &self.store {
+            DirectoryInner::Internal(store) => store.email_to_ids(address).await,
+            DirectoryInner::Ldap(store) => store.email_to_ids(address).await,
+            DirectoryInner::Sql(store) => store.email_to_ids(address).await,
+            DirectoryInner::Imap(store) => store.email_to_ids(address).await,
+            DirectoryInner::Smtp(store) => store.email_to_ids(address).await,
+            DirectoryInner::Memory(store) => store.email_to_ids(address).await,
+        }
+    }
+
     pub async fn is_local_domain(&self, domain: &str) -> crate::Result<bool> {
         // Check cache
         if let Some(cache) = &self.cache {
@@ -137,7 +202,56 @@ impl Directory {
         Ok(result)
     }
 
+    // Returns `None` for directories that can only tell whether a given
+    // domain is local, rather than enumerate them.
+    pub async fn list_domains(&self) -> crate::Result<Option<Vec<String>>> {
+        match &self.store {
+            DirectoryInner::Internal(store) => store.list_domains(None, 0).await.map(Some),
+            DirectoryInner::Memory(store) => Ok(Some(store.domains().cloned().collect())),
+            DirectoryInner::Ldap(_)
+            | DirectoryInner::Sql(_)
+            | DirectoryInner::Imap(_)
+            | DirectoryInner::Smtp(_) => Ok(None),
+        }
+    }
+
+    pub async fn update_secrets(&self, account_id: u32, secrets: Vec<String>) -> crate::Result<()> {
+        match &self.store {
+            DirectoryInner::Internal(store) => {
+                store
+                    .update_account(
+                        QueryBy::Id(account_id),
+                        vec![PrincipalUpdate::set(
+                            PrincipalField::Secrets,
+                            PrincipalValue::StringList(secrets),
+                        )],
+                    )
+                    .await
+            }
+            DirectoryInner::Ldap(_) => Err(DirectoryError::unsupported("ldap", "update_secrets")),
+            DirectoryInner::Sql(_) => Err(DirectoryError::unsupported("sql", "update_secrets")),
+            DirectoryInner::Imap(_) => Err(DirectoryError::unsupported("imap", "update_secrets")),
+            DirectoryInner::Smtp(_) => Err(DirectoryError::unsupported("smtp", "update_secrets")),
+            DirectoryInner::Memory(_) => {
+                Err(DirectoryError::unsupported("memory", "update_secrets"))
+            }
+        }
+    }
+
     pub async fn rcpt(&self, email: &str) -> crate::Result<bool> {
+        // Reserved addresses are always delivered to their role mailbox
+        if let Some(route) = self.reserved_route(email).await? {
+            return self.rcpt_unrouted(&route).await;
+        }
+
+        if let Some(alias) = self.alias_get(email).await? {
+            return Ok(!alias.is_expired(now()));
+        }
+
+        self.rcpt_unrouted(email).await
+    }
+
+    pub(crate) async fn rcpt_unrouted(&self, email: &str) -> crate::Result<bool> {
         // Expand subaddress
         let mut address = self.subaddressing.to_subaddress(email);
 
@@ -209,7 +323,14 @@ impl Directory {
         }
     }
 
-    fn store(&self) -> &Store {
+    pub fn query_report(&self) -> Option<Vec<QueryReport>> {
+        match &self.store {
+            DirectoryInner::Sql(store) => Some(store.query_report()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn store(&self) -> &Store {
         match &self.store {
             DirectoryInner::Internal(store) => store,
             DirectoryInner::Ldap(store) => &store.data_store,
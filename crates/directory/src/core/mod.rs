@@ -21,7 +21,10 @@
  * for more details.
 */
 
+pub mod alias;
 pub mod cache;
 pub mod config;
 pub mod dispatch;
+pub mod reserved;
 pub mod secret;
+pub mod suggest;
@@ -0,0 +1,188 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+use ahash::AHashMap;
+use utils::config::{utils::AsKey, Config};
+
+use crate::{
+    backend::internal::{PrincipalAction, PrincipalField, PrincipalUpdate, PrincipalValue},
+    Directory, DirectoryError, ManagementError, Principal,
+};
+
+// Role addresses required by RFC 5321 and RFC 2142
+const DEFAULT_RESERVED: [&str; 3] = ["postmaster", "abuse", "hostmaster"];
+
+#[derive(Debug, Default)]
+pub struct ReservedAddresses {
+    pub names: AHashMap<String, Option<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ReservedAddressReport {
+    pub address: String,
+    pub route: Option<String>,
+    #[serde(rename = "routeExists")]
+    pub route_exists: bool,
+    #[serde(rename = "conflicts")]
+    pub conflicts: Vec<u32>,
+}
+
+impl ReservedAddresses {
+    pub fn from_config(config: &Config, key: impl AsKey) -> utils::config::Result<Self> {
+        let key = key.as_key();
+        let mut names = AHashMap::new();
+        let mut has_names = false;
+
+        for (_, name) in config.values((key.as_str(), "names")) {
+            names.insert(name.trim().to_lowercase(), None);
+            has_names = true;
+        }
+        if !has_names {
+            for name in DEFAULT_RESERVED {
+                names.insert(name.to_string(), None);
+            }
+        }
+        for name in config.sub_keys((key.as_str(), "route"), "") {
+            let route = config
+                .value_require((key.as_str(), "route", name))?
+                .trim()
+                .to_lowercase();
+            if route.is_empty() {
+                return Err(format!(
+                    "Empty route for reserved address {name:?} in {key:?}."
+                ));
+            }
+            names.insert(name.trim().to_lowercase(), Some(route));
+        }
+
+        Ok(ReservedAddresses { names })
+    }
+
+    pub fn is_reserved(&self, address: &str) -> bool {
+        let local_part = address
+            .rsplit_once('@')
+            .map_or(address, |(local_part, _)| local_part);
+        !local_part.is_empty() && self.names.contains_key(&local_part.to_lowercase())
+    }
+
+    // Returns the address a reserved address is routed to. Routes without
+    // a domain part are resolved against the domain of the address.
+    pub fn route<'x>(&'x self, address: &str) -> Option<Cow<'x, str>> {
+        let (local_part, domain_part) = address.rsplit_once('@')?;
+        let route = self.names.get(&local_part.to_lowercase())?.as_ref()?;
+        Some(if route.contains('@') {
+            Cow::Borrowed(route.as_str())
+        } else {
+            Cow::Owned(format!("{route}@{domain_part}"))
+        })
+    }
+
+    pub fn assert_principal(&self, principal: &Principal<String>) -> crate::Result<()> {
+        self.assert_value(PrincipalField::Name, &principal.name)?;
+        for email in &principal.emails {
+            self.assert_value(PrincipalField::Emails, email)?;
+        }
+        Ok(())
+    }
+
+    pub fn assert_changes(&self, changes: &[PrincipalUpdate]) -> crate::Result<()> {
+        for change in changes {
+            if matches!(change.field, PrincipalField::Name | PrincipalField::Emails)
+                && change.action != PrincipalAction::RemoveItem
+            {
+                match &change.value {
+                    PrincipalValue::String(value) => {
+                        self.assert_value(change.field, value)?;
+                    }
+                    PrincipalValue::StringList(values) => {
+                        for value in values {
+                            self.assert_value(change.field, value)?;
+                        }
+                    }
+                    PrincipalValue::Integer(_) => (),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn assert_value(&self, field: PrincipalField, value: &str) -> crate::Result<()> {
+        if self.is_reserved(value) {
+            Err(DirectoryError::Management(ManagementError::Reserved {
+                field,
+                value: value.to_string(),
+            }))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Directory {
+    pub(crate) async fn reserved_route(&self, address: &str) -> crate::Result<Option<String>> {
+        let address = self.subaddressing.to_subaddress(address);
+        if let Some(route) = self.reserved.route(address.as_ref()) {
+            if let Some((_, domain_part)) = address.rsplit_once('@') {
+                if self.is_local_domain(domain_part).await? {
+                    return Ok(Some(route.into_owned()));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Lists the reserved addresses of each local domain along with any
+    // principals holding them and whether their route can be delivered to.
+    pub async fn reserved_report(&self) -> crate::Result<Vec<ReservedAddressReport>> {
+        let mut names = self.reserved.names.keys().collect::<Vec<_>>();
+        names.sort_unstable();
+        let mut domains = self.list_domains().await?.unwrap_or_default();
+        domains.sort_unstable();
+
+        let mut report = Vec::with_capacity(domains.len() * names.len());
+        for domain in domains {
+            for name in &names {
+                let address = format!("{name}@{domain}");
+                let route = self.reserved.route(&address).map(|r| r.into_owned());
+                let route_exists = if let Some(route) = &route {
+                    self.rcpt_unrouted(route).await?
+                } else {
+                    false
+                };
+                let conflicts = self.store_email_to_ids(&address).await?;
+
+                report.push(ReservedAddressReport {
+                    address,
+                    route,
+                    route_exists,
+                    conflicts,
+                });
+            }
+        }
+
+        Ok(report)
+    }
+}
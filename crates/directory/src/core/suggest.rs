@@ -0,0 +1,139 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::{backend::internal::lookup::DirectoryStore, Directory, DirectoryInner};
+
+impl Directory {
+    // Returns the addresses of the domain of `address` whose local part is
+    // within `max_distance` edits of it, closest matches first. Only the
+    // internal and memory directories can enumerate their addresses.
+    pub async fn suggest(
+        &self,
+        address: &str,
+        max_distance: usize,
+        max_results: usize,
+    ) -> crate::Result<Vec<String>> {
+        let address = self.subaddressing.to_subaddress(address);
+        let (local_part, domain_part) = if let Some(parts) = address.rsplit_once('@') {
+            parts
+        } else {
+            return Ok(vec![]);
+        };
+        if local_part.is_empty() || max_distance == 0 || max_results == 0 {
+            return Ok(vec![]);
+        }
+
+        let addresses = match &self.store {
+            DirectoryInner::Internal(store) => store.domain_addresses(domain_part).await?,
+            DirectoryInner::Memory(store) => store.domain_addresses(domain_part).await?,
+            DirectoryInner::Ldap(_)
+            | DirectoryInner::Sql(_)
+            | DirectoryInner::Imap(_)
+            | DirectoryInner::Smtp(_) => return Ok(vec![]),
+        };
+
+        let mut suggestions = addresses
+            .into_iter()
+            .filter_map(|address| {
+                let distance = edit_distance(
+                    local_part,
+                    address
+                        .rsplit_once('@')
+                        .map_or("", |(local_part, _)| local_part),
+                    max_distance,
+                )?;
+                if distance > 0 && !self.reserved.is_reserved(&address) {
+                    Some((distance, address))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        suggestions.sort_unstable();
+
+        Ok(suggestions
+            .into_iter()
+            .take(max_results)
+            .map(|(_, address)| address)
+            .collect())
+    }
+}
+
+// Optimal string alignment distance, counting adjacent transpositions as a
+// single edit. Returns `None` as soon as the distance exceeds `max_distance`.
+pub fn edit_distance(a: &str, b: &str, max_distance: usize) -> Option<usize> {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    if a.len().abs_diff(b.len()) > max_distance {
+        return None;
+    }
+
+    let mut prev_prev = vec![0; b.len() + 1];
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        current[0] = i;
+        let mut row_min = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (prev[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(prev_prev[j - 2] + 1);
+            }
+            current[j] = distance;
+            row_min = row_min.min(distance);
+        }
+        if row_min > max_distance {
+            return None;
+        }
+        std::mem::swap(&mut prev_prev, &mut prev);
+        std::mem::swap(&mut prev, &mut current);
+    }
+
+    Some(prev[b.len()]).filter(|distance| *distance <= max_distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::edit_distance;
+
+    #[test]
+    fn edit_distance_bounds() {
+        for (a, b, max, expected) in [
+            ("john", "john", 2, Some(0)),
+            ("jhon", "john", 2, Some(1)),
+            ("jon", "john", 2, Some(1)),
+            ("johnn", "john", 2, Some(1)),
+            ("jane", "john", 2, None),
+            ("jane", "john", 3, Some(3)),
+            ("", "ab", 2, Some(2)),
+            ("a", "abcd", 2, None),
+            ("sales", "slaes", 1, Some(1)),
+        ] {
+            assert_eq!(edit_distance(a, b, max), expected, "{a} vs {b}");
+        }
+    }
+}
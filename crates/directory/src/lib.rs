@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use core::cache::CachedDirectory;
+use core::{cache::CachedDirectory, reserved::ReservedAddresses};
 use std::{borrow::Cow, fmt::Debug, sync::Arc};
 
 use ahash::AHashMap;
@@ -46,6 +46,7 @@ pub struct Directory {
     pub store: DirectoryInner,
     pub catch_all: AddressMapping,
     pub subaddressing: AddressMapping,
+    pub reserved: ReservedAddresses,
     pub cache: Option<CachedDirectory>,
     pub blocked_ips: Arc<BlockedIps>,
 }
@@ -68,6 +69,8 @@ pub struct Principal<T> {
     pub member_of: Vec<T>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referral: Option<String>,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
@@ -109,6 +112,10 @@ pub enum ManagementError {
         value: String,
     },
     NotFound(String),
+    Reserved {
+        field: PrincipalField,
+        value: String,
+    },
 }
 
 pub enum DirectoryInner {
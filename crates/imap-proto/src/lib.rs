@@ -89,6 +89,9 @@ pub enum Command {
 
     // RFC 2971
     Id,
+
+    // RFC 5465
+    Notify,
 }
 
 impl Command {
@@ -144,6 +147,9 @@ pub enum ResponseCode {
     PrivacyRequired,
     ReadOnly,
     ReadWrite,
+    Referral {
+        url: String,
+    },
     ServerBug,
     TryCreate,
     UidNext,
@@ -167,6 +173,15 @@ pub enum ResponseCode {
 
     // USEATTR
     UseAttr,
+
+    // CATENATE
+    BadUrl {
+        url: String,
+    },
+    TooBig,
+
+    // NOTIFY
+    BadEvent,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
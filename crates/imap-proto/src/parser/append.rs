@@ -23,7 +23,8 @@
 
 use crate::{
     protocol::{
-        append::{self, Message},
+        append::{self, CatenatePart, ImapUrl, Message},
+        fetch::Section,
         Flag, ProtocolVersion,
     },
     receiver::{Request, Token},
@@ -38,6 +39,8 @@ enum State {
     Flags,
     UTF8,
     UTF8Data,
+    Catenate,
+    CatenateData,
 }
 
 impl Request<Command> {
@@ -63,6 +66,7 @@ impl Request<Command> {
                         message: vec![],
                         flags: vec![],
                         received_at: None,
+                        catenate: vec![],
                     };
                     let mut state = State::None;
                     let mut seen_flags = false;
@@ -76,6 +80,7 @@ impl Request<Command> {
                                         State::Flags
                                     }
                                     State::UTF8 => State::UTF8Data,
+                                    State::Catenate => State::CatenateData,
                                     _ => {
                                         return Err((
                                             self.tag.as_str(),
@@ -86,7 +91,7 @@ impl Request<Command> {
                                 };
                             }
                             Token::ParenthesisClose => match state {
-                                State::None | State::UTF8 => {
+                                State::None | State::UTF8 | State::Catenate => {
                                     return Err((
                                         self.tag.as_str(),
                                         "Invalid closing parenthesis found.",
@@ -99,11 +104,23 @@ impl Request<Command> {
                                 State::UTF8Data => {
                                     break;
                                 }
+                                State::CatenateData => {
+                                    if message.catenate.is_empty() {
+                                        return Err((
+                                            self.tag.as_str(),
+                                            "CATENATE requires at least one part.",
+                                        )
+                                            .into());
+                                    }
+                                    break;
+                                }
                             },
                             Token::Argument(value) => match state {
                                 State::None => {
                                     if value.eq_ignore_ascii_case(b"utf8") {
                                         state = State::UTF8;
+                                    } else if value.eq_ignore_ascii_case(b"catenate") {
+                                        state = State::Catenate;
                                     } else if matches!(tokens.peek(), Some(Token::Argument(_)))
                                         && value.len() <= 28
                                         && !value.contains(&b'\n')
@@ -135,6 +152,38 @@ impl Request<Command> {
                                     )
                                         .into());
                                 }
+                                State::Catenate => {
+                                    return Err((
+                                        self.tag.as_str(),
+                                        "Expected parenthesis after CATENATE.",
+                                    )
+                                        .into());
+                                }
+                                State::CatenateData => {
+                                    let part =
+                                        match tokens.next() {
+                                            Some(Token::Argument(url))
+                                                if value.eq_ignore_ascii_case(b"url") =>
+                                            {
+                                                CatenatePart::Url(String::from_utf8(url).map_err(
+                                                    |_| (self.tag.as_str(), "Invalid URL."),
+                                                )?)
+                                            }
+                                            Some(Token::Argument(text))
+                                                if value.eq_ignore_ascii_case(b"text") =>
+                                            {
+                                                CatenatePart::Text(text)
+                                            }
+                                            _ => {
+                                                return Err((
+                                                    self.tag.as_str(),
+                                                    "Expected URL or TEXT catenate part.",
+                                                )
+                                                    .into())
+                                            }
+                                        };
+                                    message.catenate.push(part);
+                                }
                                 State::UTF8Data => {
                                     if message.message.is_empty() {
                                         message.message = value;
@@ -164,12 +213,115 @@ impl Request<Command> {
     }
 }
 
+impl ImapUrl {
+    // Parses IMAP URLs (RFC 5092) referencing a message or message part,
+    // either absolute or relative to the server.
+    pub fn parse(url: &str) -> Option<Self> {
+        let path = if let Some(url) = url
+            .strip_prefix("imap://")
+            .or_else(|| url.strip_prefix("IMAP://"))
+        {
+            &url[url.find('/')?..]
+        } else {
+            url
+        };
+
+        // Mailbox names may contain the hierarchy separator
+        let (mailbox, parts) = path.strip_prefix('/')?.split_once("/;")?;
+
+        // Mailbox and UIDVALIDITY
+        let mut mailbox = mailbox.split(';');
+        let mailbox_name = percent_decode(mailbox.next()?)?;
+        let mut uid_validity = None;
+        for param in mailbox {
+            let (key, value) = param.split_once('=')?;
+            if key.eq_ignore_ascii_case("uidvalidity") {
+                uid_validity = Some(value.parse().ok()?);
+            } else {
+                return None;
+            }
+        }
+        if mailbox_name.is_empty() {
+            return None;
+        }
+
+        let mut url = ImapUrl {
+            mailbox_name,
+            uid_validity,
+            uid: 0,
+            sections: vec![],
+            partial: None,
+        };
+        for part in parts.split("/;") {
+            for param in part.split(';') {
+                let (key, value) = param.split_once('=')?;
+                if key.eq_ignore_ascii_case("uid") {
+                    url.uid = value.parse().ok()?;
+                } else if key.eq_ignore_ascii_case("section") {
+                    url.sections = parse_url_section(&percent_decode(value)?)?;
+                } else if key.eq_ignore_ascii_case("partial") {
+                    url.partial = Some(match value.split_once('.') {
+                        Some((offset, length)) => (offset.parse().ok()?, length.parse().ok()?),
+                        None => {
+                            let offset: u32 = value.parse().ok()?;
+                            (offset, u32::MAX - offset)
+                        }
+                    });
+                } else if !key.eq_ignore_ascii_case("expire")
+                    && !key.eq_ignore_ascii_case("urlauth")
+                {
+                    return None;
+                }
+            }
+        }
+
+        if url.uid != 0 {
+            Some(url)
+        } else {
+            None
+        }
+    }
+}
+
+fn parse_url_section(value: &str) -> Option<Vec<Section>> {
+    let mut sections = Vec::new();
+    for part in value.split('.') {
+        sections.push(if let Ok(num) = part.parse::<u32>() {
+            Section::Part { num }
+        } else if part.eq_ignore_ascii_case("header") {
+            Section::Header
+        } else if part.eq_ignore_ascii_case("text") {
+            Section::Text
+        } else if part.eq_ignore_ascii_case("mime") {
+            Section::Mime
+        } else {
+            return None;
+        });
+    }
+    Some(sections)
+}
+
+fn percent_decode(value: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut iter = value.bytes();
+    while let Some(ch) = iter.next() {
+        if ch == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(ch);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
 #[cfg(test)]
 mod tests {
 
     use crate::{
         protocol::{
-            append::{self, Message},
+            append::{self, CatenatePart, ImapUrl, Message},
+            fetch::Section,
             Flag, ProtocolVersion,
         },
         receiver::{Error, Receiver},
@@ -189,6 +341,7 @@ mod tests {
                         message: vec![b'a'],
                         flags: vec![Flag::Seen],
                         received_at: None,
+                        catenate: vec![],
                     }],
                 },
             ),
@@ -201,6 +354,7 @@ mod tests {
                         message: vec![b'a'],
                         flags: vec![Flag::Seen, Flag::Draft, Flag::MDNSent],
                         received_at: None,
+                        catenate: vec![],
                     }],
                 },
             ),
@@ -213,6 +367,7 @@ mod tests {
                         message: vec![b'a'],
                         flags: vec![Flag::Junk],
                         received_at: Some(760689784),
+                        catenate: vec![],
                     }],
                 },
             ),
@@ -225,6 +380,7 @@ mod tests {
                         message: vec![b'a'],
                         flags: vec![],
                         received_at: Some(1668977999),
+                        catenate: vec![],
                     }],
                 },
             ),
@@ -237,6 +393,7 @@ mod tests {
                         message: vec![b'a'],
                         flags: vec![],
                         received_at: Some(1668977999),
+                        catenate: vec![],
                     }],
                 },
             ),
@@ -249,6 +406,7 @@ mod tests {
                         message: vec![b'h', b'e', b'l', b'l', b'o'],
                         flags: vec![Flag::Draft],
                         received_at: None,
+                        catenate: vec![],
                     }],
                 },
             ),
@@ -261,6 +419,29 @@ mod tests {
                         message: vec![b'h', b'e', b'l', b'l', b'o'],
                         flags: vec![Flag::Draft],
                         received_at: Some(1668977999),
+                        catenate: vec![],
+                    }],
+                },
+            ),
+            (
+                concat!(
+                    "A003 APPEND Drafts (\\Seen) CATENATE (URL \"/Drafts;UIDVALIDITY=385759045/;",
+                    "UID=20/;SECTION=HEADER\" TEXT {4+}\r\nabcd URL \"/INBOX/;UID=1\")\r\n"
+                ),
+                append::Arguments {
+                    tag: "A003".to_string(),
+                    mailbox_name: "Drafts".to_string(),
+                    messages: vec![Message {
+                        message: vec![],
+                        flags: vec![Flag::Seen],
+                        received_at: None,
+                        catenate: vec![
+                            CatenatePart::Url(
+                                "/Drafts;UIDVALIDITY=385759045/;UID=20/;SECTION=HEADER".to_string(),
+                            ),
+                            CatenatePart::Text(b"abcd".to_vec()),
+                            CatenatePart::Url("/INBOX/;UID=1".to_string()),
+                        ],
                     }],
                 },
             ),
@@ -323,6 +504,7 @@ mod tests {
                                     .to_vec(),
                                     flags: vec![Flag::Seen],
                                     received_at: None,
+                                    catenate: vec![],
                                 },
                                 Message {
                                     message: concat!(
@@ -339,6 +521,7 @@ mod tests {
                                     .to_vec(),
                                     flags: vec![Flag::Seen],
                                     received_at: Some(760689784),
+                                    catenate: vec![],
                                 }
                             ],
                         },
@@ -350,5 +533,67 @@ mod tests {
                 },
             }
         }
+
+        // Empty CATENATE
+        assert!(receiver
+            .parse(&mut "A004 APPEND Drafts CATENATE ()\r\n".as_bytes().iter())
+            .unwrap()
+            .parse_append(ProtocolVersion::Rev1)
+            .is_err());
+    }
+
+    #[test]
+    fn parse_imap_url() {
+        for (url, expected) in [
+            (
+                "/Drafts;UIDVALIDITY=385759045/;UID=20/;SECTION=1.2.MIME",
+                Some(ImapUrl {
+                    mailbox_name: "Drafts".to_string(),
+                    uid_validity: Some(385759045),
+                    uid: 20,
+                    sections: vec![
+                        Section::Part { num: 1 },
+                        Section::Part { num: 2 },
+                        Section::Mime,
+                    ],
+                    partial: None,
+                }),
+            ),
+            (
+                "imap://jdoe@example.com/Sent%20Items/;UID=3;PARTIAL=10.20",
+                Some(ImapUrl {
+                    mailbox_name: "Sent Items".to_string(),
+                    uid_validity: None,
+                    uid: 3,
+                    sections: vec![],
+                    partial: Some((10, 20)),
+                }),
+            ),
+            (
+                "/INBOX/;UID=1/;SECTION=TEXT;EXPIRE=2024-01-01T00:00:00Z;URLAUTH=anonymous:internal:abc",
+                Some(ImapUrl {
+                    mailbox_name: "INBOX".to_string(),
+                    uid_validity: None,
+                    uid: 1,
+                    sections: vec![Section::Text],
+                    partial: None,
+                }),
+            ),
+            (
+                "/Archive/2023;UIDVALIDITY=1/;UID=2",
+                Some(ImapUrl {
+                    mailbox_name: "Archive/2023".to_string(),
+                    uid_validity: Some(1),
+                    uid: 2,
+                    sections: vec![],
+                    partial: None,
+                }),
+            ),
+            ("/INBOX", None),
+            (";UID=1", None),
+            ("/INBOX/;UID=1/;SECTION=BODY", None),
+        ] {
+            assert_eq!(ImapUrl::parse(url), expected, "{url}");
+        }
     }
 }
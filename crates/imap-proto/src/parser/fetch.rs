@@ -26,7 +26,7 @@ use std::iter::Peekable;
 use std::vec::IntoIter;
 
 use crate::{
-    protocol::fetch::{self, Attribute, Section},
+    protocol::fetch::{self, AnnotationAttribute, Attribute, Section},
     receiver::{Request, Token},
     Command,
 };
@@ -336,6 +336,10 @@ impl Request<Command> {
                         attributes.push_unique(Attribute::EmailId);
                     } else if value.eq_ignore_ascii_case(b"THREADID") {
                         attributes.push_unique(Attribute::ThreadId);
+                    } else if value.eq_ignore_ascii_case(b"ANNOTATION") {
+                        attributes.push_unique(
+                            parse_annotation(&mut tokens).map_err(|v| (self.tag.as_str(), v))?,
+                        );
                     } else {
                         return Err((
                             self.tag,
@@ -420,6 +424,93 @@ impl Request<Command> {
     }
 }
 
+pub fn parse_annotation(tokens: &mut Peekable<IntoIter<Token>>) -> super::Result<Attribute> {
+    if tokens
+        .next()
+        .map_or(true, |token| !token.is_parenthesis_open())
+    {
+        return Err("Expected '(' after 'ANNOTATION'.".into());
+    }
+
+    // Parse entries
+    let mut entries = Vec::new();
+    let is_list = tokens
+        .peek()
+        .map_or(false, |token| token.is_parenthesis_open());
+    if is_list {
+        tokens.next();
+    }
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Argument(value) => {
+                entries.push(
+                    String::from_utf8(value)
+                        .map_err(|_| Cow::from("Invalid UTF-8 in annotation entry."))?,
+                );
+                if !is_list {
+                    break;
+                }
+            }
+            Token::ParenthesisClose if is_list => break,
+            _ => return Err("Expected annotation entry.".into()),
+        }
+    }
+
+    // Parse attributes, which are split into tokens on '.'
+    let mut attributes = Vec::new();
+    let is_list = tokens
+        .peek()
+        .map_or(false, |token| token.is_parenthesis_open());
+    if is_list {
+        tokens.next();
+    }
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Argument(mut value) => {
+                if tokens.peek().map_or(false, |token| token.is_dot()) {
+                    tokens.next();
+                    value.push(b'.');
+                    value.extend(
+                        tokens
+                            .next()
+                            .ok_or_else(|| Cow::from("Missing annotation attribute."))?
+                            .unwrap_bytes(),
+                    );
+                }
+                for attribute in AnnotationAttribute::parse(&value).ok_or_else(|| {
+                    Cow::from(format!(
+                        "Invalid annotation attribute {:?}.",
+                        String::from_utf8_lossy(&value)
+                    ))
+                })? {
+                    attributes.push_unique(*attribute);
+                }
+                if !is_list {
+                    break;
+                }
+            }
+            Token::ParenthesisClose if is_list => break,
+            _ => return Err("Expected annotation attribute.".into()),
+        }
+    }
+
+    if tokens
+        .next()
+        .map_or(true, |token| !token.is_parenthesis_close())
+    {
+        return Err("Expected ')' after annotation attributes.".into());
+    }
+
+    if !entries.is_empty() && !attributes.is_empty() {
+        Ok(Attribute::Annotation {
+            entries,
+            attributes,
+        })
+    } else {
+        Err("Missing annotation entries or attributes.".into())
+    }
+}
+
 pub fn parse_partial(tokens: &mut Peekable<IntoIter<Token>>) -> super::Result<Option<(u32, u32)>> {
     if tokens.peek().map_or(true, |token| !token.is_lt()) {
         return Ok(None);
@@ -501,7 +592,7 @@ pub fn parse_partial(tokens: &mut Peekable<IntoIter<Token>>) -> super::Result<Op
 mod tests {
     use crate::{
         protocol::{
-            fetch::{self, Attribute, Section},
+            fetch::{self, AnnotationAttribute, Attribute, Section},
             Sequence,
         },
         receiver::Receiver,
@@ -792,6 +883,39 @@ mod tests {
                     include_vanished: true,
                 },
             ),
+            (
+                "A005 FETCH 1:* (UID ANNOTATION (/comment value.priv))\r\n",
+                fetch::Arguments {
+                    tag: "A005".to_string(),
+                    sequence_set: Sequence::range(1.into(), None),
+                    attributes: vec![
+                        Attribute::Uid,
+                        Attribute::Annotation {
+                            entries: vec!["/comment".to_string()],
+                            attributes: vec![AnnotationAttribute::ValuePriv],
+                        },
+                    ],
+                    changed_since: None,
+                    include_vanished: false,
+                },
+            ),
+            (
+                "A006 FETCH 7 ANNOTATION ((/comment \"/vendor/stalwart/*\") (value size.priv))\r\n",
+                fetch::Arguments {
+                    tag: "A006".to_string(),
+                    sequence_set: Sequence::number(7),
+                    attributes: vec![Attribute::Annotation {
+                        entries: vec!["/comment".to_string(), "/vendor/stalwart/*".to_string()],
+                        attributes: vec![
+                            AnnotationAttribute::ValuePriv,
+                            AnnotationAttribute::ValueShared,
+                            AnnotationAttribute::SizePriv,
+                        ],
+                    }],
+                    changed_since: None,
+                    include_vanished: false,
+                },
+            ),
         ] {
             assert_eq!(
                 receiver
@@ -32,6 +32,7 @@ pub mod fetch;
 pub mod list;
 pub mod login;
 pub mod lsub;
+pub mod notify;
 pub mod rename;
 pub mod search;
 pub mod select;
@@ -94,13 +95,14 @@ impl CommandParser for Command {
             b"MYRIGHTS" => Some(Command::MyRights),
             b"UNAUTHENTICATE" => Some(Command::Unauthenticate),
             b"ID" => Some(Command::Id),
+            b"NOTIFY" => Some(Command::Notify),
             _ => None,
         }
     }
 
     #[inline(always)]
     fn tokenize_brackets(&self) -> bool {
-        matches!(self, Command::Fetch(_))
+        matches!(self, Command::Fetch(_) | Command::Notify)
     }
 }
 
@@ -0,0 +1,392 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+use crate::{
+    protocol::{
+        notify::{self, Event, EventGroup, Filter},
+        ProtocolVersion,
+    },
+    receiver::{Request, Token},
+    utf7::utf7_maybe_decode,
+    Command, ResponseCode, StatusResponse,
+};
+
+impl Request<Command> {
+    pub fn parse_notify(self, version: ProtocolVersion) -> crate::Result<notify::Arguments> {
+        let mut tokens = self.tokens.into_iter().peekable();
+
+        match tokens.next() {
+            Some(Token::Argument(value)) if value.eq_ignore_ascii_case(b"NONE") => {
+                return if tokens.next().is_none() {
+                    Ok(notify::Arguments {
+                        tag: self.tag,
+                        status: false,
+                        groups: vec![],
+                    })
+                } else {
+                    Err((self.tag.as_str(), "Unexpected arguments after NONE.").into())
+                };
+            }
+            Some(Token::Argument(value)) if value.eq_ignore_ascii_case(b"SET") => (),
+            _ => {
+                return Err((self.tag.as_str(), "Expected SET or NONE.").into());
+            }
+        }
+
+        let status = if tokens
+            .peek()
+            .map_or(false, |token| token.eq_ignore_ascii_case(b"STATUS"))
+        {
+            tokens.next();
+            true
+        } else {
+            false
+        };
+
+        let mut groups: Vec<EventGroup> = Vec::new();
+        while let Some(token) = tokens.next() {
+            if !token.is_parenthesis_open() {
+                return Err((self.tag.as_str(), "Expected event group.").into());
+            }
+
+            // Parse filter
+            let filter = match tokens.next() {
+                Some(Token::Argument(value)) => {
+                    if value.eq_ignore_ascii_case(b"SELECTED") {
+                        Filter::Selected
+                    } else if value.eq_ignore_ascii_case(b"SELECTED-DELAYED") {
+                        Filter::SelectedDelayed
+                    } else if value.eq_ignore_ascii_case(b"INBOXES") {
+                        Filter::Inboxes
+                    } else if value.eq_ignore_ascii_case(b"PERSONAL") {
+                        Filter::Personal
+                    } else if value.eq_ignore_ascii_case(b"SUBSCRIBED") {
+                        Filter::Subscribed
+                    } else if value.eq_ignore_ascii_case(b"SUBTREE") {
+                        Filter::Subtree(
+                            parse_mailboxes(&mut tokens, version)
+                                .map_err(|v| (self.tag.as_str(), v))?,
+                        )
+                    } else if value.eq_ignore_ascii_case(b"MAILBOXES") {
+                        Filter::Mailboxes(
+                            parse_mailboxes(&mut tokens, version)
+                                .map_err(|v| (self.tag.as_str(), v))?,
+                        )
+                    } else {
+                        return Err((
+                            self.tag,
+                            format!(
+                                "Invalid mailbox filter {:?}.",
+                                String::from_utf8_lossy(&value)
+                            ),
+                        )
+                            .into());
+                    }
+                }
+                _ => {
+                    return Err((self.tag.as_str(), "Expected mailbox filter.").into());
+                }
+            };
+            if groups.iter().any(|group| {
+                group.filter == filter || (group.filter.is_selected() && filter.is_selected())
+            }) {
+                return Err((self.tag.as_str(), "Duplicate mailbox filter.").into());
+            }
+
+            // Parse events
+            let mut events = Vec::new();
+            match tokens.next() {
+                Some(Token::Argument(value)) if value.eq_ignore_ascii_case(b"NONE") => (),
+                Some(Token::ParenthesisOpen) => loop {
+                    match tokens.next() {
+                        Some(Token::ParenthesisClose) => break,
+                        Some(Token::Argument(value)) => {
+                            let event = if value.eq_ignore_ascii_case(b"MessageNew") {
+                                Event::MessageNew {
+                                    attributes: if tokens
+                                        .peek()
+                                        .map_or(false, |token| token.is_parenthesis_open())
+                                    {
+                                        parse_fetch_attributes(&mut tokens, &self.tag)?
+                                    } else {
+                                        vec![]
+                                    },
+                                }
+                            } else if value.eq_ignore_ascii_case(b"MessageExpunge") {
+                                Event::MessageExpunge
+                            } else if value.eq_ignore_ascii_case(b"FlagChange") {
+                                Event::FlagChange
+                            } else if value.eq_ignore_ascii_case(b"MailboxName") {
+                                Event::MailboxName
+                            } else if value.eq_ignore_ascii_case(b"SubscriptionChange") {
+                                Event::SubscriptionChange
+                            } else {
+                                return Err(StatusResponse::no(format!(
+                                    "Unsupported event {:?}.",
+                                    String::from_utf8_lossy(&value)
+                                ))
+                                .with_tag(self.tag)
+                                .with_code(ResponseCode::BadEvent));
+                            };
+                            if !events.contains(&event) {
+                                events.push(event);
+                            }
+                        }
+                        _ => {
+                            return Err((self.tag.as_str(), "Invalid event.").into());
+                        }
+                    }
+                },
+                _ => {
+                    return Err((self.tag.as_str(), "Expected event list.").into());
+                }
+            }
+
+            // MessageNew and MessageExpunge must be requested together, and FlagChange requires both
+            let has_new = events
+                .iter()
+                .any(|event| matches!(event, Event::MessageNew { .. }));
+            let has_expunge = events.contains(&Event::MessageExpunge);
+            if has_new != has_expunge || (events.contains(&Event::FlagChange) && !has_new) {
+                return Err((
+                    self.tag.as_str(),
+                    "MessageNew and MessageExpunge must be requested together.",
+                )
+                    .into());
+            }
+            if filter.is_selected()
+                && events
+                    .iter()
+                    .any(|event| matches!(event, Event::MailboxName | Event::SubscriptionChange))
+            {
+                return Err((
+                    self.tag.as_str(),
+                    "Mailbox events are not allowed for the selected mailbox.",
+                )
+                    .into());
+            }
+
+            if tokens
+                .next()
+                .map_or(true, |token| !token.is_parenthesis_close())
+            {
+                return Err((self.tag.as_str(), "Expected ')' after event list.").into());
+            }
+
+            groups.push(EventGroup { filter, events });
+        }
+
+        if !groups.is_empty() {
+            Ok(notify::Arguments {
+                tag: self.tag,
+                status,
+                groups,
+            })
+        } else {
+            Err((self.tag, "At least one event group is required.").into())
+        }
+    }
+}
+
+fn parse_mailboxes(
+    tokens: &mut Peekable<IntoIter<Token>>,
+    version: ProtocolVersion,
+) -> super::Result<Vec<String>> {
+    let mut mailboxes = Vec::new();
+    match tokens.next() {
+        Some(Token::ParenthesisOpen) => loop {
+            match tokens.next() {
+                Some(Token::ParenthesisClose) if !mailboxes.is_empty() => break,
+                Some(token @ Token::Argument(_)) => {
+                    mailboxes.push(utf7_maybe_decode(token.unwrap_string()?, version));
+                }
+                _ => return Err("Invalid mailbox list.".into()),
+            }
+        },
+        Some(token @ Token::Argument(_)) => {
+            mailboxes.push(utf7_maybe_decode(token.unwrap_string()?, version));
+        }
+        _ => return Err("Expected mailbox name.".into()),
+    }
+    Ok(mailboxes)
+}
+
+fn parse_fetch_attributes(
+    tokens: &mut Peekable<IntoIter<Token>>,
+    tag: &str,
+) -> crate::Result<Vec<crate::protocol::fetch::Attribute>> {
+    // Collect the parenthesized list and hand it over to the FETCH parser
+    let mut fetch_tokens = vec![Token::Argument(b"1".to_vec())];
+    let mut depth = 0;
+    for token in tokens.by_ref() {
+        match token {
+            Token::ParenthesisOpen => depth += 1,
+            Token::ParenthesisClose => depth -= 1,
+            _ => (),
+        }
+        fetch_tokens.push(token);
+        if depth == 0 {
+            break;
+        }
+    }
+    if depth != 0 {
+        return Err((tag, "Unterminated fetch attribute list.").into());
+    }
+
+    Request {
+        tag: tag.to_string(),
+        command: Command::Fetch(false),
+        tokens: fetch_tokens,
+    }
+    .parse_fetch()
+    .map(|arguments| arguments.attributes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        protocol::{
+            fetch,
+            notify::{self, Event, EventGroup, Filter},
+            ProtocolVersion,
+        },
+        receiver::Receiver,
+        ResponseCode,
+    };
+
+    #[test]
+    fn parse_notify() {
+        let mut receiver = Receiver::new();
+
+        for (command, arguments) in [
+            (
+                concat!(
+                    "A01 NOTIFY SET STATUS (selected (MessageNew (UID FLAGS) ",
+                    "MessageExpunge FlagChange)) (subtree (Lists \"Work/Projects\") ",
+                    "(MessageNew MessageExpunge)) (personal (MailboxName SubscriptionChange))\r\n"
+                ),
+                notify::Arguments {
+                    tag: "A01".to_string(),
+                    status: true,
+                    groups: vec![
+                        EventGroup {
+                            filter: Filter::Selected,
+                            events: vec![
+                                Event::MessageNew {
+                                    attributes: vec![
+                                        fetch::Attribute::Uid,
+                                        fetch::Attribute::Flags,
+                                    ],
+                                },
+                                Event::MessageExpunge,
+                                Event::FlagChange,
+                            ],
+                        },
+                        EventGroup {
+                            filter: Filter::Subtree(vec![
+                                "Lists".to_string(),
+                                "Work/Projects".to_string(),
+                            ]),
+                            events: vec![
+                                Event::MessageNew { attributes: vec![] },
+                                Event::MessageExpunge,
+                            ],
+                        },
+                        EventGroup {
+                            filter: Filter::Personal,
+                            events: vec![Event::MailboxName, Event::SubscriptionChange],
+                        },
+                    ],
+                },
+            ),
+            (
+                "A02 NOTIFY SET (mailboxes INBOX (MessageNew MessageExpunge)) (inboxes NONE)\r\n",
+                notify::Arguments {
+                    tag: "A02".to_string(),
+                    status: false,
+                    groups: vec![
+                        EventGroup {
+                            filter: Filter::Mailboxes(vec!["INBOX".to_string()]),
+                            events: vec![
+                                Event::MessageNew { attributes: vec![] },
+                                Event::MessageExpunge,
+                            ],
+                        },
+                        EventGroup {
+                            filter: Filter::Inboxes,
+                            events: vec![],
+                        },
+                    ],
+                },
+            ),
+            (
+                "A03 NOTIFY NONE\r\n",
+                notify::Arguments {
+                    tag: "A03".to_string(),
+                    status: false,
+                    groups: vec![],
+                },
+            ),
+        ] {
+            assert_eq!(
+                receiver
+                    .parse(&mut command.as_bytes().iter())
+                    .unwrap()
+                    .parse_notify(ProtocolVersion::Rev2)
+                    .unwrap(),
+                arguments,
+                "{}",
+                command
+            );
+        }
+
+        // Unsupported events are rejected with BADEVENT
+        assert_eq!(
+            receiver
+                .parse(
+                    &mut "A04 NOTIFY SET (personal (MessageNew MessageExpunge AnnotationChange))\r\n"
+                        .as_bytes()
+                        .iter()
+                )
+                .unwrap()
+                .parse_notify(ProtocolVersion::Rev2)
+                .unwrap_err()
+                .code,
+            Some(ResponseCode::BadEvent)
+        );
+
+        // MessageNew requires MessageExpunge
+        assert!(receiver
+            .parse(
+                &mut "A05 NOTIFY SET (personal (MessageNew))\r\n"
+                    .as_bytes()
+                    .iter()
+            )
+            .unwrap()
+            .parse_notify(ProtocolVersion::Rev2)
+            .is_err());
+    }
+}
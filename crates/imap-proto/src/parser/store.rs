@@ -21,10 +21,11 @@
  * for more details.
 */
 
-use std::borrow::Cow;
+use std::{borrow::Cow, iter::Peekable, vec::IntoIter};
 
 use crate::{
     protocol::{
+        fetch::{AnnotationAttribute, AnnotationEntry},
         store::{self, Operation},
         Flag,
     },
@@ -94,6 +95,17 @@ impl Request<Command> {
             (false, Operation::Clear)
         } else if operation.eq_ignore_ascii_case(b"-FLAGS.SILENT") {
             (true, Operation::Clear)
+        } else if operation.eq_ignore_ascii_case(b"ANNOTATION") {
+            let annotations = parse_annotations(&mut tokens).map_err(|v| (self.tag.as_str(), v))?;
+            return Ok(store::Arguments {
+                tag: self.tag,
+                sequence_set,
+                operation: Operation::Annotation,
+                is_silent: true,
+                keywords: Vec::new(),
+                annotations,
+                unchanged_since,
+            });
         } else {
             return Err((
                 self.tag,
@@ -142,6 +154,7 @@ impl Request<Command> {
                 operation,
                 is_silent,
                 keywords,
+                annotations: Vec::new(),
                 unchanged_since,
             })
         } else {
@@ -150,11 +163,83 @@ impl Request<Command> {
     }
 }
 
+pub fn parse_annotations(
+    tokens: &mut Peekable<IntoIter<Token>>,
+) -> super::Result<Vec<AnnotationEntry>> {
+    if tokens
+        .next()
+        .map_or(true, |token| !token.is_parenthesis_open())
+    {
+        return Err("Expected '(' after 'ANNOTATION'.".into());
+    }
+
+    let mut annotations = Vec::new();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Argument(entry) => {
+                let entry = String::from_utf8(entry)
+                    .map_err(|_| Cow::from("Invalid UTF-8 in annotation entry."))?;
+                if tokens
+                    .next()
+                    .map_or(true, |token| !token.is_parenthesis_open())
+                {
+                    return Err("Expected '(' after annotation entry.".into());
+                }
+                let mut values = Vec::new();
+                while let Some(token) = tokens.next() {
+                    let attribute = match token {
+                        Token::Argument(attribute) => {
+                            match AnnotationAttribute::parse(&attribute) {
+                                Some([attribute]) => *attribute,
+                                _ => {
+                                    return Err(format!(
+                                        "Invalid annotation attribute {:?}.",
+                                        String::from_utf8_lossy(&attribute)
+                                    )
+                                    .into())
+                                }
+                            }
+                        }
+                        Token::ParenthesisClose => break,
+                        _ => return Err("Expected annotation attribute.".into()),
+                    };
+                    let value = match tokens
+                        .next()
+                        .ok_or_else(|| Cow::from("Missing annotation value."))?
+                    {
+                        Token::Argument(value) if value.eq_ignore_ascii_case(b"NIL") => None,
+                        Token::Argument(value) => Some(
+                            String::from_utf8(value)
+                                .map_err(|_| Cow::from("Invalid UTF-8 in annotation value."))?,
+                        ),
+                        Token::Nil => Some(String::new()),
+                        _ => return Err("Expected annotation value.".into()),
+                    };
+                    values.push((attribute, value));
+                }
+                if values.is_empty() {
+                    return Err("Missing annotation attributes.".into());
+                }
+                annotations.push(AnnotationEntry { entry, values });
+            }
+            Token::ParenthesisClose => break,
+            _ => return Err("Expected annotation entry.".into()),
+        }
+    }
+
+    if !annotations.is_empty() {
+        Ok(annotations)
+    } else {
+        Err("Missing annotation entries.".into())
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use crate::{
         protocol::{
+            fetch::{AnnotationAttribute, AnnotationEntry},
             store::{self, Operation},
             Flag, Sequence,
         },
@@ -176,6 +261,7 @@ mod tests {
                     is_silent: false,
                     operation: Operation::Add,
                     keywords: vec![Flag::Deleted],
+                    annotations: vec![],
                     tag: "A003".to_string(),
                     unchanged_since: None,
                 },
@@ -190,6 +276,7 @@ mod tests {
                     is_silent: true,
                     operation: Operation::Clear,
                     keywords: vec![Flag::Phishing, Flag::Junk],
+                    annotations: vec![],
                     tag: "A004".to_string(),
                     unchanged_since: None,
                 },
@@ -207,10 +294,35 @@ mod tests {
                     is_silent: true,
                     operation: Operation::Add,
                     keywords: vec![Flag::Deleted],
+                    annotations: vec![],
                     tag: "d105".to_string(),
                     unchanged_since: Some(320162338),
                 },
             ),
+            (
+                "A005 STORE 3 ANNOTATION (/comment (value.priv \"Call back\") /vendor/stalwart/color (value.priv NIL))\r\n",
+                store::Arguments {
+                    sequence_set: Sequence::Number { value: 3 },
+                    is_silent: true,
+                    operation: Operation::Annotation,
+                    keywords: vec![],
+                    annotations: vec![
+                        AnnotationEntry {
+                            entry: "/comment".to_string(),
+                            values: vec![(
+                                AnnotationAttribute::ValuePriv,
+                                Some("Call back".to_string()),
+                            )],
+                        },
+                        AnnotationEntry {
+                            entry: "/vendor/stalwart/color".to_string(),
+                            values: vec![(AnnotationAttribute::ValuePriv, None)],
+                        },
+                    ],
+                    tag: "A005".to_string(),
+                    unchanged_since: None,
+                },
+            ),
         ] {
             assert_eq!(
                 receiver
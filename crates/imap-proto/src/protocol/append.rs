@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use super::Flag;
+use super::{fetch::Section, Flag};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Arguments {
@@ -35,4 +35,20 @@ pub struct Message {
     pub message: Vec<u8>,
     pub flags: Vec<Flag>,
     pub received_at: Option<i64>,
+    pub catenate: Vec<CatenatePart>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CatenatePart {
+    Url(String),
+    Text(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImapUrl {
+    pub mailbox_name: String,
+    pub uid_validity: Option<u32>,
+    pub uid: u32,
+    pub sections: Vec<Section>,
+    pub partial: Option<(u32, u32)>,
 }
@@ -64,6 +64,10 @@ pub enum Capability {
     ObjectId,
     Preview,
     Utf8Accept,
+    Catenate,
+    Annotate,       //ANNOTATE-EXPERIMENT-1
+    LoginReferrals, //LOGIN-REFERRALS
+    Notify,
     Auth(Mechanism),
 }
 
@@ -109,6 +113,10 @@ impl Capability {
             Capability::CreateSpecialUse => b"CREATE-SPECIAL-USE",
             Capability::Move => b"MOVE",
             Capability::Utf8Accept => b"UTF8=ACCEPT",
+            Capability::Catenate => b"CATENATE",
+            Capability::Annotate => b"ANNOTATE-EXPERIMENT-1",
+            Capability::LoginReferrals => b"LOGIN-REFERRALS",
+            Capability::Notify => b"NOTIFY",
         });
     }
 
@@ -121,6 +129,7 @@ impl Capability {
             Capability::LiteralPlus,
             Capability::Id,
             Capability::Utf8Accept,
+            Capability::LoginReferrals,
         ];
 
         if is_authenticated {
@@ -129,6 +138,7 @@ impl Capability {
                 Capability::Namespace,
                 Capability::Children,
                 Capability::MultiAppend,
+                Capability::Catenate,
                 Capability::Binary,
                 Capability::Unselect,
                 Capability::ACL,
@@ -150,6 +160,8 @@ impl Capability {
                 Capability::StatusSize,
                 Capability::ObjectId,
                 Capability::Preview,
+                Capability::Annotate,
+                Capability::Notify,
             ]);
         } else {
             capabilties.extend([
@@ -81,6 +81,24 @@ pub enum Attribute {
     ModSeq,
     EmailId,
     ThreadId,
+    Annotation {
+        entries: Vec<String>,
+        attributes: Vec<AnnotationAttribute>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnnotationAttribute {
+    ValuePriv,
+    ValueShared,
+    SizePriv,
+    SizeShared,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnotationEntry {
+    pub entry: String,
+    pub values: Vec<(AnnotationAttribute, Option<String>)>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -114,6 +132,15 @@ pub enum DataItem<'x> {
         origin_octet: Option<u32>,
         contents: Cow<'x, [u8]>,
     },
+    // Whole message literals whose contents are written by the caller
+    // at the offsets returned by `FetchItem::serialize_streamed`
+    BodySectionStream {
+        origin_octet: Option<u32>,
+        size: usize,
+    },
+    Rfc822Stream {
+        size: usize,
+    },
     Envelope {
         envelope: Envelope<'x>,
     },
@@ -150,6 +177,9 @@ pub enum DataItem<'x> {
     ThreadId {
         thread_id: String,
     },
+    Annotation {
+        entries: Vec<AnnotationEntry>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -648,6 +678,99 @@ impl Section {
     }
 }
 
+impl AnnotationAttribute {
+    pub fn parse(value: &[u8]) -> Option<&'static [AnnotationAttribute]> {
+        if value.eq_ignore_ascii_case(b"value") {
+            Some(&[
+                AnnotationAttribute::ValuePriv,
+                AnnotationAttribute::ValueShared,
+            ])
+        } else if value.eq_ignore_ascii_case(b"value.priv") {
+            Some(&[AnnotationAttribute::ValuePriv])
+        } else if value.eq_ignore_ascii_case(b"value.shared") {
+            Some(&[AnnotationAttribute::ValueShared])
+        } else if value.eq_ignore_ascii_case(b"size") {
+            Some(&[
+                AnnotationAttribute::SizePriv,
+                AnnotationAttribute::SizeShared,
+            ])
+        } else if value.eq_ignore_ascii_case(b"size.priv") {
+            Some(&[AnnotationAttribute::SizePriv])
+        } else if value.eq_ignore_ascii_case(b"size.shared") {
+            Some(&[AnnotationAttribute::SizeShared])
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnnotationAttribute::ValuePriv => "value.priv",
+            AnnotationAttribute::ValueShared => "value.shared",
+            AnnotationAttribute::SizePriv => "size.priv",
+            AnnotationAttribute::SizeShared => "size.shared",
+        }
+    }
+
+    pub fn is_private(&self) -> bool {
+        matches!(
+            self,
+            AnnotationAttribute::ValuePriv | AnnotationAttribute::SizePriv
+        )
+    }
+}
+
+impl AnnotationEntry {
+    pub fn serialize(&self, buf: &mut Vec<u8>) {
+        quoted_or_literal_string(buf, &self.entry);
+        buf.extend_from_slice(b" (");
+        for (pos, (attribute, value)) in self.values.iter().enumerate() {
+            if pos > 0 {
+                buf.push(b' ');
+            }
+            buf.extend_from_slice(attribute.as_str().as_bytes());
+            buf.push(b' ');
+            quoted_or_literal_string_or_nil(buf, value.as_deref());
+        }
+        buf.push(b')');
+    }
+}
+
+// Matches an entry name against a pattern where '*' matches any
+// sequence of characters and '%' matches any sequence except '/'.
+pub fn annotation_entry_matches(pattern: &str, entry: &str) -> bool {
+    let (pattern, entry) = (pattern.as_bytes(), entry.as_bytes());
+    let (mut p, mut e) = (0, 0);
+    let mut backtrack: Option<(usize, usize, bool)> = None;
+
+    while e < entry.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, e, true));
+                p += 1;
+            }
+            Some(b'%') => {
+                backtrack = Some((p, e, false));
+                p += 1;
+            }
+            Some(ch) if ch.eq_ignore_ascii_case(&entry[e]) => {
+                p += 1;
+                e += 1;
+            }
+            _ => match backtrack {
+                Some((bp, be, crosses)) if crosses || entry[be] != b'/' => {
+                    backtrack = Some((bp, be + 1, crosses));
+                    p = bp + 1;
+                    e = be + 1;
+                }
+                _ => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|ch| matches!(ch, b'*' | b'%'))
+}
+
 static DUMMY_ADDRESS: [Address; 1] = [Address::Single(EmailAddress {
     name: None,
     address: Cow::Borrowed("unknown@localhost"),
@@ -800,6 +923,22 @@ impl<'x> DataItem<'x> {
                 }
                 literal_string(buf, contents);
             }
+            DataItem::BodySectionStream { origin_octet, size } => {
+                if let Some(origin_octet) = origin_octet {
+                    buf.extend_from_slice(b"BODY[]<");
+                    buf.extend_from_slice(origin_octet.to_string().as_bytes());
+                    buf.extend_from_slice(b"> {");
+                } else {
+                    buf.extend_from_slice(b"BODY[] {");
+                }
+                buf.extend_from_slice(size.to_string().as_bytes());
+                buf.extend_from_slice(b"}\r\n");
+            }
+            DataItem::Rfc822Stream { size } => {
+                buf.extend_from_slice(b"RFC822 {");
+                buf.extend_from_slice(size.to_string().as_bytes());
+                buf.extend_from_slice(b"}\r\n");
+            }
             DataItem::Envelope { envelope } => {
                 buf.extend_from_slice(b"ENVELOPE ");
                 envelope.serialize(buf);
@@ -861,12 +1000,29 @@ impl<'x> DataItem<'x> {
                 buf.extend_from_slice(thread_id.as_bytes());
                 buf.push(b')');
             }
+            DataItem::Annotation { entries } => {
+                buf.extend_from_slice(b"ANNOTATION (");
+                for (pos, entry) in entries.iter().enumerate() {
+                    if pos > 0 {
+                        buf.push(b' ');
+                    }
+                    entry.serialize(buf);
+                }
+                buf.push(b')');
+            }
         }
     }
 }
 
 impl<'x> FetchItem<'x> {
     pub fn serialize(&self, buf: &mut Vec<u8>) {
+        self.serialize_streamed(buf);
+    }
+
+    // Returns the offsets in `buf` where the contents of each streamed
+    // literal have to be written.
+    pub fn serialize_streamed(&self, buf: &mut Vec<u8>) -> Vec<usize> {
+        let mut offsets = Vec::new();
         buf.extend_from_slice(b"* ");
         buf.extend_from_slice(self.id.to_string().as_bytes());
         buf.extend_from_slice(b" FETCH (");
@@ -875,8 +1031,15 @@ impl<'x> FetchItem<'x> {
                 buf.push(b' ');
             }
             item.serialize(buf);
+            if matches!(
+                item,
+                DataItem::BodySectionStream { .. } | DataItem::Rfc822Stream { .. }
+            ) {
+                offsets.push(buf.len());
+            }
         }
         buf.extend_from_slice(b")\r\n");
+        offsets
     }
 }
 
@@ -938,8 +1101,9 @@ mod tests {
     use crate::protocol::{Flag, ImapResponse};
 
     use super::{
-        Address, AddressGroup, BodyPart, BodyPartExtension, BodyPartFields, DataItem, EmailAddress,
-        Envelope, FetchItem, Response, Section,
+        annotation_entry_matches, Address, AddressGroup, AnnotationAttribute, AnnotationEntry,
+        BodyPart, BodyPartExtension, BodyPartFields, DataItem, EmailAddress, Envelope, FetchItem,
+        Response, Section,
     };
 
     #[test]
@@ -1365,6 +1529,38 @@ mod tests {
                 },
                 "BODY[HEADER.FIELDS (FROM LIST-ARCHIVE)] {5}\r\nhowdy",
             ),
+            (
+                super::DataItem::Annotation {
+                    entries: vec![
+                        AnnotationEntry {
+                            entry: "/comment".into(),
+                            values: vec![
+                                (AnnotationAttribute::ValuePriv, Some("My comment".into())),
+                                (AnnotationAttribute::ValueShared, None),
+                            ],
+                        },
+                        AnnotationEntry {
+                            entry: "/vendor/stalwart/color".into(),
+                            values: vec![(AnnotationAttribute::SizePriv, Some("7".into()))],
+                        },
+                    ],
+                },
+                concat!(
+                    "ANNOTATION (\"/comment\" (value.priv \"My comment\" value.shared NIL) ",
+                    "\"/vendor/stalwart/color\" (size.priv \"7\"))"
+                ),
+            ),
+            (
+                super::DataItem::BodySectionStream {
+                    origin_octet: 10.into(),
+                    size: 5,
+                },
+                "BODY[]<10> {5}\r\n",
+            ),
+            (
+                super::DataItem::Rfc822Stream { size: 1024 },
+                "RFC822 {1024}\r\n",
+            ),
             (
                 super::DataItem::Flags {
                     flags: vec![Flag::Seen],
@@ -1384,6 +1580,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn match_annotation_entries() {
+        for (pattern, entry, expected) in [
+            ("/comment", "/comment", true),
+            ("/COMMENT", "/comment", true),
+            ("/comment", "/comments", false),
+            ("*", "/vendor/stalwart/color", true),
+            ("/vendor/*", "/vendor/stalwart/color", true),
+            ("/vendor/%", "/vendor/stalwart/color", false),
+            ("/vendor/%/color", "/vendor/stalwart/color", true),
+            ("/%", "/comment", true),
+            ("/%", "/vendor/stalwart/color", false),
+            ("/c*t", "/comment", true),
+        ] {
+            assert_eq!(
+                annotation_entry_matches(pattern, entry),
+                expected,
+                "{pattern} {entry}"
+            );
+        }
+    }
+
     #[test]
     fn serialize_fetch() {
         assert_eq!(
@@ -1419,4 +1637,28 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn serialize_fetch_streamed() {
+        let mut buf = Vec::new();
+        let offsets = super::FetchItem {
+            id: 7,
+            items: vec![
+                super::DataItem::Uid { uid: 12 },
+                super::DataItem::BodySectionStream {
+                    origin_octet: None,
+                    size: 5,
+                },
+                super::DataItem::Rfc822Size { size: 5 },
+            ],
+        }
+        .serialize_streamed(&mut buf);
+        assert_eq!(offsets.len(), 1);
+
+        buf.splice(offsets[0]..offsets[0], b"hello".iter().copied());
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "* 7 FETCH (UID 12 BODY[] {5}\r\nhello RFC822.SIZE 5)\r\n"
+        );
+    }
 }
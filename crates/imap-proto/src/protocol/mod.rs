@@ -42,6 +42,7 @@ pub mod fetch;
 pub mod list;
 pub mod login;
 pub mod namespace;
+pub mod notify;
 pub mod rename;
 pub mod search;
 pub mod select;
@@ -374,6 +375,11 @@ impl ResponseCode {
             ResponseCode::PrivacyRequired => b"PRIVACYREQUIRED",
             ResponseCode::ReadOnly => b"READ-ONLY",
             ResponseCode::ReadWrite => b"READ-WRITE",
+            ResponseCode::Referral { url } => {
+                buf.extend_from_slice(b"REFERRAL ");
+                buf.extend_from_slice(url.as_bytes());
+                return;
+            }
             ResponseCode::ServerBug => b"SERVERBUG",
             ResponseCode::TryCreate => b"TRYCREATE",
             ResponseCode::UidNext => b"UIDNEXT",
@@ -398,6 +404,15 @@ impl ResponseCode {
                 return;
             }
             ResponseCode::UseAttr => b"USEATTR",
+            ResponseCode::BadUrl { url } => {
+                buf.extend_from_slice(b"BADURL ");
+                buf.extend_from_slice(url.as_bytes());
+                return;
+            }
+            ResponseCode::TooBig => b"TOOBIG",
+            ResponseCode::BadEvent => {
+                b"BADEVENT (MessageNew MessageExpunge FlagChange MailboxName SubscriptionChange)"
+            }
         });
     }
 }
@@ -526,6 +541,7 @@ impl Display for Command {
             Command::MyRights => write!(f, "MYRIGHTS"),
             Command::Unauthenticate => write!(f, "UNAUTHENTICATE"),
             Command::Id => write!(f, "ID"),
+            Command::Notify => write!(f, "NOTIFY"),
         }
     }
 }
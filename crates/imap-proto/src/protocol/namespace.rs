@@ -24,19 +24,70 @@
 use super::{quoted_string, ImapResponse};
 
 pub struct Response {
+    pub personal_prefix: Option<String>,
     pub shared_prefix: Option<String>,
+    pub public_prefix: Option<String>,
 }
 
 impl ImapResponse for Response {
     fn serialize(self) -> Vec<u8> {
         let mut buf = Vec::with_capacity(64);
-        if let Some(shared_prefix) = &self.shared_prefix {
-            buf.extend_from_slice(b"* NAMESPACE ((\"\" \"/\")) ((");
-            quoted_string(&mut buf, shared_prefix);
-            buf.extend_from_slice(b" \"/\")) NIL\r\n");
-        } else {
-            buf.extend_from_slice(b"* NAMESPACE ((\"\" \"/\")) NIL NIL\r\n");
+        buf.extend_from_slice(b"* NAMESPACE ((");
+        quoted_string(
+            &mut buf,
+            self.personal_prefix.as_deref().unwrap_or_default(),
+        );
+        buf.extend_from_slice(b" \"/\"))");
+        for prefix in [&self.shared_prefix, &self.public_prefix] {
+            if let Some(prefix) = prefix {
+                buf.extend_from_slice(b" ((");
+                quoted_string(&mut buf, prefix);
+                buf.extend_from_slice(b" \"/\"))");
+            } else {
+                buf.extend_from_slice(b" NIL");
+            }
         }
+        buf.extend_from_slice(b"\r\n");
         buf
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::ImapResponse;
+
+    #[test]
+    fn serialize_namespace() {
+        for (response, expected) in [
+            (
+                super::Response {
+                    personal_prefix: None,
+                    shared_prefix: None,
+                    public_prefix: None,
+                },
+                "* NAMESPACE ((\"\" \"/\")) NIL NIL\r\n",
+            ),
+            (
+                super::Response {
+                    personal_prefix: None,
+                    shared_prefix: "Shared Folders".to_string().into(),
+                    public_prefix: None,
+                },
+                "* NAMESPACE ((\"\" \"/\")) ((\"Shared Folders\" \"/\")) NIL\r\n",
+            ),
+            (
+                super::Response {
+                    personal_prefix: "INBOX".to_string().into(),
+                    shared_prefix: "Other Users".to_string().into(),
+                    public_prefix: "Public Folders".to_string().into(),
+                },
+                concat!(
+                    "* NAMESPACE ((\"INBOX\" \"/\")) ((\"Other Users\" \"/\")) ",
+                    "((\"Public Folders\" \"/\"))\r\n"
+                ),
+            ),
+        ] {
+            assert_eq!(String::from_utf8(response.serialize()).unwrap(), expected);
+        }
+    }
+}
@@ -56,6 +56,7 @@ pub struct Response {
     pub closed_previous: bool,
     pub highest_modseq: Option<HighestModSeq>,
     pub mailbox_id: String,
+    pub keywords: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -73,15 +74,15 @@ impl ImapResponse for Response {
         }
         buf.extend_from_slice(b"* ");
         buf.extend_from_slice(self.total_messages.to_string().as_bytes());
+        buf.extend_from_slice(b" EXISTS\r\n* FLAGS (\\Answered \\Flagged \\Deleted \\Seen \\Draft");
         if !self.is_rev2 && self.recent_messages > 0 {
-            buf.extend_from_slice(
-                b" EXISTS\r\n* FLAGS (\\Answered \\Flagged \\Deleted \\Seen \\Draft \\Recent)\r\n",
-            );
-        } else {
-            buf.extend_from_slice(
-                b" EXISTS\r\n* FLAGS (\\Answered \\Flagged \\Deleted \\Seen \\Draft)\r\n",
-            );
+            buf.extend_from_slice(b" \\Recent");
+        }
+        for keyword in &self.keywords {
+            buf.push(b' ');
+            buf.extend_from_slice(keyword.as_bytes());
         }
+        buf.extend_from_slice(b")\r\n");
         if self.is_rev2 {
             self.mailbox.serialize(&mut buf, self.is_rev2, false);
         } else {
@@ -95,8 +96,13 @@ impl ImapResponse for Response {
             }
         }
         buf.extend_from_slice(
-            b"* OK [PERMANENTFLAGS (\\Deleted \\Seen \\Answered \\Flagged \\Draft \\*)] All allowed\r\n",
+            b"* OK [PERMANENTFLAGS (\\Deleted \\Seen \\Answered \\Flagged \\Draft",
         );
+        for keyword in &self.keywords {
+            buf.push(b' ');
+            buf.extend_from_slice(keyword.as_bytes());
+        }
+        buf.extend_from_slice(b" \\*)] All allowed\r\n");
         buf.extend_from_slice(b"* OK [UIDVALIDITY ");
         buf.extend_from_slice(self.uid_validity.to_string().as_bytes());
         buf.extend_from_slice(b"] UIDs valid\r\n* OK [UIDNEXT ");
@@ -165,6 +171,7 @@ mod tests {
                     is_rev2: true,
                     highest_modseq: HighestModSeq::new(100).into(),
                     mailbox_id: "abc".into(),
+                    keywords: vec![],
                 },
                 "A142",
                 concat!(
@@ -201,15 +208,16 @@ mod tests {
                     is_rev2: true,
                     highest_modseq: None,
                     mailbox_id: "abc".into(),
+                    keywords: vec!["$Work".into(), "$Travel".into()],
                 },
                 "A142",
                 concat!(
                     "* OK [CLOSED] Closed previous mailbox\r\n",
                     "* 172 EXISTS\r\n",
-                    "* FLAGS (\\Answered \\Flagged \\Deleted \\Seen \\Draft)\r\n",
+                    "* FLAGS (\\Answered \\Flagged \\Deleted \\Seen \\Draft $Work $Travel)\r\n",
                     "* LIST () \"/\" \"~peter/mail/台北/日本語\" (\"OLDNAME\" ",
                     "(\"~peter/mail/&U,BTFw-/&ZeVnLIqe-\"))\r\n",
-                    "* OK [PERMANENTFLAGS (\\Deleted \\Seen \\Answered \\Flagged \\Draft \\*)] All allowed\r\n",
+                    "* OK [PERMANENTFLAGS (\\Deleted \\Seen \\Answered \\Flagged \\Draft $Work $Travel \\*)] All allowed\r\n",
                     "* OK [UIDVALIDITY 3857529045] UIDs valid\r\n",
                     "* OK [UIDNEXT 4392] Next predicted UID\r\n",
                     "* OK [MAILBOXID (abc)] Unique Mailbox ID\r\n"
@@ -217,10 +225,10 @@ mod tests {
                 concat!(
                     "* OK [CLOSED] Closed previous mailbox\r\n",
                     "* 172 EXISTS\r\n",
-                    "* FLAGS (\\Answered \\Flagged \\Deleted \\Seen \\Draft \\Recent)\r\n",
+                    "* FLAGS (\\Answered \\Flagged \\Deleted \\Seen \\Draft \\Recent $Work $Travel)\r\n",
                     "* 5 RECENT\r\n",
                     "* OK [UNSEEN 3] Unseen messages\r\n",
-                    "* OK [PERMANENTFLAGS (\\Deleted \\Seen \\Answered \\Flagged \\Draft \\*)] All allowed\r\n",
+                    "* OK [PERMANENTFLAGS (\\Deleted \\Seen \\Answered \\Flagged \\Draft $Work $Travel \\*)] All allowed\r\n",
                     "* OK [UIDVALIDITY 3857529045] UIDs valid\r\n",
                     "* OK [UIDNEXT 4392] Next predicted UID\r\n",
                     "* OK [MAILBOXID (abc)] Unique Mailbox ID\r\n"
@@ -21,7 +21,10 @@
  * for more details.
 */
 
-use super::{fetch::FetchItem, Flag, ImapResponse, Sequence};
+use super::{
+    fetch::{AnnotationEntry, FetchItem},
+    Flag, ImapResponse, Sequence,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Arguments {
@@ -30,6 +33,7 @@ pub struct Arguments {
     pub operation: Operation,
     pub is_silent: bool,
     pub keywords: Vec<Flag>,
+    pub annotations: Vec<AnnotationEntry>,
     pub unchanged_since: Option<u64>,
 }
 
@@ -38,6 +42,7 @@ pub enum Operation {
     Set,
     Add,
     Clear,
+    Annotation,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
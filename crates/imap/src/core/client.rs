@@ -29,6 +29,7 @@ use imap_proto::{
 };
 use jmap::auth::rate_limit::AuthenticatedLimiter;
 use utils::listener::{
+    bandwidth::Bandwidth,
     limiter::{ConcurrencyLimiter, RateLimiter},
     SessionStream,
 };
@@ -194,6 +195,9 @@ impl<T: SessionStream> Session<T> {
                 Command::Id => {
                     self.handle_id(request).await?;
                 }
+                Command::Notify => {
+                    self.handle_notify(request).await?;
+                }
             }
         }
 
@@ -292,7 +296,8 @@ impl<T: SessionStream> Session<T> {
             | Command::GetAcl
             | Command::ListRights
             | Command::MyRights
-            | Command::Unauthenticate => {
+            | Command::Unauthenticate
+            | Command::Notify => {
                 if let State::Authenticated { .. } | State::Selected { .. } = state {
                     Ok(request)
                 } else {
@@ -399,6 +404,9 @@ impl IMAP {
                     request_limiter: RateLimiter::new(&self.rate_requests),
                     concurrent_requests: ConcurrencyLimiter::new(self.rate_concurrent),
                     concurrent_uploads: ConcurrencyLimiter::new(self.rate_concurrent),
+                    patch_limiter: RateLimiter::new(&self.rate_requests),
+                    recovery_limiter: RateLimiter::new(&self.rate_recovery),
+                    bandwidth: Bandwidth::new(&self.rate_download, &self.rate_upload),
                 });
                 self.rate_limiter.insert(account_id, limiter.clone());
                 limiter
@@ -15,13 +15,14 @@ use parking_lot::Mutex;
 use store::query::log::{Change, Query};
 use utils::listener::{limiter::InFlight, SessionStream};
 
-use super::{Account, Mailbox, MailboxId, MailboxSync, Session, SessionData};
+use super::{Account, Mailbox, MailboxId, MailboxSync, Namespaces, Session, SessionData};
 
 impl<T: SessionStream> SessionData<T> {
     pub async fn new(
         session: &Session<T>,
         access_token: &AccessToken,
         in_flight: InFlight,
+        account_session: Option<InFlight>,
     ) -> crate::Result<Self> {
         let mut session = SessionData {
             stream_tx: session.stream_tx.clone(),
@@ -32,6 +33,9 @@ impl<T: SessionStream> SessionData<T> {
             mailboxes: Mutex::new(vec![]),
             state: access_token.state().into(),
             in_flight,
+            account_session,
+            remote_addr: session.remote_addr,
+            instance: session.instance.clone(),
         };
 
         // Fetch mailboxes for the main account
@@ -42,24 +46,9 @@ impl<T: SessionStream> SessionData<T> {
 
         // Fetch shared mailboxes
         for &account_id in access_token.shared_accounts(Collection::Mailbox) {
+            let prefix = session.shared_account_prefix(account_id).await;
             match session
-                .fetch_account_mailboxes(
-                    account_id,
-                    format!(
-                        "{}/{}",
-                        session.imap.name_shared,
-                        session
-                            .jmap
-                            .directory
-                            .query(QueryBy::Id(account_id), false)
-                            .await
-                            .unwrap_or_default()
-                            .map(|p| p.name)
-                            .unwrap_or_else(|| Id::from(account_id).to_string())
-                    )
-                    .into(),
-                    access_token,
-                )
+                .fetch_account_mailboxes(account_id, prefix.into(), access_token)
                 .await
             {
                 Ok(account_mailboxes) => {
@@ -169,6 +158,14 @@ impl<T: SessionStream> SessionData<T> {
                 if *mailbox_parent_id == parent_id {
                     let mut mailbox_path = path.clone();
                     if *mailbox_id != INBOX_ID || account.prefix.is_some() {
+                        // Personal mailboxes other than INBOX are placed under the personal prefix
+                        if let (Some(personal), true, 0) = (
+                            &self.imap.namespace.personal,
+                            account.prefix.is_none(),
+                            parent_id,
+                        ) {
+                            mailbox_path.push(personal.to_string());
+                        }
                         mailbox_path.push(
                             mailbox
                                 .get(&Property::Name)
@@ -248,9 +245,36 @@ impl<T: SessionStream> SessionData<T> {
             }
         }
 
+        // When the personal prefix is INBOX, all personal mailboxes are listed as its children
+        if account.prefix.is_none()
+            && account.mailbox_names.len() > 1
+            && self
+                .imap
+                .namespace
+                .personal
+                .as_ref()
+                .map_or(false, |personal| personal.eq_ignore_ascii_case("INBOX"))
+        {
+            if let Some(inbox) = account.mailbox_state.get_mut(&INBOX_ID) {
+                inbox.has_children = true;
+            }
+        }
+
         Ok(account)
     }
 
+    pub async fn shared_account_prefix(&self, account_id: u32) -> String {
+        self.imap.namespace.account_prefix(
+            self.jmap
+                .directory
+                .query(QueryBy::Id(account_id), false)
+                .await
+                .unwrap_or_default()
+                .map(|p| p.name)
+                .unwrap_or_else(|| Id::from(account_id).to_string()),
+        )
+    }
+
     pub async fn synchronize_mailboxes(
         &self,
         return_changes: bool,
@@ -314,17 +338,7 @@ impl<T: SessionStream> SessionData<T> {
 
             // Fetch mailboxes for each new shared account
             for account_id in added_account_ids {
-                let prefix = format!(
-                    "{}/{}",
-                    self.imap.name_shared,
-                    self.jmap
-                        .directory
-                        .query(QueryBy::Id(account_id), false)
-                        .await
-                        .unwrap_or_default()
-                        .map(|p| p.name)
-                        .unwrap_or_else(|| Id::from(account_id).to_string())
-                );
+                let prefix = self.shared_account_prefix(account_id).await;
                 match self
                     .fetch_account_mailboxes(account_id, prefix.into(), &access_token)
                     .await
@@ -401,18 +415,7 @@ impl<T: SessionStream> SessionData<T> {
                 } else {
                     // Refresh mailboxes for changed account
                     let mailbox_prefix = if !access_token.is_primary_id(account_id) {
-                        format!(
-                            "{}/{}",
-                            self.imap.name_shared,
-                            self.jmap
-                                .directory
-                                .query(QueryBy::Id(account_id), false)
-                                .await
-                                .unwrap_or_default()
-                                .map(|p| p.name)
-                                .unwrap_or_else(|| Id::from(account_id).to_string())
-                        )
-                        .into()
+                        self.shared_account_prefix(account_id).await.into()
                     } else {
                         None
                     };
@@ -456,6 +459,13 @@ impl<T: SessionStream> SessionData<T> {
                                     {
                                         changes.changed.push(mailbox_name.to_string());
                                     }
+                                    if mailbox.is_subscribed != old_mailbox.is_subscribed {
+                                        if mailbox.is_subscribed {
+                                            changes.subscribed.push(mailbox_name.to_string());
+                                        } else {
+                                            changes.unsubscribed.push(mailbox_name.to_string());
+                                        }
+                                    }
                                 }
                             } else {
                                 changes.added.push(mailbox_name.to_string());
@@ -542,3 +552,64 @@ impl<T: SessionStream> SessionData<T> {
                 .ok_or_else(|| StatusResponse::no("Mailbox no longer exists."))?)
     }
 }
+
+impl Account {
+    pub fn child_names(&self, mailbox_name: &str) -> Vec<String> {
+        // Names are sorted, so all children are stored next to each other
+        let prefix = format!("{mailbox_name}/");
+        self.mailbox_names
+            .range(prefix.clone()..)
+            .map(|(name, _)| name)
+            .take_while(|name| name.starts_with(&prefix))
+            .cloned()
+            .collect()
+    }
+}
+
+impl Namespaces {
+    pub fn account_prefix(&self, account_name: String) -> String {
+        match &self.public {
+            Some(public) if self.public_accounts.contains(&account_name) => {
+                format!("{public}/{account_name}")
+            }
+            _ => format!("{}/{account_name}", self.shared),
+        }
+    }
+
+    pub fn is_personal_root(&self, mailbox_name: &str) -> bool {
+        self.personal.as_ref().map_or(false, |personal| {
+            if personal.eq_ignore_ascii_case("INBOX") {
+                mailbox_name.eq_ignore_ascii_case("INBOX")
+            } else {
+                mailbox_name == personal
+            }
+        })
+    }
+
+    pub fn is_other_users_root(&self, mailbox_name: &str) -> bool {
+        mailbox_name == self.shared || self.public.as_ref().map_or(false, |p| mailbox_name == p)
+    }
+
+    pub fn root_of(&self, account_prefix: &str) -> &str {
+        match &self.public {
+            Some(public)
+                if account_prefix
+                    .strip_prefix(public.as_str())
+                    .map_or(false, |name| name.starts_with('/')) =>
+            {
+                public
+            }
+            _ => &self.shared,
+        }
+    }
+
+    pub fn is_no_select(&self, mailbox_name: &str) -> bool {
+        self.is_other_users_root(mailbox_name)
+            || mailbox_name
+                .split_once('/')
+                .map_or(false, |(base_name, path)| {
+                    self.is_other_users_root(base_name) && !path.contains('/')
+                })
+            || (self.is_personal_root(mailbox_name) && !mailbox_name.eq_ignore_ascii_case("INBOX"))
+    }
+}
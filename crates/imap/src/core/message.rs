@@ -553,4 +553,22 @@ impl SelectedMailbox {
         deleted_ids.sort_unstable();
         deleted_ids
     }
+
+    pub async fn sequence_expand_tombstones(
+        &self,
+        sequence: &Sequence,
+        tombstones: Vec<u32>,
+    ) -> Vec<u32> {
+        if sequence.is_saved_search() {
+            return self.sequence_expand_missing(sequence, true).await;
+        }
+
+        let state = self.state.lock();
+        tombstones
+            .into_iter()
+            .filter(|uid| {
+                sequence.contains(*uid, state.uid_max) && !state.uid_to_id.contains_key(uid)
+            })
+            .collect()
+    }
 }
@@ -28,10 +28,15 @@ use std::{
     time::Duration,
 };
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use dashmap::DashMap;
 use imap_proto::{
-    protocol::{list::Attribute, ProtocolVersion},
+    protocol::{
+        fetch::{BodyPart, Envelope},
+        list::Attribute,
+        notify::EventGroup,
+        ProtocolVersion,
+    },
     receiver::Receiver,
     Command, ResponseCode, StatusResponse,
 };
@@ -39,19 +44,22 @@ use jmap::{
     auth::{rate_limit::AuthenticatedLimiter, AccessToken},
     JMAP,
 };
-use store::roaring::RoaringBitmap;
+use jmap_proto::types::state::StateChange;
+use store::{roaring::RoaringBitmap, BlobHash};
 use tokio::{
     io::{ReadHalf, WriteHalf},
-    sync::watch,
+    sync::{mpsc, watch},
 };
 use utils::{
     config::Rate,
-    listener::{limiter::InFlight, ServerInstance, SessionStream},
+    listener::{limiter::InFlight, tls::ClientCertificate, ServerInstance, SessionStream},
+    map::ttl_dashmap::TtlDashMap,
 };
 
 pub mod client;
 pub mod mailbox;
 pub mod message;
+pub mod proxy;
 pub mod session;
 
 #[derive(Clone)]
@@ -69,7 +77,8 @@ impl ImapSessionManager {
 pub struct IMAP {
     pub max_request_size: usize,
     pub max_auth_failures: u32,
-    pub name_shared: String,
+    pub namespace: Namespaces,
+    pub referral: Referrals,
     pub allow_plain_auth: bool,
     pub enable_uidplus: bool,
 
@@ -83,6 +92,33 @@ pub struct IMAP {
     pub rate_limiter: DashMap<u32, Arc<AuthenticatedLimiter>>,
     pub rate_requests: Rate,
     pub rate_concurrent: u64,
+    pub rate_download: Rate,
+    pub rate_recovery: Rate,
+    pub rate_upload: Rate,
+
+    pub structure_cache: TtlDashMap<BlobHash, Arc<MessageStructure>>,
+    pub structure_cache_size: usize,
+    pub structure_cache_ttl: Duration,
+}
+
+pub struct Referrals {
+    pub proxy: bool,
+    pub timeout_connect: Duration,
+    pub timeout_auth: Duration,
+    pub allow_invalid_certs: bool,
+}
+
+pub struct Namespaces {
+    pub personal: Option<String>,
+    pub shared: String,
+    pub public: Option<String>,
+    pub public_accounts: AHashSet<String>,
+}
+
+pub struct MessageStructure {
+    pub envelope: Envelope<'static>,
+    pub body: BodyPart<'static>,
+    pub body_structure: BodyPart<'static>,
 }
 
 pub struct Session<T: SessionStream> {
@@ -93,8 +129,10 @@ pub struct Session<T: SessionStream> {
     pub version: ProtocolVersion,
     pub state: State<T>,
     pub is_tls: bool,
+    pub tls_client_cert: Option<ClientCertificate>,
     pub is_condstore: bool,
     pub is_qresync: bool,
+    pub notify: Option<Notify>,
     pub stream_rx: ReadHalf<T>,
     pub stream_tx: Arc<tokio::sync::Mutex<WriteHalf<T>>>,
     pub in_flight: InFlight,
@@ -102,6 +140,11 @@ pub struct Session<T: SessionStream> {
     pub span: tracing::Span,
 }
 
+pub struct Notify {
+    pub groups: Arc<Vec<EventGroup>>,
+    pub change_rx: mpsc::Receiver<StateChange>,
+}
+
 pub struct SessionData<T: SessionStream> {
     pub account_id: u32,
     pub jmap: Arc<JMAP>,
@@ -111,6 +154,9 @@ pub struct SessionData<T: SessionStream> {
     pub stream_tx: Arc<tokio::sync::Mutex<WriteHalf<T>>>,
     pub state: AtomicU32,
     pub in_flight: InFlight,
+    pub account_session: Option<InFlight>,
+    pub remote_addr: IpAddr,
+    pub instance: Arc<ServerInstance>,
 }
 
 #[derive(Debug, Default)]
@@ -174,6 +220,8 @@ pub struct MailboxSync {
     pub added: Vec<String>,
     pub changed: Vec<String>,
     pub deleted: Vec<String>,
+    pub subscribed: Vec<String>,
+    pub unsubscribed: Vec<String>,
 }
 
 pub enum SavedSearch {
@@ -253,6 +301,9 @@ impl<T: SessionStream> SessionData<T> {
             stream_tx: new_stream,
             state: self.state,
             in_flight: self.in_flight,
+            account_session: self.account_session,
+            remote_addr: self.remote_addr,
+            instance: self.instance,
         }
     }
 }
@@ -0,0 +1,510 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::{sync::atomic::Ordering, time::Duration};
+
+use base64::{engine::general_purpose, Engine};
+use imap_proto::{protocol::capability::Capability, ResponseCode, StatusResponse};
+use jmap::cluster::{ClusterMetrics, NodeAddress, Protocol};
+use mail_send::{smtp::tls::build_tls_connector, Credentials};
+use rustls::pki_types::ServerName;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use utils::listener::SessionStream;
+
+use super::Session;
+
+#[derive(Debug)]
+enum ProxyError {
+    Unavailable(String),
+    AuthFailed(String),
+}
+
+impl<T: SessionStream> Session<T> {
+    /// Relays the session to the account's home node when it is not the local node.
+    /// Returns `None` when the session has to be served locally.
+    pub async fn proxy_session(
+        &mut self,
+        account: &str,
+        credentials: &Credentials<String>,
+        tag: &str,
+    ) -> Option<crate::Result<()>> {
+        let jmap = self.jmap.clone();
+        let cluster = jmap.cluster.as_ref()?;
+
+        // Sessions relayed by other nodes are always served locally
+        if !cluster.is_peer(&self.remote_addr) {
+            for node in cluster.route(account, Protocol::Imap) {
+                if cluster.is_local(node) {
+                    break;
+                }
+                let address = node.address(Protocol::Imap)?;
+
+                let result = match tokio::time::timeout(
+                    cluster.timeout_connect,
+                    TcpStream::connect((address.host.as_str(), address.port)),
+                )
+                .await
+                {
+                    Ok(Ok(stream)) => {
+                        if address.tls {
+                            match tokio::time::timeout(
+                                cluster.timeout_connect,
+                                connect_tls(address, stream),
+                            )
+                            .await
+                            {
+                                Ok(Ok(stream)) => {
+                                    self.proxy_to(
+                                        cluster.timeout_auth,
+                                        Some(&cluster.metrics),
+                                        stream,
+                                        credentials,
+                                        tag,
+                                        true,
+                                    )
+                                    .await
+                                }
+                                Ok(Err(err)) => Err(err),
+                                Err(_) => Err(ProxyError::Unavailable(
+                                    "TLS handshake timed out".to_string(),
+                                )),
+                            }
+                        } else {
+                            self.proxy_to(
+                                cluster.timeout_auth,
+                                Some(&cluster.metrics),
+                                stream,
+                                credentials,
+                                tag,
+                                true,
+                            )
+                            .await
+                        }
+                    }
+                    Ok(Err(err)) => Err(ProxyError::Unavailable(err.to_string())),
+                    Err(_) => Err(ProxyError::Unavailable("Connection timed out".to_string())),
+                };
+
+                match result {
+                    Ok(result) => return Some(result),
+                    Err(ProxyError::Unavailable(reason)) => {
+                        // Fail over to the next node in the ranking
+                        tracing::warn!(
+                            parent: &self.span,
+                            context = "cluster",
+                            event = "failover",
+                            node = %node.id,
+                            reason = %reason,
+                            "Home node unavailable, failing over."
+                        );
+                        cluster.mark_down(node);
+                    }
+                    Err(ProxyError::AuthFailed(reason)) => {
+                        tracing::warn!(
+                            parent: &self.span,
+                            context = "cluster",
+                            event = "error",
+                            node = %node.id,
+                            reason = %reason,
+                            "Home node rejected credentials, serving session locally."
+                        );
+                        cluster.metrics.proxy_errors.fetch_add(1, Ordering::Relaxed);
+                        break;
+                    }
+                }
+            }
+        }
+
+        cluster
+            .metrics
+            .sessions_local
+            .fetch_add(1, Ordering::Relaxed);
+        None
+    }
+
+    /// Refers the client to the server hosting the account, or relays the
+    /// session to it when referrals are proxied.
+    pub async fn refer_session(
+        &mut self,
+        referral: &str,
+        credentials: Option<&Credentials<String>>,
+        tag: String,
+    ) -> crate::Result<()> {
+        let (url, address) = if let Some(referral) =
+            parse_referral(referral, self.imap.referral.allow_invalid_certs)
+        {
+            referral
+        } else {
+            tracing::warn!(
+                parent: &self.span,
+                context = "referral",
+                event = "error",
+                referral = referral,
+                "Invalid account referral."
+            );
+            return self
+                .write_bytes(
+                    StatusResponse::no("Account is temporarily unavailable.")
+                        .with_tag(tag)
+                        .with_code(ResponseCode::Unavailable)
+                        .into_bytes(),
+                )
+                .await;
+        };
+
+        if let (true, Some(credentials)) = (self.imap.referral.proxy, credentials) {
+            let timeout_connect = self.imap.referral.timeout_connect;
+            let timeout_auth = self.imap.referral.timeout_auth;
+            let result = match tokio::time::timeout(
+                timeout_connect,
+                TcpStream::connect((address.host.as_str(), address.port)),
+            )
+            .await
+            {
+                Ok(Ok(stream)) => {
+                    // Credentials are never relayed in clear text, plain IMAP referrals
+                    // have to be upgraded with STARTTLS
+                    match tokio::time::timeout(timeout_connect, async {
+                        if address.tls {
+                            connect_tls(&address, stream).await
+                        } else {
+                            connect_starttls(&address, stream).await
+                        }
+                    })
+                    .await
+                    {
+                        Ok(Ok(stream)) => {
+                            self.proxy_to(
+                                timeout_auth,
+                                None,
+                                stream,
+                                credentials,
+                                &tag,
+                                address.tls,
+                            )
+                            .await
+                        }
+                        Ok(Err(err)) => Err(err),
+                        Err(_) => Err(ProxyError::Unavailable(
+                            "TLS handshake timed out".to_string(),
+                        )),
+                    }
+                }
+                Ok(Err(err)) => Err(ProxyError::Unavailable(err.to_string())),
+                Err(_) => Err(ProxyError::Unavailable("Connection timed out".to_string())),
+            };
+
+            match result {
+                Ok(result) => return result,
+                Err(ProxyError::Unavailable(reason) | ProxyError::AuthFailed(reason)) => {
+                    // Fall back to referring the client
+                    tracing::warn!(
+                        parent: &self.span,
+                        context = "referral",
+                        event = "error",
+                        url = url,
+                        reason = %reason,
+                        "Failed to relay session to referred server."
+                    );
+                }
+            }
+        }
+
+        tracing::debug!(
+            parent: &self.span,
+            context = "referral",
+            event = "refer",
+            url = url,
+            "Referring client to another server."
+        );
+
+        self.write_bytes(
+            StatusResponse::no("Account has been moved to another server.")
+                .with_tag(tag)
+                .with_code(ResponseCode::Referral { url })
+                .into_bytes(),
+        )
+        .await
+    }
+
+    async fn proxy_to<S: AsyncRead + AsyncWrite + Unpin>(
+        &mut self,
+        timeout_auth: Duration,
+        metrics: Option<&ClusterMetrics>,
+        backend: S,
+        credentials: &Credentials<String>,
+        tag: &str,
+        has_greeting: bool,
+    ) -> Result<crate::Result<()>, ProxyError> {
+        let mut backend = BufReader::new(backend);
+        tokio::time::timeout(
+            timeout_auth,
+            authenticate(&mut backend, credentials, has_greeting),
+        )
+        .await
+        .map_err(|_| ProxyError::Unavailable("Authentication timed out".to_string()))??;
+
+        tracing::debug!(
+            parent: &self.span,
+            context = "proxy",
+            event = "start",
+            "Relaying session to remote server."
+        );
+
+        if self
+            .write_bytes(
+                StatusResponse::ok("Authentication successful")
+                    .with_code(ResponseCode::Capability {
+                        capabilities: Capability::all_capabilities(true, self.is_tls),
+                    })
+                    .with_tag(tag)
+                    .into_bytes(),
+            )
+            .await
+            .is_err()
+        {
+            return Ok(Err(()));
+        }
+
+        if let Some(metrics) = metrics {
+            metrics.sessions_proxied.fetch_add(1, Ordering::Relaxed);
+            metrics
+                .sessions_proxied_active
+                .fetch_add(1, Ordering::Relaxed);
+        }
+
+        // Relay traffic in both directions until either side closes the connection
+        let (mut backend_rx, mut backend_tx) = tokio::io::split(backend);
+        let mut stream_tx = self.stream_tx.lock().await;
+        let mut shutdown_rx = self.instance.shutdown_rx.clone();
+        tokio::select! {
+            result = tokio::io::copy(&mut self.stream_rx, &mut backend_tx) => {
+                if let Err(err) = result {
+                    tracing::debug!(parent: &self.span, event = "error", reason = %err, "IMAP connection error.");
+                }
+            },
+            result = tokio::io::copy(&mut backend_rx, &mut *stream_tx) => {
+                if let Err(err) = result {
+                    tracing::debug!(parent: &self.span, context = "proxy", event = "error", reason = %err, "Remote server connection error.");
+                    if let Some(metrics) = metrics {
+                        metrics.proxy_errors.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            },
+            _ = shutdown_rx.changed() => {
+                let _ = stream_tx.write_all(b"* BYE Server shutting down.\r\n").await;
+            }
+        };
+        let _ = stream_tx.flush().await;
+
+        if let Some(metrics) = metrics {
+            metrics
+                .sessions_proxied_active
+                .fetch_sub(1, Ordering::Relaxed);
+        }
+
+        Ok(Err(()))
+    }
+}
+
+async fn connect_tls(
+    address: &NodeAddress,
+    stream: TcpStream,
+) -> Result<tokio_rustls::client::TlsStream<TcpStream>, ProxyError> {
+    build_tls_connector(address.allow_invalid_certs)
+        .connect(
+            ServerName::try_from(address.host.clone())
+                .map_err(|_| ProxyError::Unavailable("Invalid TLS name".to_string()))?,
+            stream,
+        )
+        .await
+        .map_err(|err| ProxyError::Unavailable(err.to_string()))
+}
+
+async fn connect_starttls(
+    address: &NodeAddress,
+    stream: TcpStream,
+) -> Result<tokio_rustls::client::TlsStream<TcpStream>, ProxyError> {
+    let mut backend = BufReader::new(stream);
+    let mut line = String::with_capacity(128);
+    read_greeting(&mut backend, &mut line).await?;
+
+    let backend_tx = backend.get_mut();
+    backend_tx
+        .write_all(b"P0 STARTTLS\r\n")
+        .await
+        .map_err(|err| ProxyError::Unavailable(err.to_string()))?;
+    backend_tx
+        .flush()
+        .await
+        .map_err(|err| ProxyError::Unavailable(err.to_string()))?;
+
+    // Skip untagged responses until the command completes
+    loop {
+        read_line(&mut backend, &mut line).await?;
+        if let Some(response) = line.strip_prefix("P0 ") {
+            if !response.starts_with("OK") {
+                return Err(ProxyError::Unavailable(format!(
+                    "STARTTLS failed: {}",
+                    response.trim_end()
+                )));
+            } else if !backend.buffer().is_empty() {
+                return Err(ProxyError::Unavailable(
+                    "Unexpected data after STARTTLS".to_string(),
+                ));
+            }
+            break;
+        }
+    }
+
+    connect_tls(address, backend.into_inner()).await
+}
+
+async fn authenticate<S: AsyncRead + AsyncWrite + Unpin>(
+    backend: &mut BufReader<S>,
+    credentials: &Credentials<String>,
+    has_greeting: bool,
+) -> Result<(), ProxyError> {
+    let mut line = String::with_capacity(128);
+
+    // The greeting was already consumed when the connection was upgraded with STARTTLS
+    if has_greeting {
+        read_greeting(backend, &mut line).await?;
+    }
+
+    // Forward the client's credentials
+    let command = match credentials {
+        Credentials::Plain { username, secret } | Credentials::XOauth2 { username, secret } => {
+            format!(
+                "P1 AUTHENTICATE PLAIN {}\r\n",
+                general_purpose::STANDARD.encode(format!("\0{username}\0{secret}"))
+            )
+        }
+        Credentials::OAuthBearer { token } => {
+            format!(
+                "P1 AUTHENTICATE OAUTHBEARER {}\r\n",
+                general_purpose::STANDARD.encode(format!("n,,\x01auth=Bearer {token}\x01\x01"))
+            )
+        }
+    };
+    let backend_tx = backend.get_mut();
+    backend_tx
+        .write_all(command.as_bytes())
+        .await
+        .map_err(|err| ProxyError::Unavailable(err.to_string()))?;
+    backend_tx
+        .flush()
+        .await
+        .map_err(|err| ProxyError::Unavailable(err.to_string()))?;
+
+    // Skip untagged responses until the command completes
+    loop {
+        read_line(backend, &mut line).await?;
+        if let Some(response) = line.strip_prefix("P1 ") {
+            return if response.starts_with("OK") {
+                Ok(())
+            } else {
+                Err(ProxyError::AuthFailed(response.trim_end().to_string()))
+            };
+        } else if line.starts_with('+') {
+            return Err(ProxyError::AuthFailed(
+                "Unexpected continuation request".to_string(),
+            ));
+        }
+    }
+}
+
+async fn read_greeting<S: AsyncRead + AsyncWrite + Unpin>(
+    backend: &mut BufReader<S>,
+    line: &mut String,
+) -> Result<(), ProxyError> {
+    read_line(backend, line).await?;
+    if line.starts_with("* OK") {
+        Ok(())
+    } else {
+        Err(ProxyError::Unavailable(format!(
+            "Unexpected greeting: {}",
+            line.trim_end()
+        )))
+    }
+}
+
+async fn read_line<S: AsyncRead + AsyncWrite + Unpin>(
+    backend: &mut BufReader<S>,
+    line: &mut String,
+) -> Result<(), ProxyError> {
+    line.clear();
+    match backend.read_line(line).await {
+        Ok(0) => Err(ProxyError::Unavailable(
+            "Connection closed by home node".to_string(),
+        )),
+        Ok(_) => Ok(()),
+        Err(err) => Err(ProxyError::Unavailable(err.to_string())),
+    }
+}
+
+/// Parses an account referral, either an IMAP URL or a bare `host[:port]`,
+/// returning the URL to send to clients and the address to relay sessions to.
+/// Bare referrals are assumed to use implicit TLS.
+fn parse_referral(referral: &str, allow_invalid_certs: bool) -> Option<(String, NodeAddress)> {
+    let referral = referral.trim();
+    let (url, tls, location) = if let Some((scheme, location)) = referral.split_once("://") {
+        let tls = if scheme.eq_ignore_ascii_case("imap") {
+            false
+        } else if scheme.eq_ignore_ascii_case("imaps") {
+            true
+        } else {
+            return None;
+        };
+        (referral.to_string(), tls, location)
+    } else {
+        (format!("imaps://{referral}/"), true, referral)
+    };
+
+    // Skip the user information and path of the URL
+    let authority = location.split('/').next()?;
+    let authority = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, authority)| authority);
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.ends_with(']') => (host, port.parse::<u16>().ok()?),
+        _ => (authority, if tls { 993 } else { 143 }),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    if !host.is_empty() {
+        Some((
+            url,
+            NodeAddress {
+                host: host.to_string(),
+                port,
+                tls,
+                allow_invalid_certs,
+            },
+        ))
+    } else {
+        None
+    }
+}
@@ -28,6 +28,8 @@ use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio_rustls::server::TlsStream;
 use utils::listener::{stream::NullIo, SessionManager, SessionStream};
 
+use crate::op::notify::next_notify_change;
+
 use super::{ImapSessionManager, Session, State};
 
 impl SessionManager for ImapSessionManager {
@@ -96,6 +98,14 @@ impl<T: SessionStream> Session<T> {
                         }
                     }
                 },
+                state_change = next_notify_change(&mut self.notify) => {
+                    if let Some(state_change) = state_change {
+                        self.write_notify_changes(state_change).await;
+                    } else {
+                        tracing::debug!(parent: &self.span, "NOTIFY channel closed.");
+                        self.notify = None;
+                    }
+                },
                 _ = shutdown_rx.changed() => {
                     self.write_bytes(&b"* BYE Server shutting down.\r\n"[..]).await.ok();
                     tracing::debug!(parent: &self.span, event = "shutdown", "IMAP server shutting down.");
@@ -117,13 +127,26 @@ impl<T: SessionStream> Session<T> {
         } else {
             (false, &manager.imap.greeting_plain)
         };
-        if let Err(err) = session.stream.write_all(greeting).await {
+        let greeting: Cow<[u8]> = if let Some(banner) = &session.instance.banner {
+            // Legal notices and other banners are sent as untagged OK responses
+            let mut greeting = greeting.clone();
+            for line in banner.render(&session.instance.hostname) {
+                greeting.extend_from_slice(b"* OK ");
+                greeting.extend_from_slice(line.as_bytes());
+                greeting.extend_from_slice(b"\r\n");
+            }
+            greeting.into()
+        } else {
+            greeting.as_slice().into()
+        };
+        if let Err(err) = session.stream.write_all(&greeting).await {
             tracing::debug!(parent: &session.span, event = "error", reason = %err, "Failed to write greeting.");
             return Err(());
         }
         let _ = session.stream.flush().await;
 
         // Split stream into read and write halves
+        let tls_client_cert = session.stream.tls_client_certificate();
         let (stream_rx, stream_tx) = tokio::io::split(session.stream);
 
         Ok(Session {
@@ -131,8 +154,10 @@ impl<T: SessionStream> Session<T> {
             version: ProtocolVersion::Rev1,
             state: State::NotAuthenticated { auth_failures: 0 },
             is_tls,
+            tls_client_cert,
             is_condstore: false,
             is_qresync: false,
+            notify: None,
             imap: manager.imap,
             jmap: manager.jmap,
             instance: session.instance,
@@ -168,22 +193,29 @@ impl<T: SessionStream> Session<T> {
         };
 
         // Upgrade to TLS
-        let (stream_rx, stream_tx) =
-            tokio::io::split(self.instance.tls_accept(stream, &self.span).await?);
+        let stream = self.instance.tls_accept(stream, &self.span).await?;
+        let (instance, in_flight) = match self.instance.virtual_host(&stream, &self.span)? {
+            Some((instance, in_flight)) => (instance, in_flight.with_parent(self.in_flight)),
+            None => (self.instance, self.in_flight),
+        };
+        let tls_client_cert = stream.tls_client_certificate();
+        let (stream_rx, stream_tx) = tokio::io::split(stream);
         let stream_tx = Arc::new(tokio::sync::Mutex::new(stream_tx));
 
         Ok(Session {
             jmap: self.jmap,
             imap: self.imap,
-            instance: self.instance,
+            instance,
             receiver: self.receiver,
             version: self.version,
             state: state.try_replace_stream_tx(stream_tx.clone()).unwrap(),
             is_tls: true,
+            tls_client_cert,
             is_condstore: self.is_condstore,
             is_qresync: self.is_qresync,
+            notify: self.notify,
             span: self.span,
-            in_flight: self.in_flight,
+            in_flight,
             remote_addr: self.remote_addr,
             stream_rx,
             stream_tx,
@@ -237,4 +269,22 @@ impl<T: SessionStream> super::SessionData<T> {
             true
         }
     }
+
+    pub async fn throttle_download(&self, bytes: usize) {
+        self.imap
+            .get_authenticated_limiter(self.account_id)
+            .bandwidth
+            .throttle_download(bytes)
+            .await;
+        self.instance.bandwidth.throttle_download(bytes).await;
+    }
+
+    pub async fn throttle_upload(&self, bytes: usize) {
+        self.imap
+            .get_authenticated_limiter(self.account_id)
+            .bandwidth
+            .throttle_upload(bytes)
+            .await;
+        self.instance.bandwidth.throttle_upload(bytes).await;
+    }
 }
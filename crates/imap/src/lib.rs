@@ -23,11 +23,14 @@
 
 use std::{collections::hash_map::RandomState, sync::Arc};
 
-use crate::core::IMAP;
+use crate::core::{Namespaces, Referrals, IMAP};
 
 use dashmap::DashMap;
 use imap_proto::{protocol::capability::Capability, ResponseCode, StatusResponse};
-use utils::config::Config;
+use utils::{
+    config::Config,
+    map::ttl_dashmap::{TtlDashMap, TtlMap},
+};
 
 pub mod core;
 pub mod op;
@@ -40,13 +43,19 @@ static SERVER_GREETING: &str = concat!(
 
 impl IMAP {
     pub async fn init(config: &Config) -> utils::config::Result<Arc<Self>> {
+        let structure_cache_size = config.property("imap.fetch.cache.size")?.unwrap_or(4096);
         Ok(Arc::new(IMAP {
             max_request_size: config.property_or_static("imap.request.max-size", "52428800")?,
             max_auth_failures: config.property_or_static("imap.auth.max-failures", "3")?,
-            name_shared: config
-                .value("imap.folders.name.shared")
-                .unwrap_or("Shared Folders")
-                .to_string(),
+            namespace: Namespaces::parse(config)?,
+            referral: Referrals {
+                proxy: config.property_or_static("imap.referral.proxy", "false")?,
+                timeout_connect: config
+                    .property_or_static("imap.referral.timeout.connect", "5s")?,
+                timeout_auth: config.property_or_static("imap.referral.timeout.auth", "15s")?,
+                allow_invalid_certs: config
+                    .property_or_static("imap.referral.allow-invalid-certs", "false")?,
+            },
             timeout_auth: config.property_or_static("imap.timeout.authenticated", "30m")?,
             timeout_unauth: config.property_or_static("imap.timeout.anonymous", "1m")?,
             timeout_idle: config.property_or_static("imap.timeout.idle", "30m")?,
@@ -72,12 +81,78 @@ impl IMAP {
             ),
             rate_requests: config.property_or_static("imap.rate-limit.requests", "2000/1m")?,
             rate_concurrent: config.property("imap.rate-limit.concurrent")?.unwrap_or(4),
+            rate_download: config
+                .property("imap.rate-limit.bandwidth.download")?
+                .unwrap_or_default(),
+            rate_upload: config
+                .property("imap.rate-limit.bandwidth.upload")?
+                .unwrap_or_default(),
+            rate_recovery: config.property_or_static("jmap.recovery.rate", "5/1h")?,
+            structure_cache: TtlDashMap::with_capacity(
+                structure_cache_size,
+                config
+                    .property::<u64>("global.shared-map.shard")?
+                    .unwrap_or(32)
+                    .next_power_of_two() as usize,
+            ),
+            structure_cache_size,
+            structure_cache_ttl: config.property_or_static("imap.fetch.cache.ttl", "1d")?,
             allow_plain_auth: config.property_or_static("imap.auth.allow-plain-text", "false")?,
             enable_uidplus: config.property_or_static("imap.protocol.uidplus", "false")?,
         }))
     }
 }
 
+impl Namespaces {
+    pub fn parse(config: &Config) -> utils::config::Result<Self> {
+        let prefix = |key: &str| {
+            config
+                .value(key)
+                .map(|value| value.trim().trim_end_matches(['/', '.']).trim())
+                .filter(|value| !value.is_empty())
+                .map(|value| {
+                    if !value.contains('/') {
+                        Ok(value.to_string())
+                    } else {
+                        Err(format!(
+                            "Invalid namespace prefix {value:?} for key {key:?}."
+                        ))
+                    }
+                })
+                .transpose()
+        };
+
+        // "imap.folders.name.shared" is kept for backwards compatibility
+        let namespace = Namespaces {
+            personal: prefix("imap.namespace.personal")?,
+            shared: if let Some(shared) = prefix("imap.namespace.shared")? {
+                shared
+            } else {
+                prefix("imap.folders.name.shared")?.unwrap_or_else(|| "Shared Folders".to_string())
+            },
+            public: prefix("imap.namespace.public")?,
+            public_accounts: config
+                .values("imap.namespace.public-accounts")
+                .map(|(_, name)| name.to_string())
+                .collect(),
+        };
+
+        if namespace.personal.as_ref().map_or(false, |personal| {
+            personal == &namespace.shared || namespace.public.as_ref() == Some(personal)
+        }) || namespace.public.as_ref() == Some(&namespace.shared)
+        {
+            Err("Personal, shared and public namespace prefixes must be different.".to_string())
+        } else if namespace.public.is_none() && !namespace.public_accounts.is_empty() {
+            Err(
+                "Property \"imap.namespace.public-accounts\" requires a public namespace prefix."
+                    .to_string(),
+            )
+        } else {
+            Ok(namespace)
+        }
+    }
+}
+
 pub struct ImapError;
 
 pub type Result<T> = std::result::Result<T, ()>;
@@ -38,6 +38,7 @@ use jmap::{
 };
 use jmap_proto::{
     error::method::MethodError,
+    method::activity::ActivityEvent,
     object::{index::ObjectIndexBuilder, Object},
     types::{
         acl::Acl,
@@ -49,7 +50,7 @@ use jmap_proto::{
     },
 };
 use store::write::{assert::HashedValue, log::ChangeLogBuilder, BatchBuilder};
-use utils::{listener::SessionStream, map::bitmap::Bitmap};
+use utils::{config::ServerProtocol, listener::SessionStream, map::bitmap::Bitmap};
 
 use crate::core::{MailboxId, Session, SessionData};
 
@@ -363,6 +364,19 @@ impl<T: SessionStream> Session<T> {
                                                     .with_change(DataType::Mailbox, change_id),
                                             )
                                             .await;
+                                        data.jmap
+                                            .log_activity(
+                                                mailbox.account_id,
+                                                ActivityEvent::SharingChange,
+                                                ServerProtocol::Imap,
+                                                data.remote_addr.into(),
+                                                format!(
+                                                    "Mailbox ACL changed for {}",
+                                                    arguments.identifier.as_deref().unwrap_or("")
+                                                )
+                                                .into(),
+                                            )
+                                            .await;
                                     }
                                     Err(_) => {
                                         data.write_bytes(
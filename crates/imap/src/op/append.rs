@@ -24,19 +24,28 @@
 use std::sync::Arc;
 
 use imap_proto::{
-    protocol::{append::Arguments, select::HighestModSeq},
+    protocol::{
+        append::{Arguments, CatenatePart, ImapUrl},
+        select::HighestModSeq,
+    },
     receiver::Request,
     Command, ResponseCode, StatusResponse,
 };
 
-use jmap::email::ingest::IngestEmail;
-use jmap_proto::types::{acl::Acl, keyword::Keyword, state::StateChange, type_state::DataType};
+use jmap::{
+    email::{ingest::IngestEmail, metadata::MessageMetadata},
+    Bincode,
+};
+use jmap_proto::types::{
+    acl::Acl, collection::Collection, keyword::Keyword, property::Property, state::StateChange,
+    type_state::DataType,
+};
 use mail_parser::MessageParser;
 use utils::listener::SessionStream;
 
 use crate::core::{MailboxId, SelectedMailbox, Session, SessionData};
 
-use super::ToModSeq;
+use super::{fetch::AsImapDataItem, ToModSeq};
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_append(&mut self, request: Request<Command>) -> crate::OpResult {
@@ -90,7 +99,7 @@ impl<T: SessionStream> Session<T> {
 impl<T: SessionStream> SessionData<T> {
     async fn append_messages(
         &self,
-        arguments: Arguments,
+        mut arguments: Arguments,
         selected_mailbox: Option<Arc<SelectedMailbox>>,
         mailbox: MailboxId,
         is_qresync: bool,
@@ -117,11 +126,22 @@ impl<T: SessionStream> SessionData<T> {
             .map_err(|r| r.with_tag(&arguments.tag))?
             .quota as i64;
 
+        // Compose catenated messages before appending any of them
+        for message in &mut arguments.messages {
+            if !message.catenate.is_empty() {
+                message.message = self
+                    .catenate_message(std::mem::take(&mut message.catenate))
+                    .await
+                    .map_err(|r| r.with_tag(&arguments.tag))?;
+            }
+        }
+
         // Append messages
         let mut response = StatusResponse::completed(Command::Append);
         let mut created_ids = Vec::with_capacity(arguments.messages.len());
         let mut last_change_id = None;
         for message in arguments.messages {
+            self.throttle_upload(message.message.len()).await;
             match self
                 .jmap
                 .email_ingest(IngestEmail {
@@ -219,4 +239,88 @@ impl<T: SessionStream> SessionData<T> {
 
         Ok(response.with_tag(arguments.tag))
     }
+
+    async fn catenate_message(&self, parts: Vec<CatenatePart>) -> crate::op::Result<Vec<u8>> {
+        let mut message = Vec::new();
+        for part in parts {
+            match part {
+                CatenatePart::Text(text) => {
+                    message.extend_from_slice(&text);
+                }
+                CatenatePart::Url(url) => {
+                    if let Some(contents) = self.fetch_url(&url).await? {
+                        message.extend_from_slice(&contents);
+                    } else {
+                        return Err(StatusResponse::no("Invalid or inaccessible URL.")
+                            .with_code(ResponseCode::BadUrl { url }));
+                    }
+                }
+            }
+
+            if message.len() > self.jmap.config.mail_max_size {
+                return Err(StatusResponse::no("Catenated message is too large.")
+                    .with_code(ResponseCode::TooBig));
+            }
+        }
+        Ok(message)
+    }
+
+    async fn fetch_url(&self, url: &str) -> crate::op::Result<Option<Vec<u8>>> {
+        let url = if let Some(url) = ImapUrl::parse(url) {
+            url
+        } else {
+            return Ok(None);
+        };
+
+        // Referenced messages are subject to the ACLs of the authenticated user
+        let mailbox = if let Some(mailbox) = self.get_mailbox_by_name(&url.mailbox_name) {
+            mailbox
+        } else {
+            return Ok(None);
+        };
+        if !self
+            .check_mailbox_acl(mailbox.account_id, mailbox.mailbox_id, Acl::ReadItems)
+            .await?
+        {
+            return Ok(None);
+        }
+        let state = self.fetch_messages(&mailbox).await?;
+        let document_id = match state.uid_to_id.get(&url.uid) {
+            Some(document_id)
+                if url
+                    .uid_validity
+                    .map_or(true, |uid_validity| uid_validity == state.uid_validity) =>
+            {
+                *document_id
+            }
+            _ => return Ok(None),
+        };
+
+        // Obtain the message or the requested part
+        let metadata = if let Some(metadata) = self
+            .jmap
+            .get_property::<Bincode<MessageMetadata>>(
+                mailbox.account_id,
+                Collection::Email,
+                document_id,
+                &Property::BodyStructure,
+            )
+            .await?
+        {
+            metadata.inner
+        } else {
+            return Ok(None);
+        };
+        let raw_message = if let Some(raw_message) =
+            self.jmap.get_blob(&metadata.blob_hash, 0..u32::MAX).await?
+        {
+            raw_message
+        } else {
+            return Ok(None);
+        };
+        let message = metadata.contents.into_message(&raw_message);
+        Ok(message
+            .body_section(&url.sections, url.partial)
+            .map(|contents| contents.into_owned()))
+    }
 }
@@ -29,9 +29,11 @@ use imap_proto::{
     receiver::{self, Request},
     Command, ResponseCode, StatusResponse,
 };
+use jmap::auth::AccessToken;
+use jmap_proto::method::activity::ActivityEvent;
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
-use utils::listener::SessionStream;
+use utils::{config::ServerProtocol, listener::SessionStream};
 
 use crate::core::{Session, SessionData, State};
 
@@ -81,6 +83,40 @@ impl<T: SessionStream> Session<T> {
                         self.write_bytes(b"+ \"\"\r\n".to_vec()).await
                     }
                 }
+                Mechanism::External => {
+                    if !args.params.is_empty() {
+                        // An empty response or "=" requests the certificate's identity
+                        let response = args.params.pop().unwrap();
+                        match if response.is_empty() || response == "=" {
+                            Some(Vec::new())
+                        } else {
+                            base64_decode(response.as_bytes())
+                        } {
+                            Some(authz_id) => self.authenticate_external(authz_id, args.tag).await,
+                            None => {
+                                self.write_bytes(
+                                    StatusResponse::no("Failed to decode challenge.")
+                                        .with_tag(args.tag)
+                                        .with_code(ResponseCode::Parse)
+                                        .into_bytes(),
+                                )
+                                .await
+                            }
+                        }
+                    } else {
+                        self.receiver.request = receiver::Request {
+                            tag: args.tag,
+                            command: Command::Authenticate,
+                            // An empty response defaults to the certificate's identity
+                            tokens: vec![
+                                receiver::Token::Argument(args.mechanism.into_bytes()),
+                                receiver::Token::Argument(b"=".to_vec()),
+                            ],
+                        };
+                        self.receiver.state = receiver::State::Argument { last_ch: b' ' };
+                        self.write_bytes(b"+ \"\"\r\n".to_vec()).await
+                    }
+                }
                 _ => {
                     self.write_bytes(
                         StatusResponse::no("Authentication mechanism not supported.")
@@ -101,25 +137,14 @@ impl<T: SessionStream> Session<T> {
         tag: String,
     ) -> crate::Result<()> {
         // Throttle authentication requests
-        if self.jmap.is_auth_allowed_soft(&self.remote_addr).is_err() {
-            self.write_bytes(
-                StatusResponse::bye("Too many authentication requests from this IP address.")
-                    .into_bytes(),
-            )
-            .await?;
-            tracing::debug!(parent: &self.span,
-                event = "disconnect",
-                "Too many authentication attempts, disconnecting.",
-            );
-            return Err(());
-        }
+        self.is_auth_allowed().await?;
 
         // Authenticate
-        let access_token = match credentials {
+        let access_token = match &credentials {
             Credentials::Plain { username, secret } | Credentials::XOauth2 { username, secret } => {
                 match self
                     .jmap
-                    .authenticate_plain(&username, &secret, self.remote_addr)
+                    .authenticate_plain(username, secret, self.remote_addr)
                     .await
                 {
                     AuthResult::Success(token) => Some(token),
@@ -128,11 +153,7 @@ impl<T: SessionStream> Session<T> {
                 }
             }
             Credentials::OAuthBearer { token } => {
-                match self
-                    .jmap
-                    .validate_access_token("access_token", &token)
-                    .await
-                {
+                match self.jmap.validate_access_token("access_token", token).await {
                     Ok((account_id, _, _)) => self.jmap.get_access_token(account_id).await,
                     Err(err) => {
                         tracing::debug!(
@@ -147,6 +168,124 @@ impl<T: SessionStream> Session<T> {
             }
         };
 
+        if let Some(access_token) = &access_token {
+            // Refer accounts hosted on another server
+            if let Some(referral) = &access_token.referral {
+                return self.refer_session(referral, Some(&credentials), tag).await;
+            }
+
+            // Relay the session to the account's home node
+            if let Some(result) = self
+                .proxy_session(&access_token.name, &credentials, &tag)
+                .await
+            {
+                return result;
+            }
+        }
+
+        self.start_authenticated_session(access_token, tag).await
+    }
+
+    pub async fn authenticate_external(
+        &mut self,
+        authz_id: Vec<u8>,
+        tag: String,
+    ) -> crate::Result<()> {
+        // Throttle authentication requests
+        self.is_auth_allowed().await?;
+
+        // Map the client certificate to a principal
+        let identities = match (&self.tls_client_cert, &self.instance.tls_client_auth) {
+            (Some(cert), Some(client_auth)) => cert.principal_names(&client_auth.principal),
+            _ => vec![],
+        };
+        let principal = if !identities.is_empty() {
+            match self
+                .jmap
+                .directory
+                .query_identities(&identities, false)
+                .await
+            {
+                Ok(principal) => principal,
+                Err(_) => {
+                    return self
+                        .write_bytes(
+                            StatusResponse::database_failure()
+                                .with_tag(tag)
+                                .into_bytes(),
+                        )
+                        .await;
+                }
+            }
+        } else {
+            None
+        };
+
+        // Validate the authorization identity, if any
+        let authz_id = String::from_utf8_lossy(&authz_id).trim().to_lowercase();
+        let access_token = match principal {
+            Some(principal)
+                if authz_id.is_empty()
+                    || principal.name.eq_ignore_ascii_case(&authz_id)
+                    || principal
+                        .emails
+                        .iter()
+                        .any(|email| email.eq_ignore_ascii_case(&authz_id)) =>
+            {
+                tracing::debug!(
+                    parent: &self.span,
+                    context = "authenticate",
+                    event = "external",
+                    account_id = principal.id,
+                    "Authenticated using a client certificate."
+                );
+                self.jmap.get_access_token(principal.id).await
+            }
+            _ => {
+                tracing::debug!(
+                    parent: &self.span,
+                    context = "authenticate",
+                    event = "external",
+                    identities = ?identities,
+                    "Client certificate does not match any principal."
+                );
+                None
+            }
+        };
+
+        // Refer accounts hosted on another server
+        if let Some(referral) = access_token
+            .as_ref()
+            .and_then(|access_token| access_token.referral.as_deref())
+        {
+            return self.refer_session(referral, None, tag).await;
+        }
+
+        self.start_authenticated_session(access_token, tag).await
+    }
+
+    async fn is_auth_allowed(&self) -> crate::Result<()> {
+        if self.jmap.is_auth_allowed_soft(&self.remote_addr).is_err() {
+            self.write_bytes(
+                StatusResponse::bye("Too many authentication requests from this IP address.")
+                    .into_bytes(),
+            )
+            .await?;
+            tracing::debug!(parent: &self.span,
+                event = "disconnect",
+                "Too many authentication attempts, disconnecting.",
+            );
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn start_authenticated_session(
+        &mut self,
+        access_token: Option<AccessToken>,
+        tag: String,
+    ) -> crate::Result<()> {
         if let Some(access_token) = access_token {
             // Enforce concurrency limits
             let in_flight = self
@@ -155,13 +294,51 @@ impl<T: SessionStream> Session<T> {
                 .concurrent_requests
                 .is_allowed();
             if let Some(in_flight) = in_flight {
+                // Enforce the session budget shared with other protocols
+                let account_session = match self.jmap.smtp.acquire_account_session(
+                    &access_token.name,
+                    access_token.role(),
+                    ServerProtocol::Imap,
+                ) {
+                    Ok(account_session) => account_session,
+                    Err(limit) => {
+                        tracing::debug!(parent: &self.span,
+                            event = "session-limit",
+                            account = access_token.name,
+                            limit = limit,
+                            "Account exceeded its concurrent session budget.",
+                        );
+                        return self
+                            .write_bytes(
+                                StatusResponse::no(
+                                    "Too many concurrent sessions for this account.",
+                                )
+                                .with_tag(tag)
+                                .with_code(ResponseCode::Limit)
+                                .into_bytes(),
+                            )
+                            .await;
+                    }
+                };
+
                 // Cache access token
                 let access_token = Arc::new(access_token);
                 self.jmap.cache_access_token(access_token.clone());
+                self.jmap
+                    .log_activity(
+                        access_token.primary_id(),
+                        ActivityEvent::Login,
+                        ServerProtocol::Imap,
+                        self.remote_addr.into(),
+                        None,
+                    )
+                    .await;
 
                 // Create session
                 self.state = State::Authenticated {
-                    data: Arc::new(SessionData::new(self, &access_token, in_flight).await?),
+                    data: Arc::new(
+                        SessionData::new(self, &access_token, in_flight, account_session).await?,
+                    ),
                 };
                 self.write_bytes(
                     StatusResponse::ok("Authentication successful")
@@ -216,6 +393,7 @@ impl<T: SessionStream> Session<T> {
 
     pub async fn handle_unauthenticate(&mut self, request: Request<Command>) -> crate::OpResult {
         self.state = State::NotAuthenticated { auth_failures: 0 };
+        self.notify = None;
 
         self.write_bytes(
             StatusResponse::completed(Command::Unauthenticate)
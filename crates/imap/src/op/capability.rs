@@ -23,6 +23,7 @@
 
 use imap_proto::{
     protocol::{
+        authenticate::Mechanism,
         capability::{Capability, Response},
         ImapResponse,
     },
@@ -36,18 +37,21 @@ use crate::core::Session;
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_capability(&mut self, request: Request<Command>) -> crate::OpResult {
+        let is_authenticated = self.state.is_authenticated();
+        let mut capabilities = Capability::all_capabilities(is_authenticated, self.is_tls);
+
+        // Advertise EXTERNAL when a client certificate was presented
+        if !is_authenticated
+            && self.tls_client_cert.is_some()
+            && self.instance.tls_client_auth.is_some()
+        {
+            capabilities.push(Capability::Auth(Mechanism::External));
+        }
+
         self.write_bytes(
             StatusResponse::completed(Command::Capability)
                 .with_tag(request.tag)
-                .serialize(
-                    Response {
-                        capabilities: Capability::all_capabilities(
-                            self.state.is_authenticated(),
-                            self.is_tls,
-                        ),
-                    }
-                    .serialize(),
-                ),
+                .serialize(Response { capabilities }.serialize()),
         )
         .await
     }
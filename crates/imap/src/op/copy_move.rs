@@ -28,16 +28,20 @@ use imap_proto::{
     StatusResponse,
 };
 
-use jmap::{email::set::TagManager, mailbox::UidMailbox};
+use jmap::{
+    email::set::TagManager,
+    mailbox::{UidMailbox, JUNK_ID},
+};
 use jmap_proto::{
     error::{method::MethodError, set::SetErrorType},
+    method::activity::ActivityEvent,
     types::{
-        acl::Acl, collection::Collection, id::Id, property::Property, state::StateChange,
-        type_state::DataType,
+        acl::Acl, collection::Collection, id::Id, keyword::Keyword, property::Property,
+        state::StateChange, type_state::DataType,
     },
 };
 use store::write::{assert::HashedValue, log::ChangeLogBuilder, BatchBuilder, F_VALUE};
-use utils::listener::SessionStream;
+use utils::{config::ServerProtocol, listener::SessionStream};
 
 use crate::core::{MailboxId, SelectedMailbox, Session, SessionData};
 
@@ -160,6 +164,23 @@ impl<T: SessionStream> SessionData<T> {
                 .with_tag(arguments.tag).with_code(ResponseCode::NoPerm));
         }
 
+        // Verify that the user can read messages from a mailbox in another account.
+        if src_mailbox.id.account_id != dest_mailbox.account_id
+            && !self
+                .check_mailbox_acl(
+                    src_mailbox.id.account_id,
+                    src_mailbox.id.mailbox_id,
+                    Acl::ReadItems,
+                )
+                .await
+                .map_err(|_| StatusResponse::database_failure().with_tag(&arguments.tag))?
+        {
+            return Err(StatusResponse::no(
+                    "You do not have the required permissions to read messages from the source mailbox.",
+                )
+                .with_tag(arguments.tag).with_code(ResponseCode::NoPerm));
+        }
+
         // Verify that the user can append messages to the destination mailbox.
         let dest_mailbox_id = dest_mailbox.mailbox_id;
         if !self
@@ -184,6 +205,7 @@ impl<T: SessionStream> SessionData<T> {
         if src_mailbox.id.account_id == dest_mailbox.account_id {
             // Mailboxes are in the same account
             let account_id = src_mailbox.id.account_id;
+            let is_junk = dest_mailbox_id == JUNK_ID && self.jmap.is_abuse_report_enabled();
             let dest_mailbox_id = UidMailbox::from(dest_mailbox_id);
             for (id, imap_id) in ids {
                 // Obtain mailbox tags
@@ -222,7 +244,6 @@ impl<T: SessionStream> SessionData<T> {
                     .with_account_id(account_id)
                     .with_collection(Collection::Email)
                     .update_document(id);
-                mailboxes.update_batch(&mut batch, Property::MailboxIds);
                 if changelog.change_id == u64::MAX {
                     changelog.change_id =
                         self.jmap.assign_change_id(account_id).await.map_err(|_| {
@@ -230,6 +251,13 @@ impl<T: SessionStream> SessionData<T> {
                         })?
                 }
                 batch.value(Property::Cid, changelog.change_id, F_VALUE);
+                self.jmap
+                    .add_tombstones(&mut batch, changelog.change_id, mailboxes.removed());
+                self.jmap
+                    .mailbox_usage_update(&mut batch, account_id, id, &mailboxes)
+                    .await
+                    .map_err(|_| StatusResponse::database_failure().with_tag(&arguments.tag))?;
+                mailboxes.update_batch(&mut batch, Property::MailboxIds);
                 match self.jmap.write_batch(batch).await {
                     Ok(_) => {
                         changelog.log_update(Collection::Email, Id::from_parts(thread_id, id));
@@ -240,6 +268,11 @@ impl<T: SessionStream> SessionData<T> {
                             did_move = true;
                         }
                         copied_ids.push((imap_id, id));
+
+                        // Report messages marked as spam
+                        if is_junk {
+                            self.jmap.report_junk(account_id, id).await;
+                        }
                     }
                     Err(MethodError::ServerUnavailable) => {
                         response.rtype = ResponseType::No;
@@ -255,16 +288,30 @@ impl<T: SessionStream> SessionData<T> {
             let src_account_id = src_mailbox.id.account_id;
             let mut dest_change_id = None;
             let dest_account_id = dest_mailbox.account_id;
+            let access_token = self
+                .get_access_token()
+                .await
+                .map_err(|r| r.with_tag(&arguments.tag))?;
             let dest_quota = self
                 .jmap
-                .get_cached_access_token(dest_account_id)
+                .get_quota(&access_token, dest_account_id)
                 .await
-                .ok_or_else(|| {
-                    StatusResponse::no("Failed to obtain access token")
-                        .with_code(ResponseCode::ContactAdmin)
-                })?
-                .quota as i64;
+                .map_err(|_| StatusResponse::database_failure().with_tag(&arguments.tag))?;
             for (id, imap_id) in ids {
+                // Keep the flags of the source message
+                let keywords = self
+                    .jmap
+                    .get_property::<Vec<Keyword>>(
+                        src_account_id,
+                        Collection::Email,
+                        id,
+                        Property::Keywords,
+                    )
+                    .await
+                    .map_err(|_| StatusResponse::database_failure().with_tag(&arguments.tag))?
+                    .unwrap_or_default();
+
+                // The message blob is linked to the destination account, not duplicated
                 match self
                     .jmap
                     .copy_message(
@@ -273,7 +320,7 @@ impl<T: SessionStream> SessionData<T> {
                         dest_account_id,
                         dest_quota,
                         vec![dest_mailbox_id],
-                        Vec::new(),
+                        keywords,
                         None,
                     )
                     .await
@@ -315,9 +362,18 @@ impl<T: SessionStream> SessionData<T> {
                         continue;
                     } else if mailboxes.current().len() == 1 {
                         // Delete message if it is no longer in any mailbox
+                        if changelog.change_id == u64::MAX {
+                            changelog.change_id = self
+                                .jmap
+                                .assign_change_id(src_account_id)
+                                .await
+                                .map_err(|_| {
+                                StatusResponse::database_failure().with_tag(&arguments.tag)
+                            })?
+                        }
                         if let Ok(changes) = self
                             .jmap
-                            .email_delete(src_account_id, id)
+                            .email_delete(src_account_id, id, changelog.change_id)
                             .await
                             .map_err(|_| {
                                 StatusResponse::database_failure().with_tag(&arguments.tag)
@@ -334,7 +390,6 @@ impl<T: SessionStream> SessionData<T> {
                             .with_collection(Collection::Email)
                             .update_document(id);
                         mailboxes.update(src_mailbox_id, false);
-                        mailboxes.update_batch(&mut batch, Property::MailboxIds);
                         if changelog.change_id == u64::MAX {
                             changelog.change_id = self
                                 .jmap
@@ -345,6 +400,18 @@ impl<T: SessionStream> SessionData<T> {
                             })?
                         }
                         batch.value(Property::Cid, changelog.change_id, F_VALUE);
+                        self.jmap.add_tombstones(
+                            &mut batch,
+                            changelog.change_id,
+                            mailboxes.removed(),
+                        );
+                        self.jmap
+                            .mailbox_usage_update(&mut batch, src_account_id, id, &mailboxes)
+                            .await
+                            .map_err(|_| {
+                                StatusResponse::database_failure().with_tag(&arguments.tag)
+                            })?;
+                        mailboxes.update_batch(&mut batch, Property::MailboxIds);
                         match self.jmap.write_batch(batch).await {
                             Ok(_) => {
                                 changelog
@@ -395,11 +462,25 @@ impl<T: SessionStream> SessionData<T> {
                 .broadcast_state_change(
                     StateChange::new(src_mailbox.id.account_id)
                         .with_change(DataType::Email, change_id)
+                        .with_change(DataType::Thread, change_id)
                         .with_change(DataType::Mailbox, change_id),
                 )
                 .await;
         }
 
+        // Record activity
+        if did_move {
+            self.jmap
+                .log_activity(
+                    src_mailbox.id.account_id,
+                    ActivityEvent::EmailMove,
+                    ServerProtocol::Imap,
+                    self.remote_addr.into(),
+                    format!("{} email(s) moved", copied_ids.len()).into(),
+                )
+                .await;
+        }
+
         // Map copied JMAP Ids to IMAP UIDs in the destination folder.
         if copied_ids.is_empty() {
             return Err(if response.rtype != ResponseType::Ok {
@@ -148,7 +148,7 @@ impl<T: SessionStream> SessionData<T> {
         // Build response
         StatusResponse::ok("Mailbox created.")
             .with_code(ResponseCode::MailboxId {
-                mailbox_id: Id::from_parts(params.account_id, parent_id - 1).to_string(),
+                mailbox_id: Id::from(parent_id - 1).to_string(),
             })
             .with_tag(arguments.tag)
     }
@@ -185,6 +185,8 @@ impl<T: SessionStream> SessionData<T> {
             parent_mailbox_name
         } else if let Some(account_prefix) = account.prefix.as_ref() {
             account_prefix.to_string()
+        } else if let Some(personal) = &self.imap.namespace.personal {
+            personal.to_string()
         } else {
             "".to_string()
         };
@@ -267,13 +269,14 @@ impl<T: SessionStream> SessionData<T> {
         }
 
         // Validate special folders
-        let full_path = path.join("/");
+        let mut full_path = path.join("/");
         let mut parent_mailbox_id = None;
         let mut parent_mailbox_name = None;
+        let namespace = &self.imap.namespace;
         let (account_id, path) = {
             let mailboxes = self.mailboxes.lock();
             let first_path_item = path.first().unwrap();
-            let account = if first_path_item == &self.imap.name_shared {
+            let (account, prefix_len) = if namespace.is_other_users_root(first_path_item) {
                 // Shared Folders/<username>/<folder>
                 if path.len() < 3 {
                     return Err(StatusResponse::no(
@@ -289,7 +292,7 @@ impl<T: SessionStream> SessionData<T> {
                     .skip(1)
                     .find(|account| account.prefix == prefix)
                 {
-                    account
+                    (account, 2)
                 } else {
                     #[allow(clippy::unnecessary_literal_unwrap)]
                     return Err(StatusResponse::no(format!(
@@ -298,7 +301,24 @@ impl<T: SessionStream> SessionData<T> {
                     )));
                 }
             } else if let Some(account) = mailboxes.first() {
-                account
+                match &namespace.personal {
+                    Some(personal) if namespace.is_personal_root(first_path_item) => {
+                        // <personal prefix>/<folder>
+                        if path.len() < 2 {
+                            return Err(StatusResponse::no("Namespace roots cannot be created.")
+                                .with_code(ResponseCode::Cannot));
+                        }
+                        full_path = format!("{personal}/{}", path[1..].join("/"));
+                        (account, 1)
+                    }
+                    Some(personal) if !first_path_item.eq_ignore_ascii_case("INBOX") => {
+                        return Err(StatusResponse::no(format!(
+                            "Mailboxes must be created under the personal namespace '{personal}'."
+                        ))
+                        .with_code(ResponseCode::Cannot));
+                    }
+                    _ => (account, 0),
+                }
             } else {
                 return Err(
                     StatusResponse::no("Internal error.").with_code(ResponseCode::ContactAdmin)
@@ -315,10 +335,13 @@ impl<T: SessionStream> SessionData<T> {
 
             (
                 account.account_id,
-                if path.len() > 1 {
+                if path.len() > prefix_len + 1 {
                     let mut create_path = Vec::with_capacity(path.len());
-                    while !path.is_empty() {
-                        let mailbox_name = path.join("/");
+                    while path.len() > prefix_len {
+                        let mailbox_name = match (&namespace.personal, prefix_len) {
+                            (Some(personal), 1) => format!("{personal}/{}", path[1..].join("/")),
+                            _ => path.join("/"),
+                        };
                         if let Some(&mailbox_id) = account.mailbox_names.get(&mailbox_name) {
                             parent_mailbox_id = mailbox_id.into();
                             parent_mailbox_name = mailbox_name.into();
@@ -330,7 +353,7 @@ impl<T: SessionStream> SessionData<T> {
                     create_path.reverse();
                     create_path
                 } else {
-                    path
+                    path.split_off(prefix_len)
                 },
             )
         };
@@ -33,13 +33,14 @@ use imap_proto::{
 use jmap::{email::set::TagManager, mailbox::UidMailbox};
 use jmap_proto::{
     error::method::MethodError,
+    method::activity::ActivityEvent,
     types::{
         acl::Acl, collection::Collection, id::Id, keyword::Keyword, property::Property,
         state::StateChange, type_state::DataType,
     },
 };
 use store::write::{assert::HashedValue, log::ChangeLogBuilder, BatchBuilder, F_VALUE};
-use utils::listener::SessionStream;
+use utils::{config::ServerProtocol, listener::SessionStream};
 
 use crate::core::{ImapId, SavedSearch, SelectedMailbox, Session, SessionData};
 
@@ -161,6 +162,7 @@ impl<T: SessionStream> SessionData<T> {
 
         // Delete ids
         let mut changelog = ChangeLogBuilder::new();
+        let mut expunged = 0;
         for id in deleted_ids {
             if sequence
                 .as_ref()
@@ -207,16 +209,22 @@ impl<T: SessionStream> SessionData<T> {
                     .with_account_id(account_id)
                     .with_collection(Collection::Email)
                     .update_document(id);
-                mailboxes.update_batch(&mut batch, Property::MailboxIds);
                 keywords.update_batch(&mut batch, Property::Keywords);
                 if changelog.change_id == u64::MAX {
                     changelog.change_id = self.jmap.assign_change_id(account_id).await?
                 }
                 batch.value(Property::Cid, changelog.change_id, F_VALUE);
+                self.jmap
+                    .add_tombstones(&mut batch, changelog.change_id, mailboxes.removed());
+                self.jmap
+                    .mailbox_usage_update(&mut batch, account_id, id, &mailboxes)
+                    .await?;
+                mailboxes.update_batch(&mut batch, Property::MailboxIds);
                 match self.jmap.write_batch(batch).await {
                     Ok(_) => {
                         changelog.log_update(Collection::Email, Id::from_parts(thread_id, id));
                         changelog.log_child_update(Collection::Mailbox, mailbox_id.mailbox_id);
+                        expunged += 1;
                     }
                     Err(MethodError::ServerUnavailable) => {}
                     Err(_) => {
@@ -225,8 +233,16 @@ impl<T: SessionStream> SessionData<T> {
                 }
             } else {
                 // Delete message from all mailboxes
-                if let Ok(changes) = self.jmap.email_delete(account_id, id).await? {
+                if changelog.change_id == u64::MAX {
+                    changelog.change_id = self.jmap.assign_change_id(account_id).await?
+                }
+                if let Ok(changes) = self
+                    .jmap
+                    .email_delete(account_id, id, changelog.change_id)
+                    .await?
+                {
                     changelog.merge(changes);
+                    expunged += 1;
                 }
             }
         }
@@ -244,6 +260,19 @@ impl<T: SessionStream> SessionData<T> {
                 .await;
         }
 
+        // Record activity
+        if expunged > 0 {
+            self.jmap
+                .log_activity(
+                    account_id,
+                    ActivityEvent::EmailDelete,
+                    ServerProtocol::Imap,
+                    self.remote_addr.into(),
+                    format!("{expunged} email(s) expunged").into(),
+                )
+                .await;
+        }
+
         Ok(())
     }
 }
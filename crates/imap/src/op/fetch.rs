@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::{borrow::Cow, sync::Arc};
+use std::{borrow::Cow, sync::Arc, time::Instant};
 
 use ahash::AHashMap;
 use imap_proto::{
@@ -29,8 +29,9 @@ use imap_proto::{
     protocol::{
         expunge::Vanished,
         fetch::{
-            self, Arguments, Attribute, BodyContents, BodyPart, BodyPartExtension, BodyPartFields,
-            DataItem, Envelope, FetchItem, Section,
+            self, annotation_entry_matches, AnnotationAttribute, AnnotationEntry, Arguments,
+            Attribute, BodyContents, BodyPart, BodyPartExtension, BodyPartFields, DataItem,
+            Envelope, FetchItem, Section,
         },
         Flag,
     },
@@ -40,19 +41,22 @@ use imap_proto::{
 use jmap::{email::metadata::MessageMetadata, Bincode};
 use jmap_proto::{
     error::method::MethodError,
+    object::Object,
     types::{
         acl::Acl, collection::Collection, id::Id, keyword::Keyword, property::Property,
-        state::StateChange, type_state::DataType,
+        state::StateChange, type_state::DataType, value::Value,
     },
 };
 use mail_parser::{Address, GetHeader, HeaderName, Message, PartType};
 use store::{
+    dispatch::blob::BlobStream,
     query::log::{Change, Query},
     write::{assert::HashedValue, BatchBuilder, F_BITMAP, F_VALUE},
+    BlobHash,
 };
-use utils::listener::SessionStream;
+use utils::{listener::SessionStream, map::ttl_dashmap::TtlMap};
 
-use crate::core::{SelectedMailbox, Session, SessionData};
+use crate::core::{MessageStructure, SelectedMailbox, Session, SessionData, IMAP};
 
 use super::FromModSeq;
 
@@ -181,11 +185,31 @@ impl<T: SessionStream> SessionData<T> {
             }
 
             // Send vanished UIDs
-            if arguments.include_vanished && has_vanished {
-                // Add to vanished all known destroyed Ids
-                let vanished = mailbox
-                    .sequence_expand_missing(&arguments.sequence_set, true)
-                    .await;
+            if arguments.include_vanished {
+                // Use the expunge tombstones when the retained history covers the
+                // requested modseq, otherwise add to vanished all known destroyed Ids
+                let vanished = match self
+                    .jmap
+                    .tombstones(
+                        account_id,
+                        mailbox.id.mailbox_id,
+                        changed_since.saturating_sub(1),
+                    )
+                    .await
+                {
+                    Ok(Some(tombstones)) => {
+                        mailbox
+                            .sequence_expand_tombstones(&arguments.sequence_set, tombstones)
+                            .await
+                    }
+                    Ok(None) if has_vanished => {
+                        mailbox
+                            .sequence_expand_missing(&arguments.sequence_set, true)
+                            .await
+                    }
+                    Ok(None) => Vec::new(),
+                    Err(_) => return StatusResponse::database_failure().with_tag(arguments.tag),
+                };
 
                 if !vanished.is_empty() {
                     let mut buf = Vec::with_capacity(vanished.len() * 3);
@@ -219,14 +243,17 @@ impl<T: SessionStream> SessionData<T> {
         let mut set_seen_flags = false;
         let mut needs_thread_id = false;
         let mut needs_blobs = false;
+        let mut needs_stream = false;
+        let mut needs_structure = false;
+        let mut needs_preview = false;
+        let mut needs_annotation = false;
 
         for attribute in &arguments.attributes {
             match attribute {
-                Attribute::Envelope
-                | Attribute::Rfc822Header
-                | Attribute::Body
-                | Attribute::BodyStructure
-                | Attribute::BinarySize { .. } => {
+                Attribute::Envelope | Attribute::Body | Attribute::BodyStructure => {
+                    needs_structure = true;
+                }
+                Attribute::Rfc822Header | Attribute::BinarySize { .. } => {
                     /*
                         Note that this did not result in \Seen being set, because
                         RFC822.HEADER response data occurs as a result of a FETCH
@@ -236,21 +263,41 @@ impl<T: SessionStream> SessionData<T> {
                     */
                     needs_blobs = true;
                 }
+                Attribute::BodySection { peek, sections, .. } if sections.is_empty() => {
+                    // BODY[] is streamed straight from the blob store
+                    if mailbox.is_select && !*peek {
+                        set_seen_flags = true;
+                    }
+                    needs_stream = true;
+                }
                 Attribute::BodySection { peek, .. } | Attribute::Binary { peek, .. } => {
                     if mailbox.is_select && !*peek {
                         set_seen_flags = true;
                     }
                     needs_blobs = true;
                 }
-                Attribute::Rfc822Text | Attribute::Rfc822 => {
+                Attribute::Rfc822 => {
+                    if mailbox.is_select {
+                        set_seen_flags = true;
+                    }
+                    needs_stream = true;
+                }
+                Attribute::Rfc822Text => {
                     if mailbox.is_select {
                         set_seen_flags = true;
                     }
                     needs_blobs = true;
                 }
-                Attribute::ThreadId => {
+                Attribute::EmailId | Attribute::ThreadId => {
                     needs_thread_id = true;
                 }
+                Attribute::Preview { .. } => {
+                    needs_preview = true;
+                }
+                Attribute::Annotation { .. } => {
+                    // Private annotations are only available on the user's own account
+                    needs_annotation = account_id == self.account_id;
+                }
                 _ => (),
             }
         }
@@ -315,8 +362,26 @@ impl<T: SessionStream> SessionData<T> {
                 continue;
             };
 
+            // Generate the preview for messages stored without one
+            let email = if needs_preview {
+                self.jmap.email_preview(account_id, id, email).await
+            } else {
+                email
+            };
+
+            // Obtain cached envelope and body structure. These are built on the
+            // first FETCH rather than at ingest, as messages are ingested by JMAP
+            // (which knows nothing about IMAP responses) and most of them are never
+            // fetched over IMAP. Entries are keyed by blob hash, so a cached
+            // structure always matches the contents it was built from.
+            let cached_structure = if needs_structure {
+                self.imap.structure_cache.get_with_ttl(&email.blob_hash)
+            } else {
+                None
+            };
+
             // Fetch and parse blob
-            let raw_message = if needs_blobs {
+            let raw_message = if needs_blobs || (needs_structure && cached_structure.is_none()) {
                 // Retrieve raw message if needed
                 match self.jmap.get_blob(&email.blob_hash, 0..u32::MAX).await {
                     Ok(Some(raw_message)) => raw_message.into(),
@@ -339,9 +404,22 @@ impl<T: SessionStream> SessionData<T> {
             let message = email
                 .contents
                 .into_message(raw_message.as_deref().unwrap_or_default());
+            let structure = match cached_structure {
+                Some(structure) => Some(structure),
+                None if needs_structure => Some(self.imap.cache_structure(
+                    email.blob_hash.clone(),
+                    MessageStructure {
+                        envelope: message.envelope().into_owned(),
+                        body: message.body_structure(false).into_owned(),
+                        body_structure: message.body_structure(true).into_owned(),
+                    },
+                )),
+                None => None,
+            };
 
             // Build response
             let mut items = Vec::with_capacity(arguments.attributes.len());
+            let mut streams = Vec::new();
             let set_seen_flag =
                 set_seen_flags && !keywords.inner.iter().any(|k| k == &Keyword::Seen);
             let thread_id = if needs_thread_id || set_seen_flag {
@@ -357,11 +435,21 @@ impl<T: SessionStream> SessionData<T> {
             } else {
                 0
             };
+            let annotation = if needs_annotation {
+                match self.jmap.annotation_fetch(account_id, id).await {
+                    Ok(annotation) => annotation,
+                    Err(_) => {
+                        return StatusResponse::database_failure().with_tag(arguments.tag);
+                    }
+                }
+            } else {
+                None
+            };
             for attribute in &arguments.attributes {
                 match attribute {
                     Attribute::Envelope => {
                         items.push(DataItem::Envelope {
-                            envelope: message.envelope(),
+                            envelope: structure.as_ref().unwrap().envelope.clone(),
                         });
                     }
                     Attribute::Flags => {
@@ -401,6 +489,43 @@ impl<T: SessionStream> SessionData<T> {
                     Attribute::Uid => {
                         items.push(DataItem::Uid { uid });
                     }
+                    Attribute::Rfc822 | Attribute::BodySection { .. }
+                        if needs_stream && raw_message.is_none() && is_streamable(attribute) =>
+                    {
+                        let stream = match self.jmap.get_blob_stream(&email.blob_hash).await {
+                            Ok(Some(stream)) => stream,
+                            Ok(None) => {
+                                tracing::warn!(event = "not-found",
+                                account_id = account_id,
+                                collection = ?Collection::Email,
+                                document_id = id,
+                                blob_id = ?email.blob_hash,
+                                "Blob not found");
+                                continue;
+                            }
+                            Err(_) => {
+                                return StatusResponse::database_failure().with_tag(arguments.tag);
+                            }
+                        };
+                        if let Attribute::BodySection { partial, .. } = attribute {
+                            let stream = match partial {
+                                Some((start, count)) => {
+                                    stream.narrow(*start as u64..*start as u64 + *count as u64)
+                                }
+                                None => stream,
+                            };
+                            items.push(DataItem::BodySectionStream {
+                                origin_octet: partial.map(|(start, _)| start),
+                                size: stream.len() as usize,
+                            });
+                            streams.push(stream);
+                        } else {
+                            items.push(DataItem::Rfc822Stream {
+                                size: stream.len() as usize,
+                            });
+                            streams.push(stream);
+                        }
+                    }
                     Attribute::Rfc822 => {
                         items.push(DataItem::Rfc822 {
                             contents: raw_message.as_ref().unwrap().into(),
@@ -432,12 +557,12 @@ impl<T: SessionStream> SessionData<T> {
                     }
                     Attribute::Body => {
                         items.push(DataItem::Body {
-                            part: message.body_structure(false),
+                            part: structure.as_ref().unwrap().body.clone(),
                         });
                     }
                     Attribute::BodyStructure => {
                         items.push(DataItem::BodyStructure {
-                            part: message.body_structure(true),
+                            part: structure.as_ref().unwrap().body_structure.clone(),
                         });
                     }
                     Attribute::BodySection {
@@ -500,12 +625,20 @@ impl<T: SessionStream> SessionData<T> {
                     }
                     Attribute::EmailId => {
                         items.push(DataItem::EmailId {
-                            email_id: Id::from_parts(account_id, id).to_string(),
+                            email_id: Id::from_parts(thread_id, id).to_string(),
                         });
                     }
                     Attribute::ThreadId => {
                         items.push(DataItem::ThreadId {
-                            thread_id: Id::from_parts(account_id, thread_id).to_string(),
+                            thread_id: Id::from(thread_id).to_string(),
+                        });
+                    }
+                    Attribute::Annotation {
+                        entries,
+                        attributes,
+                    } => {
+                        items.push(DataItem::Annotation {
+                            entries: annotation_entries(annotation.as_ref(), entries, attributes),
                         });
                     }
                 }
@@ -524,9 +657,16 @@ impl<T: SessionStream> SessionData<T> {
 
             // Serialize fetch item
             let mut buf = Vec::with_capacity(128);
-            FetchItem { id: seqnum, items }.serialize(&mut buf);
-            if !self.write_bytes(buf).await {
-                return StatusResponse::completed(Command::Fetch(is_uid)).with_tag(arguments.tag);
+            let offsets = FetchItem { id: seqnum, items }.serialize_streamed(&mut buf);
+            match self.write_fetch_item(buf, offsets, streams).await {
+                Ok(true) => (),
+                Ok(false) => {
+                    return StatusResponse::completed(Command::Fetch(is_uid))
+                        .with_tag(arguments.tag);
+                }
+                Err(_) => {
+                    return StatusResponse::database_failure().with_tag(arguments.tag);
+                }
             }
 
             // Add to set flags
@@ -593,6 +733,75 @@ impl<T: SessionStream> SessionData<T> {
 
         StatusResponse::completed(Command::Fetch(is_uid)).with_tag(arguments.tag)
     }
+
+    // Writes a serialized FETCH response, copying each streamed literal
+    // from the blob store in bounded chunks rather than buffering it.
+    async fn write_fetch_item(
+        &self,
+        buf: Vec<u8>,
+        offsets: Vec<usize>,
+        mut streams: Vec<BlobStream>,
+    ) -> Result<bool, ()> {
+        if offsets.is_empty() {
+            self.throttle_download(buf.len()).await;
+            return Ok(self.write_bytes(buf).await);
+        }
+
+        // Read the first chunk of every literal before any part of the
+        // response is written, so a missing blob fails the command cleanly
+        // instead of leaving a literal header without its contents.
+        let mut first_chunks = Vec::with_capacity(streams.len());
+        for stream in &mut streams {
+            match stream.next_chunk().await {
+                Ok(chunk) => first_chunks.push(chunk),
+                Err(err) => {
+                    tracing::error!(event = "error",
+                                    context = "fetch",
+                                    error = ?err,
+                                    "Failed to open blob stream");
+                    return Err(());
+                }
+            }
+        }
+
+        let mut pos = 0;
+        for ((offset, mut stream), mut chunk) in offsets.into_iter().zip(streams).zip(first_chunks)
+        {
+            let bytes = buf[pos..offset].to_vec();
+            pos = offset;
+            self.throttle_download(bytes.len()).await;
+            if !self.write_bytes(bytes).await {
+                return Ok(false);
+            }
+
+            loop {
+                let next_chunk = match chunk.take() {
+                    Some(chunk) => Ok(Some(chunk)),
+                    None => stream.next_chunk().await,
+                };
+                match next_chunk {
+                    Ok(Some(chunk)) => {
+                        self.throttle_download(chunk.len()).await;
+                        if !self.write_bytes(chunk).await {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(err) => {
+                        tracing::error!(event = "error",
+                                        context = "fetch",
+                                        error = ?err,
+                                        "Failed to stream blob");
+                        return Err(());
+                    }
+                }
+            }
+        }
+
+        let bytes = buf[pos..].to_vec();
+        self.throttle_download(bytes.len()).await;
+        Ok(self.write_bytes(bytes).await)
+    }
 }
 
 #[allow(clippy::result_unit_err)]
@@ -1092,6 +1301,38 @@ impl<'x> AsImapDataItem<'x> for Message<'x> {
     }
 }
 
+impl IMAP {
+    pub fn cache_structure(
+        &self,
+        blob_hash: BlobHash,
+        structure: MessageStructure,
+    ) -> Arc<MessageStructure> {
+        let structure = Arc::new(structure);
+
+        // Messages are immutable, entries are only removed once they expire
+        if self.structure_cache.len() >= self.structure_cache_size {
+            self.structure_cache.cleanup();
+        }
+        if self.structure_cache.len() < self.structure_cache_size {
+            self.structure_cache.insert_with_ttl(
+                blob_hash,
+                structure.clone(),
+                Instant::now() + self.structure_cache_ttl,
+            );
+        }
+
+        structure
+    }
+}
+
+fn is_streamable(attribute: &Attribute) -> bool {
+    match attribute {
+        Attribute::Rfc822 => true,
+        Attribute::BodySection { sections, .. } => sections.is_empty(),
+        _ => false,
+    }
+}
+
 #[inline(always)]
 fn get_partial_bytes(bytes: &[u8], partial: Option<(u32, u32)>) -> &[u8] {
     if let Some((start, end)) = partial {
@@ -1107,6 +1348,56 @@ fn get_partial_bytes(bytes: &[u8], partial: Option<(u32, u32)>) -> &[u8] {
     }
 }
 
+pub(crate) static ANNOTATION_ENTRIES: [(&str, Property); 3] = [
+    ("/comment", Property::Text),
+    ("/vendor/stalwart/color", Property::Color),
+    ("/vendor/stalwart/reminder", Property::RemindAt),
+];
+
+fn annotation_entries(
+    annotation: Option<&Object<Value>>,
+    patterns: &[String],
+    attributes: &[AnnotationAttribute],
+) -> Vec<AnnotationEntry> {
+    ANNOTATION_ENTRIES
+        .iter()
+        .filter(|(entry, _)| {
+            patterns
+                .iter()
+                .any(|pattern| annotation_entry_matches(pattern, entry))
+        })
+        .map(|(entry, property)| {
+            let value = annotation.and_then(|annotation| match annotation.get(property) {
+                Value::Text(text) => text.to_string().into(),
+                Value::Date(date) => date.to_string().into(),
+                _ => None,
+            });
+            AnnotationEntry {
+                entry: entry.to_string(),
+                values: attributes
+                    .iter()
+                    .map(|attribute| {
+                        (
+                            *attribute,
+                            match attribute {
+                                AnnotationAttribute::ValuePriv => value.clone(),
+                                AnnotationAttribute::SizePriv => value
+                                    .as_ref()
+                                    .map_or(0, |value| value.len())
+                                    .to_string()
+                                    .into(),
+                                // Shared annotations are not supported
+                                AnnotationAttribute::ValueShared => None,
+                                AnnotationAttribute::SizeShared => "0".to_string().into(),
+                            },
+                        )
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
 trait AsImapAddress {
     fn as_imap_address(&self) -> Vec<fetch::Address>;
 }
@@ -175,6 +175,26 @@ impl<T: SessionStream> SessionData<T> {
                         }
                         .serialize(&mut buf, is_rev2, false);
                     }
+
+                    // List mailboxes with subscription changes
+                    for (mailbox_name, is_subscribed) in changes
+                        .subscribed
+                        .into_iter()
+                        .map(|name| (name, true))
+                        .chain(changes.unsubscribed.into_iter().map(|name| (name, false)))
+                    {
+                        ListItem {
+                            mailbox_name,
+                            attributes: if is_subscribed {
+                                vec![Attribute::Subscribed]
+                            } else {
+                                vec![]
+                            },
+                            tags: vec![],
+                        }
+                        .serialize(&mut buf, is_rev2, false);
+                    }
+
                     // Obtain status of changed mailboxes
                     for mailbox_name in changes.changed {
                         if let Ok(status) = self
@@ -206,75 +226,90 @@ impl<T: SessionStream> SessionData<T> {
 
         // Fetch selected mailbox changes
         if check_emails {
-            // Synchronize emails
             if let Some(mailbox) = mailbox {
-                // Obtain changes since last sync
-                let modseq = mailbox.state.lock().modseq;
-                match self.write_mailbox_changes(mailbox, is_qresync).await {
-                    Ok(new_state) => {
-                        if new_state == modseq {
-                            return;
-                        }
-                    }
-                    Err(response) => {
-                        self.write_bytes(response.into_bytes()).await;
-                        return;
-                    }
-                }
+                self.write_selected_changes(
+                    mailbox,
+                    vec![fetch::Attribute::Flags, fetch::Attribute::Uid],
+                    is_qresync,
+                    is_rev2,
+                )
+                .await;
+            }
+        }
+    }
 
-                // Obtain changed messages
-                let changed_ids = match self
-                    .jmap
-                    .changes_(
-                        mailbox.id.account_id,
-                        Collection::Email,
-                        modseq.map(Query::Since).unwrap_or(Query::All),
-                    )
-                    .await
-                {
-                    Ok(changelog) => {
-                        let state = mailbox.state.lock();
-                        changelog
-                            .changes
-                            .into_iter()
-                            .filter_map(|change| {
-                                state
-                                    .id_to_imap
-                                    .get(&((change.unwrap_id() & u32::MAX as u64) as u32))
-                                    .map(|id| id.uid)
-                            })
-                            .collect::<AHashSet<_>>()
-                    }
-                    Err(_) => {
-                        self.write_bytes(StatusResponse::database_failure().into_bytes())
-                            .await;
-                        return;
-                    }
-                };
+    pub async fn write_selected_changes(
+        &self,
+        mailbox: &Arc<SelectedMailbox>,
+        attributes: Vec<fetch::Attribute>,
+        is_qresync: bool,
+        is_rev2: bool,
+    ) {
+        // Obtain changes since last sync
+        let modseq = mailbox.state.lock().modseq;
+        match self.write_mailbox_changes(mailbox, is_qresync).await {
+            Ok(new_state) => {
+                if new_state == modseq {
+                    return;
+                }
+            }
+            Err(response) => {
+                self.write_bytes(response.into_bytes()).await;
+                return;
+            }
+        }
 
-                if !changed_ids.is_empty() {
-                    self.fetch(
-                        fetch::Arguments {
-                            tag: String::new(),
-                            sequence_set: Sequence::List {
-                                items: changed_ids
-                                    .into_iter()
-                                    .map(|uid| Sequence::Number { value: uid })
-                                    .collect(),
-                            },
-                            attributes: vec![fetch::Attribute::Flags, fetch::Attribute::Uid],
-                            changed_since: None,
-                            include_vanished: false,
-                        },
-                        mailbox.clone(),
-                        true,
-                        is_qresync,
-                        is_rev2,
-                        false,
-                    )
+        // Obtain changed messages
+        let changed_ids = match self
+            .jmap
+            .changes_(
+                mailbox.id.account_id,
+                Collection::Email,
+                modseq.map(Query::Since).unwrap_or(Query::All),
+            )
+            .await
+        {
+            Ok(changelog) => {
+                let state = mailbox.state.lock();
+                changelog
+                    .changes
+                    .into_iter()
+                    .filter_map(|change| {
+                        state
+                            .id_to_imap
+                            .get(&((change.unwrap_id() & u32::MAX as u64) as u32))
+                            .map(|id| id.uid)
+                    })
+                    .collect::<AHashSet<_>>()
+            }
+            Err(_) => {
+                self.write_bytes(StatusResponse::database_failure().into_bytes())
                     .await;
-                }
+                return;
             }
+        };
+
+        if !changed_ids.is_empty() {
+            self.fetch(
+                fetch::Arguments {
+                    tag: String::new(),
+                    sequence_set: Sequence::List {
+                        items: changed_ids
+                            .into_iter()
+                            .map(|uid| Sequence::Number { value: uid })
+                            .collect(),
+                    },
+                    attributes,
+                    changed_since: None,
+                    include_vanished: false,
+                },
+                mailbox.clone(),
+                true,
+                is_qresync,
+                is_rev2,
+                false,
+            )
+            .await;
         }
     }
 }
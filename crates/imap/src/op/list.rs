@@ -175,13 +175,15 @@ impl<T: SessionStream> SessionData<T> {
         let mut list_items = Vec::with_capacity(10);
 
         // Add mailboxes
-        let mut added_shared_folder = false;
+        let namespace = &self.imap.namespace;
+        let mut added_roots = Vec::with_capacity(2);
         for account in self.mailboxes.lock().iter() {
             if let Some(prefix) = &account.prefix {
-                if !added_shared_folder {
-                    if !filter_subscribed && matches_pattern(&patterns, &self.imap.name_shared) {
+                let root = namespace.root_of(prefix);
+                if !added_roots.contains(&root) {
+                    if !filter_subscribed && matches_pattern(&patterns, root) {
                         list_items.push(ListItem {
-                            mailbox_name: self.imap.name_shared.clone(),
+                            mailbox_name: root.to_string(),
                             attributes: if include_children {
                                 vec![Attribute::HasChildren, Attribute::NoSelect]
                             } else {
@@ -190,7 +192,7 @@ impl<T: SessionStream> SessionData<T> {
                             tags: vec![],
                         });
                     }
-                    added_shared_folder = true;
+                    added_roots.push(root);
                 }
                 if !filter_subscribed && matches_pattern(&patterns, prefix) {
                     list_items.push(ListItem {
@@ -203,6 +205,25 @@ impl<T: SessionStream> SessionData<T> {
                         tags: vec![],
                     });
                 }
+            } else if let Some(personal) = namespace
+                .personal
+                .as_ref()
+                .filter(|personal| !personal.eq_ignore_ascii_case("INBOX"))
+            {
+                if !filter_subscribed
+                    && account.mailbox_names.len() > 1
+                    && matches_pattern(&patterns, personal)
+                {
+                    list_items.push(ListItem {
+                        mailbox_name: personal.clone(),
+                        attributes: if include_children {
+                            vec![Attribute::HasChildren, Attribute::NoSelect]
+                        } else {
+                            vec![Attribute::NoSelect]
+                        },
+                        tags: vec![],
+                    });
+                }
             }
 
             for (mailbox_name, mailbox_id) in &account.mailbox_names {
@@ -41,6 +41,7 @@ pub mod login;
 pub mod logout;
 pub mod namespace;
 pub mod noop;
+pub mod notify;
 pub mod rename;
 pub mod search;
 pub mod select;
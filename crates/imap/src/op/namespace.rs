@@ -36,17 +36,31 @@ impl<T: SessionStream> Session<T> {
         self.write_bytes(
             StatusResponse::completed(Command::Namespace)
                 .with_tag(request.tag)
-                .serialize(
-                    Response {
-                        shared_prefix: if self.state.session_data().mailboxes.lock().len() > 1 {
-                            self.imap.name_shared.clone().into()
-                        } else {
-                            None
-                        },
-                    }
-                    .serialize(),
-                ),
+                .serialize(self.namespace_response().serialize()),
         )
         .await
     }
+
+    fn namespace_response(&self) -> Response {
+        let namespace = &self.imap.namespace;
+        let mut response = Response {
+            personal_prefix: namespace.personal.clone(),
+            shared_prefix: None,
+            public_prefix: None,
+        };
+
+        // Other users' and public namespaces are only advertised when accounts are shared
+        for account in self.state.session_data().mailboxes.lock().iter().skip(1) {
+            if let Some(prefix) = &account.prefix {
+                let root = namespace.root_of(prefix);
+                if namespace.public.as_deref() == Some(root) {
+                    response.public_prefix = root.to_string().into();
+                } else {
+                    response.shared_prefix = root.to_string().into();
+                }
+            }
+        }
+
+        response
+    }
 }
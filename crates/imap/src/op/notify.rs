@@ -0,0 +1,347 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use imap_proto::{
+    protocol::{
+        fetch,
+        list::{Attribute, ListItem},
+        notify::{Event, EventGroup, Filter},
+        status::Status,
+        ImapResponse,
+    },
+    receiver::Request,
+    Command, ResponseCode, StatusResponse,
+};
+use jmap::mailbox::INBOX_ID;
+use jmap_proto::types::{state::StateChange, type_state::DataType};
+use utils::{listener::SessionStream, map::bitmap::Bitmap};
+
+use crate::core::{Notify, SelectedMailbox, Session, SessionData, State};
+
+const NOTIFY_STATUS: [Status; 4] = [
+    Status::Messages,
+    Status::UidNext,
+    Status::UidValidity,
+    Status::Unseen,
+];
+
+impl<T: SessionStream> Session<T> {
+    pub async fn handle_notify(&mut self, request: Request<Command>) -> crate::OpResult {
+        let arguments = match request.parse_notify(self.version) {
+            Ok(arguments) => arguments,
+            Err(response) => return self.write_bytes(response.into_bytes()).await,
+        };
+
+        // NOTIFY NONE disables all notifications
+        if arguments.is_none() {
+            self.notify = None;
+            return self
+                .write_bytes(
+                    StatusResponse::completed(Command::Notify)
+                        .with_tag(arguments.tag)
+                        .into_bytes(),
+                )
+                .await;
+        }
+
+        let (data, mailbox) = match &self.state {
+            State::Authenticated { data } => (data.clone(), None),
+            State::Selected { data, mailbox } => (data.clone(), mailbox.clone().into()),
+            _ => unreachable!(),
+        };
+
+        // Register with state manager, replacing any previous subscription
+        self.notify = None;
+        let change_rx = if let Some(change_rx) = self
+            .jmap
+            .subscribe_state_manager(
+                data.account_id,
+                data.account_id,
+                Bitmap::from_iter([DataType::Email, DataType::Mailbox, DataType::EmailDelivery]),
+            )
+            .await
+        {
+            change_rx
+        } else {
+            return self
+                .write_bytes(
+                    StatusResponse::no("It was not possible to enable notifications.")
+                        .with_tag(arguments.tag)
+                        .with_code(ResponseCode::ContactAdmin)
+                        .into_bytes(),
+                )
+                .await;
+        };
+
+        // Refresh mailboxes so that only changes from this point on are reported
+        if let Err(err) = data.synchronize_mailboxes(false).await {
+            return self
+                .write_bytes(err.with_tag(arguments.tag).into_bytes())
+                .await;
+        }
+
+        // Send the initial status of all monitored mailboxes
+        let groups = Arc::new(arguments.groups);
+        if arguments.status {
+            let selected_name = mailbox
+                .as_ref()
+                .and_then(|mailbox| data.notify_mailbox_name(mailbox));
+            let mailbox_names = data
+                .mailboxes
+                .lock()
+                .iter()
+                .flat_map(|account| account.mailbox_names.keys().cloned())
+                .collect::<Vec<_>>();
+            let mut buf = Vec::with_capacity(64);
+            for mailbox_name in mailbox_names {
+                if selected_name.as_ref() != Some(&mailbox_name)
+                    && groups.iter().any(|group| {
+                        group.has_message_events()
+                            && data.notify_matches(&group.filter, &mailbox_name)
+                    })
+                {
+                    if let Ok(status) = data.status(mailbox_name, &NOTIFY_STATUS).await {
+                        status.serialize(&mut buf, self.version.is_rev2());
+                    }
+                }
+            }
+            if !buf.is_empty() {
+                self.write_bytes(buf).await?;
+            }
+        }
+
+        tracing::debug!(parent: &self.span, event = "start", context = "notify", "Enabled NOTIFY.");
+
+        self.notify = Notify { groups, change_rx }.into();
+        self.write_bytes(
+            StatusResponse::completed(Command::Notify)
+                .with_tag(arguments.tag)
+                .into_bytes(),
+        )
+        .await
+    }
+
+    pub async fn write_notify_changes(&self, state_change: StateChange) {
+        let (data, mailbox) = match &self.state {
+            State::Authenticated { data } => (data, None),
+            State::Selected { data, mailbox } => (data, mailbox.clone().into()),
+            State::NotAuthenticated { .. } => return,
+        };
+        let groups = if let Some(notify) = &self.notify {
+            notify.groups.clone()
+        } else {
+            return;
+        };
+
+        let has_email_changes = state_change
+            .types
+            .iter()
+            .any(|(type_state, _)| matches!(type_state, DataType::Email | DataType::EmailDelivery));
+
+        data.write_notify_changes(
+            &groups,
+            &mailbox,
+            has_email_changes,
+            self.is_qresync,
+            self.version.is_rev2(),
+        )
+        .await;
+    }
+}
+
+impl<T: SessionStream> SessionData<T> {
+    pub async fn write_notify_changes(
+        &self,
+        groups: &[EventGroup],
+        mailbox: &Option<Arc<SelectedMailbox>>,
+        check_emails: bool,
+        is_qresync: bool,
+        is_rev2: bool,
+    ) {
+        match self.synchronize_mailboxes(true).await {
+            Ok(Some(changes)) => {
+                let mut buf = Vec::with_capacity(64);
+                let is_monitored = |mailbox_name: &str, event: fn(&EventGroup) -> bool| {
+                    groups.iter().any(|group| {
+                        event(group) && self.notify_matches(&group.filter, mailbox_name)
+                    })
+                };
+                let has_mailbox_name =
+                    |group: &EventGroup| group.events.contains(&Event::MailboxName);
+                let has_subscription_change =
+                    |group: &EventGroup| group.events.contains(&Event::SubscriptionChange);
+
+                // List deleted and added mailboxes
+                for (mailbox_name, attributes) in changes
+                    .deleted
+                    .into_iter()
+                    .map(|name| (name, vec![Attribute::NonExistent]))
+                    .chain(changes.added.into_iter().map(|name| (name, vec![])))
+                {
+                    if is_monitored(&mailbox_name, has_mailbox_name) {
+                        ListItem {
+                            mailbox_name,
+                            attributes,
+                            tags: vec![],
+                        }
+                        .serialize(&mut buf, is_rev2, false);
+                    }
+                }
+
+                // List mailboxes with subscription changes
+                for (mailbox_name, is_subscribed) in changes
+                    .subscribed
+                    .into_iter()
+                    .map(|name| (name, true))
+                    .chain(changes.unsubscribed.into_iter().map(|name| (name, false)))
+                {
+                    if is_monitored(&mailbox_name, has_subscription_change) {
+                        ListItem {
+                            mailbox_name,
+                            attributes: if is_subscribed {
+                                vec![Attribute::Subscribed]
+                            } else {
+                                vec![]
+                            },
+                            tags: vec![],
+                        }
+                        .serialize(&mut buf, is_rev2, false);
+                    }
+                }
+
+                // Obtain status of changed mailboxes, the selected mailbox is reported separately
+                let selected_name = mailbox
+                    .as_ref()
+                    .and_then(|mailbox| self.notify_mailbox_name(mailbox));
+                for mailbox_name in changes.changed {
+                    if selected_name.as_ref() != Some(&mailbox_name)
+                        && is_monitored(&mailbox_name, EventGroup::has_message_events)
+                    {
+                        if let Ok(status) = self.status(mailbox_name, &NOTIFY_STATUS).await {
+                            status.serialize(&mut buf, is_rev2);
+                        }
+                    }
+                }
+
+                if !buf.is_empty() {
+                    self.write_bytes(buf).await;
+                }
+            }
+            Err(_) => {
+                tracing::debug!(parent: &self.span, "Failed to refresh mailboxes.");
+            }
+            _ => unreachable!(),
+        }
+
+        // Changes to the selected mailbox are only pushed for the "selected" filter,
+        // "selected-delayed" changes are delivered on the next NOOP or IDLE.
+        if let (true, Some(mailbox), Some(group)) = (
+            check_emails,
+            mailbox,
+            groups
+                .iter()
+                .find(|group| group.filter == Filter::Selected && group.has_message_events()),
+        ) {
+            let mut attributes = vec![fetch::Attribute::Flags, fetch::Attribute::Uid];
+            for attribute in group.message_new_attributes().unwrap_or_default() {
+                if !attributes.contains(attribute) {
+                    attributes.push(attribute.clone());
+                }
+            }
+            self.write_selected_changes(mailbox, attributes, is_qresync, is_rev2)
+                .await;
+        }
+    }
+
+    pub fn notify_matches(&self, filter: &Filter, mailbox_name: &str) -> bool {
+        let mailboxes = self.mailboxes.lock();
+        let account = mailboxes
+            .iter()
+            .find(|account| account.mailbox_names.contains_key(mailbox_name));
+
+        match filter {
+            Filter::Selected | Filter::SelectedDelayed => false,
+            Filter::Inboxes => account.map_or(false, |account| {
+                account.prefix.is_none()
+                    && account.mailbox_names.get(mailbox_name) == Some(&INBOX_ID)
+            }),
+            Filter::Personal => account.map_or_else(
+                || {
+                    // Mailboxes that no longer exist are matched by name
+                    mailbox_name
+                        .split('/')
+                        .next()
+                        .map_or(true, |root| !self.imap.namespace.is_other_users_root(root))
+                },
+                |account| account.prefix.is_none(),
+            ),
+            Filter::Subscribed => account
+                .and_then(|account| {
+                    account
+                        .mailbox_names
+                        .get(mailbox_name)
+                        .and_then(|mailbox_id| account.mailbox_state.get(mailbox_id))
+                })
+                .map_or(false, |mailbox| mailbox.is_subscribed),
+            Filter::Subtree(names) => names.iter().any(|name| {
+                is_same_mailbox(name, mailbox_name)
+                    || mailbox_name
+                        .strip_prefix(name.as_str())
+                        .map_or(false, |child| child.starts_with('/'))
+            }),
+            Filter::Mailboxes(names) => {
+                names.iter().any(|name| is_same_mailbox(name, mailbox_name))
+            }
+        }
+    }
+
+    fn notify_mailbox_name(&self, mailbox: &SelectedMailbox) -> Option<String> {
+        self.mailboxes
+            .lock()
+            .iter()
+            .find(|account| account.account_id == mailbox.id.account_id)
+            .and_then(|account| {
+                account
+                    .mailbox_names
+                    .iter()
+                    .find(|(_, mailbox_id)| **mailbox_id == mailbox.id.mailbox_id)
+                    .map(|(mailbox_name, _)| mailbox_name.clone())
+            })
+    }
+}
+
+pub async fn next_notify_change(notify: &mut Option<Notify>) -> Option<StateChange> {
+    if let Some(notify) = notify {
+        notify.change_rx.recv().await
+    } else {
+        std::future::pending().await
+    }
+}
+
+#[inline(always)]
+fn is_same_mailbox(name: &str, mailbox_name: &str) -> bool {
+    name == mailbox_name
+        || (name.eq_ignore_ascii_case("INBOX") && mailbox_name.eq_ignore_ascii_case("INBOX"))
+}
@@ -21,8 +21,6 @@
  * for more details.
 */
 
-use std::collections::BTreeMap;
-
 use imap_proto::{
     protocol::rename::Arguments, receiver::Request, Command, ResponseCode, StatusResponse,
 };
@@ -78,12 +76,12 @@ impl<T: SessionStream> SessionData<T> {
         };
 
         // Validate source mailbox
-        let mailbox_id = {
-            let mut mailbox_id = None;
+        let (mailbox_id, child_names) = {
+            let mut result = None;
             for account in self.mailboxes.lock().iter() {
-                if let Some(mailbox_id_) = account.mailbox_names.get(&arguments.mailbox_name) {
+                if let Some(mailbox_id) = account.mailbox_names.get(&arguments.mailbox_name) {
                     if account.account_id == params.account_id {
-                        mailbox_id = (*mailbox_id_).into();
+                        result = (*mailbox_id, account.child_names(&arguments.mailbox_name)).into();
                         break;
                     } else {
                         return StatusResponse::no("Cannot move mailboxes between accounts.")
@@ -92,8 +90,8 @@ impl<T: SessionStream> SessionData<T> {
                     }
                 }
             }
-            if let Some(mailbox_id) = mailbox_id {
-                mailbox_id
+            if let Some(result) = result {
+                result
             } else {
                 return StatusResponse::no(format!(
                     "Mailbox '{}' not found.",
@@ -104,6 +102,31 @@ impl<T: SessionStream> SessionData<T> {
             }
         };
 
+        // A mailbox cannot be moved under itself or any of its children
+        if params
+            .full_path
+            .strip_prefix(&arguments.mailbox_name)
+            .map_or(false, |suffix| suffix.starts_with('/'))
+        {
+            return StatusResponse::no("Cannot move a mailbox under one of its children.")
+                .with_tag(arguments.tag)
+                .with_code(ResponseCode::Cannot);
+        }
+
+        // Make sure that the children do not exceed the maximum depth
+        let max_child_depth = child_names
+            .iter()
+            .map(|name| name[arguments.mailbox_name.len()..].split('/').count() - 1)
+            .max()
+            .unwrap_or(0);
+        if params.full_path.split('/').count() + max_child_depth
+            > self.jmap.config.mailbox_max_depth
+        {
+            return StatusResponse::no("Mailbox path is too deep.")
+                .with_tag(arguments.tag)
+                .with_code(ResponseCode::Cannot);
+        }
+
         // Obtain mailbox
         let mailbox = if let Ok(Some(mailbox)) = self
             .jmap
@@ -139,7 +162,13 @@ impl<T: SessionStream> SessionData<T> {
         // Get new mailbox name from path
         let new_mailbox_name = params.path.pop().unwrap();
 
-        // Build batch
+        // Build batch. Note that descendants reference their parent by id, so
+        // only the renamed mailbox and any newly created parents are written,
+        // regardless of the size of the hierarchy. ACLs, subscriptions and roles
+        // of the descendants are left untouched. Writing a single batch keeps the
+        // rename atomic, whereas splitting it would expose a partially renamed
+        // hierarchy to other sessions without any per-descendant work to report
+        // progress on.
         let mut changes = match self.jmap.begin_changes(params.account_id).await {
             Ok(changes) => changes,
             Err(_) => {
@@ -253,24 +282,36 @@ impl<T: SessionStream> SessionData<T> {
                     parent_mailbox.has_children = true;
                 }
 
-                let prefix = format!("{}/", arguments.mailbox_name);
-                let mut new_mailbox_names = BTreeMap::new();
-                for (mailbox_name, mailbox_id) in std::mem::take(&mut account.mailbox_names) {
-                    if mailbox_name != arguments.mailbox_name {
-                        if let Some(child_name) = mailbox_name.strip_prefix(&prefix) {
-                            new_mailbox_names
-                                .insert(format!("{}/{}", params.full_path, child_name), mailbox_id);
-                        } else {
-                            new_mailbox_names.insert(mailbox_name, mailbox_id);
-                        }
+                // Move the mailbox and its children to their new path, children
+                // are linked by id so only the cached names need to be updated
+                account.mailbox_names.remove(&arguments.mailbox_name);
+                for child_name in &child_names {
+                    if let Some(child_id) = account.mailbox_names.remove(child_name) {
+                        account.mailbox_names.insert(
+                            format!(
+                                "{}{}",
+                                params.full_path,
+                                &child_name[arguments.mailbox_name.len()..]
+                            ),
+                            child_id,
+                        );
                     }
                 }
-                new_mailbox_names.insert(params.full_path, mailbox_id);
-                account.mailbox_names = new_mailbox_names;
+                account.mailbox_names.insert(params.full_path, mailbox_id);
                 break;
             }
         }
 
+        tracing::debug!(
+            parent: &self.span,
+            event = "rename",
+            context = "mailbox",
+            account_id = params.account_id,
+            mailbox_id = mailbox_id,
+            children = child_names.len(),
+            "Renamed mailbox hierarchy."
+        );
+
         StatusResponse::completed(Command::Rename).with_tag(arguments.tag)
     }
 }
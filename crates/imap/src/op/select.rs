@@ -129,11 +129,12 @@ impl<T: SessionStream> Session<T> {
                                 closed_previous,
                                 is_rev2,
                                 highest_modseq,
-                                mailbox_id: Id::from_parts(
-                                    mailbox.id.account_id,
-                                    mailbox.id.mailbox_id,
-                                )
-                                .to_string(),
+                                mailbox_id: Id::from(mailbox.id.mailbox_id).to_string(),
+                                keywords: data
+                                    .jmap
+                                    .label_keywords(mailbox.id.account_id)
+                                    .await
+                                    .unwrap_or_default(),
                             };
 
                             // Update state
@@ -93,13 +93,7 @@ impl<T: SessionStream> SessionData<T> {
             mailbox
         } else {
             // Some IMAP clients will try to get the status of a mailbox with the NoSelect flag
-            return if mailbox_name == self.imap.name_shared
-                || mailbox_name
-                    .split_once('/')
-                    .map_or(false, |(base_name, path)| {
-                        base_name == self.imap.name_shared && !path.contains('/')
-                    })
-            {
+            return if self.imap.namespace.is_no_select(&mailbox_name) {
                 Ok(StatusItem {
                     mailbox_name,
                     items: items
@@ -193,10 +187,7 @@ impl<T: SessionStream> SessionData<T> {
                         Status::MailboxId => {
                             items_response.push((
                                 *item,
-                                StatusItemType::String(
-                                    Id::from_parts(mailbox.account_id, mailbox.mailbox_id)
-                                        .to_string(),
-                                ),
+                                StatusItemType::String(Id::from(mailbox.mailbox_id).to_string()),
                             ));
                         }
                         Status::Recent => {
@@ -26,7 +26,7 @@ use std::sync::Arc;
 use ahash::AHashSet;
 use imap_proto::{
     protocol::{
-        fetch::{DataItem, FetchItem},
+        fetch::{AnnotationAttribute, DataItem, FetchItem},
         store::{Arguments, Operation, Response},
         Flag, ImapResponse,
     },
@@ -35,12 +35,13 @@ use imap_proto::{
 };
 use jmap::{email::set::TagManager, mailbox::UidMailbox};
 use jmap_proto::{
-    error::method::MethodError,
+    error::{method::MethodError, set::SetErrorType},
     types::{
-        acl::Acl, collection::Collection, id::Id, keyword::Keyword, property::Property,
-        state::StateChange, type_state::DataType,
+        acl::Acl, collection::Collection, date::UTCDate, id::Id, keyword::Keyword,
+        property::Property, state::StateChange, type_state::DataType, value::Value,
     },
 };
+use mail_parser::DateTime;
 use store::{
     query::log::{Change, Query},
     write::{assert::HashedValue, log::ChangeLogBuilder, BatchBuilder, F_VALUE},
@@ -49,7 +50,7 @@ use utils::listener::SessionStream;
 
 use crate::core::{message::MAX_RETRIES, SelectedMailbox, Session, SessionData};
 
-use super::FromModSeq;
+use super::{fetch::ANNOTATION_ENTRIES, FromModSeq};
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_store(
@@ -108,6 +109,13 @@ impl<T: SessionStream> SessionData<T> {
             }
         };
 
+        // Annotations are stored separately from the message flags
+        if matches!(arguments.operation, Operation::Annotation) {
+            return self
+                .store_annotations(arguments, account_id, ids.into_keys(), is_uid)
+                .await;
+        }
+
         // Verify that the user can modify messages in this mailbox.
         if !self
             .check_mailbox_acl(
@@ -195,6 +203,12 @@ impl<T: SessionStream> SessionData<T> {
             .into_iter()
             .map(Keyword::from)
             .collect::<Vec<_>>();
+        if !matches!(arguments.operation, Operation::Clear) {
+            let _ = self
+                .jmap
+                .label_sync_keywords(account_id, &set_keywords)
+                .await;
+        }
         let mut changelog = ChangeLogBuilder::new();
         let mut changed_mailboxes = AHashSet::new();
         'outer: for (id, imap_id) in ids {
@@ -242,6 +256,7 @@ impl<T: SessionStream> SessionData<T> {
                             keywords.update(keyword.clone(), false);
                         }
                     }
+                    Operation::Annotation => (),
                 }
 
                 if keywords.has_changes() {
@@ -249,6 +264,8 @@ impl<T: SessionStream> SessionData<T> {
                     let seen_changed = keywords
                         .changed_tags()
                         .any(|keyword| keyword == &Keyword::Seen);
+                    let is_junk = self.jmap.is_abuse_report_enabled()
+                        && keywords.added().contains(&Keyword::Junk);
                     let flags = if !arguments.is_silent {
                         keywords
                             .current()
@@ -277,6 +294,11 @@ impl<T: SessionStream> SessionData<T> {
                     batch.value(Property::Cid, changelog.change_id, F_VALUE);
                     match self.jmap.write_batch(batch).await {
                         Ok(_) => {
+                            // Report messages marked as spam
+                            if is_junk {
+                                self.jmap.report_junk(account_id, id).await;
+                            }
+
                             // Set all current mailboxes as changed if the Seen tag changed
                             if seen_changed {
                                 if let Some(mailboxes) = self
@@ -375,4 +397,98 @@ impl<T: SessionStream> SessionData<T> {
         // Send response
         Ok(response.serialize(items.serialize()))
     }
+
+    async fn store_annotations(
+        &self,
+        arguments: Arguments,
+        account_id: u32,
+        ids: impl Iterator<Item = u32>,
+        is_uid: bool,
+    ) -> Result<Vec<u8>, StatusResponse> {
+        // Only private annotations on the user's own messages are supported
+        if account_id != self.account_id {
+            return Err(StatusResponse::no(
+                "Annotations can only be stored on messages in your own mailboxes.",
+            )
+            .with_tag(arguments.tag)
+            .with_code(ResponseCode::NoPerm));
+        }
+
+        // Map entries to annotation properties
+        let mut updates = Vec::with_capacity(arguments.annotations.len());
+        for annotation in arguments.annotations {
+            let property = if let Some((_, property)) = ANNOTATION_ENTRIES
+                .iter()
+                .find(|(entry, _)| entry.eq_ignore_ascii_case(&annotation.entry))
+            {
+                property
+            } else {
+                return Err(StatusResponse::no(format!(
+                    "Unsupported annotation entry {:?}.",
+                    annotation.entry
+                ))
+                .with_tag(arguments.tag));
+            };
+
+            for (attribute, value) in annotation.values {
+                if attribute != AnnotationAttribute::ValuePriv {
+                    return Err(StatusResponse::no(format!(
+                        "Attribute {:?} cannot be stored.",
+                        attribute.as_str()
+                    ))
+                    .with_tag(arguments.tag));
+                }
+                let value = match (property, value) {
+                    (Property::RemindAt, Some(value)) => {
+                        if let Some(date) = DateTime::parse_rfc3339(&value) {
+                            Value::Date(UTCDate::from_timestamp(date.to_timestamp()))
+                        } else {
+                            return Err(StatusResponse::no(format!(
+                                "Invalid RFC 3339 date {value:?}."
+                            ))
+                            .with_tag(arguments.tag));
+                        }
+                    }
+                    (_, Some(value)) => Value::Text(value),
+                    (_, None) => Value::Null,
+                };
+                updates.push((property.clone(), value));
+            }
+        }
+
+        for id in ids {
+            let mut annotation = self
+                .jmap
+                .annotation_fetch(account_id, id)
+                .await
+                .map_err(|_| StatusResponse::database_failure().with_tag(&arguments.tag))?
+                .unwrap_or_default();
+            for (property, value) in &updates {
+                annotation.set(property.clone(), value.clone());
+            }
+
+            match self.jmap.annotation_store(account_id, id, annotation).await {
+                Ok(Ok(())) => (),
+                Ok(Err(err)) => {
+                    let response = StatusResponse::no(
+                        err.description
+                            .unwrap_or_else(|| "Failed to store annotation.".into()),
+                    )
+                    .with_tag(arguments.tag);
+                    return Err(if err.type_ == SetErrorType::OverQuota {
+                        response.with_code(ResponseCode::Limit)
+                    } else {
+                        response
+                    });
+                }
+                Err(_) => {
+                    return Err(StatusResponse::database_failure().with_tag(arguments.tag));
+                }
+            }
+        }
+
+        Ok(StatusResponse::completed(Command::Store(is_uid))
+            .with_tag(arguments.tag)
+            .into_bytes())
+    }
 }
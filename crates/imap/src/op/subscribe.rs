@@ -115,8 +115,9 @@ impl<T: SessionStream> SessionData<T> {
             return StatusResponse::database_failure().with_tag(tag);
         };
 
-        // Subscribe/unsubscribe to mailbox
-        if let Some(value) = mailbox.inner.mailbox_subscribe(account_id, subscribe) {
+        // Subscribe/unsubscribe to mailbox, subscriptions are always stored under the
+        // authenticated account id so that they match the ones set over JMAP
+        if let Some(value) = mailbox.inner.mailbox_subscribe(self.account_id, subscribe) {
             // Build batch
             let mut changes = match self.jmap.begin_changes(account_id).await {
                 Ok(changes) => changes,
@@ -160,10 +161,10 @@ impl<T: SessionStream> SessionData<T> {
                 )
                 .await;
 
-            // Update mailbox cache
+            // Update mailbox cache, the mailbox state is not advanced as there could be
+            // other changes made over JMAP that have not been synchronized yet
             for account in self.mailboxes.lock().iter_mut() {
                 if account.account_id == account_id {
-                    account.state_mailbox = change_id.into();
                     if let Some(mailbox) = account.mailbox_state.get_mut(&mailbox_id) {
                         mailbox.is_subscribed = subscribe;
                     }
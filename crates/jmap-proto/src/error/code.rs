@@ -0,0 +1,494 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use serde::Serialize;
+
+/// Stable error codes attached to SMTP responses, JMAP error objects and
+/// management API responses. Codes are never renumbered or reused, new
+/// codes are only appended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    // Request and management API errors
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict,
+    RateLimited,
+    AuthRateLimited,
+    QuotaExceeded,
+    LimitExceeded,
+    InternalError,
+    Unavailable,
+    UnknownCapability,
+    NotJson,
+    NotRequest,
+    MissingField,
+    AlreadyExists,
+    ItemNotFound,
+    ReservedValue,
+    Unsupported,
+
+    // JMAP method errors
+    InvalidArguments,
+    TooManyIds,
+    StateMismatch,
+    AnchorNotFound,
+    UnsupportedFilter,
+    UnsupportedSort,
+    ServerFail,
+    UnknownMethod,
+    ServerUnavailable,
+    ServerPartialFail,
+    InvalidResultReference,
+    MethodForbidden,
+    AccountNotFound,
+    AccountNotSupportedByMethod,
+    AccountReadOnly,
+    CannotCalculateChanges,
+    UnknownDataType,
+
+    // JMAP set errors
+    SetForbidden,
+    SetOverQuota,
+    SetTooLarge,
+    SetRateLimit,
+    SetNotFound,
+    SetInvalidPatch,
+    SetWillDestroy,
+    SetInvalidProperties,
+    SetSingleton,
+    SetMailboxHasChild,
+    SetMailboxHasEmail,
+    SetBlobNotFound,
+    SetTooManyKeywords,
+    SetTooManyMailboxes,
+    SetForbiddenFrom,
+    SetInvalidEmail,
+    SetTooManyRecipients,
+    SetNoRecipients,
+    SetInvalidRecipients,
+    SetForbiddenMailFrom,
+    SetForbiddenToSend,
+    SetCannotUnsend,
+    SetAlreadyExists,
+    SetInvalidScript,
+    SetScriptIsActive,
+
+    // SMTP errors, derived from the enhanced status code
+    SmtpOther,
+    SmtpAddress,
+    SmtpMailboxUnknown,
+    SmtpMailbox,
+    SmtpMailboxFull,
+    SmtpMailSystem,
+    SmtpMessageTooLarge,
+    SmtpNetwork,
+    SmtpCongestion,
+    SmtpProtocol,
+    SmtpContent,
+    SmtpPolicy,
+    SmtpNotAuthorized,
+    SmtpAuthFailed,
+}
+
+impl ErrorCode {
+    pub const ALL: &'static [ErrorCode] = &[
+        ErrorCode::BadRequest,
+        ErrorCode::Unauthorized,
+        ErrorCode::Forbidden,
+        ErrorCode::NotFound,
+        ErrorCode::Conflict,
+        ErrorCode::RateLimited,
+        ErrorCode::AuthRateLimited,
+        ErrorCode::QuotaExceeded,
+        ErrorCode::LimitExceeded,
+        ErrorCode::InternalError,
+        ErrorCode::Unavailable,
+        ErrorCode::UnknownCapability,
+        ErrorCode::NotJson,
+        ErrorCode::NotRequest,
+        ErrorCode::MissingField,
+        ErrorCode::AlreadyExists,
+        ErrorCode::ItemNotFound,
+        ErrorCode::ReservedValue,
+        ErrorCode::Unsupported,
+        ErrorCode::InvalidArguments,
+        ErrorCode::TooManyIds,
+        ErrorCode::StateMismatch,
+        ErrorCode::AnchorNotFound,
+        ErrorCode::UnsupportedFilter,
+        ErrorCode::UnsupportedSort,
+        ErrorCode::ServerFail,
+        ErrorCode::UnknownMethod,
+        ErrorCode::ServerUnavailable,
+        ErrorCode::ServerPartialFail,
+        ErrorCode::InvalidResultReference,
+        ErrorCode::MethodForbidden,
+        ErrorCode::AccountNotFound,
+        ErrorCode::AccountNotSupportedByMethod,
+        ErrorCode::AccountReadOnly,
+        ErrorCode::CannotCalculateChanges,
+        ErrorCode::UnknownDataType,
+        ErrorCode::SetForbidden,
+        ErrorCode::SetOverQuota,
+        ErrorCode::SetTooLarge,
+        ErrorCode::SetRateLimit,
+        ErrorCode::SetNotFound,
+        ErrorCode::SetInvalidPatch,
+        ErrorCode::SetWillDestroy,
+        ErrorCode::SetInvalidProperties,
+        ErrorCode::SetSingleton,
+        ErrorCode::SetMailboxHasChild,
+        ErrorCode::SetMailboxHasEmail,
+        ErrorCode::SetBlobNotFound,
+        ErrorCode::SetTooManyKeywords,
+        ErrorCode::SetTooManyMailboxes,
+        ErrorCode::SetForbiddenFrom,
+        ErrorCode::SetInvalidEmail,
+        ErrorCode::SetTooManyRecipients,
+        ErrorCode::SetNoRecipients,
+        ErrorCode::SetInvalidRecipients,
+        ErrorCode::SetForbiddenMailFrom,
+        ErrorCode::SetForbiddenToSend,
+        ErrorCode::SetCannotUnsend,
+        ErrorCode::SetAlreadyExists,
+        ErrorCode::SetInvalidScript,
+        ErrorCode::SetScriptIsActive,
+        ErrorCode::SmtpOther,
+        ErrorCode::SmtpAddress,
+        ErrorCode::SmtpMailboxUnknown,
+        ErrorCode::SmtpMailbox,
+        ErrorCode::SmtpMailboxFull,
+        ErrorCode::SmtpMailSystem,
+        ErrorCode::SmtpMessageTooLarge,
+        ErrorCode::SmtpNetwork,
+        ErrorCode::SmtpCongestion,
+        ErrorCode::SmtpProtocol,
+        ErrorCode::SmtpContent,
+        ErrorCode::SmtpPolicy,
+        ErrorCode::SmtpNotAuthorized,
+        ErrorCode::SmtpAuthFailed,
+    ];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => "E1000",
+            ErrorCode::Unauthorized => "E1001",
+            ErrorCode::Forbidden => "E1002",
+            ErrorCode::NotFound => "E1003",
+            ErrorCode::Conflict => "E1004",
+            ErrorCode::RateLimited => "E1005",
+            ErrorCode::AuthRateLimited => "E1006",
+            ErrorCode::QuotaExceeded => "E1007",
+            ErrorCode::LimitExceeded => "E1008",
+            ErrorCode::InternalError => "E1009",
+            ErrorCode::Unavailable => "E1010",
+            ErrorCode::UnknownCapability => "E1011",
+            ErrorCode::NotJson => "E1012",
+            ErrorCode::NotRequest => "E1013",
+            ErrorCode::MissingField => "E1100",
+            ErrorCode::AlreadyExists => "E1101",
+            ErrorCode::ItemNotFound => "E1102",
+            ErrorCode::ReservedValue => "E1103",
+            ErrorCode::Unsupported => "E1104",
+            ErrorCode::InvalidArguments => "E2000",
+            ErrorCode::TooManyIds => "E2001",
+            ErrorCode::StateMismatch => "E2002",
+            ErrorCode::AnchorNotFound => "E2003",
+            ErrorCode::UnsupportedFilter => "E2004",
+            ErrorCode::UnsupportedSort => "E2005",
+            ErrorCode::ServerFail => "E2006",
+            ErrorCode::UnknownMethod => "E2007",
+            ErrorCode::ServerUnavailable => "E2008",
+            ErrorCode::ServerPartialFail => "E2009",
+            ErrorCode::InvalidResultReference => "E2010",
+            ErrorCode::MethodForbidden => "E2011",
+            ErrorCode::AccountNotFound => "E2012",
+            ErrorCode::AccountNotSupportedByMethod => "E2013",
+            ErrorCode::AccountReadOnly => "E2014",
+            ErrorCode::CannotCalculateChanges => "E2015",
+            ErrorCode::UnknownDataType => "E2016",
+            ErrorCode::SetForbidden => "E2100",
+            ErrorCode::SetOverQuota => "E2101",
+            ErrorCode::SetTooLarge => "E2102",
+            ErrorCode::SetRateLimit => "E2103",
+            ErrorCode::SetNotFound => "E2104",
+            ErrorCode::SetInvalidPatch => "E2105",
+            ErrorCode::SetWillDestroy => "E2106",
+            ErrorCode::SetInvalidProperties => "E2107",
+            ErrorCode::SetSingleton => "E2108",
+            ErrorCode::SetMailboxHasChild => "E2109",
+            ErrorCode::SetMailboxHasEmail => "E2110",
+            ErrorCode::SetBlobNotFound => "E2111",
+            ErrorCode::SetTooManyKeywords => "E2112",
+            ErrorCode::SetTooManyMailboxes => "E2113",
+            ErrorCode::SetForbiddenFrom => "E2114",
+            ErrorCode::SetInvalidEmail => "E2115",
+            ErrorCode::SetTooManyRecipients => "E2116",
+            ErrorCode::SetNoRecipients => "E2117",
+            ErrorCode::SetInvalidRecipients => "E2118",
+            ErrorCode::SetForbiddenMailFrom => "E2119",
+            ErrorCode::SetForbiddenToSend => "E2120",
+            ErrorCode::SetCannotUnsend => "E2121",
+            ErrorCode::SetAlreadyExists => "E2122",
+            ErrorCode::SetInvalidScript => "E2123",
+            ErrorCode::SetScriptIsActive => "E2124",
+            ErrorCode::SmtpOther => "E3000",
+            ErrorCode::SmtpAddress => "E3001",
+            ErrorCode::SmtpMailboxUnknown => "E3002",
+            ErrorCode::SmtpMailbox => "E3003",
+            ErrorCode::SmtpMailboxFull => "E3004",
+            ErrorCode::SmtpMailSystem => "E3005",
+            ErrorCode::SmtpMessageTooLarge => "E3006",
+            ErrorCode::SmtpNetwork => "E3007",
+            ErrorCode::SmtpCongestion => "E3008",
+            ErrorCode::SmtpProtocol => "E3009",
+            ErrorCode::SmtpContent => "E3010",
+            ErrorCode::SmtpPolicy => "E3011",
+            ErrorCode::SmtpNotAuthorized => "E3012",
+            ErrorCode::SmtpAuthFailed => "E3013",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => "The request is malformed or contains invalid parameters.",
+            ErrorCode::Unauthorized => "The request requires authentication.",
+            ErrorCode::Forbidden => "The authenticated user is not allowed to perform this action.",
+            ErrorCode::NotFound => "The requested resource does not exist.",
+            ErrorCode::Conflict => "The request conflicts with an operation already in progress.",
+            ErrorCode::RateLimited => "Too many requests were sent in a given amount of time.",
+            ErrorCode::AuthRateLimited => "Too many failed authentication attempts.",
+            ErrorCode::QuotaExceeded => "The account has exceeded its quota.",
+            ErrorCode::LimitExceeded => "The request exceeds a server limit.",
+            ErrorCode::InternalError => "An unexpected error occurred on the server.",
+            ErrorCode::Unavailable => "The server is temporarily unavailable.",
+            ErrorCode::UnknownCapability => {
+                "The request uses a capability the server does not support."
+            }
+            ErrorCode::NotJson => "The request body is not valid JSON.",
+            ErrorCode::NotRequest => "The request body is not a valid JMAP request object.",
+            ErrorCode::MissingField => "A required field is missing.",
+            ErrorCode::AlreadyExists => "Another record exists with the same value.",
+            ErrorCode::ItemNotFound => "A referenced item does not exist.",
+            ErrorCode::ReservedValue => "The value is reserved and cannot be assigned.",
+            ErrorCode::Unsupported => "The requested action is not supported by the backend.",
+            ErrorCode::InvalidArguments => "One of the method arguments is invalid.",
+            ErrorCode::TooManyIds => "The number of ids requested exceeds the server limit.",
+            ErrorCode::StateMismatch => "The supplied state does not match the current state.",
+            ErrorCode::AnchorNotFound => "The anchor was not found in the query results.",
+            ErrorCode::UnsupportedFilter => "The filter is not supported.",
+            ErrorCode::UnsupportedSort => "The sort order is not supported.",
+            ErrorCode::ServerFail => "An unexpected error occurred while processing the method.",
+            ErrorCode::UnknownMethod => "The method is not recognised.",
+            ErrorCode::ServerUnavailable => {
+                "The server is temporarily unable to process the method."
+            }
+            ErrorCode::ServerPartialFail => "Only some of the requested changes were applied.",
+            ErrorCode::InvalidResultReference => "A result reference could not be resolved.",
+            ErrorCode::MethodForbidden => {
+                "The method or arguments are not permitted for this user."
+            }
+            ErrorCode::AccountNotFound => "The account does not exist.",
+            ErrorCode::AccountNotSupportedByMethod => "The account does not support this method.",
+            ErrorCode::AccountReadOnly => "The account is read-only.",
+            ErrorCode::CannotCalculateChanges => {
+                "The changes since the given state are unavailable."
+            }
+            ErrorCode::UnknownDataType => "The data type is not recognised.",
+            ErrorCode::SetForbidden => "The object cannot be modified by this user.",
+            ErrorCode::SetOverQuota => "Creating the object would exceed the account quota.",
+            ErrorCode::SetTooLarge => "The object is larger than the server allows.",
+            ErrorCode::SetRateLimit => "Too many objects of this type were created recently.",
+            ErrorCode::SetNotFound => "The object to update or destroy does not exist.",
+            ErrorCode::SetInvalidPatch => "The patch object is invalid.",
+            ErrorCode::SetWillDestroy => "The object is also being destroyed in the same call.",
+            ErrorCode::SetInvalidProperties => "One or more object properties are invalid.",
+            ErrorCode::SetSingleton => "Singleton objects cannot be created or destroyed.",
+            ErrorCode::SetMailboxHasChild => "The mailbox has child mailboxes.",
+            ErrorCode::SetMailboxHasEmail => "The mailbox contains messages.",
+            ErrorCode::SetBlobNotFound => "A referenced blob does not exist.",
+            ErrorCode::SetTooManyKeywords => "The message has too many keywords.",
+            ErrorCode::SetTooManyMailboxes => "The message belongs to too many mailboxes.",
+            ErrorCode::SetForbiddenFrom => "The user is not allowed to send from this address.",
+            ErrorCode::SetInvalidEmail => "The message is not valid for submission.",
+            ErrorCode::SetTooManyRecipients => "The submission has too many recipients.",
+            ErrorCode::SetNoRecipients => "The submission has no recipients.",
+            ErrorCode::SetInvalidRecipients => "One or more recipient addresses are invalid.",
+            ErrorCode::SetForbiddenMailFrom => "The envelope sender is not allowed for this user.",
+            ErrorCode::SetForbiddenToSend => "The user is not allowed to send messages.",
+            ErrorCode::SetCannotUnsend => "The submission can no longer be cancelled.",
+            ErrorCode::SetAlreadyExists => "An object with the same unique value exists.",
+            ErrorCode::SetInvalidScript => "The Sieve script could not be compiled.",
+            ErrorCode::SetScriptIsActive => "Active Sieve scripts cannot be destroyed.",
+            ErrorCode::SmtpOther => "The message was rejected for an unspecified reason.",
+            ErrorCode::SmtpAddress => "The address is invalid or cannot be routed.",
+            ErrorCode::SmtpMailboxUnknown => "The destination mailbox does not exist.",
+            ErrorCode::SmtpMailbox => "The destination mailbox cannot accept messages.",
+            ErrorCode::SmtpMailboxFull => "The destination mailbox is full.",
+            ErrorCode::SmtpMailSystem => "The mail system cannot accept the message.",
+            ErrorCode::SmtpMessageTooLarge => "The message exceeds the maximum size.",
+            ErrorCode::SmtpNetwork => "A network or routing problem prevented delivery.",
+            ErrorCode::SmtpCongestion => "The server is congested or rate limiting the client.",
+            ErrorCode::SmtpProtocol => "The command is invalid or out of sequence.",
+            ErrorCode::SmtpContent => "The message content was rejected.",
+            ErrorCode::SmtpPolicy => "The message was rejected by a security or policy check.",
+            ErrorCode::SmtpNotAuthorized => "The sender is not authorized to relay or deliver.",
+            ErrorCode::SmtpAuthFailed => "The authentication credentials are invalid.",
+        }
+    }
+
+    pub fn from_http_status(status: u16) -> Self {
+        match status {
+            401 => ErrorCode::Unauthorized,
+            403 => ErrorCode::Forbidden,
+            404 => ErrorCode::NotFound,
+            409 => ErrorCode::Conflict,
+            413 => ErrorCode::LimitExceeded,
+            429 => ErrorCode::RateLimited,
+            503 => ErrorCode::Unavailable,
+            500.. => ErrorCode::InternalError,
+            _ => ErrorCode::BadRequest,
+        }
+    }
+
+    pub fn from_smtp_status(subject: u16, detail: u16) -> Self {
+        match (subject, detail) {
+            (1, 1) => ErrorCode::SmtpMailboxUnknown,
+            (1, _) => ErrorCode::SmtpAddress,
+            (2, 2) => ErrorCode::SmtpMailboxFull,
+            (2, _) => ErrorCode::SmtpMailbox,
+            (3, 4) => ErrorCode::SmtpMessageTooLarge,
+            (3, _) => ErrorCode::SmtpMailSystem,
+            (4, 5) => ErrorCode::SmtpCongestion,
+            (4, _) => ErrorCode::SmtpNetwork,
+            (5, _) => ErrorCode::SmtpProtocol,
+            (6, _) => ErrorCode::SmtpContent,
+            (7, 1) => ErrorCode::SmtpNotAuthorized,
+            (7, 8) => ErrorCode::SmtpAuthFailed,
+            (7, _) => ErrorCode::SmtpPolicy,
+            _ => ErrorCode::SmtpOther,
+        }
+    }
+
+    /// Inserts the error code after the enhanced status code of each line
+    /// of an SMTP error response, leaving any other response untouched.
+    pub fn tag_smtp_response(response: &[u8]) -> Option<Vec<u8>> {
+        if !matches!(response.first(), Some(b'4' | b'5')) {
+            return None;
+        }
+
+        let mut tagged = Vec::with_capacity(response.len() + 8);
+        let mut is_tagged = false;
+        for line in response.split_inclusive(|&ch| ch == b'\n') {
+            let status = line
+                .get(4..)
+                .and_then(|status| {
+                    status
+                        .iter()
+                        .position(|&ch| ch == b' ')
+                        .map(|pos| (status, pos))
+                })
+                .and_then(|(status, pos)| std::str::from_utf8(&status[..pos]).ok());
+            let mut parts = status.unwrap_or_default().split('.');
+            match (
+                parts.next().and_then(|class| class.parse::<u16>().ok()),
+                parts.next().and_then(|subject| subject.parse::<u16>().ok()),
+                parts.next().and_then(|detail| detail.parse::<u16>().ok()),
+                parts.next(),
+            ) {
+                (Some(4 | 5), Some(subject), Some(detail), None) => {
+                    let pos = 4 + status.unwrap_or_default().len();
+                    tagged.extend_from_slice(&line[..pos]);
+                    tagged.extend_from_slice(b" [");
+                    tagged.extend_from_slice(
+                        ErrorCode::from_smtp_status(subject, detail)
+                            .code()
+                            .as_bytes(),
+                    );
+                    tagged.push(b']');
+                    tagged.extend_from_slice(&line[pos..]);
+                    is_tagged = true;
+                }
+                _ => {
+                    tagged.extend_from_slice(line);
+                }
+            }
+        }
+
+        if is_tagged {
+            Some(tagged)
+        } else {
+            None
+        }
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ErrorCode;
+
+    #[test]
+    fn error_codes() {
+        // Codes must be unique
+        let mut codes = ErrorCode::ALL
+            .iter()
+            .map(|code| code.code())
+            .collect::<Vec<_>>();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), ErrorCode::ALL.len());
+
+        for (response, expected) in [
+            (
+                "550 5.1.1 Mailbox does not exist.\r\n",
+                Some("550 5.1.1 [E3002] Mailbox does not exist.\r\n"),
+            ),
+            (
+                "452-4.4.5 Try again later.\r\n452 4.4.5 Rate limit exceeded.\r\n",
+                Some(
+                    "452-4.4.5 [E3008] Try again later.\r\n452 4.4.5 [E3008] Rate limit exceeded.\r\n",
+                ),
+            ),
+            ("503 Bad sequence of commands.\r\n", None),
+            ("250 2.1.0 OK\r\n", None),
+        ] {
+            assert_eq!(
+                ErrorCode::tag_smtp_response(response.as_bytes())
+                    .map(|response| String::from_utf8(response).unwrap()),
+                expected.map(|expected| expected.to_string()),
+                "{response}"
+            );
+        }
+    }
+}
@@ -26,6 +26,8 @@ use std::fmt::Display;
 use serde::ser::SerializeMap;
 use serde::Serialize;
 
+use super::code::ErrorCode;
+
 #[derive(Debug)]
 pub enum MethodError {
     InvalidArguments(String),
@@ -77,12 +79,36 @@ impl Display for MethodError {
     }
 }
 
+impl MethodError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            MethodError::InvalidArguments(_) => ErrorCode::InvalidArguments,
+            MethodError::RequestTooLarge => ErrorCode::TooManyIds,
+            MethodError::StateMismatch => ErrorCode::StateMismatch,
+            MethodError::AnchorNotFound => ErrorCode::AnchorNotFound,
+            MethodError::UnsupportedFilter(_) => ErrorCode::UnsupportedFilter,
+            MethodError::UnsupportedSort(_) => ErrorCode::UnsupportedSort,
+            MethodError::ServerFail(_) => ErrorCode::ServerFail,
+            MethodError::UnknownMethod(_) => ErrorCode::UnknownMethod,
+            MethodError::ServerUnavailable => ErrorCode::ServerUnavailable,
+            MethodError::ServerPartialFail | MethodError::NotFound => ErrorCode::ServerPartialFail,
+            MethodError::InvalidResultReference(_) => ErrorCode::InvalidResultReference,
+            MethodError::Forbidden(_) => ErrorCode::MethodForbidden,
+            MethodError::AccountNotFound => ErrorCode::AccountNotFound,
+            MethodError::AccountNotSupportedByMethod => ErrorCode::AccountNotSupportedByMethod,
+            MethodError::AccountReadOnly => ErrorCode::AccountReadOnly,
+            MethodError::CannotCalculateChanges => ErrorCode::CannotCalculateChanges,
+            MethodError::UnknownDataType => ErrorCode::UnknownDataType,
+        }
+    }
+}
+
 impl Serialize for MethodError {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let mut map = serializer.serialize_map(2.into())?;
+        let mut map = serializer.serialize_map(3.into())?;
 
         let (error_type, description) = match self {
             MethodError::InvalidArguments(description) => {
@@ -177,6 +203,7 @@ impl Serialize for MethodError {
         };
 
         map.serialize_entry("type", error_type)?;
+        map.serialize_entry("code", &self.code())?;
         if !description.is_empty() {
             map.serialize_entry("description", description)?;
         }
@@ -21,6 +21,7 @@
  * for more details.
 */
 
+pub mod code;
 pub mod method;
 pub mod request;
 pub mod set;
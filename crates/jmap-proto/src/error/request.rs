@@ -23,6 +23,8 @@
 
 use std::{borrow::Cow, fmt::Display};
 
+use super::code::ErrorCode;
+
 #[derive(Debug, Clone, Copy, serde::Serialize)]
 pub enum RequestLimitError {
     #[serde(rename(serialize = "maxSizeRequest"))]
@@ -56,11 +58,15 @@ pub struct RequestError {
     #[serde(rename(serialize = "type"))]
     pub p_type: RequestErrorType,
     pub status: u16,
+    pub code: ErrorCode,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub title: Option<Cow<'static, str>>,
     pub detail: Cow<'static, str>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub limit: Option<RequestLimitError>,
+    #[serde(rename(serialize = "retryAfter"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
 }
 
 impl RequestError {
@@ -72,9 +78,11 @@ impl RequestError {
         RequestError {
             p_type: RequestErrorType::Other,
             status,
+            code: ErrorCode::from_http_status(status),
             title: Some(title.into()),
             detail: detail.into(),
             limit: None,
+            retry_after: None,
         }
     }
 
@@ -125,6 +133,7 @@ impl RequestError {
                 max_files, max_bytes
             ),
         )
+        .with_code(ErrorCode::QuotaExceeded)
     }
 
     pub fn too_many_requests() -> Self {
@@ -135,18 +144,30 @@ impl RequestError {
         )
     }
 
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = code;
+        self
+    }
+
+    pub fn with_retry_after(mut self, retry_after: u64) -> Self {
+        self.retry_after = Some(retry_after);
+        self
+    }
+
     pub fn too_many_auth_attempts() -> Self {
         RequestError::blank(
             429,
             "Too Many Authentication Attempts",
             "Your request has been rate limited. Please try again in a few minutes.",
         )
+        .with_code(ErrorCode::AuthRateLimited)
     }
 
     pub fn limit(limit_type: RequestLimitError) -> Self {
         RequestError {
             p_type: RequestErrorType::Limit,
             status: 400,
+            code: ErrorCode::LimitExceeded,
             title: None,
             detail: match limit_type {
                 RequestLimitError::SizeRequest => concat!(
@@ -172,6 +193,7 @@ impl RequestError {
             }
             .into(),
             limit: Some(limit_type),
+            retry_after: None,
         }
     }
 
@@ -191,8 +213,10 @@ impl RequestError {
         RequestError {
             p_type: RequestErrorType::UnknownCapability,
             limit: None,
+            retry_after: None,
             title: None,
             status: 400,
+            code: ErrorCode::UnknownCapability,
             detail: format!(
                 concat!(
                     "The Request object used capability ",
@@ -209,8 +233,10 @@ impl RequestError {
         RequestError {
             p_type: RequestErrorType::NotJSON,
             limit: None,
+            retry_after: None,
             title: None,
             status: 400,
+            code: ErrorCode::NotJson,
             detail: format!("Failed to parse JSON: {detail}").into(),
         }
     }
@@ -219,8 +245,10 @@ impl RequestError {
         RequestError {
             p_type: RequestErrorType::NotRequest,
             limit: None,
+            retry_after: None,
             title: None,
             status: 400,
+            code: ErrorCode::NotRequest,
             detail: detail.into(),
         }
     }
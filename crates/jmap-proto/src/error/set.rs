@@ -25,11 +25,15 @@ use std::borrow::Cow;
 
 use crate::types::{id::Id, property::Property};
 
+use super::code::ErrorCode;
+
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct SetError {
     #[serde(rename = "type")]
     pub type_: SetErrorType,
 
+    code: ErrorCode,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<Cow<'static, str>>,
 
@@ -131,11 +135,42 @@ impl SetErrorType {
             SetErrorType::ScriptIsActive => "scriptIsActive",
         }
     }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            SetErrorType::Forbidden => ErrorCode::SetForbidden,
+            SetErrorType::OverQuota => ErrorCode::SetOverQuota,
+            SetErrorType::TooLarge => ErrorCode::SetTooLarge,
+            SetErrorType::RateLimit => ErrorCode::SetRateLimit,
+            SetErrorType::NotFound => ErrorCode::SetNotFound,
+            SetErrorType::InvalidPatch => ErrorCode::SetInvalidPatch,
+            SetErrorType::WillDestroy => ErrorCode::SetWillDestroy,
+            SetErrorType::InvalidProperties => ErrorCode::SetInvalidProperties,
+            SetErrorType::Singleton => ErrorCode::SetSingleton,
+            SetErrorType::BlobNotFound => ErrorCode::SetBlobNotFound,
+            SetErrorType::MailboxHasChild => ErrorCode::SetMailboxHasChild,
+            SetErrorType::MailboxHasEmail => ErrorCode::SetMailboxHasEmail,
+            SetErrorType::TooManyKeywords => ErrorCode::SetTooManyKeywords,
+            SetErrorType::TooManyMailboxes => ErrorCode::SetTooManyMailboxes,
+            SetErrorType::ForbiddenFrom => ErrorCode::SetForbiddenFrom,
+            SetErrorType::InvalidEmail => ErrorCode::SetInvalidEmail,
+            SetErrorType::TooManyRecipients => ErrorCode::SetTooManyRecipients,
+            SetErrorType::NoRecipients => ErrorCode::SetNoRecipients,
+            SetErrorType::InvalidRecipients => ErrorCode::SetInvalidRecipients,
+            SetErrorType::ForbiddenMailFrom => ErrorCode::SetForbiddenMailFrom,
+            SetErrorType::ForbiddenToSend => ErrorCode::SetForbiddenToSend,
+            SetErrorType::CannotUnsend => ErrorCode::SetCannotUnsend,
+            SetErrorType::AlreadyExists => ErrorCode::SetAlreadyExists,
+            SetErrorType::InvalidScript => ErrorCode::SetInvalidScript,
+            SetErrorType::ScriptIsActive => ErrorCode::SetScriptIsActive,
+        }
+    }
 }
 
 impl SetError {
     pub fn new(type_: SetErrorType) -> Self {
         SetError {
+            code: type_.code(),
             type_,
             description: None,
             properties: None,
@@ -0,0 +1,135 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::net::IpAddr;
+
+use crate::{
+    parser::{json::Parser, Ignore, JsonObjectParser, Token},
+    request::RequestProperty,
+    types::{date::UTCDate, id::Id},
+};
+
+#[derive(Debug, Clone)]
+pub struct GetActivityLogRequest {
+    pub account_id: Id,
+    pub since: Option<UTCDate>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GetActivityLogResponse {
+    #[serde(rename = "accountId")]
+    pub account_id: Id,
+
+    #[serde(rename = "list")]
+    pub list: Vec<ActivityLogEntry>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ActivityLogEntry {
+    #[serde(rename = "id")]
+    pub id: Id,
+
+    #[serde(rename = "timestamp")]
+    pub timestamp: UTCDate,
+
+    #[serde(rename = "event")]
+    pub event: ActivityEvent,
+
+    #[serde(rename = "protocol")]
+    pub protocol: String,
+
+    #[serde(rename = "remoteIp")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_ip: Option<IpAddr>,
+
+    #[serde(rename = "details")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ActivityEvent {
+    #[serde(rename = "login")]
+    Login,
+    #[serde(rename = "emailMove")]
+    EmailMove,
+    #[serde(rename = "emailDelete")]
+    EmailDelete,
+    #[serde(rename = "sieveChange")]
+    SieveChange,
+    #[serde(rename = "sharingChange")]
+    SharingChange,
+    #[serde(rename = "recoveryChange")]
+    RecoveryChange,
+    #[serde(rename = "accountRecovery")]
+    AccountRecovery,
+    #[serde(rename = "legalHold")]
+    LegalHold,
+    #[serde(rename = "redaction")]
+    Redaction,
+    #[serde(rename = "stepUp")]
+    StepUp,
+    #[serde(rename = "sieveRedirectBlocked")]
+    SieveRedirectBlocked,
+}
+
+impl JsonObjectParser for GetActivityLogRequest {
+    fn parse(parser: &mut Parser<'_>) -> crate::parser::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut request = GetActivityLogRequest {
+            account_id: Id::default(),
+            since: None,
+            limit: None,
+        };
+
+        parser
+            .next_token::<String>()?
+            .assert_jmap(Token::DictStart)?;
+
+        while let Some(key) = parser.next_dict_key::<RequestProperty>()? {
+            match &key.hash[0] {
+                0x0064_4974_6e75_6f63_6361 if !key.is_ref => {
+                    request.account_id = parser.next_token::<Id>()?.unwrap_string("accountId")?;
+                }
+                0x0065_636e_6973 if !key.is_ref => {
+                    request.since = parser
+                        .next_token::<UTCDate>()?
+                        .unwrap_string_or_null("since")?;
+                }
+                0x0074_696d_696c if !key.is_ref => {
+                    request.limit = parser
+                        .next_token::<Ignore>()?
+                        .unwrap_usize_or_null("limit")?;
+                }
+                _ => {
+                    parser.skip_token(parser.depth_array, parser.depth_dict)?;
+                }
+            }
+        }
+
+        Ok(request)
+    }
+}
@@ -69,6 +69,11 @@ pub enum RequestArguments {
     Identity,
     EmailSubmission,
     Quota,
+    Label,
+    TagRule,
+    ArchivePolicy,
+    DisposableAlias,
+    Annotation,
 }
 
 impl JsonObjectParser for ChangesRequest {
@@ -84,6 +89,11 @@ impl JsonObjectParser for ChangesRequest {
                 MethodObject::Identity => RequestArguments::Identity,
                 MethodObject::EmailSubmission => RequestArguments::EmailSubmission,
                 MethodObject::Quota => RequestArguments::Quota,
+                MethodObject::Label => RequestArguments::Label,
+                MethodObject::TagRule => RequestArguments::TagRule,
+                MethodObject::ArchivePolicy => RequestArguments::ArchivePolicy,
+                MethodObject::DisposableAlias => RequestArguments::DisposableAlias,
+                MethodObject::Annotation => RequestArguments::Annotation,
                 _ => {
                     return Err(Error::Method(MethodError::UnknownMethod(format!(
                         "{}/changes",
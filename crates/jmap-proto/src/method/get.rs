@@ -54,6 +54,11 @@ pub enum RequestArguments {
     Principal,
     Quota,
     Blob(blob::GetArguments),
+    Label,
+    TagRule,
+    ArchivePolicy,
+    DisposableAlias,
+    Annotation,
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -89,6 +94,11 @@ impl JsonObjectParser for GetRequest<RequestArguments> {
                 MethodObject::Principal => RequestArguments::Principal,
                 MethodObject::Blob => RequestArguments::Blob(Default::default()),
                 MethodObject::Quota => RequestArguments::Quota,
+                MethodObject::Label => RequestArguments::Label,
+                MethodObject::TagRule => RequestArguments::TagRule,
+                MethodObject::ArchivePolicy => RequestArguments::ArchivePolicy,
+                MethodObject::DisposableAlias => RequestArguments::DisposableAlias,
+                MethodObject::Annotation => RequestArguments::Annotation,
                 _ => {
                     return Err(Error::Method(MethodError::UnknownMethod(format!(
                         "{}/get",
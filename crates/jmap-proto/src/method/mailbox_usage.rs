@@ -0,0 +1,99 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::{
+    parser::{json::Parser, JsonObjectParser, Token},
+    request::RequestProperty,
+    types::{date::UTCDate, id::Id},
+};
+
+#[derive(Debug, Clone)]
+pub struct GetMailboxUsageRequest {
+    pub account_id: Id,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GetMailboxUsageResponse {
+    #[serde(rename = "accountId")]
+    pub account_id: Id,
+
+    /// Time of the last full recount of the account's counters.
+    #[serde(rename = "reconciledAt")]
+    pub reconciled_at: UTCDate,
+
+    /// Upper bound in seconds on how long a drifted counter can go uncorrected.
+    #[serde(rename = "maxStaleness")]
+    pub max_staleness: u64,
+
+    #[serde(rename = "list")]
+    pub list: Vec<MailboxUsage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct MailboxUsage {
+    #[serde(rename = "id")]
+    pub id: Id,
+
+    #[serde(rename = "parentId")]
+    pub parent_id: Option<Id>,
+
+    #[serde(rename = "emails")]
+    pub emails: u64,
+
+    #[serde(rename = "size")]
+    pub size: u64,
+
+    #[serde(rename = "totalEmails")]
+    pub total_emails: u64,
+
+    #[serde(rename = "totalSize")]
+    pub total_size: u64,
+}
+
+impl JsonObjectParser for GetMailboxUsageRequest {
+    fn parse(parser: &mut Parser<'_>) -> crate::parser::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut request = GetMailboxUsageRequest {
+            account_id: Id::default(),
+        };
+
+        parser
+            .next_token::<String>()?
+            .assert_jmap(Token::DictStart)?;
+
+        while let Some(key) = parser.next_dict_key::<RequestProperty>()? {
+            match &key.hash[0] {
+                0x0064_4974_6e75_6f63_6361 if !key.is_ref => {
+                    request.account_id = parser.next_token::<Id>()?.unwrap_string("accountId")?;
+                }
+                _ => {
+                    parser.skip_token(parser.depth_array, parser.depth_dict)?;
+                }
+            }
+        }
+
+        Ok(request)
+    }
+}
@@ -23,16 +23,19 @@
 
 use ahash::AHashMap;
 
+pub mod activity;
 pub mod changes;
 pub mod copy;
 pub mod get;
 pub mod import;
 pub mod lookup;
+pub mod mailbox_usage;
 pub mod parse;
 pub mod query;
 pub mod query_changes;
 pub mod search_snippet;
 pub mod set;
+pub mod update_flags;
 pub mod upload;
 pub mod validate;
 
@@ -23,6 +23,7 @@
 
 use std::fmt::Display;
 
+use mail_parser::HeaderName;
 use store::fts::{FilterItem, FilterType, FtsFilter};
 
 use crate::{
@@ -809,8 +810,8 @@ impl FilterItem for Filter {
             | Filter::Cc(_)
             | Filter::Bcc(_)
             | Filter::Subject(_)
-            | Filter::Body(_)
-            | Filter::Header(_) => FilterType::Fts,
+            | Filter::Body(_) => FilterType::Fts,
+            Filter::Header(header) if !header_needs_scan(header) => FilterType::Fts,
             Filter::And => FilterType::And,
             Filter::Or => FilterType::Or,
             Filter::Not => FilterType::Not,
@@ -820,6 +821,18 @@ impl FilterItem for Filter {
     }
 }
 
+// Headers that are not indexed, or matched with a regular expression,
+// are evaluated by scanning the message headers.
+fn header_needs_scan(header: &[String]) -> bool {
+    header.get(2).map_or(false, |mode| mode != "contains")
+        || matches!(
+            header
+                .first()
+                .and_then(|name| HeaderName::parse(name.as_str())),
+            Some(HeaderName::Other(_))
+        )
+}
+
 impl From<FilterType> for Filter {
     fn from(value: FilterType) -> Self {
         match value {
@@ -71,6 +71,11 @@ pub enum RequestArguments {
     PushSubscription,
     SieveScript(sieve::SetArguments),
     VacationResponse,
+    Label,
+    TagRule,
+    ArchivePolicy,
+    DisposableAlias,
+    Annotation,
 }
 
 #[derive(Debug, Clone, Default, serde::Serialize)]
@@ -131,6 +136,11 @@ impl JsonObjectParser for SetRequest<RequestArguments> {
                 MethodObject::PushSubscription => RequestArguments::PushSubscription,
                 MethodObject::VacationResponse => RequestArguments::VacationResponse,
                 MethodObject::SieveScript => RequestArguments::SieveScript(Default::default()),
+                MethodObject::Label => RequestArguments::Label,
+                MethodObject::TagRule => RequestArguments::TagRule,
+                MethodObject::ArchivePolicy => RequestArguments::ArchivePolicy,
+                MethodObject::DisposableAlias => RequestArguments::DisposableAlias,
+                MethodObject::Annotation => RequestArguments::Annotation,
                 _ => {
                     return Err(Error::Method(MethodError::UnknownMethod(format!(
                         "{}/set",
@@ -214,7 +224,8 @@ impl JsonObjectParser for Object<SetValue> {
                     | Property::ReceivedAt
                     | Property::Expires
                     | Property::FromDate
-                    | Property::ToDate => parser
+                    | Property::ToDate
+                    | Property::RemindAt => parser
                         .next_token::<UTCDate>()?
                         .unwrap_string_or_null("")?
                         .map(|date| SetValue::Value(Value::Date(date)))
@@ -238,6 +249,12 @@ impl JsonObjectParser for Object<SetValue> {
                     | Property::Location
                     | Property::Cid
                     | Property::Role
+                    | Property::Color
+                    | Property::Text
+                    | Property::Keyword
+                    | Property::Tag
+                    | Property::MailboxName
+                    | Property::EnvelopeSender
                     | Property::PartId => parser
                         .next_token::<String>()?
                         .unwrap_string_or_null("")?
@@ -265,12 +282,18 @@ impl JsonObjectParser for Object<SetValue> {
                         .unwrap_bool_or_null("")?
                         .map(|bool| SetValue::Value(Value::Bool(bool)))
                         .unwrap_or(SetValue::Value(Value::Null)),
-                    Property::Size | Property::SortOrder | Property::Quota => parser
+                    Property::Size
+                    | Property::SortOrder
+                    | Property::Quota
+                    | Property::OlderThanDays => parser
                         .next_token::<String>()?
                         .unwrap_uint_or_null("")?
                         .map(|uint| SetValue::Value(Value::UnsignedInt(uint)))
                         .unwrap_or(SetValue::Value(Value::Null)),
-                    Property::ParentId | Property::EmailId | Property::IdentityId => parser
+                    Property::ParentId
+                    | Property::EmailId
+                    | Property::IdentityId
+                    | Property::MailboxId => parser
                         .next_token::<MaybeReference<Id, String>>()?
                         .unwrap_string_or_null("")?
                         .map(SetValue::from)
@@ -325,6 +348,7 @@ impl JsonObjectParser for Object<SetValue> {
                     },
                     Property::Aliases
                     | Property::Attachments
+                    | Property::BlockedSenders
                     | Property::Bcc
                     | Property::BodyStructure
                     | Property::BodyValues
@@ -0,0 +1,115 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use utils::map::vec_map::VecMap;
+
+use crate::{
+    error::set::SetError,
+    parser::{json::Parser, Ignore, JsonObjectParser, Token},
+    request::RequestProperty,
+    types::{
+        id::Id,
+        keyword::Keyword,
+        state::{State, StateChange},
+    },
+};
+
+use super::query::{parse_filter, Filter};
+
+#[derive(Debug, Clone)]
+pub struct UpdateFlagsRequest {
+    pub account_id: Id,
+    pub if_in_state: Option<State>,
+    pub filter: Vec<Filter>,
+    pub keywords: VecMap<Keyword, bool>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct UpdateFlagsResponse {
+    #[serde(rename = "accountId")]
+    pub account_id: Id,
+
+    #[serde(rename = "oldState")]
+    pub old_state: State,
+
+    #[serde(rename = "newState")]
+    pub new_state: State,
+
+    #[serde(rename = "updated")]
+    pub updated: Vec<Id>,
+
+    #[serde(rename = "notUpdated")]
+    #[serde(skip_serializing_if = "VecMap::is_empty")]
+    pub not_updated: VecMap<Id, SetError>,
+
+    #[serde(skip)]
+    pub state_change: Option<StateChange>,
+}
+
+impl JsonObjectParser for UpdateFlagsRequest {
+    fn parse(parser: &mut Parser<'_>) -> crate::parser::Result<Self>
+    where
+        Self: Sized,
+    {
+        let mut request = UpdateFlagsRequest {
+            account_id: Id::default(),
+            if_in_state: None,
+            filter: vec![],
+            keywords: VecMap::new(),
+        };
+
+        parser
+            .next_token::<String>()?
+            .assert_jmap(Token::DictStart)?;
+
+        while let Some(key) = parser.next_dict_key::<RequestProperty>()? {
+            match &key.hash[0] {
+                0x0064_4974_6e75_6f63_6361 if !key.is_ref => {
+                    request.account_id = parser.next_token::<Id>()?.unwrap_string("accountId")?;
+                }
+                0x0065_7461_7453_6e49_6669 if !key.is_ref => {
+                    request.if_in_state = parser
+                        .next_token::<State>()?
+                        .unwrap_string_or_null("ifInState")?;
+                }
+                0x7265_746c_6966 if !key.is_ref => match parser.next_token::<Ignore>()? {
+                    Token::DictStart => {
+                        request.filter = parse_filter(parser)?;
+                    }
+                    Token::Null => (),
+                    token => {
+                        return Err(token.error("filter", "object or null"));
+                    }
+                },
+                0x7364_726f_7779_656b if !key.is_ref => {
+                    request.keywords = <VecMap<Keyword, bool>>::parse(parser)?;
+                }
+                _ => {
+                    parser.skip_token(parser.depth_array, parser.depth_dict)?;
+                }
+            }
+        }
+
+        Ok(request)
+    }
+}
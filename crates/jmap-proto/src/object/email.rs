@@ -34,6 +34,7 @@ pub struct GetArguments {
     pub fetch_html_body_values: Option<bool>,
     pub fetch_all_body_values: Option<bool>,
     pub max_body_value_bytes: Option<usize>,
+    pub proxy_remote_images: Option<bool>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -71,6 +72,11 @@ impl RequestPropertyParser for GetArguments {
                     .next_token::<Ignore>()?
                     .unwrap_usize_or_null("maxBodyValueBytes")?;
             }
+            (0x6567_616d_4965_746f_6d65_5279_786f_7270, 0x73) => {
+                self.proxy_remote_images = parser
+                    .next_token::<Ignore>()?
+                    .unwrap_bool_or_null("proxyRemoteImages")?;
+            }
             _ => return Ok(false),
         }
 
@@ -48,6 +48,22 @@ pub enum Capability {
     Blob = 1 << 8,
     #[serde(rename(serialize = "urn:ietf:params:jmap:quota"))]
     Quota = 1 << 9,
+    #[serde(rename(serialize = "urn:ietf:params:jmap:activitylog"))]
+    ActivityLog = 1 << 10,
+    #[serde(rename(serialize = "urn:ietf:params:jmap:labels"))]
+    Labels = 1 << 11,
+    #[serde(rename(serialize = "urn:ietf:params:jmap:blobpatch"))]
+    BlobPatch = 1 << 12,
+    #[serde(rename(serialize = "urn:ietf:params:jmap:tagfiling"))]
+    TagFiling = 1 << 13,
+    #[serde(rename(serialize = "urn:ietf:params:jmap:archive"))]
+    Archive = 1 << 14,
+    #[serde(rename(serialize = "urn:ietf:params:jmap:disposablealias"))]
+    DisposableAlias = 1 << 15,
+    #[serde(rename(serialize = "urn:ietf:params:jmap:annotations"))]
+    Annotations = 1 << 16,
+    #[serde(rename(serialize = "urn:ietf:params:jmap:mailboxusage"))]
+    MailboxUsage = 1 << 17,
 }
 
 impl JsonObjectParser for Capability {
@@ -77,6 +93,14 @@ impl JsonObjectParser for Capability {
                 0x0065_7665_6973 => Ok(Capability::Sieve),
                 0x626f_6c62 => Ok(Capability::Blob),
                 0x0061_746f_7571 => Ok(Capability::Quota),
+                0x0067_6f6c_7974_6976_6974_6361 => Ok(Capability::ActivityLog),
+                0x736c_6562_616c => Ok(Capability::Labels),
+                0x0068_6374_6170_626f_6c62 => Ok(Capability::BlobPatch),
+                0x0067_6e69_6c69_6667_6174 => Ok(Capability::TagFiling),
+                0x0065_7669_6863_7261 => Ok(Capability::Archive),
+                0x0073_6169_6c61_656c_6261_736f_7073_6964 => Ok(Capability::DisposableAlias),
+                0x0073_6e6f_6974_6174_6f6e_6e61 => Ok(Capability::Annotations),
+                0x6567_6173_7578_6f62_6c69_616d => Ok(Capability::MailboxUsage),
                 _ => Err(parser.error_capability()),
             },
             Err(Error::Method(_)) => Err(parser.error_capability()),
@@ -46,6 +46,13 @@ pub enum MethodObject {
     SieveScript,
     Principal,
     Quota,
+    ActivityLog,
+    Label,
+    TagRule,
+    ArchivePolicy,
+    DisposableAlias,
+    Annotation,
+    MailboxUsage,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -61,6 +68,7 @@ pub enum MethodFunction {
     Validate,
     Lookup,
     Upload,
+    UpdateFlags,
     Echo,
 }
 
@@ -113,6 +121,13 @@ impl JsonObjectParser for MethodName {
                 0x0074_7069_7263_5365_7665_6953 => MethodObject::SieveScript,
                 0x006c_6170_6963_6e69_7250 => MethodObject::Principal,
                 0x0061_746f_7551 => MethodObject::Quota,
+                0x0067_6f4c_7974_6976_6974_6341 => MethodObject::ActivityLog,
+                0x006c_6562_614c => MethodObject::Label,
+                0x0065_6c75_5267_6154 => MethodObject::TagRule,
+                0x0079_6369_6c6f_5065_7669_6863_7241 => MethodObject::ArchivePolicy,
+                0x0073_6169_6c41_656c_6261_736f_7073_6944 => MethodObject::DisposableAlias,
+                0x6e6f_6974_6174_6f6e_6e41 => MethodObject::Annotation,
+                0x6567_6173_5578_6f62_6c69_614d => MethodObject::MailboxUsage,
                 0x6572_6f43 => MethodObject::Core,
                 _ => return Err(parser.error_value()),
             },
@@ -128,6 +143,7 @@ impl JsonObjectParser for MethodName {
                 0x6574_6164_696c_6176 => MethodFunction::Validate,
                 0x7075_6b6f_6f6c => MethodFunction::Lookup,
                 0x6461_6f6c_7075 => MethodFunction::Upload,
+                0x0073_6761_6c46_6574_6164_7075 => MethodFunction::UpdateFlags,
                 0x6f68_6365 => MethodFunction::Echo,
                 _ => return Err(parser.error_value()),
             },
@@ -175,6 +191,7 @@ impl MethodName {
             (MethodFunction::Copy, MethodObject::Email) => "Email/copy",
             (MethodFunction::Import, MethodObject::Email) => "Email/import",
             (MethodFunction::Parse, MethodObject::Email) => "Email/parse",
+            (MethodFunction::UpdateFlags, MethodObject::Email) => "Email/updateFlags",
 
             (MethodFunction::Get, MethodObject::SearchSnippet) => "SearchSnippet/get",
 
@@ -207,6 +224,30 @@ impl MethodName {
             (MethodFunction::Query, MethodObject::Quota) => "Quota/query",
             (MethodFunction::QueryChanges, MethodObject::Quota) => "Quota/queryChanges",
 
+            (MethodFunction::Get, MethodObject::ActivityLog) => "ActivityLog/get",
+
+            (MethodFunction::Get, MethodObject::MailboxUsage) => "MailboxUsage/get",
+
+            (MethodFunction::Get, MethodObject::Label) => "Label/get",
+            (MethodFunction::Changes, MethodObject::Label) => "Label/changes",
+            (MethodFunction::Set, MethodObject::Label) => "Label/set",
+
+            (MethodFunction::Get, MethodObject::TagRule) => "TagRule/get",
+            (MethodFunction::Changes, MethodObject::TagRule) => "TagRule/changes",
+            (MethodFunction::Set, MethodObject::TagRule) => "TagRule/set",
+
+            (MethodFunction::Get, MethodObject::ArchivePolicy) => "ArchivePolicy/get",
+            (MethodFunction::Changes, MethodObject::ArchivePolicy) => "ArchivePolicy/changes",
+            (MethodFunction::Set, MethodObject::ArchivePolicy) => "ArchivePolicy/set",
+
+            (MethodFunction::Get, MethodObject::DisposableAlias) => "DisposableAlias/get",
+            (MethodFunction::Changes, MethodObject::DisposableAlias) => "DisposableAlias/changes",
+            (MethodFunction::Set, MethodObject::DisposableAlias) => "DisposableAlias/set",
+
+            (MethodFunction::Get, MethodObject::Annotation) => "Annotation/get",
+            (MethodFunction::Changes, MethodObject::Annotation) => "Annotation/changes",
+            (MethodFunction::Set, MethodObject::Annotation) => "Annotation/set",
+
             (MethodFunction::Get, MethodObject::Blob) => "Blob/get",
             (MethodFunction::Copy, MethodObject::Blob) => "Blob/copy",
             (MethodFunction::Lookup, MethodObject::Blob) => "Blob/lookup",
@@ -234,6 +275,13 @@ impl Display for MethodObject {
             MethodObject::Thread => "Thread",
             MethodObject::Email => "Email",
             MethodObject::Quota => "Quota",
+            MethodObject::ActivityLog => "ActivityLog",
+            MethodObject::Label => "Label",
+            MethodObject::TagRule => "TagRule",
+            MethodObject::ArchivePolicy => "ArchivePolicy",
+            MethodObject::DisposableAlias => "DisposableAlias",
+            MethodObject::Annotation => "Annotation",
+            MethodObject::MailboxUsage => "MailboxUsage",
         })
     }
 }
@@ -36,16 +36,19 @@ use std::{
 use crate::{
     error::method::MethodError,
     method::{
+        activity::GetActivityLogRequest,
         changes::ChangesRequest,
         copy::{self, CopyBlobRequest, CopyRequest},
         get::{self, GetRequest},
         import::ImportEmailRequest,
         lookup::BlobLookupRequest,
+        mailbox_usage::GetMailboxUsageRequest,
         parse::ParseEmailRequest,
         query::{self, QueryRequest},
         query_changes::QueryChangesRequest,
         search_snippet::GetSearchSnippetRequest,
         set::{self, SetRequest},
+        update_flags::UpdateFlagsRequest,
         upload::BlobUploadRequest,
         validate::ValidateSieveScriptRequest,
     },
@@ -84,9 +87,12 @@ pub enum RequestMethod {
     CopyBlob(CopyBlobRequest),
     ImportEmail(ImportEmailRequest),
     ParseEmail(ParseEmailRequest),
+    UpdateFlags(UpdateFlagsRequest),
     QueryChanges(QueryChangesRequest),
     Query(QueryRequest<query::RequestArguments>),
     SearchSnippet(GetSearchSnippetRequest),
+    ActivityLog(GetActivityLogRequest),
+    MailboxUsage(GetMailboxUsageRequest),
     ValidateScript(ValidateSieveScriptRequest),
     LookupBlob(BlobLookupRequest),
     UploadBlob(BlobUploadRequest),
@@ -29,16 +29,19 @@ use crate::{
         request::{RequestError, RequestLimitError},
     },
     method::{
+        activity::GetActivityLogRequest,
         changes::ChangesRequest,
         copy::{CopyBlobRequest, CopyRequest},
         get::GetRequest,
         import::ImportEmailRequest,
         lookup::BlobLookupRequest,
+        mailbox_usage::GetMailboxUsageRequest,
         parse::ParseEmailRequest,
         query::QueryRequest,
         query_changes::QueryChangesRequest,
         search_snippet::GetSearchSnippetRequest,
         set::SetRequest,
+        update_flags::UpdateFlagsRequest,
         upload::BlobUploadRequest,
         validate::ValidateSieveScriptRequest,
     },
@@ -143,12 +146,24 @@ impl Request {
                                 | MethodObject::SieveScript
                                 | MethodObject::Principal
                                 | MethodObject::Quota
+                                | MethodObject::Label
+                                | MethodObject::TagRule
+                                | MethodObject::ArchivePolicy
+                                | MethodObject::DisposableAlias
+                                | MethodObject::Annotation
                                 | MethodObject::Blob,
                             ) => GetRequest::parse(parser).map(RequestMethod::Get),
                             (MethodFunction::Get, MethodObject::SearchSnippet) => {
                                 GetSearchSnippetRequest::parse(parser)
                                     .map(RequestMethod::SearchSnippet)
                             }
+                            (MethodFunction::Get, MethodObject::ActivityLog) => {
+                                GetActivityLogRequest::parse(parser).map(RequestMethod::ActivityLog)
+                            }
+                            (MethodFunction::Get, MethodObject::MailboxUsage) => {
+                                GetMailboxUsageRequest::parse(parser)
+                                    .map(RequestMethod::MailboxUsage)
+                            }
                             (MethodFunction::Query, _) => {
                                 QueryRequest::parse(parser).map(RequestMethod::Query)
                             }
@@ -179,6 +194,9 @@ impl Request {
                             (MethodFunction::Parse, MethodObject::Email) => {
                                 ParseEmailRequest::parse(parser).map(RequestMethod::ParseEmail)
                             }
+                            (MethodFunction::UpdateFlags, MethodObject::Email) => {
+                                UpdateFlagsRequest::parse(parser).map(RequestMethod::UpdateFlags)
+                            }
                             (MethodFunction::Validate, MethodObject::SieveScript) => {
                                 ValidateSieveScriptRequest::parse(parser)
                                     .map(RequestMethod::ValidateScript)
@@ -107,6 +107,10 @@ pub struct WebSocketRequestError {
     status: u16,
     detail: Cow<'static, str>,
 
+    #[serde(rename = "retryAfter")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after: Option<u64>,
+
     #[serde(rename = "requestId")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub request_id: Option<String>,
@@ -207,6 +211,7 @@ impl WebSocketRequestError {
             limit: error.limit,
             status: error.status,
             detail: error.detail,
+            retry_after: error.retry_after,
             request_id,
         }
     }
@@ -29,16 +29,19 @@ use std::collections::HashMap;
 use crate::{
     error::method::MethodError,
     method::{
+        activity::GetActivityLogResponse,
         changes::ChangesResponse,
         copy::{CopyBlobResponse, CopyResponse},
         get::GetResponse,
         import::ImportEmailResponse,
         lookup::BlobLookupResponse,
+        mailbox_usage::GetMailboxUsageResponse,
         parse::ParseEmailResponse,
         query::QueryResponse,
         query_changes::QueryChangesResponse,
         search_snippet::GetSearchSnippetResponse,
         set::SetResponse,
+        update_flags::UpdateFlagsResponse,
         upload::BlobUploadResponse,
         validate::ValidateSieveScriptResponse,
     },
@@ -61,6 +64,9 @@ pub enum ResponseMethod {
     QueryChanges(QueryChangesResponse),
     Query(QueryResponse),
     SearchSnippet(GetSearchSnippetResponse),
+    UpdateFlags(UpdateFlagsResponse),
+    ActivityLog(GetActivityLogResponse),
+    MailboxUsage(GetMailboxUsageResponse),
     ValidateScript(ValidateSieveScriptResponse),
     LookupBlob(BlobLookupResponse),
     UploadBlob(BlobUploadResponse),
@@ -189,6 +195,24 @@ impl From<GetSearchSnippetResponse> for ResponseMethod {
     }
 }
 
+impl From<UpdateFlagsResponse> for ResponseMethod {
+    fn from(update_flags: UpdateFlagsResponse) -> Self {
+        ResponseMethod::UpdateFlags(update_flags)
+    }
+}
+
+impl From<GetActivityLogResponse> for ResponseMethod {
+    fn from(activity_log: GetActivityLogResponse) -> Self {
+        ResponseMethod::ActivityLog(activity_log)
+    }
+}
+
+impl From<GetMailboxUsageResponse> for ResponseMethod {
+    fn from(mailbox_usage: GetMailboxUsageResponse) -> Self {
+        ResponseMethod::MailboxUsage(mailbox_usage)
+    }
+}
+
 impl From<ValidateSieveScriptResponse> for ResponseMethod {
     fn from(validate_script: ValidateSieveScriptResponse) -> Self {
         ResponseMethod::ValidateScript(validate_script)
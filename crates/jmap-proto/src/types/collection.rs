@@ -38,7 +38,12 @@ pub enum Collection {
     SieveScript = 5,
     PushSubscription = 6,
     Principal = 7,
-    None = 8,
+    Label = 8,
+    TagRule = 9,
+    ArchivePolicy = 10,
+    DisposableAlias = 11,
+    Annotation = 12,
+    None = 13,
 }
 
 impl From<u8> for Collection {
@@ -52,6 +57,11 @@ impl From<u8> for Collection {
             5 => Collection::SieveScript,
             6 => Collection::PushSubscription,
             7 => Collection::Principal,
+            8 => Collection::Label,
+            9 => Collection::TagRule,
+            10 => Collection::ArchivePolicy,
+            11 => Collection::DisposableAlias,
+            12 => Collection::Annotation,
             _ => Collection::None,
         }
     }
@@ -68,6 +78,11 @@ impl From<u64> for Collection {
             5 => Collection::SieveScript,
             6 => Collection::PushSubscription,
             7 => Collection::Principal,
+            8 => Collection::Label,
+            9 => Collection::TagRule,
+            10 => Collection::ArchivePolicy,
+            11 => Collection::DisposableAlias,
+            12 => Collection::Annotation,
             _ => Collection::None,
         }
     }
@@ -97,6 +112,11 @@ impl TryFrom<Collection> for DataType {
             Collection::EmailSubmission => Ok(DataType::EmailSubmission),
             Collection::SieveScript => Ok(DataType::SieveScript),
             Collection::PushSubscription => Ok(DataType::PushSubscription),
+            Collection::Label => Ok(DataType::Label),
+            Collection::TagRule => Ok(DataType::TagRule),
+            Collection::ArchivePolicy => Ok(DataType::ArchivePolicy),
+            Collection::DisposableAlias => Ok(DataType::DisposableAlias),
+            Collection::Annotation => Ok(DataType::Annotation),
             _ => Err(()),
         }
     }
@@ -113,6 +133,11 @@ impl Display for Collection {
             Collection::EmailSubmission => write!(f, "emailSubmission"),
             Collection::SieveScript => write!(f, "sieveScript"),
             Collection::Principal => write!(f, "principal"),
+            Collection::Label => write!(f, "label"),
+            Collection::TagRule => write!(f, "tagRule"),
+            Collection::ArchivePolicy => write!(f, "archivePolicy"),
+            Collection::DisposableAlias => write!(f, "disposableAlias"),
+            Collection::Annotation => write!(f, "annotation"),
             Collection::None => write!(f, ""),
         }
     }
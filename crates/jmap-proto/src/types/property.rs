@@ -136,6 +136,23 @@ pub enum Property {
     WarnLimit,
     SoftLimit,
     Scope,
+    Color,
+    Keyword,
+    Recovery,
+    Tag,
+    MailboxId,
+    MailboxName,
+    LegalHold,
+    EnvelopeSender,
+    OlderThanDays,
+    StepUp,
+    BlockedSenders,
+    AcceptedCount,
+    BlockedCount,
+    ExpiredCount,
+    Text,
+    RemindAt,
+    DeliveryDedup,
     Digest(DigestProperty),
     Data(DataProperty),
     _T(String),
@@ -350,6 +367,7 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
     Some(match first_char {
         b'a' => match hash {
             0x6c63 => Property::Acl,
+            0x746e_756f_4364_6574_7065_6363 => Property::AcceptedCount,
             0x7365_7361_696c => Property::Aliases,
             0x7374_6e65_6d68_6361_7474 => Property::Attachments,
             _ => return None,
@@ -357,6 +375,8 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
         b'b' => match hash {
             0x6363 => Property::Bcc,
             0x0064_4962_6f6c => Property::BlobId,
+            0x0073_7265_646e_6553_6465_6b63_6f6c => Property::BlockedSenders,
+            0x0074_6e75_6f43_6465_6b63_6f6c => Property::BlockedCount,
             0x6572_7574_6375_7274_5379_646f => Property::BodyStructure,
             0x0073_6575_6c61_5679_646f => Property::BodyValues,
             _ => return None,
@@ -366,6 +386,7 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x63 => Property::Cc,
             0x7465_7372_6168 => Property::Charset,
             0x6469 => Property::Cid,
+            0x726f_6c6f => Property::Color,
             _ => return None,
         },
         b'd' => match hash {
@@ -382,7 +403,9 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x6449_6c69_616d => Property::EmailId,
             0x0073_6449_6c69_616d => Property::EmailIds,
             0x0065_706f_6c65_766e => Property::Envelope,
+            0x0072_6564_6e65_5365_706f_6c65_766e => Property::EnvelopeSender,
             0x7365_7269_7078 => Property::Expires,
+            0x0074_6e75_6f43_6465_7269_7078 => Property::ExpiredCount,
             _ => return None,
         },
         b'f' => match hash {
@@ -408,6 +431,7 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
         },
         b'k' => match hash {
             0x0073_7965 => Property::Keys,
+            0x6472_6f77_7965 => Property::Keyword,
             0x0073_6472_6f77_7965 => Property::Keywords,
             _ => return None,
         },
@@ -417,7 +441,9 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             _ => return None,
         },
         b'm' => match hash {
+            0x6449_786f_626c_6961 => Property::MailboxId,
             0x0073_6449_786f_626c_6961 => Property::MailboxIds,
+            0x656d_614e_786f_626c_6961 => Property::MailboxName,
             0x6574_656c_6544_7961 => Property::MayDelete,
             0x0073_6449_626f_6c42_6e64 => Property::MdnBlobIds,
             0x7372_6562_6d65 => Property::Members,
@@ -429,6 +455,10 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             0x0065_6d61 => Property::Name,
             _ => return None,
         },
+        b'o' => match hash {
+            0x7379_6144_6e61_6854_7265_646c => Property::OlderThanDays,
+            _ => return None,
+        },
         b'p' => match hash {
             0x0064_4974_6e65_7261 => Property::ParentId,
             0x0064_4974_7261 => Property::PartId,
@@ -443,6 +473,7 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
         b'r' => match hash {
             0x0074_4164_6576_6965_6365 => Property::ReceivedAt,
             0x0073_6563_6e65_7265_6665 => Property::References,
+            0x0074_4164_6e69_6d65 => Property::RemindAt,
             0x6f54_796c_7065 => Property::ReplyTo,
             0x0065_6c6f => Property::Role,
             _ => return None,
@@ -459,6 +490,8 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             _ => return None,
         },
         b't' => match hash {
+            0x6761 => Property::Tag,
+            0x0074_7865 => Property::Text,
             0x0079_646f_4274_7865 => Property::TextBody,
             0x6572_7574_616e_6769_5374_7865 => Property::TextSignature,
             0x0064_4964_6165_7268 => Property::ThreadId,
@@ -900,6 +933,23 @@ impl Display for Property {
             Property::Scope => write!(f, "scope"),
             Property::WarnLimit => write!(f, "warnLimit"),
             Property::SoftLimit => write!(f, "softLimit"),
+            Property::Color => write!(f, "color"),
+            Property::Keyword => write!(f, "keyword"),
+            Property::Recovery => write!(f, "recovery"),
+            Property::Tag => write!(f, "tag"),
+            Property::MailboxId => write!(f, "mailboxId"),
+            Property::MailboxName => write!(f, "mailboxName"),
+            Property::LegalHold => write!(f, "legalHold"),
+            Property::EnvelopeSender => write!(f, "envelopeSender"),
+            Property::OlderThanDays => write!(f, "olderThanDays"),
+            Property::StepUp => write!(f, "stepUp"),
+            Property::BlockedSenders => write!(f, "blockedSenders"),
+            Property::AcceptedCount => write!(f, "acceptedCount"),
+            Property::BlockedCount => write!(f, "blockedCount"),
+            Property::ExpiredCount => write!(f, "expiredCount"),
+            Property::Text => write!(f, "text"),
+            Property::RemindAt => write!(f, "remindAt"),
+            Property::DeliveryDedup => write!(f, "deliveryDedup"),
             Property::_T(s) => write!(f, "{s}"),
         }
     }
@@ -1080,6 +1130,23 @@ impl From<&Property> for u8 {
             Property::WarnLimit => 101,
             Property::SoftLimit => 102,
             Property::Scope => 103,
+            Property::Color => 104,
+            Property::Keyword => 105,
+            Property::Recovery => 106,
+            Property::Tag => 107,
+            Property::MailboxId => 108,
+            Property::MailboxName => 109,
+            Property::LegalHold => 110,
+            Property::EnvelopeSender => 111,
+            Property::OlderThanDays => 112,
+            Property::StepUp => 113,
+            Property::BlockedSenders => 114,
+            Property::AcceptedCount => 115,
+            Property::BlockedCount => 116,
+            Property::ExpiredCount => 117,
+            Property::Text => 118,
+            Property::RemindAt => 119,
+            Property::DeliveryDedup => 120,
             Property::Digest(_) | Property::Data(_) => unreachable!("invalid property"),
         }
     }
@@ -1222,6 +1289,23 @@ impl SerializeInto for Property {
             Property::WarnLimit => 101,
             Property::SoftLimit => 102,
             Property::Scope => 103,
+            Property::Color => 104,
+            Property::Keyword => 105,
+            Property::Recovery => 106,
+            Property::Tag => 107,
+            Property::MailboxId => 108,
+            Property::MailboxName => 109,
+            Property::LegalHold => 110,
+            Property::EnvelopeSender => 111,
+            Property::OlderThanDays => 112,
+            Property::StepUp => 113,
+            Property::BlockedSenders => 114,
+            Property::AcceptedCount => 115,
+            Property::BlockedCount => 116,
+            Property::ExpiredCount => 117,
+            Property::Text => 118,
+            Property::RemindAt => 119,
+            Property::DeliveryDedup => 120,
             Property::Digest(_) | Property::Data(_) => {
                 unreachable!("Property::Digest and Property::Data are not serializable")
             }
@@ -1340,6 +1424,23 @@ impl DeserializeFrom for Property {
             101 => Some(Property::WarnLimit),
             102 => Some(Property::SoftLimit),
             103 => Some(Property::Scope),
+            104 => Some(Property::Color),
+            105 => Some(Property::Keyword),
+            106 => Some(Property::Recovery),
+            107 => Some(Property::Tag),
+            108 => Some(Property::MailboxId),
+            109 => Some(Property::MailboxName),
+            110 => Some(Property::LegalHold),
+            111 => Some(Property::EnvelopeSender),
+            112 => Some(Property::OlderThanDays),
+            113 => Some(Property::StepUp),
+            114 => Some(Property::BlockedSenders),
+            115 => Some(Property::AcceptedCount),
+            116 => Some(Property::BlockedCount),
+            117 => Some(Property::ExpiredCount),
+            118 => Some(Property::Text),
+            119 => Some(Property::RemindAt),
+            120 => Some(Property::DeliveryDedup),
             _ => None,
         }
     }
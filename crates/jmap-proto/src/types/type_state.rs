@@ -58,7 +58,17 @@ pub enum DataType {
     Quota = 11,
     #[serde(rename = "SieveScript")]
     SieveScript = 12,
-    None = 13,
+    #[serde(rename = "Label")]
+    Label = 13,
+    #[serde(rename = "TagRule")]
+    TagRule = 14,
+    #[serde(rename = "ArchivePolicy")]
+    ArchivePolicy = 15,
+    #[serde(rename = "DisposableAlias")]
+    DisposableAlias = 16,
+    #[serde(rename = "Annotation")]
+    Annotation = 17,
+    None = 18,
 }
 
 impl BitmapItem for DataType {
@@ -87,6 +97,11 @@ impl From<u64> for DataType {
             10 => DataType::Mdn,
             11 => DataType::Quota,
             12 => DataType::SieveScript,
+            13 => DataType::Label,
+            14 => DataType::TagRule,
+            15 => DataType::ArchivePolicy,
+            16 => DataType::DisposableAlias,
+            17 => DataType::Annotation,
             _ => {
                 debug_assert!(false, "Invalid type_state value: {}", value);
                 DataType::None
@@ -132,6 +147,11 @@ impl JsonObjectParser for DataType {
             0x004e_444d => Ok(DataType::Mdn),
             0x0061_746f_7551 => Ok(DataType::Quota),
             0x0074_7069_7263_5365_7665_6953 => Ok(DataType::SieveScript),
+            0x006c_6562_614c => Ok(DataType::Label),
+            0x0065_6c75_5267_6154 => Ok(DataType::TagRule),
+            0x0079_6369_6c6f_5065_7669_6863_7241 => Ok(DataType::ArchivePolicy),
+            0x0073_6169_6c41_656c_6261_736f_7073_6944 => Ok(DataType::DisposableAlias),
+            0x6e6f_6974_6174_6f6e_6e41 => Ok(DataType::Annotation),
             _ => Err(parser.error_value()),
         }
     }
@@ -167,6 +187,11 @@ impl TryFrom<&str> for DataType {
             0x004e_444d => Ok(DataType::Mdn),
             0x0061_746f_7551 => Ok(DataType::Quota),
             0x0074_7069_7263_5365_7665_6953 => Ok(DataType::SieveScript),
+            0x006c_6562_614c => Ok(DataType::Label),
+            0x0065_6c75_5267_6154 => Ok(DataType::TagRule),
+            0x0079_6369_6c6f_5065_7669_6863_7241 => Ok(DataType::ArchivePolicy),
+            0x0073_6169_6c41_656c_6261_736f_7073_6944 => Ok(DataType::DisposableAlias),
+            0x6e6f_6974_6174_6f6e_6e41 => Ok(DataType::Annotation),
             _ => Err(()),
         }
     }
@@ -188,6 +213,11 @@ impl DataType {
             DataType::Mdn => "MDN",
             DataType::Quota => "Quota",
             DataType::SieveScript => "SieveScript",
+            DataType::Label => "Label",
+            DataType::TagRule => "TagRule",
+            DataType::ArchivePolicy => "ArchivePolicy",
+            DataType::DisposableAlias => "DisposableAlias",
+            DataType::Annotation => "Annotation",
             DataType::None => "",
         }
     }
@@ -221,6 +251,11 @@ impl DeserializeFrom for DataType {
             10 => Some(DataType::Mdn),
             11 => Some(DataType::Quota),
             12 => Some(DataType::SieveScript),
+            13 => Some(DataType::Label),
+            14 => Some(DataType::TagRule),
+            15 => Some(DataType::ArchivePolicy),
+            16 => Some(DataType::DisposableAlias),
+            17 => Some(DataType::Annotation),
             _ => None,
         }
     }
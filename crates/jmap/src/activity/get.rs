@@ -0,0 +1,70 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::method::MethodError,
+    method::activity::{GetActivityLogRequest, GetActivityLogResponse},
+};
+
+use crate::JMAP;
+
+impl JMAP {
+    pub async fn activity_log_get(
+        &self,
+        request: GetActivityLogRequest,
+    ) -> Result<GetActivityLogResponse, MethodError> {
+        if !self.config.activity_log_enable {
+            return Err(MethodError::UnknownMethod(
+                "ActivityLog/get is not enabled on this server.".to_string(),
+            ));
+        }
+
+        let account_id = request.account_id.document_id();
+        let max_results = self.config.activity_log_max_results;
+        let limit = request
+            .limit
+            .filter(|limit| *limit > 0)
+            .map_or(max_results, |limit| std::cmp::min(limit, max_results));
+        let since = request
+            .since
+            .map(|since| since.timestamp().max(0) as u64)
+            .unwrap_or(0);
+
+        Ok(GetActivityLogResponse {
+            account_id: request.account_id,
+            list: self
+                .activity_log_query(account_id, since, limit)
+                .await
+                .map_err(|err| {
+                    tracing::error!(
+                        event = "error",
+                        context = "activity_log_get",
+                        account_id = account_id,
+                        error = ?err,
+                        "Failed to retrieve activity log."
+                    );
+                    MethodError::ServerPartialFail
+                })?,
+        })
+    }
+}
@@ -0,0 +1,329 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{net::IpAddr, time::SystemTime};
+
+use jmap_proto::{
+    method::{
+        activity::{ActivityEvent, ActivityLogEntry},
+        set::{RequestArguments, SetResponse},
+    },
+    request::RequestMethod,
+    types::{date::UTCDate, id::Id, property::Property},
+};
+use store::{
+    ahash::AHashSet,
+    write::{key::DeserializeBigEndian, now, BatchBuilder, ValueClass},
+    Deserialize, IterateParams, ValueKey, U64_LEN,
+};
+use utils::config::ServerProtocol;
+
+use crate::{Bincode, JMAP};
+
+pub mod get;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Activity {
+    pub event: ActivityEvent,
+    pub protocol: String,
+    pub remote_ip: Option<IpAddr>,
+    pub details: Option<String>,
+}
+
+/// Tracks the changes requested by a `/set` call that should be recorded in
+/// the activity log once the call completes.
+pub struct SetActivity {
+    account_id: u32,
+    object: SetActivityObject,
+    created: AHashSet<String>,
+    updated: AHashSet<Id>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SetActivityObject {
+    Email,
+    Mailbox,
+    SieveScript,
+}
+
+impl JMAP {
+    pub async fn log_activity(
+        &self,
+        account_id: u32,
+        event: ActivityEvent,
+        protocol: ServerProtocol,
+        remote_ip: Option<IpAddr>,
+        details: Option<String>,
+    ) {
+        if !self.config.activity_log_enable {
+            return;
+        }
+
+        // Activity ids are prefixed with the timestamp in seconds so entries
+        // can be retrieved and expired by range, followed by the milliseconds
+        // and the lower bits of a snowflake id to keep them unique and ordered.
+        let elapsed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let id = (elapsed.as_secs() << 32)
+            | ((elapsed.subsec_millis() as u64) << 22)
+            | (self
+                .snowflake_id
+                .generate()
+                .unwrap_or_else(rand::random::<u64>)
+                & ((1 << 22) - 1));
+        let mut batch = BatchBuilder::new();
+        batch.with_account_id(account_id).set(
+            ValueClass::Activity(id),
+            Bincode::new(Activity {
+                event,
+                protocol: protocol.to_string(),
+                remote_ip,
+                details,
+            }),
+        );
+
+        if let Err(err) = self.store.write(batch.build()).await {
+            tracing::error!(
+                context = "activity_log",
+                event = "error",
+                account_id = account_id,
+                reason = ?err,
+                "Failed to write activity log entry."
+            );
+        }
+    }
+
+    pub async fn activity_log_query(
+        &self,
+        account_id: u32,
+        since: u64,
+        limit: usize,
+    ) -> store::Result<Vec<ActivityLogEntry>> {
+        let from_key = ValueKey {
+            account_id,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Activity(since << 32),
+        };
+        let to_key = ValueKey {
+            account_id,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Activity(u64::MAX),
+        };
+
+        let mut entries = Vec::new();
+        self.store
+            .iterate(
+                IterateParams::new(from_key, to_key).descending(),
+                |key, value| {
+                    let id = key.deserialize_be_u64(key.len() - U64_LEN)?;
+                    let activity = Bincode::<Activity>::deserialize(value)?.inner;
+                    entries.push(ActivityLogEntry {
+                        id: id.into(),
+                        timestamp: UTCDate::from_timestamp((id >> 32) as i64),
+                        event: activity.event,
+                        protocol: activity.protocol,
+                        remote_ip: activity.remote_ip,
+                        details: activity.details,
+                    });
+
+                    Ok(limit == 0 || entries.len() < limit)
+                },
+            )
+            .await?;
+
+        Ok(entries)
+    }
+
+    pub async fn purge_activity_log(&self) {
+        let cutoff = now().saturating_sub(self.config.activity_log_retention.as_secs());
+        let from_key = ValueKey {
+            account_id: 0,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Activity(0),
+        };
+        let to_key = ValueKey {
+            account_id: u32::MAX,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Activity(u64::MAX),
+        };
+
+        let mut batch = BatchBuilder::new();
+        let mut last_account_id = u32::MAX;
+        let result = self
+            .store
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending().no_values(),
+                |key, _| {
+                    let id = key.deserialize_be_u64(key.len() - U64_LEN)?;
+                    if (id >> 32) < cutoff {
+                        let account_id = key.deserialize_be_u32(1)?;
+                        if account_id != last_account_id {
+                            last_account_id = account_id;
+                            batch.with_account_id(account_id);
+                        }
+                        batch.clear(ValueClass::Activity(id));
+                    }
+
+                    Ok(true)
+                },
+            )
+            .await;
+
+        if let Err(err) = result {
+            tracing::error!(
+                context = "activity_log",
+                event = "error",
+                reason = ?err,
+                "Failed to iterate over activity log entries."
+            );
+        } else if !batch.is_empty() {
+            if let Err(err) = self.store.write(batch.build()).await {
+                tracing::error!(
+                    context = "activity_log",
+                    event = "error",
+                    reason = ?err,
+                    "Failed to purge activity log entries."
+                );
+            }
+        }
+    }
+}
+
+impl SetActivity {
+    pub fn new(method: &RequestMethod) -> Option<Self> {
+        let request = if let RequestMethod::Set(request) = method {
+            request
+        } else {
+            return None;
+        };
+        let (object, property) = match &request.arguments {
+            RequestArguments::Email => (SetActivityObject::Email, Some(Property::MailboxIds)),
+            RequestArguments::Mailbox(_) => (SetActivityObject::Mailbox, Some(Property::Acl)),
+            RequestArguments::SieveScript(_) => (SetActivityObject::SieveScript, None),
+            _ => return None,
+        };
+        let mut activity = SetActivity {
+            account_id: request.account_id.document_id(),
+            object,
+            created: AHashSet::new(),
+            updated: AHashSet::new(),
+        };
+
+        // Email moves are only tracked on updates, sharing changes can also be
+        // requested on mailbox creation.
+        if let Some(property) = property {
+            if object == SetActivityObject::Mailbox {
+                if let Some(create) = &request.create {
+                    activity.created.extend(
+                        create
+                            .iter()
+                            .filter(|(_, value)| value.properties.contains_key(&property))
+                            .map(|(id, _)| id.clone()),
+                    );
+                }
+            }
+            if let Some(update) = &request.update {
+                activity.updated.extend(
+                    update
+                        .iter()
+                        .filter(|(_, value)| value.properties.contains_key(&property))
+                        .map(|(id, _)| *id),
+                );
+            }
+        }
+
+        Some(activity)
+    }
+
+    pub async fn log(
+        self,
+        jmap: &JMAP,
+        response: &SetResponse,
+        protocol: ServerProtocol,
+        remote_ip: IpAddr,
+    ) {
+        let mut events = Vec::with_capacity(2);
+        match self.object {
+            SetActivityObject::Email => {
+                let moved = response
+                    .updated
+                    .keys()
+                    .filter(|id| self.updated.contains(*id))
+                    .count();
+                if moved > 0 {
+                    events.push((ActivityEvent::EmailMove, format!("{moved} email(s) moved")));
+                }
+                if !response.destroyed.is_empty() {
+                    events.push((
+                        ActivityEvent::EmailDelete,
+                        format!("{} email(s) deleted", response.destroyed.len()),
+                    ));
+                }
+            }
+            SetActivityObject::Mailbox => {
+                let changed = response
+                    .created
+                    .keys()
+                    .filter(|id| self.created.contains(*id))
+                    .count()
+                    + response
+                        .updated
+                        .keys()
+                        .filter(|id| self.updated.contains(*id))
+                        .count();
+                if changed > 0 {
+                    events.push((
+                        ActivityEvent::SharingChange,
+                        format!("{changed} mailbox ACL(s) changed"),
+                    ));
+                }
+            }
+            SetActivityObject::SieveScript => {
+                let changed =
+                    response.created.len() + response.updated.len() + response.destroyed.len();
+                if changed > 0 {
+                    events.push((
+                        ActivityEvent::SieveChange,
+                        format!("{changed} script(s) changed"),
+                    ));
+                }
+            }
+        }
+
+        for (event, details) in events {
+            jmap.log_activity(
+                self.account_id,
+                event,
+                protocol,
+                remote_ip.into(),
+                details.into(),
+            )
+            .await;
+        }
+    }
+}
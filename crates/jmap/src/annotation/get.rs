@@ -0,0 +1,118 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::method::MethodError,
+    method::get::{GetRequest, GetResponse, RequestArguments},
+    object::Object,
+    types::{collection::Collection, id::Id, property::Property, value::Value},
+};
+
+use crate::JMAP;
+
+impl JMAP {
+    pub async fn annotation_get(
+        &self,
+        mut request: GetRequest<RequestArguments>,
+    ) -> Result<GetResponse, MethodError> {
+        let ids = request.unwrap_ids(self.config.get_max_objects)?;
+        let properties = request.unwrap_properties(&[
+            Property::Id,
+            Property::EmailId,
+            Property::Text,
+            Property::Color,
+            Property::RemindAt,
+        ]);
+        let account_id = request.account_id.document_id();
+        let annotation_ids = self
+            .get_document_ids(account_id, Collection::Annotation)
+            .await?
+            .unwrap_or_default();
+        let ids = if let Some(ids) = ids {
+            ids
+        } else {
+            annotation_ids
+                .iter()
+                .take(self.config.get_max_objects)
+                .map(Into::into)
+                .collect::<Vec<_>>()
+        };
+        let mut response = GetResponse {
+            account_id: request.account_id.into(),
+            state: self
+                .get_state(account_id, Collection::Annotation)
+                .await?
+                .into(),
+            list: Vec::with_capacity(ids.len()),
+            not_found: vec![],
+        };
+
+        for id in ids {
+            // Obtain the annotation object
+            let document_id = id.document_id();
+            if !annotation_ids.contains(document_id) {
+                response.not_found.push(id.into());
+                continue;
+            }
+            let mut annotation =
+                if let Some(annotation) = self.annotation_fetch(account_id, document_id).await? {
+                    annotation
+                } else {
+                    response.not_found.push(id.into());
+                    continue;
+                };
+            let mut result = Object::with_capacity(properties.len());
+            for property in &properties {
+                match property {
+                    Property::Id => {
+                        result.append(Property::Id, Value::Id(id));
+                    }
+                    Property::EmailId => {
+                        result.append(
+                            Property::EmailId,
+                            if let Some(thread_id) = self
+                                .get_property::<u32>(
+                                    account_id,
+                                    Collection::Email,
+                                    document_id,
+                                    Property::ThreadId,
+                                )
+                                .await?
+                            {
+                                Value::Id(Id::from_parts(thread_id, document_id))
+                            } else {
+                                Value::Null
+                            },
+                        );
+                    }
+                    property => {
+                        result.append(property.clone(), annotation.remove(property));
+                    }
+                }
+            }
+            response.list.push(result);
+        }
+
+        Ok(response)
+    }
+}
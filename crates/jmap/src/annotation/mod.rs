@@ -0,0 +1,166 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod get;
+pub mod set;
+
+use jmap_proto::{
+    error::{
+        method::MethodError,
+        set::{SetError, SetErrorType},
+    },
+    object::Object,
+    types::{
+        collection::Collection, property::Property, state::StateChange, type_state::DataType,
+        value::Value,
+    },
+};
+use store::write::{log::ChangeLogBuilder, BatchBuilder, F_CLEAR, F_VALUE};
+
+use crate::{label::set::is_valid_color, JMAP};
+
+impl JMAP {
+    pub async fn annotation_fetch(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> Result<Option<Object<Value>>, MethodError> {
+        self.get_property::<Object<Value>>(
+            account_id,
+            Collection::Annotation,
+            document_id,
+            Property::Value,
+        )
+        .await
+    }
+
+    // Annotations share the document id of the message they are attached to
+    pub async fn annotation_store(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        annotation: Object<Value>,
+    ) -> Result<Result<(), SetError>, MethodError> {
+        if !self
+            .get_document_ids(account_id, Collection::Email)
+            .await?
+            .map_or(false, |ids| ids.contains(document_id))
+        {
+            return Ok(Err(SetError::not_found()));
+        }
+        let mut validated = Object::with_capacity(annotation.properties.len());
+        for (property, value) in annotation.properties {
+            match self.validate_annotation_value(&property, value) {
+                Ok(Value::Null) => (),
+                Ok(value) => {
+                    validated.set(property, value);
+                }
+                Err(err) => {
+                    return Ok(Err(err));
+                }
+            }
+        }
+        let annotation = validated;
+
+        let annotation_ids = self
+            .get_document_ids(account_id, Collection::Annotation)
+            .await?
+            .unwrap_or_default();
+        let mut batch = BatchBuilder::new();
+        let mut changes = ChangeLogBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Annotation);
+
+        if annotation.properties.is_empty() {
+            // Remove the annotation once all its entries are cleared
+            if !annotation_ids.contains(document_id) {
+                return Ok(Ok(()));
+            }
+            batch
+                .delete_document(document_id)
+                .value(Property::Value, (), F_VALUE | F_CLEAR);
+            changes.log_delete(Collection::Annotation, document_id);
+        } else if annotation_ids.contains(document_id) {
+            batch
+                .update_document(document_id)
+                .value(Property::Value, annotation, F_VALUE);
+            changes.log_update(Collection::Annotation, document_id);
+        } else if annotation_ids.len() as usize >= self.config.annotation_max_annotations {
+            return Ok(Err(SetError::new(SetErrorType::OverQuota)
+                .with_description(
+                    "There are too many annotations, please delete some before adding a new one.",
+                )));
+        } else {
+            batch
+                .create_document(document_id)
+                .value(Property::Value, annotation, F_VALUE);
+            changes.log_insert(Collection::Annotation, document_id);
+        }
+        self.write_batch(batch).await?;
+
+        let change_id = self.commit_changes(account_id, changes).await?;
+        self.broadcast_state_change(
+            StateChange::new(account_id).with_change(DataType::Annotation, change_id),
+        )
+        .await;
+
+        Ok(Ok(()))
+    }
+
+    pub fn validate_annotation_value(
+        &self,
+        property: &Property,
+        value: Value,
+    ) -> Result<Value, SetError> {
+        Ok(match (property, value) {
+            (Property::Text, Value::Text(value))
+                if value.chars().count() <= self.config.annotation_max_length =>
+            {
+                if !value.is_empty() {
+                    Value::Text(value)
+                } else {
+                    Value::Null
+                }
+            }
+            (Property::Text, Value::Text(_)) => {
+                return Err(SetError::new(SetErrorType::TooLarge)
+                    .with_property(Property::Text)
+                    .with_description(format!(
+                        "Annotation text exceeds the maximum length of {} characters.",
+                        self.config.annotation_max_length
+                    )));
+            }
+            (Property::Color, Value::Text(value)) if is_valid_color(&value) => {
+                Value::Text(value.to_ascii_lowercase())
+            }
+            (Property::RemindAt, Value::Date(value)) => Value::Date(value),
+            (Property::Text | Property::Color | Property::RemindAt, Value::Null) => Value::Null,
+            (property, _) => {
+                return Err(SetError::invalid_properties()
+                    .with_property(property.clone())
+                    .with_description("Field could not be set."));
+            }
+        })
+    }
+}
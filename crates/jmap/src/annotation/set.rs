@@ -0,0 +1,257 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::{
+        method::MethodError,
+        set::{SetError, SetErrorType},
+    },
+    method::set::{RequestArguments, SetRequest, SetResponse},
+    object::Object,
+    response::references::EvalObjectReferences,
+    types::{
+        collection::Collection,
+        property::Property,
+        state::StateChange,
+        type_state::DataType,
+        value::{MaybePatchValue, Value},
+    },
+};
+use store::write::{log::ChangeLogBuilder, BatchBuilder, F_CLEAR, F_VALUE};
+
+use crate::JMAP;
+
+impl JMAP {
+    pub async fn annotation_set(
+        &self,
+        mut request: SetRequest<RequestArguments>,
+    ) -> Result<SetResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let email_ids = self
+            .get_document_ids(account_id, Collection::Email)
+            .await?
+            .unwrap_or_default();
+        let mut annotation_ids = self
+            .get_document_ids(account_id, Collection::Annotation)
+            .await?
+            .unwrap_or_default();
+        let mut response = self
+            .prepare_set_response(&request, Collection::Annotation)
+            .await?;
+        let will_destroy = request.unwrap_destroy();
+
+        // Process creates
+        let mut changes = ChangeLogBuilder::new();
+        'create: for (id, object) in request.unwrap_create() {
+            let mut annotation = Object::with_capacity(object.properties.len());
+            let mut email_id = None;
+            for (property, value) in object.properties {
+                match response.eval_object_references(value) {
+                    Ok(MaybePatchValue::Value(Value::Id(value)))
+                        if property == Property::EmailId =>
+                    {
+                        email_id = value.into();
+                    }
+                    Ok(MaybePatchValue::Value(value)) => {
+                        match self.validate_annotation_value(&property, value) {
+                            Ok(Value::Null) => (),
+                            Ok(value) => {
+                                annotation.set(property, value);
+                            }
+                            Err(err) => {
+                                response.not_created.append(id, err);
+                                continue 'create;
+                            }
+                        }
+                    }
+                    Ok(_) => {
+                        response.not_created.append(
+                            id,
+                            SetError::invalid_properties()
+                                .with_property(property)
+                                .with_description("Field could not be set."),
+                        );
+                        continue 'create;
+                    }
+                    Err(err) => {
+                        response.not_created.append(id, err);
+                        continue 'create;
+                    }
+                }
+            }
+
+            // Validate message
+            let document_id = match email_id {
+                Some(email_id) if email_ids.contains(email_id.document_id()) => {
+                    email_id.document_id()
+                }
+                Some(_) => {
+                    response.not_created.append(
+                        id,
+                        SetError::invalid_properties()
+                            .with_property(Property::EmailId)
+                            .with_description("Message does not exist."),
+                    );
+                    continue 'create;
+                }
+                None => {
+                    response.not_created.append(
+                        id,
+                        SetError::invalid_properties()
+                            .with_property(Property::EmailId)
+                            .with_description("Missing emailId."),
+                    );
+                    continue 'create;
+                }
+            };
+            if annotation_ids.contains(document_id) {
+                response.not_created.append(
+                    id,
+                    SetError::already_exists()
+                        .with_existing_id(document_id.into())
+                        .with_description("This message already has an annotation."),
+                );
+                continue 'create;
+            } else if annotation_ids.len() as usize >= self.config.annotation_max_annotations {
+                response.not_created.append(
+                    id,
+                    SetError::new(SetErrorType::OverQuota).with_description(
+                        "There are too many annotations, please delete some before adding a new one.",
+                    ),
+                );
+                continue 'create;
+            }
+
+            // Insert record
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Annotation)
+                .create_document(document_id)
+                .value(Property::Value, annotation, F_VALUE);
+            self.write_batch(batch).await?;
+            changes.log_insert(Collection::Annotation, document_id);
+            annotation_ids.insert(document_id);
+            response.created.insert(
+                id,
+                Object::with_capacity(1).with_property(Property::Id, Value::Id(document_id.into())),
+            );
+        }
+
+        // Process updates
+        'update: for (id, object) in request.unwrap_update() {
+            // Make sure id won't be destroyed
+            if will_destroy.contains(&id) {
+                response.not_updated.append(id, SetError::will_destroy());
+                continue 'update;
+            }
+
+            // Obtain annotation
+            let document_id = id.document_id();
+            let mut annotation = if let Some(annotation) = self
+                .annotation_fetch(account_id, document_id)
+                .await?
+                .filter(|_| annotation_ids.contains(document_id))
+            {
+                annotation
+            } else {
+                response.not_updated.append(id, SetError::not_found());
+                continue 'update;
+            };
+
+            for (property, value) in object.properties {
+                match response.eval_object_references(value) {
+                    Ok(MaybePatchValue::Value(Value::Id(value)))
+                        if property == Property::EmailId && value.document_id() == document_id => {}
+                    Ok(MaybePatchValue::Value(value)) if property != Property::EmailId => {
+                        match self.validate_annotation_value(&property, value) {
+                            Ok(Value::Null) => {
+                                annotation.remove(&property);
+                            }
+                            Ok(value) => {
+                                annotation.set(property, value);
+                            }
+                            Err(err) => {
+                                response.not_updated.append(id, err);
+                                continue 'update;
+                            }
+                        }
+                    }
+                    Ok(_) => {
+                        response.not_updated.append(
+                            id,
+                            SetError::invalid_properties()
+                                .with_property(property)
+                                .with_description("Field could not be set."),
+                        );
+                        continue 'update;
+                    }
+                    Err(err) => {
+                        response.not_updated.append(id, err);
+                        continue 'update;
+                    }
+                }
+            }
+
+            // Update record
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Annotation)
+                .update_document(document_id)
+                .value(Property::Value, annotation, F_VALUE);
+            self.write_batch(batch).await?;
+            changes.log_update(Collection::Annotation, document_id);
+            response.updated.append(id, None);
+        }
+
+        // Process deletions
+        for id in will_destroy {
+            let document_id = id.document_id();
+            if annotation_ids.remove(document_id) {
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(Collection::Annotation)
+                    .delete_document(document_id)
+                    .value(Property::Value, (), F_VALUE | F_CLEAR);
+                self.write_batch(batch).await?;
+                changes.log_delete(Collection::Annotation, document_id);
+                response.destroyed.push(id);
+            } else {
+                response.not_destroyed.append(id, SetError::not_found());
+            }
+        }
+
+        // Write changes
+        if !changes.is_empty() {
+            let change_id = self.commit_changes(account_id, changes).await?;
+            response.new_state = Some(change_id.into());
+            response.state_change = StateChange::new(account_id)
+                .with_change(DataType::Annotation, change_id)
+                .into();
+        }
+
+        Ok(response)
+    }
+}
@@ -21,19 +21,42 @@
  * for more details.
 */
 
+use std::{sync::Arc, time::Instant};
+
 use directory::{
     backend::internal::{lookup::DirectoryStore, manage::ManageDirectory, PrincipalUpdate},
     DirectoryError, ManagementError, Principal, QueryBy, Type,
 };
-use http_body_util::combinators::BoxBody;
-use hyper::{body::Bytes, Method, StatusCode};
-use jmap_proto::error::request::RequestError;
+use http_body_util::BodyExt;
+use hyper::{Method, StatusCode};
+use jmap_proto::{
+    error::{code::ErrorCode, request::RequestError},
+    object::Object,
+    types::{
+        date::UTCDate,
+        property::Property,
+        value::{MaybePatchValue, Value},
+    },
+};
 use serde_json::json;
-use utils::config::ConfigKey;
+use smtp::{core::management::ParseValues, scripts::plugins::bayes};
+use store::write::log::ChangeLogBuilder;
+use utils::{
+    config::ConfigKey,
+    listener::{expiry::certificate_expiry, manager::LISTENER_KEY},
+    map::stats::CacheReport,
+};
 
-use crate::{services::housekeeper, JMAP};
+use crate::{
+    disposable_alias::set::validate_alias_value,
+    jobs::{JobTask, QueueSelection},
+    migrate::MigrationRequest,
+    overlay::{OverlaySubject, Overlays},
+    services::housekeeper,
+    JMAP,
+};
 
-use super::{http::ToHttpResponse, HttpRequest, JsonResponse};
+use super::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse, TextResponse};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct PrincipalResponse {
@@ -50,14 +73,25 @@ pub struct PrincipalResponse {
     pub member_of: Vec<String>,
     pub members: Vec<String>,
     pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referral: Option<String>,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct DisposableAliasRequest {
+    pub description: Option<String>,
+    pub expires: Option<u64>,
+    #[serde(rename = "blockedSenders")]
+    #[serde(default)]
+    pub blocked_senders: Vec<String>,
 }
 
 impl JMAP {
     pub async fn handle_manage_request(
-        &self,
+        self: &Arc<Self>,
         req: &HttpRequest,
         body: Option<Vec<u8>>,
-    ) -> hyper::Response<BoxBody<Bytes, hyper::Error>> {
+    ) -> HttpResponse {
         let mut path = req.uri().path().split('/');
         path.next();
         path.next();
@@ -68,6 +102,9 @@ impl JMAP {
                 if let Some(principal) =
                     body.and_then(|body| serde_json::from_slice::<Principal<String>>(&body).ok())
                 {
+                    if let Err(err) = self.directory.reserved.assert_principal(&principal) {
+                        return map_directory_error(err);
+                    }
                     match self.store.create_account(principal).await {
                         Ok(account_id) => JsonResponse::new(json!({
                             "data": account_id,
@@ -119,6 +156,21 @@ impl JMAP {
                     Err(err) => map_directory_error(err),
                 }
             }
+            ("principal", None, &Method::DELETE) => {
+                // Purge multiple principals in the background
+                if let Some(names) =
+                    body.and_then(|body| serde_json::from_slice::<Vec<String>>(&body).ok())
+                {
+                    self.job_response(JobTask::PurgeAccounts(names))
+                } else {
+                    RequestError::blank(
+                        StatusCode::BAD_REQUEST.as_u16(),
+                        "Invalid parameters",
+                        "Failed to deserialize purge request",
+                    )
+                    .into_http_response()
+                }
+            }
             ("principal", Some(name), method) => {
                 // Fetch, update or delete principal
                 let account_id = match self.store.get_account_id(name).await {
@@ -179,6 +231,22 @@ impl JMAP {
                         }
                     }
                     Method::DELETE => {
+                        // Accounts under legal hold cannot be deleted
+                        match self.get_legal_hold(account_id).await {
+                            Ok(legal_hold) if legal_hold.is_active() => {
+                                return RequestError::blank(
+                                    StatusCode::FORBIDDEN.as_u16(),
+                                    "Forbidden",
+                                    "Account is under legal hold.",
+                                )
+                                .into_http_response();
+                            }
+                            Ok(_) => (),
+                            Err(_) => {
+                                return RequestError::internal_server_error().into_http_response()
+                            }
+                        }
+
                         // Remove FTS index
                         if let Err(err) = self.fts_store.remove_all(account_id).await {
                             tracing::warn!(
@@ -208,6 +276,9 @@ impl JMAP {
                         if let Some(changes) = body.and_then(|body| {
                             serde_json::from_slice::<Vec<PrincipalUpdate>>(&body).ok()
                         }) {
+                            if let Err(err) = self.directory.reserved.assert_changes(&changes) {
+                                return map_directory_error(err);
+                            }
                             match self
                                 .store
                                 .update_account(QueryBy::Id(account_id), changes)
@@ -231,6 +302,16 @@ impl JMAP {
                     _ => RequestError::not_found().into_http_response(),
                 }
             }
+            ("reserved", None, &Method::GET) => {
+                // Report on the reserved addresses of all local domains
+                match self.directory.reserved_report().await {
+                    Ok(report) => JsonResponse::new(json!({
+                        "data": report,
+                    }))
+                    .into_http_response(),
+                    Err(err) => map_directory_error(err),
+                }
+            }
             ("domain", None, &Method::GET) => {
                 // List principal ids
                 let mut from_key = None;
@@ -278,26 +359,92 @@ impl JMAP {
                     Err(err) => map_directory_error(err),
                 }
             }
+            ("autoconfig", Some(domain), &Method::GET) => self.autoconfig_srv_records(domain),
             ("store", Some("maintenance"), &Method::GET) => {
-                match self.store.purge_blobs(self.blob_store.clone()).await {
-                    Ok(_) => match self.store.purge_bitmaps().await {
-                        Ok(_) => JsonResponse::new(json!({
-                            "data": [],
-                        }))
-                        .into_http_response(),
-                        Err(err) => RequestError::blank(
-                            StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
-                            "Purge database failed",
-                            err.to_string(),
-                        )
-                        .into_http_response(),
-                    },
-                    Err(err) => RequestError::blank(
-                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
-                        "Purge blob failed",
-                        err.to_string(),
+                // Purge blobs and bitmaps in the background
+                self.job_response(JobTask::StoreMaintenance)
+            }
+            ("store", Some("compact"), &Method::GET) => {
+                // Compact the store in the background
+                if !self.is_compaction_running() {
+                    self.job_response(JobTask::StoreCompaction)
+                } else {
+                    RequestError::blank(
+                        StatusCode::CONFLICT.as_u16(),
+                        "Compaction in progress",
+                        "A store compaction is already running.",
                     )
+                    .into_http_response()
+                }
+            }
+            ("store", Some("reindex"), &Method::GET) => {
+                // Queue all messages, or those of a single account, for full-text indexing
+                let mut account_id = None;
+                if let Some(account) = req.uri().query().and_then(|query| {
+                    form_urlencoded::parse(query.as_bytes())
+                        .find(|(key, _)| key == "account")
+                        .map(|(_, value)| value.into_owned())
+                }) {
+                    match self.store.get_account_id(&account).await {
+                        Ok(Some(id)) => {
+                            account_id = id.into();
+                        }
+                        Ok(None) => {
+                            return RequestError::blank(
+                                StatusCode::NOT_FOUND.as_u16(),
+                                "Not found",
+                                "Account not found.",
+                            )
+                            .into_http_response();
+                        }
+                        Err(err) => {
+                            return map_directory_error(err);
+                        }
+                    }
+                }
+
+                self.job_response(JobTask::Reindex(account_id))
+            }
+            ("store", Some("vacation"), &Method::DELETE) => {
+                // Reset the vacation responses sent by all accounts, or by a single account
+                let mut account_id = None;
+                if let Some(account) = req.uri().query().and_then(|query| {
+                    form_urlencoded::parse(query.as_bytes())
+                        .find(|(key, _)| key == "account")
+                        .map(|(_, value)| value.into_owned())
+                }) {
+                    match self.store.get_account_id(&account).await {
+                        Ok(Some(id)) => {
+                            account_id = id.into();
+                        }
+                        Ok(None) => {
+                            return RequestError::blank(
+                                StatusCode::NOT_FOUND.as_u16(),
+                                "Not found",
+                                "Account not found.",
+                            )
+                            .into_http_response();
+                        }
+                        Err(err) => {
+                            return map_directory_error(err);
+                        }
+                    }
+                }
+
+                match self.sieve_vacation_reset(account_id).await {
+                    Ok(_) => JsonResponse::new(json!({
+                        "data": [],
+                    }))
                     .into_http_response(),
+                    Err(err) => {
+                        tracing::warn!(
+                            context = "sieve",
+                            event = "error",
+                            reason = %err,
+                            "Failed to reset vacation responses."
+                        );
+                        RequestError::internal_server_error().into_http_response()
+                    }
                 }
             }
             ("reload", Some("config"), &Method::GET) => {
@@ -306,10 +453,19 @@ impl JMAP {
                     .send(housekeeper::Event::ReloadConfig)
                     .await;
 
-                JsonResponse::new(json!({
-                    "data": [],
-                }))
-                .into_http_response()
+                // Apply listener changes
+                match self.store.config_list(LISTENER_KEY).await {
+                    Ok(config) => JsonResponse::new(json!({
+                        "data": self.listener_manager.reload(config),
+                    }))
+                    .into_http_response(),
+                    Err(err) => RequestError::blank(
+                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        "Config fetch failed",
+                        err.to_string(),
+                    )
+                    .into_http_response(),
+                }
             }
             ("reload", Some("certificates"), &Method::GET) => {
                 let _ = self
@@ -322,6 +478,77 @@ impl JMAP {
                 }))
                 .into_http_response()
             }
+            ("cluster", Some("metrics"), &Method::GET) => {
+                if let Some(cluster) = &self.cluster {
+                    JsonResponse::new(json!({
+                        "data": {
+                            "node": cluster.local_id,
+                            "sessions": cluster.metrics.to_json(),
+                        },
+                    }))
+                    .into_http_response()
+                } else {
+                    RequestError::blank(
+                        StatusCode::NOT_FOUND.as_u16(),
+                        "Not found",
+                        "Cluster routing is not enabled.",
+                    )
+                    .into_http_response()
+                }
+            }
+            ("errors", None, &Method::GET) => JsonResponse::new(json!({
+                "data": ErrorCode::ALL
+                    .iter()
+                    .map(|code| json!({
+                        "code": code,
+                        "name": format!("{code:?}"),
+                        "description": code.description(),
+                    }))
+                    .collect::<Vec<_>>(),
+            }))
+            .into_http_response(),
+            ("telemetry", Some("caches"), &Method::GET) => JsonResponse::new(json!({
+                "data": self.cache_reports(),
+            }))
+            .into_http_response(),
+            ("acme", None, &Method::GET) => JsonResponse::new(json!({
+                "data": self
+                    .acme_managers
+                    .iter()
+                    .flat_map(|acme| acme.status())
+                    .collect::<Vec<_>>(),
+            }))
+            .into_http_response(),
+            ("certificates", None, &Method::GET) => JsonResponse::new(json!({
+                "data": certificate_expiry(&self.certificates, &self.acme_managers),
+            }))
+            .into_http_response(),
+            ("telemetry", Some("directory"), &Method::GET) => JsonResponse::new(json!({
+                "data": self.directory.query_report().unwrap_or_default(),
+            }))
+            .into_http_response(),
+            ("telemetry", Some("logs"), &Method::GET) => match utils::logging::disk_usage() {
+                Some(Ok(usage)) => JsonResponse::new(json!({
+                    "data": usage,
+                }))
+                .into_http_response(),
+                Some(Err(err)) => RequestError::blank(
+                    StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                    "Log directory read failed",
+                    err.to_string(),
+                )
+                .into_http_response(),
+                None => RequestError::blank(
+                    StatusCode::NOT_FOUND.as_u16(),
+                    "Not found",
+                    "File logging is not enabled.",
+                )
+                .into_http_response(),
+            },
+            ("telemetry", Some("metrics"), &Method::GET) => {
+                TextResponse::new("text/plain; version=0.0.4", self.prometheus_metrics())
+                    .into_http_response()
+            }
             ("config", key, &Method::GET) => {
                 match self.store.config_list(key.unwrap_or_default()).await {
                     Ok(config) => JsonResponse::new(json!({
@@ -343,6 +570,9 @@ impl JMAP {
                     }
                     _ => self.store.config_clear(prefix).await,
                 };
+                if Overlays::is_overlay_key(prefix) {
+                    self.overlays.invalidate();
+                }
                 match result {
                     Ok(_) => JsonResponse::new(json!({
                         "data": [],
@@ -360,6 +590,9 @@ impl JMAP {
                 if let Some(changes) = body
                     .and_then(|body| serde_json::from_slice::<Vec<(String, String)>>(&body).ok())
                 {
+                    if changes.iter().any(|(key, _)| Overlays::is_overlay_key(key)) {
+                        self.overlays.invalidate();
+                    }
                     match self
                         .store
                         .config_set(
@@ -389,41 +622,670 @@ impl JMAP {
                     .into_http_response()
                 }
             }
-            (path_1 @ ("queue" | "report"), Some(path_2), &Method::GET) => {
-                self.smtp
-                    .handle_manage_request(req.uri(), req.method(), path_1, path_2)
-                    .await
+            ("overlay", None, &Method::GET) => {
+                // Resolve the effective value of one or all overlay settings
+                let mut subject = OverlaySubject::default();
+                let mut key = None;
+                if let Some(query) = req.uri().query() {
+                    for (name, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match name.as_ref() {
+                            "key" => key = value.into_owned().into(),
+                            "account" => subject.account = value.into_owned().into(),
+                            "domain" => subject.domain = value.to_lowercase().into(),
+                            "tenant" => subject.tenant = value.into_owned().into(),
+                            _ => {}
+                        }
+                    }
+                }
+
+                match self.overlay_resolve(subject, key.as_deref()).await {
+                    Ok(Some(values)) => JsonResponse::new(json!({
+                        "data": values,
+                    }))
+                    .into_http_response(),
+                    Ok(None) => RequestError::blank(
+                        StatusCode::NOT_FOUND.as_u16(),
+                        "Not found",
+                        "Account not found.",
+                    )
+                    .into_http_response(),
+                    Err(err) => RequestError::blank(
+                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        "Overlay resolution failed",
+                        err,
+                    )
+                    .into_http_response(),
+                }
+            }
+            ("activity", Some(name), &Method::GET) => {
+                // List activity log entries for an account
+                let account_id = match self.store.get_account_id(name).await {
+                    Ok(Some(account_id)) => account_id,
+                    Ok(None) => {
+                        return RequestError::blank(
+                            StatusCode::NOT_FOUND.as_u16(),
+                            "Not found",
+                            "Account not found.",
+                        )
+                        .into_http_response();
+                    }
+                    Err(err) => {
+                        return map_directory_error(err);
+                    }
+                };
+                let mut since = 0;
+                let mut limit = self.config.activity_log_max_results;
+
+                if let Some(query) = req.uri().query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "since" => {
+                                since = value.parse().unwrap_or_default();
+                            }
+                            "limit" => {
+                                limit = value.parse().unwrap_or(limit);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+
+                match self.activity_log_query(account_id, since, limit).await {
+                    Ok(entries) => JsonResponse::new(json!({
+                        "data": entries,
+                    }))
+                    .into_http_response(),
+                    Err(err) => RequestError::blank(
+                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        "Activity log fetch failed",
+                        err.to_string(),
+                    )
+                    .into_http_response(),
+                }
             }
+            ("usage", name, &Method::GET) => {
+                // Export usage records, optionally filtered by account
+                let account_id = if let Some(name) = name {
+                    match self.store.get_account_id(name).await {
+                        Ok(Some(account_id)) => Some(account_id),
+                        Ok(None) => {
+                            return RequestError::blank(
+                                StatusCode::NOT_FOUND.as_u16(),
+                                "Not found",
+                                "Account not found.",
+                            )
+                            .into_http_response();
+                        }
+                        Err(err) => {
+                            return map_directory_error(err);
+                        }
+                    }
+                } else {
+                    None
+                };
+                let mut since = 0;
+                let mut until = u64::MAX;
+                let mut limit = 0;
+
+                if let Some(query) = req.uri().query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "since" => {
+                                since = value.parse().unwrap_or_default();
+                            }
+                            "until" => {
+                                until = value.parse().unwrap_or(until);
+                            }
+                            "limit" => {
+                                limit = value.parse().unwrap_or(limit);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+
+                match self.usage_query(account_id, since, until, limit).await {
+                    Ok(records) => JsonResponse::new(json!({
+                        "data": records,
+                    }))
+                    .into_http_response(),
+                    Err(err) => RequestError::blank(
+                        StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                        "Usage fetch failed",
+                        err.to_string(),
+                    )
+                    .into_http_response(),
+                }
+            }
+            ("alias", Some(name), method) => {
+                // List, create or delete the disposable aliases of an account
+                let account_id = match self.store.get_account_id(name).await {
+                    Ok(Some(account_id)) => account_id,
+                    Ok(None) => {
+                        return RequestError::blank(
+                            StatusCode::NOT_FOUND.as_u16(),
+                            "Not found",
+                            "Account not found.",
+                        )
+                        .into_http_response();
+                    }
+                    Err(err) => {
+                        return map_directory_error(err);
+                    }
+                };
+
+                match *method {
+                    Method::GET => match self.disposable_alias_report(account_id).await {
+                        Ok(aliases) => JsonResponse::new(json!({
+                            "data": aliases,
+                        }))
+                        .into_http_response(),
+                        Err(_) => RequestError::internal_server_error().into_http_response(),
+                    },
+                    Method::POST => {
+                        let request = if let Some(request) = body.and_then(|body| {
+                            serde_json::from_slice::<DisposableAliasRequest>(&body).ok()
+                        }) {
+                            request
+                        } else {
+                            return RequestError::blank(
+                                StatusCode::BAD_REQUEST.as_u16(),
+                                "Invalid parameters",
+                                "Failed to deserialize alias request",
+                            )
+                            .into_http_response();
+                        };
+
+                        let mut alias = Object::with_capacity(3);
+                        for (property, value) in [
+                            (
+                                Property::Description,
+                                request.description.map_or(Value::Null, Value::Text),
+                            ),
+                            (
+                                Property::Expires,
+                                request.expires.map_or(Value::Null, |expires| {
+                                    Value::Date(UTCDate::from_timestamp(expires as i64))
+                                }),
+                            ),
+                            (
+                                Property::BlockedSenders,
+                                Value::List(
+                                    request
+                                        .blocked_senders
+                                        .into_iter()
+                                        .map(Value::Text)
+                                        .collect(),
+                                ),
+                            ),
+                        ] {
+                            match validate_alias_value(&property, MaybePatchValue::Value(value)) {
+                                Ok(Value::Null) => (),
+                                Ok(value) => {
+                                    alias.set(property, value);
+                                }
+                                Err(err) => {
+                                    return RequestError::blank(
+                                        StatusCode::BAD_REQUEST.as_u16(),
+                                        "Invalid parameters",
+                                        err.description.unwrap_or_default(),
+                                    )
+                                    .into_http_response();
+                                }
+                            }
+                        }
+
+                        let mut changes = ChangeLogBuilder::new();
+                        match self
+                            .disposable_alias_create(account_id, alias, &mut changes)
+                            .await
+                        {
+                            Ok(Some((_, address))) => {
+                                if self.commit_changes(account_id, changes).await.is_err() {
+                                    return RequestError::internal_server_error()
+                                        .into_http_response();
+                                }
+                                JsonResponse::new(json!({
+                                    "data": address,
+                                }))
+                                .into_http_response()
+                            }
+                            Ok(None) => RequestError::blank(
+                                StatusCode::BAD_REQUEST.as_u16(),
+                                "Invalid parameters",
+                                "Account does not have an e-mail address.",
+                            )
+                            .into_http_response(),
+                            Err(_) => RequestError::internal_server_error().into_http_response(),
+                        }
+                    }
+                    Method::DELETE => {
+                        let mut address = String::new();
+                        if let Some(query) = req.uri().query() {
+                            for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                                if key == "address" {
+                                    address = value.to_lowercase();
+                                }
+                            }
+                        }
+
+                        let aliases = match self.disposable_alias_list(account_id).await {
+                            Ok(aliases) => aliases,
+                            Err(_) => {
+                                return RequestError::internal_server_error().into_http_response()
+                            }
+                        };
+                        if let Some((document_id, alias)) =
+                            aliases.into_iter().find(|(_, alias)| {
+                                matches!(
+                                    alias.get(&Property::Email),
+                                    Value::Text(email) if *email == address
+                                )
+                            })
+                        {
+                            let mut changes = ChangeLogBuilder::new();
+                            if self
+                                .disposable_alias_destroy(
+                                    account_id,
+                                    document_id,
+                                    &alias,
+                                    &mut changes,
+                                )
+                                .await
+                                .is_err()
+                                || self.commit_changes(account_id, changes).await.is_err()
+                            {
+                                return RequestError::internal_server_error().into_http_response();
+                            }
+                            JsonResponse::new(json!({
+                                "data": [],
+                            }))
+                            .into_http_response()
+                        } else {
+                            RequestError::not_found().into_http_response()
+                        }
+                    }
+                    _ => RequestError::not_found().into_http_response(),
+                }
+            }
+            ("bayes", Some("opt-out"), method) => {
+                // Obtain, set or clear the Bayes auto-learning opt-out of an account
+                let name = path.next().unwrap_or_default();
+                let account_id = match self.store.get_account_id(name).await {
+                    Ok(Some(account_id)) => account_id,
+                    Ok(None) => {
+                        return RequestError::blank(
+                            StatusCode::NOT_FOUND.as_u16(),
+                            "Not found",
+                            "Account not found.",
+                        )
+                        .into_http_response();
+                    }
+                    Err(err) => {
+                        return map_directory_error(err);
+                    }
+                };
+                let emails = match self.store.query(QueryBy::Id(account_id), false).await {
+                    Ok(Some(principal)) if !principal.emails.is_empty() => principal.emails,
+                    Ok(_) => {
+                        return RequestError::blank(
+                            StatusCode::BAD_REQUEST.as_u16(),
+                            "Invalid parameters",
+                            "Account does not have an e-mail address.",
+                        )
+                        .into_http_response();
+                    }
+                    Err(err) => {
+                        return map_directory_error(err);
+                    }
+                };
+
+                let mut store = &self.smtp.queue.config.lookup_store;
+                if let Some(query) = req.uri().query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        if key == "store" {
+                            if let Some(store_) = self.smtp.sieve.lookup_stores.get(value.as_ref())
+                            {
+                                store = store_;
+                            } else {
+                                return RequestError::blank(
+                                    StatusCode::BAD_REQUEST.as_u16(),
+                                    "Invalid parameters",
+                                    format!("Unknown store {value:?}."),
+                                )
+                                .into_http_response();
+                            }
+                        }
+                    }
+                }
+
+                let opt_out = match *method {
+                    Method::GET => None,
+                    Method::POST => Some(true),
+                    Method::DELETE => Some(false),
+                    _ => return RequestError::not_found().into_http_response(),
+                };
+                let mut opted_out = opt_out.unwrap_or_default();
+                for email in &emails {
+                    let result = if let Some(opt_out) = opt_out {
+                        bayes::set_opt_out(store, email, opt_out).await
+                    } else {
+                        bayes::is_opted_out(store, email)
+                            .await
+                            .map(|is_opted_out| opted_out |= is_opted_out)
+                    };
+                    if result.is_err() {
+                        return RequestError::internal_server_error().into_http_response();
+                    }
+                }
+
+                JsonResponse::new(json!({
+                    "data": {
+                        "optOut": opted_out,
+                        "addresses": emails,
+                    },
+                }))
+                .into_http_response()
+            }
+            ("delivery", Some("deduplicate"), method) => {
+                // Obtain, enable or disable the alias fan-out deduplication of an account
+                let name = path.next().unwrap_or_default();
+                let account_id = match self.store.get_account_id(name).await {
+                    Ok(Some(account_id)) => account_id,
+                    Ok(None) => {
+                        return RequestError::blank(
+                            StatusCode::NOT_FOUND.as_u16(),
+                            "Not found",
+                            "Account not found.",
+                        )
+                        .into_http_response();
+                    }
+                    Err(err) => {
+                        return map_directory_error(err);
+                    }
+                };
+                let enabled = match *method {
+                    Method::GET => None,
+                    Method::POST => Some(true),
+                    Method::DELETE => Some(false),
+                    _ => return RequestError::not_found().into_http_response(),
+                };
+                if let Some(enabled) = enabled {
+                    if self
+                        .set_delivery_dedup(account_id, enabled.into())
+                        .await
+                        .is_err()
+                    {
+                        return RequestError::internal_server_error().into_http_response();
+                    }
+                }
+
+                match self.is_delivery_dedup_enabled(account_id).await {
+                    Ok(enabled) => JsonResponse::new(json!({
+                        "data": {
+                            "deduplicate": enabled,
+                        },
+                    }))
+                    .into_http_response(),
+                    Err(_) => RequestError::internal_server_error().into_http_response(),
+                }
+            }
+            ("migrate", Some(name), method) => {
+                // Start, monitor or cancel an IMAP migration
+                let account_id = match self.store.get_account_id(name).await {
+                    Ok(Some(account_id)) => account_id,
+                    Ok(None) => {
+                        return RequestError::blank(
+                            StatusCode::NOT_FOUND.as_u16(),
+                            "Not found",
+                            "Account not found.",
+                        )
+                        .into_http_response();
+                    }
+                    Err(err) => {
+                        return map_directory_error(err);
+                    }
+                };
+
+                match *method {
+                    Method::POST => {
+                        if let Some(request) = body
+                            .and_then(|body| serde_json::from_slice::<MigrationRequest>(&body).ok())
+                        {
+                            if self.migration_start(account_id, request).is_some() {
+                                JsonResponse::new(json!({
+                                    "data": [],
+                                }))
+                                .into_http_response()
+                            } else {
+                                RequestError::blank(
+                                    StatusCode::CONFLICT.as_u16(),
+                                    "Migration in progress",
+                                    "A migration is already running for this account.",
+                                )
+                                .into_http_response()
+                            }
+                        } else {
+                            RequestError::blank(
+                                StatusCode::BAD_REQUEST.as_u16(),
+                                "Invalid parameters",
+                                "Failed to deserialize migration request",
+                            )
+                            .into_http_response()
+                        }
+                    }
+                    Method::GET => match self.migration_state(account_id).await {
+                        Ok(state) => JsonResponse::new(json!({
+                            "data": {
+                                "progress": self.migrations
+                                    .get(&account_id)
+                                    .map(|job| job.progress.lock().clone()),
+                                "folders": state.folders,
+                            },
+                        }))
+                        .into_http_response(),
+                        Err(err) => RequestError::blank(
+                            StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                            "Migration state fetch failed",
+                            err.to_string(),
+                        )
+                        .into_http_response(),
+                    },
+                    Method::DELETE => {
+                        let cancelled = self.migration_cancel(account_id);
+                        let mut reset = false;
+                        if let Some(query) = req.uri().query() {
+                            for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                                if key == "reset" {
+                                    reset = value == "true";
+                                }
+                            }
+                        }
+
+                        if reset && !cancelled {
+                            // Forget the synchronization state of an idle migration
+                            if let Err(err) = self.migration_reset(account_id).await {
+                                return RequestError::blank(
+                                    StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                                    "Migration reset failed",
+                                    err.to_string(),
+                                )
+                                .into_http_response();
+                            }
+                            self.migrations.remove(&account_id);
+                        }
+
+                        JsonResponse::new(json!({
+                            "data": cancelled,
+                        }))
+                        .into_http_response()
+                    }
+                    _ => RequestError::not_found().into_http_response(),
+                }
+            }
+            ("job", None, &Method::GET) => JsonResponse::new(json!({
+                "data": self.job_list(),
+            }))
+            .into_http_response(),
+            ("job", Some(id), method) => {
+                // Monitor or cancel a background job
+                let id = match id.parse::<u64>() {
+                    Ok(id) => id,
+                    Err(_) => {
+                        return RequestError::blank(
+                            StatusCode::BAD_REQUEST.as_u16(),
+                            "Invalid parameters",
+                            "Invalid job id.",
+                        )
+                        .into_http_response();
+                    }
+                };
+
+                match *method {
+                    Method::GET => match self.job_status(id).await {
+                        Ok(Some(progress)) => JsonResponse::new(json!({
+                            "data": progress,
+                        }))
+                        .into_http_response(),
+                        Ok(None) => RequestError::not_found().into_http_response(),
+                        Err(err) => RequestError::blank(
+                            StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                            "Job state fetch failed",
+                            err.to_string(),
+                        )
+                        .into_http_response(),
+                    },
+                    Method::DELETE => {
+                        if self.job_cancel(id) {
+                            JsonResponse::new(json!({
+                                "data": [],
+                            }))
+                            .into_http_response()
+                        } else {
+                            RequestError::blank(
+                                StatusCode::CONFLICT.as_u16(),
+                                "Job not running",
+                                "The job does not exist or has already finished.",
+                            )
+                            .into_http_response()
+                        }
+                    }
+                    _ => RequestError::not_found().into_http_response(),
+                }
+            }
+            ("queue", Some(action @ ("cancel" | "retry")), &Method::GET)
+                if !has_queue_ids(req.uri().query()) =>
+            {
+                // Cancel or retry all queued messages matching a selection in the background
+                match parse_queue_selection(req.uri().query(), action == "retry") {
+                    Ok(task) => self.job_response(task),
+                    Err(reason) => RequestError::blank(
+                        StatusCode::BAD_REQUEST.as_u16(),
+                        "Invalid parameters",
+                        reason,
+                    )
+                    .into_http_response(),
+                }
+            }
+            (
+                path_1 @ ("queue" | "report" | "expression" | "moderation" | "operator" | "dns"
+                | "filter" | "archive" | "sessions" | "warmup" | "loop" | "bayes"),
+                Some(path_2),
+                &Method::GET,
+            ) => self
+                .smtp
+                .handle_manage_request(req.uri(), req.method(), path_1, path_2)
+                .await
+                .map(|body| body.map_err(Into::into).boxed()),
             _ => RequestError::not_found().into_http_response(),
         }
     }
 }
 
-fn map_directory_error(err: DirectoryError) -> hyper::Response<BoxBody<Bytes, hyper::Error>> {
+fn has_queue_ids(query: Option<&str>) -> bool {
+    query.map_or(false, |query| {
+        form_urlencoded::parse(query.as_bytes()).any(|(key, _)| key == "id" || key == "ids")
+    })
+}
+
+fn parse_queue_selection(query: Option<&str>, is_retry: bool) -> Result<JobTask, String> {
+    let mut selection = QueueSelection::default();
+    let mut time = Instant::now();
+
+    if let Some(query) = query {
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            match key.as_ref() {
+                "from" => {
+                    selection.from = value.into_owned().into();
+                }
+                "to" => {
+                    selection.to = value.into_owned().into();
+                }
+                "before" => {
+                    selection.before = value.parse_timestamp()?.into();
+                }
+                "after" => {
+                    selection.after = value.parse_timestamp()?.into();
+                }
+                "filter" => {
+                    selection.item = value.into_owned().into();
+                }
+                "reason" => {
+                    selection.reason = value.parse_defer_reason()?.into();
+                }
+                "at" if is_retry => {
+                    time = value.parse_timestamp()?;
+                }
+                _ => {
+                    return Err(format!("Invalid parameter {key:?}."));
+                }
+            }
+        }
+    }
+
+    Ok(if is_retry {
+        JobTask::QueueRetry(selection, time)
+    } else {
+        JobTask::QueueCancel(selection)
+    })
+}
+
+fn map_directory_error(err: DirectoryError) -> HttpResponse {
     match err {
         DirectoryError::Management(err) => {
             let response = match err {
                 ManagementError::MissingField(field) => json!({
                     "error": "missingField",
+                    "code": ErrorCode::MissingField,
                     "field": field,
                     "details": format!("Missing required field '{field}'."),
                 }),
                 ManagementError::AlreadyExists { field, value } => json!({
                     "error": "alreadyExists",
+                    "code": ErrorCode::AlreadyExists,
                     "field": field,
                     "value": value,
                     "details": format!("Another record exists containing '{value}' in the '{field}' field."),
                 }),
                 ManagementError::NotFound(details) => json!({
                     "error": "notFound",
+                    "code": ErrorCode::ItemNotFound,
                     "item": details,
                     "details": format!("'{details}' does not exist."),
                 }),
+                ManagementError::Reserved { field, value } => json!({
+                    "error": "reserved",
+                    "code": ErrorCode::ReservedValue,
+                    "field": field,
+                    "value": value,
+                    "details": format!("'{value}' is a reserved address and cannot be assigned."),
+                }),
             };
             JsonResponse::new(response).into_http_response()
         }
         DirectoryError::Unsupported => JsonResponse::new(json!({
             "error": "unsupported",
+            "code": ErrorCode::Unsupported,
             "details": "Requested action is unsupported",
         }))
         .into_http_response(),
@@ -445,6 +1307,60 @@ fn map_directory_error(err: DirectoryError) -> hyper::Response<BoxBody<Bytes, hy
     }
 }
 
+impl JMAP {
+    fn job_response(self: &Arc<Self>, task: JobTask) -> HttpResponse {
+        match self.job_start(task) {
+            Some(id) => JsonResponse::new(json!({
+                "data": {
+                    "jobId": id,
+                },
+            }))
+            .into_http_response(),
+            None => RequestError::blank(
+                StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                "Job start failed",
+                "Failed to generate a job id.",
+            )
+            .into_http_response(),
+        }
+    }
+
+    pub(crate) fn cache_reports(&self) -> Vec<CacheReport> {
+        let dns_cache = &self.smtp.resolvers.cache;
+        vec![
+            self.sessions
+                .stats
+                .report("sessions", Some(self.sessions.len()), None),
+            self.access_tokens
+                .stats
+                .report("access_tokens", Some(self.access_tokens.len()), None),
+            self.oauth_codes
+                .stats
+                .report("oauth_codes", Some(self.oauth_codes.len()), None),
+            self.image_proxy_cache.stats.report(
+                "image_proxy",
+                Some(self.image_proxy_cache.len()),
+                None,
+            ),
+            self.smtp.sieve.runtime.context().bayes_cache.report(),
+            self.smtp
+                .sieve
+                .runtime
+                .context()
+                .verdict_stats
+                .report("spam_verdicts", None, None),
+            dns_cache.tlsa_stats.report("dns_tlsa", None, None),
+            dns_cache.mta_sts_stats.report("dns_mta_sts", None, None),
+            dns_cache.rbl_stats.report("dns_rbl", None, None),
+            self.smtp
+                .session
+                .sender_verify
+                .stats
+                .report("sender_verify", None, None),
+        ]
+    }
+}
+
 impl From<Principal<String>> for PrincipalResponse {
     fn from(principal: Principal<String>) -> Self {
         PrincipalResponse {
@@ -455,6 +1371,7 @@ impl From<Principal<String>> for PrincipalResponse {
             emails: principal.emails,
             member_of: principal.member_of,
             description: principal.description,
+            referral: principal.referral,
             secrets: principal.secrets,
             used_quota: 0,
             members: Vec::new(),
@@ -0,0 +1,284 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{fmt::Write, net::SocketAddr};
+
+use jmap_proto::error::request::{RequestError, RequestLimitError};
+use serde_json::json;
+use utils::config::ServerProtocol;
+
+use crate::{auth::oauth::fetch_body, JMAP};
+
+use super::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse, XmlResponse};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientService {
+    pub protocol: ServerProtocol,
+    pub port: u16,
+    pub implicit_tls: bool,
+}
+
+impl JMAP {
+    // Mozilla Thunderbird autoconfig
+    pub async fn handle_autoconfig_request(&self, req: &HttpRequest) -> HttpResponse {
+        // Obtain parameters
+        let emailaddress = req
+            .uri()
+            .query()
+            .and_then(|q| {
+                form_urlencoded::parse(q.as_bytes())
+                    .find(|(k, _)| k == "emailaddress")
+                    .map(|(_, v)| v.into_owned())
+            })
+            .unwrap_or_default();
+        let domain = match autoconfig_domain(&emailaddress) {
+            Ok(domain) => domain,
+            Err(err) => return err.into_http_response(),
+        };
+        let hostname = &self.config.autoconfig_hostname;
+
+        let mut config = String::with_capacity(1024);
+        config.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        config.push_str("<clientConfig version=\"1.1\">\n");
+        let _ = writeln!(
+            &mut config,
+            "\t<emailProvider id=\"{}\">",
+            escape_xml(&domain)
+        );
+        let _ = writeln!(&mut config, "\t\t<domain>{}</domain>", escape_xml(&domain));
+        let _ = writeln!(
+            &mut config,
+            "\t\t<displayName>{}</displayName>",
+            escape_xml(&emailaddress)
+        );
+        let _ = writeln!(
+            &mut config,
+            "\t\t<displayShortName>{}</displayShortName>",
+            escape_xml(&domain)
+        );
+        for service in &self.config.autoconfig_services {
+            let tag = match service.protocol {
+                ServerProtocol::Imap => "incomingServer",
+                ServerProtocol::Smtp => "outgoingServer",
+                _ => continue,
+            };
+            let _ = writeln!(&mut config, "\t\t<{tag} type=\"{}\">", service.protocol);
+            let _ = writeln!(
+                &mut config,
+                "\t\t\t<hostname>{}</hostname>",
+                escape_xml(hostname)
+            );
+            let _ = writeln!(&mut config, "\t\t\t<port>{}</port>", service.port);
+            let _ = writeln!(
+                &mut config,
+                "\t\t\t<socketType>{}</socketType>",
+                if service.implicit_tls {
+                    "SSL"
+                } else {
+                    "STARTTLS"
+                }
+            );
+            // Login names are not disclosed to unauthenticated clients
+            config.push_str("\t\t\t<username>%EMAILADDRESS%</username>\n");
+            config.push_str("\t\t\t<authentication>password-cleartext</authentication>\n");
+            let _ = writeln!(&mut config, "\t\t</{tag}>");
+        }
+        config.push_str("\t</emailProvider>\n");
+        config.push_str("</clientConfig>\n");
+
+        XmlResponse::new(config).into_http_response()
+    }
+
+    // Microsoft Outlook autodiscover (POX)
+    pub async fn handle_autodiscover_request(&self, req: &mut HttpRequest) -> HttpResponse {
+        // Obtain the e-mail address from the request body
+        let body = match fetch_body(req, 8192).await {
+            Some(body) => body,
+            None => {
+                return RequestError::limit(RequestLimitError::SizeRequest).into_http_response()
+            }
+        };
+        let body = String::from_utf8_lossy(&body);
+        let emailaddress = body
+            .split_once("<EMailAddress>")
+            .and_then(|(_, value)| value.split_once("</EMailAddress>"))
+            .map(|(value, _)| value.trim())
+            .unwrap_or_default();
+        if let Err(err) = autoconfig_domain(emailaddress) {
+            return err.into_http_response();
+        }
+        let hostname = &self.config.autoconfig_hostname;
+
+        let mut config = String::with_capacity(1024);
+        config.push_str("<?xml version=\"1.0\" encoding=\"utf-8\" ?>\n");
+        config.push_str("<Autodiscover xmlns=\"http://schemas.microsoft.com/exchange/autodiscover/responseschema/2006\">\n");
+        config.push_str("\t<Response xmlns=\"http://schemas.microsoft.com/exchange/autodiscover/outlook/responseschema/2006a\">\n");
+        config.push_str("\t\t<User>\n");
+        let _ = writeln!(
+            &mut config,
+            "\t\t\t<DisplayName>{}</DisplayName>",
+            escape_xml(emailaddress)
+        );
+        config.push_str("\t\t</User>\n");
+        config.push_str("\t\t<Account>\n");
+        config.push_str("\t\t\t<AccountType>email</AccountType>\n");
+        config.push_str("\t\t\t<Action>settings</Action>\n");
+        for service in &self.config.autoconfig_services {
+            if !matches!(
+                service.protocol,
+                ServerProtocol::Imap | ServerProtocol::Smtp
+            ) {
+                continue;
+            }
+            config.push_str("\t\t\t<Protocol>\n");
+            let _ = writeln!(
+                &mut config,
+                "\t\t\t\t<Type>{}</Type>",
+                service.protocol.to_string().to_ascii_uppercase()
+            );
+            let _ = writeln!(
+                &mut config,
+                "\t\t\t\t<Server>{}</Server>",
+                escape_xml(hostname)
+            );
+            let _ = writeln!(&mut config, "\t\t\t\t<Port>{}</Port>", service.port);
+            let _ = writeln!(
+                &mut config,
+                "\t\t\t\t<LoginName>{}</LoginName>",
+                escape_xml(emailaddress)
+            );
+            config.push_str("\t\t\t\t<DomainRequired>off</DomainRequired>\n");
+            config.push_str("\t\t\t\t<SPA>off</SPA>\n");
+            let _ = writeln!(
+                &mut config,
+                "\t\t\t\t<Encryption>{}</Encryption>",
+                if service.implicit_tls { "SSL" } else { "TLS" }
+            );
+            config.push_str("\t\t\t\t<AuthRequired>on</AuthRequired>\n");
+            config.push_str("\t\t\t</Protocol>\n");
+        }
+        config.push_str("\t\t</Account>\n");
+        config.push_str("\t</Response>\n");
+        config.push_str("</Autodiscover>\n");
+
+        XmlResponse::new(config).into_http_response()
+    }
+
+    // RFC 6186 and RFC 8620 SRV records to publish for a domain
+    pub fn autoconfig_srv_records(&self, domain: &str) -> HttpResponse {
+        let hostname = &self.config.autoconfig_hostname;
+        let mut records = Vec::new();
+        for service in &self.config.autoconfig_services {
+            let name = match (service.protocol, service.implicit_tls) {
+                (ServerProtocol::Imap, true) => "imaps",
+                (ServerProtocol::Imap, false) => "imap",
+                (ServerProtocol::Smtp, true) => "submissions",
+                (ServerProtocol::Smtp, false) => "submission",
+                (ServerProtocol::Jmap, true) => "jmap",
+                _ => continue,
+            };
+            records.push(format!(
+                "_{name}._tcp.{domain}. IN SRV 0 1 {} {hostname}.",
+                service.port
+            ));
+        }
+
+        JsonResponse::new(json!({
+            "data": records,
+        }))
+        .into_http_response()
+    }
+}
+
+impl crate::Config {
+    pub fn parse_autoconfig_services(
+        settings: &utils::config::Config,
+    ) -> Result<Vec<ClientService>, String> {
+        let mut services = Vec::new();
+        for id in settings.sub_keys("server.listener", ".protocol") {
+            let protocol =
+                settings.property_require::<ServerProtocol>(("server.listener", id, "protocol"))?;
+            let port = match settings
+                .properties::<SocketAddr>(("server.listener", id, "bind"))
+                .next()
+            {
+                Some(result) => result?.1.port(),
+                None => continue,
+            };
+
+            // Advertise only encrypted client services, port 25 is used for relaying
+            if !matches!(
+                protocol,
+                ServerProtocol::Imap | ServerProtocol::Smtp | ServerProtocol::Jmap
+            ) || (protocol == ServerProtocol::Smtp && port == 25)
+                || !settings
+                    .property_or_default(
+                        ("server.listener", id, "tls.enable"),
+                        "server.tls.enable",
+                    )?
+                    .unwrap_or(false)
+            {
+                continue;
+            }
+
+            services.push(ClientService {
+                protocol,
+                port,
+                implicit_tls: settings
+                    .property_or_default(
+                        ("server.listener", id, "tls.implicit"),
+                        "server.tls.implicit",
+                    )?
+                    .unwrap_or(true),
+            });
+        }
+
+        // Prefer implicit TLS
+        services.sort_by_key(|service| !service.implicit_tls);
+
+        Ok(services)
+    }
+}
+
+fn autoconfig_domain(emailaddress: &str) -> Result<String, RequestError> {
+    emailaddress
+        .rsplit_once('@')
+        .filter(|(local, domain)| !local.is_empty() && !domain.is_empty())
+        .map(|(_, domain)| domain.to_lowercase())
+        .ok_or_else(RequestError::invalid_parameters)
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '&' => result.push_str("&amp;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&apos;"),
+            _ => result.push(ch),
+        }
+    }
+    result
+}
@@ -25,8 +25,17 @@ use std::{str::FromStr, time::Duration};
 
 use nlp::language::Language;
 use store::rand::{distributions::Alphanumeric, thread_rng, Rng};
+use utils::config::Rate;
 
-use super::session::BaseCapabilities;
+use crate::{
+    auth::{
+        rate_limit::HttpEndpoint,
+        step_up::{base32_decode, StepUpOperation},
+    },
+    sieve::redirect::RedirectPolicy,
+};
+
+use super::{metrics::MetricsToken, session::BaseCapabilities};
 
 impl crate::Config {
     pub fn new(settings: &utils::config::Config) -> Result<Self, String> {
@@ -40,12 +49,16 @@ impl crate::Config {
             query_max_results: settings
                 .property("jmap.protocol.query.max-results")?
                 .unwrap_or(5000),
+            query_max_header_scan: settings
+                .property("jmap.protocol.query.max-header-scan")?
+                .unwrap_or(10000),
             changes_max_results: settings
                 .property("jmap.protocol.changes.max-results")?
                 .unwrap_or(5000),
             snippet_max_results: settings
                 .property("jmap.protocol.search-snippet.max-results")?
                 .unwrap_or(100),
+            snippet_allowed_tags: Self::parse_snippet_allowed_tags(settings)?,
             request_max_size: settings
                 .property("jmap.protocol.request.max-size")?
                 .unwrap_or(10000000),
@@ -61,6 +74,9 @@ impl crate::Config {
             set_max_objects: settings
                 .property("jmap.protocol.set.max-objects")?
                 .unwrap_or(500),
+            update_flags_max_objects: settings
+                .property("jmap.protocol.update-flags.max-objects")?
+                .unwrap_or(50000),
             upload_max_size: settings
                 .property("jmap.protocol.upload.max-size")?
                 .unwrap_or(50000000),
@@ -80,6 +96,8 @@ impl crate::Config {
             mailbox_name_max_len: settings
                 .property("jmap.mailbox.max-name-length")?
                 .unwrap_or(255),
+            mailbox_usage_reconcile: settings
+                .property_or_static("jmap.mailbox.usage.reconcile-interval", "1d")?,
             mail_attachments_max_size: settings
                 .property("jmap.email.max-attachment-size")?
                 .unwrap_or(50000000),
@@ -95,6 +113,15 @@ impl crate::Config {
             sieve_max_scripts: settings
                 .property("sieve.untrusted.limits.max-scripts")?
                 .unwrap_or(256),
+            sieve_max_duplicate_expiry: settings
+                .property::<Duration>("sieve.untrusted.limits.duplicate-expiry")?
+                .unwrap_or(Duration::from_secs(90 * 86400))
+                .as_secs(),
+            sieve_max_vacation_period: settings
+                .property::<Duration>("sieve.untrusted.limits.vacation-period")?
+                .unwrap_or(Duration::from_secs(90 * 86400))
+                .as_secs(),
+            sieve_redirect: RedirectPolicy::parse(settings)?,
             capabilities: BaseCapabilities::default(),
             session_cache_ttl: settings
                 .property("jmap.session.cache.ttl")?
@@ -104,9 +131,26 @@ impl crate::Config {
             rate_authenticate_req: settings
                 .property_or_static("jmap.rate-limit.authentication", "10/1m")?,
             rate_anonymous: settings.property_or_static("jmap.rate-limit.anonymous", "100/1m")?,
+            rate_endpoint: settings
+                .properties::<Rate>("jmap.rate-limit.endpoint")
+                .map(|result| {
+                    result.and_then(|(key, rate)| {
+                        key.strip_prefix("jmap.rate-limit.endpoint.")
+                            .and_then(HttpEndpoint::from_name)
+                            .map(|endpoint| (endpoint, rate))
+                            .ok_or_else(|| format!("Invalid HTTP endpoint in property {key:?}."))
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?,
             rate_use_forwarded: settings
                 .property("jmap.rate-limit.use-forwarded")?
                 .unwrap_or(false),
+            rate_download: settings
+                .property("jmap.rate-limit.bandwidth.download")?
+                .unwrap_or_default(),
+            rate_upload: settings
+                .property("jmap.rate-limit.bandwidth.upload")?
+                .unwrap_or_default(),
             oauth_key: settings
                 .text_file_contents("oauth.key")?
                 .unwrap_or_else(|| {
@@ -141,6 +185,164 @@ impl crate::Config {
             principal_allow_lookups: settings
                 .property("jmap.principal.allow-lookups")?
                 .unwrap_or(true),
+            image_proxy_enable: settings
+                .property("jmap.image-proxy.enable")?
+                .unwrap_or(false),
+            image_proxy_allow_local: settings
+                .property("jmap.image-proxy.allow-local-ips")?
+                .unwrap_or(false),
+            image_proxy_max_size: settings
+                .property("jmap.image-proxy.max-size")?
+                .unwrap_or(5000000),
+            image_proxy_timeout: settings.property_or_static("jmap.image-proxy.timeout", "10s")?,
+            image_proxy_cache_size: settings
+                .property("jmap.image-proxy.cache.size")?
+                .unwrap_or(1024),
+            image_proxy_cache_ttl: settings
+                .property_or_static("jmap.image-proxy.cache.ttl", "1h")?,
+            delivery_dedup_enable: settings
+                .property("jmap.delivery.deduplicate.enable")?
+                .unwrap_or(true),
+            autoconfig_enable: settings
+                .property("jmap.autoconfig.enable")?
+                .unwrap_or(false),
+            autoconfig_hostname: settings
+                .value("jmap.autoconfig.hostname")
+                .or_else(|| settings.value("server.hostname"))
+                .unwrap_or("localhost")
+                .to_string(),
+            autoconfig_services: Self::parse_autoconfig_services(settings)?,
+            activity_log_enable: settings
+                .property("jmap.activity-log.enable")?
+                .unwrap_or(false),
+            activity_log_retention: settings
+                .property_or_static("jmap.activity-log.retention", "30d")?,
+            activity_log_max_results: settings
+                .property("jmap.activity-log.max-results")?
+                .unwrap_or(1000),
+            tombstone_enable: settings
+                .property("jmap.email.tombstones.enable")?
+                .unwrap_or(true),
+            tombstone_retention: settings
+                .property_or_static("jmap.email.tombstones.retention", "30d")?,
+            tombstone_max_per_mailbox: settings
+                .property("jmap.email.tombstones.max-per-mailbox")?
+                .unwrap_or(10000),
+            migration_throttle: settings.property("jmap.migration.throttle")?,
+            migration_batch_size: settings
+                .property("jmap.migration.batch-size")?
+                .unwrap_or(50),
+            migration_timeout: settings.property_or_static("jmap.migration.timeout", "5m")?,
+            job_retention: settings.property_or_static("jmap.jobs.retention", "7d")?,
+            idempotency_enable: settings
+                .property("jmap.idempotency.enable")?
+                .unwrap_or(true),
+            idempotency_ttl: settings.property_or_static("jmap.idempotency.ttl", "24h")?,
+            compaction_enable: settings
+                .property("storage.compaction.enable")?
+                .unwrap_or(false),
+            compaction_business_hours: settings.property("storage.compaction.business-hours")?,
+            compaction_throttle: settings
+                .property_or_static("storage.compaction.throttle", "30s")?,
+            label_max_labels: settings.property("jmap.labels.max-labels")?.unwrap_or(250),
+            label_max_name_length: settings
+                .property("jmap.labels.max-name-length")?
+                .unwrap_or(128),
+            tag_filing_enable: settings.property("jmap.tag-filing.enable")?.unwrap_or(true),
+            tag_filing_separator: settings
+                .value("jmap.tag-filing.separator")
+                .filter(|v| !v.is_empty())
+                .unwrap_or("+")
+                .to_string(),
+            tag_filing_auto_create: settings
+                .property("jmap.tag-filing.auto-create")?
+                .unwrap_or(true),
+            tag_filing_max_rules: settings
+                .property("jmap.tag-filing.max-rules")?
+                .unwrap_or(100),
+            archive_enable: settings.property("jmap.archive.enable")?.unwrap_or(true),
+            archive_folder: settings
+                .value("jmap.archive.folder")
+                .map(|v| v.trim_matches('/'))
+                .filter(|v| !v.is_empty())
+                .unwrap_or("Archive")
+                .to_string(),
+            archive_batch_size: settings.property("jmap.archive.batch-size")?.unwrap_or(500),
+            archive_max_policies: settings
+                .property("jmap.archive.max-policies")?
+                .unwrap_or(10),
+            archive_min_days: settings
+                .property::<u64>("jmap.archive.min-days")?
+                .unwrap_or(1)
+                .max(1),
+            disposable_alias_enable: settings
+                .property("jmap.disposable-alias.enable")?
+                .unwrap_or(true),
+            disposable_alias_max_aliases: settings
+                .property("jmap.disposable-alias.max-aliases")?
+                .unwrap_or(100),
+            disposable_alias_local_part_length: settings
+                .property::<usize>("jmap.disposable-alias.local-part-length")?
+                .unwrap_or(16)
+                .clamp(8, 64),
+            annotation_max_annotations: settings
+                .property("jmap.annotations.max-annotations")?
+                .unwrap_or(10000),
+            annotation_max_length: settings
+                .property("jmap.annotations.max-length")?
+                .unwrap_or(4096),
+            blob_patch_enable: settings
+                .property("jmap.email.patch.enable")?
+                .unwrap_or(true),
+            blob_patch_max_patches: settings
+                .property("jmap.email.patch.max-patches")?
+                .unwrap_or(256),
+            blob_patch_max_size: settings
+                .property("jmap.email.patch.max-size")?
+                .unwrap_or(10 * 1024 * 1024),
+            blob_patch_rate: settings.property_or_static("jmap.email.patch.rate", "60/1m")?,
+            recovery_enable: settings.property("jmap.recovery.enable")?.unwrap_or(false),
+            recovery_codes: settings.property("jmap.recovery.codes")?.unwrap_or(10),
+            recovery_token_expiry: settings
+                .property_or_static("jmap.recovery.token-expiry", "30m")?,
+            recovery_rate: settings.property_or_static("jmap.recovery.rate", "5/1h")?,
+            step_up_enable: settings.property("jmap.step-up.enable")?.unwrap_or(false),
+            step_up_max_age: settings.property_or_static("jmap.step-up.max-age", "5m")?,
+            step_up_operations: settings
+                .values("jmap.step-up.operations")
+                .map(|(_, value)| {
+                    StepUpOperation::parse(value)
+                        .ok_or_else(|| format!("Invalid step-up operation {value:?}."))
+                })
+                .collect::<Result<Vec<_>, String>>()?,
+            step_up_issuer: settings
+                .value("jmap.step-up.issuer")
+                .unwrap_or("Stalwart Mail Server")
+                .to_string(),
+            step_up_rate: settings.property_or_static("jmap.step-up.rate", "5/15m")?,
+            step_up_secrets: settings
+                .values("jmap.step-up.totp")
+                .map(|(key, secret)| {
+                    let account = key.strip_prefix("jmap.step-up.totp.").unwrap_or(key);
+                    base32_decode(secret)
+                        .filter(|secret| !secret.is_empty())
+                        .map(|secret| (account.to_string(), secret))
+                        .ok_or_else(|| format!("Invalid TOTP secret for key {key:?}."))
+                })
+                .collect::<Result<_, String>>()?,
+            submission_sent_fanout: settings
+                .property("jmap.submission.sent-fanout")?
+                .unwrap_or(false),
+            metrics_enable: settings.property("jmap.metrics.enable")?.unwrap_or(false),
+            metrics_tokens: MetricsToken::parse(settings)?,
+            compliance_officers: settings
+                .values("jmap.compliance.officers")
+                .map(|(_, name)| name.to_string())
+                .collect(),
+            queue_inspectors: settings
+                .values("jmap.queue.inspectors")
+                .map(|(_, name)| name.to_string())
+                .collect(),
             encrypt: settings.property_or_static("storage.encryption.enable", "true")?,
             encrypt_append: settings.property_or_static("storage.encryption.append", "false")?,
             spam_header: settings.value("storage.spam.header").and_then(|v| {
@@ -178,7 +380,29 @@ impl crate::Config {
                 })
                 .collect::<Result<Vec<_>, String>>()?,
         };
+        if config.step_up_operations.is_empty() {
+            config.step_up_operations =
+                vec![StepUpOperation::AccountPurge, StepUpOperation::StorePurge];
+        }
         config.add_capabilites(settings);
         Ok(config)
     }
+
+    fn parse_snippet_allowed_tags(settings: &utils::config::Config) -> Result<Vec<String>, String> {
+        let mut tags = Vec::new();
+        for (_, tag) in settings.values("jmap.protocol.search-snippet.allowed-tags") {
+            if !tag.is_empty() && tag.chars().all(|c| c.is_ascii_alphanumeric()) {
+                tags.push(tag.to_ascii_lowercase());
+            } else {
+                return Err(format!(
+                    "Invalid tag {:?} found in property \"jmap.protocol.search-snippet.allowed-tags\".",
+                    tag
+                ));
+            }
+        }
+        if tags.is_empty() {
+            tags.push("mark".to_string());
+        }
+        Ok(tags)
+    }
 }
@@ -93,12 +93,13 @@ impl JMAP {
             #[cfg(feature = "test_mode")]
             let interval = ping * 1000;
 
+            // The interval reported to the client is expressed in seconds (RFC 8620 §7.3)
             Ping {
                 interval: Duration::from_millis(interval as u64),
                 last_ping: Instant::now() - Duration::from_millis(interval as u64),
                 payload: Bytes::from(format!(
                     "event: ping\ndata: {{\"interval\": {}}}\n\n",
-                    interval
+                    interval / 1000
                 )),
             }
             .into()
@@ -157,8 +158,14 @@ impl JMAP {
                                 break;
                             }
 
+                            // Pings are only sent after a period of inactivity
                             response.changed.clear();
-                                ping.as_ref().map(|p| p.interval).unwrap_or(LONG_SLUMBER)
+                            if let Some(ping) = &mut ping {
+                                ping.last_ping = last_message;
+                                ping.interval
+                            } else {
+                                LONG_SLUMBER
+                            }
                         } else {
                             throttle - elapsed
                         }
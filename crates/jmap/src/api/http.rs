@@ -21,11 +21,17 @@
  * for more details.
 */
 
-use std::{net::IpAddr, sync::Arc};
+use std::{
+    net::IpAddr,
+    ops::Range,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
 
 use http_body_util::{BodyExt, Full};
 use hyper::{
-    body::{self, Bytes},
+    body::{self, Bytes, Frame},
     header::{self, CONTENT_TYPE},
     server::conn::http1,
     service::service_fn,
@@ -38,19 +44,29 @@ use jmap_proto::{
     response::Response,
     types::{blob::BlobId, id::Id},
 };
+use tokio::sync::mpsc;
 
-use utils::listener::{ServerInstance, SessionData, SessionManager, SessionStream};
+use utils::{
+    listener::{ServerInstance, SessionData, SessionManager, SessionStream},
+    map::ttl_dashmap::TtlMap,
+};
 
 use crate::{
-    auth::{oauth::OAuthMetadata, AccessToken},
+    auth::{
+        oauth::OAuthMetadata,
+        rate_limit::{HttpEndpoint, RateLimitKey},
+        AccessToken,
+    },
     blob::{DownloadResponse, UploadResponse},
+    email::proxy::ProxiedImage,
     services::state,
     websocket::upgrade::upgrade_websocket_connection,
     JMAP,
 };
 
 use super::{
-    session::Session, HtmlResponse, HttpRequest, HttpResponse, JmapSessionManager, JsonResponse,
+    session::Session, HtmlResponse, HttpBodyError, HttpRequest, HttpResponse, JmapSessionManager,
+    JsonResponse, TextResponse, XmlResponse,
 };
 
 pub async fn parse_jmap_request(
@@ -77,6 +93,7 @@ pub async fn parse_jmap_request(
                 }
                 Err(err) => return err.into_http_response(),
             };
+            let remote_ip = jmap.build_remote_addr(&req, remote_ip);
 
             match (path.next().unwrap_or(""), req.method()) {
                 ("", &Method::POST) => {
@@ -93,7 +110,10 @@ pub async fn parse_jmap_request(
                         Ok(request) => {
                             //let _ = println!("<- {}", String::from_utf8_lossy(&bytes));
 
-                            match jmap.handle_request(request, access_token, &instance).await {
+                            match jmap
+                                .handle_request(request, access_token, &instance, remote_ip)
+                                .await
+                            {
                                 Ok(response) => response.into_http_response(),
                                 Err(err) => err.into_http_response(),
                             }
@@ -107,21 +127,58 @@ pub async fn parse_jmap_request(
                         path.next().and_then(BlobId::from_base32),
                         path.next(),
                     ) {
-                        return match jmap.blob_download(&blob_id, &access_token).await {
-                            Ok(Some(blob)) => DownloadResponse {
-                                filename: name.to_string(),
-                                content_type: req
-                                    .uri()
-                                    .query()
-                                    .and_then(|q| {
-                                        form_urlencoded::parse(q.as_bytes())
-                                            .find(|(k, _)| k == "accept")
-                                            .map(|(_, v)| v.into_owned())
-                                    })
-                                    .unwrap_or("application/octet-stream".to_string()),
-                                blob,
+                        return match jmap.blob_download_stream(&blob_id, &access_token).await {
+                            Ok(Some(blob)) => {
+                                // Serve a single byte range if requested
+                                let size = blob.len();
+                                let (blob, range) = match req
+                                    .headers()
+                                    .get(header::RANGE)
+                                    .and_then(|h| h.to_str().ok())
+                                    .map(|h| parse_byte_range(h, size))
+                                {
+                                    Some(Ok(Some(range))) => {
+                                        (blob.narrow(range.clone()), Some((range, size)))
+                                    }
+                                    Some(Err(_)) => {
+                                        return hyper::Response::builder()
+                                            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                                            .header(
+                                                header::CONTENT_RANGE,
+                                                format!("bytes */{size}"),
+                                            )
+                                            .body(
+                                                Full::new(Bytes::new())
+                                                    .map_err(|never| match never {})
+                                                    .boxed(),
+                                            )
+                                            .unwrap();
+                                    }
+                                    _ => (blob, None),
+                                };
+                                jmap.throttle_download(
+                                    &access_token,
+                                    &instance,
+                                    blob.len() as usize,
+                                )
+                                .await;
+
+                                DownloadResponse {
+                                    filename: name.to_string(),
+                                    content_type: req
+                                        .uri()
+                                        .query()
+                                        .and_then(|q| {
+                                            form_urlencoded::parse(q.as_bytes())
+                                                .find(|(k, _)| k == "accept")
+                                                .map(|(_, v)| v.into_owned())
+                                        })
+                                        .unwrap_or("application/octet-stream".to_string()),
+                                    blob,
+                                    range,
+                                }
+                                .into_http_response()
                             }
-                            .into_http_response(),
                             Ok(None) => RequestError::not_found().into_http_response(),
                             Err(_) => RequestError::internal_server_error().into_http_response(),
                         };
@@ -138,6 +195,9 @@ pub async fn parse_jmap_request(
                         .await
                         {
                             Some(bytes) => {
+                                jmap.throttle_upload(&access_token, &instance, bytes.len())
+                                    .await;
+
                                 match jmap
                                     .blob_upload(
                                         account_id,
@@ -159,12 +219,26 @@ pub async fn parse_jmap_request(
                         };
                     }
                 }
+                ("proxy", &Method::GET) if jmap.config.image_proxy_enable => {
+                    if let (Some(signature), Some(url)) = (path.next(), path.next()) {
+                        return match jmap.image_proxy_fetch(signature, url).await {
+                            Ok(image) => image.into_http_response(),
+                            Err(err) => err.into_http_response(),
+                        };
+                    }
+                }
                 ("eventsource", &Method::GET) => {
                     return jmap.handle_event_source(req, access_token).await
                 }
                 ("ws", &Method::GET) => {
-                    return upgrade_websocket_connection(jmap, req, access_token, instance.clone())
-                        .await;
+                    return upgrade_websocket_connection(
+                        jmap,
+                        req,
+                        access_token,
+                        instance.clone(),
+                        remote_ip,
+                    )
+                    .await;
                 }
                 (_, &Method::OPTIONS) => {
                     return ().into_http_response();
@@ -197,11 +271,48 @@ pub async fn parse_jmap_request(
                     Err(err) => err.into_http_response(),
                 };
             }
+            ("mta-sts.txt", &Method::GET) => {
+                if let Some(policy) = &jmap.smtp.mail_auth.mta_sts {
+                    return TextResponse::new("text/plain", policy.to_string())
+                        .into_http_response();
+                }
+            }
+            ("autoconfig", &Method::GET) if jmap.config.autoconfig_enable => {
+                if let (Some("mail"), Some("config-v1.1.xml")) = (path.next(), path.next()) {
+                    let remote_addr = jmap.build_remote_addr(&req, remote_ip);
+                    return match jmap.is_anonymous_allowed(&remote_addr) {
+                        Ok(_) => jmap.handle_autoconfig_request(&req).await,
+                        Err(err) => err.into_http_response(),
+                    };
+                }
+            }
             (_, &Method::OPTIONS) => {
                 return ().into_http_response();
             }
             _ => (),
         },
+        "mail" if jmap.config.autoconfig_enable => {
+            if let ("config-v1.1.xml", &Method::GET) = (path.next().unwrap_or(""), req.method()) {
+                let remote_addr = jmap.build_remote_addr(&req, remote_ip);
+                return match jmap.is_anonymous_allowed(&remote_addr) {
+                    Ok(_) => jmap.handle_autoconfig_request(&req).await,
+                    Err(err) => err.into_http_response(),
+                };
+            }
+        }
+        "autodiscover" | "Autodiscover" if jmap.config.autoconfig_enable => {
+            if path
+                .next()
+                .map_or(false, |p| p.eq_ignore_ascii_case("autodiscover.xml"))
+                && *req.method() == Method::POST
+            {
+                let remote_addr = jmap.build_remote_addr(&req, remote_ip);
+                return match jmap.is_anonymous_allowed(&remote_addr) {
+                    Ok(_) => jmap.handle_autodiscover_request(&mut req).await,
+                    Err(err) => err.into_http_response(),
+                };
+            }
+        }
         "auth" => {
             let remote_addr = jmap.build_remote_addr(&req, remote_ip);
 
@@ -267,17 +378,76 @@ pub async fn parse_jmap_request(
                 _ => (),
             }
         }
+        "recovery" if jmap.config.recovery_enable => {
+            let remote_addr = jmap.build_remote_addr(&req, remote_ip);
+
+            return jmap
+                .handle_recovery_request(&mut req, remote_ip, remote_addr)
+                .await;
+        }
+        "step-up" if jmap.config.step_up_enable => {
+            let remote_addr = jmap.build_remote_addr(&req, remote_ip);
+
+            return jmap
+                .handle_step_up_request(&mut req, remote_ip, remote_addr)
+                .await;
+        }
+        "metrics" if jmap.config.metrics_enable && req.method() == Method::GET => {
+            let remote_addr = jmap.build_remote_addr(&req, remote_ip);
+
+            return jmap.handle_metrics_request(&req, remote_addr).await;
+        }
+        "api" => {
+            let access_token = match jmap.authenticate_headers(&req, remote_ip).await {
+                Ok(Some((_, access_token))) => access_token,
+                Ok(None) => return RequestError::unauthorized().into_http_response(),
+                Err(err) => return err.into_http_response(),
+            };
+
+            if let (Some("queue"), Some("scheduled")) = (path.next(), path.next()) {
+                return jmap
+                    .handle_queue_scheduled_request(&req, access_token)
+                    .await;
+            }
+        }
         "admin" => {
-            // Make sure the user is a superuser
-            let body = match jmap.authenticate_headers(&req, remote_ip).await {
-                Ok(Some((_, access_token))) if access_token.is_super_user() => {
-                    fetch_body(&mut req, 8192, &access_token).await
-                }
-                Ok(_) => return RequestError::unauthorized().into_http_response(),
+            let access_token = match jmap.authenticate_headers(&req, remote_ip).await {
+                Ok(Some((_, access_token))) => access_token,
+                Ok(None) => return RequestError::unauthorized().into_http_response(),
                 Err(err) => return err.into_http_response(),
             };
 
-            return jmap.handle_manage_request(&req, body).await;
+            // Compliance officers and queue inspectors are authorized separately
+            match (path.next(), path.next()) {
+                (Some("compliance"), _) => {
+                    let remote_addr = jmap.build_remote_addr(&req, remote_ip);
+                    return jmap
+                        .handle_compliance_request(&mut req, access_token, remote_addr)
+                        .await;
+                }
+                (Some("queue"), Some("source")) if req.method() == Method::GET => {
+                    return jmap.handle_queue_source_request(&req, access_token).await;
+                }
+                _ => (),
+            }
+
+            // Make sure the user is a superuser
+            if !access_token.is_super_user() {
+                return RequestError::unauthorized().into_http_response();
+            }
+
+            // Destructive operations require a recent second-factor verification
+            if let Err(response) = jmap
+                .assert_step_up(&req, &access_token, jmap.build_remote_addr(&req, remote_ip))
+                .await
+            {
+                return response;
+            }
+            let body = fetch_body(&mut req, 8192, &access_token).await;
+
+            return jmap
+                .handle_idempotent_request(&req, &access_token, body)
+                .await;
         }
         _ => (),
     }
@@ -320,9 +490,38 @@ async fn handle_request<T: SessionStream>(jmap: Arc<JMAP>, session: SessionData<
                         uri = req.uri().to_string(),
                     );
 
+                    // Enforce endpoint rate limits
+                    let endpoint = HttpEndpoint::parse(req.method(), req.uri().path());
+                    let remote_addr = jmap.build_remote_addr(&req, session.remote_ip);
+                    let auth_token = req
+                        .headers()
+                        .get(header::AUTHORIZATION)
+                        .and_then(|h| h.to_str().ok())
+                        .and_then(|h| h.split_once(' ').map(|(_, t)| t.trim().to_string()));
+                    let rate_limit_key = |jmap: &JMAP| {
+                        auth_token
+                            .as_ref()
+                            .and_then(|token| jmap.sessions.get_with_ttl(token))
+                            .map(RateLimitKey::Account)
+                            .unwrap_or(RateLimitKey::Address(remote_addr))
+                    };
+
                     // Parse JMAP request
-                    let mut response =
-                        parse_jmap_request(jmap.clone(), req, session.remote_ip, instance).await;
+                    let mut response = match endpoint.map_or(Ok(()), |endpoint| {
+                        jmap.is_endpoint_allowed(endpoint, rate_limit_key(&jmap))
+                    }) {
+                        Ok(_) => {
+                            parse_jmap_request(jmap.clone(), req, session.remote_ip, instance).await
+                        }
+                        Err(err) => err.into_http_response(),
+                    };
+
+                    // Add rate limit headers
+                    if let Some(status) = endpoint.and_then(|endpoint| {
+                        jmap.rate_limit_status(endpoint, rate_limit_key(&jmap))
+                    }) {
+                        status.write_headers(response.headers_mut());
+                    }
 
                     // Add custom headers
                     if !jmap.config.http_headers.is_empty() {
@@ -412,6 +611,25 @@ impl HtmlResponse {
     }
 }
 
+impl XmlResponse {
+    pub fn new(body: String) -> Self {
+        XmlResponse {
+            body,
+            status: StatusCode::OK,
+        }
+    }
+}
+
+impl TextResponse {
+    pub fn new(content_type: &'static str, body: String) -> Self {
+        TextResponse {
+            body,
+            content_type,
+            status: StatusCode::OK,
+        }
+    }
+}
+
 impl ToHttpResponse for Response {
     fn into_http_response(self) -> HttpResponse {
         //let c = println!("-> {}", serde_json::to_string_pretty(&self).unwrap());
@@ -428,9 +646,49 @@ impl ToHttpResponse for Session {
 
 impl ToHttpResponse for DownloadResponse {
     fn into_http_response(self) -> HttpResponse {
-        hyper::Response::builder()
-            .status(StatusCode::OK)
+        let mut builder = hyper::Response::builder();
+        if let Some((range, size)) = &self.range {
+            builder = builder.status(StatusCode::PARTIAL_CONTENT).header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{size}", range.start, range.end - 1),
+            );
+        } else {
+            builder = builder.status(StatusCode::OK);
+        }
+
+        // Stream the blob through a bounded channel
+        let (tx, rx) = mpsc::channel(2);
+        let mut blob = self.blob;
+        let len = blob.len();
+        tokio::spawn(async move {
+            loop {
+                match blob.next_chunk().await {
+                    Ok(Some(chunk)) => {
+                        if tx.send(Ok(Bytes::from(chunk))).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(err) => {
+                        tracing::error!(event = "error",
+                                        context = "blob_download",
+                                        error = ?err,
+                                        "Failed to read blob");
+
+                        // Abort the response rather than ending it short of its Content-Length
+                        let _ = tx
+                            .send(Err(std::io::Error::other("Failed to read blob").into()))
+                            .await;
+                        break;
+                    }
+                }
+            }
+        });
+
+        builder
             .header(header::CONTENT_TYPE, self.content_type)
+            .header(header::CONTENT_LENGTH, len)
+            .header(header::ACCEPT_RANGES, "bytes")
             .header(
                 header::CONTENT_DISPOSITION,
                 format!(
@@ -442,8 +700,74 @@ impl ToHttpResponse for DownloadResponse {
                 header::CACHE_CONTROL,
                 "private, immutable, max-age=31536000",
             )
+            .body(ChannelBody { rx }.boxed())
+            .unwrap()
+    }
+}
+
+struct ChannelBody {
+    rx: mpsc::Receiver<Result<Bytes, HttpBodyError>>,
+}
+
+impl body::Body for ChannelBody {
+    type Data = Bytes;
+    type Error = HttpBodyError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        self.rx
+            .poll_recv(cx)
+            .map(|chunk| chunk.map(|chunk| chunk.map(Frame::data)))
+    }
+}
+
+// Parses a single "bytes" range, returns `Ok(None)` for ranges that are
+// not supported (such as multiple ranges) and should be ignored.
+fn parse_byte_range(value: &str, size: u64) -> Result<Option<Range<u64>>, ()> {
+    let (start, end) = match value
+        .trim()
+        .strip_prefix("bytes=")
+        .filter(|spec| !spec.contains(','))
+        .and_then(|spec| spec.split_once('-'))
+    {
+        Some((start, end)) => (start.trim(), end.trim()),
+        None => return Ok(None),
+    };
+
+    let range = match (start.parse::<u64>().ok(), end.parse::<u64>().ok()) {
+        (Some(start), None) if end.is_empty() => start..size,
+        (Some(start), Some(end)) if start <= end => start..std::cmp::min(end + 1, size),
+        (None, Some(suffix)) if start.is_empty() => {
+            if suffix == 0 {
+                return Err(());
+            }
+            size.saturating_sub(suffix)..size
+        }
+        _ => return Ok(None),
+    };
+
+    if range.start < size {
+        Ok(Some(range))
+    } else {
+        Err(())
+    }
+}
+
+impl ToHttpResponse for ProxiedImage {
+    fn into_http_response(self) -> HttpResponse {
+        hyper::Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, self.content_type)
+            .header(header::CACHE_CONTROL, "private, max-age=86400")
+            .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+            .header(
+                header::CONTENT_SECURITY_POLICY,
+                "default-src 'none'; sandbox",
+            )
             .body(
-                Full::new(Bytes::from(self.blob))
+                Full::new(self.contents)
                     .map_err(|never| match never {})
                     .boxed(),
             )
@@ -459,9 +783,13 @@ impl ToHttpResponse for UploadResponse {
 
 impl ToHttpResponse for RequestError {
     fn into_http_response(self) -> HttpResponse {
-        hyper::Response::builder()
+        let mut builder = hyper::Response::builder()
             .status(StatusCode::from_u16(self.status).unwrap())
-            .header(header::CONTENT_TYPE, "application/problem+json")
+            .header(header::CONTENT_TYPE, "application/problem+json");
+        if let Some(retry_after) = self.retry_after {
+            builder = builder.header(header::RETRY_AFTER, retry_after);
+        }
+        builder
             .body(
                 Full::new(Bytes::from(serde_json::to_string(&self).unwrap()))
                     .map_err(|never| match never {})
@@ -485,6 +813,34 @@ impl ToHttpResponse for HtmlResponse {
     }
 }
 
+impl ToHttpResponse for XmlResponse {
+    fn into_http_response(self) -> HttpResponse {
+        hyper::Response::builder()
+            .status(self.status)
+            .header(header::CONTENT_TYPE, "application/xml; charset=utf-8")
+            .body(
+                Full::new(Bytes::from(self.body))
+                    .map_err(|never| match never {})
+                    .boxed(),
+            )
+            .unwrap()
+    }
+}
+
+impl ToHttpResponse for TextResponse {
+    fn into_http_response(self) -> HttpResponse {
+        hyper::Response::builder()
+            .status(self.status)
+            .header(header::CONTENT_TYPE, self.content_type)
+            .body(
+                Full::new(Bytes::from(self.body))
+                    .map_err(|never| match never {})
+                    .boxed(),
+            )
+            .unwrap()
+    }
+}
+
 impl ToHttpResponse for () {
     fn into_http_response(self) -> HttpResponse {
         hyper::Response::builder()
@@ -0,0 +1,282 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use http_body_util::{BodyExt, Full};
+use hyper::{body::Bytes, header, Method, StatusCode};
+use jmap_proto::error::request::RequestError;
+use store::{LookupKey, LookupValue};
+
+use crate::{auth::AccessToken, JMAP};
+
+use super::{http::ToHttpResponse, HttpRequest, HttpResponse};
+
+pub const KV_IDEMPOTENCY: &[u8] = b"idem:";
+pub const IDEMPOTENCY_KEY: &str = "Idempotency-Key";
+pub const IDEMPOTENT_REPLAYED: &str = "Idempotent-Replayed";
+
+// Requests still being processed block their key for this long, so
+// an interrupted request can eventually be retried.
+const IN_FLIGHT_EXPIRY: u64 = 60;
+const MAX_KEY_LENGTH: usize = 255;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct CachedResponse {
+    fingerprint: String,
+    #[serde(default)]
+    status: u16,
+    #[serde(default)]
+    content_type: Option<String>,
+    #[serde(default, with = "base64_body")]
+    body: Vec<u8>,
+}
+
+// Response bodies are stored base64 encoded, so that replays return
+// exactly the original bytes even when they are not valid UTF-8.
+mod base64_body {
+    use base64::{engine::general_purpose, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(body: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&general_purpose::STANDARD.encode(body))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        general_purpose::STANDARD
+            .decode(String::deserialize(deserializer)?)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl JMAP {
+    pub async fn handle_idempotent_request(
+        self: &Arc<Self>,
+        req: &HttpRequest,
+        access_token: &AccessToken,
+        body: Option<Vec<u8>>,
+    ) -> HttpResponse {
+        // Only mutations that include an idempotency key are cached
+        let key = match req.headers().get(IDEMPOTENCY_KEY) {
+            Some(key)
+                if self.config.idempotency_enable
+                    && matches!(req.method(), &Method::POST | &Method::PUT | &Method::PATCH) =>
+            {
+                match key.to_str() {
+                    Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LENGTH => key,
+                    _ => {
+                        return RequestError::blank(
+                            StatusCode::BAD_REQUEST.as_u16(),
+                            "Invalid Idempotency-Key",
+                            "The Idempotency-Key header must contain between 1 and 255 visible characters.",
+                        )
+                        .into_http_response()
+                    }
+                }
+            }
+            _ => return self.handle_manage_request(req, body).await,
+        };
+
+        // Keys are scoped to the authenticated account
+        let mut kv_key = Vec::with_capacity(KV_IDEMPOTENCY.len() + std::mem::size_of::<u32>() + 32);
+        kv_key.extend_from_slice(KV_IDEMPOTENCY);
+        kv_key.extend_from_slice(&access_token.primary_id.to_be_bytes());
+        kv_key.extend_from_slice(blake3::hash(key.as_bytes()).as_bytes());
+
+        // Replays are only allowed for the same request
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(req.method().as_str().as_bytes());
+        hasher.update(b" ");
+        hasher.update(
+            req.uri()
+                .path_and_query()
+                .map(|p| p.as_str())
+                .unwrap_or_default()
+                .as_bytes(),
+        );
+        hasher.update(b"\n");
+        if let Some(body) = &body {
+            hasher.update(body);
+        }
+        let fingerprint = hasher.finalize().to_hex().to_string();
+
+        let store = &self.smtp.queue.config.lookup_store;
+        let pending = serde_json::to_vec(&CachedResponse {
+            fingerprint: fingerprint.clone(),
+            status: 0,
+            content_type: None,
+            body: Vec::new(),
+        })
+        .unwrap_or_default();
+        match store
+            .key_insert(kv_key.clone(), pending, IN_FLIGHT_EXPIRY)
+            .await
+        {
+            Ok(true) => (),
+            Ok(false) => {
+                return match store.key_get::<String>(LookupKey::Key(kv_key)).await {
+                    Ok(LookupValue::Value { value, .. }) => {
+                        match serde_json::from_str::<CachedResponse>(&value) {
+                            Ok(cached) if cached.fingerprint != fingerprint => RequestError::blank(
+                                StatusCode::UNPROCESSABLE_ENTITY.as_u16(),
+                                "Idempotency-Key reused",
+                                "This Idempotency-Key was used with a different request.",
+                            )
+                            .into_http_response(),
+                            Ok(cached) if cached.status == 0 => RequestError::blank(
+                                StatusCode::CONFLICT.as_u16(),
+                                "Request in progress",
+                                "A request with this Idempotency-Key is still being processed.",
+                            )
+                            .into_http_response(),
+                            Ok(cached) => cached.into_http_response(),
+                            Err(_) => RequestError::internal_server_error().into_http_response(),
+                        }
+                    }
+                    Ok(_) => RequestError::blank(
+                        StatusCode::CONFLICT.as_u16(),
+                        "Request in progress",
+                        "A request with this Idempotency-Key is still being processed.",
+                    )
+                    .into_http_response(),
+                    Err(err) => {
+                        tracing::warn!(
+                            context = "idempotency",
+                            event = "error",
+                            reason = %err,
+                            "Failed to fetch cached response."
+                        );
+                        RequestError::internal_server_error().into_http_response()
+                    }
+                };
+            }
+            Err(err) => {
+                tracing::warn!(
+                    context = "idempotency",
+                    event = "error",
+                    reason = %err,
+                    "Failed to reserve idempotency key."
+                );
+                return RequestError::internal_server_error().into_http_response();
+            }
+        }
+
+        // Execute the request and cache its result
+        let (parts, response_body) = self.handle_manage_request(req, body).await.into_parts();
+        let response_body = match response_body.collect().await {
+            Ok(response_body) => response_body.to_bytes(),
+            Err(_) => return RequestError::internal_server_error().into_http_response(),
+        };
+        let cached = CachedResponse {
+            fingerprint,
+            status: parts.status.as_u16(),
+            content_type: parts
+                .headers
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string()),
+            body: response_body.to_vec(),
+        };
+
+        // Server errors are not cached, allowing the request to be retried
+        let expires = if !parts.status.is_server_error() {
+            self.config.idempotency_ttl.as_secs()
+        } else {
+            1
+        };
+        if let Err(err) = store
+            .key_set(
+                kv_key,
+                LookupValue::Value {
+                    value: serde_json::to_vec(&cached).unwrap_or_default(),
+                    expires,
+                },
+            )
+            .await
+        {
+            tracing::warn!(
+                context = "idempotency",
+                event = "error",
+                reason = %err,
+                "Failed to cache response."
+            );
+        }
+
+        hyper::Response::from_parts(
+            parts,
+            Full::new(response_body)
+                .map_err(|never| match never {})
+                .boxed(),
+        )
+    }
+}
+
+impl ToHttpResponse for CachedResponse {
+    fn into_http_response(self) -> HttpResponse {
+        let mut builder = hyper::Response::builder()
+            .status(StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK))
+            .header(IDEMPOTENT_REPLAYED, "true");
+        if let Some(content_type) = self.content_type {
+            builder = builder.header(header::CONTENT_TYPE, content_type);
+        }
+        builder
+            .body(
+                Full::new(Bytes::from(self.body))
+                    .map_err(|never| match never {})
+                    .boxed(),
+            )
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http_body_util::BodyExt;
+
+    use super::CachedResponse;
+    use crate::api::http::ToHttpResponse;
+
+    #[test]
+    fn replay_binary_body() {
+        let body = vec![b'{', 0xff, 0xfe, 0x00, b'}'];
+        let cached = serde_json::to_string(&CachedResponse {
+            fingerprint: "abc".to_string(),
+            status: 200,
+            content_type: "application/octet-stream".to_string().into(),
+            body: body.clone(),
+        })
+        .unwrap();
+        let cached = serde_json::from_str::<CachedResponse>(&cached).unwrap();
+        assert_eq!(cached.body, body);
+
+        let response = cached.into_http_response();
+        assert_eq!(response.headers()["Idempotent-Replayed"], "true");
+        let replayed = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(response.into_body().collect())
+            .unwrap()
+            .to_bytes();
+        assert_eq!(replayed, body);
+    }
+}
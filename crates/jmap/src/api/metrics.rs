@@ -0,0 +1,328 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::net::IpAddr;
+
+use directory::backend::sql::health;
+use hyper::header::AUTHORIZATION;
+use jmap_proto::error::request::RequestError;
+use store::{ahash::AHashMap, blake3};
+use utils::{
+    config::Config,
+    listener::{bandwidth, vhost},
+    map::stats::to_prometheus,
+};
+
+use crate::JMAP;
+
+use super::{http::ToHttpResponse, HttpRequest, HttpResponse, TextResponse};
+
+// Scrape tokens for the Prometheus endpoint. Tokens may be restricted to a
+// list of metric families, and labels can be dropped to reduce cardinality.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsToken {
+    pub name: String,
+    pub secret: [u8; 32],
+    pub families: Vec<String>,
+    pub drop_labels: Vec<String>,
+}
+
+const SAMPLE_SUFFIXES: [&str; 5] = ["_total", "_bucket", "_sum", "_count", "_created"];
+
+impl JMAP {
+    pub async fn handle_metrics_request(
+        &self,
+        req: &HttpRequest,
+        remote_addr: IpAddr,
+    ) -> HttpResponse {
+        if let Err(err) = self.is_auth_allowed_soft(&remote_addr) {
+            return err.into_http_response();
+        }
+
+        let secret = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|header| header.to_str().ok())
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .map(|secret| *blake3::hash(secret.trim().as_bytes()).as_bytes());
+        let token = if let Some(token) = secret.and_then(|secret| {
+            self.config
+                .metrics_tokens
+                .iter()
+                .find(|token| token.secret == secret)
+        }) {
+            token
+        } else {
+            return match self.is_auth_allowed_hard(&remote_addr) {
+                Ok(_) => RequestError::unauthorized(),
+                Err(err) => err,
+            }
+            .into_http_response();
+        };
+
+        tracing::debug!(
+            context = "metrics",
+            event = "scrape",
+            token = token.name,
+            remote_addr = remote_addr.to_string(),
+        );
+
+        let metrics = self.prometheus_metrics();
+        TextResponse::new(
+            "text/plain; version=0.0.4",
+            if !token.families.is_empty() || !token.drop_labels.is_empty() {
+                token.filter(&metrics)
+            } else {
+                metrics
+            },
+        )
+        .into_http_response()
+    }
+
+    pub(crate) fn prometheus_metrics(&self) -> String {
+        let mut metrics = to_prometheus(&self.cache_reports());
+        if let Some(report) = self.directory.query_report() {
+            metrics.push_str(&health::to_prometheus(&report));
+        }
+        metrics.push_str(&bandwidth::to_prometheus());
+        metrics.push_str(&vhost::to_prometheus());
+        metrics.push_str(&self.smtp.queue.delivery_latency_prometheus());
+        metrics
+    }
+}
+
+impl MetricsToken {
+    pub fn parse(config: &Config) -> utils::config::Result<Vec<Self>> {
+        let mut tokens = Vec::new();
+        for name in config.sub_keys("jmap.metrics.tokens", ".secret") {
+            let secret = config.value_require(("jmap.metrics.tokens", name, "secret"))?;
+            if secret.is_empty() {
+                return Err(format!("Empty secret for metrics token {name:?}."));
+            }
+            tokens.push(MetricsToken {
+                name: name.to_string(),
+                secret: *blake3::hash(secret.as_bytes()).as_bytes(),
+                families: config
+                    .values(("jmap.metrics.tokens", name, "families"))
+                    .map(|(_, family)| family.trim().to_string())
+                    .collect(),
+                drop_labels: config
+                    .values(("jmap.metrics.tokens", name, "drop-labels"))
+                    .map(|(_, label)| label.trim().to_string())
+                    .collect(),
+            });
+        }
+
+        Ok(tokens)
+    }
+
+    fn allows(&self, name: &str) -> bool {
+        self.families.is_empty()
+            || self.families.iter().any(|family| {
+                if let Some(prefix) = family.strip_suffix('*') {
+                    name.starts_with(prefix)
+                } else {
+                    name.strip_prefix(family.as_str()).map_or(false, |suffix| {
+                        suffix.is_empty() || SAMPLE_SUFFIXES.contains(&suffix)
+                    })
+                }
+            })
+    }
+
+    // Removes the metric families not included in the allowlist and drops the
+    // configured labels, adding up the values of series that become identical.
+    pub fn filter(&self, metrics: &str) -> String {
+        let mut lines: Vec<(String, Option<f64>)> = Vec::new();
+        let mut series: AHashMap<String, usize> = AHashMap::new();
+        let mut include = true;
+
+        for line in metrics.lines() {
+            if let Some(comment) = line.strip_prefix('#') {
+                // HELP and TYPE lines are followed by the metric family name
+                let mut parts = comment.split_whitespace();
+                if matches!(parts.next(), Some("HELP" | "TYPE")) {
+                    include = parts.next().map_or(false, |name| self.allows(name));
+                }
+                if include {
+                    lines.push((line.to_string(), None));
+                }
+                continue;
+            } else if line.trim().is_empty() {
+                continue;
+            }
+
+            let name_end = line.find(['{', ' ']).unwrap_or(line.len());
+            if !include || !self.allows(&line[..name_end]) {
+                continue;
+            }
+
+            let (sample, value) = match split_sample(line) {
+                Some((name, labels, value)) if !self.drop_labels.is_empty() => {
+                    let labels = labels
+                        .into_iter()
+                        .filter(|label| {
+                            label.split_once('=').map_or(true, |(key, _)| {
+                                !self.drop_labels.iter().any(|drop| drop == key.trim())
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    if labels.is_empty() {
+                        (name.to_string(), value)
+                    } else {
+                        (format!("{name}{{{}}}", labels.join(",")), value)
+                    }
+                }
+                _ => {
+                    lines.push((line.to_string(), None));
+                    continue;
+                }
+            };
+
+            match (value.parse::<f64>(), series.get(&sample)) {
+                (Ok(value), Some(idx)) => {
+                    if let Some(total) = &mut lines[*idx].1 {
+                        *total += value;
+                    }
+                }
+                (Ok(value), None) => {
+                    series.insert(sample.clone(), lines.len());
+                    lines.push((sample, Some(value)));
+                }
+                (Err(_), _) => {
+                    lines.push((format!("{sample} {value}"), None));
+                }
+            }
+        }
+
+        let mut out = String::with_capacity(metrics.len());
+        for (line, value) in lines {
+            out.push_str(&line);
+            if let Some(value) = value {
+                out.push(' ');
+                if value.is_infinite() {
+                    out.push_str(if value > 0.0 { "+Inf" } else { "-Inf" });
+                } else {
+                    out.push_str(&value.to_string());
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+// Splits a sample line into its name, labels and value, timestamps are not supported.
+fn split_sample(line: &str) -> Option<(&str, Vec<&str>, &str)> {
+    if let Some((name, rest)) = line.split_once('{') {
+        let mut labels = Vec::new();
+        let mut start = 0;
+        let mut in_quotes = false;
+        let mut escaped = false;
+        for (pos, ch) in rest.char_indices() {
+            match ch {
+                _ if escaped => escaped = false,
+                '\\' if in_quotes => escaped = true,
+                '"' => in_quotes = !in_quotes,
+                ',' if !in_quotes => {
+                    labels.push(rest[start..pos].trim());
+                    start = pos + 1;
+                }
+                '}' if !in_quotes => {
+                    let label = rest[start..pos].trim();
+                    if !label.is_empty() {
+                        labels.push(label);
+                    }
+                    let value = rest[pos + 1..].trim();
+                    return (!value.is_empty() && !value.contains(' '))
+                        .then_some((name, labels, value));
+                }
+                _ => (),
+            }
+        }
+        None
+    } else {
+        let (name, value) = line.split_once(' ')?;
+        let value = value.trim();
+        (!value.contains(' ')).then_some((name, Vec::new(), value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MetricsToken;
+
+    const METRICS: &str = concat!(
+        "# HELP stalwart_cache_hits_total Number of cache lookups.\n",
+        "# TYPE stalwart_cache_hits_total counter\n",
+        "stalwart_cache_hits_total{cache=\"dns\",key=\"a,b\"} 3\n",
+        "stalwart_cache_hits_total{cache=\"dns\",key=\"c\"} 4\n",
+        "stalwart_cache_hits_total{cache=\"acl\",key=\"c\"} 1\n",
+        "# HELP stalwart_queue_messages Messages in the queue.\n",
+        "# TYPE stalwart_queue_messages gauge\n",
+        "stalwart_queue_messages{domain=\"example.org\"} 10\n",
+        "stalwart_queue_messages{domain=\"example.net\"} 5\n",
+    );
+
+    #[test]
+    fn filter_metrics() {
+        // Families allowlist
+        let token = MetricsToken {
+            families: vec!["stalwart_queue_*".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            token.filter(METRICS),
+            concat!(
+                "# HELP stalwart_queue_messages Messages in the queue.\n",
+                "# TYPE stalwart_queue_messages gauge\n",
+                "stalwart_queue_messages{domain=\"example.org\"} 10\n",
+                "stalwart_queue_messages{domain=\"example.net\"} 5\n",
+            )
+        );
+
+        // Exact family names match their samples
+        let token = MetricsToken {
+            families: vec!["stalwart_cache_hits".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(token.filter(METRICS).lines().count(), 5);
+
+        // Dropped labels are aggregated
+        let token = MetricsToken {
+            drop_labels: vec!["key".to_string(), "domain".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            token.filter(METRICS),
+            concat!(
+                "# HELP stalwart_cache_hits_total Number of cache lookups.\n",
+                "# TYPE stalwart_cache_hits_total counter\n",
+                "stalwart_cache_hits_total{cache=\"dns\"} 7\n",
+                "stalwart_cache_hits_total{cache=\"acl\"} 1\n",
+                "# HELP stalwart_queue_messages Messages in the queue.\n",
+                "# TYPE stalwart_queue_messages gauge\n",
+                "stalwart_queue_messages 15\n",
+            )
+        );
+    }
+}
@@ -31,9 +31,13 @@ use utils::map::vec_map::VecMap;
 use crate::JMAP;
 
 pub mod admin;
+pub mod autoconfig;
 pub mod config;
 pub mod event_source;
 pub mod http;
+pub mod idempotency;
+pub mod metrics;
+pub mod queue;
 pub mod request;
 pub mod session;
 
@@ -58,9 +62,21 @@ pub struct HtmlResponse {
     body: String,
 }
 
+pub struct XmlResponse {
+    status: StatusCode,
+    body: String,
+}
+
+pub struct TextResponse {
+    status: StatusCode,
+    content_type: &'static str,
+    body: String,
+}
+
 pub type HttpRequest = hyper::Request<hyper::body::Incoming>;
+pub type HttpBodyError = Box<dyn std::error::Error + Send + Sync>;
 pub type HttpResponse =
-    hyper::Response<http_body_util::combinators::BoxBody<hyper::body::Bytes, hyper::Error>>;
+    hyper::Response<http_body_util::combinators::BoxBody<hyper::body::Bytes, HttpBodyError>>;
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub enum StateChangeType {
@@ -0,0 +1,337 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{sync::Arc, time::Instant};
+
+use directory::QueryBy;
+use http_body_util::{combinators::BoxBody, BodyExt, Full, StreamBody};
+use hyper::{
+    body::{Bytes, Frame},
+    header, Method, StatusCode,
+};
+use jmap_proto::error::request::RequestError;
+use mail_parser::{MessageParser, PartType};
+use serde_json::json;
+use smtp::core::management::ParseValues;
+use tokio::io::AsyncReadExt;
+
+use crate::{auth::AccessToken, JMAP};
+
+use super::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+impl JMAP {
+    pub async fn handle_queue_source_request(
+        &self,
+        req: &HttpRequest,
+        access_token: Arc<AccessToken>,
+    ) -> HttpResponse {
+        if !self.is_queue_inspector(&access_token) {
+            return RequestError::forbidden().into_http_response();
+        }
+
+        // Parse request, support staff can only obtain the message structure
+        let queue_id = match req
+            .uri()
+            .path()
+            .rsplit('/')
+            .next()
+            .and_then(|id| id.parse::<u64>().ok())
+        {
+            Some(queue_id) => queue_id,
+            None => return RequestError::not_found().into_http_response(),
+        };
+        let mut redact = !access_token.is_super_user();
+        for (key, value) in form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+        {
+            match (key.as_ref(), value.as_ref()) {
+                ("redact", "attachments" | "true") => {
+                    redact = true;
+                }
+                ("redact", "none" | "false") => (),
+                _ => {
+                    return RequestError::blank(
+                        StatusCode::BAD_REQUEST.as_u16(),
+                        "Invalid parameters",
+                        format!("Invalid parameter {key:?}."),
+                    )
+                    .into_http_response();
+                }
+            }
+        }
+
+        // Obtain the spool file from the queue manager
+        let (path, size) = match self.smtp.queue_message_source(queue_id).await {
+            Some(source) => source,
+            None => {
+                return RequestError::blank(
+                    StatusCode::NOT_FOUND.as_u16(),
+                    "Not found",
+                    "Queued message not found.",
+                )
+                .into_http_response()
+            }
+        };
+        let mut file = match tokio::fs::File::open(&path).await {
+            Ok(file) => file,
+            Err(err) => {
+                tracing::debug!(
+                    context = "queue",
+                    event = "error",
+                    path = path.display().to_string(),
+                    reason = %err,
+                    "Failed to open queued message."
+                );
+                return RequestError::blank(
+                    StatusCode::NOT_FOUND.as_u16(),
+                    "Not found",
+                    "Queued message not found.",
+                )
+                .into_http_response();
+            }
+        };
+
+        tracing::info!(
+            context = "queue",
+            event = "source",
+            account = access_token.name.as_str(),
+            queue_id = queue_id,
+            redact = redact,
+            "Queued message source accessed."
+        );
+
+        let body = if redact {
+            // Redaction requires parsing the entire message
+            let mut raw_message = vec![0u8; size];
+            if let Err(err) = file.read_exact(&mut raw_message).await {
+                tracing::debug!(
+                    context = "queue",
+                    event = "error",
+                    path = path.display().to_string(),
+                    reason = %err,
+                    "Failed to read queued message."
+                );
+                return RequestError::internal_server_error().into_http_response();
+            }
+            Full::new(Bytes::from(redact_attachments(&raw_message)))
+                .map_err(|never| match never {})
+                .boxed()
+        } else {
+            // Stream the message, skipping the queue metadata stored after it
+            BoxBody::new(StreamBody::new(async_stream::stream! {
+                let mut remaining = size;
+                while remaining > 0 {
+                    let mut buf = vec![0u8; std::cmp::min(remaining, CHUNK_SIZE)];
+                    match file.read(&mut buf).await {
+                        Ok(0) => break,
+                        Ok(br) => {
+                            buf.truncate(br);
+                            remaining -= br;
+                            yield Ok(Frame::data(Bytes::from(buf)));
+                        }
+                        Err(err) => {
+                            tracing::debug!(
+                                context = "queue",
+                                event = "error",
+                                reason = %err,
+                                "Failed to read queued message."
+                            );
+                            break;
+                        }
+                    }
+                }
+            }))
+        };
+
+        hyper::Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "message/rfc822")
+            .header(header::CACHE_CONTROL, "no-store")
+            .body(body)
+            .unwrap()
+    }
+
+    // Lists, reschedules and cancels messages held for future release. Superusers
+    // manage all scheduled messages, other accounts only those sent from one of
+    // their addresses.
+    pub async fn handle_queue_scheduled_request(
+        &self,
+        req: &HttpRequest,
+        access_token: Arc<AccessToken>,
+    ) -> HttpResponse {
+        let senders = if !access_token.is_super_user() {
+            match self
+                .directory
+                .query(QueryBy::Id(access_token.primary_id()), false)
+                .await
+            {
+                Ok(Some(principal)) => Some(
+                    principal
+                        .emails
+                        .into_iter()
+                        .map(|email| email.to_lowercase())
+                        .collect::<Vec<_>>(),
+                ),
+                Ok(None) => Some(vec![]),
+                Err(_) => return RequestError::internal_server_error().into_http_response(),
+            }
+        } else {
+            None
+        };
+
+        let queue_id = req
+            .uri()
+            .path()
+            .split('/')
+            .nth(4)
+            .filter(|id| !id.is_empty());
+        match (req.method(), queue_id) {
+            (&Method::GET, None) => match self.smtp.queue_scheduled(senders).await {
+                Some(messages) => JsonResponse::new(json!({
+                    "data": messages,
+                }))
+                .into_http_response(),
+                None => RequestError::internal_server_error().into_http_response(),
+            },
+            (&Method::PATCH | &Method::DELETE, Some(queue_id)) => {
+                let queue_id = match queue_id.parse::<u64>() {
+                    Ok(queue_id) => queue_id,
+                    Err(_) => return RequestError::not_found().into_http_response(),
+                };
+                let release = if req.method() == Method::PATCH {
+                    // Release immediately unless a new time is requested
+                    let mut release = Instant::now();
+                    for (key, value) in
+                        form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+                    {
+                        match key.as_ref() {
+                            "at" => match value.parse_timestamp() {
+                                Ok(at) => {
+                                    release = at;
+                                }
+                                Err(reason) => {
+                                    return RequestError::blank(
+                                        StatusCode::BAD_REQUEST.as_u16(),
+                                        "Invalid parameters",
+                                        reason,
+                                    )
+                                    .into_http_response();
+                                }
+                            },
+                            _ => {
+                                return RequestError::blank(
+                                    StatusCode::BAD_REQUEST.as_u16(),
+                                    "Invalid parameters",
+                                    format!("Invalid parameter {key:?}."),
+                                )
+                                .into_http_response();
+                            }
+                        }
+                    }
+                    Some(release)
+                } else {
+                    None
+                };
+
+                match self
+                    .smtp
+                    .queue_reschedule(vec![queue_id], senders, release)
+                    .await
+                {
+                    Some(result) if result.first().copied().unwrap_or_default() => {
+                        tracing::info!(
+                            context = "queue",
+                            event = if release.is_some() {
+                                "reschedule"
+                            } else {
+                                "cancel"
+                            },
+                            account = access_token.name.as_str(),
+                            queue_id = queue_id,
+                            "Scheduled message updated."
+                        );
+
+                        JsonResponse::new(json!({
+                            "data": true,
+                        }))
+                        .into_http_response()
+                    }
+                    Some(_) => RequestError::blank(
+                        StatusCode::NOT_FOUND.as_u16(),
+                        "Not found",
+                        "Scheduled message not found.",
+                    )
+                    .into_http_response(),
+                    None => RequestError::internal_server_error().into_http_response(),
+                }
+            }
+            _ => RequestError::not_found().into_http_response(),
+        }
+    }
+
+    pub fn is_queue_inspector(&self, access_token: &AccessToken) -> bool {
+        access_token.is_super_user()
+            || self
+                .config
+                .queue_inspectors
+                .iter()
+                .any(|name| name == &access_token.name)
+    }
+}
+
+// Replaces the contents of all attachments with a placeholder while
+// preserving the headers and MIME structure of the message.
+pub fn redact_attachments(raw_message: &[u8]) -> Vec<u8> {
+    let message = if let Some(message) = MessageParser::default().parse(raw_message) {
+        message
+    } else {
+        return raw_message.to_vec();
+    };
+    let mut redacted = Vec::with_capacity(raw_message.len());
+    let mut last_offset = 0;
+
+    for (part_id, part) in message.parts.iter().enumerate() {
+        let is_attachment = match &part.body {
+            PartType::Text(_) | PartType::Html(_) => {
+                !message.text_body.contains(&part_id) && !message.html_body.contains(&part_id)
+            }
+            PartType::Binary(_) | PartType::InlineBinary(_) | PartType::Message(_) => true,
+            PartType::Multipart(_) => false,
+        };
+        if is_attachment && part.offset_body >= last_offset && part.offset_end > part.offset_body {
+            redacted.extend_from_slice(&raw_message[last_offset..part.offset_body]);
+            redacted.extend_from_slice(
+                format!(
+                    "[Redacted {} bytes]\r\n",
+                    part.offset_end - part.offset_body
+                )
+                .as_bytes(),
+            );
+            last_offset = part.offset_end;
+        }
+    }
+    redacted.extend_from_slice(raw_message.get(last_offset..).unwrap_or_default());
+
+    redacted
+}
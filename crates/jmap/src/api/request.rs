@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::sync::Arc;
+use std::{net::IpAddr, sync::Arc};
 
 use jmap_proto::{
     error::{method::MethodError, request::RequestError},
@@ -33,9 +33,9 @@ use jmap_proto::{
     response::{Response, ResponseMethod},
     types::collection::Collection,
 };
-use utils::listener::ServerInstance;
+use utils::{config::ServerProtocol, listener::ServerInstance};
 
-use crate::{auth::AccessToken, JMAP};
+use crate::{activity::SetActivity, auth::AccessToken, JMAP};
 
 impl JMAP {
     pub async fn handle_request(
@@ -43,6 +43,7 @@ impl JMAP {
         request: Request,
         access_token: Arc<AccessToken>,
         instance: &Arc<ServerInstance>,
+        remote_ip: IpAddr,
     ) -> Result<Response, RequestError> {
         let mut response = Response::new(
             access_token.state(),
@@ -50,6 +51,7 @@ impl JMAP {
             request.method_calls.len(),
         );
         let add_created_ids = !response.created_ids.is_empty();
+        self.meter_api_calls(access_token.primary_id(), request.method_calls.len());
 
         for mut call in request.method_calls {
             // Resolve result and id references
@@ -60,6 +62,11 @@ impl JMAP {
 
             loop {
                 let mut next_call = None;
+                let set_activity = if self.config.activity_log_enable {
+                    SetActivity::new(&call.method)
+                } else {
+                    None
+                };
 
                 // Add response
                 match self
@@ -76,6 +83,13 @@ impl JMAP {
                                 if let Some(state_change) = set_response.state_change.take() {
                                     self.broadcast_state_change(state_change).await;
                                 }
+
+                                // Record activity
+                                if let Some(set_activity) = set_activity {
+                                    set_activity
+                                        .log(self, set_response, ServerProtocol::Jmap, remote_ip)
+                                        .await;
+                                }
                             }
                             ResponseMethod::ImportEmail(import_response) => {
                                 // Add created ids
@@ -86,6 +100,14 @@ impl JMAP {
                                     self.broadcast_state_change(state_change).await;
                                 }
                             }
+                            ResponseMethod::UpdateFlags(update_flags_response) => {
+                                // Publish state changes
+                                if let Some(state_change) =
+                                    update_flags_response.state_change.take()
+                                {
+                                    self.broadcast_state_change(state_change).await;
+                                }
+                            }
                             ResponseMethod::Copy(copy_response) => {
                                 // Publish state changes
                                 if let Some(state_change) = copy_response.state_change.take() {
@@ -193,6 +215,31 @@ impl JMAP {
                         .await?
                         .into()
                 }
+                get::RequestArguments::Label => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.label_get(req).await?.into()
+                }
+                get::RequestArguments::TagRule => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.tag_rule_get(req).await?.into()
+                }
+                get::RequestArguments::ArchivePolicy => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.archive_policy_get(req).await?.into()
+                }
+                get::RequestArguments::DisposableAlias => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.disposable_alias_get(req).await?.into()
+                }
+                get::RequestArguments::Annotation => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.annotation_get(req).await?.into()
+                }
             },
             RequestMethod::Query(mut req) => match req.take_arguments() {
                 query::RequestArguments::Email(arguments) => {
@@ -255,9 +302,14 @@ impl JMAP {
                 set::RequestArguments::EmailSubmission(arguments) => {
                     access_token.assert_is_member(req.account_id)?;
 
-                    self.email_submission_set(req.with_arguments(arguments), instance, next_call)
-                        .await?
-                        .into()
+                    self.email_submission_set(
+                        req.with_arguments(arguments),
+                        access_token,
+                        instance,
+                        next_call,
+                    )
+                    .await?
+                    .into()
                 }
                 set::RequestArguments::PushSubscription => {
                     self.push_subscription_set(req, access_token).await?.into()
@@ -274,6 +326,31 @@ impl JMAP {
 
                     self.vacation_response_set(req).await?.into()
                 }
+                set::RequestArguments::Label => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.label_set(req).await?.into()
+                }
+                set::RequestArguments::TagRule => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.tag_rule_set(req).await?.into()
+                }
+                set::RequestArguments::ArchivePolicy => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.archive_policy_set(req).await?.into()
+                }
+                set::RequestArguments::DisposableAlias => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.disposable_alias_set(req).await?.into()
+                }
+                set::RequestArguments::Annotation => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.annotation_set(req).await?.into()
+                }
             },
             RequestMethod::Changes(req) => self.changes(req, access_token).await?.into(),
             RequestMethod::Copy(req) => {
@@ -293,12 +370,27 @@ impl JMAP {
 
                 self.email_parse(req, access_token).await?.into()
             }
+            RequestMethod::UpdateFlags(req) => {
+                access_token.assert_has_access(req.account_id, Collection::Email)?;
+
+                self.email_update_flags(req, access_token).await?.into()
+            }
             RequestMethod::QueryChanges(req) => self.query_changes(req, access_token).await?.into(),
             RequestMethod::SearchSnippet(req) => {
                 access_token.assert_has_access(req.account_id, Collection::Email)?;
 
                 self.email_search_snippet(req, access_token).await?.into()
             }
+            RequestMethod::ActivityLog(req) => {
+                access_token.assert_is_member(req.account_id)?;
+
+                self.activity_log_get(req).await?.into()
+            }
+            RequestMethod::MailboxUsage(req) => {
+                access_token.assert_is_member(req.account_id)?;
+
+                self.mailbox_usage_get(req).await?.into()
+            }
             RequestMethod::ValidateScript(req) => {
                 access_token.assert_is_member(req.account_id)?;
 
@@ -83,6 +83,12 @@ pub enum Capabilities {
     SieveAccount(SieveAccountCapabilities),
     SieveSession(SieveSessionCapabilities),
     Blob(BlobCapabilities),
+    Labels(LabelsCapabilities),
+    BlobPatch(BlobPatchCapabilities),
+    TagFiling(TagFilingCapabilities),
+    Archive(ArchiveCapabilities),
+    DisposableAlias(DisposableAliasCapabilities),
+    Annotations(AnnotationsCapabilities),
     Empty(EmptyCapabilities),
 }
 
@@ -106,6 +112,56 @@ pub struct CoreCapabilities {
     collation_algorithms: Vec<String>,
 }
 
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LabelsCapabilities {
+    #[serde(rename(serialize = "maxLabels"))]
+    max_labels: usize,
+    #[serde(rename(serialize = "maxSizeLabelName"))]
+    max_label_name: usize,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BlobPatchCapabilities {
+    #[serde(rename(serialize = "maxPatches"))]
+    max_patches: usize,
+    #[serde(rename(serialize = "maxSizeBodyValue"))]
+    max_size: usize,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TagFilingCapabilities {
+    #[serde(rename(serialize = "maxRules"))]
+    max_rules: usize,
+    #[serde(rename(serialize = "separator"))]
+    separator: String,
+    #[serde(rename(serialize = "mayCreateMailboxes"))]
+    may_create_mailboxes: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ArchiveCapabilities {
+    #[serde(rename(serialize = "maxPolicies"))]
+    max_policies: usize,
+    #[serde(rename(serialize = "folder"))]
+    folder: String,
+    #[serde(rename(serialize = "minDays"))]
+    min_days: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DisposableAliasCapabilities {
+    #[serde(rename(serialize = "maxAliases"))]
+    max_aliases: usize,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AnnotationsCapabilities {
+    #[serde(rename(serialize = "maxAnnotations"))]
+    max_annotations: usize,
+    #[serde(rename(serialize = "maxSizeText"))]
+    max_text: usize,
+}
+
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct WebSocketCapabilities {
     #[serde(rename(serialize = "url"))]
@@ -307,6 +363,115 @@ impl crate::Config {
             Capability::Quota,
             Capabilities::Empty(EmptyCapabilities::default()),
         );
+
+        // Add Labels capabilities
+        self.capabilities.session.append(
+            Capability::Labels,
+            Capabilities::Empty(EmptyCapabilities::default()),
+        );
+        self.capabilities.account.append(
+            Capability::Labels,
+            Capabilities::Labels(LabelsCapabilities {
+                max_labels: self.label_max_labels,
+                max_label_name: self.label_max_name_length,
+            }),
+        );
+
+        // Add BlobPatch capabilities
+        if self.blob_patch_enable {
+            self.capabilities.session.append(
+                Capability::BlobPatch,
+                Capabilities::Empty(EmptyCapabilities::default()),
+            );
+            self.capabilities.account.append(
+                Capability::BlobPatch,
+                Capabilities::BlobPatch(BlobPatchCapabilities {
+                    max_patches: self.blob_patch_max_patches,
+                    max_size: self.blob_patch_max_size,
+                }),
+            );
+        }
+
+        // Add TagFiling capabilities
+        if self.tag_filing_enable {
+            self.capabilities.session.append(
+                Capability::TagFiling,
+                Capabilities::Empty(EmptyCapabilities::default()),
+            );
+            self.capabilities.account.append(
+                Capability::TagFiling,
+                Capabilities::TagFiling(TagFilingCapabilities {
+                    max_rules: self.tag_filing_max_rules,
+                    separator: self.tag_filing_separator.clone(),
+                    may_create_mailboxes: self.tag_filing_auto_create,
+                }),
+            );
+        }
+
+        // Add Archive capabilities
+        if self.archive_enable {
+            self.capabilities.session.append(
+                Capability::Archive,
+                Capabilities::Empty(EmptyCapabilities::default()),
+            );
+            self.capabilities.account.append(
+                Capability::Archive,
+                Capabilities::Archive(ArchiveCapabilities {
+                    max_policies: self.archive_max_policies,
+                    folder: self.archive_folder.clone(),
+                    min_days: self.archive_min_days,
+                }),
+            );
+        }
+
+        // Add DisposableAlias capabilities
+        if self.disposable_alias_enable {
+            self.capabilities.session.append(
+                Capability::DisposableAlias,
+                Capabilities::Empty(EmptyCapabilities::default()),
+            );
+            self.capabilities.account.append(
+                Capability::DisposableAlias,
+                Capabilities::DisposableAlias(DisposableAliasCapabilities {
+                    max_aliases: self.disposable_alias_max_aliases,
+                }),
+            );
+        }
+
+        // Add Annotations capabilities
+        self.capabilities.session.append(
+            Capability::Annotations,
+            Capabilities::Empty(EmptyCapabilities::default()),
+        );
+        self.capabilities.account.append(
+            Capability::Annotations,
+            Capabilities::Annotations(AnnotationsCapabilities {
+                max_annotations: self.annotation_max_annotations,
+                max_text: self.annotation_max_length,
+            }),
+        );
+
+        // Add ActivityLog capabilities
+        if self.activity_log_enable {
+            self.capabilities.session.append(
+                Capability::ActivityLog,
+                Capabilities::Empty(EmptyCapabilities::default()),
+            );
+            self.capabilities.account.append(
+                Capability::ActivityLog,
+                Capabilities::Empty(EmptyCapabilities::default()),
+            );
+        }
+
+        // Add MailboxUsage capabilities
+        self.capabilities.session.append(
+            Capability::MailboxUsage,
+            Capabilities::Empty(EmptyCapabilities::default()),
+        );
+        self.capabilities.account.append(
+            Capability::MailboxUsage,
+            Capabilities::Empty(EmptyCapabilities::default()),
+        );
     }
 }
 
@@ -0,0 +1,269 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod get;
+pub mod set;
+
+use jmap_proto::{
+    error::method::MethodError,
+    object::Object,
+    types::{
+        collection::Collection, date::UTCDate, id::Id, property::Property, state::StateChange,
+        type_state::DataType, value::Value,
+    },
+};
+use store::{
+    ahash::AHashMap,
+    query::Filter,
+    write::{assert::HashedValue, log::ChangeLogBuilder, now, BatchBuilder, F_VALUE},
+};
+
+use crate::{
+    email::{metadata::MessageMetadata, set::TagManager},
+    mailbox::UidMailbox,
+    Bincode, JMAP,
+};
+
+impl JMAP {
+    pub async fn archive_policy_list(
+        &self,
+        account_id: u32,
+    ) -> Result<Vec<(u32, Object<Value>)>, MethodError> {
+        let policy_ids = self
+            .get_document_ids(account_id, Collection::ArchivePolicy)
+            .await?
+            .unwrap_or_default();
+        let mut policies = Vec::with_capacity(policy_ids.len() as usize);
+        for document_id in policy_ids {
+            if let Some(policy) = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::ArchivePolicy,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                policies.push((document_id, policy));
+            }
+        }
+
+        Ok(policies)
+    }
+
+    pub async fn archive_all(&self) {
+        let account_ids = match self.get_document_ids(u32::MAX, Collection::Principal).await {
+            Ok(account_ids) => account_ids.unwrap_or_default(),
+            Err(_) => return,
+        };
+
+        for account_id in account_ids {
+            match self.archive_account(account_id).await {
+                Ok(0) => (),
+                Ok(archived) => {
+                    tracing::info!(
+                        context = "archive",
+                        event = "archive",
+                        account_id = account_id,
+                        archived = archived,
+                        "Archived messages."
+                    );
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        context = "archive",
+                        event = "error",
+                        account_id = account_id,
+                        reason = ?err,
+                        "Failed to archive messages."
+                    );
+                }
+            }
+        }
+    }
+
+    pub async fn archive_account(&self, account_id: u32) -> Result<usize, MethodError> {
+        let policies = self.archive_policy_list(account_id).await?;
+        if policies.is_empty() {
+            return Ok(0);
+        }
+        let mailbox_ids = self
+            .get_document_ids(account_id, Collection::Mailbox)
+            .await?
+            .unwrap_or_default();
+        let mut archive_ids = AHashMap::new();
+        let mut changes = ChangeLogBuilder::new();
+        let mut mailbox_change_id = None;
+        let mut archived = 0;
+
+        for (_, policy) in policies {
+            let (source_id, days) = match (
+                policy.get(&Property::MailboxId),
+                policy.get(&Property::OlderThanDays),
+                policy.get(&Property::IsEnabled),
+            ) {
+                (
+                    Value::Id(mailbox_id),
+                    Value::UnsignedInt(days),
+                    Value::Bool(true) | Value::Null,
+                ) if mailbox_ids.contains(mailbox_id.document_id()) => {
+                    (mailbox_id.document_id(), *days)
+                }
+                _ => continue,
+            };
+            let folder = policy
+                .get(&Property::MailboxName)
+                .as_string()
+                .unwrap_or(self.config.archive_folder.as_str())
+                .to_string();
+
+            // Only process a limited number of messages on each run to avoid
+            // invalidating the UID caches of all IMAP clients at once
+            let message_ids = self
+                .filter(
+                    account_id,
+                    Collection::Email,
+                    vec![
+                        Filter::is_in_bitmap(Property::MailboxIds, source_id),
+                        Filter::lt(
+                            Property::ReceivedAt,
+                            now().saturating_sub(days.saturating_mul(86400)),
+                        ),
+                    ],
+                )
+                .await?
+                .results;
+            for message_id in message_ids {
+                if archived >= self.config.archive_batch_size {
+                    break;
+                }
+
+                // Obtain the year the message was received
+                let year = if let Some(metadata) = self
+                    .get_property::<Bincode<MessageMetadata>>(
+                        account_id,
+                        Collection::Email,
+                        message_id,
+                        Property::BodyStructure,
+                    )
+                    .await?
+                {
+                    UTCDate::from_timestamp(metadata.inner.received_at as i64).year
+                } else {
+                    continue;
+                };
+
+                // Obtain the archive folder, creating it on demand
+                let path = format!("{folder}/{year}");
+                let archive_id = if let Some(archive_id) = archive_ids.get(&path) {
+                    *archive_id
+                } else if let Some((archive_id, change_id)) =
+                    self.mailbox_create_path(account_id, &path).await?
+                {
+                    if change_id.is_some() {
+                        mailbox_change_id = change_id;
+                    }
+                    archive_ids.insert(path, archive_id);
+                    archive_id
+                } else {
+                    tracing::debug!(
+                        context = "archive",
+                        event = "error",
+                        account_id = account_id,
+                        folder = folder,
+                        "Failed to create archive folder."
+                    );
+                    break;
+                };
+                if archive_id == source_id {
+                    continue;
+                }
+
+                // Move message
+                let (mut mailboxes, thread_id) = match (
+                    self.get_property::<HashedValue<Vec<UidMailbox>>>(
+                        account_id,
+                        Collection::Email,
+                        message_id,
+                        Property::MailboxIds,
+                    )
+                    .await?,
+                    self.get_property::<u32>(
+                        account_id,
+                        Collection::Email,
+                        message_id,
+                        Property::ThreadId,
+                    )
+                    .await?,
+                ) {
+                    (Some(mailboxes), Some(thread_id)) => (TagManager::new(mailboxes), thread_id),
+                    _ => continue,
+                };
+                mailboxes.update(UidMailbox::from(source_id), false);
+                mailboxes.update(UidMailbox::from(archive_id), true);
+                if changes.change_id == u64::MAX {
+                    changes.change_id = self.assign_change_id(account_id).await?;
+                }
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(Collection::Email)
+                    .update_document(message_id);
+                batch.value(Property::Cid, changes.change_id, F_VALUE);
+                self.add_tombstones(&mut batch, changes.change_id, mailboxes.removed());
+                self.mailbox_usage_update(&mut batch, account_id, message_id, &mailboxes)
+                    .await?;
+                mailboxes.update_batch(&mut batch, Property::MailboxIds);
+                match self.write_batch(batch).await {
+                    Ok(_) => {
+                        changes
+                            .log_update(Collection::Email, Id::from_parts(thread_id, message_id));
+                        changes.log_child_update(Collection::Mailbox, source_id);
+                        changes.log_child_update(Collection::Mailbox, archive_id);
+                        archived += 1;
+                    }
+                    Err(MethodError::ServerUnavailable) => {
+                        // The message was modified concurrently, try again on the next run
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+
+        // Write changes
+        let mut state_change = StateChange::new(account_id);
+        if !changes.is_empty() {
+            let change_id = self.commit_changes(account_id, changes).await?;
+            state_change = state_change
+                .with_change(DataType::Email, change_id)
+                .with_change(DataType::Mailbox, change_id);
+        } else if let Some(change_id) = mailbox_change_id {
+            state_change = state_change.with_change(DataType::Mailbox, change_id);
+        }
+        if state_change.has_changes() {
+            self.broadcast_state_change(state_change).await;
+        }
+
+        Ok(archived)
+    }
+}
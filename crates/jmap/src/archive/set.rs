@@ -0,0 +1,292 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::{
+        method::MethodError,
+        set::{SetError, SetErrorType},
+    },
+    method::set::{RequestArguments, SetRequest, SetResponse},
+    object::Object,
+    response::references::EvalObjectReferences,
+    types::{
+        collection::Collection,
+        property::Property,
+        state::StateChange,
+        type_state::DataType,
+        value::{MaybePatchValue, Value},
+    },
+};
+use store::{
+    roaring::RoaringBitmap,
+    write::{log::ChangeLogBuilder, BatchBuilder, F_CLEAR, F_VALUE},
+};
+
+use crate::JMAP;
+
+const MAX_DAYS: u64 = 100 * 365;
+
+impl JMAP {
+    pub async fn archive_policy_set(
+        &self,
+        mut request: SetRequest<RequestArguments>,
+    ) -> Result<SetResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let mut policies = self.archive_policy_list(account_id).await?;
+        let mailbox_ids = self
+            .get_document_ids(account_id, Collection::Mailbox)
+            .await?
+            .unwrap_or_default();
+        let mut response = self
+            .prepare_set_response(&request, Collection::ArchivePolicy)
+            .await?;
+        let will_destroy = request.unwrap_destroy();
+
+        // Process creates
+        let mut changes = ChangeLogBuilder::new();
+        'create: for (id, object) in request.unwrap_create() {
+            if policies.len() >= self.config.archive_max_policies {
+                response.not_created.append(
+                    id,
+                    SetError::new(SetErrorType::OverQuota).with_description(
+                        "There are too many archive policies, please delete some before adding a new one.",
+                    ),
+                );
+                continue 'create;
+            }
+
+            let mut policy = Object::with_capacity(object.properties.len());
+            for (property, value) in object.properties {
+                match response.eval_object_references(value).and_then(|value| {
+                    self.validate_archive_policy_value(&property, value, &mailbox_ids)
+                }) {
+                    Ok(Value::Null) => (),
+                    Ok(value) => {
+                        policy.set(property, value);
+                    }
+                    Err(err) => {
+                        response.not_created.append(id, err);
+                        continue 'create;
+                    }
+                }
+            }
+
+            if let Err(err) = validate_policy(&policies, None, &policy) {
+                response.not_created.append(id, err);
+                continue 'create;
+            }
+
+            // Insert record
+            let mut batch = BatchBuilder::new();
+            let document_id = self
+                .assign_document_id(account_id, Collection::ArchivePolicy)
+                .await?;
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::ArchivePolicy)
+                .create_document(document_id)
+                .value(Property::Value, policy.clone(), F_VALUE);
+            self.write_batch(batch).await?;
+            changes.log_insert(Collection::ArchivePolicy, document_id);
+            policies.push((document_id, policy));
+            response.created.insert(
+                id,
+                Object::with_capacity(1).with_property(Property::Id, Value::Id(document_id.into())),
+            );
+        }
+
+        // Process updates
+        'update: for (id, object) in request.unwrap_update() {
+            // Make sure id won't be destroyed
+            if will_destroy.contains(&id) {
+                response.not_updated.append(id, SetError::will_destroy());
+                continue 'update;
+            }
+
+            // Obtain policy
+            let document_id = id.document_id();
+            let mut policy = if let Some((_, policy)) = policies
+                .iter()
+                .find(|(policy_id, _)| *policy_id == document_id)
+            {
+                policy.clone()
+            } else {
+                response.not_updated.append(id, SetError::not_found());
+                continue 'update;
+            };
+
+            for (property, value) in object.properties {
+                match response.eval_object_references(value).and_then(|value| {
+                    self.validate_archive_policy_value(&property, value, &mailbox_ids)
+                }) {
+                    Ok(Value::Null) => {
+                        policy.remove(&property);
+                    }
+                    Ok(value) => {
+                        policy.set(property, value);
+                    }
+                    Err(err) => {
+                        response.not_updated.append(id, err);
+                        continue 'update;
+                    }
+                };
+            }
+
+            if let Err(err) = validate_policy(&policies, Some(document_id), &policy) {
+                response.not_updated.append(id, err);
+                continue 'update;
+            }
+
+            // Update record
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::ArchivePolicy)
+                .update_document(document_id)
+                .value(Property::Value, policy.clone(), F_VALUE);
+            self.write_batch(batch).await?;
+            changes.log_update(Collection::ArchivePolicy, document_id);
+            if let Some((_, current)) = policies
+                .iter_mut()
+                .find(|(policy_id, _)| *policy_id == document_id)
+            {
+                *current = policy;
+            }
+            response.updated.append(id, None);
+        }
+
+        // Process deletions
+        for id in will_destroy {
+            let document_id = id.document_id();
+            if let Some(pos) = policies
+                .iter()
+                .position(|(policy_id, _)| *policy_id == document_id)
+            {
+                // Delete record, archived messages and folders are kept
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(Collection::ArchivePolicy)
+                    .delete_document(document_id)
+                    .value(Property::Value, (), F_VALUE | F_CLEAR);
+                self.write_batch(batch).await?;
+                changes.log_delete(Collection::ArchivePolicy, document_id);
+                policies.swap_remove(pos);
+                response.destroyed.push(id);
+            } else {
+                response.not_destroyed.append(id, SetError::not_found());
+            }
+        }
+
+        // Write changes
+        if !changes.is_empty() {
+            let change_id = self.commit_changes(account_id, changes).await?;
+            response.new_state = Some(change_id.into());
+            response.state_change = StateChange::new(account_id)
+                .with_change(DataType::ArchivePolicy, change_id)
+                .into();
+        }
+
+        Ok(response)
+    }
+
+    fn validate_archive_policy_value(
+        &self,
+        property: &Property,
+        value: MaybePatchValue,
+        mailbox_ids: &RoaringBitmap,
+    ) -> Result<Value, SetError> {
+        Ok(match (property, value) {
+            (Property::MailboxId, MaybePatchValue::Value(Value::Id(value))) => {
+                if mailbox_ids.contains(value.document_id()) {
+                    Value::Id(value)
+                } else {
+                    return Err(SetError::invalid_properties()
+                        .with_property(Property::MailboxId)
+                        .with_description(format!("Mailbox {value} does not exist.")));
+                }
+            }
+            (Property::OlderThanDays, MaybePatchValue::Value(Value::UnsignedInt(value)))
+                if (self.config.archive_min_days..=MAX_DAYS).contains(&value) =>
+            {
+                Value::UnsignedInt(value)
+            }
+            (Property::OlderThanDays, MaybePatchValue::Value(Value::UnsignedInt(_))) => {
+                return Err(SetError::invalid_properties()
+                    .with_property(Property::OlderThanDays)
+                    .with_description(format!(
+                        "Messages can only be archived after {} to {} days.",
+                        self.config.archive_min_days, MAX_DAYS
+                    )));
+            }
+            (Property::MailboxName, MaybePatchValue::Value(Value::Text(value)))
+                if value.split('/').all(|name| {
+                    !name.trim().is_empty()
+                        && name.chars().count() <= self.config.mailbox_name_max_len
+                }) =>
+            {
+                Value::Text(value)
+            }
+            (Property::IsEnabled, MaybePatchValue::Value(Value::Bool(value))) => Value::Bool(value),
+            (Property::MailboxName | Property::IsEnabled, MaybePatchValue::Value(Value::Null)) => {
+                Value::Null
+            }
+            (property, _) => {
+                return Err(SetError::invalid_properties()
+                    .with_property(property.clone())
+                    .with_description("Field could not be set."));
+            }
+        })
+    }
+}
+
+fn validate_policy(
+    policies: &[(u32, Object<Value>)],
+    document_id: Option<u32>,
+    policy: &Object<Value>,
+) -> Result<(), SetError> {
+    let mailbox_id = if let Value::Id(mailbox_id) = policy.get(&Property::MailboxId) {
+        mailbox_id
+    } else {
+        return Err(SetError::invalid_properties()
+            .with_property(Property::MailboxId)
+            .with_description("Missing mailboxId."));
+    };
+    if matches!(policy.get(&Property::OlderThanDays), Value::Null) {
+        return Err(SetError::invalid_properties()
+            .with_property(Property::OlderThanDays)
+            .with_description("Missing olderThanDays."));
+    }
+
+    for (policy_id, policy) in policies {
+        if Some(*policy_id) != document_id
+            && matches!(policy.get(&Property::MailboxId), Value::Id(id) if id == mailbox_id)
+        {
+            return Err(SetError::already_exists()
+                .with_existing_id((*policy_id).into())
+                .with_description(format!("A policy for mailbox {mailbox_id} already exists.")));
+        }
+    }
+
+    Ok(())
+}
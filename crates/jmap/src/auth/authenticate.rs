@@ -25,21 +25,21 @@ use std::{net::IpAddr, sync::Arc, time::Instant};
 
 use directory::{AuthResult, QueryBy};
 use hyper::header;
-use jmap_proto::error::request::RequestError;
+use jmap_proto::{error::request::RequestError, method::activity::ActivityEvent};
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
-use utils::{listener::limiter::InFlight, map::ttl_dashmap::TtlMap};
+use utils::{config::ServerProtocol, map::ttl_dashmap::TtlMap};
 
 use crate::JMAP;
 
-use super::AccessToken;
+use super::{rate_limit::InFlightRequest, AccessToken};
 
 impl JMAP {
     pub async fn authenticate_headers(
         &self,
         req: &hyper::Request<hyper::body::Incoming>,
         remote_ip: IpAddr,
-    ) -> Result<Option<(InFlight, Arc<AccessToken>)>, RequestError> {
+    ) -> Result<Option<(InFlightRequest, Arc<AccessToken>)>, RequestError> {
         if let Some((mechanism, token)) = req
             .headers()
             .get(header::AUTHORIZATION)
@@ -50,7 +50,7 @@ impl JMAP {
                 self.get_cached_access_token(account_id).await
             } else {
                 let addr = self.build_remote_addr(req, remote_ip);
-                if mechanism.eq_ignore_ascii_case("basic") {
+                let access_token = if mechanism.eq_ignore_ascii_case("basic") {
                     // Enforce rate limit for authentication requests
                     self.is_auth_allowed_soft(&addr)?;
 
@@ -103,7 +103,20 @@ impl JMAP {
                     self.cache_session(token, &access_token);
                     self.cache_access_token(access_token.clone());
                     access_token
-                })
+                });
+
+                if let Some(access_token) = &access_token {
+                    self.log_activity(
+                        access_token.primary_id(),
+                        ActivityEvent::Login,
+                        ServerProtocol::Jmap,
+                        addr.into(),
+                        None,
+                    )
+                    .await;
+                }
+
+                access_token
             };
 
             if let Some(session) = session {
@@ -191,6 +204,7 @@ impl JMAP {
             Ok(AuthResult::Success(principal)) => AuthResult::Success(AccessToken::new(principal)),
             Ok(AuthResult::Failure) => {
                 let _ = self.is_auth_allowed_hard(&remote_ip);
+                self.smtp.report.operator.telemetry.record_auth_failure();
                 AuthResult::Failure
             }
             Ok(AuthResult::Banned) => AuthResult::Banned,
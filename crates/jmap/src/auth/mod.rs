@@ -43,6 +43,8 @@ pub mod acl;
 pub mod authenticate;
 pub mod oauth;
 pub mod rate_limit;
+pub mod recovery;
+pub mod step_up;
 
 #[derive(Debug, Clone, Default)]
 pub struct AccessToken {
@@ -53,6 +55,7 @@ pub struct AccessToken {
     pub description: Option<String>,
     pub quota: u32,
     pub is_superuser: bool,
+    pub referral: Option<String>,
 }
 
 impl AccessToken {
@@ -65,6 +68,7 @@ impl AccessToken {
             description: principal.description,
             quota: principal.quota,
             is_superuser: principal.typ == Type::Superuser,
+            referral: principal.referral,
         }
     }
 
@@ -102,6 +106,14 @@ impl AccessToken {
         self.is_superuser
     }
 
+    pub fn role(&self) -> Type {
+        if self.is_superuser {
+            Type::Superuser
+        } else {
+            Type::Individual
+        }
+    }
+
     pub fn is_shared(&self, account_id: u32) -> bool {
         !self.is_member(account_id) && self.access_to.iter().any(|(id, _)| *id == account_id)
     }
@@ -23,8 +23,16 @@
 
 use std::{net::IpAddr, sync::Arc};
 
+use hyper::{header::HeaderValue, HeaderMap, Method};
 use jmap_proto::error::request::{RequestError, RequestLimitError};
-use utils::listener::limiter::{ConcurrencyLimiter, InFlight, RateLimiter};
+use utils::{
+    config::{Rate, ServerProtocol},
+    listener::{
+        bandwidth::Bandwidth,
+        limiter::{ConcurrencyLimiter, InFlight, RateLimiter},
+        ServerInstance,
+    },
+};
 
 use crate::JMAP;
 
@@ -34,6 +42,15 @@ pub struct AuthenticatedLimiter {
     pub request_limiter: RateLimiter,
     pub concurrent_requests: ConcurrencyLimiter,
     pub concurrent_uploads: ConcurrencyLimiter,
+    pub patch_limiter: RateLimiter,
+    pub recovery_limiter: RateLimiter,
+    pub step_up_limiter: RateLimiter,
+    pub bandwidth: Bandwidth,
+}
+
+pub struct InFlightRequest {
+    _request: InFlight,
+    _session: Option<InFlight>,
 }
 
 #[derive(Debug)]
@@ -42,6 +59,35 @@ pub struct AnonymousLimiter {
     auth_limiter: RateLimiter,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RateLimitKey {
+    Account(u32),
+    Address(IpAddr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HttpEndpoint {
+    Api,
+    Upload,
+    Download,
+    EventSource,
+    WebSocket,
+    Session,
+    Auth,
+    Autoconfig,
+    Recovery,
+    StepUp,
+    Crypto,
+    Admin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    pub limit: u64,
+    pub remaining: u64,
+    pub reset: u64,
+}
+
 impl JMAP {
     pub fn get_authenticated_limiter(&self, account_id: u32) -> Arc<AuthenticatedLimiter> {
         self.rate_limit_auth
@@ -54,6 +100,10 @@ impl JMAP {
                         self.config.request_max_concurrent,
                     ),
                     concurrent_uploads: ConcurrencyLimiter::new(self.config.upload_max_concurrent),
+                    patch_limiter: RateLimiter::new(&self.config.blob_patch_rate),
+                    recovery_limiter: RateLimiter::new(&self.config.recovery_rate),
+                    step_up_limiter: RateLimiter::new(&self.config.step_up_rate),
+                    bandwidth: Bandwidth::new(&self.config.rate_download, &self.config.rate_upload),
                 });
                 self.rate_limit_auth.insert(account_id, limiter.clone());
                 limiter
@@ -74,37 +124,141 @@ impl JMAP {
             })
     }
 
-    pub fn is_account_allowed(&self, access_token: &AccessToken) -> Result<InFlight, RequestError> {
+    pub fn is_account_allowed(
+        &self,
+        access_token: &AccessToken,
+    ) -> Result<InFlightRequest, RequestError> {
         let limiter = self.get_authenticated_limiter(access_token.primary_id());
 
-        if limiter
+        let request = if limiter
             .request_limiter
             .is_allowed(&self.config.rate_authenticated)
         {
             if let Some(in_flight_request) = limiter.concurrent_requests.is_allowed() {
-                Ok(in_flight_request)
+                in_flight_request
             } else if access_token.is_super_user() {
-                Ok(InFlight::default())
+                InFlight::default()
             } else {
-                Err(RequestError::limit(RequestLimitError::ConcurrentRequest))
+                return Err(RequestError::limit(RequestLimitError::ConcurrentRequest));
             }
         } else if access_token.is_super_user() {
-            Ok(InFlight::default())
+            InFlight::default()
         } else {
-            Err(RequestError::too_many_requests())
-        }
+            return Err(RequestError::too_many_requests()
+                .with_retry_after(limiter.request_limiter.secs_to_refill()));
+        };
+
+        // Enforce the session budget shared with other protocols
+        let session = self
+            .smtp
+            .acquire_account_session(
+                &access_token.name,
+                access_token.role(),
+                ServerProtocol::Jmap,
+            )
+            .map_err(|limit| {
+                RequestError::blank(
+                    429,
+                    "Too Many Sessions",
+                    format!("The account has reached its limit of {limit} concurrent sessions."),
+                )
+            })?;
+
+        Ok(InFlightRequest {
+            _request: request,
+            _session: session,
+        })
     }
 
     pub fn is_anonymous_allowed(&self, addr: &IpAddr) -> Result<(), RequestError> {
-        if self
-            .get_anonymous_limiter(addr)
+        let limiter = self.get_anonymous_limiter(addr);
+        if limiter
             .request_limiter
             .is_allowed(&self.config.rate_anonymous)
         {
             Ok(())
         } else {
-            Err(RequestError::too_many_requests())
+            Err(RequestError::too_many_requests()
+                .with_retry_after(limiter.request_limiter.secs_to_refill()))
+        }
+    }
+
+    pub fn is_endpoint_allowed(
+        &self,
+        endpoint: HttpEndpoint,
+        key: RateLimitKey,
+    ) -> Result<(), RequestError> {
+        if let Some(rate) = self.endpoint_rate(endpoint) {
+            let limiter = self.get_endpoint_limiter(endpoint, key, rate);
+            if !limiter.is_allowed(rate) {
+                return Err(
+                    RequestError::too_many_requests().with_retry_after(limiter.secs_to_refill())
+                );
+            }
         }
+
+        Ok(())
+    }
+
+    pub fn rate_limit_status(
+        &self,
+        endpoint: HttpEndpoint,
+        key: RateLimitKey,
+    ) -> Option<RateLimitStatus> {
+        // Endpoint limits take precedence over the global account and address limits
+        let (limiter, rate) = if let Some(rate) = self.endpoint_rate(endpoint) {
+            (
+                self.rate_limit_endpoint
+                    .get(&(endpoint, key))
+                    .map(|limiter| limiter.clone())?,
+                rate,
+            )
+        } else {
+            match key {
+                RateLimitKey::Account(account_id) => {
+                    let limiter = self.rate_limit_auth.get(&account_id)?.clone();
+                    return RateLimitStatus::new(
+                        &limiter.request_limiter,
+                        &self.config.rate_authenticated,
+                    )
+                    .into();
+                }
+                RateLimitKey::Address(addr) => {
+                    let limiter = self.rate_limit_unauth.get(&addr)?.clone();
+                    return RateLimitStatus::new(
+                        &limiter.request_limiter,
+                        &self.config.rate_anonymous,
+                    )
+                    .into();
+                }
+            }
+        };
+
+        RateLimitStatus::new(&limiter, rate).into()
+    }
+
+    fn endpoint_rate(&self, endpoint: HttpEndpoint) -> Option<&Rate> {
+        self.config
+            .rate_endpoint
+            .iter()
+            .find_map(|(e, rate)| if *e == endpoint { Some(rate) } else { None })
+    }
+
+    fn get_endpoint_limiter(
+        &self,
+        endpoint: HttpEndpoint,
+        key: RateLimitKey,
+        rate: &Rate,
+    ) -> Arc<RateLimiter> {
+        self.rate_limit_endpoint
+            .get(&(endpoint, key))
+            .map(|limiter| limiter.clone())
+            .unwrap_or_else(|| {
+                let limiter = Arc::new(RateLimiter::new(rate));
+                self.rate_limit_endpoint
+                    .insert((endpoint, key), limiter.clone());
+                limiter
+            })
     }
 
     pub fn is_upload_allowed(&self, access_token: &AccessToken) -> Result<InFlight, RequestError> {
@@ -121,6 +275,90 @@ impl JMAP {
         }
     }
 
+    pub fn is_patch_allowed(&self, access_token: &AccessToken) -> bool {
+        access_token.is_super_user()
+            || self
+                .get_authenticated_limiter(access_token.primary_id())
+                .patch_limiter
+                .is_allowed(&self.config.blob_patch_rate)
+    }
+
+    pub fn is_recovery_allowed(&self, account_id: u32) -> Result<(), RequestError> {
+        let limiter = self.get_authenticated_limiter(account_id);
+        if limiter
+            .recovery_limiter
+            .is_allowed(&self.config.recovery_rate)
+        {
+            Ok(())
+        } else {
+            Err(RequestError::too_many_auth_attempts()
+                .with_retry_after(limiter.recovery_limiter.secs_to_refill()))
+        }
+    }
+
+    // Throttles recovery requests for account names that do not exist,
+    // keyed by the submitted name.
+    pub fn is_unknown_recovery_allowed(&self, name: &str) -> Result<(), RequestError> {
+        let name = name.to_lowercase();
+        let limiter = self
+            .rate_limit_recovery
+            .get(&name)
+            .map(|limiter| limiter.clone())
+            .unwrap_or_else(|| {
+                let limiter = Arc::new(RateLimiter::new(&self.config.recovery_rate));
+                self.rate_limit_recovery.insert(name, limiter.clone());
+                limiter
+            });
+        if limiter.is_allowed(&self.config.recovery_rate) {
+            Ok(())
+        } else {
+            Err(RequestError::too_many_auth_attempts().with_retry_after(limiter.secs_to_refill()))
+        }
+    }
+
+    pub fn is_step_up_allowed(&self, account_id: u32) -> Result<(), RequestError> {
+        let limiter = self.get_authenticated_limiter(account_id);
+        if limiter
+            .step_up_limiter
+            .is_allowed(&self.config.step_up_rate)
+        {
+            Ok(())
+        } else {
+            Err(RequestError::too_many_auth_attempts()
+                .with_retry_after(limiter.step_up_limiter.secs_to_refill()))
+        }
+    }
+
+    pub async fn throttle_download(
+        &self,
+        access_token: &AccessToken,
+        instance: &ServerInstance,
+        bytes: usize,
+    ) {
+        if !access_token.is_super_user() {
+            self.get_authenticated_limiter(access_token.primary_id())
+                .bandwidth
+                .throttle_download(bytes)
+                .await;
+        }
+        instance.bandwidth.throttle_download(bytes).await;
+    }
+
+    pub async fn throttle_upload(
+        &self,
+        access_token: &AccessToken,
+        instance: &ServerInstance,
+        bytes: usize,
+    ) {
+        if !access_token.is_super_user() {
+            self.get_authenticated_limiter(access_token.primary_id())
+                .bandwidth
+                .throttle_upload(bytes)
+                .await;
+        }
+        instance.bandwidth.throttle_upload(bytes).await;
+    }
+
     pub fn is_auth_allowed_soft(&self, addr: &IpAddr) -> Result<(), RequestError> {
         match self.rate_limit_unauth.get(addr) {
             Some(limiter)
@@ -128,21 +366,23 @@ impl JMAP {
                     .auth_limiter
                     .is_allowed_soft(&self.config.rate_authenticate_req) =>
             {
-                Err(RequestError::too_many_auth_attempts())
+                Err(RequestError::too_many_auth_attempts()
+                    .with_retry_after(limiter.auth_limiter.secs_to_refill()))
             }
             _ => Ok(()),
         }
     }
 
     pub fn is_auth_allowed_hard(&self, addr: &IpAddr) -> Result<(), RequestError> {
-        if self
-            .get_anonymous_limiter(addr)
+        let limiter = self.get_anonymous_limiter(addr);
+        if limiter
             .auth_limiter
             .is_allowed(&self.config.rate_authenticate_req)
         {
             Ok(())
         } else {
-            Err(RequestError::too_many_auth_attempts())
+            Err(RequestError::too_many_auth_attempts()
+                .with_retry_after(limiter.auth_limiter.secs_to_refill()))
         }
     }
 }
@@ -152,6 +392,10 @@ impl AuthenticatedLimiter {
         self.request_limiter.is_active()
             || self.concurrent_requests.is_active()
             || self.concurrent_uploads.is_active()
+            || self.patch_limiter.is_active()
+            || self.recovery_limiter.is_active()
+            || self.step_up_limiter.is_active()
+            || self.bandwidth.is_active()
     }
 }
 
@@ -160,3 +404,72 @@ impl AnonymousLimiter {
         self.request_limiter.is_active() || self.auth_limiter.is_active()
     }
 }
+
+impl HttpEndpoint {
+    pub fn parse(method: &Method, path: &str) -> Option<Self> {
+        let mut path = path.split('/').skip(1);
+        match path.next().unwrap_or_default() {
+            "jmap" => match (path.next().unwrap_or_default(), method) {
+                ("", &Method::POST) => HttpEndpoint::Api,
+                ("upload", _) => HttpEndpoint::Upload,
+                ("download", _) => HttpEndpoint::Download,
+                ("eventsource", _) => HttpEndpoint::EventSource,
+                ("ws", _) => HttpEndpoint::WebSocket,
+                _ => return None,
+            },
+            ".well-known" => match path.next().unwrap_or_default() {
+                "jmap" => HttpEndpoint::Session,
+                "oauth-authorization-server" => HttpEndpoint::Auth,
+                "autoconfig" => HttpEndpoint::Autoconfig,
+                _ => return None,
+            },
+            "mail" | "autodiscover" | "Autodiscover" => HttpEndpoint::Autoconfig,
+            "auth" => HttpEndpoint::Auth,
+            "recovery" => HttpEndpoint::Recovery,
+            "step-up" => HttpEndpoint::StepUp,
+            "crypto" => HttpEndpoint::Crypto,
+            "admin" => HttpEndpoint::Admin,
+            _ => return None,
+        }
+        .into()
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "api" => HttpEndpoint::Api,
+            "upload" => HttpEndpoint::Upload,
+            "download" => HttpEndpoint::Download,
+            "eventsource" => HttpEndpoint::EventSource,
+            "websocket" => HttpEndpoint::WebSocket,
+            "session" => HttpEndpoint::Session,
+            "auth" => HttpEndpoint::Auth,
+            "autoconfig" => HttpEndpoint::Autoconfig,
+            "recovery" => HttpEndpoint::Recovery,
+            "step-up" => HttpEndpoint::StepUp,
+            "crypto" => HttpEndpoint::Crypto,
+            "admin" => HttpEndpoint::Admin,
+            _ => return None,
+        }
+        .into()
+    }
+}
+
+impl RateLimitStatus {
+    pub fn new(limiter: &RateLimiter, rate: &Rate) -> Self {
+        RateLimitStatus {
+            limit: rate.requests,
+            remaining: limiter.remaining(rate),
+            reset: limiter.secs_to_refill(),
+        }
+    }
+
+    pub fn write_headers(&self, headers: &mut HeaderMap) {
+        for (name, value) in [
+            ("ratelimit-limit", self.limit),
+            ("ratelimit-remaining", self.remaining),
+            ("ratelimit-reset", self.reset),
+        ] {
+            headers.insert(name, HeaderValue::from(value));
+        }
+    }
+}
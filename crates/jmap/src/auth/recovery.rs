@@ -0,0 +1,609 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{net::IpAddr, sync::Arc};
+
+use directory::{DirectoryError, Principal, QueryBy};
+use hyper::{Method, StatusCode};
+use jmap_proto::{
+    error::request::RequestError,
+    method::activity::ActivityEvent,
+    types::{collection::Collection, property::Property},
+};
+use serde_json::json;
+use store::{
+    blake3,
+    rand::{distributions::Alphanumeric, thread_rng, Rng},
+    write::{now, BatchBuilder, F_CLEAR, F_VALUE},
+};
+use utils::config::ServerProtocol;
+
+use crate::{
+    api::{
+        http::{fetch_body, ToHttpResponse},
+        HttpRequest, HttpResponse, JsonResponse,
+    },
+    Bincode, JMAP,
+};
+
+use super::AccessToken;
+
+const VERIFICATION_CODE_LEN: usize = 8;
+const RESET_TOKEN_LEN: usize = 24;
+const RECOVERY_CODE_LEN: usize = 12;
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RecoverySettings {
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub pending: Option<PendingToken>,
+    pub codes: Vec<String>,
+    pub reverify_required: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PendingToken {
+    pub purpose: TokenPurpose,
+    pub hash: String,
+    pub expires: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TokenPurpose {
+    VerifyEmail,
+    ResetPassword,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct RecoveryRequest {
+    #[serde(default)]
+    account: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+}
+
+impl JMAP {
+    pub async fn handle_recovery_request(
+        &self,
+        req: &mut HttpRequest,
+        remote_ip: IpAddr,
+        remote_addr: IpAddr,
+    ) -> HttpResponse {
+        let uri = req.uri().clone();
+        let mut path = uri.path().split('/');
+        path.next();
+        path.next();
+
+        let method = req.method().clone();
+        match (path.next().unwrap_or(""), path.next(), method) {
+            // Unauthenticated recovery requests
+            ("request", None, Method::POST) => {
+                if let Err(err) = self.is_auth_allowed_soft(&remote_addr) {
+                    return err.into_http_response();
+                }
+                match self.parse_recovery_request(req).await {
+                    Ok(request) => self.request_password_reset(request, remote_addr).await,
+                    Err(err) => err.into_http_response(),
+                }
+            }
+            ("reset", None, Method::POST) => {
+                if let Err(err) = self.is_auth_allowed_soft(&remote_addr) {
+                    return err.into_http_response();
+                }
+                match self.parse_recovery_request(req).await {
+                    Ok(request) => self.reset_password(request, remote_addr).await,
+                    Err(err) => err.into_http_response(),
+                }
+            }
+
+            // Authenticated recovery settings management
+            (op, sub_op, method) => {
+                let access_token = match self.authenticate_headers(req, remote_ip).await {
+                    Ok(Some((_, access_token))) => access_token,
+                    Ok(None) => return RequestError::unauthorized().into_http_response(),
+                    Err(err) => return err.into_http_response(),
+                };
+                let account_id = access_token.primary_id();
+                let mut settings = match self.get_recovery_settings(account_id).await {
+                    Ok(settings) => settings,
+                    Err(err) => return err.into_http_response(),
+                };
+
+                match (op, sub_op, method) {
+                    ("", None, Method::GET) => {
+                        return JsonResponse::new(json!({
+                            "data": recovery_status(&settings),
+                        }))
+                        .into_http_response();
+                    }
+                    ("email", None, Method::POST) => {
+                        let email = match self
+                            .parse_recovery_request(req)
+                            .await
+                            .map(|request| request.email.unwrap_or_default())
+                        {
+                            Ok(email) if is_valid_email(&email) => email.to_lowercase(),
+                            Ok(_) => {
+                                return RequestError::blank(
+                                    StatusCode::BAD_REQUEST.as_u16(),
+                                    "Invalid parameters",
+                                    "A valid recovery e-mail address is required.",
+                                )
+                                .into_http_response()
+                            }
+                            Err(err) => return err.into_http_response(),
+                        };
+
+                        // Send verification code to the new recovery address
+                        let code = random_code(VERIFICATION_CODE_LEN);
+                        settings.email = email.clone().into();
+                        settings.email_verified = false;
+                        settings.pending = PendingToken {
+                            purpose: TokenPurpose::VerifyEmail,
+                            hash: hash_code(&code),
+                            expires: now() + self.config.recovery_token_expiry.as_secs(),
+                        }
+                        .into();
+                        if let Err(err) = self.set_recovery_settings(account_id, &settings).await {
+                            return err.into_http_response();
+                        }
+                        self.smtp
+                            .send_account_notification(
+                                &email,
+                                "Verify your recovery e-mail address",
+                                format!(
+                                    concat!(
+                                        "This address was registered as the recovery address ",
+                                        "for account {}.\r\n\r\n",
+                                        "Verification code: {}\r\n\r\n",
+                                        "The code expires in {} minutes. If you did not request ",
+                                        "this change, please ignore this message.\r\n"
+                                    ),
+                                    access_token.name,
+                                    code,
+                                    self.config.recovery_token_expiry.as_secs() / 60
+                                ),
+                            )
+                            .await;
+                        self.log_recovery_change(&access_token, remote_addr, "email")
+                            .await;
+                    }
+                    ("email", Some("verify"), Method::POST) => {
+                        let code = match self.parse_recovery_request(req).await {
+                            Ok(request) => request.code.unwrap_or_default(),
+                            Err(err) => return err.into_http_response(),
+                        };
+                        // Count attempts against the account before checking the code
+                        if let Err(err) = self.is_recovery_allowed(account_id) {
+                            return err.into_http_response();
+                        }
+                        if !settings.take_token(TokenPurpose::VerifyEmail, &code) {
+                            return invalid_code();
+                        }
+                        settings.email_verified = true;
+                        settings.reverify_required = false;
+                        if let Err(err) = self.set_recovery_settings(account_id, &settings).await {
+                            return err.into_http_response();
+                        }
+                        self.log_recovery_change(&access_token, remote_addr, "email-verified")
+                            .await;
+                    }
+                    ("codes", None, Method::POST) => {
+                        // Generate a new set of recovery codes, replacing any previous ones
+                        let codes = (0..self.config.recovery_codes)
+                            .map(|_| random_code(RECOVERY_CODE_LEN))
+                            .collect::<Vec<_>>();
+                        settings.codes = codes.iter().map(|code| hash_code(code)).collect();
+                        settings.reverify_required = false;
+                        if let Err(err) = self.set_recovery_settings(account_id, &settings).await {
+                            return err.into_http_response();
+                        }
+                        self.log_recovery_change(&access_token, remote_addr, "codes")
+                            .await;
+
+                        // Codes are only returned once
+                        return JsonResponse::new(json!({
+                            "data": {
+                                "codes": codes,
+                                "status": recovery_status(&settings),
+                            },
+                        }))
+                        .into_http_response();
+                    }
+                    ("", None, Method::DELETE) => {
+                        settings = RecoverySettings::default();
+                        let mut batch = BatchBuilder::new();
+                        batch
+                            .with_account_id(account_id)
+                            .with_collection(Collection::Principal)
+                            .update_document(0)
+                            .value(Property::Recovery, (), F_VALUE | F_CLEAR);
+                        if self.write_batch(batch).await.is_err() {
+                            return RequestError::internal_server_error().into_http_response();
+                        }
+                        self.log_recovery_change(&access_token, remote_addr, "removed")
+                            .await;
+                    }
+                    _ => return RequestError::not_found().into_http_response(),
+                }
+
+                JsonResponse::new(json!({
+                    "data": recovery_status(&settings),
+                }))
+                .into_http_response()
+            }
+        }
+    }
+
+    async fn request_password_reset(
+        &self,
+        request: RecoveryRequest,
+        remote_addr: IpAddr,
+    ) -> HttpResponse {
+        // The response is identical whether or not the account exists
+        let response = JsonResponse::new(json!({
+            "data": "If the account has a verified recovery address, a reset code has been sent.",
+        }));
+        let principal = match self.recovery_principal(request.account.as_deref()).await {
+            Ok(Some(principal)) => principal,
+            Ok(None) => {
+                // Unknown names are throttled like accounts so that a 429
+                // does not reveal which accounts exist
+                let _ = self.is_auth_allowed_hard(&remote_addr);
+                if let Err(err) =
+                    self.is_unknown_recovery_allowed(request.account.as_deref().unwrap_or_default())
+                {
+                    return err.into_http_response();
+                }
+                return response.into_http_response();
+            }
+            Err(err) => return err.into_http_response(),
+        };
+        let account_id = principal.id;
+        if let Err(err) = self.is_recovery_allowed(account_id) {
+            return err.into_http_response();
+        }
+        let mut settings = match self.get_recovery_settings(account_id).await {
+            Ok(settings) => settings,
+            Err(err) => return err.into_http_response(),
+        };
+        let email = match (&settings.email, settings.email_verified) {
+            (Some(email), true) if !settings.reverify_required => email.clone(),
+            _ => return response.into_http_response(),
+        };
+
+        let token = random_code(RESET_TOKEN_LEN);
+        settings.pending = PendingToken {
+            purpose: TokenPurpose::ResetPassword,
+            hash: hash_code(&token),
+            expires: now() + self.config.recovery_token_expiry.as_secs(),
+        }
+        .into();
+        if let Err(err) = self.set_recovery_settings(account_id, &settings).await {
+            return err.into_http_response();
+        }
+
+        self.smtp
+            .send_account_notification(
+                &email,
+                "Password reset requested",
+                format!(
+                    concat!(
+                        "A password reset was requested for account {} from {}.\r\n\r\n",
+                        "Reset code: {}\r\n\r\n",
+                        "The code expires in {} minutes. If you did not request ",
+                        "a password reset, please ignore this message.\r\n"
+                    ),
+                    principal.name,
+                    remote_addr,
+                    token,
+                    self.config.recovery_token_expiry.as_secs() / 60
+                ),
+            )
+            .await;
+
+        response.into_http_response()
+    }
+
+    async fn reset_password(&self, request: RecoveryRequest, remote_addr: IpAddr) -> HttpResponse {
+        let (code, password) = match (request.code, request.password) {
+            (Some(code), Some(password)) if !code.is_empty() && !password.is_empty() => {
+                (code, password)
+            }
+            _ => {
+                return RequestError::blank(
+                    StatusCode::BAD_REQUEST.as_u16(),
+                    "Invalid parameters",
+                    "An account, recovery code and new password are required.",
+                )
+                .into_http_response()
+            }
+        };
+        let principal = match self.recovery_principal(request.account.as_deref()).await {
+            Ok(Some(principal)) => principal,
+            Ok(None) => {
+                if let Err(err) = self.is_auth_allowed_hard(&remote_addr).and_then(|_| {
+                    self.is_unknown_recovery_allowed(request.account.as_deref().unwrap_or_default())
+                }) {
+                    return err.into_http_response();
+                }
+                return invalid_code();
+            }
+            Err(err) => return err.into_http_response(),
+        };
+        let account_id = principal.id;
+        if let Err(err) = self.is_recovery_allowed(account_id) {
+            return err.into_http_response();
+        }
+        let mut settings = match self.get_recovery_settings(account_id).await {
+            Ok(settings) => settings,
+            Err(err) => return err.into_http_response(),
+        };
+
+        // Recovery stays locked until the factors are re-verified after a reset
+        if settings.reverify_required {
+            return RequestError::blank(
+                StatusCode::FORBIDDEN.as_u16(),
+                "Recovery locked",
+                "Recovery settings must be re-verified before they can be used again.",
+            )
+            .into_http_response();
+        }
+
+        let code_hash = hash_code(&code);
+        let method = if settings.take_token(TokenPurpose::ResetPassword, &code) {
+            "email"
+        } else if let Some(pos) = settings.codes.iter().position(|c| c == &code_hash) {
+            settings.codes.swap_remove(pos);
+            "code"
+        } else {
+            if let Err(err) = self.is_auth_allowed_hard(&remote_addr) {
+                return err.into_http_response();
+            }
+            return invalid_code();
+        };
+
+        // Update password
+        match self
+            .directory
+            .update_secrets(account_id, vec![password])
+            .await
+        {
+            Ok(_) => (),
+            Err(DirectoryError::Unsupported) => {
+                return RequestError::blank(
+                    StatusCode::NOT_IMPLEMENTED.as_u16(),
+                    "Unsupported",
+                    "Passwords cannot be reset on this directory.",
+                )
+                .into_http_response();
+            }
+            Err(err) => {
+                tracing::warn!(
+                    context = "recovery",
+                    event = "error",
+                    account_id = account_id,
+                    reason = ?err,
+                    "Failed to reset password."
+                );
+                return RequestError::internal_server_error().into_http_response();
+            }
+        }
+
+        // Require the recovery factors to be verified again
+        settings.email_verified = false;
+        settings.reverify_required = true;
+        settings.pending = None;
+        if let Err(err) = self.set_recovery_settings(account_id, &settings).await {
+            return err.into_http_response();
+        }
+
+        // Invalidate cached sessions
+        self.access_tokens.remove(&account_id);
+        self.sessions.retain(|_, entry| *entry.item() != account_id);
+
+        self.log_activity(
+            account_id,
+            ActivityEvent::AccountRecovery,
+            ServerProtocol::Jmap,
+            remote_addr.into(),
+            format!("method={method}").into(),
+        )
+        .await;
+
+        // Notify the primary address
+        if let Some(primary) = principal.emails.first() {
+            self.smtp
+                .send_account_notification(
+                    primary,
+                    "Your password was reset",
+                    format!(
+                        concat!(
+                            "The password for account {} was reset from {} using ",
+                            "a recovery {}.\r\n\r\n",
+                            "Your recovery settings must be verified again before they ",
+                            "can be used. If you did not perform this reset, please ",
+                            "contact your administrator immediately.\r\n"
+                        ),
+                        principal.name, remote_addr, method
+                    ),
+                )
+                .await;
+        }
+
+        tracing::info!(
+            context = "recovery",
+            event = "reset",
+            account_id = account_id,
+            remote_ip = remote_addr.to_string(),
+            method = method,
+            "Password reset through account recovery."
+        );
+
+        JsonResponse::new(json!({
+            "data": "Password updated.",
+        }))
+        .into_http_response()
+    }
+
+    pub async fn get_recovery_settings(
+        &self,
+        account_id: u32,
+    ) -> Result<RecoverySettings, RequestError> {
+        self.get_property::<Bincode<RecoverySettings>>(
+            account_id,
+            Collection::Principal,
+            0,
+            Property::Recovery,
+        )
+        .await
+        .map(|settings| settings.map(|s| s.inner).unwrap_or_default())
+        .map_err(|_| RequestError::internal_server_error())
+    }
+
+    pub async fn set_recovery_settings(
+        &self,
+        account_id: u32,
+        settings: &RecoverySettings,
+    ) -> Result<(), RequestError> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Principal)
+            .update_document(0)
+            .value(Property::Recovery, Bincode::new(settings.clone()), F_VALUE);
+        self.write_batch(batch)
+            .await
+            .map_err(|_| RequestError::internal_server_error())
+    }
+
+    async fn recovery_principal(
+        &self,
+        account: Option<&str>,
+    ) -> Result<Option<Principal<u32>>, RequestError> {
+        match account {
+            Some(account) if !account.is_empty() => self
+                .directory
+                .query(QueryBy::Name(account), false)
+                .await
+                .map_err(|err| {
+                    tracing::warn!(
+                        context = "recovery",
+                        event = "error",
+                        reason = ?err,
+                        "Failed to lookup account."
+                    );
+                    RequestError::internal_server_error()
+                }),
+            _ => Ok(None),
+        }
+    }
+
+    async fn parse_recovery_request(
+        &self,
+        req: &mut HttpRequest,
+    ) -> Result<RecoveryRequest, RequestError> {
+        fetch_body(req, 8192, &AccessToken::default())
+            .await
+            .and_then(|body| serde_json::from_slice::<RecoveryRequest>(&body).ok())
+            .ok_or_else(|| {
+                RequestError::blank(
+                    StatusCode::BAD_REQUEST.as_u16(),
+                    "Invalid parameters",
+                    "Failed to deserialize recovery request",
+                )
+            })
+    }
+
+    async fn log_recovery_change(
+        &self,
+        access_token: &Arc<AccessToken>,
+        remote_addr: IpAddr,
+        change: &str,
+    ) {
+        self.log_activity(
+            access_token.primary_id(),
+            ActivityEvent::RecoveryChange,
+            ServerProtocol::Jmap,
+            remote_addr.into(),
+            change.to_string().into(),
+        )
+        .await;
+    }
+}
+
+impl RecoverySettings {
+    fn take_token(&mut self, purpose: TokenPurpose, code: &str) -> bool {
+        match &self.pending {
+            Some(token)
+                if token.purpose == purpose
+                    && token.expires >= now()
+                    && token.hash == hash_code(code) =>
+            {
+                self.pending = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn recovery_status(settings: &RecoverySettings) -> serde_json::Value {
+    json!({
+        "email": settings.email,
+        "emailVerified": settings.email_verified,
+        "codesRemaining": settings.codes.len(),
+        "reverifyRequired": settings.reverify_required,
+    })
+}
+
+fn invalid_code() -> HttpResponse {
+    RequestError::blank(
+        StatusCode::UNAUTHORIZED.as_u16(),
+        "Invalid code",
+        "The recovery code is invalid or has expired.",
+    )
+    .into_http_response()
+}
+
+fn random_code(len: usize) -> String {
+    thread_rng()
+        .sample_iter(Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+fn hash_code(code: &str) -> String {
+    blake3::hash(code.trim().as_bytes()).to_hex().to_string()
+}
+
+fn is_valid_email(email: &str) -> bool {
+    email.split_once('@').map_or(false, |(local, domain)| {
+        !local.is_empty() && domain.contains('.') && !domain.ends_with('.')
+    })
+}
@@ -0,0 +1,552 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use directory::backend::internal::manage::ManageDirectory;
+use hmac::{Hmac, Mac};
+use hyper::{header, Method, StatusCode};
+use jmap_proto::{
+    error::request::RequestError,
+    method::activity::ActivityEvent,
+    types::{collection::Collection, property::Property},
+};
+use serde_json::json;
+use sha1::Sha1;
+use store::{
+    blake3,
+    rand::{thread_rng, RngCore},
+    write::{now, BatchBuilder, F_CLEAR, F_VALUE},
+};
+use utils::{config::ServerProtocol, map::ttl_dashmap::TtlMap};
+
+use crate::{
+    api::{
+        http::{fetch_body, ToHttpResponse},
+        HttpRequest, HttpResponse, JsonResponse,
+    },
+    Bincode, JMAP,
+};
+
+use super::AccessToken;
+
+const TOTP_SECRET_LEN: usize = 20;
+const TOTP_PERIOD: u64 = 30;
+const TOTP_MODULO: u32 = 1_000_000;
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StepUpOperation {
+    AccountPurge,
+    StorePurge,
+    ConfigChange,
+    QueueCancel,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct StepUpSettings {
+    pub totp: Option<Vec<u8>>,
+    pub pending: Option<Vec<u8>>,
+    pub last_counter: u64,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct StepUpRequest {
+    #[serde(default)]
+    code: Option<String>,
+}
+
+impl JMAP {
+    pub async fn handle_step_up_request(
+        &self,
+        req: &mut HttpRequest,
+        remote_ip: IpAddr,
+        remote_addr: IpAddr,
+    ) -> HttpResponse {
+        let access_token = match self.authenticate_headers(req, remote_ip).await {
+            Ok(Some((_, access_token))) => access_token,
+            Ok(None) => return RequestError::unauthorized().into_http_response(),
+            Err(err) => return err.into_http_response(),
+        };
+        let key = step_up_key(req).unwrap_or_default();
+        let account_id = access_token.primary_id();
+        let mut settings = match self.get_step_up_settings(account_id).await {
+            Ok(settings) => settings,
+            Err(err) => return err.into_http_response(),
+        };
+
+        let uri = req.uri().clone();
+        let mut path = uri.path().split('/');
+        path.next();
+        path.next();
+
+        let method = req.method().clone();
+        match (path.next().unwrap_or(""), path.next(), method) {
+            ("", None, Method::GET) => {}
+            ("totp", None, Method::POST) => {
+                // Second factors are provisioned out of band, either in the configuration
+                // or by an administrator, and can only be replaced after proving possession
+                // of the current one.
+                if !self.has_step_up(&key, account_id) {
+                    return step_up_required();
+                }
+
+                let mut secret = vec![0u8; TOTP_SECRET_LEN];
+                thread_rng().fill_bytes(&mut secret);
+                let encoded_secret = base32_encode(&secret);
+                let account_name =
+                    match form_urlencoded::parse(uri.query().unwrap_or_default().as_bytes())
+                        .find(|(key, _)| key == "account")
+                    {
+                        Some((_, name)) => {
+                            // Administrators may provision the factor of any account
+                            if !access_token.is_super_user() {
+                                return RequestError::forbidden().into_http_response();
+                            }
+                            let target_id = match self.store.get_account_id(&name).await {
+                                Ok(Some(target_id)) => target_id,
+                                Ok(None) => return RequestError::not_found().into_http_response(),
+                                Err(_) => {
+                                    return RequestError::internal_server_error()
+                                        .into_http_response()
+                                }
+                            };
+                            let target = StepUpSettings {
+                                totp: secret.into(),
+                                pending: None,
+                                last_counter: 0,
+                            };
+                            if let Err(err) = self.set_step_up_settings(target_id, &target).await {
+                                return err.into_http_response();
+                            }
+                            self.log_step_up_event(
+                                &access_token,
+                                remote_addr,
+                                &format!("totp-provision:{name}"),
+                            )
+                            .await;
+                            name.into_owned()
+                        }
+                        None => {
+                            settings.pending = secret.into();
+                            if let Err(err) = self.set_step_up_settings(account_id, &settings).await
+                            {
+                                return err.into_http_response();
+                            }
+                            self.log_step_up_event(&access_token, remote_addr, "totp-enroll")
+                                .await;
+                            access_token.name.clone()
+                        }
+                    };
+
+                // The secret is only returned once
+                let issuer = form_urlencoded::byte_serialize(self.config.step_up_issuer.as_bytes())
+                    .collect::<String>();
+                return JsonResponse::new(json!({
+                    "data": {
+                        "secret": encoded_secret,
+                        "uri": format!(
+                            concat!(
+                                "otpauth://totp/{}:{}?secret={}&issuer={}",
+                                "&algorithm=SHA1&digits=6&period={}"
+                            ),
+                            issuer,
+                            form_urlencoded::byte_serialize(account_name.as_bytes())
+                                .collect::<String>(),
+                            encoded_secret,
+                            issuer,
+                            TOTP_PERIOD
+                        ),
+                    },
+                }))
+                .into_http_response();
+            }
+            ("totp", Some("confirm"), Method::POST) => {
+                let code = match self.parse_step_up_request(req).await {
+                    Ok(code) => code,
+                    Err(err) => return err.into_http_response(),
+                };
+                let secret = match settings.pending.take() {
+                    Some(secret) => secret,
+                    None => {
+                        return RequestError::blank(
+                            StatusCode::BAD_REQUEST.as_u16(),
+                            "Invalid parameters",
+                            "No second factor enrollment is pending.",
+                        )
+                        .into_http_response()
+                    }
+                };
+                // Every attempt counts against the account so that codes can not be guessed
+                if let Err(err) = self.is_step_up_allowed(account_id) {
+                    return err.into_http_response();
+                }
+                if !settings.verify_code(&secret, &code) {
+                    self.log_step_up_event(&access_token, remote_addr, "failed")
+                        .await;
+                    return invalid_code();
+                }
+                settings.totp = secret.into();
+                if let Err(err) = self.set_step_up_settings(account_id, &settings).await {
+                    return err.into_http_response();
+                }
+                self.grant_step_up(key, account_id);
+                self.log_step_up_event(&access_token, remote_addr, "totp-enabled")
+                    .await;
+            }
+            ("totp", None, Method::DELETE) => {
+                if !self.has_step_up(&key, account_id) {
+                    return step_up_required();
+                }
+                settings = StepUpSettings::default();
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(Collection::Principal)
+                    .update_document(0)
+                    .value(Property::StepUp, (), F_VALUE | F_CLEAR);
+                if self.write_batch(batch).await.is_err() {
+                    return RequestError::internal_server_error().into_http_response();
+                }
+                self.revoke_step_up(key);
+                self.log_step_up_event(&access_token, remote_addr, "totp-removed")
+                    .await;
+            }
+            ("verify", None, Method::POST) => {
+                let code = match self.parse_step_up_request(req).await {
+                    Ok(code) => code,
+                    Err(err) => return err.into_http_response(),
+                };
+                let secret = match self.step_up_secret(&access_token, &settings) {
+                    Some(secret) => secret,
+                    None => {
+                        return RequestError::blank(
+                            StatusCode::BAD_REQUEST.as_u16(),
+                            "Invalid parameters",
+                            "No second factor is enrolled for this account.",
+                        )
+                        .into_http_response()
+                    }
+                };
+                // Every attempt counts against the account so that codes can not be guessed
+                if let Err(err) = self.is_step_up_allowed(account_id) {
+                    return err.into_http_response();
+                }
+                if !settings.verify_code(&secret, &code) {
+                    self.log_step_up_event(&access_token, remote_addr, "failed")
+                        .await;
+                    return invalid_code();
+                }
+                if let Err(err) = self.set_step_up_settings(account_id, &settings).await {
+                    return err.into_http_response();
+                }
+                self.grant_step_up(key, account_id);
+                self.log_step_up_event(&access_token, remote_addr, "verified")
+                    .await;
+            }
+            _ => return RequestError::not_found().into_http_response(),
+        }
+
+        JsonResponse::new(json!({
+            "data": {
+                "totpEnabled": self.step_up_secret(&access_token, &settings).is_some(),
+                "verified": self.has_step_up(&key, account_id),
+                "maxAge": self.config.step_up_max_age.as_secs(),
+            },
+        }))
+        .into_http_response()
+    }
+
+    pub async fn assert_step_up(
+        &self,
+        req: &HttpRequest,
+        access_token: &Arc<AccessToken>,
+        remote_addr: IpAddr,
+    ) -> Result<(), HttpResponse> {
+        let operation = match StepUpOperation::classify(req.method(), req.uri().path()) {
+            Some(operation)
+                if self.config.step_up_enable
+                    && self.config.step_up_operations.contains(&operation) =>
+            {
+                operation
+            }
+            _ => return Ok(()),
+        };
+
+        let key = step_up_key(req).unwrap_or_default();
+        if self.has_step_up(&key, access_token.primary_id()) {
+            tracing::info!(
+                context = "step-up",
+                event = "authorized",
+                account = access_token.name.as_str(),
+                operation = operation.as_str(),
+                "Sensitive operation authorized by step-up verification."
+            );
+            self.log_step_up_event(
+                access_token,
+                remote_addr,
+                &format!("authorized:{}", operation.as_str()),
+            )
+            .await;
+            Ok(())
+        } else {
+            tracing::info!(
+                context = "step-up",
+                event = "required",
+                account = access_token.name.as_str(),
+                operation = operation.as_str(),
+                "Sensitive operation rejected, step-up verification required."
+            );
+            self.log_step_up_event(
+                access_token,
+                remote_addr,
+                &format!("required:{}", operation.as_str()),
+            )
+            .await;
+            Err(step_up_required())
+        }
+    }
+
+    // Enrolled factors take precedence over the secrets provisioned in the configuration
+    fn step_up_secret(
+        &self,
+        access_token: &AccessToken,
+        settings: &StepUpSettings,
+    ) -> Option<Vec<u8>> {
+        settings
+            .totp
+            .clone()
+            .or_else(|| self.config.step_up_secrets.get(&access_token.name).cloned())
+    }
+
+    fn has_step_up(&self, key: &str, account_id: u32) -> bool {
+        !key.is_empty() && self.step_up.get_with_ttl(key) == Some(account_id)
+    }
+
+    fn grant_step_up(&self, key: String, account_id: u32) {
+        if !key.is_empty() {
+            self.step_up.insert_with_ttl(
+                key,
+                account_id,
+                Instant::now() + self.config.step_up_max_age,
+            );
+        }
+    }
+
+    fn revoke_step_up(&self, key: String) {
+        if let Some(valid_until) = Instant::now().checked_sub(Duration::from_secs(1)) {
+            self.step_up.insert_with_ttl(key, u32::MAX, valid_until);
+        }
+    }
+
+    pub async fn get_step_up_settings(
+        &self,
+        account_id: u32,
+    ) -> Result<StepUpSettings, RequestError> {
+        self.get_property::<Bincode<StepUpSettings>>(
+            account_id,
+            Collection::Principal,
+            0,
+            Property::StepUp,
+        )
+        .await
+        .map(|settings| settings.map(|s| s.inner).unwrap_or_default())
+        .map_err(|_| RequestError::internal_server_error())
+    }
+
+    pub async fn set_step_up_settings(
+        &self,
+        account_id: u32,
+        settings: &StepUpSettings,
+    ) -> Result<(), RequestError> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Principal)
+            .update_document(0)
+            .value(Property::StepUp, Bincode::new(settings.clone()), F_VALUE);
+        self.write_batch(batch)
+            .await
+            .map_err(|_| RequestError::internal_server_error())
+    }
+
+    async fn parse_step_up_request(&self, req: &mut HttpRequest) -> Result<String, RequestError> {
+        fetch_body(req, 1024, &AccessToken::default())
+            .await
+            .and_then(|body| serde_json::from_slice::<StepUpRequest>(&body).ok())
+            .and_then(|request| request.code)
+            .ok_or_else(|| {
+                RequestError::blank(
+                    StatusCode::BAD_REQUEST.as_u16(),
+                    "Invalid parameters",
+                    "Failed to deserialize step-up request",
+                )
+            })
+    }
+
+    async fn log_step_up_event(
+        &self,
+        access_token: &Arc<AccessToken>,
+        remote_addr: IpAddr,
+        event: &str,
+    ) {
+        self.log_activity(
+            access_token.primary_id(),
+            ActivityEvent::StepUp,
+            ServerProtocol::Jmap,
+            remote_addr.into(),
+            event.to_string().into(),
+        )
+        .await;
+    }
+}
+
+impl StepUpOperation {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "account-purge" => StepUpOperation::AccountPurge,
+            "store-purge" => StepUpOperation::StorePurge,
+            "config-change" => StepUpOperation::ConfigChange,
+            "queue-cancel" => StepUpOperation::QueueCancel,
+            _ => return None,
+        }
+        .into()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StepUpOperation::AccountPurge => "account-purge",
+            StepUpOperation::StorePurge => "store-purge",
+            StepUpOperation::ConfigChange => "config-change",
+            StepUpOperation::QueueCancel => "queue-cancel",
+        }
+    }
+
+    // Maps an admin API request to the class of sensitive operation it performs
+    pub fn classify(method: &Method, path: &str) -> Option<Self> {
+        let mut path = path.split('/').skip(2);
+        match (path.next().unwrap_or_default(), path.next(), method) {
+            ("principal", _, &Method::DELETE) => StepUpOperation::AccountPurge,
+            ("store", Some("maintenance"), _) => StepUpOperation::StorePurge,
+            ("config", _, &Method::POST | &Method::DELETE) => StepUpOperation::ConfigChange,
+            ("queue", Some("cancel"), _) => StepUpOperation::QueueCancel,
+            _ => return None,
+        }
+        .into()
+    }
+}
+
+impl StepUpSettings {
+    // Accepts codes from the adjacent time steps to allow for clock drift,
+    // codes can not be reused once accepted.
+    fn verify_code(&mut self, secret: &[u8], code: &str) -> bool {
+        let code = match code.trim().parse::<u32>() {
+            Ok(code) => code,
+            Err(_) => return false,
+        };
+        let counter = now() / TOTP_PERIOD;
+        for counter in counter.saturating_sub(1)..=counter + 1 {
+            if counter > self.last_counter && totp_code(secret, counter) == code {
+                self.last_counter = counter;
+                return true;
+            }
+        }
+        false
+    }
+}
+
+pub fn totp_code(secret: &[u8], counter: u64) -> u32 {
+    // HMAC-SHA1 over the big-endian counter
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation (RFC 4226)
+    let offset = (hash[19] & 0x0f) as usize;
+    (u32::from_be_bytes(hash[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff) % TOTP_MODULO
+}
+
+pub fn base32_decode(value: &str) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(value.len() * 5 / 8);
+    let mut buf = 0u32;
+    let mut bits = 0;
+    for ch in value.trim().trim_end_matches('=').bytes() {
+        let pos = BASE32_ALPHABET
+            .iter()
+            .position(|&b| b == ch.to_ascii_uppercase())?;
+        buf = (buf << 5) | pos as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buf >> bits) as u8);
+        }
+    }
+    Some(result)
+}
+
+pub fn base32_encode(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity((bytes.len() * 8 + 4) / 5);
+    let mut buf = 0u32;
+    let mut bits = 0;
+    for &byte in bytes {
+        buf = (buf << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            result.push(BASE32_ALPHABET[((buf >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        result.push(BASE32_ALPHABET[((buf << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    result
+}
+
+// Binds step-up verifications to the credentials presented by the client
+fn step_up_key(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .map(|value| blake3::hash(value.as_bytes()).to_hex().to_string())
+}
+
+fn step_up_required() -> HttpResponse {
+    RequestError::blank(
+        StatusCode::FORBIDDEN.as_u16(),
+        "Step-up required",
+        "This operation requires a recent second-factor verification.",
+    )
+    .into_http_response()
+}
+
+fn invalid_code() -> HttpResponse {
+    RequestError::blank(
+        StatusCode::UNAUTHORIZED.as_u16(),
+        "Invalid code",
+        "The verification code is invalid or has expired.",
+    )
+    .into_http_response()
+}
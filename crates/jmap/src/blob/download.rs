@@ -35,17 +35,64 @@ use mail_parser::{
     decoders::{base64::base64_decode, quoted_printable::quoted_printable_decode},
     Encoding,
 };
-use store::{BlobClass, BlobHash};
+use store::{
+    dispatch::blob::{BlobStream, BLOB_STREAM_CHUNK_SIZE},
+    BlobClass, BlobHash,
+};
 
 use crate::{auth::AccessToken, JMAP};
 
 impl JMAP {
-    #[allow(clippy::blocks_in_if_conditions)]
     pub async fn blob_download(
         &self,
         blob_id: &BlobId,
         access_token: &AccessToken,
     ) -> Result<Option<Vec<u8>>, MethodError> {
+        if !self.blob_download_access(blob_id, access_token).await? {
+            return Ok(None);
+        }
+
+        if let Some(section) = &blob_id.section {
+            self.get_blob_section(&blob_id.hash, section).await
+        } else {
+            self.get_blob(&blob_id.hash, 0..u32::MAX).await
+        }
+    }
+
+    // Streams the blob from the store in bounded chunks, only sections
+    // that need to be decoded are loaded into memory.
+    pub async fn blob_download_stream(
+        &self,
+        blob_id: &BlobId,
+        access_token: &AccessToken,
+    ) -> Result<Option<BlobStream>, MethodError> {
+        if !self.blob_download_access(blob_id, access_token).await? {
+            return Ok(None);
+        }
+
+        match &blob_id.section {
+            Some(section) if !matches!(Encoding::from(section.encoding), Encoding::None) => {
+                Ok(self
+                    .get_blob_section(&blob_id.hash, section)
+                    .await?
+                    .map(|bytes| BlobStream::from_bytes(bytes, BLOB_STREAM_CHUNK_SIZE)))
+            }
+            Some(section) => Ok(self.get_blob_stream(&blob_id.hash).await?.map(|stream| {
+                stream.narrow(
+                    section.offset_start as u64
+                        ..section.offset_start.saturating_add(section.size) as u64,
+                )
+            })),
+            None => self.get_blob_stream(&blob_id.hash).await,
+        }
+    }
+
+    #[allow(clippy::blocks_in_if_conditions)]
+    async fn blob_download_access(
+        &self,
+        blob_id: &BlobId,
+        access_token: &AccessToken,
+    ) -> Result<bool, MethodError> {
         if !self
             .store
             .blob_has_access(&blob_id.hash, &blob_id.class)
@@ -58,7 +105,7 @@ impl JMAP {
                 MethodError::ServerPartialFail
             })?
         {
-            return Ok(None);
+            return Ok(false);
         }
 
         if !access_token.is_member(blob_id.class.account_id()) {
@@ -74,7 +121,7 @@ impl JMAP {
                             .await
                         {
                             Ok(shared_messages) if shared_messages.contains(*document_id) => (),
-                            _ => return Ok(None),
+                            _ => return Ok(false),
                         }
                     } else {
                         match self
@@ -88,21 +135,17 @@ impl JMAP {
                             .await
                         {
                             Ok(has_access) if has_access => (),
-                            _ => return Ok(None),
+                            _ => return Ok(false),
                         }
                     }
                 }
                 BlobClass::Reserved { .. } => {
-                    return Ok(None);
+                    return Ok(false);
                 }
             }
         }
 
-        if let Some(section) = &blob_id.section {
-            self.get_blob_section(&blob_id.hash, section).await
-        } else {
-            self.get_blob(&blob_id.hash, 0..u32::MAX).await
-        }
+        Ok(true)
     }
 
     pub async fn get_blob_section(
@@ -142,6 +185,27 @@ impl JMAP {
         }
     }
 
+    pub async fn get_blob_stream(
+        &self,
+        hash: &BlobHash,
+    ) -> Result<Option<BlobStream>, MethodError> {
+        match self
+            .blob_store
+            .get_blob_stream(hash.as_ref(), BLOB_STREAM_CHUNK_SIZE)
+            .await
+        {
+            Ok(stream) => Ok(stream),
+            Err(err) => {
+                tracing::error!(event = "error",
+                                context = "blob_store",
+                                blob_id = ?hash,
+                                error = ?err,
+                                "Failed to open blob stream");
+                Err(MethodError::ServerPartialFail)
+            }
+        }
+    }
+
     pub async fn has_access_blob(
         &self,
         blob_id: &BlobId,
@@ -21,7 +21,10 @@
  * for more details.
 */
 
+use std::ops::Range;
+
 use jmap_proto::types::{blob::BlobId, id::Id};
+use store::dispatch::blob::BlobStream;
 
 pub mod copy;
 pub mod download;
@@ -42,5 +45,7 @@ pub struct UploadResponse {
 pub struct DownloadResponse {
     pub filename: String,
     pub content_type: String,
-    pub blob: Vec<u8>,
+    pub blob: BlobStream,
+    // Requested byte range and the size of the whole blob
+    pub range: Option<(Range<u64>, u64)>,
 }
@@ -62,6 +62,31 @@ impl JMAP {
 
                 Collection::EmailSubmission
             }
+            RequestArguments::Label => {
+                access_token.assert_is_member(request.account_id)?;
+
+                Collection::Label
+            }
+            RequestArguments::TagRule => {
+                access_token.assert_is_member(request.account_id)?;
+
+                Collection::TagRule
+            }
+            RequestArguments::ArchivePolicy => {
+                access_token.assert_is_member(request.account_id)?;
+
+                Collection::ArchivePolicy
+            }
+            RequestArguments::DisposableAlias => {
+                access_token.assert_is_member(request.account_id)?;
+
+                Collection::DisposableAlias
+            }
+            RequestArguments::Annotation => {
+                access_token.assert_is_member(request.account_id)?;
+
+                Collection::Annotation
+            }
             RequestArguments::Quota => {
                 access_token.assert_is_member(request.account_id)?;
 
@@ -0,0 +1,263 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::{
+    net::{IpAddr, ToSocketAddrs},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use store::{ahash::AHashSet, blake3, write::now};
+use utils::config::Config;
+
+pub struct Cluster {
+    pub local_id: String,
+    pub nodes: Vec<ClusterNode>,
+    pub peer_ips: AHashSet<IpAddr>,
+    pub timeout_connect: Duration,
+    pub timeout_auth: Duration,
+    pub retry_after: Duration,
+    pub metrics: ClusterMetrics,
+}
+
+pub struct ClusterNode {
+    pub id: String,
+    pub imap: Option<NodeAddress>,
+    pub down_until: AtomicU64,
+}
+
+#[derive(Debug, Clone)]
+pub struct NodeAddress {
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    pub allow_invalid_certs: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ClusterMetrics {
+    pub sessions_local: AtomicU64,
+    pub sessions_proxied: AtomicU64,
+    pub sessions_proxied_active: AtomicU64,
+    pub failovers: AtomicU64,
+    pub proxy_errors: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Imap,
+}
+
+impl Cluster {
+    pub fn parse(config: &Config) -> Result<Option<Self>, String> {
+        let local_id = if let Some(local_id) = config.value("cluster.node") {
+            local_id.to_string()
+        } else {
+            return Ok(None);
+        };
+
+        let mut nodes = Vec::new();
+        let mut peer_ips = AHashSet::new();
+        for id in config.sub_keys("cluster.nodes", "") {
+            let imap = if let Some(address) = config.value(("cluster.nodes", id, "imap.address")) {
+                let (host, port) = address
+                    .rsplit_once(':')
+                    .and_then(|(host, port)| {
+                        (
+                            host.trim_start_matches('[').trim_end_matches(']'),
+                            port.parse::<u16>().ok()?,
+                        )
+                            .into()
+                    })
+                    .ok_or_else(|| {
+                        format!(
+                            "Invalid address {address:?} for property \"cluster.nodes.{id}.imap.address\"."
+                        )
+                    })?;
+
+                // Sessions arriving from other nodes are never proxied again
+                if id != local_id {
+                    if let Ok(addrs) = (host, port).to_socket_addrs() {
+                        peer_ips.extend(addrs.map(|addr| addr.ip()));
+                    }
+                }
+
+                NodeAddress {
+                    host: host.to_string(),
+                    port,
+                    tls: config.property_or_static(("cluster.nodes", id, "imap.tls"), "false")?,
+                    allow_invalid_certs: config.property_or_static(
+                        ("cluster.nodes", id, "imap.allow-invalid-certs"),
+                        "false",
+                    )?,
+                }
+                .into()
+            } else {
+                None
+            };
+
+            nodes.push(ClusterNode {
+                id: id.to_string(),
+                imap,
+                down_until: AtomicU64::new(0),
+            });
+        }
+
+        if !nodes.iter().any(|node| node.id == local_id) {
+            return Err(format!(
+                "Local node {local_id:?} is not defined in \"cluster.nodes\"."
+            ));
+        }
+
+        Ok(Some(Cluster {
+            local_id,
+            nodes,
+            peer_ips,
+            timeout_connect: config.property_or_static("cluster.routing.timeout.connect", "5s")?,
+            timeout_auth: config.property_or_static("cluster.routing.timeout.auth", "15s")?,
+            retry_after: config.property_or_static("cluster.routing.retry-after", "1m")?,
+            metrics: ClusterMetrics::default(),
+        }))
+    }
+
+    /// Returns the nodes able to serve the account, ordered by their
+    /// rendezvous score. Nodes currently marked as down are skipped.
+    pub fn route(&self, account: &str, protocol: Protocol) -> Vec<&ClusterNode> {
+        let now = now();
+        let mut nodes = self
+            .nodes
+            .iter()
+            .filter(|node| {
+                (node.id == self.local_id || node.address(protocol).is_some())
+                    && node.down_until.load(Ordering::Relaxed) <= now
+            })
+            .map(|node| (node.score(account), node))
+            .collect::<Vec<_>>();
+        nodes.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+        nodes.into_iter().map(|(_, node)| node).collect()
+    }
+
+    pub fn is_local(&self, node: &ClusterNode) -> bool {
+        node.id == self.local_id
+    }
+
+    pub fn is_peer(&self, ip: &IpAddr) -> bool {
+        self.peer_ips.contains(ip)
+    }
+
+    pub fn mark_down(&self, node: &ClusterNode) {
+        node.down_until
+            .store(now() + self.retry_after.as_secs(), Ordering::Relaxed);
+        self.metrics.failovers.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl ClusterNode {
+    pub fn address(&self, protocol: Protocol) -> Option<&NodeAddress> {
+        match protocol {
+            Protocol::Imap => self.imap.as_ref(),
+        }
+    }
+
+    fn score(&self, account: &str) -> u64 {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(self.id.as_bytes());
+        hasher.update(&[0]);
+        hasher.update(account.to_lowercase().as_bytes());
+        let hash = hasher.finalize();
+        u64::from_be_bytes(hash.as_bytes()[..8].try_into().unwrap())
+    }
+}
+
+impl ClusterMetrics {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "sessionsLocal": self.sessions_local.load(Ordering::Relaxed),
+            "sessionsProxied": self.sessions_proxied.load(Ordering::Relaxed),
+            "sessionsProxiedActive": self.sessions_proxied_active.load(Ordering::Relaxed),
+            "failovers": self.failovers.load(Ordering::Relaxed),
+            "proxyErrors": self.proxy_errors.load(Ordering::Relaxed),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use utils::config::Config;
+
+    use super::{Cluster, Protocol};
+
+    #[test]
+    fn rendezvous_routing() {
+        let config = Config::new(
+            r#"[cluster]
+node = "a"
+
+[cluster.nodes.a.imap]
+address = "127.0.0.1:143"
+
+[cluster.nodes.b.imap]
+address = "127.0.0.1:1143"
+
+[cluster.nodes.c.imap]
+address = "127.0.0.1:2143"
+"#,
+        )
+        .unwrap();
+        let cluster = Cluster::parse(&config).unwrap().unwrap();
+
+        // Routing is stable and case insensitive
+        let mut homes = [0usize; 3];
+        for n in 0..300 {
+            let account = format!("user{n}@example.org");
+            let route = cluster.route(&account, Protocol::Imap);
+            assert_eq!(route.len(), 3);
+            assert_eq!(
+                route[0].id,
+                cluster.route(&account.to_uppercase(), Protocol::Imap)[0].id
+            );
+            homes[cluster
+                .nodes
+                .iter()
+                .position(|n| n.id == route[0].id)
+                .unwrap()] += 1;
+        }
+        assert!(homes.iter().all(|&count| count > 50), "{homes:?}");
+
+        // Removing a node only moves the accounts it owned
+        let account = "john@example.org";
+        let route = cluster
+            .route(account, Protocol::Imap)
+            .into_iter()
+            .map(|n| n.id.clone())
+            .collect::<Vec<_>>();
+        let home = cluster.nodes.iter().find(|n| n.id == route[0]).unwrap();
+        cluster.mark_down(home);
+        assert_eq!(cluster.metrics.failovers.load(Ordering::Relaxed), 1);
+        let failover = cluster.route(account, Protocol::Imap);
+        assert_eq!(failover.len(), 2);
+        assert_eq!(failover[0].id, route[1]);
+        assert_eq!(failover[1].id, route[2]);
+    }
+}
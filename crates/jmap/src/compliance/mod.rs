@@ -0,0 +1,380 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{net::IpAddr, sync::Arc};
+
+use directory::backend::internal::manage::ManageDirectory;
+use hyper::{Method, StatusCode};
+use jmap_proto::{
+    error::{method::MethodError, request::RequestError},
+    method::activity::ActivityEvent,
+    types::{collection::Collection, id::Id, property::Property},
+};
+use serde_json::json;
+use store::write::{now, BatchBuilder, F_VALUE};
+use utils::config::ServerProtocol;
+
+use crate::{
+    api::{
+        http::{fetch_body, ToHttpResponse},
+        HttpRequest, HttpResponse, JsonResponse,
+    },
+    auth::AccessToken,
+    Bincode, JMAP,
+};
+
+pub mod redact;
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LegalHold {
+    pub account: Option<HoldEntry>,
+    pub messages: Vec<MessageHold>,
+    pub redactions: Vec<Redaction>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HoldEntry {
+    pub reason: String,
+    pub placed_by: String,
+    pub placed_at: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageHold {
+    pub id: String,
+    #[serde(skip_serializing)]
+    pub document_id: u32,
+    pub hold: HoldEntry,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Redaction {
+    pub id: String,
+    #[serde(skip_serializing)]
+    pub document_id: u32,
+    pub message_id: Option<String>,
+    pub blob_hash: String,
+    pub size: usize,
+    pub received_at: u64,
+    pub reason: String,
+    pub redacted_by: String,
+    pub redacted_at: u64,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct ComplianceRequest {
+    #[serde(default)]
+    reason: Option<String>,
+    #[serde(default)]
+    messages: Vec<String>,
+}
+
+impl JMAP {
+    pub async fn handle_compliance_request(
+        &self,
+        req: &mut HttpRequest,
+        access_token: Arc<AccessToken>,
+        remote_addr: IpAddr,
+    ) -> HttpResponse {
+        if !self.is_compliance_officer(&access_token) {
+            return RequestError::forbidden().into_http_response();
+        }
+
+        let uri = req.uri().clone();
+        let mut path = uri.path().split('/');
+        path.next();
+        path.next();
+        path.next();
+
+        let method = req.method().clone();
+        let (op, account_name, message_id) = (path.next().unwrap_or(""), path.next(), path.next());
+        let account_id = match account_name {
+            Some(name) if !name.is_empty() => match self.store.get_account_id(name).await {
+                Ok(Some(account_id)) => account_id,
+                Ok(None) => {
+                    return RequestError::blank(
+                        StatusCode::NOT_FOUND.as_u16(),
+                        "Not found",
+                        "Account not found.",
+                    )
+                    .into_http_response()
+                }
+                Err(_) => return RequestError::internal_server_error().into_http_response(),
+            },
+            _ => return RequestError::not_found().into_http_response(),
+        };
+        let request = match method {
+            Method::POST | Method::DELETE => match fetch_body(req, 8192, &access_token).await {
+                Some(body) if !body.is_empty() => {
+                    match serde_json::from_slice::<ComplianceRequest>(&body) {
+                        Ok(request) => request,
+                        Err(_) => {
+                            return RequestError::blank(
+                                StatusCode::BAD_REQUEST.as_u16(),
+                                "Invalid parameters",
+                                "Failed to deserialize compliance request",
+                            )
+                            .into_http_response()
+                        }
+                    }
+                }
+                _ => ComplianceRequest::default(),
+            },
+            _ => ComplianceRequest::default(),
+        };
+        let mut legal_hold = match self.get_legal_hold(account_id).await {
+            Ok(legal_hold) => legal_hold,
+            Err(_) => return RequestError::internal_server_error().into_http_response(),
+        };
+
+        match (op, message_id, method) {
+            ("hold", None, Method::GET) => JsonResponse::new(json!({
+                "data": {
+                    "account": legal_hold.account,
+                    "messages": legal_hold.messages,
+                },
+            }))
+            .into_http_response(),
+            ("hold", None, Method::POST) => {
+                let reason = match request.reason.filter(|reason| !reason.trim().is_empty()) {
+                    Some(reason) => reason,
+                    None => return missing_reason(),
+                };
+                let hold = HoldEntry {
+                    reason,
+                    placed_by: access_token.name.clone(),
+                    placed_at: now(),
+                };
+
+                let details = if request.messages.is_empty() {
+                    legal_hold.account = hold.into();
+                    "action=place, scope=account".to_string()
+                } else {
+                    let document_ids =
+                        match self.get_document_ids(account_id, Collection::Email).await {
+                            Ok(document_ids) => document_ids.unwrap_or_default(),
+                            Err(_) => {
+                                return RequestError::internal_server_error().into_http_response()
+                            }
+                        };
+                    for id in &request.messages {
+                        match Id::from_bytes(id.as_bytes()) {
+                            Some(id) if document_ids.contains(id.document_id()) => {
+                                let document_id = id.document_id();
+                                legal_hold
+                                    .messages
+                                    .retain(|hold| hold.document_id != document_id);
+                                legal_hold.messages.push(MessageHold {
+                                    id: id.to_string(),
+                                    document_id,
+                                    hold: hold.clone(),
+                                });
+                            }
+                            _ => return message_not_found(id),
+                        }
+                    }
+                    format!("action=place, messages={}", request.messages.join(","))
+                };
+
+                self.update_legal_hold(account_id, &legal_hold, &access_token, remote_addr, details)
+                    .await
+            }
+            ("hold", None, Method::DELETE) => {
+                let details = if request.messages.is_empty() {
+                    if legal_hold.account.take().is_none() {
+                        return RequestError::blank(
+                            StatusCode::NOT_FOUND.as_u16(),
+                            "Not found",
+                            "Account is not under legal hold.",
+                        )
+                        .into_http_response();
+                    }
+                    "action=release, scope=account".to_string()
+                } else {
+                    for id in &request.messages {
+                        let num_holds = legal_hold.messages.len();
+                        legal_hold.messages.retain(|hold| &hold.id != id);
+                        if num_holds == legal_hold.messages.len() {
+                            return message_not_found(id);
+                        }
+                    }
+                    format!("action=release, messages={}", request.messages.join(","))
+                };
+
+                self.update_legal_hold(account_id, &legal_hold, &access_token, remote_addr, details)
+                    .await
+            }
+            ("redact", None, Method::GET) => JsonResponse::new(json!({
+                "data": legal_hold.redactions,
+            }))
+            .into_http_response(),
+            ("redact", Some(message_id), Method::POST) => {
+                let reason = match request.reason.filter(|reason| !reason.trim().is_empty()) {
+                    Some(reason) => reason,
+                    None => return missing_reason(),
+                };
+                let id = match Id::from_bytes(message_id.as_bytes()) {
+                    Some(id) => id,
+                    None => return message_not_found(message_id),
+                };
+
+                match self
+                    .email_redact(account_id, id, reason, &access_token, remote_addr)
+                    .await
+                {
+                    Ok(Some(redaction)) => JsonResponse::new(json!({
+                        "data": redaction,
+                    }))
+                    .into_http_response(),
+                    Ok(None) => message_not_found(message_id),
+                    Err(_) => RequestError::internal_server_error().into_http_response(),
+                }
+            }
+            _ => RequestError::not_found().into_http_response(),
+        }
+    }
+
+    pub fn is_compliance_officer(&self, access_token: &AccessToken) -> bool {
+        if self.config.compliance_officers.is_empty() {
+            access_token.is_super_user()
+        } else {
+            self.config
+                .compliance_officers
+                .iter()
+                .any(|name| name == &access_token.name)
+        }
+    }
+
+    pub async fn get_legal_hold(&self, account_id: u32) -> Result<LegalHold, MethodError> {
+        self.get_property::<Bincode<LegalHold>>(
+            account_id,
+            Collection::Principal,
+            0,
+            Property::LegalHold,
+        )
+        .await
+        .map(|legal_hold| legal_hold.map(|l| l.inner).unwrap_or_default())
+    }
+
+    pub async fn set_legal_hold(
+        &self,
+        account_id: u32,
+        legal_hold: &LegalHold,
+    ) -> Result<(), MethodError> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Principal)
+            .update_document(0)
+            .value(
+                Property::LegalHold,
+                Bincode::new(legal_hold.clone()),
+                F_VALUE,
+            );
+        self.write_batch(batch).await
+    }
+
+    pub async fn is_under_legal_hold(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> Result<bool, MethodError> {
+        self.get_legal_hold(account_id)
+            .await
+            .map(|legal_hold| legal_hold.is_held(document_id))
+    }
+
+    async fn update_legal_hold(
+        &self,
+        account_id: u32,
+        legal_hold: &LegalHold,
+        access_token: &AccessToken,
+        remote_addr: IpAddr,
+        details: String,
+    ) -> HttpResponse {
+        if self.set_legal_hold(account_id, legal_hold).await.is_err() {
+            return RequestError::internal_server_error().into_http_response();
+        }
+
+        tracing::info!(
+            context = "compliance",
+            event = "legal-hold",
+            account_id = account_id,
+            officer = access_token.name,
+            details = details,
+            "Legal hold updated."
+        );
+        self.log_activity(
+            account_id,
+            ActivityEvent::LegalHold,
+            ServerProtocol::Jmap,
+            remote_addr.into(),
+            format!("{details}, officer={}", access_token.name).into(),
+        )
+        .await;
+
+        JsonResponse::new(json!({
+            "data": {
+                "account": legal_hold.account,
+                "messages": legal_hold.messages,
+            },
+        }))
+        .into_http_response()
+    }
+}
+
+impl LegalHold {
+    pub fn is_held(&self, document_id: u32) -> bool {
+        self.account.is_some()
+            || self
+                .messages
+                .iter()
+                .any(|hold| hold.document_id == document_id)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.account.is_some() || !self.messages.is_empty()
+    }
+}
+
+fn missing_reason() -> HttpResponse {
+    RequestError::blank(
+        StatusCode::BAD_REQUEST.as_u16(),
+        "Invalid parameters",
+        "A reason is required for compliance operations.",
+    )
+    .into_http_response()
+}
+
+fn message_not_found(id: &str) -> HttpResponse {
+    RequestError::blank(
+        StatusCode::NOT_FOUND.as_u16(),
+        "Not found",
+        format!("Message {id} does not exist."),
+    )
+    .into_http_response()
+}
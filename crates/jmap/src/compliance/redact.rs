@@ -0,0 +1,236 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::net::IpAddr;
+
+use jmap_proto::{
+    error::method::MethodError,
+    method::activity::ActivityEvent,
+    types::{
+        collection::Collection, date::UTCDate, id::Id, property::Property, state::StateChange,
+        type_state::DataType,
+    },
+};
+use mail_parser::{HeaderName, MessageParser};
+use store::write::{assert::HashedValue, log::ChangeLogBuilder, now, BatchBuilder, ValueClass};
+use utils::config::ServerProtocol;
+
+use crate::{
+    auth::AccessToken,
+    email::{index::EmailIndexBuilder, metadata::MessageMetadata},
+    services::housekeeper::Event,
+    Bincode, JMAP,
+};
+
+use super::Redaction;
+
+const REDACTED_SUBJECT: &str = "[Redacted]";
+const REDACTED_PREVIEW: &str = "This message has been redacted.";
+
+impl JMAP {
+    pub async fn email_redact(
+        &self,
+        account_id: u32,
+        id: Id,
+        reason: String,
+        access_token: &AccessToken,
+        remote_addr: IpAddr,
+    ) -> Result<Option<Redaction>, MethodError> {
+        // Obtain message metadata and contents
+        let document_id = id.document_id();
+        let (metadata, thread_id) = match (
+            self.get_property::<HashedValue<Bincode<MessageMetadata>>>(
+                account_id,
+                Collection::Email,
+                document_id,
+                Property::BodyStructure,
+            )
+            .await?,
+            self.get_property::<u32>(
+                account_id,
+                Collection::Email,
+                document_id,
+                Property::ThreadId,
+            )
+            .await?,
+        ) {
+            (Some(metadata), Some(thread_id)) => (metadata, thread_id),
+            _ => return Ok(None),
+        };
+        let raw_message = self
+            .get_blob(&metadata.inner.inner.blob_hash, 0..u32::MAX)
+            .await?
+            .ok_or_else(|| {
+                tracing::error!(
+                    event = "error",
+                    context = "email_redact",
+                    account_id = account_id,
+                    document_id = document_id,
+                    blob_hash = ?metadata.inner.inner.blob_hash,
+                    "Message blob not found."
+                );
+                MethodError::ServerPartialFail
+            })?;
+        let message = MessageParser::default()
+            .parse(&raw_message)
+            .ok_or(MethodError::ServerPartialFail)?;
+
+        // Build tombstone, keeping only the headers needed to audit the original message
+        let redacted_at = now();
+        let mut tombstone = Vec::with_capacity(1024);
+        for header in &message.parts[0].headers {
+            if matches!(
+                header.name,
+                HeaderName::MessageId
+                    | HeaderName::Date
+                    | HeaderName::From
+                    | HeaderName::Sender
+                    | HeaderName::To
+                    | HeaderName::Cc
+                    | HeaderName::InReplyTo
+                    | HeaderName::References
+            ) {
+                if let Some(bytes) = raw_message.get(header.offset_field..header.offset_end) {
+                    tombstone.extend_from_slice(bytes);
+                    if !tombstone.ends_with(b"\n") {
+                        tombstone.extend_from_slice(b"\r\n");
+                    }
+                }
+            }
+        }
+        tombstone.extend_from_slice(
+            format!(
+                concat!(
+                    "Subject: {}\r\n",
+                    "MIME-Version: 1.0\r\n",
+                    "Content-Type: text/plain; charset=\"utf-8\"\r\n",
+                    "Content-Transfer-Encoding: 7bit\r\n",
+                    "\r\n",
+                    "{} The original contents were removed on {} and ",
+                    "are no longer available.\r\n"
+                ),
+                REDACTED_SUBJECT,
+                REDACTED_PREVIEW,
+                UTCDate::from_timestamp(redacted_at as i64)
+            )
+            .as_bytes(),
+        );
+        let redaction = Redaction {
+            id: Id::from_parts(thread_id, document_id).to_string(),
+            document_id,
+            message_id: message.message_id().map(|id| id.to_string()),
+            blob_hash: metadata
+                .inner
+                .inner
+                .blob_hash
+                .as_slice()
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect(),
+            size: metadata.inner.inner.size,
+            received_at: metadata.inner.inner.received_at,
+            reason,
+            redacted_by: access_token.name.clone(),
+            redacted_at,
+        };
+        let tombstone_message = MessageParser::default()
+            .parse(&tombstone)
+            .ok_or(MethodError::ServerPartialFail)?;
+
+        // Store tombstone
+        let blob_id = self.put_blob(account_id, &tombstone, false).await?;
+
+        // Remove the original contents from the full-text index
+        if let Err(err) = self
+            .fts_store
+            .remove(account_id, Collection::Email.into(), document_id)
+            .await
+        {
+            tracing::error!(
+                event = "error",
+                context = "email_redact",
+                account_id = account_id,
+                document_id = document_id,
+                reason = ?err,
+                "Failed to remove document from FTS index."
+            );
+            return Err(MethodError::ServerPartialFail);
+        }
+
+        // Replace message contents
+        let received_at = metadata.inner.inner.received_at;
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Email)
+            .update_document(document_id)
+            .assert_value(Property::BodyStructure, &metadata)
+            .custom(EmailIndexBuilder::clear(metadata.inner.inner))
+            .custom(EmailIndexBuilder::set(MessageMetadata {
+                preview: REDACTED_PREVIEW.to_string(),
+                size: tombstone.len(),
+                contents: tombstone_message.into(),
+                received_at,
+                has_attachments: false,
+                blob_hash: blob_id.hash.clone(),
+            }))
+            .set(
+                ValueClass::IndexEmail(self.generate_snowflake_id()?),
+                blob_id.hash.clone(),
+            );
+        self.write_batch(batch).await?;
+        let _ = self.housekeeper_tx.send(Event::IndexStart).await;
+
+        let mut changes = ChangeLogBuilder::new();
+        changes.log_update(Collection::Email, Id::from_parts(thread_id, document_id));
+        let change_id = self.commit_changes(account_id, changes).await?;
+        self.broadcast_state_change(
+            StateChange::new(account_id).with_change(DataType::Email, change_id),
+        )
+        .await;
+
+        // Keep an audit record of the redaction
+        let mut legal_hold = self.get_legal_hold(account_id).await?;
+        legal_hold.redactions.push(redaction.clone());
+        self.set_legal_hold(account_id, &legal_hold).await?;
+
+        tracing::info!(
+            context = "compliance",
+            event = "redact",
+            account_id = account_id,
+            document_id = document_id,
+            officer = access_token.name,
+            "Message redacted."
+        );
+        self.log_activity(
+            account_id,
+            ActivityEvent::Redaction,
+            ServerProtocol::Jmap,
+            remote_addr.into(),
+            format!("id={}, officer={}", redaction.id, access_token.name).into(),
+        )
+        .await;
+
+        Ok(Some(redaction))
+    }
+}
@@ -0,0 +1,256 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod get;
+pub mod set;
+
+use directory::{
+    core::alias::{AliasStats, DisposableAlias},
+    QueryBy,
+};
+use jmap_proto::{
+    error::method::MethodError,
+    object::Object,
+    types::{collection::Collection, property::Property, value::Value},
+};
+use store::{
+    rand::{distributions::Alphanumeric, thread_rng, Rng},
+    write::{log::ChangeLogBuilder, BatchBuilder, F_CLEAR, F_VALUE},
+};
+
+use crate::JMAP;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DisposableAliasReport {
+    pub id: u32,
+    pub address: String,
+    pub description: Option<String>,
+    pub expires: Option<u64>,
+    #[serde(rename = "blockedSenders")]
+    pub blocked_senders: Vec<String>,
+    pub stats: AliasStats,
+}
+
+impl JMAP {
+    pub async fn disposable_alias_list(
+        &self,
+        account_id: u32,
+    ) -> Result<Vec<(u32, Object<Value>)>, MethodError> {
+        let alias_ids = self
+            .get_document_ids(account_id, Collection::DisposableAlias)
+            .await?
+            .unwrap_or_default();
+        let mut aliases = Vec::with_capacity(alias_ids.len() as usize);
+        for document_id in alias_ids {
+            if let Some(alias) = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::DisposableAlias,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                aliases.push((document_id, alias));
+            }
+        }
+
+        Ok(aliases)
+    }
+
+    pub async fn disposable_alias_report(
+        &self,
+        account_id: u32,
+    ) -> Result<Vec<DisposableAliasReport>, MethodError> {
+        let aliases = self.disposable_alias_list(account_id).await?;
+        let mut report = Vec::with_capacity(aliases.len());
+        for (document_id, mut alias) in aliases {
+            let address = if let Value::Text(address) = alias.remove(&Property::Email) {
+                address
+            } else {
+                continue;
+            };
+            let stats = self
+                .directory
+                .alias_stats(&address)
+                .await
+                .map_err(|_| MethodError::ServerPartialFail)?;
+
+            report.push(DisposableAliasReport {
+                id: document_id,
+                description: match alias.remove(&Property::Description) {
+                    Value::Text(description) => Some(description),
+                    _ => None,
+                },
+                expires: match alias.remove(&Property::Expires) {
+                    Value::Date(expires) => Some(expires.timestamp() as u64),
+                    _ => None,
+                },
+                blocked_senders: match alias.remove(&Property::BlockedSenders) {
+                    Value::List(senders) => senders
+                        .into_iter()
+                        .filter_map(|sender| match sender {
+                            Value::Text(sender) => Some(sender),
+                            _ => None,
+                        })
+                        .collect(),
+                    _ => vec![],
+                },
+                address,
+                stats,
+            });
+        }
+
+        Ok(report)
+    }
+
+    // Creates an alias with a random local part on the domain of the
+    // account's primary address. Returns None if the account has no address.
+    pub async fn disposable_alias_create(
+        &self,
+        account_id: u32,
+        mut alias: Object<Value>,
+        changes: &mut ChangeLogBuilder,
+    ) -> Result<Option<(u32, String)>, MethodError> {
+        let domain = if let Some(domain) = self
+            .directory
+            .query(QueryBy::Id(account_id), false)
+            .await
+            .map_err(|_| MethodError::ServerPartialFail)?
+            .and_then(|principal| {
+                principal
+                    .emails
+                    .first()
+                    .and_then(|email| email.rsplit_once('@'))
+                    .map(|(_, domain)| domain.to_lowercase())
+            }) {
+            domain
+        } else {
+            return Ok(None);
+        };
+
+        // Generate an address that is not in use
+        let mut address = String::new();
+        for _ in 0..10 {
+            let local_part = thread_rng()
+                .sample_iter(Alphanumeric)
+                .take(self.config.disposable_alias_local_part_length)
+                .map(|ch| char::from(ch).to_ascii_lowercase())
+                .collect::<String>();
+            let candidate = format!("{local_part}@{domain}");
+            if !self
+                .directory
+                .rcpt(&candidate)
+                .await
+                .map_err(|_| MethodError::ServerPartialFail)?
+            {
+                address = candidate;
+                break;
+            }
+        }
+        if address.is_empty() {
+            return Err(MethodError::ServerPartialFail);
+        }
+        alias.set(Property::Email, Value::Text(address.clone()));
+
+        // Insert record
+        let mut batch = BatchBuilder::new();
+        let document_id = self
+            .assign_document_id(account_id, Collection::DisposableAlias)
+            .await?;
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::DisposableAlias)
+            .create_document(document_id)
+            .value(Property::Value, alias.clone(), F_VALUE);
+        self.write_batch(batch).await?;
+        self.disposable_alias_publish(account_id, document_id, &alias)
+            .await?;
+        changes.log_insert(Collection::DisposableAlias, document_id);
+
+        Ok(Some((document_id, address)))
+    }
+
+    pub async fn disposable_alias_destroy(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        alias: &Object<Value>,
+        changes: &mut ChangeLogBuilder,
+    ) -> Result<(), MethodError> {
+        if let Value::Text(address) = alias.get(&Property::Email) {
+            self.directory
+                .alias_set(address, None)
+                .await
+                .map_err(|_| MethodError::ServerPartialFail)?;
+        }
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::DisposableAlias)
+            .delete_document(document_id)
+            .value(Property::Value, (), F_VALUE | F_CLEAR);
+        self.write_batch(batch).await?;
+        changes.log_delete(Collection::DisposableAlias, document_id);
+
+        Ok(())
+    }
+
+    // Writes the routing record used by the directory to accept, expire
+    // or block messages addressed to the alias.
+    pub(crate) async fn disposable_alias_publish(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        alias: &Object<Value>,
+    ) -> Result<(), MethodError> {
+        let address = if let Value::Text(address) = alias.get(&Property::Email) {
+            address
+        } else {
+            return Ok(());
+        };
+        let record = DisposableAlias {
+            account_id,
+            document_id,
+            expires: if let Value::Date(expires) = alias.get(&Property::Expires) {
+                Some(expires.timestamp().max(1) as u64)
+            } else {
+                None
+            },
+            blocked_senders: if let Value::List(senders) = alias.get(&Property::BlockedSenders) {
+                senders
+                    .iter()
+                    .filter_map(|sender| sender.as_string())
+                    .map(|sender| sender.to_lowercase())
+                    .collect()
+            } else {
+                vec![]
+            },
+        };
+
+        self.directory
+            .alias_set(address, Some(&record))
+            .await
+            .map_err(|_| MethodError::ServerPartialFail)
+    }
+}
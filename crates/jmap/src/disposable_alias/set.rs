@@ -0,0 +1,250 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::{
+        method::MethodError,
+        set::{SetError, SetErrorType},
+    },
+    method::set::{RequestArguments, SetRequest, SetResponse},
+    object::Object,
+    response::references::EvalObjectReferences,
+    types::{
+        collection::Collection,
+        property::Property,
+        state::StateChange,
+        type_state::DataType,
+        value::{MaybePatchValue, Value},
+    },
+};
+use store::write::{log::ChangeLogBuilder, now, BatchBuilder, F_VALUE};
+
+use crate::JMAP;
+
+impl JMAP {
+    pub async fn disposable_alias_set(
+        &self,
+        mut request: SetRequest<RequestArguments>,
+    ) -> Result<SetResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let mut aliases = self.disposable_alias_list(account_id).await?;
+        let mut response = self
+            .prepare_set_response(&request, Collection::DisposableAlias)
+            .await?;
+        let will_destroy = request.unwrap_destroy();
+
+        // Process creates
+        let mut changes = ChangeLogBuilder::new();
+        'create: for (id, object) in request.unwrap_create() {
+            if aliases.len() >= self.config.disposable_alias_max_aliases {
+                response.not_created.append(
+                    id,
+                    SetError::new(SetErrorType::OverQuota).with_description(
+                        "There are too many disposable aliases, please delete some before adding a new one.",
+                    ),
+                );
+                continue 'create;
+            }
+
+            let mut alias = Object::with_capacity(object.properties.len() + 1);
+            for (property, value) in object.properties {
+                match response
+                    .eval_object_references(value)
+                    .and_then(|value| validate_alias_value(&property, value))
+                {
+                    Ok(Value::Null) => (),
+                    Ok(value) => {
+                        alias.set(property, value);
+                    }
+                    Err(err) => {
+                        response.not_created.append(id, err);
+                        continue 'create;
+                    }
+                }
+            }
+
+            // Insert record
+            if let Some((document_id, address)) = self
+                .disposable_alias_create(account_id, alias.clone(), &mut changes)
+                .await?
+            {
+                alias.set(Property::Email, Value::Text(address.clone()));
+                aliases.push((document_id, alias));
+                response.created.insert(
+                    id,
+                    Object::with_capacity(2)
+                        .with_property(Property::Id, Value::Id(document_id.into()))
+                        .with_property(Property::Email, Value::Text(address)),
+                );
+            } else {
+                response.not_created.append(
+                    id,
+                    SetError::forbidden()
+                        .with_description("Account does not have an e-mail address."),
+                );
+            }
+        }
+
+        // Process updates
+        'update: for (id, object) in request.unwrap_update() {
+            // Make sure id won't be destroyed
+            if will_destroy.contains(&id) {
+                response.not_updated.append(id, SetError::will_destroy());
+                continue 'update;
+            }
+
+            // Obtain alias
+            let document_id = id.document_id();
+            let mut alias = if let Some((_, alias)) = aliases
+                .iter()
+                .find(|(alias_id, _)| *alias_id == document_id)
+            {
+                alias.clone()
+            } else {
+                response.not_updated.append(id, SetError::not_found());
+                continue 'update;
+            };
+
+            for (property, value) in object.properties {
+                match response
+                    .eval_object_references(value)
+                    .and_then(|value| validate_alias_value(&property, value))
+                {
+                    Ok(Value::Null) => {
+                        alias.remove(&property);
+                    }
+                    Ok(value) => {
+                        alias.set(property, value);
+                    }
+                    Err(err) => {
+                        response.not_updated.append(id, err);
+                        continue 'update;
+                    }
+                };
+            }
+
+            // Update record
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::DisposableAlias)
+                .update_document(document_id)
+                .value(Property::Value, alias.clone(), F_VALUE);
+            self.write_batch(batch).await?;
+            self.disposable_alias_publish(account_id, document_id, &alias)
+                .await?;
+            changes.log_update(Collection::DisposableAlias, document_id);
+            if let Some((_, current)) = aliases
+                .iter_mut()
+                .find(|(alias_id, _)| *alias_id == document_id)
+            {
+                *current = alias;
+            }
+            response.updated.append(id, None);
+        }
+
+        // Process deletions
+        for id in will_destroy {
+            let document_id = id.document_id();
+            if let Some(pos) = aliases
+                .iter()
+                .position(|(alias_id, _)| *alias_id == document_id)
+            {
+                let (_, alias) = aliases.swap_remove(pos);
+                self.disposable_alias_destroy(account_id, document_id, &alias, &mut changes)
+                    .await?;
+                response.destroyed.push(id);
+            } else {
+                response.not_destroyed.append(id, SetError::not_found());
+            }
+        }
+
+        // Write changes
+        if !changes.is_empty() {
+            let change_id = self.commit_changes(account_id, changes).await?;
+            response.new_state = Some(change_id.into());
+            response.state_change = StateChange::new(account_id)
+                .with_change(DataType::DisposableAlias, change_id)
+                .into();
+        }
+
+        Ok(response)
+    }
+}
+
+pub(crate) fn validate_alias_value(
+    property: &Property,
+    value: MaybePatchValue,
+) -> Result<Value, SetError> {
+    Ok(match (property, value) {
+        (Property::Description, MaybePatchValue::Value(Value::Text(value)))
+            if value.chars().count() <= 255 =>
+        {
+            Value::Text(value)
+        }
+        (Property::Expires, MaybePatchValue::Value(Value::Date(value))) => {
+            if value.timestamp() > now() as i64 {
+                Value::Date(value)
+            } else {
+                return Err(SetError::invalid_properties()
+                    .with_property(Property::Expires)
+                    .with_description("Expiration date must be in the future."));
+            }
+        }
+        (Property::BlockedSenders, MaybePatchValue::Value(Value::List(values))) => {
+            let mut senders = Vec::with_capacity(values.len());
+            for value in values {
+                match value {
+                    Value::Text(sender)
+                        if !sender.trim().is_empty()
+                            && !sender.trim().contains(char::is_whitespace) =>
+                    {
+                        let sender = Value::Text(sender.trim().to_lowercase());
+                        if !senders.contains(&sender) {
+                            senders.push(sender);
+                        }
+                    }
+                    _ => {
+                        return Err(SetError::invalid_properties()
+                            .with_property(Property::BlockedSenders)
+                            .with_description("Invalid sender address or domain."));
+                    }
+                }
+            }
+            if !senders.is_empty() {
+                Value::List(senders)
+            } else {
+                Value::Null
+            }
+        }
+        (
+            Property::Description | Property::Expires | Property::BlockedSenders,
+            MaybePatchValue::Value(Value::Null),
+        ) => Value::Null,
+        (property, _) => {
+            return Err(SetError::invalid_properties()
+                .with_property(property.clone())
+                .with_description("Field could not be set."));
+        }
+    })
+}
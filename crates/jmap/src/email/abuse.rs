@@ -0,0 +1,83 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::QueryBy;
+use jmap_proto::types::{collection::Collection, property::Property};
+
+use crate::{Bincode, JMAP};
+
+use super::metadata::MessageMetadata;
+
+impl JMAP {
+    pub fn is_abuse_report_enabled(&self) -> bool {
+        !self.smtp.report.config.abuse.report.send.is_empty()
+    }
+
+    // Sends an abuse report for a message the user marked as spam
+    pub async fn report_junk(&self, account_id: u32, document_id: u32) {
+        let metadata = match self
+            .get_property::<Bincode<MessageMetadata>>(
+                account_id,
+                Collection::Email,
+                document_id,
+                &Property::BodyStructure,
+            )
+            .await
+        {
+            Ok(Some(metadata)) => metadata.inner,
+            _ => return,
+        };
+        let headers = match self
+            .get_blob(
+                &metadata.blob_hash,
+                0..metadata.contents.parts[0].offset_body as u32,
+            )
+            .await
+        {
+            Ok(Some(headers)) => headers,
+            _ => {
+                tracing::debug!(
+                    event = "not-found",
+                    context = "abuse_report",
+                    account_id = account_id,
+                    document_id = document_id,
+                    "Failed to fetch message headers."
+                );
+                return;
+            }
+        };
+        let reported_by = match self.directory.query(QueryBy::Id(account_id), false).await {
+            Ok(Some(principal)) => principal
+                .emails
+                .into_iter()
+                .next()
+                .unwrap_or(principal.name),
+            _ => return,
+        };
+
+        let smtp = self.smtp.clone();
+        tokio::spawn(async move {
+            smtp.send_abuse_report(&headers, &reported_by).await;
+        });
+    }
+}
@@ -53,7 +53,12 @@ use store::{
 };
 use utils::map::vec_map::VecMap;
 
-use crate::{auth::AccessToken, mailbox::UidMailbox, services::housekeeper::Event, Bincode, JMAP};
+use crate::{
+    auth::AccessToken,
+    mailbox::{usage::MailboxUsageBuilder, UidMailbox},
+    services::housekeeper::Event,
+    Bincode, JMAP,
+};
 
 use super::{
     index::{EmailIndexBuilder, TrimTextValue, VisitValues, MAX_ID_LENGTH, MAX_SORT_FIELD_LENGTH},
@@ -406,6 +411,7 @@ impl JMAP {
             .with_collection(Collection::Email)
             .create_document(message_id)
             .value(Property::ThreadId, thread_id, F_VALUE | F_BITMAP)
+            .mailbox_usage(mailboxes.iter().copied(), 1, metadata.size as i64)
             .value(
                 Property::MailboxIds,
                 mailboxes
@@ -36,7 +36,7 @@ use jmap_proto::{
         value::Value,
     },
 };
-use mail_parser::HeaderName;
+use mail_parser::{HeaderName, PartType};
 use store::BlobClass;
 
 use crate::{auth::AccessToken, email::headers::HeaderToValue, mailbox::UidMailbox, Bincode, JMAP};
@@ -98,6 +98,8 @@ impl JMAP {
         let fetch_html_body_values = request.arguments.fetch_html_body_values.unwrap_or(false);
         let fetch_all_body_values = request.arguments.fetch_all_body_values.unwrap_or(false);
         let max_body_value_bytes = request.arguments.max_body_value_bytes.unwrap_or(0);
+        let proxy_remote_images = self.config.image_proxy_enable
+            && request.arguments.proxy_remote_images.unwrap_or(false);
 
         let account_id = request.account_id.document_id();
         let message_ids = self
@@ -130,6 +132,7 @@ impl JMAP {
         };
 
         // Check if we need to fetch the raw headers or body
+        let needs_preview = properties.contains(&Property::Preview);
         let mut needs_headers = false;
         let mut needs_body = false;
         for property in &properties {
@@ -173,6 +176,11 @@ impl JMAP {
                     continue;
                 }
             };
+            if needs_preview {
+                metadata = self
+                    .email_preview(account_id, id.document_id(), metadata)
+                    .await;
+            }
 
             // Retrieve raw message if needed
             let raw_message = if needs_body || needs_headers {
@@ -411,8 +419,15 @@ impl JMAP {
                                     MetadataPartType::Text | MetadataPartType::Html
                                 )
                             {
-                                let (is_truncated, value) = part
-                                    .decode_contents(&raw_message)
+                                let (is_truncated, value) =
+                                    match part.decode_contents(&raw_message) {
+                                        PartType::Html(html) if proxy_remote_images => {
+                                            PartType::Html(
+                                                self.proxy_remote_images(&html).into_owned().into(),
+                                            )
+                                        }
+                                        contents => contents,
+                                    }
                                     .truncate(max_body_value_bytes);
                                 body_values.append(
                                     Property::_T(part_id.to_string()),
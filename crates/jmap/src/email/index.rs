@@ -30,7 +30,7 @@ use mail_parser::{
     Addr, Address, GetHeader, Group, Header, HeaderName, HeaderValue, Message, MessagePart,
     PartType,
 };
-use nlp::language::Language;
+use nlp::language::{sanitize::sanitize_text, Language};
 use store::{
     backend::MAX_TOKEN_LENGTH,
     fts::{index::FtsDocument, Field},
@@ -40,7 +40,10 @@ use store::{
     BlobHash,
 };
 
-use crate::{mailbox::UidMailbox, Bincode};
+use crate::{
+    mailbox::{usage::MailboxUsageBuilder, UidMailbox},
+    Bincode,
+};
 
 use super::metadata::MessageMetadata;
 
@@ -85,6 +88,13 @@ impl IndexMessage for BatchBuilder {
         // Index keywords
         self.value(Property::Keywords, keywords, F_VALUE | F_BITMAP);
 
+        // Update mailbox usage
+        self.mailbox_usage(
+            mailbox_ids.iter().map(|m| m.mailbox_id),
+            1,
+            message.raw_message.len() as i64,
+        );
+
         // Index mailboxIds
         self.value(Property::MailboxIds, mailbox_ids, F_VALUE | F_BITMAP);
 
@@ -116,8 +126,11 @@ impl IndexMessage for BatchBuilder {
             match &part.body {
                 PartType::Text(text) => {
                     if part_id == preview_part_id {
-                        preview =
-                            preview_text(text.replace('\r', "").into(), PREVIEW_LENGTH).into();
+                        preview = preview_text(
+                            sanitize_text(text, false).into_owned().into(),
+                            PREVIEW_LENGTH,
+                        )
+                        .into();
                     }
 
                     if !message.text_body.contains(&part_id)
@@ -129,8 +142,11 @@ impl IndexMessage for BatchBuilder {
                 PartType::Html(html) => {
                     let text = html_to_text(html);
                     if part_id == preview_part_id {
-                        preview =
-                            preview_text(text.replace('\r', "").into(), PREVIEW_LENGTH).into();
+                        preview = preview_text(
+                            sanitize_text(&text, true).into_owned().into(),
+                            PREVIEW_LENGTH,
+                        )
+                        .into();
                     }
 
                     if !message.text_body.contains(&part_id)
@@ -21,6 +21,7 @@
  * for more details.
 */
 
+pub mod abuse;
 pub mod body;
 pub mod copy;
 pub mod crypto;
@@ -31,6 +32,11 @@ pub mod index;
 pub mod ingest;
 pub mod metadata;
 pub mod parse;
+pub mod patch;
+pub mod preview;
+pub mod proxy;
 pub mod query;
 pub mod set;
 pub mod snippet;
+pub mod tombstone;
+pub mod update_flags;
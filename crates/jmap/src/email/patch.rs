@@ -0,0 +1,243 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::collections::HashMap;
+
+use jmap_proto::{
+    error::{
+        method::MethodError,
+        set::{SetError, SetErrorType},
+    },
+    types::{
+        blob::BlobId,
+        property::Property,
+        value::{Object, Value},
+    },
+};
+
+use crate::{auth::AccessToken, JMAP};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BodyPatch {
+    pub offset: usize,
+    pub length: usize,
+    pub value: String,
+}
+
+impl JMAP {
+    // Body values are either provided inline as "value", or as a reference
+    // to a previously uploaded "blobId" plus a list of "patches" to apply to it.
+    pub(crate) async fn email_body_values(
+        &self,
+        body_values: Object<Value>,
+        access_token: &AccessToken,
+    ) -> Result<Result<Option<HashMap<String, String>>, SetError>, MethodError> {
+        let mut values = HashMap::with_capacity(body_values.properties.len());
+        for (key, value) in body_values.properties {
+            let (part_id, mut body_value) = match (key, value) {
+                (Property::_T(part_id), Value::Object(body_value)) => (part_id, body_value),
+                _ => return Ok(Ok(None)),
+            };
+
+            let value = match (
+                body_value.properties.remove(&Property::Value),
+                body_value.properties.remove(&Property::BlobId),
+                body_value
+                    .properties
+                    .remove(&Property::_T("patches".to_string())),
+            ) {
+                (Some(Value::Text(value)), None, None) => value,
+                (None, Some(Value::BlobId(blob_id)), Some(Value::List(patches))) => {
+                    match self
+                        .email_body_patch(&part_id, &blob_id, patches, access_token)
+                        .await?
+                    {
+                        Ok(value) => value,
+                        Err(err) => return Ok(Err(err)),
+                    }
+                }
+                _ => return Ok(Ok(None)),
+            };
+
+            values.insert(part_id, value);
+        }
+
+        Ok(Ok(Some(values)))
+    }
+
+    async fn email_body_patch(
+        &self,
+        part_id: &str,
+        blob_id: &BlobId,
+        patches: Vec<Value>,
+        access_token: &AccessToken,
+    ) -> Result<Result<String, SetError>, MethodError> {
+        if !self.config.blob_patch_enable {
+            return Ok(Err(SetError::invalid_properties()
+                .with_property(Property::BodyValues)
+                .with_description("Body value patching is not enabled.")));
+        }
+
+        let patches = match parse_patches(patches) {
+            Some(patches) if patches.len() <= self.config.blob_patch_max_patches => patches,
+            Some(_) => {
+                return Ok(Err(SetError::invalid_properties()
+                    .with_property(Property::BodyValues)
+                    .with_description(format!(
+                        "Too many patches for partId {part_id:?}, maximum is {}.",
+                        self.config.blob_patch_max_patches
+                    ))));
+            }
+            None => {
+                return Ok(Err(SetError::invalid_properties()
+                    .with_property(Property::BodyValues)
+                    .with_description(format!(
+                        "Invalid patches for partId {part_id:?}."
+                    ))));
+            }
+        };
+
+        if !self.is_patch_allowed(access_token) {
+            return Ok(Err(SetError::new(SetErrorType::RateLimit)
+                .with_description(
+                    "Too many patch requests, upload the full body instead.",
+                )));
+        }
+
+        let base = match self.blob_download(blob_id, access_token).await? {
+            Some(base) => base,
+            None => {
+                return Ok(Err(SetError::new(SetErrorType::BlobNotFound)
+                    .with_description(format!(
+                        "blobId {blob_id} does not exist on this server."
+                    ))));
+            }
+        };
+
+        Ok(match apply_patches(&base, &patches) {
+            Some(value) if value.len() <= self.config.blob_patch_max_size => {
+                String::from_utf8(value).map_err(|_| {
+                    SetError::invalid_properties()
+                        .with_property(Property::BodyValues)
+                        .with_description(format!(
+                            "Patched body value for partId {part_id:?} is not valid UTF-8."
+                        ))
+                })
+            }
+            Some(_) => Err(
+                SetError::new(SetErrorType::TooLarge).with_description(format!(
+                    "Patched body value exceeds maximum size of {} bytes.",
+                    self.config.blob_patch_max_size
+                )),
+            ),
+            None => Err(SetError::invalid_properties()
+                .with_property(Property::BodyValues)
+                .with_description(format!(
+                    "Patches for partId {part_id:?} are out of range or overlap."
+                ))),
+        })
+    }
+}
+
+fn parse_patches(patches: Vec<Value>) -> Option<Vec<BodyPatch>> {
+    let mut result = Vec::with_capacity(patches.len());
+    for patch in patches {
+        let mut patch = patch.try_unwrap_object()?;
+        result.push(BodyPatch {
+            offset: patch
+                .properties
+                .remove(&Property::_T("offset".to_string()))?
+                .try_unwrap_uint()? as usize,
+            length: match patch.properties.remove(&Property::_T("length".to_string())) {
+                Some(length) => length.try_unwrap_uint()? as usize,
+                None => 0,
+            },
+            value: match patch.properties.remove(&Property::Value) {
+                Some(value) => value.try_unwrap_string()?,
+                None => String::new(),
+            },
+        });
+    }
+    Some(result)
+}
+
+// Replaces "length" bytes at each "offset" of the base blob with the patch value.
+// Offsets refer to the original blob and patches must not overlap.
+pub fn apply_patches(base: &[u8], patches: &[BodyPatch]) -> Option<Vec<u8>> {
+    let mut patches = patches.iter().collect::<Vec<_>>();
+    patches.sort_by_key(|patch| patch.offset);
+
+    let mut result = Vec::with_capacity(
+        base.len() + patches.iter().map(|patch| patch.value.len()).sum::<usize>(),
+    );
+    let mut pos = 0;
+    for patch in patches {
+        let end = patch.offset.checked_add(patch.length)?;
+        if patch.offset < pos || end > base.len() {
+            return None;
+        }
+        result.extend_from_slice(&base[pos..patch.offset]);
+        result.extend_from_slice(patch.value.as_bytes());
+        pos = end;
+    }
+    result.extend_from_slice(&base[pos..]);
+
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_patches, BodyPatch};
+
+    fn patch(offset: usize, length: usize, value: &str) -> BodyPatch {
+        BodyPatch {
+            offset,
+            length,
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn body_patches() {
+        let base = b"<p>Hello world</p>";
+
+        for (patches, expected) in [
+            (vec![], Some("<p>Hello world</p>")),
+            (vec![patch(9, 5, "there")], Some("<p>Hello there</p>")),
+            (
+                vec![patch(18, 0, "<p>Bye</p>"), patch(3, 0, "<b>")],
+                Some("<p><b>Hello world</p><p>Bye</p>"),
+            ),
+            (vec![patch(0, 18, "")], Some("")),
+            (vec![patch(3, 6, "x"), patch(5, 1, "y")], None),
+            (vec![patch(10, 9, "")], None),
+            (vec![patch(usize::MAX, 1, "")], None),
+        ] {
+            assert_eq!(
+                apply_patches(base, &patches),
+                expected.map(|v| v.as_bytes().to_vec()),
+                "{patches:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,113 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use jmap_proto::types::{collection::Collection, property::Property};
+use mail_parser::{decoders::html::html_to_text, parsers::preview::preview_text, PartType};
+use nlp::language::sanitize::sanitize_text;
+use store::write::{BatchBuilder, F_VALUE};
+
+use crate::{Bincode, JMAP};
+
+use super::{index::PREVIEW_LENGTH, metadata::MessageMetadata};
+
+impl JMAP {
+    // Messages stored before previews were generated at ingestion time have an empty
+    // preview, which is generated on first access and cached in the message metadata.
+    pub async fn email_preview(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        metadata: MessageMetadata<'static>,
+    ) -> MessageMetadata<'static> {
+        if !metadata.preview.is_empty() {
+            return metadata;
+        }
+
+        // Obtain the part used to build the preview
+        let part = match metadata
+            .contents
+            .text_body
+            .first()
+            .or_else(|| metadata.contents.html_body.first())
+            .and_then(|part_id| metadata.contents.parts.get(*part_id))
+        {
+            Some(part) if part.size > 0 => part,
+            _ => return metadata,
+        };
+
+        // Fetch the message up to the end of the preview part
+        let raw_message = match self
+            .get_blob(&metadata.blob_hash, 0..part.offset_end as u32)
+            .await
+        {
+            Ok(Some(raw_message)) => raw_message,
+            Ok(None) => {
+                tracing::debug!(
+                    event = "not-found",
+                    account_id = account_id,
+                    collection = ?Collection::Email,
+                    document_id = document_id,
+                    blob_id = ?metadata.blob_hash,
+                    "Blob not found while generating preview.");
+                return metadata;
+            }
+            Err(_) => return metadata,
+        };
+
+        let preview = match part.decode_contents(&raw_message) {
+            PartType::Text(text) => preview_text(
+                sanitize_text(&text, false).into_owned().into(),
+                PREVIEW_LENGTH,
+            ),
+            PartType::Html(html) => preview_text(
+                sanitize_text(&html_to_text(&html), true)
+                    .into_owned()
+                    .into(),
+                PREVIEW_LENGTH,
+            ),
+            _ => return metadata,
+        };
+        if preview.is_empty() {
+            return metadata;
+        }
+
+        // Cache the generated preview
+        let mut metadata = Bincode::new(metadata);
+        metadata.inner.preview = preview.into_owned();
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Email)
+            .update_document(document_id)
+            .value(Property::BodyStructure, &metadata, F_VALUE);
+        if self.write_batch(batch).await.is_err() {
+            tracing::debug!(
+                event = "error",
+                account_id = account_id,
+                collection = ?Collection::Email,
+                document_id = document_id,
+                "Failed to cache generated preview.");
+        }
+
+        metadata.inner
+    }
+}
@@ -0,0 +1,460 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    borrow::Cow,
+    net::{IpAddr, SocketAddr},
+    time::Instant,
+};
+
+use base64::{engine::general_purpose, Engine};
+use hyper::body::Bytes;
+use jmap_proto::error::request::RequestError;
+use reqwest::{header::CONTENT_TYPE, redirect::Policy, StatusCode, Url};
+use store::blake3;
+use utils::map::ttl_dashmap::TtlMap;
+
+use crate::JMAP;
+
+const MAX_REDIRECTS: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct ProxiedImage {
+    pub content_type: String,
+    pub contents: Bytes,
+}
+
+impl JMAP {
+    pub fn proxy_remote_images<'x>(&self, html: &'x str) -> Cow<'x, str> {
+        rewrite_remote_images(html, |url| {
+            format!(
+                "/jmap/proxy/{}/{}",
+                general_purpose::URL_SAFE_NO_PAD.encode(self.image_proxy_signature(url).as_bytes()),
+                general_purpose::URL_SAFE_NO_PAD.encode(url.as_bytes())
+            )
+        })
+    }
+
+    pub async fn image_proxy_fetch(
+        &self,
+        signature: &str,
+        url: &str,
+    ) -> Result<ProxiedImage, RequestError> {
+        // Decode and verify the URL, only URLs rewritten by this server are proxied
+        let url = general_purpose::URL_SAFE_NO_PAD
+            .decode(url.as_bytes())
+            .ok()
+            .and_then(|url| String::from_utf8(url).ok())
+            .ok_or_else(RequestError::invalid_parameters)?;
+        let signature = general_purpose::URL_SAFE_NO_PAD
+            .decode(signature.as_bytes())
+            .ok()
+            .and_then(|signature| <[u8; blake3::OUT_LEN]>::try_from(signature).ok())
+            .ok_or_else(RequestError::forbidden)?;
+        if self.image_proxy_signature(&url) != blake3::Hash::from(signature) {
+            return Err(RequestError::forbidden());
+        }
+
+        // Check cache
+        if let Some(image) = self.image_proxy_cache.get_with_ttl(&url) {
+            return Ok(image);
+        }
+
+        let mut next_url = Url::parse(&url).map_err(|_| RequestError::invalid_parameters())?;
+        for _ in 0..=MAX_REDIRECTS {
+            let response = self.image_proxy_request(&next_url).await?;
+
+            if response.status().is_redirection() {
+                next_url = response
+                    .headers()
+                    .get(reqwest::header::LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .and_then(|location| next_url.join(location).ok())
+                    .ok_or_else(|| bad_gateway("Invalid redirect from remote server."))?;
+                continue;
+            }
+
+            let image = read_remote_image(response, self.config.image_proxy_max_size).await?;
+
+            // Cache image
+            if self.image_proxy_cache.len() < self.config.image_proxy_cache_size {
+                self.image_proxy_cache.insert_with_ttl(
+                    url,
+                    image.clone(),
+                    Instant::now() + self.config.image_proxy_cache_ttl,
+                );
+            }
+
+            return Ok(image);
+        }
+
+        Err(bad_gateway("Too many redirects."))
+    }
+
+    async fn image_proxy_request(&self, url: &Url) -> Result<reqwest::Response, RequestError> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(RequestError::invalid_parameters());
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(RequestError::invalid_parameters)?
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(RequestError::invalid_parameters)?;
+
+        // Resolve the host name and make sure it does not point to an internal address
+        let addr = if let Ok(ip) = host.parse::<IpAddr>() {
+            SocketAddr::new(ip, port)
+        } else {
+            tokio::net::lookup_host((host, port))
+                .await
+                .ok()
+                .and_then(|mut addrs| addrs.next())
+                .ok_or_else(|| bad_gateway("Failed to resolve remote host."))?
+        };
+        if !self.config.image_proxy_allow_local && !is_public_ip(addr.ip()) {
+            tracing::debug!(
+                context = "image-proxy",
+                event = "forbidden",
+                url = url.as_str(),
+                ip = %addr.ip(),
+                "Blocked request to non-public address."
+            );
+            return Err(RequestError::forbidden());
+        }
+
+        let client_builder = reqwest::Client::builder()
+            .timeout(self.config.image_proxy_timeout)
+            .redirect(Policy::none())
+            .resolve(host, addr);
+
+        #[cfg(feature = "test_mode")]
+        let client_builder = client_builder.danger_accept_invalid_certs(true);
+
+        client_builder
+            .build()
+            .unwrap_or_default()
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(|err| {
+                tracing::debug!(
+                    context = "image-proxy",
+                    event = "error",
+                    url = url.as_str(),
+                    reason = %err,
+                    "Failed to fetch remote image."
+                );
+                bad_gateway("Failed to fetch remote image.")
+            })
+    }
+
+    fn image_proxy_signature(&self, url: &str) -> blake3::Hash {
+        blake3::keyed_hash(
+            &blake3::derive_key(
+                "Stalwart JMAP image proxy",
+                self.config.oauth_key.as_bytes(),
+            ),
+            url.as_bytes(),
+        )
+    }
+}
+
+/// Reads an image returned by a remote server, rejecting unsuccessful responses,
+/// content that is not an image and images larger than `max_size`.
+pub async fn read_remote_image(
+    mut response: reqwest::Response,
+    max_size: usize,
+) -> Result<ProxiedImage, RequestError> {
+    if response.status() != StatusCode::OK {
+        return Err(bad_gateway(format!(
+            "Remote server returned status {}.",
+            response.status().as_u16()
+        )));
+    }
+
+    // Validate content type
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok())
+        .and_then(|ct| ct.split(';').next())
+        .map(|ct| ct.trim().to_ascii_lowercase())
+        .filter(|ct| ct.starts_with("image/") && !ct.starts_with("image/svg"))
+        .ok_or_else(|| bad_gateway("Remote content is not a supported image type."))?;
+
+    // Read contents up to the maximum allowed size
+    if response
+        .content_length()
+        .map_or(false, |size| size as usize > max_size)
+    {
+        return Err(bad_gateway(
+            "Remote image exceeds the maximum allowed size.",
+        ));
+    }
+    let mut contents = Vec::with_capacity(1024);
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|err| bad_gateway(format!("Failed to read remote image: {err}")))?
+    {
+        if contents.len() + chunk.len() > max_size {
+            return Err(bad_gateway(
+                "Remote image exceeds the maximum allowed size.",
+            ));
+        }
+        contents.extend_from_slice(&chunk);
+    }
+
+    Ok(ProxiedImage {
+        content_type,
+        contents: contents.into(),
+    })
+}
+
+fn bad_gateway(detail: impl Into<Cow<'static, str>>) -> RequestError {
+    RequestError::blank(502, "Bad Gateway", detail)
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || ip.octets()[0] == 0
+                || (ip.octets()[0] == 100 && (ip.octets()[1] & 0xc0) == 64))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                is_public_ip(IpAddr::V4(ip))
+            } else {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || (ip.segments()[0] & 0xfe00) == 0xfc00
+                    || (ip.segments()[0] & 0xffc0) == 0xfe80)
+            }
+        }
+    }
+}
+
+/// Rewrites the remote image references found in `src`, `srcset` and `background`
+/// attributes using the provided function.
+pub fn rewrite_remote_images(html: &str, mut rewrite: impl FnMut(&str) -> String) -> Cow<'_, str> {
+    let bytes = html.as_bytes();
+    let mut result = String::new();
+    let mut last_pos = 0;
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        if bytes[pos] != b'<' {
+            pos += 1;
+            continue;
+        }
+
+        // Skip comments
+        if bytes[pos..].starts_with(b"<!--") {
+            pos = html[pos + 4..]
+                .find("-->")
+                .map_or(bytes.len(), |end| pos + 4 + end + 3);
+            continue;
+        }
+
+        // Tag name
+        pos += 1;
+        let name_start = pos;
+        while pos < bytes.len() && bytes[pos].is_ascii_alphanumeric() {
+            pos += 1;
+        }
+        if name_start == pos {
+            continue;
+        }
+
+        // Attributes
+        loop {
+            while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b'/') {
+                pos += 1;
+            }
+            if pos >= bytes.len() || bytes[pos] == b'>' {
+                break;
+            }
+
+            let attr_start = pos;
+            while pos < bytes.len()
+                && !bytes[pos].is_ascii_whitespace()
+                && !matches!(bytes[pos], b'=' | b'>' | b'/')
+            {
+                pos += 1;
+            }
+            let attr_name = &html[attr_start..pos];
+            if attr_name.is_empty() {
+                // Stray character, skip it
+                pos += 1;
+                continue;
+            }
+            while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if pos >= bytes.len() || bytes[pos] != b'=' {
+                continue;
+            }
+            pos += 1;
+            while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if pos >= bytes.len() {
+                break;
+            }
+
+            // Attribute value
+            let (value_start, value_end) = if matches!(bytes[pos], b'"' | b'\'') {
+                let quote = bytes[pos];
+                let value_start = pos + 1;
+                let value_end = bytes[value_start..]
+                    .iter()
+                    .position(|&ch| ch == quote)
+                    .map_or(bytes.len(), |end| value_start + end);
+                pos = (value_end + 1).min(bytes.len());
+                (value_start, value_end)
+            } else {
+                let value_start = pos;
+                while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() && bytes[pos] != b'>' {
+                    pos += 1;
+                }
+                (value_start, pos)
+            };
+            let value = &html[value_start..value_end];
+
+            let new_value = if attr_name.eq_ignore_ascii_case("src")
+                || attr_name.eq_ignore_ascii_case("background")
+            {
+                remote_url(value).map(&mut rewrite)
+            } else if attr_name.eq_ignore_ascii_case("srcset") {
+                let mut has_remote = false;
+                let candidates = value
+                    .split(',')
+                    .map(|candidate| {
+                        let candidate = candidate.trim();
+                        let (url, descriptor) = candidate
+                            .split_once(|ch: char| ch.is_ascii_whitespace())
+                            .unwrap_or((candidate, ""));
+                        if let Some(url) = remote_url(url) {
+                            has_remote = true;
+                            let url = rewrite(&url);
+                            if !descriptor.is_empty() {
+                                format!("{url} {}", descriptor.trim())
+                            } else {
+                                url
+                            }
+                        } else {
+                            candidate.to_string()
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                if has_remote {
+                    Some(candidates.join(", "))
+                } else {
+                    None
+                }
+            } else {
+                None
+            };
+
+            if let Some(new_value) = new_value {
+                result.push_str(&html[last_pos..value_start]);
+                result.push_str(&new_value);
+                last_pos = value_end;
+            }
+        }
+    }
+
+    if last_pos > 0 {
+        result.push_str(&html[last_pos..]);
+        result.into()
+    } else {
+        html.into()
+    }
+}
+
+fn remote_url(value: &str) -> Option<String> {
+    let value = value.trim();
+    let url = if value
+        .get(..7)
+        .map_or(false, |s| s.eq_ignore_ascii_case("http://"))
+        || value
+            .get(..8)
+            .map_or(false, |s| s.eq_ignore_ascii_case("https://"))
+    {
+        value.to_string()
+    } else if value.starts_with("//") {
+        format!("https:{value}")
+    } else {
+        return None;
+    };
+
+    Some(url.replace("&amp;", "&"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rewrite_remote_images;
+
+    #[test]
+    fn rewrite_remote_image_urls() {
+        for (html, expected) in [
+            (
+                "<p>Hello <img src=\"https://tracker.com/p.gif?a=1&amp;b=2\" width=1></p>",
+                "<p>Hello <img src=\"[https://tracker.com/p.gif?a=1&b=2]\" width=1></p>",
+            ),
+            (
+                "<IMG SRC='http://example.com/a.png'><img src=cid:1234@local>",
+                "<IMG SRC='[http://example.com/a.png]'><img src=cid:1234@local>",
+            ),
+            (
+                "<td background=//example.com/bg.jpg><!-- <img src=\"http://x.com\"> --></td>",
+                "<td background=[https://example.com/bg.jpg]><!-- <img src=\"http://x.com\"> --></td>",
+            ),
+            (
+                "<img srcset=\"https://a.com/1.png 1x, data:abc 2x\" alt=\"src=http://b.com\">",
+                "<img srcset=\"[https://a.com/1.png] 1x, data:abc 2x\" alt=\"src=http://b.com\">",
+            ),
+            (
+                "<a href=\"https://example.com\">no images here</a>",
+                "<a href=\"https://example.com\">no images here</a>",
+            ),
+        ] {
+            assert_eq!(
+                rewrite_remote_images(html, |url| format!("[{url}]")),
+                expected,
+                "failed for {html}"
+            );
+        }
+    }
+}
@@ -27,8 +27,9 @@ use jmap_proto::{
     object::email::QueryArguments,
     types::{acl::Acl, collection::Collection, keyword::Keyword, property::Property},
 };
-use mail_parser::HeaderName;
+use mail_parser::{parsers::MessageStream, HeaderName, HeaderValue};
 use nlp::language::Language;
+use regex::{Regex, RegexBuilder};
 use store::{
     fts::{Field, FilterGroup, FtsFilter, IntoFilterGroup},
     query::{self},
@@ -37,7 +38,17 @@ use store::{
     ValueKey,
 };
 
-use crate::{auth::AccessToken, JMAP};
+use crate::{auth::AccessToken, Bincode, JMAP};
+
+use super::metadata::MessageMetadata;
+
+const HEADER_REGEX_SIZE_LIMIT: usize = 64 * 1024;
+
+enum HeaderMatch {
+    Exists,
+    Contains(String),
+    Regex(Regex),
+}
 
 impl JMAP {
     pub async fn email_query(
@@ -46,9 +57,112 @@ impl JMAP {
         access_token: &AccessToken,
     ) -> Result<QueryResponse, MethodError> {
         let account_id = request.account_id.document_id();
-        let mut filters = Vec::with_capacity(request.filter.len());
+        let filters = self
+            .email_filters(account_id, std::mem::take(&mut request.filter))
+            .await?;
+
+        let mut result_set = self.filter(account_id, Collection::Email, filters).await?;
+        if access_token.is_shared(account_id) {
+            result_set.apply_mask(
+                self.shared_messages(access_token, account_id, Acl::ReadItems)
+                    .await?,
+            );
+        }
+        let (response, paginate) = self.build_query_response(&result_set, &request).await?;
+
+        if let Some(paginate) = paginate {
+            // Parse sort criteria
+            let mut comparators = Vec::with_capacity(request.sort.as_ref().map_or(1, |s| s.len()));
+            for comparator in request
+                .sort
+                .and_then(|s| if !s.is_empty() { s.into() } else { None })
+                .unwrap_or_else(|| vec![Comparator::descending(SortProperty::ReceivedAt)])
+            {
+                comparators.push(match comparator.property {
+                    SortProperty::ReceivedAt => {
+                        query::Comparator::field(Property::ReceivedAt, comparator.is_ascending)
+                    }
+                    SortProperty::Size => {
+                        query::Comparator::field(Property::Size, comparator.is_ascending)
+                    }
+                    SortProperty::From => {
+                        query::Comparator::field(Property::From, comparator.is_ascending)
+                    }
+                    SortProperty::To => {
+                        query::Comparator::field(Property::To, comparator.is_ascending)
+                    }
+                    SortProperty::Subject => {
+                        query::Comparator::field(Property::Subject, comparator.is_ascending)
+                    }
+                    SortProperty::SentAt => {
+                        query::Comparator::field(Property::SentAt, comparator.is_ascending)
+                    }
+                    SortProperty::HasKeyword => query::Comparator::set(
+                        self.get_tag(
+                            account_id,
+                            Collection::Email,
+                            Property::Keywords,
+                            comparator.keyword.unwrap_or(Keyword::Seen),
+                        )
+                        .await?
+                        .unwrap_or_default(),
+                        comparator.is_ascending,
+                    ),
+                    SortProperty::AllInThreadHaveKeyword => query::Comparator::set(
+                        self.thread_keywords(
+                            account_id,
+                            comparator.keyword.unwrap_or(Keyword::Seen),
+                            true,
+                        )
+                        .await?,
+                        comparator.is_ascending,
+                    ),
+                    SortProperty::SomeInThreadHaveKeyword => query::Comparator::set(
+                        self.thread_keywords(
+                            account_id,
+                            comparator.keyword.unwrap_or(Keyword::Seen),
+                            false,
+                        )
+                        .await?,
+                        comparator.is_ascending,
+                    ),
+                    // Non-standard
+                    SortProperty::Cc => {
+                        query::Comparator::field(Property::Cc, comparator.is_ascending)
+                    }
+
+                    other => return Err(MethodError::UnsupportedSort(other.to_string())),
+                });
+            }
+
+            // Sort results
+            self.sort(
+                result_set,
+                comparators,
+                paginate
+                    .with_prefix_key(ValueKey {
+                        account_id,
+                        collection: Collection::Email.into(),
+                        document_id: 0,
+                        class: ValueClass::Property(Property::ThreadId.into()),
+                    })
+                    .with_prefix_unique(request.arguments.collapse_threads.unwrap_or(false)),
+                response,
+            )
+            .await
+        } else {
+            Ok(response)
+        }
+    }
 
-        for cond_group in std::mem::take(&mut request.filter).into_filter_group() {
+    pub async fn email_filters(
+        &self,
+        account_id: u32,
+        filter: Vec<Filter>,
+    ) -> Result<Vec<query::Filter>, MethodError> {
+        let mut filters = Vec::with_capacity(filter.len());
+
+        for cond_group in filter.into_filter_group() {
             match cond_group {
                 FilterGroup::Fts(conds) => {
                     let mut fts_filters = Vec::with_capacity(filters.len());
@@ -261,6 +375,9 @@ impl JMAP {
                             Property::ThreadId,
                             id.document_id(),
                         )),
+                        Filter::Header(header) => filters.push(query::Filter::is_in_set(
+                            self.header_scan(account_id, header).await?,
+                        )),
                         Filter::And | Filter::Or | Filter::Not | Filter::Close => {
                             filters.push(cond.into());
                         }
@@ -271,98 +388,127 @@ impl JMAP {
             }
         }
 
-        let mut result_set = self.filter(account_id, Collection::Email, filters).await?;
-        if access_token.is_shared(account_id) {
-            result_set.apply_mask(
-                self.shared_messages(access_token, account_id, Acl::ReadItems)
-                    .await?,
-            );
+        Ok(filters)
+    }
+
+    async fn header_scan(
+        &self,
+        account_id: u32,
+        header: Vec<String>,
+    ) -> Result<RoaringBitmap, MethodError> {
+        let mut header = header.into_iter();
+        let header_name = header
+            .next()
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| MethodError::InvalidArguments("Header name is missing.".to_string()))?;
+        let matcher = match (header.next(), header.next().as_deref()) {
+            (None, _) => HeaderMatch::Exists,
+            (Some(value), None | Some("contains")) => HeaderMatch::Contains(value.to_lowercase()),
+            (Some(value), Some("regex")) => HeaderMatch::Regex(
+                RegexBuilder::new(&format!("^(?:{value})$"))
+                    .case_insensitive(true)
+                    .size_limit(HEADER_REGEX_SIZE_LIMIT)
+                    .build()
+                    .map_err(|err| {
+                        MethodError::InvalidArguments(format!(
+                            "Invalid header regular expression: {err}"
+                        ))
+                    })?,
+            ),
+            (Some(_), Some(mode)) => {
+                return Err(MethodError::InvalidArguments(format!(
+                    "Unsupported header match mode '{mode}'."
+                )));
+            }
+        };
+
+        // Indexed headers narrow down the messages to scan
+        let candidates = match HeaderName::parse(header_name.as_str()) {
+            Some(HeaderName::Other(_)) | None => self
+                .get_document_ids(account_id, Collection::Email)
+                .await?
+                .unwrap_or_default(),
+            Some(name) => {
+                let document_ids = self
+                    .fts_filter(
+                        account_id,
+                        Collection::Email,
+                        vec![FtsFilter::has_keyword(
+                            Field::<HeaderName>::Keyword,
+                            name.as_str().to_lowercase(),
+                        )],
+                    )
+                    .await?;
+                if matches!(matcher, HeaderMatch::Exists) {
+                    return Ok(document_ids);
+                }
+                document_ids
+            }
+        };
+        if candidates.len() > self.config.query_max_header_scan as u64 {
+            return Err(MethodError::UnsupportedFilter(format!(
+                "Header filter would scan more than {} messages, narrow down the query.",
+                self.config.query_max_header_scan
+            )));
         }
-        let (response, paginate) = self.build_query_response(&result_set, &request).await?;
 
-        if let Some(paginate) = paginate {
-            // Parse sort criteria
-            let mut comparators = Vec::with_capacity(request.sort.as_ref().map_or(1, |s| s.len()));
-            for comparator in request
-                .sort
-                .and_then(|s| if !s.is_empty() { s.into() } else { None })
-                .unwrap_or_else(|| vec![Comparator::descending(SortProperty::ReceivedAt)])
+        let mut matched_ids = RoaringBitmap::new();
+        for document_id in candidates {
+            let metadata = if let Some(metadata) = self
+                .get_property::<Bincode<MessageMetadata>>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    &Property::BodyStructure,
+                )
+                .await?
             {
-                comparators.push(match comparator.property {
-                    SortProperty::ReceivedAt => {
-                        query::Comparator::field(Property::ReceivedAt, comparator.is_ascending)
-                    }
-                    SortProperty::Size => {
-                        query::Comparator::field(Property::Size, comparator.is_ascending)
-                    }
-                    SortProperty::From => {
-                        query::Comparator::field(Property::From, comparator.is_ascending)
-                    }
-                    SortProperty::To => {
-                        query::Comparator::field(Property::To, comparator.is_ascending)
-                    }
-                    SortProperty::Subject => {
-                        query::Comparator::field(Property::Subject, comparator.is_ascending)
-                    }
-                    SortProperty::SentAt => {
-                        query::Comparator::field(Property::SentAt, comparator.is_ascending)
-                    }
-                    SortProperty::HasKeyword => query::Comparator::set(
-                        self.get_tag(
-                            account_id,
-                            Collection::Email,
-                            Property::Keywords,
-                            comparator.keyword.unwrap_or(Keyword::Seen),
-                        )
-                        .await?
-                        .unwrap_or_default(),
-                        comparator.is_ascending,
-                    ),
-                    SortProperty::AllInThreadHaveKeyword => query::Comparator::set(
-                        self.thread_keywords(
-                            account_id,
-                            comparator.keyword.unwrap_or(Keyword::Seen),
-                            true,
-                        )
-                        .await?,
-                        comparator.is_ascending,
-                    ),
-                    SortProperty::SomeInThreadHaveKeyword => query::Comparator::set(
-                        self.thread_keywords(
-                            account_id,
-                            comparator.keyword.unwrap_or(Keyword::Seen),
-                            false,
-                        )
-                        .await?,
-                        comparator.is_ascending,
-                    ),
-                    // Non-standard
-                    SortProperty::Cc => {
-                        query::Comparator::field(Property::Cc, comparator.is_ascending)
-                    }
-
-                    other => return Err(MethodError::UnsupportedSort(other.to_string())),
-                });
+                metadata.inner
+            } else {
+                continue;
+            };
+            let root = &metadata.contents.parts[0];
+            let mut headers = root
+                .headers
+                .iter()
+                .filter(|h| h.name.as_str().eq_ignore_ascii_case(&header_name))
+                .peekable();
+            if headers.peek().is_none() {
+                continue;
+            } else if matches!(matcher, HeaderMatch::Exists) {
+                matched_ids.insert(document_id);
+                continue;
             }
 
-            // Sort results
-            self.sort(
-                result_set,
-                comparators,
-                paginate
-                    .with_prefix_key(ValueKey {
-                        account_id,
-                        collection: Collection::Email.into(),
-                        document_id: 0,
-                        class: ValueClass::Property(Property::ThreadId.into()),
-                    })
-                    .with_prefix_unique(request.arguments.collapse_threads.unwrap_or(false)),
-                response,
-            )
-            .await
-        } else {
-            Ok(response)
+            let raw_headers = if let Some(raw_headers) = self
+                .get_blob(&metadata.blob_hash, 0..root.offset_body as u32)
+                .await?
+            {
+                raw_headers
+            } else {
+                continue;
+            };
+            for header in headers {
+                let value = match raw_headers
+                    .get(header.offset_start..header.offset_end)
+                    .map(|bytes| MessageStream::new(bytes).parse_unstructured())
+                {
+                    Some(HeaderValue::Text(value)) => value,
+                    _ => "".into(),
+                };
+                let is_match = match &matcher {
+                    HeaderMatch::Contains(text) => value.to_lowercase().contains(text.as_str()),
+                    HeaderMatch::Regex(regex) => regex.is_match(value.as_ref()),
+                    HeaderMatch::Exists => true,
+                };
+                if is_match {
+                    matched_ids.insert(document_id);
+                    break;
+                }
+            }
         }
+
+        Ok(matched_ids)
     }
 
     async fn thread_keywords(
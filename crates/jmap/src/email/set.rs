@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::{borrow::Cow, collections::HashMap};
+use std::borrow::Cow;
 
 use jmap_proto::{
     error::{
@@ -58,10 +58,13 @@ use store::{
     },
     Serialize,
 };
+use utils::events::{self, EventKind, LifecycleEvent};
 
 use crate::{
-    auth::AccessToken, mailbox::UidMailbox, services::housekeeper::Event, Bincode, IngestError,
-    JMAP,
+    auth::AccessToken,
+    mailbox::{usage::MailboxUsageBuilder, UidMailbox, JUNK_ID},
+    services::housekeeper::Event,
+    Bincode, IngestError, JMAP,
 };
 
 use super::{
@@ -125,29 +128,18 @@ impl JMAP {
             let mut received_at = None;
 
             // Parse body values
-            let body_values = object
-                .properties
-                .remove(&Property::BodyValues)
-                .and_then(|obj| {
-                    if let SetValue::Value(Value::Object(obj)) = obj {
-                        let mut values = HashMap::with_capacity(obj.properties.len());
-                        for (key, value) in obj.properties {
-                            if let (Property::_T(id), Value::Object(mut bv)) = (key, value) {
-                                values.insert(
-                                    id,
-                                    bv.properties
-                                        .remove(&Property::Value)?
-                                        .try_unwrap_string()?,
-                                );
-                            } else {
-                                return None;
-                            }
+            let body_values = match object.properties.remove(&Property::BodyValues) {
+                Some(SetValue::Value(Value::Object(obj))) => {
+                    match self.email_body_values(obj, access_token).await? {
+                        Ok(values) => values,
+                        Err(err) => {
+                            response.not_created.append(id, err);
+                            continue 'create;
                         }
-                        Some(values)
-                    } else {
-                        None
                     }
-                });
+                }
+                _ => None,
+            };
             let mut size_attachments = 0;
 
             // Parse properties
@@ -859,6 +851,14 @@ impl JMAP {
                 continue 'update;
             }
 
+            // Report messages marked as spam
+            let is_junk = self.is_abuse_report_enabled()
+                && (keywords.added().contains(&Keyword::Junk)
+                    || mailboxes
+                        .added()
+                        .iter()
+                        .any(|mailbox| mailbox.mailbox_id == JUNK_ID));
+
             // Log change
             batch.update_document(document_id);
             let mut changed_mailboxes = AHashSet::new();
@@ -959,6 +959,14 @@ impl JMAP {
                 }
 
                 // Update mailboxIds property
+                if !mailboxes.removed().is_empty() {
+                    if changes.change_id == u64::MAX {
+                        changes.change_id = self.assign_change_id(account_id).await?;
+                    }
+                    self.add_tombstones(&mut batch, changes.change_id, mailboxes.removed());
+                }
+                self.mailbox_usage_update(&mut batch, account_id, document_id, &mailboxes)
+                    .await?;
                 mailboxes.update_batch(&mut batch, Property::MailboxIds);
             }
 
@@ -973,6 +981,10 @@ impl JMAP {
                     Ok(_) => {
                         // Add to updated list
                         response.updated.append(id, None);
+
+                        if is_junk {
+                            self.report_junk(account_id, document_id).await;
+                        }
                     }
                     Err(store::Error::AssertValueFailed) => {
                         response.not_updated.append(
@@ -1013,7 +1025,13 @@ impl JMAP {
                 if email_ids.contains(document_id) {
                     if !matches!(&can_destroy_message_ids, Some(ids) if !ids.contains(document_id))
                     {
-                        match self.email_delete(account_id, document_id).await? {
+                        if changes.change_id == u64::MAX {
+                            changes.change_id = self.assign_change_id(account_id).await?;
+                        }
+                        match self
+                            .email_delete(account_id, document_id, changes.change_id)
+                            .await?
+                        {
                             Ok(change) => {
                                 changes.merge(change);
                                 response.destroyed.push(destroy_id);
@@ -1058,11 +1076,21 @@ impl JMAP {
         Ok(response)
     }
 
+    // Tombstones are written using the change id of the changelog the returned
+    // changes are merged into, so that they match the IMAP MODSEQ of the expunge.
     pub async fn email_delete(
         &self,
         account_id: u32,
         document_id: u32,
+        change_id: u64,
     ) -> Result<Result<ChangeLogBuilder, SetError>, MethodError> {
+        // Messages under legal hold cannot be deleted or expired
+        if self.is_under_legal_hold(account_id, document_id).await? {
+            return Ok(Err(
+                SetError::forbidden().with_description("This message is under legal hold.")
+            ));
+        }
+
         // Create batch
         let mut batch = BatchBuilder::new();
         let mut changes = ChangeLogBuilder::with_change_id(0);
@@ -1104,6 +1132,12 @@ impl JMAP {
         for mailbox_id in &mailboxes.inner {
             changes.log_child_update(Collection::Mailbox, mailbox_id.mailbox_id);
         }
+        self.add_tombstones(&mut batch, change_id, &mailboxes.inner);
+        let mailbox_ids = mailboxes
+            .inner
+            .iter()
+            .map(|m| m.mailbox_id)
+            .collect::<Vec<_>>();
         batch.assert_value(Property::MailboxIds, &mailboxes).value(
             Property::MailboxIds,
             mailboxes.inner,
@@ -1202,7 +1236,9 @@ impl JMAP {
             )
             .await?
         {
-            batch.custom(EmailIndexBuilder::clear(metadata.inner));
+            batch
+                .mailbox_usage(mailbox_ids, -1, -(metadata.inner.size as i64))
+                .custom(EmailIndexBuilder::clear(metadata.inner));
         } else {
             tracing::debug!(
                 event = "error",
@@ -1214,6 +1250,19 @@ impl JMAP {
             return Ok(Err(SetError::not_found()));
         };
 
+        // Delete annotation
+        if self
+            .get_document_ids(account_id, Collection::Annotation)
+            .await?
+            .map_or(false, |ids| ids.contains(document_id))
+        {
+            batch
+                .with_collection(Collection::Annotation)
+                .delete_document(document_id)
+                .value(Property::Value, (), F_VALUE | F_CLEAR);
+            changes.log_delete(Collection::Annotation, document_id);
+        }
+
         // Delete threadId
         if let Some(thread_id) = delete_thread_id {
             batch
@@ -1242,6 +1291,12 @@ impl JMAP {
         // Request FTS index
         let _ = self.housekeeper_tx.send(Event::IndexStart).await;
 
+        events::publish(
+            LifecycleEvent::new(EventKind::Deleted)
+                .with_account_id(account_id)
+                .with_document_id(document_id),
+        );
+
         Ok(Ok(changes))
     }
 }
@@ -1304,8 +1359,8 @@ impl<
                     self.current.inner.push(tag);
                 }
             } else if let Some(index) = self.current.inner.iter().position(|t| t == &tag) {
-                self.current.inner.swap_remove(index);
-                self.removed.push(tag);
+                // Keep the stored tag, it might carry data not used for comparison
+                self.removed.push(self.current.inner.swap_remove(index));
             }
             self.last = LastTag::Update;
         }
@@ -30,7 +30,12 @@ use jmap_proto::{
     types::{acl::Acl, collection::Collection, property::Property},
 };
 use mail_parser::{decoders::html::html_to_text, GetHeader, HeaderName, PartType};
-use nlp::language::{search_snippet::generate_snippet, stemmer::Stemmer, Language};
+use nlp::language::{
+    sanitize::{sanitize_html, sanitize_text},
+    search_snippet::generate_snippet,
+    stemmer::Stemmer,
+    Language,
+};
 use store::backend::MAX_TOKEN_LENGTH;
 
 use crate::{auth::AccessToken, Bincode, JMAP};
@@ -140,9 +145,11 @@ impl JMAP {
                 .headers
                 .header_value(&HeaderName::Subject)
                 .and_then(|v| v.as_text())
-                .and_then(|v| generate_snippet(v, &terms, language, is_exact))
+                .and_then(|v| {
+                    generate_snippet(&sanitize_text(v, false), &terms, language, is_exact)
+                })
             {
-                snippet.subject = subject.into();
+                snippet.subject = sanitize_html(&subject, &self.config.snippet_allowed_tags).into();
             }
 
             // Check if the snippet can be generated from the preview
@@ -169,13 +176,16 @@ impl JMAP {
                 match &part.body {
                     MetadataPartType::Text | MetadataPartType::Html => {
                         let text = match part.decode_contents(&raw_message) {
-                            PartType::Text(text) => text,
-                            PartType::Html(html) => html_to_text(&html).into(),
+                            PartType::Text(text) => sanitize_text(&text, false).into_owned(),
+                            PartType::Html(html) => {
+                                sanitize_text(&html_to_text(&html), true).into_owned()
+                            }
                             _ => unreachable!(),
                         };
 
                         if let Some(body) = generate_snippet(&text, &terms, language, is_exact) {
-                            snippet.preview = body.into();
+                            snippet.preview =
+                                sanitize_html(&body, &self.config.snippet_allowed_tags).into();
                             break;
                         }
                     }
@@ -183,15 +193,21 @@ impl JMAP {
                         for part in &message.parts {
                             if let MetadataPartType::Text | MetadataPartType::Html = part.body {
                                 let text = match part.decode_contents(&raw_message) {
-                                    PartType::Text(text) => text,
-                                    PartType::Html(html) => html_to_text(&html).into(),
+                                    PartType::Text(text) => {
+                                        sanitize_text(&text, false).into_owned()
+                                    }
+                                    PartType::Html(html) => {
+                                        sanitize_text(&html_to_text(&html), true).into_owned()
+                                    }
                                     _ => unreachable!(),
                                 };
 
                                 if let Some(body) =
                                     generate_snippet(&text, &terms, language, is_exact)
                                 {
-                                    snippet.preview = body.into();
+                                    snippet.preview =
+                                        sanitize_html(&body, &self.config.snippet_allowed_tags)
+                                            .into();
                                     break 'outer;
                                 }
                             }
@@ -0,0 +1,248 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::error::method::MethodError;
+use store::{
+    write::{key::DeserializeBigEndian, now, BatchBuilder, ValueClass},
+    Deserialize, IterateParams, Serialize, ValueKey, U32_LEN,
+};
+
+use crate::{mailbox::UidMailbox, JMAP};
+
+impl JMAP {
+    // Records the UIDs that are removed from their mailboxes, allowing IMAP
+    // clients to obtain the expunged messages since a given MODSEQ.
+    pub fn add_tombstones<'x>(
+        &self,
+        batch: &mut BatchBuilder,
+        change_id: u64,
+        mailboxes: impl IntoIterator<Item = &'x UidMailbox>,
+    ) {
+        if self.config.tombstone_enable {
+            let deleted_at = now();
+            for mailbox in mailboxes {
+                batch.set(
+                    ValueClass::Tombstone {
+                        mailbox_id: mailbox.mailbox_id,
+                        change_id,
+                        uid: mailbox.uid,
+                    },
+                    deleted_at.serialize(),
+                );
+            }
+        }
+    }
+
+    // Returns the UIDs removed from a mailbox after the given change id, or
+    // `None` if the tombstones covering that period have been purged.
+    pub async fn tombstones(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+        since_change_id: u64,
+    ) -> Result<Option<Vec<u32>>, MethodError> {
+        if !self.config.tombstone_enable {
+            return Ok(None);
+        }
+
+        // Make sure the history is available
+        if self
+            .store
+            .get_value::<u64>(ValueKey {
+                account_id,
+                collection: 0,
+                document_id: 0,
+                class: ValueClass::TombstoneHorizon(mailbox_id),
+            })
+            .await
+            .map_err(|err| map_error(account_id, err))?
+            .map_or(false, |horizon| horizon > since_change_id)
+        {
+            return Ok(None);
+        }
+
+        let mut uids = Vec::new();
+        self.store
+            .iterate(
+                IterateParams::new(
+                    ValueKey {
+                        account_id,
+                        collection: 0,
+                        document_id: 0,
+                        class: ValueClass::Tombstone {
+                            mailbox_id,
+                            change_id: since_change_id.saturating_add(1),
+                            uid: 0,
+                        },
+                    },
+                    ValueKey {
+                        account_id,
+                        collection: 0,
+                        document_id: 0,
+                        class: ValueClass::Tombstone {
+                            mailbox_id,
+                            change_id: u64::MAX,
+                            uid: u32::MAX,
+                        },
+                    },
+                )
+                .ascending()
+                .no_values(),
+                |key, _| {
+                    uids.push(key.deserialize_be_u32(key.len() - U32_LEN)?);
+                    Ok(true)
+                },
+            )
+            .await
+            .map_err(|err| map_error(account_id, err))?;
+        uids.sort_unstable();
+        uids.dedup();
+
+        Ok(Some(uids))
+    }
+
+    pub async fn purge_tombstones(&self) {
+        if !self.config.tombstone_enable {
+            return;
+        }
+
+        let cutoff = now().saturating_sub(self.config.tombstone_retention.as_secs());
+        let max_tombstones = self.config.tombstone_max_per_mailbox;
+        let from_key = ValueKey {
+            account_id: 0,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Tombstone {
+                mailbox_id: 0,
+                change_id: 0,
+                uid: 0,
+            },
+        };
+        let to_key = ValueKey {
+            account_id: u32::MAX,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Tombstone {
+                mailbox_id: u32::MAX,
+                change_id: u64::MAX,
+                uid: u32::MAX,
+            },
+        };
+
+        // Tombstones are grouped by mailbox and sorted by change id,
+        // the oldest ones are removed first.
+        let mut batch = BatchBuilder::new();
+        let mut mailbox = MailboxTombstones::default();
+        let result = self
+            .store
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending(),
+                |key, value| {
+                    let account_id = key.deserialize_be_u32(1)?;
+                    let mailbox_id = key.deserialize_be_u32(1 + U32_LEN)?;
+                    if (account_id, mailbox_id) != (mailbox.account_id, mailbox.mailbox_id) {
+                        mailbox.purge(&mut batch, cutoff, max_tombstones);
+                        mailbox = MailboxTombstones {
+                            account_id,
+                            mailbox_id,
+                            entries: Vec::new(),
+                        };
+                    }
+                    mailbox.entries.push((
+                        key.deserialize_be_u64(1 + U32_LEN * 2)?,
+                        key.deserialize_be_u32(key.len() - U32_LEN)?,
+                        u64::deserialize(value)?,
+                    ));
+
+                    Ok(true)
+                },
+            )
+            .await;
+        mailbox.purge(&mut batch, cutoff, max_tombstones);
+
+        if let Err(err) = result {
+            tracing::error!(
+                context = "tombstones",
+                event = "error",
+                reason = ?err,
+                "Failed to iterate over tombstones."
+            );
+        } else if !batch.is_empty() {
+            if let Err(err) = self.store.write(batch.build()).await {
+                tracing::error!(
+                    context = "tombstones",
+                    event = "error",
+                    reason = ?err,
+                    "Failed to purge tombstones."
+                );
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct MailboxTombstones {
+    account_id: u32,
+    mailbox_id: u32,
+    entries: Vec<(u64, u32, u64)>,
+}
+
+impl MailboxTombstones {
+    fn purge(&mut self, batch: &mut BatchBuilder, cutoff: u64, max_tombstones: usize) {
+        let excess = self.entries.len().saturating_sub(max_tombstones);
+        let mut horizon = None;
+
+        for (pos, (change_id, uid, deleted_at)) in self.entries.drain(..).enumerate() {
+            if pos < excess || deleted_at < cutoff {
+                if horizon.is_none() {
+                    batch.with_account_id(self.account_id);
+                }
+                batch.clear(ValueClass::Tombstone {
+                    mailbox_id: self.mailbox_id,
+                    change_id,
+                    uid,
+                });
+                horizon = Some(change_id);
+            }
+        }
+
+        // Clients that synchronized before the horizon need a full resync
+        if let Some(horizon) = horizon {
+            batch.set(
+                ValueClass::TombstoneHorizon(self.mailbox_id),
+                horizon.serialize(),
+            );
+        }
+    }
+}
+
+fn map_error(account_id: u32, err: store::Error) -> MethodError {
+    tracing::error!(
+        event = "error",
+        context = "tombstones",
+        account_id = account_id,
+        error = ?err,
+        "Failed to retrieve tombstones."
+    );
+    MethodError::ServerPartialFail
+}
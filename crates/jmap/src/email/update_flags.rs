@@ -0,0 +1,213 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::{method::MethodError, set::SetError},
+    method::update_flags::{UpdateFlagsRequest, UpdateFlagsResponse},
+    types::{
+        acl::Acl,
+        collection::Collection,
+        id::Id,
+        keyword::Keyword,
+        property::Property,
+        state::{State, StateChange},
+        type_state::DataType,
+    },
+};
+use store::{
+    ahash::AHashSet,
+    roaring::RoaringBitmap,
+    write::{assert::HashedValue, log::ChangeLogBuilder, BatchBuilder, F_VALUE},
+};
+use utils::map::vec_map::VecMap;
+
+use crate::{auth::AccessToken, mailbox::UidMailbox, JMAP};
+
+use super::set::TagManager;
+
+impl JMAP {
+    pub async fn email_update_flags(
+        &self,
+        request: UpdateFlagsRequest,
+        access_token: &AccessToken,
+    ) -> Result<UpdateFlagsResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let old_state = self
+            .assert_state(account_id, Collection::Email, &request.if_in_state)
+            .await?;
+        if request.keywords.is_empty() {
+            return Err(MethodError::InvalidArguments(
+                "At least one keyword has to be specified.".to_string(),
+            ));
+        }
+
+        // Obtain the messages matching the filter
+        let filters = self.email_filters(account_id, request.filter).await?;
+        let mut result_set = self.filter(account_id, Collection::Email, filters).await?;
+        if access_token.is_shared(account_id) {
+            result_set.apply_mask(
+                self.shared_messages(access_token, account_id, Acl::ModifyItems)
+                    .await?,
+            );
+        }
+
+        // Skip messages that already have the requested keywords
+        let mut document_ids = RoaringBitmap::new();
+        for (keyword, set) in request.keywords.iter() {
+            let tagged = self
+                .get_tag(
+                    account_id,
+                    Collection::Email,
+                    Property::Keywords,
+                    keyword.clone(),
+                )
+                .await?
+                .unwrap_or_default();
+            if *set {
+                document_ids |= &result_set.results - &tagged;
+            } else {
+                document_ids |= &result_set.results & &tagged;
+            }
+        }
+        if document_ids.len() as usize > self.config.update_flags_max_objects {
+            return Err(MethodError::RequestTooLarge);
+        }
+
+        let mut response = UpdateFlagsResponse {
+            account_id: request.account_id,
+            old_state: old_state.clone(),
+            new_state: old_state,
+            updated: Vec::with_capacity(document_ids.len() as usize),
+            not_updated: VecMap::new(),
+            state_change: None,
+        };
+        let changes_seen = request.keywords.keys().any(|k| k == &Keyword::Seen);
+        let is_junk = self.is_abuse_report_enabled()
+            && request
+                .keywords
+                .iter()
+                .any(|(k, set)| k == &Keyword::Junk && *set);
+        let mut changed_mailboxes = AHashSet::new();
+        let mut changes = ChangeLogBuilder::new();
+        changes.change_id = self.assign_change_id(account_id).await?;
+
+        for document_id in document_ids {
+            // Obtain current keywords
+            let (thread_id, keywords) = if let (Some(thread_id), Some(keywords)) = (
+                self.get_property::<u32>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::ThreadId,
+                )
+                .await?,
+                self.get_property::<HashedValue<Vec<Keyword>>>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::Keywords,
+                )
+                .await?,
+            ) {
+                (thread_id, keywords)
+            } else {
+                // Message was deleted in the meantime
+                continue;
+            };
+            let id = Id::from_parts(thread_id, document_id);
+            let mut keywords = TagManager::new(keywords);
+            for (keyword, set) in request.keywords.iter() {
+                keywords.update(keyword.clone(), *set);
+            }
+            if !keywords.has_changes() {
+                continue;
+            }
+
+            // Mailbox unread counts change when the Seen keyword is modified
+            if changes_seen {
+                for mailbox in self
+                    .get_property::<Vec<UidMailbox>>(
+                        account_id,
+                        Collection::Email,
+                        document_id,
+                        Property::MailboxIds,
+                    )
+                    .await?
+                    .unwrap_or_default()
+                {
+                    changed_mailboxes.insert(mailbox.mailbox_id);
+                }
+            }
+
+            // Write changes
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Email)
+                .update_document(document_id);
+            keywords.update_batch(&mut batch, Property::Keywords);
+            batch.value(Property::Cid, changes.change_id, F_VALUE);
+            match self.store.write(batch.build()).await {
+                Ok(_) => {
+                    changes.log_update(Collection::Email, id);
+                    response.updated.push(id);
+
+                    if is_junk {
+                        self.report_junk(account_id, document_id).await;
+                    }
+                }
+                Err(store::Error::AssertValueFailed) => {
+                    response.not_updated.append(
+                        id,
+                        SetError::forbidden().with_description(
+                            "Another process modified this message, please try again.",
+                        ),
+                    );
+                }
+                Err(err) => {
+                    tracing::error!(
+                        event = "error",
+                        context = "email_update_flags",
+                        error = ?err,
+                        "Failed to write message changes to database.");
+                    return Err(MethodError::ServerPartialFail);
+                }
+            }
+        }
+
+        // Update state
+        if !changes.is_empty() {
+            for mailbox_id in changed_mailboxes {
+                changes.log_child_update(Collection::Mailbox, mailbox_id);
+            }
+            let change_id = self.commit_changes(account_id, changes).await?;
+            response.new_state = State::Exact(change_id);
+            response.state_change = StateChange::new(account_id)
+                .with_change(DataType::Email, change_id)
+                .with_change(DataType::Mailbox, change_id)
+                .into();
+        }
+
+        Ok(response)
+    }
+}
@@ -23,3 +23,6 @@
 
 pub mod get;
 pub mod set;
+
+pub const ENVELOPE_SENDER_IDENTITY: &str = "identity";
+pub const ENVELOPE_SENDER_PRIMARY: &str = "primary";
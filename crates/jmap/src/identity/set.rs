@@ -37,6 +37,8 @@ use store::write::{log::ChangeLogBuilder, BatchBuilder, F_CLEAR, F_VALUE};
 
 use crate::JMAP;
 
+use super::{ENVELOPE_SENDER_IDENTITY, ENVELOPE_SENDER_PRIMARY};
+
 impl JMAP {
     pub async fn identity_set(
         &self,
@@ -49,6 +51,13 @@ impl JMAP {
             .unwrap_or_default();
         let mut response = SetResponse::from_request(&request, self.config.set_max_objects)?;
         let will_destroy = request.unwrap_destroy();
+        let account_emails = self
+            .directory
+            .query(QueryBy::Id(account_id), false)
+            .await
+            .unwrap_or_default()
+            .unwrap_or_default()
+            .emails;
 
         // Process creates
         let mut changes = ChangeLogBuilder::new();
@@ -73,15 +82,7 @@ impl JMAP {
 
             // Validate email address
             if let Value::Text(email) = identity.get(&Property::Email) {
-                if !self
-                    .directory
-                    .query(QueryBy::Id(account_id), false)
-                    .await
-                    .unwrap_or_default()
-                    .unwrap_or_default()
-                    .emails
-                    .contains(email)
-                {
+                if !account_emails.contains(email) {
                     response.not_created.append(
                         id,
                         SetError::invalid_properties()
@@ -102,6 +103,12 @@ impl JMAP {
                 continue 'create;
             }
 
+            // Validate envelope sender
+            if let Err(err) = validate_envelope_sender(&identity, &account_emails) {
+                response.not_created.append(id, err);
+                continue 'create;
+            }
+
             // Insert record
             let mut batch = BatchBuilder::new();
             let document_id = self
@@ -161,6 +168,12 @@ impl JMAP {
                 };
             }
 
+            // Validate envelope sender
+            if let Err(err) = validate_envelope_sender(&identity, &account_emails) {
+                response.not_updated.append(id, err);
+                continue 'update;
+            }
+
             // Update record
             let mut batch = BatchBuilder::new();
             batch
@@ -223,6 +236,25 @@ fn validate_identity_value(
             Property::TextSignature | Property::HtmlSignature,
             MaybePatchValue::Value(Value::Text(value)),
         ) if value.len() < 2048 => Value::Text(value),
+        (Property::EnvelopeSender, MaybePatchValue::Value(Value::Text(value)))
+            if value.len() < 255 =>
+        {
+            let value = value.trim().to_lowercase();
+            if matches!(
+                value.as_str(),
+                ENVELOPE_SENDER_IDENTITY | ENVELOPE_SENDER_PRIMARY
+            ) {
+                Value::Text(value)
+            } else {
+                Value::Text(sanitize_email(&value).ok_or_else(|| {
+                    SetError::invalid_properties()
+                        .with_property(Property::EnvelopeSender)
+                        .with_description(
+                            "Envelope sender must be 'identity', 'primary' or an e-mail address.",
+                        )
+                })?)
+            }
+        }
         (Property::ReplyTo | Property::Bcc, MaybePatchValue::Value(Value::List(value))) => {
             for addr in &value {
                 let mut is_valid = false;
@@ -256,7 +288,8 @@ fn validate_identity_value(
             | Property::TextSignature
             | Property::HtmlSignature
             | Property::ReplyTo
-            | Property::Bcc,
+            | Property::Bcc
+            | Property::EnvelopeSender,
             MaybePatchValue::Value(Value::Null),
         ) => Value::Null,
 
@@ -268,6 +301,26 @@ fn validate_identity_value(
     })
 }
 
+fn validate_envelope_sender(
+    identity: &Object<Value>,
+    account_emails: &[String],
+) -> Result<(), SetError> {
+    match identity.get(&Property::EnvelopeSender) {
+        Value::Text(sender)
+            if sender != ENVELOPE_SENDER_IDENTITY
+                && sender != ENVELOPE_SENDER_PRIMARY
+                && !account_emails
+                    .iter()
+                    .any(|email| email.eq_ignore_ascii_case(sender)) =>
+        {
+            Err(SetError::invalid_properties()
+                .with_property(Property::EnvelopeSender)
+                .with_description("E-mail address not configured for this account."))
+        }
+        _ => Ok(()),
+    }
+}
+
 // Basic email sanitizer
 pub fn sanitize_email(email: &str) -> Option<String> {
     let mut result = String::with_capacity(email.len());
@@ -0,0 +1,185 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{sync::Arc, time::Instant};
+
+use chrono::Timelike;
+use store::COMPACT_SUBSPACES;
+use utils::config::utils::{AsKey, ParseValue};
+
+use crate::JMAP;
+
+use super::{Job, JobError, JobKind, JobStatus, JobTask};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusinessHours {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl JMAP {
+    pub(super) async fn job_compact(&self, job: &Job) -> Result<(), JobError> {
+        job.progress.lock().total = COMPACT_SUBSPACES.len();
+        let started = Instant::now();
+        let mut throttled = 0;
+
+        for subspace in COMPACT_SUBSPACES {
+            job.check_cancel()?;
+
+            // Spread the IO load while users are active
+            if self.is_business_hours() {
+                tokio::time::sleep(self.config.compaction_throttle).await;
+                job.check_cancel()?;
+                throttled += 1;
+            }
+
+            let step = Instant::now();
+            self.store.compact(subspace).await.map_err(|err| {
+                JobError::Failed(format!(
+                    "Compaction of subspace {:?} failed: {err}",
+                    char::from(subspace)
+                ))
+            })?;
+            tracing::debug!(
+                context = "store",
+                event = "compact",
+                subspace = %char::from(subspace),
+                elapsed = step.elapsed().as_millis() as u64,
+                "Compacted subspace."
+            );
+
+            job.progress.lock().processed += 1;
+            self.job_persist(job).await;
+        }
+
+        let mut progress = job.progress.lock();
+        let mut summary = format!(
+            "Compacted {} subspace(s) in {}s.",
+            progress.processed,
+            started.elapsed().as_secs()
+        );
+        if throttled > 0 {
+            summary.push_str(&format!(
+                " Throttled {throttled} step(s) during business hours."
+            ));
+        }
+        progress.summary = summary.into();
+
+        Ok(())
+    }
+
+    pub fn compaction_schedule(self: &Arc<Self>) -> Option<u64> {
+        if self.is_business_hours() {
+            tracing::info!(
+                context = "store",
+                event = "skip",
+                "Skipping scheduled compaction during business hours."
+            );
+            None
+        } else if self.is_compaction_running() {
+            tracing::info!(
+                context = "store",
+                event = "skip",
+                "Skipping scheduled compaction, a previous run is still active."
+            );
+            None
+        } else {
+            self.job_start(JobTask::StoreCompaction)
+        }
+    }
+
+    pub fn is_compaction_running(&self) -> bool {
+        self.jobs.iter().any(|job| {
+            let progress = job.progress.lock();
+            progress.kind == JobKind::StoreCompaction && progress.status == JobStatus::Running
+        })
+    }
+
+    fn is_business_hours(&self) -> bool {
+        self.config
+            .compaction_business_hours
+            .map_or(false, |hours| hours.contains(chrono::Local::now().hour()))
+    }
+}
+
+impl BusinessHours {
+    pub fn contains(&self, hour: u32) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&hour)
+        } else {
+            hour >= self.start || hour < self.end
+        }
+    }
+}
+
+impl ParseValue for BusinessHours {
+    fn parse_value(key: impl AsKey, value: &str) -> utils::config::Result<Self> {
+        let key = key.as_key();
+        let (start, end) = value
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid business hours {value:?} for key {key:?}."))?;
+        let mut hours = [0u32; 2];
+        for (hour, value) in hours.iter_mut().zip([start, end]) {
+            *hour = value
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|hour| *hour <= 24)
+                .ok_or_else(|| format!("Invalid business hours {value:?} for key {key:?}."))?;
+        }
+
+        Ok(BusinessHours {
+            start: hours[0],
+            end: hours[1],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use utils::config::utils::ParseValue;
+
+    use super::BusinessHours;
+
+    #[test]
+    fn business_hours() {
+        let hours = BusinessHours::parse_value("", "8-18").unwrap();
+        assert!(!hours.contains(7));
+        assert!(hours.contains(8));
+        assert!(hours.contains(17));
+        assert!(!hours.contains(18));
+
+        let hours = BusinessHours::parse_value("", "22 - 6").unwrap();
+        assert!(hours.contains(23));
+        assert!(hours.contains(0));
+        assert!(!hours.contains(6));
+        assert!(!hours.contains(12));
+
+        for invalid in ["", "8", "8-25", "a-b"] {
+            assert!(
+                BusinessHours::parse_value("", invalid).is_err(),
+                "{invalid}"
+            );
+        }
+    }
+}
@@ -0,0 +1,581 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use directory::{backend::internal::manage::ManageDirectory, QueryBy};
+use jmap_proto::types::{collection::Collection, property::Property, value::Value};
+use smtp::{
+    core::management::QueueRequest,
+    queue::{self, deferred::DeferReason},
+};
+use store::{
+    parking_lot::Mutex,
+    write::{now, BatchBuilder, ValueClass},
+    LookupKey, LookupValue,
+};
+use tokio::sync::oneshot;
+
+use crate::{email::metadata::MessageMetadata, services::housekeeper, Bincode, JMAP};
+
+pub mod compaction;
+
+pub const KV_JOB: &[u8] = b"job:";
+
+const QUEUE_BATCH_SIZE: usize = 500;
+const REINDEX_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum JobKind {
+    StoreMaintenance,
+    StoreCompaction,
+    PurgeAccounts,
+    Reindex,
+    QueueCancel,
+    QueueRetry,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobProgress {
+    pub id: u64,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub total: usize,
+    pub processed: usize,
+    pub failed: usize,
+    pub summary: Option<String>,
+    pub error: Option<String>,
+}
+
+pub struct Job {
+    pub progress: Mutex<JobProgress>,
+    cancel: AtomicBool,
+}
+
+#[derive(Debug, Default)]
+pub struct QueueSelection {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub before: Option<Instant>,
+    pub after: Option<Instant>,
+    pub item: Option<String>,
+    pub reason: Option<DeferReason>,
+}
+
+#[derive(Debug)]
+pub enum JobTask {
+    StoreMaintenance,
+    StoreCompaction,
+    PurgeAccounts(Vec<String>),
+    Reindex(Option<u32>),
+    QueueCancel(QueueSelection),
+    QueueRetry(QueueSelection, Instant),
+}
+
+enum JobError {
+    Cancelled,
+    Failed(String),
+}
+
+impl JMAP {
+    pub fn job_start(self: &Arc<Self>, task: JobTask) -> Option<u64> {
+        let id = self.snowflake_id.generate()?;
+        let job = Arc::new(Job {
+            progress: Mutex::new(JobProgress {
+                id,
+                kind: task.kind(),
+                status: JobStatus::Running,
+                started_at: now(),
+                finished_at: None,
+                total: 0,
+                processed: 0,
+                failed: 0,
+                summary: None,
+                error: None,
+            }),
+            cancel: AtomicBool::new(false),
+        });
+
+        // Forget jobs that finished before the retention period
+        let expired = now().saturating_sub(self.config.job_retention.as_secs());
+        self.jobs.retain(|_, job| {
+            job.progress
+                .lock()
+                .finished_at
+                .map_or(true, |finished_at| finished_at > expired)
+        });
+        self.jobs.insert(id, job.clone());
+
+        let jmap = self.clone();
+        tokio::spawn(async move {
+            jmap.job_persist(&job).await;
+            let result = jmap.job_run(task, &job).await;
+
+            // Persist the final state before publishing it
+            let mut progress = job.progress.lock().clone();
+            progress.finished_at = now().into();
+            match result {
+                Ok(_) => {
+                    progress.status = JobStatus::Completed;
+                    tracing::info!(
+                        context = "job",
+                        event = "completed",
+                        id = id,
+                        kind = ?progress.kind,
+                        processed = progress.processed,
+                        failed = progress.failed,
+                        "Job completed."
+                    );
+                }
+                Err(JobError::Cancelled) => {
+                    progress.status = JobStatus::Cancelled;
+                    tracing::info!(
+                        context = "job",
+                        event = "cancelled",
+                        id = id,
+                        kind = ?progress.kind,
+                        "Job cancelled."
+                    );
+                }
+                Err(JobError::Failed(err)) => {
+                    progress.status = JobStatus::Failed;
+                    tracing::warn!(
+                        context = "job",
+                        event = "error",
+                        id = id,
+                        kind = ?progress.kind,
+                        reason = %err,
+                        "Job failed."
+                    );
+                    progress.error = err.into();
+                }
+            }
+            jmap.job_store(&progress).await;
+            *job.progress.lock() = progress;
+        });
+
+        Some(id)
+    }
+
+    pub fn job_cancel(&self, id: u64) -> bool {
+        if let Some(job) = self.jobs.get(&id) {
+            if job.is_running() {
+                job.cancel.store(true, Ordering::Relaxed);
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn job_list(&self) -> Vec<JobProgress> {
+        let mut jobs = self
+            .jobs
+            .iter()
+            .map(|job| job.progress.lock().clone())
+            .collect::<Vec<_>>();
+        jobs.sort_unstable_by_key(|job| job.id);
+        jobs
+    }
+
+    pub async fn job_status(&self, id: u64) -> store::Result<Option<JobProgress>> {
+        if let Some(job) = self.jobs.get(&id) {
+            return Ok(Some(job.progress.lock().clone()));
+        }
+
+        match self
+            .smtp
+            .queue
+            .config
+            .lookup_store
+            .key_get::<String>(LookupKey::Key(job_key(id)))
+            .await?
+        {
+            LookupValue::Value { value, .. } => Ok(serde_json::from_str::<JobProgress>(&value)
+                .ok()
+                .map(|mut progress| {
+                    // Jobs are not resumed, a running job that is not
+                    // tracked by this node was interrupted by a restart.
+                    if progress.status == JobStatus::Running {
+                        progress.status = JobStatus::Failed;
+                        progress.error = "Job was interrupted.".to_string().into();
+                    }
+                    progress
+                })),
+            _ => Ok(None),
+        }
+    }
+
+    async fn job_persist(&self, job: &Job) {
+        let progress = job.progress.lock().clone();
+        self.job_store(&progress).await;
+    }
+
+    async fn job_store(&self, progress: &JobProgress) {
+        if let Err(err) = self
+            .smtp
+            .queue
+            .config
+            .lookup_store
+            .key_set(
+                job_key(progress.id),
+                LookupValue::Value {
+                    value: serde_json::to_vec(progress).unwrap_or_default(),
+                    expires: self.config.job_retention.as_secs(),
+                },
+            )
+            .await
+        {
+            tracing::warn!(
+                context = "job",
+                event = "error",
+                id = progress.id,
+                reason = %err,
+                "Failed to persist job state."
+            );
+        }
+    }
+
+    async fn job_run(&self, task: JobTask, job: &Job) -> Result<(), JobError> {
+        match task {
+            JobTask::StoreMaintenance => {
+                job.progress.lock().total = 2;
+                self.store
+                    .purge_blobs(self.blob_store.clone())
+                    .await
+                    .map_err(|err| JobError::Failed(format!("Purge blob failed: {err}")))?;
+                job.progress.lock().processed += 1;
+                job.check_cancel()?;
+                self.store
+                    .purge_bitmaps()
+                    .await
+                    .map_err(|err| JobError::Failed(format!("Purge database failed: {err}")))?;
+                let mut progress = job.progress.lock();
+                progress.processed += 1;
+                progress.summary = "Purged unlinked blobs and empty bitmaps."
+                    .to_string()
+                    .into();
+            }
+            JobTask::StoreCompaction => {
+                self.job_compact(job).await?;
+            }
+            JobTask::PurgeAccounts(accounts) => {
+                job.progress.lock().total = accounts.len();
+                let mut failed_accounts = Vec::new();
+                for name in accounts {
+                    job.check_cancel()?;
+                    match self.job_purge_account(&name).await {
+                        Ok(_) => {
+                            job.progress.lock().processed += 1;
+                        }
+                        Err(err) => {
+                            tracing::debug!(
+                                context = "job",
+                                event = "error",
+                                account = %name,
+                                reason = %err,
+                                "Failed to purge account."
+                            );
+                            job.progress.lock().failed += 1;
+                            failed_accounts.push(name);
+                        }
+                    }
+                    self.job_persist(job).await;
+                }
+
+                let mut progress = job.progress.lock();
+                let mut summary = format!("Purged {} account(s).", progress.processed);
+                if !failed_accounts.is_empty() {
+                    summary.push_str(&format!(
+                        " Unable to purge account(s): {}.",
+                        failed_accounts.join(", ")
+                    ));
+                }
+                progress.summary = summary.into();
+            }
+            JobTask::Reindex(account_id) => {
+                let account_ids = if let Some(account_id) = account_id {
+                    vec![account_id]
+                } else {
+                    self.get_document_ids(u32::MAX, Collection::Principal)
+                        .await
+                        .map_err(|_| JobError::Failed("Failed to obtain account ids.".into()))?
+                        .map(|ids| ids.into_iter().collect())
+                        .unwrap_or_default()
+                };
+
+                // Obtain the messages to reindex in advance in order to report progress
+                let mut accounts = Vec::with_capacity(account_ids.len());
+                for account_id in account_ids {
+                    if let Some(document_ids) = self
+                        .get_document_ids(account_id, Collection::Email)
+                        .await
+                        .map_err(|_| JobError::Failed("Failed to obtain message ids.".into()))?
+                    {
+                        job.progress.lock().total += document_ids.len() as usize;
+                        accounts.push((account_id, document_ids));
+                    }
+                }
+
+                for (account_id, document_ids) in accounts {
+                    let mut batch = BatchBuilder::new();
+                    let mut batch_size = 0;
+                    batch
+                        .with_account_id(account_id)
+                        .with_collection(Collection::Email);
+
+                    for document_id in document_ids {
+                        job.check_cancel()?;
+                        if let Ok(Some(metadata)) = self
+                            .get_property::<Bincode<MessageMetadata>>(
+                                account_id,
+                                Collection::Email,
+                                document_id,
+                                Property::BodyStructure,
+                            )
+                            .await
+                        {
+                            let seq = self.generate_snowflake_id().map_err(|_| {
+                                JobError::Failed("Failed to generate snowflake id.".into())
+                            })?;
+                            batch.update_document(document_id).set(
+                                ValueClass::IndexEmail(seq),
+                                metadata.inner.blob_hash.as_slice().to_vec(),
+                            );
+                            batch_size += 1;
+                            job.progress.lock().processed += 1;
+                        } else {
+                            job.progress.lock().failed += 1;
+                        }
+
+                        if batch_size >= REINDEX_BATCH_SIZE {
+                            self.job_reindex_commit(batch).await?;
+                            self.job_persist(job).await;
+                            batch = BatchBuilder::new();
+                            batch_size = 0;
+                            batch
+                                .with_account_id(account_id)
+                                .with_collection(Collection::Email);
+                        }
+                    }
+
+                    if batch_size > 0 {
+                        self.job_reindex_commit(batch).await?;
+                        self.job_persist(job).await;
+                    }
+                }
+
+                let mut progress = job.progress.lock();
+                progress.summary =
+                    format!("Queued {} message(s) for indexing.", progress.processed).into();
+            }
+            JobTask::QueueCancel(selection) => {
+                self.job_queue_run(job, selection, None).await?;
+                let mut progress = job.progress.lock();
+                progress.summary =
+                    format!("Cancelled delivery of {} message(s).", progress.processed).into();
+            }
+            JobTask::QueueRetry(selection, time) => {
+                self.job_queue_run(job, selection, time.into()).await?;
+                let mut progress = job.progress.lock();
+                progress.summary = format!("Rescheduled {} message(s).", progress.processed).into();
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn job_purge_account(&self, name: &str) -> Result<(), String> {
+        let account_id = self
+            .store
+            .get_account_id(name)
+            .await
+            .map_err(|err| format!("{err:?}"))?
+            .ok_or_else(|| "Account not found.".to_string())?;
+        if self
+            .get_legal_hold(account_id)
+            .await
+            .map_err(|err| format!("{err:?}"))?
+            .is_active()
+        {
+            return Err("Account is under legal hold.".to_string());
+        }
+        self.fts_store
+            .remove_all(account_id)
+            .await
+            .map_err(|err| err.to_string())?;
+        for (_, alias) in self
+            .disposable_alias_list(account_id)
+            .await
+            .map_err(|err| format!("{err:?}"))?
+        {
+            if let Value::Text(address) = alias.get(&Property::Email) {
+                self.directory
+                    .alias_set(address, None)
+                    .await
+                    .map_err(|err| format!("{err:?}"))?;
+            }
+        }
+        self.store
+            .delete_account(QueryBy::Id(account_id))
+            .await
+            .map_err(|err| format!("{err:?}"))
+    }
+
+    async fn job_reindex_commit(&self, batch: BatchBuilder) -> Result<(), JobError> {
+        self.store.write(batch.build()).await.map_err(|err| {
+            JobError::Failed(format!("Failed to queue messages for indexing: {err}"))
+        })?;
+        let _ = self
+            .housekeeper_tx
+            .send(housekeeper::Event::IndexStart)
+            .await;
+        Ok(())
+    }
+
+    async fn job_queue_run(
+        &self,
+        job: &Job,
+        selection: QueueSelection,
+        retry_at: Option<Instant>,
+    ) -> Result<(), JobError> {
+        let (result_tx, result_rx) = oneshot::channel();
+        let queue_ids = self
+            .job_queue_request(
+                QueueRequest::List {
+                    from: selection.from,
+                    to: selection.to,
+                    before: selection.before,
+                    after: selection.after,
+                    reason: selection.reason,
+                    result_tx,
+                },
+                result_rx,
+            )
+            .await?;
+        job.progress.lock().total = queue_ids.len();
+
+        for queue_ids in queue_ids.chunks(QUEUE_BATCH_SIZE) {
+            job.check_cancel()?;
+            let (result_tx, result_rx) = oneshot::channel();
+            let request = if let Some(time) = retry_at {
+                QueueRequest::Retry {
+                    queue_ids: queue_ids.to_vec(),
+                    item: selection.item.clone(),
+                    reason: selection.reason,
+                    time,
+                    result_tx,
+                }
+            } else {
+                QueueRequest::Cancel {
+                    queue_ids: queue_ids.to_vec(),
+                    item: selection.item.clone(),
+                    result_tx,
+                }
+            };
+            let results = self.job_queue_request(request, result_rx).await?;
+            let success = results.iter().filter(|success| **success).count();
+            {
+                let mut progress = job.progress.lock();
+                progress.processed += success;
+                progress.failed += results.len() - success;
+            }
+            self.job_persist(job).await;
+        }
+
+        Ok(())
+    }
+
+    async fn job_queue_request<T>(
+        &self,
+        request: QueueRequest,
+        rx: oneshot::Receiver<T>,
+    ) -> Result<T, JobError> {
+        if self
+            .smtp
+            .queue
+            .tx
+            .send(queue::Event::Manage(request))
+            .await
+            .is_ok()
+        {
+            if let Ok(result) = rx.await {
+                return Ok(result);
+            }
+        }
+
+        Err(JobError::Failed("Queue manager is unavailable.".into()))
+    }
+}
+
+impl Job {
+    pub fn is_running(&self) -> bool {
+        self.progress.lock().status == JobStatus::Running
+    }
+
+    fn check_cancel(&self) -> Result<(), JobError> {
+        if !self.cancel.load(Ordering::Relaxed) {
+            Ok(())
+        } else {
+            Err(JobError::Cancelled)
+        }
+    }
+}
+
+impl JobTask {
+    pub fn kind(&self) -> JobKind {
+        match self {
+            JobTask::StoreMaintenance => JobKind::StoreMaintenance,
+            JobTask::StoreCompaction => JobKind::StoreCompaction,
+            JobTask::PurgeAccounts(_) => JobKind::PurgeAccounts,
+            JobTask::Reindex(_) => JobKind::Reindex,
+            JobTask::QueueCancel(_) => JobKind::QueueCancel,
+            JobTask::QueueRetry(_, _) => JobKind::QueueRetry,
+        }
+    }
+}
+
+fn job_key(id: u64) -> Vec<u8> {
+    let mut key = Vec::with_capacity(KV_JOB.len() + std::mem::size_of::<u64>());
+    key.extend_from_slice(KV_JOB);
+    key.extend_from_slice(&id.to_be_bytes());
+    key
+}
@@ -0,0 +1,168 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod get;
+pub mod set;
+
+use jmap_proto::{
+    error::method::MethodError,
+    object::Object,
+    types::{
+        collection::Collection, keyword::Keyword, property::Property, state::StateChange,
+        type_state::DataType, value::Value,
+    },
+};
+use store::write::{log::ChangeLogBuilder, BatchBuilder, F_VALUE};
+
+use crate::JMAP;
+
+impl JMAP {
+    pub async fn label_list(
+        &self,
+        account_id: u32,
+    ) -> Result<Vec<(u32, Object<Value>)>, MethodError> {
+        let label_ids = self
+            .get_document_ids(account_id, Collection::Label)
+            .await?
+            .unwrap_or_default();
+        let mut labels = Vec::with_capacity(label_ids.len() as usize);
+        for document_id in label_ids {
+            if let Some(label) = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::Label,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                labels.push((document_id, label));
+            }
+        }
+
+        Ok(labels)
+    }
+
+    pub async fn label_keywords(&self, account_id: u32) -> Result<Vec<String>, MethodError> {
+        let mut labels = self.label_list(account_id).await?;
+        labels.sort_unstable_by_key(|(_, label)| label.get(&Property::SortOrder).as_uint());
+
+        Ok(labels
+            .into_iter()
+            .filter_map(|(_, mut label)| match label.remove(&Property::Keyword) {
+                Value::Text(keyword) => Some(keyword),
+                _ => None,
+            })
+            .collect())
+    }
+
+    pub async fn label_sync_keywords(
+        &self,
+        account_id: u32,
+        keywords: &[Keyword],
+    ) -> Result<(), MethodError> {
+        // Create labels for custom keywords set from IMAP
+        let mut labels = None;
+        let mut changes = ChangeLogBuilder::new();
+        for keyword in keywords {
+            let keyword = match keyword {
+                Keyword::Other(keyword) if is_valid_keyword(keyword) => keyword,
+                _ => continue,
+            };
+            let labels = match &mut labels {
+                Some(labels) => labels,
+                None => labels.insert(self.label_list(account_id).await?),
+            };
+            if labels.len() >= self.config.label_max_labels {
+                break;
+            } else if labels.iter().any(|(_, label)| {
+                label
+                    .get(&Property::Keyword)
+                    .as_string()
+                    .map_or(false, |k| k.eq_ignore_ascii_case(keyword))
+                    || label
+                        .get(&Property::Name)
+                        .as_string()
+                        .map_or(false, |n| n.eq_ignore_ascii_case(keyword))
+            }) {
+                continue;
+            }
+
+            let label = Object::with_capacity(2)
+                .with_property(Property::Name, keyword.clone())
+                .with_property(Property::Keyword, keyword.clone());
+            let document_id = self
+                .assign_document_id(account_id, Collection::Label)
+                .await?;
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Label)
+                .create_document(document_id)
+                .value(Property::Value, label.clone(), F_VALUE);
+            self.write_batch(batch).await?;
+            changes.log_insert(Collection::Label, document_id);
+            labels.push((document_id, label));
+        }
+
+        if !changes.is_empty() {
+            let change_id = self.commit_changes(account_id, changes).await?;
+            self.broadcast_state_change(
+                StateChange::new(account_id).with_change(DataType::Label, change_id),
+            )
+            .await;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn is_valid_keyword(keyword: &str) -> bool {
+    !keyword.is_empty()
+        && keyword.len() <= 255
+        && keyword.bytes().all(|ch| {
+            ch.is_ascii_graphic()
+                && !matches!(ch, b'(' | b')' | b'{' | b'%' | b'*' | b'"' | b'\\' | b']')
+        })
+        && matches!(Keyword::from(keyword.to_string()), Keyword::Other(_))
+}
+
+pub fn keyword_from_name(name: &str) -> Option<String> {
+    let keyword = name
+        .trim()
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || matches!(ch, '$' | '-' | '_' | '.') {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+
+    if keyword.chars().any(|ch| ch.is_ascii_alphanumeric()) && is_valid_keyword(&keyword) {
+        Some(keyword)
+    } else {
+        None
+    }
+}
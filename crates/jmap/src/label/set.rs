@@ -0,0 +1,338 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::{
+        method::MethodError,
+        set::{SetError, SetErrorType},
+    },
+    method::set::{RequestArguments, SetRequest, SetResponse},
+    object::Object,
+    response::references::EvalObjectReferences,
+    types::{
+        collection::Collection,
+        property::Property,
+        state::StateChange,
+        type_state::DataType,
+        value::{MaybePatchValue, Value},
+    },
+};
+use store::write::{log::ChangeLogBuilder, BatchBuilder, F_CLEAR, F_VALUE};
+
+use crate::JMAP;
+
+use super::{is_valid_keyword, keyword_from_name};
+
+impl JMAP {
+    pub async fn label_set(
+        &self,
+        mut request: SetRequest<RequestArguments>,
+    ) -> Result<SetResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let mut labels = self.label_list(account_id).await?;
+        let mut response = self
+            .prepare_set_response(&request, Collection::Label)
+            .await?;
+        let will_destroy = request.unwrap_destroy();
+
+        // Process creates
+        let mut changes = ChangeLogBuilder::new();
+        'create: for (id, object) in request.unwrap_create() {
+            if labels.len() >= self.config.label_max_labels {
+                response.not_created.append(
+                    id,
+                    SetError::new(SetErrorType::OverQuota).with_description(
+                        "There are too many labels, please delete some before adding a new one.",
+                    ),
+                );
+                continue 'create;
+            }
+
+            let mut label = Object::with_capacity(object.properties.len());
+            for (property, value) in object.properties {
+                match response
+                    .eval_object_references(value)
+                    .and_then(|value| self.validate_label_value(&property, value, None))
+                {
+                    Ok(Value::Null) => (),
+                    Ok(value) => {
+                        label.set(property, value);
+                    }
+                    Err(err) => {
+                        response.not_created.append(id, err);
+                        continue 'create;
+                    }
+                }
+            }
+
+            // Validate name
+            let name = if let Value::Text(name) = label.get(&Property::Name) {
+                name.clone()
+            } else {
+                response.not_created.append(
+                    id,
+                    SetError::invalid_properties()
+                        .with_property(Property::Name)
+                        .with_description("Missing label name."),
+                );
+                continue 'create;
+            };
+
+            // Obtain or derive the IMAP keyword
+            let keyword = match label.get(&Property::Keyword) {
+                Value::Text(keyword) => keyword.clone(),
+                _ => {
+                    if let Some(keyword) = keyword_from_name(&name) {
+                        label.set(Property::Keyword, keyword.clone());
+                        keyword
+                    } else {
+                        response.not_created.append(
+                            id,
+                            SetError::invalid_properties()
+                                .with_property(Property::Keyword)
+                                .with_description(
+                                    "Could not derive a keyword from the label name, please provide one.",
+                                ),
+                        );
+                        continue 'create;
+                    }
+                }
+            };
+
+            if let Err(err) = assert_unique(&labels, None, &name, &keyword) {
+                response.not_created.append(id, err);
+                continue 'create;
+            }
+
+            // Insert record
+            let mut batch = BatchBuilder::new();
+            let document_id = self
+                .assign_document_id(account_id, Collection::Label)
+                .await?;
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Label)
+                .create_document(document_id)
+                .value(Property::Value, label.clone(), F_VALUE);
+            self.write_batch(batch).await?;
+            changes.log_insert(Collection::Label, document_id);
+            labels.push((document_id, label));
+            response.created.insert(
+                id,
+                Object::with_capacity(2)
+                    .with_property(Property::Id, Value::Id(document_id.into()))
+                    .with_property(Property::Keyword, keyword),
+            );
+        }
+
+        // Process updates
+        'update: for (id, object) in request.unwrap_update() {
+            // Make sure id won't be destroyed
+            if will_destroy.contains(&id) {
+                response.not_updated.append(id, SetError::will_destroy());
+                continue 'update;
+            }
+
+            // Obtain label
+            let document_id = id.document_id();
+            let mut label = if let Some((_, label)) =
+                labels.iter().find(|(label_id, _)| *label_id == document_id)
+            {
+                label.clone()
+            } else {
+                response.not_updated.append(id, SetError::not_found());
+                continue 'update;
+            };
+
+            for (property, value) in object.properties {
+                match response
+                    .eval_object_references(value)
+                    .and_then(|value| self.validate_label_value(&property, value, Some(&label)))
+                {
+                    Ok(Value::Null) => {
+                        label.remove(&property);
+                    }
+                    Ok(value) => {
+                        label.set(property, value);
+                    }
+                    Err(err) => {
+                        response.not_updated.append(id, err);
+                        continue 'update;
+                    }
+                };
+            }
+
+            // Validate name
+            if let Err(err) = assert_unique(
+                &labels,
+                Some(document_id),
+                label.get(&Property::Name).as_string().unwrap_or_default(),
+                label
+                    .get(&Property::Keyword)
+                    .as_string()
+                    .unwrap_or_default(),
+            ) {
+                response.not_updated.append(id, err);
+                continue 'update;
+            }
+
+            // Update record
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Label)
+                .update_document(document_id)
+                .value(Property::Value, label.clone(), F_VALUE);
+            self.write_batch(batch).await?;
+            changes.log_update(Collection::Label, document_id);
+            if let Some((_, current)) = labels
+                .iter_mut()
+                .find(|(label_id, _)| *label_id == document_id)
+            {
+                *current = label;
+            }
+            response.updated.append(id, None);
+        }
+
+        // Process deletions
+        for id in will_destroy {
+            let document_id = id.document_id();
+            if let Some(pos) = labels
+                .iter()
+                .position(|(label_id, _)| *label_id == document_id)
+            {
+                // Delete record, messages keep their IMAP keyword
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(Collection::Label)
+                    .delete_document(document_id)
+                    .value(Property::Value, (), F_VALUE | F_CLEAR);
+                self.write_batch(batch).await?;
+                changes.log_delete(Collection::Label, document_id);
+                labels.swap_remove(pos);
+                response.destroyed.push(id);
+            } else {
+                response.not_destroyed.append(id, SetError::not_found());
+            }
+        }
+
+        // Write changes
+        if !changes.is_empty() {
+            let change_id = self.commit_changes(account_id, changes).await?;
+            response.new_state = Some(change_id.into());
+            response.state_change = StateChange::new(account_id)
+                .with_change(DataType::Label, change_id)
+                .into();
+        }
+
+        Ok(response)
+    }
+
+    fn validate_label_value(
+        &self,
+        property: &Property,
+        value: MaybePatchValue,
+        current: Option<&Object<Value>>,
+    ) -> Result<Value, SetError> {
+        Ok(match (property, value) {
+            (Property::Name, MaybePatchValue::Value(Value::Text(value)))
+                if !value.trim().is_empty()
+                    && value.chars().count() <= self.config.label_max_name_length =>
+            {
+                Value::Text(value.trim().to_string())
+            }
+            (Property::Color, MaybePatchValue::Value(Value::Text(value)))
+                if is_valid_color(&value) =>
+            {
+                Value::Text(value.to_ascii_lowercase())
+            }
+            (Property::SortOrder, MaybePatchValue::Value(Value::UnsignedInt(value))) => {
+                Value::UnsignedInt(value)
+            }
+            (Property::Keyword, MaybePatchValue::Value(Value::Text(value))) => match current {
+                None if is_valid_keyword(&value) => Value::Text(value),
+                Some(current)
+                    if current.get(&Property::Keyword).as_string() == Some(value.as_str()) =>
+                {
+                    Value::Text(value)
+                }
+                Some(_) => {
+                    return Err(SetError::invalid_properties()
+                        .with_property(Property::Keyword)
+                        .with_description("The keyword of an existing label cannot be changed."));
+                }
+                None => {
+                    return Err(SetError::invalid_properties()
+                        .with_property(Property::Keyword)
+                        .with_description("Invalid IMAP keyword."));
+                }
+            },
+            (Property::Color | Property::SortOrder, MaybePatchValue::Value(Value::Null)) => {
+                Value::Null
+            }
+            (property, _) => {
+                return Err(SetError::invalid_properties()
+                    .with_property(property.clone())
+                    .with_description("Field could not be set."));
+            }
+        })
+    }
+}
+
+fn assert_unique(
+    labels: &[(u32, Object<Value>)],
+    document_id: Option<u32>,
+    name: &str,
+    keyword: &str,
+) -> Result<(), SetError> {
+    for (label_id, label) in labels {
+        if Some(*label_id) == document_id {
+            continue;
+        } else if label
+            .get(&Property::Name)
+            .as_string()
+            .map_or(false, |n| n.eq_ignore_ascii_case(name))
+        {
+            return Err(SetError::already_exists()
+                .with_existing_id((*label_id).into())
+                .with_description(format!("A label with name {name:?} already exists.")));
+        } else if label
+            .get(&Property::Keyword)
+            .as_string()
+            .map_or(false, |k| k.eq_ignore_ascii_case(keyword))
+        {
+            return Err(SetError::already_exists()
+                .with_existing_id((*label_id).into())
+                .with_description(format!("A label for keyword {keyword:?} already exists.")));
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn is_valid_color(color: &str) -> bool {
+    color.strip_prefix('#').map_or(false, |hex| {
+        matches!(hex.len(), 3 | 6) && hex.chars().all(|ch| ch.is_ascii_hexdigit())
+    })
+}
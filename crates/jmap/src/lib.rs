@@ -26,14 +26,17 @@ use std::{
 };
 
 use ::sieve::{Compiler, Runtime};
-use api::session::BaseCapabilities;
+use api::{autoconfig::ClientService, metrics::MetricsToken, session::BaseCapabilities};
 use auth::{
     oauth::OAuthCode,
-    rate_limit::{AnonymousLimiter, AuthenticatedLimiter},
+    rate_limit::{AnonymousLimiter, AuthenticatedLimiter, HttpEndpoint, RateLimitKey},
+    step_up::StepUpOperation,
     AccessToken,
 };
+use cluster::Cluster;
 use dashmap::DashMap;
 use directory::{Directories, Directory, QueryBy};
+use email::proxy::ProxiedImage;
 use jmap_proto::{
     error::method::MethodError,
     method::{
@@ -42,15 +45,20 @@ use jmap_proto::{
     },
     types::{collection::Collection, property::Property},
 };
+use jobs::{compaction::BusinessHours, Job};
 use mail_parser::HeaderName;
+use metering::Metering;
+use migrate::MigrationJob;
 use nlp::language::Language;
+use overlay::Overlays;
 use services::{
     delivery::spawn_delivery_manager,
     housekeeper::{self, init_housekeeper, spawn_housekeeper},
     state::{self, init_state_manager, spawn_state_manager},
 };
-use smtp::core::SMTP;
+use smtp::{config::scripts::ConfigSieve, core::SMTP};
 use store::{
+    ahash::AHashMap,
     fts::FtsFilter,
     query::{sort::Pagination, Comparator, Filter, ResultSet, SortedResultSet},
     roaring::RoaringBitmap,
@@ -59,26 +67,40 @@ use store::{
 };
 use tokio::sync::mpsc;
 use utils::{
+    acme::AcmeManager,
     config::{Rate, Servers},
     ipc::DeliveryEvent,
+    listener::{limiter::RateLimiter, manager::ListenerManager, tls::Certificate},
     map::ttl_dashmap::{TtlDashMap, TtlMap},
     snowflake::SnowflakeIdGenerator,
     UnwrapFailure,
 };
 
+pub mod activity;
+pub mod annotation;
 pub mod api;
+pub mod archive;
 pub mod auth;
 pub mod blob;
 pub mod changes;
+pub mod cluster;
+pub mod compliance;
+pub mod disposable_alias;
 pub mod email;
 pub mod identity;
+pub mod jobs;
+pub mod label;
 pub mod mailbox;
+pub mod metering;
+pub mod migrate;
+pub mod overlay;
 pub mod principal;
 pub mod push;
 pub mod quota;
 pub mod services;
 pub mod sieve;
 pub mod submission;
+pub mod tag_rule;
 pub mod thread;
 pub mod vacation;
 pub mod websocket;
@@ -98,12 +120,26 @@ pub struct JMAP {
 
     pub rate_limit_auth: DashMap<u32, Arc<AuthenticatedLimiter>>,
     pub rate_limit_unauth: DashMap<IpAddr, Arc<AnonymousLimiter>>,
+    pub rate_limit_endpoint: DashMap<(HttpEndpoint, RateLimitKey), Arc<RateLimiter>>,
+    pub rate_limit_recovery: DashMap<String, Arc<RateLimiter>>,
 
     pub oauth_codes: TtlDashMap<String, Arc<OAuthCode>>,
+    pub step_up: TtlDashMap<String, u32>,
+
+    pub image_proxy_cache: TtlDashMap<String, ProxiedImage>,
+    pub migrations: DashMap<u32, Arc<MigrationJob>>,
+    pub jobs: DashMap<u64, Arc<Job>>,
+    pub mailbox_usage: DashMap<u32, u64>,
 
     pub state_tx: mpsc::Sender<state::Event>,
     pub housekeeper_tx: mpsc::Sender<housekeeper::Event>,
     pub smtp: Arc<SMTP>,
+    pub cluster: Option<Cluster>,
+    pub metering: Option<Metering>,
+    pub overlays: Overlays,
+    pub acme_managers: Vec<Arc<AcmeManager>>,
+    pub certificates: Vec<Arc<Certificate>>,
+    pub listener_manager: Arc<ListenerManager>,
 
     pub sieve_compiler: Compiler,
     pub sieve_runtime: Runtime<()>,
@@ -112,8 +148,10 @@ pub struct JMAP {
 pub struct Config {
     pub default_language: Language,
     pub query_max_results: usize,
+    pub query_max_header_scan: usize,
     pub changes_max_results: usize,
     pub snippet_max_results: usize,
+    pub snippet_allowed_tags: Vec<String>,
 
     pub request_max_size: usize,
     pub request_max_calls: usize,
@@ -121,6 +159,7 @@ pub struct Config {
 
     pub get_max_objects: usize,
     pub set_max_objects: usize,
+    pub update_flags_max_objects: usize,
 
     pub upload_max_size: usize,
     pub upload_max_concurrent: u64,
@@ -131,18 +170,25 @@ pub struct Config {
 
     pub mailbox_max_depth: usize,
     pub mailbox_name_max_len: usize,
+    pub mailbox_usage_reconcile: Duration,
     pub mail_attachments_max_size: usize,
     pub mail_parse_max_items: usize,
     pub mail_max_size: usize,
 
     pub sieve_max_script_name: usize,
     pub sieve_max_scripts: usize,
+    pub sieve_max_duplicate_expiry: u64,
+    pub sieve_max_vacation_period: u64,
+    pub sieve_redirect: crate::sieve::redirect::RedirectPolicy,
 
     pub session_cache_ttl: Duration,
     pub rate_authenticated: Rate,
     pub rate_authenticate_req: Rate,
     pub rate_anonymous: Rate,
+    pub rate_endpoint: Vec<(HttpEndpoint, Rate)>,
     pub rate_use_forwarded: bool,
+    pub rate_download: Rate,
+    pub rate_upload: Rate,
 
     pub event_source_throttle: Duration,
     pub push_max_total: usize,
@@ -168,6 +214,85 @@ pub struct Config {
 
     pub principal_allow_lookups: bool,
 
+    pub image_proxy_enable: bool,
+    pub image_proxy_allow_local: bool,
+    pub image_proxy_max_size: usize,
+    pub image_proxy_timeout: Duration,
+    pub image_proxy_cache_size: usize,
+    pub image_proxy_cache_ttl: Duration,
+
+    pub delivery_dedup_enable: bool,
+
+    pub autoconfig_enable: bool,
+    pub autoconfig_hostname: String,
+    pub autoconfig_services: Vec<ClientService>,
+
+    pub activity_log_enable: bool,
+    pub activity_log_retention: Duration,
+    pub activity_log_max_results: usize,
+
+    pub tombstone_enable: bool,
+    pub tombstone_retention: Duration,
+    pub tombstone_max_per_mailbox: usize,
+
+    pub migration_throttle: Option<Duration>,
+    pub migration_batch_size: usize,
+    pub migration_timeout: Duration,
+
+    pub job_retention: Duration,
+    pub idempotency_enable: bool,
+    pub idempotency_ttl: Duration,
+
+    pub compaction_enable: bool,
+    pub compaction_business_hours: Option<BusinessHours>,
+    pub compaction_throttle: Duration,
+
+    pub label_max_labels: usize,
+    pub label_max_name_length: usize,
+
+    pub tag_filing_enable: bool,
+    pub tag_filing_separator: String,
+    pub tag_filing_auto_create: bool,
+    pub tag_filing_max_rules: usize,
+
+    pub archive_enable: bool,
+    pub archive_folder: String,
+    pub archive_batch_size: usize,
+    pub archive_max_policies: usize,
+    pub archive_min_days: u64,
+
+    pub disposable_alias_enable: bool,
+    pub disposable_alias_max_aliases: usize,
+    pub disposable_alias_local_part_length: usize,
+
+    pub annotation_max_annotations: usize,
+    pub annotation_max_length: usize,
+
+    pub blob_patch_enable: bool,
+    pub blob_patch_max_patches: usize,
+    pub blob_patch_max_size: usize,
+    pub blob_patch_rate: Rate,
+
+    pub recovery_enable: bool,
+    pub recovery_codes: usize,
+    pub recovery_token_expiry: Duration,
+    pub recovery_rate: Rate,
+
+    pub step_up_enable: bool,
+    pub step_up_max_age: Duration,
+    pub step_up_operations: Vec<StepUpOperation>,
+    pub step_up_issuer: String,
+    pub step_up_rate: Rate,
+    pub step_up_secrets: AHashMap<String, Vec<u8>>,
+
+    pub submission_sent_fanout: bool,
+
+    pub metrics_enable: bool,
+    pub metrics_tokens: Vec<MetricsToken>,
+
+    pub compliance_officers: Vec<String>,
+    pub queue_inspectors: Vec<String>,
+
     pub capabilities: BaseCapabilities,
 }
 
@@ -238,13 +363,46 @@ impl JMAP {
                 RandomState::default(),
                 shard_amount,
             ),
+            rate_limit_endpoint: DashMap::with_capacity_and_hasher_and_shard_amount(
+                config
+                    .property("jmap.rate-limit.cache.size")?
+                    .unwrap_or(1024),
+                RandomState::default(),
+                shard_amount,
+            ),
+            rate_limit_recovery: DashMap::with_capacity_and_hasher_and_shard_amount(
+                config
+                    .property("jmap.rate-limit.cache.size")?
+                    .unwrap_or(1024),
+                RandomState::default(),
+                shard_amount,
+            ),
             oauth_codes: TtlDashMap::with_capacity(
                 config.property("oauth.cache.size")?.unwrap_or(128),
                 shard_amount,
             ),
+            step_up: TtlDashMap::with_capacity(
+                config.property("jmap.session.cache.size")?.unwrap_or(100),
+                shard_amount,
+            ),
+            image_proxy_cache: TtlDashMap::with_capacity(
+                config
+                    .property("jmap.image-proxy.cache.size")?
+                    .unwrap_or(1024),
+                shard_amount,
+            ),
+            migrations: DashMap::new(),
+            jobs: DashMap::new(),
+            mailbox_usage: DashMap::new(),
             state_tx,
             housekeeper_tx,
             smtp,
+            cluster: Cluster::parse(config)?,
+            metering: Metering::parse(config)?,
+            overlays: Overlays::parse(config, shard_amount)?,
+            acme_managers: servers.acme_managers.clone(),
+            certificates: servers.monitored_certificates.clone(),
+            listener_manager: servers.listener_manager.clone(),
             sieve_compiler: Compiler::new()
                 .with_max_script_size(
                     config
@@ -296,102 +454,103 @@ impl JMAP {
                         .property("sieve.untrusted.limits.includes")?
                         .unwrap_or(3),
                 ),
-            sieve_runtime: Runtime::new()
-                .with_max_nested_includes(
-                    config
-                        .property("sieve.untrusted.limits.nested-includes")?
-                        .unwrap_or(3),
-                )
-                .with_cpu_limit(
-                    config
-                        .property("sieve.untrusted.limits.cpu")?
-                        .unwrap_or(5000),
-                )
-                .with_max_variable_size(
-                    config
-                        .property("sieve.untrusted.limits.variable-size")?
-                        .unwrap_or(4096),
-                )
-                .with_max_redirects(
-                    config
-                        .property("sieve.untrusted.limits.redirects")?
-                        .unwrap_or(1),
-                )
-                .with_max_received_headers(
-                    config
-                        .property("sieve.untrusted.limits.received-headers")?
-                        .unwrap_or(10),
-                )
-                .with_max_header_size(
-                    config
-                        .property("sieve.untrusted.limits.header-size")?
-                        .unwrap_or(1024),
-                )
-                .with_max_out_messages(
-                    config
-                        .property("sieve.untrusted.limits.outgoing-messages")?
-                        .unwrap_or(3),
-                )
-                .with_default_vacation_expiry(
-                    config
-                        .property::<Duration>("sieve.untrusted.default-expiry.vacation")?
-                        .unwrap_or(Duration::from_secs(30 * 86400))
-                        .as_secs(),
-                )
-                .with_default_duplicate_expiry(
-                    config
-                        .property::<Duration>("sieve.untrusted.default-expiry.duplicate")?
-                        .unwrap_or(Duration::from_secs(7 * 86400))
-                        .as_secs(),
-                )
-                .without_capabilities(
-                    config
-                        .values("sieve.untrusted.disable-capabilities")
-                        .map(|(_, v)| v),
-                )
-                .with_valid_notification_uris({
-                    let values = config
-                        .values("sieve.untrusted.notification-uris")
-                        .map(|(_, v)| v.to_string())
-                        .collect::<Vec<_>>();
-                    if !values.is_empty() {
-                        values
-                    } else {
-                        vec!["mailto".to_string()]
-                    }
-                })
-                .with_protected_headers({
-                    let values = config
-                        .values("sieve.untrusted.protected-headers")
-                        .map(|(_, v)| v.to_string())
-                        .collect::<Vec<_>>();
-                    if !values.is_empty() {
-                        values
-                    } else {
-                        vec![
-                            "Original-Subject".to_string(),
-                            "Original-From".to_string(),
-                            "Received".to_string(),
-                            "Auto-Submitted".to_string(),
-                        ]
-                    }
-                })
-                .with_vacation_default_subject(
-                    config
-                        .value("sieve.untrusted.vacation.default-subject")
-                        .unwrap_or("Automated reply")
-                        .to_string(),
-                )
-                .with_vacation_subject_prefix(
-                    config
-                        .value("sieve.untrusted.vacation.subject-prefix")
-                        .unwrap_or("Auto: ")
-                        .to_string(),
-                )
-                .with_env_variable("name", "Stalwart JMAP")
-                .with_env_variable("version", env!("CARGO_PKG_VERSION"))
-                .with_env_variable("location", "MS")
-                .with_env_variable("phase", "during"),
+            sieve_runtime: config.parse_sieve_environment(
+                Runtime::new()
+                    .with_max_nested_includes(
+                        config
+                            .property("sieve.untrusted.limits.nested-includes")?
+                            .unwrap_or(3),
+                    )
+                    .with_cpu_limit(
+                        config
+                            .property("sieve.untrusted.limits.cpu")?
+                            .unwrap_or(5000),
+                    )
+                    .with_max_variable_size(
+                        config
+                            .property("sieve.untrusted.limits.variable-size")?
+                            .unwrap_or(4096),
+                    )
+                    .with_max_redirects(
+                        config
+                            .property("sieve.untrusted.limits.redirects")?
+                            .unwrap_or(1),
+                    )
+                    .with_max_received_headers(
+                        config
+                            .property("sieve.untrusted.limits.received-headers")?
+                            .unwrap_or(10),
+                    )
+                    .with_max_header_size(
+                        config
+                            .property("sieve.untrusted.limits.header-size")?
+                            .unwrap_or(1024),
+                    )
+                    .with_max_out_messages(
+                        config
+                            .property("sieve.untrusted.limits.outgoing-messages")?
+                            .unwrap_or(3),
+                    )
+                    .with_default_vacation_expiry(
+                        config
+                            .property::<Duration>("sieve.untrusted.default-expiry.vacation")?
+                            .unwrap_or(Duration::from_secs(30 * 86400))
+                            .as_secs(),
+                    )
+                    .with_default_duplicate_expiry(
+                        config
+                            .property::<Duration>("sieve.untrusted.default-expiry.duplicate")?
+                            .unwrap_or(Duration::from_secs(7 * 86400))
+                            .as_secs(),
+                    )
+                    .without_capabilities(
+                        config
+                            .values("sieve.untrusted.disable-capabilities")
+                            .map(|(_, v)| v),
+                    )
+                    .with_valid_notification_uris({
+                        let values = config
+                            .values("sieve.untrusted.notification-uris")
+                            .map(|(_, v)| v.to_string())
+                            .collect::<Vec<_>>();
+                        if !values.is_empty() {
+                            values
+                        } else {
+                            vec!["mailto".to_string()]
+                        }
+                    })
+                    .with_protected_headers({
+                        let values = config
+                            .values("sieve.untrusted.protected-headers")
+                            .map(|(_, v)| v.to_string())
+                            .collect::<Vec<_>>();
+                        if !values.is_empty() {
+                            values
+                        } else {
+                            vec![
+                                "Original-Subject".to_string(),
+                                "Original-From".to_string(),
+                                "Received".to_string(),
+                                "Auto-Submitted".to_string(),
+                            ]
+                        }
+                    })
+                    .with_vacation_default_subject(
+                        config
+                            .value("sieve.untrusted.vacation.default-subject")
+                            .unwrap_or("Automated reply")
+                            .to_string(),
+                    )
+                    .with_vacation_subject_prefix(
+                        config
+                            .value("sieve.untrusted.vacation.subject-prefix")
+                            .unwrap_or("Auto: ")
+                            .to_string(),
+                    ),
+                "sieve.untrusted",
+                "Stalwart JMAP",
+                "MS",
+            )?,
         });
 
         // Spawn delivery manager
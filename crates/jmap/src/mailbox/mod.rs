@@ -32,6 +32,7 @@ use utils::codec::leb128::{Leb128Iterator, Leb128Vec};
 pub mod get;
 pub mod query;
 pub mod set;
+pub mod usage;
 
 pub const INBOX_ID: u32 = 0;
 pub const TRASH_ID: u32 = 1;
@@ -359,6 +359,13 @@ impl JMAP {
             .await?
         {
             if remove_emails {
+                // Messages under legal hold cannot be removed
+                let legal_hold = self.get_legal_hold(account_id).await?;
+                if legal_hold.is_active() && message_ids.iter().any(|id| legal_hold.is_held(id)) {
+                    return Ok(Err(SetError::forbidden()
+                        .with_description("Mailbox contains messages under legal hold.")));
+                }
+
                 // Flag removal for state change notification
                 did_remove_emails = true;
 
@@ -440,8 +447,12 @@ impl JMAP {
                             }
                         } else {
                             // Delete message
-                            if let Ok(mut change) =
-                                self.email_delete(account_id, message_id).await?
+                            if changes.change_id == u64::MAX {
+                                changes.change_id = self.assign_change_id(account_id).await?;
+                            }
+                            if let Ok(mut change) = self
+                                .email_delete(account_id, message_id, changes.change_id)
+                                .await?
                             {
                                 change.changes.remove(&(Collection::Mailbox as u8));
                                 changes.merge(change);
@@ -496,6 +507,8 @@ impl JMAP {
                 .delete_document(document_id)
                 .value(Property::EmailIds, (), F_VALUE | F_CLEAR)
                 .custom(ObjectIndexBuilder::new(SCHEMA).with_current(mailbox));
+            self.mailbox_usage_reset(&mut batch, account_id, document_id)
+                .await?;
 
             match self.store.write(batch.build()).await {
                 Ok(_) => {
@@ -0,0 +1,336 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use ahash::AHashMap;
+use jmap_proto::{
+    error::method::MethodError,
+    method::mailbox_usage::{GetMailboxUsageRequest, GetMailboxUsageResponse, MailboxUsage},
+    object::Object,
+    types::{collection::Collection, date::UTCDate, id::Id, property::Property, value::Value},
+};
+use store::{
+    roaring::RoaringBitmap,
+    write::{key::DeserializeBigEndian, now, BatchBuilder, ValueClass},
+    Deserialize, IndexKeyPrefix, IterateParams, ValueKey, U32_LEN,
+};
+
+use crate::{
+    email::{metadata::MessageMetadata, set::TagManager},
+    Bincode, JMAP,
+};
+
+use super::UidMailbox;
+
+pub trait MailboxUsageBuilder {
+    fn mailbox_usage(
+        &mut self,
+        mailbox_ids: impl IntoIterator<Item = u32>,
+        emails: i64,
+        size: i64,
+    ) -> &mut Self;
+}
+
+impl MailboxUsageBuilder for BatchBuilder {
+    fn mailbox_usage(
+        &mut self,
+        mailbox_ids: impl IntoIterator<Item = u32>,
+        emails: i64,
+        size: i64,
+    ) -> &mut Self {
+        for mailbox_id in mailbox_ids {
+            self.add(
+                ValueClass::MailboxUsage {
+                    mailbox_id,
+                    field: Property::TotalEmails.into(),
+                },
+                emails,
+            )
+            .add(
+                ValueClass::MailboxUsage {
+                    mailbox_id,
+                    field: Property::Size.into(),
+                },
+                size,
+            );
+        }
+        self
+    }
+}
+
+impl JMAP {
+    pub async fn mailbox_usage_get(
+        &self,
+        request: GetMailboxUsageRequest,
+    ) -> Result<GetMailboxUsageResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let mailbox_ids = self.mailbox_get_or_create(account_id).await?;
+
+        // Counters are maintained as messages are ingested, moved and expunged,
+        // a full recount is only done periodically to correct any drift.
+        let max_staleness = self.config.mailbox_usage_reconcile.as_secs();
+        let last_reconcile = self.mailbox_usage.get(&account_id).map(|v| *v);
+        let reconciled_at = match last_reconcile {
+            Some(reconciled_at) if reconciled_at + max_staleness > now() => reconciled_at,
+            _ => {
+                self.mailbox_usage_reconcile(account_id, &mailbox_ids)
+                    .await?;
+                let reconciled_at = now();
+                self.mailbox_usage.insert(account_id, reconciled_at);
+                reconciled_at
+            }
+        };
+
+        // Obtain counters and parent of each mailbox
+        let mut mailboxes = AHashMap::with_capacity(mailbox_ids.len() as usize);
+        for mailbox_id in &mailbox_ids {
+            let parent_id = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::Mailbox,
+                    mailbox_id,
+                    Property::Value,
+                )
+                .await?
+                .and_then(
+                    |mut mailbox| match mailbox.properties.remove(&Property::ParentId) {
+                        Some(Value::Id(parent_id)) if parent_id.document_id() > 0 => {
+                            Some(parent_id.document_id() - 1)
+                        }
+                        _ => None,
+                    },
+                );
+            let (emails, size) = self.mailbox_usage_counters(account_id, mailbox_id).await?;
+            mailboxes.insert(mailbox_id, (parent_id, emails, size));
+        }
+
+        // Aggregate counters bottom-up
+        let mut totals = AHashMap::with_capacity(mailboxes.len());
+        for (&mailbox_id, &(parent_id, emails, size)) in &mailboxes {
+            let total = totals.entry(mailbox_id).or_insert((0u64, 0u64));
+            total.0 += emails;
+            total.1 += size;
+
+            let mut parent_id = parent_id;
+            let mut depth = 0;
+            while let Some(ancestor_id) =
+                parent_id.filter(|_| depth < self.config.mailbox_max_depth)
+            {
+                let total = totals.entry(ancestor_id).or_insert((0u64, 0u64));
+                total.0 += emails;
+                total.1 += size;
+                parent_id = mailboxes
+                    .get(&ancestor_id)
+                    .and_then(|(parent_id, _, _)| *parent_id);
+                depth += 1;
+            }
+        }
+
+        Ok(GetMailboxUsageResponse {
+            account_id: request.account_id,
+            reconciled_at: UTCDate::from_timestamp(reconciled_at as i64),
+            max_staleness,
+            list: mailbox_ids
+                .iter()
+                .filter_map(|mailbox_id| {
+                    let (parent_id, emails, size) = mailboxes.get(&mailbox_id)?;
+                    let (total_emails, total_size) =
+                        totals.get(&mailbox_id).copied().unwrap_or_default();
+                    Some(MailboxUsage {
+                        id: Id::from(mailbox_id),
+                        parent_id: parent_id.map(Id::from),
+                        emails: *emails,
+                        size: *size,
+                        total_emails,
+                        total_size,
+                    })
+                })
+                .collect(),
+        })
+    }
+
+    pub async fn mailbox_usage_update(
+        &self,
+        batch: &mut BatchBuilder,
+        account_id: u32,
+        document_id: u32,
+        mailboxes: &TagManager<UidMailbox>,
+    ) -> Result<(), MethodError> {
+        if mailboxes.has_changes() {
+            let size = self
+                .get_property::<Bincode<MessageMetadata>>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::BodyStructure,
+                )
+                .await?
+                .map_or(0, |metadata| metadata.inner.size as i64);
+            batch
+                .mailbox_usage(mailboxes.added().iter().map(|m| m.mailbox_id), 1, size)
+                .mailbox_usage(mailboxes.removed().iter().map(|m| m.mailbox_id), -1, -size);
+        }
+
+        Ok(())
+    }
+
+    pub async fn mailbox_usage_reset(
+        &self,
+        batch: &mut BatchBuilder,
+        account_id: u32,
+        mailbox_id: u32,
+    ) -> Result<(), MethodError> {
+        let (emails, size) = self.mailbox_usage_counters(account_id, mailbox_id).await?;
+        if emails != 0 || size != 0 {
+            batch.mailbox_usage([mailbox_id], -(emails as i64), -(size as i64));
+        }
+        Ok(())
+    }
+
+    async fn mailbox_usage_counters(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+    ) -> Result<(u64, u64), MethodError> {
+        let mut counters = [0u64; 2];
+        for (counter, field) in counters
+            .iter_mut()
+            .zip([Property::TotalEmails, Property::Size])
+        {
+            *counter = self
+                .store
+                .get_counter(ValueKey {
+                    account_id,
+                    collection: Collection::Mailbox.into(),
+                    document_id: mailbox_id,
+                    class: ValueClass::MailboxUsage {
+                        mailbox_id,
+                        field: field.into(),
+                    },
+                })
+                .await
+                .map_err(|err| {
+                    tracing::error!(
+                        event = "error",
+                        context = "mailbox_usage",
+                        account_id = account_id,
+                        mailbox_id = mailbox_id,
+                        error = ?err,
+                        "Failed to obtain mailbox usage counter."
+                    );
+                    MethodError::ServerPartialFail
+                })?
+                .max(0) as u64;
+        }
+
+        Ok((counters[0], counters[1]))
+    }
+
+    async fn mailbox_usage_reconcile(
+        &self,
+        account_id: u32,
+        mailbox_ids: &RoaringBitmap,
+    ) -> Result<(), MethodError> {
+        // Obtain the size of every message in the account
+        let mut sizes = AHashMap::new();
+        self.store
+            .iterate(
+                IterateParams::new(
+                    IndexKeyPrefix {
+                        account_id,
+                        collection: Collection::Email.into(),
+                        field: Property::Size.into(),
+                    },
+                    IndexKeyPrefix {
+                        account_id,
+                        collection: Collection::Email.into(),
+                        field: u8::from(Property::Size) + 1,
+                    },
+                )
+                .ascending()
+                .no_values(),
+                |key, _| {
+                    let id_pos = key.len() - U32_LEN;
+                    let document_id = key.deserialize_be_u32(id_pos)?;
+                    let size = key
+                        .get(IndexKeyPrefix::len()..id_pos)
+                        .ok_or_else(|| {
+                            store::Error::InternalError("Invalid key length".to_string())
+                        })
+                        .and_then(u32::deserialize)?;
+                    sizes.insert(document_id, size as u64);
+                    Ok(true)
+                },
+            )
+            .await
+            .map_err(|err| {
+                tracing::error!(
+                    event = "error",
+                    context = "mailbox_usage",
+                    account_id = account_id,
+                    error = ?err,
+                    "Failed to obtain message sizes."
+                );
+                MethodError::ServerPartialFail
+            })?;
+
+        // Adjust counters that drifted from the actual values
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Mailbox);
+        for mailbox_id in mailbox_ids {
+            let (emails, size) = self
+                .get_tag(
+                    account_id,
+                    Collection::Email,
+                    Property::MailboxIds,
+                    mailbox_id,
+                )
+                .await?
+                .map_or((0, 0), |message_ids| {
+                    (
+                        message_ids.len(),
+                        message_ids
+                            .iter()
+                            .map(|id| sizes.get(&id).copied().unwrap_or(0))
+                            .sum::<u64>(),
+                    )
+                });
+            let (current_emails, current_size) =
+                self.mailbox_usage_counters(account_id, mailbox_id).await?;
+            if emails != current_emails || size != current_size {
+                batch.update_document(mailbox_id).mailbox_usage(
+                    [mailbox_id],
+                    emails as i64 - current_emails as i64,
+                    size as i64 - current_size as i64,
+                );
+            }
+        }
+
+        if !batch.is_empty() {
+            self.write_batch(batch).await?;
+        }
+
+        Ok(())
+    }
+}
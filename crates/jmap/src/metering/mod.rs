@@ -0,0 +1,384 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use dashmap::DashMap;
+use directory::QueryBy;
+use jmap_proto::types::collection::Collection;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use store::{
+    write::{key::DeserializeBigEndian, now, BatchBuilder, ValueClass},
+    Deserialize, IterateParams, ValueKey, U64_LEN,
+};
+use utils::config::Config;
+
+use crate::{Bincode, JMAP};
+
+pub const USAGE_RECORD_VERSION: u32 = 1;
+
+pub struct Metering {
+    pub interval: Duration,
+    pub retention: Duration,
+    webhook: Option<Webhook>,
+    counters: DashMap<u32, UsageCounters>,
+    period_start: AtomicU64,
+}
+
+struct Webhook {
+    url: String,
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct UsageCounters {
+    messages_received: u64,
+    bytes_received: u64,
+    messages_sent: u64,
+    bytes_sent: u64,
+    api_calls: u64,
+}
+
+/// Usage totals of an account over a metering period. The serialized form is
+/// part of the public export schema, new fields must be added in a backwards
+/// compatible way and `version` bumped on breaking changes.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageRecord {
+    pub version: u32,
+    pub account_id: u32,
+    pub account: String,
+    pub tenant: Option<String>,
+    pub period_start: u64,
+    pub period_end: u64,
+    pub messages_received: u64,
+    pub bytes_received: u64,
+    pub messages_sent: u64,
+    pub bytes_sent: u64,
+    pub api_calls: u64,
+    pub storage_bytes: u64,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct UsageReport<'x> {
+    #[serde(rename = "type")]
+    typ: &'static str,
+    records: &'x [UsageRecord],
+}
+
+impl Metering {
+    pub fn parse(config: &Config) -> Result<Option<Self>, String> {
+        if !config.property_or_static("jmap.metering.enable", "false")? {
+            return Ok(None);
+        }
+
+        let webhook = if let Some(url) = config.value("jmap.metering.webhook.url") {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            for (key, value) in config.values("jmap.metering.webhook.headers") {
+                let (name, value) = value
+                    .split_once(':')
+                    .and_then(|(name, value)| {
+                        Some((
+                            HeaderName::from_bytes(name.trim().as_bytes()).ok()?,
+                            HeaderValue::from_str(value.trim()).ok()?,
+                        ))
+                    })
+                    .ok_or_else(|| format!("Invalid header found in property {key:?}."))?;
+                headers.insert(name, value);
+            }
+
+            Some(Webhook {
+                url: url.to_string(),
+                client: reqwest::Client::builder()
+                    .timeout(config.property_or_static("jmap.metering.webhook.timeout", "30s")?)
+                    .default_headers(headers)
+                    .build()
+                    .map_err(|err| format!("Failed to create webhook client: {err}"))?,
+            })
+        } else {
+            None
+        };
+
+        Ok(Some(Metering {
+            interval: config.property_or_static("jmap.metering.interval", "1h")?,
+            retention: config.property_or_static("jmap.metering.retention", "400d")?,
+            webhook,
+            counters: DashMap::new(),
+            period_start: AtomicU64::new(now()),
+        }))
+    }
+
+    fn update(&self, account_id: u32, f: impl FnOnce(&mut UsageCounters)) {
+        f(&mut self.counters.entry(account_id).or_default());
+    }
+}
+
+impl JMAP {
+    pub fn meter_received(&self, account_id: u32, size: usize) {
+        if let Some(metering) = &self.metering {
+            metering.update(account_id, |counters| {
+                counters.messages_received += 1;
+                counters.bytes_received += size as u64;
+            });
+        }
+    }
+
+    pub fn meter_sent(&self, account_id: u32, size: usize) {
+        if let Some(metering) = &self.metering {
+            metering.update(account_id, |counters| {
+                counters.messages_sent += 1;
+                counters.bytes_sent += size as u64;
+            });
+        }
+    }
+
+    pub fn meter_api_calls(&self, account_id: u32, calls: usize) {
+        if let Some(metering) = &self.metering {
+            metering.update(account_id, |counters| {
+                counters.api_calls += calls as u64;
+            });
+        }
+    }
+
+    /// Closes the current metering period, persisting one usage record per
+    /// account and delivering the records to the configured webhook.
+    pub async fn flush_usage(&self) -> Vec<UsageRecord> {
+        let metering = if let Some(metering) = &self.metering {
+            metering
+        } else {
+            return vec![];
+        };
+        let period_end = now();
+        let period_start = metering.period_start.swap(period_end, Ordering::Relaxed);
+
+        let account_ids = match self.get_document_ids(u32::MAX, Collection::Principal).await {
+            Ok(account_ids) => account_ids.unwrap_or_default(),
+            Err(_) => {
+                return vec![];
+            }
+        };
+
+        let mut records = Vec::with_capacity(account_ids.len() as usize);
+        let mut batch = BatchBuilder::new();
+        for account_id in account_ids {
+            let counters = metering
+                .counters
+                .remove(&account_id)
+                .map(|(_, counters)| counters)
+                .unwrap_or_default();
+            let principal = match self.directory.query(QueryBy::Id(account_id), false).await {
+                Ok(Some(principal)) => principal,
+                Ok(None) => continue,
+                Err(err) => {
+                    tracing::warn!(
+                        context = "metering",
+                        event = "error",
+                        account_id = account_id,
+                        reason = ?err,
+                        "Failed to obtain principal."
+                    );
+                    continue;
+                }
+            };
+            let tenant = principal
+                .emails
+                .first()
+                .and_then(|email| email.rsplit_once('@'))
+                .map(|(_, domain)| domain.to_string())
+                .or_else(|| {
+                    principal
+                        .name
+                        .rsplit_once('@')
+                        .map(|(_, domain)| domain.to_string())
+                });
+            let record = UsageRecord {
+                version: USAGE_RECORD_VERSION,
+                account_id,
+                account: principal.name,
+                tenant,
+                period_start,
+                period_end,
+                messages_received: counters.messages_received,
+                bytes_received: counters.bytes_received,
+                messages_sent: counters.messages_sent,
+                bytes_sent: counters.bytes_sent,
+                api_calls: counters.api_calls,
+                storage_bytes: self.get_used_quota(account_id).await.unwrap_or(0).max(0) as u64,
+            };
+
+            batch.with_account_id(account_id).set(
+                ValueClass::Usage(period_start),
+                Bincode::new(record.clone()),
+            );
+            if batch.ops.len() >= 1000 {
+                self.write_usage(std::mem::take(&mut batch)).await;
+            }
+            records.push(record);
+        }
+        if !batch.is_empty() {
+            self.write_usage(batch).await;
+        }
+
+        if let (Some(webhook), false) = (&metering.webhook, records.is_empty()) {
+            match webhook
+                .client
+                .post(&webhook.url)
+                .body(
+                    serde_json::to_string(&UsageReport {
+                        typ: "usage",
+                        records: &records,
+                    })
+                    .unwrap_or_default(),
+                )
+                .send()
+                .await
+            {
+                Ok(response) if response.status().is_success() => (),
+                Ok(response) => {
+                    tracing::warn!(
+                        context = "metering",
+                        event = "error",
+                        url = webhook.url,
+                        status = response.status().as_u16(),
+                        "Usage webhook returned an unexpected status."
+                    );
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        context = "metering",
+                        event = "error",
+                        url = webhook.url,
+                        reason = %err,
+                        "Failed to deliver usage records to webhook."
+                    );
+                }
+            }
+        }
+
+        records
+    }
+
+    async fn write_usage(&self, batch: BatchBuilder) {
+        if let Err(err) = self.store.write(batch.build()).await {
+            tracing::error!(
+                context = "metering",
+                event = "error",
+                reason = ?err,
+                "Failed to write usage records."
+            );
+        }
+    }
+
+    pub async fn usage_query(
+        &self,
+        account_id: Option<u32>,
+        since: u64,
+        until: u64,
+        limit: usize,
+    ) -> store::Result<Vec<UsageRecord>> {
+        let from_key = ValueKey {
+            account_id: 0,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Usage(since),
+        };
+        let to_key = ValueKey {
+            account_id: u32::MAX,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Usage(until),
+        };
+
+        let mut records = Vec::new();
+        self.store
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending(),
+                |key, value| {
+                    if account_id.map_or(true, |account_id| {
+                        key.deserialize_be_u32(1 + U64_LEN)
+                            .map_or(false, |id| id == account_id)
+                    }) {
+                        records.push(Bincode::<UsageRecord>::deserialize(value)?.inner);
+                    }
+
+                    Ok(limit == 0 || records.len() < limit)
+                },
+            )
+            .await?;
+
+        Ok(records)
+    }
+
+    pub async fn purge_usage(&self) {
+        let cutoff = if let Some(metering) = &self.metering {
+            now().saturating_sub(metering.retention.as_secs())
+        } else {
+            return;
+        };
+        let from_key = ValueKey {
+            account_id: 0,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Usage(0),
+        };
+        let to_key = ValueKey {
+            account_id: u32::MAX,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Usage(cutoff),
+        };
+
+        let mut batch = BatchBuilder::new();
+        let result = self
+            .store
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending().no_values(),
+                |key, _| {
+                    let period = key.deserialize_be_u64(1)?;
+                    if period < cutoff {
+                        batch
+                            .with_account_id(key.deserialize_be_u32(1 + U64_LEN)?)
+                            .clear(ValueClass::Usage(period));
+                    }
+
+                    Ok(true)
+                },
+            )
+            .await;
+
+        if let Err(err) = result {
+            tracing::error!(
+                context = "metering",
+                event = "error",
+                reason = ?err,
+                "Failed to iterate over usage records."
+            );
+        } else if !batch.is_empty() {
+            self.write_usage(batch).await;
+        }
+    }
+}
@@ -0,0 +1,619 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::{collections::VecDeque, time::Duration};
+
+use base64::{engine::general_purpose, Engine};
+use mail_send::smtp::tls::build_tls_connector;
+use rustls_pki_types::ServerName;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+pub trait ImapStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ImapStream for T {}
+
+pub struct ImapClient {
+    stream: BufReader<Box<dyn ImapStream>>,
+    timeout: Duration,
+    tag: u32,
+    capabilities: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum ImapError {
+    Io(std::io::Error),
+    Timeout,
+    Tls(String),
+    Protocol(String),
+    Rejected(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Atom(String),
+    Bytes(Vec<u8>),
+    List(Vec<Token>),
+    Nil,
+}
+
+#[derive(Debug, Clone)]
+pub struct RemoteFolder {
+    pub name: String,
+    pub raw_name: String,
+    pub attributes: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct RemoteMessage {
+    pub uid: u32,
+    pub flags: Vec<String>,
+    pub internal_date: Option<u64>,
+    pub contents: Vec<u8>,
+}
+
+struct Response {
+    text: Vec<u8>,
+    literals: VecDeque<Vec<u8>>,
+}
+
+pub type Result<T> = std::result::Result<T, ImapError>;
+
+impl ImapClient {
+    pub async fn connect(
+        host: &str,
+        port: u16,
+        tls: bool,
+        allow_invalid_certs: bool,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let stream = tokio::time::timeout(timeout, TcpStream::connect((host, port)))
+            .await
+            .map_err(|_| ImapError::Timeout)??;
+        let stream: Box<dyn ImapStream> = if tls {
+            Box::new(
+                tokio::time::timeout(
+                    timeout,
+                    build_tls_connector(allow_invalid_certs).connect(
+                        ServerName::try_from(host.to_string())
+                            .map_err(|_| ImapError::Tls("Invalid TLS name".to_string()))?,
+                        stream,
+                    ),
+                )
+                .await
+                .map_err(|_| ImapError::Timeout)?
+                .map_err(|err| ImapError::Tls(err.to_string()))?,
+            )
+        } else {
+            Box::new(stream)
+        };
+
+        let mut client = ImapClient {
+            stream: BufReader::new(stream),
+            timeout,
+            tag: 0,
+            capabilities: Vec::new(),
+        };
+
+        // Expect greeting
+        let greeting = tokio::time::timeout(timeout, client.read_response())
+            .await
+            .map_err(|_| ImapError::Timeout)??;
+        if !greeting.text.starts_with(b"* OK") && !greeting.text.starts_with(b"* PREAUTH") {
+            return Err(ImapError::Protocol(format!(
+                "Unexpected greeting: {}",
+                String::from_utf8_lossy(&greeting.text)
+            )));
+        }
+        client.capability().await?;
+
+        Ok(client)
+    }
+
+    pub async fn login(&mut self, username: &str, secret: &str) -> Result<()> {
+        if self.has_capability("AUTH=PLAIN") && self.has_capability("SASL-IR") {
+            self.command(&format!(
+                "AUTHENTICATE PLAIN {}",
+                general_purpose::STANDARD.encode(format!("\0{username}\0{secret}"))
+            ))
+            .await?;
+        } else if self.has_capability("LOGINDISABLED") {
+            return Err(ImapError::Rejected(
+                "Remote server does not allow LOGIN, try enabling TLS".to_string(),
+            ));
+        } else {
+            self.command(&format!("LOGIN {} {}", quote(username)?, quote(secret)?))
+                .await?;
+        }
+
+        // Capabilities may change after authentication
+        self.capability().await
+    }
+
+    pub async fn list(&mut self) -> Result<Vec<RemoteFolder>> {
+        let command = if self.has_capability("SPECIAL-USE") {
+            "LIST \"\" \"*\" RETURN (SPECIAL-USE)"
+        } else {
+            "LIST \"\" \"*\""
+        };
+        let mut folders = Vec::new();
+
+        for response in self.command(command).await? {
+            let mut tokens = response.into_iter();
+            if !matches!(tokens.next(), Some(Token::Atom(cmd)) if cmd.eq_ignore_ascii_case("LIST"))
+            {
+                continue;
+            }
+            let attributes = match tokens.next() {
+                Some(Token::List(attributes)) => attributes
+                    .into_iter()
+                    .filter_map(|attr| match attr {
+                        Token::Atom(attr) => Some(attr.to_ascii_lowercase()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>(),
+                _ => continue,
+            };
+            let delimiter = match tokens.next() {
+                Some(Token::Bytes(delimiter)) => delimiter.first().copied(),
+                Some(Token::Atom(delimiter)) => delimiter.as_bytes().first().copied(),
+                _ => None,
+            };
+            let raw_name = match tokens.next() {
+                Some(Token::Bytes(name)) => name,
+                Some(Token::Atom(name)) => name.into_bytes(),
+                _ => continue,
+            };
+
+            if attributes
+                .iter()
+                .any(|attr| attr == "\\noselect" || attr == "\\nonexistent")
+            {
+                continue;
+            }
+
+            let name = imap_proto::utf7::utf7_decode(&raw_name)
+                .unwrap_or_else(|| String::from_utf8_lossy(&raw_name).into_owned());
+            let name = match delimiter {
+                Some(delimiter) if delimiter != b'/' => name
+                    .split(delimiter as char)
+                    .map(|part| part.replace('/', "_"))
+                    .collect::<Vec<_>>()
+                    .join("/"),
+                _ => name,
+            };
+
+            folders.push(RemoteFolder {
+                name,
+                raw_name: String::from_utf8_lossy(&raw_name).into_owned(),
+                attributes,
+            });
+        }
+
+        Ok(folders)
+    }
+
+    /// Opens the folder in read-only mode and returns its UIDVALIDITY and message count.
+    pub async fn examine(&mut self, folder: &str) -> Result<(u32, u32)> {
+        let mut uid_validity = 0;
+        let mut exists = 0;
+
+        for response in self.command(&format!("EXAMINE {}", quote(folder)?)).await? {
+            match response.as_slice() {
+                [Token::Atom(ok), Token::Atom(code), ..]
+                    if ok.eq_ignore_ascii_case("OK")
+                        && code.to_ascii_uppercase().starts_with("[UIDVALIDITY ") =>
+                {
+                    uid_validity = code
+                        .trim_start_matches('[')
+                        .trim_end_matches(']')
+                        .split_ascii_whitespace()
+                        .nth(1)
+                        .and_then(|v| v.parse().ok())
+                        .unwrap_or_default();
+                }
+                [Token::Atom(num), Token::Atom(cmd)] if cmd.eq_ignore_ascii_case("EXISTS") => {
+                    exists = num.parse().unwrap_or_default();
+                }
+                _ => (),
+            }
+        }
+
+        Ok((uid_validity, exists))
+    }
+
+    pub async fn uid_search(&mut self, from_uid: u32) -> Result<Vec<u32>> {
+        let mut uids = Vec::new();
+        for response in self
+            .command(&format!("UID SEARCH UID {from_uid}:*"))
+            .await?
+        {
+            let mut tokens = response.into_iter();
+            if matches!(tokens.next(), Some(Token::Atom(cmd)) if cmd.eq_ignore_ascii_case("SEARCH"))
+            {
+                uids.extend(tokens.filter_map(|token| match token {
+                    Token::Atom(uid) => uid.parse::<u32>().ok().filter(|uid| *uid >= from_uid),
+                    _ => None,
+                }));
+            }
+        }
+        uids.sort_unstable();
+        uids.dedup();
+
+        Ok(uids)
+    }
+
+    pub async fn uid_fetch(&mut self, uids: &[u32]) -> Result<Vec<RemoteMessage>> {
+        let mut messages = Vec::with_capacity(uids.len());
+        let uid_set = uids
+            .iter()
+            .map(|uid| uid.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        for response in self
+            .command(&format!(
+                "UID FETCH {uid_set} (UID FLAGS INTERNALDATE BODY.PEEK[])"
+            ))
+            .await?
+        {
+            let items = match response.as_slice() {
+                [Token::Atom(_), Token::Atom(cmd), Token::List(items)]
+                    if cmd.eq_ignore_ascii_case("FETCH") =>
+                {
+                    items
+                }
+                _ => continue,
+            };
+
+            let mut message = RemoteMessage {
+                uid: 0,
+                flags: Vec::new(),
+                internal_date: None,
+                contents: Vec::new(),
+            };
+            let mut has_body = false;
+            for pair in items.chunks(2) {
+                match pair {
+                    [Token::Atom(name), Token::Atom(uid)] if name.eq_ignore_ascii_case("UID") => {
+                        message.uid = uid.parse().unwrap_or_default();
+                    }
+                    [Token::Atom(name), Token::List(flags)]
+                        if name.eq_ignore_ascii_case("FLAGS") =>
+                    {
+                        message.flags = flags
+                            .iter()
+                            .filter_map(|flag| match flag {
+                                Token::Atom(flag) => Some(flag.clone()),
+                                _ => None,
+                            })
+                            .collect();
+                    }
+                    [Token::Atom(name), Token::Bytes(date)]
+                        if name.eq_ignore_ascii_case("INTERNALDATE") =>
+                    {
+                        message.internal_date = chrono::DateTime::parse_from_str(
+                            String::from_utf8_lossy(date).trim(),
+                            "%d-%b-%Y %H:%M:%S %z",
+                        )
+                        .ok()
+                        .map(|date| date.timestamp().max(0) as u64);
+                    }
+                    [Token::Atom(name), Token::Bytes(contents)]
+                        if name.eq_ignore_ascii_case("BODY[]") =>
+                    {
+                        message.contents = contents.clone();
+                        has_body = true;
+                    }
+                    _ => (),
+                }
+            }
+
+            if message.uid != 0 && has_body {
+                messages.push(message);
+            }
+        }
+
+        Ok(messages)
+    }
+
+    pub async fn logout(&mut self) {
+        let _ = self.command("LOGOUT").await;
+    }
+
+    async fn capability(&mut self) -> Result<()> {
+        for response in self.command("CAPABILITY").await? {
+            let mut tokens = response.into_iter();
+            if matches!(tokens.next(), Some(Token::Atom(cmd)) if cmd.eq_ignore_ascii_case("CAPABILITY"))
+            {
+                self.capabilities = tokens
+                    .filter_map(|token| match token {
+                        Token::Atom(capability) => Some(capability.to_ascii_uppercase()),
+                        _ => None,
+                    })
+                    .collect();
+            }
+        }
+        Ok(())
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+
+    /// Sends a command and returns its untagged responses.
+    async fn command(&mut self, command: &str) -> Result<Vec<Vec<Token>>> {
+        self.tag += 1;
+        let tag = format!("M{}", self.tag);
+        let timeout = self.timeout;
+
+        tokio::time::timeout(timeout, async {
+            let stream = self.stream.get_mut();
+            stream
+                .write_all(format!("{tag} {command}\r\n").as_bytes())
+                .await?;
+            stream.flush().await?;
+
+            let mut responses = Vec::new();
+            loop {
+                let mut response = self.read_response().await?;
+                if let Some(status) = response
+                    .text
+                    .strip_prefix(tag.as_bytes())
+                    .and_then(|status| status.strip_prefix(b" "))
+                {
+                    return if status.starts_with(b"OK") || status.starts_with(b"ok") {
+                        Ok(responses)
+                    } else {
+                        Err(ImapError::Rejected(
+                            String::from_utf8_lossy(status).into_owned(),
+                        ))
+                    };
+                } else if let Some(text) = response.text.strip_prefix(b"* ") {
+                    responses.push(tokenize(text, &mut response.literals));
+                } else if response.text.starts_with(b"+") {
+                    return Err(ImapError::Protocol(
+                        "Unexpected continuation request".to_string(),
+                    ));
+                }
+            }
+        })
+        .await
+        .map_err(|_| ImapError::Timeout)?
+    }
+
+    async fn read_response(&mut self) -> Result<Response> {
+        let mut response = Response {
+            text: Vec::new(),
+            literals: VecDeque::new(),
+        };
+
+        loop {
+            let mut line = Vec::new();
+            if self.stream.read_until(b'\n', &mut line).await? == 0 {
+                return Err(ImapError::Io(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "Connection closed by remote server",
+                )));
+            }
+            while matches!(line.last(), Some(b'\r' | b'\n')) {
+                line.pop();
+            }
+
+            let literal_size = literal_size(&line);
+            response.text.extend_from_slice(&line);
+            if let Some(size) = literal_size {
+                let mut literal = vec![0u8; size];
+                self.stream.read_exact(&mut literal).await?;
+                response.literals.push_back(literal);
+            } else {
+                return Ok(response);
+            }
+        }
+    }
+}
+
+fn literal_size(line: &[u8]) -> Option<usize> {
+    let line = line.strip_suffix(b"}")?;
+    let start = line.iter().rposition(|&ch| ch == b'{')?;
+    std::str::from_utf8(&line[start + 1..])
+        .ok()?
+        .trim_end_matches('+')
+        .parse()
+        .ok()
+}
+
+fn literal_end(text: &[u8]) -> Option<usize> {
+    let end = text.iter().position(|&ch| ch == b'}')? + 1;
+    literal_size(&text[..end]).map(|_| end)
+}
+
+fn quote(value: &str) -> Result<String> {
+    if value.contains(['\r', '\n', '\0']) {
+        return Err(ImapError::Protocol(
+            "Invalid characters in argument".to_string(),
+        ));
+    }
+    Ok(format!(
+        "\"{}\"",
+        value.replace('\\', "\\\\").replace('"', "\\\"")
+    ))
+}
+
+fn tokenize(text: &[u8], literals: &mut VecDeque<Vec<u8>>) -> Vec<Token> {
+    let mut stack: Vec<Vec<Token>> = vec![Vec::new()];
+    let mut pos = 0;
+
+    while pos < text.len() {
+        match text[pos] {
+            b' ' => {
+                pos += 1;
+            }
+            b'(' => {
+                stack.push(Vec::new());
+                pos += 1;
+            }
+            b')' => {
+                if stack.len() > 1 {
+                    let list = stack.pop().unwrap();
+                    stack.last_mut().unwrap().push(Token::List(list));
+                }
+                pos += 1;
+            }
+            b'"' => {
+                let mut value = Vec::new();
+                pos += 1;
+                while pos < text.len() {
+                    match text[pos] {
+                        b'\\' if pos + 1 < text.len() => {
+                            value.push(text[pos + 1]);
+                            pos += 2;
+                        }
+                        b'"' => {
+                            pos += 1;
+                            break;
+                        }
+                        ch => {
+                            value.push(ch);
+                            pos += 1;
+                        }
+                    }
+                }
+                stack.last_mut().unwrap().push(Token::Bytes(value));
+            }
+            b'{' if literal_end(&text[pos..]).is_some() => {
+                pos += literal_end(&text[pos..]).unwrap();
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .push(Token::Bytes(literals.pop_front().unwrap_or_default()));
+            }
+            _ => {
+                // Atoms may contain bracketed sections, such as "BODY[]" or "[UIDVALIDITY 1]"
+                let start = pos;
+                let mut depth = 0;
+                while pos < text.len() {
+                    match text[pos] {
+                        b'[' => depth += 1,
+                        b']' if depth > 0 => depth -= 1,
+                        b' ' | b'(' | b')' if depth == 0 => break,
+                        _ => (),
+                    }
+                    pos += 1;
+                }
+                let atom = String::from_utf8_lossy(&text[start..pos]).into_owned();
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .push(if atom.eq_ignore_ascii_case("NIL") {
+                        Token::Nil
+                    } else {
+                        Token::Atom(atom)
+                    });
+            }
+        }
+    }
+
+    while stack.len() > 1 {
+        let list = stack.pop().unwrap();
+        stack.last_mut().unwrap().push(Token::List(list));
+    }
+    stack.pop().unwrap()
+}
+
+impl From<std::io::Error> for ImapError {
+    fn from(err: std::io::Error) -> Self {
+        ImapError::Io(err)
+    }
+}
+
+impl std::fmt::Display for ImapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImapError::Io(err) => write!(f, "I/O error: {err}"),
+            ImapError::Timeout => write!(f, "Connection timed out"),
+            ImapError::Tls(err) => write!(f, "TLS error: {err}"),
+            ImapError::Protocol(err) => write!(f, "Protocol error: {err}"),
+            ImapError::Rejected(err) => write!(f, "Command rejected: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::{tokenize, Token};
+
+    #[test]
+    fn tokenize_responses() {
+        let mut literals = VecDeque::from([b"Subject: test\r\n\r\nhello".to_vec()]);
+        assert_eq!(
+            tokenize(
+                b"1 FETCH (UID 42 FLAGS (\\Seen $Forwarded) INTERNALDATE \"17-Jul-1996 02:44:25 -0700\" BODY[] {22})",
+                &mut literals
+            ),
+            vec![
+                Token::Atom("1".to_string()),
+                Token::Atom("FETCH".to_string()),
+                Token::List(vec![
+                    Token::Atom("UID".to_string()),
+                    Token::Atom("42".to_string()),
+                    Token::Atom("FLAGS".to_string()),
+                    Token::List(vec![
+                        Token::Atom("\\Seen".to_string()),
+                        Token::Atom("$Forwarded".to_string()),
+                    ]),
+                    Token::Atom("INTERNALDATE".to_string()),
+                    Token::Bytes(b"17-Jul-1996 02:44:25 -0700".to_vec()),
+                    Token::Atom("BODY[]".to_string()),
+                    Token::Bytes(b"Subject: test\r\n\r\nhello".to_vec()),
+                ]),
+            ]
+        );
+
+        assert_eq!(
+            tokenize(
+                b"LIST (\\HasNoChildren \\Sent) \".\" \"Sent \\\"Items\\\"\"",
+                &mut literals
+            ),
+            vec![
+                Token::Atom("LIST".to_string()),
+                Token::List(vec![
+                    Token::Atom("\\HasNoChildren".to_string()),
+                    Token::Atom("\\Sent".to_string()),
+                ]),
+                Token::Bytes(b".".to_vec()),
+                Token::Bytes(b"Sent \"Items\"".to_vec()),
+            ]
+        );
+
+        assert_eq!(
+            tokenize(b"OK [UIDVALIDITY 3857529045] UIDs valid", &mut literals)[..2],
+            [
+                Token::Atom("OK".to_string()),
+                Token::Atom("[UIDVALIDITY 3857529045]".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,506 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use directory::QueryBy;
+use jmap_proto::types::{collection::Collection, keyword::Keyword, property::Property};
+use mail_parser::MessageParser;
+use store::{
+    parking_lot::Mutex,
+    write::{now, BatchBuilder, F_CLEAR, F_VALUE},
+};
+
+use crate::{email::ingest::IngestEmail, Bincode, IngestError, JMAP};
+
+use self::client::{ImapClient, RemoteFolder, RemoteMessage};
+
+pub mod client;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MigrationRequest {
+    pub host: String,
+    pub port: Option<u16>,
+    #[serde(default = "default_tls")]
+    pub tls: bool,
+    #[serde(default, rename = "allowInvalidCerts")]
+    pub allow_invalid_certs: bool,
+    pub username: String,
+    pub secret: String,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub reset: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MigrationStatus {
+    Running,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationProgress {
+    pub status: MigrationStatus,
+    pub host: String,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub folders_total: usize,
+    pub folders_done: usize,
+    pub current_folder: Option<String>,
+    pub messages_total: usize,
+    pub messages_imported: usize,
+    pub messages_skipped: usize,
+    pub messages_failed: usize,
+    pub bytes_imported: usize,
+    pub error: Option<String>,
+}
+
+pub struct MigrationJob {
+    pub progress: Mutex<MigrationProgress>,
+    cancel: AtomicBool,
+}
+
+/// Synchronization state of an account, stored so interrupted migrations
+/// can be resumed from the last imported UID of each remote folder.
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MigrationState {
+    pub folders: BTreeMap<String, FolderState>,
+}
+
+#[derive(Debug, Default, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderState {
+    pub uid_validity: u32,
+    pub last_uid: u32,
+}
+
+enum MigrationError {
+    Cancelled,
+    Failed(String),
+}
+
+impl JMAP {
+    pub fn migration_start(
+        self: &Arc<Self>,
+        account_id: u32,
+        request: MigrationRequest,
+    ) -> Option<Arc<MigrationJob>> {
+        if self
+            .migrations
+            .get(&account_id)
+            .map_or(false, |job| job.is_running())
+        {
+            return None;
+        }
+
+        let job = Arc::new(MigrationJob {
+            progress: Mutex::new(MigrationProgress {
+                status: MigrationStatus::Running,
+                host: request.host.clone(),
+                started_at: now(),
+                finished_at: None,
+                folders_total: 0,
+                folders_done: 0,
+                current_folder: None,
+                messages_total: 0,
+                messages_imported: 0,
+                messages_skipped: 0,
+                messages_failed: 0,
+                bytes_imported: 0,
+                error: None,
+            }),
+            cancel: AtomicBool::new(false),
+        });
+        self.migrations.insert(account_id, job.clone());
+
+        let jmap = self.clone();
+        let job_ = job.clone();
+        tokio::spawn(async move {
+            let result = jmap.migration_run(account_id, &request, &job_).await;
+            let mut progress = job_.progress.lock();
+            progress.finished_at = now().into();
+            progress.current_folder = None;
+            match result {
+                Ok(_) => {
+                    progress.status = MigrationStatus::Completed;
+                    tracing::info!(
+                        context = "migrate",
+                        event = "completed",
+                        account_id = account_id,
+                        host = %request.host,
+                        imported = progress.messages_imported,
+                        skipped = progress.messages_skipped,
+                        failed = progress.messages_failed,
+                        "Migration completed."
+                    );
+                }
+                Err(MigrationError::Cancelled) => {
+                    progress.status = MigrationStatus::Cancelled;
+                    tracing::info!(
+                        context = "migrate",
+                        event = "cancelled",
+                        account_id = account_id,
+                        host = %request.host,
+                        "Migration cancelled."
+                    );
+                }
+                Err(MigrationError::Failed(err)) => {
+                    progress.status = MigrationStatus::Failed;
+                    tracing::warn!(
+                        context = "migrate",
+                        event = "error",
+                        account_id = account_id,
+                        host = %request.host,
+                        reason = %err,
+                        "Migration failed."
+                    );
+                    progress.error = err.into();
+                }
+            }
+        });
+
+        Some(job)
+    }
+
+    pub fn migration_cancel(&self, account_id: u32) -> bool {
+        if let Some(job) = self.migrations.get(&account_id) {
+            if job.is_running() {
+                job.cancel.store(true, Ordering::Relaxed);
+                return true;
+            }
+        }
+        false
+    }
+
+    pub async fn migration_state(&self, account_id: u32) -> store::Result<MigrationState> {
+        self.store
+            .get_value::<Bincode<MigrationState>>(store::ValueKey {
+                account_id,
+                collection: Collection::Principal.into(),
+                document_id: 0,
+                class: store::write::ValueClass::Property(Property::Value.into()),
+            })
+            .await
+            .map(|state| state.map(|state| state.inner).unwrap_or_default())
+    }
+
+    async fn migration_run(
+        &self,
+        account_id: u32,
+        request: &MigrationRequest,
+        job: &MigrationJob,
+    ) -> Result<(), MigrationError> {
+        // Connect to the remote server
+        let mut client = ImapClient::connect(
+            &request.host,
+            request.port.unwrap_or(if request.tls { 993 } else { 143 }),
+            request.tls,
+            request.allow_invalid_certs,
+            self.config.migration_timeout,
+        )
+        .await?;
+        client
+            .login(&request.username, &request.secret)
+            .await
+            .map_err(|err| MigrationError::Failed(format!("Authentication failed: {err}")))?;
+        let folders = client
+            .list()
+            .await?
+            .into_iter()
+            .filter(|folder| {
+                !request
+                    .exclude
+                    .iter()
+                    .any(|exclude| exclude.eq_ignore_ascii_case(&folder.name))
+            })
+            .collect::<Vec<_>>();
+        job.progress.lock().folders_total = folders.len();
+
+        // Obtain the account's quota and synchronization state
+        let account_quota = self
+            .directory
+            .query(QueryBy::Id(account_id), false)
+            .await
+            .map_err(|_| MigrationError::failed("Failed to obtain account quota"))?
+            .map_or(0, |principal| principal.quota as i64);
+        let mut state = if !request.reset {
+            self.migration_state(account_id)
+                .await
+                .map_err(|_| MigrationError::failed("Failed to obtain migration state"))?
+        } else {
+            MigrationState::default()
+        };
+        self.mailbox_get_or_create(account_id)
+            .await
+            .map_err(|_| MigrationError::failed("Failed to create default mailboxes"))?;
+
+        for folder in folders {
+            if job.cancel.load(Ordering::Relaxed) {
+                self.migration_save_state(account_id, &state).await?;
+                client.logout().await;
+                return Err(MigrationError::Cancelled);
+            }
+            job.progress.lock().current_folder = folder.name.clone().into();
+            let mailbox_id = self.migration_mailbox(account_id, &folder).await?;
+
+            // A new UIDVALIDITY invalidates the UIDs imported so far, in which
+            // case the folder is imported again skipping existing messages.
+            let (uid_validity, exists) = client.examine(&folder.raw_name).await?;
+            let folder_state = state.folders.entry(folder.name.clone()).or_default();
+            let resync = folder_state.uid_validity != uid_validity && folder_state.last_uid > 0;
+            if folder_state.uid_validity != uid_validity {
+                *folder_state = FolderState {
+                    uid_validity,
+                    last_uid: 0,
+                };
+            }
+
+            let uids = if exists > 0 {
+                client.uid_search(folder_state.last_uid + 1).await?
+            } else {
+                vec![]
+            };
+            job.progress.lock().messages_total += uids.len();
+
+            for uids in uids.chunks(self.config.migration_batch_size.max(1)) {
+                for message in client.uid_fetch(uids).await? {
+                    if job.cancel.load(Ordering::Relaxed) {
+                        self.migration_save_state(account_id, &state).await?;
+                        client.logout().await;
+                        return Err(MigrationError::Cancelled);
+                    }
+
+                    self.migration_import(
+                        account_id,
+                        account_quota,
+                        mailbox_id,
+                        &message,
+                        resync,
+                        job,
+                    )
+                    .await?;
+                    let folder_state = state.folders.get_mut(&folder.name).unwrap();
+                    folder_state.last_uid = folder_state.last_uid.max(message.uid);
+
+                    if let Some(throttle) = self.config.migration_throttle {
+                        tokio::time::sleep(throttle).await;
+                    }
+                }
+                self.migration_save_state(account_id, &state).await?;
+            }
+
+            job.progress.lock().folders_done += 1;
+        }
+
+        self.migration_save_state(account_id, &state).await?;
+        client.logout().await;
+
+        Ok(())
+    }
+
+    async fn migration_import(
+        &self,
+        account_id: u32,
+        account_quota: i64,
+        mailbox_id: u32,
+        message: &RemoteMessage,
+        skip_duplicates: bool,
+        job: &MigrationJob,
+    ) -> Result<(), MigrationError> {
+        match self
+            .email_ingest(IngestEmail {
+                raw_message: &message.contents,
+                message: MessageParser::new().parse(&message.contents),
+                account_id,
+                account_quota,
+                mailbox_ids: vec![mailbox_id],
+                keywords: message
+                    .flags
+                    .iter()
+                    .filter_map(|flag| flag_to_keyword(flag))
+                    .collect(),
+                received_at: message.internal_date,
+                skip_duplicates,
+                encrypt: self.config.encrypt && self.config.encrypt_append,
+            })
+            .await
+        {
+            Ok(email) => {
+                let mut progress = job.progress.lock();
+                if email.change_id != u64::MAX {
+                    progress.messages_imported += 1;
+                    progress.bytes_imported += email.size;
+                } else {
+                    progress.messages_skipped += 1;
+                }
+                Ok(())
+            }
+            Err(IngestError::OverQuota) => Err(MigrationError::failed("Account is over quota")),
+            Err(IngestError::Temporary) => Err(MigrationError::failed(
+                "Temporary failure importing message",
+            )),
+            Err(IngestError::Permanent { reason, .. }) => {
+                tracing::debug!(
+                    context = "migrate",
+                    event = "skip",
+                    account_id = account_id,
+                    uid = message.uid,
+                    reason = %reason,
+                    "Failed to import message."
+                );
+                job.progress.lock().messages_failed += 1;
+                Ok(())
+            }
+        }
+    }
+
+    async fn migration_mailbox(
+        &self,
+        account_id: u32,
+        folder: &RemoteFolder,
+    ) -> Result<u32, MigrationError> {
+        if let Some(role) = folder.role() {
+            if let Some(mailbox_id) = self
+                .mailbox_get_by_role(account_id, role)
+                .await
+                .map_err(|_| MigrationError::failed("Failed to obtain mailbox"))?
+            {
+                return Ok(mailbox_id);
+            }
+        }
+
+        // Subfolders of the remote INBOX are placed under the local one
+        let name = match folder.name.split_once('/') {
+            Some((inbox, name)) if inbox.eq_ignore_ascii_case("INBOX") => {
+                format!("Inbox/{name}")
+            }
+            _ => folder.name.clone(),
+        };
+
+        self.mailbox_create_path(account_id, &name)
+            .await
+            .map_err(|_| MigrationError::failed("Failed to create mailbox"))?
+            .map(|(mailbox_id, _)| mailbox_id)
+            .ok_or_else(|| MigrationError::Failed(format!("Invalid folder name {name:?}")))
+    }
+
+    async fn migration_save_state(
+        &self,
+        account_id: u32,
+        state: &MigrationState,
+    ) -> Result<(), MigrationError> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Principal)
+            .update_document(0)
+            .value(Property::Value, Bincode::new(state.clone()), F_VALUE);
+        self.store
+            .write(batch.build())
+            .await
+            .map_err(|_| MigrationError::failed("Failed to save migration state"))
+    }
+
+    pub async fn migration_reset(&self, account_id: u32) -> store::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Principal)
+            .update_document(0)
+            .value(Property::Value, (), F_VALUE | F_CLEAR);
+        self.store.write(batch.build()).await
+    }
+}
+
+impl MigrationJob {
+    pub fn is_running(&self) -> bool {
+        self.progress.lock().status == MigrationStatus::Running
+    }
+}
+
+impl RemoteFolder {
+    pub fn role(&self) -> Option<&'static str> {
+        if self.name.eq_ignore_ascii_case("INBOX") {
+            return Some("inbox");
+        }
+
+        for attribute in &self.attributes {
+            match attribute.as_str() {
+                "\\sent" => return Some("sent"),
+                "\\drafts" => return Some("drafts"),
+                "\\trash" => return Some("trash"),
+                "\\junk" => return Some("junk"),
+                _ => (),
+            }
+        }
+
+        // Servers without SPECIAL-USE support are matched by well-known names
+        match self.name.to_lowercase().as_str() {
+            "sent" | "sent items" | "sent messages" | "sent mail" => Some("sent"),
+            "drafts" => Some("drafts"),
+            "trash" | "deleted items" | "deleted messages" => Some("trash"),
+            "junk" | "spam" | "junk e-mail" | "junk mail" => Some("junk"),
+            _ => None,
+        }
+    }
+}
+
+fn flag_to_keyword(flag: &str) -> Option<Keyword> {
+    match flag.to_ascii_lowercase().as_str() {
+        "\\seen" => Keyword::Seen.into(),
+        "\\draft" => Keyword::Draft.into(),
+        "\\flagged" => Keyword::Flagged.into(),
+        "\\answered" => Keyword::Answered.into(),
+        "\\deleted" => Keyword::Deleted.into(),
+        "\\recent" => None,
+        flag if flag.starts_with('\\') => None,
+        flag if flag.starts_with('$') => Keyword::from(flag.to_string()).into(),
+        _ => Keyword::Other(flag.to_string()).into(),
+    }
+}
+
+fn default_tls() -> bool {
+    true
+}
+
+impl MigrationError {
+    fn failed(reason: &str) -> Self {
+        MigrationError::Failed(reason.to_string())
+    }
+}
+
+impl From<client::ImapError> for MigrationError {
+    fn from(err: client::ImapError) -> Self {
+        MigrationError::Failed(err.to_string())
+    }
+}
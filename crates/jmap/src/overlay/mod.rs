@@ -0,0 +1,232 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use ahash::AHashMap;
+use directory::QueryBy;
+use utils::{
+    config::Config,
+    map::ttl_dashmap::{TtlDashMap, TtlMap},
+};
+
+use crate::JMAP;
+
+// Overlay settings are stored in the configuration store as
+// "overlay.<scope>.<subject>:<key>", or "overlay.global:<key>" for the
+// global scope, and the tenant a domain belongs to as "overlay.member.<domain>".
+pub const OVERLAY_PREFIX: &str = "overlay";
+
+pub struct Overlays {
+    layers: TtlDashMap<String, Arc<AHashMap<String, String>>>,
+    members: TtlDashMap<String, Option<String>>,
+    ttl: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverlayScope {
+    Account,
+    Domain,
+    Tenant,
+    Global,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct EffectiveValue {
+    pub key: String,
+    pub value: String,
+    pub scope: OverlayScope,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct OverlaySubject {
+    pub account: Option<String>,
+    pub domain: Option<String>,
+    pub tenant: Option<String>,
+}
+
+impl Overlays {
+    pub fn parse(config: &Config, shard_amount: usize) -> Result<Self, String> {
+        let size = config.property("jmap.overlay.cache.size")?.unwrap_or(1024);
+        Ok(Overlays {
+            layers: TtlDashMap::with_capacity(size, shard_amount),
+            members: TtlDashMap::with_capacity(size, shard_amount),
+            ttl: config.property_or_static("jmap.overlay.cache.ttl", "5m")?,
+        })
+    }
+
+    pub fn invalidate(&self) {
+        self.layers.clear();
+        self.members.clear();
+    }
+
+    pub fn is_overlay_key(key: &str) -> bool {
+        key.strip_prefix(OVERLAY_PREFIX)
+            .map_or(false, |key| key.is_empty() || key.starts_with('.'))
+    }
+}
+
+impl OverlayScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OverlayScope::Account => "account",
+            OverlayScope::Domain => "domain",
+            OverlayScope::Tenant => "tenant",
+            OverlayScope::Global => "global",
+        }
+    }
+}
+
+impl JMAP {
+    /// Resolves the effective value of `key`, or of all overlay settings when no
+    /// key is given, walking the account, domain, tenant and global layers in
+    /// that order. Returns `None` if the account does not exist.
+    pub async fn overlay_resolve(
+        &self,
+        subject: OverlaySubject,
+        key: Option<&str>,
+    ) -> Result<Option<Vec<EffectiveValue>>, String> {
+        let mut layers = Vec::with_capacity(4);
+        let mut domain = subject.domain;
+
+        // Obtain the account's domain
+        if let Some(account) = subject.account {
+            let principal = match self
+                .directory
+                .query(QueryBy::Name(&account), false)
+                .await
+                .map_err(|err| format!("{err:?}"))?
+            {
+                Some(principal) => principal,
+                None => return Ok(None),
+            };
+            if domain.is_none() {
+                domain = principal
+                    .emails
+                    .first()
+                    .unwrap_or(&principal.name)
+                    .rsplit_once('@')
+                    .map(|(_, domain)| domain.to_lowercase());
+            }
+            layers.push((OverlayScope::Account, Some(account)));
+        }
+
+        // Obtain the domain's tenant
+        let tenant = match (subject.tenant, &domain) {
+            (Some(tenant), _) => Some(tenant),
+            (None, Some(domain)) => self.overlay_tenant(domain).await?,
+            (None, None) => None,
+        };
+        if let Some(domain) = domain {
+            layers.push((OverlayScope::Domain, Some(domain)));
+        }
+        if let Some(tenant) = tenant {
+            layers.push((OverlayScope::Tenant, Some(tenant)));
+        }
+        layers.push((OverlayScope::Global, None));
+
+        let mut values: Vec<EffectiveValue> = Vec::new();
+        for (scope, subject) in layers {
+            let layer = self.overlay_layer(scope, subject.as_deref()).await?;
+            if let Some(key) = key {
+                if let Some(value) = layer.get(key) {
+                    return Ok(Some(vec![EffectiveValue {
+                        key: key.to_string(),
+                        value: value.to_string(),
+                        scope,
+                        subject,
+                    }]));
+                }
+            } else {
+                for (key, value) in layer.iter() {
+                    if !values.iter().any(|v| &v.key == key) {
+                        values.push(EffectiveValue {
+                            key: key.to_string(),
+                            value: value.to_string(),
+                            scope,
+                            subject: subject.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        values.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+
+        Ok(Some(values))
+    }
+
+    async fn overlay_layer(
+        &self,
+        scope: OverlayScope,
+        subject: Option<&str>,
+    ) -> Result<Arc<AHashMap<String, String>>, String> {
+        let prefix = if let Some(subject) = subject {
+            format!("{OVERLAY_PREFIX}.{}.{subject}:", scope.as_str())
+        } else {
+            format!("{OVERLAY_PREFIX}.{}:", scope.as_str())
+        };
+        if let Some(layer) = self.overlays.layers.get_with_ttl(&prefix) {
+            return Ok(layer);
+        }
+
+        let layer = self
+            .store
+            .config_list(&prefix)
+            .await
+            .map_err(|err| err.to_string())?
+            .keys
+            .into_iter()
+            .filter_map(|(key, value)| Some((key.strip_prefix(&prefix)?.to_string(), value)))
+            .collect::<AHashMap<_, _>>();
+
+        Ok(self.overlays.layers.insert_with_ttl(
+            prefix,
+            Arc::new(layer),
+            Instant::now() + self.overlays.ttl,
+        ))
+    }
+
+    async fn overlay_tenant(&self, domain: &str) -> Result<Option<String>, String> {
+        if let Some(tenant) = self.overlays.members.get_with_ttl(domain) {
+            return Ok(tenant);
+        }
+
+        let tenant = self
+            .store
+            .config_get(format!("{OVERLAY_PREFIX}.member.{domain}"))
+            .await
+            .map_err(|err| err.to_string())?;
+
+        Ok(self.overlays.members.insert_with_ttl(
+            domain.to_string(),
+            tenant,
+            Instant::now() + self.overlays.ttl,
+        ))
+    }
+}
@@ -23,6 +23,7 @@
 
 use std::sync::Arc;
 
+use directory::backend::internal::manage::ManageDirectory;
 use tokio::sync::mpsc;
 use utils::ipc::DeliveryEvent;
 
@@ -35,6 +36,13 @@ pub fn spawn_delivery_manager(core: Arc<JMAP>, mut delivery_rx: mpsc::Receiver<D
                 DeliveryEvent::Ingest { message, result_tx } => {
                     result_tx.send(core.deliver_message(message).await).ok();
                 }
+                DeliveryEvent::Submission { account, size } => {
+                    if core.metering.is_some() {
+                        if let Ok(Some(account_id)) = core.store.get_account_id(&account).await {
+                            core.meter_sent(account_id, size);
+                        }
+                    }
+                }
                 DeliveryEvent::Stop => break,
             }
         }
@@ -26,7 +26,7 @@ use std::sync::Arc;
 use tokio::sync::mpsc;
 use utils::{
     config::{cron::SimpleCron, Config, Servers},
-    listener::blocked::BLOCKED_IP_KEY,
+    listener::{blocked::BLOCKED_IP_KEY, expiry::ExpiryMonitor},
     map::ttl_dashmap::TtlMap,
     UnwrapFailure,
 };
@@ -59,6 +59,92 @@ pub fn spawn_housekeeper(
     let certificates = std::mem::take(&mut servers.certificates);
     let blocked_ips = servers.blocked_ips.clone();
 
+    // Issue certificates for the domains present in the directory
+    for acme in &servers.acme_managers {
+        if let Some(refresh) = acme.directory_domains_refresh() {
+            let acme = acme.clone();
+            let core = core.clone();
+            tokio::spawn(async move {
+                loop {
+                    match core.directory.list_domains().await {
+                        Ok(Some(domains)) => {
+                            acme.set_directory_domains(domains);
+                        }
+                        Ok(None) => {
+                            tracing::warn!(
+                                context = "acme",
+                                event = "error",
+                                "The directory does not support listing domains, \
+                                 certificates will only be issued for the configured domains."
+                            );
+                            break;
+                        }
+                        Err(err) => {
+                            tracing::warn!(
+                                context = "acme",
+                                event = "error",
+                                error = ?err,
+                                "Failed to list directory domains."
+                            );
+                        }
+                    }
+                    tokio::time::sleep(refresh).await;
+                }
+            });
+        }
+    }
+
+    // Monitor the expiry of all loaded certificates
+    if let Some(monitor) = ExpiryMonitor::parse(settings).failed("Initialize housekeeper") {
+        let certificates = servers.monitored_certificates.clone();
+        let acme_managers = servers.acme_managers.clone();
+        tokio::spawn(async move {
+            loop {
+                monitor.check(&certificates, &acme_managers).await;
+                tokio::time::sleep(monitor.interval).await;
+            }
+        });
+    }
+
+    // Archive old messages according to the account archive policies
+    if core.config.archive_enable {
+        let archive_frequency = settings
+            .property_or_static::<SimpleCron>("jmap.archive.frequency", "0 3 *")
+            .failed("Initialize housekeeper");
+        let core = core.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(archive_frequency.time_to_next()).await;
+                core.archive_all().await;
+            }
+        });
+    }
+
+    // Compact the store outside business hours
+    if core.config.compaction_enable {
+        let compaction_frequency = settings
+            .property_or_static::<SimpleCron>("storage.compaction.frequency", "0 2 *")
+            .failed("Initialize housekeeper");
+        let core = core.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(compaction_frequency.time_to_next()).await;
+                core.compaction_schedule();
+            }
+        });
+    }
+
+    // Close the usage metering period
+    if let Some(interval) = core.metering.as_ref().map(|metering| metering.interval) {
+        let core = core.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                core.flush_usage().await;
+            }
+        });
+    }
+
     tokio::spawn(async move {
         tracing::debug!("Housekeeper task started.");
 
@@ -109,6 +195,8 @@ pub fn spawn_housekeeper(
                     Event::ReloadConfig => {
                         // Future releases will support reloading the configuration
                         // for now, we just reload the blocked IP addresses
+                        // and reopen the log file
+                        utils::logging::reopen();
                         let core = core.clone();
                         let blocked_ips = blocked_ips.clone();
                         tokio::spawn(async move {
@@ -183,10 +271,21 @@ pub fn spawn_housekeeper(
                     core.sessions.cleanup();
                     core.access_tokens.cleanup();
                     core.oauth_codes.cleanup();
+                    core.step_up.cleanup();
+                    core.image_proxy_cache.cleanup();
+                    core.smtp.session.account_sessions.cleanup();
                     core.rate_limit_auth
                         .retain(|_, limiter| limiter.is_active());
                     core.rate_limit_unauth
                         .retain(|_, limiter| limiter.is_active());
+                    core.rate_limit_endpoint
+                        .retain(|_, limiter| limiter.is_active());
+                    core.rate_limit_recovery
+                        .retain(|_, limiter| limiter.is_active());
+                    core.purge_activity_log().await;
+                    core.purge_tombstones().await;
+                    core.purge_usage().await;
+                    core.purge_sieve_vacation().await;
                 });
             }
         }
@@ -22,12 +22,18 @@
 */
 
 use directory::QueryBy;
-use jmap_proto::types::{state::StateChange, type_state::DataType};
+use jmap_proto::{
+    error::method::MethodError,
+    types::{collection::Collection, property::Property, state::StateChange, type_state::DataType},
+};
 use mail_parser::MessageParser;
-use store::ahash::AHashMap;
+use store::{
+    ahash::AHashMap,
+    write::{BatchBuilder, F_CLEAR, F_VALUE},
+};
 use utils::ipc::{DeliveryResult, IngestMessage};
 
-use crate::{email::ingest::IngestEmail, mailbox::INBOX_ID, IngestError, JMAP};
+use crate::{email::ingest::IngestEmail, Bincode, IngestError, JMAP};
 
 impl JMAP {
     pub async fn deliver_message(&self, message: IngestMessage) -> Vec<DeliveryResult> {
@@ -49,27 +55,76 @@ impl JMAP {
         for rcpt in &message.recipients {
             let uids = self.directory.email_to_ids(rcpt).await.unwrap_or_default();
             for uid in &uids {
-                deliver_names.insert(*uid, (DeliveryResult::Success, rcpt));
+                deliver_names
+                    .entry(*uid)
+                    .or_insert_with(|| (DeliveryResult::Success, Vec::new()))
+                    .1
+                    .push(rcpt);
             }
             recipients.push(uids);
         }
 
+        // Messages addressed to multiple aliases of the same account are ingested once,
+        // unless the account opted out of deduplication
+        let mut deliveries = Vec::with_capacity(deliver_names.len());
+        for (uid, (status, rcpts)) in &mut deliver_names {
+            match self.is_delivery_dedup_enabled(*uid).await {
+                Ok(true) => {
+                    if rcpts.len() > 1 {
+                        tracing::debug!(
+                            context = "delivery",
+                            event = "skip",
+                            account_id = *uid,
+                            rcpts = ?&rcpts[1..],
+                            "Duplicate delivery skipped."
+                        );
+                    }
+                    deliveries.push((*uid, rcpts[0], true));
+                }
+                Ok(false) => deliveries.extend(
+                    rcpts
+                        .iter()
+                        .enumerate()
+                        .map(|(pos, rcpt)| (*uid, *rcpt, pos == 0)),
+                ),
+                Err(_) => {
+                    *status = DeliveryResult::TemporaryFailure {
+                        reason: "Transient server failure.".into(),
+                    };
+                }
+            }
+        }
+
         // Deliver to each recipient
-        for (uid, (status, rcpt)) in &mut deliver_names {
+        for (uid, rcpt, skip_duplicates) in deliveries {
+            let status = &mut deliver_names.get_mut(&uid).unwrap().0;
+
+            // Obtain the mailbox for tagged addresses before running Sieve
+            let mailbox_id = match self.tag_rule_mailbox(uid, rcpt).await {
+                Ok(mailbox_id) => mailbox_id,
+                Err(_) => {
+                    *status = DeliveryResult::TemporaryFailure {
+                        reason: "Transient server failure.".into(),
+                    };
+                    continue;
+                }
+            };
+
             // Check if there is an active sieve script
-            let result = match self.sieve_script_get_active(*uid).await {
+            let result = match self.sieve_script_get_active(uid).await {
                 Ok(Some(active_script)) => {
                     self.sieve_script_ingest(
                         &raw_message,
                         &message.sender_address,
                         rcpt,
-                        *uid,
+                        uid,
+                        mailbox_id,
                         active_script,
                     )
                     .await
                 }
                 Ok(None) => {
-                    let account_quota = match self.directory.query(QueryBy::Id(*uid), false).await {
+                    let account_quota = match self.directory.query(QueryBy::Id(uid), false).await {
                         Ok(Some(p)) => p.quota as i64,
                         Ok(None) => 0,
                         Err(_) => {
@@ -83,12 +138,12 @@ impl JMAP {
                     self.email_ingest(IngestEmail {
                         raw_message: &raw_message,
                         message: MessageParser::new().parse(&raw_message),
-                        account_id: *uid,
+                        account_id: uid,
                         account_quota,
-                        mailbox_ids: vec![INBOX_ID],
+                        mailbox_ids: vec![mailbox_id],
                         keywords: vec![],
                         received_at: None,
-                        skip_duplicates: true,
+                        skip_duplicates,
                         encrypt: self.config.encrypt,
                     })
                     .await
@@ -103,10 +158,12 @@ impl JMAP {
 
             match result {
                 Ok(ingested_message) => {
+                    self.meter_received(uid, raw_message.len());
+
                     // Notify state change
                     if ingested_message.change_id != u64::MAX {
                         self.broadcast_state_change(
-                            StateChange::new(*uid)
+                            StateChange::new(uid)
                                 .with_change(DataType::EmailDelivery, ingested_message.change_id)
                                 .with_change(DataType::Email, ingested_message.change_id)
                                 .with_change(DataType::Mailbox, ingested_message.change_id)
@@ -180,4 +237,33 @@ impl JMAP {
             })
             .collect()
     }
+
+    pub async fn is_delivery_dedup_enabled(&self, account_id: u32) -> Result<bool, MethodError> {
+        self.get_property::<Bincode<bool>>(
+            account_id,
+            Collection::Principal,
+            0,
+            Property::DeliveryDedup,
+        )
+        .await
+        .map(|enabled| enabled.map_or(self.config.delivery_dedup_enable, |e| e.inner))
+    }
+
+    pub async fn set_delivery_dedup(
+        &self,
+        account_id: u32,
+        enabled: Option<bool>,
+    ) -> Result<(), MethodError> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Principal)
+            .update_document(0);
+        if let Some(enabled) = enabled {
+            batch.value(Property::DeliveryDedup, Bincode::new(enabled), F_VALUE);
+        } else {
+            batch.value(Property::DeliveryDedup, (), F_VALUE | F_CLEAR);
+        }
+        self.write_batch(batch).await
+    }
 }
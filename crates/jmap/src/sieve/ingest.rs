@@ -24,24 +24,20 @@
 use std::borrow::Cow;
 
 use directory::QueryBy;
-use jmap_proto::types::{collection::Collection, id::Id, keyword::Keyword, property::Property};
-use mail_parser::MessageParser;
-use sieve::{Envelope, Event, Input, Mailbox, Recipient};
+use jmap_proto::types::{id::Id, keyword::Keyword};
+use mail_parser::{Message, MessageParser};
+use sieve::{runtime::Variable, Envelope, Event, Input, Mailbox, Recipient};
 use smtp::core::{Session, SessionAddress};
-use store::{
-    ahash::AHashSet,
-    write::{now, BatchBuilder, F_VALUE},
-};
+use store::ahash::AHashMap;
 use utils::listener::stream::NullIo;
 
 use crate::{
     email::ingest::{IngestEmail, IngestedEmail},
     mailbox::{INBOX_ID, TRASH_ID},
-    sieve::SeenIdHash,
-    Bincode, IngestError, JMAP,
+    IngestError, JMAP,
 };
 
-use super::ActiveScript;
+use super::{ActiveScript, DuplicateIds};
 
 struct SieveMessage<'x> {
     pub raw_message: Cow<'x, [u8]>,
@@ -57,7 +53,8 @@ impl JMAP {
         envelope_from: &str,
         envelope_to: &str,
         account_id: u32,
-        mut active_script: ActiveScript,
+        default_mailbox_id: u32,
+        active_script: ActiveScript,
     ) -> Result<IngestedEmail, IngestError> {
         // Parse message
         let message = if let Some(message) = MessageParser::new().parse(raw_message) {
@@ -76,6 +73,10 @@ impl JMAP {
             .map_err(|_| IngestError::Temporary)?;
 
         // Create Sieve instance
+        let mut vars_env: AHashMap<Cow<'static, str>, Variable> = AHashMap::with_capacity(3);
+        if let Some(score) = spam_score(&message, raw_message) {
+            vars_env.insert("spam_score".into(), Variable::Float(score));
+        }
         let mut instance = self.sieve_runtime.filter_parsed(message);
 
         // Set account name and obtain quota
@@ -83,6 +84,7 @@ impl JMAP {
             match self.directory.query(QueryBy::Id(account_id), false).await {
                 Ok(Some(p)) => {
                     instance.set_user_full_name(p.description().unwrap_or_else(|| p.name()));
+                    vars_env.insert("authenticated_as".into(), p.name().to_string().into());
                     (p.quota as i64, p.emails.into_iter().next())
                 }
                 Ok(None) => (0, None),
@@ -94,24 +96,34 @@ impl JMAP {
         // Set account address
         let mail_from = mail_from.unwrap_or_else(|| envelope_to.to_string());
         instance.set_user_address(&mail_from);
+        if let Some((_, domain)) = mail_from.rsplit_once('@') {
+            vars_env.insert("tenant".into(), domain.to_lowercase().into());
+        }
+        let mut instance = instance.with_vars_env(vars_env);
 
         // Set envelope
         instance.set_envelope(Envelope::From, envelope_from);
         instance.set_envelope(Envelope::To, envelope_to);
 
+        // Obtain the redirect policy of the account
+        let redirect_rule = self
+            .sieve_redirect_rule(account_id)
+            .await
+            .map_err(|_| IngestError::Temporary)?;
+
         let mut input = Input::script(active_script.script_name, active_script.script.clone());
+        let mut duplicate_ids = DuplicateIds::new(active_script.document_id);
+        let mut has_runtime_error = false;
 
         let mut do_discard = false;
         let mut do_deliver = false;
 
-        let mut new_ids = AHashSet::new();
         let mut reject_reason = None;
         let mut messages: Vec<SieveMessage> = vec![SieveMessage {
             raw_message: raw_message.into(),
             file_into: Vec::new(),
             flags: Vec::new(),
         }];
-        let now = now();
         let mut ingested_message = IngestedEmail {
             id: Id::default(),
             change_id: u64::MAX,
@@ -206,14 +218,15 @@ impl JMAP {
                             input = false.into();
                         }
                     }
+                    Event::DuplicateId { id, expiry, .. } if id.starts_with("_v") => {
+                        // Vacation responses are tracked separately from duplicates
+                        input = self.sieve_vacation(account_id, &id, expiry).await.into();
+                    }
                     Event::DuplicateId { id, expiry, last } => {
-                        let id_hash = SeenIdHash::new(&id, expiry + now);
-                        let seen_id = active_script.seen_ids.ids.contains(&id_hash);
-                        if !seen_id || last {
-                            new_ids.insert(id_hash);
-                        }
-
-                        input = seen_id.into();
+                        input = self
+                            .sieve_duplicate(account_id, &id, expiry, last, &mut duplicate_ids)
+                            .await
+                            .into();
                     }
                     Event::Discard => {
                         do_discard = true;
@@ -227,8 +240,8 @@ impl JMAP {
                     Event::Keep { flags, message_id } => {
                         if let Some(message) = messages.get_mut(message_id) {
                             message.flags = flags.into_iter().map(Keyword::from).collect();
-                            if !message.file_into.contains(&INBOX_ID) {
-                                message.file_into.push(INBOX_ID);
+                            if !message.file_into.contains(&default_mailbox_id) {
+                                message.file_into.push(default_mailbox_id);
                             }
                             do_deliver = true;
                         } else {
@@ -327,20 +340,34 @@ impl JMAP {
                     } => {
                         input = true.into();
                         if let Some(message) = messages.get(message_id) {
+                            // Replies to the sender, such as vacation responses, are not redirects
+                            let mut rcpts = Vec::new();
+                            for rcpt in match recipient {
+                                Recipient::Address(rcpt) => vec![rcpt],
+                                Recipient::Group(rcpts) => rcpts,
+                                Recipient::List(_) => {
+                                    // Not yet implemented
+                                    continue;
+                                }
+                            } {
+                                if rcpt.eq_ignore_ascii_case(envelope_from)
+                                    || redirect_rule.is_allowed(&rcpt)
+                                {
+                                    rcpts.push(SessionAddress::new(rcpt));
+                                } else {
+                                    self.sieve_redirect_blocked(account_id, redirect_rule, &rcpt)
+                                        .await;
+                                }
+                            }
+                            if rcpts.is_empty() {
+                                continue;
+                            }
+
                             if message.raw_message.len() <= self.config.mail_max_size {
                                 let result = Session::<NullIo>::sieve(
                                     self.smtp.clone(),
                                     SessionAddress::new(mail_from.clone()),
-                                    match recipient {
-                                        Recipient::Address(rcpt) => vec![SessionAddress::new(rcpt)],
-                                        Recipient::Group(rcpts) => {
-                                            rcpts.into_iter().map(SessionAddress::new).collect()
-                                        }
-                                        Recipient::List(_) => {
-                                            // Not yet implemented
-                                            continue;
-                                        }
-                                    },
+                                    rcpts,
                                     message.raw_message.to_vec(),
                                 )
                                 .queue_message()
@@ -399,6 +426,7 @@ impl JMAP {
                         reason = %err,
                         "Runtime error",
                     );
+                    has_runtime_error = true;
                     input = true.into();
                 }
             }
@@ -406,7 +434,7 @@ impl JMAP {
 
         // Fail-safe, no discard and no keep seen, assume that something went wrong and file anyway.
         if !do_deliver && !do_discard {
-            messages[0].file_into.push(INBOX_ID);
+            messages[0].file_into.push(default_mailbox_id);
         }
 
         // Deliver messages
@@ -456,20 +484,12 @@ impl JMAP {
             }
         }
 
-        // Save new ids script changes
-        if !new_ids.is_empty() || active_script.seen_ids.has_changes {
-            active_script.seen_ids.ids.extend(new_ids);
-            let mut batch = BatchBuilder::new();
-            batch
-                .with_account_id(account_id)
-                .with_collection(Collection::SieveScript)
-                .update_document(active_script.document_id)
-                .value(
-                    Property::EmailIds,
-                    Bincode::new(active_script.seen_ids),
-                    F_VALUE,
-                );
-            let _ = self.write_batch(batch).await;
+        // Duplicate ids are only recorded once the script completed successfully,
+        // so that a message retried after a temporary failure is not a duplicate.
+        if !has_runtime_error
+            && (reject_reason.is_some() || has_delivered || last_temp_error.is_none())
+        {
+            self.sieve_duplicate_commit(account_id, duplicate_ids).await;
         }
 
         if let Some(reject_reason) = reject_reason {
@@ -487,6 +507,23 @@ impl JMAP {
     }
 }
 
+// Obtains the score added by the spam filter to the X-Spam-Status header
+fn spam_score(message: &Message, raw_message: &[u8]) -> Option<f64> {
+    let header = message
+        .root_part()
+        .headers()
+        .iter()
+        .find(|header| header.name.as_str().eq_ignore_ascii_case("X-Spam-Status"))?;
+    let value =
+        std::str::from_utf8(raw_message.get(header.offset_start..header.offset_end)?).ok()?;
+    let score = value.split_once("score=")?.1;
+    score
+        .split(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+        .next()?
+        .parse()
+        .ok()
+}
+
 #[inline(always)]
 pub fn is_valid_role(role: &str) -> bool {
     [
@@ -23,101 +23,342 @@
 
 use std::sync::Arc;
 
+use jmap_proto::{
+    error::method::MethodError,
+    types::{collection::Collection, property::Property},
+};
 use serde::ser::SerializeSeq;
 use sieve::Sieve;
-use store::{ahash::AHashSet, blake3, write::now};
+use store::{
+    ahash::AHashMap,
+    blake3,
+    write::{now, BatchBuilder, F_CLEAR, F_VALUE},
+    LookupKey, LookupStore, LookupValue,
+};
+
+use crate::{Bincode, JMAP};
 
 pub mod get;
 pub mod ingest;
 pub mod query;
+pub mod redirect;
 pub mod set;
 pub mod validate;
 
+pub const KV_SIEVE_DUPLICATE: &[u8] = b"sdup:";
+pub const KV_SIEVE_VACATION: &[u8] = b"svac:";
+
 pub struct ActiveScript {
     pub document_id: u32,
     pub script_name: String,
     pub script: Arc<Sieve>,
-    pub seen_ids: SeenIds,
 }
 
-#[derive(Debug, Clone)]
-pub struct SeenIdHash {
-    hash: [u8; 32],
-    expiry: u64,
+/// Duplicate tracking ids looked up during a script run, which are only
+/// recorded once the script has completed successfully.
+pub struct DuplicateIds {
+    document_id: u32,
+    is_migrated: bool,
+    ids: AHashMap<Vec<u8>, DuplicateId>,
 }
 
-#[derive(Debug, Clone, Default)]
-pub struct SeenIds {
-    pub ids: AHashSet<SeenIdHash>,
-    pub has_changes: bool,
+struct DuplicateId {
+    expiry: u64,
+    seen: bool,
+    refresh: bool,
 }
 
-impl SeenIdHash {
-    pub fn new(id: &str, expiry: u64) -> Self {
-        let mut hasher = blake3::Hasher::new();
-        hasher.update(id.as_bytes());
-        SeenIdHash {
-            hash: hasher.finalize().into(),
-            expiry,
+impl DuplicateIds {
+    pub fn new(document_id: u32) -> Self {
+        DuplicateIds {
+            document_id,
+            is_migrated: false,
+            ids: AHashMap::new(),
         }
     }
 }
 
-impl PartialOrd for SeenIdHash {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+impl JMAP {
+    /// Returns the store used to track duplicate ids and vacation responses,
+    /// falling back to the data store when the configured lookup store is
+    /// read-only.
+    fn sieve_lookup_store(&self) -> LookupStore {
+        match &self.smtp.queue.config.lookup_store {
+            LookupStore::Query(_) | LookupStore::Memory(_) => {
+                LookupStore::Store(self.store.clone())
+            }
+            store => store.clone(),
+        }
     }
-}
 
-impl Ord for SeenIdHash {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.expiry.cmp(&other.expiry)
+    /// Returns whether a "duplicate" tracking id was seen by a previous
+    /// run of the script. The id is recorded by `sieve_duplicate_commit`.
+    pub async fn sieve_duplicate(
+        &self,
+        account_id: u32,
+        id: &str,
+        expiry: u64,
+        last: bool,
+        duplicate_ids: &mut DuplicateIds,
+    ) -> bool {
+        if !duplicate_ids.is_migrated {
+            duplicate_ids.is_migrated = true;
+            if let Err(err) = self
+                .sieve_migrate_seen_ids(account_id, duplicate_ids.document_id)
+                .await
+            {
+                tracing::warn!(
+                    context = "sieve",
+                    event = "error",
+                    account_id = account_id,
+                    reason = ?err,
+                    "Failed to migrate duplicate tracking ids."
+                );
+            }
+        }
+
+        let expiry = std::cmp::min(expiry, self.config.sieve_max_duplicate_expiry).max(1);
+        let key = sieve_key(
+            KV_SIEVE_DUPLICATE,
+            account_id,
+            blake3::hash(id.as_bytes()).as_bytes(),
+        );
+
+        // Ids encountered earlier in this run are not duplicates (RFC 7352)
+        if let Some(duplicate_id) = duplicate_ids.ids.get_mut(&key) {
+            if last {
+                duplicate_id.expiry = expiry;
+                duplicate_id.refresh = true;
+            }
+            return duplicate_id.seen;
+        }
+
+        let seen = match self
+            .sieve_lookup_store()
+            .key_get::<String>(LookupKey::Key(key.clone()))
+            .await
+        {
+            Ok(LookupValue::Value { .. }) => true,
+            Ok(_) => false,
+            Err(err) => {
+                tracing::warn!(
+                    context = "sieve",
+                    event = "error",
+                    account_id = account_id,
+                    reason = %err,
+                    "Failed to obtain duplicate tracking id."
+                );
+                false
+            }
+        };
+        duplicate_ids.ids.insert(
+            key,
+            DuplicateId {
+                expiry,
+                seen,
+                refresh: !seen || last,
+            },
+        );
+
+        seen
     }
-}
 
-impl std::hash::Hash for SeenIdHash {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.hash.hash(state);
+    /// Records the new duplicate tracking ids, and restarts the expiry period
+    /// of those seen with ":last", after the script completed successfully.
+    pub async fn sieve_duplicate_commit(&self, account_id: u32, duplicate_ids: DuplicateIds) {
+        let store = self.sieve_lookup_store();
+        for (key, duplicate_id) in duplicate_ids.ids {
+            if !duplicate_id.refresh {
+                continue;
+            }
+            if let Err(err) = store
+                .key_set(
+                    key,
+                    LookupValue::Value {
+                        value: vec![],
+                        expires: duplicate_id.expiry,
+                    },
+                )
+                .await
+            {
+                tracing::warn!(
+                    context = "sieve",
+                    event = "error",
+                    account_id = account_id,
+                    reason = %err,
+                    "Failed to record duplicate tracking id."
+                );
+            }
+        }
     }
-}
 
-impl PartialEq for SeenIdHash {
-    fn eq(&self, other: &Self) -> bool {
-        self.hash == other.hash
+    /// Moves the tracking ids that older versions stored on the script itself
+    /// to the lookup store.
+    async fn sieve_migrate_seen_ids(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> Result<(), MethodError> {
+        let seen_ids = if let Some(seen_ids) = self
+            .get_property::<Bincode<LegacySeenIds>>(
+                account_id,
+                Collection::SieveScript,
+                document_id,
+                Property::EmailIds,
+            )
+            .await?
+        {
+            seen_ids.inner
+        } else {
+            return Ok(());
+        };
+
+        // Vacation responses shared the same list, the hashes of both kinds
+        // of ids are copied so neither is forgotten.
+        let store = self.sieve_lookup_store();
+        for (expires, hash) in seen_ids.unexpired(now()) {
+            for prefix in [KV_SIEVE_DUPLICATE, KV_SIEVE_VACATION] {
+                store
+                    .key_set(
+                        sieve_key(prefix, account_id, &hash),
+                        LookupValue::Value {
+                            value: vec![],
+                            expires,
+                        },
+                    )
+                    .await
+                    .map_err(|err| {
+                        tracing::error!(
+                            context = "sieve",
+                            event = "error",
+                            account_id = account_id,
+                            reason = %err,
+                            "Failed to store migrated tracking id."
+                        );
+                        MethodError::ServerPartialFail
+                    })?;
+            }
+        }
+
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::SieveScript)
+            .update_document(document_id)
+            .value(Property::EmailIds, (), F_VALUE | F_CLEAR);
+        self.write_batch(batch).await
     }
+
+    /// Records that a vacation response is about to be sent for the tracking
+    /// id and returns whether one was already sent within the response period.
+    pub async fn sieve_vacation(&self, account_id: u32, id: &str, period: u64) -> bool {
+        let period = std::cmp::min(period, self.config.sieve_max_vacation_period).max(1);
+        let key = sieve_key(
+            KV_SIEVE_VACATION,
+            account_id,
+            blake3::hash(id.as_bytes()).as_bytes(),
+        );
+
+        match self
+            .sieve_lookup_store()
+            .key_insert(key, vec![], period)
+            .await
+        {
+            Ok(inserted) => !inserted,
+            Err(err) => {
+                tracing::warn!(
+                    context = "sieve",
+                    event = "error",
+                    account_id = account_id,
+                    reason = %err,
+                    "Failed to record vacation response."
+                );
+                false
+            }
+        }
+    }
+
+    /// Forgets the vacation responses sent by an account, or by all accounts,
+    /// so that senders receive a response again.
+    pub async fn sieve_vacation_reset(&self, account_id: Option<u32>) -> store::Result<()> {
+        let mut prefix = KV_SIEVE_VACATION.to_vec();
+        if let Some(account_id) = account_id {
+            prefix.extend_from_slice(&account_id.to_be_bytes());
+        }
+        self.sieve_lookup_store()
+            .key_delete_prefix(&prefix, false)
+            .await
+    }
+
+    pub async fn purge_sieve_vacation(&self) {
+        if let Err(err) = self
+            .sieve_lookup_store()
+            .key_delete_prefix(KV_SIEVE_VACATION, true)
+            .await
+        {
+            tracing::warn!(
+                context = "sieve",
+                event = "error",
+                reason = %err,
+                "Failed to purge expired vacation responses."
+            );
+        }
+    }
+}
+
+fn sieve_key(prefix: &[u8], account_id: u32, hash: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(prefix.len() + std::mem::size_of::<u32>() + hash.len());
+    key.extend_from_slice(prefix);
+    key.extend_from_slice(&account_id.to_be_bytes());
+    key.extend_from_slice(hash);
+    key
 }
 
-impl Eq for SeenIdHash {}
+// Tracking ids stored by older versions as a sequence of
+// alternating expiry and hash entries.
+struct LegacySeenIds(Vec<(u64, [u8; 32])>);
+
+impl LegacySeenIds {
+    // Returns the remaining lifetime of the ids that have not expired yet,
+    // ids may have expired since they were deserialized.
+    fn unexpired(self, now: u64) -> impl Iterator<Item = (u64, [u8; 32])> {
+        self.0.into_iter().filter_map(move |(expiry, hash)| {
+            expiry
+                .checked_sub(now)
+                .filter(|expires| *expires > 0)
+                .map(|expires| (expires, hash))
+        })
+    }
+}
 
-// SeenIds serializer
-impl serde::Serialize for SeenIds {
+impl serde::Serialize for LegacySeenIds {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let mut seq = serializer.serialize_seq((self.ids.len() * 2).into())?;
-        for id in &self.ids {
-            seq.serialize_element(&id.expiry)?;
-            seq.serialize_element(&id.hash)?;
+        let mut seq = serializer.serialize_seq((self.0.len() * 2).into())?;
+        for (expiry, hash) in &self.0 {
+            seq.serialize_element(expiry)?;
+            seq.serialize_element(hash)?;
         }
 
         seq.end()
     }
 }
 
-impl<'de> serde::Deserialize<'de> for SeenIds {
+impl<'de> serde::Deserialize<'de> for LegacySeenIds {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        deserializer.deserialize_seq(SeenIdsVisitor)
+        deserializer.deserialize_seq(LegacySeenIdsVisitor)
     }
 }
 
-struct SeenIdsVisitor;
+struct LegacySeenIdsVisitor;
 
-impl<'de> serde::de::Visitor<'de> for SeenIdsVisitor {
-    type Value = SeenIds;
+impl<'de> serde::de::Visitor<'de> for LegacySeenIdsVisitor {
+    type Value = LegacySeenIds;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
         formatter.write_str("invalid SeenIds")
@@ -128,30 +369,58 @@ impl<'de> serde::de::Visitor<'de> for SeenIdsVisitor {
         A: serde::de::SeqAccess<'de>,
     {
         let num_entries = seq.size_hint().unwrap_or(0) / 2;
-        let mut seen_ids = SeenIds {
-            ids: AHashSet::with_capacity(num_entries),
-            has_changes: false,
-        };
+        let mut seen_ids = Vec::with_capacity(num_entries);
         let now = now();
 
         for _ in 0..num_entries {
             let expiry = seq
                 .next_element::<u64>()?
                 .ok_or_else(|| serde::de::Error::custom("Expected expiry."))?;
+            let hash = seq
+                .next_element::<[u8; 32]>()?
+                .ok_or_else(|| serde::de::Error::custom("Expected hash."))?;
             if expiry > now {
-                seen_ids.ids.insert(SeenIdHash {
-                    hash: seq
-                        .next_element()?
-                        .ok_or_else(|| serde::de::Error::custom("Expected hash."))?,
-                    expiry,
-                });
-            } else {
-                seq.next_element::<[u8; 32]>()?
-                    .ok_or_else(|| serde::de::Error::custom("Expected hash."))?;
-                seen_ids.has_changes = true;
+                seen_ids.push((expiry, hash));
             }
         }
 
-        Ok(seen_ids)
+        Ok(LegacySeenIds(seen_ids))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use store::{write::now, Deserialize, Serialize};
+
+    use super::LegacySeenIds;
+    use crate::Bincode;
+
+    #[test]
+    fn migrate_legacy_seen_ids() {
+        let now = now();
+        let seen_ids = LegacySeenIds(vec![
+            (now - 10, [1; 32]),
+            (now, [2; 32]),
+            (now + 60, [3; 32]),
+        ]);
+
+        // Ids that expired while being migrated are skipped
+        assert_eq!(
+            LegacySeenIds(seen_ids.0.clone())
+                .unexpired(now)
+                .collect::<Vec<_>>(),
+            vec![(60, [3; 32])]
+        );
+        assert_eq!(
+            LegacySeenIds(seen_ids.0.clone())
+                .unexpired(now + 120)
+                .count(),
+            0
+        );
+
+        // Expired ids are dropped when reading the stored property
+        let stored =
+            Bincode::<LegacySeenIds>::deserialize(&Bincode::new(seen_ids).serialize()).unwrap();
+        assert_eq!(stored.inner.0, vec![(now + 60, [3; 32])]);
     }
 }
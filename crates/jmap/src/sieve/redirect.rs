@@ -0,0 +1,386 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::QueryBy;
+use jmap_proto::{
+    error::method::MethodError,
+    method::activity::ActivityEvent,
+    object::Object,
+    types::{collection::Collection, property::Property, value::Value},
+};
+use utils::config::{Config, ServerProtocol};
+
+use crate::JMAP;
+
+use super::set::ObjectBlobId;
+
+// Restricts the destinations user scripts may redirect messages to. Named
+// policies apply to accounts of a domain or members of a group, accounts
+// not covered by any policy use the default allow and deny lists.
+#[derive(Debug, Default)]
+pub struct RedirectPolicy {
+    pub default: RedirectRule,
+    pub policies: Vec<RedirectRule>,
+}
+
+#[derive(Debug, Default)]
+pub struct RedirectRule {
+    pub name: String,
+    pub domains: Vec<String>,
+    pub groups: Vec<String>,
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl RedirectPolicy {
+    pub fn parse(config: &Config) -> utils::config::Result<Self> {
+        let prefix = "sieve.untrusted.redirect";
+        let mut policy = RedirectPolicy {
+            default: RedirectRule::parse(config, prefix.to_string(), "default"),
+            policies: Vec::new(),
+        };
+        for name in config.sub_keys((prefix, "policy"), "") {
+            let rule = RedirectRule::parse(config, format!("{prefix}.policy.{name}"), name);
+            if rule.domains.is_empty() && rule.groups.is_empty() {
+                return Err(format!(
+                    "Sieve redirect policy {name:?} does not apply to any domain or group."
+                ));
+            }
+            policy.policies.push(rule);
+        }
+
+        Ok(policy)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.is_empty() && self.default.allow.is_empty() && self.default.deny.is_empty()
+    }
+}
+
+impl RedirectRule {
+    fn parse(config: &Config, prefix: String, name: &str) -> Self {
+        let list = |key: &str| {
+            config
+                .values((prefix.as_str(), key))
+                .map(|(_, value)| value.trim().trim_end_matches('.').to_lowercase())
+                .filter(|value| !value.is_empty())
+                .collect::<Vec<_>>()
+        };
+
+        RedirectRule {
+            name: name.to_string(),
+            domains: list("domains"),
+            groups: list("groups"),
+            allow: list("allow"),
+            deny: list("deny"),
+        }
+    }
+
+    pub fn is_allowed(&self, address: &str) -> bool {
+        let address = address.trim().to_lowercase();
+        let domain = address
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .unwrap_or_default();
+        let matches = |entry: &String| {
+            if entry == "*" {
+                true
+            } else if let Some(suffix) = entry.strip_prefix("*.") {
+                domain
+                    .strip_suffix(suffix)
+                    .map_or(false, |prefix| prefix.ends_with('.'))
+            } else if entry.contains('@') {
+                *entry == address
+            } else {
+                entry == domain
+            }
+        };
+
+        !self.deny.iter().any(matches) && (self.allow.is_empty() || self.allow.iter().any(matches))
+    }
+}
+
+impl JMAP {
+    pub async fn sieve_redirect_rule(&self, account_id: u32) -> Result<&RedirectRule, MethodError> {
+        let policy = &self.config.sieve_redirect;
+        if policy.policies.is_empty() {
+            return Ok(&policy.default);
+        }
+
+        let principal = match self.directory.query(QueryBy::Id(account_id), true).await {
+            Ok(Some(principal)) => principal,
+            Ok(None) => return Ok(&policy.default),
+            Err(err) => {
+                tracing::warn!(
+                    context = "sieve_redirect",
+                    event = "error",
+                    account_id = account_id,
+                    reason = ?err,
+                    "Failed to obtain principal."
+                );
+                return Err(MethodError::ServerPartialFail);
+            }
+        };
+        let domain = principal
+            .emails
+            .first()
+            .and_then(|email| email.rsplit_once('@'))
+            .map(|(_, domain)| domain.to_lowercase());
+
+        for rule in &policy.policies {
+            if domain
+                .as_ref()
+                .map_or(false, |domain| rule.domains.contains(domain))
+            {
+                return Ok(rule);
+            }
+            for group in &rule.groups {
+                if let Ok(Some(group)) = self.directory.query(QueryBy::Name(group), false).await {
+                    if principal.member_of.contains(&group.id) {
+                        return Ok(rule);
+                    }
+                }
+            }
+        }
+
+        Ok(&policy.default)
+    }
+
+    /// Returns a description of the first redirect target in the script
+    /// source that is not allowed by the account's redirect policy.
+    pub async fn sieve_redirect_violation(
+        &self,
+        account_id: u32,
+        script: &[u8],
+    ) -> Result<Option<String>, MethodError> {
+        if self.config.sieve_redirect.is_empty() {
+            return Ok(None);
+        }
+
+        let rule = self.sieve_redirect_rule(account_id).await?;
+        Ok(redirect_targets(script)
+            .into_iter()
+            .find(|target| !rule.is_allowed(target))
+            .map(|target| {
+                format!("Redirecting messages to {target:?} is not allowed by the administrator.")
+            }))
+    }
+
+    pub async fn sieve_redirect_script_violation(
+        &self,
+        account_id: u32,
+        document_id: u32,
+    ) -> Result<Option<String>, MethodError> {
+        if self.config.sieve_redirect.is_empty() {
+            return Ok(None);
+        }
+
+        let script = self
+            .get_property::<Object<Value>>(
+                account_id,
+                Collection::SieveScript,
+                document_id,
+                Property::Value,
+            )
+            .await?
+            .and_then(|script| {
+                script
+                    .blob_id()
+                    .and_then(|id| (id.hash.clone(), id.section.as_ref()?.clone()).into())
+            });
+        if let Some((hash, section)) = script {
+            if let Some(script) = self.get_blob_section(&hash, &section).await? {
+                return self.sieve_redirect_violation(account_id, &script).await;
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub async fn sieve_redirect_blocked(&self, account_id: u32, rule: &RedirectRule, target: &str) {
+        tracing::info!(
+            context = "sieve_redirect",
+            event = "blocked",
+            account_id = account_id,
+            policy = rule.name,
+            target = target,
+            "Sieve redirect blocked by policy."
+        );
+
+        self.log_activity(
+            account_id,
+            ActivityEvent::SieveRedirectBlocked,
+            ServerProtocol::Smtp,
+            None,
+            format!("Redirect to {target} blocked by policy '{}'", rule.name).into(),
+        )
+        .await;
+    }
+}
+
+// Extracts the literal destinations of redirect commands, targets built
+// from variables can only be checked when the script runs.
+pub fn redirect_targets(script: &[u8]) -> Vec<String> {
+    let script = String::from_utf8_lossy(script);
+    let mut chars = script.chars().peekable();
+    let mut tokens = Vec::new();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '#' => {
+                for ch in chars.by_ref() {
+                    if ch == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                for ch in chars.by_ref() {
+                    if last == '*' && ch == '/' {
+                        break;
+                    }
+                    last = ch;
+                }
+            }
+            '"' => {
+                let mut value = String::new();
+                while let Some(ch) = chars.next() {
+                    match ch {
+                        '\\' => {
+                            if let Some(ch) = chars.next() {
+                                value.push(ch);
+                            }
+                        }
+                        '"' => break,
+                        _ => value.push(ch),
+                    }
+                }
+                tokens.push(Token::String(value));
+            }
+            ':' | 'a'..='z' | 'A'..='Z' | '_' => {
+                let mut value = String::from(ch);
+                while let Some(ch) = chars.peek() {
+                    if ch.is_ascii_alphanumeric() || *ch == '_' || *ch == '-' {
+                        value.push(*ch);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(if ch == ':' {
+                    Token::Tag(value.to_ascii_lowercase())
+                } else {
+                    Token::Identifier(value.to_ascii_lowercase())
+                });
+            }
+            _ if ch.is_whitespace() => (),
+            _ => tokens.push(Token::Other),
+        }
+    }
+
+    let mut targets = Vec::new();
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        if token != Token::Identifier("redirect".to_string()) {
+            continue;
+        }
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::Tag(tag) => {
+                    // Tags followed by an argument
+                    if matches!(
+                        tag.as_str(),
+                        ":notify" | ":ret" | ":bytimerelative" | ":bytimeabsolute" | ":bymode"
+                    ) {
+                        tokens.next();
+                    }
+                }
+                Token::String(target) => {
+                    if !target.contains("${") {
+                        targets.push(target);
+                    }
+                    break;
+                }
+                _ => break,
+            }
+        }
+    }
+
+    targets
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Identifier(String),
+    Tag(String),
+    String(String),
+    Other,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{redirect_targets, RedirectRule};
+
+    #[test]
+    fn redirect_policy() {
+        assert_eq!(
+            redirect_targets(
+                concat!(
+                    "require [\"copy\", \"variables\"];\n",
+                    "# redirect \"comment@example.org\";\n",
+                    "if header :contains \"subject\" \"redirect\" {\n",
+                    "  redirect :copy \"John@Example.org\";\n",
+                    "  redirect \"${user}@example.org\";\n",
+                    "  /* redirect \"block@example.org\"; */\n",
+                    "  REDIRECT :notify \"success\" \"jane@example.net\";\n",
+                    "}\n"
+                )
+                .as_bytes()
+            ),
+            vec![
+                "John@Example.org".to_string(),
+                "jane@example.net".to_string()
+            ]
+        );
+
+        let rule = RedirectRule {
+            allow: vec!["example.org".to_string(), "*.example.com".to_string()],
+            deny: vec!["ceo@example.org".to_string()],
+            ..Default::default()
+        };
+        for (address, allowed) in [
+            ("john@example.org", true),
+            ("John@EXAMPLE.ORG", true),
+            ("ceo@example.org", false),
+            ("jane@mail.example.com", true),
+            ("jane@example.com", false),
+            ("jane@badexample.com", false),
+            ("jane@example.net", false),
+        ] {
+            assert_eq!(rule.is_allowed(address), allowed, "{address}");
+        }
+        assert!(RedirectRule::default().is_allowed("anyone@example.net"));
+    }
+}
@@ -319,18 +319,28 @@ impl JMAP {
                     .unwrap_or(false))
         {
             let changed_ids = if let Some(id) = request.arguments.on_success_activate_script {
-                self.sieve_activate_script(
-                    account_id,
-                    match id {
-                        MaybeReference::Value(id) => id.document_id(),
-                        MaybeReference::Reference(id_ref) => match ctx.response.get_id(&id_ref) {
-                            Some(Value::Id(id)) => id.document_id(),
-                            _ => return Ok(ctx.response),
-                        },
-                    }
-                    .into(),
-                )
-                .await?
+                let document_id = match id {
+                    MaybeReference::Value(id) => id.document_id(),
+                    MaybeReference::Reference(id_ref) => match ctx.response.get_id(&id_ref) {
+                        Some(Value::Id(id)) => id.document_id(),
+                        _ => return Ok(ctx.response),
+                    },
+                };
+
+                // Make sure the script does not redirect to forbidden destinations
+                if let Some(reason) = self
+                    .sieve_redirect_script_violation(account_id, document_id)
+                    .await?
+                {
+                    ctx.response.not_updated.append(
+                        Id::from(document_id),
+                        SetError::new(SetErrorType::InvalidScript).with_description(reason),
+                    );
+                    Vec::new()
+                } else {
+                    self.sieve_activate_script(account_id, document_id.into())
+                        .await?
+                }
             } else {
                 self.sieve_activate_script(account_id, None).await?
             };
@@ -532,6 +542,17 @@ impl JMAP {
                         return Ok(Err(SetError::over_quota()));
                     }
 
+                    // Active scripts must comply with the redirect policy
+                    if matches!(update.as_ref().and_then(|(_, obj)| obj.inner.properties.get(&Property::IsActive)), Some(Value::Bool(true))) {
+                        if let Some(reason) = self
+                            .sieve_redirect_violation(ctx.account_id, &bytes)
+                            .await?
+                        {
+                            return Ok(Err(SetError::new(SetErrorType::InvalidScript)
+                                .with_description(reason)));
+                        }
+                    }
+
                     // Compile script
                     match self.sieve_compiler.compile(&bytes) {
                         Ok(script) => {
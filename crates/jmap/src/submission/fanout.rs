@@ -0,0 +1,182 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+use jmap_proto::{
+    error::method::MethodError,
+    types::{
+        acl::Acl, collection::Collection, keyword::Keyword, state::StateChange,
+        type_state::DataType,
+    },
+};
+use mail_parser::{HeaderName, HeaderValue, MessageParser};
+
+use crate::{
+    auth::AccessToken,
+    email::{ingest::IngestEmail, metadata::MessageMetadata},
+    identity::set::sanitize_email,
+    JMAP,
+};
+
+pub struct SentCopy {
+    pub account_id: u32,
+    pub mailbox_id: u32,
+}
+
+impl JMAP {
+    /// Returns the Sent mailboxes of the shared accounts the message is sent on behalf of.
+    /// A copy is only filed when the owner granted the delegate the submit right
+    /// on their Sent mailbox.
+    pub async fn sent_fanout_targets(
+        &self,
+        access_token: &AccessToken,
+        account_id: u32,
+        metadata: &MessageMetadata,
+    ) -> Result<Vec<SentCopy>, MethodError> {
+        let mut targets: Vec<SentCopy> = Vec::new();
+
+        for header in &metadata.contents.parts[0].headers {
+            if header.name != HeaderName::From {
+                continue;
+            }
+            let addresses = if let HeaderValue::Address(addr) = &header.value {
+                addr.iter()
+                    .filter_map(|addr| addr.address().and_then(sanitize_email))
+                    .collect::<Vec<_>>()
+            } else {
+                continue;
+            };
+
+            for address in addresses {
+                let owner_ids = self.directory.email_to_ids(&address).await.map_err(|err| {
+                    tracing::error!(
+                        event = "error",
+                        context = "sent_fanout",
+                        error = ?err,
+                        "Failed to obtain account ids for address.");
+                    MethodError::ServerPartialFail
+                })?;
+
+                for owner_id in owner_ids {
+                    if owner_id == account_id
+                        || targets.iter().any(|target| target.account_id == owner_id)
+                    {
+                        continue;
+                    }
+                    if let Some(mailbox_id) = self.mailbox_get_by_role(owner_id, "sent").await? {
+                        if self
+                            .has_access_to_document(
+                                access_token,
+                                owner_id,
+                                Collection::Mailbox,
+                                mailbox_id,
+                                Acl::Submit,
+                            )
+                            .await?
+                        {
+                            targets.push(SentCopy {
+                                account_id: owner_id,
+                                mailbox_id,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(targets)
+    }
+
+    /// Files a copy of a sent message in the Sent mailbox of each shared account,
+    /// adding a Sender header that identifies the delegate when missing.
+    pub async fn sent_fanout(
+        &self,
+        access_token: &AccessToken,
+        targets: Vec<SentCopy>,
+        sender: &str,
+        raw_message: &[u8],
+    ) {
+        let has_sender = MessageParser::new()
+            .parse_headers(raw_message)
+            .map_or(false, |message| {
+                message
+                    .root_part()
+                    .headers()
+                    .iter()
+                    .any(|header| header.name == HeaderName::Sender)
+            });
+        let raw_message = if !has_sender {
+            let mut message = Vec::with_capacity(raw_message.len() + sender.len() + 12);
+            message.extend_from_slice(b"Sender: <");
+            message.extend_from_slice(sender.as_bytes());
+            message.extend_from_slice(b">\r\n");
+            message.extend_from_slice(raw_message);
+            Cow::Owned(message)
+        } else {
+            Cow::Borrowed(raw_message)
+        };
+
+        for target in targets {
+            let account_quota = match self.get_quota(access_token, target.account_id).await {
+                Ok(account_quota) => account_quota,
+                Err(_) => continue,
+            };
+
+            match self
+                .email_ingest(IngestEmail {
+                    raw_message: &raw_message,
+                    message: MessageParser::new().parse(raw_message.as_ref()),
+                    account_id: target.account_id,
+                    account_quota,
+                    mailbox_ids: vec![target.mailbox_id],
+                    keywords: vec![Keyword::Seen],
+                    received_at: None,
+                    skip_duplicates: true,
+                    encrypt: self.config.encrypt && self.config.encrypt_append,
+                })
+                .await
+            {
+                Ok(ingested_message) => {
+                    if ingested_message.change_id != u64::MAX {
+                        self.broadcast_state_change(
+                            StateChange::new(target.account_id)
+                                .with_change(DataType::Email, ingested_message.change_id)
+                                .with_change(DataType::Mailbox, ingested_message.change_id)
+                                .with_change(DataType::Thread, ingested_message.change_id),
+                        )
+                        .await;
+                    }
+                }
+                Err(err) => {
+                    tracing::info!(
+                        context = "sent_fanout",
+                        event = "error",
+                        account_id = target.account_id,
+                        reason = ?err,
+                        "Failed to file sent message in shared account.");
+                }
+            }
+        }
+    }
+}
@@ -21,6 +21,7 @@
  * for more details.
 */
 
+pub mod fanout;
 pub mod get;
 pub mod query;
 pub mod set;
@@ -23,6 +23,8 @@
 
 use std::{collections::HashMap, sync::Arc};
 
+use directory::QueryBy;
+
 use jmap_proto::{
     error::{
         method::MethodError,
@@ -60,7 +62,12 @@ use utils::{
     map::vec_map::VecMap,
 };
 
-use crate::{email::metadata::MessageMetadata, identity::set::sanitize_email, Bincode, JMAP};
+use crate::{
+    auth::AccessToken,
+    email::metadata::MessageMetadata,
+    identity::{set::sanitize_email, ENVELOPE_SENDER_IDENTITY, ENVELOPE_SENDER_PRIMARY},
+    Bincode, JMAP,
+};
 
 pub static SCHEMA: &[IndexProperty] = &[
     IndexProperty::new(Property::UndoStatus).index_as(IndexAs::Text {
@@ -89,7 +96,7 @@ impl JMAP {
         let mut success_email_ids = HashMap::new();
         for (id, object) in request.unwrap_create() {
             match self
-                .send_message(account_id, &response, instance, object)
+                .send_message(account_id, access_token, &response, instance, object)
                 .await?
             {
                 Ok(submission) => {
@@ -331,6 +338,7 @@ impl JMAP {
     async fn send_message(
         &self,
         account_id: u32,
+        access_token: &AccessToken,
         response: &SetResponse,
         instance: &Arc<ServerInstance>,
         object: Object<SetValue>,
@@ -458,27 +466,73 @@ impl JMAP {
         }
 
         // Fetch identity's mailFrom
-        let identity_mail_from = if let Some(identity_mail_from) = self
-            .get_property::<Object<Value>>(
-                account_id,
-                Collection::Identity,
-                identity_id,
-                Property::Value,
-            )
-            .await?
-            .and_then(|mut obj| obj.properties.remove(&Property::Email))
-            .and_then(|value| value.try_unwrap_string())
-        {
-            identity_mail_from
-        } else {
-            return Ok(Err(SetError::invalid_properties()
-                .with_property(Property::IdentityId)
-                .with_description("Identity not found.")));
+        let (identity_mail_from, envelope_sender) =
+            if let Some((Some(Value::Text(identity_mail_from)), envelope_sender)) = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::Identity,
+                    identity_id,
+                    Property::Value,
+                )
+                .await?
+                .map(|mut obj| {
+                    (
+                        obj.properties.remove(&Property::Email),
+                        obj.properties.remove(&Property::EnvelopeSender),
+                    )
+                })
+            {
+                (identity_mail_from, envelope_sender)
+            } else {
+                return Ok(Err(SetError::invalid_properties()
+                    .with_property(Property::IdentityId)
+                    .with_description("Identity not found.")));
+            };
+
+        // Select the envelope sender using the identity's policy
+        let envelope_sender = match envelope_sender {
+            Some(Value::Text(policy)) if policy != ENVELOPE_SENDER_IDENTITY => {
+                let emails = self
+                    .directory
+                    .query(QueryBy::Id(account_id), false)
+                    .await
+                    .map_err(|err| {
+                        tracing::error!(
+                            event = "error",
+                            context = "email_submission_set",
+                            account_id = account_id,
+                            error = ?err,
+                            "Failed to query directory.");
+                        MethodError::ServerPartialFail
+                    })?
+                    .unwrap_or_default()
+                    .emails;
+                let sender = if policy == ENVELOPE_SENDER_PRIMARY {
+                    emails.into_iter().next()
+                } else {
+                    // Addresses may have been removed after the identity was created
+                    emails
+                        .into_iter()
+                        .find(|email| email.eq_ignore_ascii_case(&policy))
+                };
+                if let Some(sender) = sender.and_then(|sender| sanitize_email(&sender)) {
+                    sender
+                } else {
+                    return Ok(Err(SetError::new(SetErrorType::ForbiddenMailFrom)
+                        .with_description(
+                            "Identity envelope sender is not configured for this account.",
+                        )));
+                }
+            }
+            _ => identity_mail_from.clone(),
         };
 
         // Make sure the envelope address matches the identity email address
+        // or the envelope sender selected by the identity
         let mail_from = if let Some(mail_from) = mail_from {
-            if !mail_from.address.eq_ignore_ascii_case(&identity_mail_from) {
+            if !mail_from.address.eq_ignore_ascii_case(&identity_mail_from)
+                && !mail_from.address.eq_ignore_ascii_case(&envelope_sender)
+            {
                 return Ok(Err(SetError::new(SetErrorType::ForbiddenFrom)
                     .with_description(
                         "Envelope mailFrom does not match identity email address.",
@@ -496,10 +550,10 @@ impl JMAP {
                 .set(
                     Property::MailFrom,
                     Object::with_capacity(1)
-                        .with_property(Property::Email, identity_mail_from.clone()),
+                        .with_property(Property::Email, envelope_sender.clone()),
                 );
             MailFrom {
-                address: identity_mail_from,
+                address: envelope_sender,
                 ..Default::default()
             }
         };
@@ -593,6 +647,17 @@ impl JMAP {
                 .with_description("Blob for email not found.")));
         };
 
+        // Obtain the shared accounts the message is sent on behalf of
+        let (sent_copies, sender) = if self.config.submission_sent_fanout {
+            (
+                self.sent_fanout_targets(access_token, account_id, &metadata)
+                    .await?,
+                mail_from.address.clone(),
+            )
+        } else {
+            (vec![], String::new())
+        };
+
         // Begin local SMTP session
         let mut session =
             Session::<NullIo>::local(self.smtp.clone(), instance.clone(), SessionData::default());
@@ -622,10 +687,19 @@ impl JMAP {
 
         // DATA
         if has_success {
+            let sent_message = (!sent_copies.is_empty()).then(|| message.clone());
+            let message_size = message.len();
             session.data.message = message;
             let response = session.queue_message().await;
             if let State::Accepted(queue_id) = session.state {
                 submission.append(Property::MessageId, queue_id);
+                self.meter_sent(account_id, message_size);
+
+                // File a copy in the Sent mailbox of the shared accounts
+                if let Some(sent_message) = sent_message {
+                    self.sent_fanout(access_token, sent_copies, &sender, &sent_message)
+                        .await;
+                }
             } else {
                 return Ok(Err(SetError::new(SetErrorType::ForbiddenToSend)
                     .with_description(format!(
@@ -0,0 +1,147 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod get;
+pub mod set;
+
+use jmap_proto::{
+    error::method::MethodError,
+    object::Object,
+    types::{
+        collection::Collection, property::Property, state::StateChange, type_state::DataType,
+        value::Value,
+    },
+};
+use store::write::{log::ChangeLogBuilder, BatchBuilder, F_VALUE};
+
+use crate::{mailbox::INBOX_ID, JMAP};
+
+impl JMAP {
+    pub async fn tag_rule_list(
+        &self,
+        account_id: u32,
+    ) -> Result<Vec<(u32, Object<Value>)>, MethodError> {
+        let rule_ids = self
+            .get_document_ids(account_id, Collection::TagRule)
+            .await?
+            .unwrap_or_default();
+        let mut rules = Vec::with_capacity(rule_ids.len() as usize);
+        for document_id in rule_ids {
+            if let Some(rule) = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::TagRule,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            {
+                rules.push((document_id, rule));
+            }
+        }
+
+        Ok(rules)
+    }
+
+    pub async fn tag_rule_mailbox(&self, account_id: u32, rcpt: &str) -> Result<u32, MethodError> {
+        let tag = match address_tag(rcpt, &self.config.tag_filing_separator) {
+            Some(tag) if self.config.tag_filing_enable => tag,
+            _ => return Ok(INBOX_ID),
+        };
+        let (document_id, mut rule) = if let Some(rule) = self
+            .tag_rule_list(account_id)
+            .await?
+            .into_iter()
+            .find(|(_, rule)| {
+                rule.get(&Property::Tag)
+                    .as_string()
+                    .map_or(false, |t| t.eq_ignore_ascii_case(tag))
+            }) {
+            rule
+        } else {
+            return Ok(INBOX_ID);
+        };
+
+        // File into the mailbox linked to the rule, as long as it still exists
+        let mailbox_ids = self.mailbox_get_or_create(account_id).await?;
+        if let Value::Id(mailbox_id) = rule.get(&Property::MailboxId) {
+            if mailbox_ids.contains(mailbox_id.document_id()) {
+                return Ok(mailbox_id.document_id());
+            }
+        }
+
+        // Otherwise look the mailbox up by name, creating it on first use
+        let mailbox_name = match rule.get(&Property::MailboxName) {
+            Value::Text(mailbox_name) => mailbox_name.clone(),
+            _ => return Ok(INBOX_ID),
+        };
+        let mailbox_id =
+            if let Some(mailbox_id) = self.mailbox_get_by_name(account_id, &mailbox_name).await? {
+                mailbox_id
+            } else if self.config.tag_filing_auto_create {
+                match self.mailbox_create_path(account_id, &mailbox_name).await? {
+                    Some((mailbox_id, _)) => mailbox_id,
+                    None => return Ok(INBOX_ID),
+                }
+            } else {
+                return Ok(INBOX_ID);
+            };
+
+        // Link the mailbox to the rule so it is still used after being renamed
+        rule.set(Property::MailboxId, Value::Id(mailbox_id.into()));
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::TagRule)
+            .update_document(document_id)
+            .value(Property::Value, rule, F_VALUE);
+        self.write_batch(batch).await?;
+        let mut changes = ChangeLogBuilder::new();
+        changes.log_update(Collection::TagRule, document_id);
+        let change_id = self.commit_changes(account_id, changes).await?;
+        self.broadcast_state_change(
+            StateChange::new(account_id).with_change(DataType::TagRule, change_id),
+        )
+        .await;
+
+        Ok(mailbox_id)
+    }
+}
+
+pub fn address_tag<'x>(address: &'x str, separator: &str) -> Option<&'x str> {
+    let (local_part, _) = address.rsplit_once('@')?;
+    let (_, tag) = local_part.split_once(separator)?;
+    if !tag.is_empty() {
+        Some(tag)
+    } else {
+        None
+    }
+}
+
+pub fn is_valid_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.len() <= 64
+        && tag
+            .chars()
+            .all(|ch| !ch.is_whitespace() && !ch.is_control() && !matches!(ch, '@' | '<' | '>'))
+}
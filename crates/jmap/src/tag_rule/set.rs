@@ -0,0 +1,289 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    error::{
+        method::MethodError,
+        set::{SetError, SetErrorType},
+    },
+    method::set::{RequestArguments, SetRequest, SetResponse},
+    object::Object,
+    response::references::EvalObjectReferences,
+    types::{
+        collection::Collection,
+        property::Property,
+        state::StateChange,
+        type_state::DataType,
+        value::{MaybePatchValue, Value},
+    },
+};
+use store::{
+    roaring::RoaringBitmap,
+    write::{log::ChangeLogBuilder, BatchBuilder, F_CLEAR, F_VALUE},
+};
+
+use crate::JMAP;
+
+use super::is_valid_tag;
+
+impl JMAP {
+    pub async fn tag_rule_set(
+        &self,
+        mut request: SetRequest<RequestArguments>,
+    ) -> Result<SetResponse, MethodError> {
+        let account_id = request.account_id.document_id();
+        let mut rules = self.tag_rule_list(account_id).await?;
+        let mailbox_ids = self
+            .get_document_ids(account_id, Collection::Mailbox)
+            .await?
+            .unwrap_or_default();
+        let mut response = self
+            .prepare_set_response(&request, Collection::TagRule)
+            .await?;
+        let will_destroy = request.unwrap_destroy();
+
+        // Process creates
+        let mut changes = ChangeLogBuilder::new();
+        'create: for (id, object) in request.unwrap_create() {
+            if rules.len() >= self.config.tag_filing_max_rules {
+                response.not_created.append(
+                    id,
+                    SetError::new(SetErrorType::OverQuota).with_description(
+                        "There are too many tag rules, please delete some before adding a new one.",
+                    ),
+                );
+                continue 'create;
+            }
+
+            let mut rule = Object::with_capacity(object.properties.len());
+            for (property, value) in object.properties {
+                match response
+                    .eval_object_references(value)
+                    .and_then(|value| self.validate_tag_rule_value(&property, value, &mailbox_ids))
+                {
+                    Ok(Value::Null) => (),
+                    Ok(value) => {
+                        rule.set(property, value);
+                    }
+                    Err(err) => {
+                        response.not_created.append(id, err);
+                        continue 'create;
+                    }
+                }
+            }
+
+            if let Err(err) = validate_rule(&rules, None, &rule) {
+                response.not_created.append(id, err);
+                continue 'create;
+            }
+
+            // Insert record
+            let mut batch = BatchBuilder::new();
+            let document_id = self
+                .assign_document_id(account_id, Collection::TagRule)
+                .await?;
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::TagRule)
+                .create_document(document_id)
+                .value(Property::Value, rule.clone(), F_VALUE);
+            self.write_batch(batch).await?;
+            changes.log_insert(Collection::TagRule, document_id);
+            rules.push((document_id, rule));
+            response.created.insert(
+                id,
+                Object::with_capacity(1).with_property(Property::Id, Value::Id(document_id.into())),
+            );
+        }
+
+        // Process updates
+        'update: for (id, object) in request.unwrap_update() {
+            // Make sure id won't be destroyed
+            if will_destroy.contains(&id) {
+                response.not_updated.append(id, SetError::will_destroy());
+                continue 'update;
+            }
+
+            // Obtain rule
+            let document_id = id.document_id();
+            let mut rule = if let Some((_, rule)) =
+                rules.iter().find(|(rule_id, _)| *rule_id == document_id)
+            {
+                rule.clone()
+            } else {
+                response.not_updated.append(id, SetError::not_found());
+                continue 'update;
+            };
+
+            for (property, value) in object.properties {
+                match response
+                    .eval_object_references(value)
+                    .and_then(|value| self.validate_tag_rule_value(&property, value, &mailbox_ids))
+                {
+                    Ok(Value::Null) => {
+                        rule.remove(&property);
+                    }
+                    Ok(value) => {
+                        rule.set(property, value);
+                    }
+                    Err(err) => {
+                        response.not_updated.append(id, err);
+                        continue 'update;
+                    }
+                };
+            }
+
+            if let Err(err) = validate_rule(&rules, Some(document_id), &rule) {
+                response.not_updated.append(id, err);
+                continue 'update;
+            }
+
+            // Update record
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::TagRule)
+                .update_document(document_id)
+                .value(Property::Value, rule.clone(), F_VALUE);
+            self.write_batch(batch).await?;
+            changes.log_update(Collection::TagRule, document_id);
+            if let Some((_, current)) = rules
+                .iter_mut()
+                .find(|(rule_id, _)| *rule_id == document_id)
+            {
+                *current = rule;
+            }
+            response.updated.append(id, None);
+        }
+
+        // Process deletions
+        for id in will_destroy {
+            let document_id = id.document_id();
+            if let Some(pos) = rules
+                .iter()
+                .position(|(rule_id, _)| *rule_id == document_id)
+            {
+                // Delete record, auto-created mailboxes are kept
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(account_id)
+                    .with_collection(Collection::TagRule)
+                    .delete_document(document_id)
+                    .value(Property::Value, (), F_VALUE | F_CLEAR);
+                self.write_batch(batch).await?;
+                changes.log_delete(Collection::TagRule, document_id);
+                rules.swap_remove(pos);
+                response.destroyed.push(id);
+            } else {
+                response.not_destroyed.append(id, SetError::not_found());
+            }
+        }
+
+        // Write changes
+        if !changes.is_empty() {
+            let change_id = self.commit_changes(account_id, changes).await?;
+            response.new_state = Some(change_id.into());
+            response.state_change = StateChange::new(account_id)
+                .with_change(DataType::TagRule, change_id)
+                .into();
+        }
+
+        Ok(response)
+    }
+
+    fn validate_tag_rule_value(
+        &self,
+        property: &Property,
+        value: MaybePatchValue,
+        mailbox_ids: &RoaringBitmap,
+    ) -> Result<Value, SetError> {
+        Ok(match (property, value) {
+            (Property::Tag, MaybePatchValue::Value(Value::Text(value)))
+                if is_valid_tag(value.trim()) =>
+            {
+                Value::Text(value.trim().to_lowercase())
+            }
+            (Property::MailboxId, MaybePatchValue::Value(Value::Id(value))) => {
+                if mailbox_ids.contains(value.document_id()) {
+                    Value::Id(value)
+                } else {
+                    return Err(SetError::invalid_properties()
+                        .with_property(Property::MailboxId)
+                        .with_description(format!("Mailbox {value} does not exist.")));
+                }
+            }
+            (Property::MailboxName, MaybePatchValue::Value(Value::Text(value)))
+                if value.split('/').all(|name| {
+                    !name.trim().is_empty()
+                        && name.chars().count() <= self.config.mailbox_name_max_len
+                }) =>
+            {
+                Value::Text(value)
+            }
+            (Property::MailboxId | Property::MailboxName, MaybePatchValue::Value(Value::Null)) => {
+                Value::Null
+            }
+            (property, _) => {
+                return Err(SetError::invalid_properties()
+                    .with_property(property.clone())
+                    .with_description("Field could not be set."));
+            }
+        })
+    }
+}
+
+fn validate_rule(
+    rules: &[(u32, Object<Value>)],
+    document_id: Option<u32>,
+    rule: &Object<Value>,
+) -> Result<(), SetError> {
+    let tag = if let Some(tag) = rule.get(&Property::Tag).as_string() {
+        tag
+    } else {
+        return Err(SetError::invalid_properties()
+            .with_property(Property::Tag)
+            .with_description("Missing tag."));
+    };
+    if matches!(rule.get(&Property::MailboxId), Value::Null)
+        && matches!(rule.get(&Property::MailboxName), Value::Null)
+    {
+        return Err(SetError::invalid_properties()
+            .with_properties([Property::MailboxId, Property::MailboxName])
+            .with_description("Either a mailboxId or a mailboxName is required."));
+    }
+
+    for (rule_id, rule) in rules {
+        if Some(*rule_id) != document_id
+            && rule
+                .get(&Property::Tag)
+                .as_string()
+                .map_or(false, |t| t.eq_ignore_ascii_case(tag))
+        {
+            return Err(SetError::already_exists()
+                .with_existing_id((*rule_id).into())
+                .with_description(format!("A rule for tag {tag:?} already exists.")));
+        }
+    }
+
+    Ok(())
+}
@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::{sync::Arc, time::Instant};
+use std::{net::IpAddr, sync::Arc, time::Instant};
 
 use futures_util::{SinkExt, StreamExt};
 use hyper::upgrade::Upgraded;
@@ -45,6 +45,7 @@ impl JMAP {
         mut stream: WebSocketStream<TokioIo<Upgraded>>,
         access_token: Arc<AccessToken>,
         instance: Arc<ServerInstance>,
+        remote_ip: IpAddr,
     ) {
         let span = tracing::info_span!(
             "WebSocket connection established",
@@ -100,6 +101,7 @@ impl JMAP {
                                                     request.request,
                                                     access_token.clone(),
                                                     &instance,
+                                                    remote_ip,
                                                 )
                                                 .await
                                             {
@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::sync::Arc;
+use std::{net::IpAddr, sync::Arc};
 
 use http_body_util::{BodyExt, Full};
 use hyper::{body::Bytes, Response, StatusCode};
@@ -42,6 +42,7 @@ pub async fn upgrade_websocket_connection(
     req: HttpRequest,
     access_token: Arc<AccessToken>,
     instance: Arc<ServerInstance>,
+    remote_ip: IpAddr,
 ) -> HttpResponse {
     let headers = req.headers();
     if headers
@@ -89,6 +90,7 @@ pub async fn upgrade_websocket_connection(
                         .await,
                     access_token,
                     instance,
+                    remote_ip,
                 )
                 .await;
             }
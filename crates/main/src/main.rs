@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use directory::core::config::ConfigDirectory;
 use imap::core::{ImapSessionManager, IMAP};
@@ -56,6 +56,10 @@ async fn main() -> std::io::Result<()> {
     )
     .failed("Failed to enable tracing");
 
+    // Resolve secret references
+    let secrets = Arc::new(config.parse_secrets().failed("Invalid configuration"));
+    config.resolve_secrets(&secrets).await;
+
     // Bind ports and drop privileges
     let mut servers = config.parse_servers().failed("Invalid configuration");
     servers.bind(&config);
@@ -68,6 +72,7 @@ async fn main() -> std::io::Result<()> {
 
     // Update configuration
     config.update(data_store.config_list("").await.failed("Storage error"));
+    config.resolve_secrets(&secrets).await;
     servers
         .blocked_ips
         .reload(&config)
@@ -87,6 +92,11 @@ async fn main() -> std::io::Result<()> {
         .await
         .failed("Invalid configuration");
 
+    // Enable event export
+    utils::events::init(&config)
+        .await
+        .failed("Invalid configuration");
+
     // Init servers
     let (delivery_tx, delivery_rx) = mpsc::channel(IPC_CHANNEL_BUFFER);
     let smtp = SMTP::init(&config, &servers, &stores, &directory, delivery_tx)
@@ -134,6 +144,9 @@ async fn main() -> std::io::Result<()> {
         scheduler.spawn(shutdown_rx.clone());
     }
 
+    // Spawn secrets lease renewal
+    secrets.spawn(shutdown_rx.clone());
+
     // Wait for shutdown signal
     wait_for_shutdown(&format!(
         "Shutting down Stalwart Mail Server v{}...",
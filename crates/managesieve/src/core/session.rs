@@ -21,6 +21,8 @@
  * for more details.
 */
 
+use std::borrow::Cow;
+
 use imap_proto::receiver::{self, Receiver};
 use tokio_rustls::server::TlsStream;
 use utils::listener::{SessionManager, SessionStream};
@@ -50,8 +52,18 @@ impl SessionManager for ManageSieveSessionManager {
                 remote_addr: session.remote_ip,
             };
 
+            // Legal notices and other banners are included in the greeting
+            let greeting = if let Some(banner) = &session.instance.banner {
+                let mut greeting = banner.render(&session.instance.hostname).join(" ");
+                greeting.push(' ');
+                greeting.push_str(SERVER_GREETING);
+                greeting.into()
+            } else {
+                Cow::Borrowed(SERVER_GREETING)
+            };
+
             if session
-                .write(&session.handle_capability(SERVER_GREETING).await.unwrap())
+                .write(&session.handle_capability(greeting).await.unwrap())
                 .await
                 .is_ok()
                 && session.handle_conn().await
@@ -142,11 +154,16 @@ impl<T: SessionStream> Session<T> {
 
     pub async fn into_tls(self) -> Result<Session<TlsStream<T>>, ()> {
         let span = self.span;
+        let stream = self.instance.tls_accept(self.stream, &span).await?;
+        let (instance, in_flight) = match self.instance.virtual_host(&stream, &span)? {
+            Some((instance, in_flight)) => (instance, in_flight.with_parent(self.in_flight)),
+            None => (self.instance, self.in_flight),
+        };
         Ok(Session {
-            stream: self.instance.tls_accept(self.stream, &span).await?,
+            stream,
             state: self.state,
-            instance: self.instance,
-            in_flight: self.in_flight,
+            instance,
+            in_flight,
             span,
             jmap: self.jmap,
             imap: self.imap,
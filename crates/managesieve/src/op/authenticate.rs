@@ -29,9 +29,10 @@ use imap_proto::{
     protocol::authenticate::Mechanism,
     receiver::{self, Request},
 };
+use jmap_proto::method::activity::ActivityEvent;
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
-use utils::listener::SessionStream;
+use utils::{config::ServerProtocol, listener::SessionStream};
 
 use crate::core::{Command, Session, State, StatusResponse};
 
@@ -135,6 +136,15 @@ impl<T: SessionStream> Session<T> {
                 // Cache access token
                 let access_token = Arc::new(access_token);
                 self.jmap.cache_access_token(access_token.clone());
+                self.jmap
+                    .log_activity(
+                        access_token.primary_id(),
+                        ActivityEvent::Login,
+                        ServerProtocol::ManageSieve,
+                        self.remote_addr.into(),
+                        None,
+                    )
+                    .await;
 
                 // Create session
                 self.state = State::Authenticated {
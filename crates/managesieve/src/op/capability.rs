@@ -21,13 +21,18 @@
  * for more details.
 */
 
+use std::borrow::Cow;
+
 use jmap::api::session::Capabilities;
 use utils::listener::SessionStream;
 
 use crate::core::{Session, StatusResponse};
 
 impl<T: SessionStream> Session<T> {
-    pub async fn handle_capability(&self, message: &'static str) -> super::OpResult {
+    pub async fn handle_capability(
+        &self,
+        message: impl Into<Cow<'static, str>>,
+    ) -> super::OpResult {
         let mut response = Vec::with_capacity(128);
         response.extend_from_slice(b"\"IMPLEMENTATION\" \"Stalwart ManageSieve v");
         response.extend_from_slice(env!("CARGO_PKG_VERSION").as_bytes());
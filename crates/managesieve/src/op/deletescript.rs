@@ -48,6 +48,8 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
             let mut changelog = ChangeLogBuilder::new();
             changelog.log_delete(Collection::SieveScript, document_id);
             self.jmap.commit_changes(account_id, changelog).await?;
+            self.log_sieve_change(account_id, format!("Script '{name}' deleted"))
+                .await;
 
             Ok(StatusResponse::ok("Deleted.").into_bytes())
         } else {
@@ -21,9 +21,11 @@
  * for more details.
 */
 
-use jmap_proto::error::method::MethodError;
+use jmap_proto::{error::method::MethodError, method::activity::ActivityEvent};
+use tokio::io::{AsyncRead, AsyncWrite};
+use utils::config::ServerProtocol;
 
-use crate::core::StatusResponse;
+use crate::core::{Session, StatusResponse};
 
 pub mod authenticate;
 pub mod capability;
@@ -45,3 +47,17 @@ impl From<MethodError> for StatusResponse {
 }
 
 pub type OpResult = std::result::Result<Vec<u8>, StatusResponse>;
+
+impl<T: AsyncRead + AsyncWrite> Session<T> {
+    pub async fn log_sieve_change(&self, account_id: u32, details: String) {
+        self.jmap
+            .log_activity(
+                account_id,
+                ActivityEvent::SieveChange,
+                ServerProtocol::ManageSieve,
+                self.remote_addr.into(),
+                details.into(),
+            )
+            .await;
+    }
+}
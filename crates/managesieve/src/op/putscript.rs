@@ -51,6 +51,7 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
             .ok_or_else(|| StatusResponse::no("Expected script as a parameter."))?
             .unwrap_bytes();
         let script_size = script_bytes.len() as i64;
+        let details = format!("Script '{name}' uploaded");
 
         // Check quota
         let access_token = self.state.access_token();
@@ -108,6 +109,20 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
                     .with_code(ResponseCode::TryLater)
             })?;
 
+            // Active scripts must comply with the redirect policy
+            if matches!(
+                script.inner.properties.get(&Property::IsActive),
+                Some(Value::Bool(true))
+            ) {
+                if let Some(reason) = self
+                    .jmap
+                    .sieve_redirect_violation(account_id, &script_bytes[..script_size as usize])
+                    .await?
+                {
+                    return Err(StatusResponse::no(reason));
+                }
+            }
+
             // Write script blob
             let blob_id = BlobId::new(
                 self.jmap
@@ -205,6 +220,7 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
                 .custom(changelog);
             self.jmap.write_batch(batch).await?;
         }
+        self.log_sieve_change(account_id, details).await;
 
         Ok(StatusResponse::ok("Success.").into_bytes())
     }
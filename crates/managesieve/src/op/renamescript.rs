@@ -85,7 +85,9 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
             .custom(
                 ObjectIndexBuilder::new(SCHEMA)
                     .with_current(script)
-                    .with_changes(Object::with_capacity(1).with_property(Property::Name, new_name)),
+                    .with_changes(
+                        Object::with_capacity(1).with_property(Property::Name, new_name.clone()),
+                    ),
             );
         if !batch.is_empty() {
             match self.jmap.write_batch(batch).await {
@@ -93,6 +95,11 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
                     let mut changelog = ChangeLogBuilder::new();
                     changelog.log_update(Collection::SieveScript, document_id);
                     self.jmap.commit_changes(account_id, changelog).await?;
+                    self.log_sieve_change(
+                        account_id,
+                        format!("Script '{name}' renamed to '{new_name}'"),
+                    )
+                    .await;
                 }
                 Err(MethodError::ServerUnavailable) => {
                     return Err(StatusResponse::no(
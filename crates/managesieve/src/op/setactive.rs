@@ -39,16 +39,25 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
 
         // De/activate script
         let account_id = self.state.access_token().primary_id();
+        let document_id = if !name.is_empty() {
+            let document_id = self.get_script_id(account_id, &name).await?;
+
+            // Make sure the script does not redirect to forbidden destinations
+            if let Some(reason) = self
+                .jmap
+                .sieve_redirect_script_violation(account_id, document_id)
+                .await?
+            {
+                return Err(StatusResponse::no(reason));
+            }
+
+            document_id.into()
+        } else {
+            None
+        };
         let changes = self
             .jmap
-            .sieve_activate_script(
-                account_id,
-                if !name.is_empty() {
-                    self.get_script_id(account_id, &name).await?.into()
-                } else {
-                    None
-                },
-            )
+            .sieve_activate_script(account_id, document_id)
             .await?;
 
         // Write changes
@@ -58,6 +67,15 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
                 changelog.log_update(Collection::SieveScript, document_id);
             }
             self.jmap.commit_changes(account_id, changelog).await?;
+            self.log_sieve_change(
+                account_id,
+                if !name.is_empty() {
+                    format!("Script '{name}' activated")
+                } else {
+                    "Scripts deactivated".to_string()
+                },
+            )
+            .await;
         }
         Ok(StatusResponse::ok("Success").into_bytes())
     }
@@ -29,6 +29,7 @@ use std::{
 use lru_cache::LruCache;
 use nohash::NoHashHasher;
 use parking_lot::Mutex;
+use utils::map::stats::{CacheReport, CacheStats};
 
 use super::{TokenHash, Weights};
 
@@ -38,6 +39,7 @@ pub struct BayesTokenCache {
     negative: Mutex<LruCache<TokenHash, Instant, BuildHasherDefault<NoHashHasher<TokenHash>>>>,
     ttl_negative: Duration,
     ttl_positive: Duration,
+    stats: CacheStats,
 }
 
 #[derive(Debug, Clone)]
@@ -53,6 +55,7 @@ impl BayesTokenCache {
             negative: Mutex::new(LruCache::with_hasher(capacity, Default::default())),
             ttl_negative,
             ttl_positive,
+            stats: CacheStats::default(),
         }
     }
 
@@ -61,9 +64,12 @@ impl BayesTokenCache {
             let mut pos_cache = self.positive.lock();
             if let Some(entry) = pos_cache.get_mut(hash) {
                 return if entry.valid_until >= Instant::now() {
+                    self.stats.hit();
                     Some(Some(entry.item))
                 } else {
                     pos_cache.remove(hash);
+                    self.stats.evict(1);
+                    self.stats.miss();
                     None
                 };
             }
@@ -72,31 +78,52 @@ impl BayesTokenCache {
             let mut neg_cache = self.negative.lock();
             if let Some(entry) = neg_cache.get_mut(hash) {
                 return if *entry >= Instant::now() {
+                    self.stats.hit();
                     Some(None)
                 } else {
                     neg_cache.remove(hash);
+                    self.stats.evict(1);
+                    self.stats.miss();
                     None
                 };
             }
         }
 
+        self.stats.miss();
         None
     }
 
     pub fn insert_positive(&self, hash: TokenHash, weights: Weights) {
-        self.positive.lock().insert(
+        let mut cache = self.positive.lock();
+        if cache.len() >= cache.capacity() && !cache.contains_key(&hash) {
+            self.stats.evict(1);
+        }
+        cache.insert(
             hash,
             CacheItem {
                 item: weights,
                 valid_until: Instant::now() + self.ttl_positive,
             },
         );
+        self.stats.insert();
     }
 
     pub fn insert_negative(&self, hash: TokenHash) {
-        self.negative
-            .lock()
-            .insert(hash, Instant::now() + self.ttl_negative);
+        let mut cache = self.negative.lock();
+        if cache.len() >= cache.capacity() && !cache.contains_key(&hash) {
+            self.stats.evict(1);
+        }
+        cache.insert(hash, Instant::now() + self.ttl_negative);
+        self.stats.insert();
+    }
+
+    pub fn report(&self) -> CacheReport {
+        let (pos_cache, neg_cache) = (self.positive.lock(), self.negative.lock());
+        self.stats.report(
+            "bayes",
+            Some(pos_cache.len() + neg_cache.len()),
+            Some(pos_cache.capacity() + neg_cache.capacity()),
+        )
     }
 
     pub fn invalidate(&self, hash: &TokenHash) {
@@ -113,6 +140,7 @@ impl Default for BayesTokenCache {
             negative: Mutex::new(LruCache::with_hasher(1024, Default::default())),
             ttl_negative: Default::default(),
             ttl_positive: Default::default(),
+            stats: CacheStats::default(),
         }
     }
 }
@@ -0,0 +1,434 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+// Elements whose contents are never rendered as text, these are removed
+// along with everything they enclose.
+const RAW_TEXT_ELEMENTS: &[&str] = &[
+    "script", "style", "iframe", "object", "embed", "noscript", "noembed", "noframes", "template",
+    "textarea", "title", "xmp",
+];
+
+const VOID_ELEMENTS: &[&str] = &["br", "hr", "wbr"];
+
+const MAX_STRIP_PASSES: usize = 4;
+
+struct Tag<'x> {
+    name: Option<&'x str>,
+    is_closing: bool,
+    is_void: bool,
+    end: usize,
+}
+
+fn is_unsafe_char(c: char) -> bool {
+    (c.is_control() && !matches!(c, '\n' | '\t'))
+        || matches!(c, '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+}
+
+// Removes control and bidirectional override characters and, when the text
+// was obtained from an HTML part, any markup left behind after decoding entities.
+pub fn sanitize_text(text: &str, strip_tags: bool) -> Cow<'_, str> {
+    if !text
+        .chars()
+        .any(|c| is_unsafe_char(c) || (strip_tags && c == '<'))
+    {
+        return text.into();
+    }
+
+    let mut text = Cow::Borrowed(text);
+    for pass in 0..MAX_STRIP_PASSES {
+        let mut result = String::with_capacity(text.len());
+        let mut has_stripped = false;
+        let mut pos = 0;
+        while let Some(ch) = text[pos..].chars().next() {
+            if ch == '<' && strip_tags {
+                if let Some(tag) = parse_tag(&text, pos) {
+                    pos = tag.end;
+                    has_stripped = true;
+                    continue;
+                } else if pass == MAX_STRIP_PASSES - 1 {
+                    // Give up on nested fragments, drop any remaining brackets
+                    pos += 1;
+                    continue;
+                }
+            }
+            if !is_unsafe_char(ch) {
+                result.push(ch);
+            } else {
+                has_stripped = true;
+            }
+            pos += ch.len_utf8();
+        }
+        text = result.into();
+
+        // Removing characters can join fragments that form a new tag, keep
+        // going until nothing else is stripped.
+        if !has_stripped {
+            break;
+        }
+    }
+
+    text
+}
+
+// Sanitizes an HTML fragment keeping only the allowed tags, which are emitted
+// without attributes and always balanced. All other markup is removed and any
+// text is escaped, so the result is safe to render as HTML.
+pub fn sanitize_html(html: &str, allowed_tags: &[impl AsRef<str>]) -> String {
+    let mut result = String::with_capacity(html.len());
+    let mut open_tags: Vec<String> = Vec::new();
+    let mut pos = 0;
+
+    while let Some(ch) = html[pos..].chars().next() {
+        match ch {
+            '<' => {
+                if let Some(tag) = parse_tag(html, pos) {
+                    pos = tag.end;
+                    let name = if let Some(name) = tag.name {
+                        name.to_ascii_lowercase()
+                    } else {
+                        continue;
+                    };
+
+                    if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+                        if !tag.is_closing && !tag.is_void {
+                            pos = skip_element(html, pos, &name);
+                        }
+                    } else if allowed_tags
+                        .iter()
+                        .any(|allowed| allowed.as_ref().eq_ignore_ascii_case(&name))
+                    {
+                        if VOID_ELEMENTS.contains(&name.as_str()) {
+                            if !tag.is_closing {
+                                result.push('<');
+                                result.push_str(&name);
+                                result.push('>');
+                            }
+                        } else if tag.is_closing {
+                            if let Some(idx) = open_tags.iter().rposition(|open| open == &name) {
+                                for open in open_tags.drain(idx..).rev() {
+                                    result.push_str("</");
+                                    result.push_str(&open);
+                                    result.push('>');
+                                }
+                            }
+                        } else if !tag.is_void {
+                            result.push('<');
+                            result.push_str(&name);
+                            result.push('>');
+                            open_tags.push(name);
+                        }
+                    }
+                    continue;
+                }
+                result.push_str("&lt;");
+            }
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '&' => {
+                if is_entity(&html[pos..]) {
+                    result.push('&');
+                } else {
+                    result.push_str("&amp;");
+                }
+            }
+            _ if is_unsafe_char(ch) => (),
+            _ => result.push(ch),
+        }
+        pos += ch.len_utf8();
+    }
+
+    for open in open_tags.into_iter().rev() {
+        result.push_str("</");
+        result.push_str(&open);
+        result.push('>');
+    }
+
+    result
+}
+
+fn parse_tag(html: &str, start: usize) -> Option<Tag<'_>> {
+    let bytes = html.as_bytes();
+    let mut pos = start + 1;
+
+    // Comments, declarations and processing instructions are discarded
+    match bytes.get(pos)? {
+        b'!' if html[pos..].starts_with("!--") => {
+            return Some(Tag {
+                name: None,
+                is_closing: false,
+                is_void: false,
+                end: html[pos + 3..]
+                    .find("-->")
+                    .map_or(html.len(), |end| pos + 3 + end + 3),
+            });
+        }
+        b'!' | b'?' => {
+            return Some(Tag {
+                name: None,
+                is_closing: false,
+                is_void: false,
+                end: html[pos..]
+                    .find('>')
+                    .map_or(html.len(), |end| pos + end + 1),
+            });
+        }
+        _ => (),
+    }
+
+    let is_closing = bytes[pos] == b'/';
+    if is_closing {
+        pos += 1;
+    }
+    let name_start = pos;
+    if !bytes.get(pos)?.is_ascii_alphabetic() {
+        return None;
+    }
+    while bytes
+        .get(pos)
+        .map_or(false, |b| b.is_ascii_alphanumeric() || *b == b'-')
+    {
+        pos += 1;
+    }
+    let name = &html[name_start..pos];
+    match bytes.get(pos) {
+        Some(b'>' | b'/') => (),
+        Some(b) if b.is_ascii_whitespace() => (),
+        _ => return None,
+    }
+
+    // Skip attributes, an unterminated tag extends to the end of the input
+    let mut quote = None;
+    while let Some(&b) = bytes.get(pos) {
+        pos += 1;
+        match quote {
+            None if b == b'"' || b == b'\'' => quote = Some(b),
+            None if b == b'>' => {
+                return Some(Tag {
+                    name: Some(name),
+                    is_closing,
+                    is_void: pos >= 2 && bytes[pos - 2] == b'/',
+                    end: pos,
+                });
+            }
+            Some(q) if b == q => quote = None,
+            _ => (),
+        }
+    }
+
+    Some(Tag {
+        name: Some(name),
+        is_closing,
+        is_void: false,
+        end: html.len(),
+    })
+}
+
+fn skip_element(html: &str, pos: usize, name: &str) -> usize {
+    let bytes = html.as_bytes();
+    for (idx, _) in html[pos..].match_indices("</") {
+        let name_start = pos + idx + 2;
+        let name_end = name_start + name.len();
+        if html
+            .get(name_start..name_end)
+            .map_or(false, |tag| tag.eq_ignore_ascii_case(name))
+            && bytes.get(name_end).map_or(true, |b| {
+                *b == b'>' || *b == b'/' || b.is_ascii_whitespace()
+            })
+        {
+            return html[name_end..]
+                .find('>')
+                .map_or(html.len(), |end| name_end + end + 1);
+        }
+    }
+    html.len()
+}
+
+fn is_entity(text: &str) -> bool {
+    let rest = &text.as_bytes()[1..];
+    let (body, is_valid): (&[u8], fn(&u8) -> bool) = match rest {
+        [b'#', b'x' | b'X', body @ ..] => (body, u8::is_ascii_hexdigit),
+        [b'#', body @ ..] => (body, u8::is_ascii_digit),
+        [b, ..] if b.is_ascii_alphabetic() => (rest, u8::is_ascii_alphanumeric),
+        _ => return false,
+    };
+    let len = body.iter().take_while(|b| is_valid(b)).count();
+    (1..=32).contains(&len) && body.get(len) == Some(&b';')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sanitize_html, sanitize_text};
+
+    const ALLOWED: &[&str] = &["mark", "br"];
+
+    #[test]
+    fn sanitize_snippets() {
+        for (input, expected) in [
+            ("Hello <mark>world</mark>", "Hello <mark>world</mark>"),
+            (
+                "<MARK class=\"x\" onclick='alert(1)'>hi</mark>",
+                "<mark>hi</mark>",
+            ),
+            ("a<script>alert('<mark>')</script>b", "ab"),
+            (
+                "a<SCRIPT src=x>alert(1)</ScRiPt >b<style>*{}</style>c",
+                "abc",
+            ),
+            ("<img src=x onerror=alert(1)>text<br/>more", "text<br>more"),
+            ("<!-- <mark> -->x<!DOCTYPE html><?xml?>y", "xy"),
+            ("<mark><b>bold</mark></b>", "<mark>bold</mark>"),
+            ("<mark>unclosed", "<mark>unclosed</mark>"),
+            ("</mark>stray", "stray"),
+            ("1 < 2 > 0 & \"q\"", "1 &lt; 2 &gt; 0 &amp; &quot;q&quot;"),
+            ("&amp; &#60; &#x3c; &bogus", "&amp; &#60; &#x3c; &amp;bogus"),
+            ("<a href=\"javascript:x\">link", "link"),
+            ("<div title='a>b'>text</div>", "text"),
+            ("text<unterminated attr=\"x", "text"),
+            ("x\u{202e}y\u{0}z\u{7}", "xyz"),
+        ] {
+            assert_eq!(sanitize_html(input, ALLOWED), expected, "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn sanitize_previews() {
+        for (input, strip_tags, expected) in [
+            ("plain text", true, "plain text"),
+            ("<script>alert(1)</script> hi", true, "alert(1) hi"),
+            (
+                "Write to <john@example.org>",
+                true,
+                "Write to <john@example.org>",
+            ),
+            ("1 < 2", true, "1 < 2"),
+            (
+                "<b>kept</b> in text parts",
+                false,
+                "<b>kept</b> in text parts",
+            ),
+            ("bidi\u{202e}txt.exe\u{1b}[0m", false, "biditxt.exe[0m"),
+        ] {
+            assert_eq!(
+                sanitize_text(input, strip_tags),
+                expected,
+                "input: {input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn fuzz_malformed_html() {
+        const FRAGMENTS: &[&str] = &[
+            "<",
+            ">",
+            "/",
+            "</",
+            "/>",
+            "<!--",
+            "-->",
+            "<!",
+            "<?",
+            "\"",
+            "'",
+            "=",
+            " ",
+            "\n",
+            "&",
+            "&amp;",
+            "&#",
+            "&#x3c;",
+            ";",
+            "script",
+            "style",
+            "mark",
+            "MARK",
+            "br",
+            "img",
+            "src",
+            "onerror",
+            "alert(1)",
+            "javascript:",
+            "<mark>",
+            "</mark>",
+            "<script>",
+            "</script>",
+            "<style",
+            "<br/>",
+            "<img src=x onerror=",
+            "\u{202e}",
+            "\u{0}",
+            "é",
+            "孫子",
+            "text",
+        ];
+
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        for _ in 0..20000 {
+            let mut input = String::new();
+            for _ in 0..(next() % 24) {
+                input.push_str(FRAGMENTS[(next() % FRAGMENTS.len() as u64) as usize]);
+            }
+
+            // Only the allowed tags, without attributes, may appear in the output
+            let output = sanitize_html(&input, ALLOWED);
+            let mut depth = 0;
+            let mut remaining = output.as_str();
+            while let Some(pos) = remaining.find(['<', '>']) {
+                let tag = &remaining[pos..];
+                if let Some(rest) = tag.strip_prefix("<mark>") {
+                    depth += 1;
+                    remaining = rest;
+                } else if let Some(rest) = tag.strip_prefix("</mark>") {
+                    depth -= 1;
+                    assert!(depth >= 0, "unbalanced output {output:?} for {input:?}");
+                    remaining = rest;
+                } else if let Some(rest) = tag.strip_prefix("<br>") {
+                    remaining = rest;
+                } else {
+                    panic!("unexpected markup in {output:?} for {input:?}");
+                }
+            }
+            assert_eq!(depth, 0, "unbalanced output {output:?} for {input:?}");
+            assert!(!output.contains('"'), "{output:?}");
+            assert!(!output.contains(['\u{0}', '\u{202e}']), "{output:?}");
+
+            // Sanitizing twice yields the same result
+            assert_eq!(sanitize_html(&output, ALLOWED), output, "input: {input:?}");
+
+            // Previews never contain tags or control characters
+            let preview = sanitize_text(&input, true);
+            assert_eq!(sanitize_text(&preview, true), preview, "input: {input:?}");
+            assert!(!preview.contains("<mark>"), "{preview:?}");
+            assert!(!preview.contains(['\u{0}', '\u{202e}']), "{preview:?}");
+        }
+    }
+}
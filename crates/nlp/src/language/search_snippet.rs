@@ -136,12 +136,16 @@ pub fn generate_snippet(
     let mut terms = terms.iter().peekable();
 
     'outer: while let Some(term) = terms.next() {
-        if snippet.len() + ("<mark>".len() * 2) + term.len + 1 > 255 {
+        let term_text = text.get(term.offset..term.offset + term.len)?;
+        let term_len = term_text.chars().map(escape_char_len).sum::<usize>();
+        if snippet.len() + ("<mark>".len() * 2) + term_len + 1 > 255 {
             break;
         }
 
         snippet.push_str("<mark>");
-        snippet.push_str(text.get(term.offset..term.offset + term.len)?);
+        for char in term_text.chars() {
+            escape_char(char, &mut snippet);
+        }
         snippet.push_str("</mark>");
 
         let next_offset = if let Some(next_term) = terms.peek() {
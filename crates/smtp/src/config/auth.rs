@@ -33,6 +33,8 @@ use utils::config::{
     Config, DynValue,
 };
 
+use crate::outbound::mta_sts::{Mode, MxPattern, Policy};
+
 use super::{
     if_block::ConfigIf, ArcAuthConfig, ArcSealer, ConfigContext, DkimAuthConfig,
     DkimCanonicalization, DkimSigner, DmarcAuthConfig, EnvelopeKey, IfBlock, IpRevAuthConfig,
@@ -42,6 +44,7 @@ use super::{
 pub trait ConfigAuth {
     fn parse_mail_auth(&self, ctx: &ConfigContext) -> super::Result<MailAuthConfig>;
     fn parse_signatures(&self, ctx: &mut ConfigContext) -> super::Result<()>;
+    fn parse_mta_sts_policy(&self) -> super::Result<Option<Policy>>;
 }
 
 impl ConfigAuth for Config {
@@ -106,9 +109,48 @@ impl ConfigAuth for Config {
                     .parse_if_block("auth.iprev.verify", ctx, &envelope_conn_keys)?
                     .unwrap_or_else(|| IfBlock::new(VerifyStrategy::Relaxed)),
             },
+            mta_sts: self.parse_mta_sts_policy()?,
         })
     }
 
+    fn parse_mta_sts_policy(&self) -> super::Result<Option<Policy>> {
+        let mode = if let Some(mode) = self.property::<Mode>("auth.mta-sts.mode")? {
+            mode
+        } else {
+            return Ok(None);
+        };
+
+        // Publish the server hostname unless the MX hosts are listed explicitly
+        let mut mx = Vec::new();
+        for (key, value) in self.values("auth.mta-sts.mx") {
+            let value = value.trim().to_lowercase();
+            if let Some(suffix) = value.strip_prefix("*.") {
+                if !suffix.is_empty() {
+                    mx.push(MxPattern::StartsWith(suffix.to_string()));
+                    continue;
+                }
+            } else if !value.is_empty() && !value.contains('*') {
+                mx.push(MxPattern::Equals(value));
+                continue;
+            }
+            return Err(format!("Invalid MX pattern {value:?} for key {key:?}."));
+        }
+        if mx.is_empty() {
+            mx.push(MxPattern::Equals(
+                self.value_require("server.hostname")?.to_lowercase(),
+            ));
+        }
+
+        Ok(Some(Policy {
+            id: String::new(),
+            mode,
+            mx,
+            max_age: self
+                .property_or_static::<Duration>("auth.mta-sts.max-age", "7d")?
+                .as_secs(),
+        }))
+    }
+
     #[allow(clippy::type_complexity)]
     fn parse_signatures(&self, ctx: &mut ConfigContext) -> super::Result<()> {
         for id in self.sub_keys("signature", ".algorithm") {
@@ -316,6 +358,21 @@ impl ParseValue for VerifyStrategy {
     }
 }
 
+impl ParseValue for Mode {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "enforce" => Ok(Mode::Enforce),
+            "testing" => Ok(Mode::Testing),
+            "none" => Ok(Mode::None),
+            _ => Err(format!(
+                "Invalid MTA-STS mode {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
 impl ParseValue for DkimCanonicalization {
     fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
         if let Some((headers, body)) = value.split_once('/') {
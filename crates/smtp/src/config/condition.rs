@@ -164,9 +164,13 @@ impl ConfigCondition for Config {
                                     if s.id == value_str {
                                         s.internal_id.into()
                                     } else {
-                                        None
+                                        s.virtual_hosts
+                                            .iter()
+                                            .find(|vhost| vhost.id == value_str)
+                                            .map(|vhost| vhost.internal_id)
                                     }
                                 })
+                                .or_else(|| ctx.listeners.get(value_str).copied())
                                 .ok_or_else(|| {
                                     format!(
                                         "Listener {:?} does not exist for property {:?}.",
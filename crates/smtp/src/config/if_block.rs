@@ -334,6 +334,12 @@ impl IfBlock<Option<DynValue<EnvelopeKey>>> {
     }
 }
 
+impl IfBlock<bool> {
+    pub fn has_true_value(&self) -> bool {
+        self.default || self.if_then.iter().any(|v| v.then)
+    }
+}
+
 impl<T> IfBlock<Vec<T>> {
     pub fn has_empty_list(&self) -> bool {
         self.default.is_empty() || self.if_then.iter().any(|v| v.then.is_empty())
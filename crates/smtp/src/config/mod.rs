@@ -33,13 +33,13 @@ pub mod session;
 pub mod throttle;
 
 use std::{
-    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     path::PathBuf,
     sync::{atomic::AtomicU64, Arc},
     time::Duration,
 };
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use directory::{Directories, Directory};
 use mail_auth::{
     common::crypto::{Ed25519Key, RsaKey, Sha256},
@@ -51,9 +51,9 @@ use regex::Regex;
 use sieve::Sieve;
 use smtp_proto::MtPriority;
 use store::{LookupStore, Store, Stores};
-use utils::config::{ipmask::IpAddrMask, DynValue, Rate, Server, ServerProtocol};
+use utils::config::{cron::SimpleCron, ipmask::IpAddrMask, DynValue, Rate, Server, ServerProtocol};
 
-use crate::{core::Lookup, inbound::milter};
+use crate::{core::Lookup, inbound::milter, outbound::mta_sts::Policy};
 
 #[derive(Debug)]
 pub struct Host {
@@ -214,6 +214,7 @@ pub struct Extensions {
     pub future_release: IfBlock<Option<Duration>>,
     pub deliver_by: IfBlock<Option<Duration>>,
     pub mt_priority: IfBlock<Option<MtPriority>>,
+    pub xclient: IfBlock<bool>,
 }
 
 pub struct Auth {
@@ -224,11 +225,42 @@ pub struct Auth {
     pub must_match_sender: IfBlock<bool>,
     pub errors_max: IfBlock<usize>,
     pub errors_wait: IfBlock<Duration>,
+    pub sender_alignment: IfBlock<SenderAlignment>,
+    pub sender_alignment_exempt: Vec<directory::Type>,
+    pub sender_alignment_delegated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SenderAlignment {
+    #[default]
+    Disable,
+    Reject,
+    Rewrite,
+    Tag,
 }
 
 pub struct Mail {
     pub script: IfBlock<Option<Arc<Sieve>>>,
     pub rewrite: IfBlock<Option<DynValue<EnvelopeKey>>>,
+    pub verify: SenderVerifyConfig,
+}
+
+// Return paths are verified against the directory when the sender domain is
+// local, otherwise using a null-sender RCPT callout to the domain's MX hosts.
+pub struct SenderVerifyConfig {
+    pub action: IfBlock<SenderVerify>,
+    pub directory: IfBlock<Option<MaybeDynValue<Directory>>>,
+    pub timeout: Duration,
+    pub positive_ttl: Duration,
+    pub negative_ttl: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SenderVerify {
+    #[default]
+    Disable,
+    Tag,
+    Reject,
 }
 
 pub struct Rcpt {
@@ -243,12 +275,22 @@ pub struct Rcpt {
 
     // Limits
     pub max_recipients: IfBlock<usize>,
+
+    // Did-you-mean suggestions for unknown local recipients
+    pub suggest: RcptSuggest,
+}
+
+pub struct RcptSuggest {
+    pub enable: IfBlock<bool>,
+    pub max_distance: usize,
+    pub max_results: usize,
 }
 
 pub struct Data {
     pub script: IfBlock<Option<Arc<Sieve>>>,
     pub pipe_commands: Vec<Pipe>,
     pub milters: Vec<Milter>,
+    pub antivirus: Option<Antivirus>,
 
     // Limits
     pub max_messages: IfBlock<usize>,
@@ -262,6 +304,66 @@ pub struct Data {
     pub add_auth_results: IfBlock<bool>,
     pub add_message_id: IfBlock<bool>,
     pub add_date: IfBlock<bool>,
+
+    // Validation
+    pub validation: IfBlock<MessageValidation>,
+
+    // Disclaimers
+    pub disclaimer_text: IfBlock<Option<String>>,
+    pub disclaimer_html: IfBlock<Option<String>>,
+
+    // Filter time budget
+    pub budget: FilterBudgetConfig,
+
+    // Compliance archive
+    pub archive: IfBlock<Vec<String>>,
+
+    // Loop detection
+    pub loop_detection: LoopDetection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FilterStage {
+    Dkim = 0,
+    Arc = 1,
+    Dmarc = 2,
+    Milter = 3,
+    Pipe = 4,
+    Antivirus = 5,
+    Sieve = 6,
+}
+
+// Filtering stages are allocated a share of the remaining session
+// timeout, optional stages are skipped once the budget runs low.
+pub struct FilterBudgetConfig {
+    pub enable: IfBlock<bool>,
+    pub ratio: f64,
+    pub stages: [FilterStageBudget; FilterStage::ALL.len()],
+}
+
+// Messages are stamped with a loop header carrying a keyed hash of the
+// queue id and each recipient, so that messages returning to this server
+// for the same recipient can be told apart from forged headers.
+pub struct LoopDetection {
+    pub enable: IfBlock<bool>,
+    pub header: String,
+    pub key: [u8; 32],
+    pub max_hops: IfBlock<usize>,
+    pub delivered_to: IfBlock<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilterStageBudget {
+    pub weight: u32,
+    pub optional: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MessageValidation {
+    #[default]
+    Disable,
+    Fix,
+    Strict,
 }
 
 pub struct Pipe {
@@ -287,10 +389,26 @@ pub struct Milter {
     pub flags_protocol: Option<u32>,
 }
 
+// Messages are submitted to an ICAP server (RFC 3507) for scanning before
+// they are queued, the verdict is made available to Sieve scripts.
+pub struct Antivirus {
+    pub enable: IfBlock<bool>,
+    pub addrs: Vec<SocketAddr>,
+    pub hostname: String,
+    pub port: u16,
+    pub service: String,
+    pub timeout_connect: Duration,
+    pub timeout_data: Duration,
+    pub max_size: usize,
+    pub reject_infected: bool,
+    pub tempfail_on_error: bool,
+}
+
 pub struct SessionConfig {
     pub timeout: IfBlock<Duration>,
     pub duration: IfBlock<Duration>,
     pub transfer_limit: IfBlock<usize>,
+    pub error_codes: IfBlock<bool>,
     pub throttle: SessionThrottle,
 
     pub connect: Connect,
@@ -300,6 +418,38 @@ pub struct SessionConfig {
     pub rcpt: Rcpt,
     pub data: Data,
     pub extensions: Extensions,
+    pub trusted_peers: TrustedPeers,
+    pub account_sessions: AccountSessionLimits,
+    pub transparent: TransparentRelay,
+}
+
+// Listeners running in transparent relay mode scan incoming messages and
+// forward them to a fixed next hop with the original envelope and message
+// bytes, header additions are only made when explicitly enabled.
+pub struct TransparentRelay {
+    pub enable: IfBlock<bool>,
+    pub next_hop: Option<RelayHost>,
+    pub add_headers: bool,
+}
+
+// Partners presenting one of these client certificates, which must have
+// been verified by the listener, bypass throttling and are flagged to Sieve
+// scripts so greylisting and spam scoring can be skipped.
+#[derive(Debug, Default)]
+pub struct TrustedPeers {
+    pub fingerprints: AHashSet<String>,
+    pub issuers: Vec<String>,
+}
+
+// Maximum number of concurrent authenticated sessions per account, shared
+// by IMAP, JMAP and SMTP. Account limits take precedence over domain limits,
+// which take precedence over role limits.
+#[derive(Debug, Default)]
+pub struct AccountSessionLimits {
+    pub enable: bool,
+    pub max_concurrent: u64,
+    pub roles: Vec<(directory::Type, u64)>,
+    pub overrides: AHashMap<String, u64>,
 }
 
 pub struct SessionThrottle {
@@ -323,6 +473,7 @@ pub struct QueueConfig {
 
     // Schedule
     pub retry: IfBlock<Vec<Duration>>,
+    pub retry_strategy: IfBlock<Option<Arc<RetryStrategy>>>,
     pub notify: IfBlock<Vec<Duration>>,
     pub expire: IfBlock<Duration>,
 
@@ -331,6 +482,7 @@ pub struct QueueConfig {
     pub next_hop: IfBlock<Option<RelayHost>>,
     pub max_mx: IfBlock<usize>,
     pub max_multihomed: IfBlock<usize>,
+    pub max_rcpt: IfBlock<usize>,
     pub ip_strategy: IfBlock<IpLookupStrategy>,
     pub source_ip: QueueOutboundSourceIp,
     pub tls: QueueOutboundTls,
@@ -343,15 +495,48 @@ pub struct QueueConfig {
     pub throttle: QueueThrottle,
     pub quota: QueueQuotas,
 
+    // Delivery analytics
+    pub analytics: QueueAnalytics,
+
+    // Moderation of outbound mail
+    pub moderation: QueueModeration,
+
+    // Warm-up schedule of new source IPs
+    pub warmup: QueueWarmup,
+
     // Default store and directory
     pub directory: Arc<Directory>,
     pub data_store: Store,
     pub lookup_store: LookupStore,
 }
 
+pub struct RetryStrategy {
+    pub name: String,
+    pub intervals: Vec<Duration>,
+    pub multiplier: f64,
+    pub cap: Option<Duration>,
+    pub jitter: Duration,
+    pub give_up: Option<Duration>,
+}
+
+pub enum RetrySchedule<'x> {
+    Intervals(&'x [Duration]),
+    Strategy {
+        strategy: &'x RetryStrategy,
+        created: u64,
+    },
+}
+
 pub struct QueueOutboundSourceIp {
     pub ipv4: IfBlock<Vec<Ipv4Addr>>,
     pub ipv6: IfBlock<Vec<Ipv6Addr>>,
+    pub pool: IfBlock<Option<Arc<SourceIpPool>>>,
+}
+
+pub struct SourceIpPool {
+    pub name: String,
+    pub ipv4: Vec<Ipv4Addr>,
+    pub ipv6: Vec<Ipv6Addr>,
 }
 
 pub struct ReportConfig {
@@ -365,6 +550,8 @@ pub struct ReportConfig {
     pub dmarc: Report,
     pub dmarc_aggregate: AggregateReport,
     pub tls: AggregateReport,
+    pub abuse: AbuseReport,
+    pub operator: OperatorReports,
 }
 
 pub struct ReportAnalysis {
@@ -372,6 +559,45 @@ pub struct ReportAnalysis {
     pub forward: bool,
     pub store: Option<PathBuf>,
     pub report_id: AtomicU64,
+    pub feedback: FeedbackAnalysis,
+}
+
+pub struct FeedbackAnalysis {
+    pub enable: bool,
+    pub store: Option<LookupStore>,
+    pub score: f64,
+    pub expiry: Duration,
+}
+
+pub struct AbuseReport {
+    pub report: Report,
+    pub rcpts: Vec<String>,
+    pub notify_origin: bool,
+}
+
+pub struct OperatorReports {
+    pub report: Report,
+    pub schedules: Vec<OperatorSchedule>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OperatorSchedule {
+    pub id: String,
+    pub enable: bool,
+    pub frequency: SimpleCron,
+    pub period: Duration,
+    pub rcpts: Vec<String>,
+    pub sections: Vec<OperatorSection>,
+    pub top_domains: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OperatorSection {
+    Queue,
+    Deferred,
+    Spam,
+    Auth,
 }
 
 pub enum AddressMatch {
@@ -418,6 +644,32 @@ pub struct QueueOutboundTls {
     pub mta_sts: IfBlock<RequireOptional>,
     pub start: IfBlock<RequireOptional>,
     pub invalid_certs: IfBlock<bool>,
+    pub fallback: IfBlock<TlsFallback>,
+}
+
+pub struct QueueAnalytics {
+    pub enable: bool,
+    pub hourly_retention: Duration,
+    pub daily_retention: Duration,
+    pub max_domains: usize,
+    pub prometheus_domains: usize,
+}
+
+pub struct QueueModeration {
+    pub expire: Duration,
+    pub notify: Vec<String>,
+    pub notify_interval: Duration,
+    pub accounts: AHashMap<String, String>,
+}
+
+// Daily sending caps applied to new source IPs, per destination provider,
+// until the last day of the ramp schedule is reached. Providers are matched
+// by MX hostname suffix, unmatched hosts share the "other" provider.
+#[derive(Debug, Default)]
+pub struct QueueWarmup {
+    pub ips: AHashSet<IpAddr>,
+    pub schedule: Vec<u64>,
+    pub providers: Vec<(String, String)>,
 }
 
 pub struct QueueOutboundTimeout {
@@ -475,12 +727,21 @@ pub enum RequireOptional {
     Disable,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TlsFallback {
+    #[default]
+    FailClosed,
+    Opportunistic,
+    Plaintext,
+}
+
 pub struct MailAuthConfig {
     pub dkim: DkimAuthConfig,
     pub arc: ArcAuthConfig,
     pub spf: SpfAuthConfig,
     pub dmarc: DmarcAuthConfig,
     pub iprev: IpRevAuthConfig,
+    pub mta_sts: Option<Policy>,
 }
 
 pub enum DkimSigner {
@@ -532,6 +793,7 @@ pub enum VerifyStrategy {
 #[derive(Default)]
 pub struct ConfigContext<'x> {
     pub servers: &'x [Server],
+    pub listeners: AHashMap<String, u16>,
     pub hosts: AHashMap<String, Host>,
     pub scripts: AHashMap<String, Arc<Sieve>>,
     pub directory: Directories,
@@ -21,8 +21,13 @@
  * for more details.
 */
 
-use std::time::Duration;
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Arc,
+    time::Duration,
+};
 
+use ahash::AHashMap;
 use mail_send::Credentials;
 
 use super::{
@@ -39,7 +44,12 @@ use utils::config::{
 pub trait ConfigQueue {
     fn parse_queue(&self, ctx: &ConfigContext) -> super::Result<QueueConfig>;
     fn parse_queue_throttle(&self, ctx: &ConfigContext) -> super::Result<QueueThrottle>;
+    fn parse_retry_strategies(&self) -> super::Result<AHashMap<String, Arc<RetryStrategy>>>;
+    fn parse_retry_strategy(&self, id: &str) -> super::Result<RetryStrategy>;
+    fn parse_source_ip_pools(&self) -> super::Result<AHashMap<String, Arc<SourceIpPool>>>;
+    fn parse_source_ip_pool(&self, id: &str) -> super::Result<SourceIpPool>;
     fn parse_queue_quota(&self, ctx: &ConfigContext) -> super::Result<QueueQuotas>;
+    fn parse_queue_warmup(&self) -> super::Result<QueueWarmup>;
     fn parse_queue_quota_item(
         &self,
         prefix: impl AsKey,
@@ -82,6 +92,8 @@ impl ConfigQueue for Config {
             .unwrap_or_else(|| IfBlock::new(None));
 
         let default_hostname = self.value_require("server.hostname")?;
+        let retry_strategies = self.parse_retry_strategies()?;
+        let source_ip_pools = self.parse_source_ip_pools()?;
 
         let config = QueueConfig {
             path: self
@@ -105,6 +117,18 @@ impl ConfigQueue for Config {
                         Duration::from_secs(2 * 3600),
                     ])
                 }),
+            retry_strategy: self
+                .parse_if_block::<Option<String>>(
+                    "queue.schedule.retry-strategy",
+                    ctx,
+                    &host_envelope_keys,
+                )?
+                .unwrap_or_else(|| IfBlock::new(None))
+                .map_if_block(
+                    &retry_strategies,
+                    "queue.schedule.retry-strategy",
+                    "retry strategy",
+                )?,
             notify: self
                 .parse_if_block("queue.schedule.notify", ctx, &rcpt_envelope_keys)?
                 .unwrap_or_else(|| {
@@ -125,6 +149,9 @@ impl ConfigQueue for Config {
             max_multihomed: self
                 .parse_if_block("queue.outbound.limits.multihomed", ctx, &rcpt_envelope_keys)?
                 .unwrap_or_else(|| IfBlock::new(2)),
+            max_rcpt: self
+                .parse_if_block("queue.outbound.limits.rcpt", ctx, &mx_envelope_keys)?
+                .unwrap_or_else(|| IfBlock::new(100)),
             ip_strategy: self
                 .parse_if_block("queue.outbound.ip-strategy", ctx, &sender_envelope_keys)?
                 .unwrap_or_else(|| IfBlock::new(IpLookupStrategy::Ipv4thenIpv6)),
@@ -135,6 +162,18 @@ impl ConfigQueue for Config {
                 ipv6: self
                     .parse_if_block("queue.outbound.source-ip.v6", ctx, &mx_envelope_keys)?
                     .unwrap_or_else(|| IfBlock::new(Vec::new())),
+                pool: self
+                    .parse_if_block::<Option<String>>(
+                        "queue.outbound.source-ip.pool",
+                        ctx,
+                        &mx_envelope_keys,
+                    )?
+                    .unwrap_or_else(|| IfBlock::new(None))
+                    .map_if_block(
+                        &source_ip_pools,
+                        "queue.outbound.source-ip.pool",
+                        "source IP pool",
+                    )?,
             },
             next_hop: next_hop.into_relay_host(ctx)?,
             tls: QueueOutboundTls {
@@ -154,9 +193,39 @@ impl ConfigQueue for Config {
                         &mx_envelope_keys,
                     )?
                     .unwrap_or_else(|| IfBlock::new(false)),
+                fallback: self
+                    .parse_if_block("queue.outbound.tls.fallback", ctx, &mx_envelope_keys)?
+                    .unwrap_or_default(),
             },
             throttle: self.parse_queue_throttle(ctx)?,
             quota: self.parse_queue_quota(ctx)?,
+            analytics: QueueAnalytics {
+                enable: self.property_or_static("queue.analytics.enable", "true")?,
+                hourly_retention: self
+                    .property_or_static("queue.analytics.retention.hourly", "2d")?,
+                daily_retention: self
+                    .property_or_static("queue.analytics.retention.daily", "90d")?,
+                max_domains: self.property_or_static("queue.analytics.max-domains", "10000")?,
+                prometheus_domains: self
+                    .property_or_static("queue.analytics.prometheus.top-domains", "20")?,
+            },
+            moderation: QueueModeration {
+                expire: self.property_or_static("queue.moderation.expire", "3d")?,
+                notify: self
+                    .values("queue.moderation.notify")
+                    .map(|(_, address)| address.to_string())
+                    .collect(),
+                notify_interval: self
+                    .property_or_static("queue.moderation.notify-interval", "1h")?,
+                accounts: self
+                    .values("queue.moderation.accounts")
+                    .filter_map(|(key, reason)| {
+                        key.strip_prefix("queue.moderation.accounts.")
+                            .map(|account| (account.to_lowercase(), reason.to_string()))
+                    })
+                    .collect(),
+            },
+            warmup: self.parse_queue_warmup()?,
             timeout: QueueOutboundTimeout {
                 connect: self
                     .parse_if_block("queue.outbound.timeouts.connect", ctx, &host_envelope_keys)?
@@ -237,6 +306,104 @@ impl ConfigQueue for Config {
         }
     }
 
+    fn parse_retry_strategies(&self) -> super::Result<AHashMap<String, Arc<RetryStrategy>>> {
+        let mut strategies = AHashMap::new();
+        for id in self.sub_keys("queue.retry-strategy", "") {
+            strategies.insert(id.to_string(), Arc::new(self.parse_retry_strategy(id)?));
+        }
+
+        Ok(strategies)
+    }
+
+    fn parse_retry_strategy(&self, id: &str) -> super::Result<RetryStrategy> {
+        let mut intervals = Vec::new();
+        for result in self.properties::<Duration>(("queue.retry-strategy", id, "intervals")) {
+            intervals.push(result?.1);
+        }
+        if intervals.is_empty() {
+            return Err(format!(
+                "Retry strategy {id:?} must contain at least one interval."
+            ));
+        }
+
+        let multiplier = self
+            .property(("queue.retry-strategy", id, "multiplier"))?
+            .unwrap_or(1.0);
+        if !(1.0..=100.0).contains(&multiplier) {
+            return Err(format!(
+                "Invalid multiplier {multiplier} for retry strategy {id:?}, must be between 1 and 100."
+            ));
+        }
+
+        Ok(RetryStrategy {
+            name: id.to_string(),
+            intervals,
+            multiplier,
+            cap: self.property(("queue.retry-strategy", id, "cap"))?,
+            jitter: self
+                .property(("queue.retry-strategy", id, "jitter"))?
+                .unwrap_or_default(),
+            give_up: self.property(("queue.retry-strategy", id, "give-up"))?,
+        })
+    }
+
+    fn parse_source_ip_pools(&self) -> super::Result<AHashMap<String, Arc<SourceIpPool>>> {
+        let mut pools = AHashMap::new();
+        for id in self.sub_keys("queue.ip-pool", "") {
+            pools.insert(id.to_string(), Arc::new(self.parse_source_ip_pool(id)?));
+        }
+
+        Ok(pools)
+    }
+
+    fn parse_source_ip_pool(&self, id: &str) -> super::Result<SourceIpPool> {
+        let mut ipv4 = Vec::new();
+        for result in self.properties::<Ipv4Addr>(("queue.ip-pool", id, "v4")) {
+            ipv4.push(result?.1);
+        }
+        let mut ipv6 = Vec::new();
+        for result in self.properties::<Ipv6Addr>(("queue.ip-pool", id, "v6")) {
+            ipv6.push(result?.1);
+        }
+        if ipv4.is_empty() && ipv6.is_empty() {
+            return Err(format!(
+                "Source IP pool {id:?} must contain at least one IPv4 or IPv6 address."
+            ));
+        }
+
+        Ok(SourceIpPool {
+            name: id.to_string(),
+            ipv4,
+            ipv6,
+        })
+    }
+
+    fn parse_queue_warmup(&self) -> super::Result<QueueWarmup> {
+        let mut warmup = QueueWarmup::default();
+        for result in self.properties::<IpAddr>("queue.warmup.ips") {
+            warmup.ips.insert(result?.1);
+        }
+        for result in self.properties::<u64>("queue.warmup.schedule") {
+            warmup.schedule.push(result?.1);
+        }
+        if !warmup.ips.is_empty() && warmup.schedule.is_empty() {
+            return Err(
+                "Property \"queue.warmup.schedule\" must contain at least one daily limit."
+                    .to_string(),
+            );
+        }
+        for provider in self.sub_keys("queue.warmup.providers", "") {
+            for (_, suffix) in self.values(("queue.warmup.providers", provider)) {
+                warmup.providers.push((
+                    suffix.trim().trim_end_matches('.').to_lowercase(),
+                    provider.to_string(),
+                ));
+            }
+        }
+
+        Ok(warmup)
+    }
+
     fn parse_queue_throttle(&self, ctx: &ConfigContext) -> super::Result<QueueThrottle> {
         // Parse throttle
         let mut throttle = QueueThrottle {
@@ -465,6 +632,32 @@ impl From<&Host> for RelayHost {
     }
 }
 
+impl RelayHost {
+    pub fn is_same_destination(&self, other: &RelayHost) -> bool {
+        self.address == other.address
+            && self.port == other.port
+            && self.protocol == other.protocol
+            && self.tls_implicit == other.tls_implicit
+            && self.tls_allow_invalid_certs == other.tls_allow_invalid_certs
+            && self.auth == other.auth
+    }
+}
+
+impl ParseValue for TlsFallback {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "fail-closed" | "fail" | "none" => Ok(TlsFallback::FailClosed),
+            "opportunistic" => Ok(TlsFallback::Opportunistic),
+            "plaintext" | "plain-text" => Ok(TlsFallback::Plaintext),
+            _ => Err(format!(
+                "Invalid TLS fallback value {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
 impl ParseValue for RequireOptional {
     fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
         match value {
@@ -21,9 +21,12 @@
  * for more details.
 */
 
+use std::time::Duration;
+
 use super::{
-    if_block::ConfigIf, AddressMatch, AggregateFrequency, AggregateReport, ConfigContext,
-    EnvelopeKey, IfBlock, Report, ReportAnalysis, ReportConfig,
+    if_block::ConfigIf, AbuseReport, AddressMatch, AggregateFrequency, AggregateReport,
+    ConfigContext, EnvelopeKey, FeedbackAnalysis, IfBlock, OperatorReports, OperatorSchedule,
+    OperatorSection, Report, ReportAnalysis, ReportConfig,
 };
 use utils::config::{
     utils::{AsKey, ParseValue},
@@ -46,6 +49,11 @@ pub trait ConfigReport {
         default_hostname: &str,
         available_keys: &[EnvelopeKey],
     ) -> super::Result<AggregateReport>;
+    fn parse_operator_reports(
+        &self,
+        ctx: &ConfigContext,
+        default_hostname: &str,
+    ) -> super::Result<OperatorReports>;
 }
 
 impl ConfigReport for Config {
@@ -84,6 +92,17 @@ impl ConfigReport for Config {
                 &sender_envelope_keys,
             )?,
             tls: self.parse_aggregate_report(ctx, "tls", default_hostname, &rcpt_envelope_keys)?,
+            abuse: AbuseReport {
+                report: self.parse_report(ctx, "abuse", default_hostname, &sender_envelope_keys)?,
+                rcpts: self
+                    .values("report.abuse.to")
+                    .map(|(_, addr)| addr.to_lowercase())
+                    .collect(),
+                notify_origin: self
+                    .property("report.abuse.notify-origin")?
+                    .unwrap_or(false),
+            },
+            operator: self.parse_operator_reports(ctx, default_hostname)?,
             path: self
                 .parse_if_block("report.path", ctx, &sender_envelope_keys)?
                 .ok_or("Missing \"report.path\" property.")?,
@@ -98,6 +117,31 @@ impl ConfigReport for Config {
                 forward: self.property("report.analysis.forward")?.unwrap_or(false),
                 store: self.property("report.analysis.store")?,
                 report_id: 0.into(),
+                feedback: FeedbackAnalysis {
+                    enable: self
+                        .property("report.analysis.feedback.enable")?
+                        .unwrap_or(true),
+                    store: if let Some(id) = self.value("report.analysis.feedback.store") {
+                        ctx.stores
+                            .lookup_stores
+                            .get(id)
+                            .ok_or_else(|| {
+                                format!(
+                                    "Lookup store {id:?} not found for key \"report.analysis.feedback.store\"."
+                                )
+                            })?
+                            .clone()
+                            .into()
+                    } else {
+                        None
+                    },
+                    score: self
+                        .property("report.analysis.feedback.score")?
+                        .unwrap_or(10.0),
+                    expiry: self
+                        .property("report.analysis.feedback.expire")?
+                        .unwrap_or(Duration::from_secs(30 * 86400)),
+                },
             },
         })
     }
@@ -197,6 +241,59 @@ impl ConfigReport for Config {
                 .unwrap_or_else(|| IfBlock::new(25 * 1024 * 1024)),
         })
     }
+
+    fn parse_operator_reports(
+        &self,
+        ctx: &ConfigContext,
+        default_hostname: &str,
+    ) -> super::Result<OperatorReports> {
+        let mut schedules = Vec::new();
+        for id in self.sub_keys("report.operator.schedule", ".frequency") {
+            let mut sections = Vec::new();
+            for section in
+                self.properties::<OperatorSection>(("report.operator.schedule", id, "sections"))
+            {
+                sections.push(section?.1);
+            }
+            if sections.is_empty() {
+                sections = vec![
+                    OperatorSection::Queue,
+                    OperatorSection::Deferred,
+                    OperatorSection::Spam,
+                    OperatorSection::Auth,
+                ];
+            }
+
+            schedules.push(OperatorSchedule {
+                id: id.to_string(),
+                enable: self
+                    .property(("report.operator.schedule", id, "enable"))?
+                    .unwrap_or(true),
+                frequency: self.property_require(("report.operator.schedule", id, "frequency"))?,
+                period: self
+                    .property(("report.operator.schedule", id, "period"))?
+                    .unwrap_or(Duration::from_secs(86400)),
+                rcpts: self
+                    .values(("report.operator.schedule", id, "to"))
+                    .map(|(_, addr)| addr.to_lowercase())
+                    .collect(),
+                sections,
+                top_domains: self
+                    .property(("report.operator.schedule", id, "top-domains"))?
+                    .unwrap_or(10),
+            });
+        }
+
+        Ok(OperatorReports {
+            report: self.parse_report(
+                ctx,
+                "operator",
+                default_hostname,
+                &[EnvelopeKey::RecipientDomain],
+            )?,
+            schedules,
+        })
+    }
 }
 
 impl ParseValue for AggregateFrequency {
@@ -215,6 +312,22 @@ impl ParseValue for AggregateFrequency {
     }
 }
 
+impl ParseValue for OperatorSection {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "queue" => Ok(OperatorSection::Queue),
+            "deferred" => Ok(OperatorSection::Deferred),
+            "spam" => Ok(OperatorSection::Spam),
+            "auth" => Ok(OperatorSection::Auth),
+            _ => Err(format!(
+                "Invalid operator report section {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
 impl ParseValue for AddressMatch {
     fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
         if let Some(value) = value.strip_prefix('*').map(|v| v.trim()) {
@@ -21,8 +21,14 @@
  * for more details.
 */
 
-use std::io::Read;
+use std::{
+    io::Read,
+    net::{Ipv4Addr, Ipv6Addr},
+    sync::Arc,
+    time::Duration,
+};
 
+use ahash::AHashMap;
 use mail_auth::{
     common::lru::{DnsCache, LruCache},
     flate2::read::GzDecoder,
@@ -30,15 +36,19 @@ use mail_auth::{
         config::{ResolverConfig, ResolverOpts},
         system_conf::read_system_conf,
     },
-    Resolver,
+    Resolver, MX,
 };
 
-use crate::{core::Resolvers, outbound::dane::DnssecResolver};
+use crate::{
+    core::{DnsOverride, DnsOverrides, Resolvers},
+    outbound::dane::DnssecResolver,
+};
 use utils::{config::Config, suffixlist::PublicSuffix};
 
 pub trait ConfigResolver {
     fn build_resolvers(&self) -> super::Result<Resolvers>;
     fn parse_public_suffix(&self) -> super::Result<PublicSuffix>;
+    fn parse_dns_overrides(&self) -> super::Result<DnsOverrides>;
 }
 
 impl ConfigResolver for Config {
@@ -101,10 +111,84 @@ impl ConfigResolver for Config {
                 mta_sts: LruCache::with_capacity(
                     self.property("resolver.cache.mta-sts")?.unwrap_or(1024),
                 ),
+                rbl: LruCache::with_capacity(self.property("resolver.cache.rbl")?.unwrap_or(4096)),
+                rbl_positive_ttl: self
+                    .property("resolver.rbl.positive-ttl")?
+                    .unwrap_or(Duration::from_secs(3600)),
+                rbl_negative_ttl: self
+                    .property("resolver.rbl.negative-ttl")?
+                    .unwrap_or(Duration::from_secs(600)),
+                tlsa_stats: Default::default(),
+                mta_sts_stats: Default::default(),
+                rbl_stats: Default::default(),
             },
+            overrides: self.parse_dns_overrides()?,
         })
     }
 
+    fn parse_dns_overrides(&self) -> super::Result<DnsOverrides> {
+        let mut entries = AHashMap::new();
+
+        for id in self.sub_keys("resolver.override", ".domain") {
+            let domain = self
+                .value_require(("resolver.override", id, "domain"))?
+                .trim_end_matches('.')
+                .to_lowercase();
+
+            // Parse MX records as "<preference> <exchange>"
+            let mut mx: Vec<MX> = Vec::new();
+            for (key, value) in self.values(("resolver.override", id, "mx")) {
+                let (preference, exchange) = value
+                    .trim()
+                    .split_once(' ')
+                    .and_then(|(preference, exchange)| {
+                        Some((preference.parse::<u16>().ok()?, exchange.trim()))
+                    })
+                    .filter(|(_, exchange)| !exchange.is_empty())
+                    .ok_or_else(|| format!("Invalid MX record {value:?} for property {key:?}."))?;
+                let exchange = exchange.trim_end_matches('.').to_lowercase();
+                if let Some(mx) = mx.iter_mut().find(|mx| mx.preference == preference) {
+                    mx.exchanges.push(exchange);
+                } else {
+                    mx.push(MX {
+                        exchanges: vec![exchange],
+                        preference,
+                    });
+                }
+            }
+            mx.sort_unstable_by_key(|mx| mx.preference);
+
+            let mut ipv4 = Vec::new();
+            for (key, _) in self.values(("resolver.override", id, "ipv4")) {
+                ipv4.push(self.property_require::<Ipv4Addr>(key)?);
+            }
+            let mut ipv6 = Vec::new();
+            for (key, _) in self.values(("resolver.override", id, "ipv6")) {
+                ipv6.push(self.property_require::<Ipv6Addr>(key)?);
+            }
+
+            if mx.is_empty() && ipv4.is_empty() && ipv6.is_empty() {
+                return Err(format!(
+                    "DNS override {id:?} does not define any MX, IPv4 or IPv6 records."
+                ));
+            }
+
+            entries.insert(
+                domain,
+                DnsOverride {
+                    mx: (!mx.is_empty()).then(|| Arc::new(mx)),
+                    ipv4: (!ipv4.is_empty()).then(|| Arc::new(ipv4)),
+                    ipv6: (!ipv6.is_empty()).then(|| Arc::new(ipv6)),
+                    ttl: self.property_or_static(("resolver.override", id, "ttl"), "1h")?,
+                    expires: Default::default(),
+                    hits: Default::default(),
+                },
+            );
+        }
+
+        Ok(DnsOverrides { entries })
+    }
+
     fn parse_public_suffix(&self) -> super::Result<PublicSuffix> {
         let mut has_values = false;
         for (_, value) in self.values("resolver.public-suffix") {
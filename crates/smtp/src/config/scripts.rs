@@ -37,6 +37,7 @@ use crate::{
 };
 use utils::{
     config::{utils::AsKey, Config},
+    map::stats::CacheStats,
     suffixlist::PublicSuffix,
 };
 
@@ -44,6 +45,13 @@ use super::{resolver::ConfigResolver, ConfigContext};
 
 pub trait ConfigSieve {
     fn parse_sieve(&self, ctx: &mut ConfigContext) -> super::Result<SieveCore>;
+    fn parse_sieve_environment<C>(
+        &self,
+        runtime: Runtime<C>,
+        prefix: &str,
+        name: &'static str,
+        location: &'static str,
+    ) -> super::Result<Runtime<C>>;
 }
 
 #[derive(Default)]
@@ -51,6 +59,7 @@ pub struct SieveContext {
     pub psl: PublicSuffix,
     pub bayes_cache: BayesTokenCache,
     pub remote_lists: RemoteLists,
+    pub verdict_stats: CacheStats,
 }
 
 pub struct RemoteLists {
@@ -82,6 +91,7 @@ impl ConfigSieve for Config {
                 self.property_or_static("bayes.cache.ttl.negative", "1h")?,
             ),
             remote_lists: Default::default(),
+            verdict_stats: Default::default(),
         };
 
         // Allocate compiler and runtime
@@ -152,6 +162,8 @@ impl ConfigSieve for Config {
             self.value_require("server.hostname")?
         };
         runtime.set_local_hostname(hostname.to_string());
+        let runtime =
+            self.parse_sieve_environment(runtime, "sieve.trusted", "Stalwart SMTP", "MTA")?;
 
         // Parse scripts
         for id in self.sub_keys("sieve.trusted.scripts", "") {
@@ -210,4 +222,56 @@ impl ConfigSieve for Config {
             sign,
         })
     }
+
+    fn parse_sieve_environment<C>(
+        &self,
+        runtime: Runtime<C>,
+        prefix: &str,
+        name: &'static str,
+        location: &'static str,
+    ) -> super::Result<Runtime<C>> {
+        // RFC 5183 items that are the same for every script execution,
+        // session specific items are exposed as "env" variables instead.
+        let host = if let Some(hostname) = self.value((prefix, "hostname")) {
+            hostname
+        } else {
+            self.value_require("server.hostname")?
+        }
+        .to_lowercase();
+        let domain = if let Some(domain) = self.value((prefix, "environment.domain")) {
+            domain.to_lowercase()
+        } else {
+            host.split_once('.')
+                .map_or(host.as_str(), |(_, domain)| domain)
+                .to_string()
+        };
+        let version = match self
+            .value((prefix, "environment.version"))
+            .unwrap_or("full")
+        {
+            "full" => Some(env!("CARGO_PKG_VERSION")),
+            "minor" => env!("CARGO_PKG_VERSION")
+                .rsplit_once('.')
+                .map(|(version, _)| version),
+            "none" => None,
+            value => {
+                return Err(format!(
+                    "Invalid value {value:?} for property {:?}, expected \"full\", \"minor\" or \"none\".",
+                    (prefix, "environment.version").as_key()
+                ))
+            }
+        };
+
+        let mut runtime = runtime
+            .with_env_variable("name", name)
+            .with_env_variable("location", location)
+            .with_env_variable("phase", "during")
+            .with_env_variable("host", host)
+            .with_env_variable("domain", domain);
+        if let Some(version) = version {
+            runtime = runtime.with_env_variable("version", version);
+        }
+
+        Ok(runtime)
+    }
 }
@@ -26,6 +26,7 @@ use std::{net::ToSocketAddrs, time::Duration};
 use smtp_proto::*;
 
 use super::{if_block::ConfigIf, throttle::ConfigThrottle, *};
+use crate::inbound::mail_loop::LOOP_KEY_CONTEXT;
 use utils::config::{
     utils::{AsKey, ParseValue},
     Config, DynValue,
@@ -34,6 +35,9 @@ use utils::config::{
 pub trait ConfigSession {
     fn parse_session_config(&self, ctx: &ConfigContext) -> super::Result<SessionConfig>;
     fn parse_session_throttle(&self, ctx: &ConfigContext) -> super::Result<SessionThrottle>;
+    fn parse_trusted_peers(&self) -> TrustedPeers;
+    fn parse_transparent_relay(&self, ctx: &ConfigContext) -> super::Result<TransparentRelay>;
+    fn parse_account_sessions(&self) -> super::Result<AccountSessionLimits>;
     fn parse_session_connect(&self, ctx: &ConfigContext) -> super::Result<Connect>;
     fn parse_extensions(&self, ctx: &ConfigContext) -> super::Result<Extensions>;
     fn parse_session_ehlo(&self, ctx: &ConfigContext) -> super::Result<Ehlo>;
@@ -51,6 +55,21 @@ pub trait ConfigSession {
         ctx: &ConfigContext,
         available_keys: &[EnvelopeKey],
     ) -> super::Result<Vec<Milter>>;
+    fn parse_antivirus(
+        &self,
+        ctx: &ConfigContext,
+        available_keys: &[EnvelopeKey],
+    ) -> super::Result<Option<Antivirus>>;
+    fn parse_filter_budget(
+        &self,
+        ctx: &ConfigContext,
+        available_keys: &[EnvelopeKey],
+    ) -> super::Result<FilterBudgetConfig>;
+    fn parse_loop_detection(
+        &self,
+        ctx: &ConfigContext,
+        available_keys: &[EnvelopeKey],
+    ) -> super::Result<LoopDetection>;
 }
 
 impl ConfigSession for Config {
@@ -68,6 +87,9 @@ impl ConfigSession for Config {
             transfer_limit: self
                 .parse_if_block("session.transfer-limit", ctx, &available_keys)?
                 .unwrap_or_else(|| IfBlock::new(250 * 1024 * 1024)),
+            error_codes: self
+                .parse_if_block("session.error-codes", ctx, &available_keys)?
+                .unwrap_or_else(|| IfBlock::new(false)),
             timeout: self
                 .parse_if_block::<Option<Duration>>("session.timeout", ctx, &available_keys)?
                 .unwrap_or_else(|| IfBlock::new(Some(Duration::from_secs(5 * 60))))
@@ -81,9 +103,100 @@ impl ConfigSession for Config {
             rcpt: self.parse_session_rcpt(ctx)?,
             data: self.parse_session_data(ctx)?,
             extensions: self.parse_extensions(ctx)?,
+            trusted_peers: self.parse_trusted_peers(),
+            account_sessions: self.parse_account_sessions()?,
+            transparent: self.parse_transparent_relay(ctx)?,
+        })
+    }
+
+    fn parse_transparent_relay(&self, ctx: &ConfigContext) -> super::Result<TransparentRelay> {
+        let enable = self
+            .parse_if_block(
+                "session.transparent.enable",
+                ctx,
+                &[
+                    EnvelopeKey::Listener,
+                    EnvelopeKey::RemoteIp,
+                    EnvelopeKey::LocalIp,
+                ],
+            )?
+            .unwrap_or_else(|| IfBlock::new(false));
+        let next_hop = if let Some(next_hop) = self.value("session.transparent.next-hop") {
+            RelayHost::from(ctx.hosts.get(next_hop).ok_or_else(|| {
+                format!(
+                    "Relay host {next_hop:?} not found for property \"session.transparent.next-hop\".",
+                )
+            })?)
+            .into()
+        } else if enable.has_true_value() {
+            return Err(
+                "Property \"session.transparent.next-hop\" is required when transparent relay mode is enabled."
+                    .to_string(),
+            );
+        } else {
+            None
+        };
+
+        Ok(TransparentRelay {
+            enable,
+            next_hop,
+            add_headers: self
+                .property("session.transparent.add-headers")?
+                .unwrap_or(false),
         })
     }
 
+    fn parse_account_sessions(&self) -> super::Result<AccountSessionLimits> {
+        let mut limits = AccountSessionLimits {
+            enable: self
+                .property("global.account-sessions.enable")?
+                .unwrap_or(false),
+            max_concurrent: self
+                .property("global.account-sessions.max-concurrent")?
+                .unwrap_or(20),
+            ..Default::default()
+        };
+        for role in self.sub_keys("global.account-sessions.roles", "") {
+            let typ = directory::Type::parse(role).ok_or_else(|| {
+                format!("Invalid role {role:?} in global.account-sessions.roles.")
+            })?;
+            limits.roles.push((
+                typ,
+                self.property_require(("global.account-sessions.roles", role))?,
+            ));
+        }
+        for (key, value) in self.values("global.account-sessions.overrides") {
+            let (name, limit) = value
+                .rsplit_once('=')
+                .and_then(|(name, limit)| {
+                    Some((
+                        name.trim().to_lowercase(),
+                        limit.trim().parse::<u64>().ok()?,
+                    ))
+                })
+                .filter(|(name, _)| !name.is_empty())
+                .ok_or_else(|| format!("Invalid session limit {value:?} for key {key:?}."))?;
+            limits.overrides.insert(name, limit);
+        }
+
+        Ok(limits)
+    }
+
+    fn parse_trusted_peers(&self) -> TrustedPeers {
+        TrustedPeers {
+            fingerprints: self
+                .values("session.tls.trusted-peers.fingerprints")
+                .map(|(_, fingerprint)| fingerprint.replace(':', "").trim().to_lowercase())
+                .filter(|fingerprint| !fingerprint.is_empty())
+                .collect(),
+            issuers: self
+                .values("session.tls.trusted-peers.issuers")
+                .map(|(_, issuer)| issuer.trim().to_string())
+                .filter(|issuer| !issuer.is_empty())
+                .collect(),
+        }
+    }
+
     fn parse_session_throttle(&self, ctx: &ConfigContext) -> super::Result<SessionThrottle> {
         // Parse throttle
         let mut throttle = SessionThrottle {
@@ -212,6 +325,9 @@ impl ConfigSession for Config {
             mt_priority: self
                 .parse_if_block("session.extensions.mt-priority", ctx, &available_keys)?
                 .unwrap_or_default(),
+            xclient: self
+                .parse_if_block("session.extensions.xclient", ctx, &available_keys)?
+                .unwrap_or_default(),
         })
     }
 
@@ -290,6 +406,18 @@ impl ConfigSession for Config {
             must_match_sender: self
                 .parse_if_block("session.auth.must-match-sender", ctx, &available_keys)?
                 .unwrap_or_else(|| IfBlock::new(true)),
+            sender_alignment: self
+                .parse_if_block("session.auth.sender-alignment.mode", ctx, &available_keys)?
+                .unwrap_or_default(),
+            sender_alignment_exempt: self
+                .values("session.auth.sender-alignment.exempt-roles")
+                .map(|(key, value)| {
+                    directory::Type::parse(value)
+                        .ok_or_else(|| format!("Invalid role {value:?} for key {key:?}."))
+                })
+                .collect::<super::Result<Vec<_>>>()?,
+            sender_alignment_delegated: self
+                .property_or_static("session.auth.sender-alignment.delegated", "true")?,
         })
     }
 
@@ -315,6 +443,28 @@ impl ConfigSession for Config {
                     &available_keys,
                 )?
                 .unwrap_or_default(),
+            verify: SenderVerifyConfig {
+                action: self
+                    .parse_if_block("session.mail.verify.action", ctx, &available_keys)?
+                    .unwrap_or_default(),
+                directory: self
+                    .parse_if_block::<Option<DynValue<EnvelopeKey>>>(
+                        "session.mail.verify.directory",
+                        ctx,
+                        &available_keys,
+                    )?
+                    .unwrap_or_default()
+                    .map_if_block(
+                        &ctx.directory.directories,
+                        "session.mail.verify.directory",
+                        "lookup list",
+                    )?,
+                timeout: self.property_or_static("session.mail.verify.timeout", "30s")?,
+                positive_ttl: self
+                    .property_or_static("session.mail.verify.cache.positive-ttl", "1d")?,
+                negative_ttl: self
+                    .property_or_static("session.mail.verify.cache.negative-ttl", "1h")?,
+            },
         })
     }
 
@@ -375,6 +525,17 @@ impl ConfigSession for Config {
                     &available_keys_full,
                 )?
                 .unwrap_or_default(),
+            suggest: RcptSuggest {
+                enable: self
+                    .parse_if_block("session.rcpt.suggest.enable", ctx, &available_keys_full)?
+                    .unwrap_or_else(|| IfBlock::new(false)),
+                max_distance: self
+                    .property("session.rcpt.suggest.max-distance")?
+                    .unwrap_or(2),
+                max_results: self
+                    .property("session.rcpt.suggest.max-results")?
+                    .unwrap_or(3),
+            },
         })
     }
 
@@ -429,11 +590,99 @@ impl ConfigSession for Config {
             add_date: self
                 .parse_if_block("session.data.add-headers.date", ctx, &available_keys)?
                 .unwrap_or_else(|| IfBlock::new(true)),
+            validation: self
+                .parse_if_block("session.data.validation", ctx, &available_keys)?
+                .unwrap_or_default(),
+            disclaimer_text: self
+                .parse_if_block("session.data.disclaimer.text", ctx, &available_keys)?
+                .unwrap_or_default(),
+            disclaimer_html: self
+                .parse_if_block("session.data.disclaimer.html", ctx, &available_keys)?
+                .unwrap_or_default(),
             pipe_commands: self.parse_pipes(ctx, &available_keys)?,
             milters: self.parse_milters(ctx, &available_keys)?,
+            antivirus: self.parse_antivirus(ctx, &available_keys)?,
+            budget: self.parse_filter_budget(ctx, &available_keys)?,
+            archive: self
+                .parse_if_block("session.data.archive.recipients", ctx, &available_keys)?
+                .unwrap_or_default(),
+            loop_detection: self.parse_loop_detection(ctx, &available_keys)?,
         })
     }
 
+    fn parse_loop_detection(
+        &self,
+        ctx: &ConfigContext,
+        available_keys: &[EnvelopeKey],
+    ) -> super::Result<LoopDetection> {
+        let mut detection = LoopDetection {
+            enable: self
+                .parse_if_block("session.data.loop.enable", ctx, available_keys)?
+                .unwrap_or_else(|| IfBlock::new(false)),
+            max_hops: self
+                .parse_if_block("session.data.loop.max-hops", ctx, available_keys)?
+                .unwrap_or_else(|| IfBlock::new(3)),
+            delivered_to: self
+                .parse_if_block("session.data.loop.delivered-to", ctx, available_keys)?
+                .unwrap_or_else(|| IfBlock::new(true)),
+            ..Default::default()
+        };
+        if let Some(header) = self.value("session.data.loop.header") {
+            let header = header.trim();
+            if header.is_empty()
+                || !header
+                    .bytes()
+                    .all(|ch| ch.is_ascii_alphanumeric() || ch == b'-')
+            {
+                return Err(format!(
+                    "Invalid header name {header:?} for property \"session.data.loop.header\"."
+                ));
+            }
+            detection.header = header.to_string();
+        }
+
+        // Without a configured secret, loops are only detected until the next restart
+        if let Some(secret) = self.value("session.data.loop.secret") {
+            detection.key = blake3::derive_key(LOOP_KEY_CONTEXT, secret.as_bytes());
+        }
+
+        Ok(detection)
+    }
+
+    fn parse_filter_budget(
+        &self,
+        ctx: &ConfigContext,
+        available_keys: &[EnvelopeKey],
+    ) -> super::Result<FilterBudgetConfig> {
+        let mut budget = FilterBudgetConfig {
+            enable: self
+                .parse_if_block("session.data.budget.enable", ctx, available_keys)?
+                .unwrap_or_else(|| IfBlock::new(false)),
+            ratio: self.property("session.data.budget.ratio")?.unwrap_or(0.8),
+            ..Default::default()
+        };
+        if !(budget.ratio > 0.0 && budget.ratio <= 1.0) {
+            return Err(format!(
+                "Invalid value {} for session.data.budget.ratio, expected a value between 0 and 1.",
+                budget.ratio
+            ));
+        }
+        for id in self.sub_keys("session.data.budget.stage", "") {
+            let stage = FilterStage::parse(id).ok_or_else(|| {
+                format!("Unknown filter stage {id:?} in session.data.budget.stage.")
+            })?;
+            let stage_budget = &mut budget.stages[stage as usize];
+            if let Some(weight) = self.property(("session.data.budget.stage", id, "weight"))? {
+                stage_budget.weight = weight;
+            }
+            if let Some(optional) = self.property(("session.data.budget.stage", id, "optional"))? {
+                stage_budget.optional = optional;
+            }
+        }
+
+        Ok(budget)
+    }
+
     fn parse_pipes(
         &self,
         ctx: &ConfigContext,
@@ -518,6 +767,92 @@ impl ConfigSession for Config {
         }
         Ok(milters)
     }
+
+    fn parse_antivirus(
+        &self,
+        ctx: &ConfigContext,
+        available_keys: &[EnvelopeKey],
+    ) -> super::Result<Option<Antivirus>> {
+        let hostname = if let Some(hostname) = self.value("session.data.antivirus.hostname") {
+            hostname.to_string()
+        } else {
+            return Ok(None);
+        };
+        let port = self.property_or_static("session.data.antivirus.port", "1344")?;
+        let service = self
+            .value("session.data.antivirus.service")
+            .unwrap_or("avscan")
+            .trim_matches('/')
+            .to_string();
+
+        Ok(Some(Antivirus {
+            enable: self
+                .parse_if_block("session.data.antivirus.enable", ctx, available_keys)?
+                .unwrap_or_default(),
+            addrs: format!("{}:{}", hostname, port)
+                .to_socket_addrs()
+                .map_err(|err| format!("Unable to resolve ICAP hostname {hostname}: {err}"))?
+                .collect(),
+            hostname,
+            port,
+            service,
+            timeout_connect: self
+                .property_or_static("session.data.antivirus.timeout.connect", "30s")?,
+            timeout_data: self.property_or_static("session.data.antivirus.timeout.data", "60s")?,
+            max_size: self
+                .property_or_static("session.data.antivirus.options.max-size", "26214400")?,
+            reject_infected: self
+                .property_or_static("session.data.antivirus.options.reject-infected", "true")?,
+            tempfail_on_error: self
+                .property_or_static("session.data.antivirus.options.tempfail-on-error", "true")?,
+        }))
+    }
+}
+
+impl ParseValue for SenderVerify {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "reject" => Ok(SenderVerify::Reject),
+            "tag" => Ok(SenderVerify::Tag),
+            "off" | "disable" | "disabled" | "none" => Ok(SenderVerify::Disable),
+            _ => Err(format!(
+                "Invalid value {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
+impl ParseValue for SenderAlignment {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "reject" => Ok(SenderAlignment::Reject),
+            "rewrite" => Ok(SenderAlignment::Rewrite),
+            "tag" => Ok(SenderAlignment::Tag),
+            "off" | "disable" | "disabled" | "none" => Ok(SenderAlignment::Disable),
+            _ => Err(format!(
+                "Invalid value {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
+impl ParseValue for MessageValidation {
+    fn parse_value(key: impl AsKey, value: &str) -> super::Result<Self> {
+        match value {
+            "fix" => Ok(MessageValidation::Fix),
+            "strict" => Ok(MessageValidation::Strict),
+            "off" | "disable" | "disabled" | "none" => Ok(MessageValidation::Disable),
+            _ => Err(format!(
+                "Invalid value {:?} for key {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
 }
 
 struct Mechanism {
@@ -143,6 +143,24 @@ impl ParseValue for EnvelopeKey {
     }
 }
 
+impl EnvelopeKey {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EnvelopeKey::Recipient => "rcpt",
+            EnvelopeKey::RecipientDomain => "rcpt-domain",
+            EnvelopeKey::Sender => "sender",
+            EnvelopeKey::SenderDomain => "sender-domain",
+            EnvelopeKey::Mx => "mx",
+            EnvelopeKey::HeloDomain => "helo-domain",
+            EnvelopeKey::AuthenticatedAs => "authenticated-as",
+            EnvelopeKey::Listener => "listener",
+            EnvelopeKey::RemoteIp => "remote-ip",
+            EnvelopeKey::LocalIp => "local-ip",
+            EnvelopeKey::Priority => "priority",
+        }
+    }
+}
+
 pub trait ParseTrottleKey {
     fn parse_throttle_key(&self, key: &str) -> super::Result<u16>;
 }
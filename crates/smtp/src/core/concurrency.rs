@@ -0,0 +1,145 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dashmap::DashMap;
+use serde::Serialize;
+use utils::{
+    config::ServerProtocol,
+    listener::limiter::{ConcurrencyLimiter, InFlight},
+};
+
+use crate::config::AccountSessionLimits;
+
+use super::SMTP;
+
+const PROTOCOLS: [&str; 3] = ["imap", "jmap", "smtp"];
+
+#[derive(Debug, Default)]
+pub struct AccountSessions {
+    limiters: DashMap<String, ConcurrencyLimiter>,
+    granted: [AtomicU64; PROTOCOLS.len()],
+    rejected: [AtomicU64; PROTOCOLS.len()],
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct AccountSessionsReport {
+    pub accounts: usize,
+    pub sessions: u64,
+    pub protocols: Vec<ProtocolSessionsReport>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ProtocolSessionsReport {
+    pub protocol: &'static str,
+    pub granted: u64,
+    pub rejected: u64,
+}
+
+impl SMTP {
+    // Reserves a slot in the session budget the account shares across protocols,
+    // the slot is released when the returned guard is dropped. When the budget
+    // is exhausted, the limit that applies to the account is returned instead.
+    pub fn acquire_account_session(
+        &self,
+        account: &str,
+        typ: directory::Type,
+        protocol: ServerProtocol,
+    ) -> Result<Option<InFlight>, u64> {
+        let limits = &self.session.config.account_sessions;
+        if !limits.enable {
+            return Ok(None);
+        }
+
+        let account = account.to_lowercase();
+        let limit = limits.limit(&account, typ);
+        let sessions = &self.session.account_sessions;
+        let protocol = protocol_index(protocol);
+        let in_flight = sessions
+            .limiters
+            .entry(account)
+            .or_insert_with(|| ConcurrencyLimiter::new(limit))
+            .is_allowed();
+        if in_flight.is_some() {
+            sessions.granted[protocol].fetch_add(1, Ordering::Relaxed);
+            Ok(in_flight)
+        } else {
+            sessions.rejected[protocol].fetch_add(1, Ordering::Relaxed);
+            Err(limit)
+        }
+    }
+}
+
+impl AccountSessionLimits {
+    pub fn limit(&self, account: &str, typ: directory::Type) -> u64 {
+        self.overrides
+            .get(account)
+            .or_else(|| {
+                account
+                    .rsplit_once('@')
+                    .and_then(|(_, domain)| self.overrides.get(domain))
+            })
+            .or_else(|| {
+                self.roles
+                    .iter()
+                    .find_map(|(role, limit)| (*role == typ).then_some(limit))
+            })
+            .copied()
+            .unwrap_or(self.max_concurrent)
+    }
+}
+
+impl AccountSessions {
+    pub fn cleanup(&self) {
+        self.limiters.retain(|_, limiter| limiter.is_active());
+    }
+
+    pub fn report(&self) -> AccountSessionsReport {
+        AccountSessionsReport {
+            accounts: self.limiters.len(),
+            sessions: self
+                .limiters
+                .iter()
+                .map(|limiter| limiter.concurrent.load(Ordering::Relaxed))
+                .sum(),
+            protocols: PROTOCOLS
+                .iter()
+                .enumerate()
+                .map(|(idx, protocol)| ProtocolSessionsReport {
+                    protocol,
+                    granted: self.granted[idx].load(Ordering::Relaxed),
+                    rejected: self.rejected[idx].load(Ordering::Relaxed),
+                })
+                .collect(),
+        }
+    }
+}
+
+fn protocol_index(protocol: ServerProtocol) -> usize {
+    match protocol {
+        ServerProtocol::Imap | ServerProtocol::ManageSieve => 0,
+        ServerProtocol::Jmap | ServerProtocol::Http => 1,
+        ServerProtocol::Smtp | ServerProtocol::Lmtp => 2,
+    }
+}
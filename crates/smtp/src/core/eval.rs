@@ -0,0 +1,208 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    borrow::Cow,
+    net::{IpAddr, Ipv4Addr},
+};
+
+use ahash::AHashMap;
+use directory::Directories;
+use serde::{Deserialize, Serialize};
+use store::Stores;
+use utils::config::{Config, KeyLookup};
+
+use crate::{
+    config::{if_block::ConfigIf, ConfigContext, EnvelopeKey},
+    queue::DomainPart,
+};
+
+use super::SMTP;
+
+// Configuration snapshot used to evaluate expressions on demand
+#[derive(Default)]
+pub struct EvalCore {
+    pub settings: Config,
+    pub listeners: AHashMap<String, u16>,
+    pub directory: Directories,
+    pub stores: Stores,
+}
+
+pub enum Expression<'x> {
+    Inline(&'x str),
+    Setting(&'x str),
+}
+
+pub struct EvalEnvelope {
+    pub sender: String,
+    pub sender_domain: String,
+    pub rcpt: String,
+    pub rcpt_domain: String,
+    pub helo_domain: String,
+    pub authenticated_as: String,
+    pub mx: String,
+    pub listener: String,
+    pub listener_id: u16,
+    pub remote_ip: IpAddr,
+    pub local_ip: IpAddr,
+    pub priority: i16,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EvalResult {
+    pub result: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub matched: Option<usize>,
+    pub trace: Vec<EvalStep>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EvalStep {
+    pub block: usize,
+    pub key: String,
+    pub value: String,
+    pub op: String,
+    pub expected: String,
+    pub matched: bool,
+}
+
+const EVAL_KEYS: [EnvelopeKey; 11] = [
+    EnvelopeKey::Recipient,
+    EnvelopeKey::RecipientDomain,
+    EnvelopeKey::Sender,
+    EnvelopeKey::SenderDomain,
+    EnvelopeKey::Mx,
+    EnvelopeKey::HeloDomain,
+    EnvelopeKey::AuthenticatedAs,
+    EnvelopeKey::Listener,
+    EnvelopeKey::RemoteIp,
+    EnvelopeKey::LocalIp,
+    EnvelopeKey::Priority,
+];
+
+impl SMTP {
+    pub async fn eval_expression(
+        &self,
+        expression: Expression<'_>,
+        envelope: &EvalEnvelope,
+    ) -> Result<EvalResult, String> {
+        let (settings, key) = match expression {
+            Expression::Inline(expr) => (
+                Cow::Owned(
+                    Config::new(&format!("expr = {expr}"))
+                        .map_err(|err| format!("Failed to parse expression: {err}"))?,
+                ),
+                "expr",
+            ),
+            Expression::Setting(key) => (Cow::Borrowed(&self.eval.settings), key),
+        };
+
+        // Parse the expression using the server's listeners and lookups
+        let mut ctx = ConfigContext::new(&[]);
+        ctx.listeners = self.eval.listeners.clone();
+        ctx.directory = self.eval.directory.clone();
+        ctx.stores = self.eval.stores.clone();
+        let if_block = settings
+            .parse_if_block::<Vec<String>>(key, &ctx, &EVAL_KEYS)?
+            .ok_or_else(|| format!("Property {key:?} not found."))?;
+
+        let mut trace = Vec::new();
+        let (matched, result) = if_block.eval_and_trace(envelope, &mut trace).await;
+
+        Ok(EvalResult {
+            result: result.clone(),
+            matched,
+            trace,
+        })
+    }
+}
+
+impl EvalEnvelope {
+    pub fn with_sender(mut self, sender: &str) -> Self {
+        self.sender = sender.to_lowercase();
+        self.sender_domain = self.sender.domain_part().to_string();
+        self
+    }
+
+    pub fn with_rcpt(mut self, rcpt: &str) -> Self {
+        self.rcpt = rcpt.to_lowercase();
+        self.rcpt_domain = self.rcpt.domain_part().to_string();
+        self
+    }
+}
+
+impl Default for EvalEnvelope {
+    fn default() -> Self {
+        Self {
+            sender: Default::default(),
+            sender_domain: Default::default(),
+            rcpt: Default::default(),
+            rcpt_domain: Default::default(),
+            helo_domain: Default::default(),
+            authenticated_as: Default::default(),
+            mx: Default::default(),
+            listener: Default::default(),
+            listener_id: Default::default(),
+            remote_ip: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            local_ip: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            priority: Default::default(),
+        }
+    }
+}
+
+impl KeyLookup for EvalEnvelope {
+    type Key = EnvelopeKey;
+
+    fn key(&self, key: &Self::Key) -> Cow<'_, str> {
+        match key {
+            EnvelopeKey::Recipient => self.rcpt.as_str().into(),
+            EnvelopeKey::RecipientDomain => self.rcpt_domain.as_str().into(),
+            EnvelopeKey::Sender => self.sender.as_str().into(),
+            EnvelopeKey::SenderDomain => self.sender_domain.as_str().into(),
+            EnvelopeKey::Mx => self.mx.as_str().into(),
+            EnvelopeKey::HeloDomain => self.helo_domain.as_str().into(),
+            EnvelopeKey::AuthenticatedAs => self.authenticated_as.as_str().into(),
+            EnvelopeKey::Listener => self.listener.as_str().into(),
+            EnvelopeKey::RemoteIp => self.remote_ip.to_string().into(),
+            EnvelopeKey::LocalIp => self.local_ip.to_string().into(),
+            EnvelopeKey::Priority => self.priority.to_string().into(),
+        }
+    }
+
+    fn key_as_int(&self, key: &Self::Key) -> i32 {
+        match key {
+            EnvelopeKey::Listener => self.listener_id as i32,
+            EnvelopeKey::Priority => self.priority as i32,
+            _ => 0,
+        }
+    }
+
+    fn key_as_ip(&self, key: &Self::Key) -> IpAddr {
+        match key {
+            EnvelopeKey::RemoteIp => self.remote_ip,
+            EnvelopeKey::LocalIp => self.local_ip,
+            _ => IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+        }
+    }
+}
@@ -29,6 +29,8 @@ use crate::config::{
     Condition, ConditionMatch, Conditions, EnvelopeKey, IfBlock, MaybeDynValue, StringMatch,
 };
 
+use super::eval::EvalStep;
+
 pub struct Captures<'x, T> {
     value: &'x T,
     captures: Vec<String>,
@@ -63,6 +65,24 @@ impl<T: Default> IfBlock<T> {
             captures: vec![],
         }
     }
+
+    pub async fn eval_and_trace(
+        &self,
+        envelope: &impl KeyLookup<Key = EnvelopeKey>,
+        trace: &mut Vec<EvalStep>,
+    ) -> (Option<usize>, &T) {
+        for (block, if_then) in self.if_then.iter().enumerate() {
+            if if_then
+                .conditions
+                .eval_and_trace(envelope, block, trace)
+                .await
+            {
+                return (Some(block), &if_then.then);
+            }
+        }
+
+        (None, &self.default)
+    }
 }
 
 impl Conditions {
@@ -195,6 +215,126 @@ impl Conditions {
             None
         }
     }
+
+    pub async fn eval_and_trace(
+        &self,
+        envelope: &impl KeyLookup<Key = EnvelopeKey>,
+        block: usize,
+        trace: &mut Vec<EvalStep>,
+    ) -> bool {
+        let mut conditions = self.conditions.iter();
+        let mut matched = false;
+
+        while let Some(rule) = conditions.next() {
+            match rule {
+                Condition::Match { key, value, not } => {
+                    let (op, result, expected, ctx_value) = match value {
+                        ConditionMatch::String(value) => {
+                            let ctx_value = envelope.key(key);
+                            match value {
+                                StringMatch::Equal(value) => (
+                                    if *not { "ne" } else { "eq" },
+                                    value.eq(ctx_value.as_ref()),
+                                    value.to_string(),
+                                    ctx_value,
+                                ),
+                                StringMatch::StartsWith(value) => (
+                                    if *not {
+                                        "not-starts-with"
+                                    } else {
+                                        "starts-with"
+                                    },
+                                    ctx_value.starts_with(value),
+                                    value.to_string(),
+                                    ctx_value,
+                                ),
+                                StringMatch::EndsWith(value) => (
+                                    if *not { "not-ends-with" } else { "ends-with" },
+                                    ctx_value.ends_with(value),
+                                    value.to_string(),
+                                    ctx_value,
+                                ),
+                            }
+                        }
+                        ConditionMatch::IpAddrMask(value) => {
+                            let ip = envelope.key_as_ip(key);
+                            (
+                                if *not { "ne" } else { "eq" },
+                                value.matches(&ip),
+                                value.to_string(),
+                                ip.to_string().into(),
+                            )
+                        }
+                        ConditionMatch::UInt(value) => (
+                            if *not { "ne" } else { "eq" },
+                            *value == envelope.key_as_int(key) as u16,
+                            value.to_string(),
+                            envelope.key(key),
+                        ),
+                        ConditionMatch::Int(value) => (
+                            if *not { "ne" } else { "eq" },
+                            *value == envelope.key_as_int(key) as i16,
+                            value.to_string(),
+                            envelope.key(key),
+                        ),
+                        ConditionMatch::Lookup(lookup) => {
+                            let ctx_value = envelope.key(key);
+                            let op = if *not { "not-in-list" } else { "in-list" };
+                            if let Some(result) = lookup.contains(ctx_value.as_ref()).await {
+                                (op, result, String::new(), ctx_value)
+                            } else {
+                                trace.push(EvalStep {
+                                    block,
+                                    key: key.as_str().to_string(),
+                                    value: ctx_value.into_owned(),
+                                    op: op.to_string(),
+                                    expected: String::new(),
+                                    matched: false,
+                                });
+                                return false;
+                            }
+                        }
+                        ConditionMatch::Regex(value) => {
+                            let ctx_value = envelope.key(key);
+                            (
+                                if *not { "not-matches" } else { "matches" },
+                                value.is_match(ctx_value.as_ref()),
+                                value.as_str().to_string(),
+                                ctx_value,
+                            )
+                        }
+                    };
+                    matched = result ^ not;
+                    trace.push(EvalStep {
+                        block,
+                        key: key.as_str().to_string(),
+                        value: ctx_value.into_owned(),
+                        op: op.to_string(),
+                        expected,
+                        matched,
+                    });
+                }
+                Condition::JumpIfTrue { positions } => {
+                    if matched {
+                        //TODO use advance_by when stabilized
+                        for _ in 0..*positions {
+                            conditions.next();
+                        }
+                    }
+                }
+                Condition::JumpIfFalse { positions } => {
+                    if !matched {
+                        //TODO use advance_by when stabilized
+                        for _ in 0..*positions {
+                            conditions.next();
+                        }
+                    }
+                }
+            }
+        }
+
+        matched
+    }
 }
 
 impl<'x> Captures<'x, DynValue<EnvelopeKey>> {
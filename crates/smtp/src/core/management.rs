@@ -21,7 +21,10 @@
  * for more details.
 */
 
-use std::{borrow::Cow, fmt::Display, net::IpAddr, sync::Arc, time::Instant};
+use std::{
+    borrow::Cow, collections::BTreeMap, fmt::Display, net::IpAddr, path::PathBuf, sync::Arc,
+    time::Instant,
+};
 
 use directory::{AuthResult, Type};
 use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full};
@@ -41,14 +44,21 @@ use tokio::sync::oneshot;
 use utils::listener::{limiter::InFlight, SessionData, SessionManager, SessionStream};
 
 use crate::{
-    queue::{self, instant_to_timestamp, InstantFromTimestamp, QueueId, Status},
+    queue::{
+        self, archive::ExportedMessage, deferred::DeferReason, instant_to_timestamp,
+        InstantFromTimestamp, QueueId, Status,
+    },
     reporting::{
         self,
         scheduler::{ReportKey, ReportPolicy, ReportType, ReportValue},
     },
+    scripts::plugins::bayes,
 };
 
-use super::{SmtpAdminSessionManager, SMTP};
+use super::{
+    eval::{EvalEnvelope, Expression},
+    SmtpAdminSessionManager, SMTP,
+};
 
 #[derive(Debug)]
 pub enum QueueRequest {
@@ -57,6 +67,7 @@ pub enum QueueRequest {
         to: Option<String>,
         before: Option<Instant>,
         after: Option<Instant>,
+        reason: Option<DeferReason>,
         result_tx: oneshot::Sender<Vec<u64>>,
     },
     Status {
@@ -71,9 +82,42 @@ pub enum QueueRequest {
     Retry {
         queue_ids: Vec<QueueId>,
         item: Option<String>,
+        reason: Option<DeferReason>,
         time: Instant,
         result_tx: oneshot::Sender<Vec<bool>>,
     },
+    Deferred {
+        domain: Option<String>,
+        result_tx: oneshot::Sender<Vec<DeferredSummary>>,
+    },
+    Parked {
+        account: Option<String>,
+        result_tx: oneshot::Sender<Vec<ParkedMessage>>,
+    },
+    Moderate {
+        queue_ids: Vec<QueueId>,
+        account: Option<String>,
+        approve: bool,
+        result_tx: oneshot::Sender<Vec<QueueId>>,
+    },
+    Scheduled {
+        senders: Option<Vec<String>>,
+        result_tx: oneshot::Sender<Vec<ScheduledMessage>>,
+    },
+    Reschedule {
+        queue_ids: Vec<QueueId>,
+        senders: Option<Vec<String>>,
+        release: Option<Instant>,
+        result_tx: oneshot::Sender<Vec<bool>>,
+    },
+    Source {
+        queue_id: QueueId,
+        result_tx: oneshot::Sender<Option<(PathBuf, usize)>>,
+    },
+    Export {
+        remove: bool,
+        result_tx: oneshot::Sender<Vec<ExportedMessage>>,
+    },
 }
 
 #[derive(Debug)]
@@ -111,6 +155,9 @@ pub struct Message {
     pub priority: i16,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub env_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub parked: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -120,6 +167,9 @@ pub struct Domain {
     pub recipients: Vec<Recipient>,
 
     pub retry_num: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub retry_strategy: Option<String>,
     #[serde(deserialize_with = "deserialize_maybe_datetime")]
     #[serde(serialize_with = "serialize_maybe_datetime")]
     pub next_retry: Option<DateTime>,
@@ -129,6 +179,9 @@ pub struct Domain {
     #[serde(deserialize_with = "deserialize_datetime")]
     #[serde(serialize_with = "serialize_datetime")]
     pub expires: DateTime,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub defer_reason: Option<DeferReason>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -139,6 +192,126 @@ pub struct Recipient {
     pub orcpt: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ParkedMessage {
+    pub id: QueueId,
+    pub account: String,
+    pub return_path: String,
+    pub recipients: Vec<String>,
+    pub size: usize,
+    #[serde(deserialize_with = "deserialize_datetime")]
+    #[serde(serialize_with = "serialize_datetime")]
+    pub created: DateTime,
+    #[serde(deserialize_with = "deserialize_datetime")]
+    #[serde(serialize_with = "serialize_datetime")]
+    pub expires: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ScheduledMessage {
+    pub id: QueueId,
+    pub return_path: String,
+    pub recipients: Vec<String>,
+    pub size: usize,
+    #[serde(deserialize_with = "deserialize_datetime")]
+    #[serde(serialize_with = "serialize_datetime")]
+    pub created: DateTime,
+    #[serde(deserialize_with = "deserialize_datetime")]
+    #[serde(serialize_with = "serialize_datetime")]
+    pub release: DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DeferredSummary {
+    pub reason: DeferReason,
+    pub messages: usize,
+    pub domains: BTreeMap<String, usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ModeratedAccount {
+    pub account: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QueueExport {
+    pub path: String,
+    pub messages: usize,
+    pub size: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QueueImport {
+    pub messages: Vec<ImportedMessage>,
+    pub skipped: Vec<QueueId>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ImportedMessage {
+    pub previous_id: QueueId,
+    pub id: QueueId,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WarmupStatus {
+    pub ip: IpAddr,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub day: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub daily_limit: Option<u64>,
+    pub completed: bool,
+    pub providers: Vec<WarmupProvider>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WarmupProvider {
+    pub provider: String,
+    pub sent: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub remaining: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DomainAnalytics {
+    pub domain: String,
+    pub stats: AnalyticsSummary,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub series: Vec<AnalyticsSample>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AnalyticsSample {
+    #[serde(deserialize_with = "deserialize_datetime")]
+    #[serde(serialize_with = "serialize_datetime")]
+    pub from: DateTime,
+    pub duration: u64,
+    pub stats: AnalyticsSummary,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AnalyticsSummary {
+    pub attempts: u64,
+    pub delivered: u64,
+    pub temporary_failures: u64,
+    pub permanent_failures: u64,
+    pub tls: u64,
+    pub plaintext: u64,
+    pub failures: BTreeMap<String, u64>,
+    pub latency: LatencyPercentiles,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LatencyPercentiles {
+    pub p50: Option<u64>,
+    pub p90: Option<u64>,
+    pub p99: Option<u64>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Report {
     pub domain: String,
@@ -324,6 +497,7 @@ impl SMTP {
                 let mut to = None;
                 let mut before = None;
                 let mut after = None;
+                let mut reason = None;
                 let mut error = None;
 
                 if let Some(query) = uri.query() {
@@ -353,6 +527,15 @@ impl SMTP {
                                     break;
                                 }
                             },
+                            "reason" => match value.parse_defer_reason() {
+                                Ok(reason_) => {
+                                    reason = reason_.into();
+                                }
+                                Err(reason) => {
+                                    error = reason.into();
+                                    break;
+                                }
+                            },
                             _ => {
                                 error = format!("Invalid parameter {key:?}.").into();
                                 break;
@@ -370,6 +553,7 @@ impl SMTP {
                                 to,
                                 before,
                                 after,
+                                reason,
                                 result_tx,
                             },
                             result_rx,
@@ -422,6 +606,7 @@ impl SMTP {
                 let mut queue_ids = Vec::new();
                 let mut time = Instant::now();
                 let mut item = None;
+                let mut reason = None;
                 let mut error = None;
 
                 if let Some(query) = uri.query() {
@@ -448,6 +633,15 @@ impl SMTP {
                             "filter" => {
                                 item = value.into_owned().into();
                             }
+                            "reason" => match value.parse_defer_reason() {
+                                Ok(reason_) => {
+                                    reason = reason_.into();
+                                }
+                                Err(reason) => {
+                                    error = reason.into();
+                                    break;
+                                }
+                            },
                             _ => {
                                 error = format!("Invalid parameter {key:?}.").into();
                                 break;
@@ -463,6 +657,7 @@ impl SMTP {
                             QueueRequest::Retry {
                                 queue_ids,
                                 item,
+                                reason,
                                 time,
                                 result_tx,
                             },
@@ -473,6 +668,36 @@ impl SMTP {
                     Some(error) => error.into_bad_request(),
                 }
             }
+            (&Method::GET, "queue", "deferred") => {
+                let mut domain = None;
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "domain" => {
+                                domain = value.to_lowercase().into();
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match error {
+                    None => {
+                        let (result_tx, result_rx) = oneshot::channel();
+                        self.send_queue_event(
+                            QueueRequest::Deferred { domain, result_tx },
+                            result_rx,
+                        )
+                        .await
+                    }
+                    Some(error) => error.into_bad_request(),
+                }
+            }
             (&Method::GET, "queue", "cancel") => {
                 let mut queue_ids = Vec::new();
                 let mut item = None;
@@ -517,6 +742,506 @@ impl SMTP {
                     Some(error) => error.into_bad_request(),
                 }
             }
+            (&Method::GET, "queue", "analytics") => {
+                let mut domain = None;
+                let mut from = 0;
+                let mut to = u64::MAX;
+                let mut series = false;
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "domain" => {
+                                domain = value.to_lowercase().into();
+                            }
+                            "after" | "before" => match DateTime::parse_rfc3339(value.as_ref()) {
+                                Some(dt) if key == "after" => {
+                                    from = dt.to_timestamp() as u64;
+                                }
+                                Some(dt) => {
+                                    to = dt.to_timestamp() as u64;
+                                }
+                                None => {
+                                    error = format!("Invalid timestamp {value:?}.").into();
+                                    break;
+                                }
+                            },
+                            "series" => {
+                                series = value.eq_ignore_ascii_case("true") || value == "1";
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match error {
+                    None => (
+                        StatusCode::OK,
+                        serde_json::to_string(&Response {
+                            data: self.queue.delivery_analytics(
+                                domain.as_deref(),
+                                from,
+                                to,
+                                series,
+                            ),
+                        })
+                        .unwrap_or_default(),
+                    ),
+                    Some(error) => error.into_bad_request(),
+                }
+            }
+            #[cfg(feature = "simulation")]
+            (&Method::GET, "queue", "simulate") => {
+                let mut workload = None;
+                let mut config = None;
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "workload" => {
+                                match serde_json::from_str::<queue::simulation::Workload>(
+                                    value.as_ref(),
+                                ) {
+                                    Ok(value) => {
+                                        workload = value.into();
+                                    }
+                                    Err(err) => {
+                                        error = format!("Invalid workload: {err}").into();
+                                        break;
+                                    }
+                                }
+                            }
+                            "config" => {
+                                config = value.into_owned().into();
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match (error, workload) {
+                    (None, Some(workload)) => {
+                        match self.simulate_queue(&workload, config.as_deref()).await {
+                            Ok(report) => (
+                                StatusCode::OK,
+                                serde_json::to_string(&Response { data: report })
+                                    .unwrap_or_default(),
+                            ),
+                            Err(error) => error.into_bad_request(),
+                        }
+                    }
+                    (Some(error), _) => error.into_bad_request(),
+                    (None, None) => "Missing parameter \"workload\"."
+                        .to_string()
+                        .into_bad_request(),
+                }
+            }
+            (&Method::GET, "queue", action @ ("export" | "import")) => {
+                let mut path = None;
+                let mut bundle = true;
+                let mut remove = false;
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "path" => {
+                                path = PathBuf::from(value.as_ref()).into();
+                            }
+                            "content" if action == "export" => match value.as_ref() {
+                                "bundle" => {
+                                    bundle = true;
+                                }
+                                "reference" => {
+                                    bundle = false;
+                                }
+                                _ => {
+                                    error = format!("Invalid content type {value:?}.").into();
+                                    break;
+                                }
+                            },
+                            "remove" if action == "export" => match value.as_ref() {
+                                "true" | "1" => {
+                                    remove = true;
+                                }
+                                "false" | "0" => {
+                                    remove = false;
+                                }
+                                _ => {
+                                    error = format!("Invalid remove value {value:?}.").into();
+                                    break;
+                                }
+                            },
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match (error, path) {
+                    (None, Some(path)) => {
+                        let result = if action == "export" {
+                            self.export_queue(&path, bundle, remove)
+                                .await
+                                .map(|result| serde_json::to_string(&Response { data: result }))
+                        } else {
+                            self.import_queue(&path)
+                                .await
+                                .map(|result| serde_json::to_string(&Response { data: result }))
+                        };
+
+                        match result {
+                            Ok(result) => (StatusCode::OK, result.unwrap_or_default()),
+                            Err(reason) => {
+                                tracing::error!(
+                                    context = "queue",
+                                    event = "error",
+                                    action = action,
+                                    path = path.display().to_string(),
+                                    reason = reason,
+                                    "Failed to process queue archive."
+                                );
+                                (
+                                    StatusCode::INTERNAL_SERVER_ERROR,
+                                    format!(
+                                        "{{\"error\": \"internal-error\", \"details\": {}}}",
+                                        serde_json::to_string(&reason).unwrap_or_default()
+                                    ),
+                                )
+                            }
+                        }
+                    }
+                    (None, None) => "Missing parameter \"path\".".to_string().into_bad_request(),
+                    (Some(error), _) => error.into_bad_request(),
+                }
+            }
+            (&Method::GET, "warmup", "status") => match self.queue.warmup_status().await {
+                Ok(status) => (
+                    StatusCode::OK,
+                    serde_json::to_string(&Response { data: status }).unwrap_or_default(),
+                ),
+                Err(err) => {
+                    tracing::error!(
+                        context = "warmup",
+                        event = "error",
+                        reason = ?err,
+                        "Failed to obtain warm-up status."
+                    );
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        concat!(
+                            "{\"error\": \"internal-error\", ",
+                            "\"details\": \"Failed to obtain warm-up status.\"}"
+                        )
+                        .to_string(),
+                    )
+                }
+            },
+            (&Method::GET, "filter", "budget") => (
+                StatusCode::OK,
+                serde_json::to_string(&Response {
+                    data: self
+                        .session
+                        .filter_metrics
+                        .report(&self.session.config.data.budget),
+                })
+                .unwrap_or_default(),
+            ),
+            (&Method::GET, "sessions", "budget") => (
+                StatusCode::OK,
+                serde_json::to_string(&Response {
+                    data: self.session.account_sessions.report(),
+                })
+                .unwrap_or_default(),
+            ),
+            (&Method::GET, "archive", "metrics") => (
+                StatusCode::OK,
+                serde_json::to_string(&Response {
+                    data: self.session.archive_metrics.report(),
+                })
+                .unwrap_or_default(),
+            ),
+            (&Method::GET, "loop", "metrics") => (
+                StatusCode::OK,
+                serde_json::to_string(&Response {
+                    data: self.session.loop_metrics.report(),
+                })
+                .unwrap_or_default(),
+            ),
+            (&Method::GET, "bayes", "stats") => {
+                let mut store = &self.queue.config.lookup_store;
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "store" => {
+                                if let Some(store_) = self.sieve.lookup_stores.get(value.as_ref()) {
+                                    store = store_;
+                                } else {
+                                    error = format!("Unknown store {value:?}.").into();
+                                    break;
+                                }
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match error {
+                    None => match bayes::stats(store).await {
+                        Ok(stats) => (
+                            StatusCode::OK,
+                            serde_json::to_string(&Response { data: stats }).unwrap_or_default(),
+                        ),
+                        Err(err) => {
+                            tracing::error!(
+                                context = "bayes",
+                                event = "error",
+                                reason = ?err,
+                                "Failed to obtain Bayes statistics."
+                            );
+                            (
+                                StatusCode::INTERNAL_SERVER_ERROR,
+                                concat!(
+                                    "{\"error\": \"internal-error\", ",
+                                    "\"details\": \"Failed to obtain Bayes statistics.\"}"
+                                )
+                                .to_string(),
+                            )
+                        }
+                    },
+                    Some(error) => error.into_bad_request(),
+                }
+            }
+            (&Method::GET, "moderation", "accounts") => (
+                StatusCode::OK,
+                serde_json::to_string(&Response {
+                    data: self.queue.moderated_accounts(),
+                })
+                .unwrap_or_default(),
+            ),
+            (&Method::GET, "moderation", action @ ("enable" | "disable")) => {
+                let mut account = None;
+                let mut reason = String::new();
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "account" => {
+                                account = value.to_lowercase().into();
+                            }
+                            "reason" if action == "enable" => {
+                                reason = value.into_owned();
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match (error, account) {
+                    (None, Some(account)) => {
+                        let result = if action == "enable" {
+                            self.queue
+                                .moderate_account(&account, reason)
+                                .await
+                                .map(|_| true)
+                        } else {
+                            self.queue.unmoderate_account(&account).await
+                        };
+
+                        match result {
+                            Ok(result) => (
+                                StatusCode::OK,
+                                serde_json::to_string(&Response { data: result })
+                                    .unwrap_or_default(),
+                            ),
+                            Err(err) => {
+                                tracing::error!(
+                                    context = "moderation",
+                                    event = "error",
+                                    account = account,
+                                    reason = ?err,
+                                    "Failed to update moderation state."
+                                );
+                                (
+                                    StatusCode::INTERNAL_SERVER_ERROR,
+                                    concat!(
+                                        "{\"error\": \"internal-error\", ",
+                                        "\"details\": \"Failed to update moderation state.\"}"
+                                    )
+                                    .to_string(),
+                                )
+                            }
+                        }
+                    }
+                    (None, None) => "Missing parameter \"account\"."
+                        .to_string()
+                        .into_bad_request(),
+                    (Some(error), _) => error.into_bad_request(),
+                }
+            }
+            (&Method::GET, "moderation", "list") => {
+                let mut account = None;
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "account" => {
+                                account = value.to_lowercase().into();
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match error {
+                    None => {
+                        let (result_tx, result_rx) = oneshot::channel();
+                        self.send_queue_event(
+                            QueueRequest::Parked { account, result_tx },
+                            result_rx,
+                        )
+                        .await
+                    }
+                    Some(error) => error.into_bad_request(),
+                }
+            }
+            (&Method::GET, "moderation", action @ ("approve" | "reject")) => {
+                let mut queue_ids = Vec::new();
+                let mut account = None;
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "id" | "ids" => match value.parse_queue_ids() {
+                                Ok(ids) => {
+                                    queue_ids = ids;
+                                }
+                                Err(reason) => {
+                                    error = reason.into();
+                                    break;
+                                }
+                            },
+                            "account" => {
+                                account = value.to_lowercase().into();
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match error {
+                    None => {
+                        let (result_tx, result_rx) = oneshot::channel();
+                        self.send_queue_event(
+                            QueueRequest::Moderate {
+                                queue_ids,
+                                account,
+                                approve: action == "approve",
+                                result_tx,
+                            },
+                            result_rx,
+                        )
+                        .await
+                    }
+                    Some(error) => error.into_bad_request(),
+                }
+            }
+            (&Method::GET, "dns", "overrides") => (
+                StatusCode::OK,
+                serde_json::to_string(&Response {
+                    data: self.resolvers.overrides.report(),
+                })
+                .unwrap_or_default(),
+            ),
+            (&Method::GET, "operator", "list") => (
+                StatusCode::OK,
+                serde_json::to_string(&Response {
+                    data: self.operator_reports(),
+                })
+                .unwrap_or_default(),
+            ),
+            (&Method::GET, "operator", action @ ("enable" | "disable" | "send")) => {
+                let mut id = None;
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "id" => {
+                                id = value.into_owned().into();
+                            }
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                match (error, id) {
+                    (None, Some(id)) => {
+                        let result = if action == "send" {
+                            Ok(self.send_operator_report(&id).await)
+                        } else {
+                            self.set_operator_report(&id, action == "enable").await
+                        };
+
+                        match result {
+                            Ok(result) => (
+                                StatusCode::OK,
+                                serde_json::to_string(&Response { data: result })
+                                    .unwrap_or_default(),
+                            ),
+                            Err(err) => {
+                                tracing::error!(
+                                    context = "report",
+                                    event = "error",
+                                    id = id,
+                                    reason = ?err,
+                                    "Failed to update operator report state."
+                                );
+                                (
+                                    StatusCode::INTERNAL_SERVER_ERROR,
+                                    concat!(
+                                        "{\"error\": \"internal-error\", ",
+                                        "\"details\": \"Failed to update operator report state.\"}"
+                                    )
+                                    .to_string(),
+                                )
+                            }
+                        }
+                    }
+                    (None, None) => "Missing parameter \"id\".".to_string().into_bad_request(),
+                    (Some(error), _) => error.into_bad_request(),
+                }
+            }
             (&Method::GET, "report", "list") => {
                 let mut domain = None;
                 let mut type_ = None;
@@ -642,6 +1367,99 @@ impl SMTP {
                     Some(error) => error.into_bad_request(),
                 }
             }
+            (&Method::GET, "expression", "eval") => {
+                let mut expr = None;
+                let mut setting = None;
+                let mut envelope = EvalEnvelope::default();
+                let mut error = None;
+
+                if let Some(query) = uri.query() {
+                    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+                        match key.as_ref() {
+                            "expr" => {
+                                expr = value.into_owned().into();
+                            }
+                            "key" => {
+                                setting = value.into_owned().into();
+                            }
+                            "sender" => {
+                                envelope = envelope.with_sender(value.as_ref());
+                            }
+                            "rcpt" => {
+                                envelope = envelope.with_rcpt(value.as_ref());
+                            }
+                            "helo-domain" => {
+                                envelope.helo_domain = value.to_lowercase();
+                            }
+                            "authenticated-as" => {
+                                envelope.authenticated_as = value.into_owned();
+                            }
+                            "mx" => {
+                                envelope.mx = value.to_lowercase();
+                            }
+                            "listener" => {
+                                if value == "sieve" {
+                                    envelope.listener_id = u16::MAX;
+                                } else if let Some(id) = self.eval.listeners.get(value.as_ref()) {
+                                    envelope.listener_id = *id;
+                                } else {
+                                    error = format!("Listener {value:?} does not exist.").into();
+                                    break;
+                                }
+                                envelope.listener = value.into_owned();
+                            }
+                            "remote-ip" | "local-ip" => match value.parse::<IpAddr>() {
+                                Ok(ip) if key == "remote-ip" => {
+                                    envelope.remote_ip = ip;
+                                }
+                                Ok(ip) => {
+                                    envelope.local_ip = ip;
+                                }
+                                Err(_) => {
+                                    error = format!("Invalid IP address {value:?}.").into();
+                                    break;
+                                }
+                            },
+                            "priority" => match value.parse::<i16>() {
+                                Ok(priority) => {
+                                    envelope.priority = priority;
+                                }
+                                Err(_) => {
+                                    error = format!("Invalid priority {value:?}.").into();
+                                    break;
+                                }
+                            },
+                            _ => {
+                                error = format!("Invalid parameter {key:?}.").into();
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                let expression = match (&expr, &setting) {
+                    (Some(expr), None) => Expression::Inline(expr).into(),
+                    (None, Some(setting)) => Expression::Setting(setting).into(),
+                    _ => None,
+                };
+
+                match (error, expression) {
+                    (None, Some(expression)) => {
+                        match self.eval_expression(expression, &envelope).await {
+                            Ok(result) => (
+                                StatusCode::OK,
+                                serde_json::to_string(&Response { data: result })
+                                    .unwrap_or_default(),
+                            ),
+                            Err(error) => error.into_bad_request(),
+                        }
+                    }
+                    (Some(error), _) => error.into_bad_request(),
+                    (None, None) => "Either an expression or a setting key must be provided."
+                        .to_string()
+                        .into_bad_request(),
+                }
+            }
             _ => (
                 StatusCode::NOT_FOUND,
                 format!(
@@ -662,6 +1480,55 @@ impl SMTP {
             .unwrap()
     }
 
+    pub async fn queue_message_source(&self, queue_id: QueueId) -> Option<(PathBuf, usize)> {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.queue
+            .tx
+            .send(queue::Event::Manage(QueueRequest::Source {
+                queue_id,
+                result_tx,
+            }))
+            .await
+            .ok()?;
+        result_rx.await.ok().flatten()
+    }
+
+    pub async fn queue_scheduled(
+        &self,
+        senders: Option<Vec<String>>,
+    ) -> Option<Vec<ScheduledMessage>> {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.queue
+            .tx
+            .send(queue::Event::Manage(QueueRequest::Scheduled {
+                senders,
+                result_tx,
+            }))
+            .await
+            .ok()?;
+        result_rx.await.ok()
+    }
+
+    pub async fn queue_reschedule(
+        &self,
+        queue_ids: Vec<QueueId>,
+        senders: Option<Vec<String>>,
+        release: Option<Instant>,
+    ) -> Option<Vec<bool>> {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.queue
+            .tx
+            .send(queue::Event::Manage(QueueRequest::Reschedule {
+                queue_ids,
+                senders,
+                release,
+                result_tx,
+            }))
+            .await
+            .ok()?;
+        result_rx.await.ok()
+    }
+
     async fn send_queue_event<T: Serialize>(
         &self,
         request: QueueRequest,
@@ -747,6 +1614,7 @@ impl From<&queue::Message> for Message {
             size: message.size,
             priority: message.priority,
             env_id: message.env_id.clone(),
+            parked: message.parked.clone(),
             domains: message
                 .domains
                 .iter()
@@ -764,6 +1632,7 @@ impl From<&queue::Message> for Message {
                         }
                     },
                     retry_num: domain.retry.inner,
+                    retry_strategy: domain.retry_strategy.clone(),
                     next_retry: if domain.retry.due > now {
                         DateTime::from_timestamp(instant_to_timestamp(now, domain.retry.due) as i64)
                             .into()
@@ -806,6 +1675,7 @@ impl From<&queue::Message> for Message {
                     expires: DateTime::from_timestamp(
                         instant_to_timestamp(now, domain.expires) as i64
                     ),
+                    defer_reason: message.defer_reason(idx),
                 })
                 .collect(),
         }
@@ -847,10 +1717,11 @@ impl Display for ReportKey {
     }
 }
 
-trait ParseValues {
+pub trait ParseValues {
     fn parse_timestamp(&self) -> Result<Instant, String>;
     fn parse_queue_ids(&self) -> Result<Vec<QueueId>, String>;
     fn parse_report_ids(&self) -> Result<Vec<ReportKey>, String>;
+    fn parse_defer_reason(&self) -> Result<DeferReason, String>;
 }
 
 impl ParseValues for Cow<'_, str> {
@@ -909,6 +1780,11 @@ impl ParseValues for Cow<'_, str> {
         }
         Ok(ids)
     }
+
+    fn parse_defer_reason(&self) -> Result<DeferReason, String> {
+        DeferReason::parse(&self.to_lowercase())
+            .ok_or_else(|| format!("Invalid defer reason {self:?}."))
+    }
 }
 
 trait BadRequest {
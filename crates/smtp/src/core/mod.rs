@@ -23,15 +23,18 @@
 
 use std::{
     hash::Hash,
-    net::IpAddr,
-    sync::{atomic::AtomicU32, Arc},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::{
+        atomic::{AtomicU32, AtomicU64},
+        Arc,
+    },
     time::{Duration, Instant},
 };
 
 use ahash::AHashMap;
 use dashmap::DashMap;
 use directory::Directory;
-use mail_auth::{common::lru::LruCache, IprevOutput, Resolver, SpfOutput};
+use mail_auth::{common::lru::LruCache, IprevOutput, Resolver, SpfOutput, MX};
 use sieve::{runtime::Variable, Runtime, Sieve};
 use smtp_proto::{
     request::receiver::{
@@ -49,26 +52,45 @@ use tokio_rustls::TlsConnector;
 use tracing::Span;
 use utils::{
     ipc::DeliveryEvent,
-    listener::{limiter::InFlight, stream::NullIo, ServerInstance, TcpAcceptor},
+    listener::{
+        limiter::InFlight, stream::NullIo, tls::ClientCertificate, ServerInstance, TcpAcceptor,
+    },
+    map::stats::CacheStats,
 };
 
 use crate::{
     config::{
         scripts::SieveContext, DkimSigner, MailAuthConfig, QueueConfig, ReportConfig,
-        SessionConfig, VerifyStrategy,
+        SenderAlignment, SessionConfig, VerifyStrategy,
+    },
+    inbound::{
+        archive::ArchiveMetrics,
+        auth::SaslToken,
+        budget::FilterMetrics,
+        mail_loop::LoopMetrics,
+        verify::{SenderVerifyCache, SenderVerifyResult},
+        xclient::XForwardOrigin,
     },
-    inbound::auth::SaslToken,
     outbound::{
         dane::{DnssecResolver, Tlsa},
         mta_sts,
     },
-    queue::{self, DomainPart, QueueId, QuotaLimiter},
-    reporting,
+    queue::{
+        self, analytics::DeliveryAnalytics, moderation::Moderation, DomainPart, QueueId,
+        QuotaLimiter,
+    },
+    reporting::{self, operator::OperatorState},
     scripts::plugins::lookup::VariableExists,
 };
 
-use self::throttle::{Limiter, ThrottleKey, ThrottleKeyHasherBuilder};
+use self::{
+    concurrency::AccountSessions,
+    eval::EvalCore,
+    throttle::{Limiter, ThrottleKey, ThrottleKeyHasherBuilder},
+};
 
+pub mod concurrency;
+pub mod eval;
 pub mod if_block;
 pub mod management;
 pub mod params;
@@ -105,6 +127,7 @@ pub struct SMTP {
     pub mail_auth: MailAuthConfig,
     pub report: ReportCore,
     pub sieve: SieveCore,
+    pub eval: EvalCore,
     #[cfg(feature = "local_delivery")]
     pub delivery_tx: mpsc::Sender<DeliveryEvent>,
 }
@@ -125,16 +148,42 @@ pub struct Resolvers {
     pub dns: Resolver,
     pub dnssec: DnssecResolver,
     pub cache: DnsCache,
+    pub overrides: DnsOverrides,
 }
 
 pub struct DnsCache {
     pub tlsa: LruCache<String, Arc<Tlsa>>,
     pub mta_sts: LruCache<String, Arc<mta_sts::Policy>>,
+    pub rbl: LruCache<String, Arc<Vec<Ipv4Addr>>>,
+    pub rbl_positive_ttl: Duration,
+    pub rbl_negative_ttl: Duration,
+    pub tlsa_stats: CacheStats,
+    pub mta_sts_stats: CacheStats,
+    pub rbl_stats: CacheStats,
+}
+
+#[derive(Default)]
+pub struct DnsOverrides {
+    pub entries: AHashMap<String, DnsOverride>,
+}
+
+pub struct DnsOverride {
+    pub mx: Option<Arc<Vec<MX>>>,
+    pub ipv4: Option<Arc<Vec<Ipv4Addr>>>,
+    pub ipv6: Option<Arc<Vec<Ipv6Addr>>>,
+    pub ttl: Duration,
+    pub expires: parking_lot::Mutex<Option<Instant>>,
+    pub hits: AtomicU64,
 }
 
 pub struct SessionCore {
     pub config: SessionConfig,
     pub throttle: DashMap<ThrottleKey, Limiter, ThrottleKeyHasherBuilder>,
+    pub filter_metrics: FilterMetrics,
+    pub archive_metrics: ArchiveMetrics,
+    pub loop_metrics: LoopMetrics,
+    pub account_sessions: AccountSessions,
+    pub sender_verify: SenderVerifyCache,
 }
 
 pub struct QueueCore {
@@ -144,11 +193,14 @@ pub struct QueueCore {
     pub tx: mpsc::Sender<queue::Event>,
     pub id_seq: AtomicU32,
     pub connectors: TlsConnectors,
+    pub analytics: DeliveryAnalytics,
+    pub moderation: Moderation,
 }
 
 pub struct ReportCore {
     pub config: ReportConfig,
     pub tx: mpsc::Sender<reporting::Event>,
+    pub operator: OperatorState,
 }
 
 pub struct TlsConnectors {
@@ -197,6 +249,8 @@ pub struct SessionData {
 
     pub authenticated_as: String,
     pub authenticated_emails: Vec<String>,
+    pub authenticated_type: Option<directory::Type>,
+    pub authenticated_session: Option<InFlight>,
     pub auth_errors: usize,
 
     pub priority: i16,
@@ -210,7 +264,10 @@ pub struct SessionData {
     pub iprev: Option<IprevOutput>,
     pub spf_ehlo: Option<SpfOutput>,
     pub spf_mail_from: Option<SpfOutput>,
+    pub sender_verify: Option<SenderVerifyResult>,
     pub dnsbl_error: Option<Vec<u8>>,
+    pub trusted_peer: Option<ClientCertificate>,
+    pub xforward: Option<XForwardOrigin>,
 }
 
 #[derive(Clone)]
@@ -226,6 +283,7 @@ pub struct SessionAddress {
 pub struct SessionParameters {
     // Global parameters
     pub timeout: Duration,
+    pub error_codes: bool,
 
     // Ehlo parameters
     pub ehlo_require: bool,
@@ -238,6 +296,7 @@ pub struct SessionParameters {
     pub auth_errors_wait: Duration,
     pub auth_plain_text: bool,
     pub auth_match_sender: bool,
+    pub auth_sender_alignment: SenderAlignment,
 
     // Rcpt parameters
     pub rcpt_errors_max: usize,
@@ -248,6 +307,12 @@ pub struct SessionParameters {
     pub can_vrfy: bool,
     pub max_message_size: usize,
 
+    // Trusted upstream parameters
+    pub xclient: bool,
+
+    // Transparent relay parameters
+    pub transparent: bool,
+
     // Mail authentication parameters
     pub iprev: VerifyStrategy,
     pub spf_ehlo: VerifyStrategy,
@@ -265,6 +330,8 @@ impl SessionData {
             rcpt_to: Vec::new(),
             authenticated_as: String::new(),
             authenticated_emails: Vec::new(),
+            authenticated_type: None,
+            authenticated_session: None,
             priority: 0,
             valid_until: Instant::now(),
             rcpt_errors: 0,
@@ -277,7 +344,10 @@ impl SessionData {
             iprev: None,
             spf_ehlo: None,
             spf_mail_from: None,
+            sender_verify: None,
             dnsbl_error: None,
+            trusted_peer: None,
+            xforward: None,
         }
     }
 }
@@ -423,11 +493,15 @@ static ref SIEVE: Arc<ServerInstance> = Arc::new(utils::listener::ServerInstance
     protocol: utils::config::ServerProtocol::Lmtp,
     hostname: "localhost".to_string(),
     data: "localhost".to_string(),
+    banner: None,
     acceptor: TcpAcceptor::Plain,
+    tls_client_auth: None,
     limiter: utils::listener::limiter::ConcurrencyLimiter::new(0),
+    bandwidth: Default::default(),
     shutdown_rx: tokio::sync::watch::channel(false).1,
     proxy_networks: vec![],
     blocked_ips: Arc::new(Default::default()),
+    virtual_hosts: Default::default(),
 });
 }
 
@@ -461,6 +535,7 @@ impl Session<NullIo> {
             data,
             params: SessionParameters {
                 timeout: Default::default(),
+                error_codes: false,
                 ehlo_require: Default::default(),
                 ehlo_reject_non_fqdn: Default::default(),
                 auth_directory: Default::default(),
@@ -474,11 +549,14 @@ impl Session<NullIo> {
                 rcpt_dsn: Default::default(),
                 max_message_size: Default::default(),
                 auth_match_sender: false,
+                auth_sender_alignment: SenderAlignment::Disable,
                 iprev: crate::config::VerifyStrategy::Disable,
                 spf_ehlo: crate::config::VerifyStrategy::Disable,
                 spf_mail_from: crate::config::VerifyStrategy::Disable,
                 can_expn: false,
                 can_vrfy: false,
+                xclient: false,
+                transparent: false,
             },
             in_flight: vec![],
         }
@@ -530,6 +608,8 @@ impl SessionData {
             message,
             authenticated_as: "local".into(),
             authenticated_emails: vec![],
+            authenticated_type: None,
+            authenticated_session: None,
             auth_errors: 0,
             priority: 0,
             delivery_by: 0,
@@ -540,7 +620,10 @@ impl SessionData {
             iprev: None,
             spf_ehlo: None,
             spf_mail_from: None,
+            sender_verify: None,
             dnsbl_error: None,
+            trusted_peer: None,
+            xforward: None,
         }
     }
 }
@@ -32,6 +32,7 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
         self.data.valid_until += *c.duration.eval(self).await;
 
         self.params.timeout = *c.timeout.eval(self).await;
+        self.params.error_codes = *c.error_codes.eval(self).await;
         self.params.spf_ehlo = *self.core.mail_auth.spf.verify_ehlo.eval(self).await;
         self.params.spf_mail_from = *self.core.mail_auth.spf.verify_mail_from.eval(self).await;
         self.params.iprev = *self.core.mail_auth.iprev.verify.eval(self).await;
@@ -49,11 +50,17 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
         self.params.auth_errors_wait = *ac.errors_wait.eval(self).await;
         self.params.auth_plain_text = *ac.allow_plain_text.eval(self).await;
         self.params.auth_match_sender = *ac.must_match_sender.eval(self).await;
+        self.params.auth_sender_alignment = *ac.sender_alignment.eval(self).await;
 
         // VRFY/EXPN parameters
         let ec = &self.core.session.config.extensions;
         self.params.can_expn = *ec.expn.eval(self).await;
         self.params.can_vrfy = *ec.vrfy.eval(self).await;
+        self.params.xclient = *ec.xclient.eval(self).await;
+
+        // Transparent relay parameters
+        let tc = &self.core.session.config.transparent;
+        self.params.transparent = tc.next_hop.is_some() && *tc.enable.eval(self).await;
     }
 
     pub async fn eval_post_auth_params(&mut self) {
@@ -33,7 +33,7 @@ use std::{
 
 use crate::config::*;
 
-use super::Session;
+use super::{Session, SMTP};
 
 #[derive(Debug)]
 pub struct Limiter {
@@ -211,6 +211,10 @@ impl Throttle {
 
 impl<T: AsyncRead + AsyncWrite> Session<T> {
     pub async fn is_allowed(&mut self) -> bool {
+        if self.data.trusted_peer.is_some() {
+            return true;
+        }
+
         let throttles = if !self.data.rcpt_to.is_empty() {
             &self.core.session.config.throttle.rcpt_to
         } else if self.data.mail_from.is_some() {
@@ -289,6 +293,12 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
         true
     }
 
+    pub fn throttle_rcpt(&self, rcpt: &str, rate: &Rate, ctx: &str) -> bool {
+        self.core.throttle_rcpt(rcpt, rate, ctx)
+    }
+}
+
+impl SMTP {
     pub fn throttle_rcpt(&self, rcpt: &str, rate: &Rate, ctx: &str) -> bool {
         let mut hasher = blake3::Hasher::new();
         hasher.update(rcpt.as_bytes());
@@ -299,7 +309,7 @@ impl<T: AsyncRead + AsyncWrite> Session<T> {
             hash: hasher.finalize().into(),
         };
 
-        match self.core.session.throttle.entry(key) {
+        match self.session.throttle.entry(key) {
             Entry::Occupied(mut e) => {
                 if let Some(limiter) = &mut e.get_mut().rate {
                     limiter.is_allowed(rate)
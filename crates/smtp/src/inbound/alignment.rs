@@ -0,0 +1,138 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use mail_parser::MessageParser;
+use utils::listener::SessionStream;
+
+use crate::{config::SenderAlignment, core::Session};
+
+use super::validate::{remove_header, rewrite_from_header};
+
+impl<T: SessionStream> Session<T> {
+    pub fn sender_alignment(&self) -> SenderAlignment {
+        if self.data.authenticated_as.is_empty()
+            || self.data.authenticated_type.map_or(false, |typ| {
+                self.core
+                    .session
+                    .config
+                    .auth
+                    .sender_alignment_exempt
+                    .contains(&typ)
+            })
+        {
+            SenderAlignment::Disable
+        } else {
+            self.params.auth_sender_alignment
+        }
+    }
+
+    pub fn is_sender_aligned(&self, address: &str) -> bool {
+        self.data.authenticated_as == address
+            || self.data.authenticated_emails.iter().any(|a| a == address)
+    }
+
+    pub fn canonical_sender(&self) -> Option<&str> {
+        self.data.authenticated_emails.first().map(|a| a.as_str())
+    }
+
+    // Enforces the alignment of the From header and the envelope sender with the
+    // identities of the authenticated principal.
+    pub fn align_sender(&self, raw_message: Vec<u8>) -> Result<Vec<u8>, &'static [u8]> {
+        let mode = self.sender_alignment();
+        if mode == SenderAlignment::Disable {
+            return Ok(raw_message);
+        }
+
+        // Alignment verdicts supplied by the client are never trusted
+        let raw_message = if mode == SenderAlignment::Tag {
+            remove_header(&raw_message, "x-sender-alignment")
+        } else {
+            raw_message
+        };
+
+        let from = MessageParser::new()
+            .parse_headers(&raw_message)
+            .and_then(|message| {
+                message
+                    .from()
+                    .and_then(|from| from.first())
+                    .and_then(|from| from.address())
+                    .map(|from| from.trim().to_lowercase())
+            });
+        let mail_from = self
+            .data
+            .mail_from
+            .as_ref()
+            .map(|mail_from| mail_from.address_lcase.as_str())
+            .unwrap_or_default();
+        let from_aligned = from
+            .as_deref()
+            .map_or(true, |from| self.is_sender_aligned(from));
+        let mail_from_aligned = mail_from.is_empty() || self.is_sender_aligned(mail_from);
+        if from_aligned && mail_from_aligned {
+            return Ok(raw_message);
+        }
+
+        let from = from.unwrap_or_default();
+        match (mode, self.canonical_sender()) {
+            (SenderAlignment::Rewrite, Some(canonical)) if mail_from_aligned => {
+                tracing::debug!(parent: &self.span,
+                    context = "data",
+                    event = "sender-alignment",
+                    action = "rewrite",
+                    from = from.as_str(),
+                    canonical = canonical,
+                    "From header rewritten to the canonical sender address.");
+
+                Ok(rewrite_from_header(&raw_message, canonical))
+            }
+            (SenderAlignment::Tag, _) => {
+                tracing::debug!(parent: &self.span,
+                    context = "data",
+                    event = "sender-alignment",
+                    action = "tag",
+                    from = from.as_str(),
+                    return_path = mail_from,
+                    "Message tagged as not aligned with the authenticated sender.");
+
+                let mut message = Vec::with_capacity(raw_message.len() + 128);
+                message.extend_from_slice(b"X-Sender-Alignment: fail (authenticated as ");
+                message.extend_from_slice(self.data.authenticated_as.as_bytes());
+                message.extend_from_slice(b")\r\n");
+                message.extend_from_slice(&raw_message);
+                Ok(message)
+            }
+            _ => {
+                tracing::info!(parent: &self.span,
+                    context = "data",
+                    event = "sender-alignment",
+                    action = "reject",
+                    from = from.as_str(),
+                    return_path = mail_from,
+                    "Message rejected, sender not aligned with the authenticated identity.");
+
+                Err(b"550 5.7.1 Sender not aligned with authenticated identity.\r\n")
+            }
+        }
+    }
+}
@@ -0,0 +1,304 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{borrow::Cow, fmt::Display};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+use utils::listener::SessionStream;
+
+use crate::{config::Antivirus, core::Session, DAEMON_NAME};
+
+const MAX_RESPONSE_SIZE: usize = 65536;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanResult {
+    Clean,
+    Infected(String),
+    Skipped,
+    Error,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Timeout,
+    InvalidResponse,
+    Status(u16),
+}
+
+impl<T: SessionStream> Session<T> {
+    pub async fn run_antivirus(
+        &self,
+        message: &[u8],
+    ) -> Result<Option<ScanResult>, Cow<'static, [u8]>> {
+        let antivirus = if let Some(antivirus) = &self.core.session.config.data.antivirus {
+            antivirus
+        } else {
+            return Ok(None);
+        };
+        if !*antivirus.enable.eval(self).await {
+            return Ok(None);
+        } else if antivirus.max_size > 0 && message.len() > antivirus.max_size {
+            tracing::debug!(
+                parent: &self.span,
+                context = "antivirus",
+                event = "skip",
+                size = message.len(),
+                "Message too large to be scanned.");
+            return Ok(Some(ScanResult::Skipped));
+        }
+
+        match icap_scan(antivirus, message).await {
+            Ok(ScanResult::Infected(threat)) => {
+                tracing::info!(
+                    parent: &self.span,
+                    icap.host = &antivirus.hostname,
+                    icap.port = &antivirus.port,
+                    context = "antivirus",
+                    event = "infected",
+                    threat = threat.as_str(),
+                    "Virus found in message.");
+
+                if antivirus.reject_infected {
+                    Err(format!(
+                        "554 5.7.1 Message rejected, virus found: {}.\r\n",
+                        threat
+                            .chars()
+                            .filter(|ch| ch.is_ascii_graphic() || *ch == ' ')
+                            .collect::<String>()
+                    )
+                    .into_bytes()
+                    .into())
+                } else {
+                    Ok(Some(ScanResult::Infected(threat)))
+                }
+            }
+            Ok(result) => {
+                tracing::debug!(
+                    parent: &self.span,
+                    icap.host = &antivirus.hostname,
+                    icap.port = &antivirus.port,
+                    context = "antivirus",
+                    event = "clean",
+                    "Message scanned, no virus found.");
+                Ok(Some(result))
+            }
+            Err(err) => {
+                tracing::warn!(
+                    parent: &self.span,
+                    icap.host = &antivirus.hostname,
+                    icap.port = &antivirus.port,
+                    context = "antivirus",
+                    event = "error",
+                    reason = %err,
+                    "Antivirus scan failed.");
+                if antivirus.tempfail_on_error {
+                    Err((b"451 4.3.5 Unable to accept message at this time.\r\n"[..]).into())
+                } else {
+                    Ok(Some(ScanResult::Error))
+                }
+            }
+        }
+    }
+}
+
+pub async fn icap_scan(config: &Antivirus, message: &[u8]) -> Result<ScanResult, Error> {
+    let mut stream = tokio::time::timeout(config.timeout_connect, async {
+        let mut last_err = Error::Timeout;
+        for addr in &config.addrs {
+            match TcpStream::connect(addr).await {
+                Ok(stream) => return Ok(stream),
+                Err(err) => {
+                    last_err = Error::Io(err);
+                }
+            }
+        }
+        Err(last_err)
+    })
+    .await
+    .map_err(|_| Error::Timeout)??;
+
+    tokio::time::timeout(config.timeout_data, respmod(&mut stream, config, message))
+        .await
+        .map_err(|_| Error::Timeout)?
+}
+
+// Submits the message as the body of an encapsulated HTTP response (RFC 3507, section 4.9.2),
+// servers that find no threats reply with a 204 as no modifications are needed.
+async fn respmod<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    config: &Antivirus,
+    message: &[u8],
+) -> Result<ScanResult, Error> {
+    let http_headers = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: message/rfc822\r\nContent-Length: {}\r\n\r\n",
+        message.len()
+    );
+    let mut request = format!(
+        concat!(
+            "RESPMOD icap://{}:{}/{} ICAP/1.0\r\n",
+            "Host: {}\r\n",
+            "User-Agent: {}\r\n",
+            "Allow: 204\r\n",
+            "Encapsulated: res-hdr=0, res-body={}\r\n\r\n",
+            "{}",
+        ),
+        config.hostname,
+        config.port,
+        config.service,
+        config.hostname,
+        DAEMON_NAME,
+        http_headers.len(),
+        http_headers
+    )
+    .into_bytes();
+    request.reserve(message.len() + 32);
+    if !message.is_empty() {
+        request.extend_from_slice(format!("{:x}\r\n", message.len()).as_bytes());
+        request.extend_from_slice(message);
+        request.extend_from_slice(b"\r\n");
+    }
+    request.extend_from_slice(b"0\r\n\r\n");
+    stream.write_all(&request).await?;
+    stream.flush().await?;
+
+    // Read response headers, the encapsulated body is not needed
+    let mut response = Vec::with_capacity(1024);
+    let mut buf = [0u8; 4096];
+    let header_end = loop {
+        let bytes_read = stream.read(&mut buf).await?;
+        if bytes_read == 0 {
+            return Err(Error::InvalidResponse);
+        }
+        response.extend_from_slice(&buf[..bytes_read]);
+        if let Some(pos) = response.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        } else if response.len() > MAX_RESPONSE_SIZE {
+            return Err(Error::InvalidResponse);
+        }
+    };
+
+    parse_response(
+        std::str::from_utf8(&response[..header_end]).map_err(|_| Error::InvalidResponse)?,
+    )
+}
+
+fn parse_response(response: &str) -> Result<ScanResult, Error> {
+    let mut lines = response.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| {
+            let mut parts = line.split(' ');
+            if parts.next()?.starts_with("ICAP/") {
+                parts.next()?.parse::<u16>().ok()
+            } else {
+                None
+            }
+        })
+        .ok_or(Error::InvalidResponse)?;
+
+    match status {
+        204 => Ok(ScanResult::Clean),
+        200 => {
+            // Unfold header values
+            let mut headers: Vec<(&str, String)> = Vec::new();
+            for line in lines {
+                if line.starts_with([' ', '\t']) {
+                    if let Some((_, value)) = headers.last_mut() {
+                        value.push('\n');
+                        value.push_str(line.trim());
+                    }
+                } else if let Some((name, value)) = line.split_once(':') {
+                    headers.push((name.trim(), value.trim().to_string()));
+                }
+            }
+
+            for (name, value) in headers {
+                if name.eq_ignore_ascii_case("X-Infection-Found") {
+                    // Type=0; Resolution=2; Threat=Eicar-Test-Signature;
+                    let threat = value
+                        .split(';')
+                        .find_map(|part| part.trim().strip_prefix("Threat="))
+                        .unwrap_or("unknown");
+                    return Ok(ScanResult::Infected(threat.to_string()));
+                } else if name.eq_ignore_ascii_case("X-Virus-ID") {
+                    return Ok(ScanResult::Infected(value));
+                } else if name.eq_ignore_ascii_case("X-Violations-Found") {
+                    // Number of violations followed by filename, threat, id and disposition
+                    let mut lines = value.split('\n');
+                    if lines
+                        .next()
+                        .and_then(|count| count.parse::<u32>().ok())
+                        .unwrap_or(0)
+                        > 0
+                    {
+                        return Ok(ScanResult::Infected(
+                            lines.nth(1).unwrap_or("unknown").to_string(),
+                        ));
+                    }
+                }
+            }
+
+            Ok(ScanResult::Clean)
+        }
+        status => Err(Error::Status(status)),
+    }
+}
+
+impl ScanResult {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScanResult::Clean => "clean",
+            ScanResult::Infected(_) => "infected",
+            ScanResult::Skipped => "skipped",
+            ScanResult::Error => "error",
+        }
+    }
+
+    pub fn threat(&self) -> &str {
+        match self {
+            ScanResult::Infected(threat) => threat,
+            _ => "",
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::Timeout => write!(f, "Connection timed out"),
+            Error::InvalidResponse => write!(f, "Invalid ICAP response"),
+            Error::Status(status) => write!(f, "Unexpected ICAP status {}", status),
+        }
+    }
+}
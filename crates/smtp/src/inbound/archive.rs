@@ -0,0 +1,200 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use mail_parser::MessageParser;
+use serde::Serialize;
+use smtp_proto::RCPT_NOTIFY_NEVER;
+use utils::listener::SessionStream;
+
+use crate::{
+    core::{Session, SessionAddress},
+    queue::QueueId,
+};
+
+pub const ARCHIVE_HEADER: &str = "X-Compliance-Archive";
+
+#[derive(Debug, Default)]
+pub struct ArchiveMetrics {
+    queued: AtomicU64,
+    recipients: AtomicU64,
+    skipped: AtomicU64,
+    failed: AtomicU64,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ArchiveReport {
+    pub queued: u64,
+    pub recipients: u64,
+    pub skipped: u64,
+    pub failed: u64,
+}
+
+impl<T: SessionStream> Session<T> {
+    // Returns the archive addresses configured for the sender. Messages that are
+    // already archive copies, or that are sent by an archive address, are not
+    // archived again in order to prevent loops.
+    pub async fn archive_recipients(&self, raw_message: &[u8]) -> Vec<String> {
+        let archive = self.core.session.config.data.archive.eval(self).await;
+        if archive.is_empty() {
+            return vec![];
+        }
+
+        let return_path = self
+            .data
+            .mail_from
+            .as_ref()
+            .map(|mail_from| mail_from.address_lcase.as_str())
+            .unwrap_or_default();
+        let is_copy = MessageParser::new()
+            .parse_headers(raw_message)
+            .map_or(false, |message| message.header(ARCHIVE_HEADER).is_some());
+        if is_copy
+            || archive
+                .iter()
+                .any(|address| address.trim().eq_ignore_ascii_case(return_path))
+        {
+            tracing::debug!(parent: &self.span,
+                context = "archive",
+                event = "loop-detected",
+                return_path = return_path,
+                "Message is an archive copy, skipping compliance archive.");
+            self.core.session.archive_metrics.record_skip();
+            return vec![];
+        }
+
+        // Recipients of the original message already receive a copy
+        let mut recipients = Vec::with_capacity(archive.len());
+        for address in archive {
+            let address = address.trim().to_lowercase();
+            if address.contains('@')
+                && !recipients.contains(&address)
+                && !self
+                    .data
+                    .rcpt_to
+                    .iter()
+                    .any(|rcpt| rcpt.address_lcase == address)
+            {
+                recipients.push(address);
+            }
+        }
+        recipients
+    }
+
+    // Archive copies are queued as separate messages with a null sender, so
+    // archive recipients stay hidden from the original recipients and delivery
+    // failures are never reported back to the sender.
+    pub async fn queue_archive_copy(
+        &self,
+        queue_id: QueueId,
+        recipients: Vec<String>,
+        headers: &[u8],
+        raw_message: &[u8],
+    ) {
+        let rcpt_to = recipients
+            .into_iter()
+            .map(|address| SessionAddress {
+                domain: address
+                    .rsplit_once('@')
+                    .map(|(_, domain)| domain.to_string())
+                    .unwrap_or_default(),
+                address: address.clone(),
+                address_lcase: address,
+                flags: RCPT_NOTIFY_NEVER,
+                dsn_info: None,
+            })
+            .collect::<Vec<_>>();
+        let num_recipients = rcpt_to.len() as u64;
+        let mut message = self
+            .build_message(
+                SessionAddress {
+                    address: String::new(),
+                    address_lcase: String::new(),
+                    domain: String::new(),
+                    flags: 0,
+                    dsn_info: None,
+                },
+                rcpt_to,
+            )
+            .await;
+        let archive_id = message.id;
+
+        let mut archive_headers = Vec::with_capacity(headers.len() + 64);
+        archive_headers.extend_from_slice(ARCHIVE_HEADER.as_bytes());
+        archive_headers.extend_from_slice(b": ");
+        archive_headers.extend_from_slice(self.instance.hostname.as_bytes());
+        archive_headers.extend_from_slice(format!("; queue-id={queue_id}\r\n").as_bytes());
+        archive_headers.extend_from_slice(headers);
+        message.size = raw_message.len() + archive_headers.len();
+
+        let metrics = &self.core.session.archive_metrics;
+        if self.core.queue.has_quota(&mut message).await
+            && self
+                .core
+                .queue
+                .queue_message(message, Some(&archive_headers), raw_message, &self.span)
+                .await
+        {
+            tracing::debug!(parent: &self.span,
+                context = "archive",
+                event = "queued",
+                queue_id = queue_id,
+                archive_id = archive_id,
+                recipients = num_recipients,
+                "Queued compliance archive copy.");
+            metrics.record_queued(num_recipients);
+        } else {
+            tracing::warn!(parent: &self.span,
+                context = "archive",
+                event = "error",
+                queue_id = queue_id,
+                "Failed to queue compliance archive copy.");
+            metrics.record_failure();
+        }
+    }
+}
+
+impl ArchiveMetrics {
+    pub fn record_queued(&self, recipients: u64) {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        self.recipients.fetch_add(recipients, Ordering::Relaxed);
+    }
+
+    pub fn record_skip(&self) {
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_failure(&self) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn report(&self) -> ArchiveReport {
+        ArchiveReport {
+            queued: self.queued.load(Ordering::Relaxed),
+            recipients: self.recipients.load(Ordering::Relaxed),
+            skipped: self.skipped.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -21,11 +21,13 @@
  * for more details.
 */
 
-use directory::AuthResult;
+use directory::{AuthResult, Principal, QueryBy};
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
-use smtp_proto::{IntoString, AUTH_LOGIN, AUTH_OAUTHBEARER, AUTH_PLAIN, AUTH_XOAUTH2};
-use tokio::io::{AsyncRead, AsyncWrite};
+use smtp_proto::{
+    IntoString, AUTH_EXTERNAL, AUTH_LOGIN, AUTH_OAUTHBEARER, AUTH_PLAIN, AUTH_XOAUTH2,
+};
+use utils::{config::ServerProtocol, listener::SessionStream};
 
 use crate::core::Session;
 
@@ -37,7 +39,7 @@ pub struct SaslToken {
 impl SaslToken {
     pub fn from_mechanism(mechanism: u64) -> Option<SaslToken> {
         match mechanism {
-            AUTH_PLAIN | AUTH_LOGIN => SaslToken {
+            AUTH_PLAIN | AUTH_LOGIN | AUTH_EXTERNAL => SaslToken {
                 mechanism,
                 credentials: Credentials::Plain {
                     username: String::new(),
@@ -65,15 +67,18 @@ impl SaslToken {
     }
 }
 
-impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
+impl<T: SessionStream> Session<T> {
     pub async fn handle_sasl_response(
         &mut self,
         token: &mut SaslToken,
         response: &[u8],
     ) -> Result<bool, ()> {
-        if response.is_empty() {
+        if token.mechanism == AUTH_EXTERNAL && response == b"=" {
+            // Use the identity from the client certificate
+            return self.authenticate_external(b"").await;
+        } else if response.is_empty() {
             match (token.mechanism, &token.credentials) {
-                (AUTH_PLAIN | AUTH_XOAUTH2 | AUTH_OAUTHBEARER, _) => {
+                (AUTH_PLAIN | AUTH_XOAUTH2 | AUTH_OAUTHBEARER | AUTH_EXTERNAL, _) => {
                     self.write(b"334 Go ahead.\r\n").await?;
                     return Ok(true);
                 }
@@ -124,6 +129,9 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
                             .await
                     };
                 }
+                (AUTH_EXTERNAL, _) => {
+                    return self.authenticate_external(&response).await;
+                }
                 (AUTH_OAUTHBEARER, Credentials::OAuthBearer { token: token_ }) => {
                     let response = response.into_string();
                     if response.contains("auth=") {
@@ -183,7 +191,11 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
             };
 
             match lookup
-                .authenticate(&credentials, self.data.remote_ip, false)
+                .authenticate(
+                    &credentials,
+                    self.data.remote_ip,
+                    self.core.session.config.auth.sender_alignment_delegated,
+                )
                 .await
             {
                 Ok(AuthResult::Success(principal)) => {
@@ -194,16 +206,7 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
                         result = "success"
                     );
 
-                    self.data.authenticated_as = authenticated_as.to_lowercase();
-                    self.data.authenticated_emails = principal
-                        .emails
-                        .into_iter()
-                        .map(|e| e.trim().to_lowercase())
-                        .collect();
-                    self.eval_post_auth_params().await;
-                    self.write(b"235 2.7.0 Authentication succeeded.\r\n")
-                        .await?;
-                    return Ok(false);
+                    return self.auth_success(authenticated_as, principal).await;
                 }
                 Ok(AuthResult::Failure) => {
                     tracing::debug!(
@@ -243,9 +246,157 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
         Ok(false)
     }
 
+    pub async fn authenticate_external(&mut self, authz_id: &[u8]) -> Result<bool, ()> {
+        let (directory, identities) = match (
+            &self.params.auth_directory,
+            self.stream.tls_client_certificate(),
+            &self.instance.tls_client_auth,
+        ) {
+            (Some(directory), Some(cert), Some(client_auth)) => (
+                directory.clone(),
+                cert.principal_names(&client_auth.principal),
+            ),
+            _ => {
+                return self
+                    .auth_error(b"535 5.7.8 No valid client certificate was presented.\r\n")
+                    .await;
+            }
+        };
+
+        match directory
+            .query_identities(
+                &identities,
+                self.core.session.config.auth.sender_alignment_delegated,
+            )
+            .await
+        {
+            Ok(Some(principal)) => {
+                // The authorization identity, when present, must match the principal
+                let authz_id = String::from_utf8_lossy(authz_id).trim().to_lowercase();
+                if authz_id.is_empty()
+                    || principal.name.eq_ignore_ascii_case(&authz_id)
+                    || principal
+                        .emails
+                        .iter()
+                        .any(|email| email.eq_ignore_ascii_case(&authz_id))
+                {
+                    tracing::debug!(
+                        parent: &self.span,
+                        context = "auth",
+                        event = "authenticate",
+                        mechanism = "external",
+                        result = "success"
+                    );
+
+                    self.auth_success(principal.name.clone(), principal).await
+                } else {
+                    tracing::debug!(
+                        parent: &self.span,
+                        context = "auth",
+                        event = "authenticate",
+                        mechanism = "external",
+                        authz_id = authz_id.as_str(),
+                        result = "failed"
+                    );
+
+                    self.auth_error(
+                        b"535 5.7.8 Authorization identity does not match certificate.\r\n",
+                    )
+                    .await
+                }
+            }
+            Ok(None) => {
+                tracing::debug!(
+                    parent: &self.span,
+                    context = "auth",
+                    event = "authenticate",
+                    mechanism = "external",
+                    identities = ?identities,
+                    result = "failed"
+                );
+
+                self.auth_error(b"535 5.7.8 Authentication credentials invalid.\r\n")
+                    .await
+            }
+            Err(_) => {
+                self.write(b"454 4.7.0 Temporary authentication failure\r\n")
+                    .await?;
+                Ok(false)
+            }
+        }
+    }
+
+    async fn auth_success(
+        &mut self,
+        authenticated_as: String,
+        principal: Principal<u32>,
+    ) -> Result<bool, ()> {
+        // Enforce the session budget shared with other protocols
+        match self.core.acquire_account_session(
+            &authenticated_as,
+            principal.typ,
+            ServerProtocol::Smtp,
+        ) {
+            Ok(in_flight) => {
+                self.data.authenticated_session = in_flight;
+            }
+            Err(limit) => {
+                tracing::debug!(
+                    parent: &self.span,
+                    context = "auth",
+                    event = "session-limit",
+                    account = authenticated_as.as_str(),
+                    limit = limit,
+                    "Account exceeded its concurrent session budget."
+                );
+                self.write(b"454 4.7.0 Too many concurrent sessions for this account.\r\n")
+                    .await?;
+                return Ok(false);
+            }
+        }
+
+        self.data.authenticated_as = authenticated_as.to_lowercase();
+        self.data.authenticated_type = principal.typ.into();
+        self.data.authenticated_emails = self.principal_identities(principal).await;
+        self.eval_post_auth_params().await;
+        self.write(b"235 2.7.0 Authentication succeeded.\r\n")
+            .await?;
+        Ok(false)
+    }
+
+    /// Returns the addresses of a principal, including the addresses of the
+    /// groups it is a member of when delegated identities are allowed.
+    pub async fn principal_identities(&self, principal: Principal<u32>) -> Vec<String> {
+        let mut emails = principal
+            .emails
+            .into_iter()
+            .map(|e| e.trim().to_lowercase())
+            .collect::<Vec<_>>();
+
+        if self.core.session.config.auth.sender_alignment_delegated
+            && !principal.member_of.is_empty()
+        {
+            if let Some(directory) = &self.params.auth_directory {
+                for group_id in principal.member_of {
+                    if let Ok(Some(group)) = directory.query(QueryBy::Id(group_id), false).await {
+                        for email in group.emails {
+                            let email = email.trim().to_lowercase();
+                            if !emails.contains(&email) {
+                                emails.push(email);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        emails
+    }
+
     pub async fn auth_error(&mut self, response: &[u8]) -> Result<bool, ()> {
         tokio::time::sleep(self.params.auth_errors_wait).await;
         self.data.auth_errors += 1;
+        self.core.report.operator.telemetry.record_auth_failure();
         self.write(response).await?;
         if self.data.auth_errors < self.params.auth_errors_max {
             Ok(false)
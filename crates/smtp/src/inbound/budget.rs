@@ -0,0 +1,320 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    borrow::Cow,
+    future::Future,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use utils::listener::SessionStream;
+
+use crate::{
+    config::{FilterBudgetConfig, FilterStage, FilterStageBudget, IfBlock},
+    core::Session,
+};
+
+pub struct FilterBudget {
+    deadline: Option<Instant>,
+    total: Duration,
+    pub skipped: Vec<FilterStage>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StageBudget {
+    Run(Option<Duration>),
+    Skip,
+}
+
+#[derive(Debug, Default)]
+pub struct FilterMetrics {
+    stages: [StageMetrics; FilterStage::ALL.len()],
+}
+
+#[derive(Debug, Default)]
+struct StageMetrics {
+    runs: AtomicU64,
+    skipped: AtomicU64,
+    timeouts: AtomicU64,
+    elapsed_ms: AtomicU64,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct StageReport {
+    pub stage: &'static str,
+    pub weight: u32,
+    pub optional: bool,
+    pub runs: u64,
+    pub skipped: u64,
+    pub timeouts: u64,
+    #[serde(rename = "avgElapsedMs")]
+    pub avg_elapsed_ms: u64,
+}
+
+impl FilterStage {
+    pub const ALL: [FilterStage; 7] = [
+        FilterStage::Dkim,
+        FilterStage::Arc,
+        FilterStage::Dmarc,
+        FilterStage::Milter,
+        FilterStage::Pipe,
+        FilterStage::Antivirus,
+        FilterStage::Sieve,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FilterStage::Dkim => "dkim",
+            FilterStage::Arc => "arc",
+            FilterStage::Dmarc => "dmarc",
+            FilterStage::Milter => "milter",
+            FilterStage::Pipe => "pipe",
+            FilterStage::Antivirus => "antivirus",
+            FilterStage::Sieve => "sieve",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        FilterStage::ALL
+            .into_iter()
+            .find(|stage| stage.as_str().eq_ignore_ascii_case(value))
+    }
+}
+
+impl Default for FilterBudgetConfig {
+    fn default() -> Self {
+        FilterBudgetConfig {
+            enable: IfBlock::new(false),
+            ratio: 0.8,
+            stages: FilterStage::ALL.map(FilterStageBudget::default_for),
+        }
+    }
+}
+
+impl FilterStageBudget {
+    pub fn default_for(stage: FilterStage) -> Self {
+        match stage {
+            FilterStage::Dkim => FilterStageBudget {
+                weight: 2,
+                optional: false,
+            },
+            FilterStage::Arc => FilterStageBudget {
+                weight: 1,
+                optional: true,
+            },
+            FilterStage::Dmarc => FilterStageBudget {
+                weight: 1,
+                optional: false,
+            },
+            FilterStage::Milter => FilterStageBudget {
+                weight: 3,
+                optional: false,
+            },
+            FilterStage::Pipe => FilterStageBudget {
+                weight: 3,
+                optional: true,
+            },
+            FilterStage::Antivirus => FilterStageBudget {
+                weight: 3,
+                optional: false,
+            },
+            FilterStage::Sieve => FilterStageBudget {
+                weight: 4,
+                optional: false,
+            },
+        }
+    }
+}
+
+impl FilterBudget {
+    pub fn new(config: &FilterBudgetConfig, timeout: Duration) -> Self {
+        let total = timeout.mul_f64(config.ratio);
+        FilterBudget {
+            deadline: Some(Instant::now() + total),
+            total,
+            skipped: Vec::new(),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        FilterBudget {
+            deadline: None,
+            total: Duration::ZERO,
+            skipped: Vec::new(),
+        }
+    }
+
+    // Allocates a share of the remaining time to a stage, proportional to its
+    // weight relative to the stages that still have to run. Optional stages are
+    // skipped when the pipeline is behind schedule, mandatory stages may use all
+    // of the remaining time.
+    pub fn allocate(&mut self, config: &FilterBudgetConfig, stage: FilterStage) -> StageBudget {
+        let deadline = if let Some(deadline) = self.deadline {
+            deadline
+        } else {
+            return StageBudget::Run(None);
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let stage_budget = config.stages[stage as usize];
+        let total_weight = config.stages.iter().map(|s| s.weight).sum::<u32>().max(1);
+        let pending_weight = config.stages[stage as usize..]
+            .iter()
+            .map(|s| s.weight)
+            .sum::<u32>()
+            .max(1);
+        let share = remaining.mul_f64(stage_budget.weight as f64 / pending_weight as f64);
+
+        if stage_budget.optional {
+            let planned = self
+                .total
+                .mul_f64(stage_budget.weight as f64 / total_weight as f64);
+            if remaining.is_zero() || share < planned {
+                self.skipped.push(stage);
+                StageBudget::Skip
+            } else {
+                StageBudget::Run(Some(share))
+            }
+        } else {
+            StageBudget::Run(Some(remaining))
+        }
+    }
+
+    pub fn mark_skipped(&mut self, stage: FilterStage) {
+        self.skipped.push(stage);
+    }
+
+    pub fn is_skipped(&self, stage: FilterStage) -> bool {
+        self.skipped.contains(&stage)
+    }
+
+    pub fn skipped(&self) -> String {
+        self.skipped
+            .iter()
+            .map(|stage| stage.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl<T: SessionStream> Session<T> {
+    // Runs a filtering stage within its share of the time budget. Returns `None`
+    // when an optional stage was skipped or timed out, and an SMTP response when
+    // a mandatory stage could not complete in time.
+    pub async fn run_filter_stage<F: Future>(
+        &self,
+        budget: &mut FilterBudget,
+        stage: FilterStage,
+        stage_fut: F,
+    ) -> Result<Option<F::Output>, Cow<'static, [u8]>> {
+        let config = &self.core.session.config.data.budget;
+        let metrics = &self.core.session.filter_metrics;
+        let limit = match budget.allocate(config, stage) {
+            StageBudget::Run(limit) => limit,
+            StageBudget::Skip => {
+                metrics.record_skip(stage);
+                tracing::debug!(parent: &self.span,
+                    context = "budget",
+                    event = "skip",
+                    stage = stage.as_str(),
+                    "Skipping optional filter stage, time budget exceeded.");
+                return Ok(None);
+            }
+        };
+
+        let time = Instant::now();
+        let result = if let Some(limit) = limit {
+            tokio::time::timeout(limit, stage_fut).await.ok()
+        } else {
+            Some(stage_fut.await)
+        };
+
+        match result {
+            Some(output) => {
+                metrics.record_run(stage, time.elapsed());
+                Ok(Some(output))
+            }
+            None => {
+                metrics.record_timeout(stage);
+                if config.stages[stage as usize].optional {
+                    budget.mark_skipped(stage);
+                    tracing::debug!(parent: &self.span,
+                        context = "budget",
+                        event = "timeout",
+                        stage = stage.as_str(),
+                        "Optional filter stage timed out.");
+                    Ok(None)
+                } else {
+                    tracing::info!(parent: &self.span,
+                        context = "budget",
+                        event = "timeout",
+                        stage = stage.as_str(),
+                        "Filter stage timed out.");
+                    Err((&b"451 4.7.0 Message filtering timed out, please try again later.\r\n"[..]).into())
+                }
+            }
+        }
+    }
+}
+
+impl FilterMetrics {
+    pub fn record_run(&self, stage: FilterStage, elapsed: Duration) {
+        let metrics = &self.stages[stage as usize];
+        metrics.runs.fetch_add(1, Ordering::Relaxed);
+        metrics
+            .elapsed_ms
+            .fetch_add(elapsed.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_skip(&self, stage: FilterStage) {
+        self.stages[stage as usize]
+            .skipped
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_timeout(&self, stage: FilterStage) {
+        self.stages[stage as usize]
+            .timeouts
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn report(&self, config: &FilterBudgetConfig) -> Vec<StageReport> {
+        FilterStage::ALL
+            .into_iter()
+            .map(|stage| {
+                let metrics = &self.stages[stage as usize];
+                let runs = metrics.runs.load(Ordering::Relaxed);
+                StageReport {
+                    stage: stage.as_str(),
+                    weight: config.stages[stage as usize].weight,
+                    optional: config.stages[stage as usize].optional,
+                    runs,
+                    skipped: metrics.skipped.load(Ordering::Relaxed),
+                    timeouts: metrics.timeouts.load(Ordering::Relaxed),
+                    avg_elapsed_ms: metrics.elapsed_ms.load(Ordering::Relaxed) / runs.max(1),
+                }
+            })
+            .collect()
+    }
+}
@@ -36,24 +36,96 @@ use mail_auth::{
 use mail_builder::headers::{date::Date, message_id::generate_message_id_header};
 use sieve::runtime::Variable;
 use smtp_proto::{
-    MAIL_BY_RETURN, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
+    MAIL_BY_RETURN, MAIL_REQUIRETLS, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER,
+    RCPT_NOTIFY_SUCCESS,
 };
 use tokio::{io::AsyncWriteExt, process::Command};
-use utils::listener::SessionStream;
+use utils::{
+    events::{self, EventKind, LifecycleEvent},
+    listener::SessionStream,
+};
 
 use crate::{
+    config::{FilterStage, MessageValidation},
     core::{Session, SessionAddress, State},
-    queue::{self, Message, SimpleEnvelope},
+    queue::{
+        self, Message, SimpleEnvelope, MAIL_FUTURE_RELEASE, MAIL_TLS_OPTIONAL, MAIL_TRANSPARENT,
+    },
     reporting::analysis::AnalyzeReport,
     scripts::{ScriptModification, ScriptResult},
 };
 
-use super::AuthResult;
+use super::{
+    budget::FilterBudget,
+    disclaimer::add_disclaimer,
+    validate::{normalize_message, remove_header, validate_message},
+    AuthResult,
+};
 
 impl<T: SessionStream> Session<T> {
     pub async fn queue_message(&mut self) -> Cow<'static, [u8]> {
+        // Validate message
+        let mut raw_message = std::mem::take(&mut self.data.message);
+        let transparent = self.params.transparent;
+        let validation = *self.core.session.config.data.validation.eval(self).await;
+        if validation != MessageValidation::Disable {
+            let violations = validate_message(&raw_message);
+            if !violations.is_empty() {
+                let violations = violations
+                    .iter()
+                    .map(|violation| violation.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                if validation == MessageValidation::Strict {
+                    tracing::info!(parent: &self.span,
+                        context = "data",
+                        event = "validation-failed",
+                        return_path = self.data.mail_from.as_ref().unwrap().address,
+                        violations = violations,
+                        "Message rejected due to RFC 5322 violations.");
+
+                    return format!(
+                        "550 5.6.0 Message does not conform to RFC 5322: {violations}.\r\n"
+                    )
+                    .into_bytes()
+                    .into();
+                } else if !transparent {
+                    tracing::debug!(parent: &self.span,
+                        context = "data",
+                        event = "validation-fixed",
+                        return_path = self.data.mail_from.as_ref().unwrap().address,
+                        violations = violations,
+                        "Fixed RFC 5322 violations.");
+
+                    raw_message = normalize_message(
+                        &raw_message,
+                        &self.data.mail_from.as_ref().unwrap().address,
+                    );
+                }
+            }
+        }
+
+        // Enforce sender identity alignment
+        let raw_message = if !transparent {
+            match self.align_sender(raw_message) {
+                Ok(raw_message) => raw_message,
+                Err(response) => return response.into(),
+            }
+        } else {
+            raw_message
+        };
+
+        // Remove any X-Filter-Skipped headers added by the sender, only the
+        // stages skipped by this server may be reported downstream
+        let raw_message = if !transparent || self.core.session.config.transparent.add_headers {
+            remove_header(&raw_message, "x-filter-skipped")
+        } else {
+            raw_message
+        };
+
         // Authenticate message
-        let raw_message = Arc::new(std::mem::take(&mut self.data.message));
+        let raw_message = Arc::new(raw_message);
         let auth_message = if let Some(auth_message) = AuthenticatedMessage::parse(&raw_message) {
             auth_message
         } else {
@@ -79,13 +151,39 @@ impl<T: SessionStream> Session<T> {
             return (&b"450 4.4.6 Too many Received headers. Possible loop detected.\r\n"[..])
                 .into();
         }
+        let loop_detection = *dc.loop_detection.enable.eval(self).await;
+        if loop_detection {
+            if let Some(verdict) = self.detect_loop(&raw_message).await {
+                self.record_loop(&verdict);
+                return verdict.response().into();
+            }
+        }
+
+        // Allocate the filtering time budget
+        let mut budget = if *dc.budget.enable.eval(self).await {
+            FilterBudget::new(&dc.budget, self.params.timeout)
+        } else {
+            FilterBudget::unlimited()
+        };
 
         // Verify DKIM
         let dkim = *ac.dkim.verify.eval(self).await;
         let dmarc = *ac.dmarc.verify.eval(self).await;
         let dkim_output = if dkim.verify() || dmarc.verify() {
-            let dkim_output = self.core.resolvers.dns.verify_dkim(&auth_message).await;
+            let dkim_output = match self
+                .run_filter_stage(
+                    &mut budget,
+                    FilterStage::Dkim,
+                    self.core.resolvers.dns.verify_dkim(&auth_message),
+                )
+                .await
+            {
+                Ok(Some(dkim_output)) => dkim_output,
+                Ok(None) => vec![],
+                Err(response) => return response,
+            };
             let rejected = dkim.is_strict()
+                && !budget.is_skipped(FilterStage::Dkim)
                 && !dkim_output
                     .iter()
                     .any(|d| matches!(d.result(), DkimResult::Pass));
@@ -135,12 +233,19 @@ impl<T: SessionStream> Session<T> {
         let arc = *ac.arc.verify.eval(self).await;
         let arc_sealer = ac.arc.seal.eval_and_capture(self).await.into_value(self);
         let arc_output = if arc.verify() || arc_sealer.is_some() {
-            let arc_output = self.core.resolvers.dns.verify_arc(&auth_message).await;
-
-            if arc.is_strict()
-                && !matches!(arc_output.result(), DkimResult::Pass | DkimResult::None)
+            match self
+                .run_filter_stage(
+                    &mut budget,
+                    FilterStage::Arc,
+                    self.core.resolvers.dns.verify_arc(&auth_message),
+                )
+                .await
             {
-                tracing::info!(parent: &self.span,
+                Ok(Some(arc_output)) => {
+                    if arc.is_strict()
+                        && !matches!(arc_output.result(), DkimResult::Pass | DkimResult::None)
+                    {
+                        tracing::info!(parent: &self.span,
                     context = "arc",
                     event = "auth-failed",
                     return_path = self.data.mail_from.as_ref().unwrap().address,
@@ -148,20 +253,24 @@ impl<T: SessionStream> Session<T> {
                     result = %arc_output.result(),
                     "ARC validation failed.");
 
-                return if matches!(arc_output.result(), DkimResult::TempError(_)) {
-                    (&b"451 4.7.29 ARC validation failed.\r\n"[..]).into()
-                } else {
-                    (&b"550 5.7.29 ARC validation failed.\r\n"[..]).into()
-                };
-            } else {
-                tracing::debug!(parent: &self.span,
+                        return if matches!(arc_output.result(), DkimResult::TempError(_)) {
+                            (&b"451 4.7.29 ARC validation failed.\r\n"[..]).into()
+                        } else {
+                            (&b"550 5.7.29 ARC validation failed.\r\n"[..]).into()
+                        };
+                    } else {
+                        tracing::debug!(parent: &self.span,
                     context = "arc",
                     event = "verify",
                     return_path = self.data.mail_from.as_ref().unwrap().address,
                     from = auth_message.from(),
                     result = %arc_output.result());
+                    }
+                    arc_output.into()
+                }
+                Ok(None) => None,
+                Err(response) => return response,
             }
-            arc_output.into()
         } else {
             None
         };
@@ -193,12 +302,26 @@ impl<T: SessionStream> Session<T> {
 
         // Verify DMARC
         let (dmarc_result, dmarc_policy) = match &self.data.spf_mail_from {
-            Some(spf_output) if dmarc.verify() => {
-                let dmarc_output = self
-                    .core
-                    .resolvers
-                    .dns
-                    .verify_dmarc(
+            Some(_) if dmarc.verify() && budget.is_skipped(FilterStage::Dkim) => {
+                // Without DKIM results DMARC could only evaluate SPF alignment
+                // and would fail messages that rely on DKIM, so skip it too
+                budget.mark_skipped(FilterStage::Dmarc);
+                self.core
+                    .session
+                    .filter_metrics
+                    .record_skip(FilterStage::Dmarc);
+                tracing::debug!(parent: &self.span,
+                    context = "budget",
+                    event = "skip",
+                    stage = FilterStage::Dmarc.as_str(),
+                    "Skipping DMARC verification, DKIM stage was skipped.");
+                (None, None)
+            }
+            Some(spf_output) if dmarc.verify() => match self
+                .run_filter_stage(
+                    &mut budget,
+                    FilterStage::Dmarc,
+                    self.core.resolvers.dns.verify_dmarc(
                         &auth_message,
                         &dkim_output,
                         if !mail_from.domain.is_empty() {
@@ -207,90 +330,104 @@ impl<T: SessionStream> Session<T> {
                             &self.data.helo_domain
                         },
                         spf_output,
-                    )
-                    .await;
-
-                let rejected = dmarc.is_strict()
-                    && dmarc_output.policy() == dmarc::Policy::Reject
-                    && !(matches!(dmarc_output.spf_result(), DmarcResult::Pass)
-                        || matches!(dmarc_output.dkim_result(), DmarcResult::Pass));
-                let is_temp_fail = rejected
-                    && matches!(dmarc_output.spf_result(), DmarcResult::TempError(_))
-                    || matches!(dmarc_output.dkim_result(), DmarcResult::TempError(_));
-
-                // Add to DMARC output to the Authentication-Results header
-                auth_results = auth_results.with_dmarc_result(&dmarc_output);
-                let dmarc_result = if dmarc_output.spf_result() == &DmarcResult::Pass
-                    || dmarc_output.dkim_result() == &DmarcResult::Pass
-                {
-                    DmarcResult::Pass
-                } else if dmarc_output.spf_result() != &DmarcResult::None {
-                    dmarc_output.spf_result().clone()
-                } else if dmarc_output.dkim_result() != &DmarcResult::None {
-                    dmarc_output.dkim_result().clone()
-                } else {
-                    DmarcResult::None
-                };
-                let dmarc_policy = dmarc_output.policy();
+                    ),
+                )
+                .await
+            {
+                Ok(Some(dmarc_output)) => {
+                    let rejected = dmarc.is_strict()
+                        && dmarc_output.policy() == dmarc::Policy::Reject
+                        && !(matches!(dmarc_output.spf_result(), DmarcResult::Pass)
+                            || matches!(dmarc_output.dkim_result(), DmarcResult::Pass));
+                    let is_temp_fail = rejected
+                        && matches!(dmarc_output.spf_result(), DmarcResult::TempError(_))
+                        || matches!(dmarc_output.dkim_result(), DmarcResult::TempError(_));
 
-                if !rejected {
-                    tracing::debug!(parent: &self.span,
+                    // Add to DMARC output to the Authentication-Results header
+                    auth_results = auth_results.with_dmarc_result(&dmarc_output);
+                    let dmarc_result = if dmarc_output.spf_result() == &DmarcResult::Pass
+                        || dmarc_output.dkim_result() == &DmarcResult::Pass
+                    {
+                        DmarcResult::Pass
+                    } else if dmarc_output.spf_result() != &DmarcResult::None {
+                        dmarc_output.spf_result().clone()
+                    } else if dmarc_output.dkim_result() != &DmarcResult::None {
+                        dmarc_output.dkim_result().clone()
+                    } else {
+                        DmarcResult::None
+                    };
+                    let dmarc_policy = dmarc_output.policy();
+
+                    if !rejected {
+                        tracing::debug!(parent: &self.span,
                     context = "dmarc",
                     event = "verify",
                     return_path = mail_from.address,
                     from = auth_message.from(),
                     dkim_result = %dmarc_output.dkim_result(),
                     spf_result = %dmarc_output.spf_result());
-                } else {
-                    tracing::info!(parent: &self.span,
+                    } else {
+                        tracing::info!(parent: &self.span,
                     context = "dmarc",
                     event = "auth-failed",
                     return_path = mail_from.address,
                     from = auth_message.from(),
                     dkim_result = %dmarc_output.dkim_result(),
                     spf_result = %dmarc_output.spf_result());
-                }
+                    }
 
-                // Send DMARC report
-                if dmarc_output.requested_reports() {
-                    self.send_dmarc_report(
-                        &auth_message,
-                        &auth_results,
-                        rejected,
-                        dmarc_output,
-                        &dkim_output,
-                        &arc_output,
-                    )
-                    .await;
-                }
+                    // Send DMARC report
+                    if dmarc_output.requested_reports() {
+                        self.send_dmarc_report(
+                            &auth_message,
+                            &auth_results,
+                            rejected,
+                            dmarc_output,
+                            &dkim_output,
+                            &arc_output,
+                        )
+                        .await;
+                    }
 
-                if rejected {
-                    return if is_temp_fail {
-                        (&b"451 4.7.1 Email temporarily rejected per DMARC policy.\r\n"[..]).into()
-                    } else {
-                        (&b"550 5.7.1 Email rejected per DMARC policy.\r\n"[..]).into()
-                    };
-                }
+                    if rejected {
+                        return if is_temp_fail {
+                            (&b"451 4.7.1 Email temporarily rejected per DMARC policy.\r\n"[..])
+                                .into()
+                        } else {
+                            (&b"550 5.7.1 Email rejected per DMARC policy.\r\n"[..]).into()
+                        };
+                    }
 
-                (dmarc_result.into(), dmarc_policy.into())
-            }
+                    (dmarc_result.into(), dmarc_policy.into())
+                }
+                Ok(None) => (None, None),
+                Err(response) => return response,
+            },
             _ => (None, None),
         };
 
         // Analyze reports
         if self.is_report() {
             self.core.analyze_report(raw_message.clone());
-            if !rc.analysis.forward {
+            if !rc.analysis.forward && !transparent {
                 self.data.messages_sent += 1;
                 return (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into();
             }
         }
 
         // Run Milter filters
-        let mut edited_message = match self.run_milters(&auth_message).await {
-            Ok(modifications) => {
-                if !modifications.is_empty() {
-                    tracing::debug!(
+        let mut edited_message = if !dc.milters.is_empty() {
+            match self
+                .run_filter_stage(
+                    &mut budget,
+                    FilterStage::Milter,
+                    self.run_milters(&auth_message),
+                )
+                .await
+            {
+                Ok(Some(Ok(modifications))) => {
+                    if !modifications.is_empty() && !transparent {
+                        tracing::debug!(
                     parent: &self.span,
                     context = "milter",
                     event = "accept",
@@ -304,100 +441,148 @@ impl<T: SessionStream> Session<T> {
                     }),
                     "Milter filter(s) accepted message.");
 
-                    self.data
-                        .apply_milter_modifications(modifications, &auth_message)
-                        .map(Arc::new)
-                } else {
-                    None
+                        self.data
+                            .apply_milter_modifications(modifications, &auth_message)
+                            .map(Arc::new)
+                    } else {
+                        None
+                    }
+                }
+                Ok(Some(Err(response))) => {
+                    self.export_filtered_event(String::from_utf8_lossy(&response).trim_end());
+                    return response;
                 }
+                Ok(None) => None,
+                Err(response) => return response,
             }
-            Err(response) => return response,
+        } else {
+            None
         };
 
         // Pipe message
-        for pipe in &dc.pipe_commands {
-            if let Some(command_) = pipe.command.eval(self).await {
-                let piped_message = edited_message.as_ref().unwrap_or(&raw_message).clone();
-                let timeout = *pipe.timeout.eval(self).await;
-
-                let mut command = Command::new(command_);
-                for argument in pipe.arguments.eval(self).await {
-                    command.arg(argument);
-                }
-                match command
-                    .stdin(Stdio::piped())
-                    .stdout(Stdio::piped())
-                    .kill_on_drop(true)
-                    .spawn()
-                {
-                    Ok(mut child) => {
-                        if let Some(mut stdin) = child.stdin.take() {
-                            match tokio::time::timeout(timeout, stdin.write_all(&piped_message))
-                                .await
-                            {
-                                Ok(Ok(_)) => {
-                                    drop(stdin);
-                                    match tokio::time::timeout(timeout, child.wait_with_output())
-                                        .await
+        if !dc.pipe_commands.is_empty() {
+            let pipes = async {
+                for pipe in &dc.pipe_commands {
+                    if let Some(command_) = pipe.command.eval(self).await {
+                        let piped_message = edited_message.as_ref().unwrap_or(&raw_message).clone();
+                        let timeout = *pipe.timeout.eval(self).await;
+
+                        let mut command = Command::new(command_);
+                        for argument in pipe.arguments.eval(self).await {
+                            command.arg(argument);
+                        }
+                        match command
+                            .stdin(Stdio::piped())
+                            .stdout(Stdio::piped())
+                            .kill_on_drop(true)
+                            .spawn()
+                        {
+                            Ok(mut child) => {
+                                if let Some(mut stdin) = child.stdin.take() {
+                                    match tokio::time::timeout(
+                                        timeout,
+                                        stdin.write_all(&piped_message),
+                                    )
+                                    .await
                                     {
-                                        Ok(Ok(output)) => {
-                                            if output.status.success()
-                                                && !output.stdout.is_empty()
-                                                && output.stdout[..] != piped_message[..]
+                                        Ok(Ok(_)) => {
+                                            drop(stdin);
+                                            match tokio::time::timeout(
+                                                timeout,
+                                                child.wait_with_output(),
+                                            )
+                                            .await
                                             {
-                                                edited_message = Arc::new(output.stdout).into();
-                                            }
+                                                Ok(Ok(output)) => {
+                                                    if output.status.success()
+                                                        && !output.stdout.is_empty()
+                                                        && output.stdout[..] != piped_message[..]
+                                                    {
+                                                        edited_message =
+                                                            Arc::new(output.stdout).into();
+                                                    }
 
-                                            tracing::debug!(parent: &self.span,
+                                                    tracing::debug!(parent: &self.span,
                                                 context = "pipe",
                                                 event = "success",
                                                 command = command_,
                                                 status = output.status.to_string());
-                                        }
-                                        Ok(Err(err)) => {
-                                            tracing::warn!(parent: &self.span,
+                                                }
+                                                Ok(Err(err)) => {
+                                                    tracing::warn!(parent: &self.span,
                                                 context = "pipe",
                                                 event = "exec-error",
                                                 command = command_,
                                                 reason = %err);
-                                        }
-                                        Err(_) => {
-                                            tracing::warn!(parent: &self.span,
+                                                }
+                                                Err(_) => {
+                                                    tracing::warn!(parent: &self.span,
                                                 context = "pipe",
                                                 event = "timeout",
                                                 command = command_);
+                                                }
+                                            }
                                         }
-                                    }
-                                }
-                                Ok(Err(err)) => {
-                                    tracing::warn!(parent: &self.span,
+                                        Ok(Err(err)) => {
+                                            tracing::warn!(parent: &self.span,
                                         context = "pipe",
                                         event = "write-error",
                                         command = command_,
                                         reason = %err);
-                                }
-                                Err(_) => {
-                                    tracing::warn!(parent: &self.span,
+                                        }
+                                        Err(_) => {
+                                            tracing::warn!(parent: &self.span,
                                         context = "pipe",
                                         event = "stdin-timeout",
                                         command = command_);
-                                }
-                            }
-                        } else {
-                            tracing::warn!(parent: &self.span,
+                                        }
+                                    }
+                                } else {
+                                    tracing::warn!(parent: &self.span,
                                 context = "pipe",
                                 event = "stdin-failed",
                                 command = command_);
-                        }
-                    }
-                    Err(err) => {
-                        tracing::warn!(parent: &self.span,
+                                }
+                            }
+                            Err(err) => {
+                                tracing::warn!(parent: &self.span,
                                 context = "pipe",
                                 event = "spawn-error",
                                 command = command_,
                                 reason = %err);
+                            }
+                        }
                     }
                 }
+            };
+            if let Err(response) = self
+                .run_filter_stage(&mut budget, FilterStage::Pipe, pipes)
+                .await
+            {
+                return response;
+            }
+        }
+
+        // Antivirus scanning
+        let mut antivirus_result = None;
+        if dc.antivirus.is_some() {
+            match self
+                .run_filter_stage(
+                    &mut budget,
+                    FilterStage::Antivirus,
+                    self.run_antivirus(edited_message.as_ref().unwrap_or(&raw_message)),
+                )
+                .await
+            {
+                Ok(Some(Ok(result))) => {
+                    antivirus_result = result;
+                }
+                Ok(Some(Err(response))) => {
+                    self.export_filtered_event(String::from_utf8_lossy(&response).trim_end());
+                    return response;
+                }
+                Ok(None) => (),
+                Err(response) => return response,
             }
         }
 
@@ -450,9 +635,38 @@ impl<T: SessionStream> Session<T> {
                         .as_ref()
                         .map(|a| a.as_str())
                         .unwrap_or_default(),
-                );
+                )
+                .set_variable(
+                    "antivirus.result",
+                    antivirus_result
+                        .as_ref()
+                        .map(|r| r.as_str())
+                        .unwrap_or_default(),
+                )
+                .set_variable(
+                    "antivirus.threat",
+                    antivirus_result
+                        .as_ref()
+                        .map(|r| r.threat().to_string())
+                        .unwrap_or_default(),
+                )
+                .set_variable("filter.skipped", budget.skipped());
 
-            let modifications = match self.run_script(script.clone(), params).await {
+            let result = match self
+                .run_filter_stage(
+                    &mut budget,
+                    FilterStage::Sieve,
+                    self.run_script(script.clone(), params),
+                )
+                .await
+            {
+                Ok(Some(result)) => result,
+                Ok(None) => ScriptResult::Accept {
+                    modifications: vec![],
+                },
+                Err(response) => return response,
+            };
+            let modifications = match result {
                 ScriptResult::Accept { modifications } => modifications,
                 ScriptResult::Replace {
                     message,
@@ -466,13 +680,35 @@ impl<T: SessionStream> Session<T> {
                         context = "sieve",
                         event = "reject",
                         reason = message);
+                    self.core
+                        .report
+                        .operator
+                        .telemetry
+                        .record_spam_verdict(true);
+                    self.export_filtered_event(message.trim_end());
 
                     return message.into_bytes().into();
                 }
                 ScriptResult::Discard => {
+                    self.core
+                        .report
+                        .operator
+                        .telemetry
+                        .record_spam_verdict(true);
+                    self.export_filtered_event("discarded");
                     return (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into();
                 }
             };
+            let is_spam = modifications.iter().any(|modification| {
+                matches!(modification, ScriptModification::AddHeader { name, value }
+                    if name.eq_ignore_ascii_case("X-Spam-Status")
+                        && value.trim_start().starts_with("Yes"))
+            });
+            self.core
+                .report
+                .operator
+                .telemetry
+                .record_spam_verdict(is_spam);
 
             // Apply modifications
             for modification in modifications {
@@ -486,30 +722,77 @@ impl<T: SessionStream> Session<T> {
                         }
                     }
                     ScriptModification::SetEnvelope { name, value } => {
-                        self.data.apply_envelope_modification(name, value);
+                        if !transparent {
+                            self.data.apply_envelope_modification(name, value);
+                        }
                     }
                 }
             }
         }
 
+        // Record the stages skipped due to the time budget
+        if !budget.skipped.is_empty() {
+            tracing::info!(parent: &self.span,
+                context = "data",
+                event = "budget-exceeded",
+                skipped = budget.skipped(),
+                "Filter stages skipped due to time budget.");
+            headers.extend_from_slice(b"X-Filter-Skipped: ");
+            headers.extend_from_slice(budget.skipped().as_bytes());
+            headers.extend_from_slice(b"\r\n");
+        }
+
+        // Compliance archive recipients
+        let archive_rcpts = self.archive_recipients(&raw_message).await;
+
         // Build message
         let mail_from = self.data.mail_from.clone().unwrap();
         let rcpt_to = std::mem::take(&mut self.data.rcpt_to);
         let mut message = self.build_message(mail_from, rcpt_to).await;
 
+        // Honor "TLS-Required: No" unless REQUIRETLS was requested (RFC 8689)
+        if (message.flags & MAIL_REQUIRETLS) == 0
+            && auth_message
+                .raw_parsed_headers()
+                .iter()
+                .any(|(name, value)| {
+                    name.eq_ignore_ascii_case(b"TLS-Required")
+                        && std::str::from_utf8(value)
+                            .map_or(false, |value| value.trim().eq_ignore_ascii_case("no"))
+                })
+        {
+            message.flags |= MAIL_TLS_OPTIONAL;
+        }
+
+        // Transparent relays forward the original message to a fixed next hop
+        let add_headers = if transparent {
+            message.flags |= MAIL_TRANSPARENT;
+            self.core.session.config.transparent.add_headers
+        } else {
+            true
+        };
+        if !add_headers {
+            headers.clear();
+        }
+
         // Add Received header
-        if *dc.add_received.eval(self).await {
+        if add_headers && *dc.add_received.eval(self).await {
             self.write_received(&mut headers, message.id)
         }
 
+        // Add loop detection header
+        if add_headers && loop_detection {
+            self.write_loop_header(&mut headers, &message);
+        }
+
         // Add authentication results header
-        if *dc.add_auth_results.eval(self).await {
+        if add_headers && *dc.add_auth_results.eval(self).await {
             auth_results.write_header(&mut headers);
         }
 
         // Add Received-SPF header
         if let Some(spf_output) = &self.data.spf_mail_from {
-            if *dc.add_received_spf.eval(self).await {
+            if add_headers && *dc.add_received_spf.eval(self).await {
                 ReceivedSpf::new(
                     spf_output,
                     self.data.remote_ip,
@@ -523,7 +806,7 @@ impl<T: SessionStream> Session<T> {
 
         // ARC Seal
         if let (Some(arc_sealer), Some(arc_output)) = (arc_sealer, &arc_output) {
-            if !dkim_output.is_empty() && arc_output.can_be_sealed() {
+            if add_headers && !dkim_output.is_empty() && arc_output.can_be_sealed() {
                 match arc_sealer.seal(&auth_message, &auth_results, arc_output) {
                     Ok(set) => {
                         set.write_header(&mut headers);
@@ -541,27 +824,58 @@ impl<T: SessionStream> Session<T> {
         }
 
         // Add any missing headers
-        if !auth_message.has_date_header() && *dc.add_date.eval(self).await {
+        if add_headers && !auth_message.has_date_header() && *dc.add_date.eval(self).await {
             headers.extend_from_slice(b"Date: ");
             headers.extend_from_slice(Date::now().to_rfc822().as_bytes());
             headers.extend_from_slice(b"\r\n");
         }
-        if !auth_message.has_message_id_header() && *dc.add_message_id.eval(self).await {
+        if add_headers
+            && !auth_message.has_message_id_header()
+            && *dc.add_message_id.eval(self).await
+        {
             headers.extend_from_slice(b"Message-ID: ");
             let _ = generate_message_id_header(&mut headers, &self.instance.hostname);
             headers.extend_from_slice(b"\r\n");
         }
 
         // Add Return-Path
-        if *dc.add_return_path.eval(self).await {
+        if add_headers && *dc.add_return_path.eval(self).await {
             headers.extend_from_slice(b"Return-Path: <");
             headers.extend_from_slice(message.return_path.as_bytes());
             headers.extend_from_slice(b">\r\n");
         }
 
+        // Add disclaimer
+        let mut raw_message = if !transparent {
+            edited_message.unwrap_or(raw_message)
+        } else {
+            raw_message
+        };
+        let disclaimer_text = dc.disclaimer_text.eval(self).await;
+        let disclaimer_html = dc.disclaimer_html.eval(self).await;
+        if !transparent && (disclaimer_text.is_some() || disclaimer_html.is_some()) {
+            if let Some(disclaimed_message) = add_disclaimer(
+                &raw_message,
+                disclaimer_text.as_deref(),
+                disclaimer_html.as_deref(),
+            ) {
+                tracing::debug!(parent: &self.span,
+                    context = "data",
+                    event = "disclaimer",
+                    return_path = message.return_path,
+                    "Added disclaimer to message.");
+
+                raw_message = Arc::new(disclaimed_message);
+            }
+        }
+
         // DKIM sign
-        let raw_message = edited_message.unwrap_or(raw_message);
-        for signer in ac.dkim.sign.eval_and_capture(self).await.into_value(self) {
+        let signers = if add_headers {
+            ac.dkim.sign.eval_and_capture(self).await.into_value(self)
+        } else {
+            Vec::new()
+        };
+        for signer in signers {
             match signer.sign_chained(&[headers.as_ref(), &raw_message]) {
                 Ok(signature) => {
                     signature.write_header(&mut headers);
@@ -579,15 +893,89 @@ impl<T: SessionStream> Session<T> {
         // Update size
         message.size = raw_message.len() + headers.len();
 
+        // Park messages submitted by accounts under moderation
+        let parked = if self.core.queue.is_moderated(&self.data.authenticated_as) {
+            tracing::info!(
+                parent: &self.span,
+                context = "moderation",
+                event = "parked",
+                account = self.data.authenticated_as,
+                return_path = message.return_path,
+                "Message from account under moderation parked for review."
+            );
+            message.parked = self.data.authenticated_as.clone().into();
+            (
+                message.return_path.clone(),
+                message
+                    .recipients
+                    .iter()
+                    .map(|rcpt| rcpt.address.clone())
+                    .collect::<Vec<_>>(),
+            )
+                .into()
+        } else {
+            None
+        };
+
         // Verify queue quota
         if self.core.queue.has_quota(&mut message).await {
             let queue_id = message.id;
+            let event = events::is_enabled(EventKind::Received).then(|| {
+                LifecycleEvent::new(EventKind::Received)
+                    .with_queue_id(queue_id)
+                    .with_sender(message.return_path.as_str())
+                    .with_recipients(message.recipients.iter().map(|rcpt| rcpt.address.as_str()))
+                    .with_remote(self.data.remote_ip)
+                    .with_size(message.size)
+            });
             if self
                 .core
                 .queue
                 .queue_message(message, Some(&headers), &raw_message, &self.span)
                 .await
             {
+                if let Some((return_path, recipients)) = parked {
+                    self.core
+                        .notify_moderators(
+                            queue_id,
+                            &self.data.authenticated_as,
+                            &return_path,
+                            &recipients,
+                            &self.span,
+                        )
+                        .await;
+                }
+                if let Some(cert) = &self.data.trusted_peer {
+                    tracing::info!(
+                        parent: &self.span,
+                        context = "tls",
+                        event = "trusted-peer",
+                        queue_id = queue_id,
+                        subject = cert.subject,
+                        issuer = cert.issuer,
+                        serial = cert.serial,
+                        fingerprint = cert.fingerprint,
+                        "Message accepted from trusted peer."
+                    );
+                }
+                if let Some(event) = event {
+                    events::publish(event);
+                }
+                if !archive_rcpts.is_empty() {
+                    self.queue_archive_copy(queue_id, archive_rcpts, &headers, &raw_message)
+                        .await;
+                }
+                #[cfg(feature = "local_delivery")]
+                if !self.data.authenticated_as.is_empty() {
+                    // Report the submission for usage metering
+                    let _ = self
+                        .core
+                        .delivery_tx
+                        .try_send(utils::ipc::DeliveryEvent::Submission {
+                            account: self.data.authenticated_as.clone(),
+                            size: raw_message.len() + headers.len(),
+                        });
+                }
                 self.state = State::Accepted(queue_id);
                 self.data.messages_sent += 1;
                 (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into()
@@ -623,8 +1011,13 @@ impl<T: SessionStream> Session<T> {
             return_path_domain: mail_from.domain,
             recipients: Vec::with_capacity(rcpt_to.len()),
             domains: Vec::with_capacity(3),
-            flags: mail_from.flags,
+            flags: if self.data.future_release > 0 {
+                mail_from.flags | MAIL_FUTURE_RELEASE
+            } else {
+                mail_from.flags
+            },
             priority: self.data.priority,
+            parked: None,
             size: 0,
             env_id: mail_from.dsn_info,
             queue_refs: Vec::with_capacity(0),
@@ -691,6 +1084,7 @@ impl<T: SessionStream> Session<T> {
                     expires,
                     status: queue::Status::Scheduled,
                     domain: rcpt.domain,
+                    retry_strategy: None,
                     disable_tls: false,
                     changed: false,
                 });
@@ -741,6 +1135,24 @@ impl<T: SessionStream> Session<T> {
         }
     }
 
+    fn export_filtered_event(&self, reason: &str) {
+        if events::is_enabled(EventKind::Filtered) {
+            events::publish(
+                LifecycleEvent::new(EventKind::Filtered)
+                    .with_sender(
+                        self.data
+                            .mail_from
+                            .as_ref()
+                            .map(|from| from.address.as_str())
+                            .unwrap_or_default(),
+                    )
+                    .with_recipients(self.data.rcpt_to.iter().map(|rcpt| rcpt.address.as_str()))
+                    .with_remote(self.data.remote_ip)
+                    .with_reason(reason),
+            );
+        }
+    }
+
     fn write_received(&self, headers: &mut Vec<u8>, id: u64) {
         headers.extend_from_slice(b"Received: from ");
         headers.extend_from_slice(self.data.helo_domain.as_bytes());
@@ -0,0 +1,209 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+use mail_builder::encoders::base64::base64_encode_mime;
+use mail_parser::{
+    decoders::html::html_to_text, Encoding, HeaderName, MessageParser, MimeHeaders, PartType,
+};
+
+/// Appends a disclaimer to the text and HTML bodies of a message.
+///
+/// Parts that are not encoded and use a compatible charset are modified in place,
+/// otherwise they are rewritten as UTF-8 with base64 transfer encoding. Signed or
+/// encrypted messages are left untouched. Returns `None` when no changes were made.
+pub fn add_disclaimer(
+    raw_message: &[u8],
+    text: Option<&str>,
+    html: Option<&str>,
+) -> Option<Vec<u8>> {
+    let message = MessageParser::new().parse(raw_message)?;
+
+    // Modifying signed or encrypted messages would invalidate them
+    if message.parts.iter().any(|part| {
+        part.content_type().map_or(false, |ct| {
+            let subtype = ct.subtype().unwrap_or_default();
+            (ct.ctype().eq_ignore_ascii_case("multipart")
+                && (subtype.eq_ignore_ascii_case("signed")
+                    || subtype.eq_ignore_ascii_case("encrypted")))
+                || (ct.ctype().eq_ignore_ascii_case("application")
+                    && (subtype.eq_ignore_ascii_case("pkcs7-mime")
+                        || subtype.eq_ignore_ascii_case("x-pkcs7-mime")))
+        })
+    }) {
+        return None;
+    }
+
+    // Obtain the body parts, which in multipart/alternative messages
+    // include both the text and HTML versions
+    let mut parts = message
+        .text_body
+        .iter()
+        .chain(message.html_body.iter())
+        .filter_map(|part_id| message.parts.get(*part_id))
+        .collect::<Vec<_>>();
+    parts.sort_unstable_by_key(|part| part.offset_header);
+    parts.dedup_by_key(|part| part.offset_header);
+
+    let mut output = Vec::with_capacity(raw_message.len() + 1024);
+    let mut last_offset = 0;
+    let mut has_changes = false;
+
+    for part in parts {
+        let (contents, is_html) = match &part.body {
+            PartType::Text(text) => (text.as_ref(), false),
+            PartType::Html(html) => (html.as_ref(), true),
+            _ => continue,
+        };
+        if part.offset_header < last_offset
+            || part
+                .content_disposition()
+                .map_or(false, |cd| cd.is_attachment())
+        {
+            continue;
+        }
+        let disclaimer: Cow<str> = if is_html {
+            match (html, text) {
+                (Some(html), _) => html.into(),
+                (None, Some(text)) => text_to_html(text).into(),
+                (None, None) => continue,
+            }
+        } else {
+            match (text, html) {
+                (Some(text), _) => text.into(),
+                (None, Some(html)) => html_to_text(html).into(),
+                (None, None) => continue,
+            }
+        };
+        let disclaimer = disclaimer.trim();
+
+        // Do not add the disclaimer twice (for example, on resubmitted messages)
+        if disclaimer.is_empty() || contents.contains(disclaimer) {
+            continue;
+        }
+
+        let charset = part
+            .content_type()
+            .and_then(|ct| ct.attribute("charset"))
+            .unwrap_or("us-ascii");
+        let is_compatible = disclaimer.is_ascii()
+            || charset.eq_ignore_ascii_case("utf-8")
+            || charset.eq_ignore_ascii_case("utf8");
+
+        if part.encoding == Encoding::None && is_compatible {
+            // Insert the disclaimer without altering the part's headers
+            let body = raw_message.get(part.offset_body..part.offset_end)?;
+            let insert_at = part.offset_body
+                + if is_html {
+                    find_closing_body(body).unwrap_or(body.len())
+                } else {
+                    body.len()
+                };
+            output.extend_from_slice(raw_message.get(last_offset..insert_at)?);
+            if !output.ends_with(b"\n") {
+                output.extend_from_slice(b"\r\n");
+            }
+            write_lines(&mut output, disclaimer);
+            if is_html {
+                output.extend_from_slice(b"\r\n");
+            }
+            last_offset = insert_at;
+        } else {
+            // Rewrite the part using UTF-8 and base64 encoding
+            output.extend_from_slice(raw_message.get(last_offset..part.offset_header)?);
+            for header in &part.headers {
+                if !matches!(
+                    header.name,
+                    HeaderName::ContentType | HeaderName::ContentTransferEncoding
+                ) {
+                    output.extend_from_slice(
+                        raw_message.get(header.offset_field..header.offset_end)?,
+                    );
+                }
+            }
+            output.extend_from_slice(if is_html {
+                b"Content-Type: text/html; charset=\"utf-8\"\r\n".as_slice()
+            } else {
+                b"Content-Type: text/plain; charset=\"utf-8\"\r\n".as_slice()
+            });
+            output.extend_from_slice(b"Content-Transfer-Encoding: base64\r\n\r\n");
+
+            let mut body = String::with_capacity(contents.len() + disclaimer.len() + 2);
+            let insert_at = if is_html {
+                find_closing_body(contents.as_bytes()).unwrap_or(contents.len())
+            } else {
+                contents.len()
+            };
+            body.push_str(&contents[..insert_at]);
+            if !body.ends_with('\n') {
+                body.push_str("\r\n");
+            }
+            body.push_str(disclaimer);
+            body.push_str("\r\n");
+            body.push_str(&contents[insert_at..]);
+            base64_encode_mime(body.as_bytes(), &mut output, false).ok()?;
+            last_offset = part.offset_end;
+        }
+        has_changes = true;
+    }
+
+    if has_changes {
+        output.extend_from_slice(raw_message.get(last_offset..)?);
+        Some(output)
+    } else {
+        None
+    }
+}
+
+fn find_closing_body(html: &[u8]) -> Option<usize> {
+    html.windows(6)
+        .rposition(|window| window.eq_ignore_ascii_case(b"</body"))
+}
+
+fn write_lines(output: &mut Vec<u8>, text: &str) {
+    for (pos, line) in text.lines().enumerate() {
+        if pos > 0 {
+            output.extend_from_slice(b"\r\n");
+        }
+        output.extend_from_slice(line.as_bytes());
+    }
+}
+
+fn text_to_html(text: &str) -> String {
+    let mut html = String::with_capacity(text.len() + 16);
+    html.push_str("<p>");
+    for ch in text.trim().chars() {
+        match ch {
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '&' => html.push_str("&amp;"),
+            '"' => html.push_str("&quot;"),
+            '\n' => html.push_str("<br>"),
+            '\r' => (),
+            _ => html.push(ch),
+        }
+    }
+    html.push_str("</p>");
+    html
+}
@@ -30,6 +30,9 @@ use utils::listener::SessionStream;
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_ehlo(&mut self, domain: String) -> Result<(), ()> {
+        // Discard XFORWARD attributes, they do not outlive a new greeting
+        self.reset_xforward();
+
         // Set EHLO domain
 
         if domain != self.data.helo_domain {
@@ -157,6 +160,11 @@ impl<T: SessionStream> Session<T> {
                 if !self.stream.is_tls() && !self.params.auth_plain_text {
                     response.auth_mechanisms &= !(AUTH_PLAIN | AUTH_LOGIN);
                 }
+                if self.instance.tls_client_auth.is_none()
+                    || self.stream.tls_client_certificate().is_none()
+                {
+                    response.auth_mechanisms &= !AUTH_EXTERNAL;
+                }
                 if response.auth_mechanisms != 0 {
                     response.capabilities |= EXT_AUTH;
                 }
@@ -205,6 +213,23 @@ impl<T: SessionStream> Session<T> {
         // Generate response
         let mut buf = Vec::with_capacity(64);
         response.write(&mut buf).ok();
+
+        // Advertise XCLIENT and XFORWARD to trusted upstream proxies
+        if self.params.xclient && buf.len() > 4 {
+            let last_line = buf[..buf.len() - 2]
+                .iter()
+                .rposition(|&ch| ch == b'\n')
+                .map_or(0, |pos| pos + 1);
+            buf[last_line + 3] = b'-';
+            buf.extend_from_slice(
+                concat!(
+                    "250-XCLIENT NAME ADDR PORT PROTO HELO LOGIN DESTADDR DESTPORT\r\n",
+                    "250 XFORWARD NAME ADDR PORT PROTO HELO IDENT SOURCE\r\n"
+                )
+                .as_bytes(),
+            );
+        }
+
         self.write(&buf).await
     }
 }
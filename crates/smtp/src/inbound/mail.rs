@@ -24,10 +24,11 @@
 use std::time::SystemTime;
 
 use mail_auth::{IprevOutput, IprevResult, SpfOutput, SpfResult};
-use smtp_proto::{MailFrom, MAIL_BY_NOTIFY, MAIL_BY_RETURN, MAIL_REQUIRETLS};
+use smtp_proto::{MailFrom, MAIL_BY_NOTIFY, MAIL_BY_RETURN, MAIL_REQUIRETLS, MAIL_SMTPUTF8};
 use utils::listener::SessionStream;
 
 use crate::{
+    config::SenderAlignment,
     core::{Session, SessionAddress},
     queue::DomainPart,
     scripts::{ScriptModification, ScriptResult},
@@ -94,7 +95,14 @@ impl<T: SessionStream> Session<T> {
             return self.write(message).await;
         }
 
-        let (address, address_lcase, domain) = if !from.address.is_empty() {
+        // Internationalized addresses require the SMTPUTF8 extension (RFC 6531)
+        if !from.address.is_ascii() && (from.flags & MAIL_SMTPUTF8) == 0 {
+            return self
+                .write(b"553 5.6.7 SMTPUTF8 is required for internationalized addresses.\r\n")
+                .await;
+        }
+
+        let (mut address, mut address_lcase, mut domain) = if !from.address.is_empty() {
             let address_lcase = from.address.to_lowercase();
             let domain = address_lcase.domain_part().to_string();
             (from.address, address_lcase, domain)
@@ -113,6 +121,38 @@ impl<T: SessionStream> Session<T> {
                 .await;
         }
 
+        // Enforce the alignment of the envelope sender with the authenticated identities
+        if !address_lcase.is_empty() && !self.is_sender_aligned(&address_lcase) {
+            match (self.sender_alignment(), self.canonical_sender()) {
+                (SenderAlignment::Disable | SenderAlignment::Tag, _) => (),
+                (SenderAlignment::Rewrite, Some(canonical)) => {
+                    tracing::debug!(parent: &self.span,
+                        context = "mail-from",
+                        event = "sender-alignment",
+                        action = "rewrite",
+                        address = address.as_str(),
+                        canonical = canonical,
+                        "Envelope sender rewritten to the canonical sender address.");
+
+                    address = canonical.to_string();
+                    address_lcase = address.clone();
+                    domain = address_lcase.domain_part().to_string();
+                }
+                _ => {
+                    tracing::info!(parent: &self.span,
+                        context = "mail-from",
+                        event = "sender-alignment",
+                        action = "reject",
+                        address = address.as_str(),
+                        "Envelope sender not aligned with the authenticated identity.");
+
+                    return self
+                        .write(b"550 5.7.1 Sender not aligned with authenticated identity.\r\n")
+                        .await;
+                }
+            }
+        }
+
         let has_dsn = from.env_id.is_some();
         self.data.mail_from = SessionAddress {
             address,
@@ -130,7 +170,7 @@ impl<T: SessionStream> Session<T> {
                 .await
             {
                 ScriptResult::Accept { modifications } => {
-                    if !modifications.is_empty() {
+                    if !modifications.is_empty() && !self.params.transparent {
                         tracing::debug!(parent: &self.span,
                             context = "sieve",
                             event = "modify",
@@ -156,18 +196,21 @@ impl<T: SessionStream> Session<T> {
             }
         }
 
-        // Address rewriting
-        if let Some(new_address) = self
-            .core
-            .session
-            .config
-            .mail
-            .rewrite
-            .eval_and_capture(self)
-            .await
-            .into_value(self)
-            .map(|s| s.into_owned())
-        {
+        // Address rewriting, transparent relays preserve the original envelope
+        let new_address = if !self.params.transparent {
+            self.core
+                .session
+                .config
+                .mail
+                .rewrite
+                .eval_and_capture(self)
+                .await
+                .into_value(self)
+                .map(|s| s.into_owned())
+        } else {
+            None
+        };
+        if let Some(new_address) = new_address {
             let mail_from = self.data.mail_from.as_mut().unwrap();
             if new_address.contains('@') {
                 mail_from.address_lcase = new_address.to_lowercase();
@@ -188,6 +231,11 @@ impl<T: SessionStream> Session<T> {
             return self
                 .write(b"501 5.5.4 REQUIRETLS has been disabled.\r\n")
                 .await;
+        } else if (from.flags & MAIL_REQUIRETLS) != 0 && !self.stream.is_tls() {
+            self.data.mail_from = None;
+            return self
+                .write(b"530 5.7.10 REQUIRETLS requires a TLS session.\r\n")
+                .await;
         }
         if (from.flags & (MAIL_BY_NOTIFY | MAIL_BY_RETURN)) != 0 {
             if let Some(duration) = config.deliver_by.eval(self).await {
@@ -327,6 +375,12 @@ impl<T: SessionStream> Session<T> {
                 }
             }
 
+            // Verify that the return path is deliverable
+            if !self.verify_sender().await? {
+                self.data.mail_from = None;
+                return Ok(());
+            }
+
             tracing::debug!(parent: &self.span,
                 context = "mail-from",
                 event = "success",
@@ -0,0 +1,262 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::fmt::Write;
+
+use ahash::AHashMap;
+use mail_parser::MessageParser;
+use serde::Serialize;
+use utils::listener::SessionStream;
+
+use crate::{
+    config::{IfBlock, LoopDetection},
+    core::Session,
+    queue::{Message, QueueId},
+};
+
+pub const LOOP_KEY_CONTEXT: &str = "Stalwart SMTP loop detection key";
+const MAC_LEN: usize = 8;
+
+#[derive(Debug, Default)]
+pub struct LoopMetrics {
+    domains: parking_lot::Mutex<AHashMap<String, u64>>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct LoopReport {
+    pub total: u64,
+    pub domains: Vec<LoopDomainReport>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct LoopDomainReport {
+    pub domain: String,
+    pub count: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LoopVerdict {
+    DeliveredTo { rcpt: String },
+    Recipient { rcpt: String, path: Vec<String> },
+    MaxHops { path: Vec<String> },
+}
+
+struct LoopStamp<'x> {
+    host: &'x str,
+    queue_id: QueueId,
+    macs: Vec<&'x str>,
+}
+
+impl<T: SessionStream> Session<T> {
+    pub async fn detect_loop(&self, raw_message: &[u8]) -> Option<LoopVerdict> {
+        let config = &self.core.session.config.data.loop_detection;
+        let message = MessageParser::new().parse_headers(raw_message)?;
+
+        // A Delivered-To header matching a recipient means the message was already delivered
+        if *config.delivered_to.eval(self).await {
+            for value in message.header_values("Delivered-To") {
+                let address = value
+                    .as_text()
+                    .unwrap_or_default()
+                    .trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_lowercase();
+                if let Some(rcpt) = self
+                    .data
+                    .rcpt_to
+                    .iter()
+                    .find(|rcpt| rcpt.address_lcase == address)
+                {
+                    return Some(LoopVerdict::DeliveredTo {
+                        rcpt: rcpt.address_lcase.clone(),
+                    });
+                }
+            }
+        }
+
+        // Loop headers are prepended on each hop, the oldest one is the last
+        let stamps = message
+            .header_values(config.header.as_str())
+            .filter_map(|value| value.as_text().and_then(LoopStamp::parse))
+            .collect::<Vec<_>>();
+        if stamps.is_empty() {
+            return None;
+        }
+        let path = || {
+            stamps
+                .iter()
+                .rev()
+                .map(|stamp| format!("{} ({:X})", stamp.host, stamp.queue_id))
+                .collect::<Vec<_>>()
+        };
+
+        // The message was already handled by this server for one of the recipients
+        for stamp in &stamps {
+            for rcpt in &self.data.rcpt_to {
+                let mac = loop_mac(&config.key, stamp.queue_id, &rcpt.address_lcase);
+                if stamp.macs.contains(&mac.as_str()) {
+                    return Some(LoopVerdict::Recipient {
+                        rcpt: rcpt.address_lcase.clone(),
+                        path: path(),
+                    });
+                }
+            }
+        }
+
+        if stamps.len() >= *config.max_hops.eval(self).await {
+            Some(LoopVerdict::MaxHops { path: path() })
+        } else {
+            None
+        }
+    }
+
+    pub fn write_loop_header(&self, headers: &mut Vec<u8>, message: &Message) {
+        let config = &self.core.session.config.data.loop_detection;
+        let mut header = format!(
+            "{}: {} id {:X};",
+            config.header, self.instance.hostname, message.id
+        );
+        let mut line_len = header.len();
+        for rcpt in &message.recipients {
+            let mac = loop_mac(&config.key, message.id, &rcpt.address_lcase);
+            if line_len + mac.len() + 1 > 76 {
+                header.push_str("\r\n\t");
+                line_len = 1;
+            } else {
+                header.push(' ');
+                line_len += 1;
+            }
+            header.push_str(&mac);
+            line_len += mac.len();
+        }
+        header.push_str("\r\n");
+        headers.extend_from_slice(header.as_bytes());
+    }
+
+    pub fn record_loop(&self, verdict: &LoopVerdict) {
+        let mut domains = self
+            .data
+            .rcpt_to
+            .iter()
+            .map(|rcpt| rcpt.domain.as_str())
+            .collect::<Vec<_>>();
+        domains.sort_unstable();
+        domains.dedup();
+
+        tracing::info!(parent: &self.span,
+            context = "data",
+            event = "loop-detected",
+            return_path = self.data.mail_from.as_ref().unwrap().address,
+            verdict = ?verdict,
+            "Message rejected due to a mail loop.");
+
+        self.core.session.loop_metrics.record(&domains);
+    }
+}
+
+impl LoopVerdict {
+    pub fn response(&self) -> Vec<u8> {
+        match self {
+            LoopVerdict::DeliveredTo { rcpt } => {
+                format!(
+                    "554 5.4.6 Mail loop detected: message was already delivered to <{rcpt}>.\r\n"
+                )
+            }
+            LoopVerdict::Recipient { rcpt, path } => format!(
+                "554 5.4.6 Mail loop detected for <{rcpt}>, message path: {}.\r\n",
+                path.join(" -> ")
+            ),
+            LoopVerdict::MaxHops { path } => format!(
+                "554 5.4.6 Mail loop detected, too many hops through this server: {}.\r\n",
+                path.join(" -> ")
+            ),
+        }
+        .into_bytes()
+    }
+}
+
+impl<'x> LoopStamp<'x> {
+    fn parse(value: &'x str) -> Option<Self> {
+        let (host_id, macs) = value.split_once(';')?;
+        let mut host_id = host_id.split_whitespace();
+        let host = host_id.next()?;
+        if host_id.next()? != "id" {
+            return None;
+        }
+
+        Some(LoopStamp {
+            host,
+            queue_id: QueueId::from_str_radix(host_id.next()?, 16).ok()?,
+            macs: macs.split_whitespace().collect(),
+        })
+    }
+}
+
+impl LoopMetrics {
+    pub fn record(&self, domains: &[&str]) {
+        let mut metrics = self.domains.lock();
+        for domain in domains {
+            *metrics.entry(domain.to_string()).or_default() += 1;
+        }
+    }
+
+    pub fn report(&self) -> LoopReport {
+        let metrics = self.domains.lock();
+        let mut domains = metrics
+            .iter()
+            .map(|(domain, count)| LoopDomainReport {
+                domain: domain.clone(),
+                count: *count,
+            })
+            .collect::<Vec<_>>();
+        domains
+            .sort_unstable_by(|a, b| b.count.cmp(&a.count).then_with(|| a.domain.cmp(&b.domain)));
+
+        LoopReport {
+            total: domains.iter().map(|domain| domain.count).sum(),
+            domains,
+        }
+    }
+}
+
+impl Default for LoopDetection {
+    fn default() -> Self {
+        LoopDetection {
+            enable: IfBlock::new(false),
+            header: "X-Loop-Detect".to_string(),
+            key: rand::random(),
+            max_hops: IfBlock::new(3),
+            delivered_to: IfBlock::new(true),
+        }
+    }
+}
+
+fn loop_mac(key: &[u8; 32], queue_id: QueueId, rcpt: &str) -> String {
+    let hash = blake3::keyed_hash(key, format!("{queue_id:X}:{rcpt}").as_bytes());
+    let mut mac = String::with_capacity(MAC_LEN * 2);
+    for byte in &hash.as_bytes()[..MAC_LEN] {
+        let _ = write!(mac, "{byte:02x}");
+    }
+    mac
+}
@@ -28,15 +28,24 @@ use mail_auth::{
 
 use crate::config::{ArcSealer, DkimSigner};
 
+pub mod alignment;
+pub mod antivirus;
+pub mod archive;
 pub mod auth;
+pub mod budget;
 pub mod data;
+pub mod disclaimer;
 pub mod ehlo;
 pub mod mail;
+pub mod mail_loop;
 pub mod milter;
 pub mod rcpt;
 pub mod session;
 pub mod spawn;
+pub mod validate;
+pub mod verify;
 pub mod vrfy;
+pub mod xclient;
 
 impl ArcSealer {
     pub fn seal<'x>(
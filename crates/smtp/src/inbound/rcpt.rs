@@ -21,8 +21,10 @@
  * for more details.
 */
 
+use directory::core::alias::AliasVerdict;
 use smtp_proto::{
-    RcptTo, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
+    RcptTo, MAIL_SMTPUTF8, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER,
+    RCPT_NOTIFY_SUCCESS,
 };
 use utils::listener::SessionStream;
 
@@ -61,6 +63,19 @@ impl<T: SessionStream> Session<T> {
                 .await;
         }
 
+        // Internationalized addresses require the SMTPUTF8 extension (RFC 6531)
+        if !to.address.is_ascii()
+            && self
+                .data
+                .mail_from
+                .as_ref()
+                .map_or(true, |mail_from| (mail_from.flags & MAIL_SMTPUTF8) == 0)
+        {
+            return self
+                .write(b"553 5.6.7 SMTPUTF8 is required for internationalized addresses.\r\n")
+                .await;
+        }
+
         // Build RCPT
         let address_lcase = to.address.to_lowercase();
         let rcpt = SessionAddress {
@@ -94,7 +109,7 @@ impl<T: SessionStream> Session<T> {
                     .await
                 {
                     ScriptResult::Accept { modifications } => {
-                        if !modifications.is_empty() {
+                        if !modifications.is_empty() && !self.params.transparent {
                             tracing::debug!(parent: &self.span,
                             context = "sieve",
                             event = "modify",
@@ -122,18 +137,21 @@ impl<T: SessionStream> Session<T> {
                 }
             }
 
-            // Address rewriting
-            if let Some(new_address) = self
-                .core
-                .session
-                .config
-                .rcpt
-                .rewrite
-                .eval_and_capture(self)
-                .await
-                .into_value(self)
-                .map(|s| s.into_owned())
-            {
+            // Address rewriting, transparent relays preserve the original envelope
+            let new_address = if !self.params.transparent {
+                self.core
+                    .session
+                    .config
+                    .rcpt
+                    .rewrite
+                    .eval_and_capture(self)
+                    .await
+                    .into_value(self)
+                    .map(|s| s.into_owned())
+            } else {
+                None
+            };
+            if let Some(new_address) = new_address {
                 let rcpt = self.data.rcpt_to.last_mut().unwrap();
                 if new_address.contains('@') {
                     rcpt.address_lcase = new_address.to_lowercase();
@@ -164,6 +182,55 @@ impl<T: SessionStream> Session<T> {
         {
             if let Ok(is_local_domain) = directory.is_local_domain(&rcpt.domain).await {
                 if is_local_domain {
+                    // Disposable aliases may have expired or block the sender
+                    let sender = self
+                        .data
+                        .mail_from
+                        .as_ref()
+                        .map_or("", |mail_from| mail_from.address_lcase.as_str());
+                    match directory.alias_verdict(&rcpt.address_lcase, sender).await {
+                        Ok(Some(AliasVerdict::Expired)) => {
+                            tracing::debug!(parent: &self.span,
+                                            context = "rcpt",
+                                            event = "error",
+                                            address = &rcpt.address_lcase,
+                                            "Disposable alias has expired.");
+
+                            self.data.rcpt_to.pop();
+                            return self
+                                .rcpt_error(b"550 5.1.6 Recipient address has expired.\r\n")
+                                .await;
+                        }
+                        Ok(Some(AliasVerdict::Blocked)) => {
+                            tracing::debug!(parent: &self.span,
+                                            context = "rcpt",
+                                            event = "error",
+                                            address = &rcpt.address_lcase,
+                                            sender = sender,
+                                            "Sender is blocked by disposable alias.");
+
+                            self.data.rcpt_to.pop();
+                            return self
+                                .rcpt_error(
+                                    b"550 5.7.1 Sender is not allowed to deliver to this address.\r\n",
+                                )
+                                .await;
+                        }
+                        Ok(_) => (),
+                        Err(_) => {
+                            tracing::debug!(parent: &self.span,
+                                            context = "rcpt",
+                                            event = "error",
+                                            address = &rcpt.address_lcase,
+                                            "Temporary address verification failure.");
+
+                            self.data.rcpt_to.pop();
+                            return self
+                                .write(b"451 4.4.3 Unable to verify address at this time.\r\n")
+                                .await;
+                        }
+                    }
+
                     if let Ok(is_local_address) = directory.rcpt(&rcpt.address_lcase).await {
                         if !is_local_address {
                             tracing::debug!(parent: &self.span,
@@ -172,10 +239,56 @@ impl<T: SessionStream> Session<T> {
                                             address = &rcpt.address_lcase,
                                             "Mailbox does not exist.");
 
+                            // Suggest similar addresses to authenticated users only,
+                            // so that the directory cannot be enumerated by strangers
+                            let suggestions = if !self.data.authenticated_as.is_empty()
+                                && *self
+                                    .core
+                                    .session
+                                    .config
+                                    .rcpt
+                                    .suggest
+                                    .enable
+                                    .eval(self)
+                                    .await
+                            {
+                                let config = &self.core.session.config.rcpt.suggest;
+                                directory
+                                    .suggest(
+                                        &rcpt.address_lcase,
+                                        config.max_distance,
+                                        config.max_results,
+                                    )
+                                    .await
+                                    .unwrap_or_default()
+                            } else {
+                                vec![]
+                            };
+
+                            let response = if !suggestions.is_empty() {
+                                tracing::info!(parent: &self.span,
+                                               context = "rcpt",
+                                               event = "suggest",
+                                               account = &self.data.authenticated_as,
+                                               address = &rcpt.address_lcase,
+                                               suggestions = ?suggestions,
+                                               "Suggested similar addresses for unknown recipient.");
+
+                                format!(
+                                    "550 5.1.2 Mailbox does not exist. Did you mean {}?\r\n",
+                                    suggestions
+                                        .iter()
+                                        .map(|address| format!("<{address}>"))
+                                        .collect::<Vec<_>>()
+                                        .join(" or ")
+                                )
+                                .into_bytes()
+                            } else {
+                                b"550 5.1.2 Mailbox does not exist.\r\n".to_vec()
+                            };
+
                             self.data.rcpt_to.pop();
-                            return self
-                                .rcpt_error(b"550 5.1.2 Mailbox does not exist.\r\n")
-                                .await;
+                            return self.rcpt_error(&response).await;
                         }
                     } else {
                         tracing::debug!(parent: &self.span,
@@ -23,6 +23,8 @@
 
 use std::net::{IpAddr, Ipv4Addr};
 
+use jmap_proto::error::code::ErrorCode;
+
 use smtp_proto::{
     request::receiver::{
         BdatReceiver, DataReceiver, DummyDataReceiver, DummyLineReceiver, LineReceiver,
@@ -41,7 +43,10 @@ use crate::{
     core::{Session, State},
 };
 
-use super::auth::SaslToken;
+use super::{
+    auth::SaslToken,
+    xclient::{XCommand, MAX_XCOMMAND_LENGTH},
+};
 
 impl<T: SessionStream> Session<T> {
     pub async fn ingest(&mut self, bytes: &[u8]) -> Result<bool, ()> {
@@ -51,6 +56,46 @@ impl<T: SessionStream> Session<T> {
         'outer: loop {
             match &mut state {
                 State::Request(receiver) => loop {
+                    // XCLIENT and XFORWARD are not part of the SMTP grammar understood
+                    // by the request parser, intercept them before they reach it.
+                    // Lines split across reads are buffered in the receiver until
+                    // the command is complete.
+                    let line = if receiver.buf.is_empty() {
+                        if let Some((command, args, len)) = XCommand::parse_line(iter.as_slice()) {
+                            let args = args.to_string();
+                            iter = iter.as_slice()[len..].iter();
+                            self.handle_xcommand(command, &args).await?;
+                            continue;
+                        }
+                        XCommand::is_partial(iter.as_slice()).then(Vec::new)
+                    } else if XCommand::is_partial(&receiver.buf) {
+                        std::mem::take(&mut receiver.buf).into()
+                    } else {
+                        None
+                    };
+                    if let Some(mut line) = line {
+                        let bytes_left = iter.as_slice();
+                        if let Some(eol) = bytes_left.iter().position(|&ch| ch == b'\n') {
+                            line.extend_from_slice(&bytes_left[..=eol]);
+                            iter = bytes_left[eol + 1..].iter();
+                            if let Some((command, args, _)) = XCommand::parse_line(&line) {
+                                let args = args.to_string();
+                                self.handle_xcommand(command, &args).await?;
+                            } else {
+                                self.write(b"500 5.5.1 Invalid command.\r\n").await?;
+                            }
+                            continue;
+                        } else if line.len() + bytes_left.len() < MAX_XCOMMAND_LENGTH {
+                            line.extend_from_slice(bytes_left);
+                            receiver.buf = line;
+                            break 'outer;
+                        } else {
+                            iter = [].iter();
+                            state = State::RequestTooLarge(DummyLineReceiver::default());
+                            continue 'outer;
+                        }
+                    }
+
                     match receiver.ingest(&mut iter, bytes) {
                         Ok(request) => match request {
                             Request::Rcpt { to } => {
@@ -353,15 +398,25 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
     pub fn reset(&mut self) {
         self.data.mail_from = None;
         self.data.spf_mail_from = None;
+        self.data.sender_verify = None;
         self.data.rcpt_to.clear();
         self.data.message = Vec::with_capacity(0);
         self.data.priority = 0;
         self.data.delivery_by = 0;
         self.data.future_release = 0;
+        self.reset_xforward();
     }
 
     #[inline(always)]
     pub async fn write(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        // Attach stable error codes to error responses
+        let tagged = if self.params.error_codes {
+            ErrorCode::tag_smtp_response(bytes)
+        } else {
+            None
+        };
+        let bytes = tagged.as_deref().unwrap_or(bytes);
+
         let err = match self.stream.write_all(bytes).await {
             Ok(_) => match self.stream.flush().await {
                 Ok(_) => {
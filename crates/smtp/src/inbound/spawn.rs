@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::time::Instant;
+use std::{borrow::Cow, time::Instant};
 
 use tokio_rustls::server::TlsStream;
 use utils::listener::{SessionManager, SessionStream};
@@ -51,12 +51,14 @@ impl SessionManager for SmtpSessionManager {
 
         // Enforce throttle
         async {
+            session.verify_trusted_peer();
             if session.is_allowed().await
                 && session.init_conn().await
                 && session.handle_conn().await
                 && session.instance.acceptor.is_tls()
             {
                 if let Ok(mut session) = session.into_tls().await {
+                    session.verify_trusted_peer();
                     session.handle_conn().await;
                 }
             }
@@ -98,14 +100,54 @@ impl<T: SessionStream> Session<T> {
             }
         }
 
+        // Legal notices and other banners are sent as a multi-line greeting
         let instance = self.instance.clone();
-        if self.write(instance.data.as_bytes()).await.is_err() {
+        let greeting: Cow<str> = if let Some(banner) = &instance.banner {
+            let mut greeting = String::with_capacity(instance.data.len() + 128);
+            for line in banner.render(&instance.hostname) {
+                greeting.push_str("220-");
+                greeting.push_str(&line);
+                greeting.push_str("\r\n");
+            }
+            greeting.push_str(&instance.data);
+            greeting.into()
+        } else {
+            instance.data.as_str().into()
+        };
+        if self.write(greeting.as_bytes()).await.is_err() {
             return false;
         }
 
         true
     }
 
+    pub fn verify_trusted_peer(&mut self) {
+        let peers = &self.core.session.config.trusted_peers;
+        if peers.fingerprints.is_empty() && peers.issuers.is_empty() {
+            return;
+        }
+
+        if let Some(cert) = self.stream.tls_client_certificate() {
+            if peers.fingerprints.contains(&cert.fingerprint)
+                || peers
+                    .issuers
+                    .iter()
+                    .any(|issuer| issuer.eq_ignore_ascii_case(&cert.issuer))
+            {
+                tracing::info!(
+                    parent: &self.span,
+                    context = "tls",
+                    event = "trusted-peer",
+                    subject = cert.subject,
+                    issuer = cert.issuer,
+                    fingerprint = cert.fingerprint,
+                    "Client certificate belongs to a trusted peer."
+                );
+                self.data.trusted_peer = cert.into();
+            }
+        }
+    }
+
     pub async fn handle_conn(&mut self) -> bool {
         let mut buf = vec![0; 8192];
         let mut shutdown_rx = self.instance.shutdown_rx.clone();
@@ -200,15 +242,34 @@ impl<T: SessionStream> Session<T> {
 
     pub async fn into_tls(self) -> Result<Session<TlsStream<T>>, ()> {
         let span = self.span;
-        Ok(Session {
-            stream: self.instance.tls_accept(self.stream, &span).await?,
+        let stream = self.instance.tls_accept(self.stream, &span).await?;
+        let mut in_flight = self.in_flight;
+        let (instance, is_virtual_host) = match self.instance.virtual_host(&stream, &span)? {
+            Some((instance, slot)) => {
+                in_flight.push(slot);
+                (instance, true)
+            }
+            None => (self.instance, false),
+        };
+        let mut session = Session {
+            stream,
             state: self.state,
             data: self.data,
-            instance: self.instance,
+            instance,
             core: self.core,
-            in_flight: self.in_flight,
+            in_flight,
             params: self.params,
             span,
-        })
+        };
+
+        // Virtual hosts may define their own session settings,
+        // the session duration is not extended
+        if is_virtual_host {
+            let valid_until = session.data.valid_until;
+            session.eval_session_params().await;
+            session.data.valid_until = valid_until;
+        }
+
+        Ok(session)
     }
 }
@@ -0,0 +1,268 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{fmt::Display, ops::Range};
+
+use mail_builder::headers::date::Date;
+
+/// Headers that RFC 5322 allows at most once per message.
+const SINGLE_HEADERS: &[(&str, &str)] = &[
+    ("date", "Date"),
+    ("from", "From"),
+    ("sender", "Sender"),
+    ("reply-to", "Reply-To"),
+    ("to", "To"),
+    ("cc", "Cc"),
+    ("bcc", "Bcc"),
+    ("message-id", "Message-ID"),
+    ("in-reply-to", "In-Reply-To"),
+    ("references", "References"),
+    ("subject", "Subject"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    MissingHeader(&'static str),
+    DuplicateHeader(&'static str),
+    MalformedHeader,
+    BareLineFeed,
+    LineTooLong,
+}
+
+struct HeaderField {
+    name: Option<String>,
+    range: Range<usize>,
+}
+
+struct Headers {
+    fields: Vec<HeaderField>,
+    end: usize,
+}
+
+/// Returns the RFC 5322 violations found in a message.
+pub fn validate_message(raw_message: &[u8]) -> Vec<Violation> {
+    let headers = Headers::parse(raw_message);
+    let mut violations = Vec::new();
+
+    for (name, display_name) in [("date", "Date"), ("from", "From")] {
+        if !headers.contains(name) {
+            violations.push(Violation::MissingHeader(display_name));
+        }
+    }
+    for (name, display_name) in SINGLE_HEADERS {
+        if headers.count(name) > 1 {
+            violations.push(Violation::DuplicateHeader(display_name));
+        }
+    }
+    if headers.fields.iter().any(|field| field.name.is_none()) {
+        violations.push(Violation::MalformedHeader);
+    }
+    if raw_message
+        .iter()
+        .enumerate()
+        .any(|(pos, &ch)| ch == b'\n' && (pos == 0 || raw_message[pos - 1] != b'\r'))
+    {
+        violations.push(Violation::BareLineFeed);
+    }
+    if raw_message
+        .split(|&ch| ch == b'\n')
+        .any(|line| line.strip_suffix(b"\r").unwrap_or(line).len() > 998)
+    {
+        violations.push(Violation::LineTooLong);
+    }
+
+    violations
+}
+
+/// Fixes the most common RFC 5322 violations: missing Date and From headers are added,
+/// repeated single-instance headers and malformed header lines are removed and bare
+/// line feeds are converted to CRLF. Long lines are left untouched.
+pub fn normalize_message(raw_message: &[u8], from: &str) -> Vec<u8> {
+    let headers = Headers::parse(raw_message);
+    let mut output = Vec::with_capacity(raw_message.len() + 128);
+
+    if !headers.contains("date") {
+        output.extend_from_slice(b"Date: ");
+        output.extend_from_slice(Date::now().to_rfc822().as_bytes());
+        output.extend_from_slice(b"\r\n");
+    }
+    if !headers.contains("from") && !from.is_empty() {
+        output.extend_from_slice(b"From: <");
+        output.extend_from_slice(from.as_bytes());
+        output.extend_from_slice(b">\r\n");
+    }
+
+    let mut seen: Vec<&str> = Vec::new();
+    for field in &headers.fields {
+        if let Some(name) = field.name.as_deref() {
+            if SINGLE_HEADERS.iter().any(|(single, _)| *single == name) {
+                if seen.contains(&name) {
+                    continue;
+                }
+                seen.push(name);
+            }
+            write_crlf(&mut output, &raw_message[field.range.clone()]);
+        }
+    }
+    write_crlf(&mut output, &raw_message[headers.end..]);
+
+    output
+}
+
+/// Replaces the address of the From header, keeping the display name if present.
+/// The original header is preserved as X-Original-From.
+pub fn rewrite_from_header(raw_message: &[u8], from: &str) -> Vec<u8> {
+    let headers = Headers::parse(raw_message);
+    let mut output = Vec::with_capacity(raw_message.len() + from.len() + 64);
+    let mut pos = 0;
+
+    for field in headers
+        .fields
+        .iter()
+        .filter(|field| field.name.as_deref() == Some("from"))
+    {
+        let header = &raw_message[field.range.clone()];
+        let colon = header.iter().position(|&ch| ch == b':').unwrap();
+        let value = &header[colon + 1..];
+        let value = value
+            .strip_suffix(b"\n")
+            .map(|value| value.strip_suffix(b"\r").unwrap_or(value))
+            .unwrap_or(value);
+
+        output.extend_from_slice(&raw_message[pos..field.range.start]);
+        output.extend_from_slice(b"X-Original-From:");
+        output.extend_from_slice(value);
+        output.extend_from_slice(b"\r\n");
+        output.extend_from_slice(&header[..colon + 1]);
+        match (
+            value.iter().rposition(|&ch| ch == b'<'),
+            value.iter().rposition(|&ch| ch == b'>'),
+        ) {
+            (Some(start), Some(end)) if start < end => {
+                output.extend_from_slice(&value[..start + 1]);
+                output.extend_from_slice(from.as_bytes());
+                output.extend_from_slice(&value[end..]);
+            }
+            _ => {
+                output.extend_from_slice(b" <");
+                output.extend_from_slice(from.as_bytes());
+                output.push(b'>');
+            }
+        }
+        output.extend_from_slice(b"\r\n");
+        pos = field.range.end;
+    }
+    output.extend_from_slice(&raw_message[pos..]);
+
+    output
+}
+
+/// Removes all occurrences of a header, `name` must be lowercase.
+pub fn remove_header(raw_message: &[u8], name: &str) -> Vec<u8> {
+    let headers = Headers::parse(raw_message);
+    let mut output = Vec::with_capacity(raw_message.len());
+    let mut pos = 0;
+
+    for field in headers
+        .fields
+        .iter()
+        .filter(|field| field.name.as_deref() == Some(name))
+    {
+        output.extend_from_slice(&raw_message[pos..field.range.start]);
+        pos = field.range.end;
+    }
+    output.extend_from_slice(&raw_message[pos..]);
+
+    output
+}
+
+fn write_crlf(output: &mut Vec<u8>, bytes: &[u8]) {
+    for &ch in bytes {
+        if ch == b'\n' && output.last() != Some(&b'\r') {
+            output.push(b'\r');
+        }
+        output.push(ch);
+    }
+}
+
+impl Headers {
+    fn parse(raw_message: &[u8]) -> Self {
+        let mut fields: Vec<HeaderField> = Vec::new();
+        let mut pos = 0;
+
+        while pos < raw_message.len() {
+            let end = raw_message[pos..]
+                .iter()
+                .position(|&ch| ch == b'\n')
+                .map_or(raw_message.len(), |end| pos + end + 1);
+            let line = &raw_message[pos..end];
+            let line = line.strip_suffix(b"\n").unwrap_or(line);
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+            if line.is_empty() {
+                break;
+            } else if matches!(line[0], b' ' | b'\t') && !fields.is_empty() {
+                fields.last_mut().unwrap().range.end = end;
+            } else {
+                let name = line
+                    .iter()
+                    .position(|&ch| ch == b':')
+                    .map(|colon| &line[..colon])
+                    .filter(|name| {
+                        !name.is_empty() && name.iter().all(|ch| (33..=126).contains(ch))
+                    })
+                    .map(|name| String::from_utf8_lossy(name).to_ascii_lowercase());
+                fields.push(HeaderField {
+                    name,
+                    range: pos..end,
+                });
+            }
+            pos = end;
+        }
+
+        Headers { fields, end: pos }
+    }
+
+    fn count(&self, name: &str) -> usize {
+        self.fields
+            .iter()
+            .filter(|field| field.name.as_deref() == Some(name))
+            .count()
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.count(name) > 0
+    }
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::MissingHeader(name) => write!(f, "missing {name} header"),
+            Violation::DuplicateHeader(name) => write!(f, "duplicate {name} header"),
+            Violation::MalformedHeader => f.write_str("malformed header line"),
+            Violation::BareLineFeed => f.write_str("bare LF line ending"),
+            Violation::LineTooLong => f.write_str("line longer than 998 characters"),
+        }
+    }
+}
@@ -0,0 +1,273 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use mail_auth::{common::lru::LruCache, IpLookupStrategy};
+use mail_send::{smtp::AssertReply, SmtpClient};
+use smtp_proto::Response;
+use tokio::{io::AsyncWriteExt, net::TcpStream};
+use utils::{listener::SessionStream, map::stats::CacheStats};
+
+use crate::{
+    config::SenderVerify,
+    core::{Session, SMTP},
+    outbound::{lookup::ToNextHop, session::quit},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SenderVerifyResult {
+    Pass,
+    Fail,
+    TempError,
+}
+
+pub struct SenderVerifyCache {
+    pub entries: LruCache<String, SenderVerifyResult>,
+    pub stats: CacheStats,
+}
+
+impl<T: SessionStream> Session<T> {
+    // Verifies that the return path is deliverable. Returns false when the sender
+    // has been rejected, in which case the response has already been written.
+    pub async fn verify_sender(&mut self) -> Result<bool, ()> {
+        let config = &self.core.session.config.mail.verify;
+        let action = *config.action.eval(self).await;
+        let mail_from = self.data.mail_from.as_ref().unwrap();
+        if action == SenderVerify::Disable || mail_from.address_lcase.is_empty() {
+            return Ok(true);
+        }
+
+        let cache = &self.core.session.sender_verify;
+        let result = if let Some(result) = cache.entries.get(&mail_from.address_lcase) {
+            cache.stats.hit();
+            result
+        } else {
+            cache.stats.miss();
+
+            let result = if let Some(directory) = config
+                .directory
+                .eval_and_capture(self)
+                .await
+                .into_value(self)
+            {
+                match directory.is_local_domain(&mail_from.domain).await {
+                    Ok(true) => match directory.rcpt(&mail_from.address_lcase).await {
+                        Ok(true) => SenderVerifyResult::Pass,
+                        Ok(false) => SenderVerifyResult::Fail,
+                        Err(_) => SenderVerifyResult::TempError,
+                    },
+                    Ok(false) => {
+                        self.core
+                            .sender_callout(
+                                &mail_from.address_lcase,
+                                &mail_from.domain,
+                                &self.instance.hostname,
+                            )
+                            .await
+                    }
+                    Err(_) => SenderVerifyResult::TempError,
+                }
+            } else {
+                self.core
+                    .sender_callout(
+                        &mail_from.address_lcase,
+                        &mail_from.domain,
+                        &self.instance.hostname,
+                    )
+                    .await
+            };
+
+            // Temporary failures are not cached
+            let ttl = match result {
+                SenderVerifyResult::Pass => Some(config.positive_ttl),
+                SenderVerifyResult::Fail => Some(config.negative_ttl),
+                SenderVerifyResult::TempError => None,
+            };
+            if let Some(ttl) = ttl {
+                cache.stats.insert();
+                cache.entries.insert(
+                    mail_from.address_lcase.clone(),
+                    result,
+                    Instant::now() + ttl,
+                );
+            }
+
+            result
+        };
+
+        tracing::debug!(parent: &self.span,
+            context = "mail-from",
+            event = "verify",
+            address = &mail_from.address_lcase,
+            result = result.as_str());
+
+        self.data.sender_verify = result.into();
+
+        if action == SenderVerify::Reject {
+            match result {
+                SenderVerifyResult::Pass => (),
+                SenderVerifyResult::Fail => {
+                    self.write(b"550 5.1.7 Sender address verification failed.\r\n")
+                        .await?;
+                    return Ok(false);
+                }
+                SenderVerifyResult::TempError => {
+                    self.write(b"451 4.1.7 Unable to verify sender address at this time.\r\n")
+                        .await?;
+                    return Ok(false);
+                }
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+impl SMTP {
+    pub async fn sender_callout(
+        &self,
+        address: &str,
+        domain: &str,
+        local_hostname: &str,
+    ) -> SenderVerifyResult {
+        let mx_list = match self.mx_lookup(domain).await {
+            Ok(mx) => mx,
+            Err(mail_auth::Error::DnsRecordNotFound(_)) => return SenderVerifyResult::Fail,
+            Err(_) => return SenderVerifyResult::TempError,
+        };
+
+        // Domains publishing a null MX do not accept mail
+        let remote_hosts = if let Some(remote_hosts) = mx_list.to_remote_hosts(domain, 2) {
+            remote_hosts
+        } else {
+            return SenderVerifyResult::Fail;
+        };
+
+        let timeout = self.session.config.mail.verify.timeout;
+        for remote_host in remote_hosts {
+            let remote_ips = match self
+                .ip_lookup(
+                    remote_host.fqdn_hostname().as_ref(),
+                    IpLookupStrategy::Ipv4thenIpv6,
+                    2,
+                )
+                .await
+            {
+                Ok(remote_ips) => remote_ips,
+                Err(_) => continue,
+            };
+
+            for remote_ip in remote_ips {
+                let addr = SocketAddr::new(remote_ip, remote_host.port());
+                match tokio::time::timeout(timeout, callout(addr, local_hostname, address, timeout))
+                    .await
+                {
+                    Ok(Ok(response)) => {
+                        tracing::debug!(
+                            context = "mail-from",
+                            event = "callout",
+                            mx = remote_host.hostname(),
+                            address = address,
+                            response = ?response,
+                        );
+
+                        return match response.code() {
+                            200..=299 => SenderVerifyResult::Pass,
+                            500..=599 => SenderVerifyResult::Fail,
+                            _ => SenderVerifyResult::TempError,
+                        };
+                    }
+                    Ok(Err(err)) => {
+                        tracing::debug!(
+                            context = "mail-from",
+                            event = "callout-error",
+                            mx = remote_host.hostname(),
+                            address = address,
+                            reason = %err,
+                        );
+                    }
+                    Err(_) => {
+                        tracing::debug!(
+                            context = "mail-from",
+                            event = "callout-error",
+                            mx = remote_host.hostname(),
+                            address = address,
+                            reason = "timeout",
+                        );
+                    }
+                }
+            }
+        }
+
+        SenderVerifyResult::TempError
+    }
+}
+
+async fn callout(
+    addr: SocketAddr,
+    local_hostname: &str,
+    address: &str,
+    timeout: Duration,
+) -> mail_send::Result<Response<String>> {
+    let mut smtp_client: SmtpClient<TcpStream> = SmtpClient::connect(addr, timeout).await?;
+    smtp_client.read().await?.assert_code(220)?;
+    smtp_client
+        .stream
+        .write_all(format!("EHLO {local_hostname}\r\n").as_bytes())
+        .await?;
+    smtp_client.stream.flush().await?;
+    smtp_client.read_ehlo().await?;
+    smtp_client
+        .cmd(b"MAIL FROM:<>\r\n")
+        .await?
+        .assert_code(250)?;
+    let response = smtp_client
+        .cmd(format!("RCPT TO:<{address}>\r\n").as_bytes())
+        .await?;
+    quit(smtp_client).await;
+
+    Ok(response)
+}
+
+impl SenderVerifyCache {
+    pub fn new(capacity: usize) -> Self {
+        SenderVerifyCache {
+            entries: LruCache::with_capacity(capacity),
+            stats: CacheStats::default(),
+        }
+    }
+}
+
+impl SenderVerifyResult {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SenderVerifyResult::Pass => "pass",
+            SenderVerifyResult::Fail => "fail",
+            SenderVerifyResult::TempError => "temperror",
+        }
+    }
+}
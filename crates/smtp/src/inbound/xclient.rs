@@ -0,0 +1,299 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::net::IpAddr;
+
+use directory::QueryBy;
+use mail_auth::{IprevOutput, SpfOutput};
+use utils::listener::SessionStream;
+
+use crate::core::Session;
+
+// Longest XCLIENT or XFORWARD line buffered while waiting for its end
+pub const MAX_XCOMMAND_LENGTH: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XCommand {
+    XClient,
+    XForward,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct XAttributes {
+    pub name: Option<String>,
+    pub addr: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub proto: Option<String>,
+    pub helo: Option<String>,
+    pub login: Option<String>,
+    pub dest_addr: Option<IpAddr>,
+    pub dest_port: Option<u16>,
+}
+
+// Connection details replaced by XFORWARD, restored once the
+// transaction they describe is over.
+#[derive(Debug)]
+pub struct XForwardOrigin {
+    pub local_ip: IpAddr,
+    pub remote_ip: IpAddr,
+    pub remote_port: u16,
+    pub helo_domain: String,
+    pub iprev: Option<IprevOutput>,
+    pub spf_ehlo: Option<SpfOutput>,
+    pub dnsbl_error: Option<Vec<u8>>,
+}
+
+impl<T: SessionStream> Session<T> {
+    pub async fn handle_xcommand(&mut self, command: XCommand, args: &str) -> Result<(), ()> {
+        if !self.params.xclient {
+            return self
+                .write(b"550 5.7.0 Insufficient authorization.\r\n")
+                .await;
+        } else if self.data.mail_from.is_some() {
+            return self
+                .write(b"503 5.5.1 Mail transaction in progress.\r\n")
+                .await;
+        }
+
+        let attributes = match XAttributes::parse(command, args) {
+            Ok(attributes) => attributes,
+            Err(attribute) => {
+                return self
+                    .write(format!("501 5.5.4 Invalid attribute {attribute:?}.\r\n").as_bytes())
+                    .await;
+            }
+        };
+
+        tracing::info!(
+            parent: &self.span,
+            context = "xclient",
+            event = if command == XCommand::XClient { "xclient" } else { "xforward" },
+            name = attributes.name.as_deref().unwrap_or_default(),
+            remote.ip = ?attributes.addr,
+            remote.port = ?attributes.port,
+            helo = attributes.helo.as_deref().unwrap_or_default(),
+            login = attributes.login.as_deref().unwrap_or_default(),
+            "Upstream proxy supplied client information."
+        );
+
+        let has_helo = attributes.helo.is_some();
+        if command == XCommand::XClient {
+            // Start over as if the original client had connected directly
+            self.reset();
+        } else if self.data.xforward.is_none() {
+            // XFORWARD attributes only apply to the next mail transaction
+            self.data.xforward = XForwardOrigin {
+                local_ip: self.data.local_ip,
+                remote_ip: self.data.remote_ip,
+                remote_port: self.data.remote_port,
+                helo_domain: self.data.helo_domain.clone(),
+                iprev: self.data.iprev.clone(),
+                spf_ehlo: self.data.spf_ehlo.clone(),
+                dnsbl_error: self.data.dnsbl_error.clone(),
+            }
+            .into();
+        }
+
+        if let Some(addr) = attributes.addr {
+            self.data.remote_ip = addr;
+            self.data.iprev = None;
+            self.data.dnsbl_error = None;
+        }
+        if let Some(port) = attributes.port {
+            self.data.remote_port = port;
+        }
+        if let Some(addr) = attributes.dest_addr {
+            self.data.local_ip = addr;
+        }
+        if let Some(helo) = attributes.helo {
+            self.data.helo_domain = helo;
+            self.data.spf_ehlo = None;
+        }
+
+        match command {
+            XCommand::XClient => {
+                if !has_helo {
+                    self.data.helo_domain.clear();
+                    self.data.spf_ehlo = None;
+                }
+                if let Some(login) = attributes.login {
+                    self.xclient_login(login).await;
+                } else {
+                    self.data.authenticated_as.clear();
+                    self.data.authenticated_emails.clear();
+                    self.data.authenticated_type = None;
+                }
+                if self.is_allowed().await && self.init_conn().await {
+                    Ok(())
+                } else {
+                    Err(())
+                }
+            }
+            XCommand::XForward => self.write(b"250 2.0.0 OK\r\n").await,
+        }
+    }
+
+    pub fn reset_xforward(&mut self) {
+        if let Some(origin) = self.data.xforward.take() {
+            self.data.local_ip = origin.local_ip;
+            self.data.remote_ip = origin.remote_ip;
+            self.data.remote_port = origin.remote_port;
+            self.data.helo_domain = origin.helo_domain;
+            self.data.iprev = origin.iprev;
+            self.data.spf_ehlo = origin.spf_ehlo;
+            self.data.dnsbl_error = origin.dnsbl_error;
+        }
+    }
+
+    async fn xclient_login(&mut self, login: String) {
+        let principal = if let Some(directory) = self.params.auth_directory.clone() {
+            directory
+                .query(
+                    QueryBy::Name(&login),
+                    self.core.session.config.auth.sender_alignment_delegated,
+                )
+                .await
+                .ok()
+                .flatten()
+        } else {
+            None
+        };
+        self.data.authenticated_as = login.to_lowercase();
+        if let Some(principal) = principal {
+            self.data.authenticated_type = principal.typ.into();
+            self.data.authenticated_emails = self.principal_identities(principal).await;
+        } else {
+            self.data.authenticated_type = None;
+            self.data.authenticated_emails = Vec::new();
+        }
+    }
+}
+
+impl XCommand {
+    // Matches a complete "XCLIENT" or "XFORWARD" command line, returning
+    // its arguments and the number of bytes consumed.
+    pub fn parse_line(bytes: &[u8]) -> Option<(XCommand, &str, usize)> {
+        let (command, name_len) = if bytes
+            .get(..7)
+            .map_or(false, |b| b.eq_ignore_ascii_case(b"XCLIENT"))
+        {
+            (XCommand::XClient, 7)
+        } else if bytes
+            .get(..8)
+            .map_or(false, |b| b.eq_ignore_ascii_case(b"XFORWARD"))
+        {
+            (XCommand::XForward, 8)
+        } else {
+            return None;
+        };
+        if !matches!(bytes.get(name_len), Some(b' ' | b'\r' | b'\n')) {
+            return None;
+        }
+        let eol = bytes.iter().position(|&ch| ch == b'\n')?;
+        let args = std::str::from_utf8(&bytes[name_len..eol]).ok()?.trim();
+
+        Some((command, args, eol + 1))
+    }
+}
+
+impl XCommand {
+    // Returns whether the bytes could be the start of an "XCLIENT" or
+    // "XFORWARD" command, which no other SMTP command begins with.
+    pub fn is_partial(bytes: &[u8]) -> bool {
+        !bytes.is_empty()
+            && [&b"XCLIENT"[..], &b"XFORWARD"[..]].iter().any(|name| {
+                let len = std::cmp::min(bytes.len(), name.len());
+                bytes[..len].eq_ignore_ascii_case(&name[..len])
+            })
+    }
+}
+
+impl XAttributes {
+    pub fn parse(command: XCommand, args: &str) -> Result<Self, String> {
+        let mut attributes = XAttributes::default();
+        let mut has_attributes = false;
+
+        for arg in args.split_ascii_whitespace() {
+            let (name, value) = arg.split_once('=').ok_or_else(|| arg.to_string())?;
+            let name = name.to_ascii_uppercase();
+            let value = xtext_decode(value).ok_or_else(|| name.clone())?;
+            has_attributes = true;
+
+            // Attributes the upstream could not determine are ignored
+            if value.eq_ignore_ascii_case("[UNAVAILABLE]")
+                || value.eq_ignore_ascii_case("[TEMPUNAVAIL]")
+            {
+                continue;
+            }
+
+            match (name.as_str(), command) {
+                ("NAME", _) => attributes.name = value.into(),
+                ("ADDR", _) => attributes.addr = parse_addr(&value).ok_or(name)?.into(),
+                ("PORT", _) => attributes.port = value.parse::<u16>().map_err(|_| name)?.into(),
+                ("PROTO", _) => attributes.proto = value.into(),
+                ("HELO", _) => attributes.helo = value.to_lowercase().into(),
+                ("LOGIN", XCommand::XClient) => attributes.login = value.into(),
+                ("DESTADDR", XCommand::XClient) => {
+                    attributes.dest_addr = parse_addr(&value).ok_or(name)?.into()
+                }
+                ("DESTPORT", XCommand::XClient) => {
+                    attributes.dest_port = value.parse::<u16>().map_err(|_| name)?.into()
+                }
+                ("IDENT" | "SOURCE", XCommand::XForward) => (),
+                _ => return Err(name),
+            }
+        }
+
+        if has_attributes {
+            Ok(attributes)
+        } else {
+            Err(String::new())
+        }
+    }
+}
+
+fn parse_addr(value: &str) -> Option<IpAddr> {
+    let value = if value
+        .get(..5)
+        .map_or(false, |v| v.eq_ignore_ascii_case("IPV6:"))
+    {
+        &value[5..]
+    } else {
+        value
+    };
+    value.parse().ok()
+}
+
+fn xtext_decode(value: &str) -> Option<String> {
+    let mut result = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+    while let Some(ch) = bytes.next() {
+        if ch == b'+' {
+            let hex = [bytes.next()?, bytes.next()?];
+            result.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            result.push(ch);
+        }
+    }
+    String::from_utf8(result).ok()
+}
@@ -22,7 +22,8 @@
 */
 
 use crate::core::{
-    throttle::ThrottleKeyHasherBuilder, QueueCore, ReportCore, SessionCore, TlsConnectors, SMTP,
+    concurrency::AccountSessions, eval::EvalCore, throttle::ThrottleKeyHasherBuilder, QueueCore,
+    ReportCore, SessionCore, TlsConnectors, SMTP,
 };
 use std::sync::Arc;
 
@@ -32,9 +33,13 @@ use config::{
 };
 use dashmap::DashMap;
 use directory::Directories;
+use inbound::{
+    archive::ArchiveMetrics, budget::FilterMetrics, mail_loop::LoopMetrics,
+    verify::SenderVerifyCache,
+};
 use mail_send::smtp::tls::build_tls_connector;
-use queue::manager::SpawnQueue;
-use reporting::scheduler::SpawnReport;
+use queue::{manager::SpawnQueue, moderation::Moderation};
+use reporting::{operator::OperatorState, scheduler::SpawnReport};
 use store::Stores;
 use tokio::sync::mpsc;
 use utils::{
@@ -93,6 +98,7 @@ impl SMTP {
         let sieve_config = config.parse_sieve(&mut config_ctx)?;
         let session_config = config.parse_session_config(&config_ctx)?;
         let queue_config = config.parse_queue(&config_ctx)?;
+        let moderation = Moderation::new(&queue_config.moderation);
         let mail_auth_config = config.parse_mail_auth(&config_ctx)?;
         let report_config = config.parse_reports(&config_ctx)?;
 
@@ -120,6 +126,15 @@ impl SMTP {
                         .unwrap_or(32)
                         .next_power_of_two() as usize,
                 ),
+                filter_metrics: FilterMetrics::default(),
+                archive_metrics: ArchiveMetrics::default(),
+                loop_metrics: LoopMetrics::default(),
+                account_sessions: AccountSessions::default(),
+                sender_verify: SenderVerifyCache::new(
+                    config
+                        .property("session.mail.verify.cache.size")?
+                        .unwrap_or(4096),
+                ),
             },
             queue: QueueCore {
                 config: queue_config,
@@ -141,6 +156,8 @@ impl SMTP {
                         .next_power_of_two() as usize,
                 ),
                 tx: queue_tx,
+                analytics: Default::default(),
+                moderation,
                 connectors: TlsConnectors {
                     pki_verify: build_tls_connector(false),
                     dummy_verify: build_tls_connector(true),
@@ -148,10 +165,28 @@ impl SMTP {
             },
             report: ReportCore {
                 tx: report_tx,
+                operator: OperatorState::new(&report_config.operator.schedules),
                 config: report_config,
             },
             mail_auth: mail_auth_config,
             sieve: sieve_config,
+            eval: EvalCore {
+                settings: config.clone(),
+                listeners: servers
+                    .inner
+                    .iter()
+                    .flat_map(|server| {
+                        std::iter::once((server.id.clone(), server.internal_id)).chain(
+                            server
+                                .virtual_hosts
+                                .iter()
+                                .map(|vhost| (vhost.id.clone(), vhost.internal_id)),
+                        )
+                    })
+                    .collect(),
+                directory: directory.clone(),
+                stores: stores.clone(),
+            },
             #[cfg(feature = "local_delivery")]
             delivery_tx,
         });
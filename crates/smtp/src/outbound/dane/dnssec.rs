@@ -57,8 +57,10 @@ impl Resolvers {
     ) -> mail_auth::Result<Option<Arc<Tlsa>>> {
         let key = key.into_fqdn();
         if let Some(value) = self.cache.tlsa.get(key.as_ref()) {
+            self.cache.tlsa_stats.hit();
             return Ok(Some(value));
         }
+        self.cache.tlsa_stats.miss();
 
         #[cfg(any(test, feature = "test_mode"))]
         if true {
@@ -112,6 +114,7 @@ impl Resolvers {
             }
         }
 
+        self.cache.tlsa_stats.insert();
         Ok(Some(self.cache.tlsa.insert(
             key.into_owned(),
             Arc::new(Tlsa {
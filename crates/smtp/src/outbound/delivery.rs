@@ -32,27 +32,34 @@ use mail_auth::{
     report::tlsrpt::{FailureDetails, ResultType},
 };
 use mail_send::SmtpClient;
+use rand::Rng;
 use smtp_proto::MAIL_REQUIRETLS;
 use utils::config::ServerProtocol;
 
 use crate::{
-    config::{AggregateFrequency, TlsStrategy},
+    config::{
+        AggregateFrequency, QueueConfig, RelayHost, RetrySchedule, RetryStrategy, TlsFallback,
+        TlsStrategy,
+    },
     core::SMTP,
     queue::ErrorDetails,
     reporting::{tls::TlsRptOptions, PolicyType, TlsEvent},
 };
 
 use super::{
+    downgrade::domain_to_ascii,
     lookup::ToNextHop,
     mta_sts,
     session::{read_greeting, say_helo, try_start_tls, SessionParams, StartTlsResult},
     NextHop,
 };
 use crate::queue::{
-    manager::Queue, throttle, DeliveryAttempt, Domain, Error, Event, OnHold, QueueEnvelope,
-    Schedule, Status, WorkerResult,
+    manager::Queue, throttle, DeliveryAttempt, Domain, Error, Event, InstantFromTimestamp, OnHold,
+    QueueEnvelope, Schedule, Status, WorkerResult, MAIL_TLS_OPTIONAL, MAIL_TRANSPARENT,
 };
 
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(30 * 86400);
+
 impl DeliveryAttempt {
     pub async fn try_deliver(mut self, core: Arc<SMTP>, queue: &mut Queue) {
         // Check that the message still has recipients to be delivered
@@ -121,13 +128,52 @@ impl DeliveryAttempt {
 
             let mut domains = std::mem::take(&mut self.message.domains);
             let mut recipients = std::mem::take(&mut self.message.recipients);
+
+            // Messages accepted by transparent relays are forwarded to a fixed next hop
+            let transparent_hop = if self.message.has_flag(MAIL_TRANSPARENT) {
+                core.session.config.transparent.next_hop.as_ref()
+            } else {
+                None
+            };
+
+            // Domains relayed through the same next hop are delivered in a single transaction
+            let mut coalesced = vec![None; domains.len()];
+            let mut relays: Vec<(usize, &RelayHost)> = Vec::new();
+            for (domain_idx, domain) in domains.iter().enumerate() {
+                if domain.is_due() {
+                    let envelope = QueueEnvelope {
+                        message: self.message.as_ref(),
+                        domain: &domain.domain,
+                        mx: "",
+                        remote_ip: no_ip,
+                        local_ip: no_ip,
+                    };
+                    let next_hop = match transparent_hop {
+                        Some(next_hop) => Some(next_hop),
+                        None => queue_config.next_hop.eval(&envelope).await.as_ref(),
+                    };
+                    if let Some(next_hop) = next_hop {
+                        if next_hop.protocol != ServerProtocol::Jmap {
+                            if let Some((leader_idx, _)) = relays
+                                .iter()
+                                .find(|(_, relay)| relay.is_same_destination(next_hop))
+                            {
+                                coalesced[domain_idx] = Some(*leader_idx);
+                            } else {
+                                relays.push((domain_idx, next_hop));
+                            }
+                        }
+                    }
+                }
+            }
+
             'next_domain: for (domain_idx, domain) in domains.iter_mut().enumerate() {
-                // Only process domains due for delivery
-                if !matches!(&domain.status, Status::Scheduled | Status::TemporaryFailure(_)
-                if domain.retry.due <= Instant::now())
-                {
+                // Only process domains due for delivery that were not coalesced
+                if !domain.is_due() || coalesced[domain_idx].is_some() {
                     continue;
                 }
+                let in_transaction =
+                    |idx: usize| idx == domain_idx || coalesced[idx] == Some(domain_idx);
 
                 // Create new span for domain
                 let span = tracing::info_span!(
@@ -137,6 +183,11 @@ impl DeliveryAttempt {
                     attempt_number = domain.retry.inner,
                 );
 
+                // Internationalized domain names are resolved using their A-label form
+                let ascii_domain = domain_to_ascii(&domain.domain)
+                    .map(|domain| domain.into_owned())
+                    .unwrap_or_else(|| domain.domain.clone());
+
                 // Build envelope
                 let mut envelope = QueueEnvelope {
                     message: self.message.as_ref(),
@@ -160,8 +211,11 @@ impl DeliveryAttempt {
                 }
 
                 // Obtain next hop
-                let (mut remote_hosts, is_smtp) = match queue_config.next_hop.eval(&envelope).await
-                {
+                let next_hop = match transparent_hop {
+                    Some(next_hop) => Some(next_hop),
+                    None => queue_config.next_hop.eval(&envelope).await.as_ref(),
+                };
+                let (mut remote_hosts, is_smtp) = match next_hop {
                     #[cfg(feature = "local_delivery")]
                     Some(next_hop) if next_hop.protocol == ServerProtocol::Jmap => {
                         // Deliver message locally
@@ -175,8 +229,10 @@ impl DeliveryAttempt {
                             .await;
 
                         // Update status for the current domain and continue with the next one
-                        domain
-                            .set_status(delivery_result, queue_config.retry.eval(&envelope).await);
+                        domain.set_status(
+                            delivery_result,
+                            queue_config.retry_schedule(&envelope).await,
+                        );
                         continue 'next_domain;
                     }
                     Some(next_hop) => (
@@ -193,6 +249,13 @@ impl DeliveryAttempt {
                     ..Default::default()
                 };
                 let allow_invalid_certs = *queue_config.tls.invalid_certs.eval(&envelope).await;
+                let is_require_tls = self.message.has_flag(MAIL_REQUIRETLS);
+                let is_tls_optional = !is_require_tls && self.message.has_flag(MAIL_TLS_OPTIONAL);
+                if is_require_tls {
+                    tls_strategy.enforce();
+                } else if is_tls_optional {
+                    tls_strategy.relax();
+                }
 
                 // Obtain TLS reporting
                 let tls_report = match core.report.config.tls.send.eval(&envelope).await {
@@ -204,7 +267,7 @@ impl DeliveryAttempt {
                         match core
                             .resolvers
                             .dns
-                            .txt_lookup::<TlsRpt>(format!("_smtp._tls.{}.", envelope.domain))
+                            .txt_lookup::<TlsRpt>(format!("_smtp._tls.{}.", ascii_domain))
                             .await
                         {
                             Ok(record) => {
@@ -234,10 +297,11 @@ impl DeliveryAttempt {
                 };
 
                 // Obtain MTA-STS policy for domain
+                let mut sts_fallback = None;
                 let mta_sts_policy = if tls_strategy.try_mta_sts() && is_smtp {
                     match core
                         .lookup_mta_sts_policy(
-                            envelope.domain,
+                            &ascii_domain,
                             *queue_config.timeout.mta_sts.eval(&envelope).await,
                         )
                         .await
@@ -294,8 +358,22 @@ impl DeliveryAttempt {
                                     "Failed to retrieve MTA-STS policy: {}",
                                     err
                                 );
-                                domain.set_status(err, queue_config.retry.eval(&envelope).await);
-                                continue 'next_domain;
+
+                                let fallback = *queue_config.tls.fallback.eval(&envelope).await;
+                                if fallback == TlsFallback::FailClosed || is_require_tls {
+                                    domain.set_status(
+                                        err,
+                                        queue_config.retry_schedule(&envelope).await,
+                                    );
+                                    continue 'next_domain;
+                                }
+                                core.tls_downgrade(
+                                    &span,
+                                    &envelope,
+                                    fallback,
+                                    "MTA-STS policy could not be retrieved.",
+                                );
+                                sts_fallback = fallback.into();
                             } else {
                                 tracing::debug!(
                                     parent: &span,
@@ -317,7 +395,7 @@ impl DeliveryAttempt {
                 let mx_list;
                 if is_smtp && remote_hosts.is_empty() {
                     // Lookup MX
-                    mx_list = match core.resolvers.dns.mx_lookup(&domain.domain).await {
+                    mx_list = match core.mx_lookup(&ascii_domain).await {
                         Ok(mx) => mx,
                         Err(err) => {
                             tracing::info!(
@@ -326,13 +404,20 @@ impl DeliveryAttempt {
                                 event = "mx-lookup-failed",
                                 reason = %err,
                             );
-                            domain.set_status(err, queue_config.retry.eval(&envelope).await);
+                            let status = Status::from(err);
+                            core.queue.record_delivery(
+                                &domain.domain,
+                                &status,
+                                None,
+                                self.message.created,
+                            );
+                            domain.set_status(status, queue_config.retry_schedule(&envelope).await);
                             continue 'next_domain;
                         }
                     };
 
                     if let Some(remote_hosts_) = mx_list
-                        .to_remote_hosts(&domain.domain, *queue_config.max_mx.eval(&envelope).await)
+                        .to_remote_hosts(&ascii_domain, *queue_config.max_mx.eval(&envelope).await)
                     {
                         remote_hosts = remote_hosts_;
                     } else {
@@ -346,7 +431,7 @@ impl DeliveryAttempt {
                             Status::PermanentFailure(Error::DnsError(
                                 "Domain does not accept messages (null MX)".to_string(),
                             )),
-                            queue_config.retry.eval(&envelope).await,
+                            queue_config.retry_schedule(&envelope).await,
                         );
                         continue 'next_domain;
                     }
@@ -358,6 +443,8 @@ impl DeliveryAttempt {
                 'next_host: for remote_host in &remote_hosts {
                     // Validate MTA-STS
                     envelope.mx = remote_host.hostname();
+                    let tls_fallback = *queue_config.tls.fallback.eval(&envelope).await;
+                    let mut downgrade = sts_fallback;
                     if let Some(mta_sts_policy) = &mta_sts_policy {
                         if !mta_sts_policy.verify(envelope.mx) {
                             // Report MTA-STS failed verification
@@ -383,11 +470,20 @@ impl DeliveryAttempt {
                                 "MX not authorized by policy."
                             );
 
-                            if mta_sts_policy.enforce() {
-                                last_status = Status::PermanentFailure(Error::MtaStsError(
-                                    format!("MX {:?} not authorized by policy.", envelope.mx),
-                                ));
-                                continue 'next_host;
+                            if mta_sts_policy.enforce() || is_require_tls {
+                                if tls_fallback == TlsFallback::FailClosed || is_require_tls {
+                                    last_status = Status::PermanentFailure(Error::MtaStsError(
+                                        format!("MX {:?} not authorized by policy.", envelope.mx),
+                                    ));
+                                    continue 'next_host;
+                                }
+                                core.tls_downgrade(
+                                    &span,
+                                    &envelope,
+                                    tls_fallback,
+                                    "MX not authorized by MTA-STS policy.",
+                                );
+                                downgrade = tls_fallback.into();
                             }
                         }
                     }
@@ -415,6 +511,11 @@ impl DeliveryAttempt {
                     // Update TLS strategy
                     tls_strategy.dane = *queue_config.tls.dane.eval(&envelope).await;
                     tls_strategy.tls = *queue_config.tls.start.eval(&envelope).await;
+                    if is_require_tls {
+                        tls_strategy.enforce();
+                    } else if is_tls_optional {
+                        tls_strategy.relax();
+                    }
 
                     // Lookup DANE policy
                     let dane_policy = if tls_strategy.try_dane() && is_smtp {
@@ -459,14 +560,24 @@ impl DeliveryAttempt {
                                     }
 
                                     if tls_strategy.is_dane_required() {
-                                        last_status = Status::PermanentFailure(Error::DaneError(
-                                            ErrorDetails {
-                                                entity: envelope.mx.to_string(),
-                                                details: "No valid TLSA records were found"
-                                                    .to_string(),
-                                            },
-                                        ));
-                                        continue 'next_host;
+                                        if tls_fallback == TlsFallback::FailClosed || is_require_tls
+                                        {
+                                            last_status = Status::PermanentFailure(
+                                                Error::DaneError(ErrorDetails {
+                                                    entity: envelope.mx.to_string(),
+                                                    details: "No valid TLSA records were found"
+                                                        .to_string(),
+                                                }),
+                                            );
+                                            continue 'next_host;
+                                        }
+                                        core.tls_downgrade(
+                                            &span,
+                                            &envelope,
+                                            tls_fallback,
+                                            "No valid TLSA records were found.",
+                                        );
+                                        downgrade = tls_fallback.into();
                                     }
                                     None
                                 }
@@ -498,12 +609,22 @@ impl DeliveryAttempt {
                                         "No TLSA DNSSEC records found."
                                     );
 
-                                    last_status =
-                                        Status::PermanentFailure(Error::DaneError(ErrorDetails {
-                                            entity: envelope.mx.to_string(),
-                                            details: "No TLSA DNSSEC records found".to_string(),
-                                        }));
-                                    continue 'next_host;
+                                    if tls_fallback == TlsFallback::FailClosed || is_require_tls {
+                                        last_status = Status::PermanentFailure(Error::DaneError(
+                                            ErrorDetails {
+                                                entity: envelope.mx.to_string(),
+                                                details: "No TLSA DNSSEC records found".to_string(),
+                                            },
+                                        ));
+                                        continue 'next_host;
+                                    }
+                                    core.tls_downgrade(
+                                        &span,
+                                        &envelope,
+                                        tls_fallback,
+                                        "No TLSA DNSSEC records found.",
+                                    );
+                                    downgrade = tls_fallback.into();
                                 }
                                 None
                             }
@@ -517,37 +638,47 @@ impl DeliveryAttempt {
                                         "No TLSA records found."
                                     );
 
-                                    last_status =
-                                        if matches!(&err, mail_auth::Error::DnsRecordNotFound(_)) {
-                                            // Report DANE required
-                                            if let Some(tls_report) = &tls_report {
-                                                core.schedule_report(TlsEvent {
-                                                    policy: PolicyType::Tlsa(None),
-                                                    domain: envelope.domain.to_string(),
-                                                    failure: FailureDetails::new(
-                                                        ResultType::DaneRequired,
-                                                    )
-                                                    .with_receiving_mx_hostname(envelope.mx)
-                                                    .with_failure_reason_code(
-                                                        "No TLSA records found for MX.",
-                                                    )
-                                                    .into(),
-                                                    tls_record: tls_report.record.clone(),
-                                                    interval: tls_report.interval,
-                                                })
-                                                .await;
-                                            }
+                                    if matches!(&err, mail_auth::Error::DnsRecordNotFound(_)) {
+                                        // Report DANE required
+                                        if let Some(tls_report) = &tls_report {
+                                            core.schedule_report(TlsEvent {
+                                                policy: PolicyType::Tlsa(None),
+                                                domain: envelope.domain.to_string(),
+                                                failure: FailureDetails::new(
+                                                    ResultType::DaneRequired,
+                                                )
+                                                .with_receiving_mx_hostname(envelope.mx)
+                                                .with_failure_reason_code(
+                                                    "No TLSA records found for MX.",
+                                                )
+                                                .into(),
+                                                tls_record: tls_report.record.clone(),
+                                                interval: tls_report.interval,
+                                            })
+                                            .await;
+                                        }
 
-                                            Status::PermanentFailure(Error::DaneError(
-                                                ErrorDetails {
+                                        if tls_fallback == TlsFallback::FailClosed || is_require_tls
+                                        {
+                                            last_status = Status::PermanentFailure(
+                                                Error::DaneError(ErrorDetails {
                                                     entity: envelope.mx.to_string(),
                                                     details: "No TLSA records found".to_string(),
-                                                },
-                                            ))
-                                        } else {
-                                            err.into()
-                                        };
-                                    continue 'next_host;
+                                                }),
+                                            );
+                                            continue 'next_host;
+                                        }
+                                        core.tls_downgrade(
+                                            &span,
+                                            &envelope,
+                                            tls_fallback,
+                                            "No TLSA records found for MX.",
+                                        );
+                                        downgrade = tls_fallback.into();
+                                    } else {
+                                        last_status = err.into();
+                                        continue 'next_host;
+                                    }
                                 }
                                 None
                             }
@@ -580,6 +711,21 @@ impl DeliveryAttempt {
                             }
                         }
 
+                        // Enforce warm-up limits of the source IP
+                        if let Some(ip_addr) = source_ip {
+                            if let Err(retry_at) = core
+                                .queue
+                                .warmup_acquire(ip_addr, remote_host.hostname(), &span)
+                                .await
+                            {
+                                domain.set_throttle_error(
+                                    throttle::Error::Rate { retry_at },
+                                    &mut on_hold,
+                                );
+                                continue 'next_domain;
+                            }
+                        }
+
                         // Connect
                         let mut smtp_client = match if let Some(ip_addr) = source_ip {
                             SmtpClient::connect_using(
@@ -632,20 +778,30 @@ impl DeliveryAttempt {
                             timeout_mail: *queue_config.timeout.mail.eval(&envelope).await,
                             timeout_rcpt: *queue_config.timeout.rcpt.eval(&envelope).await,
                             timeout_data: *queue_config.timeout.data.eval(&envelope).await,
+                            max_rcpt: std::cmp::max(
+                                *queue_config.max_rcpt.eval(&envelope).await,
+                                1,
+                            ),
                         };
 
                         // Prepare TLS connector
                         let is_strict_tls = tls_strategy.is_tls_required()
-                            || (self.message.flags & MAIL_REQUIRETLS) != 0
-                            || mta_sts_policy.is_some()
-                            || dane_policy.is_some();
-                        let tls_connector =
-                            if allow_invalid_certs || remote_host.allow_invalid_certs() {
-                                &core.queue.connectors.dummy_verify
-                            } else {
-                                &core.queue.connectors.pki_verify
+                            || is_require_tls
+                            || match downgrade {
+                                Some(fallback) => fallback != TlsFallback::Plaintext,
+                                None => mta_sts_policy.is_some() || dane_policy.is_some(),
                             };
+                        let tls_connector = if (allow_invalid_certs
+                            || remote_host.allow_invalid_certs()
+                            || downgrade == Some(TlsFallback::Opportunistic))
+                            && (!is_require_tls || dane_policy.is_some())
+                        {
+                            &core.queue.connectors.dummy_verify
+                        } else {
+                            &core.queue.connectors.pki_verify
+                        };
 
+                        let mut is_tls = false;
                         let delivery_result = if !remote_host.implicit_tls() {
                             // Read greeting
                             smtp_client.timeout =
@@ -730,8 +886,18 @@ impl DeliveryAttempt {
                                                     .await;
                                                 }
 
-                                                last_status = status;
-                                                continue 'next_host;
+                                                if tls_fallback == TlsFallback::FailClosed
+                                                    || is_require_tls
+                                                {
+                                                    last_status = status;
+                                                    continue 'next_host;
+                                                }
+                                                core.tls_downgrade(
+                                                    &span,
+                                                    &envelope,
+                                                    tls_fallback,
+                                                    "No matching certificates found.",
+                                                );
                                             }
                                         }
 
@@ -748,12 +914,13 @@ impl DeliveryAttempt {
                                         }
 
                                         // Deliver message over TLS
+                                        is_tls = true;
                                         self.message
                                             .deliver(
                                                 smtp_client,
                                                 recipients
                                                     .iter_mut()
-                                                    .filter(|r| r.domain_idx == domain_idx),
+                                                    .filter(|r| in_transaction(r.domain_idx)),
                                                 params,
                                             )
                                             .await
@@ -806,7 +973,7 @@ impl DeliveryAttempt {
                                                     smtp_client,
                                                     recipients
                                                         .iter_mut()
-                                                        .filter(|r| r.domain_idx == domain_idx),
+                                                        .filter(|r| in_transaction(r.domain_idx)),
                                                     params,
                                                 )
                                                 .await
@@ -851,6 +1018,22 @@ impl DeliveryAttempt {
                                         continue 'next_host;
                                     }
                                 }
+                            } else if is_require_tls {
+                                // REQUIRETLS messages are never sent in plain-text
+                                tracing::info!(
+                                    parent: &span,
+                                    context = "tls",
+                                    event = "required",
+                                    mx = envelope.mx,
+                                    reason = "TLS is disabled for this host",
+                                );
+
+                                last_status =
+                                    Status::PermanentFailure(Error::RequireTls(ErrorDetails {
+                                        entity: envelope.mx.to_string(),
+                                        details: "TLS is disabled for this host".to_string(),
+                                    }));
+                                continue 'next_host;
                             } else {
                                 // TLS has been disabled
                                 tracing::info!(
@@ -866,7 +1049,7 @@ impl DeliveryAttempt {
                                         smtp_client,
                                         recipients
                                             .iter_mut()
-                                            .filter(|r| r.domain_idx == domain_idx),
+                                            .filter(|r| in_transaction(r.domain_idx)),
                                         params,
                                     )
                                     .await
@@ -909,25 +1092,81 @@ impl DeliveryAttempt {
                             }
 
                             // Deliver message
+                            is_tls = true;
                             self.message
                                 .deliver(
                                     smtp_client,
-                                    recipients.iter_mut().filter(|r| r.domain_idx == domain_idx),
+                                    recipients
+                                        .iter_mut()
+                                        .filter(|r| in_transaction(r.domain_idx)),
                                     params,
                                 )
                                 .await
                         };
 
                         // Update status for the current domain and continue with the next one
-                        domain
-                            .set_status(delivery_result, queue_config.retry.eval(&envelope).await);
+                        core.queue.record_delivery(
+                            &domain.domain,
+                            &delivery_result,
+                            Some(is_tls),
+                            self.message.created,
+                        );
+                        domain.set_status(
+                            delivery_result,
+                            queue_config.retry_schedule(&envelope).await,
+                        );
                         continue 'next_domain;
                     }
                 }
 
                 // Update status
+                core.queue.record_delivery(
+                    &domain.domain,
+                    &last_status,
+                    None,
+                    self.message.created,
+                );
                 domain.disable_tls = disable_tls;
-                domain.set_status(last_status, queue_config.retry.eval(&envelope).await);
+                domain.set_status(last_status, queue_config.retry_schedule(&envelope).await);
+            }
+
+            // Update the status of the domains that were part of a coalesced transaction
+            for domain_idx in 0..domains.len() {
+                let leader_idx = match coalesced[domain_idx] {
+                    Some(leader_idx) => leader_idx,
+                    None if coalesced.contains(&Some(domain_idx)) => domain_idx,
+                    None => continue,
+                };
+                let status = if recipients
+                    .iter()
+                    .filter(|r| r.domain_idx == domain_idx)
+                    .all(|r| {
+                        matches!(
+                            &r.status,
+                            Status::Completed(_) | Status::PermanentFailure(_)
+                        )
+                    }) {
+                    Status::Completed(())
+                } else if domain_idx != leader_idx {
+                    domains[leader_idx].status.clone()
+                } else {
+                    continue;
+                };
+                let (disable_tls, retry_due, retry_inner) = (
+                    domains[leader_idx].disable_tls,
+                    domains[leader_idx].retry.due,
+                    domains[leader_idx].retry.inner,
+                );
+                let domain = &mut domains[domain_idx];
+                if matches!(&status, Status::Scheduled | Status::TemporaryFailure(_)) {
+                    // Retry at the same time as the leading domain, so that the
+                    // domains are still delivered in a single transaction
+                    domain.retry.due = retry_due;
+                    domain.retry.inner = retry_inner;
+                }
+                domain.status = status;
+                domain.disable_tls = disable_tls;
+                domain.changed = true;
             }
             self.message.domains = domains;
             self.message.recipients = recipients;
@@ -1056,8 +1295,38 @@ impl DeliveryAttempt {
     }
 }
 
+impl SMTP {
+    fn tls_downgrade(
+        &self,
+        span: &tracing::Span,
+        envelope: &QueueEnvelope<'_>,
+        fallback: TlsFallback,
+        reason: &str,
+    ) {
+        tracing::warn!(
+            parent: span,
+            context = "tls",
+            event = "downgrade",
+            domain = envelope.domain,
+            mx = envelope.mx,
+            fallback = ?fallback,
+            reason = reason,
+        );
+        self.report.operator.telemetry.record_tls_downgrade();
+    }
+}
+
 impl Domain {
-    pub fn set_status(&mut self, status: impl Into<Status<(), Error>>, schedule: &[Duration]) {
+    pub fn is_due(&self) -> bool {
+        matches!(&self.status, Status::Scheduled | Status::TemporaryFailure(_)
+            if self.retry.due <= Instant::now())
+    }
+
+    pub fn set_status<'x>(
+        &mut self,
+        status: impl Into<Status<(), Error>>,
+        schedule: impl Into<RetrySchedule<'x>>,
+    ) {
         self.status = status.into();
         self.changed = true;
         if matches!(
@@ -1068,9 +1337,85 @@ impl Domain {
         }
     }
 
-    pub fn retry(&mut self, schedule: &[Duration]) {
-        self.retry.due =
-            Instant::now() + schedule[std::cmp::min(self.retry.inner as usize, schedule.len() - 1)];
+    pub fn retry<'x>(&mut self, schedule: impl Into<RetrySchedule<'x>>) {
+        let interval = match schedule.into() {
+            RetrySchedule::Intervals(schedule) => {
+                self.retry_strategy = None;
+                schedule[std::cmp::min(self.retry.inner as usize, schedule.len() - 1)]
+            }
+            RetrySchedule::Strategy { strategy, created } => {
+                if self.retry_strategy.as_deref() != Some(strategy.name.as_str()) {
+                    self.retry_strategy = strategy.name.clone().into();
+                }
+
+                // Give up once the strategy's maximum delivery time has elapsed
+                if let Some(give_up) = strategy.give_up {
+                    let give_up_at = (created + give_up.as_secs()).to_instant();
+                    if give_up_at < self.expires {
+                        self.expires = give_up_at;
+                    }
+                }
+
+                strategy.interval(self.retry.inner)
+            }
+        };
+
+        self.retry.due = Instant::now() + interval;
         self.retry.inner += 1;
     }
 }
+
+impl RetryStrategy {
+    pub fn interval(&self, attempt: u32) -> Duration {
+        let attempt = attempt as usize;
+        let last = self.intervals.len() - 1;
+        let mut interval = if attempt <= last || self.multiplier <= 1.0 {
+            self.intervals[std::cmp::min(attempt, last)]
+        } else {
+            Duration::try_from_secs_f64(
+                self.intervals[last].as_secs_f64()
+                    * self
+                        .multiplier
+                        .powi(std::cmp::min(attempt - last, 64) as i32),
+            )
+            .unwrap_or(MAX_RETRY_INTERVAL)
+        };
+        if let Some(cap) = self.cap {
+            interval = std::cmp::min(interval, cap);
+        }
+        interval = std::cmp::min(interval, MAX_RETRY_INTERVAL);
+
+        if !self.jitter.is_zero() {
+            interval += Duration::from_millis(
+                rand::thread_rng().gen_range(0..=self.jitter.as_millis() as u64),
+            );
+        }
+
+        interval
+    }
+}
+
+impl QueueConfig {
+    pub async fn retry_schedule<'x>(&'x self, envelope: &QueueEnvelope<'_>) -> RetrySchedule<'x> {
+        if let Some(strategy) = self.retry_strategy.eval(envelope).await {
+            RetrySchedule::Strategy {
+                strategy,
+                created: envelope.message.created,
+            }
+        } else {
+            RetrySchedule::Intervals(self.retry.eval(envelope).await)
+        }
+    }
+}
+
+impl<'x> From<&'x [Duration]> for RetrySchedule<'x> {
+    fn from(schedule: &'x [Duration]) -> Self {
+        RetrySchedule::Intervals(schedule)
+    }
+}
+
+impl<'x> From<&'x Vec<Duration>> for RetrySchedule<'x> {
+    fn from(schedule: &'x Vec<Duration>) -> Self {
+        RetrySchedule::Intervals(schedule)
+    }
+}
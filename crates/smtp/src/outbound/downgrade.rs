@@ -0,0 +1,163 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+use mail_builder::headers::{address::Address, text::Text, Header};
+use mail_parser::{HeaderName, HeaderValue, MessageParser};
+
+/// Converts a domain name to its ASCII (A-label) form.
+pub fn domain_to_ascii(domain: &str) -> Option<Cow<'_, str>> {
+    if domain.is_ascii() {
+        Some(domain.into())
+    } else {
+        idna::domain_to_ascii(domain).ok().map(Cow::Owned)
+    }
+}
+
+/// Returns the ASCII form of an e-mail address, or `None` when the
+/// local part contains non-ASCII characters and cannot be downgraded.
+pub fn address_to_ascii(address: &str) -> Option<Cow<'_, str>> {
+    if address.is_ascii() {
+        return Some(address.into());
+    }
+    let (local_part, domain) = address.rsplit_once('@')?;
+    if !local_part.is_ascii() {
+        return None;
+    }
+    Some(format!("{}@{}", local_part, domain_to_ascii(domain)?).into())
+}
+
+/// Downgrades the header section of a message as described in RFC 6857.
+///
+/// Address headers are re-encoded using A-labels and encoded display names,
+/// addresses that cannot be represented in ASCII are replaced with empty groups,
+/// unstructured headers are encoded as MIME encoded-words and any other
+/// non-ASCII header is encapsulated in a `Downgraded-` header. The message body
+/// is left untouched. Returns `None` when no changes were made.
+pub fn downgrade_headers(raw_message: &[u8]) -> Option<Vec<u8>> {
+    let message = MessageParser::new().parse_headers(raw_message)?;
+    let headers = &message.parts.first()?.headers;
+
+    let mut output = Vec::with_capacity(raw_message.len() + 256);
+    let mut last_offset = 0;
+    let mut has_changes = false;
+
+    for header in headers {
+        let raw_header = raw_message.get(header.offset_field..header.offset_end)?;
+        if raw_header.is_ascii() {
+            continue;
+        }
+        output.extend_from_slice(raw_message.get(last_offset..header.offset_field)?);
+        last_offset = header.offset_end;
+        has_changes = true;
+
+        let name = header.name.as_str();
+        let bytes_written = name.len() + 2;
+        match (&header.name, &header.value) {
+            (
+                HeaderName::From
+                | HeaderName::To
+                | HeaderName::Cc
+                | HeaderName::Bcc
+                | HeaderName::Sender
+                | HeaderName::ReplyTo
+                | HeaderName::ResentFrom
+                | HeaderName::ResentTo
+                | HeaderName::ResentCc
+                | HeaderName::ResentBcc
+                | HeaderName::ResentSender,
+                HeaderValue::Address(address),
+            ) => {
+                let mut items = Vec::new();
+                if let Some(groups) = address.as_group() {
+                    for group in groups {
+                        let addresses = group
+                            .addresses
+                            .iter()
+                            .map(downgrade_address)
+                            .collect::<Vec<_>>();
+                        items.push(Address::new_group(
+                            group.name.as_ref().map(|name| name.as_ref()),
+                            addresses,
+                        ));
+                    }
+                } else if let Some(list) = address.as_list() {
+                    items.extend(list.iter().map(downgrade_address));
+                }
+                output.extend_from_slice(name.as_bytes());
+                output.extend_from_slice(b": ");
+                Address::new_list(items)
+                    .write_header(&mut output, bytes_written)
+                    .ok()?;
+            }
+            (
+                HeaderName::Subject | HeaderName::Comments | HeaderName::ContentDescription,
+                HeaderValue::Text(text),
+            ) => {
+                output.extend_from_slice(name.as_bytes());
+                output.extend_from_slice(b": ");
+                Text::new(text.as_ref())
+                    .write_header(&mut output, bytes_written)
+                    .ok()?;
+            }
+            _ => {
+                // Encapsulate the original header as an encoded-word
+                let value = String::from_utf8_lossy(
+                    raw_message.get(header.offset_start..header.offset_end)?,
+                );
+                output.extend_from_slice(b"Downgraded-");
+                output.extend_from_slice(name.as_bytes());
+                output.extend_from_slice(b": ");
+                Text::new(value.trim())
+                    .write_header(&mut output, bytes_written + 11)
+                    .ok()?;
+            }
+        }
+    }
+
+    if has_changes {
+        output.extend_from_slice(raw_message.get(last_offset..)?);
+        Some(output)
+    } else {
+        None
+    }
+}
+
+fn downgrade_address<'x>(addr: &'x mail_parser::Addr<'x>) -> Address<'x> {
+    let name = addr.name.as_ref().map(|name| name.as_ref());
+    match addr.address.as_deref().and_then(address_to_ascii) {
+        Some(email) => Address::new_address(name, email),
+        None => {
+            // Addresses with a non-ASCII local part are replaced by an empty
+            // group that preserves the original address in its display name.
+            let display = match (name, addr.address.as_deref()) {
+                (Some(name), Some(email)) => format!("{name} <{email}>"),
+                (None, Some(email)) => email.to_string(),
+                (Some(name), None) => name.to_string(),
+                (None, None) => String::new(),
+            };
+            Address::new_group(Some(display), vec![])
+        }
+    }
+}
@@ -21,15 +21,20 @@
  * for more details.
 */
 
-use std::{net::IpAddr, sync::Arc};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::{atomic::Ordering, Arc},
+    time::Instant,
+};
 
 use mail_auth::{IpLookupStrategy, MX};
-use rand::{seq::SliceRandom, Rng};
+use rand::seq::SliceRandom;
+use serde::Serialize;
 use utils::config::KeyLookup;
 
 use crate::{
     config::EnvelopeKey,
-    core::SMTP,
+    core::{DnsOverride, DnsOverrides, SMTP},
     queue::{Error, ErrorDetails, Status},
 };
 
@@ -41,7 +46,51 @@ pub struct IpLookupResult {
     pub remote_ips: Vec<IpAddr>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct DnsOverrideReport {
+    pub domain: String,
+    pub mx: Vec<String>,
+    pub ipv4: Vec<Ipv4Addr>,
+    pub ipv6: Vec<Ipv6Addr>,
+    pub ttl: u64,
+    pub remaining_ttl: Option<u64>,
+    pub hits: u64,
+}
+
 impl SMTP {
+    pub async fn mx_lookup(&self, domain: &str) -> mail_auth::Result<Arc<Vec<MX>>> {
+        match self
+            .resolvers
+            .overrides
+            .lookup(domain, |entry| entry.mx.clone())
+        {
+            Some(mx) => Ok(mx),
+            None => self.resolvers.dns.mx_lookup(domain).await,
+        }
+    }
+
+    pub async fn ipv4_lookup(&self, domain: &str) -> mail_auth::Result<Arc<Vec<Ipv4Addr>>> {
+        match self
+            .resolvers
+            .overrides
+            .lookup(domain, |entry| entry.ipv4.clone())
+        {
+            Some(ips) => Ok(ips),
+            None => self.resolvers.dns.ipv4_lookup(domain).await,
+        }
+    }
+
+    pub async fn ipv6_lookup(&self, domain: &str) -> mail_auth::Result<Arc<Vec<Ipv6Addr>>> {
+        match self
+            .resolvers
+            .overrides
+            .lookup(domain, |entry| entry.ipv6.clone())
+        {
+            Some(ips) => Ok(ips),
+            None => self.resolvers.dns.ipv6_lookup(domain).await,
+        }
+    }
+
     pub async fn ip_lookup(
         &self,
         key: &str,
@@ -55,7 +104,7 @@ impl SMTP {
             IpLookupStrategy::Ipv6thenIpv4 => (true, true, false),
         };
         let ipv4_addrs = if has_ipv4 {
-            match self.resolvers.dns.ipv4_lookup(key).await {
+            match self.ipv4_lookup(key).await {
                 Ok(addrs) => addrs,
                 Err(_) if has_ipv6 => Arc::new(Vec::new()),
                 Err(err) => return Err(err),
@@ -65,7 +114,7 @@ impl SMTP {
         };
 
         if has_ipv6 {
-            let ipv6_addrs = match self.resolvers.dns.ipv6_lookup(key).await {
+            let ipv6_addrs = match self.ipv6_lookup(key).await {
                 Ok(addrs) => addrs,
                 Err(_) if !ipv4_addrs.is_empty() => Arc::new(Vec::new()),
                 Err(err) => return Err(err),
@@ -131,33 +180,49 @@ impl SMTP {
                 remote_ips,
             };
 
+            // Obtain source IP pool, if any
+            let (source_ipv4, source_ipv6): (Vec<IpAddr>, Vec<IpAddr>) =
+                if let Some(pool) = self.queue.config.source_ip.pool.eval(envelope).await {
+                    (
+                        pool.ipv4.iter().copied().map(IpAddr::from).collect(),
+                        pool.ipv6.iter().copied().map(IpAddr::from).collect(),
+                    )
+                } else {
+                    (
+                        self.queue
+                            .config
+                            .source_ip
+                            .ipv4
+                            .eval(envelope)
+                            .await
+                            .iter()
+                            .copied()
+                            .map(IpAddr::from)
+                            .collect(),
+                        self.queue
+                            .config
+                            .source_ip
+                            .ipv6
+                            .eval(envelope)
+                            .await
+                            .iter()
+                            .copied()
+                            .map(IpAddr::from)
+                            .collect(),
+                    )
+                };
+
             // Obtain source IPv4 address
-            let source_ips = self.queue.config.source_ip.ipv4.eval(envelope).await;
-            match source_ips.len().cmp(&1) {
-                std::cmp::Ordering::Equal => {
-                    result.source_ipv4 = IpAddr::from(*source_ips.first().unwrap()).into();
-                }
-                std::cmp::Ordering::Greater => {
-                    result.source_ipv4 =
-                        IpAddr::from(source_ips[rand::thread_rng().gen_range(0..source_ips.len())])
-                            .into();
-                }
-                std::cmp::Ordering::Less => (),
-            }
+            result.source_ipv4 = self
+                .queue
+                .select_source_ip(source_ipv4, remote_host.hostname())
+                .await;
 
             // Obtain source IPv6 address
-            let source_ips = self.queue.config.source_ip.ipv6.eval(envelope).await;
-            match source_ips.len().cmp(&1) {
-                std::cmp::Ordering::Equal => {
-                    result.source_ipv6 = IpAddr::from(*source_ips.first().unwrap()).into();
-                }
-                std::cmp::Ordering::Greater => {
-                    result.source_ipv6 =
-                        IpAddr::from(source_ips[rand::thread_rng().gen_range(0..source_ips.len())])
-                            .into();
-                }
-                std::cmp::Ordering::Less => (),
-            }
+            result.source_ipv6 = self
+                .queue
+                .select_source_ip(source_ipv6, remote_host.hostname())
+                .await;
 
             Ok(result)
         } else {
@@ -169,6 +234,74 @@ impl SMTP {
     }
 }
 
+impl DnsOverrides {
+    pub fn lookup<T>(&self, domain: &str, record: impl Fn(&DnsOverride) -> Option<T>) -> Option<T> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        let entry = self.entries.get(&domain)?;
+        let result = record(entry)?;
+        entry.hits.fetch_add(1, Ordering::Relaxed);
+
+        // Simulate the TTL of a cached record
+        let now = Instant::now();
+        let mut expires = entry.expires.lock();
+        if expires.map_or(true, |expires| expires <= now) {
+            *expires = Some(now + entry.ttl);
+            tracing::debug!(
+                context = "dns",
+                event = "override",
+                domain = domain,
+                ttl = entry.ttl.as_secs(),
+                "Serving records from DNS override."
+            );
+        }
+
+        Some(result)
+    }
+
+    pub fn report(&self) -> Vec<DnsOverrideReport> {
+        let now = Instant::now();
+        let mut report = self
+            .entries
+            .iter()
+            .map(|(domain, entry)| DnsOverrideReport {
+                domain: domain.clone(),
+                mx: entry
+                    .mx
+                    .iter()
+                    .flat_map(|mx| mx.iter())
+                    .flat_map(|mx| {
+                        mx.exchanges
+                            .iter()
+                            .map(move |exchange| format!("{} {exchange}", mx.preference))
+                    })
+                    .collect(),
+                ipv4: entry
+                    .ipv4
+                    .as_ref()
+                    .map(|ips| ips.to_vec())
+                    .unwrap_or_default(),
+                ipv6: entry
+                    .ipv6
+                    .as_ref()
+                    .map(|ips| ips.to_vec())
+                    .unwrap_or_default(),
+                ttl: entry.ttl.as_secs(),
+                remaining_ttl: entry
+                    .expires
+                    .lock()
+                    .filter(|expires| *expires > now)
+                    .map(|expires| (expires - now).as_secs()),
+                hits: entry.hits.load(Ordering::Relaxed),
+            })
+            .collect::<Vec<_>>();
+        report.sort_unstable_by(|a, b| a.domain.cmp(&b.domain));
+        report
+    }
+}
+
 pub trait ToNextHop {
     fn to_remote_hosts<'x, 'y: 'x>(
         &'x self,
@@ -34,6 +34,7 @@ use crate::{
 
 pub mod dane;
 pub mod delivery;
+pub mod downgrade;
 #[cfg(feature = "local_delivery")]
 pub mod local;
 pub mod lookup;
@@ -239,7 +240,7 @@ pub enum NextHop<'x> {
 
 impl<'x> NextHop<'x> {
     #[inline(always)]
-    fn hostname(&self) -> &str {
+    pub(crate) fn hostname(&self) -> &str {
         match self {
             NextHop::MX(host) => {
                 if let Some(host) = host.strip_suffix('.') {
@@ -253,7 +254,7 @@ impl<'x> NextHop<'x> {
     }
 
     #[inline(always)]
-    fn fqdn_hostname(&self) -> Cow<'_, str> {
+    pub(crate) fn fqdn_hostname(&self) -> Cow<'_, str> {
         match self {
             NextHop::MX(host) => {
                 if !host.ends_with('.') {
@@ -267,7 +268,7 @@ impl<'x> NextHop<'x> {
     }
 
     #[inline(always)]
-    fn port(&self) -> u16 {
+    pub(crate) fn port(&self) -> u16 {
         match self {
             #[cfg(feature = "test_mode")]
             NextHop::MX(_) => 9925,
@@ -64,9 +64,11 @@ impl SMTP {
         // Check if the policy has been cached
         if let Some(value) = self.resolvers.cache.mta_sts.get(domain) {
             if value.id == record.id {
+                self.resolvers.cache.mta_sts_stats.hit();
                 return Ok(value);
             }
         }
+        self.resolvers.cache.mta_sts_stats.miss();
 
         // Fetch policy
         #[cfg(not(feature = "test_mode"))]
@@ -95,6 +97,7 @@ impl SMTP {
                 86400
             });
 
+        self.resolvers.cache.mta_sts_stats.insert();
         Ok(self
             .resolvers
             .cache
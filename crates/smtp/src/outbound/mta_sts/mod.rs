@@ -21,6 +21,8 @@
  * for more details.
 */
 
+use std::fmt::Display;
+
 pub mod lookup;
 pub mod parse;
 pub mod verify;
@@ -46,6 +48,35 @@ pub struct Policy {
     pub max_age: u64,
 }
 
+impl Display for Mode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mode::Enforce => f.write_str("enforce"),
+            Mode::Testing => f.write_str("testing"),
+            Mode::None => f.write_str("none"),
+        }
+    }
+}
+
+impl Display for MxPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MxPattern::Equals(host) => f.write_str(host),
+            MxPattern::StartsWith(suffix) => write!(f, "*.{suffix}"),
+        }
+    }
+}
+
+impl Display for Policy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "version: STSv1\r\nmode: {}\r\n", self.mode)?;
+        for mx in &self.mx {
+            write!(f, "mx: {mx}\r\n")?;
+        }
+        write!(f, "max_age: {}\r\n", self.max_age)
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     Dns(mail_auth::Error),
@@ -27,6 +27,7 @@ use smtp_proto::{
     EXT_SMTP_UTF8, EXT_START_TLS, MAIL_REQUIRETLS, MAIL_RET_FULL, MAIL_RET_HDRS, MAIL_SMTPUTF8,
     RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
 };
+use std::collections::VecDeque;
 use std::fmt::Write;
 use std::time::Duration;
 use tokio::{
@@ -38,6 +39,7 @@ use tokio_rustls::{client::TlsStream, TlsConnector};
 
 use crate::{
     config::{RequireOptional, TlsStrategy},
+    outbound::downgrade::{address_to_ascii, downgrade_headers},
     queue::{ErrorDetails, HostResponse, RCPT_STATUS_CHANGED},
 };
 
@@ -53,6 +55,7 @@ pub struct SessionParams<'x> {
     pub timeout_mail: Duration,
     pub timeout_rcpt: Duration,
     pub timeout_data: Duration,
+    pub max_rcpt: usize,
 }
 
 impl Message {
@@ -78,6 +81,22 @@ impl Message {
             }
         };
 
+        // REQUIRETLS messages can only be relayed to hosts supporting it (RFC 8689)
+        if self.has_flag(MAIL_REQUIRETLS) && !capabilities.has_capability(EXT_REQUIRE_TLS) {
+            tracing::info!(
+                parent: params.span,
+                context = "tls",
+                event = "requiretls-unsupported",
+                mx = &params.hostname,
+                "Remote host does not support REQUIRETLS."
+            );
+            quit(smtp_client).await;
+            return Status::PermanentFailure(Error::RequireTls(ErrorDetails {
+                entity: params.hostname.to_string(),
+                details: "REQUIRETLS not advertised by host".to_string(),
+            }));
+        }
+
         // Authenticate
         if let Some(credentials) = params.credentials {
             if let Err(err) = smtp_client.authenticate(credentials, &capabilities).await {
@@ -110,30 +129,36 @@ impl Message {
             };*/
         }
 
-        // MAIL FROM
-        smtp_client.timeout = params.timeout_mail;
-        let cmd = self.build_mail_from(&capabilities);
-        if let Err(err) = smtp_client
-            .cmd(cmd.as_bytes())
-            .await
-            .and_then(|r| r.assert_positive_completion())
-        {
-            tracing::info!(
-                parent: params.span,
-                context = "sender",
-                event = "rejected",
-                mx = &params.hostname,
-                reason = %err,
-            );
-            quit(smtp_client).await;
-            return Status::from_smtp_error(params.hostname, &cmd, err);
-        }
+        // Internationalized messages have to be downgraded (RFC 6857)
+        // when the remote host does not support SMTPUTF8
+        let downgrade = self.has_flag(MAIL_SMTPUTF8) && !capabilities.has_capability(EXT_SMTP_UTF8);
+        let return_path = if downgrade {
+            match address_to_ascii(&self.return_path) {
+                Some(return_path) => return_path,
+                None => {
+                    tracing::info!(
+                        parent: params.span,
+                        context = "sender",
+                        event = "downgrade-failed",
+                        mx = &params.hostname,
+                        return_path = self.return_path,
+                        "Remote host does not support SMTPUTF8 and the return path cannot be downgraded."
+                    );
+                    quit(smtp_client).await;
+                    return Status::PermanentFailure(Error::UnexpectedResponse(downgrade_error(
+                        params.hostname,
+                        "MAIL FROM",
+                    )));
+                }
+            }
+        } else {
+            self.return_path.as_str().into()
+        };
 
-        // RCPT TO
+        // Skip recipients that were already processed
         let mut total_rcpt = 0;
         let mut total_completed = 0;
-        let mut accepted_rcpts = Vec::new();
-        smtp_client.timeout = params.timeout_rcpt;
+        let mut pending_rcpts = VecDeque::new();
         for rcpt in recipients {
             total_rcpt += 1;
             if matches!(
@@ -141,205 +166,318 @@ impl Message {
                 Status::Completed(_) | Status::PermanentFailure(_)
             ) {
                 total_completed += 1;
-                continue;
+            } else {
+                pending_rcpts.push_back(rcpt);
             }
+        }
 
-            let cmd = self.build_rcpt_to(rcpt, &capabilities);
-            match smtp_client.cmd(cmd.as_bytes()).await {
-                Ok(response) => match response.severity() {
-                    Severity::PositiveCompletion => {
-                        accepted_rcpts.push((
-                            rcpt,
-                            Status::Completed(HostResponse {
-                                hostname: params.hostname.to_string(),
-                                response,
-                            }),
-                        ));
-                    }
-                    severity => {
-                        tracing::info!(
-                            parent: params.span,
-                            context = "rcpt",
-                            event = "rejected",
-                            rcpt = rcpt.address,
-                            mx = &params.hostname,
-                            reason = %response,
-                        );
-
-                        let response = HostResponse {
-                            hostname: ErrorDetails {
-                                entity: params.hostname.to_string(),
-                                details: cmd.trim().to_string(),
-                            },
-                            response,
-                        };
-                        rcpt.flags |= RCPT_STATUS_CHANGED;
-                        rcpt.status = if severity == Severity::PermanentNegativeCompletion {
-                            total_completed += 1;
-                            Status::PermanentFailure(response)
-                        } else {
-                            Status::TemporaryFailure(response)
-                        };
-                    }
-                },
-                Err(err) => {
+        // Recipients exceeding the per-transaction limit are sent in subsequent transactions
+        let mut raw_message = None;
+        let mut is_first_transaction = true;
+        while !pending_rcpts.is_empty() {
+            // Reset the session before starting a new transaction
+            if !is_first_transaction {
+                smtp_client.timeout = params.timeout_mail;
+                if let Err(err) = smtp_client
+                    .cmd(b"RSET\r\n")
+                    .await
+                    .and_then(|r| r.assert_positive_completion())
+                {
                     tracing::info!(
                         parent: params.span,
-                        context = "rcpt",
-                        event = "failed",
+                        context = "rset",
+                        event = "rejected",
                         mx = &params.hostname,
-                        rcpt = rcpt.address,
                         reason = %err,
                     );
-
-                    // Something went wrong, abort.
                     quit(smtp_client).await;
-                    return Status::from_smtp_error(params.hostname, "", err);
+                    return Status::from_smtp_error(params.hostname, "RSET", err);
                 }
             }
-        }
+            is_first_transaction = false;
 
-        // Send message
-        if !accepted_rcpts.is_empty() {
-            let bdat_cmd = if capabilities.has_capability(EXT_CHUNKING) {
-                format!("BDAT {} LAST\r\n", self.size).into()
-            } else {
-                None
-            };
-
-            if let Err(status) = send_message(&mut smtp_client, self, &bdat_cmd, &params).await {
+            // MAIL FROM
+            smtp_client.timeout = params.timeout_mail;
+            let cmd = self.build_mail_from(&return_path, &capabilities);
+            if let Err(err) = smtp_client
+                .cmd(cmd.as_bytes())
+                .await
+                .and_then(|r| r.assert_positive_completion())
+            {
                 tracing::info!(
                     parent: params.span,
-                    context = "message",
+                    context = "sender",
                     event = "rejected",
                     mx = &params.hostname,
-                    reason = %status,
+                    reason = %err,
                 );
-
                 quit(smtp_client).await;
-                return status;
+                return Status::from_smtp_error(params.hostname, &cmd, err);
             }
 
-            if params.is_smtp {
-                // Handle SMTP response
-                match read_smtp_data_respone(&mut smtp_client, params.hostname, &bdat_cmd).await {
-                    Ok(response) => {
-                        // Mark recipients as delivered
-                        if response.code() == 250 {
-                            for (rcpt, status) in accepted_rcpts {
-                                tracing::info!(
-                                    parent: params.span,
-                                    context = "rcpt",
-                                    event = "delivered",
-                                    rcpt = rcpt.address,
-                                    mx = &params.hostname,
-                                    response = %status,
-                                );
+            // RCPT TO
+            let mut accepted_rcpts = Vec::new();
+            smtp_client.timeout = params.timeout_rcpt;
+            while let Some(rcpt) = pending_rcpts.pop_front() {
+                if accepted_rcpts.len() >= params.max_rcpt {
+                    pending_rcpts.push_front(rcpt);
+                    break;
+                }
 
-                                rcpt.status = status;
-                                rcpt.flags |= RCPT_STATUS_CHANGED;
-                                total_completed += 1;
-                            }
-                        } else {
+                let address = if downgrade {
+                    match address_to_ascii(&rcpt.address) {
+                        Some(address) => address,
+                        None => {
                             tracing::info!(
                                 parent: params.span,
-                                context = "message",
+                                context = "rcpt",
+                                event = "downgrade-failed",
+                                rcpt = rcpt.address,
+                                mx = &params.hostname,
+                                "Remote host does not support SMTPUTF8 and the recipient cannot be downgraded."
+                            );
+                            rcpt.flags |= RCPT_STATUS_CHANGED;
+                            rcpt.status = Status::PermanentFailure(downgrade_error(
+                                params.hostname,
+                                &format!("RCPT TO:<{}>", rcpt.address),
+                            ));
+                            total_completed += 1;
+                            continue;
+                        }
+                    }
+                } else {
+                    rcpt.address.as_str().into()
+                };
+
+                let cmd = self.build_rcpt_to(rcpt, &address, &capabilities);
+                match smtp_client.cmd(cmd.as_bytes()).await {
+                    Ok(response) => match response.severity() {
+                        Severity::PositiveCompletion => {
+                            accepted_rcpts.push((
+                                rcpt,
+                                Status::Completed(HostResponse {
+                                    hostname: params.hostname.to_string(),
+                                    response,
+                                }),
+                            ));
+                        }
+                        _ if response.code() == 452 && !accepted_rcpts.is_empty() => {
+                            // Too many recipients, retry in the next transaction (RFC 5321 section 4.5.3.1.10)
+                            tracing::debug!(
+                                parent: params.span,
+                                context = "rcpt",
+                                event = "deferred",
+                                rcpt = rcpt.address,
+                                mx = &params.hostname,
+                                reason = %response,
+                            );
+                            pending_rcpts.push_front(rcpt);
+                            break;
+                        }
+                        severity => {
+                            tracing::info!(
+                                parent: params.span,
+                                context = "rcpt",
                                 event = "rejected",
+                                rcpt = rcpt.address,
                                 mx = &params.hostname,
                                 reason = %response,
                             );
 
-                            quit(smtp_client).await;
-                            return Status::from_smtp_error(
-                                params.hostname,
-                                bdat_cmd.as_deref().unwrap_or("DATA"),
-                                mail_send::Error::UnexpectedReply(response),
-                            );
+                            let response = HostResponse {
+                                hostname: ErrorDetails {
+                                    entity: params.hostname.to_string(),
+                                    details: cmd.trim().to_string(),
+                                },
+                                response,
+                            };
+                            rcpt.flags |= RCPT_STATUS_CHANGED;
+                            rcpt.status = if severity == Severity::PermanentNegativeCompletion {
+                                total_completed += 1;
+                                Status::PermanentFailure(response)
+                            } else {
+                                Status::TemporaryFailure(response)
+                            };
                         }
-                    }
-                    Err(status) => {
+                    },
+                    Err(err) => {
                         tracing::info!(
                             parent: params.span,
-                            context = "message",
+                            context = "rcpt",
                             event = "failed",
                             mx = &params.hostname,
-                            reason = %status,
+                            rcpt = rcpt.address,
+                            reason = %err,
                         );
 
+                        // Something went wrong, abort.
                         quit(smtp_client).await;
-                        return status;
+                        return Status::from_smtp_error(params.hostname, "", err);
                     }
                 }
-            } else {
-                // Handle LMTP responses
-                match read_lmtp_data_respone(
-                    &mut smtp_client,
-                    params.hostname,
-                    accepted_rcpts.len(),
-                )
-                .await
+            }
+
+            // Send message
+            if !accepted_rcpts.is_empty() {
+                if raw_message.is_none() {
+                    let mut message = match self.read_message(&params).await {
+                        Ok(message) => message,
+                        Err(status) => {
+                            quit(smtp_client).await;
+                            return status;
+                        }
+                    };
+                    if downgrade {
+                        if let Some(downgraded) = downgrade_headers(&message) {
+                            message = downgraded;
+                        }
+                    }
+                    raw_message = message.into();
+                }
+                let raw_message = raw_message.as_deref().unwrap_or_default();
+                let bdat_cmd = if capabilities.has_capability(EXT_CHUNKING) {
+                    format!("BDAT {} LAST\r\n", raw_message.len()).into()
+                } else {
+                    None
+                };
+
+                if let Err(status) =
+                    send_message(&mut smtp_client, raw_message, &bdat_cmd, &params).await
                 {
-                    Ok(responses) => {
-                        for ((rcpt, _), response) in accepted_rcpts.into_iter().zip(responses) {
-                            rcpt.flags |= RCPT_STATUS_CHANGED;
-                            rcpt.status = match response.severity() {
-                                Severity::PositiveCompletion => {
+                    tracing::info!(
+                        parent: params.span,
+                        context = "message",
+                        event = "rejected",
+                        mx = &params.hostname,
+                        reason = %status,
+                    );
+
+                    quit(smtp_client).await;
+                    return status;
+                }
+
+                if params.is_smtp {
+                    // Handle SMTP response
+                    match read_smtp_data_respone(&mut smtp_client, params.hostname, &bdat_cmd).await
+                    {
+                        Ok(response) => {
+                            // Mark recipients as delivered
+                            if response.code() == 250 {
+                                for (rcpt, status) in accepted_rcpts {
                                     tracing::info!(
                                         parent: params.span,
                                         context = "rcpt",
                                         event = "delivered",
                                         rcpt = rcpt.address,
                                         mx = &params.hostname,
-                                        response = %response,
+                                        response = %status,
                                     );
 
+                                    rcpt.status = status;
+                                    rcpt.flags |= RCPT_STATUS_CHANGED;
                                     total_completed += 1;
-                                    Status::Completed(HostResponse {
-                                        hostname: params.hostname.to_string(),
-                                        response,
-                                    })
                                 }
-                                severity => {
-                                    tracing::info!(
-                                        parent: params.span,
-                                        context = "rcpt",
-                                        event = "rejected",
-                                        rcpt = rcpt.address,
-                                        mx = &params.hostname,
-                                        reason = %response,
-                                    );
+                            } else {
+                                tracing::info!(
+                                    parent: params.span,
+                                    context = "message",
+                                    event = "rejected",
+                                    mx = &params.hostname,
+                                    reason = %response,
+                                );
+
+                                quit(smtp_client).await;
+                                return Status::from_smtp_error(
+                                    params.hostname,
+                                    bdat_cmd.as_deref().unwrap_or("DATA"),
+                                    mail_send::Error::UnexpectedReply(response),
+                                );
+                            }
+                        }
+                        Err(status) => {
+                            tracing::info!(
+                                parent: params.span,
+                                context = "message",
+                                event = "failed",
+                                mx = &params.hostname,
+                                reason = %status,
+                            );
+
+                            quit(smtp_client).await;
+                            return status;
+                        }
+                    }
+                } else {
+                    // Handle LMTP responses
+                    match read_lmtp_data_respone(
+                        &mut smtp_client,
+                        params.hostname,
+                        accepted_rcpts.len(),
+                    )
+                    .await
+                    {
+                        Ok(responses) => {
+                            for ((rcpt, _), response) in accepted_rcpts.into_iter().zip(responses) {
+                                rcpt.flags |= RCPT_STATUS_CHANGED;
+                                rcpt.status = match response.severity() {
+                                    Severity::PositiveCompletion => {
+                                        tracing::info!(
+                                            parent: params.span,
+                                            context = "rcpt",
+                                            event = "delivered",
+                                            rcpt = rcpt.address,
+                                            mx = &params.hostname,
+                                            response = %response,
+                                        );
 
-                                    let response = HostResponse {
-                                        hostname: ErrorDetails {
-                                            entity: params.hostname.to_string(),
-                                            details: bdat_cmd
-                                                .as_deref()
-                                                .unwrap_or("DATA")
-                                                .to_string(),
-                                        },
-                                        response,
-                                    };
-                                    if severity == Severity::PermanentNegativeCompletion {
                                         total_completed += 1;
-                                        Status::PermanentFailure(response)
-                                    } else {
-                                        Status::TemporaryFailure(response)
+                                        Status::Completed(HostResponse {
+                                            hostname: params.hostname.to_string(),
+                                            response,
+                                        })
                                     }
-                                }
-                            };
+                                    severity => {
+                                        tracing::info!(
+                                            parent: params.span,
+                                            context = "rcpt",
+                                            event = "rejected",
+                                            rcpt = rcpt.address,
+                                            mx = &params.hostname,
+                                            reason = %response,
+                                        );
+
+                                        let response = HostResponse {
+                                            hostname: ErrorDetails {
+                                                entity: params.hostname.to_string(),
+                                                details: bdat_cmd
+                                                    .as_deref()
+                                                    .unwrap_or("DATA")
+                                                    .to_string(),
+                                            },
+                                            response,
+                                        };
+                                        if severity == Severity::PermanentNegativeCompletion {
+                                            total_completed += 1;
+                                            Status::PermanentFailure(response)
+                                        } else {
+                                            Status::TemporaryFailure(response)
+                                        }
+                                    }
+                                };
+                            }
                         }
-                    }
-                    Err(status) => {
-                        tracing::info!(
-                            parent: params.span,
-                            context = "message",
-                            event = "rejected",
-                            mx = &params.hostname,
-                            reason = %status,
-                        );
+                        Err(status) => {
+                            tracing::info!(
+                                parent: params.span,
+                                context = "message",
+                                event = "rejected",
+                                mx = &params.hostname,
+                                reason = %status,
+                            );
 
-                        quit(smtp_client).await;
-                        return status;
+                            quit(smtp_client).await;
+                            return status;
+                        }
                     }
                 }
             }
@@ -353,9 +491,9 @@ impl Message {
         }
     }
 
-    fn build_mail_from(&self, capabilities: &EhloResponse<String>) -> String {
-        let mut mail_from = String::with_capacity(self.return_path.len() + 60);
-        let _ = write!(mail_from, "MAIL FROM:<{}>", self.return_path);
+    fn build_mail_from(&self, return_path: &str, capabilities: &EhloResponse<String>) -> String {
+        let mut mail_from = String::with_capacity(return_path.len() + 60);
+        let _ = write!(mail_from, "MAIL FROM:<{}>", return_path);
         if capabilities.has_capability(EXT_SIZE) {
             let _ = write!(mail_from, " SIZE={}", self.size);
         }
@@ -380,9 +518,14 @@ impl Message {
         mail_from
     }
 
-    fn build_rcpt_to(&self, rcpt: &Recipient, capabilities: &EhloResponse<String>) -> String {
-        let mut rcpt_to = String::with_capacity(rcpt.address.len() + 60);
-        let _ = write!(rcpt_to, "RCPT TO:<{}>", rcpt.address);
+    fn build_rcpt_to(
+        &self,
+        rcpt: &Recipient,
+        address: &str,
+        capabilities: &EhloResponse<String>,
+    ) -> String {
+        let mut rcpt_to = String::with_capacity(address.len() + 60);
+        let _ = write!(rcpt_to, "RCPT TO:<{}>", address);
         if capabilities.has_capability(EXT_DSN) {
             if rcpt.has_flag(RCPT_NOTIFY_SUCCESS | RCPT_NOTIFY_FAILURE | RCPT_NOTIFY_DELAY) {
                 rcpt_to.push_str(" NOTIFY=");
@@ -414,6 +557,33 @@ impl Message {
         rcpt_to
     }
 
+    pub async fn read_message(
+        &self,
+        params: &SessionParams<'_>,
+    ) -> Result<Vec<u8>, Status<(), Error>> {
+        let mut raw_message = vec![0u8; self.size];
+        let mut file = fs::File::open(&self.path).await.map_err(|err| {
+            tracing::error!(parent: params.span,
+                            context = "queue", 
+                            event = "error", 
+                            "Failed to open message file {}: {}", 
+                            self.path.display(),
+                            err);
+            Status::TemporaryFailure(Error::Io("Queue system error.".to_string()))
+        })?;
+        file.read_exact(&mut raw_message).await.map_err(|err| {
+            tracing::error!(parent: params.span,
+                            context = "queue", 
+                            event = "error", 
+                            "Failed to read {} bytes file {} from disk: {}", 
+                            self.size,
+                            self.path.display(),
+                            err);
+            Status::TemporaryFailure(Error::Io("Queue system error.".to_string()))
+        })?;
+        Ok(raw_message)
+    }
+
     #[inline(always)]
     pub fn has_flag(&self, flag: u64) -> bool {
         (self.flags & flag) != 0
@@ -528,38 +698,18 @@ pub async fn write_chunks<T: AsyncRead + AsyncWrite + Unpin>(
 
 pub async fn send_message<T: AsyncRead + AsyncWrite + Unpin>(
     smtp_client: &mut SmtpClient<T>,
-    message: &Message,
+    raw_message: &[u8],
     bdat_cmd: &Option<String>,
     params: &SessionParams<'_>,
 ) -> Result<(), Status<(), Error>> {
-    let mut raw_message = vec![0u8; message.size];
-    let mut file = fs::File::open(&message.path).await.map_err(|err| {
-        tracing::error!(parent: params.span,
-                            context = "queue", 
-                            event = "error", 
-                            "Failed to open message file {}: {}", 
-                            message.path.display(),
-                            err);
-        Status::TemporaryFailure(Error::Io("Queue system error.".to_string()))
-    })?;
-    file.read_exact(&mut raw_message).await.map_err(|err| {
-        tracing::error!(parent: params.span,
-                            context = "queue", 
-                            event = "error", 
-                            "Failed to read {} bytes file {} from disk: {}", 
-                            message.size,
-                            message.path.display(),
-                            err);
-        Status::TemporaryFailure(Error::Io("Queue system error.".to_string()))
-    })?;
     tokio::time::timeout(params.timeout_data, async {
         if let Some(bdat_cmd) = bdat_cmd {
-            write_chunks(smtp_client, &[bdat_cmd.as_bytes(), &raw_message]).await
+            write_chunks(smtp_client, &[bdat_cmd.as_bytes(), raw_message]).await
         } else {
             write_chunks(smtp_client, &[b"DATA\r\n"]).await?;
             smtp_client.read().await?.assert_code(354)?;
             smtp_client
-                .write_message(&raw_message)
+                .write_message(raw_message)
                 .await
                 .map_err(mail_send::Error::from)
         }
@@ -571,6 +721,21 @@ pub async fn send_message<T: AsyncRead + AsyncWrite + Unpin>(
     })
 }
 
+fn downgrade_error(hostname: &str, details: &str) -> HostResponse<ErrorDetails> {
+    HostResponse {
+        hostname: ErrorDetails {
+            entity: hostname.to_string(),
+            details: details.to_string(),
+        },
+        response: Response {
+            code: 553,
+            esc: [5, 6, 7],
+            message: "Remote host does not support SMTPUTF8 and the address cannot be downgraded."
+                .to_string(),
+        },
+    }
+}
+
 pub async fn say_helo<T: AsyncRead + AsyncWrite + Unpin>(
     smtp_client: &mut SmtpClient<T>,
     params: &SessionParams<'_>,
@@ -637,6 +802,28 @@ impl TlsStrategy {
         matches!(self.mta_sts, RequireOptional::Require)
     }
 
+    // REQUIRETLS messages (RFC 8689) need STARTTLS and are checked against
+    // any MTA-STS or DANE policy published by the recipient domain.
+    pub fn enforce(&mut self) {
+        self.tls = RequireOptional::Require;
+        if matches!(self.mta_sts, RequireOptional::Disable) {
+            self.mta_sts = RequireOptional::Optional;
+        }
+        if matches!(self.dane, RequireOptional::Disable) {
+            self.dane = RequireOptional::Optional;
+        }
+    }
+
+    // Messages with a "TLS-Required: No" header (RFC 8689) ignore MTA-STS and
+    // DANE policies and may fall back to plain-text delivery.
+    pub fn relax(&mut self) {
+        self.mta_sts = RequireOptional::Disable;
+        self.dane = RequireOptional::Disable;
+        if matches!(self.tls, RequireOptional::Require) {
+            self.tls = RequireOptional::Optional;
+        }
+    }
+
     #[inline(always)]
     pub fn is_tls_required(&self) -> bool {
         matches!(self.tls, RequireOptional::Require)
@@ -0,0 +1,491 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+};
+
+use ahash::AHashMap;
+use mail_parser::DateTime;
+
+use crate::{
+    config::QueueAnalytics,
+    core::{
+        management::{AnalyticsSample, AnalyticsSummary, DomainAnalytics, LatencyPercentiles},
+        QueueCore,
+    },
+};
+
+use super::{Error, Status};
+
+const HOUR: u64 = 3600;
+const DAY: u64 = 86400;
+
+/// Upper bounds in seconds of the delivery latency histogram buckets,
+/// delivery times above the last bound are counted in an overflow bucket.
+const LATENCY_BOUNDS: [u64; 16] = [
+    1,
+    5,
+    10,
+    30,
+    60,
+    5 * 60,
+    10 * 60,
+    30 * 60,
+    HOUR,
+    3 * HOUR,
+    6 * HOUR,
+    12 * HOUR,
+    DAY,
+    2 * DAY,
+    3 * DAY,
+    5 * DAY,
+];
+
+#[derive(Default)]
+pub struct DeliveryAnalytics {
+    inner: parking_lot::Mutex<AnalyticsInner>,
+}
+
+#[derive(Default)]
+struct AnalyticsInner {
+    domains: AHashMap<String, DomainHistory>,
+    latency: AHashMap<String, LatencyHistogram>,
+    last_compaction: u64,
+}
+
+/// Delivery latencies of a destination domain since startup, these are never
+/// compacted so that they can be exported as Prometheus counters.
+#[derive(Debug, Default, Clone)]
+struct LatencyHistogram {
+    buckets: [u64; LATENCY_BOUNDS.len() + 1],
+    sum: u64,
+    count: u64,
+}
+
+/// Delivery statistics for a destination domain, recent data is kept at an
+/// hourly resolution and older data is downsampled into daily buckets.
+#[derive(Default)]
+struct DomainHistory {
+    hourly: VecDeque<(u64, DeliveryStats)>,
+    daily: VecDeque<(u64, DeliveryStats)>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DeliveryStats {
+    pub attempts: u64,
+    pub delivered: u64,
+    pub temporary_failures: u64,
+    pub permanent_failures: u64,
+    pub tls: u64,
+    pub plaintext: u64,
+    pub failures: BTreeMap<&'static str, u64>,
+    pub latency: [u64; LATENCY_BOUNDS.len() + 1],
+}
+
+impl QueueCore {
+    /// Records the outcome of a delivery attempt to a destination domain.
+    /// `tls` is `None` when no SMTP session could be established.
+    pub fn record_delivery(
+        &self,
+        domain: &str,
+        status: &Status<(), Error>,
+        tls: Option<bool>,
+        created: u64,
+    ) {
+        let config = &self.config.analytics;
+        if config.enable && !domain.is_empty() {
+            self.analytics
+                .record(config, domain, status, tls, created, now());
+        }
+    }
+
+    /// Returns the delivery statistics of the destination domains matching
+    /// `domain` within the `from` and `to` UNIX timestamps.
+    pub fn delivery_analytics(
+        &self,
+        domain: Option<&str>,
+        from: u64,
+        to: u64,
+        series: bool,
+    ) -> Vec<DomainAnalytics> {
+        self.analytics
+            .query(&self.config.analytics, domain, from, to, series, now())
+    }
+
+    /// Returns the per-domain delivery latency histograms in the Prometheus
+    /// text format.
+    pub fn delivery_latency_prometheus(&self) -> String {
+        let config = &self.config.analytics;
+        if config.enable {
+            self.analytics.to_prometheus(config.prometheus_domains)
+        } else {
+            String::new()
+        }
+    }
+}
+
+impl DeliveryAnalytics {
+    pub fn record(
+        &self,
+        config: &QueueAnalytics,
+        domain: &str,
+        status: &Status<(), Error>,
+        tls: Option<bool>,
+        created: u64,
+        now: u64,
+    ) {
+        let mut stats = DeliveryStats::default();
+        let mut latency = None;
+        match status {
+            Status::Completed(_) => {
+                stats.delivered = 1;
+                stats.latency[latency_bucket(now.saturating_sub(created))] = 1;
+                latency = now.saturating_sub(created).into();
+            }
+            Status::TemporaryFailure(err) => {
+                stats.temporary_failures = 1;
+                stats.failures.insert(err.category(), 1);
+            }
+            Status::PermanentFailure(err) => {
+                stats.permanent_failures = 1;
+                stats.failures.insert(err.category(), 1);
+            }
+            Status::Scheduled => return,
+        }
+        stats.attempts = 1;
+        match tls {
+            Some(true) => stats.tls = 1,
+            Some(false) => stats.plaintext = 1,
+            None => (),
+        }
+
+        let mut inner = self.inner.lock();
+        inner.compact(config, now);
+        if !inner.domains.contains_key(domain) {
+            if inner.domains.len() >= config.max_domains {
+                tracing::debug!(
+                    context = "queue",
+                    event = "analytics",
+                    domain = domain,
+                    "Analytics not recorded, maximum number of tracked domains reached."
+                );
+                return;
+            }
+            inner
+                .domains
+                .insert(domain.to_string(), DomainHistory::default());
+        }
+        let history = inner.domains.get_mut(domain).unwrap();
+
+        let hour = now - (now % HOUR);
+        match history.hourly.back_mut() {
+            Some((last_hour, last_stats)) if *last_hour == hour => last_stats.merge(&stats),
+            _ => history.hourly.push_back((hour, stats)),
+        }
+
+        if let Some(latency) = latency {
+            if let Some(histogram) = inner.latency.get_mut(domain) {
+                histogram.record(latency);
+            } else if inner.latency.len() < config.max_domains {
+                let mut histogram = LatencyHistogram::default();
+                histogram.record(latency);
+                inner.latency.insert(domain.to_string(), histogram);
+            }
+        }
+    }
+
+    /// Exports the delivery latency histograms of the `top_domains` domains
+    /// with the most deliveries in the Prometheus text format, the remaining
+    /// domains are aggregated under `domain="other"`.
+    pub fn to_prometheus(&self, top_domains: usize) -> String {
+        let inner = self.inner.lock();
+        if inner.latency.is_empty() {
+            return String::new();
+        }
+        let mut domains = inner
+            .latency
+            .iter()
+            .map(|(domain, histogram)| (domain.as_str(), histogram))
+            .collect::<Vec<_>>();
+        domains.sort_unstable_by(|(a_domain, a), (b_domain, b)| {
+            b.count.cmp(&a.count).then_with(|| a_domain.cmp(b_domain))
+        });
+        let mut other = LatencyHistogram::default();
+        for (_, histogram) in domains.iter().skip(top_domains) {
+            other.merge(histogram);
+        }
+        domains.truncate(top_domains);
+
+        let metric = "stalwart_delivery_latency_seconds";
+        let mut out = String::with_capacity(256 * (domains.len() + 1));
+        let _ = writeln!(
+            out,
+            "# HELP {metric} Time from message reception to successful delivery, by destination domain."
+        );
+        let _ = writeln!(out, "# TYPE {metric} histogram");
+        for (domain, histogram) in domains
+            .into_iter()
+            .chain((other.count > 0).then_some(("other", &other)))
+        {
+            let domain = escape_label(domain);
+            let mut count = 0;
+            for (bound, bucket) in LATENCY_BOUNDS.iter().zip(histogram.buckets.iter()) {
+                count += bucket;
+                let _ = writeln!(
+                    out,
+                    "{metric}_bucket{{domain=\"{domain}\",le=\"{bound}\"}} {count}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{metric}_bucket{{domain=\"{domain}\",le=\"+Inf\"}} {}",
+                histogram.count
+            );
+            let _ = writeln!(out, "{metric}_sum{{domain=\"{domain}\"}} {}", histogram.sum);
+            let _ = writeln!(
+                out,
+                "{metric}_count{{domain=\"{domain}\"}} {}",
+                histogram.count
+            );
+        }
+
+        out
+    }
+
+    pub fn query(
+        &self,
+        config: &QueueAnalytics,
+        domain: Option<&str>,
+        from: u64,
+        to: u64,
+        series: bool,
+        now: u64,
+    ) -> Vec<DomainAnalytics> {
+        let mut inner = self.inner.lock();
+        inner.compact(config, now);
+
+        let mut results = Vec::new();
+        for (name, history) in &inner.domains {
+            if domain.map_or(false, |domain| !domain.eq_ignore_ascii_case(name)) {
+                continue;
+            }
+
+            let mut summary = DeliveryStats::default();
+            let mut samples = Vec::new();
+            for (start, duration, stats) in history
+                .daily
+                .iter()
+                .map(|(start, stats)| (*start, DAY, stats))
+                .chain(
+                    history
+                        .hourly
+                        .iter()
+                        .map(|(start, stats)| (*start, HOUR, stats)),
+                )
+            {
+                if start + duration > from && start < to {
+                    summary.merge(stats);
+                    if series {
+                        samples.push(AnalyticsSample {
+                            from: DateTime::from_timestamp(start as i64),
+                            duration,
+                            stats: stats.into(),
+                        });
+                    }
+                }
+            }
+
+            if summary.attempts > 0 {
+                results.push(DomainAnalytics {
+                    domain: name.clone(),
+                    stats: (&summary).into(),
+                    series: samples,
+                });
+            }
+        }
+        results.sort_unstable_by(|a, b| a.domain.cmp(&b.domain));
+
+        results
+    }
+}
+
+impl LatencyHistogram {
+    fn record(&mut self, latency: u64) {
+        self.buckets[latency_bucket(latency)] += 1;
+        self.sum += latency;
+        self.count += 1;
+    }
+
+    fn merge(&mut self, other: &LatencyHistogram) {
+        for (total, count) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *total += count;
+        }
+        self.sum += other.sum;
+        self.count += other.count;
+    }
+}
+
+impl AnalyticsInner {
+    fn compact(&mut self, config: &QueueAnalytics, now: u64) {
+        // Compact at most once per hour
+        let hour = now - (now % HOUR);
+        if self.last_compaction == hour {
+            return;
+        }
+        self.last_compaction = hour;
+
+        let hourly_cutoff = now.saturating_sub(config.hourly_retention.as_secs());
+        let daily_cutoff = now.saturating_sub(config.daily_retention.as_secs());
+        self.domains.retain(|_, history| {
+            // Downsample expired hourly buckets into daily buckets
+            while let Some((start, _)) = history.hourly.front() {
+                if *start + HOUR > hourly_cutoff {
+                    break;
+                }
+                let (start, stats) = history.hourly.pop_front().unwrap();
+                let day = start - (start % DAY);
+                match history.daily.back_mut() {
+                    Some((last_day, last_stats)) if *last_day == day => last_stats.merge(&stats),
+                    _ => history.daily.push_back((day, stats)),
+                }
+            }
+
+            // Remove expired daily buckets
+            while history
+                .daily
+                .front()
+                .map_or(false, |(start, _)| *start + DAY <= daily_cutoff)
+            {
+                history.daily.pop_front();
+            }
+
+            !history.hourly.is_empty() || !history.daily.is_empty()
+        });
+    }
+}
+
+impl DeliveryStats {
+    pub fn merge(&mut self, other: &DeliveryStats) {
+        self.attempts += other.attempts;
+        self.delivered += other.delivered;
+        self.temporary_failures += other.temporary_failures;
+        self.permanent_failures += other.permanent_failures;
+        self.tls += other.tls;
+        self.plaintext += other.plaintext;
+        for (category, count) in &other.failures {
+            *self.failures.entry(category).or_default() += count;
+        }
+        for (total, count) in self.latency.iter_mut().zip(other.latency.iter()) {
+            *total += count;
+        }
+    }
+
+    /// Estimates a latency percentile, returning the upper bound in seconds
+    /// of the histogram bucket that contains it.
+    pub fn latency_percentile(&self, percentile: u64) -> Option<u64> {
+        let total = self.latency.iter().sum::<u64>();
+        if total == 0 {
+            return None;
+        }
+        let target = std::cmp::max((total * percentile + 99) / 100, 1);
+        let mut count = 0;
+        for (pos, bucket) in self.latency.iter().enumerate() {
+            count += bucket;
+            if count >= target {
+                return Some(LATENCY_BOUNDS[std::cmp::min(pos, LATENCY_BOUNDS.len() - 1)]);
+            }
+        }
+        LATENCY_BOUNDS.last().copied()
+    }
+}
+
+impl From<&DeliveryStats> for AnalyticsSummary {
+    fn from(stats: &DeliveryStats) -> Self {
+        AnalyticsSummary {
+            attempts: stats.attempts,
+            delivered: stats.delivered,
+            temporary_failures: stats.temporary_failures,
+            permanent_failures: stats.permanent_failures,
+            tls: stats.tls,
+            plaintext: stats.plaintext,
+            failures: stats
+                .failures
+                .iter()
+                .map(|(category, count)| (category.to_string(), *count))
+                .collect(),
+            latency: LatencyPercentiles {
+                p50: stats.latency_percentile(50),
+                p90: stats.latency_percentile(90),
+                p99: stats.latency_percentile(99),
+            },
+        }
+    }
+}
+
+impl Error {
+    pub fn category(&self) -> &'static str {
+        match self {
+            Error::DnsError(_) => "dns",
+            Error::UnexpectedResponse(response) if response.response.code >= 500 => {
+                "remote-permanent"
+            }
+            Error::UnexpectedResponse(_) => "remote-temporary",
+            Error::ConnectionError(_) => "connection",
+            Error::TlsError(_) => "tls",
+            Error::DaneError(_) => "dane",
+            Error::MtaStsError(_) => "mta-sts",
+            Error::RequireTls(_) => "requiretls",
+            Error::RateLimited => "rate-limited",
+            Error::ConcurrencyLimited => "concurrency-limited",
+            Error::Io(_) => "io",
+        }
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+fn latency_bucket(latency: u64) -> usize {
+    LATENCY_BOUNDS
+        .iter()
+        .position(|bound| latency <= *bound)
+        .unwrap_or(LATENCY_BOUNDS.len())
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
@@ -0,0 +1,383 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::core::{
+    management::{ImportedMessage, QueueExport, QueueImport, QueueRequest},
+    SMTP,
+};
+
+use super::{manager::Queue, Event, Message, QueueId, Schedule};
+
+const ARCHIVE_MAGIC: &[u8] = b"STALWART-QUEUE-ARCHIVE 1\n";
+const ARCHIVE_END: &[u8] = b"END\n";
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    pub id: QueueId,
+    pub size: usize,
+    pub metadata: Vec<u8>,
+    pub content: ArchiveContent,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ArchiveContent {
+    Bundled(Vec<u8>),
+    Reference(PathBuf),
+}
+
+#[derive(Debug)]
+pub struct ExportedMessage {
+    pub id: QueueId,
+    pub size: usize,
+    pub path: PathBuf,
+    pub metadata: Vec<u8>,
+}
+
+impl ArchiveEntry {
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        let (kind, payload) = match &self.content {
+            ArchiveContent::Bundled(bytes) => ('B', Cow::Borrowed(bytes.as_slice())),
+            ArchiveContent::Reference(path) => (
+                'R',
+                Cow::Owned(path.to_string_lossy().into_owned().into_bytes()),
+            ),
+        };
+        buf.extend_from_slice(
+            format!(
+                "{kind} {} {} {} {}\n",
+                self.id,
+                self.size,
+                self.metadata.len(),
+                payload.len()
+            )
+            .as_bytes(),
+        );
+        buf.extend_from_slice(&self.metadata);
+        buf.extend_from_slice(&payload);
+    }
+
+    pub async fn content(&self) -> Result<Vec<u8>, String> {
+        match &self.content {
+            ArchiveContent::Bundled(bytes) => Ok(bytes.clone()),
+            ArchiveContent::Reference(path) => {
+                let mut bytes = fs::read(path).await.map_err(|err| {
+                    format!("Failed to read queue file {}: {}", path.display(), err)
+                })?;
+                if bytes.len() < self.size {
+                    return Err(format!("Queue file {} is truncated", path.display()));
+                }
+                bytes.truncate(self.size);
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+pub fn write_archive(entries: &[ArchiveEntry]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(
+        ARCHIVE_MAGIC.len()
+            + entries
+                .iter()
+                .map(|entry| {
+                    entry.metadata.len()
+                        + match &entry.content {
+                            ArchiveContent::Bundled(bytes) => bytes.len(),
+                            ArchiveContent::Reference(_) => 256,
+                        }
+                        + 64
+                })
+                .sum::<usize>()
+            + ARCHIVE_END.len(),
+    );
+    buf.extend_from_slice(ARCHIVE_MAGIC);
+    for entry in entries {
+        entry.write(&mut buf);
+    }
+    buf.extend_from_slice(ARCHIVE_END);
+    buf
+}
+
+pub fn parse_archive(bytes: &[u8]) -> Result<Vec<ArchiveEntry>, String> {
+    let mut bytes = bytes
+        .strip_prefix(ARCHIVE_MAGIC)
+        .ok_or_else(|| "Invalid queue archive header.".to_string())?;
+    let mut entries = Vec::new();
+
+    loop {
+        if bytes == ARCHIVE_END {
+            return Ok(entries);
+        }
+
+        // Parse entry header
+        let eol = bytes
+            .iter()
+            .position(|&ch| ch == b'\n')
+            .ok_or_else(|| "Queue archive is truncated.".to_string())?;
+        let header = std::str::from_utf8(&bytes[..eol])
+            .map_err(|_| "Invalid queue archive entry header.".to_string())?;
+        bytes = &bytes[eol + 1..];
+        let mut parts = header.split(' ');
+        let kind = parts.next().unwrap_or_default();
+        let mut values = [0usize; 4];
+        for value in &mut values {
+            *value = parts
+                .next()
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| format!("Invalid queue archive entry header {header:?}."))?;
+        }
+        let [id, size, metadata_len, payload_len] = values;
+        if parts.next().is_some() || bytes.len() < metadata_len + payload_len {
+            return Err(format!("Invalid queue archive entry {id}."));
+        }
+
+        let metadata = bytes[..metadata_len].to_vec();
+        let payload = &bytes[metadata_len..metadata_len + payload_len];
+        bytes = &bytes[metadata_len + payload_len..];
+        let content = match kind {
+            "B" if payload_len == size => ArchiveContent::Bundled(payload.to_vec()),
+            "R" => ArchiveContent::Reference(PathBuf::from(
+                std::str::from_utf8(payload)
+                    .map_err(|_| format!("Invalid path for queue archive entry {id}."))?,
+            )),
+            _ => return Err(format!("Invalid queue archive entry {id}.")),
+        };
+
+        entries.push(ArchiveEntry {
+            id: id as QueueId,
+            size,
+            metadata,
+            content,
+        });
+    }
+}
+
+impl Queue {
+    pub fn export(&mut self, remove: bool) -> Vec<ExportedMessage> {
+        let mut result = self
+            .messages
+            .values()
+            .map(|message| ExportedMessage {
+                id: message.id,
+                size: message.size,
+                path: message.path.clone(),
+                metadata: message.serialize(),
+            })
+            .collect::<Vec<_>>();
+        result.sort_unstable_by_key(|message| message.id & 0xFFFFFFFF);
+
+        if remove {
+            // Evacuated messages are no longer delivered by this instance
+            for message in &result {
+                self.messages.remove(&message.id);
+                self.on_hold.retain(|oh| oh.message != message.id);
+            }
+        }
+
+        result
+    }
+}
+
+impl SMTP {
+    pub async fn export_queue(
+        &self,
+        path: &Path,
+        bundle: bool,
+        remove: bool,
+    ) -> Result<QueueExport, String> {
+        let (result_tx, result_rx) = tokio::sync::oneshot::channel();
+        self.queue
+            .tx
+            .send(Event::Manage(QueueRequest::Export { remove, result_tx }))
+            .await
+            .map_err(|_| "Failed to send manage request event.".to_string())?;
+        let messages = result_rx
+            .await
+            .map_err(|_| "Failed to receive manage request response.".to_string())?;
+
+        let result = self.write_queue_archive(path, &messages, bundle).await;
+        if remove {
+            match &result {
+                Ok(_) => {
+                    for message in &messages {
+                        if let Err(err) = fs::remove_file(&message.path).await {
+                            tracing::warn!(
+                                context = "queue",
+                                event = "error",
+                                "Failed to delete exported message {}: {}",
+                                message.path.display(),
+                                err
+                            );
+                        }
+                    }
+                }
+                Err(_) => {
+                    // Put the messages back in the queue
+                    for message in &messages {
+                        match Message::from_path(message.path.clone()).await {
+                            Ok(mut message) => {
+                                self.queue.has_quota(&mut message).await;
+                                if let Some(due) = message.next_event() {
+                                    let _ = self
+                                        .queue
+                                        .tx
+                                        .send(Event::Queue(Schedule {
+                                            due,
+                                            inner: Box::new(message),
+                                        }))
+                                        .await;
+                                }
+                            }
+                            Err(err) => {
+                                tracing::error!(
+                                    context = "queue",
+                                    event = "error",
+                                    "Failed to restore exported message: {}",
+                                    err
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        result.map(|size| QueueExport {
+            path: path.display().to_string(),
+            messages: messages.len(),
+            size,
+        })
+    }
+
+    async fn write_queue_archive(
+        &self,
+        path: &Path,
+        messages: &[ExportedMessage],
+        bundle: bool,
+    ) -> Result<usize, String> {
+        let mut entries = Vec::with_capacity(messages.len());
+        for message in messages {
+            entries.push(ArchiveEntry {
+                id: message.id,
+                size: message.size,
+                metadata: message.metadata.clone(),
+                content: if bundle {
+                    let mut bytes = fs::read(&message.path).await.map_err(|err| {
+                        format!(
+                            "Failed to read queue file {}: {}",
+                            message.path.display(),
+                            err
+                        )
+                    })?;
+                    bytes.truncate(message.size);
+                    ArchiveContent::Bundled(bytes)
+                } else {
+                    ArchiveContent::Reference(message.path.clone())
+                },
+            });
+        }
+        let archive = write_archive(&entries);
+
+        // Write to a temporary file first so a failed export never leaves a partial archive
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        let mut file = fs::File::create(&tmp_path)
+            .await
+            .map_err(|err| format!("Failed to create {}: {}", tmp_path.display(), err))?;
+        file.write_all(&archive)
+            .await
+            .map_err(|err| format!("Failed to write to {}: {}", tmp_path.display(), err))?;
+        file.sync_all()
+            .await
+            .map_err(|err| format!("Failed to flush {}: {}", tmp_path.display(), err))?;
+        fs::rename(&tmp_path, path)
+            .await
+            .map_err(|err| format!("Failed to rename {}: {}", tmp_path.display(), err))?;
+
+        Ok(archive.len())
+    }
+
+    pub async fn import_queue(&self, path: &Path) -> Result<QueueImport, String> {
+        let archive = fs::read(path)
+            .await
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        let span = tracing::info_span!("queue-import", path = path.display().to_string());
+        let mut result = QueueImport {
+            messages: Vec::new(),
+            skipped: Vec::new(),
+        };
+
+        for entry in parse_archive(&archive)? {
+            let message = Message::deserialize(&entry.metadata).filter(|message| {
+                // Skip messages without pending deliveries
+                message.next_event().is_some()
+            });
+            let (mut message, content) = match (message, entry.content().await) {
+                (Some(message), Ok(content)) => (message, content),
+                (None, _) => {
+                    result.skipped.push(entry.id);
+                    continue;
+                }
+                (_, Err(err)) => {
+                    tracing::warn!(
+                        parent: &span,
+                        context = "queue",
+                        event = "error",
+                        id = entry.id,
+                        "Failed to import message: {}",
+                        err
+                    );
+                    result.skipped.push(entry.id);
+                    continue;
+                }
+            };
+
+            // Assign a new id, the original one might already be in use
+            message.id = self.queue.queue_id();
+            message.size = content.len();
+            self.queue.has_quota(&mut message).await;
+            let id = message.id;
+            if self
+                .queue
+                .queue_message(Box::new(message), None, &content, &span)
+                .await
+            {
+                result.messages.push(ImportedMessage {
+                    previous_id: entry.id,
+                    id,
+                });
+            } else {
+                result.skipped.push(entry.id);
+            }
+        }
+
+        Ok(result)
+    }
+}
@@ -0,0 +1,156 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use smtp_proto::Response;
+
+use crate::core::management::DeferredSummary;
+
+use super::{manager::Queue, Error, Message, Status};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeferReason {
+    Dns,
+    Connection,
+    Tls,
+    Remote,
+    Quota,
+    Throttled,
+    Local,
+}
+
+impl DeferReason {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "dns" => Some(DeferReason::Dns),
+            "connection" => Some(DeferReason::Connection),
+            "tls" => Some(DeferReason::Tls),
+            "remote" => Some(DeferReason::Remote),
+            "quota" => Some(DeferReason::Quota),
+            "throttled" => Some(DeferReason::Throttled),
+            "local" => Some(DeferReason::Local),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeferReason::Dns => "dns",
+            DeferReason::Connection => "connection",
+            DeferReason::Tls => "tls",
+            DeferReason::Remote => "remote",
+            DeferReason::Quota => "quota",
+            DeferReason::Throttled => "throttled",
+            DeferReason::Local => "local",
+        }
+    }
+}
+
+impl From<&Error> for DeferReason {
+    fn from(err: &Error) -> Self {
+        match err {
+            Error::DnsError(_) => DeferReason::Dns,
+            Error::ConnectionError(_) => DeferReason::Connection,
+            Error::TlsError(_)
+            | Error::DaneError(_)
+            | Error::MtaStsError(_)
+            | Error::RequireTls(_) => DeferReason::Tls,
+            Error::UnexpectedResponse(response) => (&response.response).into(),
+            Error::RateLimited | Error::ConcurrencyLimited => DeferReason::Throttled,
+            Error::Io(_) => DeferReason::Local,
+        }
+    }
+}
+
+impl From<&Response<String>> for DeferReason {
+    fn from(response: &Response<String>) -> Self {
+        // 452 and the mailbox/system full enhanced codes denote a full mailbox
+        if response.code == 452 || matches!(response.esc, [4, 2, 2] | [4, 3, 1]) {
+            DeferReason::Quota
+        } else {
+            DeferReason::Remote
+        }
+    }
+}
+
+impl Message {
+    // Returns why delivery to a domain is being deferred, either because the domain
+    // itself failed temporarily or because the remote host deferred some recipients.
+    pub fn defer_reason(&self, domain_idx: usize) -> Option<DeferReason> {
+        match &self.domains.get(domain_idx)?.status {
+            Status::TemporaryFailure(err) => Some(err.into()),
+            Status::Scheduled => self
+                .recipients
+                .iter()
+                .filter(|rcpt| rcpt.domain_idx == domain_idx)
+                .find_map(|rcpt| match &rcpt.status {
+                    Status::TemporaryFailure(response) => Some((&response.response).into()),
+                    _ => None,
+                }),
+            _ => None,
+        }
+    }
+
+    pub fn has_defer_reason(&self, reason: DeferReason) -> bool {
+        (0..self.domains.len()).any(|idx| self.defer_reason(idx) == Some(reason))
+    }
+}
+
+impl Queue {
+    pub fn deferred_summary(&self, domain: Option<&str>) -> Vec<DeferredSummary> {
+        let mut summary: BTreeMap<DeferReason, DeferredSummary> = BTreeMap::new();
+
+        for message in self.messages.values() {
+            if message.parked.is_some() {
+                continue;
+            }
+
+            let mut message_reasons = Vec::new();
+            for (idx, message_domain) in message.domains.iter().enumerate() {
+                if domain.map_or(false, |domain| !message_domain.domain.contains(domain)) {
+                    continue;
+                }
+                if let Some(reason) = message.defer_reason(idx) {
+                    let entry = summary.entry(reason).or_insert_with(|| DeferredSummary {
+                        reason,
+                        messages: 0,
+                        domains: BTreeMap::new(),
+                    });
+                    if !message_reasons.contains(&reason) {
+                        message_reasons.push(reason);
+                        entry.messages += 1;
+                    }
+                    *entry
+                        .domains
+                        .entry(message_domain.domain.clone())
+                        .or_default() += 1;
+                }
+            }
+        }
+
+        summary.into_values().collect()
+    }
+}
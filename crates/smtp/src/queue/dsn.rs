@@ -27,26 +27,37 @@ use mail_builder::mime::{make_boundary, BodyPart, MimePart};
 use mail_builder::MessageBuilder;
 use mail_parser::DateTime;
 use smtp_proto::{
-    Response, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
+    Response, MAIL_REQUIRETLS, MAIL_SMTPUTF8, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE,
+    RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
 };
 use std::fmt::Write;
 use std::time::{Duration, Instant};
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
+use utils::events::{self, EventKind, LifecycleEvent};
 
 use crate::config::QueueConfig;
 use crate::core::QueueCore;
 
 use super::{
     instant_to_timestamp, DeliveryAttempt, Domain, Error, ErrorDetails, HostResponse, Message,
-    Recipient, SimpleEnvelope, Status, RCPT_DSN_SENT, RCPT_STATUS_CHANGED,
+    Recipient, SimpleEnvelope, Status, RCPT_DSN_SENT, RCPT_EVENT_SENT, RCPT_STATUS_CHANGED,
 };
 
 impl QueueCore {
     pub async fn send_dsn(&self, attempt: &mut DeliveryAttempt) {
+        attempt.export_final_events();
+
         if !attempt.message.return_path.is_empty() {
             if let Some(dsn) = attempt.build_dsn(&self.config).await {
                 let mut dsn_message = Message::new_boxed("", "", "");
+                if !attempt.message.return_path.is_ascii() {
+                    dsn_message.flags |= MAIL_SMTPUTF8;
+                }
+                if attempt.message.has_flag(MAIL_REQUIRETLS) {
+                    // Notifications about REQUIRETLS messages are subject to the same policy
+                    dsn_message.flags |= MAIL_REQUIRETLS;
+                }
                 dsn_message
                     .add_recipient_parts(
                         &attempt.message.return_path,
@@ -71,6 +82,35 @@ impl QueueCore {
 }
 
 impl DeliveryAttempt {
+    pub fn export_final_events(&mut self) {
+        if !events::is_enabled(EventKind::Delivered) && !events::is_enabled(EventKind::Bounced) {
+            return;
+        }
+
+        for rcpt in &mut self.message.recipients {
+            if rcpt.has_flag(RCPT_EVENT_SENT) {
+                continue;
+            }
+            let domain = &self.message.domains[rcpt.domain_idx];
+            let (kind, reason) = match (&rcpt.status, &domain.status) {
+                (Status::Completed(_), _) => (EventKind::Delivered, rcpt.status.to_string()),
+                (Status::PermanentFailure(_), _) => (EventKind::Bounced, rcpt.status.to_string()),
+                (Status::Scheduled, Status::PermanentFailure(_)) => {
+                    (EventKind::Bounced, domain.status.to_string())
+                }
+                _ => continue,
+            };
+            rcpt.flags |= RCPT_EVENT_SENT | RCPT_STATUS_CHANGED;
+            events::publish(
+                LifecycleEvent::new(kind)
+                    .with_queue_id(self.message.id)
+                    .with_sender(self.message.return_path.as_str())
+                    .with_recipient(rcpt.address.as_str())
+                    .with_reason(reason),
+            );
+        }
+    }
+
     pub async fn build_dsn(&mut self, config: &QueueConfig) -> Option<Vec<u8>> {
         let now = Instant::now();
 
@@ -316,6 +356,14 @@ impl DeliveryAttempt {
             }
         };
 
+        // Internationalized messages are reported using the
+        // global media types defined in RFC 6533
+        let (ct_status, ct_headers) = if self.message.has_flag(MAIL_SMTPUTF8) {
+            ("message/global-delivery-status", "message/global-headers")
+        } else {
+            ("message/delivery-status", "message/rfc822")
+        };
+
         // Build message
         MessageBuilder::new()
             .from((from_name.as_str(), from_addr.as_str()))
@@ -330,14 +378,8 @@ impl DeliveryAttempt {
                 ContentType::new("multipart/report").attribute("report-type", "delivery-status"),
                 BodyPart::Multipart(vec![
                     MimePart::new(ContentType::new("text/plain"), BodyPart::Text(txt.into())),
-                    MimePart::new(
-                        ContentType::new("message/delivery-status"),
-                        BodyPart::Text(dsn.into()),
-                    ),
-                    MimePart::new(
-                        ContentType::new("message/rfc822"),
-                        BodyPart::Text(headers.into()),
-                    ),
+                    MimePart::new(ContentType::new(ct_status), BodyPart::Text(dsn.into())),
+                    MimePart::new(ContentType::new(ct_headers), BodyPart::Text(headers.into())),
                 ]),
             ))
             .write_to_vec()
@@ -465,6 +507,13 @@ impl Error {
                     "<{addr}> (MTA-STS failed to authenticate '{domain}': {details})\r\n",
                 );
             }
+            Error::RequireTls(details) => {
+                let _ = write!(
+                    dsn,
+                    "<{}> (REQUIRETLS could not be satisfied by '{}': {})\r\n",
+                    addr, details.entity, details.details
+                );
+            }
             Error::RateLimited => {
                 let _ = write!(dsn, "<{addr}> (rate limited)\r\n");
             }
@@ -499,7 +548,12 @@ impl Recipient {
         if let Some(orcpt) = &self.orcpt {
             let _ = write!(dsn, "Original-Recipient: rfc822;{orcpt}\r\n");
         }
-        let _ = write!(dsn, "Final-Recipient: rfc822;{}\r\n", self.address);
+        let addr_type = if self.address.is_ascii() {
+            "rfc822"
+        } else {
+            "utf-8"
+        };
+        let _ = write!(dsn, "Final-Recipient: {addr_type};{}\r\n", self.address);
     }
 }
 
@@ -605,14 +659,22 @@ impl Status<(), Error> {
     fn write_dsn_status(&self, dsn: &mut String) {
         if let Status::PermanentFailure(err) | Status::TemporaryFailure(err) = self {
             dsn.push_str("Status: ");
-            if let Error::UnexpectedResponse(response) = err {
-                response.response.write_dsn_status(dsn);
-            } else {
-                dsn.push_str(if matches!(self, Status::PermanentFailure(_)) {
-                    "5.0.0"
-                } else {
-                    "4.0.0"
-                });
+            let is_permanent = matches!(self, Status::PermanentFailure(_));
+            match err {
+                Error::UnexpectedResponse(response) => {
+                    response.response.write_dsn_status(dsn);
+                }
+                Error::RequireTls(_) => {
+                    // REQUIRETLS support required (RFC 8689)
+                    dsn.push_str(if is_permanent { "5.7.30" } else { "4.7.30" });
+                }
+                Error::TlsError(_) | Error::DaneError(_) | Error::MtaStsError(_) => {
+                    // Encryption needed (RFC 5248)
+                    dsn.push_str(if is_permanent { "5.7.10" } else { "4.7.10" });
+                }
+                _ => {
+                    dsn.push_str(if is_permanent { "5.0.0" } else { "4.0.0" });
+                }
             }
             dsn.push_str("\r\n");
         }
@@ -626,7 +688,8 @@ impl Status<(), Error> {
                 })
                 | Error::ConnectionError(details)
                 | Error::TlsError(details)
-                | Error::DaneError(details) => {
+                | Error::DaneError(details)
+                | Error::RequireTls(details) => {
                     dsn.push_str("Remote-MTA: dns;");
                     dsn.push_str(&details.entity);
                     dsn.push_str("\r\n");
@@ -47,6 +47,7 @@ pub struct Queue {
     long_wait: Duration,
     pub scheduled: BinaryHeap<Schedule<QueueId>>,
     pub on_hold: Vec<OnHold<QueueId>>,
+    pub parked: BinaryHeap<Schedule<QueueId>>,
     pub messages: AHashMap<QueueId, Box<Message>>,
 }
 
@@ -63,6 +64,18 @@ impl SpawnQueue for mpsc::Receiver<Event> {
                         .await;
                 }
 
+                // Reject parked messages that were not moderated in time
+                while let Some(message) = queue.next_expired_parked() {
+                    tracing::info!(
+                        context = "moderation",
+                        event = "expired",
+                        account = message.parked.as_deref().unwrap_or_default(),
+                        id = message.id,
+                        "Parked message expired without moderation."
+                    );
+                    message.remove().await;
+                }
+
                 match result {
                     Ok(Some(event)) => match event {
                         Event::Queue(item) => {
@@ -73,7 +86,9 @@ impl SpawnQueue for mpsc::Receiver<Event> {
                                     .await;
                             }
 
-                            if item.due <= Instant::now() {
+                            if item.inner.parked.is_some() {
+                                queue.park(item.inner, core.queue.config.moderation.expire);
+                            } else if item.due <= Instant::now() {
                                 DeliveryAttempt::from(item.inner)
                                     .try_deliver(core.clone(), &mut queue)
                                     .await;
@@ -104,6 +119,7 @@ impl SpawnQueue for mpsc::Receiver<Event> {
                                 to,
                                 before,
                                 after,
+                                reason,
                                 result_tx,
                             } => {
                                 let mut result = Vec::with_capacity(queue.messages.len());
@@ -140,6 +156,11 @@ impl SpawnQueue for mpsc::Receiver<Event> {
                                     {
                                         continue;
                                     }
+                                    if reason
+                                        .map_or(false, |reason| !message.has_defer_reason(reason))
+                                    {
+                                        continue;
+                                    }
 
                                     result.push(message.id);
                                 }
@@ -246,20 +267,33 @@ impl SpawnQueue for mpsc::Receiver<Event> {
                             management::QueueRequest::Retry {
                                 queue_ids,
                                 item,
+                                reason,
                                 time,
                                 result_tx,
                             } => {
                                 let mut result = Vec::with_capacity(queue_ids.len());
                                 for queue_id in &queue_ids {
                                     let mut found = false;
-                                    if let Some(message) = queue.messages.get_mut(queue_id) {
-                                        for domain in &mut message.domains {
+                                    if let Some(message) = queue
+                                        .messages
+                                        .get_mut(queue_id)
+                                        .filter(|message| message.parked.is_none())
+                                    {
+                                        let reasons = (0..message.domains.len())
+                                            .map(|idx| message.defer_reason(idx))
+                                            .collect::<Vec<_>>();
+                                        for (domain, domain_reason) in
+                                            message.domains.iter_mut().zip(reasons)
+                                        {
                                             if matches!(
                                                 domain.status,
                                                 Status::Scheduled | Status::TemporaryFailure(_)
                                             ) && item
                                                 .as_ref()
                                                 .map_or(true, |item| domain.domain.contains(item))
+                                                && reason.map_or(true, |reason| {
+                                                    domain_reason == Some(reason)
+                                                })
                                             {
                                                 domain.retry.due = time;
                                                 if domain.expires > time {
@@ -285,6 +319,51 @@ impl SpawnQueue for mpsc::Receiver<Event> {
                                 }
                                 let _ = result_tx.send(result);
                             }
+                            management::QueueRequest::Deferred { domain, result_tx } => {
+                                let _ = result_tx.send(queue.deferred_summary(domain.as_deref()));
+                            }
+                            management::QueueRequest::Parked { account, result_tx } => {
+                                let _ = result_tx.send(queue.parked_messages(
+                                    account.as_deref(),
+                                    core.queue.config.moderation.expire,
+                                ));
+                            }
+                            management::QueueRequest::Moderate {
+                                queue_ids,
+                                account,
+                                approve,
+                                result_tx,
+                            } => {
+                                let _ = result_tx
+                                    .send(queue.moderate(queue_ids, account, approve).await);
+                            }
+                            management::QueueRequest::Scheduled { senders, result_tx } => {
+                                let _ =
+                                    result_tx.send(queue.scheduled_messages(senders.as_deref()));
+                            }
+                            management::QueueRequest::Reschedule {
+                                queue_ids,
+                                senders,
+                                release,
+                                result_tx,
+                            } => {
+                                let _ = result_tx
+                                    .send(queue.reschedule(queue_ids, senders, release).await);
+                            }
+                            management::QueueRequest::Source {
+                                queue_id,
+                                result_tx,
+                            } => {
+                                let _ = result_tx.send(
+                                    queue
+                                        .messages
+                                        .get(&queue_id)
+                                        .map(|message| (message.path.clone(), message.size)),
+                                );
+                            }
+                            management::QueueRequest::Export { remove, result_tx } => {
+                                let _ = result_tx.send(queue.export(remove));
+                            }
                         },
                         Event::Stop => break,
                     },
@@ -339,13 +418,15 @@ impl Queue {
     }
 
     pub fn wake_up_time(&self) -> Duration {
-        self.scheduled
-            .peek()
+        [self.scheduled.peek(), self.parked.peek()]
+            .into_iter()
+            .flatten()
             .map(|item| {
                 item.due
                     .checked_duration_since(Instant::now())
                     .unwrap_or(self.short_wait)
             })
+            .min()
             .unwrap_or(self.long_wait)
     }
 }
@@ -512,6 +593,12 @@ impl QueueCore {
                     // Reserve quota
                     self.has_quota(&mut message).await;
 
+                    // Parked messages are held until moderated
+                    if message.parked.is_some() {
+                        queue.park(Box::new(message), self.config.moderation.expire);
+                        continue;
+                    }
+
                     // Schedule message
                     queue.schedule(Schedule {
                         due: message.next_event().unwrap_or_else(|| {
@@ -551,6 +638,7 @@ impl Default for Queue {
             long_wait: Duration::from_secs(86400 * 365),
             scheduled: BinaryHeap::with_capacity(128),
             on_hold: Vec::with_capacity(128),
+            parked: BinaryHeap::new(),
             messages: AHashMap::with_capacity(128),
         }
     }
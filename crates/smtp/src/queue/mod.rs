@@ -38,12 +38,20 @@ use utils::{
 
 use crate::{config::EnvelopeKey, core::management};
 
+pub mod analytics;
+pub mod archive;
+pub mod deferred;
 pub mod dsn;
 pub mod manager;
+pub mod moderation;
 pub mod quota;
+pub mod release;
 pub mod serialize;
+#[cfg(feature = "simulation")]
+pub mod simulation;
 pub mod spool;
 pub mod throttle;
+pub mod warmup;
 
 pub type QueueId = u64;
 
@@ -90,6 +98,7 @@ pub struct Message {
     pub flags: u64,
     pub env_id: Option<String>,
     pub priority: i16,
+    pub parked: Option<String>,
 
     pub size: usize,
     pub queue_refs: Vec<UsedQuota>,
@@ -102,6 +111,7 @@ pub struct Domain {
     pub notify: Schedule<u32>,
     pub expires: Instant,
     pub status: Status<(), Error>,
+    pub retry_strategy: Option<String>,
     pub disable_tls: bool,
     pub changed: bool,
 }
@@ -118,8 +128,18 @@ pub struct Recipient {
 
 pub const RCPT_DSN_SENT: u64 = 1 << 32;
 pub const RCPT_STATUS_CHANGED: u64 = 2 << 32;
+pub const RCPT_EVENT_SENT: u64 = 4 << 32;
 
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+// Set on messages carrying a "TLS-Required: No" header (RFC 8689)
+pub const MAIL_TLS_OPTIONAL: u64 = 1 << 32;
+
+// Set on messages held for future release (RFC 4865)
+pub const MAIL_FUTURE_RELEASE: u64 = 2 << 32;
+
+// Set on messages accepted in transparent relay mode
+pub const MAIL_TRANSPARENT: u64 = 4 << 32;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Status<T, E> {
     #[serde(rename = "scheduled")]
     Scheduled,
@@ -131,13 +151,13 @@ pub enum Status<T, E> {
     PermanentFailure(E),
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct HostResponse<T> {
     pub hostname: T,
     pub response: Response<String>,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Error {
     DnsError(String),
     UnexpectedResponse(HostResponse<ErrorDetails>),
@@ -145,12 +165,13 @@ pub enum Error {
     TlsError(ErrorDetails),
     DaneError(ErrorDetails),
     MtaStsError(String),
+    RequireTls(ErrorDetails),
     RateLimited,
     ConcurrencyLimited,
     Io(String),
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ErrorDetails {
     pub entity: String,
     pub details: String,
@@ -444,6 +465,13 @@ impl Display for Error {
             Error::MtaStsError(details) => {
                 write!(f, "MTA-STS auth failed: {details}")
             }
+            Error::RequireTls(details) => {
+                write!(
+                    f,
+                    "REQUIRETLS could not be satisfied by '{}': {}",
+                    details.entity, details.details
+                )
+            }
             Error::RateLimited => {
                 write!(f, "Rate limited")
             }
@@ -0,0 +1,343 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::time::{Duration, Instant};
+
+use ahash::AHashMap;
+use mail_builder::{headers::HeaderType, mime::make_boundary, MessageBuilder};
+use mail_parser::DateTime;
+use parking_lot::RwLock;
+use utils::config::ConfigKey;
+
+use crate::{
+    config::QueueModeration,
+    core::{
+        management::{ModeratedAccount, ParkedMessage},
+        QueueCore, SMTP,
+    },
+};
+
+use super::{manager::Queue, InstantFromTimestamp, Message, QueueId, Schedule};
+
+pub const MODERATION_ACCOUNTS_KEY: &str = "queue.moderation.accounts";
+
+#[derive(Default)]
+pub struct Moderation {
+    accounts: RwLock<AHashMap<String, AccountModeration>>,
+}
+
+struct AccountModeration {
+    reason: String,
+    last_notified: Option<Instant>,
+}
+
+impl Moderation {
+    pub fn new(config: &QueueModeration) -> Self {
+        Moderation {
+            accounts: RwLock::new(
+                config
+                    .accounts
+                    .iter()
+                    .map(|(account, reason)| {
+                        (
+                            account.clone(),
+                            AccountModeration {
+                                reason: reason.clone(),
+                                last_notified: None,
+                            },
+                        )
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl QueueCore {
+    pub fn is_moderated(&self, account: &str) -> bool {
+        !account.is_empty() && self.moderation.accounts.read().contains_key(account)
+    }
+
+    pub fn moderated_accounts(&self) -> Vec<ModeratedAccount> {
+        let mut accounts = self
+            .moderation
+            .accounts
+            .read()
+            .iter()
+            .map(|(account, moderation)| ModeratedAccount {
+                account: account.clone(),
+                reason: moderation.reason.clone(),
+            })
+            .collect::<Vec<_>>();
+        accounts.sort_unstable_by(|a, b| a.account.cmp(&b.account));
+        accounts
+    }
+
+    pub async fn moderate_account(&self, account: &str, reason: String) -> store::Result<()> {
+        let account = account.to_lowercase();
+        self.config
+            .data_store
+            .config_set(
+                [ConfigKey {
+                    key: format!("{MODERATION_ACCOUNTS_KEY}.{account}"),
+                    value: reason.clone(),
+                }]
+                .into_iter(),
+            )
+            .await?;
+        self.moderation.accounts.write().insert(
+            account,
+            AccountModeration {
+                reason,
+                last_notified: None,
+            },
+        );
+        Ok(())
+    }
+
+    pub async fn unmoderate_account(&self, account: &str) -> store::Result<bool> {
+        let account = account.to_lowercase();
+        self.config
+            .data_store
+            .config_clear(format!("{MODERATION_ACCOUNTS_KEY}.{account}"))
+            .await?;
+        Ok(self.moderation.accounts.write().remove(&account).is_some())
+    }
+
+    fn should_notify_moderators(&self, account: &str) -> bool {
+        if self.config.moderation.notify.is_empty() {
+            return false;
+        }
+        let now = Instant::now();
+        let mut accounts = self.moderation.accounts.write();
+        match accounts.get_mut(account) {
+            Some(moderation)
+                if moderation.last_notified.map_or(true, |last| {
+                    now.duration_since(last) >= self.config.moderation.notify_interval
+                }) =>
+            {
+                moderation.last_notified = now.into();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl SMTP {
+    pub async fn notify_moderators(
+        &self,
+        queue_id: QueueId,
+        account: &str,
+        return_path: &str,
+        recipients: &[String],
+        span: &tracing::Span,
+    ) {
+        if !self.queue.should_notify_moderators(account) {
+            return;
+        }
+
+        let envelope = Message::new_boxed(return_path, return_path.to_lowercase(), "");
+        let from_name = self.queue.config.dsn.name.eval(envelope.as_ref()).await;
+        let from_addr = self.queue.config.dsn.address.eval(envelope.as_ref()).await;
+        let hostname = self.queue.config.hostname.eval(envelope.as_ref()).await;
+        let expires = DateTime::from_timestamp(
+            (envelope.created + self.queue.config.moderation.expire.as_secs()) as i64,
+        );
+
+        let body = format!(
+            concat!(
+                "Outbound mail submitted by account {} is being held for moderation.\r\n\r\n",
+                "Queue ID: {}\r\n",
+                "Return path: <{}>\r\n",
+                "Recipients: {}\r\n",
+                "Expires: {}\r\n\r\n",
+                "Further messages from this account will be held without notification ",
+                "for the next {} seconds. Use the management API to approve or reject ",
+                "the held messages.\r\n"
+            ),
+            account,
+            queue_id,
+            return_path,
+            recipients.join(", "),
+            expires.to_rfc3339(),
+            self.queue.config.moderation.notify_interval.as_secs(),
+        );
+        let notification = MessageBuilder::new()
+            .from((from_name.as_str(), from_addr.as_str()))
+            .header(
+                "To",
+                HeaderType::Text(self.queue.config.moderation.notify.join(", ").into()),
+            )
+            .header("Auto-Submitted", HeaderType::Text("auto-generated".into()))
+            .message_id(format!("<{}@{}>", make_boundary("."), hostname))
+            .subject(format!("Message from {account} held for moderation"))
+            .text_body(body)
+            .write_to_vec()
+            .unwrap_or_default();
+
+        tracing::info!(
+            parent: span,
+            context = "moderation",
+            event = "notify",
+            account = account,
+            id = queue_id,
+            "Notifying moderators of parked message."
+        );
+
+        self.send_report(
+            from_addr,
+            self.queue.config.moderation.notify.iter(),
+            notification,
+            &self.queue.config.dsn.sign,
+            span,
+            true,
+        )
+        .await;
+    }
+}
+
+impl Queue {
+    pub fn park(&mut self, message: Box<Message>, expire: Duration) {
+        self.parked.push(Schedule {
+            due: (message.created + expire.as_secs()).to_instant(),
+            inner: message.id,
+        });
+        self.messages.insert(message.id, message);
+    }
+
+    pub fn next_expired_parked(&mut self) -> Option<Box<Message>> {
+        while let Some(item) = self.parked.peek() {
+            if item.due > Instant::now() {
+                break;
+            }
+            let queue_id = self.parked.pop().unwrap().inner;
+            if self
+                .messages
+                .get(&queue_id)
+                .map_or(false, |message| message.parked.is_some())
+            {
+                return self.messages.remove(&queue_id);
+            }
+        }
+
+        None
+    }
+
+    pub fn parked_messages(&self, account: Option<&str>, expire: Duration) -> Vec<ParkedMessage> {
+        let mut result = self
+            .messages
+            .values()
+            .filter_map(|message| {
+                let parked_by = message.parked.as_deref()?;
+                if account.map_or(true, |account| account == parked_by) {
+                    ParkedMessage {
+                        id: message.id,
+                        account: parked_by.to_string(),
+                        return_path: message.return_path.clone(),
+                        recipients: message
+                            .recipients
+                            .iter()
+                            .map(|rcpt| rcpt.address.clone())
+                            .collect(),
+                        size: message.size,
+                        created: DateTime::from_timestamp(message.created as i64),
+                        expires: DateTime::from_timestamp(
+                            (message.created + expire.as_secs()) as i64,
+                        ),
+                    }
+                    .into()
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        result.sort_unstable_by_key(|message| message.id & 0xFFFFFFFF);
+        result
+    }
+
+    pub async fn moderate(
+        &mut self,
+        queue_ids: Vec<QueueId>,
+        account: Option<String>,
+        approve: bool,
+    ) -> Vec<QueueId> {
+        let queue_ids = if let Some(account) = account {
+            self.messages
+                .values()
+                .filter(|message| message.parked.as_ref() == Some(&account))
+                .map(|message| message.id)
+                .collect()
+        } else {
+            queue_ids
+        };
+        let mut result = Vec::with_capacity(queue_ids.len());
+
+        for queue_id in queue_ids {
+            if !self
+                .messages
+                .get(&queue_id)
+                .map_or(false, |message| message.parked.is_some())
+            {
+                continue;
+            }
+            let mut message = self.messages.remove(&queue_id).unwrap();
+            let account = message.parked.take().unwrap_or_default();
+
+            if approve {
+                message.release().await;
+                tracing::info!(
+                    context = "moderation",
+                    event = "approve",
+                    account = account,
+                    id = queue_id,
+                    "Parked message approved for delivery."
+                );
+                self.schedule(Schedule {
+                    due: message.next_event().unwrap_or_else(Instant::now),
+                    inner: message,
+                });
+            } else {
+                tracing::info!(
+                    context = "moderation",
+                    event = "reject",
+                    account = account,
+                    id = queue_id,
+                    "Parked message rejected."
+                );
+                message.remove().await;
+            }
+            result.push(queue_id);
+        }
+
+        result
+    }
+}
+
+impl Message {
+    pub async fn release(&mut self) {
+        self.parked = None;
+        let mut buf = String::with_capacity(4);
+        self.serialize_parked(&mut buf);
+        self.append_changes(buf.as_bytes()).await;
+    }
+}
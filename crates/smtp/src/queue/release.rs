@@ -0,0 +1,155 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Instant;
+
+use mail_parser::DateTime;
+
+use crate::core::management::ScheduledMessage;
+
+use super::{
+    instant_to_timestamp, manager::Queue, Message, QueueId, Schedule, Status, MAIL_FUTURE_RELEASE,
+};
+
+impl Queue {
+    pub fn scheduled_messages(&self, senders: Option<&[String]>) -> Vec<ScheduledMessage> {
+        let now = Instant::now();
+        let mut result = self
+            .messages
+            .values()
+            .filter(|message| message.is_sent_by(senders))
+            .filter_map(|message| {
+                let release = message.future_release(now)?;
+                ScheduledMessage {
+                    id: message.id,
+                    return_path: message.return_path.clone(),
+                    recipients: message
+                        .recipients
+                        .iter()
+                        .map(|rcpt| rcpt.address.clone())
+                        .collect(),
+                    size: message.size,
+                    created: DateTime::from_timestamp(message.created as i64),
+                    release: DateTime::from_timestamp(instant_to_timestamp(now, release) as i64),
+                }
+                .into()
+            })
+            .collect::<Vec<_>>();
+        result.sort_unstable_by_key(|message| message.id & 0xFFFFFFFF);
+        result
+    }
+
+    // Moves the release time of messages held for future release, or removes
+    // them from the queue when no release time is provided.
+    pub async fn reschedule(
+        &mut self,
+        queue_ids: Vec<QueueId>,
+        senders: Option<Vec<String>>,
+        release: Option<Instant>,
+    ) -> Vec<bool> {
+        let now = Instant::now();
+        let mut result = Vec::with_capacity(queue_ids.len());
+
+        for queue_id in queue_ids {
+            let is_scheduled = self.messages.get(&queue_id).map_or(false, |message| {
+                message.is_sent_by(senders.as_deref())
+                    && message.future_release(now).is_some()
+                    && release.map_or(true, |release| {
+                        message.domains.iter().all(|domain| {
+                            !matches!(domain.status, Status::Scheduled) || domain.expires > release
+                        })
+                    })
+            });
+            if !is_scheduled {
+                result.push(false);
+                continue;
+            }
+
+            if let Some(release) = release {
+                let message = self.messages.get_mut(&queue_id).unwrap();
+                for domain in &mut message.domains {
+                    if matches!(domain.status, Status::Scheduled) {
+                        if release > domain.retry.due {
+                            domain.notify.due += release - domain.retry.due;
+                        }
+                        domain.retry.due = release;
+                        domain.changed = true;
+                    }
+                }
+                message.save_changes().await;
+                self.on_hold.retain(|oh| oh.message != queue_id);
+                self.scheduled.push(Schedule {
+                    due: release,
+                    inner: queue_id,
+                });
+                tracing::info!(
+                    context = "queue",
+                    event = "reschedule",
+                    id = queue_id,
+                    release = instant_to_timestamp(now, release),
+                    "Message rescheduled for future release."
+                );
+            } else {
+                let message = self.messages.remove(&queue_id).unwrap();
+                self.on_hold.retain(|oh| oh.message != queue_id);
+                message.remove().await;
+                tracing::info!(
+                    context = "queue",
+                    event = "cancel",
+                    id = queue_id,
+                    "Message scheduled for future release canceled."
+                );
+            }
+            result.push(true);
+        }
+
+        result
+    }
+}
+
+impl Message {
+    // Returns the release time of messages that are still being held
+    pub fn future_release(&self, now: Instant) -> Option<Instant> {
+        if (self.flags & MAIL_FUTURE_RELEASE) != 0 {
+            self.domains
+                .iter()
+                .filter(|domain| {
+                    matches!(domain.status, Status::Scheduled)
+                        && domain.retry.inner == 0
+                        && domain.retry.due > now
+                })
+                .map(|domain| domain.retry.due)
+                .min()
+        } else {
+            None
+        }
+    }
+
+    fn is_sent_by(&self, senders: Option<&[String]>) -> bool {
+        senders.map_or(true, |senders| {
+            senders
+                .iter()
+                .any(|sender| sender == &self.return_path_lcase)
+        })
+    }
+}
@@ -84,9 +84,19 @@ impl Message {
             rcpt.serialize(idx, &mut buf);
         }
 
+        // Serialize moderation status
+        if self.parked.is_some() {
+            self.serialize_parked(&mut buf);
+        }
+
         buf.into_bytes()
     }
 
+    pub fn serialize_parked(&self, buf: &mut String) {
+        buf.push_str("P0 ");
+        (self.parked.as_deref().unwrap_or_default()).serialize(buf);
+    }
+
     pub fn serialize_changes(&mut self) -> Vec<u8> {
         let now = Instant::now();
         let mut buf = String::with_capacity(128);
@@ -195,6 +205,7 @@ impl Message {
             },
             flags: usize::deserialize(&mut bytes)? as u64,
             priority: i16::deserialize(&mut bytes)?,
+            parked: None,
             size: 0,
             recipients: vec![],
             domains: vec![],
@@ -211,6 +222,7 @@ impl Message {
                 retry: Schedule::now(),
                 notify: Schedule::now(),
                 status: Status::Scheduled,
+                retry_strategy: None,
                 disable_tls: false,
                 changed: false,
             });
@@ -253,6 +265,28 @@ impl Message {
                         domain.retry = retry;
                         domain.notify = notify;
                         domain.status = status;
+                        domain.retry_strategy = None;
+                    } else {
+                        break;
+                    }
+                }
+                b'Y' => {
+                    if let (Some(domain), Some(retry_strategy)) = (
+                        message.domains.get_mut(idx),
+                        String::deserialize(&mut bytes),
+                    ) {
+                        domain.retry_strategy = Some(retry_strategy);
+                    } else {
+                        break;
+                    }
+                }
+                b'P' => {
+                    if let Some(account) = String::deserialize(&mut bytes) {
+                        message.parked = if !account.is_empty() {
+                            account.into()
+                        } else {
+                            None
+                        };
                     } else {
                         break;
                     }
@@ -515,6 +549,10 @@ impl QueueSerializer for Error {
                 buf.push('8');
                 e.serialize(buf);
             }
+            Error::RequireTls(e) => {
+                buf.push('9');
+                e.serialize(buf);
+            }
         }
     }
 
@@ -529,6 +567,7 @@ impl QueueSerializer for Error {
             b'6' => Error::RateLimited.into(),
             b'7' => Error::ConcurrencyLimited.into(),
             b'8' => Error::Io(String::deserialize(bytes)?).into(),
+            b'9' => Error::RequireTls(ErrorDetails::deserialize(bytes)?).into(),
             _ => None,
         }
     }
@@ -554,6 +593,10 @@ impl Domain {
             instant_to_timestamp(now, self.notify.due)
         );
         self.status.serialize(buf);
+        if let Some(retry_strategy) = &self.retry_strategy {
+            let _ = write!(buf, "Y{} ", idx);
+            retry_strategy.serialize(buf);
+        }
     }
 }
 
@@ -0,0 +1,594 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
+    net::{IpAddr, Ipv4Addr},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use ahash::AHashMap;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use utils::config::Config;
+
+use crate::{
+    config::{queue::ConfigQueue, ConfigContext, QueueConfig, RetrySchedule, Throttle},
+    core::{management::LatencyPercentiles, throttle::ThrottleKey, SMTP},
+};
+
+use super::{Domain, Message, QueueEnvelope, Schedule, Status};
+
+// Upper bound of events processed by a single simulation run
+const MAX_EVENTS: usize = 10_000_000;
+
+/// Workload replayed against the queue scheduler, either generated from
+/// per-domain arrival rates or recorded from a production queue.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Workload {
+    /// Period in seconds during which synthetic messages are generated.
+    pub duration: u64,
+    /// Interval in seconds between backlog samples.
+    pub sample_interval: u64,
+    pub seed: u64,
+    pub sender: Option<String>,
+    pub domains: Vec<DomainProfile>,
+    pub messages: Vec<RecordedMessage>,
+}
+
+/// Behaviour of a destination domain during the simulation.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DomainProfile {
+    pub domain: String,
+    pub messages_per_hour: f64,
+    pub temp_failure_rate: f64,
+    pub perm_failure_rate: f64,
+    /// Seconds taken by each delivery attempt.
+    pub delivery_time: u64,
+    /// Concurrent deliveries accepted by the remote host before deferring.
+    pub max_concurrency: Option<u64>,
+    pub outages: Vec<Outage>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Outage {
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    pub at: u64,
+    pub domain: String,
+    #[serde(default)]
+    pub sender: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SimulationReport {
+    pub messages: u64,
+    pub delivered: u64,
+    pub failed: u64,
+    pub expired: u64,
+    pub attempts: u64,
+    pub throttled: u64,
+    /// Time in seconds at which the last event was processed.
+    pub elapsed: u64,
+    pub backlog: Vec<BacklogSample>,
+    pub domains: Vec<DomainReport>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BacklogSample {
+    pub time: u64,
+    pub queued: u64,
+    pub in_flight: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DomainReport {
+    pub domain: String,
+    pub messages: u64,
+    pub delivered: u64,
+    pub failed: u64,
+    pub expired: u64,
+    pub attempts: u64,
+    pub latency: LatencyPercentiles,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Event {
+    Attempt(usize),
+    Done(usize),
+    Sample,
+}
+
+struct SimMessage {
+    domain: usize,
+    sender: String,
+    created: u64,
+    expires: u64,
+    attempts: u32,
+    outcome: Outcome,
+    slots: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Pending,
+    Delivered,
+    TempFailure,
+    PermFailure,
+}
+
+#[derive(Default)]
+struct SimLimiter {
+    concurrent: u64,
+    max_concurrent: Option<u64>,
+    window_start: u64,
+    window_count: u64,
+    waiting: VecDeque<usize>,
+}
+
+#[derive(Default)]
+struct DomainState {
+    report: DomainReport,
+    in_flight: u64,
+    latencies: Vec<u64>,
+}
+
+struct Simulation<'x> {
+    config: &'x QueueConfig,
+    workload: &'x Workload,
+    rng: StdRng,
+    now: u64,
+    seq: u64,
+    events: BinaryHeap<Reverse<(u64, u64, Event)>>,
+    messages: Vec<SimMessage>,
+    domains: Vec<DomainState>,
+    limiters: Vec<SimLimiter>,
+    limiter_ids: AHashMap<(usize, ThrottleKey), usize>,
+    report: SimulationReport,
+    queued: u64,
+    in_flight: u64,
+}
+
+impl QueueConfig {
+    /// Replays a workload against this configuration's retry schedules, expiration
+    /// times and outbound throttles in simulated time, without delivering any messages.
+    pub async fn simulate(&self, workload: &Workload) -> SimulationReport {
+        let mut sim = Simulation {
+            config: self,
+            workload,
+            rng: StdRng::seed_from_u64(workload.seed),
+            now: 0,
+            seq: 0,
+            events: BinaryHeap::new(),
+            messages: Vec::new(),
+            domains: Vec::with_capacity(workload.domains.len()),
+            limiters: Vec::new(),
+            limiter_ids: AHashMap::new(),
+            report: SimulationReport::default(),
+            queued: 0,
+            in_flight: 0,
+        };
+        sim.run().await;
+        sim.report
+    }
+}
+
+impl SMTP {
+    /// Dry-runs a workload against the running configuration, optionally
+    /// replacing some of its settings, without touching the live queue.
+    pub async fn simulate_queue(
+        &self,
+        workload: &Workload,
+        overrides: Option<&str>,
+    ) -> Result<SimulationReport, String> {
+        let config = if let Some(overrides) = overrides {
+            let mut settings = self.eval.settings.clone();
+            settings.keys.extend(
+                Config::new(overrides)
+                    .map_err(|err| format!("Failed to parse configuration: {err}"))?
+                    .keys,
+            );
+
+            let mut ctx = ConfigContext::new(&[]);
+            ctx.listeners = self.eval.listeners.clone();
+            ctx.directory = self.eval.directory.clone();
+            ctx.stores = self.eval.stores.clone();
+            Some(settings.parse_queue(&ctx)?)
+        } else {
+            None
+        };
+
+        Ok(config
+            .as_ref()
+            .unwrap_or(&self.queue.config)
+            .simulate(workload)
+            .await)
+    }
+}
+
+impl<'x> Simulation<'x> {
+    async fn run(&mut self) {
+        let workload = self.workload;
+        let mut domain_ids = AHashMap::new();
+        for (domain_id, profile) in workload.domains.iter().enumerate() {
+            domain_ids.insert(profile.domain.to_lowercase(), domain_id);
+            self.domains.push(DomainState {
+                report: DomainReport {
+                    domain: profile.domain.to_lowercase(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+        }
+
+        // Generate synthetic arrivals with exponentially distributed gaps
+        let default_sender = workload.sender.as_deref().unwrap_or("sender@localhost");
+        let mut arrivals = Vec::new();
+        for (domain_id, profile) in workload.domains.iter().enumerate() {
+            if profile.messages_per_hour > 0.0 {
+                let mean_gap = 3600.0 / profile.messages_per_hour;
+                let mut time = 0.0;
+                loop {
+                    time += -mean_gap * (1.0 - self.rng.gen::<f64>()).ln();
+                    if time >= workload.duration as f64 {
+                        break;
+                    }
+                    arrivals.push((time as u64, domain_id, default_sender.to_string()));
+                }
+            }
+        }
+
+        // Add recorded messages, unknown domains use a default profile
+        for message in &workload.messages {
+            let domain = message.domain.to_lowercase();
+            let domain_id = match domain_ids.get(&domain) {
+                Some(domain_id) => *domain_id,
+                None => {
+                    let domain_id = self.domains.len();
+                    domain_ids.insert(domain.clone(), domain_id);
+                    self.domains.push(DomainState {
+                        report: DomainReport {
+                            domain,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    });
+                    domain_id
+                }
+            };
+            arrivals.push((
+                message.at,
+                domain_id,
+                message
+                    .sender
+                    .clone()
+                    .unwrap_or_else(|| default_sender.to_string()),
+            ));
+        }
+        arrivals.sort_unstable_by_key(|(time, _, _)| *time);
+
+        for (created, domain, sender) in arrivals {
+            let id = self.messages.len();
+            self.messages.push(SimMessage {
+                domain,
+                sender,
+                created,
+                expires: u64::MAX,
+                attempts: 0,
+                outcome: Outcome::Pending,
+                slots: Vec::new(),
+            });
+            self.domains[domain].report.messages += 1;
+            self.schedule(created, Event::Attempt(id));
+        }
+        self.report.messages = self.messages.len() as u64;
+        if workload.sample_interval > 0 {
+            self.schedule(0, Event::Sample);
+        }
+
+        let mut processed = 0;
+        while let Some(Reverse((time, _, event))) = self.events.pop() {
+            self.now = time;
+            match event {
+                Event::Attempt(id) => self.attempt(id).await,
+                Event::Done(id) => self.done(id).await,
+                Event::Sample => {
+                    self.report.backlog.push(BacklogSample {
+                        time,
+                        queued: self.queued,
+                        in_flight: self.in_flight,
+                    });
+
+                    // Keep sampling while there are messages left
+                    if self.events.iter().any(|e| !matches!(e.0 .2, Event::Sample)) {
+                        self.schedule(time + workload.sample_interval, Event::Sample);
+                    }
+                    continue;
+                }
+            }
+            self.report.elapsed = time;
+
+            processed += 1;
+            if processed >= MAX_EVENTS {
+                break;
+            }
+        }
+
+        // Build domain reports
+        for mut domain in std::mem::take(&mut self.domains) {
+            domain.latencies.sort_unstable();
+            domain.report.latency = LatencyPercentiles {
+                p50: percentile(&domain.latencies, 50),
+                p90: percentile(&domain.latencies, 90),
+                p99: percentile(&domain.latencies, 99),
+            };
+            self.report.domains.push(domain.report);
+        }
+    }
+
+    async fn attempt(&mut self, id: usize) {
+        let config = self.config;
+        let message = &self.messages[id];
+        let domain_id = message.domain;
+        let profile = self.workload.domains.get(domain_id);
+        let domain_name = self.domains[domain_id].report.domain.clone();
+        let sim_message = build_message(&message.sender, &domain_name, message.created);
+        let envelope = QueueEnvelope {
+            message: &sim_message,
+            domain: &domain_name,
+            mx: &domain_name,
+            remote_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            local_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        };
+
+        // Newly queued messages obtain their expiration time
+        if message.attempts == 0 && message.expires == u64::MAX {
+            let expires = message.created + config.expire.eval(&envelope).await.as_secs();
+            self.messages[id].expires = expires;
+            self.queued += 1;
+        }
+        if self.now >= self.messages[id].expires {
+            self.expire(id);
+            return;
+        }
+
+        // Enforce outbound throttles
+        let mut slots = Vec::new();
+        for (pos, throttle) in config
+            .throttle
+            .sender
+            .iter()
+            .chain(self.config.throttle.rcpt.iter())
+            .enumerate()
+        {
+            if throttle.conditions.conditions.is_empty()
+                || throttle.conditions.eval(&envelope).await
+            {
+                let limiter_id = self.limiter_id(pos, throttle, &envelope);
+                let limiter = &mut self.limiters[limiter_id];
+                if limiter
+                    .max_concurrent
+                    .map_or(false, |max| limiter.concurrent >= max)
+                {
+                    // Wait until a delivery holding this limiter completes
+                    limiter.waiting.push_back(id);
+                    self.release(&slots);
+                    self.report.throttled += 1;
+                    return;
+                }
+                if let Some(rate) = &throttle.rate {
+                    let period = rate.period.as_secs().max(1);
+                    if self.now >= limiter.window_start + period {
+                        limiter.window_start = self.now;
+                        limiter.window_count = 0;
+                    }
+                    if limiter.window_count >= rate.requests {
+                        let retry_at = limiter.window_start + period;
+                        self.release(&slots);
+                        self.report.throttled += 1;
+                        self.schedule(retry_at, Event::Attempt(id));
+                        return;
+                    }
+                    limiter.window_count += 1;
+                }
+                limiter.concurrent += 1;
+                slots.push(limiter_id);
+            }
+        }
+
+        // Simulate the delivery attempt
+        let domain = &mut self.domains[domain_id];
+        let outcome = if profile.map_or(false, |p| {
+            p.outages
+                .iter()
+                .any(|o| self.now >= o.start && self.now < o.end)
+                || p.max_concurrency
+                    .map_or(false, |max| domain.in_flight >= max)
+        }) {
+            Outcome::TempFailure
+        } else {
+            let roll = self.rng.gen::<f64>();
+            match profile {
+                Some(p) if roll < p.perm_failure_rate => Outcome::PermFailure,
+                Some(p) if roll < p.perm_failure_rate + p.temp_failure_rate => Outcome::TempFailure,
+                _ => Outcome::Delivered,
+            }
+        };
+        domain.in_flight += 1;
+        domain.report.attempts += 1;
+        self.in_flight += 1;
+        self.report.attempts += 1;
+
+        let message = &mut self.messages[id];
+        message.attempts += 1;
+        message.outcome = outcome;
+        message.slots = slots;
+        let delivery_time = profile.map_or(1, |p| p.delivery_time.max(1));
+        self.schedule(self.now + delivery_time, Event::Done(id));
+    }
+
+    async fn done(&mut self, id: usize) {
+        let slots = std::mem::take(&mut self.messages[id].slots);
+        self.release(&slots);
+        let message = &self.messages[id];
+        let domain = &mut self.domains[message.domain];
+        domain.in_flight -= 1;
+        self.in_flight -= 1;
+
+        match message.outcome {
+            Outcome::Delivered => {
+                domain.latencies.push(self.now - message.created);
+                domain.report.delivered += 1;
+                self.report.delivered += 1;
+                self.queued -= 1;
+            }
+            Outcome::PermFailure => {
+                domain.report.failed += 1;
+                self.report.failed += 1;
+                self.queued -= 1;
+            }
+            Outcome::TempFailure | Outcome::Pending => {
+                let sim_message =
+                    build_message(&message.sender, &domain.report.domain, message.created);
+                let envelope = QueueEnvelope {
+                    message: &sim_message,
+                    domain: &domain.report.domain,
+                    mx: &domain.report.domain,
+                    remote_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                    local_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                };
+                let attempt = message.attempts - 1;
+                let (interval, give_up) = match self.config.retry_schedule(&envelope).await {
+                    RetrySchedule::Intervals(schedule) => (
+                        schedule[std::cmp::min(attempt as usize, schedule.len() - 1)],
+                        None,
+                    ),
+                    RetrySchedule::Strategy { strategy, .. } => {
+                        (strategy.interval(attempt), strategy.give_up)
+                    }
+                };
+
+                let message = &mut self.messages[id];
+                if let Some(give_up) = give_up {
+                    message.expires =
+                        std::cmp::min(message.expires, message.created + give_up.as_secs());
+                }
+                let next_attempt = self.now + interval.as_secs().max(1);
+                if next_attempt < message.expires {
+                    self.schedule(next_attempt, Event::Attempt(id));
+                } else {
+                    self.expire(id);
+                }
+            }
+        }
+    }
+
+    fn expire(&mut self, id: usize) {
+        let domain = &mut self.domains[self.messages[id].domain];
+        domain.report.expired += 1;
+        self.report.expired += 1;
+        self.queued -= 1;
+    }
+
+    fn limiter_id(
+        &mut self,
+        pos: usize,
+        throttle: &Throttle,
+        envelope: &QueueEnvelope<'_>,
+    ) -> usize {
+        let limiters = &mut self.limiters;
+        *self
+            .limiter_ids
+            .entry((pos, throttle.new_key(envelope)))
+            .or_insert_with(|| {
+                limiters.push(SimLimiter {
+                    max_concurrent: throttle.concurrency,
+                    ..Default::default()
+                });
+                limiters.len() - 1
+            })
+    }
+
+    fn release(&mut self, slots: &[usize]) {
+        for limiter_id in slots {
+            let limiter = &mut self.limiters[*limiter_id];
+            limiter.concurrent -= 1;
+            if let Some(id) = limiter.waiting.pop_front() {
+                self.schedule(self.now, Event::Attempt(id));
+            }
+        }
+    }
+
+    fn schedule(&mut self, time: u64, event: Event) {
+        self.seq += 1;
+        self.events.push(Reverse((time, self.seq, event)));
+    }
+}
+
+fn build_message(sender: &str, domain: &str, created: u64) -> Message {
+    let sender = sender.to_lowercase();
+    Message {
+        id: 0,
+        created,
+        path: PathBuf::new(),
+        return_path_domain: sender
+            .rsplit_once('@')
+            .map(|(_, domain)| domain.to_string())
+            .unwrap_or_default(),
+        return_path: sender.clone(),
+        return_path_lcase: sender,
+        recipients: vec![],
+        domains: vec![Domain {
+            domain: domain.to_string(),
+            retry: Schedule::now(),
+            notify: Schedule::now(),
+            expires: Instant::now() + Duration::from_secs(86400),
+            status: Status::Scheduled,
+            retry_strategy: None,
+            disable_tls: false,
+            changed: false,
+        }],
+        flags: 0,
+        env_id: None,
+        priority: 0,
+        parked: None,
+        size: 0,
+        queue_refs: vec![],
+    }
+}
+
+fn percentile(sorted: &[u64], pct: usize) -> Option<u64> {
+    if !sorted.is_empty() {
+        Some(sorted[std::cmp::min(sorted.len() * pct / 100, sorted.len() - 1)])
+    } else {
+        None
+    }
+}
@@ -30,6 +30,7 @@ use std::time::Instant;
 use std::time::{Duration, SystemTime};
 use tokio::fs::OpenOptions;
 use tokio::{fs, io::AsyncWriteExt};
+use utils::events::{self, EventKind, LifecycleEvent};
 
 use crate::config::QueueConfig;
 use crate::core::QueueCore;
@@ -135,6 +136,16 @@ impl QueueCore {
             "Message queued for delivery."
         );
 
+        if events::is_enabled(EventKind::Queued) {
+            events::publish(
+                LifecycleEvent::new(EventKind::Queued)
+                    .with_queue_id(message.id)
+                    .with_sender(message.return_path.as_str())
+                    .with_recipients(message.recipients.iter().map(|rcpt| rcpt.address.as_str()))
+                    .with_size(message.size),
+            );
+        }
+
         // Queue the message
         if self
             .tx
@@ -187,6 +198,7 @@ impl Message {
             flags: 0,
             env_id: None,
             priority: 0,
+            parked: None,
             size: 0,
             queue_refs: vec![],
         })
@@ -215,6 +227,7 @@ impl Message {
                     notify: Schedule::later(expires + Duration::from_secs(10)),
                     expires: Instant::now() + expires,
                     status: Status::Scheduled,
+                    retry_strategy: None,
                     disable_tls: false,
                     changed: false,
                 });
@@ -241,23 +254,27 @@ impl Message {
     pub async fn save_changes(&mut self) {
         let buf = self.serialize_changes();
         if !buf.is_empty() {
-            let err = match OpenOptions::new().append(true).open(&self.path).await {
-                Ok(mut file) => match file.write_all(&buf).await {
-                    Ok(_) => return,
-                    Err(err) => err,
-                },
-                Err(err) => err,
-            };
-            tracing::error!(
-                context = "queue",
-                event = "error",
-                "Failed to write to {}: {}",
-                self.path.display(),
-                err
-            );
+            self.append_changes(&buf).await;
         }
     }
 
+    pub async fn append_changes(&self, buf: &[u8]) {
+        let err = match OpenOptions::new().append(true).open(&self.path).await {
+            Ok(mut file) => match file.write_all(buf).await {
+                Ok(_) => return,
+                Err(err) => err,
+            },
+            Err(err) => err,
+        };
+        tracing::error!(
+            context = "queue",
+            event = "error",
+            "Failed to write to {}: {}",
+            self.path.display(),
+            err
+        );
+    }
+
     pub async fn remove(&self) {
         if let Err(err) = fs::remove_file(&self.path).await {
             tracing::error!(
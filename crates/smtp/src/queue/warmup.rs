@@ -0,0 +1,264 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+use store::{write::now, LookupKey, LookupValue};
+
+use crate::{
+    config::QueueWarmup,
+    core::{
+        management::{WarmupProvider, WarmupStatus},
+        QueueCore,
+    },
+};
+
+const DAY: u64 = 86400;
+const OTHER_PROVIDER: &str = "other";
+
+// Warm-up start dates are kept for ten years, long after any ramp schedule
+const START_EXPIRY: u64 = 10 * 365 * DAY;
+
+impl QueueWarmup {
+    pub fn provider(&self, mx: &str) -> &str {
+        let mx = mx.trim_end_matches('.').to_lowercase();
+        self.providers
+            .iter()
+            .find(|(suffix, _)| {
+                mx.strip_suffix(suffix.as_str())
+                    .map_or(false, |prefix| prefix.is_empty() || prefix.ends_with('.'))
+            })
+            .map_or(OTHER_PROVIDER, |(_, provider)| provider.as_str())
+    }
+
+    // Returns the daily limit for a day of the schedule, or None once
+    // the warm-up has been completed.
+    pub fn daily_limit(&self, day: u64) -> Option<u64> {
+        self.schedule.get(day as usize).copied()
+    }
+}
+
+impl QueueCore {
+    /// Picks a source IP among the candidates, skipping IPs that reached their
+    /// daily warm-up limit for the provider hosting the MX.
+    pub async fn select_source_ip(&self, candidates: Vec<IpAddr>, mx: &str) -> Option<IpAddr> {
+        let candidates = if candidates.len() > 1 && !self.config.warmup.ips.is_empty() {
+            let provider = self.config.warmup.provider(mx);
+            let mut available = Vec::with_capacity(candidates.len());
+            for ip in &candidates {
+                if !matches!(
+                    self.warmup_remaining(*ip, provider, false).await,
+                    Ok(Some(0))
+                ) {
+                    available.push(*ip);
+                }
+            }
+            if !available.is_empty() {
+                available
+            } else {
+                candidates
+            }
+        } else {
+            candidates
+        };
+
+        match candidates.len() {
+            0 => None,
+            1 => candidates.first().copied(),
+            len => candidates
+                .get(rand::thread_rng().gen_range(0..len))
+                .copied(),
+        }
+    }
+
+    /// Counts a delivery attempt from a source IP under warm-up, returning
+    /// the time at which sending may resume if the daily limit was reached.
+    pub async fn warmup_acquire(
+        &self,
+        ip: IpAddr,
+        mx: &str,
+        span: &tracing::Span,
+    ) -> Result<(), Instant> {
+        if !self.config.warmup.ips.contains(&ip) {
+            return Ok(());
+        }
+        let provider = self.config.warmup.provider(mx);
+        let result = match self.warmup_remaining(ip, provider, true).await {
+            Ok(Some(0)) => {
+                tracing::info!(
+                    parent: span,
+                    context = "warmup",
+                    event = "limited",
+                    source_ip = %ip,
+                    provider = provider,
+                    "Daily warm-up limit reached for source IP, deferring delivery."
+                );
+                return Err(next_day());
+            }
+            Ok(Some(_)) => {
+                self.config
+                    .lookup_store
+                    .key_set(
+                        counter_key(ip, provider, now() / DAY),
+                        LookupValue::Counter { num: 1 },
+                    )
+                    .await
+            }
+            Ok(None) => Ok(()),
+            Err(err) => Err(err),
+        };
+
+        // Warm-up tracking failures should not block delivery
+        if let Err(err) = result {
+            tracing::warn!(
+                parent: span,
+                context = "warmup",
+                event = "error",
+                source_ip = %ip,
+                reason = %err,
+                "Failed to update warm-up progress."
+            );
+        }
+
+        Ok(())
+    }
+
+    // Returns how many messages the IP may still send today to the provider,
+    // or None if the IP is not warming up. The warm-up of an IP starts the
+    // first time it is used for delivery.
+    async fn warmup_remaining(
+        &self,
+        ip: IpAddr,
+        provider: &str,
+        start: bool,
+    ) -> store::Result<Option<u64>> {
+        if !self.config.warmup.ips.contains(&ip) {
+            return Ok(None);
+        }
+        let today = now() / DAY;
+        let day = match self.warmup_day(ip).await? {
+            Some(day) => day,
+            None if start => {
+                self.config
+                    .lookup_store
+                    .key_insert(start_key(ip), today.to_string().into_bytes(), START_EXPIRY)
+                    .await?;
+                0
+            }
+            None => 0,
+        };
+
+        if let Some(limit) = self.config.warmup.daily_limit(day) {
+            let sent = self.warmup_sent(ip, provider, today).await?;
+            Ok(Some(limit.saturating_sub(sent)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Number of days since the IP started warming up
+    async fn warmup_day(&self, ip: IpAddr) -> store::Result<Option<u64>> {
+        match self
+            .config
+            .lookup_store
+            .key_get::<String>(LookupKey::Key(start_key(ip)))
+            .await?
+        {
+            LookupValue::Value { value, .. } => Ok(value
+                .parse::<u64>()
+                .ok()
+                .map(|started| (now() / DAY).saturating_sub(started))),
+            _ => Ok(None),
+        }
+    }
+
+    async fn warmup_sent(&self, ip: IpAddr, provider: &str, day: u64) -> store::Result<u64> {
+        match self
+            .config
+            .lookup_store
+            .key_get::<String>(LookupKey::Counter(counter_key(ip, provider, day)))
+            .await?
+        {
+            LookupValue::Counter { num } => Ok(num.max(0) as u64),
+            _ => Ok(0),
+        }
+    }
+
+    pub async fn warmup_status(&self) -> store::Result<Vec<WarmupStatus>> {
+        let warmup = &self.config.warmup;
+        let mut providers = warmup
+            .providers
+            .iter()
+            .map(|(_, provider)| provider.as_str())
+            .chain([OTHER_PROVIDER])
+            .collect::<Vec<_>>();
+        providers.sort_unstable();
+        providers.dedup();
+        let mut ips = warmup.ips.iter().copied().collect::<Vec<_>>();
+        ips.sort_unstable();
+
+        let today = now() / DAY;
+        let mut status = Vec::with_capacity(ips.len());
+        for ip in ips {
+            let day = self.warmup_day(ip).await?;
+            let daily_limit = warmup.daily_limit(day.unwrap_or_default());
+            let mut provider_status = Vec::with_capacity(providers.len());
+            if daily_limit.is_some() {
+                for provider in &providers {
+                    let sent = self.warmup_sent(ip, provider, today).await?;
+                    provider_status.push(WarmupProvider {
+                        provider: provider.to_string(),
+                        sent,
+                        remaining: daily_limit.map(|limit| limit.saturating_sub(sent)),
+                    });
+                }
+            }
+
+            status.push(WarmupStatus {
+                ip,
+                day,
+                daily_limit,
+                completed: daily_limit.is_none(),
+                providers: provider_status,
+            });
+        }
+
+        Ok(status)
+    }
+}
+
+fn start_key(ip: IpAddr) -> Vec<u8> {
+    format!("warmup:{ip}").into_bytes()
+}
+
+fn counter_key(ip: IpAddr, provider: &str, day: u64) -> Vec<u8> {
+    format!("warmup:{ip}:{provider}:{day}").into_bytes()
+}
+
+fn next_day() -> Instant {
+    Instant::now() + Duration::from_secs(DAY - now() % DAY)
+}
@@ -0,0 +1,373 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::SystemTime;
+
+use mail_auth::report::{Feedback, FeedbackType};
+use mail_parser::{HeaderName, HeaderValue, Message, MessageParser};
+use sieve::runtime::Variable;
+use store::{LookupKey, LookupValue};
+
+use crate::{
+    core::{eval::EvalEnvelope, SMTP},
+    queue::{DomainPart, RecipientDomain},
+    scripts::plugins::lookup::VariableWrapper,
+    USER_AGENT,
+};
+
+impl SMTP {
+    pub async fn send_abuse_report(&self, raw_message: &[u8], reported_by: &str) {
+        let message = if let Some(message) = MessageParser::new().parse_headers(raw_message) {
+            message
+        } else {
+            return;
+        };
+        let sender = if let Some(sender) = reported_sender(&message) {
+            sender
+        } else {
+            tracing::debug!(
+                context = "report",
+                report = "abuse",
+                event = "skip",
+                reported_by = reported_by,
+                "Message has no sender address."
+            );
+            return;
+        };
+
+        // The topmost Received header was added by this server
+        let (source_ip, arrival_date) = message
+            .root_part()
+            .headers
+            .iter()
+            .filter(|header| header.name == HeaderName::Received)
+            .find_map(|header| match &header.value {
+                HeaderValue::Received(received) => received
+                    .from_ip()
+                    .map(|ip| (Some(ip), received.date().map(|date| date.to_timestamp()))),
+                _ => None,
+            })
+            .unwrap_or_default();
+        let mut envelope = EvalEnvelope::default()
+            .with_sender(&sender)
+            .with_rcpt(reported_by);
+        envelope.authenticated_as = reported_by.to_string();
+        if let Some(source_ip) = source_ip {
+            envelope.remote_ip = source_ip;
+        }
+
+        // Abuse reports are disabled unless a rate is configured
+        let config = &self.report.config.abuse;
+        let rate = if let Some(rate) = config.report.send.eval(&envelope).await {
+            rate
+        } else {
+            return;
+        };
+
+        // Obtain recipients
+        let mut rcpts = config.rcpts.clone();
+        if config.notify_origin {
+            if let Some(contact) = self.abuse_contact(envelope.sender_domain.as_str()).await {
+                if !rcpts.contains(&contact) {
+                    rcpts.push(contact);
+                }
+            }
+        }
+
+        let from_addr = config.report.address.eval(&envelope).await;
+        let from_name = config.report.name.eval(&envelope).await;
+        let subject = config.report.subject.eval(&envelope).await;
+        let headers = raw_message
+            .get(..message.root_part().offset_body)
+            .unwrap_or(raw_message);
+        let span = tracing::info_span!(
+            "abuse-report",
+            sender = sender.as_str(),
+            reported_by = reported_by
+        );
+
+        for rcpt in rcpts {
+            // Throttle recipient
+            if !self.throttle_rcpt(&rcpt, rate, "abuse") {
+                tracing::debug!(
+                    parent: &span,
+                    context = "report",
+                    report = "abuse",
+                    event = "throttle",
+                    rcpt = rcpt.as_str(),
+                );
+                continue;
+            }
+
+            let reporting_mta = self
+                .report
+                .config
+                .submitter
+                .eval(&RecipientDomain::new(rcpt.domain_part()))
+                .await;
+            let mut feedback = Feedback::new(FeedbackType::Abuse)
+                .with_arrival_date(arrival_date.unwrap_or_else(|| {
+                    SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .map_or(0, |d| d.as_secs()) as i64
+                }))
+                .with_original_mail_from(sender.as_str())
+                .with_original_rcpt_to(reported_by)
+                .with_reported_domain(envelope.sender_domain.as_str())
+                .with_reporting_mta(reporting_mta.as_str())
+                .with_user_agent(USER_AGENT)
+                .with_incidents(1)
+                .with_headers(headers);
+            if let Some(source_ip) = source_ip {
+                feedback = feedback.with_source_ip(source_ip);
+            }
+
+            let mut report = Vec::with_capacity(128);
+            feedback
+                .write_rfc5322(
+                    (from_name.as_str(), from_addr.as_str()),
+                    &rcpt,
+                    subject,
+                    &mut report,
+                )
+                .ok();
+
+            tracing::info!(
+                parent: &span,
+                context = "report",
+                report = "abuse",
+                event = "queue",
+                rcpt = rcpt.as_str(),
+                "Queueing abuse report."
+            );
+
+            self.send_report(
+                from_addr,
+                [rcpt.as_str()].into_iter(),
+                report,
+                &config.report.sign,
+                &span,
+                true,
+            )
+            .await;
+        }
+    }
+
+    pub async fn process_feedback(&self, feedback: &Feedback<'_>, reported_sender: Option<&str>) {
+        let config = &self.report.config.analysis.feedback;
+        if !config.enable
+            || !matches!(
+                feedback.feedback_type(),
+                FeedbackType::Abuse | FeedbackType::Fraud | FeedbackType::Virus
+            )
+        {
+            return;
+        }
+
+        // Only account for complaints about messages sent by local users
+        let sender = if let Some(sender) = feedback
+            .original_mail_from()
+            .or(reported_sender)
+            .map(|sender| {
+                sender
+                    .trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_lowercase()
+            })
+            .filter(|sender| sender.contains('@'))
+        {
+            sender
+        } else {
+            tracing::debug!(
+                context = "arf",
+                event = "skip",
+                "Feedback report does not include the original sender."
+            );
+            return;
+        };
+        match self
+            .queue
+            .config
+            .directory
+            .is_local_domain(sender.domain_part())
+            .await
+        {
+            Ok(true) => (),
+            Ok(false) => {
+                tracing::debug!(
+                    context = "arf",
+                    event = "skip",
+                    sender = sender.as_str(),
+                    "Feedback report is not about a local sender."
+                );
+                return;
+            }
+            Err(err) => {
+                tracing::warn!(
+                    context = "arf",
+                    event = "error",
+                    sender = sender.as_str(),
+                    reason = %err,
+                    "Failed to verify sender domain."
+                );
+                return;
+            }
+        }
+        let store = config
+            .store
+            .as_ref()
+            .unwrap_or(&self.queue.config.lookup_store);
+
+        // Update complaint statistics
+        if let Err(err) = store
+            .key_set(
+                format!("fbl:{sender}").into_bytes(),
+                LookupValue::Counter {
+                    num: std::cmp::max(feedback.incidents(), 1) as i64,
+                },
+            )
+            .await
+        {
+            tracing::warn!(
+                context = "arf",
+                event = "error",
+                sender = sender.as_str(),
+                reason = %err,
+                "Failed to update complaint statistics."
+            );
+            return;
+        }
+
+        // Update sender reputation using the same tokens as the spam filter
+        let token = format!("f:{sender}").into_bytes();
+        let (token_score, token_count) = match store
+            .key_get::<VariableWrapper>(LookupKey::Key(token.clone()))
+            .await
+        {
+            Ok(LookupValue::Value { value, .. }) => match value.into_inner() {
+                Variable::Array(items) if items.len() == 2 => {
+                    (as_float(&items[0]), as_float(&items[1]))
+                }
+                _ => (0.0, 0.0),
+            },
+            Ok(_) => (0.0, 0.0),
+            Err(err) => {
+                tracing::warn!(
+                    context = "arf",
+                    event = "error",
+                    sender = sender.as_str(),
+                    reason = %err,
+                    "Failed to obtain sender reputation."
+                );
+                return;
+            }
+        };
+        let score = if token_count > 0.0 {
+            (token_count + 1.0) * (config.score + 0.98 * token_score) / (0.98 * token_count + 1.0)
+        } else {
+            config.score
+        };
+        let value = Variable::from(vec![
+            Variable::Float(score),
+            Variable::Integer(token_count as i64 + 1),
+        ]);
+        if let Err(err) = store
+            .key_set(
+                token,
+                LookupValue::Value {
+                    value: bincode::serialize(&value).unwrap_or_default(),
+                    expires: config.expiry.as_secs(),
+                },
+            )
+            .await
+        {
+            tracing::warn!(
+                context = "arf",
+                event = "error",
+                sender = sender.as_str(),
+                reason = %err,
+                "Failed to update sender reputation."
+            );
+        } else {
+            tracing::info!(
+                context = "arf",
+                event = "complaint",
+                sender = sender.as_str(),
+                feedback_type = ?feedback.feedback_type(),
+                "Updated sender reputation."
+            );
+        }
+    }
+
+    async fn abuse_contact(&self, domain: &str) -> Option<String> {
+        if domain.is_empty() {
+            return None;
+        }
+
+        // Query the abuse.net contact database
+        let contact = self
+            .resolvers
+            .dns
+            .txt_raw_lookup(format!("{domain}.contacts.abuse.net."))
+            .await
+            .ok()
+            .and_then(|contact| String::from_utf8(contact).ok())?;
+        let contact = contact.trim().to_lowercase();
+        if contact.contains('@') && !contact.contains(char::is_whitespace) {
+            Some(contact)
+        } else {
+            None
+        }
+    }
+}
+
+pub fn reported_sender(message: &Message<'_>) -> Option<String> {
+    match message.header(HeaderName::ReturnPath) {
+        Some(HeaderValue::Text(text)) => Some(text.as_ref()),
+        Some(HeaderValue::TextList(list)) => list.first().map(|text| text.as_ref()),
+        _ => None,
+    }
+    .or_else(|| {
+        message
+            .from()
+            .and_then(|addr| addr.first())
+            .and_then(|addr| addr.address())
+    })
+    .map(|sender| {
+        sender
+            .trim()
+            .trim_start_matches('<')
+            .trim_end_matches('>')
+            .to_lowercase()
+    })
+    .filter(|sender| sender.contains('@'))
+}
+
+fn as_float(value: &Variable) -> f64 {
+    match value {
+        Variable::Float(value) => *value,
+        Variable::Integer(value) => *value as f64,
+        _ => 0.0,
+    }
+}
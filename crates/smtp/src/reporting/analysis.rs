@@ -35,10 +35,12 @@ use mail_auth::{
     report::{tlsrpt::TlsReport, ActionDisposition, DmarcResult, Feedback, Report},
     zip,
 };
-use mail_parser::{DateTime, MessageParser, MimeHeaders, PartType};
+use mail_parser::{DateTime, Message, MessageParser, MimeHeaders, PartType};
 
 use crate::core::SMTP;
 
+use super::abuse::reported_sender;
+
 enum Compression {
     None,
     Gzip,
@@ -64,6 +66,7 @@ pub trait AnalyzeReport {
 impl AnalyzeReport for Arc<SMTP> {
     fn analyze_report(&self, message: Arc<Vec<u8>>) {
         let core = self.clone();
+        let handle = tokio::runtime::Handle::current();
         self.worker_pool.spawn(move || {
             let message = if let Some(message) = MessageParser::default().parse(message.as_ref()) {
                 message
@@ -245,6 +248,12 @@ impl AnalyzeReport for Arc<SMTP> {
                     Format::Arf => match Feedback::parse_arf(&data) {
                         Some(report) => {
                             report.log();
+                            if core.report.config.analysis.feedback.enable {
+                                handle.block_on(core.process_feedback(
+                                    &report,
+                                    feedback_sender(&message).as_deref(),
+                                ));
+                            }
                         }
                         None => {
                             tracing::debug!(
@@ -301,6 +310,19 @@ impl AnalyzeReport for Arc<SMTP> {
     }
 }
 
+// Obtain the sender of the message attached to a feedback report
+fn feedback_sender(message: &Message<'_>) -> Option<String> {
+    message.parts.iter().find_map(|part| match &part.body {
+        PartType::Message(message) => reported_sender(message),
+        PartType::Text(headers) if part.is_content_type("text", "rfc822-headers") => {
+            MessageParser::new()
+                .parse_headers(headers.as_bytes())
+                .and_then(|message| reported_sender(&message))
+        }
+        _ => None,
+    })
+}
+
 trait LogReport {
     fn log(&self);
 }
@@ -45,9 +45,12 @@ use crate::{
 
 use self::scheduler::{ReportKey, ReportValue};
 
+pub mod abuse;
 pub mod analysis;
 pub mod dkim;
 pub mod dmarc;
+pub mod notify;
+pub mod operator;
 pub mod scheduler;
 pub mod spf;
 pub mod tls;
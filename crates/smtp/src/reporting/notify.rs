@@ -0,0 +1,66 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use mail_builder::{headers::HeaderType, mime::make_boundary, MessageBuilder};
+
+use crate::core::{eval::EvalEnvelope, SMTP};
+
+impl SMTP {
+    // Sends an automated account notification signed and addressed as a DSN
+    pub async fn send_account_notification(&self, rcpt: &str, subject: &str, body: String) {
+        let envelope = EvalEnvelope::default().with_rcpt(rcpt);
+        let from_name = self.queue.config.dsn.name.eval(&envelope).await;
+        let from_addr = self.queue.config.dsn.address.eval(&envelope).await;
+        let hostname = self.queue.config.hostname.eval(&envelope).await;
+
+        let notification = MessageBuilder::new()
+            .from((from_name.as_str(), from_addr.as_str()))
+            .to(rcpt)
+            .header("Auto-Submitted", HeaderType::Text("auto-generated".into()))
+            .message_id(format!("<{}@{}>", make_boundary("."), hostname))
+            .subject(subject)
+            .text_body(body)
+            .write_to_vec()
+            .unwrap_or_default();
+
+        let span = tracing::info_span!("account-notification", rcpt = rcpt);
+        tracing::info!(
+            parent: &span,
+            context = "notification",
+            event = "send",
+            rcpt = rcpt,
+            subject = subject,
+            "Sending account notification."
+        );
+
+        self.send_report(
+            &from_addr,
+            [rcpt].into_iter(),
+            notification,
+            &self.queue.config.dsn.sign,
+            &span,
+            true,
+        )
+        .await;
+    }
+}
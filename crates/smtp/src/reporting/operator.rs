@@ -0,0 +1,517 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    time::{Duration, Instant, SystemTime},
+};
+
+use ahash::AHashMap;
+use mail_builder::{headers::HeaderType, mime::make_boundary, MessageBuilder};
+use mail_parser::DateTime;
+use serde::Serialize;
+use tokio::sync::oneshot;
+use utils::config::ConfigKey;
+
+use crate::{
+    config::{OperatorSchedule, OperatorSection},
+    core::{eval::EvalEnvelope, management::QueueRequest, SMTP},
+    queue,
+};
+
+pub const OPERATOR_SCHEDULE_KEY: &str = "report.operator.schedule";
+
+const HOUR: u64 = 3600;
+const TELEMETRY_RETENTION: u64 = 31 * 24 * HOUR;
+
+/// Runtime state of the operator reports, enable flags can be changed
+/// through the management API and take precedence over the configuration.
+#[derive(Default)]
+pub struct OperatorState {
+    schedules: parking_lot::Mutex<AHashMap<String, ScheduleState>>,
+    pub telemetry: TelemetryHistory,
+}
+
+struct ScheduleState {
+    enable: bool,
+    next_due: Instant,
+    last_sent: Option<u64>,
+}
+
+/// Hourly counters of the events that are not tracked elsewhere.
+#[derive(Default)]
+pub struct TelemetryHistory {
+    hourly: parking_lot::Mutex<VecDeque<(u64, TelemetrySample)>>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TelemetrySample {
+    #[serde(rename = "spamScanned")]
+    pub spam_scanned: u64,
+    #[serde(rename = "spamDetected")]
+    pub spam_detected: u64,
+    #[serde(rename = "authFailures")]
+    pub auth_failures: u64,
+    #[serde(rename = "tlsDowngrades")]
+    pub tls_downgrades: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OperatorReportStatus {
+    pub id: String,
+    pub enable: bool,
+    pub to: Vec<String>,
+    pub sections: Vec<OperatorSection>,
+    #[serde(rename = "nextRun")]
+    pub next_run: String,
+    #[serde(rename = "lastSent")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_sent: Option<String>,
+}
+
+impl OperatorState {
+    pub fn new(schedules: &[OperatorSchedule]) -> Self {
+        let now = Instant::now();
+        OperatorState {
+            schedules: parking_lot::Mutex::new(
+                schedules
+                    .iter()
+                    .map(|schedule| {
+                        (
+                            schedule.id.clone(),
+                            ScheduleState {
+                                enable: schedule.enable,
+                                next_due: now + schedule.frequency.time_to_next(),
+                                last_sent: None,
+                            },
+                        )
+                    })
+                    .collect(),
+            ),
+            telemetry: TelemetryHistory::default(),
+        }
+    }
+
+    pub fn wake_up_time(&self, default: Duration) -> Duration {
+        let now = Instant::now();
+        self.schedules
+            .lock()
+            .values()
+            .map(|state| state.next_due.saturating_duration_since(now))
+            .min()
+            .map_or(default, |wait| wait.min(default))
+    }
+}
+
+impl TelemetryHistory {
+    pub fn record_spam_verdict(&self, is_spam: bool) {
+        self.record(now(), |sample| {
+            sample.spam_scanned += 1;
+            if is_spam {
+                sample.spam_detected += 1;
+            }
+        });
+    }
+
+    pub fn record_auth_failure(&self) {
+        self.record(now(), |sample| sample.auth_failures += 1);
+    }
+
+    pub fn record_tls_downgrade(&self) {
+        self.record(now(), |sample| sample.tls_downgrades += 1);
+    }
+
+    pub fn record(&self, now: u64, f: impl FnOnce(&mut TelemetrySample)) {
+        let hour = now - (now % HOUR);
+        let mut hourly = self.hourly.lock();
+        match hourly.back_mut() {
+            Some((last_hour, sample)) if *last_hour == hour => f(sample),
+            _ => {
+                let mut sample = TelemetrySample::default();
+                f(&mut sample);
+                hourly.push_back((hour, sample));
+            }
+        }
+        while hourly
+            .front()
+            .map_or(false, |(hour, _)| hour + TELEMETRY_RETENTION < now)
+        {
+            hourly.pop_front();
+        }
+    }
+
+    /// Returns the totals recorded between the `from` and `to` UNIX timestamps.
+    pub fn summary(&self, from: u64, to: u64) -> TelemetrySample {
+        let mut total = TelemetrySample::default();
+        for (hour, sample) in self.hourly.lock().iter() {
+            if *hour + HOUR > from && *hour <= to {
+                total.spam_scanned += sample.spam_scanned;
+                total.spam_detected += sample.spam_detected;
+                total.auth_failures += sample.auth_failures;
+                total.tls_downgrades += sample.tls_downgrades;
+            }
+        }
+        total
+    }
+}
+
+impl SMTP {
+    /// Sends the operator reports that are due, returning immediately.
+    pub fn send_due_operator_reports(self: &std::sync::Arc<Self>) {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        {
+            let mut schedules = self.report.operator.schedules.lock();
+            for schedule in &self.report.config.operator.schedules {
+                if let Some(state) = schedules.get_mut(&schedule.id) {
+                    if state.next_due <= now {
+                        state.next_due = now + schedule.frequency.time_to_next();
+                        if state.enable {
+                            due.push(schedule.id.clone());
+                        }
+                    }
+                }
+            }
+        }
+
+        for id in due {
+            let core = self.clone();
+            tokio::spawn(async move {
+                core.send_operator_report(&id).await;
+            });
+        }
+    }
+
+    pub async fn send_operator_report(&self, id: &str) -> bool {
+        let schedule = if let Some(schedule) = self
+            .report
+            .config
+            .operator
+            .schedules
+            .iter()
+            .find(|schedule| schedule.id == id)
+        {
+            schedule
+        } else {
+            return false;
+        };
+        if schedule.rcpts.is_empty() {
+            tracing::debug!(
+                context = "report",
+                report = "operator",
+                event = "skip",
+                id = id,
+                "Operator report has no recipients."
+            );
+            return false;
+        }
+
+        let config = &self.report.config.operator.report;
+        let envelope = EvalEnvelope::default().with_rcpt(&schedule.rcpts[0]);
+        let from_name = config.name.eval(&envelope).await;
+        let from_addr = config.address.eval(&envelope).await;
+        let subject = config.subject.eval(&envelope).await;
+        let hostname = self.queue.config.hostname.eval(&envelope).await;
+        let (html, text) = self.build_operator_report(schedule, now()).await;
+
+        let message = MessageBuilder::new()
+            .from((from_name.as_str(), from_addr.as_str()))
+            .header("To", HeaderType::Text(schedule.rcpts.join(", ").into()))
+            .header("Auto-Submitted", HeaderType::Text("auto-generated".into()))
+            .message_id(format!("<{}@{}>", make_boundary("."), hostname))
+            .subject(format!("{subject}: {id}"))
+            .html_body(html)
+            .text_body(text)
+            .write_to_vec()
+            .unwrap_or_default();
+
+        let span = tracing::info_span!("operator-report", id = id);
+        tracing::info!(
+            parent: &span,
+            context = "report",
+            report = "operator",
+            event = "send",
+            id = id,
+            rcpts = ?schedule.rcpts,
+            "Sending operator report."
+        );
+
+        self.send_report(
+            from_addr.as_str(),
+            schedule.rcpts.iter(),
+            message,
+            &config.sign,
+            &span,
+            true,
+        )
+        .await;
+
+        if let Some(state) = self.report.operator.schedules.lock().get_mut(id) {
+            state.last_sent = now().into();
+        }
+
+        true
+    }
+
+    /// Renders the report sections as HTML and plain text.
+    pub async fn build_operator_report(
+        &self,
+        schedule: &OperatorSchedule,
+        now: u64,
+    ) -> (String, String) {
+        let from = now.saturating_sub(schedule.period.as_secs());
+        let mut html = String::with_capacity(2048);
+        let mut text = String::with_capacity(1024);
+        let _ = write!(
+            html,
+            concat!(
+                "<html><body style=\"font-family: sans-serif\">",
+                "<h2>Operator report \"{}\"</h2>",
+                "<p>Period: {} &ndash; {}</p>"
+            ),
+            html_escape(&schedule.id),
+            DateTime::from_timestamp(from as i64).to_rfc3339(),
+            DateTime::from_timestamp(now as i64).to_rfc3339()
+        );
+        let _ = write!(
+            text,
+            "Operator report \"{}\"\r\nPeriod: {} - {}\r\n",
+            schedule.id,
+            DateTime::from_timestamp(from as i64).to_rfc3339(),
+            DateTime::from_timestamp(now as i64).to_rfc3339()
+        );
+
+        let telemetry = self.report.operator.telemetry.summary(from, now);
+        for section in &schedule.sections {
+            match section {
+                OperatorSection::Queue => {
+                    let backlog = self
+                        .queue_backlog()
+                        .await
+                        .map_or_else(|| "unavailable".to_string(), |size| size.to_string());
+                    let _ = write!(
+                        html,
+                        "<h3>Queue</h3><table border=\"1\" cellpadding=\"4\"><tr><td>Messages queued</td><td>{backlog}</td></tr></table>"
+                    );
+                    let _ = write!(text, "\r\nQueue\r\n  Messages queued: {backlog}\r\n");
+                }
+                OperatorSection::Deferred => {
+                    let mut domains = self
+                        .queue
+                        .delivery_analytics(None, from, now, false)
+                        .into_iter()
+                        .filter(|domain| domain.stats.temporary_failures > 0)
+                        .collect::<Vec<_>>();
+                    domains.sort_unstable_by(|a, b| {
+                        b.stats
+                            .temporary_failures
+                            .cmp(&a.stats.temporary_failures)
+                            .then_with(|| a.domain.cmp(&b.domain))
+                    });
+                    domains.truncate(schedule.top_domains);
+
+                    html.push_str("<h3>Top deferred domains</h3>");
+                    text.push_str("\r\nTop deferred domains\r\n");
+                    if !domains.is_empty() {
+                        html.push_str(concat!(
+                            "<table border=\"1\" cellpadding=\"4\"><tr><th>Domain</th>",
+                            "<th>Attempts</th><th>Deferred</th><th>Failed</th>",
+                            "<th>Main reason</th></tr>"
+                        ));
+                        for domain in &domains {
+                            let reason = domain
+                                .stats
+                                .failures
+                                .iter()
+                                .max_by_key(|(_, count)| **count)
+                                .map(|(reason, _)| reason.as_str())
+                                .unwrap_or_default();
+                            let _ = write!(
+                                html,
+                                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                                html_escape(&domain.domain),
+                                domain.stats.attempts,
+                                domain.stats.temporary_failures,
+                                domain.stats.permanent_failures,
+                                reason
+                            );
+                            let _ = write!(
+                                text,
+                                "  {}: {} deferred, {} failed of {} attempts ({})\r\n",
+                                domain.domain,
+                                domain.stats.temporary_failures,
+                                domain.stats.permanent_failures,
+                                domain.stats.attempts,
+                                reason
+                            );
+                        }
+                        html.push_str("</table>");
+                    } else {
+                        html.push_str("<p>No deliveries were deferred.</p>");
+                        text.push_str("  No deliveries were deferred.\r\n");
+                    }
+                    let _ = write!(html, "<p>TLS downgrades: {}</p>", telemetry.tls_downgrades);
+                    let _ = write!(text, "  TLS downgrades: {}\r\n", telemetry.tls_downgrades);
+                }
+                OperatorSection::Spam => {
+                    let rate = if telemetry.spam_scanned > 0 {
+                        (telemetry.spam_detected as f64 / telemetry.spam_scanned as f64) * 100.0
+                    } else {
+                        0.0
+                    };
+                    let _ = write!(
+                        html,
+                        concat!(
+                            "<h3>Spam filter</h3><table border=\"1\" cellpadding=\"4\">",
+                            "<tr><td>Messages scanned</td><td>{}</td></tr>",
+                            "<tr><td>Spam detected</td><td>{}</td></tr>",
+                            "<tr><td>Catch rate</td><td>{:.1}%</td></tr></table>"
+                        ),
+                        telemetry.spam_scanned, telemetry.spam_detected, rate
+                    );
+                    let _ = write!(
+                        text,
+                        concat!(
+                            "\r\nSpam filter\r\n  Messages scanned: {}\r\n",
+                            "  Spam detected: {}\r\n  Catch rate: {:.1}%\r\n"
+                        ),
+                        telemetry.spam_scanned, telemetry.spam_detected, rate
+                    );
+                }
+                OperatorSection::Auth => {
+                    let _ = write!(
+                        html,
+                        concat!(
+                            "<h3>Authentication</h3><table border=\"1\" cellpadding=\"4\">",
+                            "<tr><td>Failed attempts</td><td>{}</td></tr></table>"
+                        ),
+                        telemetry.auth_failures
+                    );
+                    let _ = write!(
+                        text,
+                        "\r\nAuthentication\r\n  Failed attempts: {}\r\n",
+                        telemetry.auth_failures
+                    );
+                }
+            }
+        }
+        html.push_str("</body></html>");
+
+        (html, text)
+    }
+
+    pub fn operator_reports(&self) -> Vec<OperatorReportStatus> {
+        let now_instant = Instant::now();
+        let now = now();
+        let schedules = self.report.operator.schedules.lock();
+        self.report
+            .config
+            .operator
+            .schedules
+            .iter()
+            .filter_map(|schedule| {
+                let state = schedules.get(&schedule.id)?;
+                OperatorReportStatus {
+                    id: schedule.id.clone(),
+                    enable: state.enable,
+                    to: schedule.rcpts.clone(),
+                    sections: schedule.sections.clone(),
+                    next_run: DateTime::from_timestamp(
+                        (now + state
+                            .next_due
+                            .saturating_duration_since(now_instant)
+                            .as_secs()) as i64,
+                    )
+                    .to_rfc3339(),
+                    last_sent: state
+                        .last_sent
+                        .map(|last_sent| DateTime::from_timestamp(last_sent as i64).to_rfc3339()),
+                }
+                .into()
+            })
+            .collect()
+    }
+
+    pub async fn set_operator_report(&self, id: &str, enable: bool) -> store::Result<bool> {
+        if !self.report.operator.schedules.lock().contains_key(id) {
+            return Ok(false);
+        }
+        self.queue
+            .config
+            .data_store
+            .config_set(
+                [ConfigKey {
+                    key: format!("{OPERATOR_SCHEDULE_KEY}.{id}.enable"),
+                    value: enable.to_string(),
+                }]
+                .into_iter(),
+            )
+            .await?;
+        if let Some(state) = self.report.operator.schedules.lock().get_mut(id) {
+            state.enable = enable;
+        }
+        Ok(true)
+    }
+
+    async fn queue_backlog(&self) -> Option<usize> {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.queue
+            .tx
+            .send(queue::Event::Manage(QueueRequest::List {
+                from: None,
+                to: None,
+                before: None,
+                after: None,
+                reason: None,
+                result_tx,
+            }))
+            .await
+            .ok()?;
+        tokio::time::timeout(Duration::from_secs(30), result_rx)
+            .await
+            .ok()?
+            .ok()
+            .map(|ids| ids.len())
+    }
+}
+
+fn html_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
@@ -88,7 +88,8 @@ impl SpawnReport for mpsc::Receiver<Event> {
             let mut last_cleanup = Instant::now();
 
             loop {
-                match tokio::time::timeout(scheduler.wake_up_time(), self.recv()).await {
+                let wake_up_time = core.report.operator.wake_up_time(scheduler.wake_up_time());
+                match tokio::time::timeout(wake_up_time, self.recv()).await {
                     Ok(Some(event)) => match event {
                         Event::Dmarc(event) => {
                             scheduler.schedule_dmarc(event, &core).await;
@@ -172,6 +173,9 @@ impl SpawnReport for mpsc::Receiver<Event> {
                             }
                         }
 
+                        // Send scheduled operator reports
+                        core.send_due_operator_reports();
+
                         // Cleanup expired throttles
                         if last_cleanup.elapsed().as_secs() >= 86400 {
                             last_cleanup = Instant::now();
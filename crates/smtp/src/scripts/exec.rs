@@ -41,6 +41,17 @@ impl<T: SessionStream> Session<T> {
             .set_variable("remote_ip.reverse", self.data.remote_ip.to_reverse_name())
             .set_variable("helo_domain", self.data.helo_domain.to_lowercase())
             .set_variable("authenticated_as", self.data.authenticated_as.clone())
+            .set_variable(
+                "tenant",
+                self.data
+                    .authenticated_emails
+                    .first()
+                    .and_then(|email| email.rsplit_once('@'))
+                    .map(|(_, domain)| domain.to_lowercase())
+                    .unwrap_or_default(),
+            )
+            .set_variable("listener", self.instance.id.clone())
+            .set_variable("tls", self.stream.is_tls() as u64)
             .set_variable(
                 "now",
                 SystemTime::now()
@@ -66,13 +77,32 @@ impl<T: SessionStream> Session<T> {
             .set_variable("tls.version", tls_version)
             .set_variable("tls.cipher", tls_cipher)
             .set_variable("stage", stage);
+        if let Some(cert) = self.stream.tls_client_certificate() {
+            params = params
+                .set_variable("tls.client.subject", cert.subject)
+                .set_variable("tls.client.issuer", cert.issuer)
+                .set_variable("tls.client.serial", cert.serial)
+                .set_variable("tls.client.cn", cert.common_name.unwrap_or_default())
+                .set_variable(
+                    "tls.client.email",
+                    cert.emails.into_iter().next().unwrap_or_default(),
+                )
+                .set_variable("tls.client.fingerprint", cert.fingerprint)
+                .set_variable(
+                    "tls.client.trusted",
+                    self.data.trusted_peer.is_some() as u64,
+                );
+        }
+        if let Some(sender_verify) = &self.data.sender_verify {
+            params = params.set_variable("sender_verify.result", sender_verify.as_str());
+        }
         if let Some(ip_rev) = &self.data.iprev {
             params = params.set_variable("iprev.result", ip_rev.result().as_str());
             if let Some(ptr) = ip_rev.ptr.as_ref().and_then(|addrs| addrs.first()) {
-                params = params.set_variable(
-                    "iprev.ptr",
-                    ptr.strip_suffix('.').unwrap_or(ptr).to_lowercase(),
-                );
+                let ptr = ptr.strip_suffix('.').unwrap_or(ptr).to_lowercase();
+                params = params
+                    .set_variable("iprev.ptr", ptr.clone())
+                    .set_variable("remote_host", ptr);
             }
         }
 
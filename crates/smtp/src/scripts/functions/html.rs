@@ -80,6 +80,12 @@ pub fn fn_html_attr<'x>(_: &'x Context<'x, SieveContext>, v: Vec<Variable>) -> V
         .unwrap_or_default()
 }
 
+pub fn fn_html_analyze<'x>(_: &'x Context<'x, SieveContext>, v: Vec<Variable>) -> Variable {
+    v[0].as_array()
+        .map(|tokens| html_analyze(tokens).into_variable())
+        .unwrap_or_default()
+}
+
 pub fn html_to_tokens(input: &str) -> Vec<Variable> {
     let input = input.as_bytes();
     let mut iter = input.iter().enumerate();
@@ -439,3 +445,234 @@ pub fn get_attribute<'x>(tag: &'x str, attr_name: &str) -> Option<&'x str> {
 
     None
 }
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct HtmlAnalysis {
+    pub text_chars: u32,
+    pub hidden_chars: u32,
+    pub tiny_font_chars: u32,
+    pub same_color_chars: u32,
+    pub remote_images: u32,
+    pub tracking_pixels: u32,
+    pub forms: u32,
+    pub password_inputs: u32,
+}
+
+#[derive(Clone, Default)]
+struct HtmlElement {
+    name: String,
+    hidden: bool,
+    tiny_font: bool,
+    color: Option<String>,
+    background: Option<String>,
+}
+
+impl HtmlAnalysis {
+    pub fn into_variable(self) -> Variable {
+        vec![
+            Variable::Integer(self.text_chars as i64),
+            Variable::Integer(self.hidden_chars as i64),
+            Variable::Integer(self.tiny_font_chars as i64),
+            Variable::Integer(self.same_color_chars as i64),
+            Variable::Integer(self.remote_images as i64),
+            Variable::Integer(self.tracking_pixels as i64),
+            Variable::Integer(self.forms as i64),
+            Variable::Integer(self.password_inputs as i64),
+        ]
+        .into()
+    }
+}
+
+pub fn html_analyze(tokens: &[Variable]) -> HtmlAnalysis {
+    let mut result = HtmlAnalysis::default();
+    let mut stack: Vec<HtmlElement> = Vec::new();
+
+    for token in tokens {
+        let token = token.to_string();
+        if let Some(text) = token.strip_prefix('_') {
+            let chars = text.chars().filter(|ch| !ch.is_whitespace()).count() as u32;
+            result.text_chars += chars;
+            if let Some(element) = stack.last() {
+                if element.hidden {
+                    result.hidden_chars += chars;
+                } else if element.tiny_font {
+                    result.tiny_font_chars += chars;
+                } else if element.color.is_some() && element.color == element.background {
+                    result.same_color_chars += chars;
+                }
+            }
+        } else if let Some(tag) = token.strip_prefix("</") {
+            let name = html_tag_name(tag);
+            if let Some(pos) = stack.iter().rposition(|element| element.name == name) {
+                stack.truncate(pos);
+            }
+        } else if let Some(tag) = token.strip_prefix('<') {
+            if tag.starts_with("!--") {
+                continue;
+            }
+            let name = html_tag_name(tag);
+            let mut element = stack.last().cloned().unwrap_or_default();
+            element.name = name.to_string();
+
+            // Legacy presentation attributes
+            if let Some(color) = get_attribute(token.as_ref(), "bgcolor") {
+                element.background = html_color(color);
+            }
+            if name == "font" {
+                if let Some(color) = get_attribute(token.as_ref(), "color") {
+                    element.color = html_color(color);
+                }
+                if get_attribute(token.as_ref(), "size").map_or(false, |size| {
+                    size.trim_matches(|c| c == '\'' || c == '"').trim() == "0"
+                }) {
+                    element.tiny_font = true;
+                }
+            }
+
+            // Inline styles
+            let mut width = get_attribute(token.as_ref(), "width").and_then(html_dimension);
+            let mut height = get_attribute(token.as_ref(), "height").and_then(html_dimension);
+            if let Some(style) = get_attribute(token.as_ref(), "style") {
+                for (property, value) in html_style_properties(style) {
+                    match property.as_str() {
+                        "display" if value == "none" => element.hidden = true,
+                        "visibility" if value == "hidden" => element.hidden = true,
+                        "opacity" if value.parse::<f64>().map_or(false, |v| v <= 0.0) => {
+                            element.hidden = true
+                        }
+                        "font-size" if html_is_tiny_font(&value) => element.tiny_font = true,
+                        "color" => element.color = html_color(&value),
+                        "background-color" | "background" => {
+                            element.background = html_color(&value)
+                        }
+                        "width" => width = html_dimension(&value),
+                        "height" => height = html_dimension(&value),
+                        _ => (),
+                    }
+                }
+            }
+
+            match name {
+                "img" => {
+                    let is_remote = get_attribute(token.as_ref(), "src").map_or(false, |src| {
+                        let src = src.trim().to_ascii_lowercase();
+                        src.starts_with("http://") || src.starts_with("https://")
+                    });
+                    if is_remote {
+                        result.remote_images += 1;
+                        if element.hidden
+                            || (width.map_or(false, |w| w <= 1) && height.map_or(false, |h| h <= 1))
+                        {
+                            result.tracking_pixels += 1;
+                        }
+                    }
+                }
+                "form" => {
+                    result.forms += 1;
+                }
+                "input" => {
+                    if get_attribute(token.as_ref(), "type")
+                        .map_or(false, |t| t.trim().eq_ignore_ascii_case("password"))
+                    {
+                        result.password_inputs += 1;
+                    }
+                }
+                _ => (),
+            }
+
+            if !tag.ends_with('/')
+                && !matches!(
+                    name,
+                    "area"
+                        | "base"
+                        | "br"
+                        | "col"
+                        | "embed"
+                        | "hr"
+                        | "img"
+                        | "input"
+                        | "link"
+                        | "meta"
+                        | "param"
+                        | "source"
+                        | "track"
+                        | "wbr"
+                )
+            {
+                stack.push(element);
+            }
+        }
+    }
+
+    result
+}
+
+fn html_tag_name(tag: &str) -> &str {
+    let tag = tag.trim_start();
+    tag.split(|c: char| c.is_ascii_whitespace() || c == '/')
+        .next()
+        .unwrap_or_default()
+}
+
+fn html_style_properties(style: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    style
+        .trim_matches(|c| c == '\'' || c == '"')
+        .split(';')
+        .filter_map(|declaration| {
+            let (property, value) = declaration.split_once(':')?;
+            let value = value.trim().to_ascii_lowercase();
+            let value = value
+                .strip_suffix("!important")
+                .map(|v| v.trim_end().to_string())
+                .unwrap_or(value);
+            Some((property.trim().to_ascii_lowercase(), value))
+        })
+}
+
+fn html_is_tiny_font(value: &str) -> bool {
+    let number_end = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    if let Ok(size) = value[..number_end].parse::<f64>() {
+        match value[number_end..].trim() {
+            "" | "px" | "pt" => size <= 2.0,
+            "em" | "rem" => size <= 0.2,
+            "%" => size <= 20.0,
+            _ => false,
+        }
+    } else {
+        false
+    }
+}
+
+fn html_dimension(value: &str) -> Option<u32> {
+    let value = value.trim_matches(|c| c == '\'' || c == '"').trim();
+    value
+        .strip_suffix("px")
+        .unwrap_or(value)
+        .trim()
+        .parse::<u32>()
+        .ok()
+}
+
+fn html_color(value: &str) -> Option<String> {
+    let value = value
+        .trim_matches(|c| c == '\'' || c == '"')
+        .trim()
+        .to_ascii_lowercase();
+    let color = match value.as_str() {
+        "white" => "#ffffff".to_string(),
+        "black" => "#000000".to_string(),
+        "" | "transparent" | "inherit" | "initial" | "currentcolor" => return None,
+        _ => match value.strip_prefix('#') {
+            Some(hex) if hex.len() == 3 => hex.chars().fold(String::from("#"), |mut color, ch| {
+                color.push(ch);
+                color.push(ch);
+                color
+            }),
+            _ => value,
+        },
+    };
+
+    Some(color)
+}
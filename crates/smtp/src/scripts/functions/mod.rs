@@ -55,6 +55,7 @@ pub fn register_functions() -> FunctionMap<SieveContext> {
         .with_function("is_email", fn_is_email)
         .with_function("thread_name", fn_thread_name)
         .with_function("html_to_text", fn_html_to_text)
+        .with_function("html_analyze", fn_html_analyze)
         .with_function("is_uppercase", fn_is_uppercase)
         .with_function("is_lowercase", fn_is_lowercase)
         .with_function("has_digits", fn_has_digits)
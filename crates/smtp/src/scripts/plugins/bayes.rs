@@ -29,12 +29,49 @@ use nlp::{
     tokenizers::osb::{OsbToken, OsbTokenizer},
 };
 use sieve::{runtime::Variable, FunctionMap};
-use store::{write::key::KeySerializer, LookupKey, LookupStore, LookupValue, U64_LEN};
+use store::{
+    write::{key::KeySerializer, now},
+    LookupKey, LookupStore, LookupValue, U64_LEN,
+};
 use tokio::runtime::Handle;
 
 use crate::config::scripts::SieveContext;
 
-use super::{lookup::VariableExists, PluginContext};
+use super::{
+    lookup::{VariableExists, VariableWrapper},
+    PluginContext,
+};
+
+const KEY_OPT_OUT: &str = "bayes:opt-out:";
+const KEY_DAILY_LEARNS: &str = "bayes:learns:";
+const KEY_TOKENS: &[u8] = b"bayes:tokens";
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct BayesStats {
+    #[serde(rename = "spamLearns")]
+    pub spam_learns: u32,
+    #[serde(rename = "hamLearns")]
+    pub ham_learns: u32,
+    #[serde(rename = "spamLearnsToday")]
+    pub spam_learns_today: i64,
+    #[serde(rename = "hamLearnsToday")]
+    pub ham_learns_today: i64,
+    #[serde(rename = "tokensLearned")]
+    pub tokens_learned: i64,
+    #[serde(rename = "spamRatio")]
+    pub spam_ratio: f64,
+    pub health: BayesHealth,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BayesHealth {
+    #[default]
+    Untrained,
+    Learning,
+    Imbalanced,
+    Ready,
+}
 
 pub fn register_train(plugin_id: u32, fnc_map: &mut FunctionMap<SieveContext>) {
     fnc_map.set_external_function("bayes_train", plugin_id, 3);
@@ -52,6 +89,14 @@ pub fn register_is_balanced(plugin_id: u32, fnc_map: &mut FunctionMap<SieveConte
     fnc_map.set_external_function("bayes_is_balanced", plugin_id, 3);
 }
 
+pub fn register_autolearn(plugin_id: u32, fnc_map: &mut FunctionMap<SieveContext>) {
+    fnc_map.set_external_function("bayes_autolearn", plugin_id, 4);
+}
+
+pub fn register_opted_out(plugin_id: u32, fnc_map: &mut FunctionMap<SieveContext>) {
+    fnc_map.set_external_function("bayes_opted_out", plugin_id, 2);
+}
+
 pub fn exec_train(ctx: PluginContext<'_>) -> Variable {
     train(ctx, true)
 }
@@ -87,14 +132,30 @@ fn train(ctx: PluginContext<'_>, is_train: bool) -> Variable {
     let handle = ctx.handle;
     let ctx = ctx.core.sieve.runtime.context();
 
+    if is_train {
+        train_model(span, handle, ctx, store, text.as_ref(), is_spam).into()
+    } else {
+        //TODO: Implement untrain
+        false.into()
+    }
+}
+
+fn train_model(
+    span: &tracing::Span,
+    handle: &Handle,
+    ctx: &SieveContext,
+    store: &LookupStore,
+    text: &str,
+    is_spam: bool,
+) -> bool {
     // Train the model
     let mut model = BayesModel::default();
     model.train(
-        OsbTokenizer::new(BayesTokenizer::new(text.as_ref(), &ctx.psl), 5),
+        OsbTokenizer::new(BayesTokenizer::new(text, &ctx.psl), 5),
         is_spam,
     );
     if model.weights.is_empty() {
-        return false.into();
+        return false;
     }
 
     tracing::debug!(
@@ -106,39 +167,14 @@ fn train(ctx: PluginContext<'_>, is_train: bool) -> Variable {
     );
 
     // Update weight and invalidate cache
-    if is_train {
-        for (hash, weights) in model.weights {
-            if handle
-                .block_on(
-                    store.key_set(
-                        KeySerializer::new(U64_LEN)
-                            .write(hash.h1)
-                            .write(hash.h2)
-                            .finalize(),
-                        LookupValue::Counter {
-                            num: weights.into(),
-                        },
-                    ),
-                )
-                .is_err()
-            {
-                return false.into();
-            }
-            ctx.bayes_cache.invalidate(&hash);
-        }
-
-        // Update training counts
-        let weights = if is_spam {
-            Weights { spam: 1, ham: 0 }
-        } else {
-            Weights { spam: 0, ham: 1 }
-        };
+    let num_tokens = model.weights.len() as i64;
+    for (hash, weights) in model.weights {
         if handle
             .block_on(
                 store.key_set(
                     KeySerializer::new(U64_LEN)
-                        .write(0u64)
-                        .write(0u64)
+                        .write(hash.h1)
+                        .write(hash.h2)
                         .finalize(),
                     LookupValue::Counter {
                         num: weights.into(),
@@ -147,16 +183,43 @@ fn train(ctx: PluginContext<'_>, is_train: bool) -> Variable {
             )
             .is_err()
         {
-            return false.into();
+            return false;
         }
+        ctx.bayes_cache.invalidate(&hash);
+    }
+
+    // Update training counts
+    let weights = if is_spam {
+        Weights { spam: 1, ham: 0 }
     } else {
-        //TODO: Implement untrain
-        return false.into();
+        Weights { spam: 0, ham: 1 }
+    };
+    if handle
+        .block_on(
+            store.key_set(
+                KeySerializer::new(U64_LEN)
+                    .write(0u64)
+                    .write(0u64)
+                    .finalize(),
+                LookupValue::Counter {
+                    num: weights.into(),
+                },
+            ),
+        )
+        .is_err()
+        || handle
+            .block_on(store.key_set(
+                KEY_TOKENS.to_vec(),
+                LookupValue::Counter { num: num_tokens },
+            ))
+            .is_err()
+    {
+        return false;
     }
 
     ctx.bayes_cache.invalidate(&TokenHash::default());
 
-    true.into()
+    true
 }
 
 pub fn exec_classify(ctx: PluginContext<'_>) -> Variable {
@@ -296,15 +359,7 @@ pub fn exec_is_balanced(ctx: PluginContext<'_>) -> Variable {
         return Variable::default();
     };
 
-    let result = if spam_learns > 0.0 || ham_learns > 0.0 {
-        if learn_spam {
-            (spam_learns / (ham_learns + 1.0)) <= 1.0 / min_balance
-        } else {
-            (ham_learns / (spam_learns + 1.0)) <= 1.0 / min_balance
-        }
-    } else {
-        true
-    };
+    let result = is_balanced(spam_learns, ham_learns, learn_spam, min_balance);
 
     tracing::debug!(
         parent: span,
@@ -319,6 +374,306 @@ pub fn exec_is_balanced(ctx: PluginContext<'_>) -> Variable {
     result.into()
 }
 
+pub fn exec_autolearn(ctx: PluginContext<'_>) -> Variable {
+    let span = ctx.span;
+    let store = match &ctx.arguments[0] {
+        Variable::String(v) if !v.is_empty() => ctx.core.sieve.lookup_stores.get(v.as_ref()),
+        _ => Some(&ctx.core.queue.config.lookup_store),
+    };
+    let store = if let Some(store) = store {
+        store
+    } else {
+        tracing::warn!(
+            parent: span,
+            context = "sieve:bayes_autolearn",
+            event = "failed",
+            reason = "Unknown store id",
+            lookup_id = ctx.arguments[0].to_string().as_ref(),
+        );
+        return false.into();
+    };
+    let text = ctx.arguments[1].to_string();
+    if text.is_empty() {
+        return false.into();
+    }
+    let score = to_f64(&ctx.arguments[2]);
+
+    // Parameters: ham threshold, spam threshold, balance, daily limit,
+    // minimum confidence and learns required for a mature model
+    let mut ham_threshold = -0.5;
+    let mut spam_threshold = 6.0;
+    let mut min_balance = 0.0;
+    let mut daily_limit = 0;
+    let mut min_confidence = 0.0;
+    let mut mature_learns = 0.0;
+    if let Some(params) = ctx.arguments[3].as_array() {
+        for (pos, param) in params.iter().enumerate() {
+            let value = to_f64(param);
+            match pos {
+                0 => ham_threshold = value,
+                1 => spam_threshold = value,
+                2 => min_balance = value,
+                3 => daily_limit = value as i64,
+                4 => min_confidence = value,
+                5 => mature_learns = value,
+                _ => break,
+            }
+        }
+    }
+
+    // Obtain training counts
+    let handle = ctx.handle;
+    let ctx = ctx.core.sieve.runtime.context();
+    let (spam_learns, ham_learns) = if let Some(weights) =
+        ctx.bayes_cache
+            .get_or_update(TokenHash::default(), handle, store)
+    {
+        (weights.spam, weights.ham)
+    } else {
+        tracing::warn!(
+            parent: span,
+            context = "sieve:bayes_autolearn",
+            event = "failed",
+            reason = "Failed to obtain training counts",
+        );
+        return false.into();
+    };
+
+    // Raise the bar as the model matures, so that only high-confidence
+    // samples are learned once enough training data is available
+    let maturity = if mature_learns > 0.0 {
+        (std::cmp::min(spam_learns, ham_learns) as f64 / mature_learns).min(1.0)
+    } else {
+        0.0
+    };
+    let spam_threshold = spam_threshold + spam_threshold.abs() * 0.5 * maturity;
+    let ham_threshold = ham_threshold - ham_threshold.abs().max(1.0) * 0.5 * maturity;
+    let is_spam = if score >= spam_threshold {
+        true
+    } else if score <= ham_threshold {
+        false
+    } else {
+        return false.into();
+    };
+
+    // Keep the classes balanced
+    if min_balance > 0.0
+        && !is_balanced(spam_learns as f64, ham_learns as f64, is_spam, min_balance)
+    {
+        tracing::debug!(
+            parent: span,
+            context = "sieve:bayes_autolearn",
+            event = "skip",
+            reason = "Unbalanced",
+            is_spam = is_spam,
+            spam_learns = %spam_learns,
+            ham_learns = %ham_learns);
+        return false.into();
+    }
+
+    // Skip samples the trained model disagrees with
+    let classifier = BayesClassifier::default();
+    if min_confidence > 0.0
+        && spam_learns >= classifier.min_learns
+        && ham_learns >= classifier.min_learns
+    {
+        if let Some(probability) = classifier.classify(
+            OsbTokenizer::<_, TokenHash>::new(BayesTokenizer::new(text.as_ref(), &ctx.psl), 5)
+                .filter_map(|t| {
+                    OsbToken {
+                        inner: ctx.bayes_cache.get_or_update(t.inner, handle, store)?,
+                        idx: t.idx,
+                    }
+                    .into()
+                }),
+            ham_learns,
+            spam_learns,
+        ) {
+            if (is_spam && probability < 1.0 - min_confidence)
+                || (!is_spam && probability > min_confidence)
+            {
+                tracing::debug!(
+                    parent: span,
+                    context = "sieve:bayes_autolearn",
+                    event = "skip",
+                    reason = "Low confidence",
+                    is_spam = is_spam,
+                    probability = probability);
+                return false.into();
+            }
+        }
+    }
+
+    // Enforce the daily sampling limit for this class
+    let daily_key = daily_learns_key(is_spam, now() / 86400);
+    if daily_limit > 0 {
+        match handle
+            .block_on(store.key_get::<VariableExists>(LookupKey::Counter(daily_key.clone())))
+        {
+            Ok(LookupValue::Counter { num }) if num >= daily_limit => {
+                tracing::debug!(
+                    parent: span,
+                    context = "sieve:bayes_autolearn",
+                    event = "skip",
+                    reason = "Daily limit reached",
+                    is_spam = is_spam,
+                    daily_limit = daily_limit);
+                return false.into();
+            }
+            Ok(_) => {}
+            Err(_) => return false.into(),
+        }
+    }
+
+    if !train_model(span, handle, ctx, store, text.as_ref(), is_spam) {
+        return false.into();
+    }
+
+    handle
+        .block_on(store.key_set(daily_key, LookupValue::Counter { num: 1 }))
+        .is_ok()
+        .into()
+}
+
+pub fn exec_opted_out(ctx: PluginContext<'_>) -> Variable {
+    let store = match &ctx.arguments[0] {
+        Variable::String(v) if !v.is_empty() => ctx.core.sieve.lookup_stores.get(v.as_ref()),
+        _ => Some(&ctx.core.queue.config.lookup_store),
+    };
+    let store = if let Some(store) = store {
+        store
+    } else {
+        tracing::warn!(
+            parent: ctx.span,
+            context = "sieve:bayes_opted_out",
+            event = "failed",
+            reason = "Unknown store id",
+            lookup_id = ctx.arguments[0].to_string().as_ref(),
+        );
+        return false.into();
+    };
+
+    let addresses = match &ctx.arguments[1] {
+        Variable::Array(items) => items.iter().map(|v| v.to_string()).collect::<Vec<_>>(),
+        v if !v.is_empty() => vec![v.to_string()],
+        _ => return false.into(),
+    };
+
+    for address in addresses {
+        if let Ok(true) = ctx.handle.block_on(is_opted_out(store, address.as_ref())) {
+            return true.into();
+        }
+    }
+
+    false.into()
+}
+
+pub async fn is_opted_out(store: &LookupStore, address: &str) -> store::Result<bool> {
+    store
+        .key_get::<VariableWrapper>(LookupKey::Key(opt_out_key(address)))
+        .await
+        .map(|value| match value {
+            LookupValue::Value { value, .. } => value.into_inner().to_bool(),
+            _ => false,
+        })
+}
+
+pub async fn set_opt_out(store: &LookupStore, address: &str, opt_out: bool) -> store::Result<()> {
+    store
+        .key_set(
+            opt_out_key(address),
+            LookupValue::Value {
+                value: bincode::serialize(&Variable::from(opt_out)).unwrap_or_default(),
+                expires: 0,
+            },
+        )
+        .await
+}
+
+pub async fn stats(store: &LookupStore) -> store::Result<BayesStats> {
+    let mut stats = BayesStats::default();
+    if let LookupValue::Counter { num } = store
+        .key_get::<VariableExists>(LookupKey::Counter(
+            KeySerializer::new(U64_LEN)
+                .write(0u64)
+                .write(0u64)
+                .finalize(),
+        ))
+        .await?
+    {
+        let weights = Weights::from(num);
+        stats.spam_learns = weights.spam;
+        stats.ham_learns = weights.ham;
+    }
+    let today = now() / 86400;
+    for (is_spam, learns) in [
+        (true, &mut stats.spam_learns_today),
+        (false, &mut stats.ham_learns_today),
+    ] {
+        if let LookupValue::Counter { num } = store
+            .key_get::<VariableExists>(LookupKey::Counter(daily_learns_key(is_spam, today)))
+            .await?
+        {
+            *learns = num;
+        }
+    }
+    if let LookupValue::Counter { num } = store
+        .key_get::<VariableExists>(LookupKey::Counter(KEY_TOKENS.to_vec()))
+        .await?
+    {
+        stats.tokens_learned = num;
+    }
+
+    let total = stats.spam_learns as f64 + stats.ham_learns as f64;
+    let min_learns = BayesClassifier::default().min_learns;
+    if total > 0.0 {
+        stats.spam_ratio = stats.spam_learns as f64 / total;
+        stats.health = if stats.spam_learns < min_learns || stats.ham_learns < min_learns {
+            BayesHealth::Learning
+        } else if !(0.25..=0.75).contains(&stats.spam_ratio) {
+            // One class has more than three times the learns of the other
+            BayesHealth::Imbalanced
+        } else {
+            BayesHealth::Ready
+        };
+    }
+
+    Ok(stats)
+}
+
+fn is_balanced(spam_learns: f64, ham_learns: f64, learn_spam: bool, min_balance: f64) -> bool {
+    if spam_learns > 0.0 || ham_learns > 0.0 {
+        if learn_spam {
+            (spam_learns / (ham_learns + 1.0)) <= 1.0 / min_balance
+        } else {
+            (ham_learns / (spam_learns + 1.0)) <= 1.0 / min_balance
+        }
+    } else {
+        true
+    }
+}
+
+fn daily_learns_key(is_spam: bool, day: u64) -> Vec<u8> {
+    format!(
+        "{KEY_DAILY_LEARNS}{}:{day}",
+        if is_spam { "spam" } else { "ham" }
+    )
+    .into_bytes()
+}
+
+fn opt_out_key(address: &str) -> Vec<u8> {
+    format!("{KEY_OPT_OUT}{}", address.trim().to_lowercase()).into_bytes()
+}
+
+fn to_f64(value: &Variable) -> f64 {
+    match value {
+        Variable::Float(n) => *n,
+        Variable::Integer(n) => *n as f64,
+        Variable::String(s) => s.trim().parse().unwrap_or_default(),
+        _ => 0.0,
+    }
+}
+
 trait LookupOrInsert {
     fn get_or_update(
         &self,
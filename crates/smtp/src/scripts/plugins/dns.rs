@@ -21,12 +21,16 @@
  * for more details.
 */
 
-use std::net::IpAddr;
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    sync::Arc,
+    time::Instant,
+};
 
 use mail_auth::{Error, IpLookupStrategy};
 use sieve::{runtime::Variable, FunctionMap};
 
-use crate::config::scripts::SieveContext;
+use crate::{config::scripts::SieveContext, core::Resolvers};
 
 use super::PluginContext;
 
@@ -119,6 +123,26 @@ pub fn exec(ctx: PluginContext<'_>) -> Variable {
                 .into(),
             Err(err) => err.short_error().into(),
         }
+    } else if record_type.eq_ignore_ascii_case("rbl") {
+        #[cfg(feature = "test_mode")]
+        {
+            if entry.contains(".168.192.") {
+                let parts = entry.split('.').collect::<Vec<_>>();
+                return vec![Variable::from(format!("127.0.{}.{}", parts[1], parts[0]))].into();
+            }
+        }
+
+        match ctx
+            .handle
+            .block_on(ctx.core.resolvers.rbl_lookup(entry.as_ref()))
+        {
+            Ok(result) => result
+                .iter()
+                .map(|ip| Variable::from(ip.to_string()))
+                .collect::<Vec<_>>()
+                .into(),
+            Err(err) => err.short_error().into(),
+        }
     } else if record_type.eq_ignore_ascii_case("ipv6") {
         match ctx
             .handle
@@ -185,6 +209,21 @@ pub fn exec_exists(ctx: PluginContext<'_>) -> Variable {
             Err(Error::DnsRecordNotFound(_)) => 0,
             Err(_) => -1,
         }
+    } else if record_type.eq_ignore_ascii_case("rbl") {
+        #[cfg(feature = "test_mode")]
+        {
+            if entry.starts_with("2.0.168.192.") {
+                return 1.into();
+            }
+        }
+
+        match ctx
+            .handle
+            .block_on(ctx.core.resolvers.rbl_lookup(entry.as_ref()))
+        {
+            Ok(result) => i64::from(!result.is_empty()),
+            Err(_) => -1,
+        }
     } else if record_type.eq_ignore_ascii_case("ipv6") {
         match ctx
             .handle
@@ -215,3 +254,27 @@ impl ShortError for mail_auth::Error {
         }
     }
 }
+
+impl Resolvers {
+    pub async fn rbl_lookup(&self, name: &str) -> mail_auth::Result<Arc<Vec<Ipv4Addr>>> {
+        if let Some(result) = self.cache.rbl.get(name) {
+            self.cache.rbl_stats.hit();
+            return Ok(result);
+        }
+        self.cache.rbl_stats.miss();
+
+        // Unlisted entries are cached as well, DNSBL queries are mostly NXDOMAIN
+        let (result, ttl) = match self.dns.ipv4_lookup(name).await {
+            Ok(result) if !result.is_empty() => (result, self.cache.rbl_positive_ttl),
+            Ok(result) => (result, self.cache.rbl_negative_ttl),
+            Err(Error::DnsRecordNotFound(_)) => (Arc::new(Vec::new()), self.cache.rbl_negative_ttl),
+            Err(err) => return Err(err),
+        };
+
+        self.cache.rbl_stats.insert();
+        Ok(self
+            .cache
+            .rbl
+            .insert(name.to_string(), result, Instant::now() + ttl))
+    }
+}
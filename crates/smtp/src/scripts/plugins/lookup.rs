@@ -59,6 +59,10 @@ pub fn register_local_domain(plugin_id: u32, fnc_map: &mut FunctionMap<SieveCont
     fnc_map.set_external_function("is_local_domain", plugin_id, 2);
 }
 
+pub fn register_local_display_name(plugin_id: u32, fnc_map: &mut FunctionMap<SieveContext>) {
+    fnc_map.set_external_function("local_display_name", plugin_id, 2);
+}
+
 pub fn exec(ctx: PluginContext<'_>) -> Variable {
     let store = match &ctx.arguments[0] {
         Variable::String(v) if !v.is_empty() => ctx.core.sieve.lookup_stores.get(v.as_ref()),
@@ -395,7 +399,7 @@ pub fn exec_remote(ctx: PluginContext<'_>) -> Variable {
 }
 
 pub fn exec_local_domain(ctx: PluginContext<'_>) -> Variable {
-    let domain = ctx.arguments[0].to_string();
+    let domain = ctx.arguments[1].to_string();
 
     if !domain.is_empty() {
         let directory = match &ctx.arguments[0] {
@@ -423,6 +427,49 @@ pub fn exec_local_domain(ctx: PluginContext<'_>) -> Variable {
     Variable::default()
 }
 
+// Returns the display name of the local principal owning an address, which
+// is used to detect external senders impersonating internal users.
+pub fn exec_local_display_name(ctx: PluginContext<'_>) -> Variable {
+    let address = ctx.arguments[1].to_string().to_lowercase();
+
+    if address.contains('@') {
+        let directory = match &ctx.arguments[0] {
+            Variable::String(v) if !v.is_empty() => ctx.core.sieve.directories.get(v.as_ref()),
+            _ => Some(&ctx.core.queue.config.directory),
+        };
+
+        if let Some(directory) = directory {
+            match ctx
+                .handle
+                .block_on(directory.query_identities(&[address], false))
+            {
+                Ok(Some(principal)) => {
+                    return principal.description.unwrap_or_default().into();
+                }
+                Ok(None) => (),
+                Err(err) => {
+                    tracing::warn!(
+                        parent: ctx.span,
+                        context = "sieve:local_display_name",
+                        event = "failed",
+                        reason = ?err,
+                    );
+                }
+            }
+        } else {
+            tracing::warn!(
+                parent: ctx.span,
+                context = "sieve:local_display_name",
+                event = "failed",
+                reason = "Unknown directory",
+                lookup_id = ctx.arguments[0].to_string().as_ref(),
+            );
+        }
+    }
+
+    Variable::default()
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct VariableWrapper(Variable);
 
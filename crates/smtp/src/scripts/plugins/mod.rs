@@ -29,6 +29,7 @@ pub mod http;
 pub mod lookup;
 pub mod pyzor;
 pub mod query;
+pub mod verdict;
 
 use mail_parser::Message;
 use sieve::{runtime::Variable, FunctionMap, Input};
@@ -50,7 +51,7 @@ pub struct PluginContext<'x> {
     pub arguments: Vec<Variable>,
 }
 
-const PLUGINS_EXEC: [ExecPluginFnc; 16] = [
+const PLUGINS_EXEC: [ExecPluginFnc; 22] = [
     query::exec,
     exec::exec,
     lookup::exec,
@@ -58,6 +59,7 @@ const PLUGINS_EXEC: [ExecPluginFnc; 16] = [
     lookup::exec_set,
     lookup::exec_remote,
     lookup::exec_local_domain,
+    lookup::exec_local_display_name,
     dns::exec,
     dns::exec_exists,
     http::exec_header,
@@ -65,10 +67,15 @@ const PLUGINS_EXEC: [ExecPluginFnc; 16] = [
     bayes::exec_untrain,
     bayes::exec_classify,
     bayes::exec_is_balanced,
+    bayes::exec_autolearn,
+    bayes::exec_opted_out,
     pyzor::exec,
     headers::exec,
+    verdict::exec_get,
+    verdict::exec_set,
+    verdict::exec_attachment_hashes,
 ];
-const PLUGINS_REGISTER: [RegisterPluginFnc; 16] = [
+const PLUGINS_REGISTER: [RegisterPluginFnc; 22] = [
     query::register,
     exec::register,
     lookup::register,
@@ -76,6 +83,7 @@ const PLUGINS_REGISTER: [RegisterPluginFnc; 16] = [
     lookup::register_set,
     lookup::register_remote,
     lookup::register_local_domain,
+    lookup::register_local_display_name,
     dns::register,
     dns::register_exists,
     http::register_header,
@@ -83,8 +91,13 @@ const PLUGINS_REGISTER: [RegisterPluginFnc; 16] = [
     bayes::register_untrain,
     bayes::register_classify,
     bayes::register_is_balanced,
+    bayes::register_autolearn,
+    bayes::register_opted_out,
     pyzor::register,
     headers::register,
+    verdict::register_get,
+    verdict::register_set,
+    verdict::register_attachment_hashes,
 ];
 
 pub trait RegisterSievePlugins {
@@ -0,0 +1,75 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use sha2::{Digest, Sha256};
+use sieve::{runtime::Variable, FunctionMap};
+
+use crate::config::scripts::SieveContext;
+
+use super::{lookup, PluginContext};
+
+pub fn register_get(plugin_id: u32, fnc_map: &mut FunctionMap<SieveContext>) {
+    fnc_map.set_external_function("verdict_get", plugin_id, 2);
+}
+
+pub fn register_set(plugin_id: u32, fnc_map: &mut FunctionMap<SieveContext>) {
+    fnc_map.set_external_function("verdict_set", plugin_id, 4);
+}
+
+pub fn register_attachment_hashes(plugin_id: u32, fnc_map: &mut FunctionMap<SieveContext>) {
+    fnc_map.set_external_function("attachment_hashes", plugin_id, 0);
+}
+
+pub fn exec_get(ctx: PluginContext<'_>) -> Variable {
+    let core = ctx.core;
+    let verdict = lookup::exec_get(ctx);
+    let stats = &core.sieve.runtime.context().verdict_stats;
+    if !verdict.is_empty() {
+        stats.hit();
+    } else {
+        stats.miss();
+    }
+    verdict
+}
+
+pub fn exec_set(ctx: PluginContext<'_>) -> Variable {
+    let core = ctx.core;
+    let result = lookup::exec_set(ctx);
+    if result.to_bool() {
+        core.sieve.runtime.context().verdict_stats.insert();
+    }
+    result
+}
+
+pub fn exec_attachment_hashes(ctx: PluginContext<'_>) -> Variable {
+    ctx.message
+        .attachments()
+        .filter(|part| !part.contents().is_empty())
+        .map(|part| {
+            let mut hasher = Sha256::new();
+            hasher.update(part.contents());
+            Variable::from(format!("{:x}", hasher.finalize()))
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
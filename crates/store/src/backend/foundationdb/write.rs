@@ -389,6 +389,16 @@ impl FdbStore {
         }
     }
 
+    pub(crate) async fn compact(&self, subspace: u8) -> crate::Result<()> {
+        // Storage servers reclaim freed pages on their own,
+        // only empty bitmaps have to be cleared explicitly.
+        if subspace == SUBSPACE_BITMAPS {
+            self.purge_bitmaps().await
+        } else {
+            Ok(())
+        }
+    }
+
     pub(crate) async fn purge_bitmaps(&self) -> crate::Result<()> {
         // Obtain all empty bitmaps
         let trx = self.db.create_trx()?;
@@ -89,6 +89,15 @@ impl FsStore {
         }))
     }
 
+    pub(crate) async fn open_blob(&self, key: &[u8]) -> crate::Result<Option<(File, u64)>> {
+        let blob_path = self.build_path(key);
+        let blob_size = match fs::metadata(&blob_path).await {
+            Ok(m) => m.len(),
+            Err(_) => return Ok(None),
+        };
+        Ok(Some((File::open(&blob_path).await?, blob_size)))
+    }
+
     pub(crate) async fn put_blob(&self, key: &[u8], data: &[u8]) -> crate::Result<()> {
         let blob_path = self.build_path(key);
 
@@ -47,6 +47,7 @@ impl MysqlStore {
                     .into(),
             )
             .max_allowed_packet(config.property((&prefix, "max-allowed-packet"))?)
+            .stmt_cache_size(config.property::<usize>((&prefix, "pool.statement-cache"))?)
             .wait_timeout(
                 config
                     .property::<Duration>((&prefix, "timeout.wait"))?
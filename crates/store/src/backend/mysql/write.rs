@@ -275,6 +275,11 @@ impl MysqlStore {
         Ok(())
     }
 
+    pub(crate) async fn compact(&self, _subspace: u8) -> crate::Result<()> {
+        // Space is reclaimed by the database server
+        Ok(())
+    }
+
     pub(crate) async fn delete_range(&self, from: impl Key, to: impl Key) -> crate::Result<()> {
         let mut conn = self.conn_pool.get_conn().await?;
 
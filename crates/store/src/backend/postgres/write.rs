@@ -292,6 +292,11 @@ impl PostgresStore {
         Ok(())
     }
 
+    pub(crate) async fn compact(&self, _subspace: u8) -> crate::Result<()> {
+        // Space is reclaimed by the database server
+        Ok(())
+    }
+
     pub(crate) async fn delete_range(&self, from: impl Key, to: impl Key) -> crate::Result<()> {
         let conn = self.conn_pool.get().await?;
 
@@ -35,6 +35,24 @@ impl RedisStore {
         }
     }
 
+    pub async fn key_insert(
+        &self,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        expires: u64,
+    ) -> crate::Result<bool> {
+        match &self.pool {
+            RedisPool::Single(pool) => {
+                self.key_insert_(pool.get().await?.as_mut(), key, value, expires)
+                    .await
+            }
+            RedisPool::Cluster(pool) => {
+                self.key_insert_(pool.get().await?.as_mut(), key, value, expires)
+                    .await
+            }
+        }
+    }
+
     pub async fn key_get<T: Deserialize + std::fmt::Debug + 'static>(
         &self,
         key: LookupKey,
@@ -45,6 +63,47 @@ impl RedisStore {
         }
     }
 
+    pub async fn key_delete_prefix(&self, prefix: &[u8]) -> crate::Result<()> {
+        match &self.pool {
+            RedisPool::Single(pool) => {
+                self.key_delete_prefix_(pool.get().await?.as_mut(), prefix)
+                    .await
+            }
+            RedisPool::Cluster(_) => Err(crate::Error::InternalError(
+                "Redis clusters do not support key_delete_prefix".into(),
+            )),
+        }
+    }
+
+    async fn key_delete_prefix_(
+        &self,
+        conn: &mut impl AsyncCommands,
+        prefix: &[u8],
+    ) -> crate::Result<()> {
+        // Escape glob characters
+        let mut pattern = Vec::with_capacity(prefix.len() * 2 + 1);
+        for &ch in prefix {
+            if matches!(ch, b'*' | b'?' | b'[' | b']' | b'\\') {
+                pattern.push(b'\\');
+            }
+            pattern.push(ch);
+        }
+        pattern.push(b'*');
+
+        let mut keys = Vec::new();
+        {
+            let mut iter = conn.scan_match::<_, Vec<u8>>(pattern).await?;
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+        }
+        for chunk in keys.chunks(1000) {
+            conn.del::<_, ()>(chunk).await?;
+        }
+
+        Ok(())
+    }
+
     async fn key_get_<T: Deserialize + std::fmt::Debug + 'static>(
         &self,
         conn: &mut impl AsyncCommands,
@@ -67,6 +126,25 @@ impl RedisStore {
         }
     }
 
+    async fn key_insert_(
+        &self,
+        conn: &mut impl AsyncCommands,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        expires: u64,
+    ) -> crate::Result<bool> {
+        redis::cmd("SET")
+            .arg(key)
+            .arg(value)
+            .arg("NX")
+            .arg("EX")
+            .arg(expires)
+            .query_async::<_, Option<String>>(conn)
+            .await
+            .map(|result| result.is_some())
+            .map_err(Into::into)
+    }
+
     async fn key_set_(
         &self,
         conn: &mut impl AsyncCommands,
@@ -122,6 +122,26 @@ impl RocksDbStore {
     pub(crate) async fn purge_bitmaps(&self) -> crate::Result<()> {
         Ok(())
     }
+
+    pub(crate) async fn compact(&self, subspace: u8) -> crate::Result<()> {
+        let db = self.db.clone();
+        self.spawn_worker(move || {
+            let cf = db
+                .cf_handle(std::str::from_utf8(&[subspace]).unwrap())
+                .ok_or_else(|| {
+                    crate::Error::InternalError(format!(
+                        "Unknown column family {:?}",
+                        char::from(subspace)
+                    ))
+                })?;
+
+            // Full range compaction, empty bitmaps are dropped by the compaction filter
+            db.compact_range_cf(&cf, None::<&[u8]>, None::<&[u8]>);
+
+            Ok(())
+        })
+        .await
+    }
 }
 
 struct RocksDBTransaction<'x> {
@@ -101,6 +101,23 @@ impl S3Store {
         }
     }
 
+    pub(crate) async fn blob_size(&self, key: &[u8]) -> crate::Result<Option<u64>> {
+        match self
+            .bucket
+            .head_object(Base32Writer::from_bytes(key).finalize())
+            .await
+        {
+            Ok((result, status_code)) if (200..300).contains(&status_code) => {
+                Ok(Some(result.content_length.unwrap_or_default().max(0) as u64))
+            }
+            Ok((_, 404)) => Ok(None),
+            Ok((_, status_code)) => Err(crate::Error::InternalError(format!(
+                "S3 error code {status_code}"
+            ))),
+            Err(err) => Err(err.into()),
+        }
+    }
+
     pub(crate) async fn put_blob(&self, key: &[u8], data: &[u8]) -> crate::Result<()> {
         match self
             .bucket
@@ -38,6 +38,9 @@ use super::{pool::SqliteConnectionManager, SqliteStore};
 impl SqliteStore {
     pub async fn open(config: &Config, prefix: impl AsKey) -> crate::Result<Self> {
         let prefix = prefix.as_key();
+        let statement_cache = config
+            .property::<usize>((&prefix, "pool.statement-cache"))?
+            .unwrap_or(64);
         let db = Self {
             conn_pool: Pool::builder()
                 .max_size(
@@ -51,7 +54,8 @@ impl SqliteStore {
                             .value_require((&prefix, "path"))
                             .failed("Invalid configuration file"),
                     )
-                    .with_init(|c| {
+                    .with_init(move |c| {
+                        c.set_prepared_statement_cache_capacity(statement_cache);
                         c.execute_batch(concat!(
                             "PRAGMA journal_mode = WAL; ",
                             "PRAGMA synchronous = NORMAL; ",
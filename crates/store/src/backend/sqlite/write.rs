@@ -207,6 +207,11 @@ impl SqliteStore {
         Ok(())
     }
 
+    pub(crate) async fn compact(&self, _subspace: u8) -> crate::Result<()> {
+        // Not needed for SQLite
+        Ok(())
+    }
+
     pub(crate) async fn delete_range(&self, from: impl Key, to: impl Key) -> crate::Result<()> {
         let conn = self.conn_pool.get()?;
         self.spawn_worker(move || {
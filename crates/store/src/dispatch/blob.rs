@@ -21,11 +21,81 @@
  * for more details.
 */
 
-use std::ops::Range;
+use std::{io::SeekFrom, ops::Range};
+
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
 
 use crate::{BlobStore, Store};
 
+// Default size of the chunks read from a blob stream
+pub const BLOB_STREAM_CHUNK_SIZE: usize = 256 * 1024;
+
+// Reads a window of a blob in bounded chunks. File and S3 blobs are read
+// on demand, while blobs held by a database store are loaded once.
+pub struct BlobStream {
+    source: BlobSource,
+    offset: u64,
+    start: u64,
+    end: u64,
+    chunk_size: usize,
+}
+
+enum BlobSource {
+    File {
+        file: File,
+        position: u64,
+    },
+    #[cfg(feature = "s3")]
+    S3 {
+        store: std::sync::Arc<crate::backend::s3::S3Store>,
+        key: Vec<u8>,
+    },
+    Bytes(Vec<u8>),
+}
+
 impl BlobStore {
+    pub async fn get_blob_stream(
+        &self,
+        key: &[u8],
+        chunk_size: usize,
+    ) -> crate::Result<Option<BlobStream>> {
+        let (source, size) = match self {
+            Self::Fs(store) => match store.open_blob(key).await? {
+                Some((file, size)) => (BlobSource::File { file, position: 0 }, size),
+                None => return Ok(None),
+            },
+            #[cfg(feature = "s3")]
+            Self::S3(store) => match store.blob_size(key).await? {
+                Some(size) => (
+                    BlobSource::S3 {
+                        store: store.clone(),
+                        key: key.to_vec(),
+                    },
+                    size,
+                ),
+                None => return Ok(None),
+            },
+            Self::Store(_) => match self.get_blob(key, 0..u32::MAX).await? {
+                Some(bytes) => {
+                    let size = bytes.len() as u64;
+                    (BlobSource::Bytes(bytes), size)
+                }
+                None => return Ok(None),
+            },
+        };
+
+        Ok(Some(BlobStream {
+            source,
+            offset: 0,
+            start: 0,
+            end: size,
+            chunk_size: std::cmp::max(chunk_size, 1),
+        }))
+    }
+
     pub async fn get_blob(&self, key: &[u8], range: Range<u32>) -> crate::Result<Option<Vec<u8>>> {
         match self {
             Self::Store(store) => match store {
@@ -86,3 +156,87 @@ impl BlobStore {
         }
     }
 }
+
+impl BlobStream {
+    pub fn from_bytes(bytes: Vec<u8>, chunk_size: usize) -> Self {
+        BlobStream {
+            offset: 0,
+            start: 0,
+            end: bytes.len() as u64,
+            source: BlobSource::Bytes(bytes),
+            chunk_size: std::cmp::max(chunk_size, 1),
+        }
+    }
+
+    // Length of the current window
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    // Restricts the window to a range relative to the current window,
+    // out of bounds ranges are clamped.
+    pub fn narrow(mut self, range: Range<u64>) -> Self {
+        let end = std::cmp::min(self.start.saturating_add(range.end), self.end);
+        let start = std::cmp::min(self.start.saturating_add(range.start), end);
+        self.start = start;
+        self.offset = start;
+        self.end = end;
+        self
+    }
+
+    // Returns the next chunk of the window, or `None` once it has been read
+    pub async fn next_chunk(&mut self) -> crate::Result<Option<Vec<u8>>> {
+        if self.offset >= self.end {
+            return Ok(None);
+        }
+        let len = std::cmp::min(self.chunk_size as u64, self.end - self.offset) as usize;
+
+        let chunk = match &mut self.source {
+            BlobSource::File { file, position } => {
+                if *position != self.offset {
+                    file.seek(SeekFrom::Start(self.offset)).await?;
+                }
+                let mut buf = vec![0; len];
+                file.read_exact(&mut buf).await?;
+                *position = self.offset + len as u64;
+                buf
+            }
+            #[cfg(feature = "s3")]
+            BlobSource::S3 { store, key } => {
+                let range = self.offset as u32..(self.offset + len as u64) as u32;
+                store
+                    .get_blob(key, range)
+                    .await?
+                    .ok_or_else(|| crate::Error::InternalError("Blob not found".into()))?
+            }
+            BlobSource::Bytes(bytes) => bytes
+                .get(self.offset as usize..self.offset as usize + len)
+                .unwrap_or_default()
+                .to_vec(),
+        };
+
+        if chunk.len() != len {
+            return Err(crate::Error::InternalError(format!(
+                "Blob truncated, expected {len} bytes at offset {} but got {}",
+                self.offset,
+                chunk.len()
+            )));
+        }
+        self.offset += len as u64;
+
+        Ok(Some(chunk))
+    }
+
+    // Reads the remaining window into memory
+    pub async fn read_to_end(mut self) -> crate::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity((self.end - self.offset) as usize);
+        while let Some(chunk) = self.next_chunk().await? {
+            buf.extend_from_slice(&chunk);
+        }
+        Ok(buf)
+    }
+}
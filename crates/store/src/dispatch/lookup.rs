@@ -25,6 +25,7 @@ use crate::{backend::memory::MemoryStore, Row};
 #[allow(unused_imports)]
 use crate::{
     write::{
+        assert::HashedValue,
         key::{DeserializeBigEndian, KeySerializer},
         now, BatchBuilder, Operation, ValueClass, ValueOp,
     },
@@ -98,6 +99,59 @@ impl LookupStore {
         }
     }
 
+    /// Stores a value only if the key does not exist or has expired,
+    /// returns `false` if the key was already present.
+    pub async fn key_insert(
+        &self,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        expires: u64,
+    ) -> crate::Result<bool> {
+        match self {
+            LookupStore::Store(store) => {
+                let class = ValueClass::Key(key);
+                let current = store
+                    .get_value::<HashedValue<LookupValue<()>>>(ValueKey {
+                        account_id: 0,
+                        collection: 0,
+                        document_id: 0,
+                        class: class.clone(),
+                    })
+                    .await?;
+
+                let mut batch = BatchBuilder::new();
+                match &current {
+                    Some(HashedValue {
+                        inner: LookupValue::Value { .. },
+                        ..
+                    }) => return Ok(false),
+                    Some(current) => batch.assert_value(class.clone(), current),
+                    None => batch.assert_value(class.clone(), ()),
+                };
+                batch.ops.push(Operation::Value {
+                    class,
+                    op: ValueOp::Set(
+                        KeySerializer::new(value.len() + U64_LEN)
+                            .write(now() + expires)
+                            .write(value.as_slice())
+                            .finalize(),
+                    ),
+                });
+
+                match store.write(batch.build()).await {
+                    Ok(_) => Ok(true),
+                    Err(crate::Error::AssertValueFailed) => Ok(false),
+                    Err(err) => Err(err),
+                }
+            }
+            #[cfg(feature = "redis")]
+            LookupStore::Redis(store) => store.key_insert(key, value, expires).await,
+            LookupStore::Query(_) | LookupStore::Memory(_) => Err(crate::Error::InternalError(
+                "This store does not support key_insert".into(),
+            )),
+        }
+    }
+
     pub async fn key_get<T: Deserialize + From<Value<'static>> + std::fmt::Debug + 'static>(
         &self,
         key: LookupKey,
@@ -210,6 +264,73 @@ impl LookupStore {
 
         Ok(())
     }
+
+    /// Deletes all keys starting with `prefix`, or only those that
+    /// have expired when `expired_only` is set.
+    pub async fn key_delete_prefix(&self, prefix: &[u8], expired_only: bool) -> crate::Result<()> {
+        match self {
+            LookupStore::Store(store) => {
+                let from_key = ValueKey {
+                    account_id: 0,
+                    collection: 0,
+                    document_id: 0,
+                    class: ValueClass::Key(prefix.to_vec()),
+                };
+                let mut end = prefix.to_vec();
+                end.extend_from_slice(&[u8::MAX; 64]);
+                let to_key = ValueKey {
+                    account_id: 0,
+                    collection: 0,
+                    document_id: 0,
+                    class: ValueClass::Key(end),
+                };
+
+                let current_time = now();
+                let mut keys = Vec::new();
+                store
+                    .iterate(IterateParams::new(from_key, to_key), |key, value| {
+                        let key = key.get(1..).unwrap_or_default();
+                        if key.starts_with(prefix)
+                            && (!expired_only || value.deserialize_be_u64(0)? < current_time)
+                        {
+                            keys.push(key.to_vec());
+                        }
+                        Ok(true)
+                    })
+                    .await?;
+                if !keys.is_empty() {
+                    let mut batch = BatchBuilder::new();
+                    for key in keys {
+                        batch.ops.push(Operation::Value {
+                            class: ValueClass::Key(key),
+                            op: ValueOp::Clear,
+                        });
+                        if batch.ops.len() >= 1000 {
+                            store.write(batch.build()).await?;
+                            batch = BatchBuilder::new();
+                        }
+                    }
+                    if !batch.ops.is_empty() {
+                        store.write(batch.build()).await?;
+                    }
+                }
+
+                Ok(())
+            }
+            #[cfg(feature = "redis")]
+            LookupStore::Redis(store) => {
+                // Redis expires keys on its own
+                if !expired_only {
+                    store.key_delete_prefix(prefix).await
+                } else {
+                    Ok(())
+                }
+            }
+            LookupStore::Query(_) | LookupStore::Memory(_) => Err(crate::Error::InternalError(
+                "This store does not support key_delete_prefix".into(),
+            )),
+        }
+    }
 }
 
 impl<T: Deserialize> Deserialize for LookupValue<T> {
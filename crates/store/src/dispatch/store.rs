@@ -255,6 +255,21 @@ impl Store {
             Self::RocksDb(store) => store.purge_bitmaps().await,
         }
     }
+
+    pub async fn compact(&self, subspace: u8) -> crate::Result<()> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Self::SQLite(store) => store.compact(subspace).await,
+            #[cfg(feature = "foundation")]
+            Self::FoundationDb(store) => store.compact(subspace).await,
+            #[cfg(feature = "postgres")]
+            Self::PostgreSQL(store) => store.compact(subspace).await,
+            #[cfg(feature = "mysql")]
+            Self::MySQL(store) => store.compact(subspace).await,
+            #[cfg(feature = "rocks")]
+            Self::RocksDb(store) => store.compact(subspace).await,
+        }
+    }
     pub(crate) async fn delete_range(&self, from: impl Key, to: impl Key) -> crate::Result<()> {
         match self {
             #[cfg(feature = "sqlite")]
@@ -290,6 +305,23 @@ impl Store {
             (ValueClass::ReservedId, ValueClass::ReservedId),
             (ValueClass::Property(0), ValueClass::Property(0)),
             (ValueClass::TermIndex, ValueClass::TermIndex),
+            (ValueClass::Activity(0), ValueClass::Activity(0)),
+            (
+                ValueClass::Tombstone {
+                    mailbox_id: 0,
+                    change_id: 0,
+                    uid: 0,
+                },
+                ValueClass::Tombstone {
+                    mailbox_id: 0,
+                    change_id: 0,
+                    uid: 0,
+                },
+            ),
+            (
+                ValueClass::TombstoneHorizon(0),
+                ValueClass::TombstoneHorizon(0),
+            ),
         ] {
             self.delete_range(
                 ValueKey {
@@ -548,14 +580,17 @@ impl Store {
                         }
                         SUBSPACE_VALUES
                             if key[0] == 3
+                                || key[0] == 4
+                                || (9..=12).contains(&key[0])
                                 || key[0] >= 20
                                 || key.get(1..5).unwrap_or_default() == u32::MAX.to_be_bytes() =>
                         {
-                            // Ignore lastId counter and ID mappings
+                            // Ignore lastId counter, ID mappings, lookup keys, activity logs, tombstones
+                            // and usage records
                             return Ok(true);
                         }
-                        SUBSPACE_COUNTERS if key.len() <= 4 => {
-                            // Ignore named keys
+                        SUBSPACE_COUNTERS if key.len() <= 4 || key[0] == 13 || key[0] >= 20 => {
+                            // Ignore named keys, mailbox usage and directory counters
                             return Ok(true);
                         }
                         SUBSPACE_INDEXES => {
@@ -180,6 +180,15 @@ pub const SUBSPACE_INDEXES: u8 = b'i';
 pub const SUBSPACE_BLOBS: u8 = b't';
 pub const SUBSPACE_COUNTERS: u8 = b'c';
 
+pub const COMPACT_SUBSPACES: [u8; 6] = [
+    SUBSPACE_BITMAPS,
+    SUBSPACE_VALUES,
+    SUBSPACE_INDEXES,
+    SUBSPACE_LOGS,
+    SUBSPACE_COUNTERS,
+    SUBSPACE_BLOBS,
+];
+
 pub struct IterateParams<T: Key> {
     begin: T,
     end: T,
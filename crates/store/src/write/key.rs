@@ -276,6 +276,29 @@ impl<T: AsRef<ValueClass> + Sync + Send> Key for ValueKey<T> {
                     .write(self.document_id),
             },
             ValueClass::Config(key) => serializer.write(8u8).write(key.as_slice()),
+            ValueClass::Activity(id) => serializer.write(9u8).write(self.account_id).write(*id),
+            ValueClass::Tombstone {
+                mailbox_id,
+                change_id,
+                uid,
+            } => serializer
+                .write(10u8)
+                .write(self.account_id)
+                .write(*mailbox_id)
+                .write(*change_id)
+                .write(*uid),
+            ValueClass::TombstoneHorizon(mailbox_id) => serializer
+                .write(11u8)
+                .write(self.account_id)
+                .write(*mailbox_id),
+            ValueClass::Usage(period) => {
+                serializer.write(12u8).write(*period).write(self.account_id)
+            }
+            ValueClass::MailboxUsage { mailbox_id, field } => serializer
+                .write(13u8)
+                .write(self.account_id)
+                .write(*mailbox_id)
+                .write(*field),
             ValueClass::Directory(directory) => match directory {
                 DirectoryClass::NameToId(name) => serializer.write(20u8).write(name.as_slice()),
                 DirectoryClass::EmailToId(email) => serializer.write(21u8).write(email.as_slice()),
@@ -296,6 +319,11 @@ impl<T: AsRef<ValueClass> + Sync + Send> Key for ValueKey<T> {
                     .write(26u8)
                     .write(*principal_id)
                     .write(*has_member),
+                DirectoryClass::Alias(address) => serializer.write(27u8).write(address.as_slice()),
+                DirectoryClass::AliasStats { address, stat } => serializer
+                    .write(28u8)
+                    .write(*stat)
+                    .write(address.as_slice()),
             },
         }
         .finalize()
@@ -429,7 +457,9 @@ impl ValueClass {
             ValueClass::Directory(d) => match d {
                 DirectoryClass::NameToId(v)
                 | DirectoryClass::EmailToId(v)
-                | DirectoryClass::Domain(v) => v.len(),
+                | DirectoryClass::Domain(v)
+                | DirectoryClass::Alias(v) => v.len(),
+                DirectoryClass::AliasStats { address, .. } => address.len() + 1,
                 DirectoryClass::Principal(_) | DirectoryClass::UsedQuota(_) => U32_LEN,
                 DirectoryClass::Members { .. } | DirectoryClass::MemberOf { .. } => U32_LEN * 2,
             },
@@ -438,6 +468,11 @@ impl ValueClass {
                 BlobOp::Commit { .. } | BlobOp::Link { .. } => BLOB_HASH_LEN + U32_LEN * 2 + 2,
             },
             ValueClass::IndexEmail { .. } => U64_LEN * 2,
+            ValueClass::Activity(_) => U64_LEN + U32_LEN,
+            ValueClass::Tombstone { .. } => U64_LEN + U32_LEN * 3,
+            ValueClass::TombstoneHorizon(_) => U32_LEN * 2,
+            ValueClass::Usage(_) => U64_LEN + U32_LEN,
+            ValueClass::MailboxUsage { .. } => U32_LEN * 2 + 1,
         }
     }
 }
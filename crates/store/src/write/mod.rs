@@ -138,6 +138,18 @@ pub enum ValueClass {
     Blob(BlobOp),
     IndexEmail(u64),
     Config(Vec<u8>),
+    Activity(u64),
+    Tombstone {
+        mailbox_id: u32,
+        change_id: u64,
+        uid: u32,
+    },
+    TombstoneHorizon(u32),
+    Usage(u64),
+    MailboxUsage {
+        mailbox_id: u32,
+        field: u8,
+    },
 }
 
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
@@ -149,6 +161,8 @@ pub enum DirectoryClass {
     Domain(Vec<u8>),
     Principal(u32),
     UsedQuota(u32),
+    Alias(Vec<u8>),
+    AliasStats { address: Vec<u8>, stat: u8 },
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Default)]
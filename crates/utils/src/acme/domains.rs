@@ -0,0 +1,377 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    fmt::Debug,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+
+use ahash::AHashMap;
+use arc_swap::ArcSwap;
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use rustls::sign::CertifiedKey;
+use tokio::sync::watch;
+
+use super::{AcmeManager, SpawnAcme};
+
+pub(crate) struct DirectoryDomains {
+    subdomains: Vec<String>,
+    refresh: Duration,
+    pending: Mutex<Option<Vec<String>>>,
+    managers: Mutex<AHashMap<String, Arc<AcmeManager>>>,
+    sni: ArcSwap<AHashMap<String, Arc<AcmeManager>>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AcmeState {
+    #[default]
+    Pending,
+    Ordering,
+    Valid,
+    Failed,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AcmeStatus {
+    pub domains: Vec<String>,
+    pub state: AcmeState,
+    pub valid_from: Option<String>,
+    pub valid_until: Option<String>,
+    pub renew_at: Option<String>,
+    pub last_error: Option<String>,
+}
+
+impl AcmeManager {
+    pub fn with_directory_domains(mut self, subdomains: Vec<String>, refresh: Duration) -> Self {
+        self.directory_domains = Some(DirectoryDomains {
+            subdomains,
+            refresh,
+            pending: Mutex::new(None),
+            managers: Mutex::new(AHashMap::new()),
+            sni: ArcSwap::from_pointee(AHashMap::new()),
+        });
+        self
+    }
+
+    pub fn has_directory_domains(&self) -> bool {
+        self.directory_domains.is_some()
+    }
+
+    pub fn directory_domains_refresh(&self) -> Option<Duration> {
+        self.directory_domains.as_ref().map(|dd| dd.refresh)
+    }
+
+    // Schedules issuance for the domains currently present in the directory,
+    // the changes are applied by the manager task once its account is loaded.
+    pub fn set_directory_domains(&self, domains: impl IntoIterator<Item = String>) {
+        if let Some(dd) = &self.directory_domains {
+            let mut domains = domains
+                .into_iter()
+                .map(|domain| domain.trim().trim_end_matches('.').to_lowercase())
+                .filter(|domain| !domain.is_empty() && !self.domains.contains(domain))
+                .collect::<Vec<_>>();
+            domains.sort_unstable();
+            domains.dedup();
+            *dd.pending.lock() = Some(domains);
+            self.notify.notify_one();
+        }
+    }
+
+    pub(crate) fn apply_directory_domains(&self, shutdown_rx: &watch::Receiver<bool>) {
+        let dd = if let Some(dd) = &self.directory_domains {
+            dd
+        } else {
+            return;
+        };
+        let domains = if let Some(domains) = dd.pending.lock().take() {
+            domains
+        } else {
+            return;
+        };
+
+        let mut managers = dd.managers.lock();
+
+        // Stop managers for domains that were removed from the directory
+        managers.retain(|domain, manager| {
+            if domains.contains(domain) {
+                true
+            } else {
+                tracing::info!(
+                    context = "acme",
+                    event = "domain-removed",
+                    domain = domain,
+                    "Stopping certificate management for domain {domain}"
+                );
+                manager.cancelled.store(true, Ordering::Relaxed);
+                manager.notify.notify_one();
+                false
+            }
+        });
+
+        // Start managers for new domains
+        for domain in domains {
+            if managers.contains_key(&domain) {
+                continue;
+            }
+            let mut names = Vec::with_capacity(dd.subdomains.len() + 1);
+            names.push(domain.clone());
+            for subdomain in &dd.subdomains {
+                names.push(format!("{subdomain}.{domain}"));
+            }
+
+            match AcmeManager::new(
+                self.directory_url.clone(),
+                names,
+                self.contact.clone(),
+                self.renew_before.to_std().unwrap_or_default(),
+                self.cache_path.clone(),
+            ) {
+                Ok(manager) => {
+                    tracing::info!(
+                        context = "acme",
+                        event = "domain-added",
+                        domain = domain,
+                        "Starting certificate management for domain {domain}"
+                    );
+                    manager.account_key.store(self.account_key.load_full());
+                    let manager = Arc::new(manager);
+                    manager.clone().spawn(shutdown_rx.clone());
+                    managers.insert(domain, manager);
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        context = "acme",
+                        event = "error",
+                        domain = domain,
+                        reason = err,
+                        "Failed to create certificate manager for domain {domain}"
+                    );
+                }
+            }
+        }
+
+        // Rebuild the SNI lookup table
+        let mut sni = AHashMap::with_capacity(managers.len() * (dd.subdomains.len() + 1));
+        for manager in managers.values() {
+            for name in &manager.domains {
+                sni.insert(name.clone(), manager.clone());
+            }
+        }
+        dd.sni.store(Arc::new(sni));
+    }
+
+    pub(crate) fn resolve_domain(&self, name: &str) -> Option<Arc<AcmeManager>> {
+        self.directory_domains
+            .as_ref()?
+            .sni
+            .load()
+            .get(&name.to_lowercase())
+            .cloned()
+    }
+
+    pub fn status(&self) -> Vec<AcmeStatus> {
+        let mut status = Vec::new();
+        if !self.domains.is_empty() {
+            status.push(self.status.lock().clone());
+        }
+        if let Some(dd) = &self.directory_domains {
+            let managers = dd.managers.lock();
+            let mut domains = managers.keys().collect::<Vec<_>>();
+            domains.sort_unstable();
+            for domain in domains {
+                status.push(managers[domain].status.lock().clone());
+            }
+        }
+        status
+    }
+
+    // Returns the certificates issued so far, placeholder self-signed
+    // certificates are skipped.
+    pub fn certificates(&self) -> Vec<(String, Arc<CertifiedKey>)> {
+        let mut certificates = Vec::new();
+        if !self.domains.is_empty() && self.status.lock().valid_until.is_some() {
+            certificates.push((self.domains.join(","), self.cert.load_full()));
+        }
+        if let Some(dd) = &self.directory_domains {
+            let managers = dd.managers.lock();
+            let mut domains = managers.keys().collect::<Vec<_>>();
+            domains.sort_unstable();
+            for domain in domains {
+                let manager = &managers[domain];
+                if manager.status.lock().valid_until.is_some() {
+                    certificates.push((manager.domains.join(","), manager.cert.load_full()));
+                }
+            }
+        }
+        certificates
+    }
+
+    pub(crate) fn set_state(&self, state: AcmeState) {
+        self.status.lock().state = state;
+    }
+
+    pub(crate) fn set_valid(&self, validity: [DateTime<Utc>; 2], renewal_date: DateTime<Utc>) {
+        let mut status = self.status.lock();
+        status.state = AcmeState::Valid;
+        status.valid_from = validity[0].to_rfc3339().into();
+        status.valid_until = validity[1].to_rfc3339().into();
+        status.renew_at = renewal_date.to_rfc3339().into();
+        status.last_error = None;
+    }
+
+    pub(crate) fn set_failed(&self, err: &impl Debug) {
+        let mut status = self.status.lock();
+        status.state = AcmeState::Failed;
+        status.last_error = format!("{err:?}").into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio::sync::watch;
+
+    use crate::acme::AcmeManager;
+
+    fn manager(domains: &[&str]) -> AcmeManager {
+        let manager = AcmeManager::new(
+            "https://127.0.0.1:1/directory".to_string(),
+            domains.iter().map(|d| d.to_string()).collect(),
+            vec![],
+            Duration::from_secs(30 * 86400),
+            std::env::temp_dir().join("acme_directory_domains_test"),
+        )
+        .unwrap()
+        .with_directory_domains(vec!["mail".to_string()], Duration::from_secs(3600));
+        manager.account_key.store(Arc::new(vec![1, 2, 3]));
+        manager
+    }
+
+    #[tokio::test]
+    async fn apply_directory_domains() {
+        let acme = manager(&["mx.example.org"]);
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        // Domains are normalized, and configured domains are skipped
+        acme.set_directory_domains(
+            [
+                "Example.COM.",
+                "example.net",
+                "example.com",
+                "mx.example.org",
+                " ",
+            ]
+            .into_iter()
+            .map(String::from),
+        );
+        acme.apply_directory_domains(&shutdown_rx);
+        let status = acme.status();
+        assert_eq!(
+            status.iter().map(|s| s.domains.clone()).collect::<Vec<_>>(),
+            vec![
+                vec!["mx.example.org".to_string()],
+                vec!["example.com".to_string(), "mail.example.com".to_string()],
+                vec!["example.net".to_string(), "mail.example.net".to_string()],
+            ]
+        );
+
+        // Applying without pending changes keeps the existing managers
+        let example_com = acme.resolve_domain("example.com").unwrap();
+        acme.apply_directory_domains(&shutdown_rx);
+        assert!(Arc::ptr_eq(
+            &example_com,
+            &acme.resolve_domain("example.com").unwrap()
+        ));
+
+        // Removed domains are cancelled, unchanged ones are kept
+        acme.set_directory_domains(["example.net".to_string(), "example.org".to_string()]);
+        acme.apply_directory_domains(&shutdown_rx);
+        assert!(example_com
+            .cancelled
+            .load(std::sync::atomic::Ordering::Relaxed));
+        assert_eq!(
+            acme.status()
+                .iter()
+                .map(|s| s.domains[0].as_str())
+                .collect::<Vec<_>>(),
+            vec!["mx.example.org", "example.net", "example.org"]
+        );
+        assert!(acme.resolve_domain("example.com").is_none());
+    }
+
+    #[tokio::test]
+    async fn sni_selection() {
+        let acme = manager(&["mx.example.org"]);
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+        acme.set_directory_domains(["example.com".to_string(), "example.net".to_string()]);
+        acme.apply_directory_domains(&shutdown_rx);
+
+        // Names are matched case-insensitively against each domain and its subdomains
+        for (name, expected) in [
+            ("example.com", Some("example.com")),
+            ("MAIL.Example.com", Some("example.com")),
+            ("mail.example.net", Some("example.net")),
+            ("www.example.com", None),
+            ("mx.example.org", None),
+            ("example.org", None),
+        ] {
+            let manager = acme.resolve_domain(name);
+            assert_eq!(
+                manager.as_ref().map(|m| m.domains[0].as_str()),
+                expected,
+                "{name}"
+            );
+
+            // Each domain is served its own certificate
+            if let Some(manager) = manager {
+                assert!(!Arc::ptr_eq(
+                    &manager.cert.load_full(),
+                    &acme.cert.load_full()
+                ));
+            }
+        }
+        assert!(!Arc::ptr_eq(
+            &acme.resolve_domain("example.com").unwrap().cert.load_full(),
+            &acme.resolve_domain("example.net").unwrap().cert.load_full()
+        ));
+
+        // Managers without directory domains never select another certificate
+        let acme = AcmeManager::new(
+            "https://127.0.0.1:1/directory".to_string(),
+            vec!["mx.example.org".to_string()],
+            vec![],
+            Duration::from_secs(30 * 86400),
+            std::env::temp_dir().join("acme_directory_domains_test"),
+        )
+        .unwrap();
+        acme.set_directory_domains(["example.com".to_string()]);
+        acme.apply_directory_domains(&shutdown_rx);
+        assert!(acme.resolve_domain("example.com").is_none());
+        assert!(!acme.has_directory_domains());
+    }
+}
@@ -23,6 +23,7 @@
 
 pub mod cache;
 pub mod directory;
+pub mod domains;
 pub mod jose;
 pub mod order;
 pub mod resolver;
@@ -41,12 +42,16 @@ use ahash::AHashMap;
 use arc_swap::ArcSwap;
 use parking_lot::Mutex;
 use rustls::sign::CertifiedKey;
-use tokio::sync::watch;
+use tokio::{
+    sync::{watch, Notify},
+    time::Instant,
+};
 
 use crate::config::tls::build_self_signed_cert;
 
 use self::{
     directory::Account,
+    domains::{AcmeState, AcmeStatus, DirectoryDomains},
     order::{CertParseError, OrderError},
 };
 
@@ -60,6 +65,10 @@ pub struct AcmeManager {
     auth_keys: Mutex<AHashMap<String, Arc<CertifiedKey>>>,
     order_in_progress: AtomicBool,
     cert: ArcSwap<CertifiedKey>,
+    status: Mutex<AcmeStatus>,
+    directory_domains: Option<DirectoryDomains>,
+    notify: Notify,
+    cancelled: AtomicBool,
 }
 
 #[derive(Debug)]
@@ -99,19 +108,19 @@ impl AcmeManager {
             auth_keys: Mutex::new(AHashMap::new()),
             order_in_progress: false.into(),
             cert: ArcSwap::from_pointee(build_self_signed_cert(&domains)?),
+            status: Mutex::new(AcmeStatus {
+                domains: domains.clone(),
+                ..Default::default()
+            }),
+            directory_domains: None,
+            notify: Notify::new(),
+            cancelled: false.into(),
             domains,
         })
     }
 
     pub async fn init(&self) -> Result<Duration, AcmeError> {
-        // Load account key from cache or generate a new one
-        if let Some(account_key) = self.load_account().await? {
-            self.account_key.store(Arc::new(account_key));
-        } else {
-            let account_key = Account::generate_key_pair();
-            self.store_account(&account_key).await?;
-            self.account_key.store(Arc::new(account_key));
-        }
+        self.init_account().await?;
 
         // Load certificate from cache or request a new one
         Ok(if let Some(pem) = self.load_cert().await? {
@@ -121,6 +130,23 @@ impl AcmeManager {
         })
     }
 
+    pub(crate) async fn init_account(&self) -> Result<(), AcmeError> {
+        // Load account key from cache or generate a new one,
+        // unless it was inherited from a parent manager
+        if self.account_key.load().is_empty() {
+            let account_key = if let Some(account_key) = self.load_account().await? {
+                account_key
+            } else {
+                let account_key = Account::generate_key_pair();
+                self.store_account(&account_key).await?;
+                account_key
+            };
+            self.account_key.store(Arc::new(account_key));
+        }
+
+        Ok(())
+    }
+
     pub fn has_order_in_progress(&self) -> bool {
         self.order_in_progress.load(Ordering::Relaxed)
     }
@@ -134,7 +160,16 @@ impl SpawnAcme for Arc<AcmeManager> {
     fn spawn(self, mut shutdown_rx: watch::Receiver<bool>) {
         tokio::spawn(async move {
             let acme = self;
-            let mut renew_at = match acme.init().await {
+
+            // Managers serving only directory domains do not order certificates themselves
+            let init = if !acme.domains.is_empty() {
+                acme.init()
+                    .await
+                    .map(|renew_at| Some(Instant::now() + renew_at))
+            } else {
+                acme.init_account().await.map(|_| None)
+            };
+            let mut renew_at = match init {
                 Ok(renew_at) => renew_at,
                 Err(err) => {
                     tracing::error!(
@@ -142,28 +177,31 @@ impl SpawnAcme for Arc<AcmeManager> {
                         event = "error",
                         error = ?err,
                         "Failed to initialize ACME certificate manager.");
+                    acme.set_failed(&err);
 
                     return;
                 }
             };
 
             loop {
+                let renew_deadline = renew_at.unwrap_or_else(Instant::now);
                 tokio::select! {
-                    _ = tokio::time::sleep(renew_at) => {
+                    _ = tokio::time::sleep_until(renew_deadline), if renew_at.is_some() => {
                         tracing::info!(
                             context = "acme",
                             event = "order",
                             domains = ?acme.domains,
                             "Ordering certificates.");
 
+                        acme.set_state(AcmeState::Ordering);
                         match acme.renew().await {
                             Ok(renew_at_) => {
-                                renew_at = renew_at_;
+                                renew_at = Some(Instant::now() + renew_at_);
                                 tracing::info!(
                                     context = "acme",
                                     event = "success",
                                     domains = ?acme.domains,
-                                    next_renewal = ?renew_at,
+                                    next_renewal = ?renew_at_,
                                     "Certificates renewed.");
                             },
                             Err(err) => {
@@ -172,12 +210,26 @@ impl SpawnAcme for Arc<AcmeManager> {
                                     event = "error",
                                     error = ?err,
                                     "Failed to renew certificates.");
+                                acme.set_failed(&err);
 
-                                renew_at = Duration::from_secs(3600);
+                                renew_at = Some(Instant::now() + Duration::from_secs(3600));
                             },
                         }
 
                     },
+                    _ = acme.notify.notified() => {
+                        if acme.cancelled.load(Ordering::Relaxed) {
+                            tracing::debug!(
+                                context = "acme",
+                                event = "cancel",
+                                domains = ?acme.domains,
+                                "ACME certificate manager stopped.");
+
+                            break;
+                        }
+
+                        acme.apply_directory_domains(&shutdown_rx);
+                    },
                     _ = shutdown_rx.changed() => {
                         tracing::debug!(
                             context = "acme",
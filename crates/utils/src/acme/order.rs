@@ -59,6 +59,7 @@ impl AcmeManager {
             .to_std()
             .unwrap_or_default();
         let renewal_date = validity[1] - self.renew_before;
+        self.set_valid(validity, renewal_date);
 
         tracing::info!(
             context = "acme",
@@ -43,7 +43,13 @@ impl AcmeManager {
 
 impl ResolvesServerCert for AcmeManager {
     fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
-        if self.has_order_in_progress() && client_hello.is_tls_alpn_challenge() {
+        // Select the certificate issued for a directory domain, if any
+        if let Some(manager) = client_hello
+            .server_name()
+            .and_then(|domain| self.resolve_domain(domain))
+        {
+            manager.resolve(client_hello)
+        } else if self.has_order_in_progress() && client_hello.is_tls_alpn_challenge() {
             match client_hello.server_name() {
                 None => {
                     tracing::debug!(
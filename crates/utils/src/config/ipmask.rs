@@ -21,7 +21,10 @@
  * for more details.
 */
 
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
 
 use super::utils::{AsKey, ParseValue};
 
@@ -130,6 +133,17 @@ impl ParseValue for IpAddrMask {
     }
 }
 
+impl Display for IpAddrMask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IpAddrMask::V4 { addr, mask } if *mask == u32::MAX => addr.fmt(f),
+            IpAddrMask::V4 { addr, mask } => write!(f, "{}/{}", addr, mask.count_ones()),
+            IpAddrMask::V6 { addr, mask } if *mask == u128::MAX => addr.fmt(f),
+            IpAddrMask::V6 { addr, mask } => write!(f, "{}/{}", addr, mask.count_ones()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -21,9 +21,9 @@
  * for more details.
 */
 
-use std::{net::SocketAddr, sync::Arc};
+use std::{io::Cursor, net::SocketAddr, sync::Arc};
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use rustls::{
     crypto::ring::{
         cipher_suite::{
@@ -34,17 +34,21 @@ use rustls::{
         },
         default_provider,
     },
-    server::ResolvesServerCert,
-    ServerConfig, SupportedCipherSuite, ALL_VERSIONS,
+    server::{ResolvesServerCert, WebPkiClientVerifier},
+    RootCertStore, ServerConfig, SupportedCipherSuite, ALL_VERSIONS,
 };
+use rustls_pemfile::certs;
 use tokio::net::TcpSocket;
 use tokio_rustls::TlsAcceptor;
 
 use crate::{
     acme::{directory::ACME_TLS_ALPN_NAME, AcmeManager},
     listener::{
+        bandwidth::Bandwidth,
+        banner::Banner,
         blocked::BlockedIps,
-        tls::{Certificate, CertificateResolver},
+        manager::ListenerManager,
+        tls::{Certificate, CertificateResolver, TlsClientAuth},
         TcpAcceptor,
     },
     UnwrapFailure,
@@ -53,12 +57,15 @@ use crate::{
 use super::{
     tls::{TLS12_VERSION, TLS13_VERSION},
     utils::{AsKey, ParseKey, ParseValue},
-    Config, Listener, Server, ServerProtocol, Servers,
+    Config, Listener, Server, ServerProtocol, Servers, VirtualHost,
 };
 
 impl Config {
     pub fn parse_servers(&self) -> super::Result<Servers> {
-        let mut servers = Servers::default();
+        let mut servers = Servers {
+            listener_manager: Arc::new(ListenerManager::new(self)),
+            ..Default::default()
+        };
 
         // Parse certificates and ACME managers
         let certificates = self.parse_certificates()?;
@@ -66,8 +73,13 @@ impl Config {
 
         // Parse servers
         for (internal_id, id) in self.sub_keys("server.listener", ".protocol").enumerate() {
-            let mut server =
-                self.parse_server(id, &certificates, &acmes, servers.blocked_ips.clone())?;
+            let mut server = self.parse_server(
+                id,
+                &certificates,
+                &acmes,
+                servers.blocked_ips.clone(),
+                servers.listener_manager.clone(),
+            )?;
             if !servers.inner.iter().any(|s| s.id == server.id) {
                 server.internal_id = internal_id as u16;
                 servers.inner.push(server);
@@ -76,8 +88,30 @@ impl Config {
             }
         }
 
+        // Virtual hosts are identified as listeners in session rules
+        let mut internal_id = servers.inner.len() as u16;
+        let mut ids = servers
+            .inner
+            .iter()
+            .map(|server| server.id.clone())
+            .collect::<AHashSet<_>>();
+        for server in &mut servers.inner {
+            for vhost in &mut server.virtual_hosts {
+                if ids.insert(vhost.id.clone()) {
+                    vhost.internal_id = internal_id;
+                    internal_id += 1;
+                } else {
+                    return Err(format!(
+                        "Virtual host id {:?} is already used by another listener.",
+                        vhost.id
+                    ));
+                }
+            }
+        }
+
         // Add certificates with valid paths
         for (id, cert) in certificates {
+            servers.monitored_certificates.push(cert.clone());
             if cert.path.len() == 2 {
                 servers.certificates.push(cert);
             } else {
@@ -92,7 +126,7 @@ impl Config {
 
         // Add ACME managers with configured domains
         for (id, acme) in acmes {
-            if !acme.domains.is_empty() {
+            if !acme.domains.is_empty() || acme.has_directory_domains() {
                 servers.acme_managers.push(acme);
             } else {
                 tracing::debug!(
@@ -111,14 +145,7 @@ impl Config {
         }
     }
 
-    fn parse_server(
-        &self,
-        id: &str,
-        certificates: &AHashMap<String, Arc<Certificate>>,
-        acmes: &AHashMap<String, Arc<AcmeManager>>,
-        blocked_ips: Arc<BlockedIps>,
-    ) -> super::Result<Server> {
-        // Build listeners
+    pub fn parse_listeners(&self, id: &str) -> super::Result<Vec<Listener>> {
         let mut listeners = Vec::new();
         for result in self.properties::<SocketAddr>(("server.listener", id, "bind")) {
             // Parse bind address and build socket
@@ -182,12 +209,25 @@ impl Config {
             });
         }
 
+        Ok(listeners)
+    }
+
+    fn parse_server(
+        &self,
+        id: &str,
+        certificates: &AHashMap<String, Arc<Certificate>>,
+        acmes: &AHashMap<String, Arc<AcmeManager>>,
+        blocked_ips: Arc<BlockedIps>,
+        listener_manager: Arc<ListenerManager>,
+    ) -> super::Result<Server> {
+        // Build listeners
+        let listeners = self.parse_listeners(id)?;
         if listeners.is_empty() {
             return Err(format!("No 'bind' directive found for listener id {id:?}"));
         }
 
         // Build TLS config
-        let (acceptor, tls_implicit) = if self
+        let (acceptor, tls_implicit, tls_client_auth) = if self
             .property_or_default(("server.listener", id, "tls.enable"), "server.tls.enable")?
             .unwrap_or(false)
         {
@@ -284,8 +324,70 @@ impl Config {
                 provider.cipher_suites = ciphers;
             }
 
+            // Build client certificate verifier
+            let (client_verifier, tls_client_auth) = if self
+                .property_or_default(
+                    ("server.listener", id, "tls.client-auth.enable"),
+                    "server.tls.client-auth.enable",
+                )?
+                .unwrap_or(false)
+            {
+                let mut roots = RootCertStore::empty();
+                for (key, _) in self.values_or_default(
+                    ("server.listener", id, "tls.client-auth.ca"),
+                    "server.tls.client-auth.ca",
+                ) {
+                    for cert in certs(&mut Cursor::new(self.file_contents(key)?)) {
+                        roots
+                            .add(cert.map_err(|err| {
+                                format!("Failed to read CA certificate from {key:?}: {err}")
+                            })?)
+                            .map_err(|err| {
+                                format!("Failed to add CA certificate from {key:?}: {err}")
+                            })?;
+                    }
+                }
+                if roots.is_empty() {
+                    return Err(format!(
+                        "No CA certificates found for client authentication in listener {id:?}."
+                    ));
+                }
+
+                let mut builder = WebPkiClientVerifier::builder(Arc::new(roots));
+                if !self
+                    .property_or_default(
+                        ("server.listener", id, "tls.client-auth.required"),
+                        "server.tls.client-auth.required",
+                    )?
+                    .unwrap_or(false)
+                {
+                    builder = builder.allow_unauthenticated();
+                }
+                let verifier = builder.build().map_err(|err| {
+                    format!(
+                        "Failed to build client certificate verifier for listener {id:?}: {err}"
+                    )
+                })?;
+
+                let mut principal = self
+                    .values_or_default(
+                        ("server.listener", id, "tls.client-auth.principal"),
+                        "server.tls.client-auth.principal",
+                    )
+                    .map(|(_, value)| value.trim().to_string())
+                    .filter(|value| !value.is_empty())
+                    .collect::<Vec<_>>();
+                if principal.is_empty() {
+                    principal = vec!["{email}".to_string(), "{cn}".to_string()];
+                }
+
+                (Some(verifier), Some(TlsClientAuth { principal }))
+            } else {
+                (None, None)
+            };
+
             // Build server config
-            let mut config = ServerConfig::builder_with_provider(provider.into())
+            let config = ServerConfig::builder_with_provider(provider.into())
                 .with_protocol_versions(if tls_v3 == tls_v2 {
                     ALL_VERSIONS
                 } else if tls_v3 {
@@ -293,9 +395,13 @@ impl Config {
                 } else {
                     TLS12_VERSION
                 })
-                .map_err(|err| format!("Failed to build TLS config: {err}"))?
-                .with_no_client_auth()
-                .with_cert_resolver(resolver.clone());
+                .map_err(|err| format!("Failed to build TLS config: {err}"))?;
+            let mut config = if let Some(verifier) = client_verifier {
+                config.with_client_cert_verifier(verifier)
+            } else {
+                config.with_no_client_auth()
+            }
+            .with_cert_resolver(resolver.clone());
             config.ignore_client_order = self
                 .property_or_default(
                     ("server.listener", id, "tls.ignore-client-order"),
@@ -325,9 +431,10 @@ impl Config {
                     "server.tls.implicit",
                 )?
                 .unwrap_or(true),
+                tls_client_auth,
             )
         } else {
-            (TcpAcceptor::Plain, false)
+            (TcpAcceptor::Plain, false, None)
         };
 
         let protocol = self.property_require(("server.listener", id, "protocol"))?;
@@ -341,46 +448,153 @@ impl Config {
             proxy_networks.push(network.parse_key("server.proxy.trusted-networks")?);
         }
 
+        // Parse pre-authentication banner
+        let hostname = self
+            .value_or_default(("server.listener", id, "hostname"), "server.hostname")
+            .ok_or("Hostname directive not found.")?
+            .to_string();
+        let banner = self
+            .value_or_default(("server.listener", id, "banner"), "server.banner")
+            .map(|banner| {
+                Banner::parse(banner, &hostname)
+                    .map_err(|err| format!("Invalid banner for listener {id:?}: {err}"))
+            })
+            .transpose()?;
+
+        let data = match protocol {
+            ServerProtocol::Smtp | ServerProtocol::Lmtp => self
+                .value_or_default(("server.listener", id, "greeting"), "server.greeting")
+                .unwrap_or(concat!(
+                    "Stalwart SMTP v",
+                    env!("CARGO_PKG_VERSION"),
+                    " at your service."
+                ))
+                .to_string(),
+
+            ServerProtocol::Jmap => self
+                .value_or_default(("server.listener", id, "url"), "server.url")
+                .failed(&format!("No 'url' directive found for listener {id:?}"))
+                .to_string(),
+            ServerProtocol::Imap | ServerProtocol::Http | ServerProtocol::ManageSieve => self
+                .value_or_default(("server.listener", id, "url"), "server.url")
+                .unwrap_or_default()
+                .to_string(),
+        };
+        let virtual_hosts = self.parse_virtual_hosts(id, protocol, &hostname, &data, &banner)?;
+
         Ok(Server {
             id: id.to_string(),
             internal_id: 0,
-            hostname: self
-                .value_or_default(("server.listener", id, "hostname"), "server.hostname")
-                .ok_or("Hostname directive not found.")?
-                .to_string(),
-            data: match protocol {
-                ServerProtocol::Smtp | ServerProtocol::Lmtp => self
-                    .value_or_default(("server.listener", id, "greeting"), "server.greeting")
-                    .unwrap_or(concat!(
-                        "Stalwart SMTP v",
-                        env!("CARGO_PKG_VERSION"),
-                        " at your service."
-                    ))
-                    .to_string(),
-
-                ServerProtocol::Jmap => self
-                    .value_or_default(("server.listener", id, "url"), "server.url")
-                    .failed(&format!("No 'url' directive found for listener {id:?}"))
-                    .to_string(),
-                ServerProtocol::Imap | ServerProtocol::Http | ServerProtocol::ManageSieve => self
-                    .value_or_default(("server.listener", id, "url"), "server.url")
-                    .unwrap_or_default()
-                    .to_string(),
-            },
+            hostname,
+            banner,
+            data,
             max_connections: self
                 .property_or_default(
                     ("server.listener", id, "max-connections"),
                     "server.max-connections",
                 )?
                 .unwrap_or(8192),
+            bandwidth: Bandwidth::new(
+                &self
+                    .property_or_default(
+                        ("server.listener", id, "bandwidth.download"),
+                        "server.bandwidth.download",
+                    )?
+                    .unwrap_or_default(),
+                &self
+                    .property_or_default(
+                        ("server.listener", id, "bandwidth.upload"),
+                        "server.bandwidth.upload",
+                    )?
+                    .unwrap_or_default(),
+            ),
             protocol,
             listeners,
             acceptor,
             tls_implicit,
+            tls_client_auth,
             proxy_networks,
             blocked_ips,
+            listener_manager,
+            virtual_hosts,
         })
     }
+
+    fn parse_virtual_hosts(
+        &self,
+        id: &str,
+        protocol: ServerProtocol,
+        hostname: &str,
+        data: &str,
+        banner: &Option<Banner>,
+    ) -> super::Result<Vec<VirtualHost>> {
+        let fallback = self.value(("server.listener", id, "sni-fallback"));
+        let mut virtual_hosts = Vec::new();
+
+        for vhost_id in self.sub_keys(("server.listener", id, "virtual-host"), "") {
+            let prefix = format!("server.listener.{id}.virtual-host.{vhost_id}");
+            let prefix = prefix.as_str();
+
+            // Server names are matched exactly or by a leading wildcard label
+            let mut server_names = Vec::new();
+            for (_, name) in self.values((prefix, "server-name")) {
+                let name = name.trim().trim_end_matches('.').to_lowercase();
+                if name.is_empty()
+                    || name.contains('*') && (!name.starts_with("*.") || name[2..].contains('*'))
+                {
+                    return Err(format!(
+                        "Invalid server name {name:?} for virtual host {vhost_id:?}."
+                    ));
+                }
+                server_names.push(name);
+            }
+            if server_names.is_empty() && fallback != Some(vhost_id) {
+                return Err(format!(
+                    "Virtual host {vhost_id:?} of listener {id:?} has no server names."
+                ));
+            }
+
+            let vhost_hostname = self
+                .value((prefix, "hostname"))
+                .unwrap_or(hostname)
+                .to_string();
+            let vhost_banner = match self.value((prefix, "banner")) {
+                Some(value) => Banner::parse(value, &vhost_hostname)
+                    .map_err(|err| format!("Invalid banner for virtual host {vhost_id:?}: {err}"))?
+                    .into(),
+                None => banner.clone(),
+            };
+            let vhost_data = match protocol {
+                ServerProtocol::Smtp | ServerProtocol::Lmtp => self.value((prefix, "greeting")),
+                _ => self.value((prefix, "url")),
+            }
+            .unwrap_or(data)
+            .to_string();
+
+            virtual_hosts.push(VirtualHost {
+                id: vhost_id.to_string(),
+                internal_id: 0,
+                server_names,
+                hostname: vhost_hostname,
+                data: vhost_data,
+                banner: vhost_banner,
+                max_connections: self
+                    .property((prefix, "max-connections"))?
+                    .unwrap_or(u64::MAX),
+                is_fallback: fallback == Some(vhost_id),
+            });
+        }
+
+        if let Some(fallback) = fallback {
+            if !virtual_hosts.iter().any(|vhost| vhost.is_fallback) {
+                return Err(format!(
+                    "Fallback virtual host {fallback:?} does not exist for listener {id:?}."
+                ));
+            }
+        }
+
+        Ok(virtual_hosts)
+    }
 }
 
 impl ParseValue for ServerProtocol {
@@ -26,6 +26,7 @@ pub mod dynvalue;
 pub mod ipmask;
 pub mod listener;
 pub mod parser;
+pub mod secrets;
 pub mod tls;
 pub mod utils;
 
@@ -44,7 +45,14 @@ use tokio::net::TcpSocket;
 use crate::{
     acme::AcmeManager,
     failed,
-    listener::{blocked::BlockedIps, tls::Certificate, TcpAcceptor},
+    listener::{
+        bandwidth::Bandwidth,
+        banner::Banner,
+        blocked::BlockedIps,
+        manager::ListenerManager,
+        tls::{Certificate, TlsClientAuth},
+        TcpAcceptor,
+    },
     UnwrapFailure,
 };
 
@@ -67,21 +75,40 @@ pub struct Server {
     pub internal_id: u16,
     pub hostname: String,
     pub data: String,
+    pub banner: Option<Banner>,
     pub protocol: ServerProtocol,
     pub listeners: Vec<Listener>,
     pub proxy_networks: Vec<IpAddrMask>,
     pub blocked_ips: Arc<BlockedIps>,
+    pub listener_manager: Arc<ListenerManager>,
     pub acceptor: TcpAcceptor,
     pub tls_implicit: bool,
+    pub tls_client_auth: Option<TlsClientAuth>,
     pub max_connections: u64,
+    pub bandwidth: Bandwidth,
+    pub virtual_hosts: Vec<VirtualHost>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct VirtualHost {
+    pub id: String,
+    pub internal_id: u16,
+    pub server_names: Vec<String>,
+    pub hostname: String,
+    pub data: String,
+    pub banner: Option<Banner>,
+    pub max_connections: u64,
+    pub is_fallback: bool,
 }
 
 #[derive(Default)]
 pub struct Servers {
     pub inner: Vec<Server>,
     pub certificates: Vec<Arc<Certificate>>,
+    pub monitored_certificates: Vec<Arc<Certificate>>,
     pub acme_managers: Vec<Arc<AcmeManager>>,
     pub blocked_ips: Arc<BlockedIps>,
+    pub listener_manager: Arc<ListenerManager>,
 }
 
 #[derive(Debug)]
@@ -0,0 +1,845 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use ahash::{AHashMap, AHashSet};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use parking_lot::Mutex;
+use reqwest::{header::CONTENT_TYPE, Method};
+use ring::{digest, hmac};
+use serde_json::Value;
+use tokio::sync::watch;
+
+use super::Config;
+
+pub const SECRET_PREFIX: &str = "secret://";
+
+const LEASE_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+const LEASE_IDLE_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[derive(Default)]
+pub struct Secrets {
+    providers: AHashMap<String, SecretProvider>,
+    cache: Mutex<AHashMap<String, CachedSecret>>,
+    leases: Mutex<AHashMap<String, Lease>>,
+}
+
+enum SecretProvider {
+    Vault(VaultProvider),
+    AwsSsm(AwsProvider),
+    AwsKms(AwsProvider),
+}
+
+struct VaultProvider {
+    url: String,
+    token: String,
+    namespace: Option<String>,
+    client: reqwest::Client,
+    cache_ttl: Duration,
+}
+
+struct AwsProvider {
+    region: String,
+    endpoint: Option<String>,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+    client: reqwest::Client,
+    cache_ttl: Duration,
+}
+
+struct CachedSecret {
+    value: String,
+    expires: Instant,
+}
+
+struct Lease {
+    provider_id: String,
+    duration: Duration,
+    renew_at: Instant,
+    expires: Instant,
+}
+
+struct SecretValue {
+    value: String,
+    lease: Option<(String, Duration)>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct SecretReference<'x> {
+    provider_id: &'x str,
+    path: &'x str,
+    field: Option<&'x str>,
+}
+
+impl Config {
+    pub fn parse_secrets(&self) -> super::Result<Secrets> {
+        let mut secrets = Secrets::default();
+
+        for id in self.sub_keys("secrets", ".type") {
+            let timeout = self.property_or_static::<Duration>(("secrets", id, "timeout"), "10s")?;
+            let cache_ttl =
+                self.property_or_static::<Duration>(("secrets", id, "cache.ttl"), "5m")?;
+            let client = reqwest::Client::builder()
+                .timeout(timeout)
+                .danger_accept_invalid_certs(
+                    self.property_or_static(("secrets", id, "allow-invalid-certs"), "false")?,
+                )
+                .build()
+                .map_err(|err| format!("Failed to build HTTP client for secrets {id:?}: {err}"))?;
+
+            let provider = match self.value_require(("secrets", id, "type"))? {
+                "vault" => SecretProvider::Vault(VaultProvider {
+                    url: self
+                        .value_require(("secrets", id, "url"))?
+                        .trim_end_matches('/')
+                        .to_string(),
+                    token: self.value_require(("secrets", id, "token"))?.to_string(),
+                    namespace: self
+                        .value(("secrets", id, "namespace"))
+                        .map(|v| v.to_string()),
+                    client,
+                    cache_ttl,
+                }),
+                typ @ ("aws-ssm" | "aws-kms") => {
+                    let provider = AwsProvider {
+                        region: self.value_require(("secrets", id, "region"))?.to_string(),
+                        endpoint: self
+                            .value(("secrets", id, "endpoint"))
+                            .map(|v| v.trim_end_matches('/').to_string()),
+                        access_key: self
+                            .value_require(("secrets", id, "access-key"))?
+                            .to_string(),
+                        secret_key: self
+                            .value_require(("secrets", id, "secret-key"))?
+                            .to_string(),
+                        session_token: self
+                            .value(("secrets", id, "session-token"))
+                            .filter(|v| !v.is_empty())
+                            .map(|v| v.to_string()),
+                        client,
+                        cache_ttl,
+                    };
+                    if typ == "aws-ssm" {
+                        SecretProvider::AwsSsm(provider)
+                    } else {
+                        SecretProvider::AwsKms(provider)
+                    }
+                }
+                typ => {
+                    return Err(format!(
+                        "Invalid secrets provider type {typ:?} for property \"secrets.{id}.type\"."
+                    ))
+                }
+            };
+            secrets.providers.insert(id.to_string(), provider);
+        }
+
+        Ok(secrets)
+    }
+
+    pub async fn resolve_secrets(&mut self, secrets: &Secrets) {
+        let references = self
+            .keys
+            .iter()
+            .filter(|(key, value)| value.starts_with(SECRET_PREFIX) && !key.starts_with("secrets."))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<Vec<_>>();
+        let mut failed_listeners = AHashSet::new();
+
+        for (key, reference) in references {
+            match secrets.resolve(&reference).await {
+                Ok(value) => {
+                    self.keys.insert(key, value);
+                }
+                Err(err) => {
+                    tracing::error!(
+                        context = "secrets",
+                        event = "error",
+                        key = key,
+                        reference = reference,
+                        reason = err,
+                        "Failed to resolve secret."
+                    );
+
+                    // Never fall back to the literal reference
+                    self.keys.remove(&key);
+                    if let Some((id, _)) = key
+                        .strip_prefix("server.listener.")
+                        .and_then(|key| key.split_once('.'))
+                    {
+                        failed_listeners.insert(id.to_string());
+                    }
+                }
+            }
+        }
+
+        // Disable listeners that depend on unresolved secrets
+        for id in failed_listeners {
+            let prefix = format!("server.listener.{id}.");
+            self.keys.retain(|key, _| !key.starts_with(&prefix));
+            tracing::warn!(
+                context = "secrets",
+                event = "disabled",
+                listener = id,
+                "Listener disabled due to unresolved secrets."
+            );
+        }
+    }
+}
+
+impl Secrets {
+    pub async fn resolve(&self, reference: &str) -> super::Result<String> {
+        let reference = SecretReference::parse(reference)?;
+        let cache_key = reference.to_string();
+        let now = Instant::now();
+
+        if let Some(cached) = self.cache.lock().get(&cache_key) {
+            if cached.expires > now {
+                return Ok(cached.value.clone());
+            }
+        }
+
+        let provider = self.providers.get(reference.provider_id).ok_or_else(|| {
+            format!(
+                "Secrets provider {:?} is not configured.",
+                reference.provider_id
+            )
+        })?;
+        let result = match provider {
+            SecretProvider::Vault(vault) => vault.fetch(&reference).await,
+            SecretProvider::AwsSsm(aws) => aws.get_parameter(&reference).await,
+            SecretProvider::AwsKms(aws) => aws.decrypt(&reference).await,
+        };
+
+        match result {
+            Ok(secret) => {
+                let mut ttl = provider.cache_ttl();
+                if let Some((lease_id, duration)) = secret.lease {
+                    ttl = std::cmp::min(ttl, duration);
+                    self.leases.lock().insert(
+                        lease_id,
+                        Lease {
+                            provider_id: reference.provider_id.to_string(),
+                            duration,
+                            renew_at: now + duration * 2 / 3,
+                            expires: now + duration,
+                        },
+                    );
+                }
+                self.cache.lock().insert(
+                    cache_key,
+                    CachedSecret {
+                        value: secret.value.clone(),
+                        expires: now + ttl,
+                    },
+                );
+                Ok(secret.value)
+            }
+            Err(err) => {
+                // Serve the last known value rather than failing a reload
+                if let Some(cached) = self.cache.lock().get(&cache_key) {
+                    tracing::warn!(
+                        context = "secrets",
+                        event = "stale",
+                        reference = cache_key,
+                        reason = err,
+                        "Failed to refresh secret, using cached value."
+                    );
+                    Ok(cached.value.clone())
+                } else {
+                    Err(err)
+                }
+            }
+        }
+    }
+
+    pub fn spawn(self: Arc<Self>, mut shutdown_rx: watch::Receiver<bool>) {
+        if !self
+            .providers
+            .values()
+            .any(|provider| matches!(provider, SecretProvider::Vault(_)))
+        {
+            return;
+        }
+
+        tokio::spawn(async move {
+            loop {
+                let time_to_next = self
+                    .leases
+                    .lock()
+                    .values()
+                    .map(|lease| lease.renew_at.saturating_duration_since(Instant::now()))
+                    .min()
+                    .unwrap_or(LEASE_IDLE_INTERVAL);
+                if tokio::time::timeout(time_to_next, shutdown_rx.changed())
+                    .await
+                    .is_ok()
+                {
+                    tracing::debug!("Secrets lease renewal task exiting.");
+                    return;
+                }
+
+                self.renew_leases().await;
+            }
+        });
+    }
+
+    async fn renew_leases(&self) {
+        let now = Instant::now();
+        let due = self
+            .leases
+            .lock()
+            .iter()
+            .filter(|(_, lease)| lease.renew_at <= now)
+            .map(|(lease_id, lease)| (lease_id.clone(), lease.provider_id.clone(), lease.duration))
+            .collect::<Vec<_>>();
+
+        for (lease_id, provider_id, duration) in due {
+            let result = match self.providers.get(&provider_id) {
+                Some(SecretProvider::Vault(vault)) => vault.renew(&lease_id, duration).await,
+                _ => Err(format!(
+                    "Secrets provider {provider_id:?} does not support leases."
+                )),
+            };
+            let mut leases = self.leases.lock();
+
+            match result {
+                Ok(duration) => {
+                    tracing::debug!(
+                        context = "secrets",
+                        event = "renew",
+                        lease_id = lease_id,
+                        duration = duration.as_secs(),
+                        "Renewed secret lease."
+                    );
+                    let now = Instant::now();
+                    if let Some(lease) = leases.get_mut(&lease_id) {
+                        lease.duration = duration;
+                        lease.renew_at = now + duration * 2 / 3;
+                        lease.expires = now + duration;
+                    }
+                }
+                Err(err) => {
+                    let now = Instant::now();
+                    let expired = leases
+                        .get(&lease_id)
+                        .map_or(true, |lease| lease.expires <= now + LEASE_RETRY_INTERVAL);
+                    tracing::error!(
+                        context = "secrets",
+                        event = "error",
+                        lease_id = lease_id,
+                        reason = err,
+                        expired = expired,
+                        "Failed to renew secret lease."
+                    );
+                    if expired {
+                        leases.remove(&lease_id);
+                    } else if let Some(lease) = leases.get_mut(&lease_id) {
+                        lease.renew_at = now + LEASE_RETRY_INTERVAL;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl SecretProvider {
+    fn cache_ttl(&self) -> Duration {
+        match self {
+            SecretProvider::Vault(vault) => vault.cache_ttl,
+            SecretProvider::AwsSsm(aws) | SecretProvider::AwsKms(aws) => aws.cache_ttl,
+        }
+    }
+}
+
+impl VaultProvider {
+    async fn fetch(&self, reference: &SecretReference<'_>) -> super::Result<SecretValue> {
+        let response = self.request(Method::GET, reference.path, None).await?;
+        let data = response
+            .get("data")
+            .ok_or_else(|| "Vault response does not contain any data.".to_string())?;
+
+        // KV version 2 engines nest the secret under an additional "data" object
+        let data = match (data.get("data"), data.get("metadata")) {
+            (Some(inner @ Value::Object(_)), Some(_)) => inner,
+            _ => data,
+        };
+        let value = select_field(data, reference.field)?;
+        let lease = match (
+            response.get("lease_id").and_then(|v| v.as_str()),
+            response.get("renewable").and_then(|v| v.as_bool()),
+            response.get("lease_duration").and_then(|v| v.as_u64()),
+        ) {
+            (Some(lease_id), Some(true), Some(duration))
+                if !lease_id.is_empty() && duration > 0 =>
+            {
+                Some((lease_id.to_string(), Duration::from_secs(duration)))
+            }
+            _ => None,
+        };
+
+        Ok(SecretValue { value, lease })
+    }
+
+    async fn renew(&self, lease_id: &str, duration: Duration) -> super::Result<Duration> {
+        let response = self
+            .request(
+                Method::PUT,
+                "sys/leases/renew",
+                serde_json::json!({
+                    "lease_id": lease_id,
+                    "increment": duration.as_secs(),
+                })
+                .to_string()
+                .into(),
+            )
+            .await?;
+
+        response
+            .get("lease_duration")
+            .and_then(|v| v.as_u64())
+            .filter(|duration| *duration > 0)
+            .map(Duration::from_secs)
+            .ok_or_else(|| "Vault did not return a lease duration.".to_string())
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<String>,
+    ) -> super::Result<Value> {
+        let mut request = self
+            .client
+            .request(
+                method,
+                format!("{}/v1/{}", self.url, path.trim_start_matches('/')),
+            )
+            .header("X-Vault-Token", &self.token);
+        if let Some(namespace) = &self.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+        if let Some(body) = body {
+            request = request.header(CONTENT_TYPE, "application/json").body(body);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|err| format!("Vault request failed: {err}"))?;
+        let status = response.status();
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|err| format!("Failed to read Vault response: {err}"))?;
+        if status.is_success() {
+            serde_json::from_slice(&bytes).map_err(|err| format!("Invalid Vault response: {err}"))
+        } else {
+            Err(format!(
+                "Vault returned status {status}: {}",
+                String::from_utf8_lossy(&bytes)
+            ))
+        }
+    }
+}
+
+impl AwsProvider {
+    async fn get_parameter(&self, reference: &SecretReference<'_>) -> super::Result<SecretValue> {
+        // Hierarchical parameter names always start with a slash
+        let name = if reference.path.contains('/') {
+            format!("/{}", reference.path)
+        } else {
+            reference.path.to_string()
+        };
+        let response = self
+            .request(
+                "ssm",
+                "AmazonSSM.GetParameter",
+                serde_json::json!({
+                    "Name": name,
+                    "WithDecryption": true,
+                }),
+            )
+            .await?;
+        let value = response
+            .get("Parameter")
+            .and_then(|p| p.get("Value"))
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| format!("SSM parameter {name:?} has no value."))?;
+
+        Ok(SecretValue {
+            value: if let Some(field) = reference.field {
+                select_field(
+                    &serde_json::from_str(value)
+                        .map_err(|err| format!("SSM parameter {name:?} is not JSON: {err}"))?,
+                    Some(field),
+                )?
+            } else {
+                value.to_string()
+            },
+            lease: None,
+        })
+    }
+
+    async fn decrypt(&self, reference: &SecretReference<'_>) -> super::Result<SecretValue> {
+        let response = self
+            .request(
+                "kms",
+                "TrentService.Decrypt",
+                serde_json::json!({
+                    "CiphertextBlob": reference.path,
+                }),
+            )
+            .await?;
+        let plaintext = response
+            .get("Plaintext")
+            .and_then(|v| v.as_str())
+            .and_then(|v| STANDARD.decode(v).ok())
+            .and_then(|v| String::from_utf8(v).ok())
+            .ok_or_else(|| "KMS returned an invalid plaintext.".to_string())?;
+
+        Ok(SecretValue {
+            value: plaintext,
+            lease: None,
+        })
+    }
+
+    async fn request(&self, service: &str, target: &str, body: Value) -> super::Result<Value> {
+        let url = self
+            .endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://{service}.{}.amazonaws.com", self.region));
+        let host = url
+            .split_once("://")
+            .map_or(url.as_str(), |(_, host)| host)
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let body = body.to_string();
+        let now = chrono::Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let authorization = self.authorization(service, &host, target, &amz_date, &body);
+
+        let mut request = self
+            .client
+            .post(format!("{url}/"))
+            .header(CONTENT_TYPE, "application/x-amz-json-1.1")
+            .header("X-Amz-Target", target)
+            .header("X-Amz-Date", &amz_date)
+            .header("Authorization", authorization);
+        if let Some(session_token) = &self.session_token {
+            request = request.header("X-Amz-Security-Token", session_token);
+        }
+
+        let response = request
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| format!("AWS {service} request failed: {err}"))?;
+        let status = response.status();
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|err| format!("Failed to read AWS {service} response: {err}"))?;
+        if status.is_success() {
+            serde_json::from_slice(&bytes)
+                .map_err(|err| format!("Invalid AWS {service} response: {err}"))
+        } else {
+            Err(format!(
+                "AWS {service} returned status {status}: {}",
+                String::from_utf8_lossy(&bytes)
+            ))
+        }
+    }
+
+    fn authorization(
+        &self,
+        service: &str,
+        host: &str,
+        target: &str,
+        amz_date: &str,
+        body: &str,
+    ) -> String {
+        let date = &amz_date[..8];
+        let (signed_headers, security_token) = if let Some(token) = &self.session_token {
+            (
+                "content-type;host;x-amz-date;x-amz-security-token;x-amz-target",
+                format!("x-amz-security-token:{token}\n"),
+            )
+        } else {
+            ("content-type;host;x-amz-date;x-amz-target", String::new())
+        };
+        let canonical_request = format!(
+            concat!(
+                "POST\n/\n\n",
+                "content-type:application/x-amz-json-1.1\n",
+                "host:{}\n",
+                "x-amz-date:{}\n",
+                "{}",
+                "x-amz-target:{}\n\n",
+                "{}\n{}"
+            ),
+            host,
+            amz_date,
+            security_token,
+            target,
+            signed_headers,
+            hex(digest::digest(&digest::SHA256, body.as_bytes()).as_ref())
+        );
+        let scope = format!("{date}/{}/{service}/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex(digest::digest(&digest::SHA256, canonical_request.as_bytes()).as_ref())
+        );
+        let signature = hmac::sign(
+            &hmac::Key::new(
+                hmac::HMAC_SHA256,
+                &signing_key(&self.secret_key, date, &self.region, service),
+            ),
+            string_to_sign.as_bytes(),
+        );
+
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={}",
+            self.access_key,
+            hex(signature.as_ref())
+        )
+    }
+}
+
+impl<'x> SecretReference<'x> {
+    fn parse(reference: &'x str) -> super::Result<Self> {
+        let (provider_id, path) = reference
+            .strip_prefix(SECRET_PREFIX)
+            .and_then(|reference| reference.split_once('/'))
+            .ok_or_else(|| format!("Invalid secret reference {reference:?}."))?;
+        let (path, field) = match path.rsplit_once('#') {
+            Some((path, field)) if !field.is_empty() => (path, Some(field)),
+            _ => (path, None),
+        };
+
+        if !provider_id.is_empty() && !path.is_empty() {
+            Ok(SecretReference {
+                provider_id,
+                path,
+                field,
+            })
+        } else {
+            Err(format!("Invalid secret reference {reference:?}."))
+        }
+    }
+}
+
+impl std::fmt::Display for SecretReference<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{SECRET_PREFIX}{}/{}", self.provider_id, self.path)?;
+        if let Some(field) = self.field {
+            write!(f, "#{field}")?;
+        }
+        Ok(())
+    }
+}
+
+fn select_field(data: &Value, field: Option<&str>) -> super::Result<String> {
+    let value = if let Some(field) = field {
+        data.get(field)
+            .ok_or_else(|| format!("Secret does not contain field {field:?}."))?
+    } else {
+        match data {
+            Value::Object(map) if map.len() == 1 => map.values().next().unwrap(),
+            Value::Object(_) => {
+                return Err("Secret contains multiple fields, specify one with '#field'.".into())
+            }
+            value => value,
+        }
+    };
+
+    match value {
+        Value::String(value) => Ok(value.to_string()),
+        Value::Number(value) => Ok(value.to_string()),
+        Value::Bool(value) => Ok(value.to_string()),
+        _ => Err("Secret field is not a scalar value.".into()),
+    }
+}
+
+fn signing_key(secret_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let mut key = format!("AWS4{secret_key}").into_bytes();
+    for part in [date, region, service, "aws4_request"] {
+        key = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &key), part.as_bytes())
+            .as_ref()
+            .to_vec();
+    }
+    key
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    #[test]
+    fn parse_secret_reference() {
+        for (reference, expected) in [
+            (
+                "secret://vault/secret/data/mail#password",
+                Some(("vault", "secret/data/mail", Some("password"))),
+            ),
+            (
+                "secret://ssm/mail/db-password",
+                Some(("ssm", "mail/db-password", None)),
+            ),
+            (
+                "secret://kms/AQICAHh+a/b=",
+                Some(("kms", "AQICAHh+a/b=", None)),
+            ),
+            ("secret://vault/", None),
+            ("secret://vault", None),
+            ("file://vault/secret", None),
+        ] {
+            let result = SecretReference::parse(reference).ok();
+            assert_eq!(
+                result.as_ref().map(|r| (r.provider_id, r.path, r.field)),
+                expected,
+                "{reference}"
+            );
+            if let Some(result) = result {
+                assert_eq!(result.to_string(), reference);
+            }
+        }
+    }
+
+    #[test]
+    fn aws_signing_key() {
+        // Example from the AWS Signature Version 4 documentation
+        assert_eq!(
+            hex(&signing_key(
+                "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+                "20120215",
+                "us-east-1",
+                "iam"
+            )),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[tokio::test]
+    async fn resolve_vault_secrets() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 4096];
+                let len = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..len]).into_owned();
+                let (status, body) = if !request.contains("x-vault-token: s.token") {
+                    ("403 Forbidden", r#"{"errors":["permission denied"]}"#)
+                } else if request.starts_with("GET /v1/secret/data/mail ") {
+                    (
+                        "200 OK",
+                        concat!(
+                            r#"{"lease_id":"","renewable":false,"lease_duration":0,"#,
+                            r#""data":{"data":{"password":"s3cr3t","user":"mail"},"#,
+                            r#""metadata":{"version":1}}}"#
+                        ),
+                    )
+                } else if request.starts_with("GET /v1/database/creds/mail ") {
+                    (
+                        "200 OK",
+                        concat!(
+                            r#"{"lease_id":"database/creds/mail/abc","renewable":true,"#,
+                            r#""lease_duration":3600,"data":{"password":"dyn-pass"}}"#
+                        ),
+                    )
+                } else {
+                    ("404 Not Found", r#"{"errors":[]}"#)
+                };
+                stream
+                    .write_all(
+                        format!(
+                            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                            body.len()
+                        )
+                        .as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let mut config = Config::default();
+        config
+            .parse(&format!(
+                concat!(
+                    "[secrets.vault]\n",
+                    "type = \"vault\"\n",
+                    "url = \"http://{}\"\n",
+                    "token = \"s.token\"\n",
+                    "\n",
+                    "[store.\"sql\"]\n",
+                    "password = \"secret://vault/database/creds/mail\"\n",
+                    "\n",
+                    "[server.listener.\"smtp\"]\n",
+                    "bind = \"127.0.0.1:25\"\n",
+                    "tls.password = \"secret://vault/secret/data/mail#password\"\n",
+                    "\n",
+                    "[server.listener.\"imap\"]\n",
+                    "bind = \"127.0.0.1:143\"\n",
+                    "tls.password = \"secret://vault/secret/data/missing#password\"\n",
+                    "\n",
+                    "[directory.\"ldap\"]\n",
+                    "bind.secret = \"secret://unknown/ldap\"\n",
+                ),
+                addr
+            ))
+            .unwrap();
+        let secrets = config.parse_secrets().unwrap();
+        config.resolve_secrets(&secrets).await;
+
+        assert_eq!(config.value("store.sql.password"), Some("dyn-pass"));
+        assert_eq!(
+            config.value("server.listener.smtp.tls.password"),
+            Some("s3cr3t")
+        );
+        assert_eq!(config.value("server.listener.imap.bind"), None);
+        assert_eq!(config.value("directory.ldap.bind.secret"), None);
+        assert_eq!(secrets.leases.lock().len(), 1);
+        assert!(secrets
+            .leases
+            .lock()
+            .contains_key("database/creds/mail/abc"));
+    }
+}
@@ -53,6 +53,7 @@ impl Config {
             let key_pk = ("certificate", cert_id, "private-key");
 
             let mut cert = Certificate {
+                id: cert_id.to_string(),
                 cert: ArcSwap::from(Arc::new(build_certified_key(
                     self.file_contents(key_cert)?,
                     self.file_contents(key_pk)?,
@@ -122,16 +123,20 @@ impl Config {
                 }
             }
 
-            acmes.insert(
-                acme_id.to_string(),
-                Arc::new(AcmeManager::new(
-                    directory,
-                    domains,
-                    contact,
-                    renew_before,
-                    cache,
-                )?),
-            );
+            let mut acme = AcmeManager::new(directory, domains, contact, renew_before, cache)?;
+
+            // Issue certificates for the domains present in the directory
+            if self.property_or_static(("acme", acme_id, "directory-domains.enable"), "false")? {
+                acme = acme.with_directory_domains(
+                    self.values(("acme", acme_id, "directory-domains.subdomains"))
+                        .map(|(_, v)| v.trim().to_lowercase())
+                        .filter(|v| !v.is_empty())
+                        .collect(),
+                    self.property_or_static(("acme", acme_id, "directory-domains.refresh"), "1h")?,
+                );
+            }
+
+            acmes.insert(acme_id.to_string(), Arc::new(acme));
         }
 
         Ok(acmes)
@@ -0,0 +1,106 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use ahash::AHashMap;
+use rskafka::{
+    client::{
+        partition::{Compression, PartitionClient, UnknownTopicHandling},
+        Client, ClientBuilder,
+    },
+    record::Record,
+};
+use tokio::sync::Mutex;
+
+use crate::config::Config;
+
+use super::EventExporter;
+
+pub struct KafkaExporter {
+    client: Client,
+    partition: i32,
+    partitions: Mutex<AHashMap<String, Arc<PartitionClient>>>,
+}
+
+impl KafkaExporter {
+    pub async fn open(config: &Config, prefix: &str) -> crate::config::Result<Self> {
+        let brokers = config
+            .values((prefix, "brokers"))
+            .map(|(_, broker)| broker.to_string())
+            .collect::<Vec<_>>();
+        if brokers.is_empty() {
+            return Err(format!("Missing property \"{prefix}.brokers\"."));
+        }
+
+        let client = ClientBuilder::new(brokers)
+            .build()
+            .await
+            .map_err(|err| format!("Failed to connect to Kafka: {err}"))?;
+
+        Ok(KafkaExporter {
+            client,
+            partition: config.property_or_static((prefix, "partition"), "0")?,
+            partitions: Mutex::new(AHashMap::new()),
+        })
+    }
+
+    async fn partition_client(&self, topic: &str) -> Result<Arc<PartitionClient>, String> {
+        let mut partitions = self.partitions.lock().await;
+        if let Some(client) = partitions.get(topic) {
+            return Ok(client.clone());
+        }
+
+        let client = Arc::new(
+            self.client
+                .partition_client(topic, self.partition, UnknownTopicHandling::Retry)
+                .await
+                .map_err(|err| format!("Failed to open Kafka topic {topic:?}: {err}"))?,
+        );
+        partitions.insert(topic.to_string(), client.clone());
+        Ok(client)
+    }
+}
+
+#[async_trait::async_trait]
+impl EventExporter for KafkaExporter {
+    async fn publish(&self, topic: &str, payloads: Vec<Vec<u8>>) -> Result<(), String> {
+        let timestamp = chrono::Utc::now();
+        let records = payloads
+            .into_iter()
+            .map(|payload| Record {
+                key: None,
+                value: Some(payload),
+                headers: BTreeMap::new(),
+                timestamp,
+            })
+            .collect();
+
+        self.partition_client(topic)
+            .await?
+            .produce(records, Compression::NoCompression)
+            .await
+            .map(|_| ())
+            .map_err(|err| format!("Failed to produce Kafka records: {err}"))
+    }
+}
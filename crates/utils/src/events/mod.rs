@@ -0,0 +1,505 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::{Arc, OnceLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ahash::AHashMap;
+use tokio::sync::mpsc;
+
+use crate::config::{
+    utils::{AsKey, ParseValue},
+    Config,
+};
+
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "nats")]
+pub mod nats;
+
+static EVENT_BUS: OnceLock<EventBus> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Received,
+    Filtered,
+    Queued,
+    Delivered,
+    Bounced,
+    Deleted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryGuarantee {
+    AtMostOnce,
+    AtLeastOnce,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LifecycleEvent {
+    #[serde(rename = "type")]
+    pub kind: Option<EventKind>,
+    pub timestamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sender: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub recipients: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[async_trait::async_trait]
+pub trait EventExporter: Send + Sync {
+    async fn publish(&self, topic: &str, payloads: Vec<Vec<u8>>) -> Result<(), String>;
+}
+
+#[derive(Debug, Clone)]
+pub struct EventExportSettings {
+    pub events: Vec<EventKind>,
+    pub topic: String,
+    pub topics: AHashMap<EventKind, String>,
+    pub batch_size: usize,
+    pub batch_wait: Duration,
+    pub guarantee: DeliveryGuarantee,
+    pub retries: usize,
+    pub retry_interval: Duration,
+    pub queue_size: usize,
+}
+
+struct EventBus {
+    tx: mpsc::Sender<LifecycleEvent>,
+    events: Vec<EventKind>,
+}
+
+impl LifecycleEvent {
+    pub fn new(kind: EventKind) -> Self {
+        LifecycleEvent {
+            kind: kind.into(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_queue_id(mut self, queue_id: u64) -> Self {
+        self.queue_id = queue_id.into();
+        self
+    }
+
+    pub fn with_account_id(mut self, account_id: u32) -> Self {
+        self.account_id = account_id.into();
+        self
+    }
+
+    pub fn with_document_id(mut self, document_id: u32) -> Self {
+        self.document_id = document_id.into();
+        self
+    }
+
+    pub fn with_sender(mut self, sender: impl Into<String>) -> Self {
+        self.sender = Some(sender.into());
+        self
+    }
+
+    pub fn with_recipient(mut self, recipient: impl Into<String>) -> Self {
+        self.recipients.push(recipient.into());
+        self
+    }
+
+    pub fn with_recipients<T: Into<String>>(
+        mut self,
+        recipients: impl IntoIterator<Item = T>,
+    ) -> Self {
+        self.recipients
+            .extend(recipients.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn with_remote(mut self, remote: impl ToString) -> Self {
+        self.remote = Some(remote.to_string());
+        self
+    }
+
+    pub fn with_size(mut self, size: usize) -> Self {
+        self.size = size.into();
+        self
+    }
+
+    pub fn with_reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+}
+
+// Returns true when an exporter is running and interested in this kind of event,
+// which lets callers skip building events nobody is going to consume.
+pub fn is_enabled(kind: EventKind) -> bool {
+    EVENT_BUS
+        .get()
+        .map_or(false, |bus| bus.events.contains(&kind))
+}
+
+pub fn publish(event: LifecycleEvent) {
+    if let (Some(bus), Some(kind)) = (EVENT_BUS.get(), event.kind) {
+        if bus.events.contains(&kind) {
+            if let Err(err) = bus.tx.try_send(event) {
+                tracing::warn!(
+                    context = "event-export",
+                    event = "error",
+                    "Failed to enqueue {:?} event: {}",
+                    kind,
+                    err
+                );
+            }
+        }
+    }
+}
+
+pub async fn init(config: &Config) -> crate::config::Result<()> {
+    if !config.property_or_static::<bool>("event-export.enable", "false")? {
+        return Ok(());
+    }
+    let settings = EventExportSettings::parse(config)?;
+    let exporter: Arc<dyn EventExporter> = match config.value_require("event-export.type")? {
+        #[cfg(feature = "kafka")]
+        "kafka" => Arc::new(kafka::KafkaExporter::open(config, "event-export.kafka").await?),
+        #[cfg(feature = "nats")]
+        "nats" => Arc::new(nats::NatsExporter::open(config, "event-export.nats").await?),
+        other => {
+            return Err(format!(
+                concat!(
+                    "Unsupported event exporter {:?} for property \"event-export.type\", ",
+                    "make sure the server was built with the corresponding feature."
+                ),
+                other
+            ))
+        }
+    };
+
+    let (tx, rx) = mpsc::channel(settings.queue_size);
+    if EVENT_BUS
+        .set(EventBus {
+            tx,
+            events: settings.events.clone(),
+        })
+        .is_ok()
+    {
+        tokio::spawn(settings.spawn(exporter, rx));
+    }
+
+    Ok(())
+}
+
+impl EventExportSettings {
+    pub fn parse(config: &Config) -> crate::config::Result<Self> {
+        let mut events = Vec::new();
+        for (key, value) in config.values("event-export.events") {
+            let kind = EventKind::parse_value(key, value)?;
+            if !events.contains(&kind) {
+                events.push(kind);
+            }
+        }
+        if events.is_empty() {
+            events = vec![
+                EventKind::Received,
+                EventKind::Filtered,
+                EventKind::Queued,
+                EventKind::Delivered,
+                EventKind::Bounced,
+                EventKind::Deleted,
+            ];
+        }
+
+        let mut topics = AHashMap::new();
+        for kind in &events {
+            if let Some(topic) = config.value(("event-export.topics", kind.as_str())) {
+                topics.insert(*kind, topic.to_string());
+            }
+        }
+
+        Ok(EventExportSettings {
+            events,
+            topic: config
+                .value("event-export.topic")
+                .unwrap_or("stalwart-events")
+                .to_string(),
+            topics,
+            batch_size: config.property_or_static("event-export.batch.size", "100")?,
+            batch_wait: config.property_or_static("event-export.batch.wait", "1s")?,
+            guarantee: config
+                .property_or_static("event-export.delivery.guarantee", "at-least-once")?,
+            retries: config.property_or_static("event-export.delivery.retries", "5")?,
+            retry_interval: config
+                .property_or_static("event-export.delivery.retry-interval", "2s")?,
+            queue_size: config.property_or_static("event-export.queue-size", "10000")?,
+        })
+    }
+
+    pub fn topic(&self, kind: EventKind) -> &str {
+        self.topics.get(&kind).unwrap_or(&self.topic)
+    }
+
+    async fn spawn(self, exporter: Arc<dyn EventExporter>, mut rx: mpsc::Receiver<LifecycleEvent>) {
+        let mut batch: Vec<LifecycleEvent> = Vec::with_capacity(self.batch_size);
+
+        loop {
+            // Wait for the first event of the batch, then keep collecting until
+            // either the batch is full or the wait interval expires.
+            match rx.recv().await {
+                Some(event) => batch.push(event),
+                None => break,
+            }
+            let deadline = tokio::time::Instant::now() + self.batch_wait;
+            while batch.len() < self.batch_size {
+                match tokio::time::timeout_at(deadline, rx.recv()).await {
+                    Ok(Some(event)) => batch.push(event),
+                    Ok(None) | Err(_) => break,
+                }
+            }
+
+            self.flush(exporter.as_ref(), std::mem::take(&mut batch))
+                .await;
+        }
+    }
+
+    async fn flush(&self, exporter: &dyn EventExporter, batch: Vec<LifecycleEvent>) {
+        let mut by_topic: AHashMap<&str, Vec<Vec<u8>>> = AHashMap::new();
+        for event in batch {
+            let topic = self.topic(event.kind.unwrap_or(EventKind::Received));
+            match serde_json::to_vec(&event) {
+                Ok(payload) => by_topic.entry(topic).or_default().push(payload),
+                Err(err) => {
+                    tracing::debug!(
+                        context = "event-export",
+                        event = "error",
+                        "Failed to serialize event: {}",
+                        err
+                    );
+                }
+            }
+        }
+
+        for (topic, payloads) in by_topic {
+            let num_events = payloads.len();
+            let max_attempts = match self.guarantee {
+                DeliveryGuarantee::AtMostOnce => 1,
+                DeliveryGuarantee::AtLeastOnce => self.retries + 1,
+            };
+            let mut attempt = 0;
+
+            loop {
+                attempt += 1;
+                match exporter.publish(topic, payloads.clone()).await {
+                    Ok(_) => {
+                        tracing::trace!(
+                            context = "event-export",
+                            event = "publish",
+                            topic = topic,
+                            count = num_events,
+                            "Published events."
+                        );
+                        break;
+                    }
+                    Err(err) if attempt < max_attempts => {
+                        tracing::debug!(
+                            context = "event-export",
+                            event = "retry",
+                            topic = topic,
+                            attempt = attempt,
+                            "Failed to publish events: {}",
+                            err
+                        );
+                        tokio::time::sleep(self.retry_interval).await;
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            context = "event-export",
+                            event = "error",
+                            topic = topic,
+                            count = num_events,
+                            "Dropping events after {} attempt(s): {}",
+                            attempt,
+                            err
+                        );
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Received => "received",
+            EventKind::Filtered => "filtered",
+            EventKind::Queued => "queued",
+            EventKind::Delivered => "delivered",
+            EventKind::Bounced => "bounced",
+            EventKind::Deleted => "deleted",
+        }
+    }
+}
+
+impl ParseValue for EventKind {
+    fn parse_value(key: impl AsKey, value: &str) -> crate::config::Result<Self> {
+        match value {
+            "received" => Ok(EventKind::Received),
+            "filtered" => Ok(EventKind::Filtered),
+            "queued" => Ok(EventKind::Queued),
+            "delivered" => Ok(EventKind::Delivered),
+            "bounced" => Ok(EventKind::Bounced),
+            "deleted" => Ok(EventKind::Deleted),
+            _ => Err(format!(
+                "Invalid event type {:?} for property {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
+impl ParseValue for DeliveryGuarantee {
+    fn parse_value(key: impl AsKey, value: &str) -> crate::config::Result<Self> {
+        match value {
+            "at-most-once" => Ok(DeliveryGuarantee::AtMostOnce),
+            "at-least-once" => Ok(DeliveryGuarantee::AtLeastOnce),
+            _ => Err(format!(
+                "Invalid delivery guarantee {:?} for property {:?}.",
+                value,
+                key.as_key()
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use parking_lot::Mutex;
+    use tokio::sync::mpsc;
+
+    use crate::config::Config;
+
+    use super::{EventExportSettings, EventExporter, EventKind, LifecycleEvent};
+
+    #[derive(Default)]
+    struct MockExporter {
+        failures: Mutex<usize>,
+        published: Mutex<Vec<(String, usize)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl EventExporter for MockExporter {
+        async fn publish(&self, topic: &str, payloads: Vec<Vec<u8>>) -> Result<(), String> {
+            let mut failures = self.failures.lock();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err("broker unavailable".to_string());
+            }
+            self.published
+                .lock()
+                .push((topic.to_string(), payloads.len()));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_route_and_retry() {
+        let config = Config::new(
+            r#"
+[event-export]
+events = ["received", "bounced"]
+topic = "events"
+
+[event-export.topics]
+bounced = "bounces"
+
+[event-export.batch]
+size = 3
+wait = "50ms"
+
+[event-export.delivery]
+retries = 1
+retry-interval = "10ms"
+"#,
+        )
+        .unwrap();
+        let settings = EventExportSettings::parse(&config).unwrap();
+        assert_eq!(
+            settings.events,
+            vec![EventKind::Received, EventKind::Bounced]
+        );
+        assert_eq!(settings.topic(EventKind::Received), "events");
+        assert_eq!(settings.topic(EventKind::Bounced), "bounces");
+
+        let exporter = Arc::new(MockExporter {
+            failures: Mutex::new(1),
+            ..Default::default()
+        });
+        let (tx, rx) = mpsc::channel(10);
+        tokio::spawn(settings.spawn(exporter.clone(), rx));
+
+        // The first batch fails once and is retried, the last event is flushed
+        // once the batch wait interval expires.
+        for kind in [
+            EventKind::Received,
+            EventKind::Received,
+            EventKind::Bounced,
+            EventKind::Received,
+        ] {
+            tx.send(LifecycleEvent::new(kind).with_queue_id(1))
+                .await
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let mut published = exporter.published.lock().clone();
+        published.sort();
+        assert_eq!(
+            published,
+            vec![
+                ("bounces".to_string(), 1),
+                ("events".to_string(), 1),
+                ("events".to_string(), 2)
+            ]
+        );
+    }
+}
@@ -0,0 +1,92 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use async_nats::{Client, ConnectOptions};
+
+use crate::config::Config;
+
+use super::EventExporter;
+
+pub struct NatsExporter {
+    client: Client,
+    prefix: Option<String>,
+}
+
+impl NatsExporter {
+    pub async fn open(config: &Config, prefix: &str) -> crate::config::Result<Self> {
+        let urls = config
+            .values((prefix, "url"))
+            .map(|(_, url)| url.to_string())
+            .collect::<Vec<_>>();
+        if urls.is_empty() {
+            return Err(format!("Missing property \"{prefix}.url\"."));
+        }
+
+        let mut options = ConnectOptions::new();
+        if let Some(token) = config.value((prefix, "token")) {
+            options = options.token(token.to_string());
+        } else if let Some(user) = config.value((prefix, "user")) {
+            options = options.user_and_password(
+                user.to_string(),
+                config.value_require((prefix, "password"))?.to_string(),
+            );
+        }
+
+        let client = options
+            .connect(urls.join(",").as_str())
+            .await
+            .map_err(|err| format!("Failed to connect to NATS: {err}"))?;
+
+        Ok(NatsExporter {
+            client,
+            prefix: config
+                .value((prefix, "subject-prefix"))
+                .map(|prefix| prefix.to_string()),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EventExporter for NatsExporter {
+    async fn publish(&self, topic: &str, payloads: Vec<Vec<u8>>) -> Result<(), String> {
+        let subject = if let Some(prefix) = &self.prefix {
+            format!("{prefix}.{topic}")
+        } else {
+            topic.to_string()
+        };
+
+        for payload in payloads {
+            self.client
+                .publish(subject.clone(), payload.into())
+                .await
+                .map_err(|err| format!("Failed to publish to NATS: {err}"))?;
+        }
+
+        // Flushing waits for the server to receive the whole batch, so a failed
+        // flush is reported back and the batch retried when requested.
+        self.client
+            .flush()
+            .await
+            .map_err(|err| format!("Failed to flush NATS connection: {err}"))
+    }
+}
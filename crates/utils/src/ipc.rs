@@ -31,6 +31,10 @@ pub enum DeliveryEvent {
         message: IngestMessage,
         result_tx: oneshot::Sender<Vec<DeliveryResult>>,
     },
+    Submission {
+        account: String,
+        size: usize,
+    },
     Stop,
 }
 
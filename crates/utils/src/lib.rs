@@ -28,8 +28,10 @@ use config::Config;
 pub mod acme;
 pub mod codec;
 pub mod config;
+pub mod events;
 pub mod ipc;
 pub mod listener;
+pub mod logging;
 pub mod map;
 pub mod snowflake;
 pub mod suffixlist;
@@ -101,17 +103,9 @@ pub fn enable_tracing(config: &Config, message: &str) -> config::Result<Option<W
         .failed("Failed to log level");
     let result = match config.value("global.tracing.method").unwrap_or_default() {
         "log" => {
-            let path = config.value_require("global.tracing.path")?;
-            let prefix = config.value_require("global.tracing.prefix")?;
-            let file_appender = match config.value("global.tracing.rotate").unwrap_or("daily") {
-                "daily" => tracing_appender::rolling::daily(path, prefix),
-                "hourly" => tracing_appender::rolling::hourly(path, prefix),
-                "minutely" => tracing_appender::rolling::minutely(path, prefix),
-                "never" => tracing_appender::rolling::never(path, prefix),
-                rotate => {
-                    return Err(format!("Unsupported log rotation strategy {rotate:?}"));
-                }
-            };
+            let file_appender =
+                logging::RotatingFileWriter::new(logging::LogSettings::parse(config)?)
+                    .map_err(|err| format!("Failed to open log file: {err}"))?;
 
             let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
             tracing::subscriber::set_global_default(
@@ -0,0 +1,233 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+use crate::config::Rate;
+
+// Transfers are throttled in slices so that concurrent sessions
+// sharing the same bucket are served in turns.
+const SLICE_SIZE: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Download = 0,
+    Upload = 1,
+}
+
+#[derive(Debug)]
+pub struct BandwidthLimiter {
+    bytes: u64,
+    period: Duration,
+    direction: Direction,
+    next_free: Mutex<Instant>,
+}
+
+#[derive(Debug, Default)]
+pub struct Bandwidth {
+    pub download: Option<BandwidthLimiter>,
+    pub upload: Option<BandwidthLimiter>,
+}
+
+struct ThrottleStats {
+    bytes: AtomicU64,
+    wait_us: AtomicU64,
+}
+
+static STATS: [ThrottleStats; 2] = [ThrottleStats::new(), ThrottleStats::new()];
+
+impl BandwidthLimiter {
+    pub fn new(rate: &Rate, direction: Direction) -> Option<Self> {
+        if rate.requests > 0 {
+            Some(BandwidthLimiter {
+                bytes: rate.requests,
+                period: rate.period,
+                direction,
+                next_free: Mutex::new(Instant::now()),
+            })
+        } else {
+            None
+        }
+    }
+
+    pub async fn consume(&self, bytes: usize) {
+        let mut remaining = bytes as u64;
+        while remaining > 0 {
+            let slice = std::cmp::min(remaining, SLICE_SIZE);
+            remaining -= slice;
+
+            let wait = self.reserve(slice);
+            if !wait.is_zero() {
+                let stats = &STATS[self.direction as usize];
+                stats.bytes.fetch_add(slice, Ordering::Relaxed);
+                stats
+                    .wait_us
+                    .fetch_add(wait.as_micros() as u64, Ordering::Relaxed);
+                tokio::time::sleep(wait).await;
+            }
+        }
+    }
+
+    // Returns how long the caller has to wait before sending the requested bytes,
+    // allowing bursts of up to one period worth of traffic.
+    pub fn reserve(&self, bytes: u64) -> Duration {
+        let cost = self.period.mul_f64(bytes as f64 / self.bytes as f64);
+        let now = Instant::now();
+        let mut next_free = self.next_free.lock();
+        let start = std::cmp::max(*next_free, now.checked_sub(self.period).unwrap_or(now));
+        *next_free = start + cost;
+
+        next_free
+            .checked_sub(self.period)
+            .map_or(Duration::ZERO, |ready| ready.saturating_duration_since(now))
+    }
+
+    pub fn is_active(&self) -> bool {
+        *self.next_free.lock() > Instant::now()
+    }
+
+    fn duplicate(&self) -> Self {
+        BandwidthLimiter {
+            bytes: self.bytes,
+            period: self.period,
+            direction: self.direction,
+            next_free: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+impl Bandwidth {
+    pub fn new(download: &Rate, upload: &Rate) -> Self {
+        Bandwidth {
+            download: BandwidthLimiter::new(download, Direction::Download),
+            upload: BandwidthLimiter::new(upload, Direction::Upload),
+        }
+    }
+
+    // Creates separate buckets with the same rates
+    pub fn duplicate(&self) -> Self {
+        Bandwidth {
+            download: self.download.as_ref().map(BandwidthLimiter::duplicate),
+            upload: self.upload.as_ref().map(BandwidthLimiter::duplicate),
+        }
+    }
+
+    pub async fn throttle_download(&self, bytes: usize) {
+        if let Some(limiter) = &self.download {
+            limiter.consume(bytes).await;
+        }
+    }
+
+    pub async fn throttle_upload(&self, bytes: usize) {
+        if let Some(limiter) = &self.upload {
+            limiter.consume(bytes).await;
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.download.as_ref().map_or(false, |l| l.is_active())
+            || self.upload.as_ref().map_or(false, |l| l.is_active())
+    }
+}
+
+impl ThrottleStats {
+    const fn new() -> Self {
+        ThrottleStats {
+            bytes: AtomicU64::new(0),
+            wait_us: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Renders bandwidth throttling counters using the Prometheus text exposition format.
+pub fn to_prometheus() -> String {
+    let mut out = String::with_capacity(512);
+
+    for (metric, help, value) in [
+        (
+            "stalwart_bandwidth_throttled_bytes_total",
+            "Number of bytes delayed by bandwidth limits.",
+            (|s: &ThrottleStats| s.bytes.load(Ordering::Relaxed) as f64)
+                as fn(&ThrottleStats) -> f64,
+        ),
+        (
+            "stalwart_bandwidth_throttled_seconds_total",
+            "Total time transfers were delayed by bandwidth limits.",
+            |s| s.wait_us.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+        ),
+    ] {
+        let _ = writeln!(out, "# HELP {metric} {help}");
+        let _ = writeln!(out, "# TYPE {metric} counter");
+        for (direction, stats) in ["download", "upload"].into_iter().zip(STATS.iter()) {
+            let _ = writeln!(
+                out,
+                "{metric}{{direction=\"{direction}\"}} {}",
+                value(stats)
+            );
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::config::Rate;
+
+    use super::{BandwidthLimiter, Direction};
+
+    #[test]
+    fn bandwidth_reserve() {
+        let limiter = BandwidthLimiter::new(
+            &Rate {
+                requests: 1000,
+                period: Duration::from_secs(1),
+            },
+            Direction::Download,
+        )
+        .unwrap();
+
+        // Bursts of up to one period are allowed
+        assert_eq!(limiter.reserve(500), Duration::ZERO);
+        assert_eq!(limiter.reserve(500), Duration::ZERO);
+
+        // Further transfers have to wait for the bucket to refill
+        let wait = limiter.reserve(500);
+        assert!(
+            wait > Duration::from_millis(400) && wait <= Duration::from_millis(500),
+            "{wait:?}"
+        );
+        assert!(limiter.is_active());
+
+        // Unlimited rates do not create a limiter
+        assert!(BandwidthLimiter::new(&Rate::default(), Direction::Upload).is_none());
+    }
+}
@@ -0,0 +1,101 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+/// Maximum length of a rendered banner line, which keeps SMTP replies within
+/// the 512 octet limit of RFC 5321 once the reply code has been added.
+const MAX_LINE_LEN: usize = 500;
+const MAX_LINES: usize = 32;
+
+/// Length of a RFC 2822 timestamp as produced by the `{time}` variable.
+const TIME_LEN: usize = 31;
+
+/// Pre-authentication banner shown to clients when they connect to a listener.
+///
+/// Banners may span multiple lines and support the `{hostname}` and `{time}`
+/// variables, which are replaced on each connection.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Banner {
+    lines: Vec<String>,
+}
+
+impl Banner {
+    pub fn parse(value: &str, hostname: &str) -> Result<Self, String> {
+        let mut lines = Vec::new();
+
+        for line in value.trim().lines() {
+            let line = line.trim_end();
+            if let Some(ch) = line.chars().find(|ch| !matches!(ch, ' '..='~' | '\t')) {
+                return Err(format!(
+                    "Banner contains an invalid character {ch:?}, only printable ASCII is allowed."
+                ));
+            }
+
+            // Validate variables and the length of the rendered line
+            let mut len = line.len();
+            let mut remaining = line;
+            while let Some((_, var)) = remaining.split_once('{') {
+                let (name, rest) = var
+                    .split_once('}')
+                    .ok_or_else(|| format!("Unterminated variable in banner line {line:?}."))?;
+                len = len - name.len() - 2
+                    + match name {
+                        "hostname" => hostname.len(),
+                        "time" => TIME_LEN,
+                        _ => return Err(format!("Unknown banner variable {{{name}}}.")),
+                    };
+                remaining = rest;
+            }
+            if len > MAX_LINE_LEN {
+                return Err(format!(
+                    "Banner line {line:?} exceeds the maximum length of {MAX_LINE_LEN} characters."
+                ));
+            }
+
+            lines.push(line.to_string());
+        }
+
+        if lines.is_empty() {
+            Err("Banner cannot be empty.".to_string())
+        } else if lines.len() > MAX_LINES {
+            Err(format!("Banner exceeds the maximum of {MAX_LINES} lines."))
+        } else {
+            Ok(Banner { lines })
+        }
+    }
+
+    /// Returns the banner lines with all variables replaced.
+    pub fn render(&self, hostname: &str) -> Vec<String> {
+        let time = chrono::Utc::now().to_rfc2822();
+        self.lines
+            .iter()
+            .map(|line| {
+                if line.contains('{') {
+                    line.replace("{hostname}", hostname)
+                        .replace("{time}", &time)
+                } else {
+                    line.clone()
+                }
+            })
+            .collect()
+    }
+}
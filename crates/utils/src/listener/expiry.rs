@@ -0,0 +1,362 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{sync::Arc, time::Duration};
+
+use ahash::AHashMap;
+use chrono::{TimeZone, Utc};
+use parking_lot::Mutex;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use rustls::sign::CertifiedKey;
+use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
+
+use crate::{
+    acme::AcmeManager,
+    config::{utils::ParseValue, Config},
+};
+
+use super::tls::Certificate;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CertificateSource {
+    Manual,
+    Acme,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateExpiry {
+    pub id: String,
+    pub source: CertificateSource,
+    pub subject: String,
+    pub dns_names: Vec<String>,
+    pub valid_from: String,
+    pub valid_until: String,
+    #[serde(skip)]
+    pub expires_at: i64,
+}
+
+pub struct ExpiryMonitor {
+    pub interval: Duration,
+    thresholds: Vec<Duration>,
+    webhook: Option<Webhook>,
+    notified: Mutex<AHashMap<(String, i64), usize>>,
+}
+
+struct Webhook {
+    url: String,
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpiryAlert {
+    #[serde(rename = "type")]
+    pub typ: &'static str,
+    pub expired: bool,
+    pub threshold: u64,
+    pub seconds_left: i64,
+    pub certificate: CertificateExpiry,
+}
+
+impl ExpiryMonitor {
+    pub fn parse(config: &Config) -> crate::config::Result<Option<Self>> {
+        if !config.property_or_static("server.tls.expiry.enable", "true")? {
+            return Ok(None);
+        }
+
+        let mut thresholds = Vec::new();
+        for (key, value) in config.values("server.tls.expiry.thresholds") {
+            thresholds.push(Duration::parse_value(key, value)?);
+        }
+        if thresholds.is_empty() {
+            thresholds = [30, 14, 7, 1]
+                .into_iter()
+                .map(|days| Duration::from_secs(days * 86400))
+                .collect();
+        }
+        thresholds.sort_unstable_by(|a, b| b.cmp(a));
+        thresholds.dedup();
+
+        let webhook = if let Some(url) = config.value("server.tls.expiry.webhook.url") {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            for (key, value) in config.values("server.tls.expiry.webhook.headers") {
+                let (name, value) = value
+                    .split_once(':')
+                    .and_then(|(name, value)| {
+                        Some((
+                            HeaderName::from_bytes(name.trim().as_bytes()).ok()?,
+                            HeaderValue::from_str(value.trim()).ok()?,
+                        ))
+                    })
+                    .ok_or_else(|| format!("Invalid header found in property {key:?}."))?;
+                headers.insert(name, value);
+            }
+
+            Some(Webhook {
+                url: url.to_string(),
+                client: reqwest::Client::builder()
+                    .timeout(config.property_or_static("server.tls.expiry.webhook.timeout", "30s")?)
+                    .default_headers(headers)
+                    .build()
+                    .map_err(|err| format!("Failed to create webhook client: {err}"))?,
+            })
+        } else {
+            None
+        };
+
+        Ok(Some(ExpiryMonitor {
+            interval: config.property_or_static("server.tls.expiry.check-interval", "12h")?,
+            thresholds,
+            webhook,
+            notified: Mutex::new(AHashMap::new()),
+        }))
+    }
+
+    pub async fn check(
+        &self,
+        certificates: &[Arc<Certificate>],
+        acme_managers: &[Arc<AcmeManager>],
+    ) {
+        for alert in self.alerts(
+            certificate_expiry(certificates, acme_managers),
+            Utc::now().timestamp(),
+        ) {
+            if alert.expired {
+                tracing::error!(
+                    context = "tls",
+                    event = "expired",
+                    id = alert.certificate.id.as_str(),
+                    source = ?alert.certificate.source,
+                    valid_until = alert.certificate.valid_until.as_str(),
+                    "Certificate {:?} has expired.",
+                    alert.certificate.id
+                );
+            } else {
+                tracing::warn!(
+                    context = "tls",
+                    event = "expiring",
+                    id = alert.certificate.id.as_str(),
+                    source = ?alert.certificate.source,
+                    valid_until = alert.certificate.valid_until.as_str(),
+                    "Certificate {:?} expires in {} day(s).",
+                    alert.certificate.id,
+                    alert.seconds_left / 86400
+                );
+            }
+
+            if let Some(webhook) = &self.webhook {
+                match webhook
+                    .client
+                    .post(&webhook.url)
+                    .body(serde_json::to_string(&alert).unwrap_or_default())
+                    .send()
+                    .await
+                {
+                    Ok(response) if response.status().is_success() => (),
+                    Ok(response) => {
+                        tracing::warn!(
+                            context = "tls",
+                            event = "error",
+                            url = webhook.url.as_str(),
+                            status = response.status().as_u16(),
+                            "Certificate expiry webhook returned an error status."
+                        );
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            context = "tls",
+                            event = "error",
+                            url = webhook.url.as_str(),
+                            reason = %err,
+                            "Failed to deliver certificate expiry webhook."
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // Returns an alert the first time a certificate crosses each threshold,
+    // a renewed certificate has a different expiry and starts over.
+    fn alerts(&self, certificates: Vec<CertificateExpiry>, now: i64) -> Vec<ExpiryAlert> {
+        let mut notified = self.notified.lock();
+        let mut alerts = Vec::new();
+        let mut active = AHashMap::with_capacity(notified.len());
+
+        for certificate in certificates {
+            let seconds_left = certificate.expires_at - now;
+            let level = if seconds_left <= 0 {
+                self.thresholds.len() + 1
+            } else if let Some(pos) = self
+                .thresholds
+                .iter()
+                .rposition(|threshold| seconds_left as u64 <= threshold.as_secs())
+            {
+                pos + 1
+            } else {
+                0
+            };
+
+            let key = (certificate.id.clone(), certificate.expires_at);
+            let last_level = notified.get(&key).copied().unwrap_or_default();
+            if level > last_level {
+                alerts.push(ExpiryAlert {
+                    typ: "certificate.expiry",
+                    expired: seconds_left <= 0,
+                    threshold: self
+                        .thresholds
+                        .get(level.saturating_sub(1))
+                        .map_or(0, |threshold| threshold.as_secs()),
+                    seconds_left,
+                    certificate,
+                });
+            }
+            active.insert(key, level.max(last_level));
+        }
+
+        *notified = active;
+        alerts
+    }
+}
+
+pub fn certificate_expiry(
+    certificates: &[Arc<Certificate>],
+    acme_managers: &[Arc<AcmeManager>],
+) -> Vec<CertificateExpiry> {
+    let mut result = Vec::with_capacity(certificates.len() + acme_managers.len());
+    for certificate in certificates {
+        if let Some(expiry) = CertificateExpiry::parse(
+            &certificate.id,
+            CertificateSource::Manual,
+            &certificate.cert.load(),
+        ) {
+            result.push(expiry);
+        }
+    }
+    for acme in acme_managers {
+        for (id, key) in acme.certificates() {
+            if let Some(expiry) = CertificateExpiry::parse(&id, CertificateSource::Acme, &key) {
+                result.push(expiry);
+            }
+        }
+    }
+    result.sort_unstable_by(|a, b| a.expires_at.cmp(&b.expires_at).then(a.id.cmp(&b.id)));
+    result
+}
+
+impl CertificateExpiry {
+    pub fn parse(id: &str, source: CertificateSource, key: &CertifiedKey) -> Option<Self> {
+        let der = key.end_entity_cert().ok()?;
+        let (_, cert) = X509Certificate::from_der(der.as_ref()).ok()?;
+        let validity = cert.validity();
+        let mut dns_names = Vec::new();
+        if let Ok(Some(san)) = cert.subject_alternative_name() {
+            for name in &san.value.general_names {
+                if let GeneralName::DNSName(name) = name {
+                    dns_names.push(name.trim().to_lowercase());
+                }
+            }
+        }
+
+        Some(CertificateExpiry {
+            id: id.to_string(),
+            source,
+            subject: cert.subject().to_string(),
+            dns_names,
+            valid_from: to_rfc3339(validity.not_before.timestamp()),
+            valid_until: to_rfc3339(validity.not_after.timestamp()),
+            expires_at: validity.not_after.timestamp(),
+        })
+    }
+}
+
+fn to_rfc3339(timestamp: i64) -> String {
+    Utc.timestamp_opt(timestamp, 0)
+        .single()
+        .map(|dt| dt.to_rfc3339())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ahash::AHashMap;
+    use parking_lot::Mutex;
+
+    use crate::config::tls::build_self_signed_cert;
+
+    use super::{CertificateExpiry, CertificateSource, ExpiryMonitor};
+
+    #[test]
+    fn expiry_alerts() {
+        let monitor = ExpiryMonitor {
+            interval: Duration::from_secs(3600),
+            thresholds: vec![Duration::from_secs(30 * 86400), Duration::from_secs(86400)],
+            webhook: None,
+            notified: Mutex::new(AHashMap::new()),
+        };
+        let cert = CertificateExpiry::parse(
+            "default",
+            CertificateSource::Manual,
+            &build_self_signed_cert(&["mail.example.org".to_string()]).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(cert.dns_names, vec!["mail.example.org".to_string()]);
+        let expires_at = cert.expires_at;
+
+        // No alerts before reaching the first threshold
+        for (now, expected) in [
+            (expires_at - 40 * 86400, None),
+            (expires_at - 20 * 86400, Some((30 * 86400, false))),
+            (expires_at - 10 * 86400, None),
+            (expires_at - 3600, Some((86400, false))),
+            (expires_at - 1800, None),
+            (expires_at + 1, Some((0, true))),
+            (expires_at + 86400, None),
+        ] {
+            let alerts = monitor.alerts(vec![cert.clone()], now);
+            assert_eq!(
+                alerts.first().map(|alert| (alert.threshold, alert.expired)),
+                expected,
+                "{}",
+                expires_at - now
+            );
+            assert!(alerts.len() <= 1);
+        }
+
+        // A renewed certificate is tracked separately
+        let mut renewed = cert.clone();
+        renewed.expires_at += 90 * 86400;
+        assert!(monitor
+            .alerts(vec![renewed.clone()], expires_at + 86400)
+            .is_empty());
+        assert_eq!(
+            monitor.alerts(vec![renewed], expires_at + 89 * 86400).len(),
+            1
+        );
+    }
+}
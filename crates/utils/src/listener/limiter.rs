@@ -46,6 +46,7 @@ pub struct ConcurrencyLimiter {
 #[derive(Default)]
 pub struct InFlight {
     concurrent: Arc<AtomicU64>,
+    parent: Option<Box<InFlight>>,
 }
 
 impl Drop for InFlight {
@@ -84,6 +85,15 @@ impl RateLimiter {
             || self.next_refill.load(Ordering::Relaxed) <= now()
     }
 
+    pub fn remaining(&self, rate: &Rate) -> u64 {
+        if self.next_refill.load(Ordering::Relaxed) > now() {
+            rate.requests
+                .saturating_sub(self.used_tokens.load(Ordering::Relaxed))
+        } else {
+            rate.requests
+        }
+    }
+
     pub fn secs_to_refill(&self) -> u64 {
         self.next_refill
             .load(Ordering::Relaxed)
@@ -109,6 +119,7 @@ impl ConcurrencyLimiter {
             self.concurrent.fetch_add(1, Ordering::Relaxed);
             Some(InFlight {
                 concurrent: self.concurrent.clone(),
+                parent: None,
             })
         } else {
             None
@@ -128,6 +139,12 @@ impl InFlight {
     pub fn num_concurrent(&self) -> u64 {
         self.concurrent.load(Ordering::Relaxed)
     }
+
+    // Keeps the parent slot (such as the listener's) until this one is released
+    pub fn with_parent(mut self, parent: InFlight) -> Self {
+        self.parent = Some(Box::new(parent));
+        self
+    }
 }
 
 fn now() -> u64 {
@@ -31,7 +31,7 @@ use proxy_header::io::ProxiedStream;
 use rustls::crypto::ring::cipher_suite::TLS13_AES_128_GCM_SHA256;
 use tokio::{
     net::{TcpListener, TcpStream},
-    sync::watch,
+    sync::{mpsc, watch},
 };
 use tokio_rustls::server::TlsStream;
 use tracing::Span;
@@ -45,44 +45,46 @@ use crate::{
 };
 
 use super::{
-    limiter::ConcurrencyLimiter, ServerInstance, SessionManager, SessionStream, TcpAcceptorResult,
+    limiter::ConcurrencyLimiter, manager::ListenerEvent, vhost::VirtualHosts, ServerInstance,
+    SessionManager, SessionStream, TcpAcceptorResult,
 };
 
 impl Server {
     pub fn spawn(self, manager: impl SessionManager, shutdown_rx: watch::Receiver<bool>) {
         // Prepare instance
-        let instance = Arc::new(ServerInstance {
+        let mut instance = ServerInstance {
             data: if matches!(self.protocol, ServerProtocol::Smtp | ServerProtocol::Lmtp) {
                 format!("220 {} {}\r\n", self.hostname, self.data)
             } else {
                 self.data
             },
+            banner: self.banner,
             id: self.id,
             listener_id: self.internal_id,
             protocol: self.protocol,
             hostname: self.hostname,
             acceptor: self.acceptor,
+            tls_client_auth: self.tls_client_auth,
             proxy_networks: self.proxy_networks,
             blocked_ips: self.blocked_ips,
             limiter: ConcurrencyLimiter::new(self.max_connections),
+            bandwidth: self.bandwidth,
+            virtual_hosts: Default::default(),
             shutdown_rx,
-        });
+        };
+        instance.virtual_hosts = VirtualHosts::new(&instance, self.virtual_hosts);
+        let instance = Arc::new(instance);
         let is_tls = self.tls_implicit;
-        let has_proxies = !instance.proxy_networks.is_empty();
 
         // Spawn listeners
+        let (tx, mut rx) = mpsc::channel(8);
+        let mut close_txs = Vec::with_capacity(self.listeners.len());
         for listener in self.listeners {
-            tracing::info!(
-                id = instance.id,
-                protocol = ?instance.protocol,
-                bind.ip = listener.addr.ip().to_string(),
-                bind.port = listener.addr.port(),
-                tls = is_tls,
-                "Starting listener"
-            );
-            let local_ip = listener.addr.ip();
+            let (close_tx, close_rx) = watch::channel(false);
+            close_txs.push((listener.addr, close_tx));
 
             // Obtain TCP options
+            let addr = listener.addr;
             let opts = SocketOpts {
                 nodelay: listener.nodelay,
                 ttl: listener.ttl,
@@ -93,75 +95,140 @@ impl Server {
             let listener = listener.listen();
 
             // Spawn listener
-            let mut shutdown_rx = instance.shutdown_rx.clone();
-            let manager = manager.clone();
-            let instance = instance.clone();
-            tokio::spawn(async move {
-                loop {
-                    tokio::select! {
-                        stream = listener.accept() => {
-                            match stream {
-                                Ok((stream, remote_addr)) => {
-                                    if has_proxies && instance.proxy_networks.iter().any(|network| network.matches(&remote_addr.ip())) {
-                                        let instance = instance.clone();
-                                        let manager = manager.clone();
+            spawn_listener(
+                listener,
+                addr,
+                opts,
+                instance.clone(),
+                manager.clone(),
+                is_tls,
+                close_rx,
+            );
+        }
+        self.listener_manager.register(&instance.id, tx, close_txs);
+
+        // Spawn listeners added on reload
+        let mut shutdown_rx = instance.shutdown_rx.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    event = rx.recv() => match event {
+                        Some(ListenerEvent::Spawn { listener, addr, opts, close_rx }) => {
+                            spawn_listener(
+                                listener,
+                                addr,
+                                opts,
+                                instance.clone(),
+                                manager.clone(),
+                                is_tls,
+                                close_rx,
+                            );
+                        }
+                        None => break,
+                    },
+                    _ = shutdown_rx.changed() => break,
+                }
+            }
+        });
+    }
+}
 
-                                        // Set socket options
-                                        opts.apply(&stream);
+fn spawn_listener(
+    listener: TcpListener,
+    addr: SocketAddr,
+    opts: SocketOpts,
+    instance: Arc<ServerInstance>,
+    manager: impl SessionManager,
+    is_tls: bool,
+    mut close_rx: watch::Receiver<bool>,
+) {
+    tracing::info!(
+        id = instance.id,
+        protocol = ?instance.protocol,
+        bind.ip = addr.ip().to_string(),
+        bind.port = addr.port(),
+        tls = is_tls,
+        "Starting listener"
+    );
+    let local_ip = addr.ip();
+    let has_proxies = !instance.proxy_networks.is_empty();
+    let mut shutdown_rx = instance.shutdown_rx.clone();
 
-                                        tokio::spawn(async move {
-                                            match ProxiedStream::create_from_tokio(stream, Default::default()).await {
-                                                Ok(stream) =>{
-                                                    let remote_addr = stream.proxy_header()
-                                                                            .proxied_address()
-                                                                            .map(|addr| addr.source)
-                                                                            .unwrap_or(remote_addr);
-                                                    if let Some(session) = instance.build_session(stream, local_ip, remote_addr) {
-                                                        // Spawn session
-                                                        manager.spawn(session, is_tls);
-                                                    }
-                                                }
-                                                Err(err) => {
-                                                    tracing::trace!(context = "io",
-                                                                    event = "error",
-                                                                    instance = instance.id,
-                                                                    protocol = ?instance.protocol,
-                                                                    reason = %err,
-                                                                    "Failed to accept proxied TCP connection");
-                                                }
-                                            }
-                                        });
-                                    } else if let Some(session) = instance.build_session(stream, local_ip, remote_addr) {
-                                        // Set socket options
-                                        opts.apply(&session.stream);
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                stream = listener.accept() => {
+                    match stream {
+                        Ok((stream, remote_addr)) => {
+                            if has_proxies && instance.proxy_networks.iter().any(|network| network.matches(&remote_addr.ip())) {
+                                let instance = instance.clone();
+                                let manager = manager.clone();
 
-                                        // Spawn session
-                                        manager.spawn(session, is_tls);
+                                // Set socket options
+                                opts.apply(&stream);
+
+                                tokio::spawn(async move {
+                                    match ProxiedStream::create_from_tokio(stream, Default::default()).await {
+                                        Ok(stream) =>{
+                                            let remote_addr = stream.proxy_header()
+                                                                    .proxied_address()
+                                                                    .map(|addr| addr.source)
+                                                                    .unwrap_or(remote_addr);
+                                            if let Some(session) = instance.build_session(stream, local_ip, remote_addr) {
+                                                // Spawn session
+                                                manager.spawn(session, is_tls);
+                                            }
+                                        }
+                                        Err(err) => {
+                                            tracing::trace!(context = "io",
+                                                            event = "error",
+                                                            instance = instance.id,
+                                                            protocol = ?instance.protocol,
+                                                            reason = %err,
+                                                            "Failed to accept proxied TCP connection");
+                                        }
                                     }
-                                }
-                                Err(err) => {
-                                    tracing::trace!(context = "io",
-                                                    event = "error",
-                                                    instance = instance.id,
-                                                    protocol = ?instance.protocol,
-                                                    "Failed to accept TCP connection: {}", err);
-                                }
+                                });
+                            } else if let Some(session) = instance.build_session(stream, local_ip, remote_addr) {
+                                // Set socket options
+                                opts.apply(&session.stream);
+
+                                // Spawn session
+                                manager.spawn(session, is_tls);
                             }
-                        },
-                        _ = shutdown_rx.changed() => {
-                            tracing::debug!(
-                                event = "shutdown",
-                                instance = instance.id,
-                                protocol = ?instance.protocol,
-                                "Listener shutting down.");
-                            manager.shutdown();
-                            break;
                         }
-                    };
+                        Err(err) => {
+                            tracing::trace!(context = "io",
+                                            event = "error",
+                                            instance = instance.id,
+                                            protocol = ?instance.protocol,
+                                            "Failed to accept TCP connection: {}", err);
+                        }
+                    }
+                },
+                _ = close_rx.changed() => {
+                    // Existing sessions are not interrupted
+                    tracing::debug!(
+                        event = "close",
+                        instance = instance.id,
+                        protocol = ?instance.protocol,
+                        bind.ip = addr.ip().to_string(),
+                        bind.port = addr.port(),
+                        "Listener closed, draining sessions.");
+                    break;
+                },
+                _ = shutdown_rx.changed() => {
+                    tracing::debug!(
+                        event = "shutdown",
+                        instance = instance.id,
+                        protocol = ?instance.protocol,
+                        "Listener shutting down.");
+                    manager.shutdown();
+                    break;
                 }
-            });
+            };
         }
-    }
+    });
 }
 
 trait BuildSession {
@@ -0,0 +1,242 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::net::SocketAddr;
+
+use ahash::AHashMap;
+use parking_lot::Mutex;
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, watch},
+};
+
+use crate::config::{Config, Listener};
+
+use super::listen::SocketOpts;
+
+pub const LISTENER_KEY: &str = "server.listener";
+
+/// Keeps track of the sockets bound by each running server so that bind
+/// addresses can be added or removed without restarting.
+#[derive(Default)]
+pub struct ListenerManager {
+    base: Config,
+    servers: Mutex<AHashMap<String, RunningServer>>,
+}
+
+struct RunningServer {
+    tx: mpsc::Sender<ListenerEvent>,
+    listeners: AHashMap<SocketAddr, watch::Sender<bool>>,
+}
+
+pub enum ListenerEvent {
+    Spawn {
+        listener: TcpListener,
+        addr: SocketAddr,
+        opts: SocketOpts,
+        close_rx: watch::Receiver<bool>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ListenerStatus {
+    pub id: String,
+    pub addr: String,
+    pub action: ListenerAction,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListenerAction {
+    Bind,
+    Close,
+    Unchanged,
+}
+
+impl ListenerManager {
+    pub fn new(config: &Config) -> Self {
+        ListenerManager {
+            base: Config {
+                keys: config
+                    .keys
+                    .iter()
+                    .filter(|(key, _)| key.starts_with("server."))
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect(),
+            },
+            servers: Mutex::new(AHashMap::new()),
+        }
+    }
+
+    pub fn register(
+        &self,
+        id: &str,
+        tx: mpsc::Sender<ListenerEvent>,
+        listeners: impl IntoIterator<Item = (SocketAddr, watch::Sender<bool>)>,
+    ) {
+        self.servers.lock().insert(
+            id.to_string(),
+            RunningServer {
+                tx,
+                listeners: listeners.into_iter().collect(),
+            },
+        );
+    }
+
+    /// Applies the listener settings in `config` on top of the configuration the
+    /// servers were started with. Sockets that are no longer configured stop
+    /// accepting connections while their sessions drain, new ones are bound and
+    /// handed over to the server they belong to.
+    pub fn reload(&self, config: Config) -> Vec<ListenerStatus> {
+        // Bind addresses defined in the overrides replace the original ones
+        let mut merged = self.base.clone();
+        for key in config.keys.keys() {
+            if let Some((id, _)) = key
+                .strip_prefix("server.listener.")
+                .and_then(|key| key.split_once(".bind"))
+            {
+                let prefix = format!("server.listener.{id}.bind");
+                merged.keys.retain(|key, _| !key.starts_with(&prefix));
+            }
+        }
+        merged.update(config);
+
+        let mut results = Vec::new();
+        let mut servers = self.servers.lock();
+        for (id, server) in servers.iter_mut() {
+            let listeners = match merged.parse_listeners(id) {
+                Ok(listeners) => listeners,
+                Err(err) => {
+                    results.push(
+                        ListenerStatus::new(id, "*", ListenerAction::Unchanged).with_error(err),
+                    );
+                    continue;
+                }
+            };
+
+            // Stop accepting connections on removed addresses
+            server.listeners.retain(|addr, close_tx| {
+                if listeners.iter().any(|listener| listener.addr == *addr) {
+                    true
+                } else {
+                    tracing::info!(
+                        context = "listener",
+                        event = "close",
+                        id = id,
+                        bind.ip = addr.ip().to_string(),
+                        bind.port = addr.port(),
+                        "Closing listener"
+                    );
+                    let _ = close_tx.send(true);
+                    results.push(ListenerStatus::new(id, addr, ListenerAction::Close));
+                    false
+                }
+            });
+
+            // Bind new addresses
+            for listener in listeners {
+                let addr = listener.addr;
+                if server.listeners.contains_key(&addr) {
+                    results.push(ListenerStatus::new(id, addr, ListenerAction::Unchanged));
+                    continue;
+                }
+
+                let status = ListenerStatus::new(id, addr, ListenerAction::Bind);
+                let opts = SocketOpts {
+                    nodelay: listener.nodelay,
+                    ttl: listener.ttl,
+                    linger: listener.linger,
+                };
+                let backlog = listener.backlog.unwrap_or(1024);
+                match listener
+                    .socket
+                    .bind(addr)
+                    .and_then(|_| listener.socket.listen(backlog))
+                {
+                    Ok(tcp_listener) => {
+                        let (close_tx, close_rx) = watch::channel(false);
+                        if server
+                            .tx
+                            .try_send(ListenerEvent::Spawn {
+                                listener: tcp_listener,
+                                addr,
+                                opts,
+                                close_rx,
+                            })
+                            .is_ok()
+                        {
+                            server.listeners.insert(addr, close_tx);
+                            results.push(status);
+                        } else {
+                            results.push(status.with_error("Server is not running."));
+                        }
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            context = "listener",
+                            event = "error",
+                            id = id,
+                            bind.ip = addr.ip().to_string(),
+                            bind.port = addr.port(),
+                            reason = %err,
+                            "Failed to bind listener"
+                        );
+                        results.push(status.with_error(format!("Failed to bind to {addr}: {err}")));
+                    }
+                }
+            }
+        }
+
+        // New servers can only be started on restart
+        for id in merged.sub_keys(LISTENER_KEY, ".protocol") {
+            if !servers.contains_key(id) {
+                for (_, addr) in merged.values((LISTENER_KEY, id, "bind")) {
+                    results.push(
+                        ListenerStatus::new(id, addr, ListenerAction::Unchanged)
+                            .with_error("New listeners require a restart."),
+                    );
+                }
+            }
+        }
+
+        results
+    }
+}
+
+impl ListenerStatus {
+    fn new(id: &str, addr: impl ToString, action: ListenerAction) -> Self {
+        ListenerStatus {
+            id: id.to_string(),
+            addr: addr.to_string(),
+            action,
+            error: None,
+        }
+    }
+
+    fn with_error(mut self, error: impl Into<String>) -> Self {
+        self.error = Some(error.into());
+        self
+    }
+}
@@ -36,15 +36,24 @@ use tokio::{
 use tokio_rustls::{Accept, TlsAcceptor};
 
 use self::{
+    bandwidth::Bandwidth,
+    banner::Banner,
     blocked::BlockedIps,
     limiter::{ConcurrencyLimiter, InFlight},
+    tls::{ClientCertificate, TlsClientAuth},
+    vhost::VirtualHosts,
 };
 
+pub mod bandwidth;
+pub mod banner;
 pub mod blocked;
+pub mod expiry;
 pub mod limiter;
 pub mod listen;
+pub mod manager;
 pub mod stream;
 pub mod tls;
+pub mod vhost;
 
 pub struct ServerInstance {
     pub id: String,
@@ -52,10 +61,14 @@ pub struct ServerInstance {
     pub protocol: ServerProtocol,
     pub hostname: String,
     pub data: String,
+    pub banner: Option<Banner>,
     pub acceptor: TcpAcceptor,
+    pub tls_client_auth: Option<TlsClientAuth>,
     pub limiter: ConcurrencyLimiter,
+    pub bandwidth: Bandwidth,
     pub proxy_networks: Vec<IpAddrMask>,
     pub blocked_ips: Arc<BlockedIps>,
+    pub virtual_hosts: VirtualHosts,
     pub shutdown_rx: watch::Receiver<bool>,
 }
 
@@ -94,6 +107,7 @@ pub struct SessionData<T: SessionStream> {
 pub trait SessionStream: AsyncRead + AsyncWrite + Unpin + 'static + Sync + Send {
     fn is_tls(&self) -> bool;
     fn tls_version_and_cipher(&self) -> (Cow<'static, str>, Cow<'static, str>);
+    fn tls_client_certificate(&self) -> Option<ClientCertificate>;
 }
 
 pub trait SessionManager: Sync + Send + 'static + Clone {
@@ -105,14 +119,22 @@ pub trait SessionManager: Sync + Send + 'static + Clone {
                 match session.instance.acceptor.accept(session.stream).await {
                     TcpAcceptorResult::Tls(accept) => match accept.await {
                         Ok(stream) => {
+                            let (instance, in_flight) =
+                                match session.instance.virtual_host(&stream, &session.span) {
+                                    Ok(Some((instance, in_flight))) => {
+                                        (instance, in_flight.with_parent(session.in_flight))
+                                    }
+                                    Ok(None) => (session.instance, session.in_flight),
+                                    Err(_) => return,
+                                };
                             let session = SessionData {
                                 stream,
                                 local_ip: session.local_ip,
                                 remote_ip: session.remote_ip,
                                 remote_port: session.remote_port,
                                 span: session.span,
-                                in_flight: session.in_flight,
-                                instance: session.instance,
+                                in_flight,
+                                instance,
                             };
                             manager.handle(session).await;
                         }
@@ -30,7 +30,7 @@ use tokio::{
 };
 use tokio_rustls::server::TlsStream;
 
-use super::SessionStream;
+use super::{tls::ClientCertificate, SessionStream};
 
 impl SessionStream for TcpStream {
     fn is_tls(&self) -> bool {
@@ -40,6 +40,10 @@ impl SessionStream for TcpStream {
     fn tls_version_and_cipher(&self) -> (Cow<'static, str>, Cow<'static, str>) {
         (Cow::Borrowed(""), Cow::Borrowed(""))
     }
+
+    fn tls_client_certificate(&self) -> Option<ClientCertificate> {
+        None
+    }
 }
 
 impl<T: SessionStream> SessionStream for TlsStream<T> {
@@ -79,6 +83,14 @@ impl<T: SessionStream> SessionStream for TlsStream<T> {
             .into(),
         )
     }
+
+    fn tls_client_certificate(&self) -> Option<ClientCertificate> {
+        let (_, conn) = self.get_ref();
+
+        conn.peer_certificates()
+            .and_then(|certs| certs.first())
+            .and_then(|cert| ClientCertificate::parse(cert.as_ref()))
+    }
 }
 
 impl SessionStream for ProxiedStream<TcpStream> {
@@ -99,6 +111,10 @@ impl SessionStream for ProxiedStream<TcpStream> {
             })
             .unwrap_or((Cow::Borrowed("unknown"), Cow::Borrowed("unknown")))
     }
+
+    fn tls_client_certificate(&self) -> Option<ClientCertificate> {
+        None
+    }
 }
 
 #[derive(Default)]
@@ -157,4 +173,8 @@ impl SessionStream for NullIo {
             std::borrow::Cow::Borrowed(""),
         )
     }
+
+    fn tls_client_certificate(&self) -> Option<ClientCertificate> {
+        None
+    }
 }
@@ -29,6 +29,7 @@ use std::{
 
 use ahash::AHashMap;
 use arc_swap::ArcSwap;
+use ring::digest::{digest, SHA256};
 use rustls::{
     client::verify_server_name,
     server::{ClientHello, ParsedCertificate, ResolvesServerCert},
@@ -39,6 +40,7 @@ use rustls::{
 use rustls_pki_types::{DnsName, ServerName};
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio_rustls::{Accept, LazyConfigAcceptor, TlsAcceptor};
+use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
 
 use crate::{acme::resolver::IsTlsAlpnChallenge, config::tls::build_certified_key};
 
@@ -53,6 +55,7 @@ pub struct CertificateResolver {
 }
 
 pub struct Certificate {
+    pub id: String,
     pub cert: ArcSwap<CertifiedKey>,
     pub path: Vec<PathBuf>,
 }
@@ -198,3 +201,107 @@ impl std::fmt::Debug for CertificateResolver {
             .finish()
     }
 }
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsClientAuth {
+    pub principal: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientCertificate {
+    pub subject: String,
+    pub issuer: String,
+    pub serial: String,
+    pub common_name: Option<String>,
+    pub emails: Vec<String>,
+    pub dns_names: Vec<String>,
+    pub fingerprint: String,
+}
+
+impl ClientCertificate {
+    pub fn parse(der: &[u8]) -> Option<Self> {
+        let (_, cert) = X509Certificate::from_der(der).ok()?;
+        let mut result = ClientCertificate {
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            serial: cert.raw_serial_as_string(),
+            common_name: cert
+                .subject()
+                .iter_common_name()
+                .next()
+                .and_then(|cn| cn.as_str().ok())
+                .map(|cn| cn.trim().to_string()),
+            emails: cert
+                .subject()
+                .iter_email()
+                .filter_map(|email| email.as_str().ok())
+                .map(|email| email.trim().to_lowercase())
+                .collect(),
+            dns_names: Vec::new(),
+            fingerprint: digest(&SHA256, der)
+                .as_ref()
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect(),
+        };
+
+        if let Ok(Some(san)) = cert.subject_alternative_name() {
+            for name in &san.value.general_names {
+                match name {
+                    GeneralName::RFC822Name(email) => {
+                        let email = email.trim().to_lowercase();
+                        if !result.emails.contains(&email) {
+                            result.emails.push(email);
+                        }
+                    }
+                    GeneralName::DNSName(name) => {
+                        result.dns_names.push(name.trim().to_lowercase());
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        Some(result)
+    }
+
+    // Expands the configured templates into the names to look up in the directory,
+    // for example "{email}" or "{cn}@example.org".
+    pub fn principal_names(&self, templates: &[String]) -> Vec<String> {
+        let common_name = match &self.common_name {
+            Some(cn) => std::slice::from_ref(cn),
+            None => &[],
+        };
+        let mut names = Vec::new();
+
+        for template in templates {
+            let mut expanded = vec![template.to_string()];
+            for (variable, values) in [
+                ("{cn}", common_name),
+                ("{email}", self.emails.as_slice()),
+                ("{dns}", self.dns_names.as_slice()),
+                ("{serial}", std::slice::from_ref(&self.serial)),
+            ] {
+                if template.contains(variable) {
+                    expanded = expanded
+                        .iter()
+                        .flat_map(|name| {
+                            values
+                                .iter()
+                                .map(move |value| name.replace(variable, value))
+                        })
+                        .collect();
+                }
+            }
+
+            for name in expanded {
+                let name = name.trim();
+                if !name.is_empty() && !names.iter().any(|n| n == name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+
+        names
+    }
+}
@@ -0,0 +1,271 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use parking_lot::Mutex;
+use tokio_rustls::server::TlsStream;
+use tracing::Span;
+
+use crate::config::{ServerProtocol, VirtualHost};
+
+use super::{
+    limiter::{ConcurrencyLimiter, InFlight},
+    ServerInstance, SessionStream, TcpAcceptor,
+};
+
+/// Virtual hosts of a listener, selected by the SNI server name sent
+/// during the TLS handshake.
+#[derive(Default)]
+pub struct VirtualHosts {
+    names: ServerNames,
+    hosts: Vec<(Arc<ServerInstance>, Arc<VirtualHostStats>)>,
+    unmatched: Option<Arc<VirtualHostStats>>,
+}
+
+#[derive(Debug, Default)]
+struct ServerNames {
+    names: Vec<Vec<String>>,
+    fallback: Option<usize>,
+}
+
+#[derive(Default)]
+struct VirtualHostStats {
+    sessions: AtomicU64,
+    rejected: AtomicU64,
+}
+
+// Counters are kept across configuration reloads
+#[allow(clippy::type_complexity)]
+static STATS: Mutex<Vec<(String, String, Arc<VirtualHostStats>)>> =
+    parking_lot::const_mutex(Vec::new());
+
+impl VirtualHosts {
+    pub fn new(parent: &ServerInstance, virtual_hosts: Vec<VirtualHost>) -> Self {
+        if virtual_hosts.is_empty() {
+            return VirtualHosts::default();
+        }
+
+        let mut names = ServerNames::default();
+        let mut hosts = Vec::with_capacity(virtual_hosts.len());
+        for vhost in virtual_hosts {
+            if vhost.is_fallback {
+                names.fallback = Some(hosts.len());
+            }
+            names.names.push(vhost.server_names);
+            let stats = stats(&parent.id, &vhost.id);
+            hosts.push((
+                Arc::new(ServerInstance {
+                    data: if matches!(parent.protocol, ServerProtocol::Smtp | ServerProtocol::Lmtp)
+                    {
+                        format!("220 {} {}\r\n", vhost.hostname, vhost.data)
+                    } else {
+                        vhost.data
+                    },
+                    id: vhost.id,
+                    listener_id: vhost.internal_id,
+                    protocol: parent.protocol,
+                    hostname: vhost.hostname,
+                    banner: vhost.banner,
+                    // The TLS session is already established at this point
+                    acceptor: TcpAcceptor::Plain,
+                    tls_client_auth: parent.tls_client_auth.clone(),
+                    limiter: ConcurrencyLimiter::new(vhost.max_connections),
+                    bandwidth: parent.bandwidth.duplicate(),
+                    proxy_networks: parent.proxy_networks.clone(),
+                    blocked_ips: parent.blocked_ips.clone(),
+                    virtual_hosts: VirtualHosts::default(),
+                    shutdown_rx: parent.shutdown_rx.clone(),
+                }),
+                stats,
+            ));
+        }
+
+        VirtualHosts {
+            names,
+            hosts,
+            unmatched: stats(&parent.id, "").into(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+}
+
+impl ServerNames {
+    fn resolve(&self, server_name: Option<&str>) -> Option<usize> {
+        server_name
+            .map(|name| name.trim_end_matches('.').to_lowercase())
+            .and_then(|name| {
+                // Exact matches take precedence over wildcards
+                self.names
+                    .iter()
+                    .position(|names| names.iter().any(|n| n == &name))
+                    .or_else(|| {
+                        let (_, parent) = name.split_once('.')?;
+                        self.names.iter().position(|names| {
+                            names.iter().any(|n| n.strip_prefix("*.") == Some(parent))
+                        })
+                    })
+            })
+            .or(self.fallback)
+    }
+}
+
+impl ServerInstance {
+    /// Returns the virtual host matching the server name requested by the client,
+    /// along with a session slot, or `None` if the listener settings apply.
+    pub fn virtual_host<T: SessionStream>(
+        &self,
+        stream: &TlsStream<T>,
+        span: &Span,
+    ) -> Result<Option<(Arc<ServerInstance>, InFlight)>, ()> {
+        if self.virtual_hosts.is_empty() {
+            return Ok(None);
+        }
+
+        let server_name = stream.get_ref().1.server_name();
+        if let Some((instance, stats)) = self
+            .virtual_hosts
+            .names
+            .resolve(server_name)
+            .and_then(|pos| self.virtual_hosts.hosts.get(pos))
+        {
+            if let Some(in_flight) = instance.limiter.is_allowed() {
+                tracing::debug!(
+                    parent: span,
+                    context = "tls",
+                    event = "virtual-host",
+                    server_name = server_name.unwrap_or_default(),
+                    virtual_host = instance.id,
+                    "Session routed to virtual host."
+                );
+                stats.sessions.fetch_add(1, Ordering::Relaxed);
+                Ok(Some((instance.clone(), in_flight)))
+            } else {
+                tracing::info!(
+                    parent: span,
+                    context = "throttle",
+                    event = "too-many-requests",
+                    virtual_host = instance.id,
+                    max_concurrent = instance.limiter.max_concurrent,
+                    "Too many concurrent connections to virtual host."
+                );
+                stats.rejected.fetch_add(1, Ordering::Relaxed);
+                Err(())
+            }
+        } else {
+            if let Some(unmatched) = &self.virtual_hosts.unmatched {
+                unmatched.sessions.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(None)
+        }
+    }
+}
+
+fn stats(listener: &str, vhost: &str) -> Arc<VirtualHostStats> {
+    let mut stats = STATS.lock();
+    if let Some((_, _, item)) = stats.iter().find(|(l, v, _)| l == listener && v == vhost) {
+        item.clone()
+    } else {
+        let item = Arc::new(VirtualHostStats::default());
+        stats.push((listener.to_string(), vhost.to_string(), item.clone()));
+        item
+    }
+}
+
+/// Renders per virtual host session counters using the Prometheus text exposition format.
+pub fn to_prometheus() -> String {
+    let stats = STATS.lock();
+    if stats.is_empty() {
+        return String::new();
+    }
+
+    let mut out = String::with_capacity(512);
+    for (metric, help, value) in [
+        (
+            "stalwart_virtual_host_sessions_total",
+            "Number of TLS sessions routed to a virtual host.",
+            (|s: &VirtualHostStats| s.sessions.load(Ordering::Relaxed))
+                as fn(&VirtualHostStats) -> u64,
+        ),
+        (
+            "stalwart_virtual_host_rejected_total",
+            "Number of TLS sessions rejected by virtual host connection limits.",
+            |s| s.rejected.load(Ordering::Relaxed),
+        ),
+    ] {
+        let _ = writeln!(out, "# HELP {metric} {help}");
+        let _ = writeln!(out, "# TYPE {metric} counter");
+        for (listener, vhost, stats) in stats.iter() {
+            // Sessions that did not match any virtual host use the listener settings
+            if !vhost.is_empty() || metric.ends_with("sessions_total") {
+                let _ = writeln!(
+                    out,
+                    "{metric}{{listener=\"{listener}\",virtual_host=\"{vhost}\"}} {}",
+                    value(stats)
+                );
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ServerNames;
+
+    #[test]
+    fn resolve_virtual_host() {
+        let mut names = ServerNames {
+            names: vec![
+                vec!["mail.example.org".to_string(), "*.example.org".to_string()],
+                vec!["imap.example.org".to_string()],
+                vec![],
+            ],
+            fallback: None,
+        };
+
+        assert_eq!(names.resolve(Some("mail.example.org")), Some(0));
+        assert_eq!(names.resolve(Some("MAIL.example.org.")), Some(0));
+        assert_eq!(names.resolve(Some("imap.example.org")), Some(1));
+        assert_eq!(names.resolve(Some("smtp.example.org")), Some(0));
+        assert_eq!(names.resolve(Some("a.b.example.org")), None);
+        assert_eq!(names.resolve(Some("example.org")), None);
+        assert_eq!(names.resolve(None), None);
+
+        // Unmatched and missing server names use the fallback
+        names.fallback = Some(2);
+        assert_eq!(names.resolve(Some("example.net")), Some(2));
+        assert_eq!(names.resolve(None), Some(2));
+        assert_eq!(names.resolve(Some("imap.example.org")), Some(1));
+    }
+}
@@ -0,0 +1,476 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::config::Config;
+
+static SETTINGS: OnceLock<LogSettings> = OnceLock::new();
+static REOPEN: AtomicBool = AtomicBool::new(false);
+
+const MISSING_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Minutely,
+    Hourly,
+    Daily,
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogSettings {
+    pub path: PathBuf,
+    pub prefix: String,
+    pub rotate: Rotation,
+    pub compression: Compression,
+    pub max_size: Option<u64>,
+    pub max_age: Option<Duration>,
+    pub max_disk_usage: Option<u64>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogFile {
+    pub name: String,
+    pub size: u64,
+    pub modified: u64,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogUsage {
+    pub path: String,
+    pub files: Vec<LogFile>,
+    pub total_size: u64,
+    pub max_disk_usage: Option<u64>,
+}
+
+pub struct RotatingFileWriter {
+    settings: LogSettings,
+    file: Option<File>,
+    size: u64,
+    period: u64,
+    last_check: SystemTime,
+}
+
+impl LogSettings {
+    pub fn parse(config: &Config) -> super::config::Result<Self> {
+        Ok(LogSettings {
+            path: config.value_require("global.tracing.path")?.into(),
+            prefix: config.value_require("global.tracing.prefix")?.to_string(),
+            rotate: match config.value("global.tracing.rotate").unwrap_or("daily") {
+                "daily" => Rotation::Daily,
+                "hourly" => Rotation::Hourly,
+                "minutely" => Rotation::Minutely,
+                "never" => Rotation::Never,
+                rotate => {
+                    return Err(format!("Unsupported log rotation strategy {rotate:?}"));
+                }
+            },
+            compression: match config.value("global.tracing.compression").unwrap_or("none") {
+                "none" | "false" => Compression::None,
+                "gzip" => Compression::Gzip,
+                "zstd" => Compression::Zstd,
+                compression => {
+                    return Err(format!(
+                        "Unsupported log compression algorithm {compression:?}"
+                    ));
+                }
+            },
+            max_size: config.property("global.tracing.max-size")?,
+            max_age: config.property("global.tracing.max-age")?,
+            max_disk_usage: config.property("global.tracing.max-disk-usage")?,
+        })
+    }
+
+    fn period(&self, time: SystemTime) -> u64 {
+        let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        match self.rotate {
+            Rotation::Minutely => secs / 60,
+            Rotation::Hourly => secs / 3600,
+            Rotation::Daily => secs / 86400,
+            Rotation::Never => 0,
+        }
+    }
+
+    fn active_path(&self) -> PathBuf {
+        self.path.join(&self.prefix)
+    }
+
+    fn list_files(&self) -> io::Result<Vec<(PathBuf, LogFile)>> {
+        let rotated_prefix = format!("{}.", self.prefix);
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.path)?.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let active = name == self.prefix;
+            if !active && !name.starts_with(&rotated_prefix) {
+                continue;
+            }
+            let metadata = match entry.metadata() {
+                Ok(metadata) if metadata.is_file() => metadata,
+                _ => continue,
+            };
+            files.push((
+                entry.path(),
+                LogFile {
+                    name,
+                    size: metadata.len(),
+                    modified: metadata
+                        .modified()
+                        .ok()
+                        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                        .map_or(0, |d| d.as_secs()),
+                    active,
+                },
+            ));
+        }
+
+        // Oldest first
+        files.sort_unstable_by(|a, b| {
+            a.1.modified
+                .cmp(&b.1.modified)
+                .then_with(|| a.1.name.cmp(&b.1.name))
+        });
+
+        Ok(files)
+    }
+
+    fn enforce_retention(&self) -> io::Result<()> {
+        let mut files = self.list_files()?;
+
+        // Delete expired files
+        if let Some(max_age) = self.max_age {
+            let oldest = SystemTime::now()
+                .checked_sub(max_age)
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs());
+            files.retain(|(path, file)| {
+                if !file.active && file.modified < oldest {
+                    fs::remove_file(path).is_err()
+                } else {
+                    true
+                }
+            });
+        }
+
+        // Delete the oldest files until the total disk usage is under the limit
+        if let Some(max_disk_usage) = self.max_disk_usage {
+            let mut total_size = files.iter().map(|(_, file)| file.size).sum::<u64>();
+            for (path, file) in &files {
+                if total_size <= max_disk_usage {
+                    break;
+                } else if !file.active && fs::remove_file(path).is_ok() {
+                    total_size -= file.size;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Compression {
+    fn extension(&self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    fn output_path(&self, path: &Path) -> PathBuf {
+        let mut output_path = path.as_os_str().to_owned();
+        if *self != Compression::None {
+            output_path.push(".");
+            output_path.push(self.extension());
+        }
+        output_path.into()
+    }
+
+    fn compress(&self, path: &Path) -> io::Result<()> {
+        if *self == Compression::None {
+            return Ok(());
+        }
+        let output_path = self.output_path(path);
+        let mut input = File::open(path)?;
+        let output = File::create(&output_path)?;
+
+        let result = match self {
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(output, flate2::Compression::default());
+                io::copy(&mut input, &mut encoder).and_then(|_| encoder.finish().map(|_| ()))
+            }
+            Compression::Zstd => zstd::stream::copy_encode(&mut input, output, 0),
+            Compression::None => unreachable!(),
+        };
+
+        match result {
+            Ok(_) => fs::remove_file(path),
+            Err(err) => {
+                let _ = fs::remove_file(&output_path);
+                Err(err)
+            }
+        }
+    }
+}
+
+impl RotatingFileWriter {
+    pub fn new(settings: LogSettings) -> io::Result<Self> {
+        fs::create_dir_all(&settings.path)?;
+        let _ = SETTINGS.set(settings.clone());
+        let mut writer = RotatingFileWriter {
+            period: settings.period(SystemTime::now()),
+            settings,
+            file: None,
+            size: 0,
+            last_check: SystemTime::now(),
+        };
+        writer.open()?;
+        Ok(writer)
+    }
+
+    fn open(&mut self) -> io::Result<&mut File> {
+        let path = self.settings.active_path();
+        let now = SystemTime::now();
+        self.period = self.settings.period(now);
+
+        // Rotate files left behind from a previous period
+        if let Ok(metadata) = fs::metadata(&path) {
+            if metadata.len() > 0
+                && metadata
+                    .modified()
+                    .map_or(false, |t| self.settings.period(t) < self.period)
+            {
+                self.rotate_file();
+            }
+        }
+
+        if !self.settings.path.exists() {
+            fs::create_dir_all(&self.settings.path)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        self.size = file.metadata().map_or(0, |m| m.len());
+        self.last_check = now;
+        Ok(self.file.insert(file))
+    }
+
+    fn rotate_file(&mut self) {
+        self.file = None;
+        self.size = 0;
+
+        let active_path = self.settings.active_path();
+        let timestamp = DateTime::<Utc>::from(SystemTime::now()).format("%Y-%m-%d-%H%M%S");
+        let mut rotated_path = self
+            .settings
+            .path
+            .join(format!("{}.{timestamp}", self.settings.prefix));
+        let mut seq = 1;
+        while rotated_path.exists()
+            || self
+                .settings
+                .compression
+                .output_path(&rotated_path)
+                .exists()
+        {
+            rotated_path = self
+                .settings
+                .path
+                .join(format!("{}.{timestamp}.{seq}", self.settings.prefix));
+            seq += 1;
+        }
+        if fs::rename(&active_path, &rotated_path).is_err() {
+            return;
+        }
+
+        // Compress and purge old files in the background
+        let settings = self.settings.clone();
+        std::thread::spawn(move || {
+            if let Err(err) = settings.compression.compress(&rotated_path) {
+                eprintln!("Failed to compress log file {rotated_path:?}: {err}");
+            }
+            if let Err(err) = settings.enforce_retention() {
+                eprintln!("Failed to purge log files in {:?}: {err}", settings.path);
+            }
+        });
+    }
+
+    fn is_missing(&mut self, now: SystemTime) -> bool {
+        // Detect files removed or moved by external tools
+        if now
+            .duration_since(self.last_check)
+            .map_or(false, |d| d >= MISSING_CHECK_INTERVAL)
+        {
+            self.last_check = now;
+            !self.settings.active_path().exists()
+        } else {
+            false
+        }
+    }
+
+    fn needs_rotation(&self, now: SystemTime, len: usize) -> bool {
+        self.settings.period(now) != self.period
+            || self.settings.max_size.map_or(false, |max_size| {
+                self.size > 0 && self.size + len as u64 > max_size
+            })
+    }
+}
+
+impl Write for RotatingFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let now = SystemTime::now();
+        let reopen = REOPEN.swap(false, Ordering::Relaxed);
+        if self.needs_rotation(now, buf.len()) {
+            self.rotate_file();
+        } else if reopen || self.is_missing(now) {
+            self.file = None;
+        }
+
+        let file = match self.file {
+            Some(ref mut file) => file,
+            None => self.open()?,
+        };
+        let written = file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file {
+            Some(ref mut file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+// Requests the log file to be reopened on the next write
+pub fn reopen() {
+    REOPEN.store(true, Ordering::Relaxed);
+}
+
+pub fn disk_usage() -> Option<io::Result<LogUsage>> {
+    let settings = SETTINGS.get()?;
+    Some(settings.list_files().map(|files| {
+        let files = files.into_iter().map(|(_, file)| file).collect::<Vec<_>>();
+        LogUsage {
+            path: settings.path.to_string_lossy().into_owned(),
+            total_size: files.iter().map(|file| file.size).sum(),
+            max_disk_usage: settings.max_disk_usage,
+            files,
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, time::Duration};
+
+    use super::{Compression, LogSettings, RotatingFileWriter, Rotation};
+
+    #[test]
+    fn rotate_compress_and_purge() {
+        let path = std::env::temp_dir().join("stalwart_log_rotation_test");
+        let _ = std::fs::remove_dir_all(&path);
+        let settings = LogSettings {
+            path: path.clone(),
+            prefix: "test.log".to_string(),
+            rotate: Rotation::Never,
+            compression: Compression::Gzip,
+            max_size: Some(100),
+            max_age: Some(Duration::from_secs(86400)),
+            max_disk_usage: None,
+        };
+
+        // Exceeding the maximum size rotates the file
+        let mut writer = RotatingFileWriter::new(settings.clone()).unwrap();
+        for _ in 0..5 {
+            writer.write_all(&[b'a'; 60]).unwrap();
+        }
+        writer.flush().unwrap();
+
+        // Wait for background compression
+        let mut files = Vec::new();
+        for _ in 0..50 {
+            files = settings.list_files().unwrap();
+            if files.len() == 5
+                && files
+                    .iter()
+                    .filter(|(_, f)| f.name.ends_with(".gz"))
+                    .count()
+                    == 4
+            {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        assert_eq!(files.len(), 5, "{files:?}");
+        assert_eq!(files.iter().filter(|(_, f)| f.active).count(), 1);
+        assert_eq!(
+            files
+                .iter()
+                .filter(|(_, f)| f.name.ends_with(".gz"))
+                .count(),
+            4
+        );
+
+        // Disk usage limit removes the oldest files but never the active one
+        let settings = LogSettings {
+            max_disk_usage: Some(1),
+            ..settings
+        };
+        settings.enforce_retention().unwrap();
+        let files = settings.list_files().unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].1.active);
+        assert_eq!(files[0].1.size, 60);
+
+        // Removed files are reopened
+        std::fs::remove_file(path.join("test.log")).unwrap();
+        super::reopen();
+        writer.write_all(b"hello").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(std::fs::read(path.join("test.log")).unwrap(), b"hello");
+
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
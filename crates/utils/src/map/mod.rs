@@ -23,5 +23,6 @@
 
 pub mod bitmap;
 pub mod mutex_map;
+pub mod stats;
 pub mod ttl_dashmap;
 pub mod vec_map;
@@ -0,0 +1,210 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+#[derive(Debug, Default)]
+pub struct CacheStats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    insertions: AtomicU64,
+    evictions: AtomicU64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheReport {
+    pub name: &'static str,
+    pub hits: u64,
+    pub misses: u64,
+    pub insertions: u64,
+    pub evictions: u64,
+    pub entries: Option<usize>,
+    pub capacity: Option<usize>,
+}
+
+impl CacheStats {
+    pub fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn insert(&self) {
+        self.insertions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn evict(&self, num_items: usize) {
+        if num_items > 0 {
+            self.evictions
+                .fetch_add(num_items as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub fn report(
+        &self,
+        name: &'static str,
+        entries: Option<usize>,
+        capacity: Option<usize>,
+    ) -> CacheReport {
+        CacheReport {
+            name,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            insertions: self.insertions.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries,
+            capacity,
+        }
+    }
+}
+
+impl CacheReport {
+    pub fn hit_ratio(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total > 0 {
+            self.hits as f64 / total as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Renders cache reports using the Prometheus text exposition format.
+pub fn to_prometheus(reports: &[CacheReport]) -> String {
+    let mut out = String::with_capacity(reports.len() * 256);
+
+    write_metric(
+        &mut out,
+        reports,
+        "stalwart_cache_hits_total",
+        "counter",
+        "Number of cache lookups that returned an entry.",
+        |r| Some(r.hits as f64),
+    );
+    write_metric(
+        &mut out,
+        reports,
+        "stalwart_cache_misses_total",
+        "counter",
+        "Number of cache lookups that did not return an entry.",
+        |r| Some(r.misses as f64),
+    );
+    write_metric(
+        &mut out,
+        reports,
+        "stalwart_cache_insertions_total",
+        "counter",
+        "Number of entries added to the cache.",
+        |r| Some(r.insertions as f64),
+    );
+    write_metric(
+        &mut out,
+        reports,
+        "stalwart_cache_evictions_total",
+        "counter",
+        "Number of entries evicted or expired from the cache.",
+        |r| Some(r.evictions as f64),
+    );
+    write_metric(
+        &mut out,
+        reports,
+        "stalwart_cache_entries",
+        "gauge",
+        "Number of entries currently held by the cache.",
+        |r| r.entries.map(|e| e as f64),
+    );
+    write_metric(
+        &mut out,
+        reports,
+        "stalwart_cache_capacity",
+        "gauge",
+        "Maximum number of entries the cache can hold.",
+        |r| r.capacity.map(|c| c as f64),
+    );
+    write_metric(
+        &mut out,
+        reports,
+        "stalwart_cache_hit_ratio",
+        "gauge",
+        "Ratio of cache lookups that returned an entry.",
+        |r| Some(r.hit_ratio()),
+    );
+
+    out
+}
+
+fn write_metric(
+    out: &mut String,
+    reports: &[CacheReport],
+    metric: &str,
+    typ: &str,
+    help: &str,
+    value: impl Fn(&CacheReport) -> Option<f64>,
+) {
+    let _ = writeln!(out, "# HELP {metric} {help}");
+    let _ = writeln!(out, "# TYPE {metric} {typ}");
+    for report in reports {
+        if let Some(value) = value(report) {
+            let _ = writeln!(out, "{metric}{{cache=\"{}\"}} {value}", report.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_prometheus, CacheStats};
+
+    #[test]
+    fn cache_stats() {
+        let stats = CacheStats::default();
+        stats.hit();
+        stats.hit();
+        stats.hit();
+        stats.miss();
+        stats.insert();
+        stats.evict(2);
+
+        let report = stats.report("access_tokens", Some(10), None);
+        assert_eq!(report.hits, 3);
+        assert_eq!(report.misses, 1);
+        assert_eq!(report.insertions, 1);
+        assert_eq!(report.evictions, 2);
+        assert_eq!(report.hit_ratio(), 0.75);
+
+        let metrics = to_prometheus(&[report]);
+        for expected in [
+            "# TYPE stalwart_cache_hits_total counter\n",
+            "stalwart_cache_hits_total{cache=\"access_tokens\"} 3\n",
+            "stalwart_cache_entries{cache=\"access_tokens\"} 10\n",
+            "stalwart_cache_hit_ratio{cache=\"access_tokens\"} 0.75\n",
+        ] {
+            assert!(metrics.contains(expected), "{expected:?} not in {metrics}");
+        }
+        assert!(!metrics.contains("stalwart_cache_capacity{"));
+    }
+}
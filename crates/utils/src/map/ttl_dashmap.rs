@@ -8,11 +8,16 @@
  * except according to those terms.
  */
 
-use std::{borrow::Borrow, hash::Hash, time::Instant};
+use std::{borrow::Borrow, hash::Hash, ops::Deref, time::Instant};
 
 use dashmap::DashMap;
 
-pub type TtlDashMap<K, V> = DashMap<K, LruItem<V>, ahash::RandomState>;
+use super::stats::CacheStats;
+
+pub struct TtlDashMap<K, V> {
+    map: DashMap<K, LruItem<V>, ahash::RandomState>,
+    pub stats: CacheStats,
+}
 
 #[derive(Debug, Clone)]
 pub struct LruItem<V> {
@@ -20,6 +25,12 @@ pub struct LruItem<V> {
     valid_until: Instant,
 }
 
+impl<V> LruItem<V> {
+    pub fn item(&self) -> &V {
+        &self.item
+    }
+}
+
 pub trait TtlMap<K, V>: Sized {
     fn with_capacity(capacity: usize, shard_amount: usize) -> Self;
     fn get_with_ttl<Q: ?Sized>(&self, name: &Q) -> Option<V>
@@ -32,11 +43,14 @@ pub trait TtlMap<K, V>: Sized {
 
 impl<K: Hash + Eq, V: Clone> TtlMap<K, V> for TtlDashMap<K, V> {
     fn with_capacity(capacity: usize, shard_amount: usize) -> Self {
-        DashMap::with_capacity_and_hasher_and_shard_amount(
-            capacity,
-            ahash::RandomState::new(),
-            shard_amount,
-        )
+        TtlDashMap {
+            map: DashMap::with_capacity_and_hasher_and_shard_amount(
+                capacity,
+                ahash::RandomState::new(),
+                shard_amount,
+            ),
+            stats: CacheStats::default(),
+        }
     }
 
     fn get_with_ttl<Q: ?Sized>(&self, name: &Q) -> Option<V>
@@ -44,14 +58,21 @@ impl<K: Hash + Eq, V: Clone> TtlMap<K, V> for TtlDashMap<K, V> {
         K: Borrow<Q>,
         Q: Hash + Eq,
     {
-        match self.get(name) {
-            Some(entry) if entry.valid_until >= Instant::now() => entry.item.clone().into(),
-            _ => None,
+        match self.map.get(name) {
+            Some(entry) if entry.valid_until >= Instant::now() => {
+                self.stats.hit();
+                entry.item.clone().into()
+            }
+            _ => {
+                self.stats.miss();
+                None
+            }
         }
     }
 
     fn insert_with_ttl(&self, name: K, item: V, valid_until: Instant) -> V {
-        self.insert(
+        self.stats.insert();
+        self.map.insert(
             name,
             LruItem {
                 item: item.clone(),
@@ -62,6 +83,17 @@ impl<K: Hash + Eq, V: Clone> TtlMap<K, V> for TtlDashMap<K, V> {
     }
 
     fn cleanup(&self) {
-        self.retain(|_, entry| entry.valid_until >= Instant::now());
+        let len = self.map.len();
+        self.map
+            .retain(|_, entry| entry.valid_until >= Instant::now());
+        self.stats.evict(len.saturating_sub(self.map.len()));
+    }
+}
+
+impl<K: Hash + Eq, V> Deref for TtlDashMap<K, V> {
+    type Target = DashMap<K, LruItem<V>, ahash::RandomState>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.map
     }
 }
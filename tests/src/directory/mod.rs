@@ -29,8 +29,9 @@ pub mod sql;
 
 use ::smtp::core::Lookup;
 use directory::{
-    backend::internal::manage::ManageDirectory, core::config::ConfigDirectory, AddressMapping,
-    Directories, Principal,
+    backend::internal::{manage::ManageDirectory, PrincipalField, PrincipalUpdate, PrincipalValue},
+    core::{config::ConfigDirectory, reserved::ReservedAddresses},
+    AddressMapping, Directories, Principal,
 };
 use mail_send::Credentials;
 use rustls::ServerConfig;
@@ -656,6 +657,69 @@ fn address_mappings() {
     }
 }
 
+#[test]
+fn reserved_addresses() {
+    const RESERVED: &str = r#"
+    [default]
+
+    [custom]
+    names = ["postmaster", "Webmaster"]
+    route = { abuse = "security@example.org", postmaster = "admin" }
+    "#;
+
+    let config = utils::config::Config::new(RESERVED).unwrap();
+
+    // Role addresses are reserved by default
+    let reserved = ReservedAddresses::from_config(&config, "default").unwrap();
+    for address in ["postmaster@example.org", "ABUSE@example.org", "hostmaster"] {
+        assert!(reserved.is_reserved(address), "{address}");
+    }
+    assert!(!reserved.is_reserved("john@example.org"));
+    assert_eq!(reserved.route("postmaster@example.org"), None);
+
+    // Routes are resolved against the domain of the address
+    let reserved = ReservedAddresses::from_config(&config, "custom").unwrap();
+    for (address, expected) in [
+        ("postmaster@example.org", Some("admin@example.org")),
+        ("Abuse@example.net", Some("security@example.org")),
+        ("webmaster@example.org", None),
+        ("hostmaster@example.org", None),
+    ] {
+        assert_eq!(
+            reserved.route(address).as_deref(),
+            expected,
+            "failed route for {address:?}"
+        );
+    }
+    assert!(reserved.is_reserved("webmaster@example.org"));
+    assert!(!reserved.is_reserved("hostmaster@example.org"));
+
+    // Principals cannot be assigned reserved names or addresses
+    let mut principal = Principal {
+        name: "john".to_string(),
+        emails: vec!["john@example.org".to_string()],
+        ..Default::default()
+    };
+    assert!(reserved.assert_principal(&principal).is_ok());
+    principal.emails.push("abuse@example.org".to_string());
+    assert!(reserved.assert_principal(&principal).is_err());
+    principal.emails.pop();
+    principal.name = "Postmaster".to_string();
+    assert!(reserved.assert_principal(&principal).is_err());
+    assert!(reserved
+        .assert_changes(&[PrincipalUpdate::add_item(
+            PrincipalField::Emails,
+            PrincipalValue::String("webmaster@example.org".to_string()),
+        )])
+        .is_err());
+    assert!(reserved
+        .assert_changes(&[PrincipalUpdate::remove_item(
+            PrincipalField::Emails,
+            PrincipalValue::String("webmaster@example.org".to_string()),
+        )])
+        .is_ok());
+}
+
 async fn map_account_ids(store: &Store, names: Vec<impl AsRef<str>>) -> Vec<u32> {
     let mut ids = Vec::with_capacity(names.len());
     for name in names {
@@ -313,7 +313,7 @@ impl DirectoryStore {
             concat!(
                 "CREATE TABLE accounts (name TEXT PRIMARY KEY, secret TEXT, description TEXT,",
                 " type TEXT NOT NULL, quota INTEGER ",
-                "DEFAULT 0, active BOOLEAN DEFAULT TRUE)"
+                "DEFAULT 0, active BOOLEAN DEFAULT TRUE, referral TEXT)"
             ),
             concat!(
                 "CREATE TABLE group_members (name TEXT NOT NULL, member_of ",
@@ -438,6 +438,20 @@ impl DirectoryStore {
             .unwrap();
     }
 
+    pub async fn set_test_referral(&self, login: &str, referral: &str) {
+        self.store
+            .query::<usize>(
+                if self.is_postgresql() {
+                    "UPDATE accounts SET referral = $1 where name = $2"
+                } else {
+                    "UPDATE accounts SET referral = ? where name = ?"
+                },
+                vec![referral.into(), login.into()],
+            )
+            .await
+            .unwrap();
+    }
+
     pub async fn add_to_group(&self, login: &str, group: &str) {
         self.store
             .query::<usize>(
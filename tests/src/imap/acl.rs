@@ -226,6 +226,10 @@ pub async fn test(mut imap_john: &mut ImapConnection, _imap_check: &mut ImapConn
 
     imap_john.send("SELECT INBOX").await;
     imap_john.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_john
+        .send(&format!("UID STORE {} +FLAGS (\\Flagged)", uid))
+        .await;
+    imap_john.assert_read(Type::Tagged, ResponseType::Ok).await;
 
     // Copy from John's Inbox to Jane's Inbox
     imap_john
@@ -255,12 +259,13 @@ pub async fn test(mut imap_john: &mut ImapConnection, _imap_check: &mut ImapConn
     imap_jane.assert_read(Type::Tagged, ResponseType::Ok).await;
 
     imap_jane
-        .send(&format!("UID FETCH {} (PREVIEW)", uid))
+        .send(&format!("UID FETCH {} (PREVIEW FLAGS)", uid))
         .await;
     imap_jane
         .assert_read(Type::Tagged, ResponseType::Ok)
         .await
-        .assert_contains("copy test");
+        .assert_contains("copy test")
+        .assert_contains("\\Flagged");
 
     // Bill now moves the message to his own Inbox
     imap_bill.send(&format!("UID MOVE {} INBOX", uid)).await;
@@ -312,6 +317,15 @@ pub async fn test(mut imap_john: &mut ImapConnection, _imap_check: &mut ImapConn
         .assert_contains("\"jdoe@example.com\" rl")
         .assert_count("foobar@example.com", 0);
 
+    // John can no longer copy messages to Jane's Inbox
+    imap_john
+        .send("UID COPY 1:* \"Shared Folders/jane.smith@example.com/Inbox\"")
+        .await;
+    imap_john
+        .assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_contains("NOPERM");
+
     // Bill should not have access to Jane's Inbox anymore
     imap_bill.send("LIST \"\" \"*\"").await;
     imap_bill
@@ -46,11 +46,15 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection, h
     entries.sort();
 
     let mut expected_uid = 1;
+    let mut first_message = None;
     for file_name in entries.into_iter().take(20) {
         if file_name.extension().map_or(true, |e| e != "txt") {
             continue;
         }
         let raw_message = fs::read(&file_name).unwrap();
+        if first_message.is_none() {
+            first_message = raw_message.len().into();
+        }
 
         imap.send(&format!(
             "APPEND INBOX (Flag_{}) {{{}}}",
@@ -79,6 +83,40 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection, h
         expected_uid += 1;
     }
 
+    // Compose a message from an existing message and a literal
+    imap.send("CREATE Catenate").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    let tail = "\r\n-- \r\nForwarded\r\n";
+    imap.send(&format!(
+        "APPEND Catenate CATENATE (URL \"/INBOX/;UID=1\" TEXT {{{}+}}\r\n{})",
+        tail.len(),
+        tail
+    ))
+    .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_response_code("APPENDUID");
+    imap.send("STATUS Catenate (MESSAGES SIZE)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains(&format!("SIZE {}", first_message.unwrap() + tail.len()));
+
+    // Invalid or stale references are rejected
+    for url in [
+        "/INBOX/;UID=9999",
+        "/INBOX;UIDVALIDITY=1/;UID=1",
+        "/Does not exist/;UID=1",
+        "/INBOX/;UID=1/;SECTION=BODY",
+    ] {
+        imap.send(&format!("APPEND Catenate CATENATE (URL \"{url}\")"))
+            .await;
+        imap.assert_read(Type::Tagged, ResponseType::No)
+            .await
+            .assert_response_code("BADURL");
+    }
+    imap.send("DELETE Catenate").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+
     wait_for_index(&handle.jmap).await;
 }
 
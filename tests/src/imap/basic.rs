@@ -31,7 +31,9 @@ use super::{AssertResult, ImapConnection, Type};
 pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
     // Test CAPABILITY
     imap.send("CAPABILITY").await;
-    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("LOGIN-REFERRALS");
 
     // Test NOOP
     imap.send("NOOP").await;
@@ -52,6 +54,20 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
     imap.assert_read(Type::Continuation, ResponseType::Ok).await;
     imap.send_untagged("AGJvYXR5AG1jYm9hdGZhY2U=").await;
     imap.assert_read(Type::Tagged, ResponseType::No).await;
+
+    // Accounts hosted on another server are referred to it
+    imap.send("AUTHENTICATE PLAIN AG1vdmVkQGV4YW1wbGUuY29tAHNlY3JldA==")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_contains("[REFERRAL imaps://imap.remote.org]");
+
+    // Bare host referrals default to implicit TLS
+    imap.send("AUTHENTICATE PLAIN AHJlbG9jYXRlZEBleGFtcGxlLmNvbQBzZWNyZXQ=")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_contains("[REFERRAL imaps://imap.remote.org:9993/]");
 }
 
 #[test]
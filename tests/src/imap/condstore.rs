@@ -169,9 +169,12 @@ pub async fn test(imap: &mut ImapConnection, imap_check: &mut ImapConnection) {
     imap.assert_read(Type::Tagged, ResponseType::Ok)
         .await
         .assert_count("VANISHED", 1)
-        .assert_contains("VANISHED (EARLIER) 1")
+        .assert_equals("* VANISHED (EARLIER) 1:2")
         .assert_count("FETCH (", 3);
 
+    // Expunge tombstones are tracked per mailbox and change id, so UID 1 (moved
+    // out at SEQ 2) is no longer reported once the client is past that change.
+
     // Fetch changes since SEQ 3
     imap.send(&format!(
         "UID FETCH 1:* (FLAGS) (CHANGEDSINCE {} VANISHED)",
@@ -181,7 +184,7 @@ pub async fn test(imap: &mut ImapConnection, imap_check: &mut ImapConnection) {
     imap.assert_read(Type::Tagged, ResponseType::Ok)
         .await
         .assert_count("VANISHED", 1)
-        .assert_contains("VANISHED (EARLIER) 1:2") // .assert_contains("VANISHED (EARLIER) 2")
+        .assert_equals("* VANISHED (EARLIER) 2")
         .assert_count("FETCH (", 3);
 
     // Fetch changes since SEQ 4
@@ -193,7 +196,7 @@ pub async fn test(imap: &mut ImapConnection, imap_check: &mut ImapConnection) {
     imap.assert_read(Type::Tagged, ResponseType::Ok)
         .await
         .assert_count("VANISHED", 1)
-        .assert_contains("VANISHED (EARLIER) 1:2") // .assert_contains("VANISHED (EARLIER) 2")
+        .assert_equals("* VANISHED (EARLIER) 2")
         .assert_count("FETCH (", 2);
 
     // Fetch changes since SEQ 6
@@ -205,7 +208,7 @@ pub async fn test(imap: &mut ImapConnection, imap_check: &mut ImapConnection) {
     imap.assert_read(Type::Tagged, ResponseType::Ok)
         .await
         .assert_count("VANISHED", 1)
-        .assert_contains("VANISHED (EARLIER) 1:2") // .assert_contains("VANISHED (EARLIER) 2")
+        .assert_equals("* VANISHED (EARLIER) 2")
         .assert_count("FETCH (", 1);
 
     // Fetch changes since SEQ 7
@@ -217,7 +220,7 @@ pub async fn test(imap: &mut ImapConnection, imap_check: &mut ImapConnection) {
     imap.assert_read(Type::Tagged, ResponseType::Ok)
         .await
         .assert_count("VANISHED", 1)
-        .assert_contains("VANISHED (EARLIER) 1:2") // .assert_contains("VANISHED (EARLIER) 2")
+        .assert_equals("* VANISHED (EARLIER) 2")
         .assert_count("FETCH (", 0);
 
     // Fetch changes since SEQ 8
@@ -23,9 +23,9 @@
 
 use imap_proto::ResponseType;
 
-use super::{AssertResult, ImapConnection, Type};
+use super::{AssertResult, IMAPTest, ImapConnection, Type};
 
-pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
+pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection, handle: &IMAPTest) {
     // Examine INBOX
     imap.send("EXAMINE INBOX").await;
     imap.assert_read(Type::Tagged, ResponseType::Ok)
@@ -100,6 +100,23 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
         .assert_contains("ℌ𝔢𝔩𝔭 𝔪𝔢 𝔢𝔵𝔭𝔬𝔯𝔱 𝔪𝔶 𝔟𝔬𝔬𝔨")
         .assert_contains("Vandelay");
 
+    // Full bodies and partials are streamed from the blob store
+    imap.send("UID FETCH 10 (BODY.PEEK[] RFC822.SIZE RFC822)")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("BODY[] {1457}")
+        .assert_contains("RFC822.SIZE 1457")
+        .assert_contains("RFC822 {1457}")
+        .assert_count("Why not both importing AND exporting?", 2);
+    imap.send("UID FETCH 10 (BODY.PEEK[]<10.20> BODY.PEEK[]<1450.100> BODY.PEEK[]<2000.10>)")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("BODY[]<10> {20}")
+        .assert_contains("BODY[]<1450> {7}")
+        .assert_contains("BODY[]<2000> {0}");
+
     // We are in EXAMINE mode, fetching body should not set \Seen
     imap.send("UID FETCH 10 (FLAGS)").await;
     imap.assert_read(Type::Tagged, ResponseType::Ok)
@@ -168,4 +185,38 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
         .assert_contains("Some text appears here")
         .assert_contains("plain text version of message goes here")
         .assert_contains("This is implicitly typed plain US-ASCII text.");
+
+    // ENVELOPE and BODYSTRUCTURE are built on the first fetch and then served
+    // from the cache, producing the same output
+    handle.imap.structure_cache.clear();
+    let stats = || handle.imap.structure_cache.stats.report("imap", None, None);
+    let before = stats();
+    imap.send("FETCH 1:10 (ENVELOPE BODY BODYSTRUCTURE)").await;
+    let uncached = imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    let after = stats();
+    assert_eq!(after.misses - before.misses, 10);
+    assert_eq!(after.insertions - before.insertions, 10);
+    assert_eq!(after.hits, before.hits);
+
+    imap.send("FETCH 1:10 (ENVELOPE BODY BODYSTRUCTURE)").await;
+    let cached = imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    let before = after;
+    let after = stats();
+    assert_eq!(after.hits - before.hits, 10);
+    assert_eq!(after.misses, before.misses);
+    assert_eq!(after.insertions, before.insertions);
+    assert_eq!(cached, uncached);
+
+    // Cached structures are also used when the blob is fetched for other items
+    imap.send("FETCH 10 (ENVELOPE BODYSTRUCTURE BODY.PEEK[HEADER])")
+        .await;
+    let cached = imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    assert_eq!(stats().hits - after.hits, 1);
+    handle.imap.structure_cache.clear();
+    imap.send("FETCH 10 (ENVELOPE BODYSTRUCTURE BODY.PEEK[HEADER])")
+        .await;
+    assert_eq!(
+        imap.assert_read(Type::Tagged, ResponseType::Ok).await,
+        cached
+    );
 }
@@ -83,9 +83,11 @@ pub async fn test(mut imap: &mut ImapConnection, mut imap_check: &mut ImapConnec
     // Create missing parent folders
     imap.send("CREATE \"/Vegetable/Broccoli\" (USE (\\Important))")
         .await;
-    imap.assert_read(Type::Tagged, ResponseType::Ok)
+    let broccoli_id = imap
+        .assert_read(Type::Tagged, ResponseType::Ok)
         .await
-        .assert_contains("[MAILBOXID (");
+        .assert_contains("[MAILBOXID (")
+        .into_response_code();
 
     imap.send("CREATE \" Cars/Electric /4 doors/ Red/\"").await;
     imap.assert_read(Type::Tagged, ResponseType::Ok).await;
@@ -113,6 +115,10 @@ pub async fn test(mut imap: &mut ImapConnection, mut imap_check: &mut ImapConnec
             );
     }
 
+    // Subscriptions must survive renames of parent folders
+    imap.send("SUBSCRIBE \"Cars/Electric/4 doors/Red\"").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+
     // Rename folders
     imap.send("RENAME \"Fruit/Apple/Green\" \"Fruit/Apple/Red\"")
         .await;
@@ -122,12 +128,25 @@ pub async fn test(mut imap: &mut ImapConnection, mut imap_check: &mut ImapConnec
     imap.send("RENAME \"Vegetable/Broccoli\" \"Veggies/Green/Broccoli\"")
         .await;
     imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    // Object ids must survive renames
+    imap.send("STATUS \"Veggies/Green/Broccoli\" (MAILBOXID)")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains(&broccoli_id);
     imap.send("RENAME \"Tofu\" \"INBOX\"").await;
     imap.assert_read(Type::Tagged, ResponseType::No).await;
     imap.send("RENAME \"Tofu\" \"INBOX/Tofu\"").await;
     imap.assert_read(Type::Tagged, ResponseType::Ok).await;
     imap.send("RENAME \"Deleted Items\" \"Recycle Bin\"").await;
     imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    // Folders cannot be moved under their own children
+    imap.send("RENAME \"Vehicles\" \"Vehicles/Electric/Vehicles\"")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::No).await;
+
     for imap in [&mut imap, &mut imap_check] {
         imap.send("LIST \"\" \"*\" RETURN (CHILDREN SPECIAL-USE)")
             .await;
@@ -148,10 +167,14 @@ pub async fn test(mut imap: &mut ImapConnection, mut imap_check: &mut ImapConnec
                     ("Vegetable", ["HasNoChildren", ""]),
                     ("Veggies", ["HasChildren", ""]),
                     ("Veggies/Green", ["HasChildren", ""]),
-                    ("Veggies/Green/Broccoli", ["HasNoChildren", ""]),
+                    ("Veggies/Green/Broccoli", ["HasNoChildren", "\\Important"]),
                 ],
                 true,
             );
+        imap.send("LIST (SUBSCRIBED) \"\" \"*\"").await;
+        imap.assert_read(Type::Tagged, ResponseType::Ok)
+            .await
+            .assert_folders([("Vehicles/Electric/4 doors/Red", ["Subscribed"])], true);
     }
 
     // Delete folders
@@ -31,8 +31,10 @@ pub mod fetch;
 pub mod idle;
 pub mod mailbox;
 pub mod managesieve;
+pub mod notify;
 pub mod search;
 pub mod store;
+pub mod subscribe;
 pub mod thread;
 
 use std::{path::PathBuf, sync::Arc, time::Duration};
@@ -69,6 +71,13 @@ protocol = "imap"
 max-connections = 81920
 tls.implicit = true
 
+[server.listener.jmap]
+bind = ["127.0.0.1:9990"]
+url = "https://127.0.0.1:9990"
+protocol = "jmap"
+max-connections = 81920
+tls.implicit = true
+
 [server.listener.sieve]
 bind = ["127.0.0.1:4190"]
 protocol = "managesieve"
@@ -215,7 +224,7 @@ type = "sqlite"
 path = "{TMP}/auth.db"
 
 [store."auth".query]
-name = "SELECT name, type, secret, description, quota FROM accounts WHERE name = ? AND active = true"
+name = "SELECT name, type, secret, description, quota, referral FROM accounts WHERE name = ? AND active = true"
 members = "SELECT member_of FROM group_members WHERE name = ?"
 recipients = "SELECT name FROM emails WHERE address = ?"
 emails = "SELECT address FROM emails WHERE name = ? AND type != 'list' ORDER BY type DESC, address ASC"
@@ -234,6 +243,7 @@ secret = "secret"
 email = "address"
 quota = "quota"
 type = "type"
+referral = "referral"
 
 [store."local/domains"]
 type = "memory"
@@ -341,6 +351,18 @@ async fn init_imap_tests(store_id: &str, delete_if_exists: bool) -> IMAPTest {
     lookup
         .create_test_user_with_email("foobar@example.com", "secret", "Bill Foobar")
         .await;
+    lookup
+        .create_test_user_with_email("moved@example.com", "secret", "Moved User")
+        .await;
+    lookup
+        .set_test_referral("moved@example.com", "imaps://imap.remote.org")
+        .await;
+    lookup
+        .create_test_user_with_email("relocated@example.com", "secret", "Relocated User")
+        .await;
+    lookup
+        .set_test_referral("relocated@example.com", "imap.remote.org:9993")
+        .await;
     lookup
         .create_test_group_with_email("support@example.com", "Support Group")
         .await;
@@ -415,11 +437,13 @@ pub async fn imap_tests() {
     mailbox::test(&mut imap, &mut imap_check).await;
     append::test(&mut imap, &mut imap_check, &handle).await;
     search::test(&mut imap, &mut imap_check).await;
-    fetch::test(&mut imap, &mut imap_check).await;
+    fetch::test(&mut imap, &mut imap_check, &handle).await;
     store::test(&mut imap, &mut imap_check, &handle).await;
     copy_move::test(&mut imap, &mut imap_check).await;
     thread::test(&mut imap, &mut imap_check).await;
     idle::test(&mut imap, &mut imap_check).await;
+    notify::test(&mut imap, &mut imap_check).await;
+    subscribe::test(&mut imap, &mut imap_check).await;
     condstore::test(&mut imap, &mut imap_check).await;
     acl::test(&mut imap, &mut imap_check).await;
 
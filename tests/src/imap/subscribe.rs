@@ -0,0 +1,123 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::{Duration, Instant};
+
+use imap_proto::ResponseType;
+use jmap_client::{
+    client::{Client, Credentials},
+    mailbox,
+};
+
+use super::{AssertResult, ImapConnection, Type};
+
+pub async fn test(imap: &mut ImapConnection, imap_check: &mut ImapConnection) {
+    let client = Client::new()
+        .credentials(Credentials::basic("jdoe@example.com", "secret"))
+        .timeout(Duration::from_secs(5))
+        .accept_invalid_certs(true)
+        .connect("https://127.0.0.1:9990")
+        .await
+        .unwrap();
+
+    // Create a mailbox and make sure both IMAP sessions know about it
+    imap.send("CREATE Gorgonzola").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_check.send("LIST \"\" \"Gorgonzola\"").await;
+    imap_check
+        .assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("LIST () \"/\" \"Gorgonzola\"");
+    let mailbox_id = client
+        .mailbox_query(
+            mailbox::query::Filter::name("Gorgonzola").into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+
+    // Subscriptions made over JMAP should be pushed to IDLE sessions
+    imap_check.send("IDLE").await;
+    imap_check
+        .assert_read(Type::Continuation, ResponseType::Ok)
+        .await;
+    let start = Instant::now();
+    client.mailbox_subscribe(&mailbox_id, true).await.unwrap();
+    imap_check
+        .assert_read(Type::Status, ResponseType::Ok)
+        .await
+        .assert_contains("LIST (\\Subscribed) \"/\" \"Gorgonzola\"");
+    assert!(
+        start.elapsed() < Duration::from_secs(2),
+        "JMAP subscription took {:?} to reach IMAP",
+        start.elapsed()
+    );
+    imap.send("LIST (SUBSCRIBED) \"\" \"Gorgonzola\"").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("LIST (\\Subscribed) \"/\" \"Gorgonzola\"");
+
+    // Subscriptions made over IMAP should be visible to JMAP and other IMAP sessions
+    let start = Instant::now();
+    imap.send("UNSUBSCRIBE Gorgonzola").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_check
+        .assert_read(Type::Status, ResponseType::Ok)
+        .await
+        .assert_contains("LIST () \"/\" \"Gorgonzola\"");
+    assert!(
+        start.elapsed() < Duration::from_secs(2),
+        "IMAP subscription took {:?} to reach other IMAP sessions",
+        start.elapsed()
+    );
+    assert!(!client
+        .mailbox_get(&mailbox_id, [mailbox::Property::IsSubscribed].into())
+        .await
+        .unwrap()
+        .unwrap()
+        .is_subscribed());
+
+    imap.send("SUBSCRIBE Gorgonzola").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_check
+        .assert_read(Type::Status, ResponseType::Ok)
+        .await
+        .assert_contains("LIST (\\Subscribed) \"/\" \"Gorgonzola\"");
+    assert!(client
+        .mailbox_get(&mailbox_id, [mailbox::Property::IsSubscribed].into())
+        .await
+        .unwrap()
+        .unwrap()
+        .is_subscribed());
+
+    // Stop IDLE mode
+    imap_check.send_raw("DONE").await;
+    imap_check.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    // Cleanup
+    imap.send("DELETE Gorgonzola").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+}
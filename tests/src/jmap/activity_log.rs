@@ -0,0 +1,158 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::mailbox::INBOX_ID;
+use jmap_client::mailbox::Role;
+use jmap_proto::{method::activity::ActivityEvent, types::id::Id};
+
+use crate::jmap::{
+    assert_is_empty, jmap_json_request, mailbox::destroy_all_mailboxes, test_account_login,
+};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running activity log tests...");
+    let server = params.server.clone();
+    params
+        .directory
+        .create_test_user_with_email("audit@example.com", "secret", "Audit Test")
+        .await;
+    params
+        .directory
+        .create_test_user_with_email("auditor@example.com", "secret", "Auditor Test")
+        .await;
+    let account_id = Id::from(
+        server
+            .store
+            .get_or_create_account_id("audit@example.com")
+            .await
+            .unwrap(),
+    );
+
+    // Logging in should be recorded
+    let mut client = test_account_login("audit@example.com", "secret").await;
+    client.set_default_account_id(account_id.to_string());
+
+    // Move and delete a message
+    let inbox_id = Id::new(INBOX_ID as u64).to_string();
+    let mailbox_id = client
+        .mailbox_create("Audited", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let email_id = client
+        .email_import(
+            concat!(
+                "From: bill@example.com\r\n",
+                "To: audit@example.com\r\n",
+                "Subject: Activity log test\r\n",
+                "\r\n",
+                "Move me around."
+            )
+            .as_bytes()
+            .to_vec(),
+            [&inbox_id],
+            None::<Vec<String>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    client
+        .email_set_mailboxes(&email_id, [&mailbox_id])
+        .await
+        .unwrap();
+    client.email_destroy(&email_id).await.unwrap();
+
+    // Query the activity log as the account owner
+    let response = jmap_json_request(
+        r#"[[ "ActivityLog/get", {
+            "accountId": "$$",
+            "limit": 10
+          }, "0" ]]"#
+            .replace("$$", &account_id.to_string()),
+        "audit@example.com",
+        "secret",
+    )
+    .await;
+    let list = response
+        .pointer("/methodResponses/0/1/list")
+        .and_then(|list| list.as_array())
+        .unwrap_or_else(|| panic!("Unexpected response: {response}"));
+    let events = list
+        .iter()
+        .map(|entry| entry.get("event").unwrap().as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(events, ["emailDelete", "emailMove", "login"], "{response}");
+    for entry in list {
+        assert_eq!(entry.get("protocol").unwrap().as_str(), Some("jmap"));
+        assert_eq!(entry.get("remoteIp").unwrap().as_str(), Some("127.0.0.1"));
+    }
+
+    // Other accounts may not read the activity log
+    let response = jmap_json_request(
+        r#"[[ "ActivityLog/get", {
+            "accountId": "$$"
+          }, "0" ]]"#
+            .replace("$$", &account_id.to_string()),
+        "auditor@example.com",
+        "secret",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/type")
+            .and_then(|v| v.as_str()),
+        Some("forbidden"),
+        "{response}"
+    );
+
+    // Entries older than the requested date are not returned
+    let entries = server
+        .activity_log_query(account_id.document_id(), u32::MAX as u64, 0)
+        .await
+        .unwrap();
+    assert!(entries.is_empty(), "{entries:?}");
+    let entries = server
+        .activity_log_query(account_id.document_id(), 0, 1)
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].event, ActivityEvent::EmailDelete);
+
+    // Remove test data
+    let auditor_id = server
+        .store
+        .get_or_create_account_id("auditor@example.com")
+        .await
+        .unwrap();
+    assert!(!server
+        .activity_log_query(auditor_id, 0, 0)
+        .await
+        .unwrap()
+        .is_empty());
+    params.client.set_default_account_id(account_id.to_string());
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
@@ -0,0 +1,228 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::{
+    object::Object,
+    types::{collection::Collection, property::Property},
+};
+
+use crate::jmap::{
+    assert_is_empty, fixture::Fixture, jmap_json_request, mailbox::destroy_all_mailboxes,
+};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running annotation tests...");
+    let server = params.server.clone();
+    let seeded = Fixture::new()
+        .domain("example.com", |domain| {
+            domain.account("annotations", "secret", "Annotation Test", |account| {
+                account
+                    .message(
+                        "Inbox",
+                        concat!(
+                            "From: bill@example.com\r\n",
+                            "To: annotations@example.com\r\n",
+                            "Subject: Contract renewal\r\n",
+                            "\r\n",
+                            "Please review the attached contract.\r\n"
+                        ),
+                    )
+                    .message(
+                        "Inbox",
+                        concat!(
+                            "From: jane@example.com\r\n",
+                            "To: annotations@example.com\r\n",
+                            "Subject: Lunch\r\n",
+                            "\r\n",
+                            "Lunch on Friday?\r\n"
+                        ),
+                    )
+            })
+        })
+        .seed(params)
+        .await;
+    let account = seeded.account("annotations@example.com");
+    let account_id = account.id;
+    let (contract_id, lunch_id) = (account.messages[0], account.messages[1]);
+
+    // Create annotations, only one annotation is allowed per message
+    let response = jmap_json_request(
+        r##"[[ "Annotation/set", {
+            "accountId": "$$",
+            "create": {
+                "a": { "emailId": "%%", "text": "Call back", "color": "#FF0000",
+                       "remindAt": "2026-01-05T09:00:00Z" },
+                "b": { "emailId": "%%", "text": "Duplicate" },
+                "c": { "emailId": "&&", "text": "@@" },
+                "d": { "text": "No message" },
+                "e": { "emailId": "&&", "color": "red" }
+            }
+          }, "0" ]]"##
+            .replace("$$", &account_id.to_string())
+            .replace("%%", &contract_id.to_string())
+            .replace("&&", &lunch_id.to_string())
+            .replace("@@", &"x".repeat(5000)),
+        "annotations@example.com",
+        "secret",
+    )
+    .await;
+    let annotation_id = response
+        .pointer("/methodResponses/0/1/created/a/id")
+        .and_then(|v| v.as_str())
+        .unwrap_or_else(|| panic!("Unexpected response: {response}"))
+        .to_string();
+    for (id, error) in [
+        ("b", "alreadyExists"),
+        ("c", "tooLarge"),
+        ("d", "invalidProperties"),
+        ("e", "invalidProperties"),
+    ] {
+        assert_eq!(
+            response
+                .pointer(&format!("/methodResponses/0/1/notCreated/{id}/type"))
+                .and_then(|v| v.as_str()),
+            Some(error),
+            "{response}"
+        );
+    }
+
+    // The message is immutable, other properties can be updated or cleared
+    let response = jmap_json_request(
+        r##"[[ "Annotation/set", {
+            "accountId": "$$",
+            "update": {
+                "!!": { "color": null, "text": "Call back before Friday" }
+            }
+          }, "0" ], [ "Annotation/set", {
+            "accountId": "$$",
+            "update": {
+                "!!": { "emailId": "&&" }
+            }
+          }, "1" ], [ "Annotation/get", {
+            "accountId": "$$",
+            "ids": ["!!"]
+          }, "2" ]]"##
+            .replace("$$", &account_id.to_string())
+            .replace("!!", &annotation_id)
+            .replace("&&", &lunch_id.to_string()),
+        "annotations@example.com",
+        "secret",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer(&format!(
+                "/methodResponses/1/1/notUpdated/{annotation_id}/type"
+            ))
+            .and_then(|v| v.as_str()),
+        Some("invalidProperties"),
+        "{response}"
+    );
+    let annotation = response
+        .pointer("/methodResponses/2/1/list/0")
+        .unwrap_or_else(|| panic!("Unexpected response: {response}"));
+    assert_eq!(
+        annotation.get("emailId").unwrap().as_str(),
+        Some(contract_id.to_string().as_str())
+    );
+    assert_eq!(
+        annotation.get("text").unwrap().as_str(),
+        Some("Call back before Friday")
+    );
+    assert_eq!(
+        annotation.get("remindAt").unwrap().as_str(),
+        Some("2026-01-05T09:00:00Z")
+    );
+    assert!(annotation.get("color").unwrap().is_null(), "{annotation}");
+
+    // Annotations are removed together with their message
+    let response = jmap_json_request(
+        r##"[[ "Email/set", {
+            "accountId": "$$",
+            "destroy": ["%%"]
+          }, "0" ], [ "Annotation/get", {
+            "accountId": "$$",
+            "ids": ["!!"]
+          }, "1" ]]"##
+            .replace("$$", &account_id.to_string())
+            .replace("%%", &contract_id.to_string())
+            .replace("!!", &annotation_id),
+        "annotations@example.com",
+        "secret",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/1/1/notFound/0")
+            .and_then(|v| v.as_str()),
+        Some(annotation_id.as_str()),
+        "{response}"
+    );
+    assert!(server
+        .annotation_fetch(account_id.document_id(), contract_id.document_id())
+        .await
+        .unwrap()
+        .is_none());
+
+    // Clearing every field removes the annotation
+    server
+        .annotation_store(
+            account_id.document_id(),
+            lunch_id.document_id(),
+            Object::with_capacity(2)
+                .with_property(Property::Text, "Bring the slides")
+                .with_property(Property::Color, "#00AA00"),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        server
+            .get_document_ids(account_id.document_id(), Collection::Annotation)
+            .await
+            .unwrap()
+            .map(|ids| ids.len()),
+        Some(1)
+    );
+    server
+        .annotation_store(
+            account_id.document_id(),
+            lunch_id.document_id(),
+            Default::default(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(server
+        .annotation_fetch(account_id.document_id(), lunch_id.document_id())
+        .await
+        .unwrap()
+        .is_none());
+
+    // Remove test data
+    params.client.set_default_account_id(account_id.to_string());
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
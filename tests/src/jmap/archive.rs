@@ -0,0 +1,211 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::mailbox::INBOX_ID;
+use jmap_proto::types::{collection::Collection, id::Id, property::Property};
+
+use crate::jmap::{
+    assert_is_empty, jmap_json_request, mailbox::destroy_all_mailboxes, test_account_login,
+};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running archive policy tests...");
+    let server = params.server.clone();
+    params
+        .directory
+        .create_test_user_with_email("archive@example.com", "secret", "Archive Test")
+        .await;
+    let account_id = Id::from(
+        server
+            .store
+            .get_or_create_account_id("archive@example.com")
+            .await
+            .unwrap(),
+    );
+    let mut client = test_account_login("archive@example.com", "secret").await;
+    client.set_default_account_id(account_id.to_string());
+
+    // Import two old messages and a recent one
+    let inbox_id = Id::new(INBOX_ID as u64).to_string();
+    for (num, received_at) in [
+        Some(1592222400i64),
+        Some(1614556800i64),
+        Some(1614643200i64),
+        None,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        client
+            .email_import(
+                format!(
+                    concat!(
+                        "From: bill@example.com\r\n",
+                        "To: archive@example.com\r\n",
+                        "Subject: Archive test {}\r\n",
+                        "\r\n",
+                        "Archive me."
+                    ),
+                    num
+                )
+                .into_bytes(),
+                [&inbox_id],
+                None::<Vec<String>>,
+                received_at,
+            )
+            .await
+            .unwrap();
+    }
+
+    // Create a policy for the Inbox, invalid and duplicate policies are rejected
+    let response = jmap_json_request(
+        r#"[[ "ArchivePolicy/set", {
+            "accountId": "$$",
+            "create": {
+                "a": { "mailboxId": "%%", "olderThanDays": 30 },
+                "b": { "mailboxId": "%%", "olderThanDays": 60 },
+                "c": { "mailboxId": "%%", "olderThanDays": 0 },
+                "d": { "olderThanDays": 30 },
+                "e": { "mailboxId": "zzzzzz", "olderThanDays": 30 }
+            }
+          }, "0" ]]"#
+            .replace("$$", &account_id.to_string())
+            .replace("%%", &inbox_id),
+        "archive@example.com",
+        "secret",
+    )
+    .await;
+    let policy_id = response
+        .pointer("/methodResponses/0/1/created/a/id")
+        .and_then(|v| v.as_str())
+        .unwrap_or_else(|| panic!("Unexpected response: {response}"))
+        .to_string();
+    for (id, error) in [
+        ("b", "alreadyExists"),
+        ("c", "invalidProperties"),
+        ("d", "invalidProperties"),
+        ("e", "invalidProperties"),
+    ] {
+        assert_eq!(
+            response
+                .pointer(&format!("/methodResponses/0/1/notCreated/{id}/type"))
+                .and_then(|v| v.as_str()),
+            Some(error),
+            "{response}"
+        );
+    }
+
+    // Run the archiver, old messages are filed by the year they were received
+    let document_id = account_id.document_id();
+    assert_eq!(server.archive_account(document_id).await.unwrap(), 3);
+    for (mailbox, count) in [("Archive/2020", 1), ("Archive/2021", 2)] {
+        let mailbox_id = server
+            .mailbox_get_by_name(document_id, mailbox)
+            .await
+            .unwrap()
+            .unwrap_or_else(|| panic!("Mailbox {mailbox} was not created"));
+        assert_eq!(
+            server
+                .get_tag(
+                    document_id,
+                    Collection::Email,
+                    Property::MailboxIds,
+                    mailbox_id
+                )
+                .await
+                .unwrap()
+                .map_or(0, |ids| ids.len()),
+            count,
+            "for mailbox {mailbox}"
+        );
+    }
+    assert_eq!(
+        server
+            .get_tag(
+                document_id,
+                Collection::Email,
+                Property::MailboxIds,
+                INBOX_ID
+            )
+            .await
+            .unwrap()
+            .map_or(0, |ids| ids.len()),
+        1
+    );
+
+    // Running it again is a no-op
+    assert_eq!(server.archive_account(document_id).await.unwrap(), 0);
+
+    // Disabled policies are skipped
+    let response = jmap_json_request(
+        r#"[[ "ArchivePolicy/set", {
+            "accountId": "$$",
+            "update": {
+                "%%": { "isEnabled": false, "mailboxName": "Old Mail" }
+            }
+          }, "0" ], [ "ArchivePolicy/get", {
+            "accountId": "$$",
+            "ids": ["%%"]
+          }, "1" ]]"#
+            .replace("$$", &account_id.to_string())
+            .replace("%%", &policy_id),
+        "archive@example.com",
+        "secret",
+    )
+    .await;
+    let policy = response
+        .pointer("/methodResponses/1/1/list/0")
+        .unwrap_or_else(|| panic!("Unexpected response: {response}"));
+    assert_eq!(policy.get("isEnabled").unwrap().as_bool(), Some(false));
+    assert_eq!(
+        policy.get("mailboxName").unwrap().as_str(),
+        Some("Old Mail")
+    );
+    assert_eq!(policy.get("olderThanDays").unwrap().as_u64(), Some(30));
+
+    // Remove test data
+    let response = jmap_json_request(
+        r#"[[ "ArchivePolicy/set", {
+            "accountId": "$$",
+            "destroy": ["%%"]
+          }, "0" ]]"#
+            .replace("$$", &account_id.to_string())
+            .replace("%%", &policy_id),
+        "archive@example.com",
+        "secret",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/destroyed")
+            .and_then(|v| v.as_array())
+            .map(|v| v.len()),
+        Some(1),
+        "{response}"
+    );
+    params.client.set_default_account_id(account_id.to_string());
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
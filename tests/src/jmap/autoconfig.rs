@@ -0,0 +1,166 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use crate::jmap::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running Autoconfig tests...");
+
+    // Create test account
+    params
+        .directory
+        .create_test_user("autoconfig", "12345", "Auto Config")
+        .await;
+    params
+        .directory
+        .link_test_address("autoconfig", "autoconfig@example.org", "primary")
+        .await;
+
+    // Mozilla autoconfig
+    for url in [
+        "https://127.0.0.1:8899/.well-known/autoconfig/mail/config-v1.1.xml",
+        "https://127.0.0.1:8899/mail/config-v1.1.xml",
+    ] {
+        let response = reqwest::Client::builder()
+            .timeout(Duration::from_millis(500))
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap_or_default()
+            .get(format!("{url}?emailaddress=autoconfig@example.org"))
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+
+        for expected in [
+            "<emailProvider id=\"example.org\">",
+            "<incomingServer type=\"imap\">",
+            "<hostname>jmap.example.org</hostname>",
+            "<port>9991</port>",
+            "<socketType>STARTTLS</socketType>",
+            "<username>%EMAILADDRESS%</username>",
+        ] {
+            assert!(
+                response.contains(expected),
+                "{expected} not found in {response}"
+            );
+        }
+        assert!(!response.contains("<port>11200</port>"), "{response}");
+        assert!(
+            !response.contains("<username>autoconfig</username>"),
+            "{response}"
+        );
+    }
+
+    // Microsoft autodiscover
+    let response = reqwest::Client::builder()
+        .timeout(Duration::from_millis(500))
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap_or_default()
+        .post("https://127.0.0.1:8899/autodiscover/autodiscover.xml")
+        .body(concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>",
+            "<Autodiscover xmlns=\"http://schemas.microsoft.com/exchange/autodiscover/outlook/requestschema/2006\">",
+            "<Request><EMailAddress>autoconfig@example.org</EMailAddress>",
+            "<AcceptableResponseSchema>http://schemas.microsoft.com/exchange/autodiscover/outlook/responseschema/2006a</AcceptableResponseSchema>",
+            "</Request></Autodiscover>"
+        ))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    for expected in [
+        "<Type>IMAP</Type>",
+        "<Server>jmap.example.org</Server>",
+        "<Port>9991</Port>",
+        "<LoginName>autoconfig@example.org</LoginName>",
+        "<Encryption>TLS</Encryption>",
+    ] {
+        assert!(
+            response.contains(expected),
+            "{expected} not found in {response}"
+        );
+    }
+
+    // Invalid e-mail addresses should be rejected
+    assert_eq!(
+        reqwest::Client::builder()
+            .timeout(Duration::from_millis(500))
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap_or_default()
+            .get("https://127.0.0.1:8899/mail/config-v1.1.xml?emailaddress=invalid")
+            .send()
+            .await
+            .unwrap()
+            .status()
+            .as_u16(),
+        400
+    );
+
+    // Endpoint limits should be advertised using rate limit headers
+    for (pos, expected_remaining) in [3, 2, 1, 0, 0].into_iter().enumerate() {
+        let response = reqwest::Client::builder()
+            .timeout(Duration::from_millis(500))
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap_or_default()
+            .get("https://127.0.0.1:8899/mail/config-v1.1.xml?emailaddress=invalid")
+            .send()
+            .await
+            .unwrap();
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or_else(|| panic!("Missing header {name}: {:?}", response.headers()))
+        };
+        assert_eq!(header("ratelimit-limit"), 8);
+        assert_eq!(header("ratelimit-remaining"), expected_remaining);
+        assert!(header("ratelimit-reset") <= 60);
+
+        if pos == 4 {
+            assert_eq!(response.status().as_u16(), 429);
+
+            // Requests exceeding the limit should include retry guidance
+            let retry_after = header("retry-after");
+            assert!(retry_after <= 60);
+            let body = response.text().await.unwrap();
+            assert!(
+                body.contains(&format!("\"retryAfter\":{retry_after}")),
+                "{body}"
+            );
+        } else {
+            assert_eq!(response.status().as_u16(), 400);
+        }
+    }
+}
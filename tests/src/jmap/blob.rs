@@ -21,29 +21,27 @@
  * for more details.
 */
 
-use directory::backend::internal::manage::ManageDirectory;
 use jmap::mailbox::INBOX_ID;
 use jmap_proto::types::id::Id;
 use serde_json::Value;
 
-use crate::jmap::{assert_is_empty, jmap_json_request, mailbox::destroy_all_mailboxes};
+use crate::jmap::{
+    assert_is_empty, fixture::Fixture, jmap_json_request, mailbox::destroy_all_mailboxes,
+};
 
 use super::JMAPTest;
 
 pub async fn test(params: &mut JMAPTest) {
     println!("Running blob tests...");
     let server = params.server.clone();
-    params
-        .directory
-        .create_test_user_with_email("jdoe@example.com", "12345", "John Doe")
-        .await;
-    let account_id = Id::from(
-        server
-            .store
-            .get_or_create_account_id("jdoe@example.com")
-            .await
-            .unwrap(),
-    );
+    let account_id = Fixture::new()
+        .domain("example.com", |domain| {
+            domain.account("jdoe", "12345", "John Doe", |account| account)
+        })
+        .seed(params)
+        .await
+        .account("jdoe@example.com")
+        .id;
 
     server.store.blob_expire_all().await;
 
@@ -195,6 +193,50 @@ pub async fn test(params: &mut JMAPTest) {
         );
     }
 
+    // Download byte ranges
+    for (range, expected_status, expected_range, expected_body) in [
+        (
+            None,
+            200,
+            None,
+            "The quick brown fox jumped over the lazy dog.",
+        ),
+        (Some("bytes=4-12"), 206, Some("bytes 4-12/45"), "quick bro"),
+        (Some("bytes=40-"), 206, Some("bytes 40-44/45"), " dog."),
+        (Some("bytes=-4"), 206, Some("bytes 41-44/45"), "dog."),
+        (Some("bytes=45-50"), 416, Some("bytes */45"), ""),
+    ] {
+        let mut request = reqwest::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap()
+            .get(format!(
+                "https://127.0.0.1:8899/jmap/download/{}/{}/fox.txt",
+                account_id, blob_id
+            ))
+            .basic_auth("jdoe@example.com", Some("12345"));
+        if let Some(range) = range {
+            request = request.header(reqwest::header::RANGE, range);
+        }
+        let response = request.send().await.unwrap();
+        assert_eq!(response.status().as_u16(), expected_status, "{range:?}");
+        assert_eq!(
+            response
+                .headers()
+                .get(reqwest::header::CONTENT_RANGE)
+                .and_then(|h| h.to_str().ok()),
+            expected_range,
+            "{range:?}"
+        );
+        if expected_status != 416 {
+            assert_eq!(
+                String::from_utf8(response.bytes().await.unwrap().to_vec()).unwrap(),
+                expected_body,
+                "{range:?}"
+            );
+        }
+    }
+
     server.store.blob_expire_all().await;
 
     // Blob/upload Complex Example
@@ -493,6 +535,156 @@ pub async fn test(params: &mut JMAPTest) {
         );
     }
 
+    // Email/set with a patched body value
+    let draft_blob_id = jmap_json_request(
+        r#"[[
+            "Blob/upload",
+            {
+             "accountId": "$$",
+             "create": {
+              "draft": {
+               "data" : [
+               {
+                "data:asText": "<p>Hello world</p>"
+               }
+              ]
+              }
+             }
+            },
+            "R1"
+           ]]"#
+        .replace("$$", &account_id.to_string()),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await
+    .pointer("/methodResponses/0/1/created/draft/id")
+    .and_then(|v| v.as_str())
+    .unwrap()
+    .to_string();
+    let response = jmap_json_request(
+        r##"[[
+            "Email/set",
+            {
+             "accountId": "$$",
+             "create": {
+              "e1": {
+               "mailboxIds": {
+                "&&": true
+               },
+               "subject": "Draft",
+               "htmlBody": [
+                {
+                 "partId": "1",
+                 "type": "text/html"
+                }
+               ],
+               "bodyValues": {
+                "1": {
+                 "blobId": "%%",
+                 "patches": [
+                  {
+                   "offset": 9,
+                   "length": 5,
+                   "value": "there"
+                  },
+                  {
+                   "offset": 18,
+                   "value": "<p>Bye</p>"
+                  }
+                 ]
+                }
+               }
+              }
+             }
+            },
+            "R1"
+           ],
+           [
+            "Email/get",
+            {
+             "accountId": "$$",
+             "ids": [
+              "#e1"
+             ],
+             "properties": [
+              "bodyValues"
+             ],
+             "fetchHTMLBodyValues": true
+            },
+            "R2"
+           ]]"##
+            .replace("$$", &account_id.to_string())
+            .replace("&&", &Id::from(INBOX_ID).to_string())
+            .replace("%%", &draft_blob_id),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/1/1/list/0/bodyValues/1/value")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default(),
+        "<p>Hello there</p><p>Bye</p>",
+        "Response: {response:#?}",
+    );
+
+    // Overlapping patches are rejected
+    let response = jmap_json_request(
+        r#"[[
+            "Email/set",
+            {
+             "accountId": "$$",
+             "create": {
+              "e2": {
+               "mailboxIds": {
+                "&&": true
+               },
+               "htmlBody": [
+                {
+                 "partId": "1",
+                 "type": "text/html"
+                }
+               ],
+               "bodyValues": {
+                "1": {
+                 "blobId": "%%",
+                 "patches": [
+                  {
+                   "offset": 3,
+                   "length": 6,
+                   "value": "x"
+                  },
+                  {
+                   "offset": 5,
+                   "length": 1,
+                   "value": "y"
+                  }
+                 ]
+                }
+               }
+              }
+             }
+            },
+            "R1"
+           ]]"#
+        .replace("$$", &account_id.to_string())
+        .replace("&&", &Id::from(INBOX_ID).to_string())
+        .replace("%%", &blob_id),
+        "jdoe@example.com",
+        "12345",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/notCreated/e2/type")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default(),
+        "invalidProperties",
+        "Response: {response:#?}",
+    );
+
     // Remove test data
     params.client.set_default_account_id(account_id.to_string());
     destroy_all_mailboxes(params).await;
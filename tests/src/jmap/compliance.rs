@@ -0,0 +1,246 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use reqwest::Method;
+use serde_json::{json, Value};
+
+use crate::jmap::{
+    assert_is_empty, fixture::Fixture, jmap_json_request, mailbox::destroy_all_mailboxes,
+};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running legal hold and redaction tests...");
+    let server = params.server.clone();
+    let seeded = Fixture::new()
+        .domain("example.com", |domain| {
+            domain.account("custodian", "secret", "Custodian", |account| {
+                account
+                    .message(
+                        "Inbox",
+                        concat!(
+                            "From: bill@example.com\r\n",
+                            "To: custodian@example.com\r\n",
+                            "Message-ID: <hold@example.com>\r\n",
+                            "Subject: Quarterly figures\r\n",
+                            "\r\n",
+                            "Please keep these figures confidential.\r\n"
+                        ),
+                    )
+                    .message(
+                        "Inbox",
+                        concat!(
+                            "From: bill@example.com\r\n",
+                            "To: custodian@example.com\r\n",
+                            "Message-ID: <redact@example.com>\r\n",
+                            "Subject: Personal details\r\n",
+                            "\r\n",
+                            "My social security number is 123-45-6789.\r\n"
+                        ),
+                    )
+            })
+        })
+        .seed(params)
+        .await;
+    let account = seeded.account("custodian@example.com");
+    let account_id = account.id;
+    let held_id = account.messages[0].to_string();
+    let redact_id = account.messages[1].to_string();
+    let admin = Some(("admin", "secret"));
+    let user = Some(("custodian@example.com", "secret"));
+
+    // Only compliance officers can manage holds
+    let (status, _) = compliance_request(
+        Method::POST,
+        "/hold/custodian@example.com",
+        user,
+        Some(json!({"reason": "Case 42"})),
+    )
+    .await;
+    assert_eq!(status, 403);
+    let (status, _) = compliance_request(
+        Method::POST,
+        "/hold/custodian@example.com",
+        admin,
+        Some(json!({})),
+    )
+    .await;
+    assert_eq!(status, 400);
+
+    // Held messages cannot be destroyed
+    let (status, response) = compliance_request(
+        Method::POST,
+        "/hold/custodian@example.com",
+        admin,
+        Some(json!({"reason": "Case 42", "messages": [held_id]})),
+    )
+    .await;
+    assert_eq!(status, 200, "{response}");
+    assert_eq!(response["data"]["messages"][0]["id"], held_id);
+    assert_eq!(response["data"]["messages"][0]["hold"]["placedBy"], "admin");
+    let response = jmap_json_request(
+        r#"[[ "Email/set", {
+            "accountId": "$$",
+            "destroy": ["%%"]
+          }, "0" ]]"#
+            .replace("$$", &account_id.to_string())
+            .replace("%%", &held_id),
+        "custodian@example.com",
+        "secret",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer(&format!("/methodResponses/0/1/notDestroyed/{held_id}/type"))
+            .and_then(|v| v.as_str()),
+        Some("forbidden"),
+        "{response}"
+    );
+
+    // Accounts under hold cannot be deleted
+    let (status, _) = compliance_request(
+        Method::POST,
+        "/hold/custodian@example.com",
+        admin,
+        Some(json!({"reason": "Case 43"})),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let (status, _) = admin_request(Method::DELETE, "/principal/custodian@example.com").await;
+    assert_eq!(status, 403);
+    let (status, response) =
+        compliance_request(Method::DELETE, "/hold/custodian@example.com", admin, None).await;
+    assert_eq!(status, 200, "{response}");
+    assert_eq!(response["data"]["account"], Value::Null);
+
+    // Redact a message, keeping its audit metadata
+    let (status, _) = compliance_request(
+        Method::POST,
+        &format!("/redact/custodian@example.com/{redact_id}"),
+        user,
+        Some(json!({"reason": "Court order 7"})),
+    )
+    .await;
+    assert_eq!(status, 403);
+    let (status, response) = compliance_request(
+        Method::POST,
+        &format!("/redact/custodian@example.com/{redact_id}"),
+        admin,
+        Some(json!({"reason": "Court order 7"})),
+    )
+    .await;
+    assert_eq!(status, 200, "{response}");
+    assert_eq!(response["data"]["messageId"], "redact@example.com");
+    assert_eq!(response["data"]["reason"], "Court order 7");
+    let response = jmap_json_request(
+        r#"[[ "Email/get", {
+            "accountId": "$$",
+            "ids": ["%%"],
+            "properties": ["subject", "preview", "messageId", "from"]
+          }, "0" ]]"#
+            .replace("$$", &account_id.to_string())
+            .replace("%%", &redact_id),
+        "custodian@example.com",
+        "secret",
+    )
+    .await;
+    let email = response
+        .pointer("/methodResponses/0/1/list/0")
+        .unwrap_or_else(|| panic!("Unexpected response: {response}"));
+    assert_eq!(email["subject"], "[Redacted]");
+    assert_eq!(email["preview"], "This message has been redacted.");
+    assert_eq!(email["messageId"], json!(["redact@example.com"]));
+    assert_eq!(email["from"][0]["email"], "bill@example.com");
+    let (status, response) =
+        compliance_request(Method::GET, "/redact/custodian@example.com", admin, None).await;
+    assert_eq!(status, 200);
+    assert_eq!(response["data"].as_array().map(|r| r.len()), Some(1));
+
+    // Release the remaining hold and remove test data
+    let (status, response) = compliance_request(
+        Method::DELETE,
+        "/hold/custodian@example.com",
+        admin,
+        Some(json!({"messages": [held_id]})),
+    )
+    .await;
+    assert_eq!(status, 200, "{response}");
+    assert_eq!(response["data"]["messages"], json!([]));
+    params.client.set_default_account_id(account_id.to_string());
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
+
+async fn compliance_request(
+    method: Method,
+    path: &str,
+    auth: Option<(&str, &str)>,
+    body: Option<Value>,
+) -> (u16, Value) {
+    let mut request = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_millis(1000))
+        .build()
+        .unwrap()
+        .request(
+            method,
+            format!("https://127.0.0.1:8899/admin/compliance{path}"),
+        );
+    if let Some((username, secret)) = auth {
+        request = request.basic_auth(username, Some(secret));
+    }
+    if let Some(body) = body {
+        request = request.body(body.to_string());
+    }
+    let response = request.send().await.unwrap();
+    let status = response.status().as_u16();
+    let bytes = response.bytes().await.unwrap();
+
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+async fn admin_request(method: Method, path: &str) -> (u16, Value) {
+    let response = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_millis(1000))
+        .build()
+        .unwrap()
+        .request(method, format!("https://127.0.0.1:8899/admin{path}"))
+        .basic_auth("admin", Some("secret"))
+        .send()
+        .await
+        .unwrap();
+    let status = response.status().as_u16();
+    let bytes = response.bytes().await.unwrap();
+
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
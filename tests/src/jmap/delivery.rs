@@ -31,6 +31,7 @@ use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines, ReadHalf, WriteHalf},
     net::TcpStream,
 };
+use utils::ipc::{DeliveryResult, IngestMessage};
 
 use crate::jmap::{assert_is_empty, mailbox::destroy_all_mailboxes};
 
@@ -308,6 +309,51 @@ pub async fn test(params: &mut JMAPTest) {
         );
     }
 
+    // Messages addressed to multiple aliases of an account are ingested once
+    let message_path = params.temp_dir.path.join("fanout.eml");
+    let message = concat!(
+        "From: bill@example.com\r\n",
+        "Subject: TPS reports (fan-out)\r\n",
+        "\r\n",
+        "Did you get the memo about the TPS reports?"
+    );
+    std::fs::write(&message_path, message).unwrap();
+    for (dedup_enabled, expected_messages) in [(true, 5), (false, 7)] {
+        server
+            .set_delivery_dedup(john_id, dedup_enabled.into())
+            .await
+            .unwrap();
+        let result = server
+            .deliver_message(IngestMessage {
+                sender_address: "bill@example.com".to_string(),
+                recipients: vec![
+                    "jdoe@example.com".to_string(),
+                    "john.doe@example.com".to_string(),
+                ],
+                message_path: message_path.clone(),
+                message_size: message.len(),
+            })
+            .await;
+        assert!(
+            matches!(
+                result.as_slice(),
+                [DeliveryResult::Success, DeliveryResult::Success]
+            ),
+            "{result:?}"
+        );
+        assert_eq!(
+            server
+                .get_document_ids(john_id, Collection::Email)
+                .await
+                .unwrap()
+                .unwrap()
+                .len(),
+            expected_messages
+        );
+    }
+    server.set_delivery_dedup(john_id, None).await.unwrap();
+    std::fs::remove_file(&message_path).unwrap();
+
     // Remove test data
     for account_id in [&account_id_1, &account_id_2, &account_id_3] {
         params.client.set_default_account_id(account_id);
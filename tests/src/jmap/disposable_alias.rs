@@ -0,0 +1,220 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use jmap_proto::types::{collection::Collection, date::UTCDate, id::Id};
+use store::write::now;
+
+use crate::jmap::{
+    assert_is_empty, delivery::SmtpConnection, jmap_json_request, mailbox::destroy_all_mailboxes,
+};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running disposable alias tests...");
+    let server = params.server.clone();
+    params
+        .directory
+        .create_test_user_with_email("alias@example.com", "secret", "Alias Test")
+        .await;
+    let account_id = Id::from(
+        server
+            .store
+            .get_or_create_account_id("alias@example.com")
+            .await
+            .unwrap(),
+    );
+
+    // Create aliases, expiration dates in the past are rejected
+    let response = jmap_json_request(
+        r#"[[ "DisposableAlias/set", {
+            "accountId": "$$",
+            "create": {
+                "a": { "description": "Newsletters", "blockedSenders": ["spam.org", "Bad@Example.net"] },
+                "b": { "expires": "%%" },
+                "c": { "expires": "2000-01-01T00:00:00Z" },
+                "d": { "email": "me@example.com" }
+            }
+          }, "0" ]]"#
+            .replace("$$", &account_id.to_string())
+            .replace(
+                "%%",
+                &UTCDate::from_timestamp(now() as i64 + 2).to_string(),
+            ),
+        "alias@example.com",
+        "secret",
+    )
+    .await;
+    let mut aliases = Vec::new();
+    for id in ["a", "b"] {
+        let alias_id = response
+            .pointer(&format!("/methodResponses/0/1/created/{id}/id"))
+            .and_then(|v| v.as_str())
+            .unwrap_or_else(|| panic!("Unexpected response: {response}"))
+            .to_string();
+        let address = response
+            .pointer(&format!("/methodResponses/0/1/created/{id}/email"))
+            .and_then(|v| v.as_str())
+            .unwrap_or_else(|| panic!("Unexpected response: {response}"))
+            .to_string();
+        assert!(address.ends_with("@example.com"), "{address}");
+        aliases.push((alias_id, address));
+    }
+    for id in ["c", "d"] {
+        assert_eq!(
+            response
+                .pointer(&format!("/methodResponses/0/1/notCreated/{id}/type"))
+                .and_then(|v| v.as_str()),
+            Some("invalidProperties"),
+            "{response}"
+        );
+    }
+
+    // Messages to the alias are delivered to its owner unless the sender is blocked
+    let mut lmtp = SmtpConnection::connect().await;
+    lmtp.ingest(
+        "john@example.net",
+        &[&aliases[0].1],
+        concat!(
+            "From: john@example.net\r\n",
+            "Subject: Alias test\r\n",
+            "\r\n",
+            "Hello."
+        ),
+    )
+    .await;
+    assert_eq!(
+        server
+            .get_document_ids(account_id.document_id(), Collection::Email)
+            .await
+            .unwrap()
+            .map_or(0, |ids| ids.len()),
+        1
+    );
+    for sender in ["bad@example.net", "news@spam.org", "news@mail.spam.org"] {
+        lmtp.mail_from(sender, 2).await;
+        lmtp.rcpt_to(&aliases[0].1, 5).await;
+        lmtp.rset().await;
+    }
+
+    // Expired aliases are rejected
+    lmtp.mail_from("john@example.net", 2).await;
+    lmtp.rcpt_to(&aliases[1].1, 2).await;
+    lmtp.rset().await;
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    lmtp.mail_from("john@example.net", 2).await;
+    lmtp.rcpt_to(&aliases[1].1, 5).await;
+    lmtp.rset().await;
+    lmtp.quit().await;
+
+    // Statistics are kept for each alias
+    let response = jmap_json_request(
+        r#"[[ "DisposableAlias/get", {
+            "accountId": "$$",
+            "ids": ["%1", "%2"]
+          }, "0" ]]"#
+            .replace("$$", &account_id.to_string())
+            .replace("%1", &aliases[0].0)
+            .replace("%2", &aliases[1].0),
+        "alias@example.com",
+        "secret",
+    )
+    .await;
+    for (num, (accepted, blocked, expired)) in [(1, 3, 0), (1, 0, 1)].into_iter().enumerate() {
+        let alias = response
+            .pointer(&format!("/methodResponses/0/1/list/{num}"))
+            .unwrap_or_else(|| panic!("Unexpected response: {response}"));
+        assert_eq!(
+            alias.get("email").unwrap().as_str(),
+            Some(aliases[num].1.as_str())
+        );
+        assert_eq!(alias.get("acceptedCount").unwrap().as_u64(), Some(accepted));
+        assert_eq!(alias.get("blockedCount").unwrap().as_u64(), Some(blocked));
+        assert_eq!(alias.get("expiredCount").unwrap().as_u64(), Some(expired));
+    }
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/list/0/blockedSenders")
+            .and_then(|v| v.as_array())
+            .map(|v| v.iter().filter_map(|v| v.as_str()).collect::<Vec<_>>()),
+        Some(vec!["spam.org", "bad@example.net"]),
+        "{response}"
+    );
+
+    // Unblocking a sender takes effect immediately
+    let response = jmap_json_request(
+        r#"[[ "DisposableAlias/set", {
+            "accountId": "$$",
+            "update": {
+                "%%": { "blockedSenders": ["spam.org"] }
+            }
+          }, "0" ]]"#
+            .replace("$$", &account_id.to_string())
+            .replace("%%", &aliases[0].0),
+        "alias@example.com",
+        "secret",
+    )
+    .await;
+    assert!(
+        response
+            .pointer(&format!("/methodResponses/0/1/updated/{}", aliases[0].0))
+            .is_some(),
+        "{response}"
+    );
+    let mut lmtp = SmtpConnection::connect().await;
+    lmtp.mail_from("bad@example.net", 2).await;
+    lmtp.rcpt_to(&aliases[0].1, 2).await;
+    lmtp.rset().await;
+
+    // Destroyed aliases no longer accept messages
+    let response = jmap_json_request(
+        r#"[[ "DisposableAlias/set", {
+            "accountId": "$$",
+            "destroy": ["%1", "%2"]
+          }, "0" ]]"#
+            .replace("$$", &account_id.to_string())
+            .replace("%1", &aliases[0].0)
+            .replace("%2", &aliases[1].0),
+        "alias@example.com",
+        "secret",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/destroyed")
+            .and_then(|v| v.as_array())
+            .map(|v| v.len()),
+        Some(2),
+        "{response}"
+    );
+    lmtp.mail_from("john@example.net", 2).await;
+    lmtp.rcpt_to(&aliases[0].1, 5).await;
+    lmtp.quit().await;
+
+    // Remove test data
+    params.client.set_default_account_id(account_id.to_string());
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
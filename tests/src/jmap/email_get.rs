@@ -23,10 +23,11 @@
 
 use std::{fs, path::PathBuf};
 
-use jmap::mailbox::INBOX_ID;
+use jmap::{email::metadata::MessageMetadata, mailbox::INBOX_ID, Bincode};
 use jmap_client::email::{self, import::EmailImportResponse, Header, HeaderForm};
-use jmap_proto::types::id::Id;
+use jmap_proto::types::{collection::Collection, id::Id, property::Property};
 use mail_parser::HeaderName;
+use store::write::{BatchBuilder, F_VALUE};
 
 use crate::jmap::{assert_is_empty, mailbox::destroy_all_mailboxes, replace_blob_ids};
 
@@ -188,6 +189,79 @@ pub async fn test(params: &mut JMAPTest) {
         }
     }
 
+    // Previews missing from the stored metadata should be generated on demand
+    let email_id = params
+        .client
+        .email_import(
+            concat!(
+                "From: john@example.com\r\n",
+                "Subject: Lazy preview\r\n",
+                "Content-Type: text/html\r\n",
+                "\r\n",
+                "<html><body><p>Hello <b>world</b>!</p></body></html>"
+            )
+            .as_bytes()
+            .to_vec(),
+            [&mailbox_id],
+            None::<Vec<String>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    let document_id = Id::from_bytes(email_id.as_bytes()).unwrap().document_id();
+    let mut metadata = server
+        .get_property::<Bincode<MessageMetadata>>(
+            1,
+            Collection::Email,
+            document_id,
+            &Property::BodyStructure,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    let preview = std::mem::take(&mut metadata.inner.preview);
+    assert!(preview.contains("Hello world"), "{preview:?}");
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(1)
+        .with_collection(Collection::Email)
+        .update_document(document_id)
+        .value(Property::BodyStructure, &metadata, F_VALUE);
+    server.write_batch(batch).await.unwrap();
+
+    let mut request = params.client.build();
+    request
+        .get_email()
+        .ids([&email_id])
+        .properties([email::Property::Preview]);
+    assert_eq!(
+        request
+            .send_get_email()
+            .await
+            .unwrap()
+            .take_list()
+            .pop()
+            .unwrap()
+            .preview(),
+        Some(preview.as_str())
+    );
+    assert_eq!(
+        server
+            .get_property::<Bincode<MessageMetadata>>(
+                1,
+                Collection::Email,
+                document_id,
+                &Property::BodyStructure,
+            )
+            .await
+            .unwrap()
+            .unwrap()
+            .inner
+            .preview,
+        preview
+    );
+
     destroy_all_mailboxes(params).await;
     assert_is_empty(server).await;
 }
@@ -37,7 +37,7 @@ use mail_parser::HeaderName;
 
 use store::{ahash::AHashMap, write::BatchBuilder};
 
-use super::JMAPTest;
+use super::{jmap_json_request, JMAPTest};
 
 const MAX_THREADS: usize = 100;
 const MAX_MESSAGES: usize = 1000;
@@ -108,6 +108,9 @@ pub async fn test(params: &mut JMAPTest, insert: bool) {
     println!("Running JMAP Mail query options tests...");
     query_options(client).await;
 
+    println!("Running JMAP Mail header filter tests...");
+    query_headers(client).await;
+
     println!("Deleting all messages...");
     let mut request = client.build();
     let result_ref = request.query_email().result_reference();
@@ -385,6 +388,99 @@ pub async fn query(client: &mut Client) {
     }
 }
 
+pub async fn query_headers(client: &mut Client) {
+    let account_id = client.default_account_id().to_string();
+    for (filter, expected_results) in [
+        (
+            r#"{"header": ["Message-ID", "<t10965>", "regex"]}"#,
+            vec!["T10965"],
+        ),
+        (
+            r#"{"header": ["Message-ID", "<(P77623|T10965)>", "regex"]}"#,
+            vec!["P77623", "T10965"],
+        ),
+        (r#"{"header": ["Message-ID", "T10965", "regex"]}"#, vec![]),
+        (r#"{"header": ["X-Not-Present"]}"#, vec![]),
+        (r#"{"header": ["X-Not-Present", "value"]}"#, vec![]),
+    ] {
+        let response = jmap_json_request(
+            r##"[["Email/query", {"accountId": "$$", "filter": %%}, "c0"],
+                ["Email/get", {"accountId": "$$",
+                    "#ids": {"resultOf": "c0", "name": "Email/query", "path": "/ids"},
+                    "properties": ["messageId"]}, "c1"]]"##
+                .replace("$$", &account_id)
+                .replace("%%", filter),
+            "admin",
+            "secret",
+        )
+        .await;
+        let mut results = response
+            .pointer("/methodResponses/1/1/list")
+            .and_then(|v| v.as_array())
+            .unwrap_or_else(|| panic!("invalid response for {filter}: {response}"))
+            .iter()
+            .map(|e| {
+                e.pointer("/messageId/0")
+                    .and_then(|v| v.as_str())
+                    .unwrap()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        results.sort_unstable();
+        assert_eq!(results, expected_results, "filter: {filter}");
+    }
+
+    // Messages without the header are not matched when the header exists
+    let total = |response: serde_json::Value| {
+        response
+            .pointer("/methodResponses/0/1/total")
+            .and_then(|v| v.as_u64())
+            .unwrap()
+    };
+    let all = total(
+        jmap_json_request(
+            r#"[["Email/query", {"accountId": "$$", "calculateTotal": true}, "c0"]]"#
+                .replace("$$", &account_id),
+            "admin",
+            "secret",
+        )
+        .await,
+    );
+    let with_header = total(
+        jmap_json_request(
+            r#"[["Email/query", {"accountId": "$$", "calculateTotal": true,
+                "filter": {"header": ["Comments", ".*", "regex"]}}, "c0"]]"#
+                .replace("$$", &account_id),
+            "admin",
+            "secret",
+        )
+        .await,
+    );
+    assert_eq!(all, with_header);
+
+    // Invalid match modes and expressions are rejected
+    for filter in [
+        r#"["Comments", "value", "fuzzy"]"#,
+        r#"["Comments", "(unclosed", "regex"]"#,
+    ] {
+        let response = jmap_json_request(
+            r#"[["Email/query", {"accountId": "$$", "filter": {"header": %%}}, "c0"]]"#
+                .replace("$$", &account_id)
+                .replace("%%", filter),
+            "admin",
+            "secret",
+        )
+        .await;
+        assert_eq!(
+            response
+                .pointer("/methodResponses/0/1/type")
+                .and_then(|v| v.as_str()),
+            Some("invalidArguments"),
+            "filter: {filter}"
+        );
+    }
+}
+
 pub async fn query_options(client: &mut Client) {
     for (query, expected_results, expected_results_collapsed) in [
         (
@@ -25,7 +25,10 @@ use std::{fs, path::PathBuf};
 
 use crate::jmap::{assert_is_empty, mailbox::destroy_all_mailboxes, wait_for_index};
 use jmap::mailbox::INBOX_ID;
-use jmap_client::{core::query, email::query::Filter};
+use jmap_client::{
+    core::query,
+    email::{self, query::Filter},
+};
 use jmap_proto::types::id::Id;
 use store::ahash::AHashMap;
 
@@ -52,6 +55,7 @@ pub async fn test(params: &mut JMAPTest) {
         "mixed",
         "text_plain",
         "text_plain_chinese",
+        "html_unsafe",
     ] {
         let mut file_name = test_dir.clone();
         file_name.push(format!("{}.eml", email_name));
@@ -182,6 +186,49 @@ pub async fn test(params: &mut JMAPTest) {
         );
     }
 
+    // Snippets and previews generated from unsafe HTML must not contain markup
+    let filter: query::Filter<Filter> = Filter::text("invoice").into();
+    let email_id = email_ids.get("html_unsafe").unwrap();
+    let mut request = params.client.build();
+    request
+        .get_search_snippet()
+        .filter(filter)
+        .email_ids([email_id]);
+    let response = request
+        .send()
+        .await
+        .unwrap()
+        .unwrap_method_responses()
+        .pop()
+        .unwrap()
+        .unwrap_get_search_snippet()
+        .unwrap();
+    let snippet = response.snippet(email_id).unwrap();
+    for text in [snippet.subject().unwrap(), snippet.preview().unwrap()] {
+        assert!(text.contains("<mark>"), "{text}");
+        let text = text.replace("<mark>", "").replace("</mark>", "");
+        assert!(!text.contains(['<', '>', '"', '\u{202e}']), "{text}");
+    }
+    let mut request = params.client.build();
+    request
+        .get_email()
+        .ids([email_id])
+        .properties([email::Property::Preview]);
+    let preview = request
+        .send_get_email()
+        .await
+        .unwrap()
+        .take_list()
+        .pop()
+        .unwrap()
+        .preview()
+        .unwrap()
+        .to_string();
+    assert!(
+        !preview.contains(['<', '>', '\u{202e}']) && preview.contains("invoice"),
+        "{preview}"
+    );
+
     // Destroy test data
     destroy_all_mailboxes(params).await;
     assert_is_empty(server).await;
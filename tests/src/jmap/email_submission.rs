@@ -25,8 +25,10 @@ use ahash::AHashMap;
 use directory::backend::internal::manage::ManageDirectory;
 use jmap_client::{
     core::set::{SetError, SetErrorType, SetObject},
+    email,
     email_submission::{query::Filter, Address, Delivered, DeliveryStatus, Displayed, UndoStatus},
-    mailbox::Role,
+    mailbox::{self, Role},
+    principal::ACL,
     Error,
 };
 use jmap_proto::types::id::Id;
@@ -44,7 +46,11 @@ use tokio::{
 };
 
 use crate::jmap::{
-    assert_is_empty, email_set::assert_email_properties, mailbox::destroy_all_mailboxes,
+    assert_is_empty,
+    email_set::assert_email_properties,
+    jmap_json_request,
+    mailbox::{destroy_all_mailboxes, destroy_all_mailboxes_no_wait},
+    test_account_login,
 };
 
 use super::JMAPTest;
@@ -240,6 +246,45 @@ pub async fn test(params: &mut JMAPTest) {
     )
     .await;
 
+    // Alias identities can use the primary address as envelope sender
+    let alias_identity_id = Id::from(1u64).to_string();
+    for (envelope_sender, expected) in [("other@example.com", false), ("primary", true)] {
+        let response = jmap_json_request(
+            r#"[[ "Identity/set", {
+                "accountId": "$$",
+                "update": {
+                    "%%": { "envelopeSender": "@@" }
+                }
+              }, "0" ]]"#
+                .replace("$$", &account_id)
+                .replace("%%", &alias_identity_id)
+                .replace("@@", envelope_sender),
+            "jdoe@example.com",
+            "12345",
+        )
+        .await;
+        assert_eq!(
+            response
+                .pointer(&format!("/methodResponses/0/1/updated/{alias_identity_id}"))
+                .is_some(),
+            expected,
+            "{response}"
+        );
+    }
+    client
+        .email_submission_create(&email_id, &alias_identity_id)
+        .await
+        .unwrap();
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new(
+            "<jdoe@example.com>",
+            ["<jane_smith@remote.org>"],
+            email_body,
+        ),
+    )
+    .await;
+
     // Manually add recipients to the envelope and confirm submission
     let email_submission_id = client
         .email_submission_create_envelope(
@@ -449,6 +494,83 @@ pub async fn test(params: &mut JMAPTest) {
         ),])
     );
 
+    // List the scheduled message as its sender and as an administrator
+    for credentials in [("jdoe@example.com", "12345"), ("admin", "secret")] {
+        let (status, scheduled) = scheduled_request(reqwest::Method::GET, "", credentials).await;
+        assert_eq!(status, 200);
+        let scheduled = scheduled["data"].as_array().unwrap();
+        assert_eq!(scheduled.len(), 1, "{scheduled:?}");
+        assert_eq!(scheduled[0]["return_path"], "jdoe@example.com");
+        assert_eq!(
+            scheduled[0]["recipients"],
+            serde_json::json!(["jane_smith@remote.org"])
+        );
+        assert_eq!(scheduled[0]["release"], "2079-11-20T05:00:00Z");
+    }
+    let queue_id = scheduled_request(reqwest::Method::GET, "", ("admin", "secret"))
+        .await
+        .1["data"][0]["id"]
+        .as_u64()
+        .unwrap();
+
+    // Reschedule the message
+    assert_eq!(
+        scheduled_request(
+            reqwest::Method::PATCH,
+            &format!("/{queue_id}?at=2079-12-24T18:00:00Z"),
+            ("jdoe@example.com", "12345"),
+        )
+        .await
+        .0,
+        200
+    );
+    assert_eq!(
+        scheduled_request(reqwest::Method::GET, "", ("jdoe@example.com", "12345"))
+            .await
+            .1["data"][0]["release"],
+        "2079-12-24T18:00:00Z"
+    );
+
+    // Invalid or unknown requests should fail
+    assert_eq!(
+        scheduled_request(
+            reqwest::Method::PATCH,
+            &format!("/{queue_id}?at=yesterday"),
+            ("jdoe@example.com", "12345"),
+        )
+        .await
+        .0,
+        400
+    );
+    assert_eq!(
+        scheduled_request(
+            reqwest::Method::DELETE,
+            "/12345",
+            ("jdoe@example.com", "12345")
+        )
+        .await
+        .0,
+        404
+    );
+
+    // Cancel the scheduled message
+    assert_eq!(
+        scheduled_request(
+            reqwest::Method::DELETE,
+            &format!("/{queue_id}"),
+            ("jdoe@example.com", "12345"),
+        )
+        .await
+        .0,
+        200
+    );
+    assert_eq!(
+        scheduled_request(reqwest::Method::GET, "", ("admin", "secret"))
+            .await
+            .1["data"],
+        serde_json::json!([])
+    );
+
     // Verify onSuccessUpdateEmail action
     let mut request = client.build();
     let set_request = request.set_email_submission();
@@ -485,6 +607,81 @@ pub async fn test(params: &mut JMAPTest) {
         .await
         .unwrap()
         .is_none());
+
+    // Messages sent on behalf of a shared account are filed in its Sent mailbox
+    // once the owner grants the submit right on it
+    params
+        .directory
+        .create_test_user_with_email("support@example.com", "abcdef", "Support")
+        .await;
+    let support_client = test_account_login("support@example.com", "abcdef").await;
+    let john_client = test_account_login("jdoe@example.com", "12345").await;
+    let sent_id = support_client
+        .mailbox_query(
+            mailbox::query::Filter::role(Role::Sent).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+    let email_body = concat!(
+        "From: support@example.com\r\n",
+        "To: jane_smith@remote.org\r\n",
+        "Message-ID: <on-behalf@example.com>\r\n",
+        "Subject: on behalf\r\n",
+        "\r\n",
+        "test"
+    );
+    let email_id = john_client
+        .email_import(
+            email_body.as_bytes().to_vec(),
+            [&mailbox_id],
+            None::<Vec<&str>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    let john_identity_id = Id::from(0u64).to_string();
+    for grant in [false, true, true] {
+        if grant {
+            support_client
+                .mailbox_update_acl(&sent_id, "jdoe@example.com", [ACL::Submit])
+                .await
+                .unwrap();
+        }
+        john_client
+            .email_submission_create(&email_id, &john_identity_id)
+            .await
+            .unwrap();
+        expect_message_delivery(&mut smtp_rx).await;
+        let sent_ids = support_client
+            .email_query(
+                email::query::Filter::in_mailbox(&sent_id).into(),
+                None::<Vec<_>>,
+            )
+            .await
+            .unwrap()
+            .take_ids();
+
+        // Copies are deduplicated by Message-ID
+        assert_eq!(sent_ids.len(), usize::from(grant));
+        if grant {
+            let email = support_client
+                .email_get(&sent_ids[0], [email::Property::Sender].into())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                email.sender().unwrap().first().unwrap().email(),
+                "jdoe@example.com"
+            );
+        }
+    }
+    destroy_all_mailboxes_no_wait(&support_client).await;
+
     smtp_settings.lock().do_stop = true;
 
     // Destroy the created mailbox, identity and all submissions
@@ -667,3 +864,28 @@ pub async fn expect_nothing(event_rx: &mut mpsc::Receiver<MockMessage>) {
         }
     }
 }
+
+async fn scheduled_request(
+    method: reqwest::Method,
+    path: &str,
+    (username, secret): (&str, &str),
+) -> (u16, serde_json::Value) {
+    let response = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_millis(1000))
+        .build()
+        .unwrap()
+        .request(
+            method,
+            format!("https://127.0.0.1:8899/api/queue/scheduled{path}"),
+        )
+        .basic_auth(username, Some(secret))
+        .send()
+        .await
+        .unwrap();
+    let status = response.status().as_u16();
+    (
+        status,
+        response.json().await.unwrap_or(serde_json::Value::Null),
+    )
+}
@@ -0,0 +1,206 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::mailbox::INBOX_ID;
+use jmap_proto::types::id::Id;
+
+use crate::jmap::{
+    assert_is_empty, fixture::Fixture, jmap_json_request, mailbox::destroy_all_mailboxes,
+};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running Email/updateFlags tests...");
+    let server = params.server.clone();
+    let account_id = Fixture::new()
+        .domain("example.com", |domain| {
+            domain.account("flags", "secret", "Flags Test", |account| account)
+        })
+        .seed(params)
+        .await
+        .account("flags@example.com")
+        .id
+        .to_string();
+    let inbox_id = Id::from(INBOX_ID).to_string();
+
+    // Create test messages, one of them flagged
+    let response = jmap_json_request(
+        r#"[[ "Email/set", {
+            "accountId": "$$",
+            "create": {
+                "e1": { "mailboxIds": { "&&": true }, "subject": "Message 1",
+                        "bodyValues": { "1": { "value": "Hello" } },
+                        "textBody": [ { "partId": "1", "type": "text/plain" } ] },
+                "e2": { "mailboxIds": { "&&": true }, "subject": "Message 2",
+                        "bodyValues": { "1": { "value": "Hello" } },
+                        "textBody": [ { "partId": "1", "type": "text/plain" } ] },
+                "e3": { "mailboxIds": { "&&": true }, "subject": "Message 3",
+                        "keywords": { "$flagged": true },
+                        "bodyValues": { "1": { "value": "Hello" } },
+                        "textBody": [ { "partId": "1", "type": "text/plain" } ] }
+            }
+          }, "0" ]]"#
+            .replace("$$", &account_id)
+            .replace("&&", &inbox_id),
+        "flags@example.com",
+        "secret",
+    )
+    .await;
+    let email_ids = ["e1", "e2", "e3"]
+        .into_iter()
+        .map(|id| {
+            response
+                .pointer(&format!("/methodResponses/0/1/created/{id}/id"))
+                .and_then(|v| v.as_str())
+                .unwrap_or_else(|| panic!("Unexpected response: {response}"))
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+    let old_state = response
+        .pointer("/methodResponses/0/1/newState")
+        .and_then(|v| v.as_str())
+        .unwrap()
+        .to_string();
+
+    // Mark all messages in the inbox as read
+    let response = update_flags(
+        &account_id,
+        r#""filter": { "inMailbox": "&&" }, "keywords": { "$seen": true }"#
+            .replace("&&", &inbox_id),
+    )
+    .await;
+    let mut updated = updated_ids(&response);
+    updated.sort_unstable();
+    let mut expected_ids = email_ids.clone();
+    expected_ids.sort_unstable();
+    assert_eq!(updated, expected_ids, "{response}");
+    let new_state = response
+        .pointer("/methodResponses/0/1/newState")
+        .and_then(|v| v.as_str())
+        .unwrap()
+        .to_string();
+    assert_ne!(new_state, old_state);
+
+    // Messages that already have the keyword are not modified
+    let response = update_flags(
+        &account_id,
+        r#""filter": { "inMailbox": "&&" }, "keywords": { "$seen": true }"#
+            .replace("&&", &inbox_id),
+    )
+    .await;
+    assert!(updated_ids(&response).is_empty(), "{response}");
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/newState")
+            .and_then(|v| v.as_str()),
+        Some(new_state.as_str())
+    );
+
+    // Changes are visible through Email/changes
+    let response = jmap_json_request(
+        r#"[[ "Email/changes", {
+            "accountId": "$$",
+            "sinceState": "%%"
+          }, "0" ]]"#
+            .replace("$$", &account_id)
+            .replace("%%", &old_state),
+        "flags@example.com",
+        "secret",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/updated")
+            .and_then(|v| v.as_array())
+            .map(|v| v.len()),
+        Some(3),
+        "{response}"
+    );
+
+    // State mismatches are rejected
+    let response = update_flags(
+        &account_id,
+        format!(r#""ifInState": "{old_state}", "keywords": {{ "$seen": false }}"#),
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/type")
+            .and_then(|v| v.as_str()),
+        Some("stateMismatch"),
+        "{response}"
+    );
+
+    // Mark flagged messages as unread
+    let response = update_flags(
+        &account_id,
+        r#""filter": { "hasKeyword": "$flagged" }, "keywords": { "$seen": false }"#.to_string(),
+    )
+    .await;
+    assert_eq!(updated_ids(&response), [email_ids[2].clone()], "{response}");
+    let response = jmap_json_request(
+        r#"[[ "Email/query", {
+            "accountId": "$$",
+            "filter": { "notKeyword": "$seen" }
+          }, "0" ]]"#
+            .replace("$$", &account_id),
+        "flags@example.com",
+        "secret",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/ids")
+            .and_then(|v| v.as_array())
+            .map(|v| v.iter().filter_map(|v| v.as_str()).collect::<Vec<_>>()),
+        Some(vec![email_ids[2].as_str()]),
+        "{response}"
+    );
+
+    // Remove test data
+    params.client.set_default_account_id(account_id);
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
+
+async fn update_flags(account_id: &str, arguments: String) -> serde_json::Value {
+    jmap_json_request(
+        format!(
+            r#"[[ "Email/updateFlags", {{ "accountId": "{account_id}", {arguments} }}, "0" ]]"#
+        ),
+        "flags@example.com",
+        "secret",
+    )
+    .await
+}
+
+fn updated_ids(response: &serde_json::Value) -> Vec<String> {
+    response
+        .pointer("/methodResponses/0/1/updated")
+        .and_then(|v| v.as_array())
+        .unwrap_or_else(|| panic!("Unexpected response: {response}"))
+        .iter()
+        .filter_map(|v| v.as_str().map(|id| id.to_string()))
+        .collect()
+}
@@ -21,12 +21,11 @@
  * for more details.
 */
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::jmap::{
-    assert_is_empty, delivery::SmtpConnection, mailbox::destroy_all_mailboxes, test_account_login,
+    assert_is_empty, delivery::SmtpConnection, fixture::Fixture, mailbox::destroy_all_mailboxes,
 };
-use directory::backend::internal::manage::ManageDirectory;
 use futures::StreamExt;
 use jmap::mailbox::INBOX_ID;
 use jmap_client::{event_source::Changes, mailbox::Role, TypeState};
@@ -42,19 +41,15 @@ pub async fn test(params: &mut JMAPTest) {
 
     // Create test account
     let server = params.server.clone();
-    params
-        .directory
-        .create_test_user_with_email("jdoe@example.com", "12345", "John Doe")
+    let seeded = Fixture::new()
+        .domain("example.com", |domain| {
+            domain.account("jdoe", "12345", "John Doe", |account| account)
+        })
+        .seed(params)
         .await;
-    let account_id = Id::from(
-        server
-            .store
-            .get_or_create_account_id("jdoe@example.com")
-            .await
-            .unwrap(),
-    )
-    .to_string();
-    let client = test_account_login("jdoe@example.com", "12345").await;
+    let account = seeded.account("jdoe@example.com");
+    let account_id = account.id.to_string();
+    let client = account.client().await;
 
     let mut changes = client
         .event_source(None::<Vec<_>>, false, 1.into(), None)
@@ -144,6 +139,42 @@ pub async fn test(params: &mut JMAPTest) {
     assert_ping(&mut event_rx).await;
     assert_ping(&mut event_rx).await;
 
+    // The ping interval is reported in seconds
+    let mut response = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap()
+        .get("https://127.0.0.1:8899/jmap/eventsource/?types=*&closeafter=no&ping=1")
+        .basic_auth(&account.login, Some(&account.secret))
+        .send()
+        .await
+        .unwrap();
+    let mut buf = String::new();
+    assert_eq!(
+        read_event(&mut response, &mut buf).await,
+        "event: ping\ndata: {\"interval\": 1}"
+    );
+
+    // Pings are not sent right after a state change
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    client
+        .mailbox_create("EventSource Ping", None::<String>, Role::None)
+        .await
+        .unwrap();
+    assert!(read_event(&mut response, &mut buf)
+        .await
+        .starts_with("event: state\n"));
+    let state_received = Instant::now();
+    assert!(read_event(&mut response, &mut buf)
+        .await
+        .starts_with("event: ping\n"));
+    assert!(
+        state_received.elapsed() >= Duration::from_millis(800),
+        "Ping sent {:?} after a state change.",
+        state_received.elapsed()
+    );
+    drop(response);
+
     destroy_all_mailboxes(params).await;
     assert_is_empty(server).await;
 }
@@ -180,3 +211,19 @@ async fn assert_ping(event_rx: &mut mpsc::Receiver<Changes>) {
         }
     }
 }
+
+async fn read_event(response: &mut reqwest::Response, buf: &mut String) -> String {
+    loop {
+        if let Some(pos) = buf.find("\n\n") {
+            let event = buf[..pos].to_string();
+            buf.drain(..pos + 2);
+            return event;
+        }
+        let chunk = tokio::time::timeout(Duration::from_millis(1500), response.chunk())
+            .await
+            .expect("Timeout waiting for event.")
+            .unwrap()
+            .expect("Event stream closed.");
+        buf.push_str(std::str::from_utf8(&chunk).unwrap());
+    }
+}
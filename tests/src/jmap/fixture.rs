@@ -0,0 +1,275 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use ahash::AHashMap;
+use jmap::mailbox::INBOX_ID;
+use jmap_client::{client::Client, mailbox::Role};
+use jmap_proto::types::id::Id;
+
+use super::{test_account_login, JMAPTest};
+
+#[derive(Default)]
+pub struct Fixture {
+    domains: Vec<DomainFixture>,
+}
+
+pub struct DomainFixture {
+    name: String,
+    accounts: Vec<AccountFixture>,
+    groups: Vec<GroupFixture>,
+}
+
+pub struct AccountFixture {
+    login: String,
+    secret: String,
+    name: String,
+    aliases: Vec<String>,
+    quota: Option<u32>,
+    member_of: Vec<String>,
+    mailboxes: Vec<String>,
+    messages: Vec<MessageFixture>,
+}
+
+pub struct GroupFixture {
+    login: String,
+    name: String,
+}
+
+pub struct MessageFixture {
+    mailbox: String,
+    raw: Vec<u8>,
+    keywords: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct Seeded {
+    pub accounts: AHashMap<String, SeededAccount>,
+}
+
+pub struct SeededAccount {
+    pub id: Id,
+    pub login: String,
+    pub secret: String,
+    pub mailboxes: AHashMap<String, Id>,
+    pub messages: Vec<Id>,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn domain(
+        mut self,
+        name: impl Into<String>,
+        build: impl FnOnce(DomainFixture) -> DomainFixture,
+    ) -> Self {
+        self.domains.push(build(DomainFixture {
+            name: name.into(),
+            accounts: Vec::new(),
+            groups: Vec::new(),
+        }));
+        self
+    }
+
+    pub async fn seed(self, params: &JMAPTest) -> Seeded {
+        let mut seeded = Seeded::default();
+
+        for domain in self.domains {
+            // Groups are created first so accounts can be added to them
+            for group in domain.groups {
+                let login = format!("{}@{}", group.login, domain.name);
+                params
+                    .directory
+                    .create_test_group_with_email(&login, &group.name)
+                    .await;
+            }
+
+            for account in domain.accounts {
+                let login = format!("{}@{}", account.login, domain.name);
+                params
+                    .directory
+                    .create_test_user_with_email(&login, &account.secret, &account.name)
+                    .await;
+                for alias in &account.aliases {
+                    params
+                        .directory
+                        .link_test_address(&login, &format!("{alias}@{}", domain.name), "alias")
+                        .await;
+                }
+                if let Some(quota) = account.quota {
+                    params.directory.set_test_quota(&login, quota).await;
+                }
+                for group in &account.member_of {
+                    params
+                        .directory
+                        .add_to_group(&login, &format!("{group}@{}", domain.name))
+                        .await;
+                }
+
+                let mut seeded_account = SeededAccount {
+                    id: Id::from(
+                        params
+                            .server
+                            .store
+                            .get_or_create_account_id(&login)
+                            .await
+                            .unwrap(),
+                    ),
+                    login: login.clone(),
+                    secret: account.secret,
+                    mailboxes: AHashMap::from_iter([("Inbox".to_string(), Id::from(INBOX_ID))]),
+                    messages: Vec::new(),
+                };
+
+                if !account.mailboxes.is_empty() || !account.messages.is_empty() {
+                    let client = seeded_account.client().await;
+                    for name in account.mailboxes {
+                        let mailbox_id = client
+                            .mailbox_create(&name, None::<String>, Role::None)
+                            .await
+                            .unwrap()
+                            .take_id();
+                        seeded_account
+                            .mailboxes
+                            .insert(name, Id::from_bytes(mailbox_id.as_bytes()).unwrap());
+                    }
+                    for message in account.messages {
+                        let mailbox_id = seeded_account
+                            .mailboxes
+                            .get(&message.mailbox)
+                            .unwrap_or_else(|| {
+                                panic!("Mailbox {:?} was not declared.", message.mailbox)
+                            })
+                            .to_string();
+                        let email_id = client
+                            .email_import(message.raw, [&mailbox_id], Some(message.keywords), None)
+                            .await
+                            .unwrap()
+                            .take_id();
+                        seeded_account
+                            .messages
+                            .push(Id::from_bytes(email_id.as_bytes()).unwrap());
+                    }
+                }
+
+                seeded.accounts.insert(login, seeded_account);
+            }
+        }
+
+        seeded
+    }
+}
+
+impl DomainFixture {
+    pub fn account(
+        mut self,
+        login: impl Into<String>,
+        secret: impl Into<String>,
+        name: impl Into<String>,
+        build: impl FnOnce(AccountFixture) -> AccountFixture,
+    ) -> Self {
+        self.accounts.push(build(AccountFixture {
+            login: login.into(),
+            secret: secret.into(),
+            name: name.into(),
+            aliases: Vec::new(),
+            quota: None,
+            member_of: Vec::new(),
+            mailboxes: Vec::new(),
+            messages: Vec::new(),
+        }));
+        self
+    }
+
+    pub fn group(mut self, login: impl Into<String>, name: impl Into<String>) -> Self {
+        self.groups.push(GroupFixture {
+            login: login.into(),
+            name: name.into(),
+        });
+        self
+    }
+}
+
+impl AccountFixture {
+    pub fn alias(mut self, alias: impl Into<String>) -> Self {
+        self.aliases.push(alias.into());
+        self
+    }
+
+    pub fn quota(mut self, quota: u32) -> Self {
+        self.quota = Some(quota);
+        self
+    }
+
+    pub fn member_of(mut self, group: impl Into<String>) -> Self {
+        self.member_of.push(group.into());
+        self
+    }
+
+    pub fn mailbox(mut self, name: impl Into<String>) -> Self {
+        self.mailboxes.push(name.into());
+        self
+    }
+
+    pub fn message(self, mailbox: impl Into<String>, raw: impl Into<Vec<u8>>) -> Self {
+        self.message_with_keywords(mailbox, raw, Vec::<String>::new())
+    }
+
+    pub fn message_with_keywords(
+        mut self,
+        mailbox: impl Into<String>,
+        raw: impl Into<Vec<u8>>,
+        keywords: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.messages.push(MessageFixture {
+            mailbox: mailbox.into(),
+            raw: raw.into(),
+            keywords: keywords.into_iter().map(Into::into).collect(),
+        });
+        self
+    }
+}
+
+impl Seeded {
+    pub fn account(&self, login: &str) -> &SeededAccount {
+        self.accounts
+            .get(login)
+            .unwrap_or_else(|| panic!("Account {login:?} was not seeded."))
+    }
+}
+
+impl SeededAccount {
+    pub async fn client(&self) -> Client {
+        let mut client = test_account_login(&self.login, &self.secret).await;
+        client.set_default_account_id(self.id.to_string());
+        client
+    }
+
+    pub fn mailbox_id(&self, name: &str) -> Id {
+        *self
+            .mailboxes
+            .get(name)
+            .unwrap_or_else(|| panic!("Mailbox {name:?} was not seeded."))
+    }
+}
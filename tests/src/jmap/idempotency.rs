@@ -0,0 +1,86 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use serde_json::Value;
+
+use super::JMAPTest;
+
+pub async fn test(_params: &mut JMAPTest) {
+    println!("Running idempotency key tests...");
+    let principal = r#"{"type":"individual","name":"retry","secrets":["secret"],"emails":["retry@example.com"]}"#;
+
+    // The first request creates the principal
+    let (status, replayed, created) = manage_request(principal, Some("create-retry")).await;
+    assert_eq!(status, 200);
+    assert!(!replayed);
+    assert!(created["data"].is_u64(), "{created}");
+
+    // Replaying the same request returns the original result
+    let (status, replayed, response) = manage_request(principal, Some("create-retry")).await;
+    assert_eq!(status, 200);
+    assert!(replayed);
+    assert_eq!(response, created);
+
+    // Requests without a key are executed again
+    let (_, replayed, response) = manage_request(principal, None).await;
+    assert!(!replayed);
+    assert_eq!(response["error"], "alreadyExists", "{response}");
+
+    // Reusing a key for a different request is rejected
+    let (status, _, _) = manage_request(
+        r#"{"type":"individual","name":"retry2","secrets":["secret"]}"#,
+        Some("create-retry"),
+    )
+    .await;
+    assert_eq!(status, 422);
+
+    // Invalid keys are rejected
+    let (status, _, _) = manage_request(principal, Some("")).await;
+    assert_eq!(status, 400);
+}
+
+async fn manage_request(body: &str, idempotency_key: Option<&str>) -> (u16, bool, Value) {
+    let mut request = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_millis(1000))
+        .build()
+        .unwrap()
+        .post("https://127.0.0.1:8899/admin/principal")
+        .basic_auth("admin", Some("secret"))
+        .body(body.to_string());
+    if let Some(idempotency_key) = idempotency_key {
+        request = request.header("Idempotency-Key", idempotency_key);
+    }
+    let response = request.send().await.unwrap();
+    let status = response.status().as_u16();
+    let replayed = response.headers().contains_key("Idempotent-Replayed");
+    let body = response.bytes().await.unwrap();
+
+    (
+        status,
+        replayed,
+        serde_json::from_slice(&body).unwrap_or(Value::Null),
+    )
+}
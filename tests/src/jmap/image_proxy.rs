@@ -0,0 +1,168 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use jmap::email::proxy::read_remote_image;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+use crate::jmap::{assert_is_empty, fixture::Fixture};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running image proxy tests...");
+    let server = params.server.clone();
+    let seeded = Fixture::new()
+        .domain("example.com", |domain| {
+            domain.account("proxy", "secret", "Image Proxy", |account| account)
+        })
+        .seed(params)
+        .await;
+    let account = seeded.account("proxy@example.com");
+    let auth = (account.login.as_str(), account.secret.as_str());
+
+    // Requests with an invalid signature are rejected
+    let (signature, url) = proxy_path(&server, "https://example.org/image.png");
+    let (_, other_url) = proxy_path(&server, "https://example.org/other.png");
+    for (signature, url) in [
+        (signature.as_str(), other_url.as_str()),
+        ("AAAA", url.as_str()),
+        (&signature[1..], url.as_str()),
+    ] {
+        assert_eq!(proxy_request(Some(auth), signature, url).await, 403);
+    }
+    assert_eq!(proxy_request(None, &signature, &url).await, 401);
+
+    // Private and loopback addresses are not fetched
+    for target in [
+        "http://127.0.0.1:9999/image.png",
+        "http://[::1]:9999/image.png",
+        "http://10.0.0.1/image.png",
+        "http://192.168.1.1/image.png",
+        "http://169.254.169.254/latest/meta-data",
+        "http://[::ffff:127.0.0.1]/image.png",
+        "http://localhost:9999/image.png",
+    ] {
+        let (signature, url) = proxy_path(&server, target);
+        assert_eq!(
+            proxy_request(Some(auth), &signature, &url).await,
+            403,
+            "{target}"
+        );
+    }
+
+    // Only images up to the maximum size are returned
+    let addr = spawn_mock_http_server().await;
+    let max_size = server.config.image_proxy_max_size;
+    for (path, expected_status) in [
+        ("image", None),
+        ("html", Some(502)),
+        ("svg", Some(502)),
+        ("large", Some(502)),
+        ("large-chunked", Some(502)),
+        ("missing", Some(502)),
+    ] {
+        let response = reqwest::get(format!("http://{addr}/{path}")).await.unwrap();
+        match (read_remote_image(response, max_size).await, expected_status) {
+            (Ok(image), None) => {
+                assert_eq!(image.content_type, "image/png");
+                assert_eq!(image.contents.as_ref(), b"PNG");
+            }
+            (Err(err), Some(status)) => assert_eq!(err.status, status, "{path}"),
+            (result, _) => panic!("Unexpected result for {path}: {result:?}"),
+        }
+    }
+
+    assert_is_empty(server).await;
+}
+
+fn proxy_path(server: &jmap::JMAP, target: &str) -> (String, String) {
+    let html = server.proxy_remote_images(&format!("<img src=\"{target}\">"));
+    let path = html
+        .strip_prefix("<img src=\"/jmap/proxy/")
+        .and_then(|path| path.strip_suffix("\">"))
+        .unwrap_or_else(|| panic!("Unexpected rewrite {html}"));
+    let (signature, url) = path.split_once('/').unwrap();
+    (signature.to_string(), url.to_string())
+}
+
+async fn proxy_request(auth: Option<(&str, &str)>, signature: &str, url: &str) -> u16 {
+    let mut request = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_millis(2000))
+        .build()
+        .unwrap()
+        .get(format!(
+            "https://127.0.0.1:8899/jmap/proxy/{signature}/{url}"
+        ));
+    if let Some((username, secret)) = auth {
+        request = request.basic_auth(username, Some(secret));
+    }
+    request.send().await.unwrap().status().as_u16()
+}
+
+async fn spawn_mock_http_server() -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = vec![0u8; 1024];
+                let len = stream.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..len]).into_owned();
+                let path = request.split(' ').nth(1).unwrap_or_default();
+                let large = "A".repeat(1024 * 1024);
+                let response = match path {
+                    "/image" => {
+                        "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: 3\r\nConnection: close\r\n\r\nPNG".to_string()
+                    }
+                    "/html" => {
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 4\r\nConnection: close\r\n\r\nHTML".to_string()
+                    }
+                    "/svg" => {
+                        "HTTP/1.1 200 OK\r\nContent-Type: image/svg+xml\r\nContent-Length: 3\r\nConnection: close\r\n\r\nSVG".to_string()
+                    }
+                    "/large" => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{large}",
+                        large.len()
+                    ),
+                    "/large-chunked" => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n{:x}\r\n{large}\r\n0\r\n\r\n",
+                        large.len()
+                    ),
+                    _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string(),
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            });
+        }
+    });
+
+    addr
+}
@@ -0,0 +1,152 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{sync::Arc, time::Duration};
+
+use directory::backend::internal::manage::ManageDirectory;
+use jmap::{
+    jobs::{JobKind, JobProgress, JobStatus, JobTask},
+    mailbox::INBOX_ID,
+    JMAP,
+};
+use jmap_proto::types::id::Id;
+
+use crate::jmap::{assert_is_empty, mailbox::destroy_all_mailboxes, test_account_login};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running background job tests...");
+    let server = params.server.clone();
+    params
+        .directory
+        .create_test_user_with_email("jobs@example.com", "secret", "Jobs Test")
+        .await;
+    let account_id = Id::from(
+        server
+            .store
+            .get_or_create_account_id("jobs@example.com")
+            .await
+            .unwrap(),
+    );
+    let mut client = test_account_login("jobs@example.com", "secret").await;
+    client.set_default_account_id(account_id.to_string());
+
+    let inbox_id = Id::new(INBOX_ID as u64).to_string();
+    for num in 0..3 {
+        client
+            .email_import(
+                format!(
+                    concat!(
+                        "From: bill@example.com\r\n",
+                        "To: jobs@example.com\r\n",
+                        "Subject: Job test {}\r\n",
+                        "\r\n",
+                        "Reindex me."
+                    ),
+                    num
+                )
+                .into_bytes(),
+                [&inbox_id],
+                None::<Vec<String>>,
+                None,
+            )
+            .await
+            .unwrap();
+    }
+
+    // Reindex the account in the background
+    let job_id = server
+        .job_start(JobTask::Reindex(account_id.document_id().into()))
+        .unwrap();
+    let progress = wait_for_job(&server, job_id).await;
+    assert_eq!(progress.kind, JobKind::Reindex);
+    assert_eq!(progress.status, JobStatus::Completed, "{progress:?}");
+    assert_eq!(
+        (progress.total, progress.processed, progress.failed),
+        (3, 3, 0),
+        "{progress:?}"
+    );
+    assert!(progress.finished_at.is_some());
+    assert!(!server.job_cancel(job_id));
+
+    // Purging an unknown account is reported in the job summary
+    let job_id = server
+        .job_start(JobTask::PurgeAccounts(vec![
+            "unknown@example.com".to_string()
+        ]))
+        .unwrap();
+    let progress = wait_for_job(&server, job_id).await;
+    assert_eq!(progress.status, JobStatus::Completed, "{progress:?}");
+    assert_eq!(
+        (progress.processed, progress.failed),
+        (0, 1),
+        "{progress:?}"
+    );
+    assert!(
+        progress
+            .summary
+            .as_deref()
+            .unwrap_or_default()
+            .contains("unknown@example.com"),
+        "{progress:?}"
+    );
+    assert_eq!(server.job_list().len(), 2);
+
+    // Finished jobs remain available from the store
+    server.jobs.clear();
+    let progress = server.job_status(job_id).await.unwrap().unwrap();
+    assert_eq!(progress.kind, JobKind::PurgeAccounts);
+    assert_eq!(progress.status, JobStatus::Completed);
+    assert!(server.job_status(0).await.unwrap().is_none());
+    assert!(server.job_list().is_empty());
+
+    // Compact every subspace of the store
+    let job_id = server.job_start(JobTask::StoreCompaction).unwrap();
+    let progress = wait_for_job(&server, job_id).await;
+    assert_eq!(progress.kind, JobKind::StoreCompaction);
+    assert_eq!(progress.status, JobStatus::Completed, "{progress:?}");
+    assert_eq!(
+        (progress.total, progress.processed, progress.failed),
+        (6, 6, 0),
+        "{progress:?}"
+    );
+    assert!(!server.is_compaction_running());
+    server.jobs.clear();
+
+    // Remove test data
+    params.client.set_default_account_id(account_id.to_string());
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
+
+async fn wait_for_job(server: &Arc<JMAP>, job_id: u64) -> JobProgress {
+    for _ in 0..100 {
+        let progress = server.job_status(job_id).await.unwrap().unwrap();
+        if progress.status != JobStatus::Running {
+            return progress;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Job {job_id} did not finish.");
+}
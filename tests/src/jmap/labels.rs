@@ -0,0 +1,173 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_proto::types::{id::Id, keyword::Keyword};
+
+use crate::jmap::{assert_is_empty, fixture::Fixture, jmap_json_request};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running label tests...");
+    let server = params.server.clone();
+    let account_id = Fixture::new()
+        .domain("example.com", |domain| {
+            domain.account("labels", "secret", "Label Test", |account| account)
+        })
+        .seed(params)
+        .await
+        .account("labels@example.com")
+        .id;
+
+    // Create labels, deriving the keyword from the name when missing
+    let response = jmap_json_request(
+        r##"[[ "Label/set", {
+            "accountId": "$$",
+            "create": {
+                "a": { "name": "Work", "color": "#FF0000", "sortOrder": 2, "keyword": "$Work" },
+                "b": { "name": "Travel plans", "sortOrder": 1 },
+                "c": { "name": "work" },
+                "d": { "name": "Invalid", "color": "red" }
+            }
+          }, "0" ]]"##
+            .replace("$$", &account_id.to_string()),
+        "labels@example.com",
+        "secret",
+    )
+    .await;
+    let work_id = response
+        .pointer("/methodResponses/0/1/created/a/id")
+        .and_then(|v| v.as_str())
+        .unwrap_or_else(|| panic!("Unexpected response: {response}"))
+        .to_string();
+    let travel_id = response
+        .pointer("/methodResponses/0/1/created/b/id")
+        .and_then(|v| v.as_str())
+        .unwrap()
+        .to_string();
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/created/b/keyword")
+            .and_then(|v| v.as_str()),
+        Some("Travel_plans"),
+        "{response}"
+    );
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/notCreated/c/type")
+            .and_then(|v| v.as_str()),
+        Some("alreadyExists"),
+        "{response}"
+    );
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/notCreated/d/type")
+            .and_then(|v| v.as_str()),
+        Some("invalidProperties"),
+        "{response}"
+    );
+
+    // Keywords are immutable, other properties can be updated
+    let response = jmap_json_request(
+        r##"[[ "Label/set", {
+            "accountId": "$$",
+            "update": {
+                "%%": { "color": "#00ff00" },
+                "&&": { "keyword": "$Holidays" }
+            }
+          }, "0" ], [ "Label/get", {
+            "accountId": "$$",
+            "ids": ["%%"]
+          }, "1" ]]"##
+            .replace("$$", &account_id.to_string())
+            .replace("%%", &work_id)
+            .replace("&&", &travel_id),
+        "labels@example.com",
+        "secret",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer(&format!("/methodResponses/0/1/notUpdated/{travel_id}/type"))
+            .and_then(|v| v.as_str()),
+        Some("invalidProperties"),
+        "{response}"
+    );
+    let label = response
+        .pointer("/methodResponses/1/1/list/0")
+        .unwrap_or_else(|| panic!("Unexpected response: {response}"));
+    assert_eq!(label.get("name").unwrap().as_str(), Some("Work"));
+    assert_eq!(label.get("color").unwrap().as_str(), Some("#00ff00"));
+    assert_eq!(label.get("keyword").unwrap().as_str(), Some("$Work"));
+    assert_eq!(label.get("sortOrder").unwrap().as_u64(), Some(2));
+
+    // Custom keywords set over IMAP create new labels
+    server
+        .label_sync_keywords(
+            account_id.document_id(),
+            &[
+                Keyword::Seen,
+                Keyword::Other("$Work".to_string()),
+                Keyword::Other("$Urgent".to_string()),
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        server
+            .label_keywords(account_id.document_id())
+            .await
+            .unwrap(),
+        ["$Urgent", "Travel_plans", "$Work"]
+    );
+
+    // Remove test data
+    let ids = server
+        .label_list(account_id.document_id())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(document_id, _)| format!("\"{}\"", Id::from(document_id)))
+        .collect::<Vec<_>>()
+        .join(",");
+    let response = jmap_json_request(
+        r#"[[ "Label/set", {
+            "accountId": "$$",
+            "destroy": [%%]
+          }, "0" ]]"#
+            .replace("$$", &account_id.to_string())
+            .replace("%%", &ids),
+        "labels@example.com",
+        "secret",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/destroyed")
+            .and_then(|v| v.as_array())
+            .map(|v| v.len()),
+        Some(3),
+        "{response}"
+    );
+    assert_is_empty(server).await;
+}
@@ -0,0 +1,204 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::mailbox::INBOX_ID;
+use jmap_client::mailbox::Role;
+use jmap_proto::types::id::Id;
+use serde_json::Value;
+
+use crate::jmap::{
+    assert_is_empty, jmap_json_request, mailbox::destroy_all_mailboxes, test_account_login,
+};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running mailbox usage tests...");
+    let server = params.server.clone();
+    params
+        .directory
+        .create_test_user_with_email("usage@example.com", "secret", "Usage Test")
+        .await;
+    let account_id = Id::from(
+        server
+            .store
+            .get_or_create_account_id("usage@example.com")
+            .await
+            .unwrap(),
+    );
+    let mut client = test_account_login("usage@example.com", "secret").await;
+    client.set_default_account_id(account_id.to_string());
+
+    // Create a mailbox hierarchy
+    let inbox_id = Id::new(INBOX_ID as u64).to_string();
+    let projects_id = client
+        .mailbox_create("Projects", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let alpha_id = client
+        .mailbox_create("Alpha", Some(&projects_id), Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    // Import messages
+    let mut messages = Vec::new();
+    for (num, mailbox_id) in [&alpha_id, &alpha_id, &projects_id, &inbox_id]
+        .into_iter()
+        .enumerate()
+    {
+        let message = format!(
+            concat!(
+                "From: bill@example.com\r\n",
+                "To: usage@example.com\r\n",
+                "Subject: Usage test {}\r\n",
+                "\r\n",
+                "{}"
+            ),
+            num,
+            "Some text ".repeat(num + 1)
+        )
+        .into_bytes();
+        let size = message.len() as u64;
+        let email_id = client
+            .email_import(message, [mailbox_id], None::<Vec<String>>, None)
+            .await
+            .unwrap()
+            .take_id();
+        messages.push((email_id, size));
+    }
+    let sizes = messages.iter().map(|(_, size)| *size).collect::<Vec<_>>();
+
+    // Usage should be aggregated bottom-up
+    let response = mailbox_usage(account_id).await;
+    let reconciled_at = response.get("reconciledAt").unwrap().clone();
+    assert_eq!(
+        response.get("maxStaleness").and_then(|v| v.as_u64()),
+        Some(86400),
+        "{response}"
+    );
+    assert_usage(
+        &response,
+        &alpha_id,
+        2,
+        sizes[0] + sizes[1],
+        2,
+        sizes[0] + sizes[1],
+    );
+    assert_usage(
+        &response,
+        &projects_id,
+        1,
+        sizes[2],
+        3,
+        sizes[0] + sizes[1] + sizes[2],
+    );
+    assert_usage(&response, &inbox_id, 1, sizes[3], 1, sizes[3]);
+    assert_eq!(
+        usage_entry(&response, &alpha_id)
+            .get("parentId")
+            .and_then(|v| v.as_str()),
+        Some(projects_id.as_str())
+    );
+
+    // Moving and deleting messages should update counters incrementally
+    client
+        .email_set_mailboxes(&messages[0].0, [&inbox_id])
+        .await
+        .unwrap();
+    client.email_destroy(&messages[1].0).await.unwrap();
+    let response = mailbox_usage(account_id).await;
+    assert_eq!(response.get("reconciledAt"), Some(&reconciled_at));
+    assert_usage(&response, &alpha_id, 0, 0, 0, 0);
+    assert_usage(&response, &projects_id, 1, sizes[2], 1, sizes[2]);
+    assert_usage(
+        &response,
+        &inbox_id,
+        2,
+        sizes[0] + sizes[3],
+        2,
+        sizes[0] + sizes[3],
+    );
+
+    // A full recount should yield the same values
+    server.mailbox_usage.clear();
+    let recounted = mailbox_usage(account_id).await;
+    assert_eq!(response.get("list"), recounted.get("list"));
+
+    // Remove test data
+    params.client.set_default_account_id(account_id.to_string());
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
+
+async fn mailbox_usage(account_id: Id) -> Value {
+    let response = jmap_json_request(
+        r#"[[ "MailboxUsage/get", {
+            "accountId": "$$"
+          }, "0" ]]"#
+            .replace("$$", &account_id.to_string()),
+        "usage@example.com",
+        "secret",
+    )
+    .await;
+    response
+        .pointer("/methodResponses/0/1")
+        .filter(|response| response.get("list").is_some())
+        .cloned()
+        .unwrap_or_else(|| panic!("Unexpected response: {response}"))
+}
+
+fn usage_entry<'x>(response: &'x Value, mailbox_id: &str) -> &'x Value {
+    response
+        .get("list")
+        .and_then(|list| list.as_array())
+        .and_then(|list| {
+            list.iter()
+                .find(|entry| entry.get("id").and_then(|v| v.as_str()) == Some(mailbox_id))
+        })
+        .unwrap_or_else(|| panic!("Mailbox {mailbox_id} not found: {response}"))
+}
+
+fn assert_usage(
+    response: &Value,
+    mailbox_id: &str,
+    emails: u64,
+    size: u64,
+    total_emails: u64,
+    total_size: u64,
+) {
+    let entry = usage_entry(response, mailbox_id);
+    for (property, expected) in [
+        ("emails", emails),
+        ("size", size),
+        ("totalEmails", total_emails),
+        ("totalSize", total_size),
+    ] {
+        assert_eq!(
+            entry.get(property).and_then(|v| v.as_u64()),
+            Some(expected),
+            "{mailbox_id} {property}: {response}"
+        );
+    }
+}
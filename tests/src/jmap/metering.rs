@@ -0,0 +1,150 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use jmap_proto::types::id::Id;
+use serde_json::Value;
+
+use crate::jmap::{
+    assert_is_empty, delivery::SmtpConnection, jmap_json_request, mailbox::destroy_all_mailboxes,
+};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running usage metering tests...");
+    let server = params.server.clone();
+    params
+        .directory
+        .create_test_user_with_email("metered@example.com", "secret", "Metering Test")
+        .await;
+    let account_id = server
+        .store
+        .get_or_create_account_id("metered@example.com")
+        .await
+        .unwrap();
+
+    // Start from an empty period
+    server.flush_usage().await;
+
+    // Deliver a message and make two API calls
+    let message = concat!(
+        "From: bill@example.com\r\n",
+        "To: metered@example.com\r\n",
+        "Subject: Metering test\r\n",
+        "\r\n",
+        "Count me in."
+    );
+    let mut lmtp = SmtpConnection::connect().await;
+    lmtp.ingest("bill@example.com", &["metered@example.com"], message)
+        .await;
+    let response = jmap_json_request(
+        r#"[[ "Mailbox/get", {
+            "accountId": "$$"
+          }, "0" ], [ "Mailbox/get", {
+            "accountId": "$$"
+          }, "1" ]]"#
+            .replace("$$", &Id::from(account_id).to_string()),
+        "metered@example.com",
+        "secret",
+    )
+    .await;
+    assert!(
+        response.pointer("/methodResponses/1/1/list").is_some(),
+        "{response}"
+    );
+
+    // Closing the period produces a usage record for the account
+    let records = server.flush_usage().await;
+    let record = records
+        .iter()
+        .find(|record| record.account_id == account_id)
+        .unwrap_or_else(|| panic!("Missing usage record: {records:?}"));
+    assert_eq!(record.version, 1);
+    assert_eq!(record.account, "metered@example.com");
+    assert_eq!(record.tenant.as_deref(), Some("example.com"));
+    assert_eq!(record.messages_received, 1);
+    assert!(record.bytes_received >= message.len() as u64);
+    assert_eq!(record.messages_sent, 0);
+    assert_eq!(record.api_calls, 2);
+    assert!(record.storage_bytes > 0);
+    assert!(record.period_end >= record.period_start);
+
+    // Counters are reset after each period
+    let records = server.flush_usage().await;
+    let next_record = records
+        .iter()
+        .find(|record| record.account_id == account_id)
+        .unwrap();
+    assert_eq!(next_record.messages_received, 0);
+    assert_eq!(next_record.api_calls, 0);
+    assert_eq!(next_record.period_start, record.period_end);
+
+    // Records are exported through the management API
+    let (status, response) = usage_request("/metered@example.com").await;
+    assert_eq!(status, 200);
+    let data = response["data"].as_array().unwrap();
+    assert_eq!(data.len(), 3, "{response}");
+    assert_eq!(data[1]["accountId"], account_id, "{response}");
+    assert_eq!(data[1]["messagesReceived"], 1, "{response}");
+    assert_eq!(data[1]["apiCalls"], 2, "{response}");
+    assert_eq!(data[1]["tenant"], "example.com", "{response}");
+    let (_, response) = usage_request(&format!(
+        "/metered@example.com?since={}",
+        next_record.period_start
+    ))
+    .await;
+    assert_eq!(response["data"].as_array().unwrap().len(), 1, "{response}");
+    let (_, response) = usage_request("?until=0").await;
+    assert_eq!(response["data"].as_array().unwrap().len(), 0, "{response}");
+    let (status, _) = usage_request("/unknown@example.com").await;
+    assert_eq!(status, 404);
+
+    // Remove test data
+    params
+        .client
+        .set_default_account_id(Id::from(account_id).to_string());
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
+
+async fn usage_request(path: &str) -> (u16, Value) {
+    let response = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_millis(1000))
+        .build()
+        .unwrap()
+        .get(format!("https://127.0.0.1:8899/admin/usage{path}"))
+        .basic_auth("admin", Some("secret"))
+        .send()
+        .await
+        .unwrap();
+    let status = response.status().as_u16();
+    let bytes = response.bytes().await.unwrap();
+
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
@@ -41,12 +41,18 @@ use utils::{config::ServerProtocol, UnwrapFailure};
 
 use crate::{add_test_certs, directory::DirectoryStore, store::TempDir};
 
+pub mod activity_log;
+pub mod annotations;
+pub mod archive;
 pub mod auth_acl;
 pub mod auth_limits;
 pub mod auth_oauth;
+pub mod autoconfig;
 pub mod blob;
+pub mod compliance;
 pub mod crypto;
 pub mod delivery;
+pub mod disposable_alias;
 pub mod email_changes;
 pub mod email_copy;
 pub mod email_get;
@@ -56,12 +62,25 @@ pub mod email_query_changes;
 pub mod email_search_snippet;
 pub mod email_set;
 pub mod email_submission;
+pub mod email_update_flags;
 pub mod event_source;
+pub mod fixture;
+pub mod idempotency;
+pub mod image_proxy;
+pub mod jobs;
+pub mod labels;
 pub mod mailbox;
+pub mod mailbox_usage;
+pub mod metering;
+pub mod overlay;
 pub mod push_subscription;
+pub mod queue_source;
 pub mod quota;
+pub mod recovery;
 pub mod sieve_script;
+pub mod step_up;
 pub mod stress_test;
+pub mod tag_rules;
 pub mod thread_get;
 pub mod thread_merge;
 pub mod vacation_response;
@@ -213,6 +232,16 @@ account = "1000/1m"
 authentication = "100/2s"
 anonymous = "100/1m"
 
+[jmap.rate-limit.endpoint]
+autoconfig = "8/1m"
+
+[jmap.autoconfig]
+enable = true
+
+[jmap.image-proxy]
+enable = true
+max-size = 10000
+
 [jmap.event-source]
 throttle = "500ms"
 
@@ -223,6 +252,28 @@ throttle = "500ms"
 throttle = "500ms"
 attempts.interval = "500ms"
 
+[jmap.activity-log]
+enable = true
+
+[jmap.metering]
+enable = true
+
+[jmap.recovery]
+enable = true
+codes = 5
+rate = "100/1m"
+
+[jmap.step-up]
+enable = true
+operations = ["queue-cancel"]
+rate = "100/1m"
+
+[jmap.step-up.totp]
+admin = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"
+
+[jmap.submission]
+sent-fanout = true
+
 [store."auth"]
 type = "sqlite"
 path = "{TMP}/auth.db"
@@ -239,6 +290,7 @@ domains = "SELECT 1 FROM emails WHERE address LIKE '%@' || ? LIMIT 1"
 [directory."auth"]
 type = "sql"
 store = "auth"
+options.subaddressing = true
 
 [directory."auth".columns]
 name = "name"
@@ -299,6 +351,7 @@ pub async fn jmap_tests() {
     email_query::test(&mut params, delete).await;
     email_get::test(&mut params).await;
     email_set::test(&mut params).await;
+    email_update_flags::test(&mut params).await;
     email_parse::test(&mut params).await;
     email_search_snippet::test(&mut params).await;
     email_changes::test(&mut params).await;
@@ -311,6 +364,8 @@ pub async fn jmap_tests() {
     auth_acl::test(&mut params).await;
     auth_limits::test(&mut params).await;
     auth_oauth::test(&mut params).await;
+    autoconfig::test(&mut params).await;
+    image_proxy::test(&mut params).await;
     event_source::test(&mut params).await;
     push_subscription::test(&mut params).await;
     sieve_script::test(&mut params).await;
@@ -320,6 +375,21 @@ pub async fn jmap_tests() {
     quota::test(&mut params).await;
     crypto::test(&mut params).await;
     blob::test(&mut params).await;
+    activity_log::test(&mut params).await;
+    labels::test(&mut params).await;
+    annotations::test(&mut params).await;
+    tag_rules::test(&mut params).await;
+    archive::test(&mut params).await;
+    disposable_alias::test(&mut params).await;
+    recovery::test(&mut params).await;
+    compliance::test(&mut params).await;
+    queue_source::test(&mut params).await;
+    step_up::test(&mut params).await;
+    idempotency::test(&mut params).await;
+    jobs::test(&mut params).await;
+    metering::test(&mut params).await;
+    overlay::test(&mut params).await;
+    mailbox_usage::test(&mut params).await;
 
     if delete {
         params.temp_dir.delete();
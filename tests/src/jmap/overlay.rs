@@ -0,0 +1,165 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use reqwest::Method;
+use serde_json::{json, Value};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running configuration overlay tests...");
+    params
+        .directory
+        .create_test_user_with_email("overlay@example.com", "secret", "Overlay Test")
+        .await;
+
+    // Define settings at every scope
+    let (status, _) = overlay_request(
+        Method::POST,
+        "/config",
+        json!([
+            ["overlay.global:limits.rcpt", "100"],
+            ["overlay.global:branding.name", "Stalwart"],
+            ["overlay.global:spam.threshold", "5.0"],
+            ["overlay.tenant.acme:limits.rcpt", "50"],
+            ["overlay.tenant.acme:branding.name", "Acme"],
+            ["overlay.member.example.com", "acme"],
+            ["overlay.domain.example.com:branding.name", "Example"],
+            ["overlay.account.overlay@example.com:limits.rcpt", "10"],
+        ]),
+    )
+    .await;
+    assert_eq!(status, 200);
+
+    // The most specific scope wins
+    for (query, value, scope) in [
+        (
+            "key=limits.rcpt&account=overlay@example.com",
+            "10",
+            "account",
+        ),
+        (
+            "key=branding.name&account=overlay@example.com",
+            "Example",
+            "domain",
+        ),
+        (
+            "key=spam.threshold&account=overlay@example.com",
+            "5.0",
+            "global",
+        ),
+        ("key=limits.rcpt&domain=example.com", "50", "tenant"),
+        ("key=limits.rcpt&domain=example.org", "100", "global"),
+        ("key=branding.name&tenant=acme", "Acme", "tenant"),
+    ] {
+        let (status, response) =
+            overlay_request(Method::GET, &format!("/overlay?{query}"), Value::Null).await;
+        assert_eq!(status, 200);
+        assert_eq!(response["data"][0]["value"], value, "{query}: {response}");
+        assert_eq!(response["data"][0]["scope"], scope, "{query}: {response}");
+    }
+
+    // All effective settings are listed with their source scope
+    let (_, response) = overlay_request(
+        Method::GET,
+        "/overlay?account=overlay@example.com",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(
+        response["data"],
+        json!([
+            {"key": "branding.name", "value": "Example", "scope": "domain", "subject": "example.com"},
+            {"key": "limits.rcpt", "value": "10", "scope": "account", "subject": "overlay@example.com"},
+            {"key": "spam.threshold", "value": "5.0", "scope": "global"},
+        ]),
+        "{response}"
+    );
+
+    // Unknown settings and accounts
+    let (_, response) = overlay_request(
+        Method::GET,
+        "/overlay?key=unknown&account=overlay@example.com",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(response["data"], json!([]), "{response}");
+    let (status, _) = overlay_request(
+        Method::GET,
+        "/overlay?key=limits.rcpt&account=unknown@example.com",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, 404);
+
+    // Removing a layer setting invalidates the cache
+    let (status, _) = overlay_request(
+        Method::DELETE,
+        "/config/overlay.account.overlay@example.com:limits.rcpt",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, 200);
+    let (_, response) = overlay_request(
+        Method::GET,
+        "/overlay?key=limits.rcpt&account=overlay@example.com",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(response["data"][0]["value"], "50", "{response}");
+    assert_eq!(response["data"][0]["subject"], "acme", "{response}");
+
+    // Remove test data
+    let (status, _) = overlay_request(Method::DELETE, "/config/overlay.", Value::Null).await;
+    assert_eq!(status, 200);
+    let (_, response) = overlay_request(
+        Method::GET,
+        "/overlay?account=overlay@example.com",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(response["data"], json!([]), "{response}");
+}
+
+async fn overlay_request(method: Method, path: &str, body: Value) -> (u16, Value) {
+    let mut request = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_millis(1000))
+        .build()
+        .unwrap()
+        .request(method, format!("https://127.0.0.1:8899/admin{path}"))
+        .basic_auth("admin", Some("secret"));
+    if !body.is_null() {
+        request = request.body(body.to_string());
+    }
+    let response = request.send().await.unwrap();
+    let status = response.status().as_u16();
+    let bytes = response.bytes().await.unwrap();
+
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
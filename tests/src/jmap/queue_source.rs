@@ -0,0 +1,100 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use jmap::api::queue::redact_attachments;
+
+use crate::jmap::fixture::Fixture;
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running queue source tests...");
+    Fixture::new()
+        .domain("example.com", |domain| {
+            domain.account("nosy", "secret", "Nosy User", |account| account)
+        })
+        .seed(params)
+        .await;
+
+    // Attachment bodies are replaced while the structure is preserved
+    let message = concat!(
+        "From: bill@example.com\r\n",
+        "To: jdoe@example.com\r\n",
+        "Subject: Invoice\r\n",
+        "Content-Type: multipart/mixed; boundary=\"b1\"\r\n",
+        "\r\n",
+        "--b1\r\n",
+        "Content-Type: text/plain\r\n",
+        "\r\n",
+        "Please find the invoice attached.\r\n",
+        "--b1\r\n",
+        "Content-Type: application/pdf; name=\"invoice.pdf\"\r\n",
+        "Content-Disposition: attachment; filename=\"invoice.pdf\"\r\n",
+        "Content-Transfer-Encoding: base64\r\n",
+        "\r\n",
+        "JVBERi0xLjQKJcOkw7zDtsOfCjIgMCBvYmoKPDwvTGVuZ3RoIDMgMCBSPj4Kc3RyZWFtCg==\r\n",
+        "--b1--\r\n"
+    );
+    let redacted = String::from_utf8(redact_attachments(message.as_bytes())).unwrap();
+    assert!(
+        redacted.contains("Please find the invoice attached."),
+        "{redacted}"
+    );
+    assert!(redacted.contains("filename=\"invoice.pdf\""), "{redacted}");
+    assert!(redacted.contains("[Redacted "), "{redacted}");
+    assert!(!redacted.contains("JVBERi0xLjQK"), "{redacted}");
+    assert!(redacted.ends_with("--b1--\r\n"), "{redacted}");
+
+    // Messages without attachments are returned as-is
+    let message = "From: bill@example.com\r\nSubject: Hi\r\n\r\nHello world.\r\n";
+    assert_eq!(redact_attachments(message.as_bytes()), message.as_bytes());
+
+    // Only administrators and queue inspectors can view queued messages
+    assert_eq!(
+        source_request("/12345", ("nosy@example.com", "secret")).await,
+        403
+    );
+    assert_eq!(source_request("/12345", ("admin", "secret")).await, 404);
+    assert_eq!(source_request("/invalid", ("admin", "secret")).await, 404);
+    assert_eq!(
+        source_request("/12345?redact=everything", ("admin", "secret")).await,
+        400
+    );
+}
+
+async fn source_request(path: &str, (username, secret): (&str, &str)) -> u16 {
+    reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_millis(1000))
+        .build()
+        .unwrap()
+        .get(format!("https://127.0.0.1:8899/admin/queue/source{path}"))
+        .basic_auth(username, Some(secret))
+        .send()
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
@@ -0,0 +1,259 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use jmap_proto::method::activity::ActivityEvent;
+use reqwest::Method;
+use serde_json::{json, Value};
+
+use crate::jmap::{assert_is_empty, fixture::Fixture};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running account recovery tests...");
+    let server = params.server.clone();
+    let account_id = Fixture::new()
+        .domain("example.com", |domain| {
+            domain.account("recover", "secret", "Recovery Test", |account| account)
+        })
+        .seed(params)
+        .await
+        .account("recover@example.com")
+        .id;
+    let auth = Some(("recover@example.com", "secret"));
+
+    // Managing recovery settings requires authentication
+    let (status, _) = recovery_request(Method::GET, "", None, None).await;
+    assert_eq!(status, 401);
+    let (status, response) = recovery_request(Method::GET, "", auth, None).await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        response["data"],
+        json!({
+            "email": null,
+            "emailVerified": false,
+            "codesRemaining": 0,
+            "reverifyRequired": false,
+        })
+    );
+
+    // Generate recovery codes, they are only returned once
+    let (status, response) = recovery_request(Method::POST, "/codes", auth, None).await;
+    assert_eq!(status, 200, "{response}");
+    let codes = response["data"]["codes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|code| code.as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(codes.len(), 5);
+    assert_eq!(response["data"]["status"]["codesRemaining"], 5);
+
+    // Register a recovery address, which stays unverified until the code is entered
+    let (status, response) = recovery_request(
+        Method::POST,
+        "/email",
+        auth,
+        json!({"email": "not-an-address"}).into(),
+    )
+    .await;
+    assert_eq!(status, 400, "{response}");
+    let (status, response) = recovery_request(
+        Method::POST,
+        "/email",
+        auth,
+        json!({"email": "Backup@Example.org"}).into(),
+    )
+    .await;
+    assert_eq!(status, 200, "{response}");
+    assert_eq!(response["data"]["email"], "backup@example.org");
+    assert_eq!(response["data"]["emailVerified"], false);
+    let (status, response) = recovery_request(
+        Method::POST,
+        "/email/verify",
+        auth,
+        json!({"code": "invalid"}).into(),
+    )
+    .await;
+    assert_eq!(status, 401, "{response}");
+
+    // Reset requests do not disclose whether an account exists
+    let (status, unknown) = recovery_request(
+        Method::POST,
+        "/request",
+        None,
+        json!({"account": "nobody@example.com"}).into(),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let (status, known) = recovery_request(
+        Method::POST,
+        "/request",
+        None,
+        json!({"account": "recover@example.com"}).into(),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(unknown, known);
+
+    // Invalid codes are rejected
+    let (status, response) = recovery_request(
+        Method::POST,
+        "/reset",
+        None,
+        json!({"account": "recover@example.com", "code": "invalid", "password": "new-secret"})
+            .into(),
+    )
+    .await;
+    assert_eq!(status, 401, "{response}");
+
+    // The test directory is read-only, a valid code must not be consumed
+    let (status, response) = recovery_request(
+        Method::POST,
+        "/reset",
+        None,
+        json!({"account": "recover@example.com", "code": codes[0], "password": "new-secret"})
+            .into(),
+    )
+    .await;
+    assert_eq!(status, 501, "{response}");
+    let settings = server
+        .get_recovery_settings(account_id.document_id())
+        .await
+        .unwrap();
+    assert_eq!(settings.codes.len(), 5);
+    assert!(!settings.reverify_required);
+
+    // After a reset, recovery stays locked until the settings are verified again
+    let mut settings = settings;
+    settings.reverify_required = true;
+    server
+        .set_recovery_settings(account_id.document_id(), &settings)
+        .await
+        .unwrap();
+    let (status, response) = recovery_request(
+        Method::POST,
+        "/reset",
+        None,
+        json!({"account": "recover@example.com", "code": codes[1], "password": "new-secret"})
+            .into(),
+    )
+    .await;
+    assert_eq!(status, 403, "{response}");
+    let (status, response) = recovery_request(Method::POST, "/codes", auth, None).await;
+    assert_eq!(status, 200, "{response}");
+    assert_eq!(response["data"]["status"]["reverifyRequired"], false);
+
+    // Changes to the recovery settings are audited
+    let events = server
+        .activity_log_query(account_id.document_id(), 0, 0)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|entry| entry.event == ActivityEvent::RecoveryChange)
+        .count();
+    assert_eq!(events, 3);
+
+    // Attempts are throttled before the code is checked, and throttled
+    // requests do not disclose whether an account exists either
+    while server.is_recovery_allowed(account_id.document_id()).is_ok() {}
+    while server
+        .is_unknown_recovery_allowed("Nobody@example.com")
+        .is_ok()
+    {}
+    let (status, response) = recovery_request(
+        Method::POST,
+        "/email/verify",
+        auth,
+        json!({"code": "invalid"}).into(),
+    )
+    .await;
+    assert_eq!(status, 429, "{response}");
+    let (status, mut unknown) = recovery_request(
+        Method::POST,
+        "/request",
+        None,
+        json!({"account": "nobody@example.com"}).into(),
+    )
+    .await;
+    assert_eq!(status, 429);
+    let (status, mut known) = recovery_request(
+        Method::POST,
+        "/request",
+        None,
+        json!({"account": "recover@example.com"}).into(),
+    )
+    .await;
+    assert_eq!(status, 429);
+    for response in [&mut unknown, &mut known] {
+        // Refill times depend on when each limiter was first used
+        assert!(response
+            .as_object_mut()
+            .unwrap()
+            .remove("retryAfter")
+            .is_some());
+    }
+    assert_eq!(unknown, known);
+
+    // Remove recovery settings
+    let (status, response) = recovery_request(Method::DELETE, "", auth, None).await;
+    assert_eq!(status, 200, "{response}");
+    assert_eq!(response["data"]["codesRemaining"], 0);
+    assert!(server
+        .get_recovery_settings(account_id.document_id())
+        .await
+        .unwrap()
+        .email
+        .is_none());
+    assert_is_empty(server).await;
+}
+
+async fn recovery_request(
+    method: Method,
+    path: &str,
+    auth: Option<(&str, &str)>,
+    body: Option<Value>,
+) -> (u16, Value) {
+    let mut request = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_millis(1000))
+        .build()
+        .unwrap()
+        .request(method, format!("https://127.0.0.1:8899/recovery{path}"));
+    if let Some((username, secret)) = auth {
+        request = request.basic_auth(username, Some(secret));
+    }
+    if let Some(body) = body {
+        request = request.body(body.to_string());
+    }
+    let response = request.send().await.unwrap();
+    let status = response.status().as_u16();
+    let bytes = response.bytes().await.unwrap();
+
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
@@ -362,6 +362,30 @@ pub async fn test(params: &mut JMAPTest) {
     )
     .await;
 
+    // Run environment tests
+    client
+        .sieve_script_create("test_environment", get_script("test_environment"), true)
+        .await
+        .unwrap();
+    lmtp.ingest(
+        "bill@remote.org",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@remote.org\r\n",
+            "Message-ID: <5678@example.com>\r\n",
+            "X-Spam-Status: Yes, score=7.5\r\n",
+            "Subject: Environment\r\n",
+            "\r\n",
+            "Which environment is this?"
+        ),
+    )
+    .await;
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new("<>", ["<bill@remote.org>"], "@Environment OK"),
+    )
+    .await;
+
     // Run enclose + redirect tests
     client
         .sieve_script_create(
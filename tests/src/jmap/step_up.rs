@@ -0,0 +1,247 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use directory::backend::internal::manage::ManageDirectory;
+use jmap::auth::step_up::{base32_decode, base32_encode, totp_code};
+use jmap_proto::method::activity::ActivityEvent;
+use reqwest::Method;
+use serde_json::{json, Value};
+use store::write::now;
+
+use crate::jmap::assert_is_empty;
+
+use super::JMAPTest;
+
+const ADMIN: (&str, &str) = ("admin", "secret");
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running step-up authentication tests...");
+    let server = params.server.clone();
+
+    // RFC 6238 test vectors, truncated to six digits
+    let secret = b"12345678901234567890";
+    assert_eq!(base32_encode(secret), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+    assert_eq!(totp_code(secret, 59 / 30), 287082);
+    assert_eq!(totp_code(secret, 1111111109 / 30), 81804);
+    assert_eq!(totp_code(secret, 2000000000 / 30), 279037);
+
+    // Destructive operations require a step-up verification
+    assert_eq!(admin_request("/queue/cancel?id=invalid").await, 403);
+    let (status, response) = step_up_request(Method::GET, "", None, ADMIN).await;
+    assert_eq!(status, 200, "{response}");
+    assert_eq!(response["data"]["totpEnabled"], true);
+    assert_eq!(response["data"]["verified"], false);
+
+    // Factors can not be enrolled without proving possession of the current one
+    let (status, _) = step_up_request(Method::POST, "/totp", None, ADMIN).await;
+    assert_eq!(status, 403);
+
+    // Verify using the secret provisioned in the configuration
+    let secret = base32_decode("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap();
+    let code = totp_code(&secret, now() / 30);
+    let (status, _) = step_up_request(
+        Method::POST,
+        "/verify",
+        json!({"code": format!("{:06}", (code + 1) % 1_000_000)}).into(),
+        ADMIN,
+    )
+    .await;
+    assert_eq!(status, 401);
+    let (status, response) = step_up_request(
+        Method::POST,
+        "/verify",
+        json!({"code": format!("{code:06}")}).into(),
+        ADMIN,
+    )
+    .await;
+    assert_eq!(status, 200, "{response}");
+    assert_eq!(response["data"]["verified"], true);
+
+    // Sensitive operations are allowed once verified
+    assert_eq!(admin_request("/queue/cancel?id=invalid").await, 400);
+
+    // Codes can not be replayed
+    let (status, _) = step_up_request(
+        Method::POST,
+        "/verify",
+        json!({"code": format!("{code:06}")}).into(),
+        ADMIN,
+    )
+    .await;
+    assert_eq!(status, 401);
+
+    // Rotate the factor
+    let (status, response) = step_up_request(Method::POST, "/totp", None, ADMIN).await;
+    assert_eq!(status, 200, "{response}");
+    let secret = base32_decode(response["data"]["secret"].as_str().unwrap()).unwrap();
+    assert!(response["data"]["uri"]
+        .as_str()
+        .unwrap()
+        .starts_with("otpauth://totp/"));
+    let code = totp_code(&secret, now() / 30 + 1);
+    let (status, _) = step_up_request(
+        Method::POST,
+        "/totp/confirm",
+        json!({"code": format!("{:06}", (code + 1) % 1_000_000)}).into(),
+        ADMIN,
+    )
+    .await;
+    assert_eq!(status, 401);
+    let (status, response) = step_up_request(
+        Method::POST,
+        "/totp/confirm",
+        json!({"code": format!("{code:06}")}).into(),
+        ADMIN,
+    )
+    .await;
+    assert_eq!(status, 200, "{response}");
+    assert_eq!(response["data"]["totpEnabled"], true);
+    assert_eq!(response["data"]["verified"], true);
+
+    // Accounts without a factor can not enroll one themselves
+    params
+        .directory
+        .create_test_user_with_email("stepup@example.com", "secret", "Step-up Test")
+        .await;
+    let user = ("stepup@example.com", "secret");
+    let (status, response) = step_up_request(Method::GET, "", None, user).await;
+    assert_eq!(status, 200, "{response}");
+    assert_eq!(response["data"]["totpEnabled"], false);
+    let (status, _) = step_up_request(Method::POST, "/totp", None, user).await;
+    assert_eq!(status, 403);
+    let (status, _) = step_up_request(Method::POST, "/totp?account=admin", None, user).await;
+    assert_eq!(status, 403);
+
+    // Administrators provision factors out of band
+    let (status, response) = step_up_request(
+        Method::POST,
+        "/totp?account=stepup@example.com",
+        None,
+        ADMIN,
+    )
+    .await;
+    assert_eq!(status, 200, "{response}");
+    let secret = base32_decode(response["data"]["secret"].as_str().unwrap()).unwrap();
+    let (status, _) = step_up_request(Method::POST, "/totp?account=unknown", None, ADMIN).await;
+    assert_eq!(status, 404);
+    let (status, response) = step_up_request(
+        Method::POST,
+        "/verify",
+        json!({"code": format!("{:06}", totp_code(&secret, now() / 30))}).into(),
+        user,
+    )
+    .await;
+    assert_eq!(status, 200, "{response}");
+    assert_eq!(response["data"]["totpEnabled"], true);
+    assert_eq!(response["data"]["verified"], true);
+
+    // Step-up events are audited
+    let account_id = server.store.get_account_id("admin").await.unwrap().unwrap();
+    let events = server
+        .activity_log_query(account_id, 0, 0)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|entry| entry.event == ActivityEvent::StepUp)
+        .filter_map(|entry| entry.details)
+        .collect::<Vec<_>>();
+    for event in [
+        "required:queue-cancel",
+        "failed",
+        "verified",
+        "authorized:queue-cancel",
+        "totp-enroll",
+        "totp-enabled",
+        "totp-provision:stepup@example.com",
+    ] {
+        assert!(events.iter().any(|e| e == event), "{event} {events:?}");
+    }
+
+    // Remove the factors, the one provisioned in the configuration remains
+    let user_id = server
+        .store
+        .get_account_id("stepup@example.com")
+        .await
+        .unwrap()
+        .unwrap();
+    let (status, response) = step_up_request(Method::DELETE, "/totp", None, user).await;
+    assert_eq!(status, 200, "{response}");
+    assert_eq!(response["data"]["totpEnabled"], false);
+    let (status, response) = step_up_request(Method::DELETE, "/totp", None, ADMIN).await;
+    assert_eq!(status, 200, "{response}");
+    assert_eq!(response["data"]["totpEnabled"], true);
+    assert_eq!(response["data"]["verified"], false);
+    assert_eq!(admin_request("/queue/cancel?id=invalid").await, 403);
+    for account_id in [account_id, user_id] {
+        assert!(server
+            .get_step_up_settings(account_id)
+            .await
+            .unwrap()
+            .totp
+            .is_none());
+    }
+    assert_is_empty(server).await;
+}
+
+async fn step_up_request(
+    method: Method,
+    path: &str,
+    body: Option<Value>,
+    (login, secret): (&str, &str),
+) -> (u16, Value) {
+    let mut request = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_millis(1000))
+        .build()
+        .unwrap()
+        .request(method, format!("https://127.0.0.1:8899/step-up{path}"))
+        .basic_auth(login, Some(secret));
+    if let Some(body) = body {
+        request = request.body(body.to_string());
+    }
+    let response = request.send().await.unwrap();
+    let status = response.status().as_u16();
+    let bytes = response.bytes().await.unwrap();
+
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+async fn admin_request(path: &str) -> u16 {
+    reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(Duration::from_millis(1000))
+        .build()
+        .unwrap()
+        .get(format!("https://127.0.0.1:8899/admin{path}"))
+        .basic_auth("admin", Some("secret"))
+        .send()
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
@@ -0,0 +1,221 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::mailbox::INBOX_ID;
+use jmap_proto::types::{collection::Collection, id::Id, property::Property};
+use utils::ipc::{DeliveryResult, IngestMessage};
+
+use crate::jmap::{
+    assert_is_empty, fixture::Fixture, jmap_json_request, mailbox::destroy_all_mailboxes,
+};
+
+use super::JMAPTest;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running tag filing tests...");
+    let server = params.server.clone();
+    let account_id = Fixture::new()
+        .domain("example.com", |domain| {
+            domain.account("tagged", "secret", "Tag Test", |account| account)
+        })
+        .seed(params)
+        .await
+        .account("tagged@example.com")
+        .id;
+
+    // Create rules, either linked to an existing mailbox or to a mailbox name
+    let response = jmap_json_request(
+        r##"[[ "Mailbox/set", {
+            "accountId": "$$",
+            "create": {
+                "m": { "name": "Bills" }
+            }
+          }, "0" ], [ "TagRule/set", {
+            "accountId": "$$",
+            "create": {
+                "a": { "tag": "News", "mailboxName": "Lists/News" },
+                "b": { "tag": "bills", "mailboxId": "#m" },
+                "c": { "tag": "news", "mailboxName": "Other" },
+                "d": { "tag": "orphan" },
+                "e": { "tag": "in valid", "mailboxName": "Other" }
+            }
+          }, "1" ]]"##
+            .replace("$$", &account_id.to_string()),
+        "tagged@example.com",
+        "secret",
+    )
+    .await;
+    let news_rule_id = response
+        .pointer("/methodResponses/1/1/created/a/id")
+        .and_then(|v| v.as_str())
+        .unwrap_or_else(|| panic!("Unexpected response: {response}"))
+        .to_string();
+    let bills_id = response
+        .pointer("/methodResponses/0/1/created/m/id")
+        .and_then(|v| v.as_str())
+        .unwrap()
+        .to_string();
+    assert!(
+        response
+            .pointer("/methodResponses/1/1/created/b/id")
+            .is_some(),
+        "{response}"
+    );
+    for (id, error) in [
+        ("c", "alreadyExists"),
+        ("d", "invalidProperties"),
+        ("e", "invalidProperties"),
+    ] {
+        assert_eq!(
+            response
+                .pointer(&format!("/methodResponses/1/1/notCreated/{id}/type"))
+                .and_then(|v| v.as_str()),
+            Some(error),
+            "{response}"
+        );
+    }
+
+    // Deliver messages to tagged addresses
+    let message_path = params.temp_dir.path.join("tagged.eml");
+    for (num, rcpt) in [
+        "tagged+news@example.com",
+        "tagged+NEWS@example.com",
+        "tagged+Bills@example.com",
+        "tagged+unknown@example.com",
+        "tagged@example.com",
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let message = format!(
+            concat!(
+                "From: bill@example.com\r\n",
+                "Message-ID: <tagged-{}@example.com>\r\n",
+                "Subject: TPS reports\r\n",
+                "\r\n",
+                "Did you get the memo about the TPS reports?"
+            ),
+            num
+        );
+        std::fs::write(&message_path, &message).unwrap();
+        let result = server
+            .deliver_message(IngestMessage {
+                sender_address: "bill@example.com".to_string(),
+                recipients: vec![rcpt.to_string()],
+                message_path: message_path.clone(),
+                message_size: message.len(),
+            })
+            .await;
+        assert!(
+            matches!(result.as_slice(), [DeliveryResult::Success]),
+            "{result:?}"
+        );
+    }
+    std::fs::remove_file(&message_path).unwrap();
+
+    // The News mailbox is created on first use and linked to the rule
+    let document_id = account_id.document_id();
+    let news_id = server
+        .mailbox_get_by_name(document_id, "Lists/News")
+        .await
+        .unwrap()
+        .expect("Mailbox was not created");
+    for (mailbox_id, count) in [
+        (news_id, 2),
+        (
+            Id::from_bytes(bills_id.as_bytes()).unwrap().document_id(),
+            1,
+        ),
+        (INBOX_ID, 2),
+    ] {
+        assert_eq!(
+            server
+                .get_tag(
+                    document_id,
+                    Collection::Email,
+                    Property::MailboxIds,
+                    mailbox_id
+                )
+                .await
+                .unwrap()
+                .map_or(0, |ids| ids.len()),
+            count,
+            "for mailbox {mailbox_id}"
+        );
+    }
+    let response = jmap_json_request(
+        r#"[[ "TagRule/get", {
+            "accountId": "$$",
+            "ids": ["%%"]
+          }, "0" ]]"#
+            .replace("$$", &account_id.to_string())
+            .replace("%%", &news_rule_id),
+        "tagged@example.com",
+        "secret",
+    )
+    .await;
+    let rule = response
+        .pointer("/methodResponses/0/1/list/0")
+        .unwrap_or_else(|| panic!("Unexpected response: {response}"));
+    assert_eq!(rule.get("tag").unwrap().as_str(), Some("news"));
+    assert_eq!(
+        rule.get("mailboxName").unwrap().as_str(),
+        Some("Lists/News")
+    );
+    assert_eq!(
+        rule.get("mailboxId").unwrap().as_str(),
+        Some(Id::from(news_id).to_string().as_str())
+    );
+
+    // Remove test data
+    let ids = server
+        .tag_rule_list(document_id)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(document_id, _)| format!("\"{}\"", Id::from(document_id)))
+        .collect::<Vec<_>>()
+        .join(",");
+    let response = jmap_json_request(
+        r#"[[ "TagRule/set", {
+            "accountId": "$$",
+            "destroy": [%%]
+          }, "0" ]]"#
+            .replace("$$", &account_id.to_string())
+            .replace("%%", &ids),
+        "tagged@example.com",
+        "secret",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/destroyed")
+            .and_then(|v| v.as_array())
+            .map(|v| v.len()),
+        Some(2),
+        "{response}"
+    );
+    params.client.set_default_account_id(account_id.to_string());
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
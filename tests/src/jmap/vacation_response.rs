@@ -136,6 +136,36 @@ pub async fn test(params: &mut JMAPTest) {
 
     expect_nothing(&mut smtp_rx).await;
 
+    // Resetting the vacation tracking store should trigger a new response
+    let response = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .timeout(std::time::Duration::from_millis(1000))
+        .build()
+        .unwrap()
+        .delete("https://127.0.0.1:8899/admin/store/vacation?account=jdoe@example.com")
+        .basic_auth("admin", Some("secret"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    lmtp.ingest(
+        "bill@remote.org",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@remote.org\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: TPS Report -- last reminder\r\n",
+            "\r\n",
+            "I'll need you to come in on Saturday.",
+        ),
+    )
+    .await;
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new("<jdoe@example.com>", ["<bill@remote.org>"], "@Kokomo"),
+    )
+    .await;
+
     // Vacation responses should honor the configured date ranges
     client
         .vacation_response_set_dates((Utc::now() + Duration::days(1)).timestamp().into(), None)
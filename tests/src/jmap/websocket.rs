@@ -22,7 +22,6 @@
 */
 
 use ahash::AHashSet;
-use directory::backend::internal::manage::ManageDirectory;
 use futures::StreamExt;
 use jmap_client::{
     client_ws::WebSocketMessage,
@@ -32,12 +31,11 @@ use jmap_client::{
     },
     TypeState,
 };
-use jmap_proto::types::id::Id;
 use std::time::Duration;
 
 use tokio::sync::mpsc;
 
-use crate::jmap::{assert_is_empty, mailbox::destroy_all_mailboxes, test_account_login};
+use crate::jmap::{assert_is_empty, fixture::Fixture, mailbox::destroy_all_mailboxes};
 
 use super::JMAPTest;
 
@@ -46,19 +44,15 @@ pub async fn test(params: &mut JMAPTest) {
     let server = params.server.clone();
 
     // Authenticate all accounts
-    params
-        .directory
-        .create_test_user_with_email("jdoe@example.com", "12345", "John Doe")
+    let seeded = Fixture::new()
+        .domain("example.com", |domain| {
+            domain.account("jdoe", "12345", "John Doe", |account| account)
+        })
+        .seed(params)
         .await;
-    let account_id = Id::from(
-        server
-            .store
-            .get_or_create_account_id("jdoe@example.com")
-            .await
-            .unwrap(),
-    )
-    .to_string();
-    let client = test_account_login("jdoe@example.com", "12345").await;
+    let account = seeded.account("jdoe@example.com");
+    let account_id = account.id.to_string();
+    let client = account.client().await;
 
     let mut ws_stream = client.connect_ws().await.unwrap();
 
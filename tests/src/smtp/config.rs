@@ -35,13 +35,21 @@ use store::{
     config::ConfigStore,
     LookupStore,
 };
-use tokio::net::TcpSocket;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpSocket, TcpStream},
+};
 
 use utils::{
     config::{
         ipmask::IpAddrMask, Config, DynValue, KeyLookup, Listener, Rate, Server, ServerProtocol,
+        VirtualHost,
+    },
+    listener::{
+        banner::Banner,
+        manager::{ListenerAction, ListenerStatus},
+        TcpAcceptor,
     },
-    listener::TcpAcceptor,
 };
 
 use ahash::AHashMap;
@@ -52,10 +60,10 @@ use smtp::{
         ConditionMatch, Conditions, ConfigContext, EnvelopeKey, IfBlock, IfThen, StringMatch,
         Throttle, THROTTLE_AUTH_AS, THROTTLE_REMOTE_IP, THROTTLE_SENDER_DOMAIN,
     },
-    core::Lookup,
+    core::{Lookup, SmtpSessionManager, SMTP},
 };
 
-use super::add_test_certs;
+use super::{add_test_certs, TestConfig};
 
 struct TestEnvelope {
     pub local_ip: IpAddr,
@@ -445,6 +453,7 @@ fn parse_servers() {
             internal_id: 0,
             hostname: "mx.example.org".to_string(),
             data: "Stalwart SMTP - hi there!".to_string(),
+            banner: None,
             protocol: ServerProtocol::Smtp,
             listeners: vec![Listener {
                 socket: TcpSocket::new_v4().unwrap(),
@@ -456,15 +465,20 @@ fn parse_servers() {
             }],
             acceptor: TcpAcceptor::Plain,
             tls_implicit: false,
+            tls_client_auth: None,
             max_connections: 8192,
             proxy_networks: vec![],
             blocked_ips: Arc::new(Default::default()),
+            listener_manager: Arc::new(Default::default()),
+            bandwidth: Default::default(),
+            virtual_hosts: vec![],
         },
         Server {
             id: "smtps".to_string(),
             internal_id: 1,
             hostname: "mx.example.org".to_string(),
             data: "Stalwart SMTP - hi there!".to_string(),
+            banner: None,
             protocol: ServerProtocol::Smtp,
             listeners: vec![
                 Listener {
@@ -486,15 +500,45 @@ fn parse_servers() {
             ],
             acceptor: TcpAcceptor::Plain,
             tls_implicit: true,
+            tls_client_auth: None,
             max_connections: 1024,
             proxy_networks: vec![],
             blocked_ips: Arc::new(Default::default()),
+            listener_manager: Arc::new(Default::default()),
+            bandwidth: Default::default(),
+            virtual_hosts: vec![
+                VirtualHost {
+                    id: "default-vhost".to_string(),
+                    internal_id: 3,
+                    server_names: vec![],
+                    hostname: "mx.example.com".to_string(),
+                    data: "Stalwart SMTP - hi there!".to_string(),
+                    banner: None,
+                    max_connections: u64::MAX,
+                    is_fallback: true,
+                },
+                VirtualHost {
+                    id: "example-net".to_string(),
+                    internal_id: 4,
+                    server_names: vec!["mail.example.net".to_string(), "*.example.net".to_string()],
+                    hostname: "mail.example.net".to_string(),
+                    data: "Example.net ESMTP".to_string(),
+                    banner: None,
+                    max_connections: 100,
+                    is_fallback: false,
+                },
+            ],
         },
         Server {
             id: "submission".to_string(),
             internal_id: 2,
             hostname: "submit.example.org".to_string(),
             data: "Stalwart SMTP submission at your service".to_string(),
+            banner: Banner::parse(
+                "Authorized use only.\nConnected to {hostname} at {time}.",
+                "submit.example.org",
+            )
+            .ok(),
             protocol: ServerProtocol::Smtp,
             listeners: vec![Listener {
                 socket: TcpSocket::new_v4().unwrap(),
@@ -506,9 +550,13 @@ fn parse_servers() {
             }],
             acceptor: TcpAcceptor::Plain,
             tls_implicit: true,
+            tls_client_auth: None,
             max_connections: 8192,
             proxy_networks: vec![],
             blocked_ips: Arc::new(Default::default()),
+            listener_manager: Arc::new(Default::default()),
+            bandwidth: Default::default(),
+            virtual_hosts: vec![],
         },
     ];
 
@@ -533,6 +581,11 @@ fn parse_servers() {
             "failed for {}",
             expected_server.id
         );
+        assert_eq!(
+            server.banner, expected_server.banner,
+            "failed for {}",
+            expected_server.id
+        );
         assert_eq!(
             server.protocol, expected_server.protocol,
             "failed for {}",
@@ -543,6 +596,11 @@ fn parse_servers() {
             "failed for {}",
             expected_server.id
         );
+        assert_eq!(
+            server.virtual_hosts, expected_server.virtual_hosts,
+            "failed for {}",
+            expected_server.id
+        );
         for (listener, expected_listener) in
             server.listeners.into_iter().zip(expected_server.listeners)
         {
@@ -563,6 +621,28 @@ fn parse_servers() {
             );
         }
     }
+
+    // Test banner rendering and validation
+    let banner = Banner::parse("Welcome to {hostname}.\r\n\r\nBe nice.", "mx.example.org").unwrap();
+    assert_eq!(
+        banner.render("mx.example.org"),
+        vec!["Welcome to mx.example.org.", "", "Be nice."]
+    );
+    for invalid in [
+        "",
+        "Welcome to {host}",
+        "Welcome to {hostname",
+        "Bienvenue à {hostname}",
+        "Line\u{7}feed",
+    ] {
+        assert!(
+            Banner::parse(invalid, "mx.example.org").is_err(),
+            "{invalid:?}"
+        );
+    }
+    assert!(Banner::parse(&"a".repeat(501), "mx.example.org").is_err());
+    assert!(Banner::parse(&"{hostname}".repeat(2), &"a".repeat(251)).is_err());
+    assert!(Banner::parse(&"line\n".repeat(33), "mx.example.org").is_err());
 }
 
 #[tokio::test]
@@ -762,3 +842,115 @@ impl TestEnvelope {
         }
     }
 }
+
+const LISTENERS: &str = "
+[server]
+hostname = 'mx.example.org'
+greeting = 'Test SMTP instance'
+
+[server.listener.smtp-reload]
+bind = ['127.0.0.1:9930']
+protocol = 'smtp'
+";
+
+#[tokio::test]
+async fn reload_listeners() {
+    let config = Config::new(LISTENERS).unwrap();
+    let servers = config.parse_servers().unwrap();
+    let listener_manager = servers.listener_manager.clone();
+    servers.bind(&config);
+    let core = Arc::new(SMTP::test());
+    let (shutdown_tx, _) = servers.spawn(|server, shutdown_rx| {
+        server.spawn(SmtpSessionManager::new(core.clone()), shutdown_rx)
+    });
+    let mut session = connect("127.0.0.1:9930").await.unwrap();
+
+    // Add a bind address, new listeners require a restart
+    assert_eq!(
+        listener_manager.reload(
+            Config::new(concat!(
+                "[server.listener.smtp-reload]\n",
+                "bind = ['127.0.0.1:9930', '127.0.0.1:9931']\n",
+                "[server.listener.smtp-new]\n",
+                "bind = ['127.0.0.1:9933']\n",
+                "protocol = 'smtp'\n"
+            ))
+            .unwrap()
+        ),
+        vec![
+            ListenerStatus::test("smtp-reload", "127.0.0.1:9930", ListenerAction::Unchanged),
+            ListenerStatus::test("smtp-reload", "127.0.0.1:9931", ListenerAction::Bind),
+            ListenerStatus {
+                error: Some("New listeners require a restart.".to_string()),
+                ..ListenerStatus::test("smtp-new", "127.0.0.1:9933", ListenerAction::Unchanged)
+            },
+        ]
+    );
+    connect("127.0.0.1:9931").await.unwrap();
+    assert!(connect("127.0.0.1:9933").await.is_none());
+
+    // Removed addresses stop accepting connections while existing sessions continue
+    assert_eq!(
+        listener_manager.reload(
+            Config::new("[server.listener.smtp-reload]\nbind = ['127.0.0.1:9931']\n").unwrap()
+        ),
+        vec![
+            ListenerStatus::test("smtp-reload", "127.0.0.1:9930", ListenerAction::Close),
+            ListenerStatus::test("smtp-reload", "127.0.0.1:9931", ListenerAction::Unchanged),
+        ]
+    );
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(connect("127.0.0.1:9930").await.is_none());
+    session.write_all(b"EHLO mx.foobar.org\r\n").await.unwrap();
+    assert!(read_line(&mut session).await.starts_with("250"));
+
+    // Bind errors are reported without affecting other listeners
+    let _busy = std::net::TcpListener::bind("127.0.0.1:9932").unwrap();
+    let results = listener_manager.reload(
+        Config::new("[server.listener.smtp-reload]\nbind = ['127.0.0.1:9931', '127.0.0.1:9932']\n")
+            .unwrap(),
+    );
+    assert_eq!(
+        results[0],
+        ListenerStatus::test("smtp-reload", "127.0.0.1:9931", ListenerAction::Unchanged)
+    );
+    assert_eq!(results[1].action, ListenerAction::Bind);
+    assert!(results[1].error.is_some(), "{results:?}");
+    connect("127.0.0.1:9931").await.unwrap();
+
+    let _ = shutdown_tx.send(true);
+}
+
+trait TestListenerStatus {
+    fn test(id: &str, addr: &str, action: ListenerAction) -> Self;
+}
+
+impl TestListenerStatus for ListenerStatus {
+    fn test(id: &str, addr: &str, action: ListenerAction) -> Self {
+        ListenerStatus {
+            id: id.to_string(),
+            addr: addr.to_string(),
+            action,
+            error: None,
+        }
+    }
+}
+
+async fn connect(addr: &str) -> Option<BufReader<TcpStream>> {
+    let mut stream = BufReader::new(TcpStream::connect(addr).await.ok()?);
+    let greeting = read_line(&mut stream).await;
+    assert!(
+        greeting.starts_with("220 mx.example.org Test SMTP instance"),
+        "{greeting}"
+    );
+    Some(stream)
+}
+
+async fn read_line(stream: &mut BufReader<TcpStream>) -> String {
+    let mut line = String::new();
+    tokio::time::timeout(Duration::from_secs(1), stream.read_line(&mut line))
+        .await
+        .unwrap()
+        .unwrap();
+    line
+}
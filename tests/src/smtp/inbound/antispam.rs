@@ -9,6 +9,7 @@ use std::{
 
 use crate::smtp::session::TestSession;
 use ahash::AHashMap;
+use directory::core::config::ConfigDirectory;
 use mail_auth::{dmarc::Policy, DkimResult, DmarcResult, IprevResult, SpfResult, MX};
 use sieve::runtime::Variable;
 use smtp::{
@@ -16,13 +17,17 @@ use smtp::{
     core::{Session, SessionAddress, SMTP},
     inbound::AuthResult,
     scripts::{
-        functions::html::{get_attribute, html_attr_tokens, html_img_area, html_to_tokens},
+        functions::html::{
+            get_attribute, html_analyze, html_attr_tokens, html_img_area, html_to_tokens,
+            HtmlAnalysis,
+        },
+        plugins::bayes::{self, BayesHealth},
         ScriptModification, ScriptResult,
     },
 };
-use store::config::ConfigStore;
+use store::{config::ConfigStore, Store};
 use tokio::runtime::Handle;
-use utils::config::Config;
+use utils::config::{Config, Servers};
 
 use crate::smtp::{TestConfig, TestSMTP};
 
@@ -117,6 +122,15 @@ type = "memory"
 format = "map"
 values = "file://%CFG_PATH%/maps/scores.map"
 
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "john"
+description = "John Doe"
+secret = "secret"
+email = ["john.doe@corp-intranet.org"]
+
 [resolver]
 public-suffix = "file://%LIST_PATH%/public-suffix.dat"
 
@@ -147,6 +161,7 @@ async fn antispam() {
         "messageid",
         "date",
         "from",
+        "phishing",
         "replyto",
         "recipient",
         "mime",
@@ -228,6 +243,11 @@ async fn antispam() {
     let config = Config::new(&config).unwrap();
     let mut ctx = ConfigContext::new(&[]);
     ctx.stores = config.parse_stores().await.unwrap();
+    ctx.directory = config
+        .parse_directory(&ctx.stores, &Servers::default(), Store::default())
+        .await
+        .unwrap();
+    core.queue.config.directory = ctx.directory.directories.get("local").unwrap().clone();
     core.sieve = config.parse_sieve(&mut ctx).unwrap();
     let config = &mut core.session.config;
     config.rcpt.relay = IfBlock::new(true);
@@ -494,6 +514,33 @@ async fn antispam() {
             }
         }
     }
+
+    // Repeated Pyzor checks should have been answered from the verdict cache
+    let verdicts = core
+        .sieve
+        .runtime
+        .context()
+        .verdict_stats
+        .report("spam_verdicts", None, None);
+    assert!(verdicts.hits > 0, "{verdicts:?}");
+    assert!(verdicts.insertions > 0, "{verdicts:?}");
+
+    // Replies should have been learned as ham
+    let store = &core.queue.config.lookup_store;
+    let stats = bayes::stats(store).await.unwrap();
+    assert!(stats.ham_learns > 0, "{stats:?}");
+    assert!(stats.tokens_learned > 0, "{stats:?}");
+    assert_ne!(stats.health, BayesHealth::Untrained, "{stats:?}");
+
+    // Test auto-learning opt-out
+    let address = "john.doe@corp-intranet.org";
+    assert!(!bayes::is_opted_out(store, address).await.unwrap());
+    bayes::set_opt_out(store, "John.Doe@corp-intranet.org", true)
+        .await
+        .unwrap();
+    assert!(bayes::is_opted_out(store, address).await.unwrap());
+    bayes::set_opt_out(store, address, false).await.unwrap();
+    assert!(!bayes::is_opted_out(store, address).await.unwrap());
 }
 
 #[test]
@@ -726,6 +773,27 @@ fn html_tokens() {
         ))),
         92600
     );
+
+    assert_eq!(
+        html_analyze(&html_to_tokens(concat!(
+            "<p>visible</p><div style='display:none'><b>hidden</b></div>",
+            "<p style=\"font-size: 1px !important\">tiny</p>",
+            "<div bgcolor=\"#000\"><font color=black>dark</font></div>",
+            "<img src=\"https://tracker.net/p.gif\" width=1 height=\"1\"/>",
+            "<img src=\"https://example.org/logo.png\">",
+            "<form><input type=\"password\"></form>"
+        ))),
+        HtmlAnalysis {
+            text_chars: 21,
+            hidden_chars: 6,
+            tiny_font_chars: 4,
+            same_color_chars: 4,
+            remote_images: 2,
+            tracking_pixels: 1,
+            forms: 1,
+            password_inputs: 1,
+        }
+    );
 }
 
 trait ParseConfigValue: Sized {
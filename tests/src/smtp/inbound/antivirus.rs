@@ -0,0 +1,223 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    session::{TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+use smtp::{
+    config::{session::ConfigSession, ConfigContext, EnvelopeKey, IfBlock},
+    core::{Session, SMTP},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::watch,
+};
+use utils::config::Config;
+
+const CONFIG: &str = r#"
+[session.data.antivirus]
+enable = [ { if = "sender-domain", eq = "unscanned.org", then = false },
+           { else = true } ]
+hostname = "127.0.0.1"
+port = 9344
+service = "avscan"
+
+[session.data.antivirus.options]
+reject-infected = true
+tempfail-on-error = true
+"#;
+
+const TEST_MESSAGE: &str = concat!(
+    "From: john@foobar.org\r\n",
+    "To: bill@example.org\r\n",
+    "Subject: Scan me\r\n",
+    "\r\n",
+    "{BODY}\r\n"
+);
+
+#[tokio::test]
+async fn antivirus_scan() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::TRACE)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Prepare config
+    let _tx = spawn_mock_icap_server();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let available_keys = [EnvelopeKey::Sender, EnvelopeKey::SenderDomain];
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_antivirus_test");
+    let config = &mut core.session.config;
+    config.rcpt.relay = IfBlock::new(true);
+    config.data.antivirus = Config::new(CONFIG)
+        .unwrap()
+        .parse_antivirus(&ConfigContext::new(&[]), &available_keys)
+        .unwrap();
+    assert!(config.data.antivirus.is_some());
+
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+
+    // Clean messages are accepted
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            &TEST_MESSAGE.replace("{BODY}", "Hello Bill, how are you?"),
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("Hello Bill, how are you?");
+
+    // Infected messages are rejected
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            &TEST_MESSAGE.replace("{BODY}", "EICAR test"),
+            "554 5.7.1 Message rejected, virus found: Eicar-Test-Signature.",
+        )
+        .await;
+    qr.assert_empty_queue();
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            &TEST_MESSAGE.replace("{BODY}", "VIOLATION test"),
+            "554 5.7.1 Message rejected, virus found: Win.Test.Violation.",
+        )
+        .await;
+    qr.assert_empty_queue();
+
+    // Scanner errors result in a temporary failure
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            &TEST_MESSAGE.replace("{BODY}", "ERROR test"),
+            "451 4.3.5",
+        )
+        .await;
+    qr.assert_empty_queue();
+
+    // Messages are not scanned when disabled
+    session
+        .send_message(
+            "john@unscanned.org",
+            &["bill@example.org"],
+            &TEST_MESSAGE.replace("{BODY}", "EICAR test"),
+            "250",
+        )
+        .await;
+    qr.read_event().await.unwrap_message();
+    qr.assert_empty_queue();
+}
+
+pub fn spawn_mock_icap_server() -> watch::Sender<bool> {
+    let (tx, mut rx) = watch::channel(true);
+
+    tokio::spawn(async move {
+        let listener = TcpListener::bind("127.0.0.1:9344")
+            .await
+            .unwrap_or_else(|e| {
+                panic!("Failed to bind mock ICAP server to 127.0.0.1:9344: {e}");
+            });
+        loop {
+            tokio::select! {
+                stream = listener.accept() => {
+                    match stream {
+                        Ok((stream, _)) => {
+                            tokio::spawn(accept_icap(stream));
+                        }
+                        Err(err) => {
+                            panic!("Something went wrong: {err}" );
+                        }
+                    }
+                },
+                _ = rx.changed() => {
+                    break;
+                }
+            };
+        }
+    });
+
+    tx
+}
+
+async fn accept_icap(mut stream: TcpStream) {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.ends_with(b"\r\n0\r\n\r\n") {
+        let bytes_read = stream.read(&mut buf).await.unwrap();
+        if bytes_read == 0 {
+            return;
+        }
+        request.extend_from_slice(&buf[..bytes_read]);
+    }
+    let request = String::from_utf8(request).unwrap();
+    assert!(
+        request.starts_with("RESPMOD icap://127.0.0.1:9344/avscan ICAP/1.0\r\n"),
+        "{request}"
+    );
+
+    let response = if request.contains("EICAR") {
+        concat!(
+            "ICAP/1.0 200 OK\r\n",
+            "ISTag: \"test\"\r\n",
+            "X-Infection-Found: Type=0; Resolution=2; Threat=Eicar-Test-Signature;\r\n",
+            "Encapsulated: null-body=0\r\n",
+            "\r\n"
+        )
+    } else if request.contains("VIOLATION") {
+        concat!(
+            "ICAP/1.0 200 OK\r\n",
+            "ISTag: \"test\"\r\n",
+            "X-Violations-Found: 1\r\n",
+            "\tmessage.eml\r\n",
+            "\tWin.Test.Violation\r\n",
+            "\t0\r\n",
+            "\t0\r\n",
+            "Encapsulated: null-body=0\r\n",
+            "\r\n"
+        )
+    } else if request.contains("ERROR") {
+        "ICAP/1.0 500 Server Error\r\n\r\n"
+    } else {
+        "ICAP/1.0 204 No Content\r\nISTag: \"test\"\r\n\r\n"
+    };
+    stream.write_all(response.as_bytes()).await.unwrap();
+}
@@ -0,0 +1,178 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    session::{TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+use smtp::{
+    config::{if_block::ConfigIf, ConfigContext, EnvelopeKey, IfBlock},
+    core::{Session, SMTP},
+    inbound::archive::ArchiveReport,
+};
+use utils::config::Config;
+
+const CONFIG: &str = r#"
+[session.data.archive]
+recipients = [ { if = "sender-domain", eq = "foobar.org", then = ["Archive@foobar.org", "audit@foobar.org"] },
+               { else = [] } ]
+"#;
+
+#[tokio::test]
+async fn compliance_archive() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::TRACE)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Prepare config
+    let available_keys = [EnvelopeKey::Sender, EnvelopeKey::SenderDomain];
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_archive_test");
+    let ctx = ConfigContext::new(&[]);
+    let settings = Config::new(CONFIG).unwrap();
+    let config = &mut core.session.config;
+    config.rcpt.relay = IfBlock::new(true);
+    config.data.archive = settings
+        .parse_if_block::<Vec<String>>("session.data.archive.recipients", &ctx, &available_keys)
+        .unwrap()
+        .unwrap_or_default();
+
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+
+    // Outbound messages are copied to the archive addresses
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            concat!(
+                "From: john@foobar.org\r\n",
+                "To: bill@example.org\r\n",
+                "Subject: Outbound\r\n",
+                "\r\n",
+                "Hello Bill,\r\n"
+            ),
+            "250",
+        )
+        .await;
+    let message = qr.read_event().await.unwrap_message();
+    assert_eq!(message.return_path, "john@foobar.org");
+    assert_eq!(
+        message
+            .recipients
+            .iter()
+            .map(|rcpt| rcpt.address.as_str())
+            .collect::<Vec<_>>(),
+        ["bill@example.org"]
+    );
+    message
+        .read_lines()
+        .assert_contains("Subject: Outbound")
+        .assert_not_contains("X-Compliance-Archive")
+        .assert_not_contains("archive@foobar.org");
+    let copy = qr.read_event().await.unwrap_message();
+    assert_eq!(copy.return_path, "");
+    assert_eq!(
+        copy.recipients
+            .iter()
+            .map(|rcpt| rcpt.address.as_str())
+            .collect::<Vec<_>>(),
+        ["archive@foobar.org", "audit@foobar.org"]
+    );
+    assert_ne!(copy.id, message.id);
+    copy.read_lines()
+        .assert_contains(&format!(
+            "X-Compliance-Archive: mx.example.org; queue-id={}",
+            message.id
+        ))
+        .assert_contains("Subject: Outbound")
+        .assert_contains("Hello Bill,");
+    qr.assert_empty_queue();
+
+    // Archive addresses that are also recipients do not receive a second copy
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org", "audit@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.read_event().await.unwrap_message();
+    let copy = qr.read_event().await.unwrap_message();
+    assert_eq!(
+        copy.recipients
+            .iter()
+            .map(|rcpt| rcpt.address.as_str())
+            .collect::<Vec<_>>(),
+        ["archive@foobar.org"]
+    );
+    qr.assert_empty_queue();
+
+    // Archive copies are never archived again
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            concat!(
+                "X-Compliance-Archive: mx.foobar.org; queue-id=1\r\n",
+                "From: john@foobar.org\r\n",
+                "To: bill@example.org\r\n",
+                "Subject: Looping\r\n",
+                "\r\n",
+                "Hello Bill,\r\n"
+            ),
+            "250",
+        )
+        .await;
+    qr.read_event().await.unwrap_message();
+    qr.assert_empty_queue();
+
+    // Messages from other domains are not archived
+    session
+        .send_message(
+            "jane@example.org",
+            &["bill@example.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.read_event().await.unwrap_message();
+    qr.assert_empty_queue();
+
+    assert_eq!(
+        session.core.session.archive_metrics.report(),
+        ArchiveReport {
+            queued: 2,
+            recipients: 3,
+            skipped: 1,
+            failed: 0,
+        }
+    );
+}
@@ -22,16 +22,27 @@
 */
 
 use directory::core::config::ConfigDirectory;
-use smtp_proto::{AUTH_LOGIN, AUTH_PLAIN};
+use std::sync::Arc;
+
+use smtp_proto::{AUTH_EXTERNAL, AUTH_LOGIN, AUTH_PLAIN};
 use store::{Store, Stores};
-use utils::config::{Config, DynValue, Servers};
+use utils::{
+    config::{Config, DynValue, ServerProtocol, Servers},
+    listener::{
+        tls::{ClientCertificate, TlsClientAuth},
+        ServerInstance,
+    },
+};
 
 use crate::smtp::{
-    session::{TestSession, VerifyResponse},
-    ParseTestConfig, TestConfig,
+    inbound::{TestMessage, TestQueueEvent},
+    session::{DummyIo, TestSession, VerifyResponse},
+    ParseTestConfig, TestConfig, TestSMTP,
 };
 use smtp::{
-    config::{ConfigContext, EnvelopeKey, IfBlock},
+    config::{
+        AccountSessionLimits, ConfigContext, EnvelopeKey, IfBlock, MaybeDynValue, SenderAlignment,
+    },
     core::{Session, State, SMTP},
 };
 
@@ -54,6 +65,12 @@ secret = "p4ssw0rd"
 email = "jane@example.org"
 email-list = ["info@example.org"]
 member-of = ["sales", "support"]
+
+[[directory."local".principals]]
+name = "sales"
+type = "group"
+description = "Sales"
+email = "sales@example.org"
 "#;
 
 #[tokio::test]
@@ -83,7 +100,7 @@ async fn auth() {
     config.mechanisms = format!(
         "[{{if = 'remote-ip', eq = '10.0.0.1', then = {}}},
     {{else = 0}}]",
-        AUTH_PLAIN | AUTH_LOGIN
+        AUTH_PLAIN | AUTH_LOGIN | AUTH_EXTERNAL
     )
     .as_str()
     .parse_if(&ctx);
@@ -112,6 +129,7 @@ async fn auth() {
         .assert_contains("AUTH ")
         .assert_contains(" PLAIN")
         .assert_contains(" LOGIN")
+        .assert_not_contains(" EXTERNAL")
         .assert_not_contains("FUTURERELEASE");
 
     // Invalid password should be rejected
@@ -161,6 +179,41 @@ async fn auth() {
     session.cmd("amFuZQ==", "334").await;
     session.cmd("cDRzc3cwcmQ=", "235 2.7.0").await;
 
+    // EXTERNAL authentication requires a client certificate
+    session.data.authenticated_as.clear();
+    session.data.auth_errors = 0;
+    session.cmd("AUTH EXTERNAL =", "535 5.7.8").await;
+
+    // Map the client certificate to a principal
+    let mut instance = ServerInstance::test();
+    instance.tls_client_auth = Some(TlsClientAuth {
+        principal: vec!["{email}".to_string(), "{cn}".to_string()],
+    });
+    session.instance = Arc::new(instance);
+    session.stream.tls_client_cert = Some(ClientCertificate {
+        subject: "CN=jane".to_string(),
+        common_name: Some("jane".to_string()),
+        emails: vec!["jane@example.org".to_string()],
+        ..Default::default()
+    });
+    session
+        .ehlo("mx.foobar.org")
+        .await
+        .assert_contains(" EXTERNAL");
+
+    // The authorization identity must match the certificate
+    session.data.auth_errors = 0;
+    session
+        .cmd("AUTH EXTERNAL am9obkBleGFtcGxlLm9yZw==", "535 5.7.8")
+        .await;
+    session.data.auth_errors = 0;
+    session.cmd("AUTH EXTERNAL =", "235 2.7.0").await;
+    assert_eq!(session.data.authenticated_as, "jane");
+    session.data.authenticated_as.clear();
+    session.cmd("AUTH EXTERNAL", "334").await;
+    session.cmd("amFuZUBleGFtcGxlLm9yZw==", "235 2.7.0").await;
+    session.stream.tls_client_cert = None;
+
     // Login should not be advertised to 10.0.0.2
     session.data.remote_ip = "10.0.0.2".parse().unwrap();
     session.eval_session_params().await;
@@ -175,3 +228,200 @@ async fn auth() {
         .cmd("AUTH PLAIN AGpvaG4Ac2VjcmV0", "503 5.5.1")
         .await;
 }
+
+#[tokio::test]
+async fn sender_alignment() {
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_sender_alignment_test");
+    let directory = Config::new(DIRECTORY)
+        .unwrap()
+        .parse_directory(&Stores::default(), &Servers::default(), Store::default())
+        .await
+        .unwrap();
+    let config = &mut core.session.config;
+    config.rcpt.relay = IfBlock::new(true);
+    config.auth.directory = IfBlock::new(Some(MaybeDynValue::Static(
+        directory.directories.get("local").unwrap().clone(),
+    )));
+    config.auth.mechanisms = IfBlock::new(AUTH_PLAIN | AUTH_LOGIN);
+    config.auth.must_match_sender = IfBlock::new(true);
+    config.auth.sender_alignment = IfBlock::new(SenderAlignment::Reject);
+    config.auth.sender_alignment_exempt = vec![directory::Type::Superuser];
+    config.auth.sender_alignment_delegated = true;
+
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.stream.tls = true;
+    session.ehlo("mx.foobar.org").await;
+    session
+        .cmd("AUTH PLAIN AGpvaG4Ac2VjcmV0", "235 2.7.0")
+        .await;
+    assert!(session
+        .data
+        .authenticated_emails
+        .contains(&"sales@example.org".to_string()));
+
+    // Reject mode: both the envelope sender and the From header must be aligned
+    session.mail_from("bill@foobar.org", "550 5.7.1").await;
+    session
+        .send_message(
+            "jdoe@example.org",
+            &["bill@foobar.org"],
+            "From: Bill <bill@foobar.org>\r\nSubject: Hi\r\n\r\nHi!\r\n",
+            "550 5.7.1",
+        )
+        .await;
+    qr.assert_empty_queue();
+    session
+        .send_message(
+            "jdoe@example.org",
+            &["bill@foobar.org"],
+            "From: Sales <sales@example.org>\r\nSubject: Hi\r\n\r\nHi!\r\n",
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("From: Sales <sales@example.org>");
+
+    // Senders must match the authenticated identity regardless of the alignment policy
+    session.params.auth_sender_alignment = SenderAlignment::Tag;
+    session.mail_from("bill@foobar.org", "501 5.5.4").await;
+    session.data.authenticated_type = directory::Type::Superuser.into();
+    session.mail_from("bill@foobar.org", "501 5.5.4").await;
+    session.data.authenticated_type = directory::Type::Individual.into();
+    session.params.auth_match_sender = false;
+
+    // Rewrite mode: addresses are replaced with the canonical address
+    session.params.auth_sender_alignment = SenderAlignment::Rewrite;
+    session
+        .send_message(
+            "bill@foobar.org",
+            &["bill@foobar.org"],
+            "From: Bill <bill@foobar.org>\r\nSubject: Hi\r\n\r\nHi!\r\n",
+            "250",
+        )
+        .await;
+    let message = qr.read_event().await.unwrap_message();
+    assert_eq!(message.return_path, "john@example.org");
+    message
+        .read_lines()
+        .assert_contains("From: Bill <john@example.org>")
+        .assert_contains("X-Original-From: Bill <bill@foobar.org>");
+
+    // Tag mode: misaligned messages are accepted and tagged, replacing any
+    // verdict supplied by the client
+    session.params.auth_sender_alignment = SenderAlignment::Tag;
+    session
+        .send_message(
+            "bill@foobar.org",
+            &["bill@foobar.org"],
+            concat!(
+                "X-Sender-Alignment: pass\r\n",
+                "From: Bill <bill@foobar.org>\r\n",
+                "Subject: Hi\r\n\r\nHi!\r\n"
+            ),
+            "250",
+        )
+        .await;
+    let message = qr.read_event().await.unwrap_message();
+    assert_eq!(message.return_path, "bill@foobar.org");
+    message
+        .read_lines()
+        .assert_contains("X-Sender-Alignment: fail (authenticated as john)")
+        .assert_not_contains("X-Sender-Alignment: pass")
+        .assert_contains("From: Bill <bill@foobar.org>");
+
+    // Exempt roles are not subject to the policy
+    session.params.auth_sender_alignment = SenderAlignment::Reject;
+    session.data.authenticated_type = directory::Type::Superuser.into();
+    session
+        .send_message(
+            "bill@foobar.org",
+            &["bill@foobar.org"],
+            "From: Bill <bill@foobar.org>\r\nSubject: Hi\r\n\r\nHi!\r\n",
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_not_contains("X-Sender-Alignment");
+}
+
+#[tokio::test]
+async fn account_sessions() {
+    let mut core = SMTP::test();
+    let directory = Config::new(DIRECTORY)
+        .unwrap()
+        .parse_directory(&Stores::default(), &Servers::default(), Store::default())
+        .await
+        .unwrap();
+    let config = &mut core.session.config;
+    config.auth.directory = IfBlock::new(Some(MaybeDynValue::Static(
+        directory.directories.get("local").unwrap().clone(),
+    )));
+    config.auth.mechanisms = IfBlock::new(AUTH_PLAIN);
+    config.account_sessions = AccountSessionLimits {
+        enable: true,
+        max_concurrent: 1,
+        roles: vec![],
+        overrides: [("jane".to_string(), 2)].into_iter().collect(),
+    };
+    let core = Arc::new(core);
+
+    // A second session for the same account exceeds the budget
+    let mut first = new_session(core.clone()).await;
+    first.cmd("AUTH PLAIN AGpvaG4Ac2VjcmV0", "235 2.7.0").await;
+    let mut second = new_session(core.clone()).await;
+    second.cmd("AUTH PLAIN AGpvaG4Ac2VjcmV0", "454 4.7.0").await;
+    assert!(second.data.authenticated_as.is_empty());
+
+    // The budget is shared with other protocols
+    assert_eq!(
+        core.acquire_account_session("john", directory::Type::Individual, ServerProtocol::Imap)
+            .unwrap_err(),
+        1
+    );
+
+    // Closing a session releases its slot
+    drop(first);
+    second.cmd("AUTH PLAIN AGpvaG4Ac2VjcmV0", "235 2.7.0").await;
+
+    // Account limits take precedence over the default limit
+    let _imap_session = core
+        .acquire_account_session("jane", directory::Type::Individual, ServerProtocol::Imap)
+        .unwrap();
+    let mut third = new_session(core.clone()).await;
+    third
+        .cmd("AUTH PLAIN AGphbmUAcDRzc3cwcmQ=", "235 2.7.0")
+        .await;
+    let mut fourth = new_session(core.clone()).await;
+    fourth
+        .cmd("AUTH PLAIN AGphbmUAcDRzc3cwcmQ=", "454 4.7.0")
+        .await;
+
+    let report = core.session.account_sessions.report();
+    assert_eq!((report.accounts, report.sessions), (2, 3));
+    assert_eq!(
+        report
+            .protocols
+            .iter()
+            .map(|p| (p.protocol, p.granted, p.rejected))
+            .collect::<Vec<_>>(),
+        [("imap", 1, 1), ("jmap", 0, 0), ("smtp", 3, 2)]
+    );
+}
+
+async fn new_session(core: Arc<SMTP>) -> Session<DummyIo> {
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.stream.tls = true;
+    session.ehlo("mx.foobar.org").await;
+    session
+}
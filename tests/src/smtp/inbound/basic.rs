@@ -48,6 +48,12 @@ async fn basic_commands() {
         .assert_not_contains("STARTTLS");
     session.cmd("STARTTLS", "504 5.7.4").await;
 
+    // Error responses carry a stable error code when enabled
+    session.params.error_codes = true;
+    session.cmd("STARTTLS", "504 5.7.4 [E3011]").await;
+    session.cmd("NOOP", "250").await;
+    session.params.error_codes = false;
+
     // Test NOOP
     session.cmd("NOOP", "250").await;
 
@@ -0,0 +1,194 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    session::{TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+use smtp::{
+    config::{
+        session::ConfigSession, ConfigContext, EnvelopeKey, FilterBudgetConfig, FilterStage,
+        IfBlock, VerifyStrategy,
+    },
+    core::{Session, SMTP},
+    inbound::budget::{FilterBudget, StageBudget},
+};
+use utils::config::Config;
+
+const CONFIG: &str = r#"
+[session.data.budget]
+enable = [ { if = "sender-domain", eq = "budget.org", then = true },
+           { else = false } ]
+ratio = 0.000001
+
+[session.data.budget.stage.arc]
+weight = 2
+optional = true
+
+[session.data.budget.stage.sieve]
+optional = true
+"#;
+
+#[tokio::test]
+async fn filter_budget() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::TRACE)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Parse configuration
+    let available_keys = [EnvelopeKey::Sender, EnvelopeKey::SenderDomain];
+    let ctx = ConfigContext::new(&[]);
+    let settings = Config::new(CONFIG).unwrap();
+    let budget_config = settings.parse_filter_budget(&ctx, &available_keys).unwrap();
+    let arc = budget_config.stages[FilterStage::Arc as usize];
+    assert_eq!((arc.weight, arc.optional), (2, true));
+    let sieve = budget_config.stages[FilterStage::Sieve as usize];
+    assert_eq!((sieve.weight, sieve.optional), (4, true));
+    let dkim = budget_config.stages[FilterStage::Dkim as usize];
+    assert_eq!((dkim.weight, dkim.optional), (2, false));
+    assert!(Config::new("[session.data.budget]\nratio = 1.5")
+        .unwrap()
+        .parse_filter_budget(&ctx, &available_keys)
+        .is_err());
+
+    // Unlimited budgets never skip stages
+    let mut budget = FilterBudget::unlimited();
+    for stage in FilterStage::ALL {
+        assert_eq!(
+            budget.allocate(&budget_config, stage),
+            StageBudget::Run(None)
+        );
+    }
+    assert!(budget.skipped.is_empty());
+
+    // Optional stages are skipped once the deadline has passed,
+    // mandatory stages still run with the remaining time
+    let mut budget = FilterBudget::new(&FilterBudgetConfig::default(), Duration::ZERO);
+    assert_eq!(
+        budget.allocate(&budget_config, FilterStage::Dkim),
+        StageBudget::Run(Some(Duration::ZERO))
+    );
+    assert_eq!(
+        budget.allocate(&budget_config, FilterStage::Arc),
+        StageBudget::Skip
+    );
+    assert_eq!(
+        budget.allocate(&budget_config, FilterStage::Sieve),
+        StageBudget::Skip
+    );
+    assert!(budget.is_skipped(FilterStage::Arc));
+    assert!(!budget.is_skipped(FilterStage::Dkim));
+    assert_eq!(budget.skipped(), "arc, sieve");
+
+    // Stages get their full share when there is plenty of time
+    let mut budget = FilterBudget::new(&budget_config, Duration::from_secs(3600));
+    assert!(matches!(
+        budget.allocate(&budget_config, FilterStage::Arc),
+        StageBudget::Run(Some(_))
+    ));
+    assert!(budget.skipped.is_empty());
+
+    // Prepare session
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_budget_test");
+    let config = &mut core.session.config;
+    config.rcpt.relay = IfBlock::new(true);
+    config.data.budget = budget_config;
+    let config = &mut core.mail_auth;
+    config.dkim.verify = IfBlock::new(VerifyStrategy::Disable);
+    config.dmarc.verify = IfBlock::new(VerifyStrategy::Disable);
+    config.arc.verify = IfBlock::new(VerifyStrategy::Relaxed);
+
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+
+    // Optional stages are skipped when the budget is exhausted
+    session
+        .send_message(
+            "john@budget.org",
+            &["bill@example.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("X-Filter-Skipped: arc");
+
+    // Budgets are only enforced when enabled
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_not_contains("X-Filter-Skipped");
+
+    // X-Filter-Skipped headers added by the sender are removed
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            concat!(
+                "From: john@foobar.org\r\n",
+                "To: bill@example.org\r\n",
+                "X-Filter-Skipped: dkim, dmarc\r\n",
+                "Subject: Forged\r\n",
+                "\r\n",
+                "Test message.\r\n",
+                ".\r\n"
+            ),
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_not_contains("X-Filter-Skipped");
+
+    // Skipped stages are reported by the metrics
+    let report = session
+        .core
+        .session
+        .filter_metrics
+        .report(&session.core.session.config.data.budget);
+    let arc = report.iter().find(|stage| stage.stage == "arc").unwrap();
+    assert_eq!((arc.skipped, arc.runs), (1, 1));
+}
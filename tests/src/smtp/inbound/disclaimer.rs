@@ -0,0 +1,189 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    session::{TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+use smtp::{
+    config::{if_block::ConfigIf, ConfigContext, EnvelopeKey, IfBlock},
+    core::{Session, SMTP},
+};
+use utils::config::Config;
+
+const CONFIG: &str = r#"
+[session.data.disclaimer]
+text = [ { if = "sender-domain", eq = "foobar.org", then = "CONFIDENTIAL: Foobar Legal Notice" },
+         { else = false } ]
+html = [ { if = "sender-domain", eq = "foobar.org", then = "<p>CONFIDENTIAL: Foobar HTML Notice</p>" },
+         { else = false } ]
+"#;
+
+#[tokio::test]
+async fn disclaimer() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::TRACE)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Prepare config
+    let available_keys = [EnvelopeKey::Sender, EnvelopeKey::SenderDomain];
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_disclaimer_test");
+    let ctx = ConfigContext::new(&[]);
+    let settings = Config::new(CONFIG).unwrap();
+    let config = &mut core.session.config;
+    config.rcpt.relay = IfBlock::new(true);
+    config.data.disclaimer_text = settings
+        .parse_if_block::<Option<String>>("session.data.disclaimer.text", &ctx, &available_keys)
+        .unwrap()
+        .unwrap_or_default();
+    config.data.disclaimer_html = settings
+        .parse_if_block::<Option<String>>("session.data.disclaimer.html", &ctx, &available_keys)
+        .unwrap()
+        .unwrap_or_default();
+
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+
+    // Plain text messages should include the disclaimer
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            concat!(
+                "From: john@foobar.org\r\n",
+                "To: bill@example.org\r\n",
+                "Subject: Plain text\r\n",
+                "\r\n",
+                "Hello Bill,\r\n",
+                "-- \r\n",
+                "John\r\n"
+            ),
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("Hello Bill,")
+        .assert_contains("CONFIDENTIAL: Foobar Legal Notice")
+        .assert_not_contains("Content-Transfer-Encoding: base64");
+
+    // Both alternatives should include the disclaimer, re-encoding when necessary
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            concat!(
+                "From: john@foobar.org\r\n",
+                "To: bill@example.org\r\n",
+                "Subject: Alternative\r\n",
+                "Content-Type: multipart/alternative; boundary=\"xyz\"\r\n",
+                "\r\n",
+                "--xyz\r\n",
+                "Content-Type: text/plain; charset=\"us-ascii\"\r\n",
+                "\r\n",
+                "Hello Bill,\r\n",
+                "--xyz\r\n",
+                "Content-Type: text/html; charset=\"utf-8\"\r\n",
+                "Content-Transfer-Encoding: base64\r\n",
+                "\r\n",
+                "PGh0bWw+PGJvZHk+PHA+SGVsbG8gQmlsbCw8L3A+PC9ib2R5PjwvaHRtbD4=\r\n",
+                "--xyz--\r\n"
+            ),
+            "250",
+        )
+        .await;
+    let message = qr.read_event().await.unwrap_message().read_message();
+    let message = mail_parser::MessageParser::new()
+        .parse(message.as_bytes())
+        .unwrap();
+    assert!(message
+        .body_text(0)
+        .unwrap()
+        .contains("CONFIDENTIAL: Foobar Legal Notice"));
+    let html = message.body_html(0).unwrap();
+    assert!(
+        html.contains("<p>Hello Bill,</p>\r\n<p>CONFIDENTIAL: Foobar HTML Notice</p>\r\n</body>"),
+        "{html}"
+    );
+
+    // Messages from other domains should not be modified
+    session
+        .send_message(
+            "jane@example.org",
+            &["bill@example.org"],
+            concat!(
+                "From: jane@example.org\r\n",
+                "To: bill@example.org\r\n",
+                "Subject: Exempt\r\n",
+                "\r\n",
+                "Hello Bill,\r\n"
+            ),
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_not_contains("CONFIDENTIAL");
+
+    // Signed messages should not be modified
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            concat!(
+                "From: john@foobar.org\r\n",
+                "To: bill@example.org\r\n",
+                "Subject: Signed\r\n",
+                "Content-Type: multipart/signed; protocol=\"application/pgp-signature\"; ",
+                "boundary=\"xyz\"\r\n",
+                "\r\n",
+                "--xyz\r\n",
+                "Content-Type: text/plain\r\n",
+                "\r\n",
+                "Hello Bill,\r\n",
+                "--xyz\r\n",
+                "Content-Type: application/pgp-signature\r\n",
+                "\r\n",
+                "signature\r\n",
+                "--xyz--\r\n"
+            ),
+            "250",
+        )
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_not_contains("CONFIDENTIAL");
+}
@@ -26,18 +26,34 @@ use std::{
     time::{Duration, Instant, SystemTime},
 };
 
-use mail_auth::{common::parse::TxtRecordParser, spf::Spf, IprevResult, SpfResult};
+use directory::core::config::ConfigDirectory;
+use mail_auth::{common::parse::TxtRecordParser, spf::Spf, IprevResult, SpfResult, MX};
 use smtp_proto::{MAIL_BY_NOTIFY, MAIL_BY_RETURN, MAIL_REQUIRETLS};
+use store::{Store, Stores};
+use utils::config::{Config, ServerProtocol, Servers};
 
 use crate::smtp::{
+    outbound::start_test_server,
     session::{TestSession, VerifyResponse},
     ParseTestConfig, TestConfig,
 };
 use smtp::{
-    config::{ConfigContext, IfBlock, VerifyStrategy},
+    config::{ConfigContext, IfBlock, MaybeDynValue, SenderVerify, VerifyStrategy},
     core::{Session, SMTP},
+    inbound::verify::SenderVerifyResult,
 };
 
+const DIRECTORY: &str = r#"
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "john"
+description = "John Doe"
+secret = "secret"
+email = "john@foobar.org"
+"#;
+
 #[tokio::test]
 async fn mail() {
     let mut core = SMTP::test();
@@ -228,12 +244,19 @@ async fn mail() {
     session.rset().await;
 
     // Test REQUIRETLS extension
+    session
+        .ingest(b"MAIL FROM:<jane@foobar.org> REQUIRETLS\r\n")
+        .await
+        .unwrap();
+    session.response().assert_code("530 5.7.10");
+    session.stream.tls = true;
     session
         .ingest(b"MAIL FROM:<jane@foobar.org> REQUIRETLS\r\n")
         .await
         .unwrap();
     session.response().assert_code("250");
     assert!((session.data.mail_from.as_ref().unwrap().flags & MAIL_REQUIRETLS) != 0);
+    session.stream.tls = false;
     session.rset().await;
 
     // Test DELIVERBY extension with by-mode=R
@@ -308,3 +331,123 @@ async fn mail() {
     session.response().assert_code("501 5.5.4");
     session.rset().await;
 }
+
+#[tokio::test]
+#[serial_test::serial]
+async fn sender_verify() {
+    let directory = Config::new(DIRECTORY)
+        .unwrap()
+        .parse_directory(&Stores::default(), &Servers::default(), Store::default())
+        .await
+        .unwrap();
+    let directory = directory.directories.get("local").unwrap().clone();
+
+    // Start remote server used for callouts
+    let mut core = SMTP::test();
+    core.session.config.rcpt.directory =
+        IfBlock::new(Some(MaybeDynValue::Static(directory.clone())));
+    core.session.config.rcpt.errors_wait = IfBlock::new(Duration::from_millis(10));
+    let _rx = start_test_server(core.into(), &[ServerProtocol::Smtp]);
+
+    // Add mock DNS entries
+    let mut core = SMTP::test();
+    core.resolvers.dns.mx_add(
+        "foobar.org",
+        vec![MX {
+            exchanges: vec!["mx1.foobar.org".to_string()],
+            preference: 10,
+        }],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.resolvers.dns.ipv4_add(
+        "mx1.foobar.org",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.resolvers.dns.mx_add(
+        "nullmx.org",
+        vec![MX {
+            exchanges: vec![".".to_string()],
+            preference: 0,
+        }],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.resolvers.dns.mx_add(
+        "down.org",
+        vec![MX {
+            exchanges: vec!["mx1.down.org".to_string()],
+            preference: 10,
+        }],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.resolvers.dns.ipv4_add(
+        "mx1.down.org",
+        vec!["127.0.0.2".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.session.config.mail.verify.action =
+        r"[{if = 'remote-ip', eq = '10.0.0.1', then = 'reject'},
+    {else = 'tag'}]"
+            .parse_if(&ConfigContext::new(&[]));
+
+    // Verify senders using callouts
+    let core = Arc::new(core);
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.net").await;
+    session.mail_from("john@foobar.org", "250").await;
+    assert_eq!(session.data.sender_verify, Some(SenderVerifyResult::Pass));
+    session.rset().await;
+    session.mail_from("ghost@foobar.org", "550 5.1.7").await;
+    assert_eq!(session.data.sender_verify, Some(SenderVerifyResult::Fail));
+    session.mail_from("someone@nullmx.org", "550 5.1.7").await;
+    session.mail_from("someone@down.org", "451 4.1.7").await;
+    assert_eq!(
+        session.data.sender_verify,
+        Some(SenderVerifyResult::TempError)
+    );
+
+    // Null senders are not verified
+    session.mail_from("<>", "250").await;
+    session.rset().await;
+
+    // Results are cached, except for temporary failures
+    let cache = &core.session.sender_verify;
+    assert_eq!(
+        cache.entries.get("john@foobar.org"),
+        Some(SenderVerifyResult::Pass)
+    );
+    assert_eq!(
+        cache.entries.get("ghost@foobar.org"),
+        Some(SenderVerifyResult::Fail)
+    );
+    assert_eq!(cache.entries.get("someone@down.org"), None);
+    let report = cache.stats.report("sender_verify", None, None);
+    session.mail_from("ghost@foobar.org", "550 5.1.7").await;
+    assert_eq!(
+        cache.stats.report("sender_verify", None, None).hits,
+        report.hits + 1
+    );
+
+    // Tagged results are accepted and exposed to scripts
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.2".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.net").await;
+    session.mail_from("ghost@foobar.org", "250").await;
+    assert_eq!(session.data.sender_verify, Some(SenderVerifyResult::Fail));
+
+    // Local domains are verified using the directory
+    let mut core = SMTP::test();
+    core.session.config.mail.verify.action = IfBlock::new(SenderVerify::Reject);
+    core.session.config.mail.verify.directory =
+        IfBlock::new(Some(MaybeDynValue::Static(directory)));
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.net").await;
+    session.mail_from("john@foobar.org", "250").await;
+    session.rset().await;
+    session.mail_from("jane@foobar.org", "550 5.1.7").await;
+}
@@ -0,0 +1,183 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    session::{TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+use smtp::{
+    config::{session::ConfigSession, ConfigContext, EnvelopeKey, IfBlock},
+    core::{Session, SMTP},
+    inbound::mail_loop::{LoopDomainReport, LoopReport},
+};
+use utils::config::Config;
+
+const CONFIG: &str = r#"
+[session.data.loop]
+enable = true
+header = "X-Loop-Test"
+secret = "loop-secret"
+max-hops = 2
+delivered-to = true
+"#;
+
+#[tokio::test]
+async fn loop_detection() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::TRACE)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Prepare config
+    let available_keys = [EnvelopeKey::Sender, EnvelopeKey::SenderDomain];
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_loop_test");
+    let config = &mut core.session.config;
+    config.rcpt.relay = IfBlock::new(true);
+    config.data.loop_detection = Config::new(CONFIG)
+        .unwrap()
+        .parse_loop_detection(&ConfigContext::new(&[]), &available_keys)
+        .unwrap();
+
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+
+    // Messages are stamped with the loop header
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            concat!(
+                "From: john@foobar.org\r\n",
+                "To: bill@example.org\r\n",
+                "Subject: Loop\r\n",
+                "\r\n",
+                "Hello Bill,\r\n"
+            ),
+            "250",
+        )
+        .await;
+    let first = qr.read_event().await.unwrap_message();
+    let first_hop = format!("mx.example.org ({:X})", first.id);
+    first
+        .read_lines()
+        .assert_contains(&format!("X-Loop-Test: mx.example.org id {:X}; ", first.id));
+    qr.assert_empty_queue();
+
+    // Messages returning for the same recipient are rejected
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            &first.read_message(),
+            &format!(
+                "554 5.4.6 Mail loop detected for <bill@example.org>, message path: {first_hop}."
+            ),
+        )
+        .await;
+    qr.assert_empty_queue();
+
+    // Other recipients are accepted until the maximum number of hops is reached
+    session
+        .send_message(
+            "john@foobar.org",
+            &["jane@example.org"],
+            &first.read_message(),
+            "250",
+        )
+        .await;
+    let second = qr.read_event().await.unwrap_message();
+    second.read_lines().assert_count("X-Loop-Test:", 2);
+    qr.assert_empty_queue();
+    session
+        .send_message(
+            "john@foobar.org",
+            &["mike@example.net"],
+            &second.read_message(),
+            &format!(
+                "554 5.4.6 Mail loop detected, too many hops through this server: {first_hop} -> mx.example.org ({:X}).",
+                second.id
+            ),
+        )
+        .await;
+    qr.assert_empty_queue();
+
+    // Forged loop headers do not match any recipient
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            concat!(
+                "X-Loop-Test: mx.example.org id 1; 0011223344556677\r\n",
+                "From: john@foobar.org\r\n",
+                "To: bill@example.org\r\n",
+                "Subject: Forged\r\n",
+                "\r\n",
+                "Hello Bill,\r\n"
+            ),
+            "250",
+        )
+        .await;
+    qr.read_event().await.unwrap_message();
+    qr.assert_empty_queue();
+
+    // Messages already delivered to a recipient are rejected
+    session
+        .send_message(
+            "john@foobar.org",
+            &["bill@example.org"],
+            concat!(
+                "Delivered-To: Bill@example.org\r\n",
+                "From: john@foobar.org\r\n",
+                "To: bill@example.org\r\n",
+                "Subject: Delivered\r\n",
+                "\r\n",
+                "Hello Bill,\r\n"
+            ),
+            "554 5.4.6 Mail loop detected: message was already delivered to <bill@example.org>.",
+        )
+        .await;
+    qr.assert_empty_queue();
+
+    assert_eq!(
+        session.core.session.loop_metrics.report(),
+        LoopReport {
+            total: 3,
+            domains: vec![
+                LoopDomainReport {
+                    domain: "example.org".to_string(),
+                    count: 2,
+                },
+                LoopDomainReport {
+                    domain: "example.net".to_string(),
+                    count: 1,
+                },
+            ],
+        }
+    );
+}
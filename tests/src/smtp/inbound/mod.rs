@@ -33,20 +33,28 @@ use smtp::{
 use super::{QueueReceiver, ReportReceiver};
 
 pub mod antispam;
+pub mod antivirus;
+pub mod archive;
 pub mod auth;
 pub mod basic;
+pub mod budget;
 pub mod data;
+pub mod disclaimer;
 pub mod dmarc;
 pub mod ehlo;
 pub mod limits;
 pub mod mail;
+pub mod mail_loop;
 pub mod milter;
 pub mod rcpt;
 pub mod rewrite;
 pub mod scripts;
 pub mod sign;
 pub mod throttle;
+pub mod transparent;
+pub mod validate;
 pub mod vrfy;
+pub mod xclient;
 
 impl QueueReceiver {
     pub async fn read_event(&mut self) -> queue::Event {
@@ -96,6 +96,7 @@ async fn rcpt() {
     config.errors_wait = r"[{if = 'remote-ip', eq = '10.0.0.1', then = '5ms'},
     {else = '1s'}]"
         .parse_if(&ConfigContext::new(&[]));
+    config.suggest.enable = IfBlock::new(true);
     core.session.config.throttle.rcpt_to = r"[[throttle]]
     match = {if = 'remote-ip', eq = '10.0.0.1'}
     key = 'sender'
@@ -173,4 +174,25 @@ async fn rcpt() {
     let rcpt = session.data.rcpt_to.last().unwrap();
     assert!((rcpt.flags & (RCPT_NOTIFY_DELAY | RCPT_NOTIFY_SUCCESS | RCPT_NOTIFY_FAILURE)) != 0);
     assert_eq!(rcpt.dsn_info.as_ref().unwrap(), "Jane.Doe@Foobar.org");
+
+    // Unauthenticated senders should not receive suggestions
+    session
+        .cmd("RCPT TO:<jnae@foobar.org>", "550 5.1.2")
+        .await
+        .assert_not_contains("Did you mean");
+
+    // Suggest similar addresses to authenticated users
+    session.data.authenticated_as = "john".to_string();
+    session
+        .cmd("RCPT TO:<jnae@foobar.org>", "550 5.1.2")
+        .await
+        .assert_contains("Did you mean <jane@foobar.org>?");
+    session
+        .cmd("RCPT TO:<jon@foobar.org>", "550 5.1.2")
+        .await
+        .assert_contains("Did you mean <john@foobar.org> or <jane@foobar.org>?");
+    session
+        .cmd("RCPT TO:<zachary@foobar.org>", "550 5.1.2")
+        .await
+        .assert_not_contains("Did you mean");
 }
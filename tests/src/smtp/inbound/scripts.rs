@@ -67,6 +67,9 @@ return-path = ""
 hostname = "mx.foobar.org"
 sign = ["rsa"]
 
+[sieve.trusted.environment]
+version = "minor"
+
 [sieve.trusted.limits]
 redirects = 3
 out-messages = 5
@@ -28,6 +28,7 @@ use smtp::{
     config::ConfigContext,
     core::{Session, SessionAddress, SMTP},
 };
+use utils::listener::tls::ClientCertificate;
 
 #[tokio::test]
 async fn throttle_inbound() {
@@ -111,3 +112,56 @@ async fn throttle_inbound() {
     session.data.remote_ip = "10.0.0.2".parse().unwrap();
     assert!(session.is_allowed().await, "Rate limiter too strict.");
 }
+
+#[tokio::test]
+async fn throttle_trusted_peer() {
+    let mut core = SMTP::test();
+    let config = &mut core.session.config;
+    config.throttle.connect = r"[[throttle]]
+    key = 'remote-ip'
+    rate = '1/1h'
+    "
+    .parse_throttle(&ConfigContext::new(&[]));
+    config
+        .trusted_peers
+        .fingerprints
+        .insert("a1b2c3".to_string());
+    config
+        .trusted_peers
+        .issuers
+        .push("C=US, O=Partner Inc, CN=Partner CA".to_string());
+
+    // Untrusted certificates are throttled
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.stream.tls_client_cert = Some(ClientCertificate {
+        issuer: "CN=Unknown CA".to_string(),
+        fingerprint: "d4e5f6".to_string(),
+        ..Default::default()
+    });
+    session.verify_trusted_peer();
+    assert!(session.data.trusted_peer.is_none());
+    assert!(session.is_allowed().await, "Rate limiter too strict.");
+    assert!(!session.is_allowed().await, "Rate limiter failed.");
+
+    // Certificates matching a trusted fingerprint bypass throttling
+    session.stream.tls_client_cert = Some(ClientCertificate {
+        issuer: "CN=Unknown CA".to_string(),
+        fingerprint: "a1b2c3".to_string(),
+        ..Default::default()
+    });
+    session.verify_trusted_peer();
+    assert!(session.data.trusted_peer.is_some());
+    assert!(session.is_allowed().await, "Trusted peer was throttled.");
+
+    // Certificates issued by a trusted CA bypass throttling
+    session.data.trusted_peer = None;
+    session.stream.tls_client_cert = Some(ClientCertificate {
+        issuer: "c=US, o=Partner Inc, cn=Partner CA".to_string(),
+        fingerprint: "d4e5f6".to_string(),
+        ..Default::default()
+    });
+    session.verify_trusted_peer();
+    assert!(session.data.trusted_peer.is_some());
+    assert!(session.is_allowed().await, "Trusted peer was throttled.");
+}
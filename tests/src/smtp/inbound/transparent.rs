@@ -0,0 +1,142 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    session::{TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+use smtp::{
+    config::{
+        if_block::ConfigIf, remote::ConfigHost, session::ConfigSession, ConfigContext, EnvelopeKey,
+        IfBlock,
+    },
+    core::{Session, SMTP},
+    queue::MAIL_TRANSPARENT,
+};
+use utils::config::{Config, DynValue};
+
+const CONFIG: &str = r#"
+[remote.mta]
+address = "mta.example.org"
+port = 25
+protocol = "smtp"
+
+[session.transparent]
+enable = [ { if = "remote-ip", eq = "10.0.0.1", then = true },
+           { else = false } ]
+next-hop = "mta"
+
+[session.mail]
+rewrite = [ { if = "sender-domain", eq = "foobar.net", then = "rewritten@foobar.org" },
+            { else = false } ]
+"#;
+
+const TEST_MESSAGE: &str = concat!(
+    "From: john@foobar.net\r\n",
+    "To: bill@example.org\r\n",
+    "Subject: Forward me\r\n",
+    "\r\n",
+    "Hello Bill, how are you?\r\n"
+);
+
+#[tokio::test]
+async fn transparent_relay() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::TRACE)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Prepare config
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_transparent_test");
+    let mut ctx = ConfigContext::new(&[]);
+    let settings = Config::new(CONFIG).unwrap();
+    settings.parse_remote_hosts(&mut ctx).unwrap();
+    let config = &mut core.session.config;
+    config.rcpt.relay = IfBlock::new(true);
+    config.mail.rewrite = settings
+        .parse_if_block::<Option<DynValue<EnvelopeKey>>>(
+            "session.mail.rewrite",
+            &ctx,
+            &[EnvelopeKey::SenderDomain],
+        )
+        .unwrap()
+        .unwrap_or_default();
+    config.transparent = settings.parse_transparent_relay(&ctx).unwrap();
+    assert_eq!(
+        config.transparent.next_hop.as_ref().unwrap().address,
+        "mta.example.org"
+    );
+    let core = std::sync::Arc::new(core);
+
+    // Messages are forwarded with the original envelope and contents
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    assert!(session.params.transparent);
+    session.ehlo("mx.foobar.net").await;
+    session
+        .send_message(
+            "john@foobar.net",
+            &["bill@example.org"],
+            TEST_MESSAGE,
+            "250",
+        )
+        .await;
+    let message = qr.read_event().await.unwrap_message();
+    assert!(message.has_flag(MAIL_TRANSPARENT));
+    assert_eq!(message.return_path, "john@foobar.net");
+    assert_eq!(message.read_message(), format!("{TEST_MESSAGE}\r\n"));
+
+    // Other listeners rewrite the envelope and add headers
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.2".parse().unwrap();
+    session.eval_session_params().await;
+    assert!(!session.params.transparent);
+    session.ehlo("mx.foobar.net").await;
+    session
+        .send_message(
+            "john@foobar.net",
+            &["bill@example.org"],
+            TEST_MESSAGE,
+            "250",
+        )
+        .await;
+    let message = qr.read_event().await.unwrap_message();
+    assert!(!message.has_flag(MAIL_TRANSPARENT));
+    assert_eq!(message.return_path, "rewritten@foobar.org");
+    message
+        .read_lines()
+        .assert_contains("Received: ")
+        .assert_contains("Return-Path: <rewritten@foobar.org>");
+    qr.assert_empty_queue();
+
+    // A next hop is required when transparent relay mode is enabled
+    assert!(Config::new("[session.transparent]\nenable = true\n")
+        .unwrap()
+        .parse_transparent_relay(&ctx)
+        .is_err());
+}
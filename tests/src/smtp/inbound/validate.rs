@@ -0,0 +1,146 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    session::{TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+use smtp::{
+    config::{if_block::ConfigIf, ConfigContext, EnvelopeKey, IfBlock, MessageValidation},
+    core::{Session, SMTP},
+    inbound::validate::{validate_message, Violation},
+};
+use utils::config::Config;
+
+const CONFIG: &str = r#"
+[session.data]
+validation = [ { if = "sender-domain", eq = "strict.org", then = "strict" },
+               { if = "sender-domain", eq = "fix.org", then = "fix" },
+               { else = "off" } ]
+"#;
+
+#[tokio::test]
+async fn validate() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::TRACE)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Test violation detection
+    assert_eq!(
+        validate_message(
+            concat!(
+                "Date: Sat, 20 Nov 2021 14:22:01 -0800\r\n",
+                "From: john@example.org\r\n",
+                "Subject: Hi\r\n",
+                "\r\n",
+                "Hello\r\n"
+            )
+            .as_bytes()
+        ),
+        vec![]
+    );
+    assert_eq!(
+        validate_message(
+            concat!(
+                "Subject: Hi\r\n",
+                "Subject: Hi again\r\n",
+                "To: bill@example.org,\r\n",
+                " jane@example.org\r\n",
+                "this is not a header\r\n",
+                "\r\n",
+                "Hello\n"
+            )
+            .as_bytes()
+        ),
+        vec![
+            Violation::MissingHeader("Date"),
+            Violation::MissingHeader("From"),
+            Violation::DuplicateHeader("Subject"),
+            Violation::MalformedHeader,
+            Violation::BareLineFeed,
+        ]
+    );
+
+    // Prepare config
+    let available_keys = [EnvelopeKey::Sender, EnvelopeKey::SenderDomain];
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_validate_test");
+    let ctx = ConfigContext::new(&[]);
+    let settings = Config::new(CONFIG).unwrap();
+    let config = &mut core.session.config;
+    config.rcpt.relay = IfBlock::new(true);
+    config.data.add_date = IfBlock::new(false);
+    config.data.validation = settings
+        .parse_if_block::<MessageValidation>("session.data.validation", &ctx, &available_keys)
+        .unwrap()
+        .unwrap_or_default();
+
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.foobar.org").await;
+
+    let malformed = concat!(
+        "To: bill@example.org\r\n",
+        "Subject: Malformed\r\n",
+        "Subject: Malformed again\r\n",
+        "\r\n",
+        "Hello Bill,\nbye.\r\n"
+    );
+
+    // Strict mode should reject the message listing all violations
+    session
+        .send_message("john@strict.org", &["bill@example.org"], malformed, "550")
+        .await;
+    qr.assert_empty_queue();
+
+    // Fix mode should normalize the message
+    session
+        .send_message("john@fix.org", &["bill@example.org"], malformed, "250")
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("Date: ")
+        .assert_contains("From: <john@fix.org>")
+        .assert_contains("Subject: Malformed")
+        .assert_not_contains("Subject: Malformed again")
+        .assert_contains("Hello Bill,")
+        .assert_contains("bye.");
+
+    // Validation is disabled for other senders
+    session
+        .send_message("john@example.org", &["bill@example.org"], malformed, "250")
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_not_contains("Date: ")
+        .assert_contains("Subject: Malformed again");
+}
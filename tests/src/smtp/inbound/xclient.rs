@@ -0,0 +1,157 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use directory::core::config::ConfigDirectory;
+use store::{Store, Stores};
+use utils::config::{Config, Servers};
+
+use crate::smtp::{
+    session::{TestSession, VerifyResponse},
+    ParseTestConfig, TestConfig,
+};
+use smtp::{
+    config::{ConfigContext, IfBlock, MaybeDynValue},
+    core::{Session, SMTP},
+};
+
+const DIRECTORY: &str = r#"
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "john"
+description = "John Doe"
+secret = "secret"
+email = ["john@foobar.org", "sales@foobar.org"]
+"#;
+
+#[tokio::test]
+async fn xclient() {
+    let mut core = SMTP::test();
+    let ctx = ConfigContext::new(&[]);
+
+    let directory = Config::new(DIRECTORY)
+        .unwrap()
+        .parse_directory(&Stores::default(), &Servers::default(), Store::default())
+        .await
+        .unwrap();
+    core.session.config.auth.directory = IfBlock::new(Some(MaybeDynValue::Static(
+        directory.directories.get("local").unwrap().clone(),
+    )));
+    core.session.config.extensions.xclient = r"[{if = 'remote-ip', eq = '10.0.0.1', then = true},
+    {else = false}]"
+        .parse_if(&ctx);
+
+    // Untrusted clients should not be offered XCLIENT
+    let mut session = Session::test(core);
+    session.data.remote_ip = "10.0.0.2".parse().unwrap();
+    session.eval_session_params().await;
+    session
+        .ehlo("mx.foobar.org")
+        .await
+        .assert_not_contains("XCLIENT")
+        .assert_not_contains("XFORWARD");
+    session.cmd("XCLIENT ADDR=192.168.1.5", "550 5.7.0").await;
+    assert_eq!(session.data.remote_ip.to_string(), "10.0.0.2");
+
+    // Trusted upstream proxy
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session
+        .ehlo("mx.foobar.org")
+        .await
+        .assert_contains("XCLIENT NAME ADDR")
+        .assert_contains("XFORWARD NAME ADDR");
+    session
+        .cmd("XCLIENT ADDR=192.168.1.5 FOO=bar", "501 5.5.4")
+        .await;
+    session.mail_from("bill@foobar.org", "250").await;
+    session.cmd("XCLIENT ADDR=192.168.1.5", "503 5.5.1").await;
+    session.rset().await;
+
+    // XFORWARD only updates the client information
+    session
+        .cmd(
+            "XFORWARD NAME=[UNAVAILABLE] ADDR=IPV6:2001:db8::1 PORT=1234",
+            "250 2.0.0",
+        )
+        .await;
+    assert_eq!(session.data.remote_ip.to_string(), "2001:db8::1");
+    assert_eq!(session.data.remote_port, 1234);
+
+    // Commands split across reads are buffered until the line is complete
+    session.ingest(b"NOOP\r\nXFOR").await.unwrap();
+    session.response().assert_code("250 2.0.0");
+    for chunk in ["ward ADDR=192.0", ".2.7 PORT"] {
+        session.ingest(chunk.as_bytes()).await.unwrap();
+        assert!(session.stream.tx_buf.is_empty());
+    }
+    session.ingest(b"=99\r\n").await.unwrap();
+    session.response().assert_code("250 2.0.0");
+    assert_eq!(session.data.remote_ip.to_string(), "192.0.2.7");
+    assert_eq!(session.data.remote_port, 99);
+    session.ingest(b"XCLIENTS ADDR").await.unwrap();
+    session.ingest(b"=192.0.2.8\r\n").await.unwrap();
+    session.response().assert_code("500 5.5.1");
+    assert_eq!(session.data.remote_ip.to_string(), "192.0.2.7");
+
+    // XFORWARD attributes only last for the current transaction
+    session
+        .cmd("XFORWARD HELO=relay+2Efoobar.org", "250 2.0.0")
+        .await;
+    assert_eq!(session.data.helo_domain, "relay.foobar.org");
+    session.rset().await;
+    assert_eq!(session.data.remote_ip.to_string(), "10.0.0.1");
+    assert_eq!(session.data.remote_port, 0);
+    assert_eq!(session.data.helo_domain, "mx.foobar.org");
+    session.cmd("XFORWARD ADDR=192.0.2.9", "250 2.0.0").await;
+    session.ehlo("mx.foobar.org").await;
+    assert_eq!(session.data.remote_ip.to_string(), "10.0.0.1");
+    assert!(session.data.xforward.is_none());
+
+    // XCLIENT restarts the session as the original client
+    session
+        .cmd(
+            "XCLIENT ADDR=192.168.1.5 PORT=4321 HELO=client+2Efoobar.org LOGIN=john",
+            "220",
+        )
+        .await;
+    assert_eq!(session.data.remote_ip.to_string(), "192.168.1.5");
+    assert_eq!(session.data.remote_port, 4321);
+    assert_eq!(session.data.helo_domain, "client.foobar.org");
+    assert_eq!(session.data.authenticated_as, "john");
+    assert_eq!(
+        session.data.authenticated_emails,
+        vec![
+            "john@foobar.org".to_string(),
+            "sales@foobar.org".to_string()
+        ]
+    );
+
+    // The original client is not a trusted proxy
+    session
+        .ehlo("client.foobar.org")
+        .await
+        .assert_not_contains("XCLIENT");
+    session.cmd("XCLIENT ADDR=10.0.0.1", "550 5.7.0").await;
+}
@@ -28,13 +28,14 @@ use mail_auth::{IpLookupStrategy, MX};
 use ::smtp::{config::IfBlock, core::SMTP, outbound::NextHop};
 use mail_parser::DateTime;
 use smtp::{
-    config::AggregateFrequency,
+    config::{queue::ConfigQueue, AggregateFrequency},
     outbound::{
         lookup::ToNextHop,
         mta_sts::{Mode, MxPattern, Policy},
     },
     queue::RecipientDomain,
 };
+use utils::config::Config;
 
 use crate::smtp::TestConfig;
 
@@ -104,6 +105,45 @@ async fn lookup_ip() {
     assert!(resolve_result
         .remote_ips
         .contains(&"e:f::a".parse().unwrap()));
+
+    // Source IP pools override the default source addresses
+    let pools = Config::new(
+        r#"
+[queue.ip-pool."marketing"]
+v4 = ["10.0.1.1", "10.0.1.2"]
+v6 = ["a:c::1"]
+"#,
+    )
+    .unwrap()
+    .parse_source_ip_pools()
+    .unwrap();
+    assert!(Config::new("[queue.ip-pool.\"empty\"]\nv4 = []\n")
+        .unwrap()
+        .parse_source_ip_pools()
+        .is_err());
+    let pool = pools.get("marketing").unwrap().clone();
+    core.queue.config.source_ip.pool = IfBlock::new(Some(pool.clone()));
+    for strategy in [
+        IpLookupStrategy::Ipv4thenIpv6,
+        IpLookupStrategy::Ipv6thenIpv4,
+    ] {
+        core.queue.config.ip_strategy = IfBlock::new(strategy);
+        let resolve_result = core
+            .resolve_host(
+                &NextHop::MX("mx.foobar.org"),
+                &RecipientDomain::new("envelope"),
+                2,
+            )
+            .await
+            .unwrap();
+        assert!(pool
+            .ipv4
+            .contains(&match resolve_result.source_ipv4.unwrap() {
+                std::net::IpAddr::V4(v4) => v4,
+                _ => unreachable!(),
+            }));
+        assert_eq!(resolve_result.source_ipv6, Some("a:c::1".parse().unwrap()));
+    }
 }
 
 #[test]
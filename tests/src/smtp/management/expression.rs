@@ -0,0 +1,192 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use directory::core::config::ConfigDirectory;
+use reqwest::Url;
+use store::{Store, Stores};
+use utils::config::{Config, ServerProtocol, Servers};
+
+use crate::smtp::{management::send_manage_request, outbound::start_test_server, TestConfig};
+use smtp::core::{
+    eval::{EvalResult, EvalStep},
+    SMTP,
+};
+
+const DIRECTORY: &str = r#"
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "admin"
+type = "admin"
+description = "Superuser"
+secret = "secret"
+member-of = ["superusers"]
+
+"#;
+
+const CONFIG: &str = r#"
+[session.rcpt]
+relay = [ { if = "authenticated-as", ne = "", then = true },
+          { if = "rcpt-domain", eq = "foobar.org", then = true },
+          { else = false } ]
+"#;
+
+#[tokio::test]
+#[serial_test::serial]
+async fn manage_expressions() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::DEBUG)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Start management service
+    let mut core = SMTP::test();
+    let directory = Config::new(DIRECTORY)
+        .unwrap()
+        .parse_directory(&Stores::default(), &Servers::default(), Store::default())
+        .await
+        .unwrap();
+    core.queue.config.directory = directory.directories.get("local").unwrap().clone();
+    core.eval.settings = Config::new(CONFIG).unwrap();
+    core.eval.listeners.insert("smtp".to_string(), 1);
+    let core = Arc::new(core);
+    let _rx_manage = start_test_server(core.clone(), &[ServerProtocol::Http]);
+
+    // Evaluate a setting from the configuration file
+    assert_eq!(
+        eval(&[("key", "session.rcpt.relay"), ("rcpt", "John@Foobar.org")])
+            .await
+            .unwrap_data(),
+        EvalResult {
+            result: vec!["true".to_string()],
+            matched: Some(1),
+            trace: vec![
+                EvalStep {
+                    block: 0,
+                    key: "authenticated-as".to_string(),
+                    value: "".to_string(),
+                    op: "ne".to_string(),
+                    expected: "".to_string(),
+                    matched: false,
+                },
+                EvalStep {
+                    block: 1,
+                    key: "rcpt-domain".to_string(),
+                    value: "foobar.org".to_string(),
+                    op: "eq".to_string(),
+                    expected: "foobar.org".to_string(),
+                    matched: true,
+                }
+            ],
+        }
+    );
+    let result = eval(&[
+        ("key", "session.rcpt.relay"),
+        ("rcpt", "jane@example.org"),
+        ("authenticated-as", "jane"),
+    ])
+    .await
+    .unwrap_data();
+    assert_eq!(result.result, vec!["true".to_string()]);
+    assert_eq!(result.matched, Some(0));
+    assert_eq!(result.trace.len(), 1);
+    let result = eval(&[("key", "session.rcpt.relay"), ("rcpt", "jane@example.org")])
+        .await
+        .unwrap_data();
+    assert_eq!(result.result, vec!["false".to_string()]);
+    assert_eq!(result.matched, None);
+    assert_eq!(result.trace.len(), 2);
+
+    // Evaluate an inline expression
+    let expr = concat!(
+        "[{if = \"remote-ip\", eq = \"10.0.0.0/8\", then = \"internal\"}, ",
+        "{any-of = [{if = \"listener\", eq = \"smtp\"}, {if = \"priority\", eq = 1}], ",
+        "then = \"relay\"}, {else = \"external\"}]"
+    );
+    for (params, expected_result, expected_trace) in [
+        (
+            vec![("remote-ip", "10.0.0.1")],
+            "internal",
+            vec![("remote-ip", "10.0.0.1", "10.0.0.0/8", true)],
+        ),
+        (
+            vec![("remote-ip", "192.168.1.1"), ("listener", "smtp")],
+            "relay",
+            vec![
+                ("remote-ip", "192.168.1.1", "10.0.0.0/8", false),
+                ("listener", "smtp", "1", true),
+            ],
+        ),
+        (
+            vec![("remote-ip", "192.168.1.1"), ("priority", "2")],
+            "external",
+            vec![
+                ("remote-ip", "192.168.1.1", "10.0.0.0/8", false),
+                ("listener", "", "1", false),
+                ("priority", "2", "1", false),
+            ],
+        ),
+    ] {
+        let mut query = vec![("expr", expr)];
+        query.extend(params);
+        let result = eval(&query).await.unwrap_data();
+        assert_eq!(result.result, vec![expected_result.to_string()]);
+        assert_eq!(
+            result
+                .trace
+                .iter()
+                .map(|step| (
+                    step.key.as_str(),
+                    step.value.as_str(),
+                    step.expected.as_str(),
+                    step.matched
+                ))
+                .collect::<Vec<_>>(),
+            expected_trace,
+            "{query:?}"
+        );
+    }
+
+    // Invalid requests
+    for query in [
+        vec![("rcpt", "jane@example.org")],
+        vec![("key", "session.rcpt.relay"), ("listener", "imap")],
+        vec![("key", "session.data.unknown")],
+        vec![("expr", "[{if = \"unknown-key\", eq = 1, then = 1}]")],
+    ] {
+        let (error, _) = eval(&query).await.unwrap_error();
+        assert_eq!(error, "bad-parameters", "{query:?}");
+    }
+}
+
+async fn eval(params: &[(&str, &str)]) -> super::Response<EvalResult> {
+    let url = Url::parse_with_params("https://127.0.0.1/admin/expression/eval", params).unwrap();
+    send_manage_request::<EvalResult>(&format!("{}?{}", url.path(), url.query().unwrap()))
+        .await
+        .unwrap()
+}
@@ -26,6 +26,8 @@ use std::time::Duration;
 use reqwest::header::AUTHORIZATION;
 use serde::{de::DeserializeOwned, Deserialize};
 
+pub mod expression;
+pub mod moderation;
 pub mod queue;
 pub mod report;
 
@@ -0,0 +1,187 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use directory::core::config::ConfigDirectory;
+use store::{Store, Stores};
+use utils::config::{Config, ServerProtocol, Servers};
+
+use crate::smtp::{
+    management::send_manage_request, outbound::start_test_server, session::TestSession, TestConfig,
+};
+use smtp::{
+    config::IfBlock,
+    core::{
+        management::{ModeratedAccount, ParkedMessage},
+        Session, SMTP,
+    },
+    queue::{
+        manager::{Queue, SpawnQueue},
+        QueueId,
+    },
+};
+
+const DIRECTORY: &str = r#"
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "admin"
+type = "admin"
+description = "Superuser"
+secret = "secret"
+member-of = ["superusers"]
+
+"#;
+
+#[tokio::test]
+#[serial_test::serial]
+async fn manage_moderation() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::DEBUG)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Start local management interface
+    let mut core = SMTP::test();
+    let directory = Config::new(DIRECTORY)
+        .unwrap()
+        .parse_directory(&Stores::default(), &Servers::default(), Store::default())
+        .await
+        .unwrap();
+    core.queue.config.directory = directory.directories.get("local").unwrap().clone();
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.queue.config.retry = IfBlock::new(vec![std::time::Duration::from_secs(1000)]);
+    let local_qr = core.init_test_queue("smtp_manage_moderation");
+    let core = Arc::new(core);
+    local_qr.queue_rx.spawn(core.clone(), Queue::default());
+    let _rx_manage = start_test_server(core.clone(), &[ServerProtocol::Http]);
+
+    // Place an account under moderation
+    assert!(
+        send_manage_request::<bool>("/admin/moderation/enable?account=John&reason=Spam%20run")
+            .await
+            .unwrap()
+            .unwrap_data()
+    );
+    assert_eq!(
+        send_manage_request::<Vec<ModeratedAccount>>("/admin/moderation/accounts")
+            .await
+            .unwrap()
+            .unwrap_data(),
+        vec![ModeratedAccount {
+            account: "john".to_string(),
+            reason: "Spam run".to_string(),
+        }]
+    );
+    let (error, _) = send_manage_request::<bool>("/admin/moderation/enable?reason=none")
+        .await
+        .unwrap()
+        .unwrap_error();
+    assert_eq!(error, "bad-parameters");
+
+    // Messages submitted by the moderated account are parked
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.data.authenticated_as = "john".to_string();
+    session.eval_session_params().await;
+    session.ehlo("foobar.net").await;
+    for rcpt in ["jane@example.org", "bill@example.org"] {
+        session
+            .send_message("john@foobar.net", &[rcpt], "test:no_dkim", "250")
+            .await;
+    }
+    let parked = parked_messages("").await;
+    assert_eq!(parked.len(), 2, "{parked:?}");
+    for (message, rcpt) in parked.iter().zip(["jane@example.org", "bill@example.org"]) {
+        assert_eq!(message.account, "john");
+        assert_eq!(message.return_path, "john@foobar.net");
+        assert_eq!(message.recipients, vec![rcpt.to_string()]);
+    }
+    assert!(parked_messages("?account=jane").await.is_empty());
+    assert_eq!(parked_messages("?account=john").await, parked);
+
+    // Parked messages are not listed in the queue
+    assert!(send_manage_request::<Vec<QueueId>>("/admin/queue/list")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .is_empty());
+
+    // Reject the first message
+    assert_eq!(
+        send_manage_request::<Vec<QueueId>>(&format!(
+            "/admin/moderation/reject?id={}",
+            parked[0].id
+        ))
+        .await
+        .unwrap()
+        .unwrap_data(),
+        vec![parked[0].id]
+    );
+    assert_eq!(parked_messages("").await, parked[1..]);
+
+    // Approve the remaining messages of the account
+    assert_eq!(
+        send_manage_request::<Vec<QueueId>>("/admin/moderation/approve?account=john")
+            .await
+            .unwrap()
+            .unwrap_data(),
+        vec![parked[1].id]
+    );
+    assert!(parked_messages("").await.is_empty());
+
+    // Messages are no longer parked once moderation is lifted
+    assert!(
+        send_manage_request::<bool>("/admin/moderation/disable?account=john")
+            .await
+            .unwrap()
+            .unwrap_data()
+    );
+    assert!(
+        send_manage_request::<Vec<ModeratedAccount>>("/admin/moderation/accounts")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .is_empty()
+    );
+    session
+        .send_message(
+            "john@foobar.net",
+            &["jane@example.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    assert!(parked_messages("").await.is_empty());
+}
+
+async fn parked_messages(query: &str) -> Vec<ParkedMessage> {
+    send_manage_request(&format!("/admin/moderation/list{query}"))
+        .await
+        .unwrap()
+        .unwrap_data()
+}
@@ -25,7 +25,7 @@ use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use ahash::AHashMap;
 use dashmap::DashMap;
-use directory::{AddressMapping, Directory, DirectoryInner};
+use directory::{core::reserved::ReservedAddresses, AddressMapping, Directory, DirectoryInner};
 use mail_auth::{
     common::lru::{DnsCache, LruCache},
     hickory_resolver::config::{ResolverConfig, ResolverOpts},
@@ -40,17 +40,24 @@ use tokio::sync::mpsc;
 use smtp::{
     config::{
         if_block::ConfigIf, queue::ConfigQueue, scripts::SieveContext, session::ConfigSession,
-        throttle::ConfigThrottle, AggregateReport, ArcAuthConfig, Auth, ConfigContext, Connect,
-        Data, DkimAuthConfig, DmarcAuthConfig, Dsn, Ehlo, EnvelopeKey, Extensions, IfBlock,
-        IpRevAuthConfig, Mail, MailAuthConfig, Milter, QueueConfig, QueueOutboundSourceIp,
-        QueueOutboundTimeout, QueueOutboundTls, QueueQuotas, QueueThrottle, Rcpt, Report,
-        ReportAnalysis, ReportConfig, SessionConfig, SessionThrottle, SpfAuthConfig, Throttle,
+        throttle::ConfigThrottle, AbuseReport, AccountSessionLimits, AggregateReport,
+        ArcAuthConfig, Auth, ConfigContext, Connect, Data, DkimAuthConfig, DmarcAuthConfig, Dsn,
+        Ehlo, EnvelopeKey, Extensions, FeedbackAnalysis, FilterBudgetConfig, IfBlock,
+        IpRevAuthConfig, LoopDetection, Mail, MailAuthConfig, MessageValidation, Milter,
+        OperatorReports, QueueAnalytics, QueueConfig, QueueModeration, QueueOutboundSourceIp,
+        QueueOutboundTimeout, QueueOutboundTls, QueueQuotas, QueueThrottle, Rcpt, RcptSuggest,
+        Report, ReportAnalysis, ReportConfig, SenderAlignment, SenderVerify, SenderVerifyConfig,
+        SessionConfig, SessionThrottle, SpfAuthConfig, Throttle, TransparentRelay, TrustedPeers,
         VerifyStrategy,
     },
     core::{
         throttle::ThrottleKeyHasherBuilder, QueueCore, ReportCore, Resolvers, SessionCore,
         SieveCore, TlsConnectors, SMTP,
     },
+    inbound::{
+        archive::ArchiveMetrics, budget::FilterMetrics, mail_loop::LoopMetrics,
+        verify::SenderVerifyCache,
+    },
     outbound::dane::DnssecResolver,
 };
 use utils::config::{utils::ParseValues, Config};
@@ -178,11 +185,19 @@ impl TestConfig for SMTP {
                 cache: smtp::core::DnsCache {
                     tlsa: LruCache::with_capacity(100),
                     mta_sts: LruCache::with_capacity(100),
+                    rbl: LruCache::with_capacity(100),
+                    rbl_positive_ttl: Duration::from_secs(3600),
+                    rbl_negative_ttl: Duration::from_secs(600),
+                    tlsa_stats: Default::default(),
+                    mta_sts_stats: Default::default(),
+                    rbl_stats: Default::default(),
                 },
+                overrides: Default::default(),
             },
             mail_auth: MailAuthConfig::test(),
             report: ReportCore::test(),
             sieve: SieveCore::test(),
+            eval: Default::default(),
             delivery_tx: mpsc::channel(1).0,
         }
     }
@@ -197,6 +212,11 @@ impl TestConfig for SessionCore {
                 ThrottleKeyHasherBuilder::default(),
                 16,
             ),
+            filter_metrics: FilterMetrics::default(),
+            archive_metrics: ArchiveMetrics::default(),
+            loop_metrics: LoopMetrics::default(),
+            account_sessions: Default::default(),
+            sender_verify: SenderVerifyCache::new(128),
         }
     }
 }
@@ -207,6 +227,7 @@ impl TestConfig for SessionConfig {
             timeout: IfBlock::new(Duration::from_secs(10)),
             duration: IfBlock::new(Duration::from_secs(10)),
             transfer_limit: IfBlock::new(1024 * 1024),
+            error_codes: IfBlock::new(false),
             throttle: SessionThrottle {
                 connect: vec![],
                 mail_from: vec![],
@@ -231,6 +252,7 @@ impl TestConfig for SessionConfig {
                 dsn: IfBlock::new(true),
                 expn: IfBlock::new(true),
                 vrfy: IfBlock::new(true),
+                xclient: IfBlock::new(false),
             },
             auth: Auth {
                 directory: IfBlock::new(None),
@@ -240,10 +262,20 @@ impl TestConfig for SessionConfig {
                 errors_wait: IfBlock::new(Duration::from_secs(1)),
                 allow_plain_text: IfBlock::new(false),
                 must_match_sender: IfBlock::new(false),
+                sender_alignment: IfBlock::new(SenderAlignment::Disable),
+                sender_alignment_exempt: vec![],
+                sender_alignment_delegated: false,
             },
             mail: Mail {
                 script: IfBlock::new(None),
                 rewrite: IfBlock::new(None),
+                verify: SenderVerifyConfig {
+                    action: IfBlock::new(SenderVerify::Disable),
+                    directory: IfBlock::new(None),
+                    timeout: Duration::from_secs(10),
+                    positive_ttl: Duration::from_secs(3600),
+                    negative_ttl: Duration::from_secs(600),
+                },
             },
             rcpt: Rcpt {
                 script: IfBlock::new(None),
@@ -253,6 +285,11 @@ impl TestConfig for SessionConfig {
                 errors_wait: IfBlock::new(Duration::from_secs(1)),
                 max_recipients: IfBlock::new(3),
                 rewrite: IfBlock::new(None),
+                suggest: RcptSuggest {
+                    enable: IfBlock::new(false),
+                    max_distance: 2,
+                    max_results: 3,
+                },
             },
             data: Data {
                 script: IfBlock::new(None),
@@ -265,8 +302,22 @@ impl TestConfig for SessionConfig {
                 add_auth_results: IfBlock::new(true),
                 add_message_id: IfBlock::new(true),
                 add_date: IfBlock::new(true),
+                validation: IfBlock::new(MessageValidation::Disable),
+                disclaimer_text: IfBlock::new(None),
+                disclaimer_html: IfBlock::new(None),
                 pipe_commands: vec![],
                 milters: vec![],
+                antivirus: None,
+                budget: FilterBudgetConfig::default(),
+                archive: IfBlock::new(vec![]),
+                loop_detection: LoopDetection::default(),
+            },
+            trusted_peers: TrustedPeers::default(),
+            account_sessions: AccountSessionLimits::default(),
+            transparent: TransparentRelay {
+                enable: IfBlock::new(false),
+                next_hop: None,
+                add_headers: false,
             },
         }
     }
@@ -288,6 +339,8 @@ impl TestConfig for QueueCore {
             ),
             tx: mpsc::channel(1024).0,
             id_seq: 0.into(),
+            analytics: Default::default(),
+            moderation: Default::default(),
             connectors: TlsConnectors {
                 pki_verify: build_tls_connector(false),
                 dummy_verify: build_tls_connector(true),
@@ -303,15 +356,18 @@ impl TestConfig for QueueConfig {
             path: Default::default(),
             hash: IfBlock::new(10),
             retry: IfBlock::new(vec![Duration::from_secs(10)]),
+            retry_strategy: IfBlock::new(None),
             notify: IfBlock::new(vec![Duration::from_secs(20)]),
             expire: IfBlock::new(Duration::from_secs(10)),
             hostname: IfBlock::new("mx.example.org".to_string()),
             next_hop: Default::default(),
             max_mx: IfBlock::new(5),
             max_multihomed: IfBlock::new(5),
+            max_rcpt: IfBlock::new(100),
             source_ip: QueueOutboundSourceIp {
                 ipv4: IfBlock::new(vec![]),
                 ipv6: IfBlock::new(vec![]),
+                pool: IfBlock::new(None),
             },
             ip_strategy: IfBlock::new(IpLookupStrategy::Ipv4thenIpv6),
             tls: QueueOutboundTls {
@@ -319,6 +375,7 @@ impl TestConfig for QueueConfig {
                 mta_sts: IfBlock::new(smtp::config::RequireOptional::Optional),
                 start: IfBlock::new(smtp::config::RequireOptional::Optional),
                 invalid_certs: IfBlock::new(false),
+                fallback: IfBlock::new(smtp::config::TlsFallback::FailClosed),
             },
             dsn: Dsn {
                 name: IfBlock::new("Mail Delivery Subsystem".to_string()),
@@ -345,10 +402,25 @@ impl TestConfig for QueueConfig {
                 rcpt: vec![],
                 rcpt_domain: vec![],
             },
+            analytics: QueueAnalytics {
+                enable: true,
+                hourly_retention: Duration::from_secs(2 * 86400),
+                daily_retention: Duration::from_secs(90 * 86400),
+                max_domains: 100,
+                prometheus_domains: 20,
+            },
+            moderation: QueueModeration {
+                expire: Duration::from_secs(3600),
+                notify: vec![],
+                notify_interval: Duration::from_secs(3600),
+                accounts: Default::default(),
+            },
+            warmup: Default::default(),
             directory: Arc::new(Directory {
                 store: DirectoryInner::Internal(store.clone()),
                 catch_all: AddressMapping::Disable,
                 subaddressing: AddressMapping::Disable,
+                reserved: ReservedAddresses::default(),
                 cache: None,
                 blocked_ips: Arc::new(Default::default()),
             }),
@@ -379,6 +451,7 @@ impl TestConfig for MailAuthConfig {
             iprev: IpRevAuthConfig {
                 verify: IfBlock::new(VerifyStrategy::Relaxed),
             },
+            mta_sts: None,
         }
     }
 }
@@ -388,6 +461,7 @@ impl TestConfig for ReportCore {
         Self {
             config: ReportConfig::test(),
             tx: mpsc::channel(1024).0,
+            operator: Default::default(),
         }
     }
 }
@@ -403,12 +477,27 @@ impl TestConfig for ReportConfig {
                 forward: true,
                 store: None,
                 report_id: 0.into(),
+                feedback: FeedbackAnalysis {
+                    enable: false,
+                    store: None,
+                    score: 10.0,
+                    expiry: Duration::from_secs(30 * 86400),
+                },
             },
             dkim: Report::test(),
             spf: Report::test(),
             dmarc: Report::test(),
             dmarc_aggregate: AggregateReport::test(),
             tls: AggregateReport::test(),
+            abuse: AbuseReport {
+                report: Report::test(),
+                rcpts: vec![],
+                notify_origin: false,
+            },
+            operator: OperatorReports {
+                report: Report::test(),
+                schedules: vec![],
+            },
         }
     }
 }
@@ -237,7 +237,14 @@ async fn dane_test() {
         cache: smtp::core::DnsCache {
             tlsa: LruCache::with_capacity(10),
             mta_sts: LruCache::with_capacity(10),
+            rbl: LruCache::with_capacity(10),
+            rbl_positive_ttl: Duration::from_secs(3600),
+            rbl_negative_ttl: Duration::from_secs(600),
+            tlsa_stats: Default::default(),
+            mta_sts_stats: Default::default(),
+            rbl_stats: Default::default(),
         },
+        overrides: Default::default(),
     };
 
     // Add dns entries
@@ -115,6 +115,14 @@ async fn extensions() {
         .read_lines()
         .assert_contains("using TLSv1.3 with cipher");
 
+    // Deliveries should be recorded in the queue analytics
+    let analytics = core
+        .queue
+        .delivery_analytics(Some("foobar.org"), 0, u64::MAX, false);
+    assert_eq!(analytics.len(), 1);
+    assert_eq!(analytics[0].stats.delivered, 1);
+    assert_eq!(analytics[0].stats.tls, 1);
+
     // Test SIZE extension
     session
         .send_message("john@test.org", &["bill@foobar.org"], "test:arc", "250")
@@ -135,6 +143,7 @@ async fn extensions() {
     remote_qr.assert_empty_queue();
 
     // Test DSN, SMTPUTF8 and REQUIRETLS extensions
+    session.stream.tls = true;
     session
         .send_message(
             "<john@test.org> ENVID=abc123 RET=HDRS REQUIRETLS SMTPUTF8",
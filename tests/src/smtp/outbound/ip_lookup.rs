@@ -27,14 +27,14 @@ use std::{
 };
 
 use mail_auth::{IpLookupStrategy, MX};
-use utils::config::ServerProtocol;
+use utils::config::{Config, ServerProtocol};
 
 use crate::smtp::{
     inbound::TestQueueEvent, outbound::start_test_server, session::TestSession, TestConfig,
     TestSMTP,
 };
 use smtp::{
-    config::IfBlock,
+    config::{resolver::ConfigResolver, IfBlock},
     core::{Session, SMTP},
     queue::{manager::Queue, DeliveryAttempt},
 };
@@ -108,3 +108,72 @@ async fn ip_lookup_strategy() {
         }
     }
 }
+
+const OVERRIDES: &str = r#"
+[resolver.override.lab]
+domain = "Lab.Example."
+mx = ["10 mx.lab.example"]
+ttl = "1h"
+
+[resolver.override.lab-mx]
+domain = "mx.lab.example"
+ipv4 = ["127.0.0.1"]
+ttl = "5m"
+"#;
+
+#[tokio::test]
+#[serial_test::serial]
+async fn dns_override() {
+    // Start test server
+    let mut core = SMTP::test();
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    let mut remote_qr = core.init_test_queue("smtp_dns_override_remote");
+    let _rx = start_test_server(core.into(), &[ServerProtocol::Smtp]);
+
+    // Records are served from the override table without querying DNS
+    let mut core = SMTP::test();
+    core.queue.config.ip_strategy = IfBlock::new(IpLookupStrategy::Ipv4Only);
+    core.resolvers.overrides = Config::new(OVERRIDES)
+        .unwrap()
+        .parse_dns_overrides()
+        .unwrap();
+    let report = core.resolvers.overrides.report();
+    assert_eq!(report.len(), 2);
+    assert_eq!(report[0].domain, "lab.example");
+    assert_eq!(report[0].mx, vec!["10 mx.lab.example".to_string()]);
+    assert_eq!(report[0].remaining_ttl, None);
+    assert_eq!(
+        report[1].ipv4,
+        vec!["127.0.0.1".parse::<std::net::Ipv4Addr>().unwrap()]
+    );
+
+    let mut local_qr = core.init_test_queue("smtp_dns_override_local");
+    core.session.config.rcpt.relay = IfBlock::new(true);
+
+    let core = Arc::new(core);
+    let mut queue = Queue::default();
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+    session
+        .send_message(
+            "john@test.org",
+            &["bill@lab.example"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    DeliveryAttempt::from(local_qr.read_event().await.unwrap_message())
+        .try_deliver(core.clone(), &mut queue)
+        .await;
+    local_qr.read_event().await.unwrap_done();
+    remote_qr.read_event().await.unwrap_message();
+
+    // Lookups are tracked with a simulated TTL
+    let report = core.resolvers.overrides.report();
+    assert_eq!(report[0].hits, 1);
+    assert!(report[0].remaining_ttl.unwrap() > 3500);
+    assert_eq!(report[1].hits, 1);
+    assert!(report[1].remaining_ttl.unwrap() <= 300);
+}
@@ -35,7 +35,7 @@ use crate::smtp::{
 use smtp::{
     config::{remote::ConfigHost, ConfigContext, IfBlock},
     core::{Session, SMTP},
-    queue::{manager::Queue, DeliveryAttempt, Event, WorkerResult},
+    queue::{manager::Queue, DeliveryAttempt, Event, Status, WorkerResult},
 };
 use utils::config::{Config, ServerProtocol};
 
@@ -199,3 +199,110 @@ async fn lmtp_delivery() {
     );
     remote_qr.assert_empty_queue();
 }
+
+#[tokio::test]
+#[serial_test::serial]
+async fn lmtp_coalescing() {
+    // Start test server
+    let mut core = SMTP::test();
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    let mut remote_qr = core.init_test_queue("lmtp_coalescing_remote");
+    let _rx = start_test_server(core.into(), &[ServerProtocol::Lmtp]);
+
+    // Add mock DNS entries
+    let mut core = SMTP::test();
+    core.resolvers.dns.ipv4_add(
+        "lmtp.foobar.org",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+
+    // Relay two domains through the same host, allowing two recipients per transaction
+    let mut local_qr = core.init_test_queue("lmtp_coalescing_local");
+    let mut ctx = ConfigContext::new(&[]);
+    let config = Config::new(REMOTE).unwrap();
+    config.parse_remote_hosts(&mut ctx).unwrap();
+    core.queue.config.next_hop = "[{if = 'rcpt-domain', eq = 'foobar.org', then = 'lmtp'},
+    {if = 'rcpt-domain', eq = 'foobar.net', then = 'lmtp'},
+    {else = false}]"
+        .parse_if::<Option<String>>(&ctx)
+        .into_relay_host(&ctx)
+        .unwrap();
+    core.queue.config.max_rcpt = IfBlock::new(2);
+    core.session.config.rcpt.relay = IfBlock::new(true);
+
+    let core = Arc::new(core);
+    let mut queue = Queue::default();
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+    session
+        .send_message(
+            "john@test.org",
+            &[
+                "<bill@foobar.org>",
+                "<jane@foobar.net>",
+                "<john@foobar.org>",
+            ],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    let message = local_qr.read_event().await.unwrap_message();
+    assert_eq!(message.domains.len(), 2);
+    DeliveryAttempt::from(message)
+        .try_deliver(core.clone(), &mut queue)
+        .await;
+    local_qr.read_event().await.unwrap_done();
+    local_qr.assert_empty_queue();
+
+    // Both domains should have been delivered over a single connection,
+    // splitting the recipients in two transactions
+    for expected_rcpts in [
+        vec!["jane@foobar.net", "bill@foobar.org"],
+        vec!["john@foobar.org"],
+    ] {
+        assert_eq!(
+            remote_qr
+                .read_event()
+                .await
+                .unwrap_message()
+                .recipients
+                .into_iter()
+                .map(|r| r.address)
+                .collect::<Vec<_>>(),
+            expected_rcpts
+        );
+    }
+    remote_qr.assert_empty_queue();
+
+    // Domains that failed temporarily in the same transaction are retried together
+    session
+        .send_message(
+            "john@test.org",
+            &["<delay@foobar.org>", "<delay@foobar.net>"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    DeliveryAttempt::from(local_qr.read_event().await.unwrap_message())
+        .try_deliver(core.clone(), &mut queue)
+        .await;
+    let message = local_qr.read_event().await.unwrap_retry().inner;
+    assert_eq!(message.domains.len(), 2);
+    for domain in &message.domains[1..] {
+        assert!(
+            matches!(
+                &domain.status,
+                Status::Scheduled | Status::TemporaryFailure(_)
+            ),
+            "{:?}",
+            domain.status
+        );
+        assert_eq!(domain.retry.due, message.domains[0].retry.due);
+        assert_eq!(domain.retry.inner, message.domains[0].retry.inner);
+    }
+    local_qr.assert_empty_queue();
+    remote_qr.assert_empty_queue();
+}
@@ -35,7 +35,9 @@ pub mod extensions;
 pub mod ip_lookup;
 pub mod lmtp;
 pub mod mta_sts;
+pub mod requiretls;
 pub mod smtp;
+pub mod smtputf8;
 pub mod throttle;
 pub mod tls;
 
@@ -32,7 +32,7 @@ use mail_auth::{
     report::tlsrpt::ResultType,
     MX,
 };
-use utils::config::ServerProtocol;
+use utils::config::{Config, ServerProtocol};
 
 use crate::smtp::{
     inbound::{TestMessage, TestQueueEvent, TestReportingEvent},
@@ -41,7 +41,7 @@ use crate::smtp::{
     TestConfig, TestSMTP,
 };
 use smtp::{
-    config::{AggregateFrequency, IfBlock, RequireOptional},
+    config::{auth::ConfigAuth, AggregateFrequency, IfBlock, RequireOptional, TlsFallback},
     core::{Session, SMTP},
     outbound::mta_sts::{lookup::STS_TEST_POLICY, Policy},
     queue::{manager::Queue, DeliveryAttempt},
@@ -234,3 +234,153 @@ async fn mta_sts_verify() {
     );
     assert!(report.failure.is_none());
 }
+
+#[tokio::test]
+#[serial_test::serial]
+async fn mta_sts_fallback() {
+    // Start test server
+    let mut core = SMTP::test();
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    let mut remote_qr = core.init_test_queue("smtp_mta_sts_fallback_remote");
+    let _rx = start_test_server(core.into(), &[ServerProtocol::Smtp]);
+
+    // Add mock DNS entries
+    let mut core = SMTP::test();
+    core.resolvers.dns.mx_add(
+        "foobar.org",
+        vec![MX {
+            exchanges: vec!["mx.foobar.org".to_string()],
+            preference: 10,
+        }],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.resolvers.dns.ipv4_add(
+        "mx.foobar.org",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.resolvers.dns.txt_add(
+        "_smtp._tls.foobar.org",
+        TlsRpt::parse(b"v=TLSRPTv1; rua=mailto:reports@foobar.org").unwrap(),
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.resolvers.dns.txt_add(
+        "_mta-sts.foobar.org",
+        MtaSts::parse(b"v=STSv1; id=policy_fallback;").unwrap(),
+        Instant::now() + Duration::from_secs(10),
+    );
+    let policy = concat!(
+        "version: STSv1\n",
+        "mode: enforce\n",
+        "mx: mail.foobar.net\n",
+        "max_age: 604800\n"
+    );
+    STS_TEST_POLICY.lock().clear();
+    STS_TEST_POLICY.lock().extend_from_slice(policy.as_bytes());
+
+    // Unauthorized MX falls back to opportunistic TLS
+    let mut local_qr = core.init_test_queue("smtp_mta_sts_fallback_local");
+    let mut rr = core.init_test_report();
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.queue.config.tls.mta_sts = IfBlock::new(RequireOptional::Require);
+    core.queue.config.tls.fallback = IfBlock::new(TlsFallback::Opportunistic);
+    core.report.config.tls.send = IfBlock::new(AggregateFrequency::Weekly);
+
+    let core = Arc::new(core);
+    let mut queue = Queue::default();
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+    session
+        .send_message("john@test.org", &["bill@foobar.org"], "test:no_dkim", "250")
+        .await;
+    DeliveryAttempt::from(local_qr.read_event().await.unwrap_message())
+        .try_deliver(core.clone(), &mut queue)
+        .await;
+    local_qr.read_event().await.unwrap_done();
+    remote_qr
+        .read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("using TLSv1.3 with cipher");
+
+    // The downgrade is reported and counted
+    let report = rr.read_report().await.unwrap_tls();
+    assert_eq!(
+        report.failure.as_ref().unwrap().result_type,
+        ResultType::ValidationFailure
+    );
+    assert_eq!(
+        core.report
+            .operator
+            .telemetry
+            .summary(0, u64::MAX)
+            .tls_downgrades,
+        1
+    );
+    STS_TEST_POLICY.lock().clear();
+}
+
+#[test]
+fn mta_sts_publish() {
+    // The server hostname is published when no MX hosts are listed
+    let config = Config::new(
+        r#"
+    [server]
+    hostname = "MX.Example.org"
+
+    [auth.mta-sts]
+    mode = "testing"
+    "#,
+    )
+    .unwrap();
+    let policy = config.parse_mta_sts_policy().unwrap().unwrap();
+    assert_eq!(
+        policy.to_string(),
+        "version: STSv1\r\nmode: testing\r\nmx: mx.example.org\r\nmax_age: 604800\r\n"
+    );
+
+    // Published policies can be read back by the policy parser
+    let config = Config::new(
+        r#"
+    [server]
+    hostname = "mx.example.org"
+
+    [auth.mta-sts]
+    mode = "enforce"
+    mx = ["mx1.example.org", "*.example.net"]
+    max-age = "1d"
+    "#,
+    )
+    .unwrap();
+    let policy = config.parse_mta_sts_policy().unwrap().unwrap();
+    let text = policy.to_string();
+    assert_eq!(
+        text,
+        concat!(
+            "version: STSv1\r\n",
+            "mode: enforce\r\n",
+            "mx: mx1.example.org\r\n",
+            "mx: *.example.net\r\n",
+            "max_age: 86400\r\n"
+        )
+    );
+    assert_eq!(Policy::parse(&text, String::new()).unwrap(), policy);
+
+    // Publishing is disabled unless a mode is set
+    let config = Config::new("[server]\nhostname = \"mx.example.org\"\n").unwrap();
+    assert_eq!(config.parse_mta_sts_policy().unwrap(), None);
+
+    // Invalid values are rejected
+    for toml in [
+        "[auth.mta-sts]\nmode = \"strict\"\nmx = \"mx.example.org\"\n",
+        "[auth.mta-sts]\nmode = \"enforce\"\nmx = \"mx.*.example.org\"\n",
+    ] {
+        assert!(
+            Config::new(toml).unwrap().parse_mta_sts_policy().is_err(),
+            "{toml}"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use mail_auth::MX;
+use smtp_proto::MAIL_REQUIRETLS;
+use utils::config::ServerProtocol;
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    outbound::start_test_server,
+    session::{TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+use smtp::{
+    config::{IfBlock, RequireOptional},
+    core::{Session, SMTP},
+    queue::{manager::Queue, DeliveryAttempt, MAIL_TLS_OPTIONAL},
+};
+
+const TLS_OPTIONAL_MESSAGE: &str = "From: john@test.org
+To: bill@foobar.org
+TLS-Required: No
+Subject: TLS is optional
+
+Test message";
+
+#[tokio::test]
+#[serial_test::serial]
+async fn requiretls() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::TRACE)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Start test server without REQUIRETLS support
+    let mut core = SMTP::test();
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.session.config.extensions.requiretls = IfBlock::new(false);
+    let mut remote_qr = core.init_test_queue("smtp_requiretls_remote");
+    let _rx = start_test_server(core.into(), &[ServerProtocol::Smtp]);
+
+    // Add mock DNS entries
+    let mut core = SMTP::test();
+    core.resolvers.dns.mx_add(
+        "foobar.org",
+        vec![MX {
+            exchanges: vec!["mx.foobar.org".to_string()],
+            preference: 10,
+        }],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.resolvers.dns.ipv4_add(
+        "mx.foobar.org",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+    let mut local_qr = core.init_test_queue("smtp_requiretls_local");
+    core.session.config.rcpt.relay = IfBlock::new(true);
+
+    let core = Arc::new(core);
+    let mut queue = Queue::default();
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.stream.tls = true;
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+
+    // REQUIRETLS messages are not relayed to hosts that do not support it
+    session
+        .send_message(
+            "<john@test.org> REQUIRETLS",
+            &["bill@foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    DeliveryAttempt::from(local_qr.read_event().await.unwrap_message())
+        .try_deliver(core.clone(), &mut queue)
+        .await;
+    let dsn = local_qr.read_event().await.unwrap_message();
+    assert!((dsn.flags & MAIL_REQUIRETLS) != 0);
+    dsn.read_lines()
+        .assert_contains("<bill@foobar.org> (REQUIRETLS could not be satisfied by 'mx.foobar.org'")
+        .assert_contains("Action: failed")
+        .assert_contains("Status: 5.7.30");
+    local_qr.read_event().await.unwrap_done();
+    remote_qr.assert_empty_queue();
+
+    // Other messages are delivered normally
+    session
+        .send_message("john@test.org", &["bill@foobar.org"], "test:no_dkim", "250")
+        .await;
+    DeliveryAttempt::from(local_qr.read_event().await.unwrap_message())
+        .try_deliver(core.clone(), &mut queue)
+        .await;
+    local_qr.read_event().await.unwrap_done();
+    remote_qr
+        .read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("using TLSv1.3 with cipher");
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn tls_required_no() {
+    // Start test server
+    let mut core = SMTP::test();
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    let mut remote_qr = core.init_test_queue("smtp_tls_required_remote");
+    let _rx = start_test_server(core.into(), &[ServerProtocol::Smtp]);
+
+    // Add mock DNS entries, STARTTLS fails for this hostname
+    let mut core = SMTP::test();
+    core.queue.config.hostname = IfBlock::new("badtls.foobar.org".to_string());
+    core.resolvers.dns.mx_add(
+        "foobar.org",
+        vec![MX {
+            exchanges: vec!["mx.foobar.org".to_string()],
+            preference: 10,
+        }],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.resolvers.dns.ipv4_add(
+        "mx.foobar.org",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+    let mut local_qr = core.init_test_queue("smtp_tls_required_local");
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.queue.config.tls.start = IfBlock::new(RequireOptional::Require);
+
+    let core = Arc::new(core);
+    let mut queue = Queue::default();
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.stream.tls = true;
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+
+    // TLS is required by the local policy
+    session
+        .send_message("john@test.org", &["bill@foobar.org"], "test:no_dkim", "250")
+        .await;
+    let message = local_qr.read_event().await.unwrap_message();
+    assert_eq!(message.flags & MAIL_TLS_OPTIONAL, 0);
+    DeliveryAttempt::from(message)
+        .try_deliver(core.clone(), &mut queue)
+        .await;
+    let retry = local_qr.read_event().await.unwrap_retry();
+    assert!(!retry.inner.domains[0].disable_tls);
+
+    // "TLS-Required: No" is ignored for REQUIRETLS messages
+    session
+        .send_message(
+            "<john@test.org> REQUIRETLS",
+            &["bill@foobar.org"],
+            TLS_OPTIONAL_MESSAGE,
+            "250",
+        )
+        .await;
+    let message = local_qr.read_event().await.unwrap_message();
+    assert_eq!(message.flags & MAIL_TLS_OPTIONAL, 0);
+    DeliveryAttempt::from(message)
+        .try_deliver(core.clone(), &mut queue)
+        .await;
+    let retry = local_qr.read_event().await.unwrap_retry();
+    assert!(!retry.inner.domains[0].disable_tls);
+
+    // Otherwise the sender's preference overrides the local policy
+    session
+        .send_message(
+            "john@test.org",
+            &["bill@foobar.org"],
+            TLS_OPTIONAL_MESSAGE,
+            "250",
+        )
+        .await;
+    let message = local_qr.read_event().await.unwrap_message();
+    assert_ne!(message.flags & MAIL_TLS_OPTIONAL, 0);
+    DeliveryAttempt::from(message)
+        .try_deliver(core.clone(), &mut queue)
+        .await;
+    let mut retry = local_qr.read_event().await.unwrap_retry();
+    assert!(retry.inner.domains[0].disable_tls);
+    retry.inner.domains[0].retry.due = Instant::now();
+    DeliveryAttempt::from(retry.inner)
+        .try_deliver(core.clone(), &mut queue)
+        .await;
+    local_qr.read_event().await.unwrap_done();
+    remote_qr
+        .read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("TLS-Required: No")
+        .assert_not_contains("using TLSv1.3 with cipher");
+}
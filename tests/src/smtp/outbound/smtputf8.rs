@@ -0,0 +1,173 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use mail_auth::MX;
+use smtp_proto::MAIL_SMTPUTF8;
+use utils::config::ServerProtocol;
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    outbound::start_test_server,
+    session::{TestSession, VerifyResponse},
+    TestConfig, TestSMTP,
+};
+use smtp::{
+    config::IfBlock,
+    core::{Session, SMTP},
+    outbound::downgrade::{address_to_ascii, domain_to_ascii, downgrade_headers},
+    queue::{manager::Queue, DeliveryAttempt},
+};
+
+#[tokio::test]
+#[serial_test::serial]
+async fn smtputf8() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::TRACE)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Start test server
+    let mut core = SMTP::test();
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.session.config.extensions.dsn = IfBlock::new(true);
+    let mut remote_qr = core.init_test_queue("smtp_utf8_remote");
+    let _rx = start_test_server(core.into(), &[ServerProtocol::Smtp]);
+
+    // Add mock DNS entries
+    let mut core = SMTP::test();
+    core.resolvers.dns.mx_add(
+        "foobar.org",
+        vec![MX {
+            exchanges: vec!["mx.foobar.org".to_string()],
+            preference: 10,
+        }],
+        Instant::now() + Duration::from_secs(10),
+    );
+    core.resolvers.dns.ipv4_add(
+        "mx.foobar.org",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+
+    let mut local_qr = core.init_test_queue("smtp_utf8_local");
+    core.session.config.rcpt.relay = IfBlock::new(true);
+    core.session.config.extensions.dsn = IfBlock::new(true);
+    let core = Arc::new(core);
+    let mut queue = Queue::default();
+    let mut session = Session::test(core.clone());
+    session.data.remote_ip = "10.0.0.1".parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+
+    // Internationalized addresses require SMTPUTF8
+    session.mail_from("jöhn@test.org", "553 5.6.7").await;
+    session.mail_from("john@test.org", "250").await;
+    session.rcpt_to("bïll@foobar.org", "553 5.6.7").await;
+    session.rset().await;
+
+    // UTF-8 local parts should be preserved through the queue
+    session
+        .send_message(
+            "<jöhn@test.org> SMTPUTF8",
+            &[
+                "<bïll@foobar.org> NOTIFY=FAILURE",
+                "<jörg.fail@foobar.org> NOTIFY=FAILURE",
+            ],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    let message = local_qr.read_event().await.unwrap_message();
+    assert_eq!(message.return_path, "jöhn@test.org");
+    assert!((message.flags & MAIL_SMTPUTF8) != 0);
+    DeliveryAttempt::from(message)
+        .try_deliver(core.clone(), &mut queue)
+        .await;
+    let message = remote_qr.read_event().await.unwrap_message();
+    assert_eq!(message.return_path, "jöhn@test.org");
+    assert_eq!(message.recipients.len(), 1);
+    assert_eq!(message.recipients[0].address, "bïll@foobar.org");
+
+    // Failures should be reported using a global delivery status
+    local_qr
+        .read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("Content-Type: message/global-delivery-status")
+        .assert_contains("Final-Recipient: utf-8;jörg.fail@foobar.org")
+        .assert_contains("Action: failed");
+    local_qr.read_event().await.unwrap_done();
+
+    // Test address downgrading
+    assert_eq!(
+        domain_to_ascii("bücher.example").unwrap(),
+        "xn--bcher-kva.example"
+    );
+    assert_eq!(
+        address_to_ascii("john@bücher.example").unwrap(),
+        "john@xn--bcher-kva.example"
+    );
+    assert_eq!(
+        address_to_ascii("john@example.org").unwrap(),
+        "john@example.org"
+    );
+    assert!(address_to_ascii("jöhn@example.org").is_none());
+
+    // Test header downgrading
+    assert!(downgrade_headers(b"From: john@example.org\r\nSubject: Hi\r\n\r\nBody\r\n").is_none());
+    let message = String::from_utf8(
+        downgrade_headers(
+            concat!(
+                "From: Jöhn <john@bücher.example>\r\n",
+                "To: bïll@foobar.org\r\n",
+                "Subject: Grüße\r\n",
+                "X-Custom: Ünïcode\r\n",
+                "\r\n",
+                "Grüße\r\n"
+            )
+            .as_bytes(),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert!(message.contains("john@xn--bcher-kva.example"), "{message}");
+    assert!(message.contains("Subject: =?utf-8?"), "{message}");
+    assert!(
+        message.contains("Downgraded-X-Custom: =?utf-8?"),
+        "{message}"
+    );
+    assert!(!message.contains("bïll@foobar.org"), "{message}");
+    assert!(message.ends_with("\r\n\r\nGrüße\r\n"), "{message}");
+    assert!(
+        message.split("\r\n\r\n").next().unwrap().is_ascii(),
+        "{message}"
+    );
+}
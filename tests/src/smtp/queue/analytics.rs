@@ -0,0 +1,264 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use smtp::{
+    config::QueueAnalytics,
+    core::management::{AnalyticsSummary, LatencyPercentiles},
+    queue::{analytics::DeliveryAnalytics, Error, ErrorDetails, HostResponse, Status},
+};
+use smtp_proto::Response;
+
+const HOUR: u64 = 3600;
+const DAY: u64 = 86400;
+
+#[test]
+fn queue_analytics() {
+    let config = QueueAnalytics {
+        enable: true,
+        hourly_retention: Duration::from_secs(2 * DAY),
+        daily_retention: Duration::from_secs(7 * DAY),
+        max_domains: 2,
+        prometheus_domains: 20,
+    };
+    let analytics = DeliveryAnalytics::default();
+    let start = 1_700_006_400; // Midnight UTC
+    let rejected = Status::PermanentFailure(Error::UnexpectedResponse(HostResponse {
+        hostname: ErrorDetails {
+            entity: "mx.example.org".to_string(),
+            details: "RCPT TO:<john@example.org>".to_string(),
+        },
+        response: Response {
+            code: 550,
+            esc: [5, 1, 1],
+            message: "User unknown".to_string(),
+        },
+    }));
+
+    // Record deliveries with increasing latencies
+    for latency in [2, 3, 4, 20, 20, 45, 50, 200, 400, 4000] {
+        analytics.record(
+            &config,
+            "example.org",
+            &Status::Completed(()),
+            Some(latency != 4000),
+            start - latency,
+            start,
+        );
+    }
+    analytics.record(
+        &config,
+        "example.org",
+        &Status::TemporaryFailure(Error::DnsError("timeout".to_string())),
+        None,
+        start,
+        start + 10,
+    );
+    analytics.record(
+        &config,
+        "example.org",
+        &rejected,
+        Some(true),
+        start,
+        start + 20,
+    );
+    analytics.record(
+        &config,
+        "example.net",
+        &Status::TemporaryFailure(Error::ConnectionError(ErrorDetails {
+            entity: "mx.example.net".to_string(),
+            details: "Connection refused".to_string(),
+        })),
+        None,
+        start,
+        start + HOUR,
+    );
+
+    // Domains above the limit are not tracked
+    analytics.record(
+        &config,
+        "example.com",
+        &Status::Completed(()),
+        Some(true),
+        start,
+        start + HOUR,
+    );
+
+    let results = analytics.query(&config, None, 0, u64::MAX, false, start + HOUR);
+    assert_eq!(
+        results
+            .iter()
+            .map(|r| r.domain.as_str())
+            .collect::<Vec<_>>(),
+        vec!["example.net", "example.org"]
+    );
+    assert_eq!(
+        results[1].stats,
+        AnalyticsSummary {
+            attempts: 12,
+            delivered: 10,
+            temporary_failures: 1,
+            permanent_failures: 1,
+            tls: 10,
+            plaintext: 1,
+            failures: [("dns".to_string(), 1), ("remote-permanent".to_string(), 1)]
+                .into_iter()
+                .collect(),
+            latency: LatencyPercentiles {
+                p50: Some(30),
+                p90: Some(600),
+                p99: Some(3 * HOUR),
+            },
+        }
+    );
+    assert_eq!(results[0].stats.failures.get("connection"), Some(&1));
+    assert!(results[0].stats.latency.p50.is_none());
+
+    // Filter by domain and time range
+    let results = analytics.query(
+        &config,
+        Some("example.net"),
+        start + HOUR,
+        u64::MAX,
+        true,
+        start + HOUR,
+    );
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].series.len(), 1);
+    assert_eq!(results[0].series[0].duration, HOUR);
+    assert!(analytics
+        .query(
+            &config,
+            Some("example.org"),
+            start + HOUR,
+            u64::MAX,
+            false,
+            start + HOUR
+        )
+        .is_empty());
+
+    // Hourly samples are downsampled into daily samples after the hourly retention
+    let now = start + 3 * DAY;
+    analytics.record(
+        &config,
+        "example.org",
+        &Status::Completed(()),
+        Some(true),
+        now - 1,
+        now,
+    );
+    let results = analytics.query(&config, Some("example.org"), 0, u64::MAX, true, now);
+    assert_eq!(
+        results[0]
+            .series
+            .iter()
+            .map(|s| (s.from.to_timestamp() as u64, s.duration, s.stats.attempts))
+            .collect::<Vec<_>>(),
+        vec![(start, DAY, 12), (now, HOUR, 1)]
+    );
+    assert_eq!(results[0].stats.attempts, 13);
+
+    // Daily samples are removed after the daily retention
+    let now = start + 10 * DAY;
+    let results = analytics.query(&config, None, 0, u64::MAX, true, now);
+    assert_eq!(
+        results
+            .iter()
+            .map(|r| (r.domain.as_str(), r.stats.attempts))
+            .collect::<Vec<_>>(),
+        vec![("example.org", 1)]
+    );
+}
+
+#[test]
+fn queue_latency_metrics() {
+    let config = QueueAnalytics {
+        enable: true,
+        hourly_retention: Duration::from_secs(2 * DAY),
+        daily_retention: Duration::from_secs(7 * DAY),
+        max_domains: 10,
+        prometheus_domains: 1,
+    };
+    let analytics = DeliveryAnalytics::default();
+    assert_eq!(analytics.to_prometheus(1), "");
+
+    let start = 1_700_006_400;
+    for (domain, latency) in [
+        ("example.org", 2),
+        ("example.org", 45),
+        ("example.org", 4000),
+        ("example.net", 7),
+        ("example.com", 200),
+    ] {
+        analytics.record(
+            &config,
+            domain,
+            &Status::Completed(()),
+            Some(true),
+            start - latency,
+            start,
+        );
+    }
+
+    // Failed deliveries are not included in the latency histograms
+    analytics.record(
+        &config,
+        "example.net",
+        &Status::TemporaryFailure(Error::DnsError("timeout".to_string())),
+        None,
+        start - 10,
+        start,
+    );
+
+    // The busiest domain is exported, the rest are aggregated
+    let metrics = analytics.to_prometheus(config.prometheus_domains);
+    for line in [
+        "# TYPE stalwart_delivery_latency_seconds histogram",
+        "stalwart_delivery_latency_seconds_bucket{domain=\"example.org\",le=\"1\"} 0",
+        "stalwart_delivery_latency_seconds_bucket{domain=\"example.org\",le=\"5\"} 1",
+        "stalwart_delivery_latency_seconds_bucket{domain=\"example.org\",le=\"60\"} 2",
+        "stalwart_delivery_latency_seconds_bucket{domain=\"example.org\",le=\"3600\"} 2",
+        "stalwart_delivery_latency_seconds_bucket{domain=\"example.org\",le=\"10800\"} 3",
+        "stalwart_delivery_latency_seconds_bucket{domain=\"example.org\",le=\"+Inf\"} 3",
+        "stalwart_delivery_latency_seconds_sum{domain=\"example.org\"} 4047",
+        "stalwart_delivery_latency_seconds_count{domain=\"example.org\"} 3",
+        "stalwart_delivery_latency_seconds_bucket{domain=\"other\",le=\"10\"} 1",
+        "stalwart_delivery_latency_seconds_bucket{domain=\"other\",le=\"300\"} 2",
+        "stalwart_delivery_latency_seconds_sum{domain=\"other\"} 207",
+        "stalwart_delivery_latency_seconds_count{domain=\"other\"} 2",
+    ] {
+        assert!(
+            metrics.lines().any(|l| l == line),
+            "missing {line:?} in {metrics}"
+        );
+    }
+    assert!(!metrics.contains("example.net"));
+
+    // Histograms are not affected by the analytics retention
+    let now = start + 10 * DAY;
+    assert!(analytics
+        .query(&config, None, 0, u64::MAX, false, now)
+        .is_empty());
+    assert_eq!(analytics.to_prometheus(config.prometheus_domains), metrics);
+}
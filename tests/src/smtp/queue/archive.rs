@@ -0,0 +1,156 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use smtp::{
+    core::SMTP,
+    queue::{
+        archive::{parse_archive, write_archive, ArchiveContent, ArchiveEntry},
+        Domain, Message, Recipient, Schedule, Status,
+    },
+};
+
+use crate::smtp::{inbound::TestQueueEvent, TestSMTP};
+
+#[tokio::test]
+async fn queue_archive() {
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_queue_archive_test");
+    let temp_dir = std::env::temp_dir();
+
+    // Build message metadata
+    let mut retry = Schedule::later(Duration::from_secs(3600));
+    retry.inner = 3;
+    let message = Message {
+        size: 0,
+        id: 0,
+        path: PathBuf::new(),
+        created: 123456,
+        return_path: "sender@foobar.org".to_string(),
+        return_path_lcase: "sender@foobar.org".to_string(),
+        return_path_domain: "foobar.org".to_string(),
+        recipients: vec![Recipient {
+            domain_idx: 0,
+            address: "John@example.org".to_string(),
+            address_lcase: "john@example.org".to_string(),
+            status: Status::Scheduled,
+            flags: 0,
+            orcpt: "rfc822;john@example.org".to_string().into(),
+        }],
+        domains: vec![Domain {
+            domain: "example.org".to_string(),
+            retry,
+            notify: Schedule::later(Duration::from_secs(7200)),
+            expires: Instant::now() + Duration::from_secs(86400),
+            status: Status::Scheduled,
+            retry_strategy: None,
+            disable_tls: false,
+            changed: false,
+        }],
+        flags: 0,
+        env_id: "envid".to_string().into(),
+        priority: 2,
+        parked: None,
+        queue_refs: vec![],
+    };
+    let metadata = message.serialize();
+
+    // Archives round-trip bundled and referenced content
+    let content = b"From: sender@foobar.org\r\nSubject: test\r\n\r\ntest\r\n".to_vec();
+    let reference = temp_dir.join("smtp_queue_archive_test.msg");
+    let mut file_content = content.clone();
+    file_content.extend_from_slice(&metadata);
+    std::fs::write(&reference, &file_content).unwrap();
+    let entries = vec![
+        ArchiveEntry {
+            id: 1,
+            size: content.len(),
+            metadata: metadata.clone(),
+            content: ArchiveContent::Bundled(content.clone()),
+        },
+        ArchiveEntry {
+            id: 2,
+            size: content.len(),
+            metadata: metadata.clone(),
+            content: ArchiveContent::Reference(reference.clone()),
+        },
+    ];
+    let archive = write_archive(&entries);
+    assert_eq!(parse_archive(&archive).unwrap(), entries);
+
+    // Truncated or corrupted archives are rejected
+    assert!(parse_archive(&archive[..archive.len() - 1]).is_err());
+    assert!(parse_archive(&archive[1..]).is_err());
+
+    // Import archive
+    let archive_path = temp_dir.join("smtp_queue_archive_test.bin");
+    std::fs::write(&archive_path, &archive).unwrap();
+    let result = core.import_queue(&archive_path).await.unwrap();
+    assert_eq!(result.messages.len(), 2);
+    assert!(result.skipped.is_empty());
+
+    for imported in &result.messages {
+        let queued = qr.read_event().await.unwrap_message();
+        assert_eq!(queued.id, imported.id);
+        assert_ne!(queued.id, imported.previous_id);
+        assert_eq!(queued.size, content.len());
+        assert_eq!(queued.created, message.created);
+        assert_eq!(queued.return_path, message.return_path);
+        assert_eq!(queued.env_id, message.env_id);
+        assert_eq!(queued.priority, message.priority);
+        assert_eq!(queued.recipients, message.recipients);
+        assert_eq!(queued.domains[0].retry.inner, 3);
+        assert!(queued.domains[0].retry.due > Instant::now() + Duration::from_secs(3000));
+
+        // The spooled copy matches the archived message
+        let spooled = Message::from_path(queued.path.clone()).await.unwrap();
+        assert_eq!(spooled.recipients, message.recipients);
+        assert_eq!(
+            &std::fs::read(&queued.path).unwrap()[..content.len()],
+            &content[..]
+        );
+        queued.remove().await;
+    }
+
+    // Messages without pending deliveries are skipped
+    let mut completed = message;
+    completed.domains[0].status = Status::Completed(());
+    let archive = write_archive(&[ArchiveEntry {
+        id: 3,
+        size: content.len(),
+        metadata: completed.serialize(),
+        content: ArchiveContent::Bundled(content),
+    }]);
+    std::fs::write(&archive_path, &archive).unwrap();
+    let result = core.import_queue(&archive_path).await.unwrap();
+    assert!(result.messages.is_empty());
+    assert_eq!(result.skipped, vec![3]);
+    qr.assert_empty_queue();
+
+    std::fs::remove_file(&archive_path).unwrap();
+    std::fs::remove_file(&reference).unwrap();
+}
@@ -0,0 +1,199 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::{Duration, Instant};
+
+use smtp::queue::{
+    deferred::DeferReason, manager::Queue, Domain, Error, ErrorDetails, HostResponse, Recipient,
+    Schedule, Status,
+};
+use smtp_proto::Response;
+
+use super::manager::new_message;
+
+#[test]
+fn deferred_reasons() {
+    let mut queue = Queue::default();
+
+    // Domain level failures
+    for (id, (domain, err)) in [
+        ("dns.org", Error::DnsError("no MX".to_string())),
+        ("conn.org", Error::ConnectionError(details())),
+        ("tls.org", Error::TlsError(details())),
+        ("dane.org", Error::DaneError(details())),
+        ("limit.org", Error::RateLimited),
+        (
+            "unexpected.org",
+            Error::UnexpectedResponse(HostResponse {
+                hostname: details(),
+                response: response(421, [4, 4, 2]),
+            }),
+        ),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let mut message = new_message(id as u64);
+        message
+            .domains
+            .push(domain_with_status(domain, Status::TemporaryFailure(err)));
+        queue.messages.insert(message.id, message);
+    }
+
+    // Recipient level failures
+    let mut message = new_message(10);
+    message
+        .domains
+        .push(domain_with_status("quota.org", Status::Scheduled));
+    message
+        .domains
+        .push(domain_with_status("remote.org", Status::Scheduled));
+    message.domains.push(domain_with_status(
+        "conn.org",
+        Status::TemporaryFailure(Error::ConnectionError(details())),
+    ));
+    message
+        .domains
+        .push(domain_with_status("ok.org", Status::Scheduled));
+    message.recipients.push(recipient(
+        0,
+        Status::TemporaryFailure(HostResponse {
+            hostname: details(),
+            response: response(452, [4, 2, 2]),
+        }),
+    ));
+    message.recipients.push(recipient(
+        1,
+        Status::TemporaryFailure(HostResponse {
+            hostname: details(),
+            response: response(450, [4, 7, 1]),
+        }),
+    ));
+    message.recipients.push(recipient(3, Status::Scheduled));
+    assert_eq!(message.defer_reason(0), Some(DeferReason::Quota));
+    assert_eq!(message.defer_reason(1), Some(DeferReason::Remote));
+    assert_eq!(message.defer_reason(2), Some(DeferReason::Connection));
+    assert_eq!(message.defer_reason(3), None);
+    assert_eq!(message.defer_reason(4), None);
+    assert!(message.has_defer_reason(DeferReason::Quota));
+    assert!(!message.has_defer_reason(DeferReason::Dns));
+    queue.messages.insert(message.id, message);
+
+    // Parked messages are not included
+    let mut message = new_message(11);
+    message.domains.push(domain_with_status(
+        "dns.org",
+        Status::TemporaryFailure(Error::DnsError("no MX".to_string())),
+    ));
+    message.parked = Some("held".to_string());
+    queue.messages.insert(message.id, message);
+
+    let summary = queue.deferred_summary(None);
+    assert_eq!(
+        summary
+            .iter()
+            .map(|s| (
+                s.reason,
+                s.messages,
+                s.domains
+                    .iter()
+                    .map(|(domain, count)| (domain.as_str(), *count))
+                    .collect::<Vec<_>>()
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            (DeferReason::Dns, 1, vec![("dns.org", 1)]),
+            (DeferReason::Connection, 2, vec![("conn.org", 2)]),
+            (DeferReason::Tls, 2, vec![("dane.org", 1), ("tls.org", 1)]),
+            (
+                DeferReason::Remote,
+                2,
+                vec![("remote.org", 1), ("unexpected.org", 1)]
+            ),
+            (DeferReason::Quota, 1, vec![("quota.org", 1)]),
+            (DeferReason::Throttled, 1, vec![("limit.org", 1)]),
+        ]
+    );
+
+    // Filter by domain
+    let summary = queue.deferred_summary(Some("conn.org"));
+    assert_eq!(summary.len(), 1);
+    assert_eq!(summary[0].reason, DeferReason::Connection);
+    assert_eq!(summary[0].messages, 2);
+
+    // Reason names round-trip
+    for reason in [
+        DeferReason::Dns,
+        DeferReason::Connection,
+        DeferReason::Tls,
+        DeferReason::Remote,
+        DeferReason::Quota,
+        DeferReason::Throttled,
+        DeferReason::Local,
+    ] {
+        assert_eq!(DeferReason::parse(reason.as_str()), Some(reason));
+    }
+    assert_eq!(DeferReason::parse("unknown"), None);
+}
+
+fn details() -> ErrorDetails {
+    ErrorDetails {
+        entity: "mx.example.org".to_string(),
+        details: "failed".to_string(),
+    }
+}
+
+fn response(code: u16, esc: [u8; 3]) -> Response<String> {
+    Response {
+        code,
+        esc,
+        message: "try again later".to_string(),
+    }
+}
+
+fn recipient(
+    domain_idx: usize,
+    status: Status<HostResponse<String>, HostResponse<ErrorDetails>>,
+) -> Recipient {
+    Recipient {
+        domain_idx,
+        address: "john@example.org".to_string(),
+        address_lcase: "john@example.org".to_string(),
+        status,
+        flags: 0,
+        orcpt: None,
+    }
+}
+
+fn domain_with_status(domain: &str, status: Status<(), Error>) -> Domain {
+    Domain {
+        domain: domain.to_string(),
+        retry: Schedule::later(Duration::from_secs(60)),
+        notify: Schedule::later(Duration::from_secs(120)),
+        expires: Instant::now() + Duration::from_secs(600),
+        status,
+        retry_strategy: None,
+        disable_tls: false,
+        changed: false,
+    }
+}
@@ -91,12 +91,14 @@ async fn generate_dsn() {
                 entity: "mx.domain.org".to_string(),
                 details: "Connection timeout".to_string(),
             })),
+            retry_strategy: None,
             disable_tls: false,
             changed: false,
         }],
         flags: 0,
         env_id: None,
         priority: 0,
+        parked: None,
 
         queue_refs: vec![],
     });
@@ -25,7 +25,10 @@ use std::time::{Duration, Instant};
 
 use mail_auth::hickory_resolver::proto::op::ResponseCode;
 
-use smtp::queue::{manager::Queue, Domain, Message, Schedule, Status};
+use smtp::{
+    config::RetrySchedule,
+    queue::{manager::Queue, Domain, Message, Schedule, Status},
+};
 
 #[test]
 fn queue_due() {
@@ -108,21 +111,21 @@ fn delivery_events() {
 
     message.domain_mut("a").set_status(
         mail_auth::Error::DnsRecordNotFound(ResponseCode::BADCOOKIE),
-        &[],
+        RetrySchedule::Intervals(&[]),
     );
     assert_eq!(message.next_event().unwrap(), message.domain("b").retry.due);
     assert_eq!(message.next_delivery_event(), message.domain("b").retry.due);
 
     message.domain_mut("b").set_status(
         mail_auth::Error::DnsRecordNotFound(ResponseCode::BADCOOKIE),
-        &[],
+        RetrySchedule::Intervals(&[]),
     );
     assert_eq!(message.next_event().unwrap(), message.domain("c").retry.due);
     assert_eq!(message.next_delivery_event(), message.domain("c").retry.due);
 
     message.domain_mut("c").set_status(
         mail_auth::Error::DnsRecordNotFound(ResponseCode::BADCOOKIE),
-        &[],
+        RetrySchedule::Intervals(&[]),
     );
     assert!(message.next_event().is_none());
 }
@@ -141,6 +144,7 @@ pub fn new_message(id: u64) -> Box<Message> {
         flags: 0,
         env_id: None,
         priority: 0,
+        parked: None,
         queue_refs: vec![],
     })
 }
@@ -152,6 +156,7 @@ fn domain(domain: &str, retry: u64, notify: u64, expires: u64) -> Domain {
         notify: Schedule::later(Duration::from_secs(notify)),
         expires: Instant::now() + Duration::from_secs(expires),
         status: Status::Scheduled,
+        retry_strategy: None,
         disable_tls: false,
         changed: false,
     }
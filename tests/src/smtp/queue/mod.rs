@@ -21,7 +21,12 @@
  * for more details.
 */
 
+pub mod analytics;
+pub mod archive;
+pub mod deferred;
 pub mod dsn;
 pub mod manager;
 pub mod retry;
 pub mod serialize;
+pub mod simulation;
+pub mod warmup;
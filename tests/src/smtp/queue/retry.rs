@@ -23,19 +23,24 @@
 
 use std::{
     sync::Arc,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
 use crate::smtp::{
     inbound::{TestMessage, TestQueueEvent},
+    queue::manager::new_message,
     session::{TestSession, VerifyResponse},
     ParseTestConfig, TestConfig, TestSMTP,
 };
 use smtp::{
-    config::{ConfigContext, IfBlock},
+    config::{queue::ConfigQueue, ConfigContext, IfBlock, RetrySchedule},
     core::{Session, SMTP},
-    queue::{manager::Queue, DeliveryAttempt, Event, WorkerResult},
+    queue::{
+        manager::Queue, DeliveryAttempt, Domain, Error, Event, Message, Schedule, Status,
+        WorkerResult,
+    },
 };
+use utils::config::Config;
 
 #[tokio::test]
 async fn queue_retry() {
@@ -235,3 +240,102 @@ async fn queue_retry() {
             .as_secs()
     ));
 }
+
+#[test]
+fn queue_retry_strategy() {
+    let config = Config::new(
+        r#"
+[queue.retry-strategy."bulk"]
+intervals = ["1m", "5m"]
+multiplier = 2.0
+cap = "12m"
+give-up = "1h"
+
+[queue.retry-strategy."jitter"]
+intervals = ["10m"]
+jitter = "30s"
+"#,
+    )
+    .unwrap();
+    let strategies = config.parse_retry_strategies().unwrap();
+
+    // Intervals grow after the configured list is exhausted and are capped
+    let bulk = strategies.get("bulk").unwrap();
+    for (attempt, expected) in [60, 300, 600, 720, 720].into_iter().enumerate() {
+        assert_eq!(
+            bulk.interval(attempt as u32),
+            Duration::from_secs(expected),
+            "attempt {attempt}"
+        );
+    }
+
+    // Jitter is added on top of the interval
+    let jitter = strategies.get("jitter").unwrap();
+    for attempt in 0..10 {
+        let interval = jitter.interval(attempt);
+        assert!(
+            interval >= Duration::from_secs(600) && interval <= Duration::from_secs(630),
+            "{interval:?}"
+        );
+    }
+
+    // Invalid strategies are rejected
+    for invalid in [
+        "[queue.retry-strategy.\"empty\"]\njitter = \"1m\"\n",
+        "[queue.retry-strategy.\"slow\"]\nintervals = [\"1m\"]\nmultiplier = 0.5\n",
+    ] {
+        assert!(Config::new(invalid)
+            .unwrap()
+            .parse_retry_strategies()
+            .is_err());
+    }
+
+    // The active strategy is recorded and its give-up time limits the expiration
+    let mut message = new_message(0);
+    message.created = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    message.domains.push(Domain {
+        domain: "example.org".to_string(),
+        retry: Schedule::now(),
+        notify: Schedule::now(),
+        expires: Instant::now() + Duration::from_secs(86400),
+        status: Status::Scheduled,
+        retry_strategy: None,
+        disable_tls: false,
+        changed: false,
+    });
+    let created = message.created;
+    let domain = message.domains.first_mut().unwrap();
+    let now = Instant::now();
+    domain.set_status(
+        Status::<(), Error>::Scheduled,
+        RetrySchedule::Strategy {
+            strategy: bulk,
+            created,
+        },
+    );
+    assert_eq!(domain.retry_strategy.as_deref(), Some("bulk"));
+    assert_eq!(domain.retry.inner, 1);
+    assert!([59, 60].contains(&domain.retry.due.duration_since(now).as_secs()));
+    assert!([3599, 3600].contains(&domain.expires.duration_since(now).as_secs()));
+
+    // The strategy survives serialization
+    let message = Message::deserialize(&message.serialize()).unwrap();
+    assert_eq!(
+        message.domains.first().unwrap().retry_strategy.as_deref(),
+        Some("bulk")
+    );
+
+    // Falling back to the default schedule clears the strategy
+    let mut message = message;
+    let domain = message.domains.first_mut().unwrap();
+    domain.set_status(
+        Status::<(), Error>::Scheduled,
+        &vec![Duration::from_secs(10)],
+    );
+    assert_eq!(domain.retry_strategy, None);
+    let message = Message::deserialize(&message.serialize()).unwrap();
+    assert_eq!(message.domains.first().unwrap().retry_strategy, None);
+}
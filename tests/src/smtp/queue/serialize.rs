@@ -79,6 +79,7 @@ async fn queue_serialize() {
                 notify: Schedule::now(),
                 expires: Instant::now() + Duration::from_secs(10),
                 status: Status::Scheduled,
+                retry_strategy: None,
                 disable_tls: false,
                 changed: false,
             },
@@ -88,6 +89,7 @@ async fn queue_serialize() {
                 notify: Schedule::now(),
                 expires: Instant::now() + Duration::from_secs(10),
                 status: Status::Scheduled,
+                retry_strategy: None,
                 disable_tls: false,
                 changed: false,
             },
@@ -95,6 +97,7 @@ async fn queue_serialize() {
         flags: MAIL_REQUIRETLS | MAIL_SMTPUTF8,
         env_id: "hello".to_string().into(),
         priority: -1,
+        parked: "john@foobar.org".to_string().into(),
 
         queue_refs: vec![],
     };
@@ -165,6 +168,14 @@ async fn queue_serialize() {
     message.domains[1].retry = Schedule::later(Duration::from_secs(62));
     message.domains[1].retry.inner = 678;
 
+    // Release parked message
+    message.release().await;
+    assert_eq!(message.parked, None);
+    assert_msg_eq(
+        &message,
+        &Message::from_path(message.path.clone()).await.unwrap(),
+    );
+
     // Save changes
     message.save_changes().await;
     assert!(message.serialize_changes().is_empty());
@@ -199,6 +210,7 @@ fn assert_msg_eq(msg: &Message, other: &Message) {
     assert_eq!(msg.flags, other.flags);
     assert_eq!(msg.env_id, other.env_id);
     assert_eq!(msg.priority, other.priority);
+    assert_eq!(msg.parked, other.parked);
     assert_eq!(msg.size, other.size);
 }
 
@@ -0,0 +1,130 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use crate::smtp::{ParseTestConfig, TestConfig};
+use smtp::{
+    config::{ConfigContext, IfBlock},
+    core::SMTP,
+    queue::simulation::{DomainProfile, Outage, RecordedMessage, Workload},
+};
+use utils::config::Config;
+
+#[tokio::test]
+async fn queue_simulation() {
+    let mut core = SMTP::test();
+    let config = &mut core.queue.config;
+    config.retry = IfBlock::new(vec![
+        Duration::from_secs(60),
+        Duration::from_secs(300),
+        Duration::from_secs(3600),
+    ]);
+    config.expire = "[{if = 'rcpt-domain', eq = 'down.org', then = '2h'},
+    {else = '1d'}]"
+        .parse_if(&ConfigContext::new(&[]));
+
+    // Ten messages to a domain that is down for three hours, plus
+    // a steady stream of messages to a healthy domain.
+    let workload = Workload {
+        duration: 3600,
+        sample_interval: 300,
+        seed: 1234,
+        domains: vec![
+            DomainProfile {
+                domain: "up.org".to_string(),
+                messages_per_hour: 60.0,
+                delivery_time: 2,
+                ..Default::default()
+            },
+            DomainProfile {
+                domain: "down.org".to_string(),
+                outages: vec![Outage {
+                    start: 0,
+                    end: 10800,
+                }],
+                ..Default::default()
+            },
+        ],
+        messages: (0..10)
+            .map(|_| RecordedMessage {
+                at: 0,
+                domain: "down.org".to_string(),
+                sender: None,
+            })
+            .collect(),
+        ..Default::default()
+    };
+
+    let report = core.queue.config.simulate(&workload).await;
+    let up = &report.domains[0];
+    let down = &report.domains[1];
+    assert!(up.messages > 0);
+    assert_eq!(up.delivered, up.messages);
+    assert_eq!(up.attempts, up.messages);
+    assert_eq!(up.latency.p50, Some(2));
+    assert_eq!(up.latency.p99, Some(2));
+
+    // Attempts at 0s, 61s, 362s and 3963s, the next retry falls after expiration
+    assert_eq!(down.messages, 10);
+    assert_eq!(down.delivered, 0);
+    assert_eq!(down.expired, 10);
+    assert_eq!(down.attempts, 40);
+    assert_eq!(down.latency.p50, None);
+    assert_eq!(report.messages, up.messages + down.messages);
+    assert_eq!(report.delivered + report.expired, report.messages);
+
+    // The backlog holds the stuck messages until they expire
+    assert!(report.backlog[0].queued >= 10);
+    assert!(report
+        .backlog
+        .iter()
+        .filter(|sample| sample.time < 3900)
+        .all(|sample| sample.queued >= 10));
+    assert_eq!(report.backlog.last().unwrap().queued, 0);
+    assert_eq!(report.elapsed, 3964);
+
+    // Simulations are deterministic for a given seed
+    assert_eq!(core.queue.config.simulate(&workload).await, report);
+
+    // Dry-run with a longer expiration, the outage ends before the fifth attempt
+    core.eval.settings = Config::new(concat!(
+        "server.hostname = \"mx.example.org\"\n",
+        "queue.path = \"/tmp\"\n",
+        "queue.schedule.retry = [\"1m\", \"5m\", \"1h\"]\n",
+    ))
+    .unwrap();
+    let report = core
+        .simulate_queue(&workload, Some("queue.schedule.expire = \"1d\""))
+        .await
+        .unwrap();
+    let down = &report.domains[1];
+    assert_eq!(down.delivered, 10);
+    assert_eq!(down.expired, 0);
+    assert_eq!(down.attempts, 50);
+    assert_eq!(down.latency.p99, Some(11166));
+    assert!(core
+        .simulate_queue(&workload, Some("queue.schedule.expire = \"invalid\""))
+        .await
+        .is_err());
+}
@@ -0,0 +1,116 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::net::IpAddr;
+
+use smtp::{config::queue::ConfigQueue, core::SMTP};
+use utils::config::Config;
+
+use crate::smtp::TestConfig;
+
+const CONFIG: &str = r#"
+[queue.warmup]
+ips = ["192.0.2.10", "192.0.2.20"]
+schedule = [2, 4]
+
+[queue.warmup.providers]
+gmail = ["google.com", "googlemail.com."]
+"#;
+
+#[tokio::test]
+async fn queue_warmup() {
+    let mut core = SMTP::test();
+    core.queue.config.warmup = Config::new(CONFIG).unwrap().parse_queue_warmup().unwrap();
+    let queue = &core.queue;
+    let span = tracing::info_span!("warmup");
+    let new_ip: IpAddr = "192.0.2.10".parse().unwrap();
+    let other_ip: IpAddr = "192.0.2.20".parse().unwrap();
+    let warm_ip: IpAddr = "192.0.2.30".parse().unwrap();
+
+    // Providers are matched by MX suffix
+    for (mx, provider) in [
+        ("aspmx.l.google.com", "gmail"),
+        ("GMAIL-SMTP-IN.L.GOOGLE.COM.", "gmail"),
+        ("google.com", "gmail"),
+        ("mx.notgoogle.com", "other"),
+        ("mx.googlemail.com", "gmail"),
+        ("mx.example.org", "other"),
+    ] {
+        assert_eq!(queue.config.warmup.provider(mx), provider, "{mx}");
+    }
+
+    // IPs that are not warming up are never limited
+    for _ in 0..10 {
+        assert!(queue
+            .warmup_acquire(warm_ip, "mx.google.com", &span)
+            .await
+            .is_ok());
+    }
+
+    // New IPs are limited per provider on the first day
+    for _ in 0..2 {
+        assert!(queue
+            .warmup_acquire(new_ip, "mx.google.com", &span)
+            .await
+            .is_ok());
+    }
+    assert!(queue
+        .warmup_acquire(new_ip, "aspmx.l.google.com", &span)
+        .await
+        .is_err());
+    assert!(queue
+        .warmup_acquire(new_ip, "mx.example.org", &span)
+        .await
+        .is_ok());
+
+    // Exhausted IPs are avoided when selecting a source address
+    for _ in 0..10 {
+        assert_eq!(
+            queue
+                .select_source_ip(vec![new_ip, other_ip], "mx.google.com")
+                .await,
+            Some(other_ip)
+        );
+    }
+    assert_eq!(
+        queue.select_source_ip(vec![new_ip], "mx.google.com").await,
+        Some(new_ip)
+    );
+    assert_eq!(queue.select_source_ip(vec![], "mx.google.com").await, None);
+
+    // Check status report
+    let status = queue.warmup_status().await.unwrap();
+    assert_eq!(status.len(), 2);
+    let status = status.into_iter().find(|s| s.ip == new_ip).unwrap();
+    assert_eq!(status.day, Some(0));
+    assert_eq!(status.daily_limit, Some(2));
+    assert!(!status.completed);
+    assert_eq!(
+        status
+            .providers
+            .iter()
+            .map(|p| (p.provider.as_str(), p.sent, p.remaining))
+            .collect::<Vec<_>>(),
+        vec![("gmail", 2, Some(0)), ("other", 1, Some(1))]
+    );
+}
@@ -0,0 +1,90 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    session::VerifyResponse,
+    TestConfig, TestSMTP,
+};
+use smtp::{config::IfBlock, core::SMTP};
+use utils::config::Rate;
+
+const MESSAGE: &str = concat!(
+    "Return-Path: <spammer@foobar.org>\r\n",
+    "Received: from mx.foobar.org (mx.foobar.org [192.168.1.5])\r\n",
+    "\tby mx.example.org (Stalwart SMTP) with ESMTPS id 1234;\r\n",
+    "\tMon, 1 Jan 2024 10:00:00 +0000\r\n",
+    "From: Spammer <spammer@foobar.org>\r\n",
+    "To: bill@example.org\r\n",
+    "Subject: Cheap watches\r\n",
+    "\r\n",
+    "Buy now!\r\n"
+);
+
+#[tokio::test]
+async fn report_abuse() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::DEBUG)
+            .finish(),
+    )
+    .unwrap();*/
+
+    // Abuse reports are disabled by default
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_report_abuse_test");
+    core.report.config.abuse.rcpts = vec!["abuse@example.net".to_string()];
+    core.send_abuse_report(MESSAGE.as_bytes(), "bill@example.org")
+        .await;
+    qr.assert_empty_queue();
+
+    // Enable abuse reports
+    let config = &mut core.report.config.abuse.report;
+    config.send = IfBlock::new(Some(Rate {
+        requests: 1,
+        period: Duration::from_secs(60),
+    }));
+    config.address = IfBlock::new("abuse@example.org".to_string());
+    config.name = IfBlock::new("Abuse Desk".to_string());
+    config.subject = IfBlock::new("Abuse Report".to_string());
+    core.send_abuse_report(MESSAGE.as_bytes(), "bill@example.org")
+        .await;
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("abuse@example.net")
+        .assert_contains("Feedback-Type: abuse")
+        .assert_contains("Original-Mail-From: ")
+        .assert_contains("bill@example.org")
+        .assert_contains("Reported-Domain: foobar.org")
+        .assert_contains("Source-IP: 192.168.1.5")
+        .assert_contains("Subject: Cheap watches");
+
+    // Reports to the same recipient should be throttled
+    core.send_abuse_report(MESSAGE.as_bytes(), "bill@example.org")
+        .await;
+    qr.assert_empty_queue();
+}
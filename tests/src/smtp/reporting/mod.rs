@@ -21,7 +21,9 @@
  * for more details.
 */
 
+pub mod abuse;
 pub mod analyze;
 pub mod dmarc;
+pub mod operator;
 pub mod scheduler;
 pub mod tls;
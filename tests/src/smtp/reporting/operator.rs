@@ -0,0 +1,168 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    session::VerifyResponse,
+    TestConfig, TestSMTP,
+};
+use smtp::{
+    config::{IfBlock, OperatorSchedule, OperatorSection},
+    core::SMTP,
+    queue::{Error, Status},
+    reporting::operator::{OperatorState, TelemetryHistory, TelemetrySample},
+};
+use utils::config::cron::SimpleCron;
+
+const HOUR: u64 = 3600;
+
+#[test]
+fn operator_telemetry() {
+    let telemetry = TelemetryHistory::default();
+    let start = 1_700_006_400;
+
+    for (offset, is_spam) in [(0, true), (10, false), (HOUR, true), (3 * HOUR, false)] {
+        telemetry.record(start + offset, |sample| {
+            sample.spam_scanned += 1;
+            if is_spam {
+                sample.spam_detected += 1;
+            }
+        });
+    }
+    telemetry.record(start + 2 * HOUR, |sample| sample.auth_failures += 3);
+
+    assert_eq!(
+        telemetry.summary(start, start + 4 * HOUR),
+        TelemetrySample {
+            spam_scanned: 4,
+            spam_detected: 2,
+            auth_failures: 3,
+            tls_downgrades: 0,
+        }
+    );
+    assert_eq!(
+        telemetry.summary(start + HOUR, start + 2 * HOUR),
+        TelemetrySample {
+            spam_scanned: 1,
+            spam_detected: 1,
+            auth_failures: 3,
+            tls_downgrades: 0,
+        }
+    );
+
+    // Samples older than the retention period are removed
+    telemetry.record(start + 60 * 24 * HOUR, |sample| sample.auth_failures += 1);
+    assert_eq!(
+        telemetry.summary(0, start + 60 * 24 * HOUR),
+        TelemetrySample {
+            spam_scanned: 0,
+            spam_detected: 0,
+            auth_failures: 1,
+            tls_downgrades: 0,
+        }
+    );
+}
+
+#[tokio::test]
+async fn operator_report() {
+    /*tracing::subscriber::set_global_default(
+        tracing_subscriber::FmtSubscriber::builder()
+            .with_max_level(tracing::Level::DEBUG)
+            .finish(),
+    )
+    .unwrap();*/
+
+    let mut core = SMTP::test();
+    let mut qr = core.init_test_queue("smtp_report_operator_test");
+    let config = &mut core.report.config.operator;
+    config.report.address = IfBlock::new("reports@example.org".to_string());
+    config.report.name = IfBlock::new("Operator Reports".to_string());
+    config.report.subject = IfBlock::new("Mail Server Report".to_string());
+    config.schedules = vec![OperatorSchedule {
+        id: "daily".to_string(),
+        enable: true,
+        frequency: SimpleCron::Day { hour: 8, minute: 0 },
+        period: Duration::from_secs(86400),
+        rcpts: vec!["postmaster@example.org".to_string()],
+        sections: vec![
+            OperatorSection::Deferred,
+            OperatorSection::Spam,
+            OperatorSection::Auth,
+        ],
+        top_domains: 1,
+    }];
+    core.report.operator = OperatorState::new(&core.report.config.operator.schedules);
+    let core = Arc::new(core);
+
+    // Record activity
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    for (domain, deferrals) in [("example.net", 2), ("example.com", 1)] {
+        for _ in 0..deferrals {
+            core.queue.record_delivery(
+                domain,
+                &Status::TemporaryFailure(Error::DnsError("timeout".to_string())),
+                None,
+                now,
+            );
+        }
+    }
+    let telemetry = &core.report.operator.telemetry;
+    for is_spam in [true, true, false, false] {
+        telemetry.record_spam_verdict(is_spam);
+    }
+    telemetry.record_auth_failure();
+
+    // Send report
+    assert!(!core.send_operator_report("weekly").await);
+    assert!(core.send_operator_report("daily").await);
+    qr.read_event()
+        .await
+        .unwrap_message()
+        .read_lines()
+        .assert_contains("postmaster@example.org")
+        .assert_contains("Subject: Mail Server Report: daily")
+        .assert_contains("example.net: 2 deferred, 0 failed of 2 attempts (dns)")
+        .assert_not_contains("example.com: 1 deferred")
+        .assert_contains("Messages scanned: 4")
+        .assert_contains("Catch rate: 50.0%")
+        .assert_contains("Failed attempts: 1");
+    qr.assert_empty_queue();
+
+    // Disabled reports are not sent when due
+    assert!(core.set_operator_report("daily", false).await.unwrap());
+    assert!(!core.set_operator_report("weekly", false).await.unwrap());
+    let status = core.operator_reports();
+    assert_eq!(status.len(), 1);
+    assert!(!status[0].enable);
+    assert!(status[0].last_sent.is_some());
+    core.send_due_operator_reports();
+    qr.assert_empty_queue();
+}
@@ -33,7 +33,10 @@ use smtp::core::{Session, SessionAddress, SessionData, SessionParameters, State,
 use tokio_rustls::TlsAcceptor;
 use utils::{
     config::ServerProtocol,
-    listener::{limiter::ConcurrencyLimiter, ServerInstance, SessionStream, TcpAcceptor},
+    listener::{
+        limiter::ConcurrencyLimiter, tls::ClientCertificate, ServerInstance, SessionStream,
+        TcpAcceptor,
+    },
 };
 
 use super::TestConfig;
@@ -42,6 +45,7 @@ pub struct DummyIo {
     pub tx_buf: Vec<u8>,
     pub rx_buf: Vec<u8>,
     pub tls: bool,
+    pub tls_client_cert: Option<ClientCertificate>,
 }
 
 impl AsyncRead for DummyIo {
@@ -93,6 +97,10 @@ impl SessionStream for DummyIo {
     fn tls_version_and_cipher(&self) -> (Cow<'static, str>, Cow<'static, str>) {
         ("".into(), "".into())
     }
+
+    fn tls_client_certificate(&self) -> Option<ClientCertificate> {
+        self.tls_client_cert.clone()
+    }
 }
 
 impl Unpin for DummyIo {}
@@ -124,6 +132,7 @@ impl TestSession for Session<DummyIo> {
                 rx_buf: vec![],
                 tx_buf: vec![],
                 tls: false,
+                tls_client_cert: None,
             },
             data: SessionData::new(
                 "127.0.0.1".parse().unwrap(),
@@ -364,15 +373,19 @@ impl TestServerInstance for ServerInstance {
             hostname: "mx.example.org".to_string(),
             protocol: ServerProtocol::Smtp,
             data: "220 mx.example.org at your service.\r\n".to_string(),
+            banner: None,
             acceptor: TcpAcceptor::Tls(TlsAcceptor::from(Arc::new(
                 ServerConfig::builder()
                     .with_no_client_auth()
                     .with_cert_resolver(Arc::new(DummyCertResolver)),
             ))),
+            tls_client_auth: None,
             limiter: ConcurrencyLimiter::new(100),
+            bandwidth: Default::default(),
             shutdown_rx,
             proxy_networks: vec![],
             blocked_ips: Arc::new(Default::default()),
+            virtual_hosts: Default::default(),
         }
     }
 }
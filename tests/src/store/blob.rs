@@ -488,7 +488,47 @@ async fn test_store(store: BlobStore) {
         .unwrap(),
         std::str::from_utf8(&data[3000111..4000999]).unwrap()
     );
+
+    // Streamed reads never hold more than a chunk in memory
+    const CHUNK_SIZE: usize = 64 * 1024;
+    let mut stream = store
+        .get_blob_stream(hash.as_slice(), CHUNK_SIZE)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stream.len(), data.len() as u64);
+    let mut streamed = Vec::with_capacity(data.len());
+    while let Some(chunk) = stream.next_chunk().await.unwrap() {
+        assert!(!chunk.is_empty() && chunk.len() <= CHUNK_SIZE);
+        streamed.extend_from_slice(&chunk);
+    }
+    assert!(streamed == data, "Streamed blob does not match");
+
+    // Streamed ranges, including out of bounds ones
+    for (range, expected) in [
+        (3000111..4000999, &data[3000111..4000999]),
+        (0..10, &data[0..10]),
+        (
+            data.len() as u64 - 5..data.len() as u64 + 100,
+            &data[data.len() - 5..],
+        ),
+        (data.len() as u64 + 1..data.len() as u64 + 100, &[][..]),
+    ] {
+        let stream = store
+            .get_blob_stream(hash.as_slice(), CHUNK_SIZE)
+            .await
+            .unwrap()
+            .unwrap()
+            .narrow(range.clone());
+        assert_eq!(stream.len(), expected.len() as u64, "{range:?}");
+        assert!(stream.read_to_end().await.unwrap() == expected, "{range:?}");
+    }
     assert!(store.delete_blob(hash.as_slice()).await.unwrap());
+    assert!(store
+        .get_blob_stream(hash.as_slice(), CHUNK_SIZE)
+        .await
+        .unwrap()
+        .is_none());
     assert!(store
         .get_blob(hash.as_slice(), 0..u32::MAX)
         .await
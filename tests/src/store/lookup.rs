@@ -93,6 +93,14 @@ pub async fn lookup_tests() {
                 .unwrap()
         );
 
+        // Test insert only if absent or expired
+        let key = "dup".as_bytes().to_vec();
+        assert!(store.key_insert(key.clone(), vec![], 1).await.unwrap());
+        assert!(!store.key_insert(key.clone(), vec![], 1).await.unwrap());
+        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+        assert!(store.key_insert(key.clone(), vec![], 1).await.unwrap());
+        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+
         store.purge_expired().await.unwrap();
         if let LookupStore::Store(store) = &store {
             store.assert_is_empty(store.clone().into()).await;